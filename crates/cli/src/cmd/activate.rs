@@ -0,0 +1,69 @@
+//! Implementation of the `sys activate-login` command.
+//!
+//! Runs the login-phase binds of the current snapshot (`phase = "login"`),
+//! which `sys apply` only records, and installs the hook that does so at
+//! every login. See [`syslua_lib::execute::activate`].
+
+use anyhow::{Context, Result, bail};
+
+use syslua_lib::execute::{SessionEvent, activate};
+use syslua_lib::platform::login_hook::LoginHook;
+use syslua_lib::platform::paths::root_dir;
+
+use crate::output::{OutputFormat, print_error, print_info, print_json, print_stat, print_success, truncate_hash};
+
+/// Execute the activate-login command.
+///
+/// With `install` or `uninstall` only the login hook is changed. Otherwise
+/// the login-phase binds run their `create` actions, or their `destroy`
+/// actions with `logout`; the command fails if any of them failed.
+pub fn cmd_activate_login(logout: bool, install: bool, uninstall: bool, output: OutputFormat) -> Result<()> {
+  if install || uninstall {
+    let exe = std::env::current_exe().context("Failed to locate the running executable")?;
+    let hook = LoginHook::for_platform(&exe, &root_dir());
+    if install {
+      hook.install().context("Failed to install the login hook")?;
+      print_success("Login hook installed");
+    } else {
+      hook.uninstall().context("Failed to remove the login hook")?;
+      print_success("Login hook removed");
+    }
+    for (path, _) in hook.files.iter().chain(&hook.blocks) {
+      print_stat("File", &path.display().to_string());
+    }
+    return Ok(());
+  }
+
+  let event = if logout {
+    SessionEvent::Logout
+  } else {
+    SessionEvent::Login
+  };
+  let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
+  let result = rt.block_on(activate(event)).context("Activation failed")?;
+
+  if output.is_json() {
+    print_json(&result)?;
+  } else if result.binds.is_empty() {
+    print_info("No login-phase binds to run");
+  } else {
+    let ran = result.binds.iter().filter(|bind| bind.error.is_none()).count();
+    print_success(&format!(
+      "{} {} of {} login bind(s)",
+      if logout { "Deactivated" } else { "Activated" },
+      ran,
+      result.binds.len()
+    ));
+    for bind in &result.binds {
+      if let Some(error) = &bind.error {
+        let name = bind.id.as_deref().unwrap_or_else(|| truncate_hash(&bind.hash.0));
+        print_error(&format!("{}: {}", name, error));
+      }
+    }
+  }
+
+  if !result.is_success() {
+    bail!("Some login-phase binds failed");
+  }
+  Ok(())
+}
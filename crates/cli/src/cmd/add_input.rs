@@ -0,0 +1,43 @@
+//! Implementation of the `sys add-input` command.
+//!
+//! Looks an input up in the configured registries, declares it in the
+//! config's `inputs` table (keeping the file's formatting) and resolves it
+//! into the lock file. See [`syslua_lib::update::add_input`].
+
+use anyhow::{Context, Result, bail};
+
+use syslua_lib::inputs::registry::find;
+use syslua_lib::platform;
+use syslua_lib::update::{add_input, find_config_path};
+
+use crate::cmd::search::load_registries;
+use crate::output::{print_stat, print_success, truncate_hash};
+
+/// Execute the add-input command.
+///
+/// # Arguments
+///
+/// * `name` - Name of the input in the registries.
+/// * `config` - Optional path to config file. If not provided, uses default resolution.
+/// * `alias` - Name to declare the input under instead of its registry name.
+/// * `registries` - Registry URLs to look in instead of the configured ones.
+pub fn cmd_add_input(name: &str, config: Option<&str>, alias: Option<&str>, registries: Vec<String>) -> Result<()> {
+  let config_path = find_config_path(config).context("Failed to find config file")?;
+  let config_str = config_path.to_string_lossy();
+  let indexes = load_registries(Some(&config_str), registries)?;
+  let Some(found) = find(&indexes, name) else {
+    bail!("No registry lists an input named '{}'; try 'sys search {}'", name, name);
+  };
+
+  let declared = alias.unwrap_or(name);
+  let result = add_input(&config_path, declared, &found.entry.url, platform::is_elevated())
+    .with_context(|| format!("Failed to add input '{}'", declared))?;
+
+  print_success(&format!("Added input {} to {}", declared, config_path.display()));
+  print_stat("URL", &found.entry.url);
+  print_stat("Registry", &found.registry);
+  if let Some(resolved) = result.resolved.get(declared) {
+    print_stat("Revision", truncate_hash(&resolved.rev));
+  }
+  Ok(())
+}
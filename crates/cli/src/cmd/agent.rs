@@ -0,0 +1,247 @@
+//! Implementation of the `sys agent` command.
+//!
+//! Installs a scheduled service that keeps the machine in sync with a config
+//! in git: each run pulls the latest commit and applies it, then optionally
+//! backs up the store. See [`syslua_lib::agent`] for the schedule, jitter and
+//! failure backoff.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::Subcommand;
+
+use syslua_lib::agent::service::ServiceDefinition;
+use syslua_lib::agent::{AgentConfig, AgentError, AgentStatus, checkout, unix_now};
+use syslua_lib::eval::EvalOptions;
+use syslua_lib::execute::{ExecuteConfig, MetricsSink};
+use syslua_lib::platform::is_elevated;
+use syslua_lib::platform::paths::{root_dir, snapshots_dir};
+use syslua_lib::snapshot::SnapshotStore;
+use syslua_lib::store_backup::{BackupOptions, backup_store};
+use syslua_lib::store_lock::{LockMode, StoreLock};
+
+use crate::cmd::cmd_apply;
+use crate::output::{
+  OutputFormat, format_duration, print_error, print_info, print_json, print_stat, print_success, truncate_hash,
+};
+
+#[derive(Subcommand, Debug)]
+pub enum AgentCommand {
+  /// Re-apply a config from git on a schedule
+  Install {
+    /// Git URL of the config repository, optionally with a #branch, #tag or #commit
+    source: String,
+    /// Config file to apply, relative to the repository root
+    #[arg(long, default_value = syslua_lib::agent::DEFAULT_CONFIG_FILE)]
+    config: PathBuf,
+    /// Time between runs (e.g. 30m, 1h)
+    #[arg(long, default_value = "30m", value_parser = humantime::parse_duration)]
+    interval: Duration,
+    /// Random delay of up to this much before each run
+    #[arg(long, default_value = "5m", value_parser = humantime::parse_duration)]
+    jitter: Duration,
+    /// Longest wait between runs after repeated failures
+    #[arg(long, default_value = "6h", value_parser = humantime::parse_duration)]
+    max_backoff: Duration,
+    /// Repair drifted binds on each run
+    #[arg(long)]
+    repair: bool,
+    /// Write Prometheus metrics of each run to this file
+    #[arg(long, value_name = "PATH")]
+    metrics_textfile: Option<PathBuf>,
+    /// Push Prometheus metrics of each run to the Pushgateway at this URL
+    #[arg(long, value_name = "URL")]
+    metrics_push: Option<String>,
+    /// Back up snapshots and bind state to this directory after each successful run
+    #[arg(long, value_name = "DIR")]
+    backup: Option<PathBuf>,
+  },
+
+  /// Remove the scheduled service and the agent's config
+  Uninstall,
+
+  /// Pull the config and apply it once, as the scheduled service does
+  Run {
+    /// Run even while backing off after failures, and without the random delay
+    #[arg(long)]
+    now: bool,
+  },
+}
+
+pub fn cmd_agent(command: AgentCommand) -> Result<()> {
+  match command {
+    AgentCommand::Install {
+      source,
+      config,
+      interval,
+      jitter,
+      max_backoff,
+      repair,
+      metrics_textfile,
+      metrics_push,
+      backup,
+    } => cmd_install(AgentConfig {
+      source,
+      config,
+      interval_secs: interval.as_secs().max(60),
+      jitter_secs: jitter.as_secs(),
+      max_backoff_secs: max_backoff.as_secs(),
+      repair,
+      metrics: MetricsSink {
+        textfile: metrics_textfile,
+        push_url: metrics_push,
+      },
+      backup,
+    }),
+    AgentCommand::Uninstall => cmd_uninstall(),
+    AgentCommand::Run { now } => cmd_run(now),
+  }
+}
+
+fn service(config: &AgentConfig) -> Result<ServiceDefinition> {
+  let exe = std::env::current_exe().context("Failed to locate the running executable")?;
+  Ok(ServiceDefinition::for_platform(
+    &exe,
+    &root_dir(),
+    Duration::from_secs(config.interval_secs),
+    is_elevated(),
+  ))
+}
+
+fn cmd_install(config: AgentConfig) -> Result<()> {
+  // Fail early on a source the runs could never fetch
+  config.git_source()?;
+  config.save()?;
+  service(&config)?.install()?;
+
+  print_success("Agent installed");
+  print_stat("Source", &config.source);
+  print_stat("Config", &config.config.display().to_string());
+  print_stat("Interval", &format_duration(Duration::from_secs(config.interval_secs)));
+  if let Some(ref backup) = config.backup {
+    print_stat("Backup", &backup.display().to_string());
+  }
+  print_info("Run 'sys agent run --now' to apply right away");
+  Ok(())
+}
+
+fn cmd_uninstall() -> Result<()> {
+  let Some(config) = AgentConfig::load()? else {
+    print_info("The agent is not installed");
+    return Ok(());
+  };
+  service(&config)?.uninstall()?;
+  std::fs::remove_file(AgentConfig::path()).context("Failed to remove the agent config")?;
+  print_success("Agent uninstalled");
+  Ok(())
+}
+
+fn cmd_run(now: bool) -> Result<()> {
+  let config = AgentConfig::load()?.ok_or(AgentError::NotInstalled)?;
+  let mut status = AgentStatus::load()?;
+
+  if !now && !status.is_due(unix_now()) {
+    print_info(&format!(
+      "Backing off after {} failed run(s); next run at {}",
+      status.consecutive_failures,
+      status.retry_after.unwrap_or_default()
+    ));
+    return Ok(());
+  }
+  if !now {
+    std::thread::sleep(config.jitter());
+  }
+
+  let started = unix_now();
+  status.last_run = Some(started);
+  let result = checkout(&config).map_err(anyhow::Error::from).and_then(|(file, rev)| {
+    let file = file.to_str().context("Config path is not valid UTF-8")?;
+    cmd_apply(
+      Some(file),
+      None,
+      config.repair,
+      EvalOptions::default(),
+      ExecuteConfig::default(),
+      false,
+      Vec::new(),
+      config.metrics.clone(),
+      OutputFormat::Text,
+    )?;
+    Ok(rev)
+  });
+
+  match &result {
+    Ok(rev) => {
+      let snapshot_id = SnapshotStore::new(snapshots_dir()).current_id().ok().flatten();
+      status.record_success(unix_now(), rev.clone(), snapshot_id);
+    }
+    Err(e) => status.record_failure(started, format!("{:#}", e), &config),
+  }
+  status.save()?;
+  result?;
+
+  // A failed backup doesn't fail the applied run; the next run retries it
+  if let Some(ref dest) = config.backup
+    && let Err(e) = run_backup(dest)
+  {
+    print_error(&format!("Backup failed: {:#}", e));
+  }
+  Ok(())
+}
+
+fn run_backup(dest: &Path) -> Result<()> {
+  let _lock = StoreLock::acquire(LockMode::Shared, "agent backup").context("Failed to acquire store lock")?;
+  let report = backup_store(dest, &BackupOptions::default())?;
+  print_info(&format!(
+    "Backed up the store to {} ({} new file(s))",
+    dest.display(),
+    report.new_files
+  ));
+  Ok(())
+}
+
+/// `sys status --agent`: the installed agent and the outcome of its runs.
+pub fn cmd_agent_status(output: OutputFormat) -> Result<()> {
+  let config = AgentConfig::load()?;
+  let status = AgentStatus::load()?;
+
+  if output.is_json() {
+    return print_json(&serde_json::json!({ "installed": config.is_some(), "config": config, "status": status }));
+  }
+
+  let Some(config) = config else {
+    print_info("The agent is not installed. Run 'sys agent install <SOURCE>' to set it up.");
+    return Ok(());
+  };
+  match (&status.last_error, status.last_run) {
+    (_, None) => print_info("Agent installed, not run yet"),
+    (None, Some(_)) => print_success("Agent healthy"),
+    (Some(error), Some(_)) => print_error(&format!("Last agent run failed: {}", error)),
+  }
+  print_stat("Source", &config.source);
+  print_stat("Config", &config.config.display().to_string());
+  print_stat("Interval", &format_duration(Duration::from_secs(config.interval_secs)));
+  if let Some(ref backup) = config.backup {
+    print_stat("Backup", &backup.display().to_string());
+  }
+  if let Some(last_run) = status.last_run {
+    print_stat("Last run", &last_run.to_string());
+  }
+  if let Some(last_success) = status.last_success {
+    print_stat("Last success", &last_success.to_string());
+  }
+  if let Some(ref rev) = status.rev {
+    print_stat("Commit", truncate_hash(rev));
+  }
+  if let Some(ref snapshot_id) = status.snapshot_id {
+    print_stat("Snapshot", snapshot_id);
+  }
+  if status.consecutive_failures > 0 {
+    print_stat("Failures", &status.consecutive_failures.to_string());
+  }
+  if let Some(retry_after) = status.retry_after {
+    print_stat("Retry after", &retry_after.to_string());
+  }
+  Ok(())
+}
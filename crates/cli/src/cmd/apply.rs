@@ -1,21 +1,36 @@
 //! Implementation of the `sys apply` command.
 //!
-//! This command evaluates a Lua configuration file and applies changes to the system,
-//! tracking state via snapshots.
+//! This command evaluates a Lua configuration file (or loads a manifest written
+//! by `sys eval`) and applies changes to the system, tracking state via snapshots.
 
-use std::path::Path;
-use std::time::Instant;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow, bail};
 use owo_colors::{OwoColorize, Stream};
 use tracing::info;
 
-use syslua_lib::execute::{ApplyOptions, ExecuteConfig, apply};
+use serde::Serialize;
+use syslua_lib::api::{ApplyRequest, ApplyResponse};
+use syslua_lib::daemon::{DaemonClient, DaemonRequest, DaemonResponse, SubmitRequest};
+use syslua_lib::eval::{EvalOptions, evaluate_config};
+use syslua_lib::execute::{
+  ApplyError, ApplyMetrics, ApplyOptions, ApplyResult, DagResult, ExecuteConfig, MetricsRecorder, MetricsSink, apply,
+  changes_outside_groups, deselect_changes,
+};
+use syslua_lib::manifest::{Manifest, ManifestExport};
+use syslua_lib::snapshot::{Snapshot, SnapshotStore, compute_diff};
+use syslua_lib::util::hash::ObjectHash;
 
+use crate::cmd::daemon::delegate_apply;
+use crate::output::progress::Progress;
 use crate::output::{
-  OutputFormat, format_duration, print_error, print_info, print_json, print_stat, print_success, print_warning,
-  symbols, truncate_hash,
+  ApplySummary, OutputFormat, format_duration, print_deprecations, print_error, print_info, print_input_overrides,
+  print_json, print_skipped_binds, print_skipped_builds, print_stat, print_success, print_warning, symbols,
+  truncate_hash,
 };
+use crate::prompts::select_skipped;
 use syslua_lib::platform::paths;
 
 /// Execute the apply command.
@@ -29,34 +44,278 @@ use syslua_lib::platform::paths;
 /// - Saves new snapshot
 ///
 /// Prints a summary including counts of builds realized, binds applied/destroyed, and the snapshot ID.
-pub fn cmd_apply(file: &str, repair: bool, impure: bool, output: OutputFormat) -> Result<()> {
+/// Snapshots produced with `eval.input_overrides` record them and are flagged in the summary.
+/// If a daemon is running for the same store, the apply runs there instead.
+/// With `interactive`, the pending bind changes are listed first so the user
+/// can skip some of them; skipped changes stay pending for the next apply.
+/// With `groups`, only the bind changes of those groups are applied; the rest
+/// stay pending the same way.
+/// With `manifest`, the exported manifest is applied in-process without
+/// evaluating any config, after checking it was evaluated for this platform.
+/// `execute` carries the build options (network isolation, skipped checks,
+/// throttling), which are forwarded to a daemon as well.
+/// With a non-empty `metrics` sink, the metrics of the apply are published
+/// to it whether the apply succeeds or fails. An apply run by a daemon only
+/// reports its duration and outcome.
+pub fn cmd_apply(
+  file: Option<&str>,
+  manifest: Option<&Path>,
+  repair: bool,
+  eval: EvalOptions,
+  execute: ExecuteConfig,
+  interactive: bool,
+  groups: Vec<String>,
+  metrics: MetricsSink,
+  output: OutputFormat,
+) -> Result<()> {
   let start = Instant::now();
-  let path = Path::new(file);
+  eval.ensure_host_platform()?;
 
-  let options = ApplyOptions {
-    execute: ExecuteConfig::default(),
-    dry_run: false,
-    repair,
-    impure,
+  // An imported manifest is recorded in the snapshot as the applied "config"
+  let (path, imported) = match manifest {
+    Some(manifest_path) => {
+      let export = ManifestExport::load(manifest_path)
+        .with_context(|| format!("Failed to load manifest: {}", manifest_path.display()))?;
+      info!(platform = %export.platform, config = ?export.config, "applying exported manifest");
+      (manifest_path, Some(export.manifest))
+    }
+    None => (
+      Path::new(file.context("A config file or --manifest is required")?),
+      None,
+    ),
+  };
+
+  // A selected or imported manifest is applied in-process, never by a daemon
+  let selected = if interactive || !groups.is_empty() {
+    let mut desired = match imported {
+      Some(manifest) => manifest,
+      None => evaluate_config(path, &eval).with_context(|| format!("Failed to evaluate config: {}", path.display()))?,
+    };
+    if !groups.is_empty() {
+      desired = select_groups(desired, &groups)?;
+    }
+    if interactive {
+      match select_changes(desired)? {
+        Some(manifest) => Some(manifest),
+        None => {
+          print_info("Apply aborted.");
+          return Ok(());
+        }
+      }
+    } else {
+      Some(desired)
+    }
+  } else {
+    imported
+  };
+  let daemon = if selected.is_none() {
+    DaemonClient::detect()
+  } else {
+    None
+  };
+
+  let result = match daemon {
+    Some(client) => {
+      let request = ApplyRequest {
+        repair,
+        impure: eval.impure,
+        input_overrides: eval.input_overrides,
+        untrusted_inputs: eval.untrusted_inputs,
+        vars_file: eval.vars_file,
+        isolate_network: execute.isolate_network,
+        skip_checks: execute.skip_checks,
+        skip_preflight: execute.skip_preflight,
+        writable_store: execute.writable_store,
+        nice: execute.throttle.nice,
+        background: execute.throttle.background,
+        limit_rate: execute.limit_rate.bytes_per_sec(),
+        max_fetch: Some(execute.action_limits.max_fetch.max()),
+        max_pkg: Some(execute.action_limits.max_pkg.max()),
+        fail_at: execute.fail_at,
+        retry_failed: execute.retry_failed,
+        failure_ttl_secs: Some(execute.failure_ttl_secs),
+        ..ApplyRequest::new(path)
+      };
+      // The daemon shares this store, so its snapshot can be read back
+      let result = delegate_apply(&client, request).and_then(|response| {
+        let snapshot = SnapshotStore::default_store()
+          .load_snapshot(&response.snapshot_id)
+          .context("Failed to load the snapshot the daemon saved")?;
+        Ok(daemon_result(response, snapshot))
+      });
+      if !metrics.is_empty() {
+        let mut recorded = ApplyMetrics::default();
+        recorded.finish(start.elapsed(), result.as_ref().err().map(|_| "daemon"));
+        publish_metrics(&recorded, &metrics);
+      }
+      result.context("Apply failed")?
+    }
+    None => {
+      let (mut progress, display) = Progress::start();
+      let recorder = if metrics.is_empty() {
+        None
+      } else {
+        let (sender, recorder) = MetricsRecorder::start();
+        progress = progress.join(sender);
+        Some(recorder)
+      };
+      let options = ApplyOptions {
+        execute: ExecuteConfig { progress, ..execute },
+        dry_run: false,
+        repair,
+        impure: eval.impure,
+        input_overrides: eval.input_overrides,
+        untrusted_inputs: eval.untrusted_inputs,
+        vars_file: eval.vars_file,
+        manifest: selected,
+      };
+
+      // Run async apply
+      let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
+      let result = rt.block_on(apply(path, &options));
+
+      // Dropping the options closes the progress channel so the display can finish
+      drop(options);
+      if let Some(display) = display {
+        display.finish();
+      }
+      if let Some(recorder) = recorder {
+        let mut recorded = recorder.finish();
+        recorded.finish(start.elapsed(), result.as_ref().err().map(ApplyError::phase));
+        publish_metrics(&recorded, &metrics);
+      }
+      result.context("Apply failed")?
+    }
   };
 
-  // Run async apply
-  let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
-  let result = rt.block_on(apply(path, &options)).context("Apply failed")?;
+  report(&result, start.elapsed(), repair, output)?;
+
+  // Print plan directory
+  let snapshot_path = paths::snapshots_dir().join(format!("{}.json", result.snapshot.id));
+  info!(path = %snapshot_path.display(), "snapshot saved");
+
+  Ok(())
+}
+
+/// Publish the metrics of an apply, warning instead of failing the apply if
+/// they can't be.
+fn publish_metrics(metrics: &ApplyMetrics, sink: &MetricsSink) {
+  if let Err(err) = metrics.publish(sink) {
+    print_warning(&format!("Failed to publish apply metrics: {}", err));
+  }
+}
+
+/// Execute `sys apply --system`.
+///
+/// Evaluates the config here, as the calling user, and submits the manifest
+/// to the system daemon, which realizes it in the system store. With
+/// `build_only` only the builds are realized, which is all untrusted users
+/// may ask for; otherwise the daemon applies the binds too and saves a
+/// snapshot.
+pub fn cmd_apply_system(
+  file: Option<&str>,
+  manifest: Option<&Path>,
+  repair: bool,
+  eval: EvalOptions,
+  build_only: bool,
+  output: OutputFormat,
+) -> Result<()> {
+  let start = Instant::now();
+  eval.ensure_host_platform()?;
+
+  let export = match manifest {
+    Some(manifest_path) => ManifestExport::load(manifest_path)
+      .with_context(|| format!("Failed to load manifest: {}", manifest_path.display()))?,
+    None => {
+      // The daemon has its own working directory
+      let path = std::path::absolute(file.context("A config file or --manifest is required")?)
+        .context("Failed to resolve config path")?;
+      let manifest =
+        evaluate_config(&path, &eval).with_context(|| format!("Failed to evaluate config: {}", path.display()))?;
+      ManifestExport::new(manifest, Some(&path)).context("Failed to export manifest")?
+    }
+  };
+
+  // The system store's snapshots may not be readable here, so the applied
+  // snapshot is described from the submitted manifest
+  let config = export.config.as_deref().map(PathBuf::from);
+  let manifest = export.manifest.clone();
+  let request = DaemonRequest::Submit(SubmitRequest {
+    export,
+    build_only,
+    repair,
+  });
+  let response = DaemonClient::system()
+    .request(&request)
+    .context("Failed to reach the system daemon")?;
+
+  match response {
+    DaemonResponse::Apply(response) => {
+      let snapshot = Snapshot::new(response.snapshot_id.clone(), config, manifest);
+      report(&daemon_result(*response, snapshot), start.elapsed(), repair, output)
+    }
+    DaemonResponse::Built(result) => report_builds(&result, start.elapsed(), output),
+    DaemonResponse::Denied(reason) => bail!("The system daemon refused the request: {}", reason),
+    DaemonResponse::Error(e) => Err(anyhow!(e)).context("Apply failed"),
+    other => bail!("unexpected daemon response: {:?}", other),
+  }
+}
+
+/// The result of an apply a daemon ran, from its response and the snapshot
+/// it saved.
+fn daemon_result(response: ApplyResponse, snapshot: Snapshot) -> ApplyResult {
+  ApplyResult {
+    snapshot,
+    diff: response.diff,
+    execution: response.execution,
+    binds_destroyed: response.binds_destroyed,
+    binds_updated: response.binds_updated,
+    drift_results: response.drift_results,
+    store_bytes_added: response.store_bytes_added,
+  }
+}
+
+/// Print the builds the system daemon realized for `--build-only`.
+fn report_builds(result: &DagResult, elapsed: Duration, output: OutputFormat) -> Result<()> {
+  if output.is_json() {
+    return print_json(result);
+  }
+
+  if result.is_success() {
+    print_success("Build complete!");
+  } else {
+    print_error("Build failed!");
+  }
+  print_stat("Builds realized", &result.realized.len().to_string());
+  print_stat("Duration", &format_duration(elapsed));
+  if let Some((hash, err)) = &result.build_failed {
+    print_error(&format!("Build failed: {} - {}", truncate_hash(&hash.0), err));
+  }
+  Ok(())
+}
+
+/// Print the result of an apply, run here or by a daemon.
+fn report(result: &ApplyResult, elapsed: Duration, repair: bool, output: OutputFormat) -> Result<()> {
+  let summary = ApplySummary::new(result, elapsed);
 
   if output.is_json() {
-    print_json(&result)?;
+    #[derive(Serialize)]
+    struct ApplyOutput<'a> {
+      #[serde(flatten)]
+      result: &'a ApplyResult,
+      summary: ApplySummary,
+    }
+
+    print_json(&ApplyOutput { result, summary })?;
   } else {
     println!();
     print_success("Apply complete!");
     print_stat("Snapshot", truncate_hash(&result.snapshot.id));
-    print_stat("Builds realized", &result.execution.realized.len().to_string());
-    print_stat("Builds cached", &result.diff.builds_cached.len().to_string());
-    print_stat("Binds applied", &result.execution.applied.len().to_string());
-    print_stat("Binds updated", &result.binds_updated.to_string());
-    print_stat("Binds destroyed", &result.binds_destroyed.to_string());
-    print_stat("Binds unchanged", &result.diff.binds_unchanged.len().to_string());
-    print_stat("Duration", &format_duration(start.elapsed()));
+    summary.print();
+    print_input_overrides(&result.snapshot.input_overrides);
+    print_skipped_builds(&result.snapshot.manifest.skipped_builds);
+    print_skipped_binds(&result.snapshot.manifest.skipped);
+    print_deprecations(&result.snapshot.manifest.deprecations);
 
     let drifted_count = result.drift_results.iter().filter(|r| r.result.drifted).count();
     if drifted_count > 0 {
@@ -78,27 +337,125 @@ pub fn cmd_apply(file: &str, repair: bool, impure: bool, output: OutputFormat) -
             id
           );
         }
+        if let Some(ref reason) = drift.repair_skipped {
+          eprintln!(
+            "      {}",
+            format!("not repaired: {}", reason).if_supports_color(Stream::Stderr, |s| s.dimmed())
+          );
+        }
       }
+      let repaired_count = result.drift_results.iter().filter(|r| r.needs_repair()).count();
       if repair {
-        print_info(&format!("Binds repaired: {}", drifted_count));
-      } else {
+        print_info(&format!("Binds repaired: {}", repaired_count));
+      } else if repaired_count > 0 {
         print_info("Run with --repair to fix drifted binds");
       }
     }
 
     if !result.execution.is_success() {
-      if let Some((hash, ref err)) = result.execution.build_failed {
+      if let Some((hash, err)) = &result.execution.build_failed {
         print_error(&format!("Build failed: {} - {}", truncate_hash(&hash.0), err));
       }
-      if let Some((hash, ref err)) = result.execution.bind_failed {
+      if let Some((hash, err)) = &result.execution.bind_failed {
         print_error(&format!("Bind failed: {} - {}", truncate_hash(&hash.0), err));
       }
     }
   }
 
-  // Print plan directory
-  let snapshot_path = paths::snapshots_dir().join(format!("{}.json", result.snapshot.id));
-  info!(path = %snapshot_path.display(), "snapshot saved");
-
   Ok(())
 }
+
+/// Skip the pending bind changes of the desired manifest outside `groups`.
+fn select_groups(desired: Manifest, groups: &[String]) -> Result<Manifest> {
+  let current_snapshot = SnapshotStore::default_store()
+    .load_current()
+    .context("Failed to load current snapshot")?;
+  let current = current_snapshot.as_ref().map(|s| &s.manifest);
+  let diff = compute_diff(&desired, current, &paths::store_dir());
+
+  let outside = changes_outside_groups(&desired, current, &diff, groups)?;
+  if outside.is_empty() {
+    return Ok(desired);
+  }
+  let manifest = deselect_changes(&desired, current, &diff, &outside).context("Failed to skip changes")?;
+  print_info(&format!(
+    "Leaving {} change(s) outside the selected groups pending",
+    outside.len()
+  ));
+  Ok(manifest)
+}
+
+/// Let the user skip pending bind changes of the desired manifest.
+///
+/// Returns the manifest to apply, or `None` if the user aborted.
+fn select_changes(desired: Manifest) -> Result<Option<Manifest>> {
+  let current_snapshot = SnapshotStore::default_store()
+    .load_current()
+    .context("Failed to load current snapshot")?;
+  let current = current_snapshot.as_ref().map(|s| &s.manifest);
+  let store_path = paths::store_dir();
+  let diff = compute_diff(&desired, current, &store_path);
+
+  let label = |manifest: Option<&Manifest>, hash: &ObjectHash| {
+    manifest
+      .and_then(|m| m.bindings.get(hash))
+      .and_then(|b| b.id.clone())
+      .unwrap_or_else(|| "(unnamed)".to_string())
+  };
+  let mut changes: Vec<(&ObjectHash, String)> = Vec::new();
+  for hash in &diff.binds_to_apply {
+    let item = format!(
+      "{} {} ({})",
+      symbols::ADD,
+      label(Some(&desired), hash),
+      truncate_hash(&hash.0)
+    );
+    changes.push((hash, item));
+  }
+  for (old, new) in &diff.binds_to_update {
+    let item = format!(
+      "{} {} ({} {} {})",
+      symbols::MODIFY,
+      label(Some(&desired), new),
+      truncate_hash(&old.0),
+      symbols::ARROW,
+      truncate_hash(&new.0)
+    );
+    changes.push((new, item));
+  }
+  for hash in &diff.binds_to_destroy {
+    let item = format!(
+      "{} {} ({})",
+      symbols::REMOVE,
+      label(current, hash),
+      truncate_hash(&hash.0)
+    );
+    changes.push((hash, item));
+  }
+
+  if changes.is_empty() {
+    return Ok(Some(desired));
+  }
+
+  let items: Vec<String> = changes.iter().map(|(_, item)| item.clone()).collect();
+  let Some(skipped) = select_skipped("Pending changes:", &items)? else {
+    return Ok(None);
+  };
+  if skipped.is_empty() {
+    return Ok(Some(desired));
+  }
+
+  let deselected: HashSet<ObjectHash> = skipped.iter().map(|&i| changes[i].0.clone()).collect();
+  let manifest = deselect_changes(&desired, current, &diff, &deselected).context("Failed to skip changes")?;
+
+  // Changes that depend on a skipped one are skipped with it
+  let remaining = compute_diff(&manifest, current, &store_path);
+  let remaining_count =
+    remaining.binds_to_apply.len() + remaining.binds_to_update.len() + remaining.binds_to_destroy.len();
+  let also_skipped = (changes.len() - skipped.len()).saturating_sub(remaining_count);
+  if also_skipped > 0 {
+    print_warning(&format!("Also skipping {} dependent change(s)", also_skipped));
+  }
+
+  Ok(Some(manifest))
+}
@@ -0,0 +1,94 @@
+//! Implementation of the `sys completions` command.
+//!
+//! Prints a script registering completion for a shell. The script calls `sys`
+//! back with `COMPLETE=<shell>` set, so besides subcommands and flags it
+//! completes snapshot IDs, bind ids, tags and groups, and locked input names
+//! from the current store and lock file.
+
+use std::ffi::OsStr;
+use std::io;
+
+use anyhow::{Context, Result};
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::{self, EnvCompleter};
+
+use syslua_lib::completion::{self, Candidate};
+
+/// Environment variable that switches `sys` into completion mode.
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Name of the executable the scripts complete.
+pub const BIN_NAME: &str = "sys";
+
+/// Shells `sys completions` can print a script for.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum CompletionShell {
+  Bash,
+  Zsh,
+  Fish,
+}
+
+pub fn cmd_completions(shell: CompletionShell) -> Result<()> {
+  let mut stdout = io::stdout();
+  let registration = match shell {
+    CompletionShell::Bash => env::Bash.write_registration(COMPLETE_VAR, BIN_NAME, BIN_NAME, BIN_NAME, &mut stdout),
+    CompletionShell::Zsh => env::Zsh.write_registration(COMPLETE_VAR, BIN_NAME, BIN_NAME, BIN_NAME, &mut stdout),
+    CompletionShell::Fish => env::Fish.write_registration(COMPLETE_VAR, BIN_NAME, BIN_NAME, BIN_NAME, &mut stdout),
+  };
+  registration.context("Failed to write completion script")
+}
+
+/// Complete snapshot IDs, newest first.
+pub fn complete_snapshot_ids(current: &OsStr) -> Vec<CompletionCandidate> {
+  matching(current, completion::snapshot_ids())
+}
+
+/// Complete bind ids and tags of the current snapshot.
+pub fn complete_bind_selectors(current: &OsStr) -> Vec<CompletionCandidate> {
+  matching(current, completion::bind_selectors())
+}
+
+/// Complete bind groups of the current snapshot.
+pub fn complete_bind_groups(current: &OsStr) -> Vec<CompletionCandidate> {
+  matching(current, completion::bind_groups())
+}
+
+/// Complete input names from the lock file of the default config.
+pub fn complete_input_names(current: &OsStr) -> Vec<CompletionCandidate> {
+  matching(current, completion::input_names(None))
+}
+
+/// The candidates starting with what has been typed so far.
+fn matching(current: &OsStr, candidates: Vec<Candidate>) -> Vec<CompletionCandidate> {
+  let prefix = current.to_string_lossy();
+  candidates
+    .into_iter()
+    .filter(|c| c.value.starts_with(prefix.as_ref()))
+    .map(|c| CompletionCandidate::new(c.value).help(c.help.map(Into::into)))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn matching_filters_by_prefix() {
+    let candidates = vec![
+      Candidate {
+        value: "nvim".to_string(),
+        help: Some("bind".to_string()),
+      },
+      Candidate {
+        value: "editor".to_string(),
+        help: None,
+      },
+    ];
+
+    let values: Vec<_> = matching(OsStr::new("nv"), candidates)
+      .iter()
+      .map(|c| c.get_value().to_string_lossy().into_owned())
+      .collect();
+    assert_eq!(values, ["nvim"]);
+  }
+}
@@ -0,0 +1,188 @@
+//! Implementation of the `sys daemon` command.
+//!
+//! Runs a long-lived process that keeps evaluated configs warm and serves
+//! plan/apply requests from the CLI over a unix socket (named pipe on Windows).
+//!
+//! With `--system` the daemon runs as root on a socket every local user can
+//! connect to, and realizes the manifests they submit with
+//! `sys apply --system` in the system store.
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use clap::Subcommand;
+
+use syslua_lib::api::{ApplyRequest, ApplyResponse, PlanRequest, PlanResponse};
+use syslua_lib::build::users::{BuildUsers, DEFAULT_BUILD_GROUP};
+use syslua_lib::daemon::{DaemonClient, DaemonPolicy, DaemonRequest, DaemonResponse, serve, serve_system};
+use syslua_lib::platform::is_elevated;
+use syslua_lib::platform::paths::{daemon_socket_path, system_daemon_socket_path};
+
+use crate::output::{OutputFormat, format_duration, print_info, print_json, print_stat, print_success, print_warning};
+
+#[derive(Subcommand, Debug)]
+pub enum DaemonCommand {
+  /// Run the daemon in the foreground until stopped
+  Start {
+    /// Run the system daemon, which builds for every local user (requires root)
+    #[arg(long)]
+    system: bool,
+
+    /// User with full access to the system daemon (repeatable)
+    #[arg(long = "trusted-user", value_name = "USER", requires = "system")]
+    trusted_users: Vec<String>,
+
+    /// Only let these users connect to the system daemon (repeatable)
+    #[arg(long = "allowed-user", value_name = "USER", requires = "system")]
+    allowed_users: Vec<String>,
+
+    /// Group whose members run the builds of untrusted users, one build each at a time
+    #[arg(long, value_name = "GROUP", default_value = DEFAULT_BUILD_GROUP, requires = "system")]
+    build_group: String,
+  },
+
+  /// Ask a running daemon to exit
+  Stop {
+    /// Stop the system daemon
+    #[arg(long)]
+    system: bool,
+  },
+
+  /// Show whether a daemon is running and what it has cached
+  Status {
+    /// Show the system daemon
+    #[arg(long)]
+    system: bool,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+}
+
+pub fn cmd_daemon(command: DaemonCommand) -> Result<()> {
+  match command {
+    DaemonCommand::Start { system: false, .. } => cmd_start(),
+    DaemonCommand::Start {
+      system: true,
+      trusted_users,
+      allowed_users,
+      build_group,
+    } => cmd_start_system(DaemonPolicy {
+      trusted_users: BTreeSet::from_iter(trusted_users),
+      allowed_users: BTreeSet::from_iter(allowed_users),
+      build_group,
+    }),
+    DaemonCommand::Stop { system } => cmd_stop(system),
+    DaemonCommand::Status { system, output } => cmd_status(system, output),
+  }
+}
+
+fn client(system: bool) -> DaemonClient {
+  if system {
+    DaemonClient::system()
+  } else {
+    DaemonClient::new(daemon_socket_path())
+  }
+}
+
+fn cmd_start() -> Result<()> {
+  let path = daemon_socket_path();
+  print_info(&format!("Daemon listening on {}", path.display()));
+
+  let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
+  rt.block_on(serve(&path)).context("Daemon failed")?;
+
+  print_success("Daemon stopped");
+  Ok(())
+}
+
+fn cmd_start_system(policy: DaemonPolicy) -> Result<()> {
+  if !is_elevated() {
+    bail!("The system daemon must run as root");
+  }
+
+  let path = system_daemon_socket_path();
+  print_info(&format!("System daemon listening on {}", path.display()));
+  match BuildUsers::from_group(&policy.build_group) {
+    Ok(users) => print_stat("Build users", &format!("{} ({} users)", users.group(), users.count())),
+    Err(e) => print_warning(&format!("Untrusted users can't submit builds: {}", e)),
+  }
+
+  let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
+  rt.block_on(serve_system(&path, policy)).context("Daemon failed")?;
+
+  print_success("Daemon stopped");
+  Ok(())
+}
+
+fn cmd_stop(system: bool) -> Result<()> {
+  let client = client(system);
+  match client.request(&DaemonRequest::Shutdown) {
+    Ok(DaemonResponse::Denied(reason)) => bail!("{}", reason),
+    Ok(_) => print_success("Daemon stopped"),
+    Err(_) => print_info("No daemon running"),
+  }
+  Ok(())
+}
+
+fn cmd_status(system: bool, output: OutputFormat) -> Result<()> {
+  let client = client(system);
+  let status = match client.request(&DaemonRequest::Status) {
+    Ok(DaemonResponse::Status(status)) => Some(status),
+    Ok(DaemonResponse::Denied(reason)) => bail!("{}", reason),
+    Ok(other) => bail!("unexpected daemon response: {:?}", other),
+    Err(_) => None,
+  };
+
+  if output.is_json() {
+    return print_json(&serde_json::json!({ "running": status.is_some(), "status": status }));
+  }
+
+  match status {
+    Some(status) => {
+      print_success(&format!("Daemon running (pid {})", status.pid));
+      print_stat("Socket", &client.path().display().to_string());
+      print_stat("Store", &status.store.display().to_string());
+      print_stat("System", if status.system { "yes" } else { "no" });
+      print_stat("Uptime", &format_duration(Duration::from_secs(status.uptime_secs)));
+      print_stat("Requests", &status.requests_served.to_string());
+      print_stat("Cached configs", &status.cached_configs.to_string());
+      print_stat(
+        "Cache hits",
+        &format!("{} hit(s), {} miss(es)", status.cache_hits, status.cache_misses),
+      );
+    }
+    None => print_info("No daemon running"),
+  }
+  Ok(())
+}
+
+/// Run an apply on the daemon.
+pub fn delegate_apply(client: &DaemonClient, mut request: ApplyRequest) -> Result<ApplyResponse> {
+  // The daemon has its own working directory
+  request.config = std::path::absolute(&request.config).context("Failed to resolve config path")?;
+  match client
+    .request(&DaemonRequest::Apply(request))
+    .context("Daemon request failed")?
+  {
+    DaemonResponse::Apply(response) => Ok(*response),
+    DaemonResponse::Error(e) => Err(anyhow!(e)),
+    other => bail!("unexpected daemon response: {:?}", other),
+  }
+}
+
+/// Run a plan on the daemon.
+pub fn delegate_plan(client: &DaemonClient, mut request: PlanRequest) -> Result<PlanResponse> {
+  // The daemon has its own working directory
+  request.config = std::path::absolute(&request.config).context("Failed to resolve config path")?;
+  match client
+    .request(&DaemonRequest::Plan(request))
+    .context("Daemon request failed")?
+  {
+    DaemonResponse::Plan(response) => Ok(*response),
+    DaemonResponse::Error(e) => Err(anyhow!(e)),
+    other => bail!("unexpected daemon response: {:?}", other),
+  }
+}
@@ -1,7 +1,8 @@
 //! Implementation of the `sys destroy` command.
 //!
 //! This command destroys all binds from the current snapshot, effectively
-//! removing everything syslua has applied.
+//! removing everything syslua has applied. With `--only` or `--group`, just the
+//! selected binds and their dependents are destroyed and the rest stays applied.
 
 use std::time::Instant;
 
@@ -20,15 +21,18 @@ use crate::output::{OutputFormat, format_duration, print_json, print_stat, symbo
 /// - Loads current state from snapshots
 /// - Executes destroy_actions for each bind in reverse dependency order
 /// - Cleans up bind state files
-/// - Clears the current snapshot pointer
+/// - Clears the current snapshot pointer, or for `--only`/`--group` saves a new
+///   snapshot of the remaining binds
 ///
 /// Prints a summary including counts of binds destroyed and builds orphaned.
-pub fn cmd_destroy(dry_run: bool, output: OutputFormat) -> Result<()> {
+pub fn cmd_destroy(dry_run: bool, only: Vec<String>, groups: Vec<String>, output: OutputFormat) -> Result<()> {
   let start = Instant::now();
 
   // Log environment info for debugging
   info!(
     dry_run = dry_run,
+    only = ?only,
+    groups = ?groups,
     store = %store_dir().display(),
     data_dir = %data_dir().display(),
     "destroy command starting"
@@ -48,6 +52,8 @@ pub fn cmd_destroy(dry_run: bool, output: OutputFormat) -> Result<()> {
   let options = DestroyOptions {
     execute: ExecuteConfig::default(),
     dry_run,
+    only,
+    groups,
   };
 
   // Run async destroy
@@ -71,6 +77,7 @@ pub fn cmd_destroy(dry_run: bool, output: OutputFormat) -> Result<()> {
         "Would orphan",
         &format!("{} build(s) (for future GC)", result.builds_orphaned),
       );
+      print_destroyed(&result.destroyed);
     } else if result.binds_destroyed == 0 {
       println!("{} Nothing to destroy.", symbols::INFO.dimmed());
     } else {
@@ -80,9 +87,24 @@ pub fn cmd_destroy(dry_run: bool, output: OutputFormat) -> Result<()> {
         "Builds orphaned",
         &format!("{} (for future GC)", result.builds_orphaned),
       );
+      if let Some(snapshot) = &result.snapshot {
+        print_stat("Snapshot", snapshot);
+      }
       print_stat("Duration", &format_duration(start.elapsed()));
+      print_destroyed(&result.destroyed);
     }
   }
 
   Ok(())
 }
+
+/// List the binds selected by `--only`.
+fn print_destroyed(destroyed: &[String]) {
+  if destroyed.is_empty() {
+    return;
+  }
+  println!();
+  for bind in destroyed {
+    println!("  {} {}", symbols::REMOVE.red(), bind);
+  }
+}
@@ -1,24 +1,32 @@
 //! Diff command implementation.
 //!
-//! Compares two snapshots and displays added/removed/updated builds and binds.
+//! Compares two snapshots and displays added/removed/updated builds and binds,
+//! as a summary, in detail, or side by side.
 
 use anyhow::{Context, Result, bail};
 use owo_colors::{OwoColorize, Stream};
 
 use syslua_lib::action::Action;
+use syslua_lib::action::actions::config_section::{ConfigFormat, SectionState};
 use syslua_lib::action::actions::exec::ExecOpts;
+use syslua_lib::action::actions::file::FileState;
+use syslua_lib::action::actions::file_block::BlockState;
+use syslua_lib::action::actions::firewall::RuleState;
+use syslua_lib::action::actions::font::FontState;
 use syslua_lib::bind::BindDef;
 use syslua_lib::build::BuildDef;
 use syslua_lib::platform::paths::{snapshots_dir, store_dir};
 use syslua_lib::snapshot::{Snapshot, SnapshotStore, StateDiff, compute_diff};
 use syslua_lib::util::hash::ObjectHash;
 
-use crate::output::{OutputFormat, print_json, symbols, truncate_hash};
+use crate::output::pager::page_output;
+use crate::output::{DiffRow, OutputFormat, print_json, print_side_by_side, symbols, truncate_hash};
 
 pub fn cmd_diff(
   snapshot_a: Option<String>,
   snapshot_b: Option<String>,
   verbose: bool,
+  side_by_side: bool,
   output: OutputFormat,
 ) -> Result<()> {
   let store = SnapshotStore::new(snapshots_dir());
@@ -36,7 +44,8 @@ pub fn cmd_diff(
     });
     print_json(&diff_output)?;
   } else {
-    print_human_diff(&snap_a, &snap_b, &diff, verbose);
+    let _pager = page_output(None);
+    print_human_diff(&snap_a, &snap_b, &diff, verbose, side_by_side);
   }
 
   Ok(())
@@ -92,8 +101,9 @@ fn load_snapshots_to_compare(
   }
 }
 
-fn print_human_diff(snap_a: &Snapshot, snap_b: &Snapshot, diff: &StateDiff, verbose: bool) {
+fn print_human_diff(snap_a: &Snapshot, snap_b: &Snapshot, diff: &StateDiff, verbose: bool, side_by_side: bool) {
   println!("Comparing {} → {}", snap_a.id, snap_b.id);
+  print_provenance_change(snap_a, snap_b);
   println!();
 
   if diff.is_empty()
@@ -105,13 +115,65 @@ fn print_human_diff(snap_a: &Snapshot, snap_b: &Snapshot, diff: &StateDiff, verb
     return;
   }
 
-  if verbose {
+  if side_by_side {
+    print_side_by_side_diff(snap_a, snap_b, diff);
+  } else if verbose {
     print_verbose_diff(snap_a, snap_b, diff);
   } else {
     print_summary_diff(diff);
   }
 }
 
+/// Show where each snapshot's config came from, if recorded.
+fn print_provenance_change(snap_a: &Snapshot, snap_b: &Snapshot) {
+  let describe = |snap: &Snapshot| match &snap.provenance {
+    Some(p) => {
+      let source = match (&p.git_commit, &p.config_hash) {
+        (Some(commit), _) => format!("commit {}", truncate_hash(commit)),
+        (None, Some(hash)) => format!("config {}", truncate_hash(hash)),
+        (None, None) => "unknown config".to_string(),
+      };
+      let host = p.hostname.as_deref().unwrap_or("unknown host");
+      format!("{} on {} (syslua {})", source, host, p.syslua_version)
+    }
+    None => "no provenance recorded".to_string(),
+  };
+  if snap_a.provenance.is_none() && snap_b.provenance.is_none() {
+    return;
+  }
+  println!("  {}: {}", snap_a.id, describe(snap_a));
+  println!("  {}: {}", snap_b.id, describe(snap_b));
+
+  if let (Some(a), Some(b)) = (&snap_a.provenance, &snap_b.provenance) {
+    let names: std::collections::BTreeSet<_> = a.inputs.keys().chain(b.inputs.keys()).collect();
+    for name in names {
+      match (a.inputs.get(name), b.inputs.get(name)) {
+        (Some(old), Some(new)) if old != new => println!(
+          "  {} input {}: {} {} {}",
+          symbols::TILDE.if_supports_color(Stream::Stdout, |s| s.yellow()),
+          name,
+          truncate_hash(old),
+          symbols::ARROW,
+          truncate_hash(new)
+        ),
+        (None, Some(new)) => println!(
+          "  {} input {}: {}",
+          symbols::PLUS.if_supports_color(Stream::Stdout, |s| s.green()),
+          name,
+          truncate_hash(new)
+        ),
+        (Some(old), None) => println!(
+          "  {} input {}: {}",
+          symbols::MINUS.if_supports_color(Stream::Stdout, |s| s.red()),
+          name,
+          truncate_hash(old)
+        ),
+        _ => {}
+      }
+    }
+  }
+}
+
 fn print_summary_diff(diff: &StateDiff) {
   let has_build_changes = !diff.builds_to_realize.is_empty() || !diff.builds_orphaned.is_empty();
   let has_bind_changes =
@@ -227,6 +289,78 @@ fn print_verbose_diff(snap_a: &Snapshot, snap_b: &Snapshot, diff: &StateDiff) {
   }
 }
 
+/// Show removed and added builds and binds in two columns, lining up builds
+/// with the same id and updated binds.
+fn print_side_by_side_diff(snap_a: &Snapshot, snap_b: &Snapshot, diff: &StateDiff) {
+  let label =
+    |id: Option<&str>, hash: &ObjectHash| format!("{} ({})", id.unwrap_or("(unnamed)"), truncate_hash(&hash.0));
+  let build_id = |snap: &Snapshot, hash: &ObjectHash| snap.manifest.builds.get(hash).and_then(|b| b.id.clone());
+  let bind_id = |snap: &Snapshot, hash: &ObjectHash| snap.manifest.bindings.get(hash).and_then(|b| b.id.clone());
+
+  // Builds are keyed by hash, so a changed build is one id removed and added
+  let mut removed: Vec<(Option<String>, DiffRow)> = diff
+    .builds_orphaned
+    .iter()
+    .map(|hash| {
+      let id = build_id(snap_a, hash);
+      let old = label(id.as_deref(), hash);
+      (
+        id,
+        DiffRow {
+          old: Some(old),
+          new: None,
+        },
+      )
+    })
+    .collect();
+  for hash in &diff.builds_to_realize {
+    let id = build_id(snap_b, hash);
+    let new = Some(label(id.as_deref(), hash));
+    match removed
+      .iter_mut()
+      .find(|(old_id, row)| id.is_some() && *old_id == id && row.new.is_none())
+    {
+      Some((_, row)) => row.new = new,
+      None => removed.push((None, DiffRow { old: None, new })),
+    }
+  }
+  let builds: Vec<DiffRow> = removed.into_iter().map(|(_, row)| row).collect();
+
+  let binds: Vec<DiffRow> = diff
+    .binds_to_update
+    .iter()
+    .map(|(old, new)| DiffRow {
+      old: Some(label(bind_id(snap_a, old).as_deref(), old)),
+      new: Some(label(bind_id(snap_b, new).as_deref(), new)),
+    })
+    .chain(diff.binds_to_destroy.iter().map(|hash| DiffRow {
+      old: Some(label(bind_id(snap_a, hash).as_deref(), hash)),
+      new: None,
+    }))
+    .chain(diff.binds_to_apply.iter().map(|hash| DiffRow {
+      old: None,
+      new: Some(label(bind_id(snap_b, hash).as_deref(), hash)),
+    }))
+    .collect();
+
+  if !builds.is_empty() {
+    println!("Builds:");
+    print_side_by_side(&snap_a.id, &snap_b.id, &builds);
+    println!();
+  }
+  if !binds.is_empty() {
+    println!("Binds:");
+    print_side_by_side(&snap_a.id, &snap_b.id, &binds);
+    println!();
+  }
+  if builds.is_empty() && binds.is_empty() {
+    println!("No changes.");
+  }
+  if !diff.binds_unchanged.is_empty() {
+    println!("Binds unchanged: {}", diff.binds_unchanged.len());
+  }
+}
+
 fn print_build(hash: &ObjectHash, build: &BuildDef, prefix: &str) {
   let name = build.id.as_deref().unwrap_or("(unnamed)");
   let short_hash = truncate_hash(&hash.0);
@@ -300,9 +434,65 @@ fn print_actions(label: &str, actions: &[Action]) {
 fn format_action(action: &Action) -> String {
   match action {
     Action::Exec(opts) => format_exec(opts),
-    Action::FetchUrl { url, sha256 } => {
+    Action::FetchUrl { url, sha256, unpack } => {
       let short_sha = truncate_hash(sha256);
-      format!("fetch_url: {} (sha256: {}...)", url, short_sha)
+      let unpack = if *unpack { ", unpack" } else { "" };
+      format!("fetch_url: {} (sha256: {}...{})", url, short_sha, unpack)
+    }
+    Action::ConfigSection(opts) => {
+      let method = match opts.format {
+        ConfigFormat::Git => "git_config",
+        ConfigFormat::Ssh => "ssh_config",
+      };
+      let state = match opts.state {
+        SectionState::Present => "present",
+        SectionState::Absent => "absent",
+        SectionState::Check => "check",
+      };
+      format!("{}: {} in {} ({})", method, opts.header(), opts.path, state)
+    }
+    Action::Firewall(opts) => {
+      let state = match opts.state {
+        RuleState::Present => "present",
+        RuleState::Absent => "absent",
+        RuleState::Check => "check",
+      };
+      format!("firewall_rule: {} ({})", opts.rule.name, state)
+    }
+    Action::File(opts) => {
+      let state = match opts.state {
+        FileState::Present => "present",
+        FileState::Absent => "absent",
+        FileState::Check => "check",
+      };
+      let attrs = if opts.attrs.is_empty() {
+        String::new()
+      } else {
+        format!(", {}", opts.attrs)
+      };
+      format!("{}: {} ({}{})", opts.source.method(), opts.path, state, attrs)
+    }
+    Action::FileBlock(opts) => {
+      let state = match opts.state {
+        BlockState::Present => "present",
+        BlockState::Absent => "absent",
+        BlockState::Check => "check",
+      };
+      format!("file_block: {} in {} ({})", opts.marker, opts.path, state)
+    }
+    Action::Font(opts) => {
+      let state = match opts.state {
+        FontState::Present => "present",
+        FontState::Absent => "absent",
+        FontState::Check => "check",
+      };
+      format!(
+        "font: {} from {} ({}, {})",
+        opts.name,
+        opts.source,
+        opts.scope.as_str(),
+        state
+      )
     }
   }
 }
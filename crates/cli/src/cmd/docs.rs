@@ -0,0 +1,167 @@
+//! Implementation of the `sys docs` command.
+//!
+//! `sys docs man` renders man pages with clap_mangen from the same clap
+//! definition that `--help` and the shell completions use: `sys(1)` with the
+//! reference sections of [`crate::help`], and one page per subcommand, named
+//! like `sys-store-du(1)` for `sys store du`.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Command, CommandFactory, Subcommand};
+use clap_mangen::Man;
+use roff::{Roff, bold, roman};
+
+use crate::cmd::completions::BIN_NAME;
+use crate::help::{HelpTopic, TOPICS};
+use crate::output::print_success;
+
+/// Manual section of the pages.
+const SECTION: &str = "1";
+
+#[derive(Subcommand, Debug)]
+pub enum DocsCommand {
+  /// Write man pages for sys and each of its subcommands
+  Man {
+    /// Directory to write the pages to; without it, sys.1 is printed
+    #[arg(long, value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+  },
+}
+
+pub fn cmd_docs(command: DocsCommand) -> Result<()> {
+  match command {
+    DocsCommand::Man { out_dir } => cmd_man(out_dir.as_deref()),
+  }
+}
+
+fn cmd_man(out_dir: Option<&Path>) -> Result<()> {
+  let pages = man_pages()?;
+
+  let Some(out_dir) = out_dir else {
+    let (_, root) = &pages[0];
+    return io::stdout()
+      .write_all(root.as_bytes())
+      .context("Failed to write man page");
+  };
+
+  fs::create_dir_all(out_dir).with_context(|| format!("Failed to create {}", out_dir.display()))?;
+  for (name, page) in &pages {
+    let path = out_dir.join(name);
+    fs::write(&path, page).with_context(|| format!("Failed to write {}", path.display()))?;
+  }
+  print_success(&format!("Wrote {} man pages to {}", pages.len(), out_dir.display()));
+  Ok(())
+}
+
+/// File names and contents of all pages, `sys.1` first.
+fn man_pages() -> Result<Vec<(String, String)>> {
+  let mut root = crate::Cli::command();
+  // Propagates global options to the subcommands and names them `sys-store-du`
+  root.build();
+
+  let mut pages = Vec::new();
+  collect_pages(&root, &[BIN_NAME.to_string()], &mut pages)?;
+  Ok(pages)
+}
+
+fn collect_pages(cmd: &Command, path: &[String], pages: &mut Vec<(String, String)>) -> Result<()> {
+  pages.push(render_page(cmd, path)?);
+  for sub in visible_subcommands(cmd) {
+    let mut sub_path = path.to_vec();
+    sub_path.push(sub.get_name().to_string());
+    collect_pages(sub, &sub_path, pages)?;
+  }
+  Ok(())
+}
+
+fn visible_subcommands(cmd: &Command) -> impl Iterator<Item = &Command> {
+  cmd
+    .get_subcommands()
+    .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+}
+
+/// File name and roff source of the page for `cmd`, invoked as `path`.
+///
+/// clap_mangen renders and escapes the sections derived from the command;
+/// the reference topics and the link to the parent page are appended with
+/// [`roff`], which escapes them the same way.
+fn render_page(cmd: &Command, path: &[String]) -> Result<(String, String)> {
+  let man = Man::new(cmd.clone())
+    .title(path.join("-").to_uppercase())
+    .section(SECTION)
+    .source(format!("{} {}", BIN_NAME, env!("CARGO_PKG_VERSION")))
+    .manual("syslua manual");
+
+  let mut page = Vec::new();
+  man
+    .render(&mut page)
+    .with_context(|| format!("Failed to render the man page of {}", path.join(" ")))?;
+
+  let mut extra = Roff::new();
+  if path.len() == 1 {
+    for topic in TOPICS {
+      render_topic(&mut extra, topic);
+    }
+  } else {
+    extra
+      .control("SH", ["SEE ALSO"])
+      .text([bold(path[..path.len() - 1].join("-")), roman(format!("({})", SECTION))]);
+  }
+  let mut page = String::from_utf8(page).context("Man page is not valid UTF-8")?;
+  page.push_str(&extra.to_roff());
+
+  Ok((format!("{}.{}", path.join("-"), SECTION), page))
+}
+
+/// A reference section of [`crate::help`] as a man page section.
+fn render_topic(roff: &mut Roff, topic: &HelpTopic) {
+  roff
+    .control("SH", [topic.title.to_uppercase().as_str()])
+    .text([roman(topic.intro)]);
+  for (term, description) in topic.entries {
+    roff.control("TP", []).text([bold(*term)]).text([roman(*description)]);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn help_text_cannot_inject_roff() {
+    let cmd = Command::new("evil")
+      .about("line one")
+      .long_about("first\n.SH INJECTED\n'\\\" comment\nback\\slash")
+      .arg(clap::Arg::new("flag").long("flag").help(".TH OVERRIDE"));
+    let (_, page) = render_page(&cmd, &["evil".to_string()]).unwrap();
+
+    assert!(!page.lines().any(|line| line.starts_with(".SH INJECTED")));
+    assert!(!page.lines().any(|line| line.starts_with(".TH OVERRIDE")));
+    assert!(!page.lines().any(|line| line.starts_with("'\\\"")));
+    assert!(!page.contains("back\\slash"));
+  }
+
+  #[test]
+  fn pages_cover_every_subcommand() {
+    let pages = man_pages().unwrap();
+    let names: Vec<&str> = pages.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names[0], "sys.1");
+    assert!(names.contains(&"sys-plan.1"));
+    assert!(names.contains(&"sys-store-du.1"));
+    assert!(names.contains(&"sys-docs-man.1"));
+    assert!(!names.iter().any(|name| name.contains("help")));
+
+    let (_, root) = &pages[0];
+    assert!(root.contains(".TH SYS 1"));
+    assert!(root.contains(".SH PLACEHOLDERS"));
+    assert!(root.contains("LUA API"));
+
+    // Global options appear on the subcommand pages
+    let (_, plan) = pages.iter().find(|(name, _)| name == "sys-plan.1").unwrap();
+    assert!(plan.contains("\\-\\-no\\-pager"));
+    assert!(plan.contains("SEE ALSO"));
+  }
+}
@@ -0,0 +1,70 @@
+//! Implementation of the `sys eval` command.
+//!
+//! Evaluates a Lua configuration file into a versioned manifest document that
+//! `sys apply --manifest` can apply on other machines without evaluating again.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use syslua_lib::eval::{EvalOptions, evaluate_config};
+use syslua_lib::lua::sandbox::UntrustedInputs;
+use syslua_lib::manifest::ManifestExport;
+use syslua_lib::platform::Platform;
+
+use crate::output::{
+  print_deprecations, print_overridden_builds, print_skipped_binds, print_skipped_builds, print_stat, print_success,
+};
+
+/// Execute the eval command.
+///
+/// Writes the manifest document to `output`, or to stdout when no file is given.
+/// With `platform`, the config is evaluated for that platform, and only
+/// machines of it accept the document.
+pub fn cmd_eval(
+  file: &str,
+  impure: bool,
+  input_overrides: BTreeMap<String, String>,
+  untrusted_inputs: UntrustedInputs,
+  vars_file: Option<PathBuf>,
+  platform: Option<Platform>,
+  output: Option<&Path>,
+) -> Result<()> {
+  let path = Path::new(file);
+  let eval_options = EvalOptions {
+    impure,
+    input_overrides,
+    untrusted_inputs,
+    vars_file,
+    platform,
+  };
+  let manifest =
+    evaluate_config(path, &eval_options).with_context(|| format!("Failed to evaluate config: {}", file))?;
+
+  let config_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+  let export = match platform {
+    Some(platform) => ManifestExport::for_platform(manifest, Some(&config_path), platform),
+    None => ManifestExport::new(manifest, Some(&config_path)).context("Failed to export manifest")?,
+  };
+  let json = export.to_json()?;
+
+  let Some(output) = output else {
+    println!("{}", json);
+    return Ok(());
+  };
+
+  fs::write(output, format!("{}\n", json)).with_context(|| format!("Failed to write {}", output.display()))?;
+
+  print_success(&format!("Manifest written to {}", output.display()));
+  print_stat("Platform", &export.platform);
+  print_stat("Builds", &export.manifest.builds.len().to_string());
+  print_stat("Binds", &export.manifest.bindings.len().to_string());
+  print_skipped_builds(&export.manifest.skipped_builds);
+  print_skipped_binds(&export.manifest.skipped);
+  print_overridden_builds(&export.manifest.overridden);
+  print_deprecations(&export.manifest.deprecations);
+
+  Ok(())
+}
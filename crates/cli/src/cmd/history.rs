@@ -0,0 +1,146 @@
+//! Implementation of the `sys history` command.
+//!
+//! Shows the journal of applies, destroys and repairs kept in the store, newest last,
+//! and verifies its hash chain and signatures. See
+//! [`syslua_lib::snapshot::JournalEntry`].
+
+use anyhow::{Context, Result, bail};
+use owo_colors::{OwoColorize, Stream};
+use serde::Serialize;
+
+use syslua_lib::snapshot::{JournalEntry, JournalReport, journal_path, load_journal, verify_journal};
+
+use crate::cmd::state::read_public_key;
+use crate::output::{
+  OutputFormat, format_timestamp, print_info, print_json, print_success, print_warning, symbols, truncate_hash,
+};
+
+#[derive(Debug, Serialize)]
+struct HistoryOutput<'a> {
+  entries: &'a [JournalEntry],
+  #[serde(skip_serializing_if = "Option::is_none")]
+  verification: Option<&'a JournalReport>,
+}
+
+/// Execute the history command.
+///
+/// Shows the last `limit` entries (all of them if 0). With `verify`, fails if
+/// the journal was tampered with; `public_key` additionally requires every
+/// signed entry to be signed with that key.
+pub fn cmd_history(limit: usize, verify: bool, public_key: Option<&str>, output: OutputFormat) -> Result<()> {
+  let path = journal_path();
+  let entries = load_journal(&path).context("Failed to load the journal")?;
+
+  let report = if verify || public_key.is_some() {
+    let public_key = public_key.map(read_public_key).transpose()?;
+    let report = verify_journal(&path, public_key.as_deref()).context("Journal verification failed")?;
+    if public_key.is_some() && report.entries > 0 && report.signed == 0 {
+      bail!("Journal verification failed: no entry is signed");
+    }
+    Some(report)
+  } else {
+    None
+  };
+
+  let shown = if limit == 0 {
+    &entries[..]
+  } else {
+    &entries[entries.len().saturating_sub(limit)..]
+  };
+
+  if output.is_json() {
+    return print_json(&HistoryOutput {
+      entries: shown,
+      verification: report.as_ref(),
+    });
+  }
+
+  if entries.is_empty() {
+    print_info("No applies or destroys recorded yet");
+  }
+  for entry in shown {
+    print_entry(entry);
+  }
+  if shown.len() < entries.len() {
+    print_info(&format!(
+      "{} earlier entries not shown (pass -n 0 to show all)",
+      entries.len() - shown.len()
+    ));
+  }
+
+  if let Some(report) = report {
+    print_success(&format!(
+      "Journal intact: {} entries, {} signed",
+      report.entries, report.signed
+    ));
+    if report.signed < report.entries {
+      print_warning("Entries written before the machine key existed are only protected by the hash chain");
+    }
+    if public_key.is_none() && report.signed > 0 {
+      print_warning("Signatures were checked against the keys in the entries; pass --public-key to pin the key");
+      for key in &report.public_keys {
+        print_info(&format!("Signed with {}", key));
+      }
+    }
+  }
+  Ok(())
+}
+
+fn print_entry(entry: &JournalEntry) {
+  let status = if entry.success {
+    symbols::SUCCESS
+      .if_supports_color(Stream::Stdout, |s| s.green())
+      .to_string()
+  } else {
+    symbols::ERROR
+      .if_supports_color(Stream::Stdout, |s| s.red())
+      .to_string()
+  };
+  let who = match (&entry.user, &entry.sudo_user) {
+    (Some(user), Some(sudo_user)) => format!("{} (sudo by {})", user, sudo_user),
+    (Some(user), None) => user.clone(),
+    (None, _) => "unknown".to_string(),
+  };
+  let signed = if entry.signature.is_some() { " signed" } else { "" };
+
+  println!(
+    "{} #{} {} by {} on {} - {}{}",
+    status,
+    entry.seq,
+    entry.operation.as_str(),
+    who,
+    entry.hostname.as_deref().unwrap_or("unknown"),
+    format_timestamp(entry.recorded_at),
+    signed.if_supports_color(Stream::Stdout, |s| s.dimmed())
+  );
+
+  let mut details = Vec::new();
+  if let Some(ref snapshot) = entry.snapshot {
+    details.push(format!("snapshot {}", snapshot));
+  }
+  if let Some(ref provenance) = entry.provenance {
+    if let Some(ref commit) = provenance.git_commit {
+      details.push(format!("commit {}", truncate_hash(commit)));
+    } else if let Some(ref hash) = provenance.config_hash {
+      details.push(format!("config {}", truncate_hash(hash)));
+    }
+  }
+  let changes = &entry.changes;
+  for (count, what) in [
+    (changes.builds_realized, "built"),
+    (changes.binds_applied, "applied"),
+    (changes.binds_updated, "updated"),
+    (changes.binds_destroyed, "destroyed"),
+    (changes.binds_repaired, "repaired"),
+  ] {
+    if count > 0 {
+      details.push(format!("{} {}", count, what));
+    }
+  }
+  if !details.is_empty() {
+    println!("    {}", details.join(", "));
+  }
+  if let Some(ref error) = entry.error {
+    println!("    {}", error.if_supports_color(Stream::Stdout, |s| s.red()));
+  }
+}
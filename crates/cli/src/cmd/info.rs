@@ -1,8 +1,23 @@
 //! Info command implementation.
 //!
-//! Displays system information including the detected platform triple.
+//! Displays system information including the detected platform triple and
+//! host facts (virtualization, init system). With `--licenses`, evaluates a
+//! config and lists every input and build with its declared license instead.
 
-use syslua_lib::platform::platform_triple;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use owo_colors::{OwoColorize, Stream};
+use serde::Serialize;
+
+use syslua_lib::eval::{EvalOptions, evaluate_config_with_inputs};
+use syslua_lib::inputs::ResolvedInputs;
+use syslua_lib::lua::sandbox::UntrustedInputs;
+use syslua_lib::manifest::Manifest;
+use syslua_lib::platform::{Facts, platform_triple};
+use syslua_lib::util::metadata::Metadata;
+
+use crate::output::{OutputFormat, print_info, print_json, truncate_hash};
 
 pub fn cmd_info() {
   println!("System:");
@@ -10,4 +25,183 @@ pub fn cmd_info() {
     Some(triple) => println!("Platform: {}", triple),
     _ => println!("Could not detect platform."),
   }
+
+  let facts = Facts::current();
+  println!(
+    "Virtualization: {}",
+    facts.virtualization.map(|v| v.as_str()).unwrap_or("none")
+  );
+  let features: Vec<_> = Facts::FEATURES
+    .iter()
+    .filter(|name| facts.feature(name) == Some(true))
+    .copied()
+    .collect();
+  if !features.is_empty() {
+    println!("Facts: {}", features.join(", "));
+  }
+}
+
+/// Inputs and builds of a config with their declared metadata.
+#[derive(Debug, Serialize)]
+struct LicenseReport {
+  inputs: Vec<LicenseEntry>,
+  builds: Vec<LicenseEntry>,
+}
+
+/// One input or build in a [`LicenseReport`].
+#[derive(Debug, Serialize)]
+struct LicenseEntry {
+  /// Input path (e.g., "pkgs/utils") or build id.
+  name: String,
+  /// Resolved revision (inputs only).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  rev: Option<String>,
+  /// Build hash (builds only).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  hash: Option<String>,
+  #[serde(flatten)]
+  metadata: Metadata,
+}
+
+impl LicenseReport {
+  fn new(manifest: &Manifest, inputs: &ResolvedInputs) -> Self {
+    let mut input_entries = Vec::new();
+    collect_inputs(inputs, "", &mut input_entries);
+
+    let mut builds: Vec<LicenseEntry> = manifest
+      .builds
+      .iter()
+      .map(|(hash, build)| LicenseEntry {
+        name: build.id.clone().unwrap_or_else(|| truncate_hash(&hash.0).to_string()),
+        rev: None,
+        hash: Some(hash.0.clone()),
+        metadata: build.metadata.clone().unwrap_or_default(),
+      })
+      .collect();
+    builds.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.hash.cmp(&b.hash)));
+
+    Self {
+      inputs: input_entries,
+      builds,
+    }
+  }
+
+  fn unlicensed(&self) -> usize {
+    self
+      .inputs
+      .iter()
+      .chain(&self.builds)
+      .filter(|e| e.metadata.license.is_none())
+      .count()
+  }
+}
+
+/// Flatten the input tree depth-first, naming transitive inputs by their path.
+fn collect_inputs(inputs: &ResolvedInputs, prefix: &str, out: &mut Vec<LicenseEntry>) {
+  for (name, input) in inputs {
+    let name = format!("{}{}", prefix, name);
+    out.push(LicenseEntry {
+      name: name.clone(),
+      rev: Some(input.rev.clone()),
+      hash: None,
+      metadata: input.metadata.clone().unwrap_or_default(),
+    });
+    collect_inputs(&input.inputs, &format!("{}/", name), out);
+  }
+}
+
+fn print_entries(entries: &[LicenseEntry]) {
+  let width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+  for entry in entries {
+    let license = match &entry.metadata.license {
+      Some(license) => license.clone(),
+      None => "unknown".if_supports_color(Stream::Stdout, |s| s.yellow()).to_string(),
+    };
+    let homepage = entry.metadata.homepage.as_deref().unwrap_or("");
+    println!(
+      "    {:<width$}  {} {}",
+      entry.name,
+      license,
+      homepage.if_supports_color(Stream::Stdout, |s| s.dimmed()),
+      width = width
+    );
+  }
+}
+
+/// Evaluate `file` and list its inputs and builds with their licenses.
+pub fn cmd_info_licenses(
+  file: &str,
+  impure: bool,
+  untrusted_inputs: UntrustedInputs,
+  output: OutputFormat,
+) -> Result<()> {
+  let eval_options = EvalOptions {
+    impure,
+    untrusted_inputs,
+    ..Default::default()
+  };
+  let (manifest, inputs) = evaluate_config_with_inputs(Path::new(file), &eval_options)
+    .with_context(|| format!("Failed to evaluate config: {}", file))?;
+  let report = LicenseReport::new(&manifest, &inputs);
+
+  if output.is_json() {
+    return print_json(&report);
+  }
+
+  if report.inputs.is_empty() {
+    print_info("No inputs");
+  } else {
+    print_info("Inputs:");
+    print_entries(&report.inputs);
+  }
+
+  println!();
+  if report.builds.is_empty() {
+    print_info("No builds");
+  } else {
+    print_info("Builds:");
+    print_entries(&report.builds);
+  }
+
+  let unlicensed = report.unlicensed();
+  if unlicensed > 0 {
+    println!();
+    print_info(&format!("Without a declared license: {}", unlicensed));
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use syslua_lib::inputs::ResolvedInput;
+
+  use super::*;
+
+  fn licensed(license: &str) -> Option<Metadata> {
+    Some(Metadata {
+      license: Some(license.to_string()),
+      ..Default::default()
+    })
+  }
+
+  #[test]
+  fn report_flattens_transitive_inputs() {
+    let utils = ResolvedInput::new(PathBuf::from("/inputs/utils"), "def456".to_string()).with_metadata(licensed("MIT"));
+    let pkgs = ResolvedInput::with_inputs(
+      PathBuf::from("/inputs/pkgs"),
+      "abc123".to_string(),
+      ResolvedInputs::from([("utils".to_string(), utils)]),
+    );
+    let inputs = ResolvedInputs::from([("pkgs".to_string(), pkgs)]);
+
+    let report = LicenseReport::new(&Manifest::default(), &inputs);
+
+    let names: Vec<_> = report.inputs.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, vec!["pkgs", "pkgs/utils"]);
+    assert_eq!(report.inputs[1].metadata.license.as_deref(), Some("MIT"));
+    assert_eq!(report.unlicensed(), 1);
+  }
 }
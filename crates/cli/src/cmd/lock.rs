@@ -0,0 +1,101 @@
+//! Implementation of the `sys lock` command.
+//!
+//! Audits a config's `syslua.lock` for entries nothing uses anymore, duplicate
+//! nodes, and entries disagreeing with the lock files of inputs. See
+//! [`syslua_lib::inputs::audit`]. `sys update --prune` removes what it finds.
+
+use anyhow::{Context, Result, bail};
+use clap::Subcommand;
+use owo_colors::{OwoColorize, Stream};
+
+use syslua_lib::inputs::audit::{LockIssue, LockIssueKind};
+use syslua_lib::update::{audit_lock_file, find_config_path};
+
+use crate::output::{OutputFormat, print_json, print_success, symbols};
+
+#[derive(Subcommand, Debug)]
+pub enum LockCommand {
+  /// Report stale, duplicate and mismatched lock file entries, failing if there are any
+  Audit {
+    /// Path to config file (default: ./init.lua or ~/.config/syslua/init.lua)
+    #[arg(value_name = "CONFIG")]
+    config: Option<String>,
+
+    /// Output format
+    #[arg(short = 'o', long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+}
+
+pub fn cmd_lock(command: LockCommand) -> Result<()> {
+  match command {
+    LockCommand::Audit { config, output } => cmd_audit(config.as_deref(), output),
+  }
+}
+
+fn cmd_audit(config: Option<&str>, output: OutputFormat) -> Result<()> {
+  let config_path = find_config_path(config).context("Failed to find config file")?;
+  let audit = audit_lock_file(&config_path).context("Failed to audit lock file")?;
+
+  if output.is_json() {
+    print_json(&audit)?;
+  } else if audit.is_clean() {
+    print_success("Lock file is clean");
+  } else {
+    print_lock_issues(&audit.issues, None);
+  }
+
+  if !audit.is_clean() {
+    let prunable = audit.prunable();
+    if prunable == audit.issues.len() {
+      bail!(
+        "{} lock issue(s) found; run 'sys update --prune' to remove them",
+        prunable
+      );
+    }
+    bail!(
+      "{} lock issue(s) found; run 'sys update --prune' to remove {} and 'sys update <input>' to re-pin mismatched inputs",
+      audit.issues.len(),
+      prunable
+    );
+  }
+  Ok(())
+}
+
+/// Print lock issues. With `prune_label` (e.g. "Pruned"), the prunable ones
+/// are listed as removed under that label.
+pub fn print_lock_issues(issues: &[LockIssue], prune_label: Option<&str>) {
+  for issue in issues {
+    let (symbol, label) = match prune_label {
+      Some(label) if issue.is_prunable() => (
+        symbols::REMOVE
+          .if_supports_color(Stream::Stdout, |s| s.red())
+          .to_string(),
+        label,
+      ),
+      _ => (
+        symbols::WARNING
+          .if_supports_color(Stream::Stdout, |s| s.yellow())
+          .to_string(),
+        kind_label(&issue.kind),
+      ),
+    };
+    println!(
+      "  {} {}: {} ({})",
+      symbol,
+      label,
+      issue.key.if_supports_color(Stream::Stdout, |s| s.cyan()),
+      issue.describe()
+    );
+  }
+}
+
+fn kind_label(kind: &LockIssueKind) -> &'static str {
+  match kind {
+    LockIssueKind::Stale => "Stale",
+    LockIssueKind::Orphaned => "Orphaned",
+    LockIssueKind::Duplicate { .. } => "Duplicate",
+    LockIssueKind::Dangling { .. } => "Dangling",
+    LockIssueKind::Mismatched { .. } => "Mismatched",
+  }
+}
@@ -0,0 +1,173 @@
+//! Implementation of the `sys logs` command.
+//!
+//! Lists the transcripts of past applies kept in the store and, with
+//! `--transcript <node>`, shows exactly what a build or bind ran: resolved
+//! command lines, environment, working directory, exit codes and durations.
+//! See [`syslua_lib::execute::transcript`].
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use owo_colors::{OwoColorize, Stream};
+
+use syslua_lib::execute::transcript::{
+  ActionTranscript, ApplyTranscript, latest_transcript_of, list_transcripts, load_transcript, transcripts_dir,
+};
+
+use crate::output::{OutputFormat, format_duration, format_timestamp, print_info, print_json, symbols, truncate_hash};
+
+/// Execute the logs command.
+///
+/// Without `node` or `apply`, lists the recorded applies. With `node`, shows
+/// the commands of that build or bind (id or hash prefix) in `apply`, or in
+/// the latest apply that ran any. With only `apply`, shows all its commands.
+/// Secrets are hidden, including in transcripts saved before applies redacted
+/// them.
+pub fn cmd_logs(node: Option<&str>, apply: Option<&str>, output: OutputFormat) -> Result<()> {
+  let dir = transcripts_dir();
+  let mut transcript = match (apply, node) {
+    (Some(id), _) => load_transcript(&dir, id).context("Failed to load apply transcript")?,
+    (None, Some(node)) => match latest_transcript_of(&dir, node).context("Failed to load apply transcripts")? {
+      Some(transcript) => transcript,
+      None => bail!(
+        "No recorded commands of '{}' (only exec actions of applies are recorded)",
+        node
+      ),
+    },
+    (None, None) => return list_applies(&dir, output),
+  };
+  if let Some(node) = node {
+    transcript.actions.retain(|a| a.is_of(node));
+    if transcript.actions.is_empty() {
+      bail!("Apply {} ran no commands of '{}'", transcript.id, node);
+    }
+  }
+  transcript.redact();
+
+  if output.is_json() {
+    return print_json(&transcript);
+  }
+  print_summary(&transcript);
+  for action in &transcript.actions {
+    print_action(action);
+  }
+  Ok(())
+}
+
+fn list_applies(dir: &Path, output: OutputFormat) -> Result<()> {
+  let mut transcripts = list_transcripts(dir).context("Failed to load apply transcripts")?;
+  transcripts.iter_mut().for_each(ApplyTranscript::redact);
+  if output.is_json() {
+    return print_json(&transcripts);
+  }
+  if transcripts.is_empty() {
+    print_info("No apply transcripts recorded yet");
+  }
+  for transcript in &transcripts {
+    print_summary(transcript);
+  }
+  Ok(())
+}
+
+fn status_symbol(success: bool) -> String {
+  if success {
+    symbols::SUCCESS
+      .if_supports_color(Stream::Stdout, |s| s.green())
+      .to_string()
+  } else {
+    symbols::ERROR
+      .if_supports_color(Stream::Stdout, |s| s.red())
+      .to_string()
+  }
+}
+
+fn print_summary(transcript: &ApplyTranscript) {
+  let failed = transcript.actions.iter().filter(|a| !a.success()).count();
+  let mut details = vec![format!("{} commands", transcript.actions.len())];
+  if failed > 0 {
+    details.push(format!("{} failed", failed));
+  }
+  if let Some(ref snapshot) = transcript.snapshot {
+    details.push(format!("snapshot {}", snapshot));
+  }
+
+  println!(
+    "{} {} - {} - {}",
+    status_symbol(transcript.success),
+    transcript.id,
+    format_timestamp(transcript.recorded_at),
+    details.join(", ")
+  );
+  if let Some(ref error) = transcript.error {
+    println!("    {}", error.if_supports_color(Stream::Stdout, |s| s.red()));
+  }
+}
+
+fn print_action(action: &ActionTranscript) {
+  let exit = match action.exit_code {
+    Some(code) => format!("exit {}", code),
+    None => "no exit code".to_string(),
+  };
+  let operation = if action.probe {
+    format!("{} unless probe", action.operation.as_str())
+  } else {
+    action.operation.as_str().to_string()
+  };
+  println!();
+  println!(
+    "{} {} {} ({}) {} - {}, {}",
+    status_symbol(action.success()),
+    action.kind.as_str(),
+    action.id.as_deref().unwrap_or("-"),
+    truncate_hash(&action.hash),
+    operation,
+    format_duration(Duration::from_millis(action.duration_ms)),
+    exit
+  );
+  println!(
+    "    $ {}",
+    action
+      .command
+      .iter()
+      .map(|arg| shell_quote(arg))
+      .collect::<Vec<_>>()
+      .join(" ")
+  );
+  println!("    cwd: {}", action.cwd);
+  if let Some(ref user) = action.run_as {
+    println!("    run as: {}", user);
+  }
+  for (name, value) in &action.env {
+    println!("    env: {}={}", name, shell_quote(value));
+  }
+  if let Some(ref error) = action.error {
+    println!("    {}", error.if_supports_color(Stream::Stdout, |s| s.red()));
+  }
+}
+
+/// Quote `arg` for display when a shell would split or expand it.
+fn shell_quote(arg: &str) -> String {
+  let plain = !arg.is_empty()
+    && arg
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c));
+  if plain {
+    arg.to_string()
+  } else {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn shell_quote_only_quotes_when_needed() {
+    assert_eq!(shell_quote("/usr/bin/brew"), "/usr/bin/brew");
+    assert_eq!(shell_quote("brew install jq"), "'brew install jq'");
+    assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    assert_eq!(shell_quote(""), "''");
+  }
+}
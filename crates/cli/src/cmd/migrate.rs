@@ -0,0 +1,71 @@
+//! Implementation of the `sys migrate-config` command.
+//!
+//! Rewrites the deprecated `derive{}`/`activate{}` calls of a config into
+//! `sys.build{}`/`sys.bind{}`. See [`syslua_lib::lua::migrate`].
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use syslua_lib::lua::api::API_VERSION;
+use syslua_lib::lua::migrate::migrate_config;
+use syslua_lib::update::find_config_path;
+
+use crate::output::{OutputFormat, print_info, print_json, print_success, print_warning};
+
+/// Execute the migrate-config command.
+///
+/// `path` is a Lua file or a directory; by default the directory of the
+/// config. Calls that can't be rewritten are reported but don't fail the
+/// command.
+pub fn cmd_migrate_config(path: Option<&str>, dry_run: bool, output: OutputFormat) -> Result<()> {
+  let root = match path {
+    Some(path) => PathBuf::from(path),
+    None => {
+      let config = find_config_path(None).context("Failed to find config file")?;
+      match config.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+      }
+    }
+  };
+  let files = migrate_config(&root, dry_run).with_context(|| format!("Failed to migrate {}", root.display()))?;
+
+  if output.is_json() {
+    return print_json(&files);
+  }
+  if files.is_empty() {
+    print_info("No derive{} or activate{} calls found");
+    return Ok(());
+  }
+
+  let verb = if dry_run { "Would rewrite" } else { "Rewrote" };
+  for file in &files {
+    if !file.migrated.is_empty() {
+      print_success(&format!(
+        "{} {} call(s) in {}",
+        verb,
+        file.migrated.len(),
+        file.path.display()
+      ));
+    }
+    for call in &file.skipped {
+      print_warning(&format!(
+        "{}:{}: left {}{{}} alone: {}",
+        file.path.display(),
+        call.line,
+        call.function,
+        call.reason
+      ));
+    }
+  }
+  if files.iter().any(|file| !file.skipped.is_empty()) {
+    print_info("Calls left alone still work through the deprecated globals; migrate them by hand");
+  } else if !dry_run {
+    print_info(&format!(
+      "Declare sys.api({}) at the top of the config to drop the deprecated globals",
+      API_VERSION
+    ));
+  }
+  Ok(())
+}
@@ -2,33 +2,90 @@
 //!
 //! Each submodule implements a single CLI command:
 //!
+//! - [`activate`] - Run login-phase binds, and install the login hook
+//! - [`add_input`] - Declare an input from a registry in the config
+//! - [`agent`] - Re-apply a config from git on a schedule
 //! - [`apply`] - Evaluate config and apply changes to the system
+//! - [`completions`] - Print shell completion scripts
+//! - [`daemon`] - Run or control the background daemon
 //! - [`destroy`] - Remove all managed binds from the system
 //! - [`diff`] - Show differences between snapshots
-//! - [`info`] - Display information about builds, binds, or inputs
+//! - [`docs`] - Generate man pages
+//! - [`eval`] - Evaluate config into a manifest document for `apply --manifest`
+//! - [`history`] - Show and verify the journal of applies, destroys and repairs
+//! - [`info`] - Display system information, or the licenses of a config's inputs and builds
 //! - [`init`] - Initialize a new syslua configuration
+//! - [`lock`] - Audit the lock file for stale, duplicate and mismatched entries
+//! - [`logs`] - Show the commands past applies ran
+//! - [`migrate`] - Rewrite legacy `derive{}`/`activate{}` calls
 //! - [`plan`] - Show what changes would be made without applying
+//! - [`repair`] - Check selected binds for drift and repair them
+//! - [`search`] - Search registries of community inputs
+//! - [`self_update`] - Replace the `sys` executable with a newer release
+//! - [`state`] - Export and verify signed machine state documents
+//! - [`stats`] - Summarize build durations and bind failures from past applies
 //! - [`status`] - Show current system state vs expected state
+//! - [`store`] - Inspect store disk usage
+//! - [`test`] - Run `*_spec.lua` specs against a recording runtime
 //! - [`update`] - Update input locks to latest versions
+//! - [`vendor`] - Copy inputs into the config repository, and back out
 
+mod activate;
+mod add_input;
+pub mod agent;
 mod apply;
+pub mod completions;
+pub mod daemon;
 mod destroy;
 mod diff;
+pub mod docs;
+mod eval;
 mod gc;
+mod history;
 mod info;
 mod init;
+pub mod lock;
+mod logs;
+mod migrate;
 mod plan;
+mod repair;
+mod search;
+mod self_update;
 pub mod snapshot;
+pub mod state;
+mod stats;
 mod status;
+pub mod store;
+mod test;
 mod update;
+mod vendor;
 
-pub use apply::cmd_apply;
+pub use activate::cmd_activate_login;
+pub use add_input::cmd_add_input;
+pub use agent::{cmd_agent, cmd_agent_status};
+pub use apply::{cmd_apply, cmd_apply_system};
+pub use completions::cmd_completions;
+pub use daemon::cmd_daemon;
 pub use destroy::cmd_destroy;
 pub use diff::cmd_diff;
+pub use docs::cmd_docs;
+pub use eval::cmd_eval;
 pub use gc::cmd_gc;
-pub use info::cmd_info;
+pub use history::cmd_history;
+pub use info::{cmd_info, cmd_info_licenses};
 pub use init::cmd_init;
+pub use lock::cmd_lock;
+pub use logs::cmd_logs;
+pub use migrate::cmd_migrate_config;
 pub use plan::cmd_plan;
+pub use repair::cmd_repair;
+pub use search::cmd_search;
+pub use self_update::cmd_self_update;
 pub use snapshot::cmd_snapshot;
-pub use status::cmd_status;
+pub use state::cmd_state;
+pub use stats::cmd_stats;
+pub use status::{cmd_status, cmd_status_follow};
+pub use store::cmd_store;
+pub use test::cmd_test;
 pub use update::cmd_update;
+pub use vendor::{cmd_unvendor, cmd_vendor};
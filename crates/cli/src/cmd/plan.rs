@@ -3,32 +3,76 @@
 //! This command evaluates a Lua configuration file and writes the resulting
 //! manifest to a plan directory for later application.
 
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 use owo_colors::OwoColorize;
 
-use syslua_lib::eval::{EvalOptions, evaluate_config};
-
-use crate::output::{OutputFormat, format_duration, print_json, print_stat, symbols, truncate_hash};
-use syslua_lib::execute::{ExecuteConfig, check_unchanged_binds};
-use syslua_lib::platform::paths::{plans_dir, store_dir};
-use syslua_lib::snapshot::{SnapshotStore, compute_diff};
-use syslua_lib::util::hash::Hashable;
-
-pub fn cmd_plan(file: &str, impure: bool, output: OutputFormat) -> Result<()> {
+use syslua_lib::api::{self, PlanRequest, PlanResponse};
+use syslua_lib::daemon::DaemonClient;
+use syslua_lib::lua::sandbox::UntrustedInputs;
+use syslua_lib::platform::Platform;
+use syslua_lib::platform::paths::plans_dir;
+
+use crate::cmd::daemon::delegate_plan;
+use crate::output::pager::page_output;
+use crate::output::{
+  OutputFormat, format_duration, print_bind_touches, print_deprecations, print_group_changes, print_input_overrides,
+  print_json, print_overridden_builds, print_previously_failed, print_skipped_binds, print_skipped_builds, print_stat,
+  symbols, truncate_hash,
+};
+
+/// Execute the plan command.
+///
+/// If a daemon is running for the same store, evaluation and drift checks run
+/// there; the plan directory is always written locally. Builds to realize
+/// that failed within `failure_ttl` are listed as previously failed. A config
+/// planned for another `platform` is diffed against an empty state.
+pub fn cmd_plan(
+  file: &str,
+  impure: bool,
+  input_overrides: BTreeMap<String, String>,
+  untrusted_inputs: UntrustedInputs,
+  vars_file: Option<PathBuf>,
+  platform: Option<Platform>,
+  failure_ttl: Duration,
+  output: OutputFormat,
+) -> Result<()> {
   let start = Instant::now();
   let path = Path::new(file);
 
-  let eval_options = EvalOptions { impure };
-  let manifest =
-    evaluate_config(path, &eval_options).with_context(|| format!("Failed to evaluate config: {}", file))?;
-
-  let hash = manifest.compute_hash().context("Failed to compute manifest hash")?;
-
-  let plan_dir = plans_dir().join(&hash.0);
+  let request = PlanRequest {
+    impure,
+    input_overrides: input_overrides.clone(),
+    untrusted_inputs,
+    vars_file,
+    check_drift: true,
+    failure_ttl_secs: Some(failure_ttl.as_secs()),
+    platform,
+    ..PlanRequest::new(path)
+  };
+  let planned = match DaemonClient::detect() {
+    Some(client) => delegate_plan(&client, request),
+    None => {
+      let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
+      rt.block_on(api::plan(&request)).map_err(Into::into)
+    }
+  }
+  .with_context(|| format!("Failed to evaluate config: {}", file))?;
+  let PlanResponse {
+    manifest_hash: hash,
+    manifest,
+    diff,
+    drift_results,
+    groups,
+    touches,
+    previously_failed,
+  } = planned;
+
+  let plan_dir = plans_dir().join(&hash);
   fs::create_dir_all(&plan_dir).with_context(|| format!("Failed to create plan directory: {}", plan_dir.display()))?;
 
   let manifest_path = plan_dir.join("manifest.json");
@@ -36,38 +80,29 @@ pub fn cmd_plan(file: &str, impure: bool, output: OutputFormat) -> Result<()> {
   fs::write(&manifest_path, &manifest_json)
     .with_context(|| format!("Failed to write manifest: {}", manifest_path.display()))?;
 
-  let snapshot_store = SnapshotStore::default_store();
-  let current_snapshot = snapshot_store
-    .load_current()
-    .context("Failed to load current snapshot")?;
-  let current_manifest = current_snapshot.as_ref().map(|s| &s.manifest);
-
-  let store_path = store_dir();
-  let diff = compute_diff(&manifest, current_manifest, &store_path);
-
   if output.is_json() {
-    // For JSON output, we need to check for drift first
-    let drift_results = if !diff.binds_unchanged.is_empty() {
-      let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
-      let config = ExecuteConfig::default();
-      Some(
-        rt.block_on(check_unchanged_binds(&diff.binds_unchanged, &manifest, &config))
-          .context("Failed to check for drift")?,
-      )
-    } else {
-      None
-    };
-
     let plan_output = serde_json::json!({
-      "plan_hash": hash.0,
+      "plan_hash": hash,
       "manifest": manifest,
       "diff": diff,
-      "drift_results": drift_results,
+      "drift_results": (!diff.binds_unchanged.is_empty()).then_some(&drift_results),
+      "groups": groups,
+      "touches": touches,
+      "previously_failed": previously_failed,
+      "input_overrides": input_overrides,
+      "platform": platform,
       "plan_path": manifest_path.display().to_string()
     });
     print_json(&plan_output)?;
   } else {
-    println!("{} Plan: {}", symbols::INFO.cyan(), truncate_hash(&hash.0).cyan());
+    let _pager = page_output(Some(file));
+    println!("{} Plan: {}", symbols::INFO.cyan(), truncate_hash(&hash).cyan());
+    if let Some(platform) = platform.filter(|p| Some(*p) != Platform::current()) {
+      print_stat(
+        "Platform",
+        &format!("{} (not this host; diffed against an empty state)", platform),
+      );
+    }
     print_stat("Builds", &manifest.builds.len().to_string());
     println!(
       "    {} To realize: {}",
@@ -75,6 +110,13 @@ pub fn cmd_plan(file: &str, impure: bool, output: OutputFormat) -> Result<()> {
       diff.builds_to_realize.len()
     );
     println!("    {} Cached: {}", symbols::INFO.dimmed(), diff.builds_cached.len());
+    if !previously_failed.is_empty() {
+      println!(
+        "    {} Previously failed: {}",
+        symbols::ERROR.red(),
+        previously_failed.len()
+      );
+    }
     print_stat("Binds", &manifest.bindings.len().to_string());
     println!("    {} To apply: {}", symbols::ADD.green(), diff.binds_to_apply.len());
     println!(
@@ -92,32 +134,34 @@ pub fn cmd_plan(file: &str, impure: bool, output: OutputFormat) -> Result<()> {
       symbols::INFO.dimmed(),
       diff.binds_unchanged.len()
     );
+    print_group_changes(&groups);
+    print_bind_touches(&touches);
     print_stat("Path", &manifest_path.display().to_string());
     print_stat("Duration", &format_duration(start.elapsed()));
-
-    if !diff.binds_unchanged.is_empty() {
-      let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
-      let config = ExecuteConfig::default();
-
-      let drift_results = rt
-        .block_on(check_unchanged_binds(&diff.binds_unchanged, &manifest, &config))
-        .context("Failed to check for drift")?;
-
-      let drifted_count = drift_results.iter().filter(|r| r.result.drifted).count();
-      if drifted_count > 0 {
-        println!();
-        println!(
-          "{} {}",
-          symbols::WARNING.yellow(),
-          format!("Drift detected: {} bind(s)", drifted_count).yellow()
-        );
-        for drift in drift_results.iter().filter(|r| r.result.drifted) {
-          let id = drift.id.as_deref().unwrap_or(&drift.hash.0);
-          if let Some(ref msg) = drift.result.message {
-            println!("  {} {}: {}", symbols::MODIFY.yellow(), id, msg.dimmed());
-          } else {
-            println!("  {} {}", symbols::MODIFY.yellow(), id);
-          }
+    print_input_overrides(&input_overrides);
+    print_skipped_builds(&manifest.skipped_builds);
+    print_skipped_binds(&manifest.skipped);
+    print_overridden_builds(&manifest.overridden);
+    print_deprecations(&manifest.deprecations);
+    print_previously_failed(&previously_failed);
+
+    let drifted_count = drift_results.iter().filter(|r| r.result.drifted).count();
+    if drifted_count > 0 {
+      println!();
+      println!(
+        "{} {}",
+        symbols::WARNING.yellow(),
+        format!("Drift detected: {} bind(s)", drifted_count).yellow()
+      );
+      for drift in drift_results.iter().filter(|r| r.result.drifted) {
+        let id = drift.id.as_deref().unwrap_or(&drift.hash.0);
+        if let Some(ref msg) = drift.result.message {
+          println!("  {} {}: {}", symbols::MODIFY.yellow(), id, msg.dimmed());
+        } else {
+          println!("  {} {}", symbols::MODIFY.yellow(), id);
+        }
+        if let Some(ref reason) = drift.repair_skipped {
+          println!("      {}", format!("--repair skips it: {}", reason).dimmed());
         }
       }
     }
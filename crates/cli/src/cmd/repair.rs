@@ -0,0 +1,84 @@
+//! Implementation of the `sys repair` command.
+//!
+//! This command checks the selected binds of the current snapshot for drift
+//! and re-applies the drifted ones, without evaluating the config or touching
+//! any other bind.
+
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use owo_colors::OwoColorize;
+use tracing::info;
+
+use syslua_lib::execute::{ExecuteConfig, RepairOptions, RepairResult, repair};
+
+use crate::output::{OutputFormat, format_duration, print_json, print_stat, symbols};
+
+/// Execute the repair command.
+///
+/// Runs the drift checks of the binds matching `selectors` (id, hash prefix
+/// or tag) and repairs the drifted ones, honoring their `repair` policies.
+/// With `dry_run`, only reports the drift.
+pub fn cmd_repair(selectors: Vec<String>, dry_run: bool, output: OutputFormat) -> Result<()> {
+  let start = Instant::now();
+  info!(dry_run = dry_run, selectors = ?selectors, "repair command starting");
+
+  let options = RepairOptions {
+    execute: ExecuteConfig::default(),
+    only: selectors,
+    dry_run,
+  };
+
+  let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
+  let result = rt.block_on(repair(&options)).context("Repair failed")?;
+
+  info!(binds_repaired = result.binds_repaired, "repair command completed");
+
+  if output.is_json() {
+    print_json(&result)?;
+    return Ok(());
+  }
+
+  println!();
+  print_checked(&result);
+  let drifted = result.drift_results.iter().filter(|r| r.result.drifted).count();
+  if dry_run {
+    let repairable = result.drift_results.iter().filter(|r| r.needs_repair()).count();
+    println!("{}", "Repair dry run:".yellow());
+    print_stat("Drifted", &format!("{} bind(s)", drifted));
+    print_stat("Would repair", &format!("{} bind(s)", repairable));
+  } else if drifted == 0 {
+    println!("{} No drift detected.", symbols::INFO.dimmed());
+  } else {
+    println!("{} {}", symbols::SUCCESS.green(), "Repair complete!".green().bold());
+    print_stat("Drifted", &format!("{} bind(s)", drifted));
+    print_stat("Binds repaired", &result.binds_repaired.to_string());
+    print_stat("Duration", &format_duration(start.elapsed()));
+  }
+
+  Ok(())
+}
+
+/// List the selected binds with the outcome of their drift checks.
+fn print_checked(result: &RepairResult) {
+  for drift in &result.drift_results {
+    let id = drift.id.as_deref().unwrap_or(&drift.hash.0);
+    if !drift.result.drifted {
+      println!("  {} {}", symbols::SUCCESS.green(), id);
+      continue;
+    }
+    match &drift.result.message {
+      Some(msg) => println!("  {} {}: {}", symbols::MINUS.yellow(), id, msg),
+      None => println!("  {} {}", symbols::MINUS.yellow(), id),
+    }
+    if let Some(reason) = &drift.repair_skipped {
+      println!("      {}", format!("not repaired: {}", reason).dimmed());
+    }
+  }
+  for id in &result.unchecked {
+    println!("  {} {} {}", symbols::INFO.dimmed(), id, "(no check)".dimmed());
+  }
+  if !result.drift_results.is_empty() || !result.unchecked.is_empty() {
+    println!();
+  }
+}
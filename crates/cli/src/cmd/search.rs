@@ -0,0 +1,94 @@
+//! Implementation of the `sys search` command.
+//!
+//! Searches the registries of community inputs configured in
+//! `settings.registries` (or given with `--registry`) and lists the matching
+//! inputs with their description and URL. See
+//! [`syslua_lib::inputs::registry`].
+
+use anyhow::{Context, Result, bail};
+use owo_colors::{OwoColorize, Stream};
+
+use syslua_lib::inputs::registry::{RegistryIndex, fetch_registries, search};
+use syslua_lib::lua::entrypoint::extract_registries_setting;
+use syslua_lib::update::find_config_path;
+
+use crate::output::{OutputFormat, print_info, print_json, print_warning};
+
+/// Execute the search command.
+///
+/// # Arguments
+///
+/// * `term` - Matched against input names, tags and descriptions; empty lists everything.
+/// * `config` - Optional path to the config holding `settings.registries`.
+/// * `registries` - Registry URLs to search instead of the configured ones.
+pub fn cmd_search(term: &str, config: Option<&str>, registries: Vec<String>, output: OutputFormat) -> Result<()> {
+  let indexes = load_registries(config, registries)?;
+  let matches = search(&indexes, term);
+
+  if output.is_json() {
+    return print_json(&matches);
+  }
+  if matches.is_empty() {
+    print_info(&format!("No inputs match '{}'", term));
+    return Ok(());
+  }
+  for m in &matches {
+    let mut line = m.entry.name.if_supports_color(Stream::Stdout, |s| s.cyan()).to_string();
+    if !m.entry.description.is_empty() {
+      line = format!("{} - {}", line, m.entry.description);
+    }
+    println!("{}", line);
+    println!("    {}", m.entry.url.if_supports_color(Stream::Stdout, |s| s.dimmed()));
+  }
+  Ok(())
+}
+
+/// Fetch the indexes of `registries`, or else of the config's
+/// `settings.registries`. Registries that fail are reported and skipped,
+/// unless all of them fail.
+pub(crate) fn load_registries(config: Option<&str>, registries: Vec<String>) -> Result<Vec<(String, RegistryIndex)>> {
+  let urls = if registries.is_empty() {
+    configured_registries(config)?
+  } else {
+    registries
+  };
+  if urls.is_empty() {
+    bail!("No registries configured; list index URLs in settings.registries or pass --registry");
+  }
+
+  let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
+  let mut indexes = Vec::new();
+  let mut last_error = None;
+  for (url, result) in rt.block_on(fetch_registries(&urls)) {
+    match result {
+      Ok(index) => indexes.push((url, index)),
+      Err(e) => {
+        print_warning(&e.to_string());
+        last_error = Some(e);
+      }
+    }
+  }
+  if indexes.is_empty()
+    && let Some(e) = last_error
+  {
+    return Err(e).context("No registry could be read");
+  }
+  Ok(indexes)
+}
+
+/// Read `settings.registries`; without a config there are none.
+fn configured_registries(config: Option<&str>) -> Result<Vec<String>> {
+  let path = match config {
+    Some(_) => find_config_path(config).context("Failed to find config file")?,
+    None => match find_config_path(None) {
+      Ok(path) => path,
+      Err(_) => return Ok(Vec::new()),
+    },
+  };
+
+  let Some(path_str) = path.to_str() else {
+    bail!("Config path is not valid UTF-8: {}", path.display());
+  };
+  extract_registries_setting(path_str)
+    .with_context(|| format!("Failed to read settings.registries from {}", path.display()))
+}
@@ -0,0 +1,106 @@
+//! Implementation of the `sys self-update` command.
+//!
+//! Checks the release index of the configured channel and replaces the
+//! running executable with the newer release for this platform.
+
+use anyhow::{Context, Result, bail};
+
+use syslua_lib::lua::entrypoint::extract_self_update_settings;
+use syslua_lib::platform::Platform;
+use syslua_lib::self_update::{
+  CURRENT_VERSION, Channel, SelfUpdateSettings, UpdateOptions, cleanup_replaced, download_asset, fetch_index,
+  replace_executable,
+};
+use syslua_lib::update::find_config_path;
+
+use crate::output::{print_info, print_stat, print_success, print_warning};
+
+/// Execute the self-update command.
+///
+/// Settings come from `settings.self_update` in the config (found like
+/// `sys update` does; without one the defaults apply). `channel` overrides
+/// the configured channel.
+///
+/// # Arguments
+///
+/// * `config` - Optional path to the config holding `settings.self_update`.
+/// * `channel` - Channel to update from instead of the configured one.
+/// * `check` - Only report whether an update is available.
+/// * `allow_downgrade` - Install the channel's release even if it is not newer.
+/// * `insecure` - Accept a release verified by its SHA256 alone when no
+///   release public key is built in or configured.
+pub fn cmd_self_update(
+  config: Option<&str>,
+  channel: Option<Channel>,
+  check: bool,
+  allow_downgrade: bool,
+  insecure: bool,
+) -> Result<()> {
+  let mut settings = load_settings(config)?;
+  if channel.is_some() {
+    settings.channel = channel;
+  }
+
+  let exe = std::env::current_exe().context("Failed to locate the running executable")?;
+  let exe = dunce::canonicalize(&exe).unwrap_or(exe);
+  cleanup_replaced(&exe);
+
+  let platform = Platform::current().context("Unsupported platform: cannot determine the platform triple")?;
+  let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
+
+  let index = rt
+    .block_on(fetch_index(&settings))
+    .with_context(|| format!("Failed to check the {} channel", settings.channel()))?;
+  index.asset(&platform)?;
+  let newer = index.is_newer_than(CURRENT_VERSION)?;
+
+  print_stat("Channel", settings.channel().as_str());
+  print_stat("Current", CURRENT_VERSION);
+  print_stat("Latest", &index.release);
+
+  if !newer && !allow_downgrade {
+    print_success("sys is up to date");
+    return Ok(());
+  }
+  if check {
+    print_info(&format!("Update available: {} -> {}", CURRENT_VERSION, index.release));
+    return Ok(());
+  }
+
+  let public_key = settings.public_key();
+  if public_key.is_none() && insecure {
+    print_warning("No release public key configured; verifying the SHA256 only");
+  }
+  if !newer {
+    print_warning(&format!("Installing {} over {}", index.release, CURRENT_VERSION));
+  }
+  let options = UpdateOptions {
+    insecure,
+    allow_downgrade,
+  };
+  let download = rt
+    .block_on(download_asset(&index, &platform, public_key, options))
+    .with_context(|| format!("Failed to download sys {}", index.release))?;
+
+  replace_executable(&download, &exe)?;
+  print_success(&format!("Updated sys to {} ({})", index.release, exe.display()));
+
+  Ok(())
+}
+
+/// Read `settings.self_update`, falling back to defaults when no config exists.
+fn load_settings(config: Option<&str>) -> Result<SelfUpdateSettings> {
+  let path = match config {
+    Some(_) => find_config_path(config).context("Failed to find config file")?,
+    None => match find_config_path(None) {
+      Ok(path) => path,
+      Err(_) => return Ok(SelfUpdateSettings::default()),
+    },
+  };
+
+  let Some(path_str) = path.to_str() else {
+    bail!("Config path is not valid UTF-8: {}", path.display());
+  };
+  extract_self_update_settings(path_str)
+    .with_context(|| format!("Failed to read settings.self_update from {}", path.display()))
+}
@@ -1,7 +1,9 @@
+use std::collections::BTreeMap;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Result, bail};
 use clap::Subcommand;
+use clap_complete::ArgValueCompleter;
 use serde::Serialize;
 use syslua_lib::{
   platform::paths::snapshots_dir,
@@ -10,7 +12,10 @@ use syslua_lib::{
 };
 use tracing::{debug, info};
 
-use crate::output::{OutputFormat, print_error, print_info, print_json, print_success, print_warning};
+use crate::cmd::completions::complete_snapshot_ids;
+use crate::output::{
+  OutputFormat, format_timestamp, print_error, print_info, print_json, print_success, print_warning,
+};
 use crate::prompts::confirm;
 
 #[derive(Subcommand, Debug)]
@@ -29,6 +34,7 @@ pub enum SnapshotCommand {
   /// Show details of a specific snapshot
   Show {
     /// Snapshot ID to show
+    #[arg(add = ArgValueCompleter::new(complete_snapshot_ids))]
     id: String,
 
     /// Include list of builds and binds
@@ -43,6 +49,7 @@ pub enum SnapshotCommand {
   /// Delete snapshots
   Delete {
     /// Snapshot IDs to delete
+    #[arg(add = ArgValueCompleter::new(complete_snapshot_ids))]
     ids: Vec<String>,
 
     /// Delete snapshots older than this duration (e.g., "7d", "24h", "2w")
@@ -65,6 +72,7 @@ pub enum SnapshotCommand {
   /// Add a tag to a snapshot
   Tag {
     /// Snapshot ID to tag
+    #[arg(add = ArgValueCompleter::new(complete_snapshot_ids))]
     id: String,
 
     /// Tag name to apply
@@ -74,6 +82,7 @@ pub enum SnapshotCommand {
   /// Remove tag(s) from a snapshot
   Untag {
     /// Snapshot ID to untag
+    #[arg(add = ArgValueCompleter::new(complete_snapshot_ids))]
     id: String,
 
     /// Specific tag to remove (removes all tags if not specified)
@@ -212,6 +221,7 @@ fn cmd_show(id: &str, verbose: bool, output: OutputFormat) -> Result<()> {
       is_current: bool,
       config_path: Option<String>,
       tags: Vec<String>,
+      input_overrides: BTreeMap<String, String>,
       builds: Vec<BuildInfo>,
       binds: Vec<BindInfo>,
     }
@@ -254,6 +264,7 @@ fn cmd_show(id: &str, verbose: bool, output: OutputFormat) -> Result<()> {
       is_current,
       config_path: snapshot.config_path.as_ref().map(|p| p.display().to_string()),
       tags,
+      input_overrides: snapshot.input_overrides.clone(),
       builds,
       binds,
     })?;
@@ -273,6 +284,14 @@ fn cmd_show(id: &str, verbose: bool, output: OutputFormat) -> Result<()> {
     }
     println!("Builds:   {}", snapshot.manifest.builds.len());
     println!("Binds:    {}", snapshot.manifest.bindings.len());
+    if snapshot.has_input_overrides() {
+      let overrides: Vec<_> = snapshot
+        .input_overrides
+        .iter()
+        .map(|(name, url)| format!("{}={}", name, url))
+        .collect();
+      println!("Overrides: {}", overrides.join(", "));
+    }
 
     if verbose {
       if !snapshot.manifest.builds.is_empty() {
@@ -501,21 +520,3 @@ fn cmd_untag(id: &str, name: Option<&str>) -> Result<()> {
 
   Ok(())
 }
-
-fn format_timestamp(timestamp: u64) -> String {
-  let datetime = UNIX_EPOCH + Duration::from_secs(timestamp);
-  if let Ok(duration) = SystemTime::now().duration_since(datetime) {
-    let secs = duration.as_secs();
-    if secs < 60 {
-      format!("{} seconds ago", secs)
-    } else if secs < 3600 {
-      format!("{} minutes ago", secs / 60)
-    } else if secs < 86400 {
-      format!("{} hours ago", secs / 3600)
-    } else {
-      format!("{} days ago", secs / 86400)
-    }
-  } else {
-    format!("timestamp: {}", timestamp)
-  }
-}
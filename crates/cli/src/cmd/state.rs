@@ -0,0 +1,205 @@
+//! Implementation of the `sys state` command.
+//!
+//! Exports the machine's managed state as a signed JSON document for fleet
+//! inventory, and verifies such documents against a public key. Both default
+//! to the store's machine key, which also signs the apply journal.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::Subcommand;
+use serde::Serialize;
+use syslua_lib::{
+  execute::{ExecuteConfig, check_unchanged_binds},
+  platform::paths::snapshots_dir,
+  snapshot::{SignedStateExport, SnapshotStore, StateExport, generate_signing_key, load_signing_key, machine_key_path},
+};
+
+use crate::output::{OutputFormat, print_info, print_json, print_stat, print_success, print_warning, truncate_hash};
+
+#[derive(Subcommand, Debug)]
+pub enum StateCommand {
+  /// Export current snapshot, bind states and drift status as a signed JSON document
+  Export {
+    /// Path to the signing key (default: the store's machine key)
+    #[arg(short, long)]
+    key: Option<PathBuf>,
+
+    /// Write the document to this file instead of stdout
+    #[arg(long = "out", value_name = "FILE")]
+    out_file: Option<PathBuf>,
+  },
+
+  /// Read an exported state document, optionally verifying its signature
+  Import {
+    /// Path to the exported document
+    file: PathBuf,
+
+    /// Verify the document signature
+    #[arg(long, requires = "public_key")]
+    verify: bool,
+
+    /// Hex-encoded public key (or path to a file containing it) to verify against
+    #[arg(long)]
+    public_key: Option<String>,
+
+    /// Output format
+    #[arg(short = 'o', long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+
+  /// Generate a new signing key for state exports
+  Keygen {
+    /// Path to write the private key to (default: the store's machine key,
+    /// which also signs the apply journal)
+    path: Option<PathBuf>,
+  },
+}
+
+pub fn cmd_state(command: StateCommand) -> Result<()> {
+  match command {
+    StateCommand::Export { key, out_file } => cmd_export(&key.unwrap_or_else(machine_key_path), out_file.as_deref()),
+    StateCommand::Import {
+      file,
+      verify,
+      public_key,
+      output,
+    } => cmd_import(&file, verify, public_key.as_deref(), output),
+    StateCommand::Keygen { path } => cmd_keygen(&path.unwrap_or_else(machine_key_path)),
+  }
+}
+
+fn cmd_export(key_path: &Path, out_file: Option<&Path>) -> Result<()> {
+  let key = load_signing_key(key_path).with_context(|| format!("Failed to load key '{}'", key_path.display()))?;
+
+  let store = SnapshotStore::new(snapshots_dir());
+  let snapshot = store.load_current()?;
+
+  let drift = match snapshot {
+    Some(ref snapshot) => {
+      let hashes: Vec<_> = snapshot.manifest.bindings.keys().cloned().collect();
+      let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
+      rt.block_on(check_unchanged_binds(
+        &hashes,
+        &snapshot.manifest,
+        &ExecuteConfig::default(),
+      ))
+      .context("Drift check failed")?
+    }
+    None => vec![],
+  };
+
+  let signed = StateExport::collect(snapshot, drift)?.sign(&key)?;
+  let json = serde_json::to_string_pretty(&signed).context("Failed to serialize state export")?;
+
+  match out_file {
+    Some(path) => {
+      std::fs::write(path, json).with_context(|| format!("Failed to write '{}'", path.display()))?;
+      print_success(&format!("State exported to {}", path.display()));
+    }
+    None => println!("{}", json),
+  }
+
+  Ok(())
+}
+
+fn cmd_import(file: &Path, verify: bool, public_key: Option<&str>, output: OutputFormat) -> Result<()> {
+  let content = std::fs::read_to_string(file).with_context(|| format!("Failed to read '{}'", file.display()))?;
+
+  let signed = if verify {
+    let Some(public_key) = public_key else {
+      bail!("--verify requires --public-key");
+    };
+    let public_key = read_public_key(public_key)?;
+    SignedStateExport::verify(&content, &public_key).context("Verification failed")?
+  } else {
+    SignedStateExport::parse_unverified(&content)?
+  };
+
+  let document = &signed.document;
+
+  if output.is_json() {
+    #[derive(Serialize)]
+    struct ImportOutput<'a> {
+      verified: bool,
+      hostname: Option<&'a str>,
+      platform: Option<&'a str>,
+      exported_at: u64,
+      snapshot_id: Option<&'a str>,
+      bind_count: usize,
+      drifted: usize,
+    }
+
+    print_json(&ImportOutput {
+      verified: verify,
+      hostname: document.hostname.as_deref(),
+      platform: document.platform.as_deref(),
+      exported_at: document.exported_at,
+      snapshot_id: document.snapshot.as_ref().map(|s| s.id.as_str()),
+      bind_count: document.bind_states.len(),
+      drifted: document.drifted_count(),
+    })?;
+  } else {
+    if verify {
+      print_success("Signature verified");
+    } else {
+      print_warning("Signature not verified (pass --verify --public-key to check it)");
+    }
+    print_stat("Host", document.hostname.as_deref().unwrap_or("unknown"));
+    print_stat("Platform", document.platform.as_deref().unwrap_or("unknown"));
+    print_stat("Exported at", &document.exported_at.to_string());
+    match document.snapshot {
+      Some(ref snapshot) => print_stat("Snapshot", truncate_hash(&snapshot.id)),
+      None => print_stat("Snapshot", "none"),
+    }
+    print_stat("Binds", &document.bind_states.len().to_string());
+    print_stat("Drifted", &document.drifted_count().to_string());
+  }
+
+  Ok(())
+}
+
+fn cmd_keygen(path: &Path) -> Result<()> {
+  let (private_key, public_key) = generate_signing_key()?;
+  if let Some(parent) = path.parent()
+    && !parent.as_os_str().is_empty()
+  {
+    std::fs::create_dir_all(parent).with_context(|| format!("Failed to create '{}'", parent.display()))?;
+  }
+
+  // Only the owner may sign with the key, so it's never readable by others,
+  // even before it's written
+  let mut options = std::fs::OpenOptions::new();
+  options.write(true).create_new(true);
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.mode(0o600);
+  }
+  let mut file = match options.open(path) {
+    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+      bail!("Refusing to overwrite existing key '{}'", path.display())
+    }
+    result => result.with_context(|| format!("Failed to create '{}'", path.display()))?,
+  };
+  if let Err(e) = file.write_all(private_key.as_bytes()).and_then(|()| file.sync_all()) {
+    let _ = std::fs::remove_file(path);
+    return Err(e).with_context(|| format!("Failed to write '{}'", path.display()));
+  }
+
+  print_success(&format!("Signing key written to {}", path.display()));
+  print_info(&format!("Public key: {}", public_key));
+  Ok(())
+}
+
+/// Accept either a hex public key or a path to a file containing one.
+pub(crate) fn read_public_key(value: &str) -> Result<String> {
+  let path = Path::new(value);
+  if path.is_file() {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    Ok(content.trim().to_string())
+  } else {
+    Ok(value.trim().to_string())
+  }
+}
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use owo_colors::{OwoColorize, Stream};
+use serde::Serialize;
+
+use syslua_lib::execute::history::{ExecutionHistory, NodeKind, NodeStats, history_path};
+
+use crate::output::{OutputFormat, format_duration, print_info, print_json, truncate_hash};
+
+/// Slowest builds and flakiest binds from the execution history.
+#[derive(Debug, Serialize)]
+struct StatsReport {
+  slowest_builds: Vec<NodeStats>,
+  flakiest_binds: Vec<NodeStats>,
+}
+
+impl StatsReport {
+  fn new(stats: Vec<NodeStats>, limit: usize) -> Self {
+    let (builds, binds): (Vec<_>, Vec<_>) = stats.into_iter().partition(|s| s.kind == NodeKind::Build);
+
+    let mut slowest_builds: Vec<NodeStats> = builds.into_iter().filter(|s| s.mean_duration_ms.is_some()).collect();
+    slowest_builds.sort_by(|a, b| b.mean_duration_ms.cmp(&a.mean_duration_ms));
+    slowest_builds.truncate(limit);
+
+    let mut flakiest_binds: Vec<NodeStats> = binds.into_iter().filter(|s| s.flakiness > 0.0).collect();
+    flakiest_binds.sort_by(|a, b| b.flakiness.total_cmp(&a.flakiness).then(b.failures.cmp(&a.failures)));
+    flakiest_binds.truncate(limit);
+
+    Self {
+      slowest_builds,
+      flakiest_binds,
+    }
+  }
+}
+
+fn node_name(stats: &NodeStats) -> &str {
+  stats.id.as_deref().unwrap_or(truncate_hash(&stats.hash))
+}
+
+pub fn cmd_stats(limit: usize, output: OutputFormat) -> Result<()> {
+  let history = ExecutionHistory::load(&history_path()).context("Failed to load execution history")?;
+  let report = StatsReport::new(history.stats(), limit);
+
+  if output.is_json() {
+    return print_json(&report);
+  }
+
+  if report.slowest_builds.is_empty() {
+    print_info("No builds recorded yet");
+  } else {
+    print_info("Slowest builds (mean of successful runs):");
+    for build in &report.slowest_builds {
+      println!(
+        "    {} {} {}",
+        format_duration(Duration::from_millis(build.mean_duration_ms.unwrap_or(0))),
+        node_name(build),
+        format!("({} runs)", build.runs).if_supports_color(Stream::Stdout, |s| s.dimmed())
+      );
+    }
+  }
+
+  println!();
+  if report.flakiest_binds.is_empty() {
+    print_info("No flaky binds");
+  } else {
+    print_info("Flakiest binds (share of runs that flipped outcome):");
+    for bind in &report.flakiest_binds {
+      println!(
+        "    {:>3.0}% {} {}",
+        bind.flakiness * 100.0,
+        node_name(bind),
+        format!("({} of {} runs failed)", bind.failures, bind.runs).if_supports_color(Stream::Stdout, |s| s.dimmed())
+      );
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn stats(kind: NodeKind, hash: &str, mean: Option<u64>, flakiness: f64) -> NodeStats {
+    NodeStats {
+      kind,
+      id: None,
+      hash: hash.to_string(),
+      runs: 4,
+      failures: 1,
+      mean_duration_ms: mean,
+      max_duration_ms: mean.unwrap_or(0),
+      flakiness,
+    }
+  }
+
+  #[test]
+  fn report_ranks_and_limits_nodes() {
+    let report = StatsReport::new(
+      vec![
+        stats(NodeKind::Build, "fast", Some(10), 0.0),
+        stats(NodeKind::Build, "slow", Some(500), 0.0),
+        stats(NodeKind::Build, "never_succeeded", None, 0.0),
+        stats(NodeKind::Bind, "steady", Some(5), 0.0),
+        stats(NodeKind::Bind, "flaky", Some(5), 0.5),
+      ],
+      1,
+    );
+
+    let names = |nodes: &[NodeStats]| nodes.iter().map(|n| n.hash.clone()).collect::<Vec<_>>();
+    assert_eq!(names(&report.slowest_builds), vec!["slow"]);
+    assert_eq!(names(&report.flakiest_binds), vec!["flaky"]);
+  }
+}
@@ -1,20 +1,46 @@
 //! Status command implementation.
 //!
-//! Displays current snapshot state including build/bind counts and store usage.
+//! Displays current snapshot state including build/bind counts, bind groups,
+//! backups of files replaced by binds, and store usage. Groups are summarized
+//! by their bind counts; `--verbose` lists each group's binds.
+//!
+//! `--follow` instead tails the [live file](syslua_lib::execute::live) of the
+//! apply running in another process, showing its progress until it ends.
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
 use std::path::Path;
+use std::time::Duration;
 
+use syslua_lib::bind::backup::{BackupRecord, load_backups};
 use syslua_lib::bind::store::bind_dir_path;
 use syslua_lib::build::store::build_dir_path;
+use syslua_lib::execute::live::{LiveRecord, LiveState, LiveTail, live_path};
+use syslua_lib::execute::progress::{NodeOutcome, ProgressEvent, ProgressSender};
 use syslua_lib::platform::paths::snapshots_dir;
 use syslua_lib::snapshot::SnapshotStore;
+use syslua_lib::util::hash::ObjectHash;
 
+use crate::output::progress::Progress;
 use crate::output::{
-  self, OutputFormat, format_bytes, print_error, print_info, print_json, print_stat, print_success, truncate_hash,
+  self, OutputFormat, format_bytes, format_duration, print_error, print_info, print_json, print_provenance, print_stat,
+  print_success, print_warning, truncate_hash,
 };
 
+/// How often `--follow` checks the live file for new records.
+const FOLLOW_POLL: Duration = Duration::from_millis(200);
+
 pub fn cmd_status(verbose: bool, output: OutputFormat) -> Result<()> {
+  if !output.is_json()
+    && let Ok(live) = LiveState::load(&live_path())
+    && live.is_running()
+  {
+    print_info(&format!(
+      "An apply is running (PID {}); run 'sys status --follow' to watch it",
+      live.pid.unwrap_or_default()
+    ));
+  }
+
   let store = SnapshotStore::new(snapshots_dir());
 
   let snapshot = match store.load_current() {
@@ -30,6 +56,9 @@ pub fn cmd_status(verbose: bool, output: OutputFormat) -> Result<()> {
   };
 
   let usage = calculate_store_usage(&snapshot.manifest);
+  let backups = collect_backups(&snapshot.manifest);
+  let backup_bytes: u64 = backups.iter().map(|(_, record)| record.size()).sum();
+  let groups = snapshot.manifest.bind_groups();
 
   if output.is_json() {
     let build_list: Vec<_> = snapshot
@@ -42,16 +71,35 @@ pub fn cmd_status(verbose: bool, output: OutputFormat) -> Result<()> {
       .manifest
       .bindings
       .iter()
-      .map(|(hash, bind)| serde_json::json!({ "id": bind.id, "hash": hash.0 }))
+      .map(|(hash, bind)| serde_json::json!({ "id": bind.id, "hash": hash.0, "group": bind.group }))
       .collect();
-    let json_output = serde_json::json!({ "snapshot_id": snapshot.id, "created_at": snapshot.created_at, "builds": { "count": snapshot.manifest.builds.len(), "items": build_list }, "binds": { "count": snapshot.manifest.bindings.len(), "items": bind_list }, "store_usage_bytes": usage });
+    let group_counts: BTreeMap<_, _> = groups.iter().map(|(group, binds)| (*group, binds.len())).collect();
+    let backup_list: Vec<_> = backups
+      .iter()
+      .map(|(hash, record)| serde_json::json!({ "bind": hash.0, "target": record.target, "size": record.size() }))
+      .collect();
+    let json_output = serde_json::json!({ "snapshot_id": snapshot.id, "created_at": snapshot.created_at, "provenance": snapshot.provenance, "builds": { "count": snapshot.manifest.builds.len(), "items": build_list }, "binds": { "count": snapshot.manifest.bindings.len(), "items": bind_list, "groups": group_counts }, "backups": { "count": backups.len(), "bytes": backup_bytes, "items": backup_list }, "store_usage_bytes": usage });
     print_json(&json_output)?;
   } else {
     print_success(&format!("Current snapshot: {}", snapshot.id));
     print_stat("Created", &snapshot.created_at.to_string());
+    if let Some(ref provenance) = snapshot.provenance {
+      print_provenance(provenance);
+    }
     println!();
     print_stat("Builds", &snapshot.manifest.builds.len().to_string());
     print_stat("Binds", &snapshot.manifest.bindings.len().to_string());
+    if !verbose {
+      for (group, binds) in &groups {
+        println!("    {} {}: {} bind(s)", output::symbols::INFO, group, binds.len());
+      }
+    }
+    if !backups.is_empty() {
+      print_stat(
+        "Backups",
+        &format!("{} file(s), {}", backups.len(), format_bytes(backup_bytes)),
+      );
+    }
 
     if verbose {
       if !snapshot.manifest.builds.is_empty() {
@@ -68,13 +116,30 @@ pub fn cmd_status(verbose: bool, output: OutputFormat) -> Result<()> {
       if !snapshot.manifest.bindings.is_empty() {
         println!();
         println!("Binds:");
-        for (hash, bind) in &snapshot.manifest.bindings {
-          match &bind.id {
-            Some(id) => println!("  {} {}-{}", output::symbols::INFO, id, truncate_hash(&hash.0)),
-            None => println!("  {} {}", output::symbols::INFO, truncate_hash(&hash.0)),
+        for (hash, bind) in snapshot.manifest.bindings.iter().filter(|(_, b)| b.group.is_none()) {
+          println!("  {} {}", output::symbols::INFO, bind_label(hash, bind.id.as_deref()));
+        }
+        for (group, hashes) in &groups {
+          println!("  {} ({} bind(s))", group, hashes.len());
+          for hash in hashes {
+            let id = snapshot.manifest.bindings[*hash].id.as_deref();
+            println!("    {} {}", output::symbols::INFO, bind_label(hash, id));
           }
         }
       }
+
+      if !backups.is_empty() {
+        println!();
+        println!("Backups:");
+        for (hash, record) in &backups {
+          println!(
+            "  {} {} ({})",
+            output::symbols::INFO,
+            record.target.display(),
+            truncate_hash(&hash.0)
+          );
+        }
+      }
     }
 
     println!();
@@ -84,6 +149,180 @@ pub fn cmd_status(verbose: bool, output: OutputFormat) -> Result<()> {
   Ok(())
 }
 
+/// `sys status --follow`: the progress of the running apply, until it ends.
+///
+/// Waits for the next apply when none is running. JSON output prints each
+/// record of the live file on its own line.
+pub fn cmd_status_follow(output: OutputFormat) -> Result<()> {
+  let mut tail = LiveTail::new(live_path());
+  let backlog = tail.read().context("Failed to read the live file")?;
+  let mut previous = LiveState::default();
+  for record in &backlog {
+    previous.observe(record);
+  }
+  let mut follower = Follower::new(output);
+  if previous.is_running() {
+    // Replay the running apply from its start
+    for record in backlog {
+      if let Some(result) = follower.record(record) {
+        return result;
+      }
+    }
+  } else if !output.is_json() {
+    print_info("No apply is running; waiting for one to start (Ctrl-C to stop)");
+  }
+
+  loop {
+    std::thread::sleep(FOLLOW_POLL);
+    // Checked before reading, so the last records of an apply that just
+    // exited are read before giving up on it
+    let gone = follower.following && !follower.state.is_running();
+    for record in tail.read().context("Failed to read the live file")? {
+      if let Some(result) = follower.record(record) {
+        return result;
+      }
+    }
+    if gone {
+      follower.stop();
+      bail!(
+        "The apply (PID {}) stopped without finishing",
+        follower.state.pid.unwrap_or_default()
+      );
+    }
+  }
+}
+
+/// Shows the records of the apply being followed.
+struct Follower {
+  output: OutputFormat,
+  /// Whether an apply's `started` record was seen.
+  following: bool,
+  state: LiveState,
+  sender: ProgressSender,
+  display: Option<Progress>,
+}
+
+impl Follower {
+  fn new(output: OutputFormat) -> Self {
+    let (sender, display) = if output.is_json() {
+      (ProgressSender::default(), None)
+    } else {
+      Progress::start()
+    };
+    Self {
+      output,
+      following: false,
+      state: LiveState::default(),
+      sender,
+      display,
+    }
+  }
+
+  /// Show `record`, returning the outcome of the apply once it finished.
+  fn record(&mut self, record: LiveRecord) -> Option<Result<()>> {
+    if let LiveRecord::Started { pid, ref config, .. } = record {
+      if self.following {
+        // Another apply replaced the one followed; its end was missed
+        self.stop();
+        return Some(Err(anyhow::anyhow!(
+          "The apply (PID {}) was replaced by another one",
+          self.state.pid.unwrap_or_default()
+        )));
+      }
+      self.following = true;
+      if !self.output.is_json() {
+        let config = config
+          .as_ref()
+          .map_or("a manifest".to_string(), |c| c.display().to_string());
+        print_info(&format!("Following the apply of {} (PID {})", config, pid));
+      }
+    }
+    if !self.following {
+      return None;
+    }
+
+    if self.output.is_json() {
+      match serde_json::to_string(&record) {
+        Ok(line) => println!("{}", line),
+        Err(e) => return Some(Err(e.into())),
+      }
+    } else if let LiveRecord::Progress { ref event } = record {
+      if self.display.is_some() {
+        self.sender.send(event.clone());
+      } else {
+        self.print_event(event);
+      }
+    }
+    self.state.observe(&record);
+
+    let LiveRecord::Finished { error, .. } = record else {
+      return None;
+    };
+    self.stop();
+    Some(match error {
+      Some(error) => Err(anyhow::anyhow!("Apply failed: {}", error)),
+      None => {
+        if !self.output.is_json() {
+          print_success(&format!(
+            "Apply finished ({} build(s) and bind(s) run)",
+            self.state.done
+          ));
+        }
+        Ok(())
+      }
+    })
+  }
+
+  /// One line per step, for when stderr can't show the progress display.
+  fn print_event(&self, event: &ProgressEvent) {
+    match event {
+      ProgressEvent::WaveStarted { wave, nodes } => print_info(&format!(
+        "Wave {}/{}: {} build(s) and bind(s)",
+        wave + 1,
+        self.state.waves,
+        nodes
+      )),
+      ProgressEvent::NodeFinished {
+        hash,
+        outcome,
+        duration,
+        ..
+      } => {
+        let id = self.state.in_flight.get(hash).and_then(|node| node.id.as_deref());
+        let label = bind_label(hash, id);
+        match outcome {
+          NodeOutcome::Succeeded => println!(
+            "  {} {} ({})",
+            output::symbols::SUCCESS,
+            label,
+            format_duration(*duration)
+          ),
+          NodeOutcome::Failed => print_error(&format!("{} failed", label)),
+          NodeOutcome::Skipped => print_info(&format!("{} skipped", label)),
+        }
+      }
+      ProgressEvent::RollingBack { binds } => print_warning(&format!("Rolling back {} bind(s)", binds)),
+      _ => {}
+    }
+  }
+
+  /// Close the progress display, printing its summary.
+  fn stop(&mut self) {
+    self.sender = ProgressSender::default();
+    if let Some(display) = self.display.take() {
+      display.finish();
+    }
+  }
+}
+
+/// `id-<hash>`, or the hash alone for binds without an id.
+fn bind_label(hash: &ObjectHash, id: Option<&str>) -> String {
+  match id {
+    Some(id) => format!("{}-{}", id, truncate_hash(&hash.0)),
+    None => truncate_hash(&hash.0).to_string(),
+  }
+}
+
 fn dir_size(path: &Path) -> u64 {
   if !path.exists() {
     return 0;
@@ -103,6 +342,18 @@ fn dir_size(path: &Path) -> u64 {
   size
 }
 
+/// Backups of replaced files held by the snapshot's binds.
+fn collect_backups(manifest: &syslua_lib::manifest::Manifest) -> Vec<(ObjectHash, BackupRecord)> {
+  let mut backups = Vec::new();
+  for hash in manifest.bindings.keys() {
+    match load_backups(hash) {
+      Ok(records) => backups.extend(records.into_iter().map(|record| (hash.clone(), record))),
+      Err(e) => print_error(&format!("Error loading backups for {}: {}", truncate_hash(&hash.0), e)),
+    }
+  }
+  backups
+}
+
 fn calculate_store_usage(manifest: &syslua_lib::manifest::Manifest) -> u64 {
   let mut total = 0;
 
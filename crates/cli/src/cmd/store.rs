@@ -0,0 +1,381 @@
+//! Implementation of the `sys store` command.
+//!
+//! Inspects the store: which builds take up space and which snapshots keep
+//! them alive. Also imports existing directories as prebuilt builds, repairs
+//! builds an interrupted apply left incomplete, migrates builds of stores from
+//! before the platform partitioning, and backs up and restores the state of
+//! the machine.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use owo_colors::{OwoColorize, Stream};
+
+use syslua_lib::build::import::import_dir;
+use syslua_lib::build::parse_memory_size;
+use syslua_lib::build::store::{migrate_legacy_builds, platform_build_dir, store_triple};
+use syslua_lib::platform::paths::store_dir;
+use syslua_lib::store_backup::{BackupOptions, RestoreOptions, backup_store, restore_store};
+use syslua_lib::store_inspect::{BuildUsage, UNREFERENCED_GROUP, store_usage};
+use syslua_lib::store_lock::{LockMode, StoreLock};
+use syslua_lib::store_repair::{RepairOptions, RepairOutcome, repair_store};
+
+use crate::output::{OutputFormat, format_bytes, print_info, print_json, print_stat, print_success, truncate_hash};
+
+/// Snapshot IDs listed per build before eliding the rest.
+const MAX_LISTED_SNAPSHOTS: usize = 3;
+
+#[derive(Subcommand, Debug)]
+pub enum StoreCommand {
+  /// Show disk usage per build and id group, largest first
+  Du {
+    /// Hide builds and groups smaller than this (bytes, or a size like 100M)
+    #[arg(long, value_name = "SIZE", default_value = "0", value_parser = parse_memory_size_or_zero)]
+    threshold: u64,
+
+    /// Output format
+    #[arg(short = 'o', long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+  /// Import a directory into the store as a prebuilt build, for `sys.prebuilt`
+  Add {
+    /// Directory to import (symlinks to it, such as a Nix `result` link, are followed)
+    path: PathBuf,
+
+    /// Build id to import it as
+    #[arg(long)]
+    id: String,
+
+    /// Output format
+    #[arg(short = 'o', long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+  /// Recover or remove builds an interrupted apply left without a completion marker
+  Repair {
+    /// Fetch missing downloads of builds that only fetch URLs, instead of removing them
+    #[arg(long)]
+    resume: bool,
+
+    /// Show what would be recovered and removed without making changes
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Leave recovered builds writable instead of making them read-only
+    #[arg(long)]
+    no_readonly: bool,
+
+    /// Output format
+    #[arg(short = 'o', long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+  /// Move builds from before the platform partitioning (`build/<hash>`) under the platform that built them
+  Migrate {
+    /// Platform triple the store's legacy builds were built on (e.g. x86_64-linux)
+    #[arg(long)]
+    triple: String,
+
+    /// Output format
+    #[arg(short = 'o', long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+  /// Back up snapshots and bind state to a backup directory, copying only what changed
+  Backup {
+    /// Backup directory (created if missing)
+    dest: PathBuf,
+
+    /// Also back up builds smaller than this (bytes, or a size like 10M)
+    #[arg(long, value_name = "SIZE", value_parser = parse_memory_size)]
+    include_builds_below: Option<u64>,
+
+    /// Output format
+    #[arg(short = 'o', long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+  /// Restore snapshots and bind state from a backup, e.g. on a new machine
+  Restore {
+    /// Backup directory (restores its newest backup) or a backups/<id>.json listing in it
+    archive: PathBuf,
+
+    /// Restore even though the store already has a current snapshot
+    #[arg(long)]
+    force: bool,
+
+    /// Output format
+    #[arg(short = 'o', long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+}
+
+pub fn cmd_store(command: StoreCommand) -> Result<()> {
+  match command {
+    StoreCommand::Du { threshold, output } => cmd_du(threshold, output),
+    StoreCommand::Add { path, id, output } => cmd_add(&path, &id, output),
+    StoreCommand::Repair {
+      resume,
+      dry_run,
+      no_readonly,
+      output,
+    } => cmd_repair(
+      RepairOptions {
+        resume,
+        dry_run,
+        writable_store: no_readonly,
+      },
+      output,
+    ),
+    StoreCommand::Migrate { triple, output } => cmd_migrate(&triple, output),
+    StoreCommand::Backup {
+      dest,
+      include_builds_below,
+      output,
+    } => cmd_backup(&dest, BackupOptions { include_builds_below }, output),
+    StoreCommand::Restore { archive, force, output } => cmd_restore(&archive, RestoreOptions { force }, output),
+  }
+}
+
+fn cmd_add(path: &Path, id: &str, output: OutputFormat) -> Result<()> {
+  let imported = import_dir(path, id).with_context(|| format!("Failed to import {}", path.display()))?;
+
+  if output.is_json() {
+    return print_json(&imported);
+  }
+
+  if imported.already_present {
+    print_info(&format!("{} is already in the store", id));
+  } else {
+    print_success(&format!("Imported {} into the store", id));
+  }
+  print_stat("Hash", &imported.hash.0);
+  print_stat("Path", &imported.store_path.display().to_string());
+  println!();
+  print_info("Use it in a config with:");
+  println!(
+    "    sys.prebuilt({{ id = \"{}\", hash = \"{}\" }})",
+    id, imported.output_hash
+  );
+  print_info("Until a snapshot references it, `sys gc` removes it");
+
+  Ok(())
+}
+
+fn cmd_repair(options: RepairOptions, output: OutputFormat) -> Result<()> {
+  let _lock = StoreLock::acquire(LockMode::Exclusive, "store repair").context("Failed to acquire store lock")?;
+
+  let rt = tokio::runtime::Runtime::new().context("Failed to create async runtime")?;
+  let report = rt.block_on(repair_store(options)).context("Failed to repair store")?;
+
+  if output.is_json() {
+    return print_json(&report);
+  }
+
+  if report.builds.is_empty() {
+    print_success(&format!("All {} build(s) are complete", report.scanned));
+    return Ok(());
+  }
+
+  if report.dry_run {
+    print_info("Dry run - no changes made");
+  }
+  for build in &report.builds {
+    let line = format!(
+      "{} {} {}",
+      build_name(build.id.as_deref(), &build.hash),
+      format_bytes(build.bytes),
+      format!("({})", build.reason).if_supports_color(Stream::Stdout, |s| s.dimmed())
+    );
+    match build.outcome {
+      RepairOutcome::Recovered => print_success(&format!("Recovered {}", line)),
+      RepairOutcome::Resumed => print_success(&format!("Resumed {}", line)),
+      RepairOutcome::Removed => print_info(&format!("Removed {}", line)),
+    }
+  }
+
+  println!();
+  print_stat("Builds scanned", &report.scanned.to_string());
+  print_stat("Recovered", &report.count(RepairOutcome::Recovered).to_string());
+  print_stat("Resumed", &report.count(RepairOutcome::Resumed).to_string());
+  print_stat("Removed", &report.count(RepairOutcome::Removed).to_string());
+  Ok(())
+}
+
+fn cmd_migrate(triple: &str, output: OutputFormat) -> Result<()> {
+  let _lock = StoreLock::acquire(LockMode::Exclusive, "store migrate").context("Failed to acquire store lock")?;
+  let store = store_dir();
+  let migrated = migrate_legacy_builds(&store, triple).context("Failed to migrate legacy builds")?;
+
+  if output.is_json() {
+    return print_json(&serde_json::json!({ "triple": triple, "migrated": migrated }));
+  }
+
+  if migrated == 0 {
+    print_success("The store has no legacy builds");
+    return Ok(());
+  }
+  print_success(&format!("Migrated {} legacy build(s)", migrated));
+  print_stat("Directory", &platform_build_dir(&store, triple).display().to_string());
+  if triple != store_triple() {
+    print_info(&format!(
+      "This machine is {}, so only {} machines sharing the store use them",
+      store_triple(),
+      triple
+    ));
+  }
+  Ok(())
+}
+
+fn cmd_backup(dest: &Path, options: BackupOptions, output: OutputFormat) -> Result<()> {
+  let _lock = StoreLock::acquire(LockMode::Shared, "store backup").context("Failed to acquire store lock")?;
+  let report = backup_store(dest, &options).with_context(|| format!("Failed to back up to {}", dest.display()))?;
+
+  if output.is_json() {
+    return print_json(&report);
+  }
+
+  print_success(&format!("Backed up the store to {}", dest.display()));
+  print_stat("Backup", &report.id);
+  print_stat("Files", &format!("{} ({})", report.files, format_bytes(report.bytes)));
+  print_stat(
+    "Copied",
+    &format!("{} new file(s) ({})", report.new_files, format_bytes(report.new_bytes)),
+  );
+  if options.include_builds_below.is_some() {
+    print_stat("Builds", &report.builds.to_string());
+  }
+  Ok(())
+}
+
+fn cmd_restore(archive: &Path, options: RestoreOptions, output: OutputFormat) -> Result<()> {
+  let _lock = StoreLock::acquire(LockMode::Exclusive, "store restore").context("Failed to acquire store lock")?;
+  let report = restore_store(archive, &options).with_context(|| format!("Failed to restore {}", archive.display()))?;
+
+  if output.is_json() {
+    return print_json(&report);
+  }
+
+  print_success(&format!("Restored backup {}", report.id));
+  print_stat("Files", &format!("{} ({})", report.files, format_bytes(report.bytes)));
+  if report.builds > 0 {
+    print_stat("Builds", &report.builds.to_string());
+  }
+  if let Some(snapshot) = &report.snapshot {
+    print_stat("Current snapshot", snapshot);
+  }
+  println!();
+  print_info("Run `sys apply` to rebuild and bind the restored configuration");
+  Ok(())
+}
+
+/// Parse a size, also accepting `0` for no threshold.
+fn parse_memory_size_or_zero(value: &str) -> Result<u64, String> {
+  match value.trim() {
+    "0" => Ok(0),
+    size => parse_memory_size(size),
+  }
+}
+
+fn cmd_du(threshold: u64, output: OutputFormat) -> Result<()> {
+  let usage = store_usage(threshold).context("Failed to inspect store")?;
+
+  if output.is_json() {
+    return print_json(&usage);
+  }
+
+  if usage.total_builds == 0 {
+    print_info("The store has no builds");
+    return Ok(());
+  }
+
+  print_stat("Builds", &usage.total_builds.to_string());
+  print_stat("Total", &format_bytes(usage.total_bytes));
+
+  println!();
+  print_info("By id:");
+  for group in &usage.groups {
+    println!(
+      "    {:>10} {} {}",
+      format_bytes(group.bytes),
+      group.group,
+      format!("({} builds)", group.builds).if_supports_color(Stream::Stdout, |s| s.dimmed())
+    );
+  }
+
+  println!();
+  print_info("Builds:");
+  for build in &usage.builds {
+    println!(
+      "    {:>10} {} {}",
+      format_bytes(build.bytes),
+      build_name(build.id.as_deref(), &build.hash),
+      referenced_by(build).if_supports_color(Stream::Stdout, |s| s.dimmed())
+    );
+  }
+
+  let hidden = usage.total_builds - usage.builds.len();
+  if hidden > 0 {
+    println!();
+    print_info(&format!(
+      "{} build(s) smaller than {} hidden",
+      hidden,
+      format_bytes(threshold)
+    ));
+  }
+
+  Ok(())
+}
+
+fn build_name(id: Option<&str>, hash: &str) -> String {
+  match id {
+    Some(id) => format!("{}-{}", id, truncate_hash(hash)),
+    None => truncate_hash(hash).to_string(),
+  }
+}
+
+/// The snapshots referencing a build, newest first.
+fn referenced_by(build: &BuildUsage) -> String {
+  if build.snapshots.is_empty() {
+    return UNREFERENCED_GROUP.to_string();
+  }
+
+  let listed: Vec<&str> = build
+    .snapshots
+    .iter()
+    .rev()
+    .take(MAX_LISTED_SNAPSHOTS)
+    .map(String::as_str)
+    .collect();
+  let more = build.snapshots.len() - listed.len();
+  let more = if more > 0 {
+    format!(" +{} more", more)
+  } else {
+    String::new()
+  };
+  format!("(snapshots: {}{})", listed.join(", "), more)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn referenced_by_lists_newest_snapshots() {
+    let mut build = BuildUsage {
+      hash: "abc".to_string(),
+      id: None,
+      group: UNREFERENCED_GROUP.to_string(),
+      bytes: 0,
+      snapshots: Vec::new(),
+    };
+    assert_eq!(referenced_by(&build), "(unreferenced)");
+
+    build.snapshots = ["s1", "s2", "s3", "s4", "s5"].map(String::from).to_vec();
+    assert_eq!(referenced_by(&build), "(snapshots: s5, s4, s3 +2 more)");
+  }
+
+  #[test]
+  fn threshold_accepts_zero_and_sizes() {
+    assert_eq!(parse_memory_size_or_zero("0"), Ok(0));
+    assert_eq!(parse_memory_size_or_zero("1M"), Ok(1 << 20));
+    assert!(parse_memory_size_or_zero("lots").is_err());
+  }
+}
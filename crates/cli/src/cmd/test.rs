@@ -0,0 +1,65 @@
+//! Implementation of the `sys test` command.
+//!
+//! Runs `*_spec.lua` files found in the config directory and its inputs.
+//! Specs evaluate against a recording runtime, so nothing is built or applied.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use anyhow::{Context, Result, bail};
+use owo_colors::OwoColorize;
+
+use syslua_lib::testing::{TestOptions, run_tests};
+use syslua_lib::update::find_config_path;
+
+use crate::output::{OutputFormat, format_duration, print_info, print_json, symbols};
+
+/// Execute the test command.
+///
+/// Fails if any case fails, after printing every result.
+pub fn cmd_test(
+  config: Option<&str>,
+  filter: Option<String>,
+  input_overrides: BTreeMap<String, String>,
+  output: OutputFormat,
+) -> Result<()> {
+  let start = Instant::now();
+  let config_path = find_config_path(config).context("Failed to find config file")?;
+
+  let options = TestOptions {
+    filter,
+    input_overrides,
+  };
+  let report = run_tests(&config_path, &options).context("Failed to run specs")?;
+
+  if output.is_json() {
+    print_json(&report)?;
+  } else if report.specs == 0 {
+    print_info("No *_spec.lua files found");
+  } else {
+    for case in &report.cases {
+      match &case.failure {
+        None => println!("{} {}", symbols::SUCCESS.green(), case.full_name()),
+        Some(failure) => {
+          println!("{} {}", symbols::ERROR.red(), case.full_name().red());
+          for line in failure.lines() {
+            println!("    {}", line.dimmed());
+          }
+        }
+      }
+    }
+    println!();
+    println!(
+      "{} passed, {} failed ({} spec file(s), {})",
+      report.passed(),
+      report.failed(),
+      report.specs,
+      format_duration(start.elapsed())
+    );
+  }
+
+  if !report.is_success() {
+    bail!("{} test(s) failed", report.failed());
+  }
+  Ok(())
+}
@@ -1,16 +1,19 @@
 //! Implementation of the `sys update` command.
 //!
 //! This command re-resolves inputs (fetching latest revisions) and
-//! updates the lock file and .luarc.json.
+//! updates the lock file and .luarc.json. With `--prune`, it also removes
+//! lock entries nothing uses anymore.
 
+use std::collections::BTreeMap;
 use std::time::Instant;
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use owo_colors::OwoColorize;
 
 use syslua_lib::platform;
 use syslua_lib::update::{UpdateOptions, find_config_path, update_inputs};
 
+use crate::cmd::lock::print_lock_issues;
 use crate::output::{format_duration, symbols};
 
 /// Execute the update command.
@@ -23,11 +26,22 @@ use crate::output::{format_duration, symbols};
 /// * `config` - Optional path to config file. If not provided, uses default resolution.
 /// * `inputs` - Specific inputs to update. If empty, all inputs are updated.
 /// * `dry_run` - If true, show what would change without making changes.
+/// * `input_overrides` - Replacement URLs for inputs; their lock entries are left untouched.
+/// * `refresh` - Look up tags and heads again instead of using cached answers.
+/// * `prune` - Remove stale, orphaned and duplicate lock entries.
 ///
 /// # Errors
 ///
 /// Returns an error if the config cannot be found or input resolution fails.
-pub fn cmd_update(config: Option<&str>, inputs: Vec<String>, dry_run: bool) -> Result<()> {
+/// A dry run reports the inputs that resolved before failing.
+pub fn cmd_update(
+  config: Option<&str>,
+  inputs: Vec<String>,
+  dry_run: bool,
+  input_overrides: BTreeMap<String, String>,
+  refresh: bool,
+  prune: bool,
+) -> Result<()> {
   let start = Instant::now();
   let config_path = find_config_path(config).context("Failed to find config file")?;
   let system = platform::is_elevated();
@@ -36,6 +50,9 @@ pub fn cmd_update(config: Option<&str>, inputs: Vec<String>, dry_run: bool) -> R
     inputs,
     dry_run,
     system,
+    input_overrides,
+    refresh,
+    prune,
   };
 
   let result = update_inputs(&config_path, &options).context("Failed to update inputs")?;
@@ -82,17 +99,43 @@ pub fn cmd_update(config: Option<&str>, inputs: Vec<String>, dry_run: bool) -> R
     }
   }
 
+  // Print overridden inputs
+  if !result.overridden.is_empty() {
+    let names = result.overridden.join(", ");
+    println!(
+      "  {} Overridden (not locked): {}",
+      symbols::WARNING.yellow(),
+      names.yellow()
+    );
+  }
+
   // Print unchanged inputs
   if !result.unchanged.is_empty() {
     let names = result.unchanged.join(", ");
     println!("  {} Unchanged: {}", symbols::INFO.dimmed(), names.dimmed());
   }
 
+  // Print inputs that failed to resolve (dry run only; otherwise update fails above)
+  for (path, error) in &result.failed {
+    println!("  {} Failed: {}: {}", symbols::ERROR.red(), path.cyan(), error);
+  }
+  if !result.failed.is_empty() {
+    bail!("{} input(s) failed to resolve", result.failed.len());
+  }
+
+  // Print pruned lock entries
+  let pruned = result.lock_issues.iter().filter(|issue| issue.is_prunable()).count();
+  print_lock_issues(
+    &result.lock_issues,
+    Some(if dry_run { "Would prune" } else { "Pruned" }),
+  );
+
   // Summary
   let has_changes = !result.updated.is_empty()
     || !result.added.is_empty()
     || !result.transitive_updated.is_empty()
-    || !result.transitive_added.is_empty();
+    || !result.transitive_added.is_empty()
+    || pruned > 0;
 
   if !has_changes {
     println!("{} All inputs are up to date.", symbols::SUCCESS.green());
@@ -0,0 +1,66 @@
+//! Implementation of the `sys vendor` and `sys unvendor` commands.
+//!
+//! Copies the config's inputs into `vendor/` next to `init.lua` and declares
+//! them from there, so the config evaluates without fetching anything, and
+//! undoes it. See [`syslua_lib::update::vendor`].
+
+use anyhow::{Context, Result};
+
+use syslua_lib::platform;
+use syslua_lib::update::find_config_path;
+use syslua_lib::update::vendor::{VENDOR_DIR, unvendor_inputs, vendor_inputs};
+
+use crate::output::{print_info, print_stat, print_success, print_warning};
+
+/// Execute the vendor command.
+///
+/// # Arguments
+///
+/// * `config` - Optional path to config file. If not provided, uses default resolution.
+pub fn cmd_vendor(config: Option<&str>) -> Result<()> {
+  let config_path = find_config_path(config).context("Failed to find config file")?;
+  let result = vendor_inputs(&config_path, platform::is_elevated()).context("Failed to vendor inputs")?;
+
+  if result.vendored.is_empty() {
+    print_info("No inputs to vendor");
+    return Ok(());
+  }
+  let vendor_dir = config_path.parent().unwrap_or(&config_path).join(VENDOR_DIR);
+  print_success(&format!(
+    "Vendored {} input(s) into {}",
+    result.vendored.len(),
+    vendor_dir.display()
+  ));
+  for (input, dir) in &result.vendored {
+    print_stat(input, dir);
+  }
+  if !result.kept.is_empty() {
+    print_stat("In config", &result.kept.join(", "));
+  }
+  print_info("Run 'sys unvendor' to declare the inputs from their sources again");
+  Ok(())
+}
+
+/// Execute the unvendor command.
+///
+/// # Arguments
+///
+/// * `config` - Optional path to config file. If not provided, uses default resolution.
+pub fn cmd_unvendor(config: Option<&str>) -> Result<()> {
+  let config_path = find_config_path(config).context("Failed to find config file")?;
+  let result = unvendor_inputs(&config_path).context("Failed to unvendor inputs")?;
+
+  print_success(&format!(
+    "Restored {} input declaration(s) in {}",
+    result.restored.len(),
+    config_path.display()
+  ));
+  print_stat("Removed", &format!("{} vendored input(s)", result.removed));
+  for name in &result.skipped {
+    print_warning(&format!(
+      "Input '{}' is no longer declared as it was vendored; its declaration was left as it is",
+      name
+    ));
+  }
+  Ok(())
+}
@@ -0,0 +1,205 @@
+//! Reference sections shown by `sys --help` and in the man pages.
+//!
+//! Each [`HelpTopic`] is written once here and rendered both as the plain
+//! text after the option list of `sys --help` ([`after_long_help`]) and as a
+//! man page section by `sys docs man`. Options, subcommands and shell
+//! completions all come from the clap definition in `main.rs`, so the three
+//! can't drift apart.
+
+/// A reference section: an introduction and a list of terms.
+#[derive(Debug, Clone, Copy)]
+pub struct HelpTopic {
+  pub title: &'static str,
+  pub intro: &'static str,
+  pub entries: &'static [(&'static str, &'static str)],
+}
+
+pub const PLACEHOLDERS: HelpTopic = HelpTopic {
+  title: "Placeholders",
+  intro: "Values only known during an apply are recorded in the manifest as placeholders. \
+          Context methods such as ctx:exec() and ctx.out return them, so configs rarely \
+          write them by hand. A single `$` passes through, so shell variables like $HOME work.",
+  entries: &[
+    ("$${{out}}", "Output directory of the build or bind being defined"),
+    ("$${{action:N}}", "Stdout of the Nth action recorded before it"),
+    ("$${{build:HASH:NAME}}", "Output NAME of a realized build"),
+    ("$${{bind:HASH:NAME}}", "Output NAME of an applied bind"),
+    ("$${{env:NAME}}", "Environment variable NAME when the action runs"),
+    (
+      "$${{prev:NAME}}",
+      "Output NAME recorded by the previous apply (bind updates only)",
+    ),
+    ("$$${{", "A literal `$${{`"),
+  ],
+};
+
+pub const LUA_API: HelpTopic = HelpTopic {
+  title: "Lua API",
+  intro: "A config is a Lua file returning { inputs = ..., setup = function(inputs) ... end }. \
+          During setup, the global `sys` table declares what the system should contain.",
+  entries: &[
+    (
+      "sys.api(version)",
+      "Declare the Lua API version the config is written against (default 1)",
+    ),
+    (
+      "sys.build(spec)",
+      "Declare a cached, content-addressed build; returns a BuildRef",
+    ),
+    (
+      "sys.bind(spec)",
+      "Declare a side effect with create/update/destroy/check; returns a BindRef",
+    ),
+    (
+      "sys.pkgset(spec)",
+      "Keep a package manager's installed set (brew) in sync as one bind",
+    ),
+    (
+      "sys.override_build(id, fn)",
+      "Patch a build declared elsewhere (e.g. by an input) before it is hashed",
+    ),
+    (
+      "sys.src(spec)",
+      "Snapshot a local directory into the store as a prebuilt build",
+    ),
+    (
+      "sys.prebuilt(spec)",
+      "Refer to a directory imported with `sys store add`",
+    ),
+    (
+      "sys.getenv(name)",
+      "Placeholder for an environment variable when the action runs",
+    ),
+    (
+      "sys.path",
+      "Path helpers: resolve, join, dirname, basename, extname, ...",
+    ),
+    (
+      "sys.template.render(src, vars)",
+      "Render a template with {{ }}, {% if %} and {% for %} into a string",
+    ),
+    ("sys.os, sys.arch, sys.platform", "The host platform"),
+    (
+      "sys.facts",
+      "Host facts (wsl, container, systemd, ...) for bind `requires`",
+    ),
+    ("sys.vars", "Per-host variables from host_vars/<hostname>.lua"),
+    (
+      "sys.current",
+      "Ids of the builds and binds currently applied (read-only)",
+    ),
+    (
+      "sys.register_build_ctx_method(name, fn)",
+      "Add a method to the BuildCtx of every build",
+    ),
+    (
+      "sys.register_bind_ctx_method(name, fn)",
+      "Add a method to the BindCtx of every bind",
+    ),
+    ("ctx:exec(opts)", "Run a command; returns a placeholder for its stdout"),
+    ("ctx:fetch_url(url, sha256)", "Download a verified file (builds only)"),
+    (
+      "ctx:fetch_url({ url, sha256, unpack = true })",
+      "Download and unpack an archive; returns { archive, src }",
+    ),
+    ("ctx.out", "Placeholder for the output directory"),
+  ],
+};
+
+pub const ENVIRONMENT: HelpTopic = HelpTopic {
+  title: "Environment",
+  intro: "Variables read by sys. --store and settings.store take precedence over the store paths.",
+  entries: &[
+    ("SYSLUA_ROOT", "Directory holding the store, snapshots and plans"),
+    ("SYSLUA_STORE", "Store directory (default: $SYSLUA_ROOT/store)"),
+    (
+      "SYSLUA_SNAPSHOTS",
+      "Snapshot directory (default: $SYSLUA_ROOT/snapshots)",
+    ),
+    ("SYSLUA_PLANS", "Plan directory (default: $SYSLUA_ROOT/plans)"),
+    ("SYSLUA_DAEMON_SOCKET", "Socket or pipe of the daemon"),
+    (
+      "SYSLUA_SYSTEM_DAEMON_SOCKET",
+      "Socket of the system daemon (default: /syslua/daemon.sock)",
+    ),
+    (
+      "SYSLUA_PAGER, PAGER",
+      "Pager for plan and diff output; empty or `cat` turns paging off",
+    ),
+  ],
+};
+
+/// Every reference section, in the order they are shown.
+pub const TOPICS: &[HelpTopic] = &[PLACEHOLDERS, LUA_API, ENVIRONMENT];
+
+/// Width of the term column in the text rendering.
+const TERM_WIDTH: usize = 24;
+
+/// Width the introductions are wrapped to in the text rendering.
+const TEXT_WIDTH: usize = 78;
+
+impl HelpTopic {
+  /// Plain text rendering, as shown by `--help`.
+  pub fn to_text(&self) -> String {
+    let mut text = format!("{}:\n", self.title);
+    for line in wrap(self.intro, TEXT_WIDTH) {
+      text.push_str(&format!("  {}\n", line));
+    }
+    for (term, description) in self.entries {
+      if term.chars().count() < TERM_WIDTH {
+        text.push_str(&format!("  {:<TERM_WIDTH$}{}\n", term, description));
+      } else {
+        text.push_str(&format!("  {}\n  {:<TERM_WIDTH$}{}\n", term, "", description));
+      }
+    }
+    text
+  }
+}
+
+/// Split `text` into lines of at most `width` characters at spaces.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+  let mut lines: Vec<String> = Vec::new();
+  for word in text.split_whitespace() {
+    match lines.last_mut() {
+      Some(line) if line.chars().count() + 1 + word.chars().count() <= width => {
+        line.push(' ');
+        line.push_str(word);
+      }
+      _ => lines.push(word.to_string()),
+    }
+  }
+  lines
+}
+
+/// The reference sections shown after the options of `sys --help`.
+pub fn after_long_help() -> String {
+  TOPICS.iter().map(HelpTopic::to_text).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn text_aligns_terms_and_wraps_long_ones() {
+    let topic = HelpTopic {
+      title: "Example",
+      intro: "Intro.",
+      entries: &[("short", "fits"), ("a-term-that-is-far-too-long", "on the next line")],
+    };
+    assert_eq!(
+      topic.to_text(),
+      format!(
+        "Example:\n  Intro.\n  short{}fits\n  a-term-that-is-far-too-long\n  {}on the next line\n",
+        " ".repeat(TERM_WIDTH - 5),
+        " ".repeat(TERM_WIDTH)
+      )
+    );
+  }
+
+  #[test]
+  fn wrap_breaks_at_spaces() {
+    assert_eq!(wrap("one two three four", 9), vec!["one two", "three", "four"]);
+    assert_eq!(wrap("", 9), Vec::<String>::new());
+  }
+}
@@ -1,14 +1,40 @@
 mod cmd;
+mod help;
 mod output;
 mod prompts;
 
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::time::Duration;
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::{ArgValueCompleter, CompleteEnv};
+use cmd::completions::{
+  COMPLETE_VAR, CompletionShell, complete_bind_groups, complete_bind_selectors, complete_input_names,
+  complete_snapshot_ids,
+};
 use cmd::{
-  cmd_apply, cmd_destroy, cmd_diff, cmd_gc, cmd_info, cmd_init, cmd_plan, cmd_snapshot, cmd_status, cmd_update,
+  cmd_activate_login, cmd_add_input, cmd_agent, cmd_agent_status, cmd_apply, cmd_apply_system, cmd_completions,
+  cmd_daemon, cmd_destroy, cmd_diff, cmd_docs, cmd_eval, cmd_gc, cmd_history, cmd_info, cmd_info_licenses, cmd_init,
+  cmd_lock, cmd_logs, cmd_migrate_config, cmd_plan, cmd_repair, cmd_search, cmd_self_update, cmd_snapshot, cmd_state,
+  cmd_stats, cmd_status, cmd_status_follow, cmd_store, cmd_test, cmd_unvendor, cmd_update, cmd_vendor,
 };
-use output::OutputFormat;
+use output::pager::{self, PagerChoice};
+use output::progress::{self, LogWriter};
+use output::{OutputFormat, report_error};
+use syslua_lib::action::actions::rate_limit::RateLimit;
+use syslua_lib::build::parse_memory_size;
+use syslua_lib::eval::EvalOptions;
+use syslua_lib::execute::fault::FAIL_PHASE_ENV;
+use syslua_lib::execute::{ActionLimits, ExecuteConfig, FailPhase, FailPoint, MetricsSink};
+use syslua_lib::inputs::fetch::install_interrupt_handler;
+use syslua_lib::lua::entrypoint::extract_store_setting;
+use syslua_lib::lua::sandbox::UntrustedInputs;
+use syslua_lib::platform::Platform;
+use syslua_lib::platform::paths::set_store_root;
+use syslua_lib::platform::priority::Throttle;
+use syslua_lib::self_update::Channel;
 use tracing::Level;
 use tracing_subscriber::{Layer, fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -58,8 +84,21 @@ pub enum ColorChoice {
   Never,
 }
 
+/// Parse a `NAME=URL` input override.
+fn parse_input_override(value: &str) -> Result<(String, String), String> {
+  match value.split_once('=') {
+    Some((name, url)) if !name.is_empty() && !url.is_empty() => Ok((name.to_string(), url.to_string())),
+    _ => Err(format!("expected NAME=URL, got '{}'", value)),
+  }
+}
+
+/// Parse a download rate like `500K` or `2M` (bytes per second).
+fn parse_rate(value: &str) -> Result<u64, String> {
+  parse_memory_size(value).map_err(|_| format!("expected a rate in bytes per second like 500K or 2M, got '{}'", value))
+}
+
 #[derive(Parser)]
-#[command(name = "syslua", author, version, about, long_about = None)]
+#[command(name = "syslua", author, version, about, long_about = None, after_long_help = help::after_long_help())]
 struct Cli {
   /// Log verbosity level
   #[arg(short = 'l', long, value_enum, default_value = "info", global = true)]
@@ -73,6 +112,18 @@ struct Cli {
   #[arg(long, value_enum, default_value = "auto", global = true)]
   color: ColorChoice,
 
+  /// Page plan and diff output even if $SYSLUA_PAGER or settings.pager turns paging off
+  #[arg(short = 'p', long, global = true, overrides_with = "no_pager")]
+  pager: bool,
+
+  /// Print plan and diff output without a pager
+  #[arg(long, global = true, overrides_with = "pager")]
+  no_pager: bool,
+
+  /// Keep the store and snapshots under this directory (e.g. a project's .syslua) instead of the user or system store
+  #[arg(long, value_name = "DIR", global = true)]
+  store: Option<PathBuf>,
+
   #[command(subcommand)]
   command: Commands,
 }
@@ -86,32 +137,164 @@ enum Commands {
   },
   /// Evaluate a config and apply changes to the system
   Apply {
-    file: String,
+    #[arg(required_unless_present = "manifest")]
+    file: Option<String>,
+    /// Apply a manifest written by `sys eval` instead of evaluating a config
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["file", "impure", "override_inputs", "vars_file", "platform"])]
+    manifest: Option<PathBuf>,
     /// Check unchanged binds for drift and repair if needed
     #[arg(long)]
     repair: bool,
     /// Allow impure Lua libs (io, os). Breaks determinism.
     #[arg(long)]
     impure: bool,
+    /// Override an input's URL without touching the lock file (NAME=URL, can be repeated)
+    #[arg(long = "override-input", value_name = "NAME=URL", value_parser = parse_input_override)]
+    override_inputs: Vec<(String, String)>,
+    /// How to evaluate inputs not declared `trusted = true` (sandbox or allow)
+    #[arg(long, value_name = "POLICY", default_value = "sandbox")]
+    untrusted_inputs: UntrustedInputs,
+    /// Load host vars from this file instead of host_vars/<hostname>.lua
+    #[arg(long, value_name = "FILE")]
+    vars_file: Option<PathBuf>,
+    /// Refuse to apply unless this host is of this platform (e.g. aarch64-darwin)
+    #[arg(long, value_name = "PLATFORM")]
+    platform: Option<Platform>,
+    /// List pending bind changes and choose which ones to skip before applying
+    #[arg(short, long)]
+    interactive: bool,
+    /// Only apply bind changes of this group; others stay pending (can be repeated)
+    #[arg(long = "group", value_name = "GROUP", add = ArgValueCompleter::new(complete_bind_groups))]
+    groups: Vec<String>,
+    /// Deny build commands network access; only fetch_url may download
+    #[arg(long)]
+    isolate_network: bool,
+    /// Don't run the check actions of builds
+    #[arg(long)]
+    skip_checks: bool,
+    /// Don't check free disk space and writable bind directories before changing anything
+    #[arg(long)]
+    skip_preflight: bool,
+    /// Leave realized build outputs writable, for filesystems that mishandle read-only files
+    #[arg(long)]
+    no_readonly: bool,
+    /// Lower the priority of spawned build and bind commands by this niceness (0-19)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(0..=19))]
+    nice: Option<u8>,
+    /// Run commands at the lowest CPU and I/O priority, one build or bind at a time
+    #[arg(long)]
+    background: bool,
+    /// Cap the combined rate of fetch_url downloads, in bytes per second (e.g. 500K, 2M)
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    limit_rate: Option<u64>,
+    /// Run at most N fetch_url downloads at once (0 = unlimited) [default: 4]
+    #[arg(long, value_name = "N")]
+    max_fetch: Option<usize>,
+    /// Run at most N package manager commands at once (0 = unlimited) [default: 1]
+    #[arg(long, value_name = "N")]
+    max_pkg: Option<usize>,
+    /// Write Prometheus metrics of the apply to this file, e.g. for node_exporter's textfile collector
+    #[arg(long, value_name = "PATH")]
+    metrics_textfile: Option<PathBuf>,
+    /// Push Prometheus metrics of the apply to the Pushgateway at this URL
+    #[arg(long, value_name = "URL")]
+    metrics_push: Option<String>,
+    /// Run builds that failed recently again instead of failing before changing anything
+    #[arg(long)]
+    retry_failed: bool,
+    /// How long a failed build is skipped unless its definition changes (0s = never)
+    #[arg(long, value_name = "DURATION", default_value = "1h", value_parser = humantime::parse_duration)]
+    failure_ttl: Duration,
+    /// Fail the build or bind with this id or hash prefix before it runs, to test rollback
+    #[arg(long, value_name = "NODE", hide = true)]
+    fail_at: Option<String>,
+    /// Only fail the --fail-at node when it is built, created or updated
+    #[arg(long, value_name = "PHASE", requires = "fail_at", hide = true)]
+    fail_phase: Option<FailPhase>,
+    /// Submit the evaluated manifest to the system daemon instead of applying it here
+    #[arg(long, conflicts_with_all = [
+      "interactive", "groups", "isolate_network", "skip_checks", "skip_preflight",
+      "no_readonly", "nice", "background", "limit_rate", "fail_at", "metrics_textfile", "metrics_push",
+      "retry_failed",
+    ])]
+    system: bool,
+    /// Only realize the builds in the system store, without applying binds
+    #[arg(long, requires = "system")]
+    build_only: bool,
     /// Output format
     #[arg(short, long, value_enum, default_value = "text")]
     output: OutputFormat,
   },
+  /// Evaluate a config into a manifest document that can be applied elsewhere
+  Eval {
+    file: String,
+    /// Allow impure Lua libs (io, os). Breaks determinism.
+    #[arg(long)]
+    impure: bool,
+    /// Override an input's URL without touching the lock file (NAME=URL, can be repeated)
+    #[arg(long = "override-input", value_name = "NAME=URL", value_parser = parse_input_override)]
+    override_inputs: Vec<(String, String)>,
+    /// How to evaluate inputs not declared `trusted = true` (sandbox or allow)
+    #[arg(long, value_name = "POLICY", default_value = "sandbox")]
+    untrusted_inputs: UntrustedInputs,
+    /// Load host vars from this file instead of host_vars/<hostname>.lua
+    #[arg(long, value_name = "FILE")]
+    vars_file: Option<PathBuf>,
+    /// Evaluate for this platform instead of this host's (e.g. aarch64-darwin)
+    #[arg(long, value_name = "PLATFORM")]
+    platform: Option<Platform>,
+    /// Write the manifest to this file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+  },
   /// Evaluate a config and create a plan without applying
   Plan {
     file: String,
     /// Allow impure Lua libs (io, os). Breaks determinism.
     #[arg(long)]
     impure: bool,
+    /// Override an input's URL without touching the lock file (NAME=URL, can be repeated)
+    #[arg(long = "override-input", value_name = "NAME=URL", value_parser = parse_input_override)]
+    override_inputs: Vec<(String, String)>,
+    /// How to evaluate inputs not declared `trusted = true` (sandbox or allow)
+    #[arg(long, value_name = "POLICY", default_value = "sandbox")]
+    untrusted_inputs: UntrustedInputs,
+    /// Load host vars from this file instead of host_vars/<hostname>.lua
+    #[arg(long, value_name = "FILE")]
+    vars_file: Option<PathBuf>,
+    /// Evaluate for this platform instead of this host's, without checking this host for drift (e.g. aarch64-darwin)
+    #[arg(long, value_name = "PLATFORM")]
+    platform: Option<Platform>,
+    /// List builds that failed within this long as previously failed
+    #[arg(long, value_name = "DURATION", default_value = "1h", value_parser = humantime::parse_duration)]
+    failure_ttl: Duration,
     /// Output format
     #[arg(short, long, value_enum, default_value = "text")]
     output: OutputFormat,
   },
-  /// Remove all binds from the current snapshot
+  /// Remove all (or selected) binds from the current snapshot
   Destroy {
     /// Show what would be destroyed without making changes
     #[arg(long)]
     dry_run: bool,
+    /// Only destroy binds with this id, hash prefix or tag, plus their dependents (can be repeated)
+    #[arg(long, value_name = "SELECTOR", add = ArgValueCompleter::new(complete_bind_selectors))]
+    only: Vec<String>,
+    /// Only destroy binds of this group, plus their dependents (can be repeated)
+    #[arg(long = "group", value_name = "GROUP", add = ArgValueCompleter::new(complete_bind_groups))]
+    groups: Vec<String>,
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+  /// Check selected binds for drift and repair them, leaving the rest alone
+  Repair {
+    /// Binds to check and repair, by id, hash prefix or tag (dependents are not included)
+    #[arg(required = true, value_name = "SELECTOR", add = ArgValueCompleter::new(complete_bind_selectors))]
+    selectors: Vec<String>,
+    /// Only check for drift, without repairing
+    #[arg(long)]
+    dry_run: bool,
     /// Output format
     #[arg(short, long, value_enum, default_value = "text")]
     output: OutputFormat,
@@ -119,16 +302,19 @@ enum Commands {
   /// Compare two snapshots and show differences
   Diff {
     /// First snapshot ID (defaults to previous if not specified)
-    #[arg(value_name = "SNAPSHOT_A")]
+    #[arg(value_name = "SNAPSHOT_A", add = ArgValueCompleter::new(complete_snapshot_ids))]
     snapshot_a: Option<String>,
 
     /// Second snapshot ID (defaults to current if not specified)
-    #[arg(value_name = "SNAPSHOT_B")]
+    #[arg(value_name = "SNAPSHOT_B", add = ArgValueCompleter::new(complete_snapshot_ids))]
     snapshot_b: Option<String>,
 
     /// Show detailed changes with actions
     #[arg(short, long)]
     verbose: bool,
+    /// Show removed and added builds and binds in two columns, old and new
+    #[arg(short = 'y', long, conflicts_with = "verbose")]
+    side_by_side: bool,
     /// Output format
     #[arg(short, long, value_enum, default_value = "text")]
     output: OutputFormat,
@@ -140,20 +326,113 @@ enum Commands {
     config: Option<String>,
 
     /// Update only specific input(s) (can be repeated)
-    #[arg(short, long = "input", value_name = "NAME")]
+    #[arg(short, long = "input", value_name = "NAME", add = ArgValueCompleter::new(complete_input_names))]
     inputs: Vec<String>,
 
     /// Show what would change without making changes
     #[arg(long)]
     dry_run: bool,
+
+    /// Override an input's URL without touching the lock file (NAME=URL, can be repeated)
+    #[arg(long = "override-input", value_name = "NAME=URL", value_parser = parse_input_override)]
+    override_inputs: Vec<(String, String)>,
+
+    /// Look up tags and branch heads again instead of using cached answers
+    #[arg(long)]
+    refresh: bool,
+
+    /// Remove lock entries nothing uses anymore (see `sys lock audit`)
+    #[arg(long)]
+    prune: bool,
+  },
+  /// Search the configured registries of community inputs
+  Search {
+    /// Matched against input names, tags and descriptions (omit to list everything)
+    #[arg(default_value = "")]
+    term: String,
+    /// Config holding `settings.registries` (default: ./init.lua or ~/.config/syslua/init.lua)
+    #[arg(long, value_name = "CONFIG")]
+    config: Option<String>,
+    /// Search this registry index instead of the configured ones (can be repeated)
+    #[arg(long = "registry", value_name = "URL")]
+    registries: Vec<String>,
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+  /// Declare an input from a registry in the config and lock it
+  AddInput {
+    /// Name of the input in the registries (see `sys search`)
+    name: String,
+    /// Path to config file (default: ./init.lua or ~/.config/syslua/init.lua)
+    #[arg(value_name = "CONFIG")]
+    config: Option<String>,
+    /// Declare the input under this name instead
+    #[arg(long = "as", value_name = "NAME")]
+    alias: Option<String>,
+    /// Look the input up in this registry index instead of the configured ones (can be repeated)
+    #[arg(long = "registry", value_name = "URL")]
+    registries: Vec<String>,
+  },
+  /// Copy all inputs into vendor/ in the config repository and declare them from there
+  Vendor {
+    /// Path to config file (default: ./init.lua or ~/.config/syslua/init.lua)
+    #[arg(value_name = "CONFIG")]
+    config: Option<String>,
+  },
+  /// Declare vendored inputs from their sources again and remove the copies
+  Unvendor {
+    /// Path to config file (default: ./init.lua or ~/.config/syslua/init.lua)
+    #[arg(value_name = "CONFIG")]
+    config: Option<String>,
+  },
+  /// Replace this executable with the latest release of a channel
+  SelfUpdate {
+    /// Config holding `settings.self_update` (default: ./init.lua or ~/.config/syslua/init.lua)
+    #[arg(long, value_name = "CONFIG")]
+    config: Option<String>,
+    /// Release channel (stable or nightly), overriding `settings.self_update.channel`
+    #[arg(long, value_name = "CHANNEL")]
+    channel: Option<Channel>,
+    /// Only check whether an update is available
+    #[arg(long)]
+    check: bool,
+    /// Install the channel's release even if it is not newer than this one (e.g., to switch channels)
+    #[arg(long, conflicts_with = "check")]
+    allow_downgrade: bool,
+    /// Install a release verified only by its SHA256 when no release public key is built in or configured
+    #[arg(long)]
+    insecure: bool,
   },
   /// Display system information
-  Info,
+  Info {
+    /// Config to report on with --licenses
+    #[arg(requires = "licenses")]
+    file: Option<String>,
+    /// List every input and build of the config with its declared license
+    #[arg(long, requires = "file")]
+    licenses: bool,
+    /// Allow impure Lua libs (io, os). Breaks determinism.
+    #[arg(long)]
+    impure: bool,
+    /// How to evaluate inputs not declared `trusted = true` (sandbox or allow)
+    #[arg(long, value_name = "POLICY", default_value = "sandbox")]
+    untrusted_inputs: UntrustedInputs,
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
   /// Show current system state
   Status {
-    /// Show all builds and binds
+    /// Show all builds, binds and backed up files
     #[arg(short, long)]
     verbose: bool,
+    /// Show the scheduled apply agent and the outcome of its last runs instead
+    #[arg(long)]
+    agent: bool,
+    /// Follow the apply running in another terminal until it ends, waiting for one if none is running
+    #[arg(short, long, conflicts_with_all = ["agent", "verbose"])]
+    follow: bool,
     /// Output format
     #[arg(short, long, value_enum, default_value = "text")]
     output: OutputFormat,
@@ -167,14 +446,183 @@ enum Commands {
     #[arg(short, long, value_enum, default_value = "text")]
     output: OutputFormat,
   },
+  /// Audit the lock file of a config
+  Lock {
+    #[command(subcommand)]
+    command: cmd::lock::LockCommand,
+  },
+  /// Inspect what takes up space in the store
+  Store {
+    #[command(subcommand)]
+    command: cmd::store::StoreCommand,
+  },
+  /// Show the slowest builds and flakiest binds from past applies
+  Stats {
+    /// Number of entries to show per list
+    #[arg(short = 'n', long, default_value_t = 10)]
+    limit: usize,
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+  /// Show the journal of applies, destroys and repairs, and verify it wasn't tampered with
+  History {
+    /// Number of most recent entries to show (0 shows all)
+    #[arg(short = 'n', long, default_value_t = 20)]
+    limit: usize,
+    /// Verify the journal's hash chain and signatures
+    #[arg(long)]
+    verify: bool,
+    /// Hex-encoded machine public key (or path to a file containing it) every
+    /// signed entry must be signed with; implies --verify
+    #[arg(long)]
+    public_key: Option<String>,
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+  /// Show the commands past applies ran: command lines, env, cwd, exit codes and durations
+  Logs {
+    /// Show the commands of the build or bind with this id or hash prefix, from the latest apply that ran any
+    #[arg(long, value_name = "NODE")]
+    transcript: Option<String>,
+    /// Show the transcript of this apply (an id listed by `sys logs`)
+    #[arg(long, value_name = "ID")]
+    apply: Option<String>,
+    /// Accepted for compatibility; secrets are always redacted
+    #[arg(long, hide = true)]
+    redact: bool,
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
   /// Manage snapshots
   Snapshot {
     #[command(subcommand)]
     command: cmd::snapshot::SnapshotCommand,
   },
+  /// Export or verify signed machine state documents
+  State {
+    #[command(subcommand)]
+    command: cmd::state::StateCommand,
+  },
+  /// Keep this machine in sync with a config in git by re-applying it on a schedule
+  Agent {
+    #[command(subcommand)]
+    command: cmd::agent::AgentCommand,
+  },
+  /// Run the login-phase binds of the current snapshot, as the login hook does at login
+  ActivateLogin {
+    /// Run their destroy actions instead, as at logout
+    #[arg(long, conflicts_with_all = ["install", "uninstall"])]
+    logout: bool,
+    /// Install the hook that runs this command at every login
+    #[arg(long, conflicts_with = "uninstall")]
+    install: bool,
+    /// Remove the login hook
+    #[arg(long)]
+    uninstall: bool,
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+  /// Run a background daemon that speeds up repeated plan/apply
+  Daemon {
+    #[command(subcommand)]
+    command: cmd::daemon::DaemonCommand,
+  },
+  /// Generate documentation, such as man pages, from the command definitions
+  Docs {
+    #[command(subcommand)]
+    command: cmd::docs::DocsCommand,
+  },
+  /// Print a shell completion script (e.g. `source <(sys completions bash)`)
+  Completions {
+    /// Shell to complete in
+    #[arg(value_enum)]
+    shell: CompletionShell,
+  },
+  /// Rewrite deprecated derive{}/activate{} calls into sys.build{}/sys.bind{}
+  MigrateConfig {
+    /// Lua file or directory to migrate (default: the config's directory)
+    #[arg(value_name = "PATH")]
+    path: Option<String>,
+    /// Report what would be rewritten without changing any file
+    #[arg(long)]
+    dry_run: bool,
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+  /// Run *_spec.lua files from the config and its inputs
+  Test {
+    /// Path to config file (default: ./init.lua or ~/.config/syslua/init.lua)
+    #[arg(value_name = "CONFIG")]
+    config: Option<String>,
+    /// Only run cases whose name contains this string
+    #[arg(short, long)]
+    filter: Option<String>,
+    /// Override an input's URL without touching the lock file (NAME=URL, can be repeated)
+    #[arg(long = "override-input", value_name = "NAME=URL", value_parser = parse_input_override)]
+    override_inputs: Vec<(String, String)>,
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    output: OutputFormat,
+  },
+}
+
+impl Commands {
+  /// The `--output` format of the command; text for commands without one.
+  fn output_format(&self) -> OutputFormat {
+    match self {
+      Commands::Apply { output, .. }
+      | Commands::Plan { output, .. }
+      | Commands::Destroy { output, .. }
+      | Commands::Repair { output, .. }
+      | Commands::Diff { output, .. }
+      | Commands::Info { output, .. }
+      | Commands::Status { output, .. }
+      | Commands::Gc { output, .. }
+      | Commands::Stats { output, .. }
+      | Commands::History { output, .. }
+      | Commands::Logs { output, .. }
+      | Commands::Search { output, .. }
+      | Commands::ActivateLogin { output, .. }
+      | Commands::MigrateConfig { output, .. }
+      | Commands::Test { output, .. } => *output,
+      _ => OutputFormat::Text,
+    }
+  }
+}
+
+/// Select the store root from `--store`, or else from the `settings.store` of
+/// the config an apply or plan evaluates.
+fn select_store(store: Option<&Path>, command: &Commands) -> anyhow::Result<()> {
+  let root = match (store, command) {
+    (Some(store), _) => Some(store.to_path_buf()),
+    (None, Commands::Apply { file: Some(file), .. } | Commands::Plan { file, .. }) if Path::new(file).is_file() => {
+      extract_store_setting(file).map_err(|e| anyhow::anyhow!("Failed to read settings.store: {e}"))?
+    }
+    _ => None,
+  };
+  if let Some(root) = root {
+    set_store_root(&root);
+  }
+  Ok(())
+}
+
+/// The node `--fail-at`, or else `SYSLUA_FAIL_AT`, fails on purpose.
+fn fail_point(target: Option<String>, phase: Option<FailPhase>) -> anyhow::Result<Option<FailPoint>> {
+  match target {
+    Some(target) => Ok(Some(FailPoint { target, phase })),
+    None => FailPoint::from_env().map_err(|e| anyhow::anyhow!("Invalid {}: {}", FAIL_PHASE_ENV, e)),
+  }
 }
 
 fn main() -> ExitCode {
+  // Answers completion requests from the scripts of `sys completions` and exits
+  CompleteEnv::with_factory(Cli::command).var(COMPLETE_VAR).complete();
+
   let cli = Cli::parse();
 
   match cli.color {
@@ -182,16 +630,27 @@ fn main() -> ExitCode {
     ColorChoice::Never => owo_colors::set_override(false),
     ColorChoice::Auto => {}
   }
+  pager::configure(
+    match (cli.pager, cli.no_pager) {
+      (true, _) => PagerChoice::Always,
+      (_, true) => PagerChoice::Never,
+      _ => PagerChoice::Auto,
+    },
+    matches!(cli.color, ColorChoice::Auto),
+  );
 
   let level: Level = cli.log_level.into();
   let show_timestamps = matches!(cli.log_level, LogLevel::Debug | LogLevel::Trace);
 
   match cli.log_format {
     LogFormat::Pretty => {
+      // Pretty logs share the terminal with the apply progress display
+      progress::enable();
       if show_timestamps {
         tracing_subscriber::registry()
           .with(
             fmt::layer()
+              .with_writer(LogWriter)
               .with_target(true)
               .with_filter(tracing_subscriber::filter::LevelFilter::from_level(level)),
           )
@@ -200,6 +659,7 @@ fn main() -> ExitCode {
         tracing_subscriber::registry()
           .with(
             fmt::layer()
+              .with_writer(LogWriter)
               .without_time()
               .with_target(false)
               .with_filter(tracing_subscriber::filter::LevelFilter::from_level(level)),
@@ -221,40 +681,269 @@ fn main() -> ExitCode {
     }
   }
 
+  // Ctrl-C during input resolution cancels fetches instead of exiting
+  if let Err(err) = install_interrupt_handler() {
+    tracing::debug!(error = %err, "failed to install Ctrl-C handler");
+  }
+
+  if let Err(err) = select_store(cli.store.as_deref(), &cli.command) {
+    eprintln!("Error: {err:?}");
+    return ExitCode::FAILURE;
+  }
+
+  let error_format = cli.command.output_format();
   let result = match cli.command {
     Commands::Init { path } => cmd_init(&path),
     Commands::Apply {
       file,
+      manifest,
       repair,
       impure,
+      override_inputs,
+      untrusted_inputs,
+      vars_file,
+      platform,
+      system: true,
+      build_only,
+      output,
+      ..
+    } => cmd_apply_system(
+      file.as_deref(),
+      manifest.as_deref(),
+      repair,
+      EvalOptions {
+        impure,
+        input_overrides: BTreeMap::from_iter(override_inputs),
+        untrusted_inputs,
+        vars_file,
+        platform,
+      },
+      build_only,
       output,
-    } => cmd_apply(&file, repair, impure, output),
-    Commands::Plan { file, impure, output } => cmd_plan(&file, impure, output),
-    Commands::Destroy { dry_run, output } => cmd_destroy(dry_run, output),
+    ),
+    Commands::Apply {
+      file,
+      manifest,
+      repair,
+      impure,
+      override_inputs,
+      untrusted_inputs,
+      vars_file,
+      platform,
+      interactive,
+      groups,
+      isolate_network,
+      skip_checks,
+      skip_preflight,
+      no_readonly,
+      nice,
+      background,
+      limit_rate,
+      max_fetch,
+      max_pkg,
+      metrics_textfile,
+      metrics_push,
+      retry_failed,
+      failure_ttl,
+      fail_at,
+      fail_phase,
+      output,
+      ..
+    } => fail_point(fail_at, fail_phase).and_then(|fail_at| {
+      cmd_apply(
+        file.as_deref(),
+        manifest.as_deref(),
+        repair,
+        EvalOptions {
+          impure,
+          input_overrides: BTreeMap::from_iter(override_inputs),
+          untrusted_inputs,
+          vars_file,
+          platform,
+        },
+        ExecuteConfig {
+          isolate_network,
+          skip_checks,
+          skip_preflight,
+          writable_store: no_readonly,
+          throttle: Throttle {
+            nice: nice.unwrap_or(0),
+            background,
+          },
+          limit_rate: RateLimit::new(limit_rate),
+          action_limits: ActionLimits::new(max_fetch, max_pkg),
+          fail_at,
+          retry_failed,
+          failure_ttl_secs: failure_ttl.as_secs(),
+          ..Default::default()
+        },
+        interactive,
+        groups,
+        MetricsSink {
+          textfile: metrics_textfile,
+          push_url: metrics_push,
+        },
+        output,
+      )
+    }),
+    Commands::Eval {
+      file,
+      impure,
+      override_inputs,
+      untrusted_inputs,
+      vars_file,
+      platform,
+      output,
+    } => cmd_eval(
+      &file,
+      impure,
+      BTreeMap::from_iter(override_inputs),
+      untrusted_inputs,
+      vars_file,
+      platform,
+      output.as_deref(),
+    ),
+    Commands::Plan {
+      file,
+      impure,
+      override_inputs,
+      untrusted_inputs,
+      vars_file,
+      platform,
+      failure_ttl,
+      output,
+    } => cmd_plan(
+      &file,
+      impure,
+      BTreeMap::from_iter(override_inputs),
+      untrusted_inputs,
+      vars_file,
+      platform,
+      failure_ttl,
+      output,
+    ),
+    Commands::Destroy {
+      dry_run,
+      only,
+      groups,
+      output,
+    } => cmd_destroy(dry_run, only, groups, output),
+    Commands::Repair {
+      selectors,
+      dry_run,
+      output,
+    } => cmd_repair(selectors, dry_run, output),
     Commands::Diff {
       snapshot_a,
       snapshot_b,
       verbose,
+      side_by_side,
       output,
-    } => cmd_diff(snapshot_a, snapshot_b, verbose, output),
+    } => cmd_diff(snapshot_a, snapshot_b, verbose, side_by_side, output),
     Commands::Update {
       config,
       inputs,
       dry_run,
-    } => cmd_update(config.as_deref(), inputs, dry_run),
-    Commands::Info => {
-      cmd_info();
-      Ok(())
+      override_inputs,
+      refresh,
+      prune,
+    } => cmd_update(
+      config.as_deref(),
+      inputs,
+      dry_run,
+      BTreeMap::from_iter(override_inputs),
+      refresh,
+      prune,
+    ),
+    Commands::Search {
+      term,
+      config,
+      registries,
+      output,
+    } => cmd_search(&term, config.as_deref(), registries, output),
+    Commands::AddInput {
+      name,
+      config,
+      alias,
+      registries,
+    } => cmd_add_input(&name, config.as_deref(), alias.as_deref(), registries),
+    Commands::Vendor { config } => cmd_vendor(config.as_deref()),
+    Commands::Unvendor { config } => cmd_unvendor(config.as_deref()),
+    Commands::SelfUpdate {
+      config,
+      channel,
+      check,
+      allow_downgrade,
+      insecure,
+    } => cmd_self_update(config.as_deref(), channel, check, allow_downgrade, insecure),
+    Commands::Info {
+      file,
+      licenses,
+      impure,
+      untrusted_inputs,
+      output,
+    } => match file {
+      Some(file) if licenses => cmd_info_licenses(&file, impure, untrusted_inputs, output),
+      _ => {
+        cmd_info();
+        Ok(())
+      }
+    },
+    Commands::Status {
+      verbose,
+      agent,
+      follow,
+      output,
+    } => {
+      if agent {
+        cmd_agent_status(output)
+      } else if follow {
+        cmd_status_follow(output)
+      } else {
+        cmd_status(verbose, output)
+      }
     }
-    Commands::Status { verbose, output } => cmd_status(verbose, output),
     Commands::Gc { dry_run, output } => cmd_gc(dry_run, output),
+    Commands::Stats { limit, output } => cmd_stats(limit, output),
+    Commands::History {
+      limit,
+      verify,
+      public_key,
+      output,
+    } => cmd_history(limit, verify, public_key.as_deref(), output),
+    Commands::Logs {
+      transcript,
+      apply,
+      redact: _,
+      output,
+    } => cmd_logs(transcript.as_deref(), apply.as_deref(), output),
+    Commands::Lock { command } => cmd_lock(command),
+    Commands::Store { command } => cmd_store(command),
     Commands::Snapshot { command } => cmd_snapshot(command),
+    Commands::State { command } => cmd_state(command),
+    Commands::Agent { command } => cmd_agent(command),
+    Commands::ActivateLogin {
+      logout,
+      install,
+      uninstall,
+      output,
+    } => cmd_activate_login(logout, install, uninstall, output),
+    Commands::Daemon { command } => cmd_daemon(command),
+    Commands::Docs { command } => cmd_docs(command),
+    Commands::Completions { shell } => cmd_completions(shell),
+    Commands::MigrateConfig { path, dry_run, output } => cmd_migrate_config(path.as_deref(), dry_run, output),
+    Commands::Test {
+      config,
+      filter,
+      override_inputs,
+      output,
+    } => cmd_test(config.as_deref(), filter, BTreeMap::from_iter(override_inputs), output),
   };
 
   match result {
     Ok(()) => ExitCode::SUCCESS,
     Err(err) => {
-      eprintln!("Error: {err:?}");
+      report_error(&err, error_format);
       ExitCode::FAILURE
     }
   }
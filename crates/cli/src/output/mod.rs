@@ -0,0 +1,681 @@
+//! CLI output formatting utilities.
+//!
+//! Provides consistent formatting for terminal output including colored status
+//! messages, human-readable byte/duration formatting, and Unicode symbols.
+//! The live progress display for apply lives in [`progress`], paging of long
+//! output in [`pager`].
+
+pub mod pager;
+pub mod progress;
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use owo_colors::{OwoColorize, Stream};
+use serde::Serialize;
+use syslua_lib::execute::hooks::BindOperation;
+use syslua_lib::execute::{ApplyResult, BindTouches, PreviousFailure, TouchAction};
+use syslua_lib::lua::api::Deprecation;
+use syslua_lib::lua::diagnostics::Diagnostic;
+use syslua_lib::manifest::{OverriddenBuild, SkippedBind, SkippedBuild};
+use syslua_lib::platform::paths::home_dir;
+use syslua_lib::snapshot::{GroupChanges, Provenance};
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum OutputFormat {
+  #[default]
+  Text,
+  Json,
+}
+
+impl OutputFormat {
+  pub fn is_json(self) -> bool {
+    matches!(self, OutputFormat::Json)
+  }
+}
+
+pub mod symbols {
+  pub const SUCCESS: &str = "✓";
+  pub const ERROR: &str = "✗";
+  pub const WARNING: &str = "⚠";
+  pub const INFO: &str = "•";
+  pub const ARROW: &str = "→";
+  pub const PLUS: &str = "+";
+  pub const MINUS: &str = "-";
+  pub const TILDE: &str = "~";
+  pub const ADD: &str = "+";
+  pub const MODIFY: &str = "~";
+  pub const REMOVE: &str = "-";
+}
+
+pub fn truncate_hash(hash: &str) -> &str {
+  let len = hash.len().min(12);
+  &hash[..len]
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+  const KB: u64 = 1024;
+  const MB: u64 = KB * 1024;
+  const GB: u64 = MB * 1024;
+
+  if bytes >= GB {
+    format!("{:.1} GB", bytes as f64 / GB as f64)
+  } else if bytes >= MB {
+    format!("{:.1} MB", bytes as f64 / MB as f64)
+  } else if bytes >= KB {
+    format!("{:.1} KB", bytes as f64 / KB as f64)
+  } else {
+    format!("{} B", bytes)
+  }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+  let secs = duration.as_secs();
+  let millis = duration.subsec_millis();
+
+  if secs >= 60 {
+    let mins = secs / 60;
+    let remaining_secs = secs % 60;
+    format!("{}m {}s", mins, remaining_secs)
+  } else if secs > 0 {
+    format!("{}.{:02}s", secs, millis / 10)
+  } else {
+    format!("{}ms", millis)
+  }
+}
+
+/// How long ago a Unix timestamp was, e.g. `3 hours ago`.
+pub fn format_timestamp(timestamp: u64) -> String {
+  let datetime = UNIX_EPOCH + Duration::from_secs(timestamp);
+  if let Ok(duration) = SystemTime::now().duration_since(datetime) {
+    let secs = duration.as_secs();
+    if secs < 60 {
+      format!("{} seconds ago", secs)
+    } else if secs < 3600 {
+      format!("{} minutes ago", secs / 60)
+    } else if secs < 86400 {
+      format!("{} hours ago", secs / 3600)
+    } else {
+      format!("{} days ago", secs / 86400)
+    }
+  } else {
+    format!("timestamp: {}", timestamp)
+  }
+}
+
+pub fn print_success(message: &str) {
+  println!(
+    "{} {}",
+    symbols::SUCCESS.if_supports_color(Stream::Stdout, |s| s.green()),
+    message
+  );
+}
+
+pub fn print_error(message: &str) {
+  eprintln!(
+    "{} {}",
+    symbols::ERROR.if_supports_color(Stream::Stderr, |s| s.red()),
+    message.if_supports_color(Stream::Stderr, |s| s.red())
+  );
+}
+
+/// Report the error a command failed with.
+///
+/// Errors from evaluating or applying a config are diagnosed: the phase, the
+/// spec function that raised it and the Lua traceback are shown below the
+/// outermost message. With `--output json`, the diagnostic is printed to
+/// stdout as `{"error": ...}` instead.
+pub fn report_error(err: &anyhow::Error, format: OutputFormat) {
+  let diagnostic = Diagnostic::from_error(err.as_ref());
+  if format.is_json() {
+    match serde_json::to_string_pretty(&serde_json::json!({ "error": diagnostic })) {
+      Ok(json) => println!("{}", json),
+      Err(_) => eprintln!("Error: {err:?}"),
+    }
+    return;
+  }
+
+  match (diagnostic.phase, diagnostic.chain.first()) {
+    (None, _) => eprintln!("Error: {err:?}"),
+    (Some(_), Some(outer)) if *outer != diagnostic.message => eprintln!("Error: {}\n{}", outer, diagnostic),
+    (Some(_), _) => eprintln!("Error: {}", diagnostic),
+  }
+}
+
+pub fn print_warning(message: &str) {
+  eprintln!(
+    "{} {}",
+    symbols::WARNING.if_supports_color(Stream::Stderr, |s| s.yellow()),
+    message.if_supports_color(Stream::Stderr, |s| s.yellow())
+  );
+}
+
+/// Warn that inputs were resolved from overrides instead of the lock file.
+pub fn print_input_overrides(overrides: &BTreeMap<String, String>) {
+  if overrides.is_empty() {
+    return;
+  }
+  print_warning("Input overrides active (not recorded in lock file):");
+  for (name, url) in overrides {
+    eprintln!(
+      "    {} {} = {}",
+      symbols::MINUS.if_supports_color(Stream::Stderr, |s| s.yellow()),
+      name,
+      url
+    );
+  }
+}
+
+/// List builds skipped because they are for other `platforms`.
+pub fn print_skipped_builds(skipped: &[SkippedBuild]) {
+  if skipped.is_empty() {
+    return;
+  }
+  print_info(&format!("Skipped {} build(s):", skipped.len()));
+  for build in skipped {
+    println!(
+      "    {} {}: {}",
+      symbols::MINUS.if_supports_color(Stream::Stdout, |s| s.dimmed()),
+      build.id.as_deref().unwrap_or("(unnamed)"),
+      build.reason
+    );
+  }
+}
+
+/// List binds skipped because this host doesn't meet their `requires` or `platforms`.
+pub fn print_skipped_binds(skipped: &[SkippedBind]) {
+  if skipped.is_empty() {
+    return;
+  }
+  print_info(&format!("Skipped {} bind(s):", skipped.len()));
+  for bind in skipped {
+    println!(
+      "    {} {}: {}",
+      symbols::MINUS.if_supports_color(Stream::Stdout, |s| s.dimmed()),
+      bind.id.as_deref().unwrap_or("(unnamed)"),
+      bind.reason
+    );
+  }
+}
+
+/// List builds changed by `sys.override_build`, with where each override is.
+pub fn print_overridden_builds(overridden: &[OverriddenBuild]) {
+  if overridden.is_empty() {
+    return;
+  }
+  print_info(&format!("Overridden {} build(s):", overridden.len()));
+  for build in overridden {
+    println!(
+      "    {} {} ({}): {}",
+      symbols::MINUS.if_supports_color(Stream::Stdout, |s| s.dimmed()),
+      build.id,
+      truncate_hash(&build.hash.0),
+      build.overrides.join(", ")
+    );
+  }
+}
+
+/// List the calls of deprecated Lua helpers, with their replacements.
+pub fn print_deprecations(deprecations: &[Deprecation]) {
+  if deprecations.is_empty() {
+    return;
+  }
+  let message = format!(
+    "{} call(s) of deprecated helpers (`sys migrate-config` rewrites them):",
+    deprecations.len()
+  );
+  println!(
+    "{} {}",
+    symbols::WARNING.if_supports_color(Stream::Stdout, |s| s.yellow()),
+    message.if_supports_color(Stream::Stdout, |s| s.yellow())
+  );
+  for call in deprecations {
+    println!(
+      "    {} {}: {}{{}} -> {}{{}} (removed in API version {})",
+      symbols::MINUS.if_supports_color(Stream::Stdout, |s| s.dimmed()),
+      call.location,
+      call.helper,
+      call.replacement,
+      call.removed_in
+    );
+  }
+}
+
+/// List the builds to realize that failed recently, which an apply fails on
+/// without running them unless given `--retry-failed`.
+pub fn print_previously_failed(failures: &[PreviousFailure]) {
+  if failures.is_empty() {
+    return;
+  }
+  let message = format!(
+    "{} build(s) failed recently and will not run again before they expire (`sys apply --retry-failed` runs them now):",
+    failures.len()
+  );
+  println!(
+    "{} {}",
+    symbols::WARNING.if_supports_color(Stream::Stdout, |s| s.yellow()),
+    message.if_supports_color(Stream::Stdout, |s| s.yellow())
+  );
+  for previous in failures {
+    println!(
+      "    {} {} (failed {}, expires in {}): {}",
+      symbols::ERROR.if_supports_color(Stream::Stdout, |s| s.red()),
+      previous.name(),
+      format_timestamp(previous.failure.failed_at_ms / 1000),
+      format_duration(Duration::from_secs(previous.expires_in_secs)),
+      previous.failure.error.if_supports_color(Stream::Stdout, |s| s.dimmed())
+    );
+  }
+}
+
+/// List the host paths each bind to create or update will touch.
+pub fn print_bind_touches(touches: &[BindTouches]) {
+  if touches.is_empty() {
+    return;
+  }
+  print_stat(
+    "Touched paths",
+    &touches.iter().map(|bind| bind.paths.len()).sum::<usize>().to_string(),
+  );
+  for bind in touches {
+    let symbol = match bind.operation {
+      BindOperation::Update => symbols::MODIFY
+        .if_supports_color(Stream::Stdout, |s| s.yellow())
+        .to_string(),
+      _ => symbols::ADD
+        .if_supports_color(Stream::Stdout, |s| s.green())
+        .to_string(),
+    };
+    println!(
+      "    {} {}",
+      symbol,
+      bind.id.as_deref().unwrap_or_else(|| truncate_hash(&bind.hash.0))
+    );
+    for touch in &bind.paths {
+      let path = tilde_path(&touch.path);
+      let line = match &touch.action {
+        TouchAction::Symlink { target } => {
+          format!("symlink {} {} {}", path, symbols::ARROW, tilde_path(target))
+        }
+        TouchAction::File { attrs } if attrs.is_empty() => format!("write {}", path),
+        TouchAction::File { attrs } => format!("write {} ({})", path, attrs),
+        TouchAction::ConfigSection { section } => format!("section [{}] of {}", section, path),
+        TouchAction::FileBlock { marker } => format!("block '{}' of {}", marker, path),
+        TouchAction::Backup => format!("modify {} (backed up)", path),
+        TouchAction::Output { name } => format!("{} (output '{}')", path, name),
+      };
+      if touch.dynamic {
+        println!(
+          "        {} {}",
+          line,
+          "(resolved during apply)".if_supports_color(Stream::Stdout, |s| s.dimmed())
+        );
+      } else {
+        println!("        {}", line);
+      }
+    }
+  }
+}
+
+/// `path` with the home directory shown as `~`.
+fn tilde_path(path: &str) -> String {
+  let home = home_dir();
+  match std::path::Path::new(path).strip_prefix(&home) {
+    Ok(rest) if !home.as_os_str().is_empty() => format!("~{}{}", std::path::MAIN_SEPARATOR, rest.display()),
+    _ => path.to_string(),
+  }
+}
+
+/// Summarize the bind changes of each group, one line per group.
+pub fn print_group_changes(groups: &BTreeMap<String, GroupChanges>) {
+  if groups.is_empty() {
+    return;
+  }
+  print_stat("Groups", &groups.len().to_string());
+  for (name, changes) in groups {
+    println!(
+      "    {} {}: {}",
+      symbols::INFO.if_supports_color(Stream::Stdout, |s| s.dimmed()),
+      name,
+      format_group_changes(changes)
+    );
+  }
+}
+
+/// Bind count of a group and its pending changes, e.g. `3 bind(s) (+1 -1)`.
+pub fn format_group_changes(changes: &GroupChanges) -> String {
+  let pending: Vec<String> = [
+    (symbols::ADD, changes.to_apply),
+    (symbols::MODIFY, changes.to_update),
+    (symbols::REMOVE, changes.to_destroy),
+  ]
+  .into_iter()
+  .filter(|(_, count)| *count > 0)
+  .map(|(symbol, count)| format!("{}{}", symbol, count))
+  .collect();
+
+  if pending.is_empty() {
+    format!("{} bind(s)", changes.total())
+  } else {
+    format!("{} bind(s) ({})", changes.total(), pending.join(" "))
+  }
+}
+
+/// Show the config revision and host that produced a snapshot.
+pub fn print_provenance(provenance: &Provenance) {
+  if let Some(ref hash) = provenance.config_hash {
+    print_stat("Config hash", truncate_hash(hash));
+  }
+  if let Some(ref commit) = provenance.git_commit {
+    print_stat("Git commit", truncate_hash(commit));
+  }
+  for (name, rev) in &provenance.inputs {
+    print_stat(&format!("Input {}", name), truncate_hash(rev));
+  }
+  print_stat("syslua", &provenance.syslua_version);
+  if let Some(ref hostname) = provenance.hostname {
+    print_stat("Host", hostname);
+  }
+}
+
+pub fn print_info(message: &str) {
+  println!(
+    "{} {}",
+    symbols::INFO.if_supports_color(Stream::Stdout, |s| s.blue()),
+    message
+  );
+}
+
+pub fn print_stat(label: &str, value: &str) {
+  println!(
+    "  {}: {}",
+    label.if_supports_color(Stream::Stdout, |s| s.dimmed()),
+    value
+  );
+}
+
+/// A realized build and how long it took.
+#[derive(Debug, Serialize)]
+pub struct TimedBuild {
+  pub id: Option<String>,
+  pub hash: String,
+  pub duration_ms: u64,
+}
+
+/// End-of-apply statistics, rendered as a table (text) or an object (JSON).
+#[derive(Debug, Serialize)]
+pub struct ApplySummary {
+  /// Realized builds, slowest first.
+  pub builds_realized: Vec<TimedBuild>,
+  pub builds_cached: usize,
+  pub binds_created: usize,
+  pub binds_updated: usize,
+  pub binds_destroyed: usize,
+  pub binds_unchanged: usize,
+  pub store_bytes_added: u64,
+  pub total_ms: u64,
+}
+
+impl ApplySummary {
+  pub fn new(result: &ApplyResult, elapsed: Duration) -> Self {
+    let mut builds_realized: Vec<TimedBuild> = result
+      .execution
+      .realized
+      .keys()
+      .map(|hash| TimedBuild {
+        id: result.snapshot.manifest.builds.get(hash).and_then(|b| b.id.clone()),
+        hash: hash.0.clone(),
+        duration_ms: result
+          .execution
+          .build_timings
+          .get(hash)
+          .map(|t| t.duration().as_millis() as u64)
+          .unwrap_or(0),
+      })
+      .collect();
+    builds_realized.sort_by(|a, b| b.duration_ms.cmp(&a.duration_ms).then_with(|| a.hash.cmp(&b.hash)));
+
+    Self {
+      builds_realized,
+      builds_cached: result.diff.builds_cached.len(),
+      binds_created: result.execution.applied.len(),
+      binds_updated: result.binds_updated,
+      binds_destroyed: result.binds_destroyed,
+      binds_unchanged: result.diff.binds_unchanged.len(),
+      store_bytes_added: result.store_bytes_added,
+      total_ms: elapsed.as_millis() as u64,
+    }
+  }
+
+  pub fn print(&self) {
+    print_stat("Builds realized", &self.builds_realized.len().to_string());
+    for build in &self.builds_realized {
+      println!(
+        "    {} {} {}",
+        symbols::ADD.if_supports_color(Stream::Stdout, |s| s.green()),
+        build.id.as_deref().unwrap_or(truncate_hash(&build.hash)),
+        format_duration(Duration::from_millis(build.duration_ms)).if_supports_color(Stream::Stdout, |s| s.dimmed())
+      );
+    }
+    print_stat("Builds cached", &self.builds_cached.to_string());
+    print_stat("Binds applied", &self.binds_created.to_string());
+    print_stat("Binds updated", &self.binds_updated.to_string());
+    print_stat("Binds destroyed", &self.binds_destroyed.to_string());
+    print_stat("Binds unchanged", &self.binds_unchanged.to_string());
+    print_stat("Store added", &format_bytes(self.store_bytes_added));
+    print_stat("Duration", &format_duration(Duration::from_millis(self.total_ms)));
+  }
+}
+
+/// One line of a side-by-side diff: an entry of the old side and of the new side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffRow {
+  pub old: Option<String>,
+  pub new: Option<String>,
+}
+
+/// Widest old column of a side-by-side diff; longer entries are cut.
+const MAX_COLUMN_WIDTH: usize = 60;
+
+/// Lay out `rows` in two columns headed by `old_title` and `new_title`.
+///
+/// Entries only on the old side are marked `-`, only on the new side `+`,
+/// and differing entries on both sides `~`.
+pub fn format_side_by_side(old_title: &str, new_title: &str, rows: &[DiffRow]) -> Vec<String> {
+  let width = rows
+    .iter()
+    .filter_map(|row| row.old.as_deref())
+    .chain([old_title])
+    .map(|text| text.chars().count())
+    .max()
+    .unwrap_or(0)
+    .min(MAX_COLUMN_WIDTH);
+  let separator = "│".if_supports_color(Stream::Stdout, |s| s.dimmed()).to_string();
+
+  let mut lines = vec![format!(
+    "  {:<width$} {}   {}",
+    fit(old_title, width),
+    separator,
+    new_title
+  )];
+  for row in rows {
+    let changed = row.old != row.new;
+    let (old_marker, new_marker) = match (&row.old, &row.new) {
+      (Some(_), None) => (
+        symbols::REMOVE
+          .if_supports_color(Stream::Stdout, |s| s.red())
+          .to_string(),
+        " ".to_string(),
+      ),
+      (None, Some(_)) => (
+        " ".to_string(),
+        symbols::ADD
+          .if_supports_color(Stream::Stdout, |s| s.green())
+          .to_string(),
+      ),
+      _ if changed => {
+        let marker = symbols::MODIFY
+          .if_supports_color(Stream::Stdout, |s| s.yellow())
+          .to_string();
+        (marker.clone(), marker)
+      }
+      _ => (" ".to_string(), " ".to_string()),
+    };
+    let old = fit(row.old.as_deref().unwrap_or(""), width);
+    let line = format!(
+      "{} {:<width$} {} {} {}",
+      old_marker,
+      old,
+      separator,
+      new_marker,
+      row.new.as_deref().unwrap_or("")
+    );
+    lines.push(line.trim_end().to_string());
+  }
+  lines
+}
+
+/// Print `rows` side by side, see [`format_side_by_side`].
+pub fn print_side_by_side(old_title: &str, new_title: &str, rows: &[DiffRow]) {
+  for line in format_side_by_side(old_title, new_title, rows) {
+    println!("{}", line);
+  }
+}
+
+/// `text` cut to `width` characters, ending in `…` when cut.
+fn fit(text: &str, width: usize) -> String {
+  if text.chars().count() <= width {
+    text.to_string()
+  } else {
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+  }
+}
+
+pub fn print_json<T: serde::Serialize>(value: &T) -> anyhow::Result<()> {
+  let json = serde_json::to_string_pretty(value).context("Failed to serialize to JSON")?;
+  println!("{}", json);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_truncate_hash() {
+    assert_eq!(truncate_hash("abcdef123456789"), "abcdef123456");
+    assert_eq!(truncate_hash("short"), "short");
+    assert_eq!(truncate_hash(""), "");
+  }
+
+  #[test]
+  fn test_format_bytes() {
+    assert_eq!(format_bytes(500), "500 B");
+    assert_eq!(format_bytes(1024), "1.0 KB");
+    assert_eq!(format_bytes(1536), "1.5 KB");
+    assert_eq!(format_bytes(1048576), "1.0 MB");
+    assert_eq!(format_bytes(1073741824), "1.0 GB");
+  }
+
+  #[test]
+  fn test_format_group_changes() {
+    let mut changes = GroupChanges {
+      unchanged: 2,
+      ..Default::default()
+    };
+    assert_eq!(format_group_changes(&changes), "2 bind(s)");
+
+    changes.to_apply = 1;
+    changes.to_destroy = 1;
+    assert_eq!(format_group_changes(&changes), "3 bind(s) (+1 -1)");
+  }
+
+  #[test]
+  fn test_format_side_by_side() {
+    owo_colors::set_override(false);
+    let row = |old: Option<&str>, new: Option<&str>| DiffRow {
+      old: old.map(str::to_string),
+      new: new.map(str::to_string),
+    };
+    let lines = format_side_by_side(
+      "old",
+      "new",
+      &[
+        row(Some("nginx (abc)"), Some("nginx (def)")),
+        row(Some("vim (123)"), None),
+        row(None, Some("git (456)")),
+        row(Some("zsh (789)"), Some("zsh (789)")),
+      ],
+    );
+    assert_eq!(
+      lines,
+      vec![
+        "  old         │   new",
+        "~ nginx (abc) │ ~ nginx (def)",
+        "- vim (123)   │",
+        "              │ + git (456)",
+        "  zsh (789)   │   zsh (789)",
+      ]
+    );
+
+    assert_eq!(fit(&"x".repeat(70), MAX_COLUMN_WIDTH).chars().count(), MAX_COLUMN_WIDTH);
+  }
+
+  #[test]
+  fn test_format_duration() {
+    assert_eq!(format_duration(Duration::from_millis(50)), "50ms");
+    assert_eq!(format_duration(Duration::from_millis(1500)), "1.50s");
+    assert_eq!(format_duration(Duration::from_secs(65)), "1m 5s");
+  }
+
+  #[test]
+  fn test_apply_summary_orders_builds_by_duration() {
+    use syslua_lib::execute::{BuildResult, DagResult, NodeTiming};
+    use syslua_lib::manifest::Manifest;
+    use syslua_lib::snapshot::{Snapshot, StateDiff};
+    use syslua_lib::util::hash::ObjectHash;
+
+    let mut execution = DagResult::default();
+    for (hash, ms) in [("fast", 10), ("slow", 250)] {
+      let hash = ObjectHash(hash.to_string());
+      execution.realized.insert(
+        hash.clone(),
+        BuildResult {
+          store_path: std::path::PathBuf::from("/store"),
+          outputs: Default::default(),
+          action_results: vec![],
+          cached: false,
+        },
+      );
+      execution.build_timings.insert(
+        hash,
+        NodeTiming {
+          started_at_ms: 1000,
+          finished_at_ms: 1000 + ms,
+        },
+      );
+    }
+
+    let result = ApplyResult {
+      snapshot: Snapshot::new("1".to_string(), None, Manifest::default()),
+      diff: StateDiff::default(),
+      execution,
+      binds_destroyed: 1,
+      binds_updated: 2,
+      drift_results: vec![],
+      store_bytes_added: 2048,
+    };
+
+    let summary = ApplySummary::new(&result, Duration::from_millis(500));
+    let order: Vec<_> = summary.builds_realized.iter().map(|b| b.hash.as_str()).collect();
+    assert_eq!(order, vec!["slow", "fast"]);
+    assert_eq!(summary.builds_realized[0].duration_ms, 250);
+    assert_eq!(summary.binds_updated, 2);
+    assert_eq!(summary.store_bytes_added, 2048);
+    assert_eq!(summary.total_ms, 500);
+  }
+}
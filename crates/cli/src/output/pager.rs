@@ -0,0 +1,217 @@
+//! Paging long text output, like git does.
+//!
+//! `sys plan` and `sys diff` pipe their text output through a pager when
+//! stdout is a terminal. The pager command is the first of:
+//!
+//! 1. `$SYSLUA_PAGER`
+//! 2. `settings.pager` of the config (`sys plan` only)
+//! 3. `$PAGER`
+//! 4. `less`
+//!
+//! An empty command, `cat`, or `settings.pager = false` turns paging off;
+//! `--no-pager` always does and `--pager` pages even then. `less` gets
+//! `LESS=FRX` unless `LESS` is set, so output that fits on one screen is
+//! printed as is and colors survive. Colors stay on while paging unless
+//! `--color never` was given.
+
+use std::io::IsTerminal;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::OnceLock;
+
+use syslua_lib::lua::entrypoint::{PagerSetting, extract_pager_setting};
+use syslua_lib::platform::stdio::{StdoutRedirect, redirect_stdout};
+use tracing::debug;
+
+const DEFAULT_PAGER: &str = "less";
+
+/// Whether to page, from `--pager`/`--no-pager`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PagerChoice {
+  /// Page on a terminal unless the pager setting turns it off.
+  #[default]
+  Auto,
+  /// Page on a terminal, with the default pager if the setting turns it off.
+  Always,
+  /// Never page.
+  Never,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PagerConfig {
+  choice: PagerChoice,
+  /// Whether colors may be forced on for the pager (`--color auto`).
+  color: bool,
+}
+
+static CONFIG: OnceLock<PagerConfig> = OnceLock::new();
+
+/// Set how output is paged for this process; called once from `main`.
+pub fn configure(choice: PagerChoice, color: bool) {
+  let _ = CONFIG.set(PagerConfig { choice, color });
+}
+
+/// A running pager that stdout is redirected to.
+///
+/// Dropping it ends the output and waits for the user to quit the pager.
+pub struct Pager {
+  redirect: Option<StdoutRedirect>,
+  stdin: Option<ChildStdin>,
+  child: Child,
+}
+
+impl Drop for Pager {
+  fn drop(&mut self) {
+    // Restore stdout first, then close the last write end so the pager sees EOF
+    self.redirect.take();
+    self.stdin.take();
+    let _ = self.child.wait();
+  }
+}
+
+/// Page the rest of the text output if stdout is a terminal.
+///
+/// `config` is the entry point whose `settings.pager` applies, if any. The
+/// output is printed directly when no pager is wanted or it fails to start.
+pub fn page_output(config: Option<&str>) -> Option<Pager> {
+  let PagerConfig { choice, color } = CONFIG.get().copied().unwrap_or(PagerConfig {
+    choice: PagerChoice::Auto,
+    color: true,
+  });
+  if choice == PagerChoice::Never || !std::io::stdout().is_terminal() || is_dumb_terminal() {
+    return None;
+  }
+
+  let setting = config.and_then(|config| {
+    extract_pager_setting(config)
+      .inspect_err(|e| debug!(error = %e, "failed to read settings.pager"))
+      .ok()
+      .flatten()
+  });
+  let command = pager_command(
+    std::env::var("SYSLUA_PAGER").ok(),
+    setting,
+    std::env::var("PAGER").ok(),
+    choice,
+  )?;
+
+  match spawn(&command) {
+    Ok(pager) => {
+      if color {
+        owo_colors::set_override(true);
+      }
+      Some(pager)
+    }
+    Err(e) => {
+      debug!(pager = %command, error = %e, "failed to start pager");
+      None
+    }
+  }
+}
+
+fn is_dumb_terminal() -> bool {
+  std::env::var("TERM").is_ok_and(|term| term == "dumb")
+}
+
+/// The pager command to run, or `None` when paging is turned off.
+fn pager_command(
+  env: Option<String>,
+  setting: Option<PagerSetting>,
+  env_fallback: Option<String>,
+  choice: PagerChoice,
+) -> Option<String> {
+  let command = match (env, setting) {
+    (Some(command), _) => Some(command),
+    (None, Some(PagerSetting::Command(command))) => Some(command),
+    (None, Some(PagerSetting::Disabled)) => None,
+    (None, Some(PagerSetting::Enabled) | None) => Some(env_fallback.unwrap_or_else(|| DEFAULT_PAGER.to_string())),
+  };
+
+  match command.map(|command| command.trim().to_string()) {
+    Some(command) if !command.is_empty() && command != "cat" => Some(command),
+    _ if choice == PagerChoice::Always => Some(DEFAULT_PAGER.to_string()),
+    _ => None,
+  }
+}
+
+fn spawn(command: &str) -> std::io::Result<Pager> {
+  let mut cmd = if cfg!(windows) {
+    let mut cmd = Command::new("cmd");
+    cmd.args(["/C", command]);
+    cmd
+  } else {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", command]);
+    cmd
+  };
+  if std::env::var_os("LESS").is_none() {
+    cmd.env("LESS", "FRX");
+  }
+  if std::env::var_os("LV").is_none() {
+    cmd.env("LV", "-c");
+  }
+
+  let mut child = cmd.stdin(Stdio::piped()).spawn()?;
+  let stdin = child.stdin.take().expect("pager stdin is piped");
+  let redirect = match redirect_stdout(&stdin) {
+    Ok(redirect) => redirect,
+    Err(e) => {
+      drop(stdin);
+      let _ = child.wait();
+      return Err(e);
+    }
+  };
+
+  Ok(Pager {
+    redirect: Some(redirect),
+    stdin: Some(stdin),
+    child,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pager_env_wins_over_setting_and_fallback() {
+    let command = pager_command(
+      Some("most".to_string()),
+      Some(PagerSetting::Command("less -S".to_string())),
+      Some("more".to_string()),
+      PagerChoice::Auto,
+    );
+    assert_eq!(command.as_deref(), Some("most"));
+
+    let command = pager_command(
+      None,
+      Some(PagerSetting::Command("less -S".to_string())),
+      Some("more".to_string()),
+      PagerChoice::Auto,
+    );
+    assert_eq!(command.as_deref(), Some("less -S"));
+
+    let command = pager_command(None, None, Some("more".to_string()), PagerChoice::Auto);
+    assert_eq!(command.as_deref(), Some("more"));
+    assert_eq!(
+      pager_command(None, Some(PagerSetting::Enabled), None, PagerChoice::Auto).as_deref(),
+      Some(DEFAULT_PAGER)
+    );
+  }
+
+  #[test]
+  fn disabled_pager_is_overridden_by_always() {
+    assert_eq!(
+      pager_command(None, Some(PagerSetting::Disabled), None, PagerChoice::Auto),
+      None
+    );
+    assert_eq!(
+      pager_command(Some("cat".to_string()), None, None, PagerChoice::Auto),
+      None
+    );
+    assert_eq!(pager_command(Some(String::new()), None, None, PagerChoice::Auto), None);
+    assert_eq!(
+      pager_command(None, Some(PagerSetting::Disabled), None, PagerChoice::Always).as_deref(),
+      Some(DEFAULT_PAGER)
+    );
+  }
+}
@@ -0,0 +1,510 @@
+//! Live progress display for manifest execution.
+//!
+//! Renders the lib's progress events on stderr as a wave bar, the builds and
+//! binds currently running (with the progress of their downloads), and a one-line summary when execution ends. Log
+//! lines go through [`LogWriter`], which clears the display before printing
+//! and redraws it after, so the two never interleave.
+//!
+//! The display is only used when stderr is a terminal and logs are pretty.
+
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use owo_colors::{OwoColorize, Stream};
+use syslua_lib::execute::progress::{NodeKind, NodeOutcome, ProgressEvent, ProgressSender, progress_channel};
+use syslua_lib::util::hash::ObjectHash;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::error::TryRecvError;
+use tracing_subscriber::fmt::MakeWriter;
+
+use super::{format_bytes, format_duration, symbols, truncate_hash};
+
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const BAR_WIDTH: usize = 24;
+const MAX_ACTIVE_SHOWN: usize = 6;
+const MAX_LABEL_LEN: usize = 48;
+const TICK: Duration = Duration::from_millis(100);
+
+/// Whether logs are pretty, set once by `main`.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The display currently on screen, shared with [`LogWriter`].
+static DISPLAY: Mutex<Option<Bars>> = Mutex::new(None);
+
+/// Allow the progress display; called when logs are not JSON.
+pub fn enable() {
+  ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// A progress display running on its own thread until its sender is dropped.
+pub struct Progress {
+  thread: JoinHandle<()>,
+}
+
+impl Progress {
+  /// Start a display, returning the sender to put in the `ExecuteConfig`.
+  ///
+  /// Returns a no-op sender and no display when stderr isn't a terminal or
+  /// logs are JSON.
+  pub fn start() -> (ProgressSender, Option<Self>) {
+    if !ENABLED.load(Ordering::Relaxed) || !io::stderr().is_terminal() {
+      return (ProgressSender::default(), None);
+    }
+
+    let (sender, events) = progress_channel();
+    let thread = std::thread::spawn(move || render(events));
+    (sender, Some(Self { thread }))
+  }
+
+  /// Wait for the display to print its summary.
+  ///
+  /// Every clone of the sender must be dropped first, or this never returns.
+  pub fn finish(self) {
+    let _ = self.thread.join();
+  }
+}
+
+/// Apply events as they arrive and redraw on every tick.
+fn render(mut events: UnboundedReceiver<ProgressEvent>) {
+  loop {
+    let closed = loop {
+      match events.try_recv() {
+        Ok(event) => with_display(|display| display.apply(event)),
+        Err(TryRecvError::Empty) => break false,
+        Err(TryRecvError::Disconnected) => break true,
+      }
+    };
+
+    if closed {
+      let display = DISPLAY.lock().unwrap_or_else(|e| e.into_inner()).take();
+      if let Some(mut display) = display {
+        let mut stderr = io::stderr().lock();
+        display.clear(&mut stderr);
+        if let Some(summary) = display.summary() {
+          let _ = writeln!(stderr, "{}", summary);
+        }
+      }
+      return;
+    }
+
+    with_display(|display| display.tick());
+    std::thread::sleep(TICK);
+  }
+}
+
+/// Redraw the display around `f`, creating it on first use.
+fn with_display(f: impl FnOnce(&mut Bars)) {
+  let mut guard = DISPLAY.lock().unwrap_or_else(|e| e.into_inner());
+  let display = guard.get_or_insert_with(Bars::default);
+  let mut stderr = io::stderr().lock();
+  display.clear(&mut stderr);
+  f(display);
+  display.draw(&mut stderr);
+}
+
+/// A build or bind that is running.
+#[derive(Debug)]
+struct Active {
+  kind: NodeKind,
+  hash: ObjectHash,
+  label: String,
+  since: Instant,
+  /// Bytes received and expected of the running download, if any.
+  download: Option<(u64, Option<u64>)>,
+}
+
+/// Execution state as seen through progress events.
+#[derive(Debug)]
+struct Bars {
+  started: Instant,
+  /// Total waves, nodes and binds once execution started.
+  waves: usize,
+  total: usize,
+  binds: usize,
+  /// 1-based number of the running wave.
+  wave: usize,
+  done: usize,
+  failed: usize,
+  skipped: usize,
+  rolling_back: Option<usize>,
+  active: Vec<Active>,
+  frame: usize,
+  /// Lines currently drawn on screen.
+  drawn: usize,
+}
+
+impl Default for Bars {
+  fn default() -> Self {
+    Self {
+      started: Instant::now(),
+      waves: 0,
+      total: 0,
+      binds: 0,
+      wave: 0,
+      done: 0,
+      failed: 0,
+      skipped: 0,
+      rolling_back: None,
+      active: Vec::new(),
+      frame: 0,
+      drawn: 0,
+    }
+  }
+}
+
+impl Bars {
+  fn apply(&mut self, event: ProgressEvent) {
+    match event {
+      ProgressEvent::Started { waves, builds, binds } => {
+        self.started = Instant::now();
+        self.waves = waves;
+        self.total = builds + binds;
+        self.binds = binds;
+      }
+      ProgressEvent::WaveStarted { wave, .. } => self.wave = wave + 1,
+      ProgressEvent::NodeStarted { kind, hash, id } => {
+        let label = id.unwrap_or_else(|| truncate_hash(&hash.0).to_string());
+        self.active.push(Active {
+          kind,
+          hash,
+          label,
+          since: Instant::now(),
+          download: None,
+        });
+      }
+      ProgressEvent::NodeFinished {
+        kind, hash, outcome, ..
+      } => {
+        self.active.retain(|a| a.kind != kind || a.hash != hash);
+        self.done += 1;
+        match outcome {
+          NodeOutcome::Succeeded => {}
+          NodeOutcome::Failed => self.failed += 1,
+          NodeOutcome::Skipped => self.skipped += 1,
+        }
+      }
+      ProgressEvent::Download {
+        hash, received, total, ..
+      } => {
+        if let Some(active) = self
+          .active
+          .iter_mut()
+          .find(|a| a.kind == NodeKind::Build && a.hash == hash)
+        {
+          active.download = Some((received, total));
+        }
+      }
+      ProgressEvent::RollingBack { binds } => self.rolling_back = Some(binds),
+      ProgressEvent::Cached { .. } => {}
+    }
+  }
+
+  fn tick(&mut self) {
+    self.frame = (self.frame + 1) % SPINNER.len();
+  }
+
+  /// The lines to draw, empty until execution started.
+  fn lines(&self) -> Vec<String> {
+    if self.waves == 0 {
+      return Vec::new();
+    }
+
+    let filled = (self.done * BAR_WIDTH)
+      .checked_div(self.total)
+      .unwrap_or(BAR_WIDTH)
+      .min(BAR_WIDTH);
+    let bar = format!("{}{}", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled));
+    let mut header = format!(
+      "{} Wave {}/{} {} {}/{}  {}",
+      SPINNER[self.frame].if_supports_color(Stream::Stderr, |s| s.cyan()),
+      self.wave,
+      self.waves,
+      bar.if_supports_color(Stream::Stderr, |s| s.cyan()),
+      self.done,
+      self.total,
+      format_duration(self.started.elapsed()).if_supports_color(Stream::Stderr, |s| s.dimmed())
+    );
+    if self.failed > 0 {
+      let _ = write!(
+        header,
+        "  {}",
+        format!("{} failed", self.failed).if_supports_color(Stream::Stderr, |s| s.red())
+      );
+    }
+    if let Some(binds) = self.rolling_back {
+      let _ = write!(
+        header,
+        "  {}",
+        format!("rolling back {} bind(s)", binds).if_supports_color(Stream::Stderr, |s| s.yellow())
+      );
+    }
+
+    let mut lines = vec![header];
+    for active in self.active.iter().take(MAX_ACTIVE_SHOWN) {
+      let kind = match active.kind {
+        NodeKind::Build => "build",
+        NodeKind::Bind => "bind ",
+      };
+      let mut line = format!(
+        "  {} {} {}",
+        kind.if_supports_color(Stream::Stderr, |s| s.dimmed()),
+        shorten(&active.label),
+        format_duration(active.since.elapsed()).if_supports_color(Stream::Stderr, |s| s.dimmed())
+      );
+      if let Some((received, total)) = active.download {
+        let download = match total {
+          Some(total) => format!("{}/{}", format_bytes(received), format_bytes(total)),
+          None => format_bytes(received),
+        };
+        let _ = write!(line, "  {}", download.if_supports_color(Stream::Stderr, |s| s.cyan()));
+      }
+      lines.push(line);
+    }
+    if self.active.len() > MAX_ACTIVE_SHOWN {
+      lines.push(format!("  … and {} more", self.active.len() - MAX_ACTIVE_SHOWN));
+    }
+    lines
+  }
+
+  /// Final line once execution ended, if it started at all.
+  fn summary(&self) -> Option<String> {
+    if self.waves == 0 {
+      return None;
+    }
+
+    let builds = self.total - self.binds;
+    let mut summary = format!(
+      "Executed {} build(s) and {} bind(s) in {} wave(s), {}",
+      builds,
+      self.binds,
+      self.waves,
+      format_duration(self.started.elapsed())
+    );
+    if self.failed > 0 {
+      let _ = write!(summary, ", {} failed", self.failed);
+    }
+    if self.skipped > 0 {
+      let _ = write!(summary, ", {} skipped", self.skipped);
+    }
+
+    let symbol = if self.failed > 0 {
+      symbols::ERROR
+        .if_supports_color(Stream::Stderr, |s| s.red())
+        .to_string()
+    } else {
+      symbols::SUCCESS
+        .if_supports_color(Stream::Stderr, |s| s.green())
+        .to_string()
+    };
+    Some(format!("{} {}", symbol, summary))
+  }
+
+  fn draw(&mut self, out: &mut impl Write) {
+    let lines = self.lines();
+    for line in &lines {
+      let _ = writeln!(out, "{}", line);
+    }
+    let _ = out.flush();
+    self.drawn = lines.len();
+  }
+
+  fn clear(&mut self, out: &mut impl Write) {
+    if self.drawn > 0 {
+      // Move up over the drawn lines and erase to the end of the screen
+      let _ = write!(out, "\x1b[{}A\x1b[J", self.drawn);
+      let _ = out.flush();
+      self.drawn = 0;
+    }
+  }
+}
+
+/// Cut `label` to [`MAX_LABEL_LEN`] characters.
+fn shorten(label: &str) -> String {
+  match label.char_indices().nth(MAX_LABEL_LEN) {
+    Some((end, _)) => format!("{}…", &label[..end]),
+    None => label.to_string(),
+  }
+}
+
+/// Log writer that keeps log lines from tearing the progress display.
+pub struct LogWriter;
+
+impl<'a> MakeWriter<'a> for LogWriter {
+  type Writer = LogLine;
+
+  fn make_writer(&'a self) -> Self::Writer {
+    LogLine(Vec::new())
+  }
+}
+
+/// One formatted log event, printed to stdout when dropped.
+pub struct LogLine(Vec<u8>);
+
+impl Write for LogLine {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0.extend_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+impl Drop for LogLine {
+  fn drop(&mut self) {
+    let mut display = DISPLAY.lock().unwrap_or_else(|e| e.into_inner());
+    let mut stderr = io::stderr().lock();
+    if let Some(display) = display.as_mut() {
+      display.clear(&mut stderr);
+    }
+
+    let mut stdout = io::stdout().lock();
+    let _ = stdout.write_all(&self.0);
+    let _ = stdout.flush();
+
+    if let Some(display) = display.as_mut() {
+      display.draw(&mut stderr);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn hash(s: &str) -> ObjectHash {
+    ObjectHash(s.to_string())
+  }
+
+  fn started(display: &mut Bars) {
+    display.apply(ProgressEvent::Started {
+      waves: 2,
+      builds: 1,
+      binds: 2,
+    });
+    display.apply(ProgressEvent::WaveStarted { wave: 0, nodes: 1 });
+  }
+
+  #[test]
+  fn draws_nothing_before_execution_starts() {
+    let display = Bars::default();
+    assert!(display.lines().is_empty());
+    assert!(display.summary().is_none());
+  }
+
+  #[test]
+  fn tracks_running_and_finished_nodes() {
+    let mut display = Bars::default();
+    started(&mut display);
+    display.apply(ProgressEvent::NodeStarted {
+      kind: NodeKind::Build,
+      hash: hash("abc123"),
+      id: Some("ripgrep".to_string()),
+    });
+
+    let lines = display.lines();
+    assert!(lines[0].contains("Wave 1/2"));
+    assert!(lines[0].contains("0/3"));
+    assert!(lines[1].contains("build ripgrep"));
+
+    display.apply(ProgressEvent::NodeFinished {
+      kind: NodeKind::Build,
+      hash: hash("abc123"),
+      outcome: NodeOutcome::Succeeded,
+      duration: Duration::from_millis(5),
+    });
+    display.apply(ProgressEvent::NodeFinished {
+      kind: NodeKind::Bind,
+      hash: hash("def456"),
+      outcome: NodeOutcome::Skipped,
+      duration: Duration::ZERO,
+    });
+
+    let lines = display.lines();
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].contains("2/3"));
+
+    let summary = display.summary().unwrap();
+    assert!(summary.contains("Executed 1 build(s) and 2 bind(s) in 2 wave(s)"));
+    assert!(summary.contains("1 skipped"));
+    assert!(!summary.contains("failed"));
+  }
+
+  #[test]
+  fn shows_download_progress_of_running_builds() {
+    let mut display = Bars::default();
+    started(&mut display);
+    display.apply(ProgressEvent::NodeStarted {
+      kind: NodeKind::Build,
+      hash: hash("abc123"),
+      id: Some("toolchain".to_string()),
+    });
+    display.apply(ProgressEvent::Download {
+      hash: hash("abc123"),
+      url: "https://example.com/toolchain.tar.gz".to_string(),
+      received: 512 * 1024,
+      total: Some(2 * 1024 * 1024),
+    });
+
+    let lines = display.lines();
+    assert!(lines[1].contains("build toolchain"));
+    assert!(lines[1].contains(&format!(
+      "{}/{}",
+      format_bytes(512 * 1024),
+      format_bytes(2 * 1024 * 1024)
+    )));
+
+    // Downloads of builds that aren't running are ignored
+    display.apply(ProgressEvent::Download {
+      hash: hash("other"),
+      url: "https://example.com/other.tar.gz".to_string(),
+      received: 1,
+      total: None,
+    });
+    assert_eq!(display.lines().len(), 2);
+  }
+
+  #[test]
+  fn caps_the_list_of_running_nodes() {
+    let mut display = Bars::default();
+    started(&mut display);
+    for i in 0..MAX_ACTIVE_SHOWN + 2 {
+      display.apply(ProgressEvent::NodeStarted {
+        kind: NodeKind::Bind,
+        hash: hash(&format!("hash{}", i)),
+        id: None,
+      });
+    }
+
+    let lines = display.lines();
+    assert_eq!(lines.len(), MAX_ACTIVE_SHOWN + 2);
+    assert!(lines.last().unwrap().contains("and 2 more"));
+  }
+
+  #[test]
+  fn clear_erases_drawn_lines() {
+    let mut display = Bars::default();
+    started(&mut display);
+
+    let mut out = Vec::new();
+    display.draw(&mut out);
+    assert_eq!(display.drawn, 1);
+
+    out.clear();
+    display.clear(&mut out);
+    assert_eq!(out, b"\x1b[1A\x1b[J");
+    assert_eq!(display.drawn, 0);
+  }
+
+  #[test]
+  fn shortens_long_labels() {
+    assert_eq!(shorten("short"), "short");
+    let long = "x".repeat(MAX_LABEL_LEN + 5);
+    assert_eq!(shorten(&long).chars().count(), MAX_LABEL_LEN + 1);
+  }
+}
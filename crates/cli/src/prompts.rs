@@ -18,3 +18,84 @@ pub fn confirm(message: &str, force: bool) -> Result<bool> {
 
   Ok(matches!(input.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
 }
+
+/// Show a numbered list of `items` and ask which ones to skip.
+///
+/// Returns the zero-based indices to skip (empty when the user just presses
+/// Enter), or `None` if the user aborts with `q`.
+pub fn select_skipped(header: &str, items: &[String]) -> Result<Option<Vec<usize>>> {
+  if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+    bail!("Cannot select changes in non-interactive mode. Run without --interactive.");
+  }
+
+  let mut stderr = io::stderr();
+  writeln!(stderr, "{}", header)?;
+  let width = items.len().to_string().len();
+  for (i, item) in items.iter().enumerate() {
+    writeln!(stderr, "  {:>width$}. {}", i + 1, item)?;
+  }
+
+  loop {
+    write!(
+      stderr,
+      "Numbers to skip (e.g. \"1 3-4\"), Enter to apply all, q to abort: "
+    )?;
+    stderr.flush()?;
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+      return Ok(None);
+    }
+    if input.trim().eq_ignore_ascii_case("q") {
+      return Ok(None);
+    }
+    match parse_selection(&input, items.len()) {
+      Ok(skipped) => return Ok(Some(skipped)),
+      Err(e) => writeln!(stderr, "{}", e)?,
+    }
+  }
+}
+
+/// Parse a selection like `1 3-4,6` into sorted zero-based indices below `count`.
+fn parse_selection(input: &str, count: usize) -> std::result::Result<Vec<usize>, String> {
+  let parse = |s: &str| match s.trim().parse::<usize>() {
+    Ok(n) if (1..=count).contains(&n) => Ok(n - 1),
+    _ => Err(format!("'{}' is not a number between 1 and {}", s.trim(), count)),
+  };
+
+  let mut selected = std::collections::BTreeSet::new();
+  for part in input.split([' ', ',']).filter(|p| !p.trim().is_empty()) {
+    match part.split_once('-') {
+      Some((start, end)) => {
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start > end {
+          return Err(format!("'{}' is not a valid range", part.trim()));
+        }
+        selected.extend(start..=end);
+      }
+      None => {
+        selected.insert(parse(part)?);
+      }
+    }
+  }
+  Ok(selected.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_selection_accepts_numbers_and_ranges() {
+    assert_eq!(parse_selection("\n", 5), Ok(vec![]));
+    assert_eq!(parse_selection("1 3-4,4\n", 5), Ok(vec![0, 2, 3]));
+  }
+
+  #[test]
+  fn parse_selection_rejects_out_of_range_and_garbage() {
+    assert!(parse_selection("0", 5).is_err());
+    assert!(parse_selection("6", 5).is_err());
+    assert!(parse_selection("4-2", 5).is_err());
+    assert!(parse_selection("abc", 5).is_err());
+  }
+}
@@ -114,7 +114,7 @@ fn version_flag_works() {
 
 #[test]
 fn subcommand_help_works() {
-  for cmd in &["apply", "plan", "destroy", "init", "update", "info"] {
+  for cmd in &["apply", "plan", "destroy", "init", "update", "info", "self-update"] {
     sys_cmd()
       .arg(cmd)
       .arg("--help")
@@ -346,6 +346,137 @@ fn status_help() {
     .stdout(predicate::str::contains("Show current system state"));
 }
 
+// =============================================================================
+// store root
+// =============================================================================
+
+#[test]
+fn store_flag_keeps_state_separate() {
+  let env = TestEnv::with_config(BUILD_CONFIG);
+  let project_store = env.temp.path().join("project").join(".syslua");
+
+  env
+    .cmd()
+    .arg("--store")
+    .arg(&project_store)
+    .arg("apply")
+    .arg(env.config())
+    .assert()
+    .success();
+  assert!(project_store.join("snapshots").is_dir());
+
+  env
+    .cmd()
+    .arg("status")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("No snapshot found"));
+  env
+    .cmd()
+    .arg("status")
+    .arg("--store")
+    .arg(&project_store)
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Builds: 1"));
+}
+
+#[test]
+fn settings_store_selects_project_store() {
+  let env = TestEnv::with_config(
+    r#"
+return {
+    settings = { store = ".syslua" },
+    setup = function(_) end,
+}
+"#,
+  );
+
+  env.cmd().arg("apply").arg(env.config()).assert().success();
+  assert!(env.temp.path().join(".syslua").join("snapshots").is_dir());
+}
+
+// =============================================================================
+// store add
+// =============================================================================
+
+#[test]
+fn store_add_imports_directory_for_prebuilt() {
+  let env = TestEnv::empty();
+  let source = env.temp.path().join("tool");
+  std::fs::create_dir_all(source.join("bin")).unwrap();
+  std::fs::write(source.join("bin").join("tool"), "#!/bin/sh\n").unwrap();
+
+  let output = env
+    .cmd()
+    .args(["store", "add"])
+    .arg(&source)
+    .args(["--id", "tool", "-o", "json"])
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  let imported: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  let output_hash = imported["output_hash"].as_str().unwrap();
+
+  std::fs::write(
+    env.config(),
+    format!(
+      r#"
+return {{
+    setup = function(_)
+        sys.prebuilt({{ id = "tool", hash = "{}" }})
+    end,
+}}
+"#,
+      output_hash
+    ),
+  )
+  .unwrap();
+  env.cmd().arg("apply").arg(env.config()).assert().success();
+}
+
+#[test]
+fn prebuilt_without_import_fails_apply() {
+  let env = TestEnv::with_config(
+    r#"
+return {
+    setup = function(_)
+        sys.prebuilt({ id = "tool", hash = string.rep("ab", 32) })
+    end,
+}
+"#,
+  );
+
+  env
+    .cmd()
+    .arg("apply")
+    .arg(env.config())
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("sys store add"));
+}
+
+// =============================================================================
+// completions
+// =============================================================================
+
+#[test]
+fn completions_print_registration_script() {
+  for shell in ["bash", "zsh", "fish"] {
+    sys_cmd()
+      .arg("completions")
+      .arg(shell)
+      .assert()
+      .success()
+      .stdout(predicate::str::contains("COMPLETE"));
+  }
+}
+
+#[test]
+fn completions_reject_unknown_shell() {
+  sys_cmd().arg("completions").arg("tcsh").assert().failure();
+}
+
 // =============================================================================
 // Error Handling
 // =============================================================================
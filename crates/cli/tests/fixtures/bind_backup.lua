@@ -0,0 +1,46 @@
+--- Bind that overwrites an existing file.
+--- Tests that the original file is backed up and restored on destroy.
+
+local TEST_DIR = sys.getenv('TEST_OUTPUT_DIR')
+local TARGET = TEST_DIR .. (sys.os == 'windows' and '\\existing.txt' or '/existing.txt')
+
+local function sh(ctx, script)
+  if sys.os == 'windows' then
+    return ctx:exec({
+      bin = 'powershell.exe',
+      args = { '-NoProfile', '-NonInteractive', '-Command', script },
+      env = { PATH = sys.getenv('SystemDrive') .. '\\Windows\\System32;' .. sys.getenv('SystemDrive') .. '\\Windows' },
+    })
+  else
+    return ctx:exec({
+      bin = '/bin/sh',
+      args = { '-c', script },
+      env = { PATH = '/bin:/usr/bin' },
+    })
+  end
+end
+
+return {
+  inputs = {},
+  setup = function(_)
+    sys.bind({
+      id = 'backup-bind',
+      backup = { TARGET },
+      create = function(_, ctx)
+        if sys.os == 'windows' then
+          sh(ctx, 'Set-Content -NoNewline -Path "' .. TARGET .. '" -Value "managed"')
+        else
+          sh(ctx, 'printf managed > ' .. TARGET)
+        end
+        return { file = TARGET }
+      end,
+      destroy = function(outputs, ctx)
+        if sys.os == 'windows' then
+          sh(ctx, 'Remove-Item -Force -ErrorAction SilentlyContinue -Path "' .. outputs.file .. '"')
+        else
+          sh(ctx, 'rm -f ' .. outputs.file)
+        end
+      end,
+    })
+  end,
+}
@@ -0,0 +1,14 @@
+--- Config whose bind create function raises an error during evaluation.
+--- Used for testing that evaluation errors name the failing spec.
+return {
+  inputs = {},
+  setup = function(_)
+    sys.bind({
+      id = 'broken-bind',
+      create = function(_, _)
+        error('create exploded')
+      end,
+      destroy = function(_, _) end,
+    })
+  end,
+}
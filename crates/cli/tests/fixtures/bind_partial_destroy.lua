@@ -0,0 +1,58 @@
+--- Three binds: `base`, `dependent` (uses `base`), and `other` (tagged `shell`).
+--- Tests that `destroy --only` removes a bind and its dependents but keeps the rest.
+
+local TEST_DIR = sys.getenv('TEST_OUTPUT_DIR')
+
+local function sh(ctx, script)
+  if sys.os == 'windows' then
+    return ctx:exec({
+      bin = 'powershell.exe',
+      args = { '-NoProfile', '-NonInteractive', '-Command', script },
+      env = { PATH = sys.getenv('SystemDrive') .. '\\Windows\\System32;' .. sys.getenv('SystemDrive') .. '\\Windows' },
+    })
+  else
+    return ctx:exec({
+      bin = '/bin/sh',
+      args = { '-c', script },
+      env = { PATH = '/bin:/usr/bin' },
+    })
+  end
+end
+
+local function marker(name)
+  return TEST_DIR .. (sys.os == 'windows' and '\\' or '/') .. name .. '.txt'
+end
+
+local function file_bind(id, tags, inputs)
+  return sys.bind({
+    id = id,
+    tags = tags,
+    inputs = inputs,
+    create = function(_, ctx)
+      if sys.os == 'windows' then
+        sh(ctx, 'New-Item -ItemType Directory -Force -Path "' .. TEST_DIR .. '" | Out-Null')
+        sh(ctx, 'Set-Content -Path "' .. marker(id) .. '" -Value "' .. id .. '"')
+      else
+        sh(ctx, 'mkdir -p ' .. TEST_DIR)
+        sh(ctx, 'echo ' .. id .. ' > ' .. marker(id))
+      end
+      return { file = marker(id) }
+    end,
+    destroy = function(outputs, ctx)
+      if sys.os == 'windows' then
+        sh(ctx, 'Remove-Item -Force -ErrorAction SilentlyContinue -Path "' .. outputs.file .. '"')
+      else
+        sh(ctx, 'rm -f ' .. outputs.file)
+      end
+    end,
+  })
+end
+
+return {
+  inputs = {},
+  setup = function(_)
+    local base = file_bind('base', nil, nil)
+    file_bind('dependent', nil, { base = base })
+    file_bind('other', { 'shell' }, nil)
+  end,
+}
@@ -171,3 +171,99 @@ fn drift_does_not_affect_exit_code() {
     .success()
     .stderr(predicate::str::contains("Drift detected"));
 }
+
+#[test]
+fn apply_exported_manifest_without_evaluating() {
+  let env = TestEnv::from_fixture("bind_create.lua");
+  let manifest_path = env.output_path().join("manifest.json");
+  let marker_file = env.output_path().join("created.txt");
+
+  env
+    .sys_cmd()
+    .arg("eval")
+    .arg(&env.config_path)
+    .arg("--output")
+    .arg(&manifest_path)
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Manifest written"));
+  assert!(!marker_file.exists(), "eval should not apply anything");
+
+  // The config is gone; only the manifest is needed
+  std::fs::remove_file(&env.config_path).unwrap();
+
+  env
+    .sys_cmd()
+    .arg("apply")
+    .arg("--manifest")
+    .arg(&manifest_path)
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Binds applied: 1"));
+  assert!(marker_file.exists(), "bind from the manifest should be applied");
+}
+
+#[test]
+fn apply_manifest_rejects_other_platform() {
+  let env = TestEnv::from_fixture("minimal.lua");
+  let manifest_path = env.output_path().join("manifest.json");
+
+  env
+    .sys_cmd()
+    .arg("eval")
+    .arg(&env.config_path)
+    .arg("--output")
+    .arg(&manifest_path)
+    .assert()
+    .success();
+
+  let mut document: serde_json::Value =
+    serde_json::from_str(&std::fs::read_to_string(&manifest_path).unwrap()).unwrap();
+  document["platform"] = serde_json::Value::String("riscv64-plan9".to_string());
+  std::fs::write(&manifest_path, document.to_string()).unwrap();
+
+  env
+    .sys_cmd()
+    .arg("apply")
+    .arg("--manifest")
+    .arg(&manifest_path)
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("evaluated for riscv64-plan9"));
+}
+
+#[test]
+fn apply_refuses_config_evaluated_for_other_platform() {
+  let env = TestEnv::from_fixture("bind_create.lua");
+  let manifest_path = env.output_path().join("manifest.json");
+  let marker_file = env.output_path().join("created.txt");
+  let other = if cfg!(target_os = "macos") {
+    "x86_64-linux"
+  } else {
+    "aarch64-darwin"
+  };
+
+  env
+    .sys_cmd()
+    .arg("eval")
+    .arg(&env.config_path)
+    .arg("--platform")
+    .arg(other)
+    .arg("--output")
+    .arg(&manifest_path)
+    .assert()
+    .success();
+  let document: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&manifest_path).unwrap()).unwrap();
+  assert_eq!(document["platform"], other);
+
+  env
+    .sys_cmd()
+    .arg("apply")
+    .arg(&env.config_path)
+    .arg("--platform")
+    .arg(other)
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains(format!("config is evaluated for {}", other)));
+  assert!(!marker_file.exists(), "nothing should be applied");
+}
@@ -124,12 +124,19 @@ impl TestEnv {
   /// - `TEST_OUTPUT_DIR`: Output path for test artifacts
   pub fn sys_cmd(&self) -> Command {
     let mut cmd: Command = cargo_bin_cmd!("sys");
-    cmd.env("SYSLUA_ROOT", self.root_path());
-    cmd.env("XDG_DATA_HOME", self.data_path());
-    cmd.env("XDG_CACHE_HOME", self.cache_path());
-    cmd.env("APPDATA", self.data_path()); // For Windows
-    cmd.env("LOCALAPPDATA", self.cache_path()); // For Windows cache
-    cmd.env("TEST_OUTPUT_DIR", self.output_path());
+    cmd.envs(self.env_vars());
     cmd
   }
+
+  /// Environment variables used by [`TestEnv::sys_cmd`].
+  pub fn env_vars(&self) -> Vec<(&'static str, PathBuf)> {
+    vec![
+      ("SYSLUA_ROOT", self.root_path()),
+      ("XDG_DATA_HOME", self.data_path()),
+      ("XDG_CACHE_HOME", self.cache_path()),
+      ("APPDATA", self.data_path()),       // For Windows
+      ("LOCALAPPDATA", self.cache_path()), // For Windows cache
+      ("TEST_OUTPUT_DIR", self.output_path()),
+    ]
+  }
 }
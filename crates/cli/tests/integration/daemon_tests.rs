@@ -0,0 +1,81 @@
+//! Daemon command integration tests.
+
+use predicates::prelude::*;
+
+use super::common::TestEnv;
+
+#[test]
+fn daemon_status_when_not_running() {
+  let env = TestEnv::from_fixture("minimal.lua");
+
+  env
+    .sys_cmd()
+    .args(["daemon", "status"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("No daemon running"));
+}
+
+#[cfg(unix)]
+#[test]
+fn plan_is_delegated_to_running_daemon() {
+  use std::time::{Duration, Instant};
+
+  let env = TestEnv::from_fixture("minimal.lua");
+  let socket = env.root_path().join("daemon.sock");
+
+  let mut daemon = std::process::Command::new(env!("CARGO_BIN_EXE_sys"))
+    .args(["daemon", "start"])
+    .envs(env.env_vars())
+    .spawn()
+    .unwrap();
+
+  let deadline = Instant::now() + Duration::from_secs(10);
+  while !socket.exists() {
+    assert!(Instant::now() < deadline, "daemon did not start");
+    std::thread::sleep(Duration::from_millis(50));
+  }
+
+  for _ in 0..2 {
+    env
+      .sys_cmd()
+      .arg("plan")
+      .arg(&env.config_path)
+      .assert()
+      .success()
+      .stdout(predicate::str::contains("Builds: 0"));
+  }
+
+  // A different environment could evaluate differently, so it plans locally
+  env
+    .sys_cmd()
+    .env("SYSLUA_TEST_DAEMON_ENV", "1")
+    .arg("plan")
+    .arg(&env.config_path)
+    .assert()
+    .success();
+
+  let output = env.sys_cmd().args(["daemon", "status", "-o", "json"]).output().unwrap();
+  let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  assert_eq!(status["running"], true);
+  assert_eq!(status["status"]["cache_hits"], 1);
+  assert_eq!(status["status"]["cache_misses"], 1);
+
+  env.sys_cmd().args(["daemon", "stop"]).assert().success();
+  assert!(daemon.wait().unwrap().success());
+  assert!(!socket.exists());
+}
+
+#[test]
+fn apply_system_without_system_daemon_fails() {
+  let env = TestEnv::from_fixture("minimal.lua");
+
+  env
+    .sys_cmd()
+    .env("SYSLUA_SYSTEM_DAEMON_SOCKET", env.root_path().join("system.sock"))
+    .args(["apply", "--system", "--build-only"])
+    .arg(&env.config_path)
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("Failed to reach the system daemon"));
+}
@@ -98,3 +98,59 @@ fn destroy_dry_run_shows_plan() {
     "marker file should be removed after actual destroy"
   );
 }
+
+#[test]
+fn destroy_restores_backed_up_file() {
+  let env = TestEnv::from_fixture("bind_backup.lua");
+  let target = env.output_path().join("existing.txt");
+  std::fs::write(&target, "original").unwrap();
+
+  env.sys_cmd().arg("apply").arg(&env.config_path).assert().success();
+  assert_eq!(std::fs::read_to_string(&target).unwrap(), "managed");
+
+  let output = env.sys_cmd().args(["status", "-o", "json"]).output().unwrap();
+  let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  assert_eq!(status["backups"]["count"], 1);
+
+  env.sys_cmd().arg("destroy").assert().success();
+  assert_eq!(std::fs::read_to_string(&target).unwrap(), "original");
+}
+
+#[test]
+fn destroy_only_removes_selected_binds_and_dependents() {
+  let env = TestEnv::from_fixture("bind_partial_destroy.lua");
+  let marker = |name: &str| env.output_path().join(format!("{}.txt", name));
+
+  env.sys_cmd().arg("apply").arg(&env.config_path).assert().success();
+  assert!(marker("base").exists() && marker("dependent").exists() && marker("other").exists());
+
+  env
+    .sys_cmd()
+    .args(["destroy", "--only", "base"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("dependent"));
+
+  assert!(!marker("base").exists(), "selected bind should be destroyed");
+  assert!(!marker("dependent").exists(), "dependent bind should be destroyed");
+  assert!(marker("other").exists(), "unrelated bind should be kept");
+
+  // The remaining bind is still tracked, selectable by tag
+  env.sys_cmd().args(["destroy", "--only", "shell"]).assert().success();
+  assert!(!marker("other").exists(), "tagged bind should be destroyed");
+}
+
+#[test]
+fn destroy_only_unknown_selector_fails() {
+  let env = TestEnv::from_fixture("bind_partial_destroy.lua");
+
+  env.sys_cmd().arg("apply").arg(&env.config_path).assert().success();
+
+  env
+    .sys_cmd()
+    .args(["destroy", "--only", "missing"])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("no bind matches 'missing'"));
+  assert!(env.output_path().join("base.txt").exists());
+}
@@ -331,3 +331,138 @@ return {
   // Then update should work
   env.sys_cmd().arg("update").arg(&env.config_path).assert().success();
 }
+
+/// Test that `--override-input` swaps an input's URL without touching the lock file.
+#[test]
+fn override_input_in_apply() {
+  let env = TestEnv::empty();
+
+  for lib in ["upstream", "fork"] {
+    env.write_file(
+      &format!("libs/{}/init.lua", lib),
+      "return { inputs = {}, setup = function(_) end }",
+    );
+  }
+
+  env.write_file(
+    "init.lua",
+    r#"
+return {
+  inputs = {
+    lib = "path:./libs/upstream",
+  },
+  setup = function(inputs)
+    assert(inputs.lib.path:match("fork"), "expected overridden input, got " .. inputs.lib.path)
+  end,
+}
+"#,
+  );
+
+  env
+    .sys_cmd()
+    .arg("apply")
+    .arg(&env.config_path)
+    .arg("--override-input")
+    .arg("lib=path:./libs/fork")
+    .assert()
+    .success()
+    .stderr(predicate::str::contains("lib = path:./libs/fork"));
+
+  assert!(!env.config_path.with_file_name("syslua.lock").exists());
+}
+
+#[test]
+fn override_input_requires_name_and_url() {
+  let env = TestEnv::empty();
+  env.write_file("init.lua", "return { inputs = {}, setup = function(_) end }");
+
+  env
+    .sys_cmd()
+    .arg("plan")
+    .arg(&env.config_path)
+    .arg("--override-input")
+    .arg("lib")
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("expected NAME=URL"));
+}
+
+/// Test that `sys info --licenses` reports input and build metadata,
+/// including transitive inputs and entries without a license.
+#[test]
+fn info_licenses_lists_inputs_and_builds() {
+  let env = TestEnv::empty();
+
+  env.write_file(
+    "libs/lib_b/init.lua",
+    r#"
+return {
+  metadata = { license = "Apache-2.0" },
+}
+"#,
+  );
+
+  env.write_file(
+    "libs/lib_a/init.lua",
+    r#"
+return {
+  inputs = {
+    lib_b = "path:../lib_b",
+  },
+  metadata = { description = "Library A", license = "MIT", homepage = "https://example.com/lib_a" },
+  setup = function(_) end,
+}
+"#,
+  );
+
+  env.write_file(
+    "init.lua",
+    r#"
+return {
+  inputs = {
+    lib_a = "path:./libs/lib_a",
+  },
+  setup = function(_)
+    sys.build({
+      id = "licensed-build",
+      metadata = { license = "BSD-3-Clause" },
+      create = function(_, _)
+        return { name = "licensed" }
+      end,
+    })
+    sys.build({
+      id = "unlicensed-build",
+      create = function(_, _)
+        return { name = "unlicensed" }
+      end,
+    })
+  end,
+}
+"#,
+  );
+
+  env
+    .sys_cmd()
+    .arg("info")
+    .arg("--licenses")
+    .arg(&env.config_path)
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("lib_a/lib_b"))
+    .stdout(predicate::str::contains("Apache-2.0"))
+    .stdout(predicate::str::contains("https://example.com/lib_a"))
+    .stdout(predicate::str::contains("BSD-3-Clause"))
+    .stdout(predicate::str::contains("Without a declared license: 1"));
+
+  let output = env
+    .sys_cmd()
+    .args(["info", "--licenses", "-o", "json"])
+    .arg(&env.config_path)
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  assert_eq!(report["inputs"][0]["name"], "lib_a");
+  assert_eq!(report["inputs"][0]["license"], "MIT");
+  assert_eq!(report["builds"][0]["name"], "licensed-build");
+}
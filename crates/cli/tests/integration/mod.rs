@@ -1,5 +1,6 @@
 pub mod apply_tests;
 pub mod common;
+pub mod daemon_tests;
 pub mod destroy_tests;
 pub mod gc_tests;
 pub mod inputs_tests;
@@ -8,5 +9,6 @@ pub mod plan_tests;
 pub mod rollback_tests;
 pub mod script_tests;
 pub mod snapshot_tests;
+pub mod spec_tests;
 pub mod update_tests;
 pub mod windows_tests;
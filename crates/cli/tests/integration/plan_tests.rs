@@ -55,3 +55,85 @@ fn plan_bind_shows_count() {
     .success()
     .stdout(predicate::str::contains("Binds: 1"));
 }
+
+#[test]
+#[cfg(unix)]
+fn plan_lists_paths_binds_will_touch() {
+  let env = TestEnv::empty();
+  env.write_file(
+    "init.lua",
+    r#"
+      return {
+        setup = function()
+          local cfg = sys.build({
+            id = "nvim-config",
+            create = function(_, ctx)
+              ctx:exec({ bin = "/bin/sh", args = { "-c", "mkdir -p " .. ctx.out } })
+              return { out = ctx.out }
+            end,
+          })
+          sys.bind({
+            id = "nvim-link",
+            inputs = { cfg = cfg },
+            create = function(inputs, ctx)
+              ctx:exec({ bin = "/bin/sh", args = { "-c", 'ln -s "' .. inputs.cfg.outputs.out .. '" /tmp/syslua-nvim' } })
+              return { link = "/tmp/syslua-nvim" }
+            end,
+            destroy = function(outputs, ctx)
+              ctx:exec({ bin = "/bin/sh", args = { "-c", "rm -f " .. outputs.link } })
+            end,
+          })
+        end,
+      }
+    "#,
+  );
+
+  env
+    .sys_cmd()
+    .arg("plan")
+    .arg(&env.config_path)
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("nvim-link"))
+    .stdout(predicate::str::contains("symlink /tmp/syslua-nvim"))
+    .stdout(predicate::str::contains("/store/build/"));
+}
+
+#[test]
+fn plan_eval_error_names_the_failing_spec() {
+  let env = TestEnv::from_fixture("bind_create_error.lua");
+
+  env
+    .sys_cmd()
+    .arg("plan")
+    .arg(&env.config_path)
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("evaluation error:"))
+    .stderr(predicate::str::contains("create exploded"))
+    .stderr(predicate::str::contains("in bind 'broken-bind' create (defined at "))
+    .stderr(predicate::str::contains("stack traceback:"));
+}
+
+#[test]
+fn plan_eval_error_as_json() {
+  let env = TestEnv::from_fixture("bind_create_error.lua");
+
+  let output = env
+    .sys_cmd()
+    .arg("plan")
+    .arg(&env.config_path)
+    .args(["--output", "json"])
+    .output()
+    .unwrap();
+  assert!(!output.status.success());
+
+  let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  let error = &report["error"];
+  assert_eq!(error["phase"], "eval");
+  assert_eq!(error["spec"]["kind"], "bind");
+  assert_eq!(error["spec"]["id"], "broken-bind");
+  assert_eq!(error["spec"]["callback"], "create");
+  assert_eq!(error["spec"]["line"], 8);
+  assert!(error["message"].as_str().unwrap().ends_with("create exploded"));
+}
@@ -1,5 +1,7 @@
 //! Rollback behavior integration tests.
 
+use predicates::prelude::*;
+
 use super::common::TestEnv;
 
 #[test]
@@ -31,6 +33,63 @@ fn rollback_restores_destroyed_binds_on_failure() {
   assert!(marker_file.exists(), "original.txt should be restored after rollback");
 }
 
+#[test]
+fn failed_apply_records_transcript() {
+  let env = TestEnv::from_fixture("rollback_bind_failure.lua");
+
+  env
+    .sys_cmd()
+    .arg("apply")
+    .arg("--impure")
+    .arg(&env.config_path)
+    .env("TEST_PHASE", "initial")
+    .assert()
+    .success();
+  env
+    .sys_cmd()
+    .arg("apply")
+    .arg("--impure")
+    .arg(&env.config_path)
+    .env("TEST_PHASE", "failure")
+    .assert()
+    .failure();
+
+  let output = env
+    .sys_cmd()
+    .args(["logs", "--transcript", "failing-bind", "-o", "json"])
+    .output()
+    .unwrap();
+  assert!(
+    output.status.success(),
+    "logs failed: {}",
+    String::from_utf8_lossy(&output.stderr)
+  );
+  let transcript: serde_json::Value = serde_json::from_slice(&output.stdout).expect("valid JSON");
+  assert_eq!(transcript["success"], false);
+  assert!(transcript["snapshot"].is_null());
+  let actions = transcript["actions"].as_array().unwrap();
+  assert_eq!(actions.len(), 1);
+  assert_eq!(actions[0]["operation"], "create");
+  assert_eq!(actions[0]["exit_code"], 1);
+  assert!(actions[0]["env"]["PATH"].is_string());
+
+  // The same apply destroyed and restored the original bind
+  let output = env
+    .sys_cmd()
+    .args(["logs", "--transcript", "original-bind", "-o", "json"])
+    .output()
+    .unwrap();
+  let transcript: serde_json::Value = serde_json::from_slice(&output.stdout).expect("valid JSON");
+  let operations: Vec<&str> = transcript["actions"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|a| a["operation"].as_str().unwrap())
+    .collect();
+  assert!(operations.contains(&"destroy"), "{:?}", operations);
+  assert!(operations.contains(&"create"), "{:?}", operations);
+}
+
 #[test]
 fn build_failure_skips_dependent_binds() {
   let env = TestEnv::from_fixture("rollback_build_failure.lua");
@@ -40,3 +99,77 @@ fn build_failure_skips_dependent_binds() {
 
   assert!(!marker_file.exists(), "dependent bind should not have run");
 }
+
+#[test]
+fn failed_build_is_not_run_again_until_retried() {
+  let env = TestEnv::from_fixture("rollback_build_failure.lua");
+
+  env
+    .sys_cmd()
+    .arg("apply")
+    .arg(&env.config_path)
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("hasn't changed since").not());
+
+  // The unchanged build fails the next apply without running
+  env
+    .sys_cmd()
+    .arg("apply")
+    .arg(&env.config_path)
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("build 'failing-build' failed").and(predicate::str::contains("--retry-failed")));
+
+  env
+    .sys_cmd()
+    .arg("plan")
+    .arg(&env.config_path)
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Previously failed: 1").and(predicate::str::contains("failing-build")));
+
+  // Retrying runs it, and a TTL of zero doesn't remember failures
+  for flag in ["--retry-failed", "--failure-ttl=0s"] {
+    env
+      .sys_cmd()
+      .arg("apply")
+      .arg(flag)
+      .arg(&env.config_path)
+      .assert()
+      .failure()
+      .stderr(predicate::str::contains("hasn't changed since").not());
+  }
+}
+
+#[test]
+fn fail_at_fails_the_chosen_bind_before_it_runs() {
+  let env = TestEnv::from_fixture("bind_create.lua");
+  let marker_file = env.output_path().join("created.txt");
+
+  env
+    .sys_cmd()
+    .arg("apply")
+    .arg("--fail-at")
+    .arg("test-bind")
+    .arg("--fail-phase")
+    .arg("bind")
+    .arg(&env.config_path)
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("injected bind failure at 'test-bind'"));
+
+  assert!(!marker_file.exists(), "failed bind should not have run");
+
+  // Failing another phase leaves the bind alone
+  env
+    .sys_cmd()
+    .arg("apply")
+    .arg(&env.config_path)
+    .env("SYSLUA_FAIL_AT", "test-bind")
+    .env("SYSLUA_FAIL_PHASE", "update")
+    .assert()
+    .success();
+
+  assert!(marker_file.exists(), "bind should be created once no longer failed");
+}
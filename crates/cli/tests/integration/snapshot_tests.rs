@@ -262,3 +262,58 @@ fn test_snapshot_delete_older_than() {
   );
   assert!(combined.contains("No snapshots") || combined.contains("current") || combined.contains("Cancelled"));
 }
+
+#[test]
+fn test_history_records_applies_and_verifies_signed_entries() {
+  let env = TestEnv::from_fixture("minimal.lua");
+  let apply = || {
+    let output = env
+      .sys_cmd()
+      .args(["apply", env.config_path.to_str().unwrap()])
+      .output()
+      .unwrap();
+    assert!(
+      output.status.success(),
+      "apply failed: {}",
+      String::from_utf8_lossy(&output.stderr)
+    );
+  };
+
+  apply();
+  let keygen = env.sys_cmd().args(["state", "keygen"]).output().unwrap();
+  assert!(keygen.status.success());
+  let stdout = String::from_utf8_lossy(&keygen.stdout);
+  let public_key = stdout
+    .lines()
+    .find_map(|line| line.split("Public key: ").nth(1))
+    .expect("public key printed")
+    .trim()
+    .to_string();
+  apply();
+
+  let output = env
+    .sys_cmd()
+    .args(["history", "--public-key", &public_key, "-o", "json"])
+    .output()
+    .unwrap();
+  assert!(
+    output.status.success(),
+    "history failed: {}",
+    String::from_utf8_lossy(&output.stderr)
+  );
+  let parsed: serde_json::Value = serde_json::from_slice(&output.stdout).expect("valid JSON");
+  let entries = parsed["entries"].as_array().unwrap();
+  assert_eq!(entries.len(), 2);
+  assert_eq!(entries[0]["operation"], "apply");
+  assert!(entries[0]["signature"].is_null());
+  assert!(entries[1]["signature"].is_object());
+  assert_eq!(parsed["verification"]["signed"], 1);
+
+  // Rewriting an entry is caught
+  let journal = env.root_path().join("store").join("journal.jsonl");
+  let content = std::fs::read_to_string(&journal).unwrap();
+  std::fs::write(&journal, content.replacen("\"success\":true", "\"success\":false", 1)).unwrap();
+  let output = env.sys_cmd().args(["history", "--verify"]).output().unwrap();
+  assert!(!output.status.success());
+  assert!(String::from_utf8_lossy(&output.stderr).contains("tampered"));
+}
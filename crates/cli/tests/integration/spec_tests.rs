@@ -0,0 +1,65 @@
+//! `sys test` integration tests.
+
+use predicates::prelude::*;
+
+use super::common::TestEnv;
+
+const MODULE: &str = r#"
+local M = {}
+function M.setup()
+  sys.bind({
+    id = 'greeting',
+    create = function(_, ctx)
+      ctx:exec({ bin = '/bin/echo', args = { 'hello' } })
+    end,
+    destroy = function(_, ctx)
+      ctx:exec({ bin = '/bin/echo', args = { 'bye' } })
+    end,
+  })
+end
+return M
+"#;
+
+#[test]
+fn test_reports_passing_and_failing_specs() {
+  let env = TestEnv::from_fixture("minimal.lua");
+  env.write_file("lua/greeting.lua", MODULE);
+  env.write_file(
+    "lua/greeting_spec.lua",
+    r#"
+      local greeting = require('greeting')
+      return {
+        ['records the bind'] = function()
+          greeting.setup()
+          sys.testing.expect_bind({ id = 'greeting', exec = 'hello', destroy_exec = 'bye' })
+        end,
+        ['records no builds'] = function()
+          greeting.setup()
+          sys.testing.expect_build({ id = 'greeting' })
+        end,
+      }
+    "#,
+  );
+
+  env
+    .sys_cmd()
+    .arg("test")
+    .arg(&env.config_path)
+    .assert()
+    .failure()
+    .stdout(predicate::str::contains("1 passed, 1 failed"))
+    .stdout(predicate::str::contains("expect_build"));
+
+  env
+    .sys_cmd()
+    .arg("test")
+    .arg(&env.config_path)
+    .args(["--filter", "records the bind"])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("1 passed, 0 failed"));
+
+  // Nothing was applied
+  let output = env.sys_cmd().arg("status").output().unwrap();
+  assert!(String::from_utf8_lossy(&output.stdout).contains("No snapshot found"));
+}
@@ -48,3 +48,126 @@ fn update_command_with_no_inputs() {
     .success()
     .stdout(predicate::str::contains("up to date"));
 }
+
+#[test]
+fn prune_removes_lock_entries_of_dropped_transitive_inputs() {
+  let env = TestEnv::empty();
+  let lib = |inputs: &str| {
+    format!(
+      "return {{\n  inputs = {{ {} }},\n  setup = function(_) end,\n}}\n",
+      inputs
+    )
+  };
+  env.write_file("libs/lib_b/init.lua", &lib(""));
+  env.write_file("libs/lib_a/init.lua", &lib(r#"lib_b = "path:../lib_b""#));
+  env.write_file(
+    "init.lua",
+    r#"
+return {
+  inputs = {
+    lib_a = "path:./libs/lib_a",
+  },
+  setup = function(_) end,
+}
+"#,
+  );
+
+  env.sys_cmd().arg("update").arg(&env.config_path).assert().success();
+  env
+    .sys_cmd()
+    .args(["lock", "audit"])
+    .arg(&env.config_path)
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Lock file is clean"));
+
+  // lib_a no longer depends on lib_b, but the lock file still pins it
+  env.write_file("libs/lib_a/init.lua", &lib(""));
+  env
+    .sys_cmd()
+    .args(["lock", "audit"])
+    .arg(&env.config_path)
+    .assert()
+    .failure()
+    .stdout(predicate::str::contains("Stale: lib_a/lib_b"));
+
+  env
+    .sys_cmd()
+    .args(["update", "--prune"])
+    .arg(&env.config_path)
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Pruned: lib_a/lib_b"));
+
+  let lock = std::fs::read_to_string(env.temp.path().join("syslua.lock")).unwrap();
+  assert!(!lock.contains("lib_a/lib_b"), "{}", lock);
+  env
+    .sys_cmd()
+    .args(["lock", "audit"])
+    .arg(&env.config_path)
+    .assert()
+    .success();
+}
+
+#[test]
+fn search_and_add_input_from_registry() {
+  let env = TestEnv::empty();
+  env.write_file("libs/kit/init.lua", "return { setup = function(_) end }\n");
+  env.write_file(
+    "registry.json",
+    r#"{
+  "version": 1,
+  "inputs": [
+    { "name": "kit", "description": "Shell helpers", "url": "path:./libs/kit", "tags": ["shell"] },
+    { "name": "editor", "description": "Neovim setup", "url": "path:./libs/editor" }
+  ]
+}"#,
+  );
+  env.write_file(
+    "init.lua",
+    r#"return {
+  settings = { registries = { "registry.json" } },
+  inputs = {
+    -- nothing yet
+  },
+  setup = function(_) end,
+}
+"#,
+  );
+
+  env
+    .sys_cmd()
+    .args(["search", "shell", "--config"])
+    .arg(&env.config_path)
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("kit - Shell helpers"))
+    .stdout(predicate::str::contains("path:./libs/kit"))
+    .stdout(predicate::str::contains("editor").not());
+
+  env
+    .sys_cmd()
+    .args(["add-input", "kit"])
+    .arg(&env.config_path)
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Added input kit"));
+
+  let config = std::fs::read_to_string(&env.config_path).unwrap();
+  assert!(
+    config.contains("  inputs = {\n    -- nothing yet\n    kit = \"path:./libs/kit\",\n  },"),
+    "{}",
+    config
+  );
+  let lock = std::fs::read_to_string(env.temp.path().join("syslua.lock")).unwrap();
+  assert!(lock.contains("\"kit\""), "{}", lock);
+
+  // The input that doesn't exist on disk fails to resolve and is not declared
+  env
+    .sys_cmd()
+    .args(["add-input", "editor"])
+    .arg(&env.config_path)
+    .assert()
+    .failure();
+  assert_eq!(std::fs::read_to_string(&env.config_path).unwrap(), config);
+}
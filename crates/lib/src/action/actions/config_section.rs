@@ -0,0 +1,613 @@
+//! Config section action implementation (`git_config`, `ssh_config`).
+//!
+//! Manages one named section of a git config (INI) or ssh client config file
+//! without touching the rest of the file. The section is wrapped in marker
+//! comments so that removing it only ever deletes what syslua wrote:
+//!
+//! ```text
+//! # BEGIN SYSLUA [user]
+//! [user]
+//! 	email = jane@example.com
+//! 	name = Jane
+//! # END SYSLUA [user]
+//! ```
+//!
+//! New git sections are appended, so they override earlier settings of the
+//! same keys. New ssh hosts go before the first `Host` or `Match` block,
+//! because ssh uses the first value it finds for each keyword.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{debug, info};
+
+use crate::execute::types::ExecuteError;
+use crate::platform::paths::expand_path;
+
+/// Prefix of the comment opening a managed section.
+const BEGIN_MARKER: &str = "# BEGIN SYSLUA";
+
+/// Prefix of the comment closing a managed section.
+const END_MARKER: &str = "# END SYSLUA";
+
+/// File format of a managed config section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+  /// Git config (`~/.gitconfig`): `[section]` headers and `key = value` entries.
+  Git,
+  /// OpenSSH client config (`~/.ssh/config`): `Host` blocks and `Keyword value` entries.
+  Ssh,
+}
+
+/// What a config section action does with its section.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SectionState {
+  /// Write the section, replacing a previous managed version.
+  #[default]
+  Present,
+  /// Remove the managed section, if there is one.
+  Absent,
+  /// Report whether the file differs from the section, without writing.
+  Check,
+}
+
+impl SectionState {
+  fn is_present(&self) -> bool {
+    *self == Self::Present
+  }
+}
+
+/// Options for a config section action.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ConfigSectionOpts {
+  /// Format of the config file.
+  pub format: ConfigFormat,
+  /// Path of the config file (`~` is expanded).
+  pub path: String,
+  /// Section name: the text inside `[...]` for git (e.g. `remote "origin"`),
+  /// the host patterns for ssh (e.g. `github.com gitlab.com`).
+  pub section: String,
+  /// Entries of the section. Keys with several values are written once per value.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub entries: BTreeMap<String, Vec<String>>,
+  /// Whether to write, remove or check the section.
+  #[serde(default, skip_serializing_if = "SectionState::is_present")]
+  pub state: SectionState,
+}
+
+impl ConfigSectionOpts {
+  /// The header line of the section.
+  pub fn header(&self) -> String {
+    match self.format {
+      ConfigFormat::Git => format!("[{}]", self.section),
+      ConfigFormat::Ssh => format!("Host {}", self.section),
+    }
+  }
+
+  /// The managed section as lines, markers included.
+  fn render(&self) -> Vec<String> {
+    let header = self.header();
+    let mut lines = vec![format!("{} {}", BEGIN_MARKER, header), header.clone()];
+    for (key, values) in &self.entries {
+      for value in values {
+        lines.push(match self.format {
+          ConfigFormat::Git => format!("\t{} = {}", key, quote_git_value(value)),
+          ConfigFormat::Ssh => format!("  {} {}", key, quote_ssh_value(value)),
+        });
+      }
+    }
+    lines.push(format!("{} {}", END_MARKER, header));
+    lines
+  }
+
+  /// Entries with keys lowercased, as both formats compare keys without case.
+  fn normalized_entries(&self) -> BTreeMap<String, Vec<String>> {
+    let mut normalized: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (key, values) in &self.entries {
+      normalized
+        .entry(key.to_ascii_lowercase())
+        .or_default()
+        .extend(values.iter().cloned());
+    }
+    normalized
+  }
+}
+
+/// Write `opts`' section into `content`, returning the new file content.
+pub fn write_section(content: &str, opts: &ConfigSectionOpts) -> String {
+  let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+  let rendered = opts.render();
+
+  if let Some(range) = find_managed(&lines, &opts.header()) {
+    lines.splice(range, rendered);
+    return join_lines(&lines);
+  }
+
+  let at = match opts.format {
+    ConfigFormat::Git => lines.len(),
+    ConfigFormat::Ssh => lines
+      .iter()
+      .position(|line| is_ssh_block_start(line))
+      .unwrap_or(lines.len()),
+  };
+
+  let mut block = Vec::new();
+  if at > 0 && !lines[at - 1].trim().is_empty() {
+    block.push(String::new());
+  }
+  block.extend(rendered);
+  if at < lines.len() {
+    block.push(String::new());
+  }
+  lines.splice(at..at, block);
+  join_lines(&lines)
+}
+
+/// Remove `opts`' managed section from `content`, returning the new file content.
+///
+/// Sections that syslua didn't write are never removed.
+pub fn remove_section(content: &str, opts: &ConfigSectionOpts) -> String {
+  let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+  let Some(mut range) = find_managed(&lines, &opts.header()) else {
+    return content.to_string();
+  };
+
+  // Drop the blank line that separated the section from what follows
+  let preceded_by_blank = range.start == 0 || lines[range.start - 1].trim().is_empty();
+  if preceded_by_blank && lines.get(range.end).is_some_and(|line| line.trim().is_empty()) {
+    range.end += 1;
+  }
+  lines.drain(range);
+  while lines.last().is_some_and(|line| line.trim().is_empty()) {
+    lines.pop();
+  }
+  join_lines(&lines)
+}
+
+/// Whether the managed section in `content` is missing or differs from `opts`.
+///
+/// Entries are parsed, so formatting changes (whitespace, quoting, key case)
+/// are not drift; changed, added or removed values are.
+pub fn section_drifted(content: &str, opts: &ConfigSectionOpts) -> bool {
+  let lines: Vec<String> = content.lines().map(str::to_string).collect();
+  let header = opts.header();
+  let Some(range) = find_managed(&lines, &header) else {
+    return true;
+  };
+
+  // Lines between the markers: the header, then the entries
+  let body = &lines[range.start + 1..range.end - 1];
+  let Some((first, entries)) = body.split_first() else {
+    return true;
+  };
+  if first.trim() != header {
+    return true;
+  }
+
+  match parse_entries(opts.format, entries) {
+    Some(parsed) => parsed != opts.normalized_entries(),
+    None => true,
+  }
+}
+
+/// Line range of the managed section with this header, markers included.
+///
+/// A section whose end marker is missing extends to the end of the file.
+fn find_managed(lines: &[String], header: &str) -> Option<Range<usize>> {
+  let begin = format!("{} {}", BEGIN_MARKER, header);
+  let end = format!("{} {}", END_MARKER, header);
+
+  let start = lines.iter().position(|line| line.trim_end() == begin)?;
+  let stop = lines[start + 1..]
+    .iter()
+    .position(|line| line.trim_end() == end)
+    .map(|offset| start + 1 + offset + 1)
+    .unwrap_or(lines.len());
+  Some(start..stop)
+}
+
+/// Whether `line` opens an ssh `Host` or `Match` block.
+fn is_ssh_block_start(line: &str) -> bool {
+  let keyword = line.trim_start().split(|c: char| c.is_whitespace() || c == '=').next();
+  keyword.is_some_and(|k| k.eq_ignore_ascii_case("host") || k.eq_ignore_ascii_case("match"))
+}
+
+/// Parse the entries of a section body, with keys lowercased.
+///
+/// Returns `None` if a line isn't a valid entry, comment or blank line.
+fn parse_entries(format: ConfigFormat, lines: &[String]) -> Option<BTreeMap<String, Vec<String>>> {
+  let mut entries: BTreeMap<String, Vec<String>> = BTreeMap::new();
+  for line in lines {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || (format == ConfigFormat::Git && line.starts_with(';')) {
+      continue;
+    }
+
+    let (key, value) = match format {
+      ConfigFormat::Git => {
+        let (key, value) = line.split_once('=').unwrap_or((line, "true"));
+        (key.trim(), unquote_git_value(value.trim())?)
+      }
+      ConfigFormat::Ssh => {
+        let split = line.find(|c: char| c.is_whitespace() || c == '=')?;
+        let rest = line[split..].trim_start();
+        let rest = rest.strip_prefix('=').unwrap_or(rest).trim();
+        (&line[..split], unquote_ssh_value(rest))
+      }
+    };
+    if key.is_empty() {
+      return None;
+    }
+    entries.entry(key.to_ascii_lowercase()).or_default().push(value);
+  }
+  Some(entries)
+}
+
+/// Quote a git config value if it would otherwise be read differently.
+fn quote_git_value(value: &str) -> String {
+  let needs_quotes = value.is_empty() || value.trim() != value || value.contains(['#', ';', '"', '\\']);
+  if !needs_quotes {
+    return value.to_string();
+  }
+  let escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\t', "\\t");
+  format!("\"{}\"", escaped)
+}
+
+/// Read a git config value: quotes are removed, escapes decoded and
+/// comments outside quotes dropped. Returns `None` for an invalid escape.
+fn unquote_git_value(raw: &str) -> Option<String> {
+  let mut value = String::new();
+  let mut in_quotes = false;
+  // Length of the value up to its last quoted or escaped character, which
+  // trailing whitespace trimming must not eat into
+  let mut kept = 0;
+  let mut chars = raw.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' => in_quotes = !in_quotes,
+      '\\' => match chars.next()? {
+        '\\' => value.push('\\'),
+        '"' => value.push('"'),
+        't' => value.push('\t'),
+        'n' => value.push('\n'),
+        'b' => {
+          value.pop();
+        }
+        _ => return None,
+      },
+      '#' | ';' if !in_quotes => break,
+      c => value.push(c),
+    }
+    if in_quotes || c == '"' || c == '\\' {
+      kept = value.len();
+    }
+  }
+  let trimmed = value[kept..].trim_end().len();
+  value.truncate(kept + trimmed);
+  Some(value)
+}
+
+/// Quote an ssh config value that contains whitespace.
+fn quote_ssh_value(value: &str) -> String {
+  if value.is_empty() || value.contains(char::is_whitespace) {
+    format!("\"{}\"", value)
+  } else {
+    value.to_string()
+  }
+}
+
+/// Read an ssh config value, removing surrounding quotes.
+fn unquote_ssh_value(raw: &str) -> String {
+  raw
+    .strip_prefix('"')
+    .and_then(|v| v.strip_suffix('"'))
+    .unwrap_or(raw)
+    .to_string()
+}
+
+fn join_lines(lines: &[String]) -> String {
+  if lines.is_empty() {
+    return String::new();
+  }
+  format!("{}\n", lines.join("\n"))
+}
+
+/// Execute a config section action.
+///
+/// Returns the config file path, or for [`SectionState::Check`] `"true"` if
+/// the section has drifted and `"false"` otherwise.
+pub async fn execute_config_section(opts: &ConfigSectionOpts) -> Result<String, ExecuteError> {
+  let path = expand_path(&opts.path);
+  let content = match fs::read_to_string(&path).await {
+    Ok(content) => Some(content),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+    Err(e) => return Err(e.into()),
+  };
+
+  match opts.state {
+    SectionState::Check => {
+      let drifted = content.is_none_or(|content| section_drifted(&content, opts));
+      debug!(path = ?path, section = %opts.section, drifted, "checked config section");
+      Ok(drifted.to_string())
+    }
+    SectionState::Present => {
+      let existing = content.unwrap_or_default();
+      let updated = write_section(&existing, opts);
+      if updated != existing {
+        write_config(&path, &updated, opts.format).await?;
+        info!(path = ?path, section = %opts.section, "wrote config section");
+      }
+      Ok(path.to_string_lossy().to_string())
+    }
+    SectionState::Absent => {
+      if let Some(existing) = content {
+        let updated = remove_section(&existing, opts);
+        if updated != existing {
+          fs::write(&path, updated).await?;
+          info!(path = ?path, section = %opts.section, "removed config section");
+        }
+      }
+      Ok(path.to_string_lossy().to_string())
+    }
+  }
+}
+
+/// Write a config file, creating it (and its directory) if needed.
+///
+/// A new ssh config is only readable by its owner, as ssh requires.
+async fn write_config(path: &Path, content: &str, format: ConfigFormat) -> Result<(), ExecuteError> {
+  let created = !fs::try_exists(path).await.unwrap_or(false);
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).await?;
+  }
+  fs::write(path, content).await?;
+
+  #[cfg(unix)]
+  if created && format == ConfigFormat::Ssh {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).await?;
+  }
+  #[cfg(not(unix))]
+  let _ = (created, format);
+
+  Ok(())
+}
+
+/// Parse the Lua options of `ctx:git_config` / `ctx:ssh_config`.
+///
+/// `section_field` is the name of the section field (`section` or `host`).
+/// Entry values may be strings, numbers, booleans or arrays of those.
+pub fn parse_config_section_opts(
+  format: ConfigFormat,
+  section_field: &str,
+  opts: LuaTable,
+) -> LuaResult<ConfigSectionOpts> {
+  let method = match format {
+    ConfigFormat::Git => "git_config",
+    ConfigFormat::Ssh => "ssh_config",
+  };
+  let err = |message: String| LuaError::external(format!("{}: {}", method, message));
+
+  let path: String = opts.get("path")?;
+  let section: String = opts
+    .get::<Option<String>>(section_field)?
+    .ok_or_else(|| err(format!("'{}' is required", section_field)))?;
+  if section.trim().is_empty() || section.contains(['\n', '\r']) || section.trim() != section {
+    return Err(err(format!("invalid {} '{}'", section_field, section)));
+  }
+  if format == ConfigFormat::Git && section.contains([']', '[']) {
+    return Err(err(format!("invalid section '{}'", section)));
+  }
+
+  let state = match opts.get::<Option<String>>("state")?.as_deref() {
+    None | Some("present") => SectionState::Present,
+    Some("absent") => SectionState::Absent,
+    Some("check") => SectionState::Check,
+    Some(other) => {
+      return Err(err(format!(
+        "unknown state '{}' (expected present, absent or check)",
+        other
+      )));
+    }
+  };
+
+  let mut entries = BTreeMap::new();
+  if let Some(table) = opts.get::<Option<LuaTable>>("entries")? {
+    for pair in table.pairs::<String, LuaValue>() {
+      let (key, value) = pair?;
+      let valid_key = key.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && key
+          .chars()
+          .all(|c| c.is_ascii_alphanumeric() || (format == ConfigFormat::Git && c == '-'));
+      if !valid_key {
+        return Err(err(format!("invalid key '{}'", key)));
+      }
+
+      let values = match value {
+        LuaValue::Table(list) => list
+          .sequence_values::<LuaValue>()
+          .map(|v| v.and_then(|v| entry_value(v, &key).map_err(&err)))
+          .collect::<LuaResult<Vec<_>>>()?,
+        value => vec![entry_value(value, &key).map_err(&err)?],
+      };
+      entries.insert(key, values);
+    }
+  }
+
+  Ok(ConfigSectionOpts {
+    format,
+    path,
+    section,
+    entries,
+    state,
+  })
+}
+
+fn entry_value(value: LuaValue, key: &str) -> Result<String, String> {
+  let value = match value {
+    LuaValue::String(s) => s.to_str().map_err(|e| e.to_string())?.to_string(),
+    LuaValue::Integer(i) => i.to_string(),
+    LuaValue::Number(n) => n.to_string(),
+    LuaValue::Boolean(b) => b.to_string(),
+    other => {
+      return Err(format!(
+        "value of '{}' must be a string, number or boolean, got {}",
+        key,
+        other.type_name()
+      ));
+    }
+  };
+  if value.contains(['\n', '\r']) {
+    return Err(format!("value of '{}' must be a single line", key));
+  }
+  Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn opts(format: ConfigFormat, section: &str, entries: &[(&str, &str)]) -> ConfigSectionOpts {
+    let mut map: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (key, value) in entries {
+      map.entry(key.to_string()).or_default().push(value.to_string());
+    }
+    ConfigSectionOpts {
+      format,
+      path: String::new(),
+      section: section.to_string(),
+      entries: map,
+      state: SectionState::Present,
+    }
+  }
+
+  #[test]
+  fn git_section_is_appended_and_replaced_in_place() {
+    let existing = "[core]\n\teditor = vim\n";
+    let user = opts(
+      ConfigFormat::Git,
+      "user",
+      &[("name", "Jane"), ("email", "jane@example.com")],
+    );
+
+    let written = write_section(existing, &user);
+    assert_eq!(
+      written,
+      "[core]\n\teditor = vim\n\n# BEGIN SYSLUA [user]\n[user]\n\temail = jane@example.com\n\tname = Jane\n# END SYSLUA [user]\n"
+    );
+    assert!(!section_drifted(&written, &user));
+
+    let renamed = opts(ConfigFormat::Git, "user", &[("name", "Jane Doe")]);
+    let rewritten = write_section(&written, &renamed);
+    assert!(rewritten.starts_with("[core]\n\teditor = vim\n\n# BEGIN SYSLUA [user]\n[user]\n\tname = Jane Doe\n"));
+    assert_eq!(rewritten.matches("BEGIN SYSLUA").count(), 1);
+    assert!(section_drifted(&rewritten, &user));
+  }
+
+  #[test]
+  fn ssh_host_goes_before_existing_blocks() {
+    let existing = "IdentitiesOnly yes\n\nHost *\n  ServerAliveInterval 60\n";
+    let github = opts(
+      ConfigFormat::Ssh,
+      "github.com",
+      &[("User", "git"), ("IdentityFile", "~/.ssh/id key")],
+    );
+
+    let written = write_section(existing, &github);
+    assert_eq!(
+      written,
+      "IdentitiesOnly yes\n\n# BEGIN SYSLUA Host github.com\nHost github.com\n  IdentityFile \"~/.ssh/id key\"\n  User git\n# END SYSLUA Host github.com\n\nHost *\n  ServerAliveInterval 60\n"
+    );
+    assert!(!section_drifted(&written, &github));
+    assert_eq!(remove_section(&written, &github), existing);
+  }
+
+  #[test]
+  fn remove_leaves_unmanaged_sections() {
+    let existing = "[user]\n\tname = Someone\n";
+    let user = opts(ConfigFormat::Git, "user", &[("name", "Jane")]);
+
+    assert_eq!(remove_section(existing, &user), existing);
+    let written = write_section(existing, &user);
+    assert_eq!(remove_section(&written, &user), existing);
+  }
+
+  #[test]
+  fn drift_ignores_formatting_but_not_values() {
+    let user = opts(ConfigFormat::Git, "user", &[("name", "Jane; Doe")]);
+    let written = write_section("", &user);
+    assert!(written.contains("\tname = \"Jane; Doe\"\n"));
+
+    let reformatted = written.replace("\tname = \"Jane; Doe\"", "  Name=\"Jane; Doe\" # me");
+    assert!(!section_drifted(&reformatted, &user));
+
+    let edited = written.replace("Jane; Doe", "Janet");
+    assert!(section_drifted(&edited, &user));
+    assert!(section_drifted("", &user));
+  }
+
+  #[test]
+  fn git_values_round_trip_through_quoting() {
+    for value in ["plain", " padded ", "a#b", "back\\slash", "say \"hi\"", "tab\there", ""] {
+      assert_eq!(unquote_git_value(&quote_git_value(value)).as_deref(), Some(value));
+    }
+  }
+
+  #[test]
+  fn parses_lua_options() {
+    let lua = Lua::new();
+    let table: LuaTable = lua
+      .load(r#"return { path = "~/.ssh/config", host = "github.com", entries = { User = "git", Port = 22, LocalForward = { "8080 localhost:80", "9090 localhost:90" } } }"#)
+      .eval()
+      .unwrap();
+
+    let parsed = parse_config_section_opts(ConfigFormat::Ssh, "host", table).unwrap();
+    assert_eq!(parsed.section, "github.com");
+    assert_eq!(parsed.state, SectionState::Present);
+    assert_eq!(parsed.entries["Port"], vec!["22"]);
+    assert_eq!(parsed.entries["LocalForward"].len(), 2);
+
+    let invalid: LuaTable = lua
+      .load(r#"return { path = "~/.gitconfig", section = "user", entries = { ["bad key"] = "x" } }"#)
+      .eval()
+      .unwrap();
+    assert!(parse_config_section_opts(ConfigFormat::Git, "section", invalid).is_err());
+  }
+
+  #[tokio::test]
+  async fn execute_writes_checks_and_removes_section() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let path = temp.path().join("ssh").join("config");
+    let mut github = opts(ConfigFormat::Ssh, "github.com", &[("User", "git")]);
+    github.path = path.to_string_lossy().to_string();
+
+    let check = |opts: &ConfigSectionOpts| ConfigSectionOpts {
+      state: SectionState::Check,
+      ..opts.clone()
+    };
+    assert_eq!(execute_config_section(&check(&github)).await.unwrap(), "true");
+
+    execute_config_section(&github).await.unwrap();
+    assert_eq!(execute_config_section(&check(&github)).await.unwrap(), "false");
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let absent = ConfigSectionOpts {
+      state: SectionState::Absent,
+      ..github.clone()
+    };
+    execute_config_section(&absent).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+  }
+}
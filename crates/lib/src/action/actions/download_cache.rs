@@ -0,0 +1,683 @@
+//! Shared download cache for `fetch_url`.
+//!
+//! Downloads are kept outside the store, keyed by URL and expected SHA256, so a
+//! build whose hash changed but whose sources didn't reuses the file instead of
+//! downloading it again. An interrupted download leaves a `.part` file that the
+//! next attempt resumes with an HTTP `Range` request. A process fetching a key
+//! holds an exclusive lock on its `.lock` file, so concurrent builds (of this
+//! apply or another) fetching the same file wait for each other instead of
+//! writing over the same `.part`.
+//!
+//! Large files from servers accepting byte ranges are fetched as [`CHUNKS`]
+//! ranges in parallel, each into its own `.part.<n>` file resumed on its own,
+//! and joined once complete. Every download reports its progress and respects
+//! the apply's [rate limit](super::rate_limit) as configured in
+//! [`DownloadOptions`].
+//!
+//! # Layout
+//!
+//! ```text
+//! <cache_dir>/downloads/
+//! ├── <key>          # complete, verified download
+//! ├── <key>.lock     # held while a process fetches or verifies <key>
+//! ├── <key>.part     # interrupted download, resumed on the next fetch
+//! └── <key>.part.<n> # range of an interrupted parallel download
+//! ```
+//!
+//! Entries are verified against their hash every time they are used, and their
+//! modification time is refreshed so `sys gc` can remove the ones that haven't
+//! been used for [`MAX_UNUSED_AGE`].
+
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use reqwest::StatusCode;
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+use sha2::{Digest, Sha256};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use super::rate_limit::RateLimit;
+use crate::execute::progress::{ProgressEvent, ProgressSender};
+use crate::execute::types::ExecuteError;
+use crate::platform::paths::cache_dir;
+use crate::store_lock::{LockMode, try_lock};
+use crate::util::hash::ObjectHash;
+
+/// Cached downloads unused for longer than this are removed by `sys gc`.
+pub const MAX_UNUSED_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Suffix of interrupted downloads.
+const PART_SUFFIX: &str = ".part";
+
+/// Downloads at least this large are fetched as parallel ranges when possible.
+const CHUNKED_MIN_SIZE: u64 = 16 << 20;
+
+/// Number of ranges a parallel download is split into.
+const CHUNKS: u64 = 4;
+
+/// Minimum time between two progress reports of a download.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Suffix of the lock files of downloads.
+const LOCK_SUFFIX: &str = ".lock";
+
+/// How often a fetch waiting for another one to release a key retries.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time allowed to connect to a server.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A download receiving nothing for this long is aborted (and resumed by the
+/// next attempt). Downloads have no overall limit, since files can be large.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How a download is throttled and reported.
+#[derive(Debug, Clone, Default)]
+pub struct DownloadOptions {
+  /// Rate limit shared with the other downloads of the apply.
+  pub rate: RateLimit,
+  /// Where to report progress, and the build it is reported for.
+  pub progress: Option<(ProgressSender, ObjectHash)>,
+}
+
+/// Directory holding cached downloads.
+pub fn downloads_dir() -> PathBuf {
+  cache_dir().join("downloads")
+}
+
+/// Cache key for a download: the first 32 hex characters of the SHA256 of the
+/// URL and the expected hash.
+pub fn cache_key(url: &str, sha256: &str) -> String {
+  let mut hasher = Sha256::new();
+  hasher.update(url.as_bytes());
+  hasher.update(b"\0");
+  hasher.update(sha256.as_bytes());
+  hex::encode(hasher.finalize())[..32].to_string()
+}
+
+/// Return the cached, verified file for `url`, downloading it first if needed.
+///
+/// A corrupt cache entry is discarded. A partial download is resumed; if the
+/// resumed file doesn't match the hash it is downloaded once more from the start.
+pub async fn fetch_cached(
+  url: &str,
+  expected_sha256: &str,
+  options: &DownloadOptions,
+) -> Result<PathBuf, ExecuteError> {
+  let dir = downloads_dir();
+  fs::create_dir_all(&dir).await?;
+
+  let key = cache_key(url, expected_sha256);
+  let path = dir.join(&key);
+  let part = dir.join(format!("{}{}", key, PART_SUFFIX));
+  let _lock = lock_key(&dir.join(format!("{}{}", key, LOCK_SUFFIX))).await?;
+
+  if fs::try_exists(&path).await.unwrap_or(false) {
+    if hash_file(&path).await? == expected_sha256 {
+      info!(url = %url, path = ?path, "using cached download");
+      touch(&path);
+      return Ok(path);
+    }
+    warn!(path = ?path, "cached download does not match its hash, fetching again");
+    fs::remove_file(&path).await?;
+  }
+
+  let resumed = download(url, &part, options).await?;
+  let mut actual = hash_file(&part).await?;
+  if actual != expected_sha256 && resumed {
+    debug!(url = %url, "resumed download does not match, restarting");
+    fs::remove_file(&part).await?;
+    download(url, &part, options).await?;
+    actual = hash_file(&part).await?;
+  }
+
+  if actual != expected_sha256 {
+    let _ = fs::remove_file(&part).await;
+    return Err(ExecuteError::HashMismatch {
+      url: url.to_string(),
+      expected: expected_sha256.to_string(),
+      actual,
+    });
+  }
+
+  fs::rename(&part, &path).await?;
+  Ok(path)
+}
+
+/// Take the exclusive lock at `path`, waiting while another fetch holds it.
+///
+/// The lock is released when the returned file is closed.
+async fn lock_key(path: &Path) -> io::Result<std::fs::File> {
+  let file = std::fs::OpenOptions::new()
+    .read(true)
+    .write(true)
+    .create(true)
+    .truncate(false)
+    .open(path)?;
+  let mut waited = false;
+  loop {
+    match try_lock(&file, LockMode::Exclusive) {
+      Ok(()) => return Ok(file),
+      Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+        if !waited {
+          debug!(path = ?path, "waiting for another fetch of the same download");
+          waited = true;
+        }
+        tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+      }
+      Err(e) => return Err(e),
+    }
+  }
+}
+
+/// Client for downloads, with [`CONNECT_TIMEOUT`] and [`READ_TIMEOUT`].
+fn client(url: &str) -> Result<reqwest::Client, ExecuteError> {
+  reqwest::Client::builder()
+    .connect_timeout(CONNECT_TIMEOUT)
+    .read_timeout(READ_TIMEOUT)
+    .build()
+    .map_err(|e| fetch_failed(url, e))
+}
+
+/// Download `url` into `part`, continuing from its current length.
+///
+/// A fresh download of a large file is fetched as parallel ranges if the
+/// server supports them. Returns whether existing bytes were kept. Bytes
+/// received before an error are kept too, so the next attempt can resume.
+async fn download(url: &str, part: &Path, options: &DownloadOptions) -> Result<bool, ExecuteError> {
+  let client = client(url)?;
+  let offset = fs::metadata(part).await.map(|m| m.len()).unwrap_or(0);
+
+  if offset == 0
+    && let Some(size) = ranged_size(&client, url).await
+    && size >= CHUNKED_MIN_SIZE
+  {
+    return download_chunked(&client, url, part, size, options).await;
+  }
+  // Ranges of a parallel download the server no longer supports
+  remove_chunks(part).await;
+
+  let mut request = client.get(url);
+  if offset > 0 {
+    debug!(url = %url, offset, "resuming download");
+    request = request.header(RANGE, format!("bytes={}-", offset));
+  }
+
+  let mut response = request.send().await.map_err(|e| fetch_failed(url, e))?;
+  let status = response.status();
+
+  // Nothing left to fetch: the partial file already has every byte
+  if offset > 0 && status == StatusCode::RANGE_NOT_SATISFIABLE {
+    return Ok(true);
+  }
+  if !status.is_success() {
+    return Err(fetch_failed(url, format!("HTTP {}", status)));
+  }
+
+  // A server that ignores the range sends the whole file again
+  let resumed = offset > 0 && status == StatusCode::PARTIAL_CONTENT;
+  let kept = if resumed { offset } else { 0 };
+  let mut file = if resumed {
+    OpenOptions::new().append(true).open(part).await?
+  } else {
+    fs::File::create(part).await?
+  };
+
+  let meter = Meter::new(url, response.content_length().map(|len| len + kept), options);
+  meter.add(kept);
+  let result = receive(&mut response, &mut file, &meter, &options.rate).await;
+  file.flush().await?;
+  meter.finish();
+
+  let written = result?;
+  info!(url = %url, bytes = written, resumed, "download finished");
+  Ok(resumed)
+}
+
+/// Size of `url` if its server accepts byte ranges for it.
+async fn ranged_size(client: &reqwest::Client, url: &str) -> Option<u64> {
+  let response = client.head(url).send().await.ok()?;
+  let headers = response.headers();
+  let accepts_ranges = headers
+    .get(ACCEPT_RANGES)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| value.eq_ignore_ascii_case("bytes"));
+  if !response.status().is_success() || !accepts_ranges {
+    return None;
+  }
+  headers.get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok()
+}
+
+/// Download the `size` bytes of `url` into `part` as [`CHUNKS`] parallel ranges.
+///
+/// Each range is written to its own file, resumed from its length, and the
+/// files are joined into `part` once all of them are complete. Returns whether
+/// existing bytes were kept.
+async fn download_chunked(
+  client: &reqwest::Client,
+  url: &str,
+  part: &Path,
+  size: u64,
+  options: &DownloadOptions,
+) -> Result<bool, ExecuteError> {
+  let meter = Arc::new(Meter::new(url, Some(size), options));
+  let chunk_len = size.div_ceil(CHUNKS);
+  let ranges: Vec<Range<u64>> = (0..CHUNKS)
+    .map(|i| i * chunk_len..((i + 1) * chunk_len).min(size))
+    .filter(|range| !range.is_empty())
+    .collect();
+  debug!(url = %url, size, ranges = ranges.len(), "downloading ranges in parallel");
+
+  // Dropping the set on error cancels the other ranges; their bytes are kept
+  let mut tasks = JoinSet::new();
+  for (i, range) in ranges.iter().enumerate() {
+    tasks.spawn(download_range(
+      client.clone(),
+      url.to_string(),
+      chunk_path(part, i),
+      range.clone(),
+      meter.clone(),
+      options.rate.clone(),
+    ));
+  }
+  let mut resumed = false;
+  while let Some(result) = tasks.join_next().await {
+    resumed |= result.map_err(|e| fetch_failed(url, e))??;
+  }
+  meter.finish();
+
+  let mut file = fs::File::create(part).await?;
+  for i in 0..ranges.len() {
+    let mut chunk = fs::File::open(chunk_path(part, i)).await?;
+    tokio::io::copy(&mut chunk, &mut file).await?;
+  }
+  file.flush().await?;
+  remove_chunks(part).await;
+
+  info!(url = %url, bytes = size, resumed, "download finished");
+  Ok(resumed)
+}
+
+/// Download `range` of `url` into `path`, continuing from its current length.
+///
+/// Returns whether existing bytes were kept.
+async fn download_range(
+  client: reqwest::Client,
+  url: String,
+  path: PathBuf,
+  range: Range<u64>,
+  meter: Arc<Meter>,
+  rate: RateLimit,
+) -> Result<bool, ExecuteError> {
+  let expected = range.end - range.start;
+  let mut offset = fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
+  if offset > expected {
+    fs::remove_file(&path).await?;
+    offset = 0;
+  }
+  meter.add(offset);
+  if offset == expected {
+    return Ok(true);
+  }
+
+  let mut response = client
+    .get(&url)
+    .header(RANGE, format!("bytes={}-{}", range.start + offset, range.end - 1))
+    .send()
+    .await
+    .map_err(|e| fetch_failed(&url, e))?;
+  if response.status() != StatusCode::PARTIAL_CONTENT {
+    return Err(fetch_failed(
+      &url,
+      format!("HTTP {} for a byte range", response.status()),
+    ));
+  }
+
+  let mut file = OpenOptions::new().create(true).append(true).open(&path).await?;
+  let result = receive(&mut response, &mut file, &meter, &rate).await;
+  file.flush().await?;
+
+  let written = result?;
+  if offset + written != expected {
+    return Err(fetch_failed(
+      &url,
+      format!(
+        "range {}-{} ended after {} bytes",
+        range.start,
+        range.end - 1,
+        offset + written
+      ),
+    ));
+  }
+  Ok(offset > 0)
+}
+
+/// Write the body of `response` to `file` within the rate limit.
+///
+/// Returns the number of bytes written.
+async fn receive(
+  response: &mut reqwest::Response,
+  file: &mut fs::File,
+  meter: &Meter,
+  rate: &RateLimit,
+) -> Result<u64, ExecuteError> {
+  let mut written = 0u64;
+  loop {
+    match response.chunk().await {
+      Ok(Some(chunk)) => {
+        rate.acquire(chunk.len() as u64).await;
+        file.write_all(&chunk).await?;
+        written += chunk.len() as u64;
+        meter.add(chunk.len() as u64);
+      }
+      Ok(None) => return Ok(written),
+      Err(e) => return Err(fetch_failed(&meter.url, e)),
+    }
+  }
+}
+
+/// File holding range `index` of the parallel download into `part`.
+fn chunk_path(part: &Path, index: usize) -> PathBuf {
+  let mut name = part.as_os_str().to_owned();
+  name.push(format!(".{}", index));
+  PathBuf::from(name)
+}
+
+/// Remove the range files of a parallel download into `part`.
+async fn remove_chunks(part: &Path) {
+  for i in 0..CHUNKS as usize {
+    let _ = fs::remove_file(chunk_path(part, i)).await;
+  }
+}
+
+fn fetch_failed(url: &str, message: impl ToString) -> ExecuteError {
+  ExecuteError::FetchFailed {
+    url: url.to_string(),
+    message: message.to_string(),
+  }
+}
+
+/// Progress of one download, shared by its ranges.
+struct Meter {
+  url: String,
+  total: Option<u64>,
+  received: AtomicU64,
+  last_report: Mutex<Instant>,
+  progress: Option<(ProgressSender, ObjectHash)>,
+}
+
+impl Meter {
+  fn new(url: &str, total: Option<u64>, options: &DownloadOptions) -> Self {
+    Self {
+      url: url.to_string(),
+      total,
+      received: AtomicU64::new(0),
+      last_report: Mutex::new(Instant::now()),
+      progress: options.progress.clone(),
+    }
+  }
+
+  /// Count `bytes` more received, reporting at most every [`PROGRESS_INTERVAL`].
+  fn add(&self, bytes: u64) {
+    let received = self.received.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    if self.progress.is_none() {
+      return;
+    }
+    {
+      let mut last_report = self.last_report.lock().expect("download meter lock poisoned");
+      if last_report.elapsed() < PROGRESS_INTERVAL {
+        return;
+      }
+      *last_report = Instant::now();
+    }
+    self.report(received);
+  }
+
+  /// Report the final count.
+  fn finish(&self) {
+    self.report(self.received.load(Ordering::Relaxed));
+  }
+
+  fn report(&self, received: u64) {
+    if let Some((sender, hash)) = &self.progress {
+      sender.send(ProgressEvent::Download {
+        hash: hash.clone(),
+        url: self.url.clone(),
+        received,
+        total: self.total,
+      });
+    }
+  }
+}
+
+/// Compute the SHA256 of a file (lowercase hex).
+pub(crate) async fn hash_file(path: &Path) -> Result<String, io::Error> {
+  let mut file = fs::File::open(path).await?;
+  let mut hasher = Sha256::new();
+  let mut buf = vec![0u8; 64 * 1024];
+
+  loop {
+    let read = file.read(&mut buf).await?;
+    if read == 0 {
+      break;
+    }
+    hasher.update(&buf[..read]);
+  }
+
+  Ok(hex::encode(hasher.finalize()))
+}
+
+/// Mark a cache entry as recently used.
+fn touch(path: &Path) {
+  let result = std::fs::File::options()
+    .write(true)
+    .open(path)
+    .and_then(|file| file.set_modified(SystemTime::now()));
+  if let Err(e) = result {
+    debug!(path = ?path, error = %e, "failed to refresh cached download time");
+  }
+}
+
+/// Find cached and partial downloads in `dir` unused for longer than `max_age`.
+///
+/// Returns each stale file with its size, removing them unless `dry_run`.
+pub fn sweep_stale(dir: &Path, max_age: Duration, dry_run: bool) -> io::Result<Vec<(PathBuf, u64)>> {
+  let now = SystemTime::now();
+  let mut removed = Vec::new();
+
+  for entry in std::fs::read_dir(dir)?.flatten() {
+    let Ok(metadata) = entry.metadata() else {
+      continue;
+    };
+    if !metadata.is_file() {
+      continue;
+    }
+    let age = metadata
+      .modified()
+      .ok()
+      .and_then(|modified| now.duration_since(modified).ok())
+      .unwrap_or_default();
+    if age <= max_age {
+      continue;
+    }
+
+    let path = entry.path();
+    if !dry_run && let Err(e) = std::fs::remove_file(&path) {
+      warn!(path = %path.display(), error = %e, "failed to delete cached download");
+      continue;
+    }
+    removed.push((path, metadata.len()));
+  }
+
+  Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serial_test::serial;
+  use tempfile::TempDir;
+
+  fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+  }
+
+  #[test]
+  fn cache_key_depends_on_url_and_hash() {
+    let key = cache_key("https://example.com/a.tar.gz", "abc");
+    assert_eq!(key.len(), 32);
+    assert_eq!(key, cache_key("https://example.com/a.tar.gz", "abc"));
+    assert_ne!(key, cache_key("https://example.com/b.tar.gz", "abc"));
+    assert_ne!(key, cache_key("https://example.com/a.tar.gz", "abd"));
+  }
+
+  #[test]
+  #[serial]
+  fn concurrent_fetches_of_a_download_wait_for_each_other() {
+    let temp = TempDir::new().unwrap();
+    temp_env::with_var("XDG_CACHE_HOME", Some(temp.path()), || {
+      let body: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+      let hash = sha256_hex(&body);
+      let (url, _) = serve_ranges(body.clone());
+      let entry = downloads_dir().join(cache_key(&url, &hash));
+
+      let rt = tokio::runtime::Runtime::new().unwrap();
+      let options = DownloadOptions::default();
+      let (a, b) =
+        rt.block_on(async { tokio::join!(fetch_cached(&url, &hash, &options), fetch_cached(&url, &hash, &options)) });
+      assert_eq!(a.unwrap(), entry);
+      assert_eq!(b.unwrap(), entry);
+      assert_eq!(std::fs::read(&entry).unwrap(), body);
+
+      // A corrupt entry is discarded and fetched again
+      std::fs::write(&entry, b"corrupt").unwrap();
+      assert_eq!(rt.block_on(fetch_cached(&url, &hash, &options)).unwrap(), entry);
+      assert_eq!(std::fs::read(&entry).unwrap(), body);
+    });
+  }
+
+  /// Serve `body` with byte range support until the test ends and return the URL.
+  ///
+  /// Counts the ranged GET requests served.
+  fn serve_ranges(body: Vec<u8>) -> (String, Arc<AtomicU64>) {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let ranged = Arc::new(AtomicU64::new(0));
+    let counter = ranged.clone();
+    let body = Arc::new(body);
+    std::thread::spawn(move || {
+      for mut stream in listener.incoming().flatten() {
+        let body = body.clone();
+        let counter = counter.clone();
+        std::thread::spawn(move || {
+          let mut request = Vec::new();
+          let mut buf = [0u8; 1024];
+          while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            match stream.read(&mut buf) {
+              Ok(0) | Err(_) => return,
+              Ok(n) => request.extend_from_slice(&buf[..n]),
+            }
+          }
+          let request = String::from_utf8_lossy(&request).to_lowercase();
+          let range = request.lines().find_map(|line| {
+            let (start, end) = line.strip_prefix("range: bytes=")?.split_once('-')?;
+            Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?))
+          });
+
+          let (status, content) = match range {
+            Some((start, end)) => {
+              counter.fetch_add(1, Ordering::SeqCst);
+              ("206 Partial Content", &body[start..=end])
+            }
+            None => ("200 OK", &body[..]),
+          };
+          let header = format!(
+            "HTTP/1.1 {}\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            content.len()
+          );
+          let _ = stream.write_all(header.as_bytes());
+          if !request.starts_with("head") {
+            let _ = stream.write_all(content);
+          }
+        });
+      }
+    });
+    (format!("http://{}/toolchain.tar.gz", addr), ranged)
+  }
+
+  #[tokio::test]
+  async fn chunked_download_joins_ranges_and_reports_progress() {
+    let temp = TempDir::new().unwrap();
+    let body: Vec<u8> = (0..100_003u32).map(|i| (i % 251) as u8).collect();
+    let (url, ranged) = serve_ranges(body.clone());
+    let part = temp.path().join("key.part");
+
+    // One range was partly downloaded by an interrupted attempt
+    let chunk_len = (body.len() as u64).div_ceil(CHUNKS) as usize;
+    std::fs::write(chunk_path(&part, 1), &body[chunk_len..chunk_len + 100]).unwrap();
+
+    let (sender, mut events) = crate::execute::progress::progress_channel();
+    let options = DownloadOptions {
+      rate: RateLimit::NONE,
+      progress: Some((sender, ObjectHash("abc".to_string()))),
+    };
+    let client = reqwest::Client::new();
+    let size = ranged_size(&client, &url).await.unwrap();
+    assert_eq!(size, body.len() as u64);
+
+    let resumed = download_chunked(&client, &url, &part, size, &options).await.unwrap();
+    assert!(resumed);
+    assert_eq!(std::fs::read(&part).unwrap(), body);
+    assert_eq!(ranged.load(Ordering::SeqCst), CHUNKS);
+    assert!(!chunk_path(&part, 0).exists());
+    assert!(!chunk_path(&part, 1).exists());
+
+    let mut last = None;
+    while let Ok(event) = events.try_recv() {
+      last = Some(event);
+    }
+    assert_eq!(
+      last,
+      Some(ProgressEvent::Download {
+        hash: ObjectHash("abc".to_string()),
+        url,
+        received: body.len() as u64,
+        total: Some(body.len() as u64),
+      })
+    );
+  }
+
+  #[test]
+  fn sweep_removes_only_stale_files() {
+    let temp = TempDir::new().unwrap();
+    let fresh = temp.path().join("fresh");
+    let stale = temp.path().join("stale.part");
+    std::fs::write(&fresh, b"new").unwrap();
+    std::fs::write(&stale, b"old").unwrap();
+    let old = SystemTime::now() - MAX_UNUSED_AGE - Duration::from_secs(60);
+    std::fs::File::options()
+      .write(true)
+      .open(&stale)
+      .unwrap()
+      .set_modified(old)
+      .unwrap();
+
+    let found = sweep_stale(temp.path(), MAX_UNUSED_AGE, true).unwrap();
+    assert_eq!(found, vec![(stale.clone(), 3)]);
+    assert!(stale.exists());
+
+    sweep_stale(temp.path(), MAX_UNUSED_AGE, false).unwrap();
+    assert!(!stale.exists());
+    assert!(fresh.exists());
+  }
+}
@@ -5,13 +5,23 @@
 
 use std::collections::BTreeMap;
 use std::path::Path;
+use std::process::Stdio;
 
 use mlua::prelude::*;
 use serde::{Deserialize, Serialize};
 use tokio::process::Command;
-use tracing::{debug, info};
-
-use crate::execute::types::ExecuteError;
+use tracing::{debug, info, warn};
+
+use super::download_cache::DownloadOptions;
+use crate::action::{DEFAULT_SHELL_REGISTRY_KEY, RUN_AS_USERS_REGISTRY_KEY};
+use crate::execute::limits::ActionLimits;
+use crate::execute::transcript::TranscriptScope;
+use crate::execute::types::{ExecuteError, NodeTiming};
+use crate::platform::Shell;
+use crate::platform::cgroup::Cgroup;
+use crate::platform::network::DenyProxy;
+use crate::platform::priority;
+use crate::platform::run_as::RunAs;
 
 /// Options for executing a shell command in a build.
 ///
@@ -42,6 +52,28 @@ pub struct ExecOpts {
   pub env: Option<BTreeMap<String, String>>,
   /// Optional working directory.
   pub cwd: Option<String>,
+  /// Skip execution if this path already exists.
+  ///
+  /// A relative path is relative to the command's working directory: `cwd`,
+  /// or the output directory when it is unset.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub creates: Option<String>,
+  /// Skip execution if this probe command exits successfully.
+  ///
+  /// The probe runs through `shell`, or `/bin/sh` on Unix and `cmd.exe` on
+  /// Windows when it is unset, with the same env and cwd as the command.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub unless: Option<String>,
+  /// Run `bin` as a command line through this shell.
+  ///
+  /// Arguments are quoted for the shell and appended to `bin`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub shell: Option<Shell>,
+  /// Run the command (and its `unless` probe) as this user. Binds only.
+  ///
+  /// The user must be allowed by the config's `settings.run_as`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub run_as: Option<String>,
 }
 
 impl ExecOpts {
@@ -52,6 +84,10 @@ impl ExecOpts {
       args: None,
       env: None,
       cwd: None,
+      creates: None,
+      unless: None,
+      shell: None,
+      run_as: None,
     }
   }
 
@@ -72,6 +108,45 @@ impl ExecOpts {
     self.cwd = Some(cwd.to_string());
     self
   }
+
+  /// Skip the command if the given path already exists.
+  pub fn with_creates(mut self, path: &str) -> Self {
+    self.creates = Some(path.to_string());
+    self
+  }
+
+  /// Skip the command if the given probe command succeeds.
+  pub fn with_unless(mut self, probe: &str) -> Self {
+    self.unless = Some(probe.to_string());
+    self
+  }
+
+  /// Run the command through the given shell.
+  pub fn with_shell(mut self, shell: Shell) -> Self {
+    self.shell = Some(shell);
+    self
+  }
+
+  /// Run the command as the given user.
+  pub fn with_run_as(mut self, user: &str) -> Self {
+    self.run_as = Some(user.to_string());
+    self
+  }
+
+  /// Returns the binary and arguments to spawn for this command.
+  ///
+  /// Without a shell this is `bin` and `args` unchanged. With a shell, `bin`
+  /// and the quoted `args` are joined into one command line for that shell.
+  pub fn invocation(bin: &str, args: Option<&Vec<String>>, shell: Option<Shell>) -> (String, Option<Vec<String>>) {
+    match shell {
+      None => (bin.to_string(), args.cloned()),
+      Some(shell) => {
+        let line = shell.command_line(bin, args.map(Vec::as_slice).unwrap_or_default());
+        let (shell_bin, shell_args) = shell.invocation(&line);
+        (shell_bin.to_string(), Some(shell_args))
+      }
+    }
+  }
 }
 
 impl From<&str> for ExecOpts {
@@ -80,7 +155,76 @@ impl From<&str> for ExecOpts {
   }
 }
 
-pub fn parse_exec_opts(opts: LuaValue, args: Option<LuaValue>) -> LuaResult<ExecOpts> {
+/// Per-node settings of the actions a build or bind runs: isolation of the
+/// commands a build spawns, throttling of downloads, and where commands are
+/// recorded. Binds only set the limits and the transcript.
+#[derive(Debug, Default)]
+pub struct ExecIsolation {
+  /// Cgroup enforcing the build's resource limits.
+  pub cgroup: Option<Cgroup>,
+  /// Proxy refusing network access, when builds run network-isolated.
+  pub network: Option<DenyProxy>,
+  /// User commands without a `run_as` of their own run as, when builds run
+  /// as a build user.
+  pub user: Option<RunAs>,
+  /// Rate limit and progress reporting of the build's `fetch_url` downloads.
+  pub downloads: DownloadOptions,
+  /// Concurrency limits per action type, shared with the other builds and
+  /// binds of the apply.
+  pub limits: ActionLimits,
+  /// Where to record the commands the actions run.
+  pub transcript: Option<TranscriptScope>,
+}
+
+/// Parse the `shell` field of exec options.
+///
+/// `true` selects the config default shell (`settings.shell`), falling back to
+/// the platform default. A string names a specific shell. `false`/nil disables it.
+fn parse_shell(lua: &Lua, value: LuaValue) -> LuaResult<Option<Shell>> {
+  match value {
+    LuaValue::Nil | LuaValue::Boolean(false) => Ok(None),
+    LuaValue::Boolean(true) => {
+      let default: Option<String> = lua.named_registry_value(DEFAULT_SHELL_REGISTRY_KEY)?;
+      match default {
+        Some(name) => name.parse().map(Some).map_err(LuaError::external),
+        None => Ok(Some(Shell::platform_default())),
+      }
+    }
+    LuaValue::String(s) => s.to_str()?.parse().map(Some).map_err(LuaError::external),
+    _ => Err(LuaError::external("exec 'shell' expects a boolean or a shell name")),
+  }
+}
+
+/// Parse the `run_as` and `elevate` fields of exec options.
+///
+/// `elevate = true` is `run_as = "root"`. The user must be listed in the
+/// config's `settings.run_as`.
+fn parse_run_as(lua: &Lua, table: &LuaTable) -> LuaResult<Option<String>> {
+  let run_as: Option<String> = table
+    .get("run_as")
+    .map_err(|_| LuaError::external("exec 'run_as' expects a user name"))?;
+  let elevate: bool = table
+    .get::<Option<bool>>("elevate")
+    .map_err(|_| LuaError::external("exec 'elevate' expects a boolean"))?
+    .unwrap_or(false);
+  let user = match (run_as, elevate) {
+    (Some(_), true) => return Err(LuaError::external("exec accepts 'run_as' or 'elevate', not both")),
+    (Some(user), false) => user,
+    (None, true) => "root".to_string(),
+    (None, false) => return Ok(None),
+  };
+
+  let allowed: Option<Vec<String>> = lua.named_registry_value(RUN_AS_USERS_REGISTRY_KEY)?;
+  if !allowed.unwrap_or_default().contains(&user) {
+    return Err(LuaError::external(format!(
+      "exec may not run as '{}': add it to settings.run_as to allow it",
+      user
+    )));
+  }
+  Ok(Some(user))
+}
+
+pub fn parse_exec_opts(lua: &Lua, opts: LuaValue, args: Option<LuaValue>) -> LuaResult<ExecOpts> {
   let mut exec_opts = match opts {
     LuaValue::String(s) => {
       let cmd = s.to_str()?.to_string();
@@ -91,6 +235,10 @@ pub fn parse_exec_opts(opts: LuaValue, args: Option<LuaValue>) -> LuaResult<Exec
       let args: Option<Vec<String>> = table.get("args")?;
       let cwd: Option<String> = table.get("cwd")?;
       let env: Option<LuaTable> = table.get("env")?;
+      let creates: Option<String> = table.get("creates")?;
+      let unless: Option<String> = table.get("unless")?;
+      let shell = parse_shell(lua, table.get("shell")?)?;
+      let run_as = parse_run_as(lua, &table)?;
 
       let mut opts = ExecOpts::new(&bin);
 
@@ -112,6 +260,22 @@ pub fn parse_exec_opts(opts: LuaValue, args: Option<LuaValue>) -> LuaResult<Exec
         }
         opts = opts.with_env(env_map);
       }
+
+      if let Some(creates) = creates {
+        opts = opts.with_creates(&creates);
+      }
+
+      if let Some(unless) = unless {
+        opts = opts.with_unless(&unless);
+      }
+
+      if let Some(shell) = shell {
+        opts = opts.with_shell(shell);
+      }
+
+      if let Some(user) = run_as {
+        opts = opts.with_run_as(&user);
+      }
       Ok(opts)
     }
     _ => Err(LuaError::external("cmd() expects a string or table with 'cmd' field")),
@@ -147,11 +311,19 @@ pub fn parse_exec_opts(opts: LuaValue, args: Option<LuaValue>) -> LuaResult<Exec
 /// - Sets TMPDIR/TMP/TEMP/TEMPDIR to a temp directory within out_dir
 /// - Sets `out` to the output directory
 /// - Merges user-specified environment variables
+/// - With network isolation, points all proxy variables at a deny proxy
+///   (these override user-specified values)
+/// - Lowers the process priority while an apply is throttled
+/// - With `run_as`, runs as that user (see [`crate::platform::run_as`]); the
+///   temp variables then point at a private directory that user owns, removed
+///   once the command has finished
 ///
 /// # Arguments
 ///
 /// * `opts` - The command options to execute
 /// * `out_dir` - The build's output directory
+/// * `isolation` - Optional cgroup and network isolation for the spawned process
+/// * `run_as` - Optional user to run the command as
 ///
 /// # Returns
 ///
@@ -162,19 +334,41 @@ pub async fn execute_cmd(
   env: Option<&BTreeMap<String, String>>,
   cwd: Option<&str>,
   out_dir: &Path,
+  isolation: Option<&ExecIsolation>,
+  run_as: Option<&RunAs>,
 ) -> Result<String, ExecuteError> {
-  info!(cmd = %cmd, "executing command");
-
-  // Create temp directory for the build
-  let tmp_dir = out_dir.join("tmp");
-  tokio::fs::create_dir_all(&tmp_dir).await?;
+  info!(cmd = %cmd, user = run_as.map(|r| r.user.as_str()), "executing command");
+
+  // Create temp directory for the build; a command run as another user gets
+  // a private one that user owns
+  let tmp_dir = match run_as {
+    Some(run_as) => run_as.create_tmp_dir().await.map_err(|e| ExecuteError::RunAs {
+      user: run_as.user.clone(),
+      message: e.to_string(),
+    })?,
+    None => {
+      let tmp_dir = out_dir.join("tmp");
+      tokio::fs::create_dir_all(&tmp_dir).await?;
+      tmp_dir
+    }
+  };
 
   let working_dir = cwd.map(Path::new).unwrap_or(out_dir);
 
   // Build the command with isolated environment
   let mut command = Command::new(cmd);
+  match args.map(Vec::as_slice).unwrap_or_default() {
+    // cmd.exe splits its command line itself rather than by the rules other
+    // arguments are quoted with, so its script goes through as written
+    #[cfg(windows)]
+    [flags @ .., script] if cmd == Shell::Cmd.bin() && flags.last().is_some_and(|flag| flag == "/C") => {
+      command.args(flags).raw_arg(script);
+    }
+    args => {
+      command.args(args);
+    }
+  }
   command
-    .args(args.unwrap_or(&Vec::new()))
     .current_dir(working_dir)
     // Clear all environment variables
     .env_clear();
@@ -222,9 +416,56 @@ pub async fn execute_cmd(
     }
   }
 
-  debug!(cmd = %cmd,  working_dir = ?working_dir, "spawning process");
+  let proxy = isolation.and_then(|i| i.network.as_ref());
+  if let Some(proxy) = proxy {
+    command.envs(proxy.env());
+  }
 
-  let output = command.output().await?;
+  let output = async {
+    if let Some(run_as) = run_as {
+      command = run_as.apply(command).map_err(|e| ExecuteError::RunAs {
+        user: run_as.user.clone(),
+        message: e.to_string(),
+      })?;
+    }
+
+    priority::current().apply_to(&mut command);
+
+    if let Some(cgroup) = isolation.and_then(|i| i.cgroup.as_ref())
+      && let Err(e) = cgroup.enter_on_spawn(&mut command)
+    {
+      warn!(cmd = %cmd, error = %e, "failed to run process in cgroup");
+    }
+
+    debug!(cmd = %cmd,  working_dir = ?working_dir, "spawning process");
+
+    command
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped());
+    let child = command.spawn()?;
+
+    Ok::<_, ExecuteError>(child.wait_with_output().await?)
+  }
+  .await;
+
+  if let Some(run_as) = run_as
+    && let Err(e) = run_as.remove_tmp_dir(&tmp_dir).await
+  {
+    warn!(cmd = %cmd, error = %e, "failed to remove temp directory");
+  }
+  let output = output?;
+
+  // A denied download usually also fails the command; report the cause instead
+  if let Some(proxy) = proxy {
+    let targets = proxy.take_attempts();
+    if !targets.is_empty() {
+      return Err(ExecuteError::NetworkDenied {
+        cmd: cmd.to_string(),
+        targets,
+      });
+    }
+  }
 
   if !output.status.success() {
     let stderr = String::from_utf8_lossy(&output.stderr);
@@ -253,6 +494,56 @@ pub async fn execute_cmd(
   Ok(stdout)
 }
 
+/// Run an `unless` probe command.
+///
+/// The probe runs like the command it guards: through [`execute_cmd`] with the
+/// same isolation (cgroup, network), through the given shell (or
+/// [`Shell::probe_default`]), as the command's user. It is recorded in the
+/// isolation's transcript, if any. Returns `true` if the probe exited
+/// successfully, meaning the guarded command should be skipped.
+pub async fn run_unless_probe(
+  probe: &str,
+  shell: Option<Shell>,
+  env: Option<&BTreeMap<String, String>>,
+  cwd: Option<&str>,
+  out_dir: &Path,
+  isolation: Option<&ExecIsolation>,
+  run_as: Option<&RunAs>,
+) -> Result<bool, ExecuteError> {
+  let (shell_bin, args) = shell.unwrap_or_else(Shell::probe_default).invocation(probe);
+  let (result, timing) = NodeTiming::measure(execute_cmd(
+    shell_bin,
+    Some(&args),
+    env,
+    cwd,
+    out_dir,
+    isolation,
+    run_as,
+  ))
+  .await;
+
+  if let Some(transcript) = isolation.and_then(|i| i.transcript.as_ref()) {
+    transcript.record_probe(
+      std::iter::once(shell_bin.to_string())
+        .chain(args.iter().cloned())
+        .collect(),
+      env.cloned().unwrap_or_default(),
+      cwd
+        .map(str::to_string)
+        .unwrap_or_else(|| out_dir.to_string_lossy().to_string()),
+      run_as.map(|r| r.user.clone()),
+      timing,
+      result.as_ref().map(|_| ()),
+    );
+  }
+
+  match result {
+    Ok(_) => Ok(true),
+    Err(ExecuteError::CmdFailed { .. }) => Ok(false),
+    Err(e) => Err(e),
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -265,7 +556,9 @@ mod tests {
     let out_dir = temp_dir.path();
 
     let (cmd, args) = echo_msg("hello");
-    let result = execute_cmd(cmd, Some(&args), None, None, out_dir).await.unwrap();
+    let result = execute_cmd(cmd, Some(&args), None, None, out_dir, None, None)
+      .await
+      .unwrap();
 
     assert_eq!(result, "hello");
   }
@@ -279,7 +572,9 @@ mod tests {
     env.insert("MY_VAR".to_string(), "my_value".to_string());
 
     let (cmd, args) = shell_echo_env("MY_VAR");
-    let result = execute_cmd(cmd, Some(&args), Some(&env), None, out_dir).await.unwrap();
+    let result = execute_cmd(cmd, Some(&args), Some(&env), None, out_dir, None, None)
+      .await
+      .unwrap();
 
     assert_eq!(result, "my_value");
   }
@@ -290,7 +585,9 @@ mod tests {
     let out_dir = temp_dir.path();
 
     let (cmd, args) = shell_echo_env("out");
-    let result = execute_cmd(cmd, Some(&args), None, None, out_dir).await.unwrap();
+    let result = execute_cmd(cmd, Some(&args), None, None, out_dir, None, None)
+      .await
+      .unwrap();
 
     assert_eq!(result, out_dir.to_string_lossy());
   }
@@ -301,7 +598,9 @@ mod tests {
     let out_dir = temp_dir.path();
 
     let (cmd, args) = shell_echo_env("PATH");
-    let result = execute_cmd(cmd, Some(&args), None, None, out_dir).await.unwrap();
+    let result = execute_cmd(cmd, Some(&args), None, None, out_dir, None, None)
+      .await
+      .unwrap();
 
     #[cfg(unix)]
     assert_eq!(result, "/path-not-set");
@@ -312,6 +611,59 @@ mod tests {
     }
   }
 
+  #[tokio::test]
+  async fn execute_command_network_isolated_proxy_env() {
+    let temp_dir = TempDir::new().unwrap();
+    let out_dir = temp_dir.path();
+    let isolation = ExecIsolation {
+      network: Some(DenyProxy::start().await.unwrap()),
+      ..Default::default()
+    };
+
+    // User env can't route around the deny proxy
+    let mut env = BTreeMap::new();
+    env.insert("https_proxy".to_string(), "http://proxy.example.com".to_string());
+
+    let (cmd, args) = shell_echo_env("https_proxy");
+    let result = execute_cmd(cmd, Some(&args), Some(&env), None, out_dir, Some(&isolation), None)
+      .await
+      .unwrap();
+
+    assert_eq!(result, isolation.network.as_ref().unwrap().url());
+  }
+
+  #[tokio::test]
+  async fn execute_command_network_access_fails() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let temp_dir = TempDir::new().unwrap();
+    let out_dir = temp_dir.path();
+    let proxy = DenyProxy::start().await.unwrap();
+
+    // Stand in for a command downloading through the proxy
+    let addr = proxy.url().trim_start_matches("http://").to_string();
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+      .write_all(b"GET http://example.com/src.tar.gz HTTP/1.1\r\n\r\n")
+      .await
+      .unwrap();
+    stream.read_to_end(&mut Vec::new()).await.unwrap();
+
+    let isolation = ExecIsolation {
+      network: Some(proxy),
+      ..Default::default()
+    };
+    let (cmd, args) = echo_msg("hello");
+    let result = execute_cmd(cmd, Some(&args), None, None, out_dir, Some(&isolation), None).await;
+
+    match result {
+      Err(ExecuteError::NetworkDenied { targets, .. }) => {
+        assert_eq!(targets, vec!["http://example.com/src.tar.gz".to_string()]);
+      }
+      other => panic!("expected NetworkDenied, got {:?}", other),
+    }
+  }
+
   /// On Windows, critical system variables must be preserved for cmd.exe to function.
   #[tokio::test]
   #[cfg(windows)]
@@ -321,7 +673,9 @@ mod tests {
 
     // SystemRoot should be preserved for Windows to function properly
     let (cmd, args) = shell_echo_env("SystemRoot");
-    let result = execute_cmd(cmd, Some(&args), None, None, out_dir).await.unwrap();
+    let result = execute_cmd(cmd, Some(&args), None, None, out_dir, None, None)
+      .await
+      .unwrap();
 
     // SystemRoot is typically C:\Windows or similar
     assert!(!result.is_empty(), "SystemRoot should be preserved");
@@ -338,7 +692,9 @@ mod tests {
     let out_dir = temp_dir.path();
 
     let (cmd, args) = shell_echo_env("SOURCE_DATE_EPOCH");
-    let result = execute_cmd(cmd, Some(&args), None, None, out_dir).await.unwrap();
+    let result = execute_cmd(cmd, Some(&args), None, None, out_dir, None, None)
+      .await
+      .unwrap();
 
     assert_eq!(result, "315532800");
   }
@@ -349,7 +705,7 @@ mod tests {
     let out_dir = temp_dir.path();
 
     let (cmd, args) = shell_cmd("exit 1");
-    let result = execute_cmd(cmd, Some(&args), None, None, out_dir).await;
+    let result = execute_cmd(cmd, Some(&args), None, None, out_dir, None, None).await;
 
     assert!(matches!(result, Err(ExecuteError::CmdFailed { code: Some(1), .. })));
   }
@@ -365,9 +721,17 @@ mod tests {
 
     // Run a command that creates a marker file in the cwd
     let (cmd, args) = touch_file("cwd_marker");
-    execute_cmd(cmd, Some(&args), None, Some(sub_dir.to_str().unwrap()), out_dir)
-      .await
-      .unwrap();
+    execute_cmd(
+      cmd,
+      Some(&args),
+      None,
+      Some(sub_dir.to_str().unwrap()),
+      out_dir,
+      None,
+      None,
+    )
+    .await
+    .unwrap();
 
     // Verify the marker file was created in the subdirectory (proving cwd was set correctly)
     assert!(
@@ -382,7 +746,9 @@ mod tests {
     let out_dir = temp_dir.path();
 
     let (cmd, args) = shell_echo_env("TMPDIR");
-    execute_cmd(cmd, Some(&args), None, None, out_dir).await.unwrap();
+    execute_cmd(cmd, Some(&args), None, None, out_dir, None, None)
+      .await
+      .unwrap();
 
     // Verify tmp directory was created
     assert!(out_dir.join("tmp").exists());
@@ -401,7 +767,9 @@ mod tests {
     "#;
 
     let (cmd, args) = shell_cmd(script);
-    let result = execute_cmd(cmd, Some(&args), None, None, out_dir).await.unwrap();
+    let result = execute_cmd(cmd, Some(&args), None, None, out_dir, None, None)
+      .await
+      .unwrap();
 
     assert_eq!(result, "3");
   }
@@ -417,7 +785,9 @@ mod tests {
     let script = "echo first && echo 3";
 
     let (cmd, args) = shell_cmd(script);
-    let result = execute_cmd(cmd, Some(&args), None, None, out_dir).await.unwrap();
+    let result = execute_cmd(cmd, Some(&args), None, None, out_dir, None, None)
+      .await
+      .unwrap();
 
     // cmd.exe should execute both commands, output ends with "3"
     assert!(
@@ -426,4 +796,136 @@ mod tests {
       result
     );
   }
+
+  #[tokio::test]
+  async fn unless_probe_success_returns_true() {
+    let temp_dir = TempDir::new().unwrap();
+    let out_dir = temp_dir.path();
+
+    assert!(
+      run_unless_probe("exit 0", None, None, None, out_dir, None, None)
+        .await
+        .unwrap()
+    );
+  }
+
+  #[tokio::test]
+  async fn unless_probe_failure_returns_false() {
+    let temp_dir = TempDir::new().unwrap();
+    let out_dir = temp_dir.path();
+
+    assert!(
+      !run_unless_probe("exit 1", None, None, None, out_dir, None, None)
+        .await
+        .unwrap()
+    );
+  }
+
+  #[tokio::test]
+  async fn unless_probe_is_recorded_in_the_transcript() {
+    use crate::execute::history::NodeKind;
+    use crate::execute::transcript::{TranscriptOperation, TranscriptRecorder};
+    use crate::util::hash::ObjectHash;
+
+    let temp_dir = TempDir::new().unwrap();
+    let out_dir = temp_dir.path();
+    let recorder = TranscriptRecorder::new();
+    let isolation = ExecIsolation {
+      transcript: recorder.scope(
+        NodeKind::Bind,
+        &ObjectHash("abc123def45678901234".to_string()),
+        Some("probe"),
+        TranscriptOperation::Create,
+      ),
+      ..Default::default()
+    };
+
+    assert!(
+      !run_unless_probe("exit 3", None, None, None, out_dir, Some(&isolation), None)
+        .await
+        .unwrap()
+    );
+    let actions = recorder.take();
+    assert_eq!(actions.len(), 1);
+    assert!(actions[0].probe && actions[0].success());
+    assert_eq!(actions[0].exit_code, Some(3));
+    assert!(actions[0].command.iter().any(|arg| arg.contains("exit 3")));
+  }
+
+  #[test]
+  fn parse_exec_opts_reads_guards() {
+    let lua = Lua::new();
+    let table: LuaTable = lua
+      .load(r#"return { bin = "make", creates = "/tmp/marker", unless = "test -f /tmp/x" }"#)
+      .eval()
+      .unwrap();
+
+    let opts = parse_exec_opts(&lua, LuaValue::Table(table), None).unwrap();
+
+    assert_eq!(opts.creates.as_deref(), Some("/tmp/marker"));
+    assert_eq!(opts.unless.as_deref(), Some("test -f /tmp/x"));
+  }
+
+  #[test]
+  fn parse_exec_opts_reads_shell() {
+    let lua = Lua::new();
+
+    let table: LuaTable = lua
+      .load(r#"return { bin = "echo hi", shell = "bash" }"#)
+      .eval()
+      .unwrap();
+    let opts = parse_exec_opts(&lua, LuaValue::Table(table), None).unwrap();
+    assert_eq!(opts.shell, Some(Shell::Bash));
+
+    let table: LuaTable = lua.load(r#"return { bin = "echo hi", shell = true }"#).eval().unwrap();
+    let opts = parse_exec_opts(&lua, LuaValue::Table(table), None).unwrap();
+    assert_eq!(opts.shell, Some(Shell::platform_default()));
+
+    let table: LuaTable = lua.load(r#"return { bin = "echo hi", shell = "zsh" }"#).eval().unwrap();
+    assert!(parse_exec_opts(&lua, LuaValue::Table(table), None).is_err());
+  }
+
+  #[test]
+  fn parse_exec_opts_shell_true_uses_config_default() {
+    let lua = Lua::new();
+    lua.set_named_registry_value(DEFAULT_SHELL_REGISTRY_KEY, "cmd").unwrap();
+
+    let table: LuaTable = lua.load(r#"return { bin = "echo hi", shell = true }"#).eval().unwrap();
+    let opts = parse_exec_opts(&lua, LuaValue::Table(table), None).unwrap();
+    assert_eq!(opts.shell, Some(Shell::Cmd));
+  }
+
+  #[test]
+  fn parse_exec_opts_run_as_needs_allowed_user() {
+    let lua = Lua::new();
+    let opts = |code: &str| {
+      let table: LuaTable = lua.load(code).eval().unwrap();
+      parse_exec_opts(&lua, LuaValue::Table(table), None)
+    };
+
+    let err = opts(r#"return { bin = "psql", run_as = "postgres" }"#).unwrap_err();
+    assert!(err.to_string().contains("settings.run_as"), "{}", err);
+
+    lua
+      .set_named_registry_value(RUN_AS_USERS_REGISTRY_KEY, vec!["postgres", "root"])
+      .unwrap();
+    let parsed = opts(r#"return { bin = "psql", run_as = "postgres" }"#).unwrap();
+    assert_eq!(parsed.run_as.as_deref(), Some("postgres"));
+    let parsed = opts(r#"return { bin = "sysctl", elevate = true }"#).unwrap();
+    assert_eq!(parsed.run_as.as_deref(), Some("root"));
+    assert!(opts(r#"return { bin = "x", run_as = "postgres", elevate = true }"#).is_err());
+    assert!(opts(r#"return { bin = "x", run_as = "nobody" }"#).is_err());
+  }
+
+  #[test]
+  fn invocation_wraps_command_in_shell() {
+    let args = vec!["a b".to_string()];
+    let (bin, wrapped) = ExecOpts::invocation("echo", Some(&args), Some(Shell::Sh));
+    assert_eq!(bin, "/bin/sh");
+    assert_eq!(wrapped, Some(vec!["-c".to_string(), "echo 'a b'".to_string()]));
+
+    let (bin, unchanged) = ExecOpts::invocation("echo", Some(&args), None);
+    assert_eq!(bin, "echo");
+    assert_eq!(unchanged, Some(args));
+  }
 }
@@ -1,31 +1,40 @@
 //! FetchUrl action implementation.
 //!
 //! This module handles downloading files from URLs with SHA256 verification.
+//! Downloads go through the shared [download cache](super::download_cache).
 
 use std::path::{Path, PathBuf};
 
 use sha2::{Digest, Sha256};
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
 use tracing::{debug, info};
 
+use super::download_cache::{DownloadOptions, fetch_cached, hash_file};
 use crate::execute::types::ExecuteError;
+use crate::util::fs::copy_file;
 
 /// Execute a FetchUrl action.
 ///
-/// Downloads the file from the given URL to a temporary location within `out_dir`,
-/// verifies the SHA256 hash, and returns the path to the downloaded file.
+/// Fetches the file through the download cache (resuming an interrupted
+/// download), verifies the SHA256 hash, and copies it into `out_dir` (a
+/// copy-on-write clone where the filesystem supports it).
 ///
 /// # Arguments
 ///
 /// * `url` - The URL to download from
 /// * `expected_sha256` - The expected SHA256 hash (lowercase hex)
 /// * `out_dir` - The output directory for the build (file is stored in `out_dir/downloads/`)
+/// * `options` - Rate limit and progress reporting of the download
 ///
 /// # Returns
 ///
 /// The path to the downloaded file on success.
-pub async fn execute_fetch_url(url: &str, expected_sha256: &str, out_dir: &Path) -> Result<PathBuf, ExecuteError> {
+pub async fn execute_fetch_url(
+  url: &str,
+  expected_sha256: &str,
+  out_dir: &Path,
+  options: &DownloadOptions,
+) -> Result<PathBuf, ExecuteError> {
   info!(url = %url, "fetching URL");
 
   // Create downloads directory
@@ -48,63 +57,23 @@ pub async fn execute_fetch_url(url: &str, expected_sha256: &str, out_dir: &Path)
     }
   }
 
-  // Download the file
-  let response = reqwest::get(url).await.map_err(|e| ExecuteError::FetchFailed {
-    url: url.to_string(),
-    message: e.to_string(),
-  })?;
-
-  if !response.status().is_success() {
-    return Err(ExecuteError::FetchFailed {
-      url: url.to_string(),
-      message: format!("HTTP {}", response.status()),
-    });
-  }
-
-  let bytes = response.bytes().await.map_err(|e| ExecuteError::FetchFailed {
-    url: url.to_string(),
-    message: e.to_string(),
-  })?;
-
-  // Compute hash while writing
-  let actual_hash = {
-    let mut hasher = Sha256::new();
-    hasher.update(&bytes);
-    hex::encode(hasher.finalize())
-  };
-
-  // Verify hash before writing
-  if actual_hash != expected_sha256 {
-    return Err(ExecuteError::HashMismatch {
-      url: url.to_string(),
-      expected: expected_sha256.to_string(),
-      actual: actual_hash,
-    });
-  }
-
-  // Write to file
-  let mut file = fs::File::create(&dest_path).await?;
-  file.write_all(&bytes).await?;
-  file.flush().await?;
+  // Cloned where the filesystem supports it, so large downloads aren't copied twice
+  let cached = fetch_cached(url, expected_sha256, options).await?;
+  let target = dest_path.clone();
+  let size = tokio::task::spawn_blocking(move || copy_file(&cached, &target))
+    .await
+    .map_err(std::io::Error::other)??;
 
-  info!(path = ?dest_path, size = bytes.len(), "download complete");
+  info!(path = ?dest_path, size, "download complete");
 
   Ok(dest_path)
 }
 
-/// Compute SHA256 hash of a file.
-async fn hash_file(path: &Path) -> Result<String, std::io::Error> {
-  let bytes = fs::read(path).await?;
-  let mut hasher = Sha256::new();
-  hasher.update(&bytes);
-  Ok(hex::encode(hasher.finalize()))
-}
-
 /// Convert a URL to a safe filename.
 ///
 /// Takes the last path component and sanitizes it. Falls back to hash of URL
 /// if no suitable filename can be extracted.
-fn url_to_filename(url: &str) -> String {
+pub(crate) fn url_to_filename(url: &str) -> String {
   // Try to extract filename from URL path
   if let Some(filename) = url.rsplit('/').next() {
     // Remove query string
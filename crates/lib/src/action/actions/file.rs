@@ -0,0 +1,750 @@
+//! File action implementation (`write_file`, `symlink` and `copy`).
+//!
+//! Puts one path in place: a file with the given content, a symlink to a
+//! target, or a copy of a file or directory. The action may also give the
+//! path a mode, owner and group, instead of a `chmod`/`chown` command:
+//!
+//! ```lua
+//! ctx:write_file({ path = '/etc/app.conf', content = conf, mode = '0640', owner = 'root', group = 'app' })
+//! ```
+//!
+//! Owners and groups are names or numeric ids. A symlink has no mode of its
+//! own, only an owner and group. The owner and group of a copied directory
+//! are set on everything in it, its mode on the directory only.
+//!
+//! What the action set is returned as a [`ManagedFile`], which the bind keeps
+//! in its state: drift checks then report the path when its mode or
+//! ownership changed, and repairing the bind sets them again. Modes and
+//! owners are not supported on Windows.
+//!
+//! The new file, link or copy is staged next to the path with its mode and
+//! owner already set, then renamed over it, so the path never has partial
+//! content or the wrong permissions (a directory being replaced is removed
+//! first, which rename can't do). Rewritten content keeps the mode and owner
+//! of the file it replaces unless the action sets them.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::execute::types::ExecuteError;
+use crate::platform::paths::expand_path;
+use crate::util::fs::{copy_dir, copy_file};
+use crate::util::hash::{hash_directory, hash_file};
+
+/// Highest mode a file action sets: permissions plus the setuid, setgid and
+/// sticky bits.
+const MAX_MODE: u32 = 0o7777;
+
+/// What a file action does with its path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileState {
+  /// Put the file in place, replacing what differs.
+  #[default]
+  Present,
+  /// Remove the file, if it is there.
+  Absent,
+  /// Report whether the file is missing or differs, without writing.
+  Check,
+}
+
+impl FileState {
+  fn is_present(&self) -> bool {
+    *self == Self::Present
+  }
+}
+
+/// What a file action puts at its path.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSource {
+  /// A file with this content (`ctx:write_file`).
+  Content(String),
+  /// A symlink to this target (`ctx:symlink`).
+  Symlink(String),
+  /// A copy of this file or directory (`ctx:copy`, `~` is expanded).
+  Copy(String),
+}
+
+impl FileSource {
+  /// The ctx method recording the action.
+  pub fn method(&self) -> &'static str {
+    match self {
+      Self::Content(_) => "write_file",
+      Self::Symlink(_) => "symlink",
+      Self::Copy(_) => "copy",
+    }
+  }
+
+  /// The content, link target or copied path.
+  pub fn value(&self) -> &str {
+    match self {
+      Self::Content(value) | Self::Symlink(value) | Self::Copy(value) => value,
+    }
+  }
+
+  /// The same kind of source with another value.
+  pub fn with_value(&self, value: String) -> Self {
+    match self {
+      Self::Content(_) => Self::Content(value),
+      Self::Symlink(_) => Self::Symlink(value),
+      Self::Copy(_) => Self::Copy(value),
+    }
+  }
+}
+
+/// Mode, owner and group of a path; each is left alone when not set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileAttrs {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mode: Option<u32>,
+  /// User name or uid.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub owner: Option<String>,
+  /// Group name or gid.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub group: Option<String>,
+}
+
+impl FileAttrs {
+  pub fn is_empty(&self) -> bool {
+    self.mode.is_none() && self.owner.is_none() && self.group.is_none()
+  }
+}
+
+impl fmt::Display for FileAttrs {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut parts = Vec::new();
+    if let Some(mode) = self.mode {
+      parts.push(format!("mode {:04o}", mode));
+    }
+    if let Some(owner) = &self.owner {
+      parts.push(format!("owner {}", owner));
+    }
+    if let Some(group) = &self.group {
+      parts.push(format!("group {}", group));
+    }
+    write!(f, "{}", parts.join(", "))
+  }
+}
+
+/// Options for a file action.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileOpts {
+  /// Path to put the file at (`~` is expanded).
+  pub path: String,
+  /// Content, link target or copied path.
+  pub source: FileSource,
+  /// Mode, owner and group to give the path.
+  #[serde(default, skip_serializing_if = "FileAttrs::is_empty")]
+  pub attrs: FileAttrs,
+  /// Whether to put the file in place, remove it or check it.
+  #[serde(default, skip_serializing_if = "FileState::is_present")]
+  pub state: FileState,
+}
+
+/// A path a file action gave a mode, owner or group, kept in the bind's
+/// state to check them for drift.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManagedFile {
+  /// The path, with `~` and placeholders resolved.
+  pub path: String,
+  #[serde(flatten)]
+  pub attrs: FileAttrs,
+  /// The path is a symlink, whose own owner and group were set.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub symlink: bool,
+}
+
+impl ManagedFile {
+  /// How the path differs from the recorded mode, owner and group, if it does.
+  pub fn drift(&self) -> Option<String> {
+    let path = Path::new(&self.path);
+    let metadata = if self.symlink {
+      fs::symlink_metadata(path)
+    } else {
+      fs::metadata(path)
+    };
+    match metadata {
+      Ok(metadata) => attrs_drift(&metadata, &self.attrs).map(|drift| format!("{} {}", self.path, drift)),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Some(format!("{} is missing", self.path)),
+      Err(e) => Some(format!("cannot read {}: {}", self.path, e)),
+    }
+  }
+}
+
+/// Execute a file action.
+///
+/// Returns the path, or for [`FileState::Check`] `"true"` if the file is
+/// missing or differs and `"false"` otherwise, along with what it set when
+/// putting the file in place with a mode, owner or group.
+pub async fn execute_file(opts: &FileOpts) -> Result<(String, Option<ManagedFile>), ExecuteError> {
+  let path = opts.path.clone();
+  let opts = opts.clone();
+  tokio::task::spawn_blocking(move || run_file(&opts))
+    .await
+    .map_err(|e| ExecuteError::File {
+      path,
+      message: e.to_string(),
+    })?
+}
+
+fn run_file(opts: &FileOpts) -> Result<(String, Option<ManagedFile>), ExecuteError> {
+  let path = expand_path(&opts.path);
+  let path_str = path.to_string_lossy().to_string();
+  let error = |message: String| ExecuteError::File {
+    path: path_str.clone(),
+    message,
+  };
+  let symlink = matches!(opts.source, FileSource::Symlink(_));
+
+  match opts.state {
+    FileState::Check => {
+      let drift = if !matches_source(&path, &opts.source) {
+        Some("missing or differs".to_string())
+      } else if opts.attrs.is_empty() {
+        None
+      } else {
+        managed(&path_str, opts, symlink).drift()
+      };
+      if let Some(drift) = &drift {
+        debug!(path = ?path, method = opts.source.method(), drift = %drift, "file drifted");
+      }
+      Ok((drift.is_some().to_string(), None))
+    }
+    FileState::Present => {
+      if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| error(e.to_string()))?;
+      }
+      if !matches_source(&path, &opts.source) {
+        put_file(&path, &opts.source, &opts.attrs, symlink).map_err(error)?;
+        info!(path = ?path, method = opts.source.method(), "wrote file");
+      } else if !opts.attrs.is_empty() {
+        let recursive = !symlink && path.is_dir();
+        set_attrs(&path, &opts.attrs, recursive).map_err(error)?;
+      }
+      if opts.attrs.is_empty() {
+        return Ok((path_str, None));
+      }
+      debug!(path = ?path, attrs = %opts.attrs, "set file attributes");
+      Ok((path_str.clone(), Some(managed(&path_str, opts, symlink))))
+    }
+    FileState::Absent => {
+      match fs::symlink_metadata(&path) {
+        Ok(metadata) if symlink && !metadata.file_type().is_symlink() => {
+          warn!(path = ?path, "not removing a path that is no longer a symlink");
+        }
+        Ok(metadata) => {
+          let removed = if metadata.is_dir() {
+            fs::remove_dir_all(&path)
+          } else {
+            fs::remove_file(&path)
+          };
+          removed.map_err(|e| error(e.to_string()))?;
+          info!(path = ?path, method = opts.source.method(), "removed file");
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(error(e.to_string())),
+      }
+      Ok((path_str, None))
+    }
+  }
+}
+
+fn managed(path: &str, opts: &FileOpts, symlink: bool) -> ManagedFile {
+  ManagedFile {
+    path: path.to_string(),
+    attrs: opts.attrs.clone(),
+    symlink,
+  }
+}
+
+/// Whether `path` already has what `source` puts there.
+fn matches_source(path: &Path, source: &FileSource) -> bool {
+  let Ok(metadata) = fs::symlink_metadata(path) else {
+    return false;
+  };
+  match source {
+    FileSource::Content(content) => {
+      metadata.is_file() && fs::read(path).is_ok_and(|existing| existing == content.as_bytes())
+    }
+    FileSource::Symlink(target) => fs::read_link(path).is_ok_and(|existing| existing == Path::new(target)),
+    FileSource::Copy(source) => {
+      let source = expand_path(source);
+      if metadata.file_type().is_symlink() {
+        return false;
+      }
+      if source.is_dir() {
+        metadata.is_dir()
+          && matches!(
+            (hash_directory(&source, &[]), hash_directory(path, &[])),
+            (Ok(a), Ok(b)) if a == b
+          )
+      } else {
+        metadata.is_file() && matches!((hash_file(&source), hash_file(path)), (Ok(a), Ok(b)) if a == b)
+      }
+    }
+  }
+}
+
+/// Put `source` at `path` with `attrs`, replacing what is there.
+///
+/// Staged next to `path` and renamed into place.
+fn put_file(path: &Path, source: &FileSource, attrs: &FileAttrs, symlink: bool) -> Result<(), String> {
+  let staged = staged_path(path);
+  remove_existing(&staged).map_err(|e| e.to_string())?;
+
+  let result = stage(&staged, path, source)
+    .map_err(|e| e.to_string())
+    .and_then(|()| {
+      if attrs.is_empty() {
+        return Ok(());
+      }
+      let recursive = !symlink && staged.is_dir();
+      set_attrs(&staged, attrs, recursive)
+    })
+    .and_then(|()| replace_with(&staged, path).map_err(|e| e.to_string()));
+  if result.is_err() {
+    let _ = remove_existing(&staged);
+  }
+  result
+}
+
+/// Hidden path in `path`'s directory to stage its replacement at.
+fn staged_path(path: &Path) -> PathBuf {
+  let mut name = std::ffi::OsString::from(".");
+  name.push(path.file_name().unwrap_or_default());
+  name.push(format!(".syslua-{}", std::process::id()));
+  path.with_file_name(name)
+}
+
+/// Write `source` to the fresh path `staged`, which replaces `path`.
+fn stage(staged: &Path, path: &Path, source: &FileSource) -> io::Result<()> {
+  match source {
+    FileSource::Content(content) => {
+      fs::write(staged, content)?;
+      match fs::symlink_metadata(path) {
+        Ok(existing) if existing.is_file() => keep_attrs(&existing, staged),
+        _ => Ok(()),
+      }
+    }
+    FileSource::Symlink(target) => symlink(Path::new(target), staged),
+    FileSource::Copy(source) => {
+      let source = expand_path(source);
+      if source.is_dir() {
+        copy_dir(&source, staged, &[])
+      } else {
+        copy_file(&source, staged).map(|_| ())
+      }
+    }
+  }
+}
+
+/// Rename `staged` over `path`, removing a directory in the way first.
+fn replace_with(staged: &Path, path: &Path) -> io::Result<()> {
+  if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir()) || staged.is_dir() {
+    remove_existing(path)?;
+  }
+  fs::rename(staged, path)
+}
+
+/// Give `staged` the mode and owner of the file it replaces, as far as
+/// allowed.
+#[cfg(unix)]
+fn keep_attrs(existing: &fs::Metadata, staged: &Path) -> io::Result<()> {
+  use std::os::unix::fs::MetadataExt;
+
+  if let Err(e) = std::os::unix::fs::lchown(staged, Some(existing.uid()), Some(existing.gid())) {
+    debug!(path = ?staged, error = %e, "cannot keep the owner of the replaced file");
+  }
+  fs::set_permissions(staged, existing.permissions())
+}
+
+#[cfg(not(unix))]
+fn keep_attrs(existing: &fs::Metadata, staged: &Path) -> io::Result<()> {
+  fs::set_permissions(staged, existing.permissions())
+}
+
+fn remove_existing(path: &Path) -> io::Result<()> {
+  match fs::symlink_metadata(path) {
+    Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+    Ok(_) => fs::remove_file(path),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+    Err(e) => Err(e),
+  }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+  std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+  let resolved = link
+    .parent()
+    .map(|dir| dir.join(target))
+    .unwrap_or_else(|| target.to_path_buf());
+  if resolved.is_dir() {
+    std::os::windows::fs::symlink_dir(target, link)
+  } else {
+    std::os::windows::fs::symlink_file(target, link)
+  }
+}
+
+/// The uid an owner names: a user name or a numeric uid.
+#[cfg(unix)]
+fn resolve_owner(owner: &str) -> Result<u32, String> {
+  match owner.parse() {
+    Ok(uid) => Ok(uid),
+    Err(_) => crate::platform::run_as::user_ids(owner)
+      .map(|(uid, _)| uid)
+      .map_err(|e| e.to_string()),
+  }
+}
+
+/// The gid a group names: a group name or a numeric gid.
+#[cfg(unix)]
+fn resolve_group(group: &str) -> Result<u32, String> {
+  match group.parse() {
+    Ok(gid) => Ok(gid),
+    Err(_) => crate::platform::run_as::group_id(group).map_err(|e| e.to_string()),
+  }
+}
+
+/// Give `path` the owner, group and mode of `attrs`, without following a
+/// symlink. With `recursive`, the owner and group are set on everything
+/// under `path` too.
+#[cfg(unix)]
+fn set_attrs(path: &Path, attrs: &FileAttrs, recursive: bool) -> Result<(), String> {
+  use std::os::unix::fs::PermissionsExt;
+
+  let uid = attrs.owner.as_deref().map(resolve_owner).transpose()?;
+  let gid = attrs.group.as_deref().map(resolve_group).transpose()?;
+  // Before the mode, since changing the owner clears setuid and setgid bits
+  if uid.is_some() || gid.is_some() {
+    let paths: Vec<std::path::PathBuf> = if recursive {
+      walkdir::WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .map(|entry| entry.map(walkdir::DirEntry::into_path))
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?
+    } else {
+      vec![path.to_path_buf()]
+    };
+    for path in paths {
+      std::os::unix::fs::lchown(&path, uid, gid).map_err(|e| format!("cannot change owner: {}", e))?;
+    }
+  }
+  if let Some(mode) = attrs.mode {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(|e| format!("cannot change mode: {}", e))?;
+  }
+  Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_attrs(_path: &Path, _attrs: &FileAttrs, _recursive: bool) -> Result<(), String> {
+  Err("file modes and owners are not supported on this platform".to_string())
+}
+
+/// How `metadata` differs from `attrs`, if it does.
+#[cfg(unix)]
+fn attrs_drift(metadata: &fs::Metadata, attrs: &FileAttrs) -> Option<String> {
+  use std::os::unix::fs::MetadataExt;
+
+  let mut drift = Vec::new();
+  if let Some(mode) = attrs.mode
+    && metadata.mode() & MAX_MODE != mode
+  {
+    drift.push(format!(
+      "has mode {:04o}, expected {:04o}",
+      metadata.mode() & MAX_MODE,
+      mode
+    ));
+  }
+  if let Some(owner) = &attrs.owner {
+    match resolve_owner(owner) {
+      Ok(uid) if uid == metadata.uid() => {}
+      Ok(_) => drift.push(format!("is owned by uid {}, expected {}", metadata.uid(), owner)),
+      Err(e) => drift.push(e),
+    }
+  }
+  if let Some(group) = &attrs.group {
+    match resolve_group(group) {
+      Ok(gid) if gid == metadata.gid() => {}
+      Ok(_) => drift.push(format!("has gid {}, expected group {}", metadata.gid(), group)),
+      Err(e) => drift.push(e),
+    }
+  }
+  (!drift.is_empty()).then(|| drift.join(", "))
+}
+
+#[cfg(not(unix))]
+fn attrs_drift(_metadata: &fs::Metadata, attrs: &FileAttrs) -> Option<String> {
+  (!attrs.is_empty()).then(|| "has a mode or owner, which this platform doesn't support".to_string())
+}
+
+/// Parse an octal mode such as `"0644"` or `"0o4755"`.
+pub fn parse_mode(value: &str) -> Result<u32, String> {
+  let digits = value.strip_prefix("0o").unwrap_or(value);
+  match u32::from_str_radix(digits, 8) {
+    Ok(mode) if !digits.is_empty() && mode <= MAX_MODE => Ok(mode),
+    _ => Err(format!("invalid mode '{}' (expected octal like '0644')", value)),
+  }
+}
+
+/// Parse the Lua options of `ctx:write_file`, `ctx:symlink` and `ctx:copy`.
+///
+/// `method` is the ctx method, which decides the field naming the source:
+/// `content`, `target` or `source`.
+pub fn parse_file_opts(method: &str, opts: &LuaTable) -> LuaResult<FileOpts> {
+  let err = |message: String| LuaError::external(format!("{}: {}", method, message));
+  let string = |field: &str| {
+    opts
+      .get::<Option<String>>(field)
+      .map_err(|_| err(format!("'{}' must be a string", field)))
+  };
+  let required = |field: &str| string(field)?.ok_or_else(|| err(format!("'{}' is required", field)));
+
+  let path = required("path")?;
+  if path.trim().is_empty() {
+    return Err(err("'path' must not be empty".to_string()));
+  }
+  let source = match method {
+    "write_file" => FileSource::Content(required("content")?),
+    "symlink" => FileSource::Symlink(required("target")?),
+    "copy" => FileSource::Copy(required("source")?),
+    other => return Err(err(format!("unknown file method '{}'", other))),
+  };
+
+  let mode = match opts.get::<LuaValue>("mode")? {
+    LuaValue::Nil => None,
+    LuaValue::String(s) => Some(parse_mode(&s.to_str()?).map_err(err)?),
+    other => {
+      return Err(err(format!(
+        "'mode' must be an octal string like '0644', got {}",
+        other.type_name()
+      )));
+    }
+  };
+  if mode.is_some() && matches!(source, FileSource::Symlink(_)) {
+    return Err(err(
+      "a symlink has no mode of its own; set 'owner' or 'group' only".to_string(),
+    ));
+  }
+  let attrs = FileAttrs {
+    mode,
+    owner: string("owner")?,
+    group: string("group")?,
+  };
+  if [&attrs.owner, &attrs.group]
+    .into_iter()
+    .flatten()
+    .any(|name| name.trim().is_empty() || name.contains(char::is_whitespace))
+  {
+    return Err(err("'owner' and 'group' must be names or ids".to_string()));
+  }
+
+  let state = match string("state")?.as_deref() {
+    None | Some("present") => FileState::Present,
+    Some("absent") => FileState::Absent,
+    Some("check") => FileState::Check,
+    Some(other) => {
+      return Err(err(format!(
+        "unknown state '{}' (expected present, absent or check)",
+        other
+      )));
+    }
+  };
+
+  Ok(FileOpts {
+    path,
+    source,
+    attrs,
+    state,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  fn opts(path: &Path, source: FileSource) -> FileOpts {
+    FileOpts {
+      path: path.to_string_lossy().to_string(),
+      source,
+      attrs: FileAttrs::default(),
+      state: FileState::Present,
+    }
+  }
+
+  fn check(opts: &FileOpts) -> bool {
+    let check = FileOpts {
+      state: FileState::Check,
+      ..opts.clone()
+    };
+    run_file(&check).unwrap().0 == "true"
+  }
+
+  #[test]
+  fn parses_modes() {
+    assert_eq!(parse_mode("0644"), Ok(0o644));
+    assert_eq!(parse_mode("0o4755"), Ok(0o4755));
+    assert_eq!(parse_mode("600"), Ok(0o600));
+    assert!(parse_mode("0999").is_err());
+    assert!(parse_mode("17777").is_err());
+    assert!(parse_mode("").is_err());
+  }
+
+  #[test]
+  fn writes_checks_and_removes_files() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join("conf/app.conf");
+    let write = opts(&path, FileSource::Content("key = 1\n".to_string()));
+
+    assert!(check(&write));
+    let (output, managed) = run_file(&write).unwrap();
+    assert_eq!(output, path.to_string_lossy());
+    assert!(managed.is_none());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "key = 1\n");
+    assert!(!check(&write));
+
+    fs::write(&path, "key = 2\n").unwrap();
+    assert!(check(&write));
+
+    run_file(&FileOpts {
+      state: FileState::Absent,
+      ..write
+    })
+    .unwrap();
+    assert!(!path.exists());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn rewrites_are_staged_and_keep_the_replaced_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join("app.conf");
+    fs::write(&path, "old").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+    run_file(&opts(&path, FileSource::Content("new".to_string()))).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+    assert_eq!(
+      fs::read_dir(temp.path()).unwrap().count(),
+      1,
+      "nothing staged is left behind"
+    );
+
+    // A directory in the way is replaced too
+    let dir = temp.path().join("dir");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("inner"), "x").unwrap();
+    run_file(&opts(&dir, FileSource::Content("file".to_string()))).unwrap();
+    assert_eq!(fs::read_to_string(&dir).unwrap(), "file");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn symlinks_and_copies_are_replaced_when_they_differ() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("src");
+    fs::create_dir(&source).unwrap();
+    fs::write(source.join("a"), "a").unwrap();
+
+    let link = temp.path().join("link");
+    fs::write(&link, "not a link").unwrap();
+    let symlink = opts(&link, FileSource::Symlink(source.to_string_lossy().to_string()));
+    assert!(check(&symlink));
+    run_file(&symlink).unwrap();
+    assert_eq!(fs::read_link(&link).unwrap(), source);
+    assert!(!check(&symlink));
+
+    let copy_path = temp.path().join("copy");
+    let copy = opts(&copy_path, FileSource::Copy(source.to_string_lossy().to_string()));
+    run_file(&copy).unwrap();
+    assert_eq!(fs::read_to_string(copy_path.join("a")).unwrap(), "a");
+    assert!(!check(&copy));
+    fs::write(source.join("b"), "b").unwrap();
+    assert!(check(&copy));
+
+    // A symlink replaced by a real file is left alone on removal
+    fs::remove_file(&link).unwrap();
+    fs::write(&link, "user file").unwrap();
+    run_file(&FileOpts {
+      state: FileState::Absent,
+      ..symlink
+    })
+    .unwrap();
+    assert!(link.exists());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn modes_are_set_and_checked_for_drift() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join("secret");
+    let uid = fs::metadata(temp.path()).unwrap().uid();
+    let write = FileOpts {
+      attrs: FileAttrs {
+        mode: Some(0o600),
+        owner: Some(uid.to_string()),
+        group: None,
+      },
+      ..opts(&path, FileSource::Content("token".to_string()))
+    };
+
+    let (_, managed) = run_file(&write).unwrap();
+    let managed = managed.unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().mode() & MAX_MODE, 0o600);
+    assert_eq!(managed.drift(), None);
+    assert!(!check(&write));
+
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+    let drift = managed.drift().unwrap();
+    assert!(drift.contains("has mode 0644, expected 0600"), "{}", drift);
+    assert!(check(&write));
+
+    // Putting the file in place again restores the mode
+    run_file(&write).unwrap();
+    assert_eq!(managed.drift(), None);
+
+    fs::remove_file(&path).unwrap();
+    assert!(managed.drift().unwrap().ends_with("is missing"));
+  }
+
+  #[test]
+  fn lua_options_are_validated() -> LuaResult<()> {
+    let lua = Lua::new();
+    let table = |code: &str| lua.load(code).eval::<LuaTable>();
+
+    let opts = parse_file_opts(
+      "write_file",
+      &table("{ path = '/etc/app.conf', content = 'x', mode = '0640', owner = 'root', group = 'app' }")?,
+    )?;
+    assert_eq!(opts.source, FileSource::Content("x".to_string()));
+    assert_eq!(opts.attrs.mode, Some(0o640));
+    assert_eq!(opts.attrs.to_string(), "mode 0640, owner root, group app");
+
+    let err = parse_file_opts("symlink", &table("{ path = '/a', target = '/b', mode = '0644' }")?).unwrap_err();
+    assert!(err.to_string().contains("no mode of its own"), "{}", err);
+    let err = parse_file_opts("write_file", &table("{ path = '/a', content = 'x', mode = 420 }")?).unwrap_err();
+    assert!(err.to_string().contains("octal string"), "{}", err);
+    let err = parse_file_opts("copy", &table("{ path = '/a' }")?).unwrap_err();
+    assert!(err.to_string().contains("'source' is required"), "{}", err);
+    Ok(())
+  }
+}
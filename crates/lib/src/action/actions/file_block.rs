@@ -0,0 +1,508 @@
+//! File block action implementation (`file_block`).
+//!
+//! Manages one delimited block of a file syslua doesn't own as a whole, such
+//! as `/etc/hosts`. The block sits between marker comments, and the end marker
+//! records a digest of what syslua wrote:
+//!
+//! ```text
+//! # BEGIN SYSLUA dev-hosts
+//! 10.0.0.5 build.internal
+//! # END SYSLUA dev-hosts sha256:0f3c8a1d92b4e7a5
+//! ```
+//!
+//! Writing replaces the block in place, or appends it; removing deletes
+//! exactly its lines. The rest of the file is kept byte for byte, line endings
+//! included, and the block uses the file's line ending (`\r\n` if its first
+//! line ends with one). A block whose lines no longer match the digest was
+//! edited outside syslua: a check reports it as drifted, and writing or
+//! removing it logs a warning before doing so.
+
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{debug, info, warn};
+
+use crate::execute::types::ExecuteError;
+use crate::platform::paths::expand_path;
+use crate::util::hash::hash_bytes;
+
+/// Comment prefix of the markers unless the action sets one.
+pub const DEFAULT_COMMENT: &str = "#";
+
+/// Hex characters of the digest recorded in the end marker.
+const DIGEST_LEN: usize = 16;
+
+/// What a file block action does with its block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockState {
+  /// Write the block, replacing a previous version in place.
+  #[default]
+  Present,
+  /// Remove the block, if there is one.
+  Absent,
+  /// Report whether the block is missing or differs, without writing.
+  Check,
+}
+
+impl BlockState {
+  fn is_present(&self) -> bool {
+    *self == Self::Present
+  }
+}
+
+/// Options for a file block action.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileBlockOpts {
+  /// Path of the file (`~` is expanded).
+  pub path: String,
+  /// Name of the block in its markers, unique within the file.
+  pub marker: String,
+  /// Lines of the block, without the markers.
+  pub content: String,
+  /// Comment prefix of the marker lines; `#` when not set.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub comment: Option<String>,
+  /// Whether to write, remove or check the block.
+  #[serde(default, skip_serializing_if = "BlockState::is_present")]
+  pub state: BlockState,
+}
+
+impl FileBlockOpts {
+  /// The line opening the block.
+  pub fn begin_marker(&self) -> String {
+    format!("{} BEGIN SYSLUA {}", self.comment(), self.marker)
+  }
+
+  /// The line closing the block, without its digest.
+  pub fn end_marker(&self) -> String {
+    format!("{} END SYSLUA {}", self.comment(), self.marker)
+  }
+
+  fn comment(&self) -> &str {
+    self.comment.as_deref().unwrap_or(DEFAULT_COMMENT)
+  }
+
+  /// Lines of the block, without line endings.
+  fn lines(&self) -> Vec<&str> {
+    self.content.lines().collect()
+  }
+
+  /// The block, markers included, with `eol` after every line.
+  fn render(&self, eol: &str) -> Vec<String> {
+    let lines = self.lines();
+    let mut rendered = vec![format!("{}{}", self.begin_marker(), eol)];
+    rendered.extend(lines.iter().map(|line| format!("{}{}", line, eol)));
+    rendered.push(format!("{} sha256:{}{}", self.end_marker(), digest(&lines), eol));
+    rendered
+  }
+}
+
+/// How the block in a file compares to the action's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockStatus {
+  /// The file has no block with the marker.
+  Missing,
+  /// The block has the action's lines.
+  Current,
+  /// The block has other lines, as syslua wrote them.
+  Outdated,
+  /// The block was edited since syslua wrote it.
+  Modified,
+}
+
+/// A block found in a file.
+struct Found {
+  /// Line range of the block, markers included.
+  range: Range<usize>,
+  /// Lines between the markers, without line endings.
+  body: Vec<String>,
+  /// Digest recorded in the end marker, if any.
+  recorded: Option<String>,
+}
+
+/// Compare the block in `content` with `opts`' lines.
+///
+/// Fails if the block's end marker is missing.
+pub fn block_status(content: &str, opts: &FileBlockOpts) -> Result<BlockStatus, String> {
+  let lines: Vec<&str> = content.split_inclusive('\n').collect();
+  let Some(found) = find_block(&lines, opts)? else {
+    return Ok(BlockStatus::Missing);
+  };
+  let status = if found.body == opts.lines() {
+    BlockStatus::Current
+  } else if found.recorded.is_some_and(|recorded| recorded != digest(&found.body)) {
+    BlockStatus::Modified
+  } else {
+    BlockStatus::Outdated
+  };
+  Ok(status)
+}
+
+/// Write `opts`' block into `content`, returning the new file content.
+pub fn write_block(content: &str, opts: &FileBlockOpts) -> Result<String, String> {
+  let mut lines: Vec<String> = content.split_inclusive('\n').map(str::to_string).collect();
+  let borrowed: Vec<&str> = lines.iter().map(String::as_str).collect();
+  let found = find_block(&borrowed, opts)?;
+  let eol = line_ending(content);
+  let rendered = opts.render(eol);
+
+  match found {
+    Some(found) => {
+      lines.splice(found.range, rendered);
+    }
+    None => {
+      if let Some(last) = lines.last_mut()
+        && !last.ends_with('\n')
+      {
+        last.push_str(eol);
+      }
+      lines.extend(rendered);
+    }
+  }
+  Ok(lines.concat())
+}
+
+/// Remove `opts`' block from `content`, returning the new file content.
+///
+/// Only the lines between and including the markers are removed.
+pub fn remove_block(content: &str, opts: &FileBlockOpts) -> Result<String, String> {
+  let mut lines: Vec<&str> = content.split_inclusive('\n').collect();
+  let Some(found) = find_block(&lines, opts)? else {
+    return Ok(content.to_string());
+  };
+  lines.drain(found.range);
+  Ok(lines.concat())
+}
+
+/// Find the block with `opts`' marker in `lines`, which keep their endings.
+fn find_block(lines: &[&str], opts: &FileBlockOpts) -> Result<Option<Found>, String> {
+  let begin = opts.begin_marker();
+  let end = opts.end_marker();
+
+  let Some(start) = lines.iter().position(|line| strip_eol(line).trim_end() == begin) else {
+    return Ok(None);
+  };
+  // The end marker, with the digest that follows it if there is one
+  let recorded_digest = |line: &str| {
+    let rest = strip_eol(line).trim_end().strip_prefix(end.as_str())?;
+    match rest.strip_prefix(" sha256:") {
+      Some(digest) => Some(Some(digest.to_string())),
+      None => rest.is_empty().then_some(None),
+    }
+  };
+  let Some((offset, recorded)) = lines[start + 1..]
+    .iter()
+    .enumerate()
+    .find_map(|(offset, line)| recorded_digest(line).map(|recorded| (offset, recorded)))
+  else {
+    return Err(format!(
+      "block '{}' has no end marker '{}'; fix the file by hand",
+      opts.marker, end
+    ));
+  };
+
+  let stop = start + 1 + offset;
+  Ok(Some(Found {
+    range: start..stop + 1,
+    body: lines[start + 1..stop]
+      .iter()
+      .map(|line| strip_eol(line).to_string())
+      .collect(),
+    recorded,
+  }))
+}
+
+/// The line ending of `content`: that of its first line, `\n` if it has none.
+fn line_ending(content: &str) -> &'static str {
+  match content.find('\n') {
+    Some(i) if content[..i].ends_with('\r') => "\r\n",
+    _ => "\n",
+  }
+}
+
+fn strip_eol(line: &str) -> &str {
+  let line = line.strip_suffix('\n').unwrap_or(line);
+  line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// Digest of the block's lines, independent of line endings.
+fn digest(lines: &[impl AsRef<str>]) -> String {
+  let joined: Vec<&str> = lines.iter().map(AsRef::as_ref).collect();
+  let mut digest = hash_bytes(joined.join("\n").as_bytes()).0;
+  digest.truncate(DIGEST_LEN);
+  digest
+}
+
+/// Execute a file block action.
+///
+/// Returns the file path, or for [`BlockState::Check`] `"true"` if the block
+/// is missing or differs and `"false"` otherwise.
+pub async fn execute_file_block(opts: &FileBlockOpts) -> Result<String, ExecuteError> {
+  let path = expand_path(&opts.path);
+  let content = match fs::read_to_string(&path).await {
+    Ok(content) => Some(content),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+    Err(e) => return Err(e.into()),
+  };
+  let error = |message: String| ExecuteError::FileBlock {
+    path: path.display().to_string(),
+    message,
+  };
+
+  match opts.state {
+    BlockState::Check => {
+      let status = match content.as_deref().map(|content| block_status(content, opts)) {
+        None => BlockStatus::Missing,
+        Some(Ok(status)) => status,
+        Some(Err(message)) => {
+          warn!(path = ?path, marker = %opts.marker, "{}", message);
+          BlockStatus::Modified
+        }
+      };
+      if status == BlockStatus::Modified {
+        warn!(path = ?path, marker = %opts.marker, "file block was modified outside syslua");
+      }
+      let drifted = status != BlockStatus::Current;
+      debug!(path = ?path, marker = %opts.marker, ?status, drifted, "checked file block");
+      Ok(drifted.to_string())
+    }
+    BlockState::Present => {
+      let existing = content.unwrap_or_default();
+      if block_status(&existing, opts).map_err(error)? == BlockStatus::Modified {
+        warn!(path = ?path, marker = %opts.marker, "overwriting file block modified outside syslua");
+      }
+      let updated = write_block(&existing, opts).map_err(error)?;
+      if updated != existing {
+        write_file(&path, &updated).await?;
+        info!(path = ?path, marker = %opts.marker, "wrote file block");
+      }
+      Ok(path.to_string_lossy().to_string())
+    }
+    BlockState::Absent => {
+      if let Some(existing) = content {
+        if block_status(&existing, opts).map_err(error)? == BlockStatus::Modified {
+          warn!(path = ?path, marker = %opts.marker, "removing file block modified outside syslua");
+        }
+        let updated = remove_block(&existing, opts).map_err(error)?;
+        if updated != existing {
+          fs::write(&path, updated).await?;
+          info!(path = ?path, marker = %opts.marker, "removed file block");
+        }
+      }
+      Ok(path.to_string_lossy().to_string())
+    }
+  }
+}
+
+/// Write a file in place, creating it (and its directory) if needed.
+///
+/// Writing in place keeps the file's owner and permissions, and works for
+/// files that are bind mounts (e.g. `/etc/hosts` in a container).
+async fn write_file(path: &Path, content: &str) -> Result<(), ExecuteError> {
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).await?;
+  }
+  fs::write(path, content).await?;
+  Ok(())
+}
+
+/// Parse the Lua options of `ctx:file_block` and `sys.file_block`.
+///
+/// `content` may be a string or an array of lines.
+pub fn parse_file_block_opts(opts: &LuaTable) -> LuaResult<FileBlockOpts> {
+  let err = |message: String| LuaError::external(format!("file_block: {}", message));
+  let single_line = |field: &str, value: &str| {
+    if value.trim().is_empty() || value.trim() != value || value.contains(['\n', '\r']) {
+      return Err(err(format!("invalid {} '{}'", field, value)));
+    }
+    Ok(())
+  };
+
+  let path: String = opts
+    .get::<Option<String>>("path")
+    .map_err(|_| err("'path' must be a string".to_string()))?
+    .ok_or_else(|| err("'path' is required".to_string()))?;
+  let marker: String = opts
+    .get::<Option<String>>("marker")
+    .map_err(|_| err("'marker' must be a string".to_string()))?
+    .ok_or_else(|| err("'marker' is required".to_string()))?;
+  single_line("marker", &marker)?;
+  let comment: Option<String> = opts
+    .get("comment")
+    .map_err(|_| err("'comment' must be a string".to_string()))?;
+  if let Some(comment) = &comment {
+    single_line("comment", comment)?;
+  }
+
+  let content = match opts.get::<LuaValue>("content")? {
+    LuaValue::String(s) => s.to_str()?.to_string(),
+    LuaValue::Table(lines) => lines
+      .sequence_values::<String>()
+      .collect::<LuaResult<Vec<_>>>()
+      .map_err(|_| err("'content' lines must be strings".to_string()))?
+      .join("\n"),
+    LuaValue::Nil => return Err(err("'content' is required".to_string())),
+    other => {
+      return Err(err(format!(
+        "'content' must be a string or a list of lines, got {}",
+        other.type_name()
+      )));
+    }
+  };
+
+  let state = match opts.get::<Option<String>>("state")?.as_deref() {
+    None | Some("present") => BlockState::Present,
+    Some("absent") => BlockState::Absent,
+    Some("check") => BlockState::Check,
+    Some(other) => {
+      return Err(err(format!(
+        "unknown state '{}' (expected present, absent or check)",
+        other
+      )));
+    }
+  };
+
+  let opts = FileBlockOpts {
+    path,
+    marker,
+    content,
+    comment,
+    state,
+  };
+  let (begin, end) = (opts.begin_marker(), opts.end_marker());
+  if opts
+    .lines()
+    .iter()
+    .any(|line| line.trim_end() == begin || line.trim_end().starts_with(&end))
+  {
+    return Err(err(format!(
+      "content of block '{}' contains its own markers",
+      opts.marker
+    )));
+  }
+  Ok(opts)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn opts(content: &str) -> FileBlockOpts {
+    FileBlockOpts {
+      path: "/etc/hosts".to_string(),
+      marker: "dev".to_string(),
+      content: content.to_string(),
+      comment: None,
+      state: BlockState::Present,
+    }
+  }
+
+  const HOSTS: &str = "127.0.0.1 localhost\n::1 localhost\n";
+
+  #[test]
+  fn write_appends_then_updates_in_place() {
+    let written = write_block(HOSTS, &opts("10.0.0.5 build\n")).unwrap();
+    assert!(written.starts_with(HOSTS));
+    assert!(written.contains("# BEGIN SYSLUA dev\n10.0.0.5 build\n# END SYSLUA dev sha256:"));
+    assert_eq!(write_block(&written, &opts("10.0.0.5 build")).unwrap(), written);
+    assert_eq!(
+      block_status(&written, &opts("10.0.0.5 build")),
+      Ok(BlockStatus::Current)
+    );
+
+    // Lines added after the block stay after it
+    let edited = format!("{}10.0.0.9 other\n", written);
+    let updated = write_block(&edited, &opts("10.0.0.6 build")).unwrap();
+    assert!(updated.ends_with("\n10.0.0.9 other\n"));
+    assert!(updated.contains("\n10.0.0.6 build\n"));
+    assert_eq!(
+      block_status(&edited, &opts("10.0.0.6 build")),
+      Ok(BlockStatus::Outdated)
+    );
+  }
+
+  #[test]
+  fn remove_restores_the_file() {
+    let written = write_block(HOSTS, &opts("10.0.0.5 build")).unwrap();
+    assert_eq!(remove_block(&written, &opts("")).unwrap(), HOSTS);
+    assert_eq!(remove_block(HOSTS, &opts("")).unwrap(), HOSTS);
+
+    // A block written by hand, without a digest, is removed too
+    let manual = format!("{}# BEGIN SYSLUA dev\nx\n# END SYSLUA dev\n", HOSTS);
+    assert_eq!(remove_block(&manual, &opts("")).unwrap(), HOSTS);
+  }
+
+  #[test]
+  fn keeps_line_endings() {
+    let crlf = "127.0.0.1 localhost\r\n::1 localhost";
+    let written = write_block(crlf, &opts("10.0.0.5 build")).unwrap();
+    assert!(written.starts_with("127.0.0.1 localhost\r\n::1 localhost\r\n# BEGIN SYSLUA dev\r\n10.0.0.5 build\r\n"));
+    assert!(written.ends_with("\r\n"));
+    assert_eq!(
+      block_status(&written, &opts("10.0.0.5 build")),
+      Ok(BlockStatus::Current)
+    );
+
+    // Mixed endings of other lines are left alone
+    let mixed = "a\r\nb\n";
+    let written = write_block(mixed, &opts("x")).unwrap();
+    assert_eq!(remove_block(&written, &opts("x")).unwrap(), mixed);
+  }
+
+  #[test]
+  fn detects_outside_modifications() {
+    let written = write_block(HOSTS, &opts("10.0.0.5 build")).unwrap();
+    let tampered = written.replace("10.0.0.5 build", "10.0.0.5 build evil");
+    assert_eq!(
+      block_status(&tampered, &opts("10.0.0.5 build")),
+      Ok(BlockStatus::Modified)
+    );
+    assert_eq!(block_status(HOSTS, &opts("10.0.0.5 build")), Ok(BlockStatus::Missing));
+
+    let unterminated = format!("{}# BEGIN SYSLUA dev\nx\n", HOSTS);
+    assert!(
+      block_status(&unterminated, &opts("x"))
+        .unwrap_err()
+        .contains("no end marker")
+    );
+    assert!(write_block(&unterminated, &opts("x")).is_err());
+  }
+
+  #[test]
+  fn custom_comment_prefix() {
+    let mut opts = opts("server 10.0.0.1");
+    opts.comment = Some(";".to_string());
+    let written = write_block("", &opts).unwrap();
+    assert!(written.starts_with("; BEGIN SYSLUA dev\nserver 10.0.0.1\n; END SYSLUA dev sha256:"));
+  }
+
+  #[tokio::test]
+  async fn execute_writes_checks_and_removes() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let path = temp.path().join("hosts");
+    std::fs::write(&path, HOSTS).unwrap();
+    let with_state = |state| FileBlockOpts {
+      path: path.to_string_lossy().to_string(),
+      state,
+      ..opts("10.0.0.5 build")
+    };
+
+    assert_eq!(
+      execute_file_block(&with_state(BlockState::Check)).await.unwrap(),
+      "true"
+    );
+    execute_file_block(&with_state(BlockState::Present)).await.unwrap();
+    assert_eq!(
+      execute_file_block(&with_state(BlockState::Check)).await.unwrap(),
+      "false"
+    );
+
+    execute_file_block(&with_state(BlockState::Absent)).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), HOSTS);
+  }
+}
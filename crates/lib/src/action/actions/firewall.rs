@@ -0,0 +1,192 @@
+//! Firewall rule action implementation (`firewall_rule`).
+//!
+//! Adds, deletes or checks one named rule of the host firewall through
+//! [`crate::platform::firewall`], which picks nftables, pf or Windows
+//! Firewall. Rules are tagged with their name, so deleting a rule never
+//! touches rules syslua didn't add.
+
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::execute::types::ExecuteError;
+use crate::platform::firewall::{Backend, Direction, FirewallError, FirewallRule, Protocol, Verdict};
+
+/// What a firewall rule action does with its rule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleState {
+  /// Add the rule, replacing rules of the same name.
+  #[default]
+  Present,
+  /// Delete the rules with the rule's name, if there are any.
+  Absent,
+  /// Report whether the rule is missing, without changing anything.
+  Check,
+}
+
+impl RuleState {
+  fn is_present(&self) -> bool {
+    *self == Self::Present
+  }
+}
+
+/// Options for a firewall rule action.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FirewallOpts {
+  #[serde(flatten)]
+  pub rule: FirewallRule,
+  /// Whether to add, delete or check the rule.
+  #[serde(default, skip_serializing_if = "RuleState::is_present")]
+  pub state: RuleState,
+}
+
+impl From<FirewallError> for ExecuteError {
+  fn from(e: FirewallError) -> Self {
+    ExecuteError::Firewall { message: e.to_string() }
+  }
+}
+
+/// Execute a firewall rule action.
+///
+/// Returns the rule's tag, or for [`RuleState::Check`] `"true"` if the rule
+/// is missing and `"false"` otherwise.
+pub async fn execute_firewall(opts: &FirewallOpts) -> Result<String, ExecuteError> {
+  let backend = Backend::current().ok_or(FirewallError::Unsupported)?;
+  let rule = &opts.rule;
+
+  match opts.state {
+    RuleState::Check => {
+      let drifted = !backend.is_present(rule).await?;
+      debug!(rule = %rule.name, drifted, "checked firewall rule");
+      Ok(drifted.to_string())
+    }
+    RuleState::Present => {
+      backend.add(rule).await?;
+      info!(rule = %rule.name, "added firewall rule");
+      Ok(rule.tag())
+    }
+    RuleState::Absent => {
+      backend.delete(rule).await?;
+      info!(rule = %rule.name, "deleted firewall rule");
+      Ok(rule.tag())
+    }
+  }
+}
+
+/// Parse the Lua options of `ctx:firewall_rule` and `sys.firewall.rule`.
+///
+/// `port` may be a number or a range string such as `"8000-8100"`.
+pub fn parse_firewall_opts(opts: &LuaTable) -> LuaResult<FirewallOpts> {
+  let err = |message: String| LuaError::external(format!("firewall rule: {}", message));
+  // Reads an optional string field, one of `choices`
+  let choice = |field: &str, choices: &[&str]| -> LuaResult<Option<String>> {
+    let value: Option<String> = opts
+      .get(field)
+      .map_err(|_| err(format!("'{}' must be a string", field)))?;
+    match value {
+      Some(value) if !choices.contains(&value.as_str()) => Err(err(format!(
+        "unknown {} '{}' (expected {})",
+        field,
+        value,
+        choices.join(", ")
+      ))),
+      value => Ok(value),
+    }
+  };
+
+  let name: String = opts
+    .get::<Option<String>>("name")
+    .map_err(|_| err("'name' must be a string".to_string()))?
+    .ok_or_else(|| err("'name' is required".to_string()))?;
+  let direction = match choice("direction", &["in", "out"])?.as_deref() {
+    Some("out") => Direction::Out,
+    _ => Direction::In,
+  };
+  let verdict = match choice("action", &["allow", "deny"])?.as_deref() {
+    Some("deny") => Verdict::Deny,
+    _ => Verdict::Allow,
+  };
+  let protocol = match choice("protocol", &["tcp", "udp", "any"])?.as_deref() {
+    Some("udp") => Protocol::Udp,
+    Some("any") => Protocol::Any,
+    _ => Protocol::Tcp,
+  };
+  let state = match choice("state", &["present", "absent", "check"])?.as_deref() {
+    Some("absent") => RuleState::Absent,
+    Some("check") => RuleState::Check,
+    _ => RuleState::Present,
+  };
+  let port = match opts.get::<LuaValue>("port")? {
+    LuaValue::Nil => None,
+    LuaValue::Integer(port) => Some(port.to_string()),
+    LuaValue::String(port) => Some(port.to_str()?.to_string()),
+    other => {
+      return Err(err(format!(
+        "'port' must be a number or string, got {}",
+        other.type_name()
+      )));
+    }
+  };
+  let address: Option<String> = opts
+    .get("address")
+    .map_err(|_| err("'address' must be a string".to_string()))?;
+
+  let rule = FirewallRule {
+    name,
+    direction,
+    verdict,
+    protocol,
+    port,
+    address,
+  };
+  rule.validate().map_err(LuaError::external)?;
+  Ok(FirewallOpts { rule, state })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_lua_options() -> LuaResult<()> {
+    let lua = Lua::new();
+    let table: LuaTable = lua
+      .load(r#"return { name = "web", port = 443, address = "192.168.1.0/24", action = "deny", state = "check" }"#)
+      .eval()?;
+    let opts = parse_firewall_opts(&table)?;
+    assert_eq!(opts.rule.port.as_deref(), Some("443"));
+    assert_eq!(opts.rule.verdict, Verdict::Deny);
+    assert_eq!(opts.rule.direction, Direction::In);
+    assert_eq!(opts.state, RuleState::Check);
+
+    let table: LuaTable = lua.load(r#"return { name = "web", protocol = "icmp" }"#).eval()?;
+    let err = parse_firewall_opts(&table).unwrap_err();
+    assert!(err.to_string().contains("unknown protocol 'icmp'"), "{}", err);
+
+    let table: LuaTable = lua.load(r#"return { name = "web", port = "22; drop" }"#).eval()?;
+    assert!(parse_firewall_opts(&table).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn serializes_flat_with_the_default_state_omitted() {
+    let opts = FirewallOpts {
+      rule: FirewallRule {
+        name: "ssh".to_string(),
+        direction: Direction::In,
+        verdict: Verdict::Allow,
+        protocol: Protocol::Tcp,
+        port: Some("22".to_string()),
+        address: None,
+      },
+      state: RuleState::Present,
+    };
+    let json = serde_json::to_value(&opts).unwrap();
+    assert_eq!(
+      json,
+      serde_json::json!({ "name": "ssh", "direction": "in", "action": "allow", "protocol": "tcp", "port": "22" })
+    );
+    assert_eq!(serde_json::from_value::<FirewallOpts>(json).unwrap(), opts);
+  }
+}
@@ -0,0 +1,191 @@
+//! Font action implementation (`font`).
+//!
+//! Installs, removes or checks the fonts of a font file or directory, named
+//! so they can be removed again, through [`crate::platform::font`], which
+//! knows where each OS looks for fonts and how to refresh its font cache.
+
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::execute::types::ExecuteError;
+use crate::platform::font::{
+  FontError, FontScope, fonts_dir, fonts_missing, install_fonts, refresh_cache, remove_fonts, validate_name,
+};
+use crate::platform::paths::expand_path;
+
+/// What a font action does with its fonts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FontState {
+  /// Install the fonts, replacing an earlier version.
+  #[default]
+  Present,
+  /// Remove the fonts installed under the name, if there are any.
+  Absent,
+  /// Report whether a font is missing or differs, without installing.
+  Check,
+}
+
+impl FontState {
+  fn is_present(&self) -> bool {
+    *self == Self::Present
+  }
+}
+
+/// Options for a font action.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FontOpts {
+  /// Name the fonts are installed under: letters, digits, `-` and `_`.
+  pub name: String,
+  /// Font file, or directory whose font files are installed (`~` is expanded).
+  pub source: String,
+  /// Install for the current user (default) or for everyone.
+  #[serde(default, skip_serializing_if = "FontScope::is_user")]
+  pub scope: FontScope,
+  /// Whether to install, remove or check the fonts.
+  #[serde(default, skip_serializing_if = "FontState::is_present")]
+  pub state: FontState,
+}
+
+impl From<FontError> for ExecuteError {
+  fn from(e: FontError) -> Self {
+    ExecuteError::Font { message: e.to_string() }
+  }
+}
+
+/// Execute a font action.
+///
+/// Returns the fonts directory, or for [`FontState::Check`] `"true"` if a
+/// font is missing or differs and `"false"` otherwise.
+pub async fn execute_font(opts: &FontOpts) -> Result<String, ExecuteError> {
+  let dir = fonts_dir(opts.scope);
+  let source = expand_path(&opts.source);
+
+  match opts.state {
+    FontState::Check => {
+      let drifted = fonts_missing(&dir, &opts.name, &source).await?;
+      debug!(name = %opts.name, drifted, "checked fonts");
+      return Ok(drifted.to_string());
+    }
+    FontState::Present => {
+      if install_fonts(&dir, &opts.name, &source, opts.scope).await? {
+        refresh_cache(&dir, opts.scope).await;
+        info!(name = %opts.name, dir = ?dir, "installed fonts");
+      }
+    }
+    FontState::Absent => {
+      if remove_fonts(&dir, &opts.name, opts.scope).await? {
+        refresh_cache(&dir, opts.scope).await;
+        info!(name = %opts.name, dir = ?dir, "removed fonts");
+      }
+    }
+  }
+  Ok(dir.to_string_lossy().to_string())
+}
+
+/// Parse the Lua options of `ctx:font` and `sys.font`.
+///
+/// `name` defaults to the file name of `source` without its extension, with
+/// other characters than letters, digits, `-` and `_` replaced by `-`.
+pub fn parse_font_opts(opts: &LuaTable) -> LuaResult<FontOpts> {
+  let err = |message: String| LuaError::external(format!("font: {}", message));
+
+  let source: String = opts
+    .get::<Option<String>>("source")
+    .map_err(|_| err("'source' must be a string".to_string()))?
+    .ok_or_else(|| err("'source' is required".to_string()))?;
+  let name = match opts
+    .get::<Option<String>>("name")
+    .map_err(|_| err("'name' must be a string".to_string()))?
+  {
+    Some(name) => name,
+    None => default_name(&source).ok_or_else(|| {
+      err(format!(
+        "'name' is required when it can't be derived from source '{}'",
+        source
+      ))
+    })?,
+  };
+  validate_name(&name).map_err(|e| err(e.to_string()))?;
+
+  let scope = match opts.get::<Option<String>>("scope")?.as_deref() {
+    None | Some("user") => FontScope::User,
+    Some("system") => FontScope::System,
+    Some(other) => return Err(err(format!("unknown scope '{}' (expected user or system)", other))),
+  };
+  let state = match opts.get::<Option<String>>("state")?.as_deref() {
+    None | Some("present") => FontState::Present,
+    Some("absent") => FontState::Absent,
+    Some("check") => FontState::Check,
+    Some(other) => {
+      return Err(err(format!(
+        "unknown state '{}' (expected present, absent or check)",
+        other
+      )));
+    }
+  };
+
+  Ok(FontOpts {
+    name,
+    source,
+    scope,
+    state,
+  })
+}
+
+/// Name of the fonts of `source`, unless it is a placeholder.
+fn default_name(source: &str) -> Option<String> {
+  let file_name = source.trim_end_matches(['/', '\\']).rsplit(['/', '\\']).next()?;
+  if file_name.contains('$') {
+    return None;
+  }
+  let stem = match file_name.rsplit_once('.') {
+    Some((stem, _)) if !stem.is_empty() => stem,
+    _ => file_name,
+  };
+  let name: String = stem
+    .chars()
+    .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '-' })
+    .collect();
+  let name = name.trim_matches('-');
+  (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_lua_options() -> LuaResult<()> {
+    let lua = Lua::new();
+    let table: LuaTable = lua
+      .load(r#"return { source = "~/Downloads/FiraCode Nerd Font.ttf", scope = "system", state = "check" }"#)
+      .eval()?;
+    let opts = parse_font_opts(&table)?;
+    assert_eq!(opts.name, "FiraCode-Nerd-Font");
+    assert_eq!(opts.scope, FontScope::System);
+    assert_eq!(opts.state, FontState::Check);
+
+    let table: LuaTable = lua.load(r#"return { source = "$${{build:abc:out}}" }"#).eval()?;
+    let err = parse_font_opts(&table).unwrap_err();
+    assert!(err.to_string().contains("'name' is required"), "{}", err);
+
+    let table: LuaTable = lua.load(r#"return { source = "/fonts", name = "../x" }"#).eval()?;
+    assert!(parse_font_opts(&table).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn serializes_with_defaults_omitted() {
+    let opts = FontOpts {
+      name: "mono".to_string(),
+      source: "/store/mono".to_string(),
+      scope: FontScope::User,
+      state: FontState::Present,
+    };
+    let json = serde_json::to_value(&opts).unwrap();
+    assert_eq!(json, serde_json::json!({ "name": "mono", "source": "/store/mono" }));
+    assert_eq!(serde_json::from_value::<FontOpts>(json).unwrap(), opts);
+  }
+}
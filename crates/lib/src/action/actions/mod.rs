@@ -2,8 +2,24 @@
 //!
 //! This module contains the concrete implementations for each action type:
 //!
+//! - [`config_section`] - Syslua-managed sections of git and ssh config files
+//! - [`download_cache`] - Shared, resumable cache of `fetch_url` downloads
 //! - [`exec`] - Shell command execution with environment and working directory support
 //! - [`fetch_url`] - HTTP/HTTPS file download with SHA256 integrity verification
+//! - [`file`] - Files, symlinks and copies put in place, with their mode and owner
+//! - [`file_block`] - Syslua-managed blocks of files it doesn't own, between markers
+//! - [`firewall`] - Named rules of the host firewall
+//! - [`font`] - Fonts installed where the OS looks for them
+//! - [`rate_limit`] - Bandwidth limit shared by all downloads of an apply
+//! - [`unpack`] - Archive inspection and extraction for `fetch_url` with `unpack`
 
+pub mod config_section;
+pub mod download_cache;
 pub mod exec;
 pub mod fetch_url;
+pub mod file;
+pub mod file_block;
+pub mod firewall;
+pub mod font;
+pub mod rate_limit;
+pub mod unpack;
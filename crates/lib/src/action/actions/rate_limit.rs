@@ -0,0 +1,131 @@
+//! Bandwidth limit shared by downloads.
+//!
+//! `sys apply --limit-rate` caps the combined rate of every `fetch_url`
+//! download of an apply, however many builds download in parallel and however
+//! many ranges each download is split into. Clones of a [`RateLimit`] share
+//! one budget.
+//!
+//! The budget is a virtual clock: receiving `n` bytes moves it forward by
+//! `n / rate` seconds, and a download that gets ahead of real time sleeps
+//! until it catches up. Up to [`BURST`] of unused budget is kept, so short
+//! pauses don't slow a download down.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Unused budget kept for bursts after a pause.
+const BURST: Duration = Duration::from_millis(100);
+
+/// A download rate limit in bytes per second; unlimited by default.
+///
+/// Serialized as the byte rate, or `null` when unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimit(Option<Arc<Bucket>>);
+
+#[derive(Debug)]
+struct Bucket {
+  bytes_per_sec: u64,
+  /// When the budget spent so far runs out.
+  next: Mutex<Instant>,
+}
+
+impl RateLimit {
+  /// No limit.
+  pub const NONE: RateLimit = RateLimit(None);
+
+  /// Limit to `bytes_per_sec`; `None` or 0 means unlimited.
+  pub fn new(bytes_per_sec: Option<u64>) -> Self {
+    Self(bytes_per_sec.filter(|rate| *rate > 0).map(|bytes_per_sec| {
+      Arc::new(Bucket {
+        bytes_per_sec,
+        next: Mutex::new(Instant::now()),
+      })
+    }))
+  }
+
+  /// The limit in bytes per second, if any.
+  pub fn bytes_per_sec(&self) -> Option<u64> {
+    self.0.as_ref().map(|bucket| bucket.bytes_per_sec)
+  }
+
+  /// Whether downloads are unlimited.
+  pub fn is_none(&self) -> bool {
+    self.0.is_none()
+  }
+
+  /// Account for `bytes` just received, waiting until they fit the rate.
+  pub async fn acquire(&self, bytes: u64) {
+    let Some(bucket) = &self.0 else {
+      return;
+    };
+
+    let wait = {
+      let mut next = bucket.next.lock().expect("rate limit lock poisoned");
+      let now = Instant::now();
+      let start = (*next).max(now.checked_sub(BURST).unwrap_or(now));
+      *next = start + Duration::from_secs_f64(bytes as f64 / bucket.bytes_per_sec as f64);
+      next.saturating_duration_since(now)
+    };
+
+    if !wait.is_zero() {
+      tokio::time::sleep(wait).await;
+    }
+  }
+}
+
+impl Serialize for RateLimit {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    self.bytes_per_sec().serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for RateLimit {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    Option::<u64>::deserialize(deserializer).map(RateLimit::new)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn zero_means_unlimited() {
+    assert!(RateLimit::new(Some(0)).is_none());
+    assert!(RateLimit::new(None).is_none());
+    assert_eq!(RateLimit::new(Some(1024)).bytes_per_sec(), Some(1024));
+  }
+
+  #[tokio::test]
+  async fn clones_share_the_budget() {
+    let limit = RateLimit::new(Some(10_000));
+    let clone = limit.clone();
+    let started = Instant::now();
+
+    // 3000 bytes at 10000 bytes/s take ~300ms, whichever clone receives them
+    limit.acquire(1000).await;
+    clone.acquire(1000).await;
+    limit.acquire(1000).await;
+
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+  }
+
+  #[tokio::test]
+  async fn unlimited_never_waits() {
+    let started = Instant::now();
+    RateLimit::NONE.acquire(u64::MAX).await;
+    assert!(started.elapsed() < Duration::from_millis(100));
+  }
+
+  #[test]
+  fn serializes_as_the_byte_rate() {
+    assert_eq!(serde_json::to_string(&RateLimit::new(Some(512))).unwrap(), "512");
+    assert_eq!(serde_json::to_string(&RateLimit::NONE).unwrap(), "null");
+    let limit: RateLimit = serde_json::from_str("2048").unwrap();
+    assert_eq!(limit.bytes_per_sec(), Some(2048));
+  }
+}
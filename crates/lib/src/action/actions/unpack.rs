@@ -0,0 +1,164 @@
+//! Archive unpacking for `fetch_url` actions with `unpack = true`.
+//!
+//! The downloaded archive is inspected before extraction: its format is
+//! recognized from its first bytes, so a URL that served an HTML error page
+//! fails with a clear message instead of a `tar` error. Extraction uses the
+//! system `tar`, which handles `.tar`, `.tar.gz`, `.tar.xz`, `.tar.bz2` and
+//! `.tar.zst` (and `.zip` where `tar` is bsdtar). A single top-level
+//! directory in the archive, as in most release tarballs, is stripped.
+
+use std::path::{Path, PathBuf};
+
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use tracing::{debug, info};
+
+use crate::execute::types::ExecuteError;
+use crate::platform::archive::extract_command;
+
+/// Formats recognized by [`archive_format`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+  Tar,
+  Gzip,
+  Xz,
+  Bzip2,
+  Zstd,
+  Zip,
+}
+
+/// Recognize the format of an archive from its first bytes (`header`, at
+/// least 262 bytes to recognize an uncompressed tar).
+pub fn archive_format(header: &[u8]) -> Option<ArchiveFormat> {
+  match header {
+    [0x1f, 0x8b, ..] => Some(ArchiveFormat::Gzip),
+    [0xfd, b'7', b'z', b'X', b'Z', 0x00, ..] => Some(ArchiveFormat::Xz),
+    [b'B', b'Z', b'h', ..] => Some(ArchiveFormat::Bzip2),
+    [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(ArchiveFormat::Zstd),
+    [b'P', b'K', 0x03, 0x04, ..] => Some(ArchiveFormat::Zip),
+    _ if header.get(257..262) == Some(b"ustar") => Some(ArchiveFormat::Tar),
+    _ => None,
+  }
+}
+
+/// Name of the directory an archive is unpacked to: its file name without
+/// archive extensions (`ripgrep-14.1.0.tar.gz` -> `ripgrep-14.1.0`).
+pub fn unpacked_name(archive: &Path) -> String {
+  let name = archive
+    .file_name()
+    .map(|n| n.to_string_lossy().to_string())
+    .unwrap_or_default();
+  let mut stem = name.as_str();
+  for ext in [".gz", ".xz", ".bz2", ".zst", ".tgz", ".tbz2", ".txz", ".tar", ".zip"] {
+    stem = stem.strip_suffix(ext).unwrap_or(stem);
+  }
+  if stem.is_empty() { name } else { stem.to_string() }
+}
+
+/// Unpack `archive` into `<out_dir>/src/<name>` and return that directory.
+///
+/// Extraction is staged next to the destination, so the directory only
+/// appears once it succeeded. An existing directory is replaced.
+pub async fn unpack_archive(archive: &Path, out_dir: &Path) -> Result<PathBuf, ExecuteError> {
+  let unpack_error = |message: String| ExecuteError::Unpack {
+    path: archive.display().to_string(),
+    message,
+  };
+
+  let mut header = Vec::with_capacity(262);
+  fs::File::open(archive)
+    .await?
+    .take(262)
+    .read_to_end(&mut header)
+    .await?;
+  let format = archive_format(&header).ok_or_else(|| unpack_error("not a recognized archive format".to_string()))?;
+  debug!(path = ?archive, format = ?format, "inspected archive");
+
+  let src_dir = out_dir.join("src");
+  fs::create_dir_all(&src_dir).await?;
+  let dest = src_dir.join(unpacked_name(archive));
+
+  let staging = tempfile::Builder::new().prefix(".unpack-").tempdir_in(&src_dir)?;
+  let output = Command::from(extract_command(archive, staging.path()))
+    .output()
+    .await
+    .map_err(|e| unpack_error(format!("failed to run tar: {}", e)))?;
+  if !output.status.success() {
+    return Err(unpack_error(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+  }
+
+  // Strip a single top-level directory
+  let mut entries = Vec::new();
+  let mut dir = fs::read_dir(staging.path()).await?;
+  while let Some(entry) = dir.next_entry().await? {
+    entries.push(entry.path());
+  }
+  let root = match entries.as_slice() {
+    [only] if fs::metadata(only).await.is_ok_and(|m| m.is_dir()) => only.clone(),
+    _ => staging.path().to_path_buf(),
+  };
+
+  if fs::symlink_metadata(&dest).await.is_ok() {
+    fs::remove_dir_all(&dest).await?;
+  }
+  fs::rename(&root, &dest).await?;
+
+  info!(path = ?dest, "unpacked archive");
+  Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  #[test]
+  fn recognizes_archive_formats() {
+    assert_eq!(archive_format(&[0x1f, 0x8b, 0x08]), Some(ArchiveFormat::Gzip));
+    assert_eq!(archive_format(b"PK\x03\x04rest"), Some(ArchiveFormat::Zip));
+    let mut tar = vec![0u8; 512];
+    tar[257..262].copy_from_slice(b"ustar");
+    assert_eq!(archive_format(&tar), Some(ArchiveFormat::Tar));
+    assert_eq!(archive_format(b"<!DOCTYPE html>"), None);
+  }
+
+  #[test]
+  fn unpacked_name_drops_archive_extensions() {
+    assert_eq!(unpacked_name(Path::new("/d/ripgrep-14.1.0.tar.gz")), "ripgrep-14.1.0");
+    assert_eq!(unpacked_name(Path::new("/d/tool.zip")), "tool");
+    assert_eq!(unpacked_name(Path::new("/d/tool.tgz")), "tool");
+    assert_eq!(unpacked_name(Path::new("/d/.tar.gz")), ".tar.gz");
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn unpacks_and_strips_the_top_level_dir() {
+    let temp = TempDir::new().unwrap();
+    let src = temp.path().join("pkg-1.0");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("README"), "hi").unwrap();
+    let archive = temp.path().join("pkg-1.0.tar.gz");
+    let status = std::process::Command::new("tar")
+      .arg("-czf")
+      .arg(&archive)
+      .arg("-C")
+      .arg(temp.path())
+      .arg("pkg-1.0")
+      .status()
+      .unwrap();
+    assert!(status.success());
+
+    let out = temp.path().join("out");
+    let dest = unpack_archive(&archive, &out).await.unwrap();
+    assert_eq!(dest, out.join("src").join("pkg-1.0"));
+    assert_eq!(std::fs::read_to_string(dest.join("README")).unwrap(), "hi");
+    // Only the unpacked directory is left
+    assert_eq!(std::fs::read_dir(out.join("src")).unwrap().count(), 1);
+
+    let page = temp.path().join("error.tar.gz");
+    std::fs::write(&page, "<html>not found</html>").unwrap();
+    let err = unpack_archive(&page, &out).await.unwrap_err();
+    assert!(err.to_string().contains("not a recognized archive"), "{}", err);
+  }
+}
@@ -8,13 +8,28 @@
 //! # Action Types
 //!
 //! - [`Action::Exec`] - Execute a shell command with optional args, env, and cwd
-//! - [`Action::FetchUrl`] - Download a file from a URL with SHA256 verification
+//!   (skippable via `creates`/`unless` guards, optionally run through a shell
+//!   or, in binds, as another user)
+//! - [`Action::FetchUrl`] - Download a file from a URL with SHA256 verification,
+//!   optionally unpacking it (named outputs `archive` and `src`)
+//! - [`Action::ConfigSection`] - Manage one section of a git or ssh config file
+//!   (bind only, via `ctx:git_config` and `ctx:ssh_config`)
+//! - [`Action::Firewall`] - Add, delete or check a named host firewall rule
+//!   (bind only, via `ctx:firewall_rule`)
+//! - [`Action::File`] - Write a file, create a symlink or copy a file or
+//!   directory, with its mode, owner and group (bind only, via
+//!   `ctx:write_file`, `ctx:symlink` and `ctx:copy`)
+//! - [`Action::FileBlock`] - Manage one marker-delimited block of a file
+//!   (bind only, via `ctx:file_block`)
+//! - [`Action::Font`] - Install, remove or check fonts in the OS fonts directory
+//!   (bind only, via `ctx:font`)
 //!
 //! # Placeholder Resolution
 //!
 //! Actions support placeholder syntax for dynamic values:
 //! - `${{out}}` - The build/bind output directory
 //! - `${{action:N}}` - Output from action at index N
+//! - `${{action:N:name}}` - Named sub-output of action N
 //! - `${{build:HASH:output}}` - Output from a dependency build
 //! - `${{bind:HASH:output}}` - Output from a dependency bind
 //!
@@ -28,17 +43,37 @@ pub use types::*;
 use std::collections::BTreeMap;
 use std::path::Path;
 
-use crate::execute::types::{ActionResult, ExecuteError};
+use tracing::debug;
+
+use crate::execute::types::{ActionResult, ExecuteError, NodeTiming};
 use crate::placeholder::{self, Resolver};
-use actions::exec::ExecOpts;
-use actions::exec::execute_cmd;
+use crate::platform::run_as::RunAs;
+use actions::config_section::execute_config_section;
+use actions::exec::{ExecIsolation, ExecOpts};
+use actions::exec::{execute_cmd, run_unless_probe};
 use actions::fetch_url::execute_fetch_url;
+use actions::file::execute_file;
+use actions::file_block::execute_file_block;
+use actions::firewall::execute_firewall;
+use actions::font::execute_font;
+use actions::unpack::unpack_archive;
 
 /// Names of built-in methods on BuildCtx that cannot be overwritten.
 pub const BUILTIN_BUILD_CTX_METHODS: &[&str] = &["exec", "fetch_url", "out"];
 
 /// Names of built-in methods on BindCtx that cannot be overwritten.
-pub const BUILTIN_BIND_CTX_METHODS: &[&str] = &["exec", "out"];
+pub const BUILTIN_BIND_CTX_METHODS: &[&str] = &[
+  "copy",
+  "exec",
+  "file_block",
+  "firewall_rule",
+  "font",
+  "git_config",
+  "out",
+  "ssh_config",
+  "symlink",
+  "write_file",
+];
 
 /// Execute a single build action.
 ///
@@ -50,6 +85,9 @@ pub const BUILTIN_BIND_CTX_METHODS: &[&str] = &["exec", "out"];
 /// * `action` - The action to execute
 /// * `resolver` - The placeholder resolver for this build
 /// * `out_dir` - The build's output directory
+/// * `isolation` - Optional cgroup and network isolation for spawned commands,
+///   download throttling, the concurrency limits of the action's type, and
+///   where to record the commands it runs
 ///
 /// # Returns
 ///
@@ -58,17 +96,41 @@ pub async fn execute_action(
   action: &Action,
   resolver: &impl Resolver,
   out_dir: &Path,
+  isolation: Option<&ExecIsolation>,
 ) -> Result<ActionResult, ExecuteError> {
+  // Held until the action finishes
+  let _permit = match isolation {
+    Some(isolation) => isolation.limits.acquire(action).await,
+    None => None,
+  };
+
   match action {
-    Action::FetchUrl { url, sha256 } => {
+    Action::FetchUrl { url, sha256, unpack } => {
       // Resolve placeholders in URL (unusual but possible)
       let resolved_url = placeholder::substitute(url, resolver)?;
       let resolved_sha256 = placeholder::substitute(sha256, resolver)?;
 
-      let path = execute_fetch_url(&resolved_url, &resolved_sha256, out_dir).await?;
+      let downloads = isolation.map(|i| i.downloads.clone()).unwrap_or_default();
+      let path = execute_fetch_url(&resolved_url, &resolved_sha256, out_dir, &downloads).await?;
+      let archive = path.to_string_lossy().to_string();
+      if !*unpack {
+        return Ok(ActionResult {
+          output: archive,
+          skipped: false,
+          run_as: None,
+          outputs: BTreeMap::new(),
+          file: None,
+        });
+      }
 
+      // The unpacked directory is the main output; both are named outputs
+      let src = unpack_archive(&path, out_dir).await?.to_string_lossy().to_string();
       Ok(ActionResult {
-        output: path.to_string_lossy().to_string(),
+        output: src.clone(),
+        skipped: false,
+        run_as: None,
+        outputs: BTreeMap::from([("archive".to_string(), archive), ("src".to_string(), src)]),
+        file: None,
       })
     }
 
@@ -78,6 +140,10 @@ pub async fn execute_action(
         args,
         env,
         cwd,
+        creates,
+        unless,
+        shell,
+        run_as,
       } = opts;
       // Resolve placeholders in command, env, and cwd
       let resolved_cmd = placeholder::substitute(cmd, resolver)?;
@@ -108,16 +174,173 @@ pub async fn execute_action(
         None
       };
 
-      let output = execute_cmd(
-        &resolved_cmd,
-        resolved_args.as_ref(),
+      let run_as = match run_as {
+        Some(user) => Some(RunAs::for_user(user).map_err(|e| ExecuteError::RunAs {
+          user: user.clone(),
+          message: e.to_string(),
+        })?),
+        None => isolation.and_then(|i| i.user.clone()),
+      };
+
+      if let Some(creates) = creates {
+        let resolved_creates = placeholder::substitute(creates, resolver)?;
+        // Relative paths are relative to where the command would run
+        let working_dir = resolved_cwd.as_deref().map(Path::new).unwrap_or(out_dir);
+        if working_dir.join(&resolved_creates).exists() {
+          debug!(cmd = %resolved_cmd, creates = %resolved_creates, "skipping command, path exists");
+          // A skipped command has no output, whichever guard skipped it
+          return Ok(ActionResult {
+            output: String::new(),
+            skipped: true,
+            run_as: None,
+            outputs: BTreeMap::new(),
+            file: None,
+          });
+        }
+      }
+
+      if let Some(unless) = unless {
+        let resolved_unless = placeholder::substitute(unless, resolver)?;
+        if run_unless_probe(
+          &resolved_unless,
+          *shell,
+          resolved_env.as_ref(),
+          resolved_cwd.as_deref(),
+          out_dir,
+          isolation,
+          run_as.as_ref(),
+        )
+        .await?
+        {
+          debug!(cmd = %resolved_cmd, unless = %resolved_unless, "skipping command, probe succeeded");
+          return Ok(ActionResult {
+            output: String::new(),
+            skipped: true,
+            // The probe ran as the user
+            run_as,
+            outputs: BTreeMap::new(),
+            file: None,
+          });
+        }
+      }
+
+      let (spawn_cmd, spawn_args) = ExecOpts::invocation(&resolved_cmd, resolved_args.as_ref(), *shell);
+
+      let (output, timing) = NodeTiming::measure(execute_cmd(
+        &spawn_cmd,
+        spawn_args.as_ref(),
         resolved_env.as_ref(),
         resolved_cwd.as_deref(),
         out_dir,
-      )
-      .await?;
+        isolation,
+        run_as.as_ref(),
+      ))
+      .await;
+
+      if let Some(transcript) = isolation.and_then(|i| i.transcript.as_ref()) {
+        let command = std::iter::once(spawn_cmd.clone())
+          .chain(spawn_args.iter().flatten().cloned())
+          .collect();
+        transcript.record(
+          command,
+          resolved_env.clone().unwrap_or_default(),
+          resolved_cwd
+            .clone()
+            .unwrap_or_else(|| out_dir.to_string_lossy().to_string()),
+          run_as.as_ref().map(|r| r.user.clone()),
+          timing,
+          output.as_ref().map(|_| ()),
+        );
+      }
+      let output = output?;
+
+      Ok(ActionResult {
+        output,
+        skipped: false,
+        run_as,
+        outputs: BTreeMap::new(),
+        file: None,
+      })
+    }
+
+    Action::ConfigSection(opts) => {
+      // Resolve placeholders in the path and values (e.g. a built key's path)
+      let mut resolved = opts.clone();
+      resolved.path = placeholder::substitute(&opts.path, resolver)?;
+      for values in resolved.entries.values_mut() {
+        for value in values.iter_mut() {
+          *value = placeholder::substitute(value, resolver)?;
+        }
+      }
+
+      let output = execute_config_section(&resolved).await?;
+      Ok(ActionResult {
+        output,
+        skipped: false,
+        run_as: None,
+        outputs: BTreeMap::new(),
+        file: None,
+      })
+    }
 
-      Ok(ActionResult { output })
+    Action::Firewall(opts) => {
+      let output = execute_firewall(opts).await?;
+      Ok(ActionResult {
+        output,
+        skipped: false,
+        run_as: None,
+        outputs: BTreeMap::new(),
+        file: None,
+      })
+    }
+
+    Action::File(opts) => {
+      // Resolve placeholders in the path and source (e.g. a build output to link to)
+      let mut resolved = opts.clone();
+      resolved.path = placeholder::substitute(&opts.path, resolver)?;
+      resolved.source = opts
+        .source
+        .with_value(placeholder::substitute(opts.source.value(), resolver)?);
+
+      let (output, file) = execute_file(&resolved).await?;
+      Ok(ActionResult {
+        output,
+        skipped: false,
+        run_as: None,
+        outputs: BTreeMap::new(),
+        file,
+      })
+    }
+
+    Action::FileBlock(opts) => {
+      // Resolve placeholders in the path and lines (e.g. a built tool's path)
+      let mut resolved = opts.clone();
+      resolved.path = placeholder::substitute(&opts.path, resolver)?;
+      resolved.content = placeholder::substitute(&opts.content, resolver)?;
+
+      let output = execute_file_block(&resolved).await?;
+      Ok(ActionResult {
+        output,
+        skipped: false,
+        run_as: None,
+        outputs: BTreeMap::new(),
+        file: None,
+      })
+    }
+
+    Action::Font(opts) => {
+      // Resolve placeholders in the source (e.g. a fetched font archive)
+      let mut resolved = opts.clone();
+      resolved.source = placeholder::substitute(&opts.source, resolver)?;
+
+      let output = execute_font(&resolved).await?;
+      Ok(ActionResult {
+        output,
+        skipped: false,
+        run_as: None,
+        outputs: BTreeMap::new(),
+        file: None,
+      })
     }
   }
 }
@@ -126,7 +349,7 @@ pub async fn execute_action(
 mod tests {
   use super::*;
   use crate::placeholder::PlaceholderError;
-  use crate::util::testutil::{echo_msg, shell_echo_env};
+  use crate::util::testutil::{echo_msg, shell_cmd, shell_echo_env};
   use tempfile::TempDir;
 
   /// Simple test resolver that returns fixed values.
@@ -193,9 +416,13 @@ mod tests {
       args: Some(args),
       env: None,
       cwd: None,
+      creates: None,
+      unless: None,
+      shell: None,
+      run_as: None,
     });
 
-    let result = execute_action(&action, &resolver, out_dir).await.unwrap();
+    let result = execute_action(&action, &resolver, out_dir, None).await.unwrap();
 
     assert_eq!(result.output, "hello");
   }
@@ -212,9 +439,13 @@ mod tests {
       args: Some(args),
       env: None,
       cwd: None,
+      creates: None,
+      unless: None,
+      shell: None,
+      run_as: None,
     });
 
-    let result = execute_action(&action, &resolver, out_dir).await.unwrap();
+    let result = execute_action(&action, &resolver, out_dir, None).await.unwrap();
 
     assert_eq!(result.output, out_dir.to_string_lossy());
   }
@@ -231,9 +462,13 @@ mod tests {
       args: Some(args),
       env: None,
       cwd: None,
+      creates: None,
+      unless: None,
+      shell: None,
+      run_as: None,
     });
 
-    let result = execute_action(&action, &resolver, out_dir).await.unwrap();
+    let result = execute_action(&action, &resolver, out_dir, None).await.unwrap();
 
     assert_eq!(result.output, "/path/to/file.tar.gz");
   }
@@ -253,10 +488,118 @@ mod tests {
       args: Some(args),
       env: Some(env),
       cwd: None,
+      creates: None,
+      unless: None,
+      shell: None,
+      run_as: None,
     });
 
-    let result = execute_action(&action, &resolver, out_dir).await.unwrap();
+    let result = execute_action(&action, &resolver, out_dir, None).await.unwrap();
 
     assert_eq!(result.output, out_dir.to_string_lossy());
   }
+
+  #[tokio::test]
+  async fn execute_cmd_skipped_when_creates_exists() {
+    let temp_dir = TempDir::new().unwrap();
+    let out_dir = temp_dir.path();
+    let resolver = TestResolver::new(out_dir.to_str().unwrap());
+
+    let (cmd, args) = echo_msg("should not run");
+    let action = Action::Exec(ExecOpts::new(cmd).with_args(args).with_creates("$${{out}}"));
+
+    let result = execute_action(&action, &resolver, out_dir, None).await.unwrap();
+
+    assert!(result.skipped);
+    assert_eq!(result.output, "");
+  }
+
+  #[tokio::test]
+  async fn execute_cmd_resolves_relative_creates_against_working_dir() {
+    let temp_dir = TempDir::new().unwrap();
+    let out_dir = temp_dir.path();
+    let resolver = TestResolver::new(out_dir.to_str().unwrap());
+    std::fs::create_dir(out_dir.join("sub")).unwrap();
+    std::fs::write(out_dir.join("marker"), "").unwrap();
+    std::fs::write(out_dir.join("sub").join("nested"), "").unwrap();
+
+    let (cmd, args) = echo_msg("ran");
+    let sub = out_dir.join("sub");
+    let in_out_dir = Action::Exec(ExecOpts::new(cmd).with_args(args.clone()).with_creates("marker"));
+    let in_cwd = Action::Exec(
+      ExecOpts::new(cmd)
+        .with_args(args.clone())
+        .with_cwd(sub.to_str().unwrap())
+        .with_creates("nested"),
+    );
+    let missing_in_cwd = Action::Exec(
+      ExecOpts::new(cmd)
+        .with_args(args)
+        .with_cwd(sub.to_str().unwrap())
+        .with_creates("marker"),
+    );
+
+    assert!(
+      execute_action(&in_out_dir, &resolver, out_dir, None)
+        .await
+        .unwrap()
+        .skipped
+    );
+    assert!(execute_action(&in_cwd, &resolver, out_dir, None).await.unwrap().skipped);
+    let result = execute_action(&missing_in_cwd, &resolver, out_dir, None).await.unwrap();
+    assert!(!result.skipped);
+    assert_eq!(result.output, "ran");
+  }
+
+  #[tokio::test]
+  async fn execute_cmd_runs_when_creates_missing() {
+    let temp_dir = TempDir::new().unwrap();
+    let out_dir = temp_dir.path();
+    let resolver = TestResolver::new(out_dir.to_str().unwrap());
+
+    let missing = out_dir.join("missing-marker");
+    let (cmd, args) = echo_msg("ran");
+    let action = Action::Exec(
+      ExecOpts::new(cmd)
+        .with_args(args)
+        .with_creates(missing.to_str().unwrap()),
+    );
+
+    let result = execute_action(&action, &resolver, out_dir, None).await.unwrap();
+
+    assert!(!result.skipped);
+    assert_eq!(result.output, "ran");
+  }
+
+  #[tokio::test]
+  async fn execute_cmd_skipped_when_unless_succeeds() {
+    let temp_dir = TempDir::new().unwrap();
+    let out_dir = temp_dir.path();
+    let resolver = TestResolver::new(out_dir.to_str().unwrap());
+
+    let (cmd, args) = shell_cmd("exit 1");
+    let action = Action::Exec(ExecOpts::new(cmd).with_args(args).with_unless("exit 0"));
+
+    let result = execute_action(&action, &resolver, out_dir, None).await.unwrap();
+
+    assert!(result.skipped);
+  }
+
+  #[tokio::test]
+  #[cfg(unix)]
+  async fn execute_cmd_through_shell() {
+    let temp_dir = TempDir::new().unwrap();
+    let out_dir = temp_dir.path();
+    let resolver = TestResolver::new(out_dir.to_str().unwrap());
+
+    let action = Action::Exec(
+      ExecOpts::new("x=1; echo $((x + 1))")
+        .with_args(vec!["it's quoted".to_string()])
+        .with_shell(crate::platform::Shell::Sh),
+    );
+
+    let result = execute_action(&action, &resolver, out_dir, None).await.unwrap();
+
+    assert_eq!(result.output, "2 it's quoted");
+  }
 }
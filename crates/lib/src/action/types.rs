@@ -1,6 +1,11 @@
 use serde::{Deserialize, Serialize};
 
+use crate::action::actions::config_section::ConfigSectionOpts;
 use crate::action::actions::exec::ExecOpts;
+use crate::action::actions::file::FileOpts;
+use crate::action::actions::file_block::FileBlockOpts;
+use crate::action::actions::firewall::FirewallOpts;
+use crate::action::actions::font::FontOpts;
 
 /// Key for storing registered build ctx methods in Lua's registry.
 pub const BUILD_CTX_METHODS_REGISTRY_KEY: &str = "__syslua_build_ctx_methods";
@@ -8,6 +13,12 @@ pub const BUILD_CTX_METHODS_REGISTRY_KEY: &str = "__syslua_build_ctx_methods";
 /// Key for storing registered bind ctx methods in Lua's registry.
 pub const BIND_CTX_METHODS_REGISTRY_KEY: &str = "__syslua_bind_ctx_methods";
 
+/// Key for storing the config-level default shell (`settings.shell`) in Lua's registry.
+pub const DEFAULT_SHELL_REGISTRY_KEY: &str = "__syslua_default_shell";
+
+/// Key for storing the users exec actions may run as (`settings.run_as`) in Lua's registry.
+pub const RUN_AS_USERS_REGISTRY_KEY: &str = "__syslua_run_as_users";
+
 /// An action that can be performed during build execution.
 ///
 /// Build actions are the primitive operations that builds can perform.
@@ -17,6 +28,11 @@ pub const BIND_CTX_METHODS_REGISTRY_KEY: &str = "__syslua_bind_ctx_methods";
 ///
 /// - [`FetchUrl`](Action::FetchUrl): Download a file with integrity verification
 /// - [`Exec`](Action::Exec): Execute a shell command
+/// - [`ConfigSection`](Action::ConfigSection): Manage a section of a git or ssh config file
+/// - [`Firewall`](Action::Firewall): Add, delete or check a named host firewall rule
+/// - [`File`](Action::File): Write a file, symlink or copy, with its mode and owner
+/// - [`FileBlock`](Action::FileBlock): Manage a delimited block of a file
+/// - [`Font`](Action::Font): Install, remove or check fonts
 ///
 /// # Placeholder Resolution
 ///
@@ -33,13 +49,67 @@ pub enum Action {
   ///
   /// - `url`: The URL to download
   /// - `sha256`: Expected SHA-256 hash of the downloaded content (lowercase hex)
-  FetchUrl { url: String, sha256: String },
+  /// - `unpack`: Also unpack the archive; its outputs are then the unpacked
+  ///   directory, with the named outputs `archive` and `src`
+  FetchUrl {
+    url: String,
+    sha256: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    unpack: bool,
+  },
   /// Execute a binary.
   ///
   /// # Fields
   ///
   /// - `opts`: Execution options
   Exec(ExecOpts),
+  /// Write, remove or check one syslua-managed section of a git or ssh config.
+  ///
+  /// The rest of the file is left untouched.
+  ///
+  /// # Fields
+  ///
+  /// - `opts`: Config file, section and entries
+  ConfigSection(ConfigSectionOpts),
+  /// Add, delete or check one syslua-tagged rule of the host firewall.
+  ///
+  /// # Fields
+  ///
+  /// - `opts`: The rule and what to do with it
+  Firewall(FirewallOpts),
+  /// Write, remove or check one syslua-managed block of a file, between
+  /// marker comments.
+  ///
+  /// The rest of the file is left untouched.
+  ///
+  /// # Fields
+  ///
+  /// - `opts`: File, marker and lines of the block
+  FileBlock(FileBlockOpts),
+  /// Write a file, create a symlink or copy a file or directory to a path,
+  /// optionally giving it a mode, owner and group.
+  ///
+  /// # Fields
+  ///
+  /// - `opts`: Path, what to put there, and its mode and ownership
+  File(FileOpts),
+  /// Install, remove or check the fonts of a file or directory, in the
+  /// directory the OS looks for fonts in, refreshing its font cache.
+  ///
+  /// # Fields
+  ///
+  /// - `opts`: Name, source and scope of the fonts
+  Font(FontOpts),
+}
+
+impl Action {
+  /// Names of the action's sub-outputs, referenced as `$${{action:N:<name>}}`.
+  pub fn named_outputs(&self) -> &'static [&'static str] {
+    match self {
+      Action::FetchUrl { unpack: true, .. } => &["archive", "src"],
+      _ => &[],
+    }
+  }
 }
 
 /// Context passed to build `apply` functions for recording actions.
@@ -127,9 +197,29 @@ impl ActionCtx {
     self.record_action(Action::FetchUrl {
       url: url.to_string(),
       sha256: sha256.to_string(),
+      unpack: false,
     })
   }
 
+  /// Record a URL fetch action that also unpacks the archive, and return
+  /// placeholders for the downloaded archive and the unpacked directory.
+  ///
+  /// # Returns
+  ///
+  /// The placeholders `$${{action:N:archive}}` and `$${{action:N:src}}`.
+  pub fn fetch_and_unpack(&mut self, url: &str, sha256: &str) -> (String, String) {
+    let index = self.actions.len();
+    self.record_action(Action::FetchUrl {
+      url: url.to_string(),
+      sha256: sha256.to_string(),
+      unpack: true,
+    });
+    (
+      format!("$${{{{action:{}:archive}}}}", index),
+      format!("$${{{{action:{}:src}}}}", index),
+    )
+  }
+
   /// Record a command execution action and return a placeholder for its output.
   ///
   /// The returned placeholder resolves to the command's stdout at execution time.
@@ -147,6 +237,46 @@ impl ActionCtx {
     self.record_action(Action::Exec(opts))
   }
 
+  /// Record a config section action and return a placeholder for its output.
+  ///
+  /// The returned placeholder resolves to the config file path, or to
+  /// `"true"`/`"false"` (drifted or not) for a check.
+  pub fn config_section(&mut self, opts: ConfigSectionOpts) -> String {
+    self.record_action(Action::ConfigSection(opts))
+  }
+
+  /// Record a firewall rule action and return a placeholder for its output.
+  ///
+  /// The returned placeholder resolves to the rule's tag, or to
+  /// `"true"`/`"false"` (missing or not) for a check.
+  pub fn firewall_rule(&mut self, opts: FirewallOpts) -> String {
+    self.record_action(Action::Firewall(opts))
+  }
+
+  /// Record a file block action and return a placeholder for its output.
+  ///
+  /// The returned placeholder resolves to the file path, or to
+  /// `"true"`/`"false"` (drifted or not) for a check.
+  pub fn file_block(&mut self, opts: FileBlockOpts) -> String {
+    self.record_action(Action::FileBlock(opts))
+  }
+
+  /// Record a file action and return a placeholder for its output.
+  ///
+  /// The returned placeholder resolves to the path, or to
+  /// `"true"`/`"false"` (drifted or not) for a check.
+  pub fn file(&mut self, opts: FileOpts) -> String {
+    self.record_action(Action::File(opts))
+  }
+
+  /// Record a font action and return a placeholder for its output.
+  ///
+  /// The returned placeholder resolves to the fonts directory, or to
+  /// `"true"`/`"false"` (drifted or not) for a check.
+  pub fn font(&mut self, opts: FontOpts) -> String {
+    self.record_action(Action::Font(opts))
+  }
+
   /// Internal helper to record an action and return its placeholder.
   fn record_action(&mut self, action: Action) -> String {
     let index = self.actions.len();
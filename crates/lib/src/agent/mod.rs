@@ -0,0 +1,384 @@
+//! Pull-based config management: re-apply a config from git on a schedule.
+//!
+//! `sys agent install` saves an [`AgentConfig`] to `<root>/agent/agent.json`
+//! and registers a [`service`] (systemd timer, launchd job or scheduled task)
+//! that runs `sys agent run` every interval. Each run waits a random delay of
+//! up to the configured jitter, so a fleet of machines doesn't hit the git
+//! server at once, then fetches the latest commit of the source and applies
+//! the config file in that checkout. The outcome is recorded in
+//! `<root>/agent/status.json` ([`AgentStatus`]) for `sys status --agent`.
+//!
+//! After a failure, runs are skipped until a backoff expires: one interval
+//! after the first failure, doubling with each further one, up to the
+//! configured maximum. A successful run resets it.
+//!
+//! With a backup directory configured, each successful run also backs up the
+//! store there (see [`crate::store_backup`]).
+
+pub mod service;
+
+use std::hash::{BuildHasher, RandomState};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::execute::metrics::MetricsSink;
+use crate::inputs::fetch::{FetchError, fetch_git};
+use crate::inputs::source::{self, InputSource};
+use crate::platform::paths::root_dir;
+
+/// Name of the agent's checkout of its source, under [`agent_dir`].
+const CHECKOUT_NAME: &str = "source";
+
+/// Config file applied when none is given.
+pub const DEFAULT_CONFIG_FILE: &str = "init.lua";
+
+#[derive(Debug, Error)]
+pub enum AgentError {
+  #[error("the agent is not installed (run 'sys agent install')")]
+  NotInstalled,
+
+  #[error("invalid agent source '{url}': {message}")]
+  InvalidSource { url: String, message: String },
+
+  #[error("failed to fetch '{url}': {source}")]
+  Fetch {
+    url: String,
+    #[source]
+    source: FetchError,
+  },
+
+  #[error("config file '{}' not found in the agent source", .0.display())]
+  MissingConfig(PathBuf),
+
+  #[error("failed to access {}: {source}", path.display())]
+  Io {
+    path: PathBuf,
+    #[source]
+    source: io::Error,
+  },
+
+  #[error("invalid {}: {source}", path.display())]
+  Json {
+    path: PathBuf,
+    #[source]
+    source: serde_json::Error,
+  },
+
+  #[error("failed to set up the agent service: {0}")]
+  Service(String),
+}
+
+/// Directory holding the agent's config, status and checkout.
+pub fn agent_dir() -> PathBuf {
+  root_dir().join("agent")
+}
+
+/// What the agent applies, and how often.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentConfig {
+  /// Git URL of the config repository, optionally with a `#rev` (branch, tag
+  /// or commit); the `git:` prefix is optional.
+  pub source: String,
+  /// Config file to apply, relative to the repository root.
+  #[serde(default = "default_config_file")]
+  pub config: PathBuf,
+  /// Seconds between runs.
+  pub interval_secs: u64,
+  /// Upper bound of the random delay before each run, in seconds.
+  #[serde(default)]
+  pub jitter_secs: u64,
+  /// Upper bound of the wait after repeated failures, in seconds.
+  pub max_backoff_secs: u64,
+  /// Repair drifted binds on each run, like `sys apply --repair`.
+  #[serde(default)]
+  pub repair: bool,
+  /// Where to publish the metrics of each run, like `sys apply --metrics-*`.
+  #[serde(default, skip_serializing_if = "MetricsSink::is_empty")]
+  pub metrics: MetricsSink,
+  /// Back up the store to this directory after each successful run, like
+  /// `sys store backup`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub backup: Option<PathBuf>,
+}
+
+fn default_config_file() -> PathBuf {
+  PathBuf::from(DEFAULT_CONFIG_FILE)
+}
+
+impl AgentConfig {
+  pub fn path() -> PathBuf {
+    agent_dir().join("agent.json")
+  }
+
+  /// The installed config, or `None` if the agent isn't installed.
+  pub fn load() -> Result<Option<Self>, AgentError> {
+    read_json(&Self::path())
+  }
+
+  pub fn save(&self) -> Result<(), AgentError> {
+    write_json(&Self::path(), self)
+  }
+
+  /// The git URL and revision of [`source`](Self::source).
+  pub fn git_source(&self) -> Result<(String, Option<String>), AgentError> {
+    let url = if self.source.starts_with("git:") {
+      self.source.clone()
+    } else {
+      format!("git:{}", self.source)
+    };
+    match source::parse(&url) {
+      Ok(InputSource::Git { url, rev }) => Ok((url, rev)),
+      Ok(_) => Err(AgentError::InvalidSource {
+        url: self.source.clone(),
+        message: "not a git URL".to_string(),
+      }),
+      Err(e) => Err(AgentError::InvalidSource {
+        url: self.source.clone(),
+        message: e.to_string(),
+      }),
+    }
+  }
+
+  /// How long to wait after `failures` consecutive failed runs.
+  pub fn backoff(&self, failures: u32) -> Duration {
+    if failures == 0 {
+      return Duration::ZERO;
+    }
+    let factor = 1u64.checked_shl(failures - 1).unwrap_or(u64::MAX);
+    let secs = self.interval_secs.saturating_mul(factor);
+    Duration::from_secs(secs.min(self.max_backoff_secs.max(self.interval_secs)))
+  }
+
+  /// A random delay of up to [`jitter_secs`](Self::jitter_secs).
+  pub fn jitter(&self) -> Duration {
+    if self.jitter_secs == 0 {
+      return Duration::ZERO;
+    }
+    // RandomState is seeded randomly per process
+    let random = RandomState::new().hash_one((SystemTime::now(), std::process::id()));
+    Duration::from_secs(random % (self.jitter_secs + 1))
+  }
+}
+
+/// Outcome of the agent's runs, as shown by `sys status --agent`.
+///
+/// Times are Unix timestamps in seconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentStatus {
+  /// When the last run started.
+  pub last_run: Option<u64>,
+  /// When the last successful run finished.
+  pub last_success: Option<u64>,
+  /// Failed runs since the last successful one.
+  pub consecutive_failures: u32,
+  /// Error of the last run, if it failed.
+  pub last_error: Option<String>,
+  /// Commit applied by the last successful run.
+  pub rev: Option<String>,
+  /// Snapshot created by the last successful run.
+  pub snapshot_id: Option<String>,
+  /// Runs before this time are skipped, while backing off after failures.
+  pub retry_after: Option<u64>,
+}
+
+impl AgentStatus {
+  pub fn path() -> PathBuf {
+    agent_dir().join("status.json")
+  }
+
+  /// The recorded status; empty if the agent hasn't run yet.
+  pub fn load() -> Result<Self, AgentError> {
+    Ok(read_json(&Self::path())?.unwrap_or_default())
+  }
+
+  pub fn save(&self) -> Result<(), AgentError> {
+    write_json(&Self::path(), self)
+  }
+
+  /// Whether a run at `now` should go ahead rather than keep backing off.
+  pub fn is_due(&self, now: u64) -> bool {
+    self.retry_after.is_none_or(|retry_after| now >= retry_after)
+  }
+
+  pub fn record_success(&mut self, now: u64, rev: String, snapshot_id: Option<String>) {
+    self.last_success = Some(now);
+    self.consecutive_failures = 0;
+    self.last_error = None;
+    self.rev = Some(rev);
+    self.snapshot_id = snapshot_id;
+    self.retry_after = None;
+  }
+
+  pub fn record_failure(&mut self, now: u64, error: String, config: &AgentConfig) {
+    self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+    self.last_error = Some(error);
+    self.retry_after = Some(now + config.backoff(self.consecutive_failures).as_secs());
+  }
+}
+
+/// Fetch the latest commit of the agent's source.
+///
+/// Returns the path of the config file in the checkout and the commit hash.
+pub fn checkout(config: &AgentConfig) -> Result<(PathBuf, String), AgentError> {
+  let (url, rev) = config.git_source()?;
+  let (repo, commit) =
+    fetch_git(CHECKOUT_NAME, &url, rev.as_deref(), &agent_dir()).map_err(|source| AgentError::Fetch {
+      url: url.clone(),
+      source,
+    })?;
+  let file = repo.join(&config.config);
+  if !file.is_file() {
+    return Err(AgentError::MissingConfig(config.config.clone()));
+  }
+  Ok((file, commit))
+}
+
+/// The current Unix timestamp in seconds.
+pub fn unix_now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .expect("system time before Unix epoch")
+    .as_secs()
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, AgentError> {
+  let content = match std::fs::read_to_string(path) {
+    Ok(content) => content,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+    Err(source) => {
+      return Err(AgentError::Io {
+        path: path.to_path_buf(),
+        source,
+      });
+    }
+  };
+  serde_json::from_str(&content)
+    .map(Some)
+    .map_err(|source| AgentError::Json {
+      path: path.to_path_buf(),
+      source,
+    })
+}
+
+/// Write `value` as JSON, through a temporary file so readers never see a
+/// partial document.
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), AgentError> {
+  let io_err = |source| AgentError::Io {
+    path: path.to_path_buf(),
+    source,
+  };
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent).map_err(io_err)?;
+  }
+  let content = serde_json::to_string_pretty(value).map_err(|source| AgentError::Json {
+    path: path.to_path_buf(),
+    source,
+  })?;
+  let tmp = path.with_extension("json.tmp");
+  std::fs::write(&tmp, content).map_err(io_err)?;
+  std::fs::rename(&tmp, path).map_err(io_err)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serial_test::serial;
+
+  fn config() -> AgentConfig {
+    AgentConfig {
+      source: "https://example.com/org/config.git#main".to_string(),
+      config: default_config_file(),
+      interval_secs: 600,
+      jitter_secs: 60,
+      max_backoff_secs: 3600,
+      repair: false,
+      metrics: MetricsSink::default(),
+      backup: None,
+    }
+  }
+
+  #[test]
+  fn git_source_accepts_urls_with_or_without_prefix() {
+    let (url, rev) = config().git_source().unwrap();
+    assert_eq!(url, "https://example.com/org/config.git");
+    assert_eq!(rev.as_deref(), Some("main"));
+
+    let prefixed = AgentConfig {
+      source: "git:https://example.com/org/config.git".to_string(),
+      ..config()
+    };
+    assert_eq!(prefixed.git_source().unwrap().1, None);
+
+    let empty = AgentConfig {
+      source: String::new(),
+      ..config()
+    };
+    assert!(matches!(empty.git_source(), Err(AgentError::InvalidSource { .. })));
+  }
+
+  #[test]
+  fn backoff_doubles_up_to_the_maximum() {
+    let config = config();
+    assert_eq!(config.backoff(0), Duration::ZERO);
+    assert_eq!(config.backoff(1), Duration::from_secs(600));
+    assert_eq!(config.backoff(2), Duration::from_secs(1200));
+    assert_eq!(config.backoff(3), Duration::from_secs(2400));
+    assert_eq!(config.backoff(4), Duration::from_secs(3600));
+    assert_eq!(config.backoff(200), Duration::from_secs(3600));
+  }
+
+  #[test]
+  fn jitter_stays_within_bounds() {
+    let config = config();
+    for _ in 0..100 {
+      assert!(config.jitter() <= Duration::from_secs(60));
+    }
+    let none = AgentConfig {
+      jitter_secs: 0,
+      ..config
+    };
+    assert_eq!(none.jitter(), Duration::ZERO);
+  }
+
+  #[test]
+  fn status_backs_off_after_failures_until_a_success() {
+    let config = config();
+    let mut status = AgentStatus::default();
+    assert!(status.is_due(1000));
+
+    status.record_failure(1000, "fetch failed".to_string(), &config);
+    status.record_failure(1600, "fetch failed".to_string(), &config);
+    assert_eq!(status.consecutive_failures, 2);
+    assert_eq!(status.retry_after, Some(2800));
+    assert!(!status.is_due(2000));
+    assert!(status.is_due(2800));
+
+    status.record_success(2800, "abc123".to_string(), Some("42".to_string()));
+    assert_eq!(status.consecutive_failures, 0);
+    assert_eq!(status.last_error, None);
+    assert!(status.is_due(2800));
+    assert_eq!(status.rev.as_deref(), Some("abc123"));
+  }
+
+  #[test]
+  #[serial]
+  fn config_and_status_round_trip() {
+    let temp = tempfile::tempdir().unwrap();
+    temp_env::with_var("SYSLUA_ROOT", Some(temp.path()), || {
+      assert_eq!(AgentConfig::load().unwrap(), None);
+      assert_eq!(AgentStatus::load().unwrap(), AgentStatus::default());
+
+      config().save().unwrap();
+      assert_eq!(AgentConfig::load().unwrap(), Some(config()));
+
+      let mut status = AgentStatus::default();
+      status.record_failure(1000, "boom".to_string(), &config());
+      status.save().unwrap();
+      assert_eq!(AgentStatus::load().unwrap(), status);
+    });
+  }
+}
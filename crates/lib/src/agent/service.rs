@@ -0,0 +1,321 @@
+//! The platform service that runs `sys agent run` on a schedule.
+//!
+//! - Linux: a systemd service and timer, user units in
+//!   `~/.config/systemd/user` or system units in `/etc/systemd/system` when
+//!   elevated.
+//! - macOS: a launchd job in `~/Library/LaunchAgents`, or
+//!   `/Library/LaunchDaemons` when elevated.
+//! - Windows: a scheduled task, run as SYSTEM when elevated.
+//!
+//! The service passes the current `SYSLUA_ROOT` on (except scheduled tasks,
+//! which can't set environment variables), so the agent finds the config it
+//! was installed with.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use super::{AgentError, agent_dir};
+
+/// Name of the systemd units and the scheduled task.
+pub const SERVICE_NAME: &str = "syslua-agent";
+
+/// Label of the launchd job.
+pub const LAUNCHD_LABEL: &str = "org.syslua.agent";
+
+/// Files and commands that install or remove the service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceDefinition {
+  /// Files to write, with their contents.
+  pub files: Vec<(PathBuf, String)>,
+  /// Commands that start the schedule once the files are written.
+  pub enable: Vec<Vec<String>>,
+  /// Commands that stop the schedule before the files are removed.
+  pub disable: Vec<Vec<String>>,
+}
+
+impl ServiceDefinition {
+  /// The service for this platform, running `exe` every `interval`.
+  pub fn for_platform(exe: &Path, root: &Path, interval: Duration, system: bool) -> Self {
+    if cfg!(target_os = "macos") {
+      launchd(exe, root, interval, system)
+    } else if cfg!(windows) {
+      scheduled_task(exe, interval, system)
+    } else {
+      systemd(exe, root, interval, system)
+    }
+  }
+
+  pub fn install(&self) -> Result<(), AgentError> {
+    for (path, content) in &self.files {
+      if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|source| AgentError::Io {
+          path: parent.to_path_buf(),
+          source,
+        })?;
+      }
+      std::fs::write(path, content).map_err(|source| AgentError::Io {
+        path: path.clone(),
+        source,
+      })?;
+    }
+    self.enable.iter().try_for_each(|command| run(command))
+  }
+
+  /// Stop the schedule and remove the files; files already gone are fine.
+  pub fn uninstall(&self) -> Result<(), AgentError> {
+    for command in &self.disable {
+      // The schedule may already be stopped
+      if let Err(e) = run(command) {
+        tracing::debug!(error = %e, "disabling the agent service failed");
+      }
+    }
+    for (path, _) in &self.files {
+      match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(source) => {
+          return Err(AgentError::Io {
+            path: path.clone(),
+            source,
+          });
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// A systemd `.service` running the agent once, and a `.timer` starting it.
+pub fn systemd(exe: &Path, root: &Path, interval: Duration, system: bool) -> ServiceDefinition {
+  let unit_dir = if system {
+    PathBuf::from("/etc/systemd/system")
+  } else {
+    std::env::var("XDG_CONFIG_HOME")
+      .map(PathBuf::from)
+      .unwrap_or_else(|_| crate::platform::paths::home_dir().join(".config"))
+      .join("systemd")
+      .join("user")
+  };
+  let service = format!(
+    "[Unit]\n\
+     Description=Apply the syslua config from git\n\
+     Wants=network-online.target\n\
+     After=network-online.target\n\
+     \n\
+     [Service]\n\
+     Type=oneshot\n\
+     Environment=\"SYSLUA_ROOT={}\"\n\
+     ExecStart=\"{}\" agent run\n",
+    root.display(),
+    exe.display()
+  );
+  let timer = format!(
+    "[Unit]\n\
+     Description=Apply the syslua config from git every {}s\n\
+     \n\
+     [Timer]\n\
+     OnBootSec=5min\n\
+     OnUnitActiveSec={}s\n\
+     \n\
+     [Install]\n\
+     WantedBy=timers.target\n",
+    interval.as_secs(),
+    interval.as_secs()
+  );
+
+  let systemctl = |args: &[&str]| {
+    let mut command = vec!["systemctl".to_string()];
+    if !system {
+      command.push("--user".to_string());
+    }
+    command.extend(args.iter().map(|arg| arg.to_string()));
+    command
+  };
+  let timer_name = format!("{}.timer", SERVICE_NAME);
+  ServiceDefinition {
+    files: vec![
+      (unit_dir.join(format!("{}.service", SERVICE_NAME)), service),
+      (unit_dir.join(&timer_name), timer),
+    ],
+    enable: vec![
+      systemctl(&["daemon-reload"]),
+      systemctl(&["enable", "--now", &timer_name]),
+    ],
+    disable: vec![systemctl(&["disable", "--now", &timer_name])],
+  }
+}
+
+/// A launchd job running the agent every `interval`.
+pub fn launchd(exe: &Path, root: &Path, interval: Duration, system: bool) -> ServiceDefinition {
+  let dir = if system {
+    PathBuf::from("/Library/LaunchDaemons")
+  } else {
+    crate::platform::paths::home_dir().join("Library").join("LaunchAgents")
+  };
+  let path = dir.join(format!("{}.plist", LAUNCHD_LABEL));
+  let log = agent_dir().join("agent.log");
+  let plist = format!(
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>Label</key>
+  <string>{label}</string>
+  <key>ProgramArguments</key>
+  <array>
+    <string>{exe}</string>
+    <string>agent</string>
+    <string>run</string>
+  </array>
+  <key>EnvironmentVariables</key>
+  <dict>
+    <key>SYSLUA_ROOT</key>
+    <string>{root}</string>
+  </dict>
+  <key>StartInterval</key>
+  <integer>{interval}</integer>
+  <key>StandardOutPath</key>
+  <string>{log}</string>
+  <key>StandardErrorPath</key>
+  <string>{log}</string>
+</dict>
+</plist>
+"#,
+    label = LAUNCHD_LABEL,
+    exe = xml_escape(&exe.display().to_string()),
+    root = xml_escape(&root.display().to_string()),
+    interval = interval.as_secs(),
+    log = xml_escape(&log.display().to_string()),
+  );
+
+  let launchctl = |action: &str| {
+    vec![
+      "launchctl".to_string(),
+      action.to_string(),
+      "-w".to_string(),
+      path.display().to_string(),
+    ]
+  };
+  ServiceDefinition {
+    enable: vec![launchctl("load")],
+    disable: vec![launchctl("unload")],
+    files: vec![(path.clone(), plist)],
+  }
+}
+
+/// A scheduled task running the agent every `interval`, rounded to minutes.
+pub fn scheduled_task(exe: &Path, interval: Duration, system: bool) -> ServiceDefinition {
+  // schtasks accepts 1 to 1439 minutes
+  let minutes = (interval.as_secs() / 60).clamp(1, 1439);
+  let mut create = vec![
+    "schtasks".to_string(),
+    "/Create".to_string(),
+    "/F".to_string(),
+    "/TN".to_string(),
+    SERVICE_NAME.to_string(),
+    "/SC".to_string(),
+    "MINUTE".to_string(),
+    "/MO".to_string(),
+    minutes.to_string(),
+    "/TR".to_string(),
+    format!("\"{}\" agent run", exe.display()),
+  ];
+  if system {
+    create.extend(["/RU".to_string(), "SYSTEM".to_string()]);
+  }
+  ServiceDefinition {
+    files: Vec::new(),
+    enable: vec![create],
+    disable: vec![vec![
+      "schtasks".to_string(),
+      "/Delete".to_string(),
+      "/F".to_string(),
+      "/TN".to_string(),
+      SERVICE_NAME.to_string(),
+    ]],
+  }
+}
+
+fn run(command: &[String]) -> Result<(), AgentError> {
+  let (program, args) = command.split_first().expect("service command is empty");
+  let status = Command::new(program)
+    .args(args)
+    .status()
+    .map_err(|e| AgentError::Service(format!("failed to run {}: {}", program, e)))?;
+  if !status.success() {
+    return Err(AgentError::Service(format!(
+      "'{}' failed: {}",
+      command.join(" "),
+      status
+    )));
+  }
+  Ok(())
+}
+
+fn xml_escape(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn systemd_units_run_the_agent_on_a_timer() {
+    let def = systemd(
+      Path::new("/usr/bin/sys"),
+      Path::new("/syslua"),
+      Duration::from_secs(1800),
+      true,
+    );
+    let (service_path, service) = &def.files[0];
+    let (timer_path, timer) = &def.files[1];
+    assert_eq!(service_path, Path::new("/etc/systemd/system/syslua-agent.service"));
+    assert_eq!(timer_path, Path::new("/etc/systemd/system/syslua-agent.timer"));
+    assert!(service.contains("ExecStart=\"/usr/bin/sys\" agent run\n"));
+    assert!(service.contains("Environment=\"SYSLUA_ROOT=/syslua\"\n"));
+    assert!(timer.contains("OnUnitActiveSec=1800s\n"));
+    assert_eq!(def.enable[1], ["systemctl", "enable", "--now", "syslua-agent.timer"]);
+
+    let user = systemd(
+      Path::new("/usr/bin/sys"),
+      Path::new("/syslua"),
+      Duration::from_secs(1800),
+      false,
+    );
+    assert!(user.files[0].0.ends_with("systemd/user/syslua-agent.service"));
+    assert_eq!(
+      user.disable[0],
+      ["systemctl", "--user", "disable", "--now", "syslua-agent.timer"]
+    );
+  }
+
+  #[test]
+  fn launchd_job_escapes_paths() {
+    let def = launchd(
+      Path::new("/opt/a&b/sys"),
+      Path::new("/syslua"),
+      Duration::from_secs(900),
+      true,
+    );
+    let (path, plist) = &def.files[0];
+    assert_eq!(path, Path::new("/Library/LaunchDaemons/org.syslua.agent.plist"));
+    assert!(plist.contains("<string>/opt/a&amp;b/sys</string>"));
+    assert!(plist.contains("<integer>900</integer>"));
+    assert_eq!(def.enable[0][..3], ["launchctl", "load", "-w"]);
+  }
+
+  #[test]
+  fn scheduled_task_rounds_the_interval_to_minutes() {
+    let def = scheduled_task(Path::new("C:\\sys.exe"), Duration::from_secs(30), true);
+    let create = &def.enable[0];
+    assert_eq!(create[8], "1");
+    assert_eq!(create[10], "\"C:\\sys.exe\" agent run");
+    assert_eq!(create[11..], ["/RU", "SYSTEM"]);
+
+    let def = scheduled_task(Path::new("sys.exe"), Duration::from_secs(7 * 24 * 3600), false);
+    assert_eq!(def.enable[0][8], "1439");
+    assert_eq!(def.enable[0].len(), 11);
+  }
+}
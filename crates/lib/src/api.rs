@@ -0,0 +1,655 @@
+//! Stable programmatic interface for third-party tools.
+//!
+//! The rest of this crate exposes its internals freely and makes no
+//! compatibility promises. This module is the curated surface that GUIs,
+//! daemons and scripts should use instead: each operation takes a plain
+//! request struct and returns a response struct, and both (and [`ApiError`])
+//! round-trip through serde. No Lua runtime types cross this boundary.
+//!
+//! | Operation    | Request             | Response             |
+//! |--------------|---------------------|----------------------|
+//! | [`evaluate`] | [`EvaluateRequest`] | [`EvaluateResponse`] |
+//! | [`plan`]     | [`PlanRequest`]     | [`PlanResponse`]     |
+//! | [`apply`]    | [`ApplyRequest`]    | [`ApplyResponse`]    |
+//! | [`destroy`]  | [`DestroyRequest`]  | [`DestroyResponse`]  |
+//! | [`gc`]       | [`GcRequest`]       | [`GcResponse`]       |
+//!
+//! [`execution_dag`] turns the manifest of an [`EvaluateResponse`] into an
+//! [`ExecutionDag`] for dependency queries, such as the binds that depend on
+//! a given bind.
+//!
+//! Breaking changes to these types bump [`API_VERSION`].
+//!
+//! # Example
+//! ```ignore
+//! use syslua_lib::api::{self, PlanRequest};
+//!
+//! let request = PlanRequest::new("init.lua");
+//! let response = api::plan(&request).await?;
+//! println!("{} build(s) to realize", response.diff.builds_to_realize.len());
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::action::actions::rate_limit::RateLimit;
+use crate::eval::{EvalError, EvalOptions, evaluate_config};
+use crate::execute::failures::DEFAULT_FAILURE_TTL_SECS;
+use crate::execute::limits::ActionLimits;
+use crate::execute::types::DriftResult;
+use crate::execute::{
+  self, ApplyError, ApplyOptions, ApplyResult, BindTouches, DagResult, DestroyOptions, ExecuteConfig, FailPoint,
+  PlanOptions, PlanReport, PreviousFailure,
+};
+use crate::gc::{GcError, collect_garbage};
+use crate::lua::diagnostics::Diagnostic;
+use crate::lua::sandbox::UntrustedInputs;
+use crate::manifest::Manifest;
+use crate::platform::Platform;
+use crate::platform::priority::Throttle;
+use crate::snapshot::{GroupChanges, SnapshotError, StateDiff};
+use crate::store_lock::{LockMode, StoreLock, StoreLockError};
+use crate::util::hash::Hashable;
+
+pub use crate::execute::dag::{DagNode, ExecutionDag};
+
+/// Version of the request/response types in this module.
+pub const API_VERSION: u32 = 1;
+
+/// Errors returned by API operations.
+///
+/// Underlying errors are flattened to their messages so the error can be
+/// serialized and sent across process boundaries. Lua errors keep their
+/// [`Diagnostic`], so the spec and traceback reach the CLI too.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum ApiError {
+  /// The configuration file does not exist.
+  #[error("config file not found: {0}")]
+  ConfigNotFound(String),
+
+  /// Evaluating the configuration failed.
+  #[error("evaluation failed: {0}")]
+  Eval(String),
+
+  /// Lua code raised an error while evaluating the configuration.
+  #[error("{0}")]
+  Lua(Diagnostic),
+
+  /// Loading or saving snapshots failed.
+  #[error("state error: {0}")]
+  State(String),
+
+  /// The store lock could not be acquired.
+  #[error("store is locked: {0}")]
+  Lock(String),
+
+  /// Realizing builds or applying/destroying binds failed.
+  #[error("execution failed: {0}")]
+  Execute(String),
+
+  /// Garbage collection failed.
+  #[error("garbage collection failed: {0}")]
+  Gc(String),
+}
+
+impl From<EvalError> for ApiError {
+  fn from(e: EvalError) -> Self {
+    match e {
+      EvalError::Lua(_) => Self::Lua(Diagnostic::from_error(&e)),
+      e => Self::Eval(e.to_string()),
+    }
+  }
+}
+
+impl From<SnapshotError> for ApiError {
+  fn from(e: SnapshotError) -> Self {
+    Self::State(e.to_string())
+  }
+}
+
+impl From<StoreLockError> for ApiError {
+  fn from(e: StoreLockError) -> Self {
+    Self::Lock(e.to_string())
+  }
+}
+
+impl From<GcError> for ApiError {
+  fn from(e: GcError) -> Self {
+    Self::Gc(e.to_string())
+  }
+}
+
+impl From<ApplyError> for ApiError {
+  fn from(e: ApplyError) -> Self {
+    match e {
+      ApplyError::ConfigNotFound(path) => Self::ConfigNotFound(path.display().to_string()),
+      ApplyError::Eval(e) => e.into(),
+      ApplyError::Snapshot(e) => e.into(),
+      ApplyError::Lock(e) => e.into(),
+      ApplyError::Hash(e) => Self::Eval(format!("failed to hash manifest: {}", e)),
+      other => Self::Execute(other.to_string()),
+    }
+  }
+}
+
+/// Request to evaluate a configuration into a manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvaluateRequest {
+  /// Path to the Lua configuration file.
+  pub config: PathBuf,
+  /// Allow impure Lua libs (io, os).
+  pub impure: bool,
+  /// Replacement URLs for root inputs (name -> URL).
+  pub input_overrides: BTreeMap<String, String>,
+  /// How to evaluate inputs that aren't declared `trusted = true`.
+  pub untrusted_inputs: UntrustedInputs,
+  /// Host vars file to load instead of `host_vars/<hostname>.lua`.
+  pub vars_file: Option<PathBuf>,
+  /// Platform to evaluate for instead of this host's.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub platform: Option<Platform>,
+}
+
+impl EvaluateRequest {
+  /// Create a request for the given config file with default options.
+  pub fn new(config: impl Into<PathBuf>) -> Self {
+    Self {
+      config: config.into(),
+      ..Default::default()
+    }
+  }
+
+  pub(crate) fn eval_options(&self) -> EvalOptions {
+    EvalOptions {
+      impure: self.impure,
+      input_overrides: self.input_overrides.clone(),
+      untrusted_inputs: self.untrusted_inputs,
+      vars_file: self.vars_file.clone(),
+      platform: self.platform,
+    }
+  }
+}
+
+/// Result of evaluating a configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvaluateResponse {
+  /// Hash identifying the manifest.
+  pub manifest_hash: String,
+  /// All builds and binds declared by the configuration.
+  pub manifest: Manifest,
+}
+
+/// Request to compute what an apply would change.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlanRequest {
+  /// Path to the Lua configuration file.
+  pub config: PathBuf,
+  /// Allow impure Lua libs (io, os).
+  pub impure: bool,
+  /// Replacement URLs for root inputs (name -> URL).
+  pub input_overrides: BTreeMap<String, String>,
+  /// How to evaluate inputs that aren't declared `trusted = true`.
+  pub untrusted_inputs: UntrustedInputs,
+  /// Host vars file to load instead of `host_vars/<hostname>.lua`.
+  pub vars_file: Option<PathBuf>,
+  /// Run drift checks on binds that would be left unchanged.
+  pub check_drift: bool,
+  /// How long failed builds are skipped, in seconds (defaults to an hour).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub failure_ttl_secs: Option<u64>,
+  /// Platform to evaluate for instead of this host's.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub platform: Option<Platform>,
+}
+
+impl PlanRequest {
+  /// Create a request for the given config file with default options.
+  pub fn new(config: impl Into<PathBuf>) -> Self {
+    Self {
+      config: config.into(),
+      ..Default::default()
+    }
+  }
+
+  pub(crate) fn eval_options(&self) -> EvalOptions {
+    EvalOptions {
+      impure: self.impure,
+      input_overrides: self.input_overrides.clone(),
+      untrusted_inputs: self.untrusted_inputs,
+      vars_file: self.vars_file.clone(),
+      platform: self.platform,
+    }
+  }
+
+  pub(crate) fn plan_options(&self, manifest: Option<Manifest>) -> PlanOptions {
+    PlanOptions {
+      execute: ExecuteConfig {
+        failure_ttl_secs: self.failure_ttl_secs.unwrap_or(DEFAULT_FAILURE_TTL_SECS),
+        ..Default::default()
+      },
+      check_drift: self.check_drift,
+      impure: self.impure,
+      input_overrides: self.input_overrides.clone(),
+      untrusted_inputs: self.untrusted_inputs,
+      vars_file: self.vars_file.clone(),
+      platform: self.platform,
+      manifest,
+    }
+  }
+}
+
+/// Changes an apply of the planned configuration would make.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanResponse {
+  /// Hash identifying the desired manifest.
+  pub manifest_hash: String,
+  /// The desired manifest.
+  pub manifest: Manifest,
+  /// Difference between the current snapshot and the desired manifest.
+  pub diff: StateDiff,
+  /// Drift check results for unchanged binds (empty unless requested).
+  pub drift_results: Vec<DriftResult>,
+  /// Bind changes per bind `group`.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub groups: BTreeMap<String, GroupChanges>,
+  /// Host paths the binds to create or update will touch.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub touches: Vec<BindTouches>,
+  /// Builds to realize that failed recently, which an apply skips.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub previously_failed: Vec<PreviousFailure>,
+}
+
+impl From<PlanReport> for PlanResponse {
+  fn from(report: PlanReport) -> Self {
+    Self {
+      manifest_hash: report.manifest_hash,
+      manifest: report.manifest,
+      diff: report.diff,
+      drift_results: report.drift_results,
+      groups: report.groups,
+      touches: report.touches,
+      previously_failed: report.previously_failed,
+    }
+  }
+}
+
+/// Request to apply a configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApplyRequest {
+  /// Path to the Lua configuration file.
+  pub config: PathBuf,
+  /// Allow impure Lua libs (io, os).
+  pub impure: bool,
+  /// Replacement URLs for root inputs (name -> URL).
+  pub input_overrides: BTreeMap<String, String>,
+  /// How to evaluate inputs that aren't declared `trusted = true`.
+  pub untrusted_inputs: UntrustedInputs,
+  /// Host vars file to load instead of `host_vars/<hostname>.lua`.
+  pub vars_file: Option<PathBuf>,
+  /// Check unchanged binds for drift and repair drifted ones.
+  pub repair: bool,
+  /// Compute the diff without making changes.
+  pub dry_run: bool,
+  /// Maximum number of parallel builds (defaults to the CPU count).
+  pub parallelism: Option<usize>,
+  /// Deny build commands network access.
+  pub isolate_network: bool,
+  /// Don't run the `check` actions of builds.
+  pub skip_checks: bool,
+  /// Don't check disk space and writable directories before applying.
+  pub skip_preflight: bool,
+  /// Leave realized build outputs writable.
+  pub writable_store: bool,
+  /// Niceness added to the commands builds and binds spawn (0-19).
+  pub nice: u8,
+  /// Run spawned commands at the lowest priority, one build or bind at a time.
+  pub background: bool,
+  /// Combined rate limit of `fetch_url` downloads in bytes per second.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub limit_rate: Option<u64>,
+  /// Concurrent `fetch_url` downloads (0 = unlimited, default 4).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_fetch: Option<usize>,
+  /// Concurrent package manager invocations (0 = unlimited, default 1).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_pkg: Option<usize>,
+  /// A node to fail on purpose, for testing rollback.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub fail_at: Option<FailPoint>,
+  /// Run builds that failed recently again instead of failing the apply.
+  pub retry_failed: bool,
+  /// How long failed builds are skipped, in seconds (defaults to an hour).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub failure_ttl_secs: Option<u64>,
+}
+
+impl ApplyRequest {
+  /// Create a request for the given config file with default options.
+  pub fn new(config: impl Into<PathBuf>) -> Self {
+    Self {
+      config: config.into(),
+      ..Default::default()
+    }
+  }
+
+  pub(crate) fn eval_options(&self) -> EvalOptions {
+    EvalOptions {
+      impure: self.impure,
+      input_overrides: self.input_overrides.clone(),
+      untrusted_inputs: self.untrusted_inputs,
+      vars_file: self.vars_file.clone(),
+      platform: None,
+    }
+  }
+
+  /// Options for [`execute::apply`], optionally skipping evaluation.
+  pub(crate) fn apply_options(&self, manifest: Option<Manifest>) -> ApplyOptions {
+    ApplyOptions {
+      execute: ExecuteConfig {
+        isolate_network: self.isolate_network,
+        skip_checks: self.skip_checks,
+        skip_preflight: self.skip_preflight,
+        writable_store: self.writable_store,
+        throttle: Throttle {
+          nice: self.nice,
+          background: self.background,
+        },
+        limit_rate: RateLimit::new(self.limit_rate),
+        action_limits: ActionLimits::new(self.max_fetch, self.max_pkg),
+        fail_at: self.fail_at.clone(),
+        retry_failed: self.retry_failed,
+        failure_ttl_secs: self.failure_ttl_secs.unwrap_or(DEFAULT_FAILURE_TTL_SECS),
+        ..execute_config(self.parallelism)
+      },
+      dry_run: self.dry_run,
+      repair: self.repair,
+      impure: self.impure,
+      input_overrides: self.input_overrides.clone(),
+      untrusted_inputs: self.untrusted_inputs,
+      vars_file: self.vars_file.clone(),
+      manifest,
+    }
+  }
+}
+
+/// Outcome of an apply.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApplyResponse {
+  /// ID of the snapshot recording the new state.
+  pub snapshot_id: String,
+  /// Whether every build and bind succeeded.
+  pub success: bool,
+  /// Diff that was applied.
+  pub diff: StateDiff,
+  /// Per-node execution results and timings.
+  pub execution: DagResult,
+  /// Number of binds removed from the previous state.
+  pub binds_destroyed: usize,
+  /// Number of binds updated in place.
+  pub binds_updated: usize,
+  /// Drift check results for unchanged binds.
+  pub drift_results: Vec<DriftResult>,
+  /// Bytes added to the store by newly realized builds.
+  pub store_bytes_added: u64,
+}
+
+impl From<ApplyResult> for ApplyResponse {
+  fn from(result: ApplyResult) -> Self {
+    Self {
+      snapshot_id: result.snapshot.id,
+      success: result.execution.is_success(),
+      diff: result.diff,
+      execution: result.execution,
+      binds_destroyed: result.binds_destroyed,
+      binds_updated: result.binds_updated,
+      drift_results: result.drift_results,
+      store_bytes_added: result.store_bytes_added,
+    }
+  }
+}
+
+/// Request to destroy binds in the current snapshot.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DestroyRequest {
+  /// Report what would be destroyed without making changes.
+  pub dry_run: bool,
+  /// Maximum number of parallel operations (defaults to the CPU count).
+  pub parallelism: Option<usize>,
+  /// Only destroy binds matching these ids, hash prefixes or tags (and their
+  /// dependents). Empty destroys every bind.
+  pub only: Vec<String>,
+  /// Only destroy binds of these groups (and their dependents), in addition to `only`.
+  pub groups: Vec<String>,
+}
+
+/// Outcome of a destroy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DestroyResponse {
+  /// Number of binds destroyed.
+  pub binds_destroyed: usize,
+  /// Number of builds no longer referenced (left for gc).
+  pub builds_orphaned: usize,
+  /// Binds removed by a partial destroy, dependents first.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub destroyed: Vec<String>,
+  /// Snapshot holding the remaining binds after a partial destroy.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub snapshot: Option<String>,
+}
+
+/// Request to garbage-collect the store.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GcRequest {
+  /// Report what would be removed without deleting anything.
+  pub dry_run: bool,
+}
+
+/// Outcome of a garbage collection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GcResponse {
+  /// Number of unreferenced builds removed.
+  pub builds_deleted: usize,
+  /// Number of unreferenced inputs removed.
+  pub inputs_deleted: usize,
+  /// Total bytes freed.
+  pub bytes_freed: u64,
+  /// Paths that were (or, in a dry run, would be) removed.
+  pub deleted_paths: Vec<PathBuf>,
+}
+
+fn execute_config(parallelism: Option<usize>) -> ExecuteConfig {
+  match parallelism {
+    Some(parallelism) => ExecuteConfig {
+      parallelism: parallelism.max(1),
+      ..Default::default()
+    },
+    None => ExecuteConfig::default(),
+  }
+}
+
+/// Evaluate a configuration into a manifest without touching the system.
+pub fn evaluate(request: &EvaluateRequest) -> Result<EvaluateResponse, ApiError> {
+  if !request.config.exists() {
+    return Err(ApiError::ConfigNotFound(request.config.display().to_string()));
+  }
+
+  let manifest = evaluate_config(&request.config, &request.eval_options())?;
+  Ok(EvaluateResponse {
+    manifest_hash: manifest_hash(&manifest)?,
+    manifest,
+  })
+}
+
+fn manifest_hash(manifest: &Manifest) -> Result<String, ApiError> {
+  manifest
+    .compute_hash()
+    .map(|hash| hash.0)
+    .map_err(|e| ApiError::Eval(format!("failed to hash manifest: {}", e)))
+}
+
+/// The dependency graph of a manifest's builds and binds.
+pub fn execution_dag(manifest: &Manifest) -> Result<ExecutionDag, ApiError> {
+  ExecutionDag::from_manifest(manifest).map_err(|e| ApiError::Eval(format!("invalid dependency graph: {}", e)))
+}
+
+/// Compute the diff between the current snapshot and a configuration.
+pub async fn plan(request: &PlanRequest) -> Result<PlanResponse, ApiError> {
+  let report = execute::plan(&request.config, &request.plan_options(None)).await?;
+  Ok(report.into())
+}
+
+/// Apply a configuration, recording a new snapshot.
+pub async fn apply(request: &ApplyRequest) -> Result<ApplyResponse, ApiError> {
+  let result = execute::apply(&request.config, &request.apply_options(None)).await?;
+  Ok(result.into())
+}
+
+/// Destroy every bind in the current snapshot, or only the selected ones.
+pub async fn destroy(request: &DestroyRequest) -> Result<DestroyResponse, ApiError> {
+  let options = DestroyOptions {
+    execute: execute_config(request.parallelism),
+    dry_run: request.dry_run,
+    only: request.only.clone(),
+    groups: request.groups.clone(),
+  };
+
+  let result = execute::destroy(&options).await?;
+  Ok(DestroyResponse {
+    binds_destroyed: result.binds_destroyed,
+    builds_orphaned: result.builds_orphaned,
+    destroyed: result.destroyed,
+    snapshot: result.snapshot,
+  })
+}
+
+/// Remove builds and inputs not referenced by any snapshot.
+pub fn gc(request: &GcRequest) -> Result<GcResponse, ApiError> {
+  let _lock = StoreLock::acquire(LockMode::Exclusive, "gc")?;
+
+  let result = collect_garbage(request.dry_run)?;
+  Ok(GcResponse {
+    builds_deleted: result.stats.builds_deleted,
+    inputs_deleted: result.stats.inputs_deleted,
+    bytes_freed: result.stats.total_bytes_freed(),
+    deleted_paths: result.deleted_paths,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serial_test::serial;
+  use tempfile::TempDir;
+
+  const EMPTY_CONFIG: &str = r#"
+    local M = {}
+    function M.setup() end
+    return M
+  "#;
+
+  fn with_temp_env<F, R>(f: F) -> R
+  where
+    F: FnOnce(&TempDir) -> R,
+  {
+    let temp_dir = TempDir::new().unwrap();
+    temp_env::with_vars(
+      [
+        ("SYSLUA_STORE", Some(temp_dir.path().join("store").to_str().unwrap())),
+        ("XDG_DATA_HOME", Some(temp_dir.path().join("data").to_str().unwrap())),
+      ],
+      || f(&temp_dir),
+    )
+  }
+
+  #[test]
+  fn evaluate_missing_config_is_not_found() {
+    let result = evaluate(&EvaluateRequest::new("/nonexistent/init.lua"));
+    assert!(matches!(result, Err(ApiError::ConfigNotFound(_))));
+  }
+
+  #[test]
+  #[serial]
+  fn plan_empty_config_has_empty_diff() {
+    with_temp_env(|temp_dir| {
+      let config = temp_dir.path().join("init.lua");
+      std::fs::write(&config, EMPTY_CONFIG).unwrap();
+
+      let rt = tokio::runtime::Runtime::new().unwrap();
+      let response = rt.block_on(plan(&PlanRequest::new(&config))).unwrap();
+
+      assert!(response.diff.is_empty());
+      assert!(response.drift_results.is_empty());
+      assert_eq!(response.manifest, Manifest::default());
+    });
+  }
+
+  #[test]
+  fn error_serializes_with_kind_and_message() {
+    let error = ApiError::Eval("boom".to_string());
+    let json = serde_json::to_string(&error).unwrap();
+    assert_eq!(json, r#"{"kind":"eval","message":"boom"}"#);
+    assert_eq!(serde_json::from_str::<ApiError>(&json).unwrap(), error);
+  }
+
+  #[test]
+  fn request_fields_default_when_omitted() {
+    let request: ApplyRequest = serde_json::from_str(r#"{"config":"init.lua"}"#).unwrap();
+    assert_eq!(request, ApplyRequest::new("init.lua"));
+  }
+
+  #[test]
+  fn apply_request_throttles_execution() {
+    let request = ApplyRequest {
+      nice: 10,
+      background: true,
+      ..ApplyRequest::new("init.lua")
+    };
+    let options = request.apply_options(None);
+    assert_eq!(options.execute.throttle.nice(), 19);
+    assert!(options.execute.throttle.background);
+    assert!(
+      ApplyRequest::new("init.lua")
+        .apply_options(None)
+        .execute
+        .throttle
+        .is_none()
+    );
+  }
+
+  #[test]
+  fn apply_request_limits_download_rate() {
+    let request = ApplyRequest {
+      limit_rate: Some(512 * 1024),
+      ..ApplyRequest::new("init.lua")
+    };
+    assert_eq!(
+      request.apply_options(None).execute.limit_rate.bytes_per_sec(),
+      Some(512 * 1024)
+    );
+    let json = serde_json::to_string(&request).unwrap();
+    assert!(json.contains(r#""limit_rate":524288"#), "{}", json);
+  }
+
+  #[test]
+  fn apply_request_limits_concurrent_actions() {
+    let request = ApplyRequest {
+      max_pkg: Some(2),
+      ..ApplyRequest::new("init.lua")
+    };
+    let limits = request.apply_options(None).execute.action_limits;
+    assert_eq!(limits.max_fetch.max(), 4);
+    assert_eq!(limits.max_pkg.max(), 2);
+    let json = serde_json::to_string(&request).unwrap();
+    assert!(json.contains(r#""max_pkg":2"#), "{}", json);
+    assert!(!json.contains("max_fetch"), "{}", json);
+  }
+}
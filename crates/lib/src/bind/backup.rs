@@ -0,0 +1,391 @@
+//! Backups of files replaced by binds.
+//!
+//! Before a bind's `create` actions run, any pre-existing file at a path they
+//! replace (the symlinks its commands create, the paths its file actions
+//! write or remove) or at one of its `backup` paths is copied into its store
+//! directory. When the bind is destroyed (or rolled back after a failed
+//! apply) the originals are put back. Without this, destroying a bind that
+//! overwrote a user's file would leave the target deleted.
+//!
+//! # Storage Layout
+//!
+//! ```text
+//! store/bind/<hash>/
+//! ├── state.json
+//! ├── backups.json
+//! └── backup/
+//!     ├── 0
+//!     └── 1
+//! ```
+//!
+//! Symlinks are recorded by their link target instead of being copied.
+//! Symlinks pointing into the store were created by syslua and are skipped,
+//! as are directories and files larger than the bind's `max_size`
+//! (`settings.backup_max_size`, then [`DEFAULT_BACKUP_MAX_SIZE`]).
+//!
+//! Backups aren't part of the bind hash: which files exist on the host says
+//! nothing about the bind's definition.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::bind::DEFAULT_BACKUP_MAX_SIZE;
+use crate::bind::store::bind_dir_path;
+use crate::platform::paths::store_dir;
+use crate::util::fs::copy_file;
+use crate::util::hash::ObjectHash;
+
+const BACKUPS_FILENAME: &str = "backups.json";
+const BACKUP_DIRNAME: &str = "backup";
+
+/// A pre-existing target that was backed up before a bind replaced it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupRecord {
+  /// The path the bind overwrote.
+  pub target: PathBuf,
+  /// What was at the path.
+  pub content: BackupContent,
+}
+
+/// The saved content of a backed up target.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackupContent {
+  /// A regular file, copied into the bind's `backup/` directory as `name`.
+  File { name: String, size: u64 },
+  /// A symlink pointing at `link`.
+  Symlink { link: PathBuf },
+}
+
+impl BackupRecord {
+  /// Bytes of store space used by this backup.
+  pub fn size(&self) -> u64 {
+    match &self.content {
+      BackupContent::File { size, .. } => *size,
+      BackupContent::Symlink { .. } => 0,
+    }
+  }
+}
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+  #[error("failed to back up {path}: {source}")]
+  Backup {
+    path: PathBuf,
+    #[source]
+    source: io::Error,
+  },
+
+  #[error("failed to restore {path}: {source}")]
+  Restore {
+    path: PathBuf,
+    #[source]
+    source: io::Error,
+  },
+
+  #[error("failed to read backup index: {0}")]
+  Read(#[source] io::Error),
+
+  #[error("failed to write backup index: {0}")]
+  Write(#[source] io::Error),
+
+  #[error("failed to parse backup index: {0}")]
+  Parse(#[source] serde_json::Error),
+}
+
+fn backups_path(hash: &ObjectHash) -> PathBuf {
+  bind_dir_path(hash).join(BACKUPS_FILENAME)
+}
+
+fn backup_dir(hash: &ObjectHash) -> PathBuf {
+  bind_dir_path(hash).join(BACKUP_DIRNAME)
+}
+
+/// Returns true if backups are currently held for the bind.
+pub fn has_backups(hash: &ObjectHash) -> bool {
+  backups_path(hash).exists()
+}
+
+/// Back up the existing files at `targets`, skipping files over `max_size` bytes.
+///
+/// Missing targets are skipped. Returns the records that were written.
+pub fn backup_targets(hash: &ObjectHash, targets: &[PathBuf], max_size: u64) -> Result<Vec<BackupRecord>, BackupError> {
+  let dir = backup_dir(hash);
+  let mut records = Vec::new();
+
+  for (idx, target) in targets.iter().enumerate() {
+    let target = target.clone();
+    let metadata = match fs::symlink_metadata(&target) {
+      Ok(metadata) => metadata,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+      Err(source) => return Err(BackupError::Backup { path: target, source }),
+    };
+
+    let content = if metadata.is_symlink() {
+      let link = fs::read_link(&target).map_err(|source| BackupError::Backup {
+        path: target.clone(),
+        source,
+      })?;
+      if link.starts_with(store_dir()) {
+        debug!(target = %target.display(), "skipping backup of store symlink");
+        continue;
+      }
+      BackupContent::Symlink { link }
+    } else if metadata.is_dir() {
+      warn!(target = %target.display(), "not backing up directory");
+      continue;
+    } else if metadata.len() > max_size {
+      warn!(
+        target = %target.display(),
+        size = metadata.len(),
+        max_size,
+        "not backing up file larger than max_size"
+      );
+      continue;
+    } else {
+      let name = idx.to_string();
+      fs::create_dir_all(&dir).map_err(|source| BackupError::Backup {
+        path: target.clone(),
+        source,
+      })?;
+      copy_file(&target, &dir.join(&name)).map_err(|source| BackupError::Backup {
+        path: target.clone(),
+        source,
+      })?;
+      BackupContent::File {
+        name,
+        size: metadata.len(),
+      }
+    };
+
+    debug!(hash = %hash.0, target = %target.display(), "backed up target");
+    records.push(BackupRecord { target, content });
+  }
+
+  if !records.is_empty() {
+    save_backups(hash, &records)?;
+    info!(hash = %hash.0, count = records.len(), "backed up replaced files");
+  }
+  Ok(records)
+}
+
+fn save_backups(hash: &ObjectHash, records: &[BackupRecord]) -> Result<(), BackupError> {
+  let dir = bind_dir_path(hash);
+  fs::create_dir_all(&dir).map_err(BackupError::Write)?;
+
+  let content = serde_json::to_string_pretty(records).map_err(BackupError::Parse)?;
+  let temp_path = dir.join("backups.json.tmp");
+  fs::write(&temp_path, content).map_err(BackupError::Write)?;
+  fs::rename(&temp_path, dir.join(BACKUPS_FILENAME)).map_err(BackupError::Write)
+}
+
+/// Load the backups held for a bind. Returns an empty list if there are none.
+pub fn load_backups(hash: &ObjectHash) -> Result<Vec<BackupRecord>, BackupError> {
+  let content = match fs::read_to_string(backups_path(hash)) {
+    Ok(content) => content,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(e) => return Err(BackupError::Read(e)),
+  };
+  serde_json::from_str(&content).map_err(BackupError::Parse)
+}
+
+/// Put backed up targets back in place and drop the backups.
+///
+/// Whatever the bind left at a target is replaced, unless it is a directory.
+/// Returns the number of targets restored.
+pub fn restore_backups(hash: &ObjectHash) -> Result<usize, BackupError> {
+  let records = load_backups(hash)?;
+  if records.is_empty() {
+    return Ok(0);
+  }
+
+  let dir = backup_dir(hash);
+  let mut restored = 0;
+  for record in &records {
+    let restore_err = |source| BackupError::Restore {
+      path: record.target.clone(),
+      source,
+    };
+
+    match fs::symlink_metadata(&record.target) {
+      Ok(metadata) if metadata.is_dir() => {
+        warn!(target = %record.target.display(), "not restoring backup over a directory");
+        continue;
+      }
+      Ok(_) => fs::remove_file(&record.target).map_err(restore_err)?,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+      Err(e) => return Err(restore_err(e)),
+    }
+    if let Some(parent) = record.target.parent() {
+      fs::create_dir_all(parent).map_err(restore_err)?;
+    }
+
+    match &record.content {
+      BackupContent::File { name, .. } => {
+        copy_file(&dir.join(name), &record.target).map_err(restore_err)?;
+      }
+      BackupContent::Symlink { link } => symlink(link, &record.target).map_err(restore_err)?,
+    }
+    debug!(hash = %hash.0, target = %record.target.display(), "restored backup");
+    restored += 1;
+  }
+
+  if dir.exists() {
+    fs::remove_dir_all(&dir).map_err(BackupError::Write)?;
+  }
+  fs::remove_file(backups_path(hash)).map_err(BackupError::Write)?;
+
+  info!(hash = %hash.0, count = restored, "restored replaced files");
+  Ok(restored)
+}
+
+/// Move backups held for `old` to `new`, used when a bind is updated in place.
+pub fn transfer_backups(old: &ObjectHash, new: &ObjectHash) -> Result<(), BackupError> {
+  if old == new || !has_backups(old) || has_backups(new) {
+    return Ok(());
+  }
+
+  fs::create_dir_all(bind_dir_path(new)).map_err(BackupError::Write)?;
+  if backup_dir(old).exists() {
+    fs::rename(backup_dir(old), backup_dir(new)).map_err(BackupError::Write)?;
+  }
+  fs::rename(backups_path(old), backups_path(new)).map_err(BackupError::Write)?;
+
+  debug!(old = %old.0, new = %new.0, "transferred backups");
+  Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(link: &Path, target: &Path) -> io::Result<()> {
+  std::os::unix::fs::symlink(link, target)
+}
+
+#[cfg(windows)]
+fn symlink(link: &Path, target: &Path) -> io::Result<()> {
+  std::os::windows::fs::symlink_file(link, target)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serial_test::serial;
+  use tempfile::TempDir;
+
+  fn with_temp_store<F>(f: F)
+  where
+    F: FnOnce(&TempDir),
+  {
+    let temp_dir = TempDir::new().unwrap();
+    let store = temp_dir.path().join("store");
+    temp_env::with_var("SYSLUA_STORE", Some(store.to_str().unwrap()), || {
+      f(&temp_dir);
+    });
+  }
+
+  fn paths(paths: &[&Path]) -> Vec<PathBuf> {
+    paths.iter().map(|p| p.to_path_buf()).collect()
+  }
+
+  #[test]
+  #[serial]
+  fn backup_and_restore_roundtrip() {
+    with_temp_store(|temp| {
+      let hash = ObjectHash("backup_roundtrip12345678".to_string());
+      let target = temp.path().join("bashrc");
+      let missing = temp.path().join("missing");
+      fs::write(&target, "original").unwrap();
+
+      let records = backup_targets(&hash, &paths(&[&target, &missing]), 1024).unwrap();
+      assert_eq!(records.len(), 1);
+      assert_eq!(records[0].size(), 8);
+      assert_eq!(load_backups(&hash).unwrap(), records);
+
+      fs::write(&target, "managed by syslua").unwrap();
+      assert_eq!(restore_backups(&hash).unwrap(), 1);
+      assert_eq!(fs::read_to_string(&target).unwrap(), "original");
+      assert!(!missing.exists());
+      assert!(!has_backups(&hash));
+    });
+  }
+
+  #[test]
+  #[serial]
+  fn restore_recreates_deleted_target() {
+    with_temp_store(|temp| {
+      let hash = ObjectHash("backup_deleted123456789".to_string());
+      let target = temp.path().join("config").join("app.toml");
+      fs::create_dir_all(target.parent().unwrap()).unwrap();
+      fs::write(&target, "key = 1").unwrap();
+
+      backup_targets(&hash, &paths(&[&target]), 1024).unwrap();
+      fs::remove_dir_all(target.parent().unwrap()).unwrap();
+
+      restore_backups(&hash).unwrap();
+      assert_eq!(fs::read_to_string(&target).unwrap(), "key = 1");
+    });
+  }
+
+  #[test]
+  #[serial]
+  fn skips_files_over_max_size() {
+    with_temp_store(|temp| {
+      let hash = ObjectHash("backup_too_large1234567".to_string());
+      let target = temp.path().join("large");
+      fs::write(&target, vec![0u8; 64]).unwrap();
+
+      let records = backup_targets(&hash, &paths(&[&target]), 16).unwrap();
+      assert!(records.is_empty());
+      assert!(!has_backups(&hash));
+    });
+  }
+
+  #[cfg(unix)]
+  #[test]
+  #[serial]
+  fn records_symlinks_but_skips_store_links() {
+    with_temp_store(|temp| {
+      let hash = ObjectHash("backup_symlinks12345678".to_string());
+      let user_link = temp.path().join("user-link");
+      let store_link = temp.path().join("store-link");
+      std::os::unix::fs::symlink("/etc/hosts", &user_link).unwrap();
+      std::os::unix::fs::symlink(store_dir().join("build/abc"), &store_link).unwrap();
+
+      let records = backup_targets(&hash, &paths(&[&user_link, &store_link]), 1024).unwrap();
+      assert_eq!(records.len(), 1);
+      assert_eq!(
+        records[0].content,
+        BackupContent::Symlink {
+          link: PathBuf::from("/etc/hosts")
+        }
+      );
+
+      fs::remove_file(&user_link).unwrap();
+      restore_backups(&hash).unwrap();
+      assert_eq!(fs::read_link(&user_link).unwrap(), PathBuf::from("/etc/hosts"));
+    });
+  }
+
+  #[test]
+  #[serial]
+  fn transfer_moves_backups_to_new_hash() {
+    with_temp_store(|temp| {
+      let old = ObjectHash("backup_transfer_old1234".to_string());
+      let new = ObjectHash("backup_transfer_new1234".to_string());
+      let target = temp.path().join("profile");
+      fs::write(&target, "original").unwrap();
+
+      backup_targets(&old, &paths(&[&target]), 1024).unwrap();
+      transfer_backups(&old, &new).unwrap();
+      assert!(!has_backups(&old));
+
+      fs::write(&target, "updated").unwrap();
+      restore_backups(&new).unwrap();
+      assert_eq!(fs::read_to_string(&target).unwrap(), "original");
+    });
+  }
+}
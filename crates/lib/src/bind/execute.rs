@@ -8,11 +8,17 @@ use std::path::Path;
 
 use serde_json::Value as JsonValue;
 use tempfile::TempDir;
-use tracing::debug;
+use tracing::{debug, warn};
 
+use crate::action::actions::exec::ExecIsolation;
 use crate::action::{Action, execute_action};
-use crate::bind::BindDef;
+use crate::bind::backup::{backup_targets, has_backups, restore_backups};
+use crate::bind::target::{backup_paths, existing_targets, write_targets};
+use crate::bind::{BindDef, BindPhase, DEFAULT_BACKUP_MAX_SIZE};
+use crate::execute::history::NodeKind;
+use crate::execute::hooks::BindOperation;
 use crate::execute::resolver::BindCtxResolver;
+use crate::execute::transcript::TranscriptOperation;
 use crate::execute::types::{ActionResult, BindResult, ExecuteError};
 use crate::placeholder;
 use crate::util::hash::ObjectHash;
@@ -20,7 +26,11 @@ use crate::util::hash::ObjectHash;
 /// Apply a single bind.
 ///
 /// This executes all apply_actions in the bind definition and produces the
-/// final BindResult with resolved outputs.
+/// final BindResult with resolved outputs. The paths the bind writes are
+/// probed first (see [`crate::bind::target`]), and the existing files it
+/// replaces are backed up (see [`crate::bind::backup`]), then restored if the
+/// actions fail.
+/// Login-phase binds are only recorded: nothing runs until [`login_bind`].
 ///
 /// # Arguments
 ///
@@ -36,6 +46,14 @@ pub async fn apply_bind(
   bind_def: &BindDef,
   resolver: &BindCtxResolver<'_>,
 ) -> Result<BindResult, ExecuteError> {
+  if bind_def.phase == BindPhase::Login {
+    debug!(hash = %hash.0, "login-phase bind deferred to login");
+    return Ok(BindResult {
+      outputs: HashMap::new(),
+      action_results: vec![],
+    });
+  }
+
   debug!(hash = %hash.0, "applying bind");
 
   // Create a temporary working directory for the bind's $${{out}}
@@ -45,9 +63,41 @@ pub async fn apply_bind(
   // Create a child resolver with its own out_dir and action_results
   let mut bind_resolver = resolver.with_out_dir(out_dir.to_string_lossy().to_string());
 
+  // Fail early on targets that can't be written, or use their fallback
+  let redirected = write_targets(bind_def, BindOperation::Create, &bind_resolver)?;
+  let bind_def = &*redirected;
+
+  // Back up files the bind is about to replace. Existing backups are kept so
+  // re-running create (e.g. drift repair) doesn't back up the bind's own files.
+  let backed_up = if has_backups(hash) {
+    false
+  } else {
+    let max_size = bind_def
+      .backup
+      .as_ref()
+      .map_or(DEFAULT_BACKUP_MAX_SIZE, |backup| backup.max_size);
+    !backup_targets(hash, &backup_paths(bind_def, &bind_resolver), max_size)?.is_empty()
+  };
+
   // Execute actions in order
-  let (action_results, outputs) =
-    execute_bind_actions(&bind_def.create_actions, &mut bind_resolver, bind_def, out_dir).await?;
+  let settings = action_settings(hash, bind_def, resolver, TranscriptOperation::Create);
+  let (action_results, outputs) = match execute_bind_actions(
+    &bind_def.create_actions,
+    &mut bind_resolver,
+    bind_def,
+    out_dir,
+    &settings,
+  )
+  .await
+  {
+    Ok(result) => result,
+    Err(e) => {
+      if backed_up && let Err(restore_err) = restore_backups(hash) {
+        warn!(hash = %hash.0, error = %restore_err, "failed to restore backups after failed create");
+      }
+      return Err(e);
+    }
+  };
 
   debug!(hash = %hash.0, "bind applied");
 
@@ -63,7 +113,9 @@ pub async fn apply_bind(
 
 /// Destroy a previously applied bind.
 ///
-/// This executes the destroy_actions for a bind, typically used during rollback.
+/// This executes the destroy_actions for a bind, typically used during rollback,
+/// then restores any files that were backed up when the bind was created.
+/// Login-phase binds are only dropped: their destroy actions run at logout.
 ///
 /// # Arguments
 ///
@@ -81,9 +133,13 @@ pub async fn destroy_bind(
   bind_result: &BindResult,
   resolver: &BindCtxResolver<'_>,
 ) -> Result<(), ExecuteError> {
-  let destroy_actions = &bind_def.destroy_actions;
   let _ = bind_result; // TODO: May be used in future for referencing applied outputs
 
+  if bind_def.phase == BindPhase::Login {
+    debug!(hash = %hash.0, "login-phase bind dropped");
+    return Ok(());
+  }
+
   debug!(hash = %hash.0, "destroying bind");
 
   // Create a temporary directory for destroy actions
@@ -93,8 +149,13 @@ pub async fn destroy_bind(
   // Create a child resolver with its own out_dir and action_results
   let mut bind_resolver = resolver.with_out_dir(out_dir.to_string_lossy().to_string());
 
-  // Execute destroy actions
-  let _ = execute_bind_actions_raw(destroy_actions, &mut bind_resolver, out_dir).await?;
+  // Execute destroy actions, on the fallbacks create used
+  let redirected = existing_targets(bind_def, &bind_resolver);
+  let settings = action_settings(hash, bind_def, resolver, TranscriptOperation::Destroy);
+  let _ = execute_bind_actions_raw(&redirected.destroy_actions, &mut bind_resolver, out_dir, &settings).await?;
+
+  // Put back anything the bind replaced
+  restore_backups(hash)?;
 
   debug!(hash = %hash.0, "bind destroyed");
 
@@ -124,27 +185,34 @@ pub async fn update_bind(
   old_bind_result: &BindResult,
   resolver: &BindCtxResolver<'_>,
 ) -> Result<BindResult, ExecuteError> {
-  let _ = old_bind_result; // TODO: May be used in future for referencing old outputs
   debug!(old_hash = %old_hash.0, new_hash = %new_hash.0, "updating bind");
 
   // Create a temporary working directory for the bind's $${{out}}
   let temp_dir = TempDir::new()?;
   let out_dir = temp_dir.path();
 
-  // Get update_actions (caller should ensure this exists)
-  let update_actions = new_bind_def
-    .update_actions
-    .as_ref()
-    .ok_or_else(|| ExecuteError::CmdFailed {
+  if new_bind_def.update_actions.is_none() {
+    // Caller should ensure update_actions exist
+    return Err(ExecuteError::CmdFailed {
       cmd: "update_bind called without update_actions".to_string(),
       code: None,
-    })?;
+    });
+  }
 
-  // Create a child resolver with its own out_dir and action_results
-  let mut bind_resolver = resolver.with_out_dir(out_dir.to_string_lossy().to_string());
+  // Create a child resolver with its own out_dir and action_results, which
+  // resolves $${{prev:...}} to the outputs recorded before the update
+  let mut bind_resolver = resolver
+    .with_out_dir(out_dir.to_string_lossy().to_string())
+    .with_previous(&old_bind_result.outputs);
 
+  // Fail early on targets that can't be written, or use their fallback
+  let redirected = write_targets(new_bind_def, BindOperation::Update, &bind_resolver)?;
+  let new_bind_def = &*redirected;
+  let update_actions = new_bind_def.update_actions.as_deref().unwrap_or_default();
+
+  let settings = action_settings(new_hash, new_bind_def, resolver, TranscriptOperation::Update);
   let (action_results, outputs) =
-    execute_bind_actions(update_actions, &mut bind_resolver, new_bind_def, out_dir).await?;
+    execute_bind_actions(update_actions, &mut bind_resolver, new_bind_def, out_dir, &settings).await?;
 
   debug!(old_hash = %old_hash.0, new_hash = %new_hash.0, "bind updated");
 
@@ -168,9 +236,9 @@ pub async fn check_bind(
   resolver: &BindCtxResolver<'_>,
 ) -> Result<Option<crate::bind::BindCheckResult>, ExecuteError> {
   let _ = bind_result; // TODO: May be used in future for referencing applied outputs
-  let Some(ref check_actions) = bind_def.check_actions else {
+  if bind_def.check_actions.is_none() {
     return Ok(None);
-  };
+  }
   let Some(ref check_outputs) = bind_def.check_outputs else {
     return Ok(None);
   };
@@ -183,8 +251,15 @@ pub async fn check_bind(
   // Create a child resolver with its own out_dir and action_results
   let mut check_resolver = resolver.with_out_dir(out_dir.to_string_lossy().to_string());
 
+  // Check the fallbacks create used
+  let redirected = existing_targets(bind_def, &check_resolver);
+  let Some(ref check_actions) = redirected.check_actions else {
+    return Ok(None);
+  };
+
   // Execute check actions (this populates action_results in check_resolver)
-  execute_bind_check_actions(check_actions, &mut check_resolver, out_dir).await?;
+  let settings = action_settings(hash, bind_def, resolver, TranscriptOperation::Check);
+  execute_bind_check_actions(check_actions, &mut check_resolver, out_dir, &settings).await?;
 
   // Resolve check outputs using the resolver (now has action results)
   let drifted_str = placeholder::substitute(&check_outputs.drifted, &check_resolver)?;
@@ -200,19 +275,70 @@ pub async fn check_bind(
   Ok(Some(crate::bind::BindCheckResult { drifted, message }))
 }
 
+/// Run a login-phase bind's `create` actions, when the user logs in.
+pub async fn login_bind(
+  hash: &ObjectHash,
+  bind_def: &BindDef,
+  resolver: &BindCtxResolver<'_>,
+) -> Result<Vec<ActionResult>, ExecuteError> {
+  debug!(hash = %hash.0, "running login bind");
+  let settings = action_settings(hash, bind_def, resolver, TranscriptOperation::Create);
+  run_session_actions(&bind_def.create_actions, resolver, &settings).await
+}
+
+/// Run a login-phase bind's `destroy` actions, when the user logs out.
+pub async fn logout_bind(
+  hash: &ObjectHash,
+  bind_def: &BindDef,
+  resolver: &BindCtxResolver<'_>,
+) -> Result<Vec<ActionResult>, ExecuteError> {
+  debug!(hash = %hash.0, "running logout bind");
+  let settings = action_settings(hash, bind_def, resolver, TranscriptOperation::Destroy);
+  run_session_actions(&bind_def.destroy_actions, resolver, &settings).await
+}
+
+async fn run_session_actions(
+  actions: &[Action],
+  resolver: &BindCtxResolver<'_>,
+  settings: &ExecIsolation,
+) -> Result<Vec<ActionResult>, ExecuteError> {
+  let temp_dir = TempDir::new()?;
+  let out_dir = temp_dir.path();
+  let mut session_resolver = resolver.with_out_dir(out_dir.to_string_lossy().to_string());
+  execute_bind_actions_raw(actions, &mut session_resolver, out_dir, settings).await
+}
+
+/// Settings of the actions of one bind operation: the resolver's concurrency
+/// limits, and where their commands are recorded.
+fn action_settings(
+  hash: &ObjectHash,
+  bind_def: &BindDef,
+  resolver: &BindCtxResolver<'_>,
+  operation: TranscriptOperation,
+) -> ExecIsolation {
+  ExecIsolation {
+    limits: resolver.action_limits().clone(),
+    transcript: resolver
+      .transcript()
+      .scope(NodeKind::Bind, hash, bind_def.id.as_deref(), operation),
+    ..Default::default()
+  }
+}
+
 async fn execute_bind_check_actions(
   actions: &[Action],
   resolver: &mut BindCtxResolver<'_>,
   out_dir: &Path,
+  settings: &ExecIsolation,
 ) -> Result<Vec<ActionResult>, ExecuteError> {
   let mut action_results = Vec::new();
 
   for (idx, action) in actions.iter().enumerate() {
     debug!(action_idx = idx, "executing check action");
 
-    let result = execute_action(action, resolver, out_dir).await?;
+    let result = execute_action(action, resolver, out_dir, Some(settings)).await?;
 
-    resolver.push_action_result(result.output.clone());
+    resolver.push_action(result.output.clone(), result.outputs.clone());
     action_results.push(result);
   }
 
@@ -225,16 +351,17 @@ async fn execute_bind_actions(
   resolver: &mut BindCtxResolver<'_>,
   bind_def: &BindDef,
   out_dir: &Path,
+  settings: &ExecIsolation,
 ) -> Result<(Vec<ActionResult>, HashMap<String, JsonValue>), ExecuteError> {
   let mut action_results = Vec::new();
 
   for (idx, action) in actions.iter().enumerate() {
     debug!(action_idx = idx, "executing bind action");
 
-    let result = execute_action(action, resolver, out_dir).await?;
+    let result = execute_action(action, resolver, out_dir, Some(settings)).await?;
 
     // Record the result for subsequent actions
-    resolver.push_action_result(result.output.clone());
+    resolver.push_action(result.output.clone(), result.outputs.clone());
     action_results.push(result);
   }
 
@@ -249,15 +376,16 @@ async fn execute_bind_actions_raw(
   actions: &[Action],
   resolver: &mut BindCtxResolver<'_>,
   out_dir: &Path,
+  settings: &ExecIsolation,
 ) -> Result<Vec<ActionResult>, ExecuteError> {
   let mut action_results = Vec::new();
 
   for (idx, action) in actions.iter().enumerate() {
     debug!(action_idx = idx, "executing destroy action");
 
-    let result = execute_action(action, resolver, out_dir).await?;
+    let result = execute_action(action, resolver, out_dir, Some(settings)).await?;
 
-    resolver.push_action_result(result.output.clone());
+    resolver.push_action(result.output.clone(), result.outputs.clone());
     action_results.push(result);
   }
 
@@ -300,6 +428,7 @@ mod tests {
   use crate::manifest::Manifest;
   use crate::util::testutil::{echo_msg, shell_cmd};
   use crate::{action::actions::exec::ExecOpts, util::hash::Hashable};
+  use serial_test::serial;
 
   /// Create a test resolver with empty collections.
   fn test_resolver() -> (
@@ -321,11 +450,23 @@ mod tests {
         args: Some(args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: None,
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     }
   }
 
@@ -342,6 +483,24 @@ mod tests {
     assert_eq!(result.action_results[0].output, "applied");
   }
 
+  #[tokio::test]
+  async fn login_binds_run_at_login_only() {
+    let bind_def = BindDef {
+      phase: BindPhase::Login,
+      ..make_simple_bind()
+    };
+    let hash = bind_def.compute_hash().unwrap();
+    assert_ne!(hash, make_simple_bind().compute_hash().unwrap());
+    let (builds, binds, manifest) = test_resolver();
+    let resolver = BindCtxResolver::new(&builds, &binds, &manifest, "/tmp".to_string());
+
+    let result = apply_bind(&hash, &bind_def, &resolver).await.unwrap();
+    assert!(result.action_results.is_empty());
+
+    let results = login_bind(&hash, &bind_def, &resolver).await.unwrap();
+    assert_eq!(results[0].output, "applied");
+  }
+
   #[tokio::test]
   async fn apply_bind_with_outputs() {
     let (cmd, args) = echo_msg("/path/to/link");
@@ -358,11 +517,23 @@ mod tests {
         args: Some(args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: None,
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let hash = bind_def.compute_hash().unwrap();
     let (builds, binds, manifest) = test_resolver();
@@ -389,11 +560,23 @@ mod tests {
         args: Some(args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: None,
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let hash = bind_def.compute_hash().unwrap();
     let (builds, binds, manifest) = test_resolver();
@@ -429,11 +612,23 @@ mod tests {
         args: Some(args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: None,
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let hash = bind_def.compute_hash().unwrap();
 
@@ -443,6 +638,7 @@ mod tests {
       store_path: PathBuf::from("/store/obj/myapp"),
       outputs: build_outputs,
       action_results: vec![],
+      cached: false,
     };
     let mut builds = HashMap::new();
     builds.insert(ObjectHash("abc123def456".to_string()), build_result);
@@ -472,6 +668,10 @@ mod tests {
         args: Some(apply_args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: None,
       destroy_actions: vec![Action::Exec(ExecOpts {
@@ -479,9 +679,21 @@ mod tests {
         args: Some(destroy_args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let hash = bind_def.compute_hash().unwrap();
     let (builds, binds, manifest) = test_resolver();
@@ -513,6 +725,49 @@ mod tests {
     assert!(result.is_ok());
   }
 
+  #[test]
+  #[serial]
+  fn replaced_files_are_backed_up_and_restored() {
+    use crate::action::actions::file::{FileAttrs, FileOpts, FileSource, FileState};
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let target = temp_dir.path().join("bashrc");
+    std::fs::write(&target, "original").unwrap();
+    let file = |state| {
+      Action::File(FileOpts {
+        path: target.to_string_lossy().into_owned(),
+        source: FileSource::Content("managed".to_string()),
+        attrs: FileAttrs::default(),
+        state,
+      })
+    };
+    let bind_def = BindDef {
+      create_actions: vec![file(FileState::Present)],
+      destroy_actions: vec![file(FileState::Absent)],
+      ..make_simple_bind()
+    };
+    let hash = bind_def.compute_hash().unwrap();
+    let (builds, binds, manifest) = test_resolver();
+    let resolver = BindCtxResolver::new(&builds, &binds, &manifest, "/tmp".to_string());
+
+    let store = temp_dir.path().join("store");
+    temp_env::with_var("SYSLUA_STORE", Some(store.to_str().unwrap()), || {
+      let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+      rt.block_on(async {
+        let result = apply_bind(&hash, &bind_def, &resolver).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "managed");
+        assert!(has_backups(&hash));
+
+        destroy_bind(&hash, &bind_def, &result, &resolver).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "original");
+        assert!(!has_backups(&hash));
+      });
+    });
+  }
+
   #[tokio::test]
   async fn apply_bind_action_failure() {
     let (cmd, args) = shell_cmd("exit 1");
@@ -525,11 +780,23 @@ mod tests {
         args: Some(args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: None,
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let hash = bind_def.compute_hash().unwrap();
     let (builds, binds, manifest) = test_resolver();
@@ -559,24 +826,44 @@ mod tests {
           args: Some(args1),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         }),
         Action::Exec(ExecOpts {
           bin: cmd2.to_string(),
           args: Some(args2),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         }),
         Action::Exec(ExecOpts {
           bin: cmd3.to_string(),
           args: Some(args3),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         }),
       ],
       update_actions: None,
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let hash = bind_def.compute_hash().unwrap();
     let (builds, binds, manifest) = test_resolver();
@@ -608,16 +895,32 @@ mod tests {
         args: Some(create_args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: Some(vec![Action::Exec(ExecOpts {
         bin: update_cmd.to_string(),
         args: Some(update_args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })]),
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let old_hash = ObjectHash("old_hash".to_string());
     let new_hash = bind_def.compute_hash().unwrap();
@@ -659,16 +962,32 @@ mod tests {
         args: Some(create_args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: Some(vec![Action::Exec(ExecOpts {
         bin: update_cmd.to_string(),
         args: Some(update_args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })]),
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let old_hash = ObjectHash("old".to_string());
     let new_hash = bind_def.compute_hash().unwrap();
@@ -702,11 +1021,23 @@ mod tests {
         args: Some(args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: None, // No update actions!
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let old_hash = ObjectHash("old".to_string());
     let new_hash = bind_def.compute_hash().unwrap();
@@ -741,6 +1072,10 @@ mod tests {
         args: Some(args1.clone()),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: Some(vec![
         Action::Exec(ExecOpts {
@@ -748,23 +1083,43 @@ mod tests {
           args: Some(args1),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         }),
         Action::Exec(ExecOpts {
           bin: cmd2.to_string(),
           args: Some(args2),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         }),
         Action::Exec(ExecOpts {
           bin: cmd3.to_string(),
           args: Some(args3),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         }),
       ]),
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let old_hash = ObjectHash("old".to_string());
     let new_hash = bind_def.compute_hash().unwrap();
@@ -823,11 +1178,23 @@ mod tests {
         args: Some(args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })]),
       check_outputs: Some(BindCheckOutputs {
         drifted: "$${{action:0}}".to_string(),
         message: Some("file missing".to_string()),
       }),
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let hash = bind_def.compute_hash().unwrap();
     let (builds, binds, manifest) = test_resolver();
@@ -863,11 +1230,23 @@ mod tests {
         args: Some(args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })]),
       check_outputs: Some(BindCheckOutputs {
         drifted: "$${{action:0}}".to_string(),
         message: None,
       }),
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let hash = bind_def.compute_hash().unwrap();
     let (builds, binds, manifest) = test_resolver();
@@ -905,18 +1284,34 @@ mod tests {
           args: Some(args1),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         }),
         Action::Exec(ExecOpts {
           bin: cmd2.to_string(),
           args: Some(args2),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         }),
       ]),
       check_outputs: Some(BindCheckOutputs {
         drifted: "true".to_string(),
         message: Some("$${{action:1}}".to_string()),
       }),
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     };
     let hash = bind_def.compute_hash().unwrap();
     let (builds, binds, manifest) = test_resolver();
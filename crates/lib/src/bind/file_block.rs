@@ -0,0 +1,181 @@
+//! File blocks managed as binds.
+//!
+//! `sys.file_block` declares one marker-delimited block of a file syslua
+//! doesn't own as a whole:
+//!
+//! ```lua
+//! sys.file_block({ path = "/etc/hosts", marker = "dev", content = { "10.0.0.5 build.internal" } })
+//! ```
+//!
+//! The block is one bind (id `file-block-<marker>` unless given) whose create
+//! action writes the block and whose destroy action removes exactly its lines.
+//! Changing the lines updates the block in place. A drift check reports the
+//! block as drifted when it is missing or was edited outside syslua.
+//!
+//! An update only writes the new block, so moving a block to another `path`
+//! or `comment` under the same id leaves the old one behind; give it another
+//! marker or `update_strategy = "recreate"`.
+//!
+//! See [`crate::action::actions::file_block`] for the file format.
+
+use mlua::prelude::*;
+
+use crate::action::actions::file_block::{BlockState, FileBlockOpts, parse_file_block_opts};
+
+use super::BindCtx;
+
+/// Keys of a `sys.file_block` spec passed on to `sys.bind` unchanged.
+const BIND_KEYS: &[&str] = &[
+  "replace",
+  "tags",
+  "group",
+  "repair",
+  "requires",
+  "platforms",
+  "serialize",
+  "update_strategy",
+];
+
+/// Register the `sys.file_block` function on the sys table.
+///
+/// `sys.file_block{}` builds a bind spec from the block and passes it to
+/// `sys.bind`, so it must be registered after it. Returns the BindRef.
+pub fn register_sys_file_block(lua: &Lua, sys_table: &LuaTable) -> LuaResult<()> {
+  let bind_fn: LuaFunction = sys_table.get("bind")?;
+
+  let file_block_fn = lua.create_function(move |lua, spec: LuaTable| {
+    let opts = parse_file_block_opts(&spec)?;
+    if opts.state != BlockState::Present {
+      return Err(LuaError::external(
+        "sys.file_block does not take a 'state'; remove the block from the config to delete it",
+      ));
+    }
+    let id = spec
+      .get::<Option<String>>("id")?
+      .unwrap_or_else(|| format!("file-block-{}", opts.marker));
+
+    let bind_spec = lua.create_table()?;
+    bind_spec.set("id", id)?;
+    for key in BIND_KEYS {
+      bind_spec.set(*key, spec.get::<LuaValue>(*key)?)?;
+    }
+
+    let with_state = move |state: BlockState| FileBlockOpts { state, ..opts.clone() };
+
+    let create = with_state(BlockState::Present);
+    let write_fn = move |_: &Lua, ctx: LuaAnyUserData| -> LuaResult<()> {
+      ctx.borrow_mut::<BindCtx>()?.file_block(create.clone());
+      Ok(())
+    };
+    let write = write_fn.clone();
+    bind_spec.set(
+      "create",
+      lua.create_function(move |lua, (_inputs, ctx): (LuaValue, LuaAnyUserData)| write(lua, ctx))?,
+    )?;
+    bind_spec.set(
+      "update",
+      lua.create_function(
+        move |lua, (_outputs, _inputs, ctx): (LuaValue, LuaValue, LuaAnyUserData)| write_fn(lua, ctx),
+      )?,
+    )?;
+
+    let destroy = with_state(BlockState::Absent);
+    bind_spec.set(
+      "destroy",
+      lua.create_function(move |_, (_outputs, ctx): (LuaValue, LuaAnyUserData)| {
+        ctx.borrow_mut::<BindCtx>()?.file_block(destroy.clone());
+        Ok(())
+      })?,
+    )?;
+
+    let check = with_state(BlockState::Check);
+    bind_spec.set(
+      "check",
+      lua.create_function(
+        move |lua, (_outputs, _inputs, ctx): (LuaValue, LuaValue, LuaAnyUserData)| {
+          let drifted = ctx.borrow_mut::<BindCtx>()?.file_block(check.clone());
+          let result = lua.create_table()?;
+          result.set("drifted", drifted)?;
+          result.set(
+            "message",
+            format!("block '{}' of {} is missing or was modified", check.marker, check.path),
+          )?;
+          Ok(result)
+        },
+      )?,
+    )?;
+
+    bind_fn.call::<LuaValue>(bind_spec)
+  })?;
+
+  sys_table.set("file_block", file_block_fn)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::action::Action;
+  use crate::manifest::Manifest;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn file_block_declares_a_bind_writing_and_removing_it() -> LuaResult<()> {
+    let lua = crate::lua::runtime::create_lua(false)?;
+    let manifest = Rc::new(RefCell::new(Manifest::default()));
+    crate::lua::globals::register_globals(&lua, manifest.clone())?;
+
+    lua
+      .load(
+        r#"sys.file_block({
+          path = "/etc/hosts",
+          marker = "dev",
+          content = { "10.0.0.5 build", "10.0.0.6 cache" },
+          tags = { "net" },
+        })"#,
+      )
+      .exec()?;
+
+    let manifest = manifest.borrow();
+    let (_, def) = manifest.bindings.iter().next().unwrap();
+    assert_eq!(def.id.as_deref(), Some("file-block-dev"));
+    assert_eq!(def.tags, ["net"]);
+
+    let [Action::FileBlock(create)] = def.create_actions.as_slice() else {
+      panic!("expected one create action");
+    };
+    assert_eq!(create.state, BlockState::Present);
+    assert_eq!(create.content, "10.0.0.5 build\n10.0.0.6 cache");
+    assert_eq!(def.update_actions.as_deref(), Some(def.create_actions.as_slice()));
+    assert!(matches!(
+      def.destroy_actions.as_slice(),
+      [Action::FileBlock(FileBlockOpts {
+        state: BlockState::Absent,
+        ..
+      })]
+    ));
+    assert!(matches!(
+      def.check_actions.as_deref(),
+      Some([Action::FileBlock(FileBlockOpts {
+        state: BlockState::Check,
+        ..
+      })])
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn file_block_rejects_markers_in_content() -> LuaResult<()> {
+    let lua = crate::lua::runtime::create_lua(false)?;
+    let manifest = Rc::new(RefCell::new(Manifest::default()));
+    crate::lua::globals::register_globals(&lua, manifest)?;
+
+    let err = lua
+      .load(r##"sys.file_block({ path = "/etc/hosts", marker = "dev", content = "# END SYSLUA dev" })"##)
+      .exec()
+      .unwrap_err();
+    assert!(err.to_string().contains("contains its own markers"), "{}", err);
+    Ok(())
+  }
+}
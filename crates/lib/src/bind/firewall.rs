@@ -0,0 +1,160 @@
+//! Firewall rules managed as binds.
+//!
+//! `sys.firewall.rule` declares one rule of the host firewall:
+//!
+//! ```lua
+//! sys.firewall.rule({ name = "ssh", port = 22, address = "10.0.0.0/8" })
+//! ```
+//!
+//! The rule is one bind (id `firewall-<name>` unless given) whose create
+//! action adds the rule, tagged with its name, and whose destroy action
+//! deletes exactly the rules with that tag. A drift check reports the rule
+//! as drifted when it was removed behind syslua's back. Changing the rule
+//! replaces the bind: the old rule is deleted and the new one added.
+//!
+//! See [`crate::platform::firewall`] for how each OS stores the rules.
+
+use mlua::prelude::*;
+
+use crate::action::actions::firewall::{FirewallOpts, RuleState, parse_firewall_opts};
+
+use super::BindCtx;
+
+/// Keys of a `sys.firewall.rule` spec passed on to `sys.bind` unchanged.
+const BIND_KEYS: &[&str] = &[
+  "replace",
+  "tags",
+  "group",
+  "repair",
+  "requires",
+  "platforms",
+  "serialize",
+];
+
+/// Register the `sys.firewall` table and its `rule` function on the sys table.
+///
+/// `sys.firewall.rule{}` builds a bind spec from the rule and passes it to
+/// `sys.bind`, so it must be registered after it. Returns the BindRef.
+pub fn register_sys_firewall(lua: &Lua, sys_table: &LuaTable) -> LuaResult<()> {
+  let bind_fn: LuaFunction = sys_table.get("bind")?;
+
+  let rule_fn = lua.create_function(move |lua, spec: LuaTable| {
+    let opts = parse_firewall_opts(&spec)?;
+    if opts.state != RuleState::Present {
+      return Err(LuaError::external(
+        "sys.firewall.rule does not take a 'state'; remove the rule from the config to delete it",
+      ));
+    }
+    let id = spec
+      .get::<Option<String>>("id")?
+      .unwrap_or_else(|| format!("firewall-{}", opts.rule.name));
+
+    let bind_spec = lua.create_table()?;
+    bind_spec.set("id", id)?;
+    for key in BIND_KEYS {
+      bind_spec.set(*key, spec.get::<LuaValue>(*key)?)?;
+    }
+
+    let with_state = move |state: RuleState| FirewallOpts {
+      rule: opts.rule.clone(),
+      state,
+    };
+
+    let create = with_state(RuleState::Present);
+    bind_spec.set(
+      "create",
+      lua.create_function(move |_, (_inputs, ctx): (LuaValue, LuaAnyUserData)| {
+        ctx.borrow_mut::<BindCtx>()?.firewall_rule(create.clone());
+        Ok(())
+      })?,
+    )?;
+
+    let destroy = with_state(RuleState::Absent);
+    bind_spec.set(
+      "destroy",
+      lua.create_function(move |_, (_outputs, ctx): (LuaValue, LuaAnyUserData)| {
+        ctx.borrow_mut::<BindCtx>()?.firewall_rule(destroy.clone());
+        Ok(())
+      })?,
+    )?;
+
+    let check = with_state(RuleState::Check);
+    bind_spec.set(
+      "check",
+      lua.create_function(
+        move |lua, (_outputs, _inputs, ctx): (LuaValue, LuaValue, LuaAnyUserData)| {
+          let drifted = ctx.borrow_mut::<BindCtx>()?.firewall_rule(check.clone());
+          let result = lua.create_table()?;
+          result.set("drifted", drifted)?;
+          result.set("message", format!("firewall rule '{}' is missing", check.rule.name))?;
+          Ok(result)
+        },
+      )?,
+    )?;
+
+    bind_fn.call::<LuaValue>(bind_spec)
+  })?;
+
+  let firewall = lua.create_table()?;
+  firewall.set("rule", rule_fn)?;
+  sys_table.set("firewall", firewall)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::action::Action;
+  use crate::manifest::Manifest;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn rule_declares_a_bind_adding_and_deleting_it() -> LuaResult<()> {
+    let lua = crate::lua::runtime::create_lua(false)?;
+    let manifest = Rc::new(RefCell::new(Manifest::default()));
+    crate::lua::globals::register_globals(&lua, manifest.clone())?;
+
+    lua
+      .load(r#"sys.firewall.rule({ name = "ssh", port = 22, address = "10.0.0.0/8", tags = { "net" } })"#)
+      .exec()?;
+
+    let manifest = manifest.borrow();
+    let (_, def) = manifest.bindings.iter().next().unwrap();
+    assert_eq!(def.id.as_deref(), Some("firewall-ssh"));
+    assert_eq!(def.tags, ["net"]);
+
+    let [Action::Firewall(create)] = def.create_actions.as_slice() else {
+      panic!("expected one create action");
+    };
+    assert_eq!(create.state, RuleState::Present);
+    assert_eq!(create.rule.port.as_deref(), Some("22"));
+    let [Action::Firewall(destroy)] = def.destroy_actions.as_slice() else {
+      panic!("expected one destroy action");
+    };
+    assert_eq!(destroy.state, RuleState::Absent);
+    assert_eq!(destroy.rule, create.rule);
+    assert!(matches!(
+      def.check_actions.as_deref(),
+      Some([Action::Firewall(FirewallOpts {
+        state: RuleState::Check,
+        ..
+      })])
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn rule_rejects_a_state() -> LuaResult<()> {
+    let lua = crate::lua::runtime::create_lua(false)?;
+    let manifest = Rc::new(RefCell::new(Manifest::default()));
+    crate::lua::globals::register_globals(&lua, manifest)?;
+
+    let err = lua
+      .load(r#"sys.firewall.rule({ name = "ssh", port = 22, state = "absent" })"#)
+      .exec()
+      .unwrap_err();
+    assert!(err.to_string().contains("does not take a 'state'"), "{}", err);
+    Ok(())
+  }
+}
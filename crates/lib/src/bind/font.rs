@@ -0,0 +1,214 @@
+//! Fonts managed as binds.
+//!
+//! `sys.font` installs the font files of a file, directory or build where
+//! the OS looks for fonts, and refreshes its font cache:
+//!
+//! ```lua
+//! sys.font({ name = "jetbrains-mono", source = jetbrains_mono })
+//! sys.font({ source = "~/Downloads/Inter.ttc", scope = "system" })
+//! ```
+//!
+//! The fonts are one bind (id `font-<name>` unless given) whose create action
+//! copies the fonts in under the name and whose destroy action removes
+//! exactly those files. Changing the source updates the installed fonts in
+//! place, removing the ones it no longer has. A drift check reports the fonts
+//! as drifted when one is missing or differs from the source.
+//!
+//! A build given as `source` becomes the bind's input, and its `out` output
+//! the directory the fonts are installed from.
+//!
+//! See [`crate::platform::font`] for the per-OS locations.
+
+use mlua::prelude::*;
+
+use crate::action::actions::font::{FontOpts, FontState, parse_font_opts};
+
+use super::BindCtx;
+
+/// Keys of a `sys.font` spec passed on to `sys.bind` unchanged.
+const BIND_KEYS: &[&str] = &[
+  "replace",
+  "tags",
+  "group",
+  "repair",
+  "requires",
+  "platforms",
+  "serialize",
+  "update_strategy",
+];
+
+/// Keys of a `sys.font` spec that make up its [`FontOpts`].
+const FONT_KEYS: &[&str] = &["name", "scope", "state"];
+
+/// Register the `sys.font` function on the sys table.
+///
+/// `sys.font{}` builds a bind spec from the fonts and passes it to
+/// `sys.bind`, so it must be registered after it. Returns the BindRef.
+pub fn register_sys_font(lua: &Lua, sys_table: &LuaTable) -> LuaResult<()> {
+  let bind_fn: LuaFunction = sys_table.get("bind")?;
+
+  let font_fn = lua.create_function(move |lua, spec: LuaTable| {
+    let bind_spec = lua.create_table()?;
+
+    // A build installs from its `out` output and becomes the bind's input
+    let font_spec = lua.create_table()?;
+    for key in FONT_KEYS {
+      font_spec.set(*key, spec.get::<LuaValue>(*key)?)?;
+    }
+    match spec.get::<LuaValue>("source")? {
+      LuaValue::Table(build) => {
+        let out = build
+          .get::<Option<LuaTable>>("outputs")?
+          .and_then(|outputs| outputs.get::<Option<String>>("out").ok().flatten())
+          .ok_or_else(|| LuaError::external("sys.font: a build 'source' must have an 'out' output"))?;
+        font_spec.set("source", out)?;
+        bind_spec.set("inputs", build)?;
+      }
+      source => font_spec.set("source", source)?,
+    }
+
+    let opts = parse_font_opts(&font_spec)?;
+    if opts.state != FontState::Present {
+      return Err(LuaError::external(
+        "sys.font does not take a 'state'; remove the fonts from the config to uninstall them",
+      ));
+    }
+    let id = spec
+      .get::<Option<String>>("id")?
+      .unwrap_or_else(|| format!("font-{}", opts.name));
+
+    bind_spec.set("id", id)?;
+    for key in BIND_KEYS {
+      bind_spec.set(*key, spec.get::<LuaValue>(*key)?)?;
+    }
+
+    let with_state = move |state: FontState| FontOpts { state, ..opts.clone() };
+
+    let create = with_state(FontState::Present);
+    let install_fn = move |_: &Lua, ctx: LuaAnyUserData| -> LuaResult<()> {
+      ctx.borrow_mut::<BindCtx>()?.font(create.clone());
+      Ok(())
+    };
+    let install = install_fn.clone();
+    bind_spec.set(
+      "create",
+      lua.create_function(move |lua, (_inputs, ctx): (LuaValue, LuaAnyUserData)| install(lua, ctx))?,
+    )?;
+    bind_spec.set(
+      "update",
+      lua.create_function(
+        move |lua, (_outputs, _inputs, ctx): (LuaValue, LuaValue, LuaAnyUserData)| install_fn(lua, ctx),
+      )?,
+    )?;
+
+    let destroy = with_state(FontState::Absent);
+    bind_spec.set(
+      "destroy",
+      lua.create_function(move |_, (_outputs, ctx): (LuaValue, LuaAnyUserData)| {
+        ctx.borrow_mut::<BindCtx>()?.font(destroy.clone());
+        Ok(())
+      })?,
+    )?;
+
+    let check = with_state(FontState::Check);
+    bind_spec.set(
+      "check",
+      lua.create_function(
+        move |lua, (_outputs, _inputs, ctx): (LuaValue, LuaValue, LuaAnyUserData)| {
+          let drifted = ctx.borrow_mut::<BindCtx>()?.font(check.clone());
+          let result = lua.create_table()?;
+          result.set("drifted", drifted)?;
+          result.set("message", format!("fonts '{}' are missing or differ", check.name))?;
+          Ok(result)
+        },
+      )?,
+    )?;
+
+    bind_fn.call::<LuaValue>(bind_spec)
+  })?;
+
+  sys_table.set("font", font_fn)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::action::Action;
+  use crate::manifest::Manifest;
+  use crate::platform::font::FontScope;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn font_declares_a_bind_installing_and_removing_it() -> LuaResult<()> {
+    let lua = crate::lua::runtime::create_lua(false)?;
+    let manifest = Rc::new(RefCell::new(Manifest::default()));
+    crate::lua::globals::register_globals(&lua, manifest.clone())?;
+
+    lua
+      .load(r#"sys.font({ source = "/opt/fonts/Inter.ttc", scope = "system", tags = { "fonts" } })"#)
+      .exec()?;
+
+    let manifest = manifest.borrow();
+    let (_, def) = manifest.bindings.iter().next().unwrap();
+    assert_eq!(def.id.as_deref(), Some("font-Inter"));
+    assert_eq!(def.tags, ["fonts"]);
+
+    let [Action::Font(create)] = def.create_actions.as_slice() else {
+      panic!("expected one create action");
+    };
+    assert_eq!(create.state, FontState::Present);
+    assert_eq!(create.scope, FontScope::System);
+    assert_eq!(def.update_actions.as_deref(), Some(def.create_actions.as_slice()));
+    assert!(matches!(
+      def.destroy_actions.as_slice(),
+      [Action::Font(FontOpts {
+        state: FontState::Absent,
+        ..
+      })]
+    ));
+    assert!(matches!(
+      def.check_actions.as_deref(),
+      Some([Action::Font(FontOpts {
+        state: FontState::Check,
+        ..
+      })])
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn font_installs_from_a_build_it_depends_on() -> LuaResult<()> {
+    let lua = crate::lua::runtime::create_lua(false)?;
+    let manifest = Rc::new(RefCell::new(Manifest::default()));
+    crate::lua::globals::register_globals(&lua, manifest.clone())?;
+
+    lua
+      .load(
+        r#"
+        local fonts = sys.build({
+          id = "mono-fonts",
+          create = function(_, ctx) return { out = ctx.out } end,
+        })
+        sys.font({ name = "mono", source = fonts })
+        "#,
+      )
+      .exec()?;
+
+    let manifest = manifest.borrow();
+    let (_, def) = manifest.bindings.iter().next().unwrap();
+    assert!(def.inputs.is_some());
+    let [Action::Font(create)] = def.create_actions.as_slice() else {
+      panic!("expected one create action");
+    };
+    assert!(create.source.starts_with("$${{build:"), "{}", create.source);
+
+    let err = lua
+      .load(r#"sys.font({ source = sys.build({ id = "x", create = function(_, ctx) return { out = ctx.out } end }) })"#)
+      .exec()
+      .unwrap_err();
+    assert!(err.to_string().contains("'name' is required"), "{}", err);
+    Ok(())
+  }
+}
@@ -1,7 +1,8 @@
 //! Lua bindings for `sys.bind{}`.
 //!
 //! This module provides:
-//! - `BindCtx` as LuaUserData with methods like `exec`
+//! - `BindCtx` as LuaUserData with methods like `exec`, `git_config`, `ssh_config`, `firewall_rule`,
+//!   `write_file`, `symlink`, `copy`, `file_block` and `font`
 //! - `register_sys_bind()` to register the `sys.bind` function
 
 use std::cell::RefCell;
@@ -11,11 +12,22 @@ use std::rc::Rc;
 use mlua::prelude::*;
 
 use crate::action::BIND_CTX_METHODS_REGISTRY_KEY;
+use crate::action::actions::config_section::{ConfigFormat, parse_config_section_opts};
 use crate::action::actions::exec::parse_exec_opts;
+use crate::action::actions::file::parse_file_opts;
+use crate::action::actions::file_block::parse_file_block_opts;
+use crate::action::actions::firewall::parse_firewall_opts;
+use crate::action::actions::font::parse_font_opts;
 use crate::bind::{BindInputsDef, BindRef, BindSpec};
 use crate::build::BUILD_REF_TYPE;
 use crate::build::lua::build_hash_to_lua;
-use crate::manifest::Manifest;
+use crate::execute::dag::builds_referenced_by;
+use crate::lua::diagnostics::{SpecCaller, SpecKind};
+use crate::lua::runtime::caller_location;
+use crate::manifest::{
+  Manifest, PlaceholderMode, SkippedBind, record_strict_placeholders, registry_hash_spec, unsupported_platform,
+  validate_bind_in,
+};
 use crate::util::hash::ObjectHash;
 
 use super::{BIND_REF_TYPE, BindCtx, BindDef};
@@ -29,11 +41,37 @@ impl LuaUserData for BindCtx {
   fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
     // NO fetch_url here - binds should only use build outputs
 
-    methods.add_method_mut("exec", |_, this, (opts, args): (LuaValue, Option<LuaValue>)| {
-      let cmd_opts = parse_exec_opts(opts, args)?;
+    methods.add_method_mut("exec", |lua, this, (opts, args): (LuaValue, Option<LuaValue>)| {
+      let cmd_opts = parse_exec_opts(lua, opts, args)?;
       Ok(this.exec(cmd_opts))
     });
 
+    methods.add_method_mut("git_config", |_, this, opts: LuaTable| {
+      let opts = parse_config_section_opts(ConfigFormat::Git, "section", opts)?;
+      Ok(this.config_section(opts))
+    });
+
+    methods.add_method_mut("ssh_config", |_, this, opts: LuaTable| {
+      let opts = parse_config_section_opts(ConfigFormat::Ssh, "host", opts)?;
+      Ok(this.config_section(opts))
+    });
+
+    methods.add_method_mut("firewall_rule", |_, this, opts: LuaTable| {
+      Ok(this.firewall_rule(parse_firewall_opts(&opts)?))
+    });
+
+    for method in ["write_file", "symlink", "copy"] {
+      methods.add_method_mut(method, move |_, this, opts: LuaTable| {
+        Ok(this.file(parse_file_opts(method, &opts)?))
+      });
+    }
+
+    methods.add_method_mut("file_block", |_, this, opts: LuaTable| {
+      Ok(this.file_block(parse_file_block_opts(&opts)?))
+    });
+
+    methods.add_method_mut("font", |_, this, opts: LuaTable| Ok(this.font(parse_font_opts(&opts)?)));
+
     // Fallback for custom registered methods (bind-specific registry)
     methods.add_meta_method(mlua::MetaMethod::Index, |lua, _this, key: String| {
       let registry: LuaTable = lua.named_registry_value(BIND_CTX_METHODS_REGISTRY_KEY)?;
@@ -209,17 +247,60 @@ pub fn bind_hash_to_lua(lua: &Lua, hash: &ObjectHash, manifest: &Manifest) -> Lu
 /// 2. Resolves inputs (calls function if dynamic, uses table directly if static)
 /// 3. Creates a ActionCtx and calls the create function
 /// 4. Optionally calls the destroy function with a fresh ActionCtx
-/// 5. Creates a BindDef, computes its hash, and adds it to the manifest
-/// 6. Returns a BindRef as a Lua table with metatable marker
+/// 5. Creates a BindDef and validates its outputs against the declared ones,
+///    and its placeholders against the manifest in the spec's placeholder mode
+/// 6. Computes its hash and adds it to the manifest
+/// 7. Returns a BindRef as a Lua table with metatable marker
 pub fn register_sys_bind(lua: &Lua, sys_table: &LuaTable, manifest: Rc<RefCell<Manifest>>) -> LuaResult<()> {
   let bind_fn = lua.create_function(move |lua, spec_table: LuaTable| {
     let bind_spec: BindSpec = lua.unpack(LuaValue::Table(spec_table))?;
+
+    // Skip binds for other platforms or whose requirements this host doesn't
+    // meet, before running create
+    let skip_reason = match unsupported_platform(lua, &bind_spec.platforms)? {
+      Some(reason) => Some(reason),
+      None => unmet_requirement(lua, &bind_spec.requires)?,
+    };
+    if let Some(reason) = skip_reason {
+      tracing::info!(id = ?bind_spec.id, reason, "skipping bind");
+      // Its inputs still tell which builds it would have used, so an apply
+      // doesn't realize them for nothing
+      let caller = SpecCaller::new(SpecKind::Bind, bind_spec.id.as_deref());
+      let builds = match bind_spec.inputs {
+        Some(inputs) => builds_referenced_by(&BindInputsDef::from_spec(lua, &manifest, inputs, caller)?)
+          .into_iter()
+          .collect(),
+        None => Vec::new(),
+      };
+      manifest.borrow_mut().skipped.push(SkippedBind {
+        id: bind_spec.id,
+        reason,
+        builds,
+      });
+      return Ok(LuaValue::Nil);
+    }
+
     let replace = bind_spec.replace;
+    let schema = bind_spec.outputs.clone();
+    let mode = PlaceholderMode::for_spec(lua, bind_spec.placeholders)?;
+    let caller = caller_location(lua);
     let bind_def = BindDef::from_spec(lua, &manifest, bind_spec)?;
-    let bind_ref = BindRef::from_def(&bind_def)?;
+    if let Some(schema) = &schema {
+      schema
+        .validate(bind_def.outputs.as_ref())
+        .map_err(|e| LuaError::external(e.describe("bind", bind_def.id.as_deref(), caller.as_deref())))?;
+    }
+    validate_bind_in(&manifest.borrow(), &bind_def, mode)
+      .map_err(|e| LuaError::external(e.describe("bind", bind_def.id.as_deref(), caller.as_deref())))?;
+    let hash_spec = registry_hash_spec(lua)?;
+    let bind_ref = BindRef::from_def(lua, &bind_def, &hash_spec)?;
+    if mode == PlaceholderMode::Strict {
+      record_strict_placeholders(lua, "bind", &bind_ref.hash, caller.as_deref())?;
+    }
 
     {
       let mut manifest = manifest.borrow_mut();
+      manifest.hash = hash_spec;
 
       // Hash dedup: identical content = same hash
       if manifest.bindings.contains_key(&bind_ref.hash) {
@@ -260,6 +341,33 @@ pub fn register_sys_bind(lua: &Lua, sys_table: &LuaTable, manifest: Rc<RefCell<M
   Ok(())
 }
 
+/// Check `requires` against `sys.facts`, returning the reason for the first
+/// requirement that isn't met. `!name` requires the fact to be false.
+fn unmet_requirement(lua: &Lua, requires: &[String]) -> LuaResult<Option<String>> {
+  if requires.is_empty() {
+    return Ok(None);
+  }
+
+  let sys: LuaTable = lua.globals().get("sys")?;
+  let facts: LuaTable = sys.get("facts")?;
+  for requirement in requires {
+    let (name, wanted) = match requirement.strip_prefix('!') {
+      Some(name) => (name, false),
+      None => (requirement.as_str(), true),
+    };
+    let present = facts.get::<Option<bool>>(name)?.unwrap_or(false);
+    if present != wanted {
+      let reason = if wanted {
+        format!("requires {}", name)
+      } else {
+        format!("not supported on {}", name)
+      };
+      return Ok(Some(reason));
+    }
+  }
+  Ok(None)
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -354,6 +462,33 @@ mod tests {
       Ok(())
     }
 
+    #[test]
+    fn bind_missing_declared_output_fails() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      let result = lua
+        .load(
+          r#"
+                return sys.bind({
+                    id = "dotfiles",
+                    outputs = { link = "path" },
+                    create = function(inputs, ctx)
+                        ctx:exec("ln -sf /src /dest")
+                    end,
+                    destroy = function(outputs, ctx) end,
+                })
+            "#,
+        )
+        .eval::<LuaTable>();
+
+      let err = result.unwrap_err().to_string();
+      assert!(err.contains("outputs of bind 'dotfiles'"), "{}", err);
+      assert!(err.contains("create did not return declared output 'link'"), "{}", err);
+      assert!(manifest.borrow().bindings.is_empty());
+
+      Ok(())
+    }
+
     #[test]
     fn bind_with_destroy() -> LuaResult<()> {
       let (lua, manifest) = create_test_lua_with_manifest()?;
@@ -382,6 +517,422 @@ mod tests {
       Ok(())
     }
 
+    #[test]
+    fn bind_with_tags_records_tags_outside_hash() -> LuaResult<()> {
+      use crate::util::hash::Hashable;
+
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      lua
+        .load(
+          r#"
+                return sys.bind({
+                    tags = { "shell", "dotfiles" },
+                    create = function(inputs, ctx)
+                        ctx:exec("ln -sf /src /dest")
+                    end,
+                    destroy = function(outputs, ctx)
+                        ctx:exec("rm /dest")
+                    end,
+                })
+            "#,
+        )
+        .eval::<LuaTable>()?;
+
+      let manifest = manifest.borrow();
+      let (hash, bind_def) = manifest.bindings.iter().next().unwrap();
+      assert_eq!(bind_def.tags, vec!["shell", "dotfiles"]);
+
+      let untagged = BindDef {
+        tags: Vec::new(),
+        ..bind_def.clone()
+      };
+      assert_eq!(&untagged.compute_hash().unwrap(), hash);
+
+      Ok(())
+    }
+
+    #[test]
+    fn bind_with_invalid_tags_fails() -> LuaResult<()> {
+      let (lua, _) = create_test_lua_with_manifest()?;
+
+      let result = lua
+        .load(
+          r#"
+                return sys.bind({
+                    tags = 42,
+                    create = function(inputs, ctx) end,
+                    destroy = function(outputs, ctx) end,
+                })
+            "#,
+        )
+        .eval::<LuaTable>();
+
+      let err = result.unwrap_err().to_string();
+      assert!(
+        err.contains("bind tags must be a string or a list of strings"),
+        "{}",
+        err
+      );
+
+      Ok(())
+    }
+
+    #[test]
+    fn bind_with_group_records_group() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      lua
+        .load(
+          r#"
+                return sys.bind({
+                    group = "neovim",
+                    create = function(inputs, ctx) end,
+                    destroy = function(outputs, ctx) end,
+                })
+            "#,
+        )
+        .eval::<LuaTable>()?;
+
+      let manifest = manifest.borrow();
+      let (_, bind_def) = manifest.bindings.iter().next().unwrap();
+      assert_eq!(bind_def.group.as_deref(), Some("neovim"));
+
+      let result = lua
+        .load(
+          r#"
+                return sys.bind({
+                    group = "",
+                    create = function(inputs, ctx) end,
+                    destroy = function(outputs, ctx) end,
+                })
+            "#,
+        )
+        .eval::<LuaTable>();
+      assert!(
+        result
+          .unwrap_err()
+          .to_string()
+          .contains("bind `group` name must not be empty")
+      );
+
+      Ok(())
+    }
+
+    #[test]
+    fn bind_with_login_phase_records_phase() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      lua
+        .load(
+          r#"
+                return sys.bind({
+                    phase = "login",
+                    create = function(inputs, ctx) ctx:exec("launchctl start agent") end,
+                    destroy = function(outputs, ctx) ctx:exec("launchctl stop agent") end,
+                })
+            "#,
+        )
+        .eval::<LuaTable>()?;
+
+      {
+        let manifest = manifest.borrow();
+        let (_, bind_def) = manifest.bindings.iter().next().unwrap();
+        assert_eq!(bind_def.phase, crate::bind::BindPhase::Login);
+      }
+
+      for (fields, message) in [
+        (
+          "check = function(outputs, inputs, ctx) return { drifted = 'false' } end,",
+          "login-phase binds can't have `check`",
+        ),
+        ("", "login-phase binds can't return outputs"),
+      ] {
+        let create = if fields.is_empty() {
+          "function(inputs, ctx) return { path = '/tmp/x' } end"
+        } else {
+          "function(inputs, ctx) end"
+        };
+        let result = lua
+          .load(format!(
+            r#"return sys.bind({{ phase = "login", {} create = {}, destroy = function(outputs, ctx) end }})"#,
+            fields, create
+          ))
+          .eval::<LuaTable>();
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains(message), "{}", err);
+      }
+
+      Ok(())
+    }
+
+    #[test]
+    fn bind_with_fallback_path_records_fallbacks() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      lua
+        .load(
+          r#"
+                sys.bind({
+                    fallback_path = "/home/u/.local/bin/rg",
+                    create = function(inputs, ctx) ctx:exec("ln -sf /opt/rg /usr/local/bin/rg") end,
+                    destroy = function(outputs, ctx) ctx:exec("rm -f /usr/local/bin/rg") end,
+                })
+                sys.bind({
+                    fallback_path = { ["/etc/rg.conf"] = "/home/u/.config/rg.conf" },
+                    create = function(inputs, ctx) ctx:exec("cp /opt/rg.conf /etc/rg.conf") end,
+                    destroy = function(outputs, ctx) ctx:exec("rm -f /etc/rg.conf") end,
+                })
+            "#,
+        )
+        .exec()?;
+
+      {
+        let manifest = manifest.borrow();
+        let mut fallbacks: Vec<_> = manifest
+          .bindings
+          .values()
+          .map(|bind_def| bind_def.fallback_path.clone().unwrap())
+          .collect();
+        fallbacks.sort_by_key(|fallback| matches!(fallback, crate::bind::BindFallbackDef::Targets(_)));
+        assert_eq!(
+          fallbacks,
+          vec![
+            crate::bind::BindFallbackDef::Any("/home/u/.local/bin/rg".to_string()),
+            crate::bind::BindFallbackDef::Targets(
+              [("/etc/rg.conf".to_string(), "/home/u/.config/rg.conf".to_string())].into()
+            ),
+          ]
+        );
+      }
+
+      let result = lua
+        .load(
+          r#"return sys.bind({ fallback_path = {}, create = function(inputs, ctx) end, destroy = function(outputs, ctx) end })"#,
+        )
+        .eval::<LuaTable>();
+      let err = result.unwrap_err().to_string();
+      assert!(err.contains("bind `fallback_path` table must not be empty"), "{}", err);
+
+      Ok(())
+    }
+
+    #[test]
+    fn bind_with_repair_policy_records_policy() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      lua
+        .load(
+          r#"
+                return sys.bind({
+                    repair = "if-missing",
+                    create = function(inputs, ctx) end,
+                    destroy = function(outputs, ctx) end,
+                })
+            "#,
+        )
+        .eval::<LuaTable>()?;
+
+      let manifest = manifest.borrow();
+      let (_, bind_def) = manifest.bindings.iter().next().unwrap();
+      let repair = bind_def.repair.as_ref().unwrap();
+      assert_eq!(repair.policy, crate::bind::repair::RepairPolicy::IfMissing);
+      assert!(repair.ignore.is_empty());
+
+      let result = lua
+        .load(
+          r#"
+                return sys.bind({
+                    repair = "sometimes",
+                    create = function(inputs, ctx) end,
+                    destroy = function(outputs, ctx) end,
+                })
+            "#,
+        )
+        .eval::<LuaTable>();
+      assert!(
+        result
+          .unwrap_err()
+          .to_string()
+          .contains("unknown repair policy 'sometimes'")
+      );
+
+      Ok(())
+    }
+
+    #[test]
+    fn bind_with_unmet_requirement_is_skipped() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      let skipped: LuaValue = lua
+        .load(
+          r#"
+                sys.facts.systemd = false
+                sys.facts.container = true
+                sys.bind({
+                    id = "outside-containers",
+                    requires = "!container",
+                    create = function(inputs, ctx) end,
+                    destroy = function(outputs, ctx) end,
+                })
+                return sys.bind({
+                    id = "service",
+                    requires = { "systemd" },
+                    create = function(inputs, ctx) error("create must not run") end,
+                    destroy = function(outputs, ctx) end,
+                })
+            "#,
+        )
+        .eval()?;
+
+      assert!(skipped.is_nil());
+      let manifest = manifest.borrow();
+      assert!(manifest.bindings.is_empty());
+      assert_eq!(
+        manifest.skipped,
+        vec![
+          SkippedBind {
+            id: Some("outside-containers".to_string()),
+            reason: "not supported on container".to_string(),
+            builds: Vec::new(),
+          },
+          SkippedBind {
+            id: Some("service".to_string()),
+            reason: "requires systemd".to_string(),
+            builds: Vec::new(),
+          },
+        ]
+      );
+
+      Ok(())
+    }
+
+    #[test]
+    fn bind_for_other_platforms_is_skipped() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      lua
+        .load(
+          r#"
+                sys.platform = "x86_64-linux"
+                assert(sys.bind({
+                    id = "everywhere",
+                    platforms = { "aarch64-darwin", "x86_64-linux" },
+                    create = function(inputs, ctx) end,
+                    destroy = function(outputs, ctx) end,
+                }))
+                assert(sys.bind({
+                    id = "mac-only",
+                    platforms = { "aarch64-darwin" },
+                    create = function(inputs, ctx) error("create must not run") end,
+                    destroy = function(outputs, ctx) end,
+                }) == nil)
+            "#,
+        )
+        .exec()?;
+
+      let manifest = manifest.borrow();
+      assert_eq!(manifest.bindings.len(), 1);
+      assert_eq!(
+        manifest.skipped,
+        vec![SkippedBind {
+          id: Some("mac-only".to_string()),
+          reason: "platform (only aarch64-darwin)".to_string(),
+          builds: Vec::new(),
+        }]
+      );
+
+      let err = lua
+        .load(
+          r#"sys.bind({
+              platforms = { "x86_64-macos" },
+              create = function(inputs, ctx) end,
+              destroy = function(outputs, ctx) end,
+          })"#,
+        )
+        .exec()
+        .unwrap_err();
+      assert!(err.to_string().contains("unknown platform 'x86_64-macos'"), "{}", err);
+
+      Ok(())
+    }
+
+    #[test]
+    fn skipped_bind_records_the_builds_of_its_inputs() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      lua
+        .load(
+          r#"
+                sys.facts.systemd = false
+                local tool = sys.build({
+                    id = "tool",
+                    create = function(inputs, ctx) return { out = ctx.out } end,
+                })
+                sys.bind({
+                    id = "service",
+                    requires = "systemd",
+                    inputs = function() return { bin = tool.outputs.out .. "/bin/tool" } end,
+                    create = function(inputs, ctx) end,
+                    destroy = function(outputs, ctx) end,
+                })
+            "#,
+        )
+        .exec()?;
+
+      let manifest = manifest.borrow();
+      let (tool_hash, _) = manifest.builds.iter().next().unwrap();
+      assert_eq!(manifest.skipped[0].builds, vec![tool_hash.clone()]);
+
+      Ok(())
+    }
+
+    #[test]
+    fn bind_with_met_requirement_is_recorded() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      lua
+        .load(
+          r#"
+                sys.facts.systemd = true
+                return sys.bind({
+                    requires = "systemd",
+                    create = function(inputs, ctx) end,
+                    destroy = function(outputs, ctx) end,
+                })
+            "#,
+        )
+        .eval::<LuaTable>()?;
+
+      let manifest = manifest.borrow();
+      assert_eq!(manifest.bindings.len(), 1);
+      assert!(manifest.skipped.is_empty());
+
+      Ok(())
+    }
+
+    #[test]
+    fn bind_with_unknown_requirement_fails() -> LuaResult<()> {
+      let (lua, _) = create_test_lua_with_manifest()?;
+
+      let result = lua
+        .load(
+          r#"
+                return sys.bind({
+                    requires = "gpu",
+                    create = function(inputs, ctx) end,
+                    destroy = function(outputs, ctx) end,
+                })
+            "#,
+        )
+        .eval::<LuaTable>();
+
+      let err = result.unwrap_err().to_string();
+      assert!(err.contains("unknown bind requirement 'gpu'"), "{}", err);
+
+      Ok(())
+    }
+
     #[test]
     fn bind_with_inputs_from_build() -> LuaResult<()> {
       let (lua, manifest) = create_test_lua_with_manifest()?;
@@ -14,15 +14,29 @@
 //!
 //! # Submodules
 //!
+//! - [`backup`] - Backups of files replaced by binds
 //! - [`execute`] - Bind execution engine
+//! - [`file_block`] - `sys.file_block`, marker-delimited blocks of files managed as binds
+//! - [`firewall`] - `sys.firewall.rule`, host firewall rules managed as binds
+//! - [`font`] - `sys.font`, fonts installed where the OS looks for them, managed as binds
 //! - [`lua`] - Lua context (`BindCtx`) exposed to bind scripts
+//! - [`pkgset`] - `sys.pkgset`, package sets managed as one bind
+//! - [`repair`] - Repair policies for drifted binds
 //! - [`state`] - Bind state tracking for the current system
 //! - [`store`] - Persistent bind metadata in the store
+//! - [`target`] - Probing bind targets, and `fallback_path`
 
+pub mod backup;
 pub mod execute;
+pub mod file_block;
+pub mod firewall;
+pub mod font;
 pub mod lua;
+pub mod pkgset;
+pub mod repair;
 pub mod state;
 pub mod store;
+pub mod target;
 mod types;
 
 pub use types::*;
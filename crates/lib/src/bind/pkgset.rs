@@ -0,0 +1,503 @@
+//! Package sets managed as a single bind.
+//!
+//! `sys.pkgset` declares every package a package manager should have
+//! installed, like a Homebrew `Brewfile`:
+//!
+//! ```lua
+//! sys.pkgset({ manager = "brew", packages = { "jq", "ripgrep", "fd" } })
+//! ```
+//!
+//! The set is one bind (id `pkgset-<manager>` unless given) whose `packages`
+//! output is the sorted, space-separated list. Creating it installs the
+//! packages that aren't installed yet. Changing the list updates the bind in
+//! place: its update actions compare the list recorded by the previous apply
+//! (`$${{prev:packages}}`) with the new one, and only install the additions
+//! and uninstall the removals. Destroying the bind uninstalls the whole set.
+//! A drift check reports packages of the set that were uninstalled behind
+//! syslua's back.
+//!
+//! Commands run through `/bin/sh` with `PATH` set to the directory of the
+//! manager's binary and the system directories, and `HOME` passed through.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use mlua::prelude::*;
+
+use crate::action::actions::exec::ExecOpts;
+
+use super::BindCtx;
+
+/// Name of the output holding the installed set.
+pub const PACKAGES_OUTPUT: &str = "packages";
+
+/// Keys of a `sys.pkgset` spec passed on to `sys.bind` unchanged.
+const BIND_KEYS: &[&str] = &[
+  "replace",
+  "tags",
+  "group",
+  "repair",
+  "repair_ignore",
+  "requires",
+  "platforms",
+  "serialize",
+];
+
+const SHELL: &str = "/bin/sh";
+
+/// A package manager `sys.pkgset` can drive.
+#[derive(Debug, Clone, Copy)]
+pub struct Manager {
+  pub name: &'static str,
+  /// Arguments listing the installed packages, one name per line.
+  list: &'static str,
+  /// Arguments installing the packages that follow.
+  install: &'static str,
+  /// Arguments uninstalling the packages that follow.
+  uninstall: &'static str,
+  /// Where the manager's binary is installed by default on this host.
+  default_bin: fn() -> Option<&'static str>,
+}
+
+pub const MANAGERS: &[Manager] = &[Manager {
+  name: "brew",
+  list: "list --formula -1",
+  install: "install --formula",
+  uninstall: "uninstall --formula",
+  default_bin: default_brew,
+}];
+
+fn default_brew() -> Option<&'static str> {
+  match (std::env::consts::OS, std::env::consts::ARCH) {
+    ("macos", "aarch64") => Some("/opt/homebrew/bin/brew"),
+    ("macos", _) => Some("/usr/local/bin/brew"),
+    ("linux", _) => Some("/home/linuxbrew/.linuxbrew/bin/brew"),
+    _ => None,
+  }
+}
+
+/// Shell function telling whether `$1` is in `$installed`. Tap-qualified
+/// names (`user/tap/name`) are listed by their last component.
+const IS_INSTALLED: &str = r#"is_installed() { case "$installed" in *" ${1##*/} "*) return 0 ;; esac; return 1; }"#;
+
+impl Manager {
+  pub fn find(name: &str) -> Option<&'static Manager> {
+    MANAGERS.iter().find(|manager| manager.name == name)
+  }
+
+  fn list_installed(&self) -> String {
+    format!(r#"installed=" $("$bin" {} | tr '\n' ' ') ""#, self.list)
+  }
+
+  /// Script moving from the set `$2` to the set `$3` with the binary `$1`:
+  /// installs what is missing from `$3`, and uninstalls what `$2` has but
+  /// `$3` doesn't.
+  fn sync_script(&self) -> String {
+    [
+      "set -e",
+      "bin=$1 prev=$2 want=$3",
+      &self.list_installed(),
+      IS_INSTALLED,
+      "add=",
+      r#"for p in $want; do is_installed "$p" || add="$add $p"; done"#,
+      "remove=",
+      r#"for p in $prev; do case " $want " in *" $p "*) continue ;; esac; if is_installed "$p"; then remove="$remove $p"; fi; done"#,
+      &format!(r#"if [ -n "$add" ]; then "$bin" {} $add; fi"#, self.install),
+      &format!(r#"if [ -n "$remove" ]; then "$bin" {} $remove; fi"#, self.uninstall),
+    ]
+    .join("\n")
+  }
+
+  /// Script printing the packages of the set `$2` that aren't installed.
+  fn missing_script(&self) -> String {
+    [
+      "set -e",
+      "bin=$1 want=$2",
+      &self.list_installed(),
+      IS_INSTALLED,
+      "missing=",
+      r#"for p in $want; do is_installed "$p" || missing="$missing $p"; done"#,
+      r#"echo $missing"#,
+    ]
+    .join("\n")
+  }
+}
+
+/// A parsed `sys.pkgset` spec.
+#[derive(Debug, Clone)]
+pub struct PackageSet {
+  pub manager: &'static Manager,
+  /// Path to the manager's binary.
+  pub bin: String,
+  pub packages: BTreeSet<String>,
+}
+
+impl PackageSet {
+  /// The set as recorded in the `packages` output.
+  pub fn list(&self) -> String {
+    self.packages.iter().cloned().collect::<Vec<_>>().join(" ")
+  }
+
+  fn command(&self, script: String, args: &[&str]) -> ExecOpts {
+    let dir = std::path::Path::new(&self.bin)
+      .parent()
+      .map(|dir| dir.to_string_lossy().into_owned())
+      .unwrap_or_default();
+    let mut all_args = vec!["-c".to_string(), script, "sh".to_string(), self.bin.clone()];
+    all_args.extend(args.iter().map(|arg| arg.to_string()));
+
+    ExecOpts::new(SHELL).with_args(all_args).with_env(BTreeMap::from([
+      ("PATH".to_string(), format!("{}:/usr/bin:/bin:/usr/sbin:/sbin", dir)),
+      ("HOME".to_string(), "$${{env:HOME}}".to_string()),
+    ]))
+  }
+
+  /// Move from the set `prev` (space-separated) to `want`.
+  pub fn sync(&self, prev: &str, want: &str) -> ExecOpts {
+    self.command(self.manager.sync_script(), &[prev, want])
+  }
+
+  /// Print the packages of the set that aren't installed.
+  pub fn missing(&self) -> ExecOpts {
+    self.command(self.manager.missing_script(), &[&self.list()])
+  }
+}
+
+/// Whether `name` can be passed to a package manager as is: no whitespace,
+/// shell syntax or leading `-`.
+fn is_valid_package(name: &str) -> bool {
+  !name.is_empty()
+    && !name.starts_with('-')
+    && name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || matches!(c, '@' | '.' | '_' | '+' | '-' | '/'))
+}
+
+impl FromLua for PackageSet {
+  fn from_lua(value: LuaValue, _: &Lua) -> LuaResult<Self> {
+    let LuaValue::Table(table) = value else {
+      return Err(LuaError::external("sys.pkgset expects a table"));
+    };
+
+    let name: String = table
+      .get::<Option<String>>("manager")
+      .map_err(|_| LuaError::external("sys.pkgset `manager` must be a string"))?
+      .ok_or_else(|| LuaError::external("sys.pkgset requires a `manager`"))?;
+    let manager = Manager::find(&name).ok_or_else(|| {
+      let known: Vec<&str> = MANAGERS.iter().map(|manager| manager.name).collect();
+      LuaError::external(format!(
+        "unknown package manager '{}' (expected {})",
+        name,
+        known.join(" or ")
+      ))
+    })?;
+
+    let bin = match table
+      .get::<Option<String>>("bin")
+      .map_err(|_| LuaError::external("sys.pkgset `bin` must be a path"))?
+    {
+      Some(bin) => bin,
+      None => (manager.default_bin)()
+        .ok_or_else(|| {
+          LuaError::external(format!(
+            "package manager '{}' is not supported on {}",
+            manager.name,
+            std::env::consts::OS
+          ))
+        })?
+        .to_string(),
+    };
+
+    let packages: Vec<String> = match table.get::<LuaValue>("packages")? {
+      LuaValue::Table(list) => list
+        .sequence_values::<String>()
+        .collect::<LuaResult<_>>()
+        .map_err(|_| LuaError::external("sys.pkgset `packages` must be a list of package names"))?,
+      LuaValue::Nil => return Err(LuaError::external("sys.pkgset requires `packages`")),
+      _ => {
+        return Err(LuaError::external(
+          "sys.pkgset `packages` must be a list of package names",
+        ));
+      }
+    };
+    if let Some(invalid) = packages.iter().find(|name| !is_valid_package(name)) {
+      return Err(LuaError::external(format!("invalid package name '{}'", invalid)));
+    }
+
+    Ok(Self {
+      manager,
+      bin,
+      packages: packages.into_iter().collect(),
+    })
+  }
+}
+
+/// Register the `sys.pkgset` function on the sys table.
+///
+/// `sys.pkgset{}` builds a bind spec from the package set and passes it to
+/// `sys.bind`, so it must be registered after it. Returns the BindRef.
+pub fn register_sys_pkgset(lua: &Lua, sys_table: &LuaTable) -> LuaResult<()> {
+  let bind_fn: LuaFunction = sys_table.get("bind")?;
+
+  let pkgset_fn = lua.create_function(move |lua, spec: LuaTable| {
+    let set: PackageSet = lua.unpack(LuaValue::Table(spec.clone()))?;
+    let id = spec
+      .get::<Option<String>>("id")?
+      .unwrap_or_else(|| format!("pkgset-{}", set.manager.name));
+
+    let bind_spec = lua.create_table()?;
+    bind_spec.set("id", id)?;
+    for key in BIND_KEYS {
+      bind_spec.set(*key, spec.get::<LuaValue>(*key)?)?;
+    }
+
+    let create_set = set.clone();
+    bind_spec.set(
+      "create",
+      lua.create_function(move |lua, (_inputs, ctx): (LuaValue, LuaAnyUserData)| {
+        ctx
+          .borrow_mut::<BindCtx>()?
+          .exec(create_set.sync("", &create_set.list()));
+        outputs(lua, &create_set)
+      })?,
+    )?;
+
+    let update_set = set.clone();
+    bind_spec.set(
+      "update",
+      lua.create_function(
+        move |lua, (_outputs, _inputs, ctx): (LuaValue, LuaValue, LuaAnyUserData)| {
+          let prev = format!("$${{{{prev:{}}}}}", PACKAGES_OUTPUT);
+          ctx
+            .borrow_mut::<BindCtx>()?
+            .exec(update_set.sync(&prev, &update_set.list()));
+          outputs(lua, &update_set)
+        },
+      )?,
+    )?;
+
+    let destroy_set = set.clone();
+    bind_spec.set(
+      "destroy",
+      lua.create_function(move |_, (_outputs, ctx): (LuaValue, LuaAnyUserData)| {
+        ctx
+          .borrow_mut::<BindCtx>()?
+          .exec(destroy_set.sync(&destroy_set.list(), ""));
+        Ok(())
+      })?,
+    )?;
+
+    let check_set = set;
+    bind_spec.set(
+      "check",
+      lua.create_function(
+        move |lua, (_outputs, _inputs, ctx): (LuaValue, LuaValue, LuaAnyUserData)| {
+          let mut ctx = ctx.borrow_mut::<BindCtx>()?;
+          let missing = ctx.exec(check_set.missing());
+          let drifted = ctx.exec(ExecOpts::new(SHELL).with_args(vec![
+            "-c".to_string(),
+            r#"if [ -n "$1" ]; then echo true; else echo false; fi"#.to_string(),
+            "sh".to_string(),
+            missing.clone(),
+          ]));
+          let result = lua.create_table()?;
+          result.set("drifted", drifted)?;
+          result.set("message", format!("packages not installed: {}", missing))?;
+          Ok(result)
+        },
+      )?,
+    )?;
+
+    bind_fn.call::<LuaValue>(bind_spec)
+  })?;
+
+  sys_table.set("pkgset", pkgset_fn)?;
+  Ok(())
+}
+
+fn outputs(lua: &Lua, set: &PackageSet) -> LuaResult<LuaTable> {
+  let outputs = lua.create_table()?;
+  outputs.set(PACKAGES_OUTPUT, set.list())?;
+  Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn brew_set(packages: &[&str]) -> PackageSet {
+    PackageSet {
+      manager: Manager::find("brew").unwrap(),
+      bin: "/opt/homebrew/bin/brew".to_string(),
+      packages: packages.iter().map(|name| name.to_string()).collect(),
+    }
+  }
+
+  #[test]
+  fn package_names_are_validated() {
+    assert!(is_valid_package("python@3.12"));
+    assert!(is_valid_package("homebrew/cask/firefox"));
+    assert!(is_valid_package("gtk+3"));
+    assert!(!is_valid_package(""));
+    assert!(!is_valid_package("--force"));
+    assert!(!is_valid_package("jq; rm -rf /"));
+    assert!(!is_valid_package("two words"));
+  }
+
+  #[test]
+  fn spec_is_parsed_sorted_and_deduplicated() -> LuaResult<()> {
+    let lua = Lua::new();
+    let set: PackageSet = lua
+      .load(r#"return { manager = "brew", bin = "/usr/local/bin/brew", packages = { "ripgrep", "jq", "jq" } }"#)
+      .eval()?;
+    assert_eq!(set.list(), "jq ripgrep");
+    assert_eq!(set.bin, "/usr/local/bin/brew");
+
+    let err = lua
+      .load(r#"return { manager = "apt", packages = {} }"#)
+      .eval::<PackageSet>()
+      .unwrap_err();
+    assert!(
+      err
+        .to_string()
+        .contains("unknown package manager 'apt' (expected brew)")
+    );
+
+    let err = lua
+      .load(r#"return { manager = "brew", bin = "/b", packages = { "jq", "$(reboot)" } }"#)
+      .eval::<PackageSet>()
+      .unwrap_err();
+    assert!(err.to_string().contains("invalid package name '$(reboot)'"));
+    Ok(())
+  }
+
+  #[test]
+  fn sync_passes_sets_as_arguments() {
+    let opts = brew_set(&["jq"]).sync("$${{prev:packages}}", "jq");
+    assert_eq!(opts.bin, SHELL);
+    let args = opts.args.unwrap();
+    assert_eq!(
+      &args[2..],
+      ["sh", "/opt/homebrew/bin/brew", "$${{prev:packages}}", "jq"]
+    );
+    let env = opts.env.unwrap();
+    assert_eq!(env["PATH"], "/opt/homebrew/bin:/usr/bin:/bin:/usr/sbin:/sbin");
+  }
+
+  #[test]
+  fn pkgset_declares_one_bind_updating_from_the_previous_set() -> LuaResult<()> {
+    use crate::action::Action;
+    use crate::manifest::Manifest;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let lua = crate::lua::runtime::create_lua(false)?;
+    let manifest = Rc::new(RefCell::new(Manifest::default()));
+    crate::lua::globals::register_globals(&lua, manifest.clone())?;
+
+    let bind_ref: LuaTable = lua
+      .load(r#"return sys.pkgset({ manager = "brew", bin = "/b/brew", packages = { "jq", "fd" }, group = "tools" })"#)
+      .eval()?;
+    let outputs: LuaTable = bind_ref.get("outputs")?;
+    assert!(outputs.get::<String>(PACKAGES_OUTPUT)?.starts_with("$${{bind:"));
+
+    let manifest = manifest.borrow();
+    let (_, def) = manifest.bindings.iter().next().unwrap();
+    assert_eq!(def.id.as_deref(), Some("pkgset-brew"));
+    assert_eq!(def.group.as_deref(), Some("tools"));
+    assert_eq!(def.outputs.as_ref().unwrap()[PACKAGES_OUTPUT], "fd jq");
+    assert!(def.check_actions.is_some());
+
+    let Some([Action::Exec(update)]) = def.update_actions.as_deref() else {
+      panic!("expected one update action");
+    };
+    assert_eq!(&update.args.as_ref().unwrap()[4..], ["$${{prev:packages}}", "fd jq"]);
+    let [Action::Exec(destroy)] = def.destroy_actions.as_slice() else {
+      panic!("expected one destroy action");
+    };
+    assert_eq!(&destroy.args.as_ref().unwrap()[4..], ["fd jq", ""]);
+    Ok(())
+  }
+
+  #[cfg(unix)]
+  mod scripts {
+    use super::*;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    /// A fake manager binary keeping its installed packages in a file and
+    /// logging every install and uninstall.
+    fn fake_manager(dir: &std::path::Path, installed: &[&str]) -> String {
+      use std::os::unix::fs::PermissionsExt;
+
+      let db = dir.join("installed");
+      std::fs::write(&db, installed.iter().map(|p| format!("{p}\n")).collect::<String>()).unwrap();
+      let bin = dir.join("brew");
+      std::fs::write(
+        &bin,
+        format!(
+          r#"#!/bin/sh
+db={db}
+cmd=$1; shift 2
+case $cmd in
+  list) cat "$db" ;;
+  install) echo "install $*" >> {log}; for p in "$@"; do echo "${{p##*/}}" >> "$db"; done ;;
+  uninstall) echo "uninstall $*" >> {log}; for p in "$@"; do grep -vx "$p" "$db" > "$db.new" || true; mv "$db.new" "$db"; done ;;
+esac
+"#,
+          db = db.display(),
+          log = dir.join("log").display()
+        ),
+      )
+      .unwrap();
+      std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+      bin.to_string_lossy().into_owned()
+    }
+
+    fn run(opts: &ExecOpts) -> String {
+      let output = Command::new(&opts.bin)
+        .args(opts.args.as_ref().unwrap())
+        .env("PATH", "/usr/bin:/bin")
+        .output()
+        .unwrap();
+      assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+      String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    fn log(dir: &std::path::Path) -> String {
+      std::fs::read_to_string(dir.join("log")).unwrap_or_default()
+    }
+
+    #[test]
+    fn sync_installs_and_uninstalls_only_the_delta() {
+      let temp = TempDir::new().unwrap();
+      let mut set = brew_set(&[]);
+      set.bin = fake_manager(temp.path(), &["jq", "wget"]);
+
+      // jq is already installed; wget isn't part of any set
+      run(&set.sync("", "jq ripgrep"));
+      assert_eq!(log(temp.path()), "install ripgrep\n");
+
+      run(&set.sync("jq ripgrep", "ripgrep fd"));
+      assert_eq!(log(temp.path()), "install ripgrep\ninstall fd\nuninstall jq\n");
+
+      run(&set.sync("ripgrep fd", ""));
+      assert_eq!(
+        log(temp.path()),
+        "install ripgrep\ninstall fd\nuninstall jq\nuninstall ripgrep fd\n"
+      );
+      assert_eq!(
+        std::fs::read_to_string(temp.path().join("installed")).unwrap(),
+        "wget\n"
+      );
+    }
+
+    #[test]
+    fn missing_lists_uninstalled_packages() {
+      let temp = TempDir::new().unwrap();
+      let mut set = brew_set(&["jq", "homebrew/core/ripgrep", "fd"]);
+      set.bin = fake_manager(temp.path(), &["jq", "ripgrep"]);
+
+      assert_eq!(run(&set.missing()), "fd");
+    }
+  }
+}
@@ -0,0 +1,209 @@
+//! Repair policies for drifted binds.
+//!
+//! `sys apply --repair` re-runs `create` for binds whose check reports drift.
+//! Some drift is expected, e.g. a program that reorders its own config file,
+//! so a bind can limit when it is repaired:
+//!
+//! ```lua
+//! sys.bind({
+//!   repair = "if-missing",
+//!   repair_ignore = { "~/.config/app/state.json" },
+//!   create = function(inputs, ctx) ... end,
+//!   destroy = function(outputs, ctx) ... end,
+//! })
+//! ```
+//!
+//! Ignore patterns from `settings.repair_ignore` apply to every bind. They are
+//! matched against the paths a bind manages: its `backup` paths and the
+//! absolute paths among its outputs.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::bind::BindDef;
+use crate::util::glob::glob_match;
+
+/// Key for storing the config-level ignore patterns (`settings.repair_ignore`) in Lua's registry.
+pub const REPAIR_IGNORE_REGISTRY_KEY: &str = "__syslua_repair_ignore";
+
+/// When a drifted bind is repaired.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RepairPolicy {
+  /// Repair whenever the check reports drift.
+  #[default]
+  Always,
+  /// Never repair; drift is only reported.
+  Never,
+  /// Repair only when a managed path no longer exists.
+  IfMissing,
+}
+
+impl FromStr for RepairPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "always" => Ok(Self::Always),
+      "never" => Ok(Self::Never),
+      "if-missing" => Ok(Self::IfMissing),
+      other => Err(format!(
+        "unknown repair policy '{}' (expected always, never or if-missing)",
+        other
+      )),
+    }
+  }
+}
+
+/// A bind's repair policy and the managed paths whose drift it ignores.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BindRepairDef {
+  #[serde(default)]
+  pub policy: RepairPolicy,
+  /// Path patterns, with `~` and environment variables expanded. `*` and `?`
+  /// match within a path component, `**` across components.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub ignore: Vec<String>,
+}
+
+impl BindRepairDef {
+  /// Why `--repair` should leave a drifted bind alone, or `None` to repair it.
+  ///
+  /// `outputs` are the bind's resolved outputs. A bind left `needs_repair` by a
+  /// failed rollback is in an unknown state, so only `never` keeps it from
+  /// being repaired.
+  pub fn skip_reason(&self, def: &BindDef, outputs: &HashMap<String, JsonValue>, needs_repair: bool) -> Option<String> {
+    if self.policy == RepairPolicy::Never {
+      return Some("repair policy is 'never'".to_string());
+    }
+    if needs_repair {
+      return None;
+    }
+
+    let managed = managed_paths(def, outputs);
+    let watched: Vec<&str> = managed
+      .iter()
+      .map(String::as_str)
+      .filter(|path| !self.ignore.iter().any(|pattern| glob_match(pattern, path)))
+      .collect();
+    if !managed.is_empty() && watched.is_empty() {
+      return Some("all managed paths match repair_ignore".to_string());
+    }
+
+    match self.policy {
+      RepairPolicy::IfMissing if watched.iter().all(|path| Path::new(path).exists()) => {
+        Some("repair policy is 'if-missing' and no managed path is missing".to_string())
+      }
+      _ => None,
+    }
+  }
+}
+
+/// The paths a bind manages: its backup paths and absolute path outputs.
+fn managed_paths(def: &BindDef, outputs: &HashMap<String, JsonValue>) -> Vec<String> {
+  let mut paths: Vec<String> = def.backup.iter().flat_map(|b| b.paths.iter().cloned()).collect();
+  for value in outputs.values() {
+    if let JsonValue::String(s) = value
+      && Path::new(s).is_absolute()
+      && !paths.contains(s)
+    {
+      paths.push(s.clone());
+    }
+  }
+  paths
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::bind::BindBackupDef;
+  use tempfile::TempDir;
+
+  fn bind(backup: &[&str]) -> BindDef {
+    BindDef {
+      id: None,
+      inputs: None,
+      outputs: None,
+      create_actions: vec![],
+      update_actions: None,
+      destroy_actions: vec![],
+      check_actions: None,
+      check_outputs: None,
+      backup: (!backup.is_empty()).then(|| BindBackupDef {
+        paths: backup.iter().map(|p| p.to_string()).collect(),
+        max_size: 1024,
+      }),
+      fallback_path: None,
+      tags: vec![],
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
+    }
+  }
+
+  fn outputs(path: &str) -> HashMap<String, JsonValue> {
+    HashMap::from([
+      ("path".to_string(), JsonValue::String(path.to_string())),
+      ("name".to_string(), JsonValue::String("not-a-path".to_string())),
+    ])
+  }
+
+  #[test]
+  fn never_and_ignore_skip_repair() {
+    let def = bind(&[]);
+    let never = BindRepairDef {
+      policy: RepairPolicy::Never,
+      ignore: vec![],
+    };
+    assert!(never.skip_reason(&def, &outputs("/etc/app.conf"), false).is_some());
+    assert!(never.skip_reason(&def, &outputs("/etc/app.conf"), true).is_some());
+
+    let ignored = BindRepairDef {
+      policy: RepairPolicy::Always,
+      ignore: vec!["/etc/*.conf".to_string()],
+    };
+    assert!(ignored.skip_reason(&def, &outputs("/etc/app.conf"), false).is_some());
+    assert_eq!(ignored.skip_reason(&def, &outputs("/etc/app.yaml"), false), None);
+    // A failed rollback is repaired despite ignore rules
+    assert_eq!(ignored.skip_reason(&def, &outputs("/etc/app.conf"), true), None);
+
+    // Backup paths are managed too
+    let def = bind(&["/etc/other.yaml"]);
+    assert_eq!(ignored.skip_reason(&def, &outputs("/etc/app.conf"), false), None);
+  }
+
+  #[test]
+  fn if_missing_repairs_only_missing_paths() {
+    let temp = TempDir::new().unwrap();
+    let present = temp.path().join("present");
+    std::fs::write(&present, "").unwrap();
+    let missing = temp.path().join("missing");
+
+    let def = bind(&[]);
+    let repair = BindRepairDef {
+      policy: RepairPolicy::IfMissing,
+      ignore: vec![],
+    };
+    assert!(
+      repair
+        .skip_reason(&def, &outputs(present.to_str().unwrap()), false)
+        .is_some()
+    );
+    assert_eq!(
+      repair.skip_reason(&def, &outputs(missing.to_str().unwrap()), false),
+      None
+    );
+  }
+
+  #[test]
+  fn policy_parses_from_str() {
+    assert_eq!("if-missing".parse::<RepairPolicy>(), Ok(RepairPolicy::IfMissing));
+    assert!("sometimes".parse::<RepairPolicy>().is_err());
+  }
+}
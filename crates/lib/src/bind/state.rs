@@ -21,6 +21,10 @@
 //!   }
 //! }
 //! ```
+//!
+//! Paths the bind's file actions gave a mode, owner or group are recorded
+//! under `files`, so drift checks can compare them with the system even for
+//! binds without a `check` callback.
 
 use std::collections::HashMap;
 use std::fs;
@@ -32,26 +36,57 @@ use serde_json::Value as JsonValue;
 use thiserror::Error;
 use tracing::{debug, warn};
 
+use crate::action::actions::file::ManagedFile;
 use crate::bind::store::bind_dir_path;
+use crate::execute::types::BindResult;
 use crate::util::hash::ObjectHash;
 
 const STATE_FILENAME: &str = "state.json";
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BindState {
+  #[serde(serialize_with = "crate::util::ordered::sorted")]
   pub outputs: HashMap<String, JsonValue>,
+
+  /// Set when an update failed and the previous definition could not be
+  /// re-applied, so the system may not match `outputs`. Reported as drift
+  /// until the bind is applied again.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub needs_repair: bool,
+
+  /// Paths the bind's file actions gave a mode, owner or group.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub files: Vec<ManagedFile>,
 }
 
 impl BindState {
   pub fn new(outputs: HashMap<String, JsonValue>) -> Self {
-    Self { outputs }
+    Self {
+      outputs,
+      needs_repair: false,
+      files: Vec::new(),
+    }
   }
 
-  pub fn empty() -> Self {
+  /// The state of a bind just applied: its outputs and the files its
+  /// actions manage.
+  pub fn from_result(result: &BindResult) -> Self {
     Self {
-      outputs: HashMap::new(),
+      files: result.action_results.iter().filter_map(|r| r.file.clone()).collect(),
+      ..Self::new(result.outputs.clone())
     }
   }
+
+  /// How the files the bind manages differ from their recorded mode, owner
+  /// and group, if any does.
+  pub fn file_drift(&self) -> Option<String> {
+    let drift: Vec<String> = self.files.iter().filter_map(ManagedFile::drift).collect();
+    (!drift.is_empty()).then(|| drift.join("; "))
+  }
+
+  pub fn empty() -> Self {
+    Self::new(HashMap::new())
+  }
 }
 
 #[derive(Debug, Error)]
@@ -217,6 +252,51 @@ mod tests {
     });
   }
 
+  #[test]
+  #[serial]
+  fn needs_repair_roundtrips_and_defaults_to_false() {
+    with_temp_store(|_| {
+      let hash = ObjectHash("abc123def456789012345678".to_string());
+
+      save_bind_state(&hash, &BindState::empty()).unwrap();
+      let contents = fs::read_to_string(test_bind_state_path(&hash)).unwrap();
+      assert!(!contents.contains("needs_repair"));
+      assert!(!load_bind_state(&hash).unwrap().unwrap().needs_repair);
+
+      let state = BindState {
+        needs_repair: true,
+        ..BindState::empty()
+      };
+      save_bind_state(&hash, &state).unwrap();
+      assert!(load_bind_state(&hash).unwrap().unwrap().needs_repair);
+    });
+  }
+
+  #[test]
+  #[serial]
+  fn managed_files_roundtrip_and_report_drift() {
+    with_temp_store(|temp| {
+      let hash = ObjectHash("abc123def456789012345679".to_string());
+      let path = temp.path().join("managed.conf");
+      let state = BindState {
+        files: vec![ManagedFile {
+          path: path.to_string_lossy().to_string(),
+          attrs: Default::default(),
+          symlink: false,
+        }],
+        ..BindState::empty()
+      };
+      save_bind_state(&hash, &state).unwrap();
+      let loaded = load_bind_state(&hash).unwrap().unwrap();
+      assert_eq!(loaded, state);
+
+      assert!(loaded.file_drift().unwrap().ends_with("is missing"));
+      fs::write(&path, "x").unwrap();
+      assert_eq!(loaded.file_drift(), None);
+      assert_eq!(BindState::empty().file_drift(), None);
+    });
+  }
+
   #[test]
   #[serial]
   fn load_nonexistent_returns_none() {
@@ -1,6 +1,8 @@
 //! Bind storage paths.
 //!
 //! Provides path resolution for bind metadata in the store (`<store>/bind/<hash>/`).
+//! A bind applied before `settings.hash.length` was raised keeps its
+//! directory under the shorter hash, which lookups of the longer one reuse.
 
 use std::path::PathBuf;
 
@@ -10,8 +12,19 @@ pub fn bind_dir_name(hash: &ObjectHash) -> String {
   hash.0.clone()
 }
 
+/// Store path of a bind's metadata, or of the same bind stored under a
+/// shorter form of its hash.
 pub fn bind_dir_path(hash: &ObjectHash) -> PathBuf {
-  store_dir().join("bind").join(bind_dir_name(hash))
+  let binds = store_dir().join("bind");
+  let primary = binds.join(bind_dir_name(hash));
+  if primary.exists() {
+    return primary;
+  }
+  hash
+    .shorter_forms()
+    .map(|short| binds.join(bind_dir_name(&short)))
+    .find(|path| path.exists())
+    .unwrap_or(primary)
 }
 
 #[cfg(test)]
@@ -38,4 +51,25 @@ mod tests {
       },
     );
   }
+
+  #[test]
+  #[serial]
+  fn bind_dir_path_reuses_shorter_hash_dir() {
+    let temp = tempfile::tempdir().unwrap();
+    let short = ObjectHash("abc123def45678901234".to_string());
+    let long = ObjectHash(format!("{}{}", short.0, "0123456789ab"));
+    std::fs::create_dir_all(temp.path().join("bind").join(&short.0)).unwrap();
+
+    temp_env::with_vars(
+      [
+        ("SYSLUA_STORE", Some(temp.path().to_str().unwrap())),
+        ("SYSLUA_ROOT", None::<&str>),
+      ],
+      || {
+        assert_eq!(bind_dir_path(&long), temp.path().join("bind").join(&short.0));
+        let other = ObjectHash("0123456789abcdef0123".to_string());
+        assert_eq!(bind_dir_path(&other), temp.path().join("bind").join(&other.0));
+      },
+    );
+  }
 }
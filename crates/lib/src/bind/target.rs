@@ -0,0 +1,589 @@
+//! Probing the host paths a bind writes before it runs.
+//!
+//! Before a bind is created or updated, every target it writes (symlinks its
+//! commands create, files its `config_section` and `file_block` actions edit,
+//! fonts directories its `font` actions install into, its `backup` paths) is
+//! probed for:
+//!
+//! - a read-only file system (macOS system volume under SIP, read-only mounts)
+//! - an immutable or append-only flag (`chattr +i`, `chflags uchg`/`schg`)
+//! - permissions only root (or an administrator) has
+//!
+//! Instead of a failing command, the bind then fails with
+//! [`ExecuteError::BindTargetNotWritable`], naming the path and how to fix it.
+//! A bind may declare `fallback_path` to write somewhere else instead:
+//!
+//! ```lua
+//! sys.bind({
+//!   create = function(inputs, ctx)
+//!     ctx:exec({ bin = 'ln', args = { '-sf', inputs.rg.outputs.out .. '/bin/rg', '/usr/local/bin/rg' } })
+//!   end,
+//!   fallback_path = '~/.local/bin/rg',
+//! })
+//! ```
+//!
+//! The bind's actions, backup paths and outputs then name the fallback
+//! instead of the target. A single path stands in for the one target that
+//! can't be written; a table maps targets to their fallbacks. Destroying and
+//! checking the bind later finds the fallback the same way, without failing.
+//!
+//! Targets whose path is only known once actions have run, that depend on
+//! shell variables, or that commands write as another user (`run_as`),
+//! aren't probed.
+//!
+//! The directories the targets are in also serialize binds running in
+//! parallel; see [`target_dirs`].
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value as JsonValue;
+use tracing::warn;
+
+use crate::bind::{BindDef, BindFallbackDef};
+use crate::execute::hooks::BindOperation;
+use crate::execute::resolver::BindCtxResolver;
+use crate::execute::touches::{bind_targets, replaced_targets};
+use crate::execute::types::ExecuteError;
+use crate::placeholder;
+use crate::platform::paths::expand_path;
+
+/// Why a bind can't write a target path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetProblem {
+  /// The path is on a read-only file system.
+  ReadOnlyFilesystem,
+  /// The path (or its directory) has an immutable or append-only flag.
+  Immutable,
+  /// Only root or an administrator may write the path.
+  NeedsElevation,
+  /// Writing is denied even with elevation.
+  PermissionDenied,
+}
+
+impl TargetProblem {
+  /// How to make the path writable, or avoid writing it.
+  pub fn remediation(&self) -> &'static str {
+    match self {
+      TargetProblem::ReadOnlyFilesystem => {
+        "remount the file system read-write (on macOS, system paths are protected by SIP: target /usr/local or \
+         /etc/paths.d instead), or declare `fallback_path` on the bind"
+      }
+      TargetProblem::Immutable => {
+        "clear the flag (`chattr -i` on Linux, `chflags nouchg` or `noschg` on macOS, `attrib -r` on Windows), \
+         or declare `fallback_path` on the bind"
+      }
+      TargetProblem::NeedsElevation => "run `sudo sys apply`, or declare `fallback_path` on the bind",
+      TargetProblem::PermissionDenied => {
+        "check the owner and mode of the path and its directory, or declare `fallback_path` on the bind"
+      }
+    }
+  }
+}
+
+impl fmt::Display for TargetProblem {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      TargetProblem::ReadOnlyFilesystem => "it is on a read-only file system",
+      TargetProblem::Immutable => "it is marked immutable",
+      TargetProblem::NeedsElevation => "writing it requires elevation",
+      TargetProblem::PermissionDenied => "permission denied",
+    })
+  }
+}
+
+/// Why `path` can't be written, if it can't.
+///
+/// An existing file is probed along with the nearest existing directory
+/// above it; a symlink is replaced rather than written, so only its
+/// directory matters.
+pub fn probe_target(path: &Path) -> Option<TargetProblem> {
+  let existing = std::fs::symlink_metadata(path).ok();
+  if existing.is_some_and(|metadata| !metadata.file_type().is_symlink())
+    && let Some(problem) = probe_path(path)
+  {
+    return Some(problem);
+  }
+  let dir = path.ancestors().skip(1).find(|dir| dir.exists())?;
+  probe_path(dir)
+}
+
+#[cfg(unix)]
+fn probe_path(path: &Path) -> Option<TargetProblem> {
+  use rustix::fs::{Access, StatVfsMountFlags, access, statvfs};
+  use rustix::io::Errno;
+
+  if statvfs(path).is_ok_and(|vfs| vfs.f_flag.contains(StatVfsMountFlags::RDONLY)) {
+    return Some(TargetProblem::ReadOnlyFilesystem);
+  }
+  // Root passes the access check on some systems regardless of the flags
+  if has_immutable_flag(path) {
+    return Some(TargetProblem::Immutable);
+  }
+  match access(path, Access::WRITE_OK) {
+    Err(Errno::ROFS) => Some(TargetProblem::ReadOnlyFilesystem),
+    Err(Errno::PERM) => Some(TargetProblem::Immutable),
+    Err(Errno::ACCESS) if !crate::platform::is_elevated() => Some(TargetProblem::NeedsElevation),
+    Err(Errno::ACCESS) => Some(TargetProblem::PermissionDenied),
+    _ => None,
+  }
+}
+
+#[cfg(windows)]
+fn probe_path(path: &Path) -> Option<TargetProblem> {
+  let metadata = std::fs::metadata(path).ok()?;
+  (metadata.is_file() && metadata.permissions().readonly()).then_some(TargetProblem::Immutable)
+}
+
+/// Whether `path` has the immutable or append-only attribute (`chattr +i`/`+a`).
+#[cfg(target_os = "linux")]
+fn has_immutable_flag(path: &Path) -> bool {
+  use rustix::fs::{IFlags, Mode, OFlags, ioctl_getflags, open};
+
+  open(
+    path,
+    OFlags::RDONLY | OFlags::NOFOLLOW | OFlags::NONBLOCK | OFlags::CLOEXEC,
+    Mode::empty(),
+  )
+  .and_then(ioctl_getflags)
+  .is_ok_and(|flags| flags.intersects(IFlags::IMMUTABLE | IFlags::APPEND))
+}
+
+/// Whether `path` has a user or system immutable or append-only flag.
+#[cfg(target_os = "macos")]
+fn has_immutable_flag(path: &Path) -> bool {
+  use std::os::macos::fs::MetadataExt;
+
+  let immutable = libc::UF_IMMUTABLE | libc::SF_IMMUTABLE | libc::UF_APPEND | libc::SF_APPEND;
+  std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.st_flags() & immutable != 0)
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+fn has_immutable_flag(_path: &Path) -> bool {
+  false
+}
+
+/// `def` with the targets it writes for `operation` redirected to their
+/// fallbacks where they can't be written.
+///
+/// Fails with [`ExecuteError::BindTargetNotWritable`] when a target can't be
+/// written and has no usable fallback.
+pub(crate) fn write_targets<'a>(
+  def: &'a BindDef,
+  operation: BindOperation,
+  resolver: &BindCtxResolver<'_>,
+) -> Result<Cow<'a, BindDef>, ExecuteError> {
+  redirect_targets(def, operation, true, resolver)
+}
+
+/// `def` with its targets redirected to the fallbacks a create or update
+/// would have used, for destroying or checking it. Never fails.
+pub(crate) fn existing_targets<'a>(def: &'a BindDef, resolver: &BindCtxResolver<'_>) -> Cow<'a, BindDef> {
+  match redirect_targets(def, BindOperation::Create, false, resolver) {
+    Ok(def) => def,
+    Err(_) => Cow::Borrowed(def),
+  }
+}
+
+/// The directories the targets of `def` are in when run for `operation`,
+/// normalized and deduplicated.
+///
+/// Binds holding the same directory don't run at the same time, so one
+/// can't remove a directory another is creating a file in. Targets whose
+/// path isn't known yet are left out.
+pub(crate) fn target_dirs(def: &BindDef, operation: BindOperation, resolver: &BindCtxResolver<'_>) -> Vec<PathBuf> {
+  parent_dirs(
+    bind_targets(def, operation)
+      .iter()
+      .filter_map(|raw| resolve_target(raw, resolver)),
+  )
+}
+
+/// The paths whose existing files are backed up before `def` is created
+/// (see [`crate::bind::backup`]), resolved. Targets whose path isn't known
+/// yet are left out.
+pub(crate) fn backup_paths(def: &BindDef, resolver: &BindCtxResolver<'_>) -> Vec<PathBuf> {
+  replaced_targets(def)
+    .iter()
+    .filter_map(|raw| resolve_target(raw, resolver))
+    .collect()
+}
+
+/// The normalized parents of `targets`, sorted and deduplicated.
+fn parent_dirs(targets: impl Iterator<Item = PathBuf>) -> Vec<PathBuf> {
+  let dirs: BTreeSet<PathBuf> = targets
+    .filter_map(|target| normalize(&target).parent().map(Path::to_path_buf))
+    .collect();
+  dirs.into_iter().collect()
+}
+
+/// `path` with `.` and `..` resolved without touching the file system, and
+/// case folded where file systems usually ignore case.
+fn normalize(path: &Path) -> PathBuf {
+  let mut normalized = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::ParentDir => {
+        normalized.pop();
+      }
+      Component::CurDir => {}
+      other => normalized.push(other),
+    }
+  }
+  if cfg!(any(windows, target_os = "macos")) {
+    PathBuf::from(normalized.to_string_lossy().to_lowercase())
+  } else {
+    normalized
+  }
+}
+
+/// The absolute path a target names, unless it isn't known yet.
+fn resolve_target(raw: &str, resolver: &BindCtxResolver<'_>) -> Option<PathBuf> {
+  let value = placeholder::substitute(raw, resolver).ok()?;
+  let path = expand_path(&value);
+  (!value.contains('$') && path.is_absolute()).then_some(path)
+}
+
+fn redirect_targets<'a>(
+  def: &'a BindDef,
+  operation: BindOperation,
+  strict: bool,
+  resolver: &BindCtxResolver<'_>,
+) -> Result<Cow<'a, BindDef>, ExecuteError> {
+  let targets = bind_targets(def, operation);
+  let resolve = |raw: &str| resolve_target(raw, resolver);
+  let redirects = find_redirects(def, &targets, strict, resolve, probe_target)?;
+  if redirects.is_empty() {
+    return Ok(Cow::Borrowed(def));
+  }
+  for Redirect { path, fallback, .. } in &redirects {
+    warn!(
+      bind = def.id.as_deref().unwrap_or("-"),
+      target = %path.display(),
+      fallback = %fallback,
+      "bind target can't be written, using its fallback path"
+    );
+  }
+  Ok(Cow::Owned(redirect(def, &redirects)))
+}
+
+/// A target replaced by its fallback.
+#[derive(Debug, PartialEq)]
+struct Redirect {
+  /// The target as written in the bind.
+  raw: String,
+  /// The resolved target.
+  path: PathBuf,
+  fallback: String,
+}
+
+/// The targets among `targets` to replace by their fallback.
+///
+/// Strictly (for create and update), a target that can't be written and has
+/// no fallback fails, as does a fallback that can't be written. Otherwise a
+/// target is replaced when it can't be written, or when it is gone and its
+/// fallback exists.
+fn find_redirects(
+  def: &BindDef,
+  targets: &[String],
+  strict: bool,
+  resolve: impl Fn(&str) -> Option<PathBuf>,
+  probe: impl Fn(&Path) -> Option<TargetProblem>,
+) -> Result<Vec<Redirect>, ExecuteError> {
+  let resolved: Vec<(&String, PathBuf, Option<TargetProblem>)> = targets
+    .iter()
+    .filter_map(|raw| {
+      let path = resolve(raw)?;
+      let problem = probe(&path);
+      Some((raw, path, problem))
+    })
+    .collect();
+  let blocked = resolved.iter().filter(|(_, _, problem)| problem.is_some()).count();
+
+  let not_writable = |path: &Path, problem| ExecuteError::BindTargetNotWritable {
+    path: path.display().to_string(),
+    problem,
+  };
+
+  let mut redirects = Vec::new();
+  for (raw, path, problem) in &resolved {
+    let fallback = match &def.fallback_path {
+      // One path can only stand in for one target
+      Some(BindFallbackDef::Any(_)) if blocked > 1 || (blocked == 0 && resolved.len() > 1) => None,
+      Some(fallback) => fallback.fallback_of(raw, &path.to_string_lossy()),
+      None => None,
+    };
+    let Some(fallback) = fallback else {
+      if let Some(problem) = problem
+        && strict
+      {
+        return Err(not_writable(path.as_path(), *problem));
+      }
+      continue;
+    };
+    match problem {
+      Some(_) if strict => {
+        if let Some(problem) = probe(Path::new(fallback)) {
+          return Err(not_writable(Path::new(fallback), problem));
+        }
+      }
+      Some(_) => {}
+      None if !strict && !path.exists() && Path::new(fallback).exists() => {}
+      None => continue,
+    }
+    redirects.push(Redirect {
+      raw: raw.to_string(),
+      path: path.clone(),
+      fallback: fallback.to_string(),
+    });
+  }
+  Ok(redirects)
+}
+
+/// `def` with every mention of a redirected target in its actions, backup
+/// paths and outputs replaced by the fallback.
+fn redirect(def: &BindDef, redirects: &[Redirect]) -> BindDef {
+  let mut def = def.clone();
+  def.create_actions = rewritten(&def.create_actions, redirects);
+  def.update_actions = rewritten(&def.update_actions, redirects);
+  def.destroy_actions = rewritten(&def.destroy_actions, redirects);
+  def.check_actions = rewritten(&def.check_actions, redirects);
+  def.backup = rewritten(&def.backup, redirects);
+  def.outputs = rewritten(&def.outputs, redirects);
+  def
+}
+
+fn rewritten<T: Serialize + DeserializeOwned>(value: &T, redirects: &[Redirect]) -> T {
+  fn rewrite(value: &mut JsonValue, redirects: &[Redirect]) {
+    match value {
+      JsonValue::String(s) => {
+        for redirect in redirects {
+          *s = replace_path(s, &redirect.raw, &redirect.fallback);
+          *s = replace_path(s, &redirect.path.to_string_lossy(), &redirect.fallback);
+        }
+      }
+      JsonValue::Array(values) => values.iter_mut().for_each(|value| rewrite(value, redirects)),
+      JsonValue::Object(fields) => fields.values_mut().for_each(|value| rewrite(value, redirects)),
+      _ => {}
+    }
+  }
+
+  let mut json = serde_json::to_value(value).expect("bind definitions serialize");
+  rewrite(&mut json, redirects);
+  serde_json::from_value(json).expect("replacing paths keeps bind definitions valid")
+}
+
+/// `value` with the path `from` replaced by `to` where it appears as a whole
+/// path, or as the directory of a longer one.
+fn replace_path(value: &str, from: &str, to: &str) -> String {
+  let continues_name = |c: char| c.is_alphanumeric() || "._-~".contains(c);
+  let mut out = String::with_capacity(value.len());
+  let mut last = 0;
+  for (start, _) in value.match_indices(from) {
+    let end = start + from.len();
+    let before = value[..start].chars().next_back();
+    let after = value[end..].chars().next();
+    if before.is_some_and(continues_name) || after.is_some_and(continues_name) {
+      continue;
+    }
+    out.push_str(&value[last..start]);
+    out.push_str(to);
+    last = end;
+  }
+  out.push_str(&value[last..]);
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use super::*;
+  use crate::action::Action;
+  use crate::action::actions::exec::ExecOpts;
+
+  fn bind_linking(links: &[&str], fallback_path: Option<BindFallbackDef>) -> BindDef {
+    let script = links
+      .iter()
+      .map(|link| format!("ln -sf /store/rg/bin/rg {}", link))
+      .collect::<Vec<_>>()
+      .join(" && ");
+    BindDef {
+      id: Some("rg".to_string()),
+      inputs: None,
+      outputs: Some(BTreeMap::from([(
+        "link".to_string(),
+        JsonValue::String(links[0].to_string()),
+      )])),
+      create_actions: vec![sh(&script)],
+      update_actions: None,
+      destroy_actions: vec![sh(&format!("rm -f {}", links.join(" ")))],
+      check_actions: None,
+      check_outputs: None,
+      backup: None,
+      fallback_path,
+      tags: vec![],
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
+    }
+  }
+
+  fn sh(script: &str) -> Action {
+    Action::Exec(ExecOpts::new("/bin/sh").with_args(vec!["-c".to_string(), script.to_string()]))
+  }
+
+  fn targets(def: &BindDef) -> Vec<String> {
+    bind_targets(def, BindOperation::Create)
+  }
+
+  fn resolve(raw: &str) -> Option<PathBuf> {
+    Some(PathBuf::from(raw))
+  }
+
+  fn immutable_usr(path: &Path) -> Option<TargetProblem> {
+    path.starts_with("/usr").then_some(TargetProblem::Immutable)
+  }
+
+  #[test]
+  fn unwritable_targets_without_fallback_fail() {
+    let def = bind_linking(&["/usr/local/bin/rg"], None);
+    let err = find_redirects(&def, &targets(&def), true, resolve, immutable_usr).unwrap_err();
+    assert!(matches!(
+      err,
+      ExecuteError::BindTargetNotWritable {
+        ref path,
+        problem: TargetProblem::Immutable,
+      } if path == "/usr/local/bin/rg"
+    ));
+    assert!(err.to_string().contains("chattr -i"));
+
+    // Destroying doesn't fail
+    assert!(
+      find_redirects(&def, &targets(&def), false, resolve, immutable_usr)
+        .unwrap()
+        .is_empty()
+    );
+  }
+
+  #[test]
+  fn parent_dirs_are_normalized_and_deduplicated() {
+    let targets = [
+      "/home/u/.config/nvim",
+      "/home/u/.config/./git/../git",
+      "/home/u/.local/bin/rg",
+      "/",
+    ];
+    let dirs = parent_dirs(targets.iter().map(PathBuf::from));
+    assert_eq!(
+      dirs,
+      vec![PathBuf::from("/home/u/.config"), PathBuf::from("/home/u/.local/bin")]
+    );
+  }
+
+  #[test]
+  fn fallback_replaces_the_target() {
+    let def = bind_linking(
+      &["/usr/local/bin/rg", "/home/u/bin/rg"],
+      Some(BindFallbackDef::Any("/home/u/.local/bin/rg".to_string())),
+    );
+    let redirects = find_redirects(&def, &targets(&def), true, resolve, immutable_usr).unwrap();
+    assert_eq!(
+      redirects,
+      vec![Redirect {
+        raw: "/usr/local/bin/rg".to_string(),
+        path: PathBuf::from("/usr/local/bin/rg"),
+        fallback: "/home/u/.local/bin/rg".to_string(),
+      }]
+    );
+
+    let redirected = redirect(&def, &redirects);
+    let Action::Exec(ref opts) = redirected.create_actions[0] else {
+      panic!("expected an exec action");
+    };
+    assert_eq!(
+      opts.args.as_ref().unwrap()[1],
+      "ln -sf /store/rg/bin/rg /home/u/.local/bin/rg && ln -sf /store/rg/bin/rg /home/u/bin/rg"
+    );
+    assert_eq!(
+      redirected.outputs.unwrap()["link"],
+      JsonValue::String("/home/u/.local/bin/rg".to_string())
+    );
+  }
+
+  #[test]
+  fn one_fallback_path_cannot_replace_several_targets() {
+    let def = bind_linking(
+      &["/usr/local/bin/rg", "/usr/bin/rg"],
+      Some(BindFallbackDef::Any("/home/u/.local/bin/rg".to_string())),
+    );
+    assert!(find_redirects(&def, &targets(&def), true, resolve, immutable_usr).is_err());
+
+    let def = bind_linking(
+      &["/usr/local/bin/rg", "/usr/bin/rg"],
+      Some(BindFallbackDef::Targets(BTreeMap::from([
+        ("/usr/local/bin/rg".to_string(), "/home/u/.local/bin/rg".to_string()),
+        ("/usr/bin/rg".to_string(), "/home/u/bin/rg".to_string()),
+      ]))),
+    );
+    let redirects = find_redirects(&def, &targets(&def), true, resolve, immutable_usr).unwrap();
+    assert_eq!(redirects.len(), 2);
+
+    // An unwritable fallback fails too
+    let def = bind_linking(
+      &["/usr/local/bin/rg"],
+      Some(BindFallbackDef::Any("/usr/share/rg".to_string())),
+    );
+    let err = find_redirects(&def, &targets(&def), true, resolve, immutable_usr).unwrap_err();
+    assert!(matches!(err, ExecuteError::BindTargetNotWritable { ref path, .. } if path == "/usr/share/rg"));
+  }
+
+  #[test]
+  fn destroy_finds_existing_fallbacks() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let fallback = temp.path().join("rg");
+    std::fs::write(&fallback, "").unwrap();
+    let missing = temp.path().join("missing").join("rg");
+    let def = bind_linking(
+      &[&missing.to_string_lossy()],
+      Some(BindFallbackDef::Any(fallback.to_string_lossy().into_owned())),
+    );
+
+    let redirects = find_redirects(&def, &targets(&def), false, resolve, |_| None).unwrap();
+    assert_eq!(redirects.len(), 1);
+    assert!(
+      find_redirects(&def, &targets(&def), true, resolve, |_| None)
+        .unwrap()
+        .is_empty()
+    );
+  }
+
+  #[test]
+  fn replace_path_respects_path_boundaries() {
+    assert_eq!(replace_path("ln -sf /a /etc/rg", "/etc/rg", "/x/rg"), "ln -sf /a /x/rg");
+    assert_eq!(
+      replace_path("rm '/etc/rg/config'", "/etc/rg", "/x/rg"),
+      "rm '/x/rg/config'"
+    );
+    assert_eq!(
+      replace_path("/etc/rgx /opt/etc/rg", "/etc/rg", "/x/rg"),
+      "/etc/rgx /opt/etc/rg"
+    );
+    assert_eq!(replace_path("/etc/rg.bak", "/etc/rg", "/x/rg"), "/etc/rg.bak");
+  }
+
+  #[test]
+  fn writable_paths_have_no_problem() {
+    let temp = tempfile::TempDir::new().unwrap();
+    assert_eq!(probe_target(&temp.path().join("new").join("file")), None);
+    std::fs::write(temp.path().join("file"), "").unwrap();
+    assert_eq!(probe_target(&temp.path().join("file")), None);
+  }
+}
@@ -17,14 +17,26 @@ use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
 use mlua::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use sha2::Digest;
 
 use crate::{
-  action::{Action, ActionCtx, actions::exec::ExecOpts},
+  action::{
+    Action, ActionCtx,
+    actions::{
+      config_section::ConfigSectionOpts, exec::ExecOpts, file::FileOpts, file_block::FileBlockOpts,
+      firewall::FirewallOpts, font::FontOpts,
+    },
+  },
   bind::lua::{bind_inputs_ref_to_lua, lua_value_to_bind_inputs_def},
-  manifest::Manifest,
-  outputs::lua::{outputs_to_lua_table, parse_outputs},
-  util::hash::{HashError, Hashable, ObjectHash},
+  bind::repair::{BindRepairDef, REPAIR_IGNORE_REGISTRY_KEY, RepairPolicy},
+  build::parse_memory_size,
+  lua::diagnostics::{SpecCaller, SpecKind},
+  manifest::{Manifest, PlaceholderMode, memoized_hash, spec_placeholder_mode, spec_platforms},
+  outputs::{
+    lua::{outputs_to_lua_table, parse_outputs},
+    schema::OutputSchema,
+  },
+  platform::{Facts, paths::expand_path},
+  util::hash::{HashError, HashSpec, Hashable, ObjectHash},
 };
 
 pub enum BindInputsSpec {
@@ -60,10 +72,27 @@ pub struct BindSpec {
   pub destroy: LuaFunction,
   pub check: Option<LuaFunction>,
   pub replace: bool,
+  pub backup: Option<BindBackupDef>,
+  pub fallback_path: Option<BindFallbackDef>,
+  pub tags: Vec<String>,
+  pub group: Option<String>,
+  pub repair: Option<BindRepairDef>,
+  pub requires: Vec<String>,
+  /// Platform triples the bind is limited to; empty for every platform. Not
+  /// part of the hash.
+  pub platforms: Vec<String>,
+  pub serialize: Option<String>,
+  pub update_strategy: UpdateStrategy,
+  pub phase: BindPhase,
+  /// Declaration of the outputs `create` returns. Not part of the hash.
+  pub outputs: Option<OutputSchema>,
+  /// How placeholders are checked, overriding `settings.placeholders`. Not
+  /// part of the hash.
+  pub placeholders: Option<PlaceholderMode>,
 }
 
 impl FromLua for BindSpec {
-  fn from_lua(value: LuaValue, _lua: &Lua) -> LuaResult<Self> {
+  fn from_lua(value: LuaValue, lua: &Lua) -> LuaResult<Self> {
     let table = match value {
       LuaValue::Table(t) => t,
       _ => {
@@ -95,6 +124,63 @@ impl FromLua for BindSpec {
     }
 
     let replace: bool = table.get("replace").unwrap_or(false);
+    let backup: Option<BindBackupDef> = table.get("backup")?;
+    let fallback_path: Option<BindFallbackDef> = table.get("fallback_path")?;
+    let tags = string_list(&table, "tags")?;
+    let group: Option<String> = table
+      .get("group")
+      .map_err(|_| LuaError::external("bind `group` must be a group name string"))?;
+    if group.as_deref().is_some_and(|group| group.trim().is_empty()) {
+      return Err(LuaError::external("bind `group` name must not be empty"));
+    }
+    let repair = parse_repair(lua, &table)?;
+    let requires = string_list(&table, "requires")?;
+    let platforms = spec_platforms(&table, "bind")?;
+    let serialize: Option<String> = table
+      .get("serialize")
+      .map_err(|_| LuaError::external("bind `serialize` must be a group name string"))?;
+    if serialize.as_deref().is_some_and(|group| group.trim().is_empty()) {
+      return Err(LuaError::external("bind `serialize` group name must not be empty"));
+    }
+    let update_strategy = match table
+      .get::<Option<String>>("update_strategy")
+      .map_err(|_| LuaError::external("bind `update_strategy` must be a strategy string"))?
+    {
+      Some(strategy) => strategy.parse::<UpdateStrategy>().map_err(LuaError::external)?,
+      None => UpdateStrategy::default(),
+    };
+    let phase = match table
+      .get::<Option<String>>("phase")
+      .map_err(|_| LuaError::external("bind `phase` must be a phase string"))?
+    {
+      Some(phase) => phase.parse::<BindPhase>().map_err(LuaError::external)?,
+      None => BindPhase::default(),
+    };
+    if phase == BindPhase::Login {
+      // Nothing runs these at apply time, so nothing could act on them
+      for (field, set) in [
+        ("update", update.is_some()),
+        ("check", check.is_some()),
+        ("backup", backup.is_some()),
+        ("fallback_path", fallback_path.is_some()),
+      ] {
+        if set {
+          return Err(LuaError::external(format!("login-phase binds can't have `{}`", field)));
+        }
+      }
+    }
+    let outputs: Option<OutputSchema> = table.get("outputs")?;
+    let placeholders = spec_placeholder_mode(&table, "bind")?;
+    for requirement in &requires {
+      let name = requirement.strip_prefix('!').unwrap_or(requirement);
+      if !Facts::FEATURES.contains(&name) {
+        return Err(LuaError::external(format!(
+          "unknown bind requirement '{}' (expected one of: {}, optionally prefixed with '!')",
+          requirement,
+          Facts::FEATURES.join(", ")
+        )));
+      }
+    }
 
     Ok(BindSpec {
       id,
@@ -104,10 +190,258 @@ impl FromLua for BindSpec {
       destroy,
       check,
       replace,
+      backup,
+      fallback_path,
+      tags,
+      group,
+      repair,
+      requires,
+      platforms,
+      serialize,
+      update_strategy,
+      phase,
+      outputs,
+      placeholders,
     })
   }
 }
 
+/// When a bind's actions run.
+///
+/// Apply-phase binds run during `sys apply`. Login-phase binds are only
+/// recorded by an apply: `sys activate-login` runs their `create` actions
+/// when the user logs in, and `sys activate-login --logout` their `destroy`
+/// actions when the user logs out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BindPhase {
+  #[default]
+  Apply,
+  Login,
+}
+
+impl BindPhase {
+  pub fn is_apply(&self) -> bool {
+    *self == BindPhase::Apply
+  }
+}
+
+impl std::str::FromStr for BindPhase {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "apply" => Ok(Self::Apply),
+      "login" => Ok(Self::Login),
+      other => Err(format!("unknown bind phase '{}' (expected apply or login)", other)),
+    }
+  }
+}
+
+/// How a bind whose hash changed moves to its new definition.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateStrategy {
+  /// Run `update` if the new definition has one, else destroy and re-create.
+  #[default]
+  InPlace,
+  /// Always destroy the old bind and create the new one, even with `update`.
+  Recreate,
+}
+
+impl UpdateStrategy {
+  pub fn is_in_place(&self) -> bool {
+    *self == UpdateStrategy::InPlace
+  }
+}
+
+impl std::str::FromStr for UpdateStrategy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "in_place" => Ok(Self::InPlace),
+      "recreate" => Ok(Self::Recreate),
+      other => Err(format!(
+        "unknown update strategy '{}' (expected in_place or recreate)",
+        other
+      )),
+    }
+  }
+}
+
+/// Read a bind field that may be a single string or a list of strings.
+fn string_list(table: &LuaTable, key: &str) -> LuaResult<Vec<String>> {
+  match table.get::<LuaValue>(key)? {
+    LuaValue::Nil => Ok(Vec::new()),
+    LuaValue::String(s) => Ok(vec![s.to_str()?.to_string()]),
+    LuaValue::Table(t) => t
+      .sequence_values::<String>()
+      .collect::<LuaResult<_>>()
+      .map_err(|_| LuaError::external(format!("bind {} must be strings", key))),
+    other => Err(LuaError::external(format!(
+      "bind {} must be a string or a list of strings, got {}",
+      key,
+      other.type_name()
+    ))),
+  }
+}
+
+/// Read a bind's `repair` policy and `repair_ignore` patterns, adding the
+/// config-level `settings.repair_ignore` patterns.
+fn parse_repair(lua: &Lua, table: &LuaTable) -> LuaResult<Option<BindRepairDef>> {
+  let policy = match table
+    .get::<Option<String>>("repair")
+    .map_err(|_| LuaError::external("bind `repair` must be a policy string"))?
+  {
+    Some(policy) => policy.parse::<RepairPolicy>().map_err(LuaError::external)?,
+    None => RepairPolicy::default(),
+  };
+
+  let mut ignore: Vec<String> = string_list(table, "repair_ignore")?
+    .iter()
+    .map(|pattern| expand_path(pattern).to_string_lossy().into_owned())
+    .collect();
+  let settings_ignore: Option<Vec<String>> = lua.named_registry_value(REPAIR_IGNORE_REGISTRY_KEY)?;
+  ignore.extend(settings_ignore.unwrap_or_default());
+
+  let repair = BindRepairDef { policy, ignore };
+  Ok((repair != BindRepairDef::default()).then_some(repair))
+}
+
+/// Key for storing the config-level backup size cap (`settings.backup_max_size`) in Lua's registry.
+pub const BACKUP_MAX_SIZE_REGISTRY_KEY: &str = "__syslua_backup_max_size";
+
+/// Size cap for backed up files when neither the bind nor the config sets one.
+pub const DEFAULT_BACKUP_MAX_SIZE: u64 = 64 << 20;
+
+/// How a bind backs up the files it replaces before it is created.
+///
+/// ```lua
+/// sys.bind({
+///   backup = { "~/.bashrc", max_size = "1M" },
+///   create = function(inputs, ctx) ... end,
+///   destroy = function(outputs, ctx) ... end,
+/// })
+/// ```
+///
+/// Every bind backs up the files its actions replace (see
+/// [`crate::bind::backup`]); `backup` adds paths written in ways syslua can't
+/// see, such as by a script, and sets the size cap. Files larger than
+/// `max_size` are not backed up. `max_size` falls back to
+/// `settings.backup_max_size` and then to [`DEFAULT_BACKUP_MAX_SIZE`]. Not
+/// part of the hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BindBackupDef {
+  /// Extra target paths, with `~` and environment variables expanded.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub paths: Vec<String>,
+  /// Largest file, in bytes, that will be backed up.
+  pub max_size: u64,
+}
+
+impl FromLua for BindBackupDef {
+  fn from_lua(value: LuaValue, lua: &Lua) -> LuaResult<Self> {
+    let table = match value {
+      LuaValue::String(s) => {
+        let table = lua.create_table()?;
+        table.push(s)?;
+        table
+      }
+      LuaValue::Table(t) => t,
+      _ => {
+        return Err(LuaError::FromLuaConversionError {
+          from: value.type_name(),
+          to: "BindBackupDef".to_string(),
+          message: Some("expected a path or a table of paths".to_string()),
+        });
+      }
+    };
+
+    let mut paths = Vec::new();
+    for path in table.sequence_values::<String>() {
+      let path = path.map_err(|_| LuaError::external("backup paths must be strings"))?;
+      paths.push(expand_path(&path).to_string_lossy().into_owned());
+    }
+    let max_size = match table.get::<LuaValue>("max_size")? {
+      LuaValue::Nil => lua
+        .named_registry_value::<Option<u64>>(BACKUP_MAX_SIZE_REGISTRY_KEY)?
+        .unwrap_or(DEFAULT_BACKUP_MAX_SIZE),
+      other => parse_backup_max_size(other)?,
+    };
+
+    Ok(BindBackupDef { paths, max_size })
+  }
+}
+
+/// Where a bind writes instead of target paths it can't write (read-only
+/// file systems, immutable files, paths needing elevation).
+///
+/// ```lua
+/// fallback_path = '~/.local/bin/rg'
+/// fallback_path = { ['/usr/local/bin/rg'] = '~/.local/bin/rg' }
+/// ```
+///
+/// See [`crate::bind::target`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BindFallbackDef {
+  /// Replaces the one target that can't be written, whichever it is.
+  Any(String),
+  /// Replacement per target path.
+  Targets(BTreeMap<String, String>),
+}
+
+impl BindFallbackDef {
+  /// The fallback of `target` (`raw` as written in the bind's actions, or
+  /// resolved).
+  pub fn fallback_of(&self, raw: &str, target: &str) -> Option<&str> {
+    match self {
+      BindFallbackDef::Any(path) => Some(path),
+      BindFallbackDef::Targets(paths) => paths.get(raw).or_else(|| paths.get(target)).map(String::as_str),
+    }
+  }
+}
+
+impl FromLua for BindFallbackDef {
+  fn from_lua(value: LuaValue, _: &Lua) -> LuaResult<Self> {
+    let expand = |path: String| expand_path(&path).to_string_lossy().into_owned();
+    match value {
+      LuaValue::String(s) => Ok(BindFallbackDef::Any(expand(s.to_str()?.to_string()))),
+      LuaValue::Table(table) => {
+        let mut paths = BTreeMap::new();
+        for pair in table.pairs::<String, String>() {
+          let (target, fallback) = pair.map_err(|_| {
+            LuaError::external("bind `fallback_path` table must map target paths to fallback path strings")
+          })?;
+          paths.insert(expand(target), expand(fallback));
+        }
+        if paths.is_empty() {
+          return Err(LuaError::external("bind `fallback_path` table must not be empty"));
+        }
+        Ok(BindFallbackDef::Targets(paths))
+      }
+      _ => Err(LuaError::FromLuaConversionError {
+        from: value.type_name(),
+        to: "BindFallbackDef".to_string(),
+        message: Some("expected a path or a table of target paths to fallback paths".to_string()),
+      }),
+    }
+  }
+}
+
+/// Parse a backup size cap given as a byte count or a size string like `"1M"`.
+pub fn parse_backup_max_size(value: LuaValue) -> LuaResult<u64> {
+  match value {
+    LuaValue::Integer(n) if n > 0 => Ok(n as u64),
+    LuaValue::String(s) => parse_memory_size(&s.to_str()?).map_err(LuaError::external),
+    other => Err(LuaError::external(format!(
+      "backup max_size must be a byte count or a size string like \"1M\", got {}",
+      other.type_name()
+    ))),
+  }
+}
+
 /// A resolved, serializable input value.
 ///
 /// This is the manifest-side representation of inputs. All values are fully
@@ -167,11 +501,16 @@ pub enum BindInputsDef {
 }
 
 impl BindInputsDef {
-  pub fn from_spec(_lua: &Lua, manifest: &Rc<RefCell<Manifest>>, spec: BindInputsSpec) -> LuaResult<Self> {
+  pub fn from_spec(
+    _lua: &Lua,
+    manifest: &Rc<RefCell<Manifest>>,
+    spec: BindInputsSpec,
+    caller: SpecCaller,
+  ) -> LuaResult<Self> {
     match spec {
       BindInputsSpec::Value(v) => Ok(lua_value_to_bind_inputs_def(v, &manifest.borrow())?),
       BindInputsSpec::Function(f) => {
-        let result = f.call::<LuaValue>(())?;
+        let result = caller.call::<LuaValue>("inputs", &f, ())?;
         if result.is_nil() {
           Ok(BindInputsDef::Table(BTreeMap::new()))
         } else {
@@ -259,10 +598,35 @@ pub struct BindDef {
   /// Contains `drifted` (string "true"/"false") and optional `message`.
   #[serde(skip_serializing_if = "Option::is_none")]
   pub check_outputs: Option<BindCheckOutputs>,
+  /// Extra files to back up before `create` and restore after `destroy`, and
+  /// the size cap of backups. Not part of the hash.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub backup: Option<BindBackupDef>,
+  /// Paths to write instead of targets the bind can't write.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub fallback_path: Option<BindFallbackDef>,
+  /// Labels for selecting binds (e.g. `sys destroy --only <tag>`). Not part of the hash.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub tags: Vec<String>,
+  /// Role the bind belongs to, for per-group summaries and `--group` filters. Not part of the hash.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub group: Option<String>,
+  /// When `--repair` may re-create the bind after drift. Not part of the hash.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub repair: Option<BindRepairDef>,
+  /// Serialization group: binds of the same group never run concurrently. Not part of the hash.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub serialize: Option<String>,
+  /// Whether a changed bind is updated in place or re-created. Not part of the hash.
+  #[serde(default, skip_serializing_if = "UpdateStrategy::is_in_place")]
+  pub update_strategy: UpdateStrategy,
+  /// When the bind's actions run.
+  #[serde(default, skip_serializing_if = "BindPhase::is_apply")]
+  pub phase: BindPhase,
 }
 
 impl Hashable for BindDef {
-  fn compute_hash(&self) -> Result<ObjectHash, HashError> {
+  fn hash_input(&self) -> Result<String, HashError> {
     #[derive(Serialize)]
     struct BindDefHashable<'a> {
       id: &'a Option<String>,
@@ -271,6 +635,10 @@ impl Hashable for BindDef {
       create_actions: &'a Vec<Action>,
       update_actions: &'a Option<Vec<Action>>,
       destroy_actions: &'a Vec<Action>,
+      #[serde(skip_serializing_if = "Option::is_none")]
+      fallback_path: &'a Option<BindFallbackDef>,
+      #[serde(skip_serializing_if = "BindPhase::is_apply")]
+      phase: &'a BindPhase,
     }
 
     let hashable = BindDefHashable {
@@ -280,20 +648,19 @@ impl Hashable for BindDef {
       create_actions: &self.create_actions,
       update_actions: &self.update_actions,
       destroy_actions: &self.destroy_actions,
+      fallback_path: &self.fallback_path,
+      phase: &self.phase,
     };
 
-    let serialized = serde_json::to_string(&hashable)?;
-    let mut hasher = sha2::Sha256::new();
-    sha2::Digest::update(&mut hasher, serialized.as_bytes());
-    let full = format!("{:x}", hasher.finalize());
-    Ok(ObjectHash(full[..crate::consts::OBJ_HASH_PREFIX_LEN].to_string()))
+    serde_json::to_string(&hashable)
   }
 }
 
 impl BindDef {
   pub fn from_spec(lua: &Lua, manifest: &Rc<RefCell<Manifest>>, spec: BindSpec) -> LuaResult<Self> {
+    let caller = SpecCaller::new(SpecKind::Bind, spec.id.as_deref());
     let inputs = match spec.inputs {
-      Some(input_spec) => Some(BindInputsDef::from_spec(lua, manifest, input_spec)?),
+      Some(input_spec) => Some(BindInputsDef::from_spec(lua, manifest, input_spec, caller)?),
       None => None,
     };
 
@@ -307,7 +674,7 @@ impl BindDef {
     };
 
     // Call: create(inputs, ctx) -> outputs (optional)
-    let create_result: LuaValue = spec.create.call((&inputs_arg, &create_ctx_userdata))?;
+    let create_result: LuaValue = caller.call("create", &spec.create, (&inputs_arg, &create_ctx_userdata))?;
 
     // Extract outputs from create return value (optional for binds)
     let outputs: Option<BTreeMap<String, JsonValue>> = match create_result {
@@ -320,6 +687,11 @@ impl BindDef {
         return Err(LuaError::external("bind create must return a table of outputs or nil"));
       }
     };
+    if spec.phase == BindPhase::Login && outputs.is_some() {
+      return Err(LuaError::external(
+        "login-phase binds can't return outputs: they are not created until login",
+      ));
+    }
 
     // Extract create actions from ActionCtx
     create_ctx = create_ctx_userdata.take()?;
@@ -340,7 +712,8 @@ impl BindDef {
       let update_ctx_userdata = lua.create_userdata(update_ctx)?;
 
       // Call: update(outputs, inputs, ctx) -> outputs (must match create's output keys)
-      let update_result: LuaValue = update_fn.call((&outputs_arg, &inputs_arg, &update_ctx_userdata))?;
+      let update_result: LuaValue =
+        caller.call("update", &update_fn, (&outputs_arg, &inputs_arg, &update_ctx_userdata))?;
 
       // Validate update returns same output shape as create
       match (&update_result, &outputs) {
@@ -401,7 +774,7 @@ impl BindDef {
       let destroy_ctx_userdata = lua.create_userdata(destroy_ctx)?;
 
       // Call: destroy(outputs, ctx) -> ignored
-      let _: LuaValue = spec.destroy.call((outputs_arg.clone(), &destroy_ctx_userdata))?;
+      let _: LuaValue = caller.call("destroy", &spec.destroy, (outputs_arg.clone(), &destroy_ctx_userdata))?;
 
       let destroy_ctx: BindCtx = destroy_ctx_userdata.take()?;
       destroy_ctx.into_actions()
@@ -413,7 +786,7 @@ impl BindDef {
       let check_ctx_userdata = lua.create_userdata(check_ctx)?;
 
       // Call: check(outputs, inputs, ctx) -> { drifted, message? }
-      let check_result: LuaValue = check_fn.call((&outputs_arg, &inputs_arg, &check_ctx_userdata))?;
+      let check_result: LuaValue = caller.call("check", &check_fn, (&outputs_arg, &inputs_arg, &check_ctx_userdata))?;
 
       let (drifted, message) = match check_result {
         LuaValue::Table(t) => {
@@ -442,6 +815,18 @@ impl BindDef {
       (None, None)
     };
 
+    // Without a `backup` of its own, the bind still backs up the files it
+    // replaces, up to `settings.backup_max_size`
+    let backup = match spec.backup {
+      Some(backup) => Some(backup),
+      None => lua
+        .named_registry_value::<Option<u64>>(BACKUP_MAX_SIZE_REGISTRY_KEY)?
+        .map(|max_size| BindBackupDef {
+          paths: Vec::new(),
+          max_size,
+        }),
+    };
+
     // Create BindDef
     Ok(BindDef {
       id: spec.id,
@@ -452,6 +837,14 @@ impl BindDef {
       destroy_actions,
       check_actions,
       check_outputs,
+      backup,
+      fallback_path: spec.fallback_path,
+      tags: spec.tags,
+      group: spec.group,
+      repair: spec.repair,
+      serialize: spec.serialize,
+      update_strategy: spec.update_strategy,
+      phase: spec.phase,
     })
   }
 }
@@ -480,6 +873,31 @@ impl BindCtx {
     self.0.exec(opts)
   }
 
+  /// Record a config section action and return a placeholder for its output.
+  pub fn config_section(&mut self, opts: ConfigSectionOpts) -> String {
+    self.0.config_section(opts)
+  }
+
+  /// Record a firewall rule action and return a placeholder for its output.
+  pub fn firewall_rule(&mut self, opts: FirewallOpts) -> String {
+    self.0.firewall_rule(opts)
+  }
+
+  /// Record a file block action and return a placeholder for its output.
+  pub fn file_block(&mut self, opts: FileBlockOpts) -> String {
+    self.0.file_block(opts)
+  }
+
+  /// Record a file action and return a placeholder for its output.
+  pub fn file(&mut self, opts: FileOpts) -> String {
+    self.0.file(opts)
+  }
+
+  /// Record a font action and return a placeholder for its output.
+  pub fn font(&mut self, opts: FontOpts) -> String {
+    self.0.font(opts)
+  }
+
   /// Returns the number of actions recorded so far.
   pub fn action_count(&self) -> usize {
     self.0.action_count()
@@ -504,8 +922,9 @@ pub struct BindRef {
 }
 
 impl BindRef {
-  pub fn from_def(def: &BindDef) -> Result<Self, LuaError> {
-    let hash = match def.compute_hash() {
+  /// Create a BindRef from a BindDef, hashing it with `spec` (memoized in `lua`).
+  pub fn from_def(lua: &Lua, def: &BindDef, spec: &HashSpec) -> Result<Self, LuaError> {
+    let hash = match memoized_hash(lua, def, spec) {
       Ok(it) => it,
       Err(err) => return Err(LuaError::external(format!("failed to compute bind hash: {}", err))),
     };
@@ -562,11 +981,23 @@ mod tests {
           args: None,
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         update_actions: None,
         destroy_actions: vec![],
         check_actions: None,
         check_outputs: None,
+        backup: None,
+        fallback_path: None,
+        tags: Vec::new(),
+        group: None,
+        repair: None,
+        serialize: None,
+        update_strategy: Default::default(),
+        phase: Default::default(),
       }
     }
 
@@ -597,6 +1028,10 @@ mod tests {
         args: None,
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       }));
 
       assert_ne!(def1.compute_hash().unwrap(), def2.compute_hash().unwrap());
@@ -614,6 +1049,10 @@ mod tests {
         args: None,
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })];
 
       assert_ne!(def1.compute_hash().unwrap(), def2.compute_hash().unwrap());
@@ -631,18 +1070,34 @@ mod tests {
             args: None,
             env: None,
             cwd: None,
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
           Action::Exec(ExecOpts {
             bin: "step2".to_string(),
             args: None,
             env: None,
             cwd: None,
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
         ],
         update_actions: None,
         destroy_actions: vec![],
         check_actions: None,
         check_outputs: None,
+        backup: None,
+        fallback_path: None,
+        tags: Vec::new(),
+        group: None,
+        repair: None,
+        serialize: None,
+        update_strategy: Default::default(),
+        phase: Default::default(),
       };
 
       let def2 = BindDef {
@@ -655,18 +1110,34 @@ mod tests {
             args: None,
             env: None,
             cwd: None,
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
           Action::Exec(ExecOpts {
             bin: "step1".to_string(),
             args: None,
             env: None,
             cwd: None,
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
         ],
         update_actions: None,
         destroy_actions: vec![],
         check_actions: None,
         check_outputs: None,
+        backup: None,
+        fallback_path: None,
+        tags: Vec::new(),
+        group: None,
+        repair: None,
+        serialize: None,
+        update_strategy: Default::default(),
+        phase: Default::default(),
       };
 
       assert_ne!(def1.compute_hash().unwrap(), def2.compute_hash().unwrap());
@@ -689,29 +1160,53 @@ mod tests {
           args: None,
           env: Some(env),
           cwd: Some("/home".to_string()),
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         update_actions: Some(vec![Action::Exec(ExecOpts {
           bin: "echo updated".to_string(),
           args: None,
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })]),
         destroy_actions: vec![Action::Exec(ExecOpts {
           bin: "rm /dest".to_string(),
           args: None,
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         check_actions: Some(vec![Action::Exec(ExecOpts {
           bin: "test".to_string(),
           args: Some(vec!["-L".to_string(), "/dest".to_string()]),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })]),
         check_outputs: Some(BindCheckOutputs {
           drifted: "$${{action:0}}".to_string(),
           message: Some("link check".to_string()),
         }),
+        backup: None,
+        fallback_path: None,
+        tags: Vec::new(),
+        group: None,
+        repair: None,
+        serialize: None,
+        update_strategy: Default::default(),
+        phase: Default::default(),
       };
 
       let json = serde_json::to_string(&def).unwrap();
@@ -730,6 +1225,10 @@ mod tests {
         args: Some(vec!["-f".to_string(), "/some/path".to_string()]),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })]);
       def2.check_outputs = Some(BindCheckOutputs {
         drifted: "$${{action:0}}".to_string(),
@@ -738,5 +1237,60 @@ mod tests {
 
       assert_eq!(def1.compute_hash().unwrap(), def2.compute_hash().unwrap());
     }
+
+    #[test]
+    fn group_does_not_affect_hash() {
+      let def1 = simple_def();
+
+      let mut def2 = simple_def();
+      def2.group = Some("neovim".to_string());
+
+      assert_eq!(def1.compute_hash().unwrap(), def2.compute_hash().unwrap());
+    }
+
+    #[test]
+    fn repair_policy_does_not_affect_hash() {
+      let def1 = simple_def();
+
+      let mut def2 = simple_def();
+      def2.repair = Some(BindRepairDef {
+        policy: RepairPolicy::Never,
+        ignore: vec!["/etc/*.conf".to_string()],
+      });
+
+      assert_eq!(def1.compute_hash().unwrap(), def2.compute_hash().unwrap());
+    }
+
+    #[test]
+    fn serialize_group_does_not_affect_hash() {
+      let def1 = simple_def();
+
+      let mut def2 = simple_def();
+      def2.serialize = Some("apt".to_string());
+
+      assert_eq!(def1.compute_hash().unwrap(), def2.compute_hash().unwrap());
+    }
+
+    #[test]
+    fn update_strategy_does_not_affect_hash() {
+      let def1 = simple_def();
+
+      let mut def2 = simple_def();
+      def2.update_strategy = UpdateStrategy::Recreate;
+
+      assert_eq!(def1.compute_hash().unwrap(), def2.compute_hash().unwrap());
+      assert!(!serde_json::to_string(&def1).unwrap().contains("update_strategy"));
+      assert_eq!(
+        serde_json::to_value(&def2).unwrap()["update_strategy"],
+        serde_json::json!("recreate")
+      );
+    }
+
+    #[test]
+    fn update_strategy_parses_known_names() {
+      assert_eq!("in_place".parse::<UpdateStrategy>(), Ok(UpdateStrategy::InPlace));
+      assert_eq!("recreate".parse::<UpdateStrategy>(), Ok(UpdateStrategy::Recreate));
+      assert!("replace".parse::<UpdateStrategy>().is_err());
+    }
   }
 }
@@ -4,7 +4,7 @@
 //! producing the final BuildResult.
 
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
@@ -12,14 +12,25 @@ use tokio::fs;
 use tracing::{debug, warn};
 
 use crate::build::BuildDef;
+use crate::build::refs::scan_references;
 use crate::build::store::build_dir_path;
+use crate::build::users::BuildUserLease;
 use crate::manifest::Manifest;
 use crate::placeholder;
-
-use crate::action::execute_action;
+use crate::platform::cgroup::Cgroup;
+use crate::platform::immutable::{make_immutable, remove_store_path};
+use crate::platform::network::DenyProxy;
+use crate::platform::run_as::{RunAs, chown_tree, kill_user_processes, reclaim_tree, user_ids};
+
+use crate::action::actions::download_cache::DownloadOptions;
+use crate::action::actions::exec::ExecIsolation;
+use crate::action::{Action, execute_action};
+use crate::execute::history::NodeKind;
+use crate::execute::progress::ProgressEvent;
 use crate::execute::resolver::BuildCtxResolver;
+use crate::execute::transcript::TranscriptOperation;
 use crate::execute::types::{ActionResult, BindResult, BuildResult, ExecuteConfig, ExecuteError};
-use crate::util::hash::{ObjectHash, hash_directory};
+use crate::util::hash::{Hashable, ObjectHash, hash_directory};
 
 /// Marker file name indicating a build completed successfully.
 pub const BUILD_COMPLETE_MARKER: &str = ".syslua-complete";
@@ -27,7 +38,7 @@ pub const BUILD_COMPLETE_MARKER: &str = ".syslua-complete";
 /// Files/directories excluded when hashing build outputs.
 /// - BUILD_COMPLETE_MARKER: The marker itself (written after hash)
 /// - "tmp": Build temp directory (may have leftovers)
-const BUILD_HASH_EXCLUSIONS: &[&str] = &[".syslua-complete", "tmp"];
+pub(crate) const BUILD_HASH_EXCLUSIONS: &[&str] = &[".syslua-complete", "tmp"];
 
 /// Marker file content structure.
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,23 +50,37 @@ pub struct BuildMarker {
   /// Full 64-character SHA256 hash of build outputs.
   #[serde(skip_serializing_if = "Option::is_none")]
   pub output_hash: Option<String>,
+  /// Hashes of other builds whose store paths appear in the outputs.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub references: Vec<String>,
 }
 
-/// Write the build completion marker with output hash.
+/// Write the build completion marker with output hash and references.
 /// Called after build succeeds, before returning BuildResult.
 async fn write_build_complete_marker(store_path: &Path) -> Result<(), ExecuteError> {
+  let content = serde_json::to_string(&complete_marker(store_path)?).expect("failed to serialize marker");
+  fs::write(store_path.join(BUILD_COMPLETE_MARKER), format!("{}\n", content))
+    .await
+    .map_err(|e| ExecuteError::WriteMarker { message: e.to_string() })
+}
+
+/// The completion marker for the build outputs in `store_path`.
+pub(crate) fn complete_marker(store_path: &Path) -> Result<BuildMarker, ExecuteError> {
   // Compute hash of build outputs (excluding marker and tmp)
   let output_hash = hash_directory(store_path, BUILD_HASH_EXCLUSIONS)?;
 
-  let marker = BuildMarker {
+  // Record store paths of other builds embedded in the outputs
+  let references = scan_references(store_path, BUILD_HASH_EXCLUSIONS)?;
+  if !references.is_empty() {
+    debug!(path = ?store_path, references = ?references, "found store references");
+  }
+
+  Ok(BuildMarker {
     version: 1,
     status: "complete".to_string(),
     output_hash: Some(output_hash.0),
-  };
-  let content = serde_json::to_string(&marker).expect("failed to serialize marker");
-  fs::write(store_path.join(BUILD_COMPLETE_MARKER), format!("{}\n", content))
-    .await
-    .map_err(|e| ExecuteError::WriteMarker { message: e.to_string() })
+    references: references.into_iter().map(|hash| hash.0).collect(),
+  })
 }
 
 /// Read the build completion marker.
@@ -85,7 +110,7 @@ pub fn is_build_complete(store_path: &Path) -> bool {
 ///
 /// Returns `true` if valid (should use cache), `false` if should rebuild.
 /// Legacy markers without `output_hash` are trusted.
-fn verify_build_hash(store_path: &Path, marker: &BuildMarker) -> bool {
+pub(crate) fn verify_build_hash(store_path: &Path, marker: &BuildMarker) -> bool {
   let Some(stored_hash) = &marker.output_hash else {
     // Legacy marker without hash - trust it
     debug!(path = ?store_path, "legacy marker without hash, trusting cache");
@@ -117,6 +142,34 @@ fn verify_build_hash(store_path: &Path, marker: &BuildMarker) -> bool {
   }
 }
 
+/// Make a completed build's outputs read-only, unless the config leaves them writable.
+fn protect_build_output(store_path: &Path, config: &ExecuteConfig) {
+  if !config.writable_store
+    && let Err(e) = make_immutable(store_path)
+  {
+    warn!(path = ?store_path, error = %e, "failed to make build output read-only");
+  }
+}
+
+/// Move a prebuilt build imported under the default hash spec to `store_path`.
+///
+/// `sys store add` can't know a config's `settings.hash`, so it stores imports
+/// under the default spec; a config with another spec finds them here.
+async fn adopt_prebuilt(hash: &ObjectHash, build_def: &BuildDef, store_path: &Path) -> Result<(), ExecuteError> {
+  if store_path.exists() {
+    return Ok(());
+  }
+  let default_hash = build_def
+    .compute_hash()
+    .map_err(|e| ExecuteError::InvalidManifest(format!("failed to hash prebuilt build: {}", e)))?;
+  let imported = build_dir_path(&default_hash);
+  if imported != store_path && is_build_complete(&imported) {
+    debug!(hash = %hash.0, from = ?imported, "adopting prebuilt build imported under the default hash spec");
+    fs::rename(&imported, store_path).await?;
+  }
+  Ok(())
+}
+
 /// Realize a single build.
 ///
 /// This executes all actions in the build definition and produces the
@@ -145,9 +198,13 @@ pub async fn realize_build(
     hash = %hash.0,
     "realizing build"
   );
+  config.roots.add(hash)?;
 
   // Compute the store path for this build
   let store_path = build_dir_path(hash);
+  if build_def.prebuilt.is_some() {
+    adopt_prebuilt(hash, build_def, &store_path).await?;
+  }
 
   // Check if already built (cache hit)
   if store_path.exists() {
@@ -155,49 +212,115 @@ pub async fn realize_build(
       Ok(Some(marker)) => {
         if verify_build_hash(&store_path, &marker) {
           debug!(path = ?store_path, "build already exists in store (cache hit)");
+          config.progress.send(ProgressEvent::Cached { hash: hash.clone() });
           let outputs = resolve_outputs(build_def, &store_path, &[], completed_builds, manifest, config)?;
           return Ok(BuildResult {
             store_path,
             outputs,
             action_results: vec![],
+            cached: true,
           });
         }
         // Hash mismatch - remove and rebuild
         debug!(path = ?store_path, "removing corrupted build");
-        fs::remove_dir_all(&store_path).await?;
+        remove_store_path(&store_path)?;
       }
       Ok(None) => {
         // No marker - incomplete build
         debug!(path = ?store_path, "incomplete build found, removing");
-        fs::remove_dir_all(&store_path).await?;
+        remove_store_path(&store_path)?;
       }
       Err(e) => {
         // Invalid marker - treat as incomplete
         debug!(path = ?store_path, error = %e, "invalid marker, removing");
-        fs::remove_dir_all(&store_path).await?;
+        remove_store_path(&store_path)?;
       }
     }
   }
 
+  // Prebuilt builds have nothing to run
+  if build_def.prebuilt.is_some() {
+    return Err(ExecuteError::PrebuiltMissing {
+      id: build_def.id.clone().unwrap_or_else(|| hash.0.clone()),
+    });
+  }
+
   // Create the output directory
   fs::create_dir_all(&store_path).await?;
 
   // Create resolver for this build
   let mut resolver = BuildCtxResolver::new(completed_builds, manifest, store_path.to_string_lossy().to_string());
 
+  // Commands run as a build user of their own, if any, owning the output directory
+  let mut build_user = BuildUser::for_config(config, &store_path).await?;
+
+  // Apply declared resource limits (best-effort) and network isolation to the build's commands,
+  // and the rate and concurrency limits to its downloads and package manager invocations
+  let mut isolation = ExecIsolation {
+    cgroup: build_def
+      .resources
+      .and_then(|r| Cgroup::create(&format!("build-{}", hash.0), r.cpus, r.memory)),
+    network: if config.isolate_network {
+      Some(DenyProxy::start().await?)
+    } else {
+      None
+    },
+    user: build_user.as_ref().map(|u| u.run_as.clone()),
+    downloads: DownloadOptions {
+      rate: config.limit_rate.clone(),
+      progress: Some((config.progress.clone(), hash.clone())),
+    },
+    limits: config.action_limits.clone(),
+    transcript: config.transcript.scope(
+      NodeKind::Build,
+      hash,
+      build_def.id.as_deref(),
+      TranscriptOperation::Build,
+    ),
+  };
+
   // Execute actions in order
   let mut action_results = Vec::new();
 
   for (idx, action) in build_def.create_actions.iter().enumerate() {
     debug!(action_idx = idx, "executing action");
 
-    let result = execute_action(action, &resolver, &store_path).await?;
+    if let Some(user) = &mut build_user {
+      user.prepare(action)?;
+    }
+    let result = execute_action(action, &resolver, &store_path, Some(&isolation)).await?;
 
     // Record the result for subsequent actions
-    resolver.push_action_result(result.output.clone());
+    resolver.push_action(result.output.clone(), result.outputs.clone());
     action_results.push(result);
   }
 
+  // Run checks before the completion marker, so a failed check leaves the build incomplete
+  if let Some(check_actions) = &build_def.check_actions
+    && !config.skip_checks
+  {
+    isolation.transcript = isolation
+      .transcript
+      .map(|t| t.with_operation(TranscriptOperation::Check));
+    for (idx, action) in check_actions.iter().enumerate() {
+      debug!(action_idx = idx, "executing check action");
+
+      if let Some(user) = &mut build_user {
+        user.prepare(action)?;
+      }
+      let result = execute_action(action, &resolver, &store_path, Some(&isolation))
+        .await
+        .map_err(|e| ExecuteError::CheckFailed { message: e.to_string() })?;
+
+      resolver.push_action(result.output.clone(), result.outputs.clone());
+      action_results.push(result);
+    }
+  }
+
+  if let Some(user) = &mut build_user {
+    user.release()?;
+  }
+
   // Resolve outputs
   let outputs = resolve_outputs(
     build_def,
@@ -210,6 +333,7 @@ pub async fn realize_build(
 
   // Write completion marker
   write_build_complete_marker(&store_path).await?;
+  protect_build_output(&store_path, config);
 
   debug!(
     id = ?build_def.id,
@@ -221,9 +345,89 @@ pub async fn realize_build(
     store_path,
     outputs,
     action_results,
+    cached: false,
   })
 }
 
+/// The user of [`ExecuteConfig::build_users`] a build holds, and whether it
+/// owns the output directory.
+struct BuildUser {
+  lease: BuildUserLease,
+  run_as: RunAs,
+  uid: u32,
+  gid: u32,
+  store_path: PathBuf,
+  owns_output: bool,
+}
+
+impl BuildUser {
+  async fn for_config(config: &ExecuteConfig, store_path: &Path) -> Result<Option<Self>, ExecuteError> {
+    let Some(users) = &config.build_users else {
+      return Ok(None);
+    };
+    let lease = users.acquire().await.map_err(|e| ExecuteError::RunAs {
+      user: users.group().to_string(),
+      message: e.to_string(),
+    })?;
+    let run_as_error = |message: String| ExecuteError::RunAs {
+      user: lease.user().to_string(),
+      message,
+    };
+    let run_as = RunAs::for_user(lease.user()).map_err(|e| run_as_error(e.to_string()))?;
+    let (uid, gid) = user_ids(lease.user()).map_err(|e| run_as_error(e.to_string()))?;
+    // In case an earlier build couldn't end its processes
+    kill_user_processes(uid, gid).map_err(|e| run_as_error(format!("failed to kill leftover processes: {}", e)))?;
+    Ok(Some(Self {
+      lease,
+      run_as,
+      uid,
+      gid,
+      store_path: store_path.to_path_buf(),
+      owns_output: false,
+    }))
+  }
+
+  /// Hand the output directory to the user before `action` runs a command,
+  /// or take it back before an action running as this process.
+  ///
+  /// Other actions (downloads, unpacking) write as root, so they must never
+  /// see a tree the user could have planted symlinks in.
+  fn prepare(&mut self, action: &Action) -> Result<(), ExecuteError> {
+    match action {
+      Action::Exec(_) if !self.owns_output => {
+        chown_tree(&self.store_path, self.uid, self.gid, false)?;
+        self.owns_output = true;
+        Ok(())
+      }
+      Action::Exec(_) => Ok(()),
+      _ => self.release(),
+    }
+  }
+
+  /// Kill what the user left running and take the output directory back.
+  fn release(&mut self) -> Result<(), ExecuteError> {
+    if !self.owns_output {
+      return Ok(());
+    }
+    kill_user_processes(self.uid, self.gid).map_err(|e| ExecuteError::RunAs {
+      user: self.lease.user().to_string(),
+      message: format!("failed to kill the build's processes: {}", e),
+    })?;
+    reclaim_tree(&self.store_path)?;
+    self.owns_output = false;
+    Ok(())
+  }
+}
+
+impl Drop for BuildUser {
+  // A failed build still ends its processes before the user is reused
+  fn drop(&mut self) {
+    if let Err(e) = self.release() {
+      warn!(user = self.lease.user(), error = %e, "failed to release build user");
+    }
+  }
+}
+
 /// Realize a single build (DAG execution variant).
 ///
 /// This is similar to `realize_build()` but accepts `completed_binds` for
@@ -255,9 +459,13 @@ pub async fn realize_build_with_resolver(
     hash = %hash.0,
     "realizing build (with unified resolver)"
   );
+  config.roots.add(hash)?;
 
   // Compute the store path for this build
   let store_path = build_dir_path(hash);
+  if build_def.prebuilt.is_some() {
+    adopt_prebuilt(hash, build_def, &store_path).await?;
+  }
 
   // Check if already built (cache hit)
   if store_path.exists() {
@@ -265,6 +473,7 @@ pub async fn realize_build_with_resolver(
       Ok(Some(marker)) => {
         if verify_build_hash(&store_path, &marker) {
           debug!(path = ?store_path, "build already exists in store (cache hit)");
+          config.progress.send(ProgressEvent::Cached { hash: hash.clone() });
           let outputs = resolve_outputs_with_resolver(
             build_def,
             &store_path,
@@ -278,25 +487,33 @@ pub async fn realize_build_with_resolver(
             store_path,
             outputs,
             action_results: vec![],
+            cached: true,
           });
         }
         // Hash mismatch - remove and rebuild
         debug!(path = ?store_path, "removing corrupted build");
-        fs::remove_dir_all(&store_path).await?;
+        remove_store_path(&store_path)?;
       }
       Ok(None) => {
         // No marker - incomplete build
         debug!(path = ?store_path, "incomplete build found, removing");
-        fs::remove_dir_all(&store_path).await?;
+        remove_store_path(&store_path)?;
       }
       Err(e) => {
         // Invalid marker - treat as incomplete
         debug!(path = ?store_path, error = %e, "invalid marker, removing");
-        fs::remove_dir_all(&store_path).await?;
+        remove_store_path(&store_path)?;
       }
     }
   }
 
+  // Prebuilt builds have nothing to run
+  if build_def.prebuilt.is_some() {
+    return Err(ExecuteError::PrebuiltMissing {
+      id: build_def.id.clone().unwrap_or_else(|| hash.0.clone()),
+    });
+  }
+
   // Create the output directory
   fs::create_dir_all(&store_path).await?;
 
@@ -304,19 +521,76 @@ pub async fn realize_build_with_resolver(
   let mut resolver = BuildCtxResolver::new(completed_builds, manifest, store_path.to_string_lossy().to_string());
   let _ = completed_binds; // Unused - builds cannot reference binds
 
+  // Commands run as a build user of their own, if any, owning the output directory
+  let mut build_user = BuildUser::for_config(config, &store_path).await?;
+
+  // Apply declared resource limits (best-effort) and network isolation to the build's commands,
+  // and the rate and concurrency limits to its downloads and package manager invocations
+  let mut isolation = ExecIsolation {
+    cgroup: build_def
+      .resources
+      .and_then(|r| Cgroup::create(&format!("build-{}", hash.0), r.cpus, r.memory)),
+    network: if config.isolate_network {
+      Some(DenyProxy::start().await?)
+    } else {
+      None
+    },
+    user: build_user.as_ref().map(|u| u.run_as.clone()),
+    downloads: DownloadOptions {
+      rate: config.limit_rate.clone(),
+      progress: Some((config.progress.clone(), hash.clone())),
+    },
+    limits: config.action_limits.clone(),
+    transcript: config.transcript.scope(
+      NodeKind::Build,
+      hash,
+      build_def.id.as_deref(),
+      TranscriptOperation::Build,
+    ),
+  };
+
   // Execute actions in order
   let mut action_results = Vec::new();
 
   for (idx, action) in build_def.create_actions.iter().enumerate() {
     debug!(action_idx = idx, "executing action");
 
-    let result = execute_action(action, &resolver, &store_path).await?;
+    if let Some(user) = &mut build_user {
+      user.prepare(action)?;
+    }
+    let result = execute_action(action, &resolver, &store_path, Some(&isolation)).await?;
 
     // Record the result for subsequent actions
-    resolver.push_action_result(result.output.clone());
+    resolver.push_action(result.output.clone(), result.outputs.clone());
     action_results.push(result);
   }
 
+  // Run checks before the completion marker, so a failed check leaves the build incomplete
+  if let Some(check_actions) = &build_def.check_actions
+    && !config.skip_checks
+  {
+    isolation.transcript = isolation
+      .transcript
+      .map(|t| t.with_operation(TranscriptOperation::Check));
+    for (idx, action) in check_actions.iter().enumerate() {
+      debug!(action_idx = idx, "executing check action");
+
+      if let Some(user) = &mut build_user {
+        user.prepare(action)?;
+      }
+      let result = execute_action(action, &resolver, &store_path, Some(&isolation))
+        .await
+        .map_err(|e| ExecuteError::CheckFailed { message: e.to_string() })?;
+
+      resolver.push_action(result.output.clone(), result.outputs.clone());
+      action_results.push(result);
+    }
+  }
+
+  if let Some(user) = &mut build_user {
+    user.release()?;
+  }
+
   // Resolve outputs
   let outputs = resolve_outputs_with_resolver(
     build_def,
@@ -330,6 +604,7 @@ pub async fn realize_build_with_resolver(
 
   // Write completion marker
   write_build_complete_marker(&store_path).await?;
+  protect_build_output(&store_path, config);
 
   debug!(
     id = ?build_def.id,
@@ -341,6 +616,7 @@ pub async fn realize_build_with_resolver(
     store_path,
     outputs,
     action_results,
+    cached: false,
   })
 }
 
@@ -369,7 +645,7 @@ fn resolve_outputs(
     // Create a resolver with the action results
     let mut resolver = BuildCtxResolver::new(completed_builds, manifest, store_path.to_string_lossy().to_string());
     for result in action_results {
-      resolver.push_action_result(result.output.clone());
+      resolver.push_action(result.output.clone(), result.outputs.clone());
     }
 
     for (name, value) in def_outputs {
@@ -417,7 +693,7 @@ fn resolve_outputs_with_resolver(
     // Create a resolver with the action results
     let mut resolver = BuildCtxResolver::new(completed_builds, manifest, store_path.to_string_lossy().to_string());
     for result in action_results {
-      resolver.push_action_result(result.output.clone());
+      resolver.push_action(result.output.clone(), result.outputs.clone());
     }
 
     for (name, value) in def_outputs {
@@ -456,13 +732,25 @@ mod tests {
         args: Some(args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     }
   }
 
   fn test_config() -> ExecuteConfig {
-    ExecuteConfig { parallelism: 1 }
+    ExecuteConfig {
+      parallelism: 1,
+      ..Default::default()
+    }
   }
 
   /// Helper to set up a temp store and run a test.
@@ -475,13 +763,16 @@ mod tests {
     let temp_dir = TempDir::new().unwrap();
     let store_path = temp_dir.path().join("store");
 
-    temp_env::with_var("SYSLUA_STORE", Some(store_path.to_str().unwrap()), || {
+    let result = temp_env::with_var("SYSLUA_STORE", Some(store_path.to_str().unwrap()), || {
       tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap()
         .block_on(f())
-    })
+    });
+    // Realized builds are read-only; let the temp dir be deleted
+    let _ = crate::platform::make_mutable(&store_path);
+    result
   }
 
   #[test]
@@ -493,6 +784,7 @@ mod tests {
       let manifest = Manifest {
         builds: [(hash.clone(), build_def.clone())].into_iter().collect(),
         bindings: Default::default(),
+        ..Default::default()
       };
 
       let config = test_config();
@@ -530,6 +822,10 @@ mod tests {
           args: Some(args),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         outputs: Some(
           [
@@ -539,12 +835,18 @@ mod tests {
           .into_iter()
           .collect(),
         ),
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       };
       let hash = build_def.compute_hash().unwrap();
 
       let manifest = Manifest {
         builds: [(hash.clone(), build_def.clone())].into_iter().collect(),
         bindings: Default::default(),
+        ..Default::default()
       };
 
       let config = test_config();
@@ -561,6 +863,38 @@ mod tests {
     });
   }
 
+  #[test]
+  fn failed_check_fails_build_before_completion() {
+    with_temp_store(|| async {
+      let (cmd, args) = shell_cmd("exit 1");
+      let build_def = BuildDef {
+        check_actions: Some(vec![Action::Exec(ExecOpts::new(cmd).with_args(args))]),
+        ..make_simple_build()
+      };
+      let hash = build_def.compute_hash().unwrap();
+
+      let manifest = Manifest {
+        builds: [(hash.clone(), build_def.clone())].into_iter().collect(),
+        ..Default::default()
+      };
+
+      let result = realize_build(&hash, &build_def, &HashMap::new(), &manifest, &test_config()).await;
+      assert!(matches!(result, Err(ExecuteError::CheckFailed { .. })));
+      assert!(!is_build_complete(&build_dir_path(&hash)));
+
+      // Skipping checks realizes the same build
+      let config = ExecuteConfig {
+        skip_checks: true,
+        ..test_config()
+      };
+      let result = realize_build(&hash, &build_def, &HashMap::new(), &manifest, &config)
+        .await
+        .unwrap();
+      assert_eq!(result.action_results.len(), 1);
+      assert!(is_build_complete(&result.store_path));
+    });
+  }
+
   #[test]
   fn realize_build_with_multiple_actions() {
     with_temp_store(|| async {
@@ -576,12 +910,20 @@ mod tests {
             args: Some(args1),
             env: None,
             cwd: None,
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
           Action::Exec(ExecOpts {
             bin: cmd2.to_string(),
             args: Some(args2),
             env: None,
             cwd: None,
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
           Action::Exec(ExecOpts {
             // Reference previous action output
@@ -589,6 +931,10 @@ mod tests {
             args: Some(args3),
             env: None,
             cwd: None,
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
         ],
         outputs: Some(
@@ -596,12 +942,18 @@ mod tests {
             .into_iter()
             .collect(),
         ),
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       };
       let hash = build_def.compute_hash().unwrap();
 
       let manifest = Manifest {
         builds: [(hash.clone(), build_def.clone())].into_iter().collect(),
         bindings: Default::default(),
+        ..Default::default()
       };
 
       let config = test_config();
@@ -631,14 +983,24 @@ mod tests {
           args: Some(args),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       };
       let hash = build_def.compute_hash().unwrap();
 
       let manifest = Manifest {
         builds: [(hash.clone(), build_def.clone())].into_iter().collect(),
         bindings: Default::default(),
+        ..Default::default()
       };
 
       let config = test_config();
@@ -682,6 +1044,7 @@ mod tests {
       let manifest = Manifest {
         builds: [(hash.clone(), build_def.clone())].into_iter().collect(),
         bindings: Default::default(),
+        ..Default::default()
       };
       let config = test_config();
 
@@ -709,6 +1072,7 @@ mod tests {
       let manifest = Manifest {
         builds: [(hash.clone(), build_def.clone())].into_iter().collect(),
         bindings: Default::default(),
+        ..Default::default()
       };
       let config = test_config();
 
@@ -835,6 +1199,44 @@ mod tests {
     assert!(verify_build_hash(temp.path(), &marker));
   }
 
+  #[test]
+  #[cfg(unix)]
+  fn realized_build_is_read_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    with_temp_store(|| async {
+      let build_def = make_simple_build();
+      let hash = build_def.compute_hash().unwrap();
+      let manifest = Manifest {
+        builds: [(hash.clone(), build_def.clone())].into_iter().collect(),
+        ..Default::default()
+      };
+
+      let result = realize_build(&hash, &build_def, &HashMap::new(), &manifest, &test_config())
+        .await
+        .unwrap();
+      let mode = std::fs::metadata(&result.store_path).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, 0o555);
+      let marker_mode = std::fs::metadata(result.store_path.join(BUILD_COMPLETE_MARKER))
+        .unwrap()
+        .permissions()
+        .mode();
+      assert_eq!(marker_mode & 0o777, 0o444);
+
+      // Removing it restores write permissions first
+      remove_store_path(&result.store_path).unwrap();
+
+      let config = ExecuteConfig {
+        writable_store: true,
+        ..test_config()
+      };
+      let result = realize_build(&hash, &build_def, &HashMap::new(), &manifest, &config)
+        .await
+        .unwrap();
+      assert!(!std::fs::metadata(&result.store_path).unwrap().permissions().readonly());
+    });
+  }
+
   #[test]
   fn corrupted_build_triggers_full_rebuild() {
     with_temp_store(|| async {
@@ -843,6 +1245,7 @@ mod tests {
       let manifest = Manifest {
         builds: [(hash.clone(), build_def.clone())].into_iter().collect(),
         bindings: Default::default(),
+        ..Default::default()
       };
       let config = test_config();
 
@@ -850,8 +1253,16 @@ mod tests {
       let result1 = realize_build(&hash, &build_def, &HashMap::new(), &manifest, &config)
         .await
         .unwrap();
+      assert!(!result1.cached);
+
+      // Found in the store as it is
+      let cached = realize_build(&hash, &build_def, &HashMap::new(), &manifest, &config)
+        .await
+        .unwrap();
+      assert!(cached.cached);
 
       // Corrupt the build by adding a file
+      crate::platform::make_mutable(&result1.store_path).unwrap();
       std::fs::write(result1.store_path.join("corrupt.txt"), "bad data").unwrap();
 
       // Second build - should detect corruption and rebuild
@@ -860,6 +1271,7 @@ mod tests {
         .unwrap();
 
       // Verify rebuild happened (corruption file removed)
+      assert!(!result2.cached);
       assert!(!result2.store_path.join("corrupt.txt").exists());
       assert!(is_build_complete(&result2.store_path));
     });
@@ -0,0 +1,199 @@
+//! Importing existing directories into the store as prebuilt builds.
+//!
+//! `sys store add <dir> --id <name>` copies a directory (a Nix store path, an
+//! unpacked release, another machine's build output) into the store as a
+//! realized build, so a config can use it without knowing how it was made:
+//!
+//! ```lua
+//! local nvim = sys.prebuilt({ id = "nvim", hash = "<output hash printed by sys store add>" })
+//! ```
+//!
+//! The build's definition records only its id and the hash of the imported
+//! files, so the same directory imported on another machine lands at the same
+//! store path.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{debug, info};
+
+use crate::build::BuildDef;
+use crate::build::execute::{BUILD_COMPLETE_MARKER, BUILD_HASH_EXCLUSIONS, complete_marker, read_build_marker};
+use crate::build::store::build_dir_path;
+use crate::execute::ExecuteError;
+use crate::platform::immutable::remove_store_path;
+use crate::store_lock::{LockMode, StoreLock, StoreLockError};
+use crate::util::fs::copy_dir;
+use crate::util::hash::{DirHashError, HashError, Hashable, ObjectHash, hash_directory};
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+  #[error("'{0}' is not a directory")]
+  NotADirectory(PathBuf),
+
+  #[error("failed to read {path}: {source}")]
+  Read { path: PathBuf, source: std::io::Error },
+
+  #[error(transparent)]
+  Lock(#[from] StoreLockError),
+
+  #[error("failed to hash '{path}': {source}")]
+  HashSource { path: PathBuf, source: DirHashError },
+
+  #[error("failed to hash build definition: {0}")]
+  HashDef(#[from] HashError),
+
+  #[error("failed to copy into {path}: {source}")]
+  Copy { path: PathBuf, source: std::io::Error },
+
+  #[error("imported copy at {path} doesn't match its source (special files are not imported)")]
+  Mismatch { path: PathBuf },
+
+  #[error("failed to write build marker: {0}")]
+  Marker(#[from] ExecuteError),
+}
+
+/// A directory imported into the store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportedBuild {
+  pub id: String,
+  /// Build hash, the name of its store directory.
+  pub hash: ObjectHash,
+  /// Hash of the imported files, what `sys.prebuilt` takes.
+  pub output_hash: String,
+  pub store_path: PathBuf,
+  /// The same files were already imported under this id.
+  pub already_present: bool,
+}
+
+/// Import the directory `source` into the store as the prebuilt build `id`.
+///
+/// The build is stored under the default hash spec. A symlink `source` (such
+/// as a Nix `result` link) is followed; symlinks inside it are kept.
+pub fn import_dir(source: &Path, id: &str) -> Result<ImportedBuild, ImportError> {
+  let source = source
+    .canonicalize()
+    .ok()
+    .filter(|path| path.is_dir())
+    .ok_or_else(|| ImportError::NotADirectory(source.to_path_buf()))?;
+
+  let output_hash = hash_directory(&source, BUILD_HASH_EXCLUSIONS).map_err(|e| ImportError::HashSource {
+    path: source.clone(),
+    source: e,
+  })?;
+
+  let _lock = StoreLock::acquire(LockMode::Exclusive, "store add")?;
+  store_prebuilt(id, output_hash.0, |store_path| {
+    copy_dir(&source, store_path, BUILD_HASH_EXCLUSIONS)
+  })
+}
+
+/// Store files hashing to `output_hash` as the prebuilt build `id`, unless
+/// they already are. `place` puts them at the store path it is given.
+///
+/// The caller holds the store lock or otherwise keeps GC away.
+pub(crate) fn store_prebuilt(
+  id: &str,
+  output_hash: String,
+  place: impl FnOnce(&Path) -> std::io::Result<()>,
+) -> Result<ImportedBuild, ImportError> {
+  let hash = BuildDef::prebuilt(id, &output_hash).compute_hash()?;
+  let store_path = build_dir_path(&hash);
+
+  if let Ok(Some(marker)) = read_build_marker(&store_path)
+    && marker.output_hash.as_deref() == Some(output_hash.as_str())
+  {
+    debug!(hash = %hash.0, "prebuilt build already in store");
+    return Ok(ImportedBuild {
+      id: id.to_string(),
+      hash,
+      output_hash,
+      store_path,
+      already_present: true,
+    });
+  }
+
+  // Leftovers of an interrupted import
+  let copy_error = |e| ImportError::Copy {
+    path: store_path.clone(),
+    source: e,
+  };
+  if store_path.exists() {
+    remove_store_path(&store_path).map_err(copy_error)?;
+  } else if let Some(parent) = store_path.parent() {
+    std::fs::create_dir_all(parent).map_err(copy_error)?;
+  }
+  place(&store_path).map_err(copy_error)?;
+
+  // Write the marker last, so an interrupted import is never taken for a build
+  let marker = complete_marker(&store_path)?;
+  if marker.output_hash.as_deref() != Some(output_hash.as_str()) {
+    let _ = std::fs::remove_dir_all(&store_path);
+    return Err(ImportError::Mismatch { path: store_path });
+  }
+  let content = serde_json::to_string(&marker).expect("failed to serialize marker");
+  std::fs::write(store_path.join(BUILD_COMPLETE_MARKER), format!("{}\n", content))
+    .map_err(|e| ExecuteError::WriteMarker { message: e.to_string() })?;
+
+  info!(id, hash = %hash.0, path = ?store_path, "imported prebuilt build");
+  Ok(ImportedBuild {
+    id: id.to_string(),
+    hash,
+    output_hash,
+    store_path,
+    already_present: false,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::build::execute::is_build_complete;
+  use serial_test::serial;
+  use tempfile::TempDir;
+
+  #[test]
+  #[serial]
+  fn import_dir_stores_complete_build_once() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("nvim");
+    std::fs::create_dir_all(source.join("bin")).unwrap();
+    std::fs::write(source.join("bin").join("nvim"), b"binary").unwrap();
+
+    temp_env::with_vars(
+      [
+        ("SYSLUA_ROOT", Some(temp.path().join("root").to_str().unwrap())),
+        ("SYSLUA_STORE", None),
+      ],
+      || {
+        let imported = import_dir(&source, "nvim").unwrap();
+        assert!(!imported.already_present);
+        assert_eq!(
+          imported.hash,
+          BuildDef::prebuilt("nvim", &imported.output_hash)
+            .compute_hash()
+            .unwrap()
+        );
+        assert!(is_build_complete(&imported.store_path));
+        assert_eq!(
+          std::fs::read(imported.store_path.join("bin").join("nvim")).unwrap(),
+          b"binary"
+        );
+
+        let again = import_dir(&source, "nvim").unwrap();
+        assert!(again.already_present);
+        assert_eq!(again.hash, imported.hash);
+      },
+    );
+  }
+
+  #[test]
+  fn import_dir_rejects_files() {
+    let temp = TempDir::new().unwrap();
+    let file = temp.path().join("file");
+    std::fs::write(&file, b"").unwrap();
+
+    assert!(matches!(import_dir(&file, "x"), Err(ImportError::NotADirectory(_))));
+  }
+}
@@ -1,8 +1,10 @@
-//! Lua bindings for `sys.build{}`.
+//! Lua bindings for `sys.build{}`, `sys.prebuilt{}` and `sys.src{}`.
 //!
 //! This module provides:
 //! - `BuildCtx` as LuaUserData with methods like `fetch_url` and `exec`
 //! - `register_sys_build()` to register the `sys.build` function
+//! - `register_sys_prebuilt()` to register the `sys.prebuilt` function
+//! - `register_sys_src()` to register the `sys.src` function
 //! - Helper functions for converting between Lua values and Rust types
 
 use std::cell::RefCell;
@@ -13,10 +15,17 @@ use mlua::prelude::*;
 
 use crate::action::BUILD_CTX_METHODS_REGISTRY_KEY;
 use crate::action::actions::exec::parse_exec_opts;
-use crate::manifest::Manifest;
+use crate::lua::runtime::{caller_dir, caller_location};
+use crate::manifest::{
+  Manifest, PlaceholderMode, SkippedBuild, record_strict_placeholders, registry_hash_spec, unsupported_platform,
+  validate_build_in,
+};
 use crate::outputs::lua::parse_outputs;
+use crate::platform::paths::expand_path;
 use crate::{bind::BIND_REF_TYPE, util::hash::ObjectHash};
 
+use super::overrides::{apply_build_overrides, record_build_overrides};
+use super::src::{SourceFilter, snapshot_dir};
 use super::{BUILD_REF_TYPE, BuildCtx, BuildDef, BuildInputs, BuildRef, BuildSpec};
 
 impl LuaUserData for BuildCtx {
@@ -26,12 +35,45 @@ impl LuaUserData for BuildCtx {
   }
 
   fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
-    methods.add_method_mut("fetch_url", |_, this, (url, sha256): (String, String)| {
-      Ok(this.fetch_url(&url, &sha256))
+    // ctx:fetch_url(url, sha256) or ctx:fetch_url{ url, sha256, unpack = true }
+    methods.add_method_mut("fetch_url", |lua, this, (spec, sha256): (LuaValue, Option<String>)| {
+      let (url, sha256, unpack) = match (spec, sha256) {
+        (LuaValue::String(url), Some(sha256)) => (url.to_str()?.to_string(), sha256, false),
+        (LuaValue::Table(opts), None) => {
+          let field = |name: &str| -> LuaResult<String> {
+            opts
+              .get::<Option<String>>(name)?
+              .ok_or_else(|| LuaError::external(format!("fetch_url: '{}' is required", name)))
+          };
+          (
+            field("url")?,
+            field("sha256")?,
+            opts.get::<Option<bool>>("unpack")?.unwrap_or(false),
+          )
+        }
+        _ => {
+          return Err(LuaError::external(
+            "fetch_url expects (url, sha256) or { url = ..., sha256 = ..., unpack = ... }",
+          ));
+        }
+      };
+
+      if !unpack {
+        return this.fetch_url(&url, &sha256).into_lua(lua);
+      }
+      let (archive, src) = this.fetch_and_unpack(&url, &sha256);
+      let outputs = lua.create_table()?;
+      outputs.set("archive", archive)?;
+      outputs.set("src", src)?;
+      Ok(LuaValue::Table(outputs))
     });
 
-    methods.add_method_mut("exec", |_, this, (opts, args): (LuaValue, Option<LuaValue>)| {
-      let cmd_opts = parse_exec_opts(opts, args)?;
+    methods.add_method_mut("exec", |lua, this, (opts, args): (LuaValue, Option<LuaValue>)| {
+      let cmd_opts = parse_exec_opts(lua, opts, args)?;
+      // Builds must stay pure; switching user is for binds changing the system
+      if cmd_opts.run_as.is_some() {
+        return Err(LuaError::external("build exec does not support 'run_as' or 'elevate'"));
+      }
       Ok(this.exec(cmd_opts))
     });
 
@@ -192,17 +234,35 @@ pub fn build_hash_to_lua(lua: &Lua, hash: &ObjectHash, manifest: &Manifest) -> L
 /// Register the `sys.build` function on the sys table.
 ///
 /// The `sys.build{}` function:
-/// 1. Parses a BuildSpec from the Lua table (id, inputs, create)
+/// 1. Runs the `sys.override_build` overrides of its id on the spec, then
+///    parses a BuildSpec from the Lua table (id, inputs, create); a build for
+///    other `platforms` is recorded as skipped and returns nil
 /// 2. Resolves inputs (calls function if dynamic, uses table directly if static)
 /// 3. Creates a BuildCtx and calls the create function
 /// 4. Captures the returned outputs (must be non-empty)
-/// 5. Creates a BuildDef, computes its hash, and adds it to the manifest
-/// 6. Returns a BuildRef as a Lua table with metatable marker
+/// 5. Calls the optional check function with the outputs, recording check actions
+/// 6. Creates a BuildDef and validates its outputs against the declared ones,
+///    and its placeholders against the manifest in the spec's placeholder mode,
+///    warning about environment variables read without `impure_env`
+/// 7. Computes its hash and adds it to the manifest, recording the overrides
+/// 8. Returns a BuildRef as a Lua table with metatable marker
 pub fn register_sys_build(lua: &Lua, sys_table: &LuaTable, manifest: Rc<RefCell<Manifest>>) -> LuaResult<()> {
   let build_fn = lua.create_function(move |lua, spec_table: LuaTable| {
+    let (spec_table, overrides) = apply_build_overrides(lua, spec_table)?;
     let build_spec: BuildSpec = lua.unpack(LuaValue::Table(spec_table))?;
     let id = build_spec.id.clone();
+
+    // Skip builds for other platforms, before running create
+    if let Some(reason) = unsupported_platform(lua, &build_spec.platforms)? {
+      tracing::info!(id = ?id, reason, "skipping build");
+      manifest.borrow_mut().skipped_builds.push(SkippedBuild { id, reason });
+      return Ok(LuaValue::Nil);
+    }
+
     let replace = build_spec.replace;
+    let schema = build_spec.outputs.clone();
+    let mode = PlaceholderMode::for_spec(lua, build_spec.placeholders)?;
+    let caller = caller_location(lua);
 
     let build_def = BuildDef::from_spec(
       lua,
@@ -212,52 +272,183 @@ pub fn register_sys_build(lua: &Lua, sys_table: &LuaTable, manifest: Rc<RefCell<
       build_inputs_def_to_lua,
       parse_outputs,
     )?;
+    if let Some(schema) = &schema {
+      schema
+        .validate(build_def.outputs.as_ref())
+        .map_err(|e| LuaError::external(e.describe("build", id.as_deref(), caller.as_deref())))?;
+    }
+    validate_build_in(&manifest.borrow(), &build_def, mode)
+      .map_err(|e| LuaError::external(e.describe("build", id.as_deref(), caller.as_deref())))?;
+    for name in build_def.undeclared_env() {
+      tracing::warn!(
+        build = id.as_deref().unwrap_or("<anonymous>"),
+        location = caller.as_deref().unwrap_or("<unknown>"),
+        "build reads ${} without declaring it in `impure_env`; changing it won't rebuild",
+        name
+      );
+    }
 
-    let build_ref = BuildRef::from_def(&build_def)?;
+    let build_ref = insert_build(lua, &manifest, build_def, replace)?;
+    if mode == PlaceholderMode::Strict {
+      record_strict_placeholders(lua, "build", &build_ref.hash, caller.as_deref())?;
+    }
+    if let Some(id) = &id {
+      record_build_overrides(&mut manifest.borrow_mut(), id, &build_ref.hash, overrides);
+    }
+    lua.pack(build_ref)
+  })?;
 
-    {
-      let mut manifest = manifest.borrow_mut();
-
-      // Hash dedup (existing behavior): identical content = same hash
-      if manifest.builds.contains_key(&build_ref.hash) {
-        tracing::warn!(
-          hash = %build_ref.hash.0,
-          id = ?id,
-          "duplicate build detected, skipping insertion"
-        );
-        return lua.pack(build_ref);
-      }
+  sys_table.set("build", build_fn)?;
+  Ok(())
+}
 
-      // ID dedup with explicit replace flag
-      if let Some(ref build_id) = id {
-        let existing = manifest
-          .builds
-          .iter()
-          .find(|(_, def)| def.id.as_ref() == Some(build_id))
-          .map(|(h, _)| h.clone());
-
-        if let Some(old_hash) = existing {
-          if !replace {
-            return Err(LuaError::external(format!(
-              "build with id '{}' already exists (hash: {}). Use `replace = true` to override, \
-               or use a different id. This error prevents accidental collisions.",
-              build_id, old_hash.0
-            )));
-          }
-          manifest.builds.remove(&old_hash);
-        }
-      }
+/// Register the `sys.prebuilt` function on the sys table.
+///
+/// `sys.prebuilt({ id = "nvim", hash = "..." })` declares a build imported with
+/// `sys store add`, by the output hash that command printed. It returns a
+/// BuildRef whose `out` output is the imported directory.
+pub fn register_sys_prebuilt(lua: &Lua, sys_table: &LuaTable, manifest: Rc<RefCell<Manifest>>) -> LuaResult<()> {
+  let prebuilt_fn = lua.create_function(move |lua, spec: LuaTable| {
+    let id: String = spec
+      .get::<Option<String>>("id")?
+      .ok_or_else(|| LuaError::external("sys.prebuilt requires an 'id'"))?;
+    let hash: String = spec.get::<Option<String>>("hash")?.ok_or_else(|| {
+      LuaError::external(format!(
+        "sys.prebuilt '{}' requires the 'hash' printed by `sys store add`",
+        id
+      ))
+    })?;
+    if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+      return Err(LuaError::external(format!(
+        "sys.prebuilt '{}': hash must be the 64-character output hash printed by `sys store add`",
+        id
+      )));
+    }
+    let replace = spec.get::<Option<bool>>("replace")?.unwrap_or(false);
 
-      manifest.builds.insert(build_ref.hash.clone(), build_def);
+    let build_ref = insert_build(
+      lua,
+      &manifest,
+      BuildDef::prebuilt(&id, &hash.to_ascii_lowercase()),
+      replace,
+    )?;
+    lua.pack(build_ref)
+  })?;
+
+  sys_table.set("prebuilt", prebuilt_fn)?;
+  Ok(())
+}
+
+/// Register the `sys.src` function on the sys table.
+///
+/// `sys.src({ path = "./app", include = {...}, exclude = {...} })` snapshots
+/// the filtered directory into the store and returns a BuildRef whose `out`
+/// output is the snapshot. A relative `path` is resolved against the directory
+/// of the calling file. The id defaults to the directory's name.
+pub fn register_sys_src(lua: &Lua, sys_table: &LuaTable, manifest: Rc<RefCell<Manifest>>) -> LuaResult<()> {
+  let src_fn = lua.create_function(move |lua, spec: LuaTable| {
+    let path: String = spec
+      .get::<Option<String>>("path")?
+      .ok_or_else(|| LuaError::external("sys.src requires a 'path'"))?;
+    let mut source = expand_path(&path);
+    if source.is_relative()
+      && let Some(dir) = caller_dir(lua)
+    {
+      source = dir.join(source);
     }
 
+    let filter = SourceFilter {
+      include: src_patterns(&spec, "include")?,
+      exclude: src_patterns(&spec, "exclude")?,
+      gitignore: spec.get::<Option<bool>>("gitignore")?.unwrap_or(true),
+    };
+    let id = match spec.get::<Option<String>>("id")? {
+      Some(id) => id,
+      None => source
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| LuaError::external(format!("sys.src '{}' requires an 'id'", path)))?,
+    };
+    let replace = spec.get::<Option<bool>>("replace")?.unwrap_or(false);
+
+    let snapshot =
+      snapshot_dir(&source, &id, &filter).map_err(|e| LuaError::external(format!("sys.src '{}': {}", path, e)))?;
+    let build_ref = insert_build(lua, &manifest, BuildDef::prebuilt(&id, &snapshot.output_hash), replace)?;
     lua.pack(build_ref)
   })?;
 
-  sys_table.set("build", build_fn)?;
+  sys_table.set("src", src_fn)?;
   Ok(())
 }
 
+/// Parse the `include` or `exclude` patterns of a `sys.src` spec.
+fn src_patterns(spec: &LuaTable, field: &str) -> LuaResult<Vec<String>> {
+  match spec.get::<LuaValue>(field)? {
+    LuaValue::Nil => Ok(Vec::new()),
+    LuaValue::String(s) => Ok(vec![s.to_str()?.to_string()]),
+    LuaValue::Table(t) => t
+      .sequence_values::<String>()
+      .collect::<LuaResult<_>>()
+      .map_err(|_| LuaError::external(format!("sys.src '{}' patterns must be strings", field))),
+    other => Err(LuaError::external(format!(
+      "sys.src '{}' must be a pattern or a list of patterns, got {}",
+      field,
+      other.type_name()
+    ))),
+  }
+}
+
+/// Hash `build_def` and add it to the manifest, returning its BuildRef.
+///
+/// Identical builds are added once. A different build with the same id is an
+/// error unless `replace` is set, in which case it is removed.
+fn insert_build(
+  lua: &Lua,
+  manifest: &Rc<RefCell<Manifest>>,
+  build_def: BuildDef,
+  replace: bool,
+) -> LuaResult<BuildRef> {
+  let hash_spec = registry_hash_spec(lua)?;
+  let build_ref = BuildRef::from_def(lua, &build_def, &hash_spec)?;
+  let id = build_def.id.clone();
+
+  let mut manifest = manifest.borrow_mut();
+  manifest.hash = hash_spec;
+
+  // Hash dedup (existing behavior): identical content = same hash
+  if manifest.builds.contains_key(&build_ref.hash) {
+    tracing::warn!(
+      hash = %build_ref.hash.0,
+      id = ?id,
+      "duplicate build detected, skipping insertion"
+    );
+    return Ok(build_ref);
+  }
+
+  // ID dedup with explicit replace flag
+  if let Some(ref build_id) = id {
+    let existing = manifest
+      .builds
+      .iter()
+      .find(|(_, def)| def.id.as_ref() == Some(build_id))
+      .map(|(h, _)| h.clone());
+
+    if let Some(old_hash) = existing {
+      if !replace {
+        return Err(LuaError::external(format!(
+          "build with id '{}' already exists (hash: {}). Use `replace = true` to override, \
+           or use a different id. This error prevents accidental collisions.",
+          build_id, old_hash.0
+        )));
+      }
+      manifest.builds.remove(&old_hash);
+    }
+  }
+
+  manifest.builds.insert(build_ref.hash.clone(), build_def);
+  Ok(build_ref)
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -363,6 +554,49 @@ mod tests {
       Ok(())
     }
 
+    #[test]
+    fn fetch_url_with_unpack_returns_archive_and_src() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      let build: LuaTable = lua
+        .load(
+          r#"
+                return sys.build({
+                    id = "unpacked",
+                    create = function(inputs, ctx)
+                        local fetched = ctx:fetch_url({ url = "https://example.com/src.tar.gz", sha256 = "abc123", unpack = true })
+                        ctx:exec("ls " .. fetched.archive)
+                        return { out = fetched.src }
+                    end,
+                })
+            "#,
+        )
+        .eval()?;
+      let hash: String = build.get("hash")?;
+
+      let manifest = manifest.borrow();
+      let build_def = &manifest.builds[&ObjectHash(hash)];
+      assert!(matches!(
+        &build_def.create_actions[0],
+        Action::FetchUrl { unpack: true, .. }
+      ));
+      let Action::Exec(ls) = &build_def.create_actions[1] else {
+        panic!("expected an exec action");
+      };
+      assert_eq!(ls.bin, "ls $${{action:0:archive}}");
+      assert_eq!(
+        build_def.outputs.as_ref().unwrap()["out"],
+        serde_json::json!("$${{action:0:src}}")
+      );
+
+      let err = lua
+        .load(r#"sys.build({ id = "bad", create = function(_, ctx) ctx:fetch_url({ url = "https://x" }) end })"#)
+        .exec()
+        .unwrap_err();
+      assert!(err.to_string().contains("'sha256' is required"), "{}", err);
+      Ok(())
+    }
+
     #[test]
     fn build_with_dynamic_inputs() -> LuaResult<()> {
       let (lua, manifest) = create_test_lua_with_manifest()?;
@@ -527,6 +761,94 @@ mod tests {
       Ok(())
     }
 
+    #[test]
+    fn build_with_unknown_build_output_fails_at_call_site() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      let result = lua
+        .load(
+          r#"
+local rg = sys.build({
+  id = "rg",
+  create = function(inputs, ctx)
+    return { out = ctx.out }
+  end,
+})
+return sys.build({
+  id = "wrapper",
+  inputs = { rg = rg },
+  create = function(inputs, ctx)
+    ctx:exec(inputs.rg.outputs.out .. "/bin/rg")
+    return { out = inputs.rg.outputs.out:gsub(":out}}", ":bin}}") }
+  end,
+})
+"#,
+        )
+        .set_name("@init.lua")
+        .eval::<LuaTable>();
+
+      let err = result.unwrap_err().to_string();
+      assert!(
+        err.contains("invalid placeholder in build 'wrapper' at init.lua:8: output 'out': build "),
+        "error should name the build and call site: {}",
+        err
+      );
+      assert!(err.contains("has no output 'bin' (outputs: out)"), "{}", err);
+      assert_eq!(manifest.borrow().builds.len(), 1);
+
+      Ok(())
+    }
+
+    #[test]
+    fn build_outputs_are_checked_against_declaration() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      let result = lua
+        .load(
+          r#"
+return sys.build({
+  id = "rg",
+  outputs = { out = "dir", bin = "file", version = "string?" },
+  create = function(inputs, ctx)
+    return { out = ctx.out, bni = ctx.out .. "/bin/rg" }
+  end,
+})
+"#,
+        )
+        .set_name("@init.lua")
+        .eval::<LuaTable>();
+
+      let err = result.unwrap_err().to_string();
+      assert!(
+        err.contains(
+          "outputs of build 'rg' at init.lua:2 don't match their declaration: \
+           create returned undeclared output 'bni' (declared: bin, out, version)"
+        ),
+        "{}",
+        err
+      );
+      assert!(manifest.borrow().builds.is_empty());
+
+      // Matching outputs pass, and the declaration doesn't change the hash
+      let declared: String = lua
+        .load(
+          r#"
+return sys.build({
+  id = "rg",
+  outputs = { out = "dir", version = "string?" },
+  create = function(inputs, ctx) return { out = ctx.out } end,
+}).hash
+"#,
+        )
+        .eval()?;
+      let undeclared: String = lua
+        .load(r#"return sys.build({ id = "rg", create = function(inputs, ctx) return { out = ctx.out } end }).hash"#)
+        .eval()?;
+      assert_eq!(declared, undeclared);
+
+      Ok(())
+    }
+
     #[test]
     fn multiple_builds_added_to_manifest() -> LuaResult<()> {
       let (lua, manifest) = create_test_lua_with_manifest()?;
@@ -833,6 +1155,46 @@ mod tests {
       Ok(())
     }
 
+    #[test]
+    fn check_records_check_actions_after_create() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      lua
+        .load(
+          r#"
+                sys.build({
+                    id = "checked",
+                    create = function(inputs, ctx)
+                        local bin = ctx:exec("make")
+                        return { out = ctx.out, bin = bin }
+                    end,
+                    check = function(outputs, ctx)
+                        local version = ctx:exec(outputs.bin .. " --version")
+                        ctx:exec("test -n " .. version)
+                    end,
+                })
+            "#,
+        )
+        .exec()?;
+
+      let manifest = manifest.borrow();
+      let (_, build_def) = manifest.builds.iter().next().unwrap();
+      assert_eq!(build_def.create_actions.len(), 1);
+
+      // Check placeholders continue after the create actions
+      let check_actions = build_def.check_actions.as_ref().expect("should have check actions");
+      let bins: Vec<_> = check_actions
+        .iter()
+        .map(|action| match action {
+          Action::Exec(opts) => opts.bin.as_str(),
+          _ => panic!("expected Exec action"),
+        })
+        .collect();
+      assert_eq!(bins, ["$${{action:0}} --version", "test -n $${{action:1}}"]);
+
+      Ok(())
+    }
+
     #[test]
     fn build_with_bind_input_fails() -> LuaResult<()> {
       let (lua, _) = create_test_lua_with_manifest()?;
@@ -918,4 +1280,217 @@ mod tests {
       Ok(())
     }
   }
+
+  mod sys_prebuilt {
+    use super::*;
+    use crate::util::hash::Hashable;
+
+    const OUTPUT_HASH: &str = "0f1e2d3c4b5a69788796a5b4c3d2e1f00f1e2d3c4b5a69788796a5b4c3d2e1f0";
+
+    #[test]
+    fn prebuilt_returns_build_ref_to_imported_dir() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      let result: LuaTable = lua
+        .load(format!(
+          r#"return sys.prebuilt({{ id = "nvim", hash = "{}" }})"#,
+          OUTPUT_HASH
+        ))
+        .eval()?;
+
+      let hash: String = result.get("hash")?;
+      let outputs: LuaTable = result.get("outputs")?;
+      let out: String = outputs.get("out")?;
+      assert_eq!(out, format!("$${{{{build:{}:out}}}}", hash));
+
+      // Same hash as `sys store add` computes for the import
+      let expected = BuildDef::prebuilt("nvim", OUTPUT_HASH).compute_hash().unwrap();
+      assert_eq!(hash, expected.0);
+
+      let manifest = manifest.borrow();
+      let build_def = manifest.builds.get(&expected).unwrap();
+      assert_eq!(build_def.prebuilt.as_deref(), Some(OUTPUT_HASH));
+      assert!(build_def.create_actions.is_empty());
+
+      Ok(())
+    }
+
+    #[test]
+    fn prebuilt_requires_output_hash() -> LuaResult<()> {
+      let (lua, _) = create_test_lua_with_manifest()?;
+
+      let missing = lua.load(r#"sys.prebuilt({ id = "nvim" })"#).exec();
+      assert!(missing.unwrap_err().to_string().contains("requires the 'hash'"));
+
+      let short = lua.load(r#"sys.prebuilt({ id = "nvim", hash = "abc" })"#).exec();
+      assert!(short.unwrap_err().to_string().contains("64-character"));
+
+      Ok(())
+    }
+
+    const IMPURE_BUILD: &str = r#"
+      return sys.build({
+        id = "cc-build",
+        impure_env = { "SYSLUA_TEST_CC" },
+        create = function(inputs, ctx)
+          ctx:exec("$${{env:SYSLUA_TEST_CC}} -o " .. ctx.out .. "/hello hello.c")
+          return { out = ctx.out }
+        end,
+      }).hash
+    "#;
+
+    fn impure_build_hash(cc: Option<&str>) -> LuaResult<String> {
+      temp_env::with_var("SYSLUA_TEST_CC", cc, || {
+        let (lua, _) = create_test_lua_with_manifest()?;
+        lua.load(IMPURE_BUILD).eval()
+      })
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn impure_env_values_are_part_of_the_hash() -> LuaResult<()> {
+      let gcc = impure_build_hash(Some("gcc"))?;
+      assert_eq!(gcc, impure_build_hash(Some("gcc"))?);
+      assert_ne!(gcc, impure_build_hash(Some("clang"))?);
+      assert_ne!(gcc, impure_build_hash(None)?);
+
+      // Undeclared, the variable doesn't affect the hash
+      let undeclared = IMPURE_BUILD.replace(r#"impure_env = { "SYSLUA_TEST_CC" },"#, "");
+      let hashes = ["gcc", "clang"].map(|cc| {
+        temp_env::with_var("SYSLUA_TEST_CC", Some(cc), || -> LuaResult<String> {
+          let (lua, _) = create_test_lua_with_manifest()?;
+          lua.load(undeclared.as_str()).eval()
+        })
+      });
+      assert_eq!(hashes[0].as_ref().unwrap(), hashes[1].as_ref().unwrap());
+
+      Ok(())
+    }
+
+    #[test]
+    fn undeclared_env_lists_unlisted_references() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      lua
+        .load(
+          r#"
+            sys.build({
+              id = "env-build",
+              impure_env = { "CC" },
+              create = function(inputs, ctx)
+                ctx:exec("$${{env:CC}} -I$${{env:HOME}}/include main.c")
+                return { out = ctx.out, path = "$${{env:PATH}}" }
+              end,
+            })
+          "#,
+        )
+        .exec()?;
+
+      let manifest = manifest.borrow();
+      let build_def = manifest.builds.values().next().unwrap();
+      let undeclared: Vec<_> = build_def.undeclared_env().into_iter().collect();
+      assert_eq!(undeclared, ["HOME", "PATH"]);
+
+      let invalid = lua
+        .load(r#"sys.build({ id = "bad", impure_env = { "A=B" }, create = function(i, ctx) return { out = ctx.out } end })"#)
+        .exec();
+      assert!(invalid.unwrap_err().to_string().contains("invalid variable name 'A=B'"));
+
+      Ok(())
+    }
+
+    #[test]
+    fn build_for_other_platforms_is_skipped() -> LuaResult<()> {
+      let (lua, manifest) = create_test_lua_with_manifest()?;
+
+      let skipped: LuaValue = lua
+        .load(
+          r#"
+            sys.platform = "aarch64-darwin"
+            sys.build({
+              id = "native",
+              platforms = { "aarch64-darwin" },
+              create = function(inputs, ctx) return { out = ctx.out } end,
+            })
+            return sys.build({
+              id = "linux-tool",
+              platforms = { "x86_64-linux", "aarch64-linux" },
+              create = function(inputs, ctx) error("create must not run") end,
+            })
+          "#,
+        )
+        .eval()?;
+
+      assert!(skipped.is_nil());
+      let manifest = manifest.borrow();
+      assert_eq!(manifest.builds.len(), 1);
+      assert_eq!(
+        manifest.skipped_builds,
+        vec![SkippedBuild {
+          id: Some("linux-tool".to_string()),
+          reason: "platform (only x86_64-linux, aarch64-linux)".to_string(),
+        }]
+      );
+
+      Ok(())
+    }
+  }
+
+  mod sys_src {
+    use super::*;
+    use crate::lua::runtime::load_file;
+    use serial_test::serial;
+    use tempfile::TempDir;
+
+    #[test]
+    #[serial]
+    fn src_snapshots_dir_relative_to_calling_file() -> LuaResult<()> {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path().join("config");
+      std::fs::create_dir_all(config_dir.join("app")).unwrap();
+      std::fs::write(config_dir.join("app").join("main.lua"), "print('hi')").unwrap();
+      std::fs::write(config_dir.join("app").join("README.md"), "docs").unwrap();
+      std::fs::write(
+        config_dir.join("init.lua"),
+        r#"return sys.src({ path = "app", exclude = "*.md" })"#,
+      )
+      .unwrap();
+
+      temp_env::with_vars(
+        [
+          ("SYSLUA_ROOT", Some(temp.path().join("root").to_str().unwrap())),
+          ("SYSLUA_STORE", None),
+        ],
+        || -> LuaResult<()> {
+          let (lua, manifest) = create_test_lua_with_manifest()?;
+          let LuaValue::Table(result) = load_file(&lua, &config_dir.join("init.lua"))? else {
+            panic!("sys.src should return a BuildRef");
+          };
+          let hash = ObjectHash(result.get("hash")?);
+
+          let manifest = manifest.borrow();
+          let build_def = manifest.builds.get(&hash).unwrap();
+          assert_eq!(build_def.id.as_deref(), Some("app"));
+
+          let store_path = crate::build::store::build_dir_path(&hash);
+          assert!(store_path.join("main.lua").exists());
+          assert!(!store_path.join("README.md").exists());
+          Ok(())
+        },
+      )
+    }
+
+    #[test]
+    fn src_requires_existing_directory() -> LuaResult<()> {
+      let (lua, _) = create_test_lua_with_manifest()?;
+
+      let missing = lua.load(r#"sys.src({ id = "app" })"#).exec();
+      assert!(missing.unwrap_err().to_string().contains("requires a 'path'"));
+
+      let absent = lua.load(r#"sys.src({ path = "/nonexistent/syslua-src" })"#).exec();
+      assert!(absent.unwrap_err().to_string().contains("is not a directory"));
+
+      Ok(())
+    }
+  }
 }
@@ -13,12 +13,22 @@
 //! # Submodules
 //!
 //! - [`execute`] - Build execution engine
+//! - [`import`] - Importing directories into the store as prebuilt builds
 //! - [`lua`] - Lua context (`BuildCtx`) exposed to build scripts
+//! - [`overrides`] - Local overrides of builds declared by inputs (`sys.override_build`)
+//! - [`refs`] - Store path reference scanning for undeclared dependencies
+//! - [`src`] - Filtered snapshots of local source directories (`sys.src`)
 //! - [`store`] - Build artifact storage and retrieval
+//! - [`users`] - Build users the system daemon runs untrusted builds as
 
 pub mod execute;
+pub mod import;
 pub mod lua;
+pub mod overrides;
+pub mod refs;
+pub mod src;
 pub mod store;
 mod types;
+pub mod users;
 
 pub use types::*;
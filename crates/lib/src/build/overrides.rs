@@ -0,0 +1,293 @@
+//! Local overrides of builds defined elsewhere (`sys.override_build`).
+//!
+//! A config can patch a build an input defines without forking the input:
+//!
+//! ```lua
+//! sys.override_build("ripgrep", function(spec)
+//!   local create = spec.create
+//!   spec.create = function(inputs, ctx)
+//!     ctx:exec({ bin = "/bin/sh", args = { "-c", "echo patched" } })
+//!     return create(inputs, ctx)
+//!   end
+//!   return spec
+//! end)
+//! ```
+//!
+//! When `sys.build` is called with an id that has overrides, each override
+//! receives a copy of the spec and returns the spec to use instead (or `nil`
+//! to keep the copy it changed), in the order they were registered. The
+//! result is hashed like any other build, so overriding a build changes its
+//! hash. Overrides must be registered before the build is declared, usually
+//! at the top of the config; the build records where its overrides came from
+//! in [`Manifest::overridden`].
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use mlua::prelude::*;
+
+use crate::lua::runtime::caller_location;
+use crate::manifest::{Manifest, OverriddenBuild};
+use crate::util::hash::ObjectHash;
+
+/// Lua registry key of the list of registered overrides.
+pub const BUILD_OVERRIDES_REGISTRY_KEY: &str = "__syslua_build_overrides";
+
+/// Register the `sys.override_build` function on the sys table.
+pub fn register_sys_override_build(lua: &Lua, sys_table: &LuaTable, manifest: Rc<RefCell<Manifest>>) -> LuaResult<()> {
+  lua.set_named_registry_value(BUILD_OVERRIDES_REGISTRY_KEY, lua.create_table()?)?;
+
+  let override_fn = lua.create_function(move |lua, (id, func): (String, LuaFunction)| {
+    let location = caller_location(lua).unwrap_or_else(|| "<unknown>".to_string());
+    if manifest
+      .borrow()
+      .builds
+      .values()
+      .any(|def| def.id.as_ref() == Some(&id))
+    {
+      return Err(LuaError::external(format!(
+        "sys.override_build('{}') at {}: the build is already declared; \
+         override it before the input that declares it is set up (e.g. at the top of the config)",
+        id, location
+      )));
+    }
+
+    let entry = lua.create_table()?;
+    entry.set("id", id)?;
+    entry.set("fn", func)?;
+    entry.set("location", location)?;
+    entry.set("used", false)?;
+    let overrides: LuaTable = lua.named_registry_value(BUILD_OVERRIDES_REGISTRY_KEY)?;
+    overrides.push(entry)
+  })?;
+
+  sys_table.set("override_build", override_fn)?;
+  Ok(())
+}
+
+/// Run the overrides registered for the id of `spec` on it.
+///
+/// Returns the spec to build and the locations of the overrides that ran,
+/// which is empty (and the spec unchanged) for builds without overrides.
+pub fn apply_build_overrides(lua: &Lua, spec: LuaTable) -> LuaResult<(LuaTable, Vec<String>)> {
+  let Some(id) = spec.get::<Option<String>>("id")? else {
+    return Ok((spec, Vec::new()));
+  };
+  let Some(overrides) = lua.named_registry_value::<Option<LuaTable>>(BUILD_OVERRIDES_REGISTRY_KEY)? else {
+    return Ok((spec, Vec::new()));
+  };
+
+  let mut spec = spec;
+  let mut applied = Vec::new();
+  for entry in overrides.sequence_values::<LuaTable>() {
+    let entry = entry?;
+    if entry.get::<String>("id")? != id {
+      continue;
+    }
+    let func: LuaFunction = entry.get("fn")?;
+    let location: String = entry.get("location")?;
+    let failed =
+      |message: String| LuaError::external(format!("sys.override_build('{}') at {}: {}", id, location, message));
+
+    let copy = shallow_copy(lua, &spec)?;
+    spec = match func.call::<LuaValue>(copy.clone()).map_err(|e| failed(e.to_string()))? {
+      LuaValue::Nil => copy,
+      LuaValue::Table(replaced) => replaced,
+      other => {
+        return Err(failed(format!(
+          "must return a build spec table or nil, got {}",
+          other.type_name()
+        )));
+      }
+    };
+    if spec.get::<Option<String>>("id")?.as_deref() != Some(id.as_str()) {
+      return Err(failed("must keep the build's id".to_string()));
+    }
+
+    entry.set("used", true)?;
+    applied.push(location);
+  }
+  Ok((spec, applied))
+}
+
+/// Record that the build at `hash` was changed by the overrides at `locations`.
+pub fn record_build_overrides(manifest: &mut Manifest, id: &str, hash: &ObjectHash, locations: Vec<String>) {
+  if locations.is_empty() || manifest.overridden.iter().any(|o| &o.hash == hash) {
+    return;
+  }
+  manifest.overridden.push(OverriddenBuild {
+    id: id.to_string(),
+    hash: hash.clone(),
+    overrides: locations,
+  });
+}
+
+/// Check the overrides once evaluation finished.
+///
+/// Fails for the first override whose id no build was declared with, naming
+/// the closest declared id. Drops provenance of builds that a later build
+/// with the same id replaced.
+pub fn finish_build_overrides(lua: &Lua, manifest: &mut Manifest) -> LuaResult<()> {
+  let builds = &manifest.builds;
+  manifest.overridden.retain(|o| builds.contains_key(&o.hash));
+
+  let Some(overrides) = lua.named_registry_value::<Option<LuaTable>>(BUILD_OVERRIDES_REGISTRY_KEY)? else {
+    return Ok(());
+  };
+  for entry in overrides.sequence_values::<LuaTable>() {
+    let entry = entry?;
+    if entry.get::<bool>("used")? {
+      continue;
+    }
+    let id: String = entry.get("id")?;
+    let location: String = entry.get("location")?;
+    let mut message = format!(
+      "sys.override_build('{}') at {}: no sys.build declared a build with this id",
+      id, location
+    );
+    if let Some(similar) = closest_id(&id, manifest.builds.values().filter_map(|def| def.id.as_deref())) {
+      message.push_str(&format!(" (did you mean '{}'?)", similar));
+    }
+    return Err(LuaError::external(message));
+  }
+  Ok(())
+}
+
+fn shallow_copy(lua: &Lua, table: &LuaTable) -> LuaResult<LuaTable> {
+  let copy = lua.create_table()?;
+  for pair in table.pairs::<LuaValue, LuaValue>() {
+    let (key, value) = pair?;
+    copy.set(key, value)?;
+  }
+  Ok(copy)
+}
+
+/// The id among `ids` closest to `id`, if one is within a few edits of it.
+fn closest_id<'a>(id: &str, ids: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+  let limit = (id.chars().count() / 3).max(1);
+  ids
+    .map(|candidate| (edit_distance(id, candidate), candidate))
+    .filter(|(distance, _)| *distance <= limit)
+    .min()
+    .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`, by character.
+fn edit_distance(a: &str, b: &str) -> usize {
+  let b: Vec<char> = b.chars().collect();
+  let mut previous: Vec<usize> = (0..=b.len()).collect();
+  for (i, ca) in a.chars().enumerate() {
+    let mut current = vec![i + 1];
+    for (j, cb) in b.iter().enumerate() {
+      let substitution = previous[j] + usize::from(ca != *cb);
+      current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+    }
+    previous = current;
+  }
+  previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::lua::globals::register_globals;
+
+  fn setup() -> LuaResult<(Lua, Rc<RefCell<Manifest>>)> {
+    let lua = crate::lua::runtime::create_lua(false)?;
+    let manifest = Rc::new(RefCell::new(Manifest::default()));
+    register_globals(&lua, manifest.clone())?;
+    Ok((lua, manifest))
+  }
+
+  const BUILD: &str = r#"
+    return sys.build({
+      id = "tool",
+      inputs = { version = "1.0" },
+      create = function(inputs, ctx)
+        return { out = ctx.out }
+      end,
+    })
+  "#;
+
+  #[test]
+  fn override_changes_the_build_and_records_where_it_came_from() -> LuaResult<()> {
+    let original_hash: String = {
+      let (lua, _manifest) = setup()?;
+      lua.load(BUILD).eval::<LuaTable>()?.get("hash")?
+    };
+
+    let (lua, manifest) = setup()?;
+    lua
+      .load(
+        r#"
+        sys.override_build("tool", function(spec)
+          spec.inputs = { version = "2.0" }
+        end)
+        sys.override_build("tool", function(spec)
+          local create = spec.create
+          return {
+            id = spec.id,
+            inputs = spec.inputs,
+            create = function(inputs, ctx)
+              ctx:exec("patch -p1 < fix.patch")
+              return create(inputs, ctx)
+            end,
+          }
+        end)
+        "#,
+      )
+      .exec()?;
+    let overridden: LuaTable = lua.load(BUILD).eval()?;
+    let overridden_hash: String = overridden.get("hash")?;
+    assert_ne!(overridden_hash, original_hash);
+
+    let mut manifest = manifest.borrow_mut();
+    finish_build_overrides(&lua, &mut manifest)?;
+    let (_, def) = manifest.builds.iter().next().unwrap();
+    assert_eq!(def.create_actions.len(), 1);
+    assert_eq!(manifest.overridden.len(), 1);
+    assert_eq!(manifest.overridden[0].id, "tool");
+    assert_eq!(manifest.overridden[0].overrides.len(), 2);
+    Ok(())
+  }
+
+  #[test]
+  fn override_must_keep_the_id() -> LuaResult<()> {
+    let (lua, _manifest) = setup()?;
+    lua
+      .load(r#"sys.override_build("tool", function(spec) spec.id = "other" end)"#)
+      .exec()?;
+    let err = lua.load(BUILD).exec().unwrap_err();
+    assert!(err.to_string().contains("must keep the build's id"), "{}", err);
+    Ok(())
+  }
+
+  #[test]
+  fn unused_override_names_the_closest_id() -> LuaResult<()> {
+    let (lua, manifest) = setup()?;
+    lua.load(r#"sys.override_build("tools", function(spec) end)"#).exec()?;
+    lua.load(BUILD).exec()?;
+
+    let err = finish_build_overrides(&lua, &mut manifest.borrow_mut()).unwrap_err();
+    let message = err.to_string();
+    assert!(
+      message.contains("no sys.build declared a build with this id"),
+      "{}",
+      message
+    );
+    assert!(message.contains("did you mean 'tool'?"), "{}", message);
+    Ok(())
+  }
+
+  #[test]
+  fn override_after_the_build_fails() -> LuaResult<()> {
+    let (lua, _manifest) = setup()?;
+    lua.load(BUILD).exec()?;
+    let err = lua
+      .load(r#"sys.override_build("tool", function(spec) end)"#)
+      .exec()
+      .unwrap_err();
+    assert!(err.to_string().contains("already declared"), "{}", err);
+    Ok(())
+  }
+}
@@ -0,0 +1,278 @@
+//! Store path reference scanning.
+//!
+//! Builds can embed the store paths of other builds in their outputs (shebangs,
+//! wrapper scripts, symlinks, rpaths) without declaring them as inputs. After a
+//! build completes its outputs are scanned for `build/<triple>/<hash>` path
+//! segments (or legacy `build/<hash>` ones), and the hashes of builds that exist
+//! in the store are recorded in the completion marker. GC keeps the referenced builds alive through [`reference_closure`].
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use walkdir::WalkDir;
+
+use crate::build::execute::read_build_marker;
+use crate::build::store::build_exists_in_store;
+use crate::consts::{OBJ_HASH_MAX_LEN, OBJ_HASH_PREFIX_LEN};
+use crate::platform::paths::parent_store_dir;
+use crate::util::hash::ObjectHash;
+
+/// Directory name of builds inside a store.
+const BUILD_SEGMENT: &[u8] = b"build";
+
+/// Longest platform triple segment recognized between `build` and the hash.
+const TRIPLE_MAX_LEN: usize = 32;
+
+/// Bytes kept between read chunks so a reference split across them is found.
+const OVERLAP: usize = BUILD_SEGMENT.len() + 1 + TRIPLE_MAX_LEN + 1 + OBJ_HASH_MAX_LEN;
+
+/// Find the builds referenced by the files under `store_path`.
+///
+/// `store_path` is a build directory (`<store>/build/<triple>/<hash>`). A
+/// reference is a `build/<triple>/<hash>` or `build/<hash>` segment (either
+/// separator) naming another build that exists next to it, in the legacy
+/// layout of its store, or in the parent store. Symlink targets are scanned as text and
+/// never followed. The build's own hash is not included.
+pub fn scan_references(store_path: &Path, exclude: &[&str]) -> io::Result<Vec<ObjectHash>> {
+  let own = store_path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+  let mut candidates = BTreeSet::new();
+
+  let walker = WalkDir::new(store_path)
+    .follow_links(false)
+    .into_iter()
+    .filter_entry(|e| e.depth() != 1 || !exclude.iter().any(|name| e.file_name() == *name));
+  for entry in walker {
+    let entry = entry.map_err(io::Error::other)?;
+    let file_type = entry.file_type();
+    if file_type.is_symlink() {
+      let target = std::fs::read_link(entry.path())?;
+      find_hashes(target.to_string_lossy().as_bytes(), &mut candidates);
+    } else if file_type.is_file() {
+      scan_file(entry.path(), &mut candidates)?;
+    }
+  }
+
+  let build_dir = store_path.parent();
+  let legacy_dir = build_dir.and_then(Path::parent);
+  let parent_store = parent_store_dir();
+  Ok(
+    candidates
+      .into_iter()
+      .filter(|hash| hash != own)
+      .map(ObjectHash)
+      .filter(|hash| {
+        build_dir.is_some_and(|dir| dir.join(&hash.0).is_dir())
+          || legacy_dir.is_some_and(|dir| dir.join(&hash.0).is_dir())
+          || parent_store
+            .as_ref()
+            .is_some_and(|store| build_exists_in_store(hash, store))
+      })
+      .collect(),
+  )
+}
+
+/// Builds reachable from `roots` through recorded references, including the roots.
+///
+/// `build_dir` is the directory of the platform's builds (see
+/// [`builds_dir`](crate::build::store::builds_dir)). Hashes without a build
+/// directory (e.g. bind hashes) are kept but not followed.
+pub fn reference_closure(build_dir: &Path, roots: impl IntoIterator<Item = String>) -> HashSet<String> {
+  let mut closure = HashSet::new();
+  let mut pending: Vec<String> = roots.into_iter().collect();
+
+  while let Some(hash) = pending.pop() {
+    if !closure.insert(hash.clone()) {
+      continue;
+    }
+    if let Ok(Some(marker)) = read_build_marker(&build_dir.join(&hash)) {
+      pending.extend(marker.references.into_iter().filter(|r| !closure.contains(r)));
+    }
+  }
+
+  closure
+}
+
+/// Scan a file in chunks, carrying over enough bytes to catch split references.
+fn scan_file(path: &Path, found: &mut BTreeSet<String>) -> io::Result<()> {
+  let mut file = File::open(path)?;
+  let mut buf = vec![0u8; 64 * 1024];
+  let mut carry = 0;
+
+  loop {
+    let read = file.read(&mut buf[carry..])?;
+    if read == 0 {
+      break;
+    }
+    let len = carry + read;
+    find_hashes(&buf[..len], found);
+
+    carry = OVERLAP.min(len);
+    buf.copy_within(len - carry..len, 0);
+  }
+
+  Ok(())
+}
+
+/// Collect every hash that follows a `build/` or `build\` segment in `bytes`,
+/// possibly with a platform triple segment in between.
+///
+/// Hashes of any configured length are accepted.
+fn find_hashes(bytes: &[u8], found: &mut BTreeSet<String>) {
+  let is_hash_char = |b: &u8| b.is_ascii_digit() || (b'a'..=b'f').contains(b);
+
+  let mut start = 0;
+  while let Some(pos) = bytes[start..]
+    .windows(BUILD_SEGMENT.len())
+    .position(|w| w == BUILD_SEGMENT)
+  {
+    let segment_end = start + pos + BUILD_SEGMENT.len();
+    start = segment_end;
+
+    let Some(rest) = bytes.get(segment_end..) else {
+      break;
+    };
+    let Some(b'/' | b'\\') = rest.first() else {
+      continue;
+    };
+    let rest = match triple_len(&rest[1..]) {
+      Some(len) => &rest[len + 1..],
+      None => rest,
+    };
+    let hash_len = rest[1..].iter().take_while(|b| is_hash_char(b)).count();
+    let hash = &rest[1..=hash_len];
+    let terminated = rest.get(hash_len + 1).is_none_or(|b| !b.is_ascii_alphanumeric());
+    if (OBJ_HASH_PREFIX_LEN..=OBJ_HASH_MAX_LEN).contains(&hash_len) && terminated {
+      found.insert(String::from_utf8_lossy(hash).into_owned());
+    }
+  }
+}
+
+/// Length of a platform triple segment (e.g. `x86_64-linux`) followed by a
+/// separator at the start of `bytes`. Hashes never contain a `-`.
+fn triple_len(bytes: &[u8]) -> Option<usize> {
+  let len = bytes
+    .iter()
+    .take(TRIPLE_MAX_LEN + 1)
+    .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_' || **b == b'-')
+    .count();
+  let segment = &bytes[..len];
+  let separated = matches!(bytes.get(len), Some(b'/' | b'\\'));
+  (len <= TRIPLE_MAX_LEN && separated && segment.contains(&b'-')).then_some(len)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  const DEP: &str = "0123456789abcdef0123";
+  const OWN: &str = "fedcba9876543210fedc";
+
+  fn store_with_dep(temp: &TempDir) -> std::path::PathBuf {
+    let build_dir = temp.path().join("build");
+    std::fs::create_dir_all(build_dir.join(DEP)).unwrap();
+    let own = build_dir.join(OWN);
+    std::fs::create_dir_all(&own).unwrap();
+    own
+  }
+
+  #[test]
+  fn finds_references_to_existing_builds() {
+    let temp = TempDir::new().unwrap();
+    let own = store_with_dep(&temp);
+    let dep_path = temp.path().join("build").join(DEP);
+
+    std::fs::write(
+      own.join("wrapper.sh"),
+      format!(
+        "#!/bin/sh\nexec {}/bin/tool \"$@\"\n# self: {}\n# gone: build/aaaaaaaaaaaaaaaaaaaa\n",
+        dep_path.display(),
+        own.display()
+      ),
+    )
+    .unwrap();
+
+    let refs = scan_references(&own, &[]).unwrap();
+    assert_eq!(refs, vec![ObjectHash(DEP.to_string())]);
+  }
+
+  #[test]
+  fn finds_references_split_across_chunks() {
+    let temp = TempDir::new().unwrap();
+    let own = store_with_dep(&temp);
+
+    let mut contents = vec![b'x'; 64 * 1024 - 10];
+    contents.extend_from_slice(format!("/store/build/{}/lib", DEP).as_bytes());
+    std::fs::write(own.join("blob"), contents).unwrap();
+
+    let refs = scan_references(&own, &[]).unwrap();
+    assert_eq!(refs, vec![ObjectHash(DEP.to_string())]);
+  }
+
+  #[test]
+  fn finds_references_in_platform_directories() {
+    let temp = TempDir::new().unwrap();
+    let build_dir = temp.path().join("build").join("x86_64-linux");
+    std::fs::create_dir_all(build_dir.join(DEP)).unwrap();
+    let own = build_dir.join(OWN);
+    std::fs::create_dir_all(&own).unwrap();
+
+    std::fs::write(
+      own.join("paths"),
+      format!("/store/build/x86_64-linux/{}/bin\nbuild\\x86_64-linux\\{}\n", DEP, DEP),
+    )
+    .unwrap();
+
+    let refs = scan_references(&own, &[]).unwrap();
+    assert_eq!(refs, vec![ObjectHash(DEP.to_string())]);
+  }
+
+  #[test]
+  fn finds_references_with_longer_hashes() {
+    let temp = TempDir::new().unwrap();
+    let own = store_with_dep(&temp);
+    let long = format!("{}{}", DEP, "456789abcdef");
+    std::fs::create_dir_all(temp.path().join("build").join(&long)).unwrap();
+
+    std::fs::write(own.join("paths"), format!("build/{}/bin\nbuild/{}x\n", long, DEP)).unwrap();
+
+    let refs = scan_references(&own, &[]).unwrap();
+    assert_eq!(refs, vec![ObjectHash(long)]);
+  }
+
+  #[test]
+  #[cfg(unix)]
+  fn scans_symlink_targets_and_skips_excluded() {
+    let temp = TempDir::new().unwrap();
+    let own = store_with_dep(&temp);
+    let dep_path = temp.path().join("build").join(DEP);
+
+    std::fs::create_dir(own.join("tmp")).unwrap();
+    std::fs::write(own.join("tmp").join("log"), format!("build/{}", DEP)).unwrap();
+    assert!(scan_references(&own, &["tmp"]).unwrap().is_empty());
+
+    std::os::unix::fs::symlink(&dep_path, own.join("link")).unwrap();
+    let refs = scan_references(&own, &["tmp"]).unwrap();
+    assert_eq!(refs, vec![ObjectHash(DEP.to_string())]);
+  }
+
+  #[test]
+  fn closure_follows_recorded_references() {
+    let temp = TempDir::new().unwrap();
+    let build_dir = temp.path().join("build");
+    for (hash, refs) in [("a", vec!["b"]), ("b", vec!["c"]), ("c", vec![]), ("d", vec![])] {
+      std::fs::create_dir_all(build_dir.join(hash)).unwrap();
+      std::fs::write(
+        build_dir.join(hash).join(crate::build::execute::BUILD_COMPLETE_MARKER),
+        serde_json::json!({ "version": 1, "status": "complete", "references": refs }).to_string(),
+      )
+      .unwrap();
+    }
+
+    let closure = reference_closure(&build_dir, ["a".to_string(), "bind".to_string()]);
+    let expected: HashSet<String> = ["a", "b", "c", "bind"].into_iter().map(String::from).collect();
+    assert_eq!(closure, expected);
+  }
+}
@@ -0,0 +1,351 @@
+//! Local source directories snapshotted into the store.
+//!
+//! A `path:` input is whatever is on disk when the config is evaluated, so a
+//! build reading a local checkout through it isn't reproducible. `sys.src`
+//! copies a filtered snapshot of the directory into the store instead:
+//!
+//! ```lua
+//! local src = sys.src({ path = "./app", exclude = { "docs/**" } })
+//! sys.build({
+//!   id = "app",
+//!   inputs = { src = src },
+//!   create = function(inputs, ctx)
+//!     ctx:exec({ bin = "make", args = { "-C", inputs.src.outputs.out, "install" } })
+//!   end,
+//! })
+//! ```
+//!
+//! Files ignored by the `.gitignore` files of the directory are left out, as
+//! is `.git`. `exclude` drops more paths; with `include`, only files matching
+//! one of its patterns are kept. Patterns are globs relative to the directory.
+//!
+//! The snapshot is stored as a prebuilt build (see [`super::import`]) whose
+//! hash covers only its id and the hash of the copied files: editing an
+//! ignored file rebuilds nothing, and an unchanged source keeps its store path.
+//! The copy is made during evaluation, without taking the store lock; the
+//! apply evaluating the config holds it.
+
+use std::io;
+use std::path::Path;
+
+use crate::build::execute::BUILD_HASH_EXCLUSIONS;
+use crate::platform::paths::store_dir;
+use crate::util::fs::copy_file;
+use crate::util::glob::glob_match;
+use crate::util::hash::hash_directory;
+
+use super::import::{ImportError, ImportedBuild, store_prebuilt};
+
+const GITIGNORE: &str = ".gitignore";
+
+/// Which files of a source directory are snapshotted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFilter {
+  /// Keep only files matching one of these globs; all files when empty.
+  pub include: Vec<String>,
+  /// Leave out files and directories matching one of these globs.
+  pub exclude: Vec<String>,
+  /// Honor `.gitignore` files.
+  pub gitignore: bool,
+}
+
+impl Default for SourceFilter {
+  fn default() -> Self {
+    Self {
+      include: Vec::new(),
+      exclude: Vec::new(),
+      gitignore: true,
+    }
+  }
+}
+
+/// One rule of a `.gitignore` file, as a glob relative to the source root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IgnoreRule {
+  pattern: String,
+  negated: bool,
+  dir_only: bool,
+}
+
+/// Parse a `.gitignore` file found in the directory `base` (relative to the
+/// source root, empty for the root itself).
+fn parse_gitignore(content: &str, base: &str) -> Vec<IgnoreRule> {
+  let prefix = if base.is_empty() {
+    String::new()
+  } else {
+    format!("{base}/")
+  };
+
+  content
+    .lines()
+    .filter_map(|line| {
+      let line = line.trim_end();
+      if line.is_empty() || line.starts_with('#') {
+        return None;
+      }
+      let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+      };
+      let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+      };
+      if line.is_empty() {
+        return None;
+      }
+
+      // A slash anywhere but at the end anchors the pattern to its directory
+      let pattern = if line.contains('/') {
+        format!("{prefix}{}", line.trim_start_matches('/'))
+      } else {
+        format!("{prefix}**/{line}")
+      };
+      Some(IgnoreRule {
+        pattern,
+        negated,
+        dir_only,
+      })
+    })
+    .collect()
+}
+
+/// Whether the last rule matching `rel` ignores it.
+fn is_ignored(rules: &[IgnoreRule], rel: &str, is_dir: bool) -> bool {
+  rules
+    .iter()
+    .filter(|rule| is_dir || !rule.dir_only)
+    .fold(false, |ignored, rule| {
+      if glob_match(&rule.pattern, rel) {
+        !rule.negated
+      } else {
+        ignored
+      }
+    })
+}
+
+/// Paths of the files and symlinks under `root` that pass `filter`, relative
+/// to `root` with `/` separators and in a stable order.
+pub fn source_files(root: &Path, filter: &SourceFilter) -> io::Result<Vec<String>> {
+  let mut files = Vec::new();
+  collect(root, "", filter, &mut Vec::new(), &mut files)?;
+  Ok(files)
+}
+
+fn collect(
+  root: &Path,
+  rel: &str,
+  filter: &SourceFilter,
+  rules: &mut Vec<IgnoreRule>,
+  files: &mut Vec<String>,
+) -> io::Result<()> {
+  let dir = root.join(rel);
+  let inherited = rules.len();
+  if filter.gitignore {
+    match std::fs::read_to_string(dir.join(GITIGNORE)) {
+      Ok(content) => rules.extend(parse_gitignore(&content, rel)),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+      Err(e) => return Err(e),
+    }
+  }
+
+  let mut entries = std::fs::read_dir(&dir)?.collect::<io::Result<Vec<_>>>()?;
+  entries.sort_by_key(|entry| entry.file_name());
+
+  for entry in entries {
+    let name = entry.file_name();
+    let Some(name) = name.to_str() else {
+      return Err(io::Error::other(format!(
+        "'{}' is not valid UTF-8",
+        entry.path().display()
+      )));
+    };
+    if name == ".git" {
+      continue;
+    }
+    let child = if rel.is_empty() {
+      name.to_string()
+    } else {
+      format!("{rel}/{name}")
+    };
+    let is_dir = entry.file_type()?.is_dir();
+
+    if (filter.gitignore && is_ignored(rules, &child, is_dir))
+      || filter.exclude.iter().any(|pattern| glob_match(pattern, &child))
+    {
+      continue;
+    }
+    if is_dir {
+      collect(root, &child, filter, rules, files)?;
+    } else if filter.include.is_empty() || filter.include.iter().any(|pattern| glob_match(pattern, &child)) {
+      files.push(child);
+    }
+  }
+
+  rules.truncate(inherited);
+  Ok(())
+}
+
+/// Snapshot the files of `source` passing `filter` into the store as the
+/// prebuilt build `id`.
+///
+/// Like [`super::import::import_dir`], the build is stored under the default
+/// hash spec. Symlinks are copied as symlinks on Unix and followed elsewhere.
+pub fn snapshot_dir(source: &Path, id: &str, filter: &SourceFilter) -> Result<ImportedBuild, ImportError> {
+  let source = source
+    .canonicalize()
+    .ok()
+    .filter(|path| path.is_dir())
+    .ok_or_else(|| ImportError::NotADirectory(source.to_path_buf()))?;
+  let files = source_files(&source, filter).map_err(|e| ImportError::Read {
+    path: source.clone(),
+    source: e,
+  })?;
+
+  // Stage the copy in the store, so it is moved into place rather than copied twice
+  let store = store_dir();
+  let staging = std::fs::create_dir_all(&store)
+    .and_then(|_| tempfile::Builder::new().prefix(".src-").tempdir_in(&store))
+    .map_err(|e| ImportError::Copy {
+      path: store.clone(),
+      source: e,
+    })?;
+  for rel in &files {
+    copy_entry(&source.join(rel), &staging.path().join(rel)).map_err(|e| ImportError::Copy {
+      path: staging.path().join(rel),
+      source: e,
+    })?;
+  }
+
+  let output_hash = hash_directory(staging.path(), BUILD_HASH_EXCLUSIONS).map_err(|e| ImportError::HashSource {
+    path: source.clone(),
+    source: e,
+  })?;
+  store_prebuilt(id, output_hash.0, |store_path| {
+    std::fs::rename(staging.path(), store_path)
+  })
+}
+
+/// Copy the file or symlink `from` to `to`, creating its parent directories.
+fn copy_entry(from: &Path, to: &Path) -> io::Result<()> {
+  if let Some(parent) = to.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  #[cfg(unix)]
+  if std::fs::symlink_metadata(from)?.file_type().is_symlink() {
+    return std::os::unix::fs::symlink(std::fs::read_link(from)?, to);
+  }
+  copy_file(from, to).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::build::BuildDef;
+  use crate::build::execute::is_build_complete;
+  use crate::util::hash::Hashable;
+  use serial_test::serial;
+  use tempfile::TempDir;
+
+  fn write(root: &Path, rel: &str, content: &str) {
+    let path = root.join(rel);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+  }
+
+  #[test]
+  fn gitignore_rules_are_relative_to_their_directory() {
+    let rules = parse_gitignore("# build output\ntarget/\n*.log\n!keep.log\n/local.lua\n", "sub");
+    assert!(is_ignored(&rules, "sub/target", true));
+    assert!(!is_ignored(&rules, "sub/target", false));
+    assert!(is_ignored(&rules, "sub/a/b/debug.log", false));
+    assert!(!is_ignored(&rules, "sub/a/keep.log", false));
+    assert!(is_ignored(&rules, "sub/local.lua", false));
+    assert!(!is_ignored(&rules, "sub/a/local.lua", false));
+    assert!(!is_ignored(&rules, "other/debug.log", false));
+  }
+
+  #[test]
+  fn source_files_honor_gitignore_and_filters() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    write(root, ".gitignore", "target/\n*.tmp\n");
+    write(root, "src/main.rs", "");
+    write(root, "src/scratch.tmp", "");
+    write(root, "src/.gitignore", "generated.rs\n");
+    write(root, "src/generated.rs", "");
+    write(root, "target/debug/app", "");
+    write(root, "docs/index.md", "");
+    write(root, ".git/HEAD", "");
+
+    let all = source_files(root, &SourceFilter::default()).unwrap();
+    assert_eq!(
+      all,
+      vec![".gitignore", "docs/index.md", "src/.gitignore", "src/main.rs"]
+    );
+
+    let filtered = SourceFilter {
+      include: vec!["**/*.rs".to_string(), "**/*.md".to_string()],
+      exclude: vec!["docs".to_string()],
+      gitignore: true,
+    };
+    assert_eq!(source_files(root, &filtered).unwrap(), vec!["src/main.rs"]);
+
+    let unfiltered = SourceFilter {
+      gitignore: false,
+      ..SourceFilter::default()
+    };
+    assert!(
+      source_files(root, &unfiltered)
+        .unwrap()
+        .contains(&"target/debug/app".to_string())
+    );
+  }
+
+  #[test]
+  #[serial]
+  fn snapshot_is_keyed_by_filtered_content() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("app");
+    write(&source, ".gitignore", "*.log\n");
+    write(&source, "main.lua", "print('hi')");
+
+    temp_env::with_vars(
+      [
+        ("SYSLUA_ROOT", Some(temp.path().join("root").to_str().unwrap())),
+        ("SYSLUA_STORE", None),
+      ],
+      || {
+        let first = snapshot_dir(&source, "app", &SourceFilter::default()).unwrap();
+        assert!(!first.already_present);
+        assert!(is_build_complete(&first.store_path));
+        assert_eq!(
+          first.hash,
+          BuildDef::prebuilt("app", &first.output_hash).compute_hash().unwrap()
+        );
+        assert_eq!(
+          std::fs::read_to_string(first.store_path.join("main.lua")).unwrap(),
+          "print('hi')"
+        );
+
+        // Ignored files don't change the snapshot
+        write(&source, "debug.log", "noise");
+        let again = snapshot_dir(&source, "app", &SourceFilter::default()).unwrap();
+        assert!(again.already_present);
+        assert_eq!(again.hash, first.hash);
+
+        write(&source, "main.lua", "print('bye')");
+        let changed = snapshot_dir(&source, "app", &SourceFilter::default()).unwrap();
+        assert_ne!(changed.hash, first.hash);
+
+        // No staging directories are left behind
+        let leftovers = std::fs::read_dir(store_dir())
+          .unwrap()
+          .flatten()
+          .filter(|entry| entry.file_name().to_string_lossy().starts_with(".src-"))
+          .count();
+        assert_eq!(leftovers, 0);
+      },
+    );
+  }
+}
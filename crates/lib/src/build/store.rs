@@ -1,22 +1,61 @@
 //! Build artifact storage.
 //!
-//! Provides path resolution for build outputs in the store (`<store>/build/<hash>/`).
+//! Provides path resolution for build outputs in the store
+//! (`<store>/build/<triple>/<hash>/`). Builds are partitioned by platform
+//! triple, so a store shared between machines (e.g. a home directory mounted
+//! on Intel and ARM Macs) never hands one platform another's outputs.
+//!
+//! Stores from before the partitioning keep builds in `<store>/build/<hash>`.
+//! Nothing records which platform built them, so lookups treat them as cache
+//! misses. `sys store migrate --triple <triple>` moves them under the triple
+//! the store was built on ([`migrate_legacy_builds`]), leaving a symlink in
+//! the old place so store paths embedded in other outputs keep resolving.
 
+use std::io;
 use std::path::{Path, PathBuf};
 
-use tracing::warn;
+use tracing::{debug, warn};
 
+use crate::consts::{OBJ_HASH_MAX_LEN, OBJ_HASH_PREFIX_LEN};
+use crate::platform::immutable::{make_immutable, make_mutable};
 use crate::platform::link::link_dir;
 use crate::platform::paths::{parent_store_dir, store_dir};
+use crate::platform::platform_triple;
 use crate::util::hash::ObjectHash;
 
 pub fn build_dir_name(hash: &ObjectHash) -> String {
   hash.0.clone()
 }
 
+/// Triple the current platform's builds are stored under (e.g.
+/// "aarch64-darwin"), or "unknown" on unsupported platforms.
+pub fn store_triple() -> String {
+  platform_triple().unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Directory of the builds of `triple` in `store` (`<store>/build/<triple>`).
+pub fn platform_build_dir(store: &Path, triple: &str) -> PathBuf {
+  store.join("build").join(triple)
+}
+
+/// Directory of the current platform's builds in `store`.
+pub fn builds_dir(store: &Path) -> PathBuf {
+  platform_build_dir(store, &store_triple())
+}
+
+/// Store path of a build of the current platform.
 pub fn build_dir_path(hash: &ObjectHash) -> PathBuf {
+  build_dir_path_for(hash, &store_triple())
+}
+
+/// Store path of a build of `triple`, linking it from the parent store when
+/// it's found there.
+///
+/// Builds in the legacy layout aren't used: their platform is unknown.
+pub fn build_dir_path_for(hash: &ObjectHash, triple: &str) -> PathBuf {
+  let store = store_dir();
   let dir_name = build_dir_name(hash);
-  let primary = store_dir().join("build").join(&dir_name);
+  let primary = platform_build_dir(&store, triple).join(&dir_name);
 
   // If exists in primary store, use it
   if primary.exists() {
@@ -25,8 +64,8 @@ pub fn build_dir_path(hash: &ObjectHash) -> PathBuf {
 
   // Check parent store for fallback
   if let Some(parent) = parent_store_dir() {
-    let fallback = parent.join("build").join(&dir_name);
-    if fallback.exists() {
+    let fallback = platform_build_dir(&parent, triple).join(&dir_name);
+    if fallback.is_dir() {
       // Create symlink in primary store pointing to parent
       if let Err(e) = link_dir(&fallback, &primary) {
         warn!(hash = %hash.0, error = %e, "Failed to link from parent store, using direct path");
@@ -36,14 +75,122 @@ pub fn build_dir_path(hash: &ObjectHash) -> PathBuf {
     }
   }
 
+  // A build stored under a shorter hash of the same definition, from before
+  // the hash length was raised
+  for short in hash.shorter_forms().map(|short| build_dir_name(&short)) {
+    let path = platform_build_dir(&store, triple).join(&short);
+    if path.exists() {
+      return path;
+    }
+  }
+
   // Return primary path even if doesn't exist (for new builds)
   primary
 }
 
+/// Whether the current platform has the build in `store_path`.
 pub fn build_exists_in_store(hash: &ObjectHash, store_path: &Path) -> bool {
-  let dir_name = build_dir_name(hash);
-  let build_path = store_path.join("build").join(dir_name);
-  build_path.exists()
+  let builds = builds_dir(store_path);
+  std::iter::once(hash.clone())
+    .chain(hash.shorter_forms())
+    .map(|candidate| build_dir_name(&candidate))
+    .any(|name| builds.join(&name).exists())
+}
+
+/// Build directories of `store` still in the legacy layout.
+pub fn legacy_builds(store: &Path) -> io::Result<Vec<PathBuf>> {
+  let dir = store.join("build");
+  if !dir.exists() {
+    return Ok(Vec::new());
+  }
+  let mut builds = Vec::new();
+  for entry in std::fs::read_dir(&dir)? {
+    let path = entry?.path();
+    if is_legacy_build(&path) {
+      builds.push(path);
+    }
+  }
+  builds.sort();
+  Ok(builds)
+}
+
+/// Move every legacy build of `store` into the directory of `triple`, the
+/// platform the store was built on.
+///
+/// Returns the number of builds moved. Only call this while holding the
+/// store lock exclusively, so no apply is writing a build being moved.
+pub fn migrate_legacy_builds(store: &Path, triple: &str) -> io::Result<usize> {
+  if !is_triple(triple) {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!("'{}' is not a platform triple (e.g. {})", triple, store_triple()),
+    ));
+  }
+  let mut migrated = 0;
+  for path in legacy_builds(store)? {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+      continue;
+    };
+    migrate_legacy_build(store, triple, name)?;
+    migrated += 1;
+  }
+  Ok(migrated)
+}
+
+/// A name usable as a platform build directory, like "x86_64-linux".
+fn is_triple(triple: &str) -> bool {
+  triple.contains('-')
+    && triple
+      .bytes()
+      .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// A real directory named like a build hash directly in `<store>/build`.
+///
+/// Symlinks there are left behind by migrations (or link a parent store's
+/// builds) and aren't builds of their own; platform directories aren't named
+/// like hashes.
+fn is_legacy_build(path: &Path) -> bool {
+  let is_hash = path.file_name().and_then(|n| n.to_str()).is_some_and(|name| {
+    (OBJ_HASH_PREFIX_LEN..=OBJ_HASH_MAX_LEN).contains(&name.len())
+      && name.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+  });
+  is_hash && path.symlink_metadata().is_ok_and(|meta| meta.is_dir())
+}
+
+/// Move the legacy build `name` of `store` under `triple`, leaving a symlink
+/// in its place.
+fn migrate_legacy_build(store: &Path, triple: &str, name: &str) -> io::Result<()> {
+  let legacy = store.join("build").join(name);
+  let target = platform_build_dir(store, triple).join(name);
+  if target.symlink_metadata().is_ok() {
+    return Err(io::Error::new(
+      io::ErrorKind::AlreadyExists,
+      format!("{} is already in the store as {}", name, target.display()),
+    ));
+  }
+  move_build(&legacy, &target)?;
+  if let Err(e) = link_dir(&target, &legacy) {
+    warn!(path = %legacy.display(), error = %e, "failed to link migrated build from its old path");
+  }
+  debug!(from = %legacy.display(), to = %target.display(), "migrated legacy build");
+  Ok(())
+}
+
+/// Rename a build directory, making a read-only one writable for the move
+/// (a directory's `..` entry changes with its parent).
+fn move_build(from: &Path, to: &Path) -> io::Result<()> {
+  if let Some(parent) = to.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  match std::fs::rename(from, to) {
+    Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+      make_mutable(from).map_err(io::Error::other)?;
+      std::fs::rename(from, to)?;
+      make_immutable(to).map_err(io::Error::other)
+    }
+    result => result,
+  }
 }
 
 #[cfg(test)]
@@ -66,7 +213,12 @@ mod tests {
       || {
         let hash = ObjectHash("abc123def45678901234".to_string());
         let path = build_dir_path(&hash);
-        assert_eq!(path, PathBuf::from("/test/store/build/abc123def45678901234"));
+        assert_eq!(
+          path,
+          PathBuf::from("/test/store/build")
+            .join(store_triple())
+            .join("abc123def45678901234")
+        );
       },
     );
   }
@@ -80,7 +232,7 @@ mod tests {
 
     // Create build in parent store only
     let hash = ObjectHash("abc123def45678901234".to_string());
-    let parent_build = parent_store.join("build").join(&hash.0);
+    let parent_build = builds_dir(&parent_store).join(&hash.0);
     std::fs::create_dir_all(&parent_build).unwrap();
     std::fs::write(parent_build.join("marker.txt"), "exists").unwrap();
 
@@ -112,11 +264,11 @@ mod tests {
     let hash = ObjectHash("abc123def45678901234".to_string());
 
     // Create build in BOTH stores
-    let parent_build = parent_store.join("build").join(&hash.0);
+    let parent_build = builds_dir(&parent_store).join(&hash.0);
     std::fs::create_dir_all(&parent_build).unwrap();
     std::fs::write(parent_build.join("marker.txt"), "parent").unwrap();
 
-    let user_build = user_store.join("build").join(&hash.0);
+    let user_build = builds_dir(&user_store).join(&hash.0);
     std::fs::create_dir_all(&user_build).unwrap();
     std::fs::write(user_build.join("marker.txt"), "user").unwrap();
 
@@ -138,4 +290,97 @@ mod tests {
       },
     );
   }
+
+  #[test]
+  #[serial]
+  fn build_dir_path_reuses_shorter_hash_dir() {
+    let temp = tempfile::tempdir().unwrap();
+    let store = temp.path().join("store");
+
+    let short = ObjectHash("abc123def45678901234".to_string());
+    std::fs::create_dir_all(builds_dir(&store).join(&short.0)).unwrap();
+    let long = ObjectHash(format!("{}{}", short.0, "0123456789ab"));
+
+    temp_env::with_vars(
+      [
+        ("SYSLUA_STORE", Some(store.to_str().unwrap())),
+        ("SYSLUA_PARENT_STORE", None::<&str>),
+        ("SYSLUA_ROOT", None::<&str>),
+      ],
+      || {
+        assert_eq!(build_dir_path(&long), builds_dir(&store).join(&short.0));
+        assert!(build_exists_in_store(&long, &store));
+      },
+    );
+  }
+
+  #[test]
+  #[serial]
+  fn legacy_builds_are_only_used_once_migrated() {
+    let temp = tempfile::tempdir().unwrap();
+    let store = temp.path().join("store");
+
+    let hash = ObjectHash("abc123def45678901234".to_string());
+    let legacy = store.join("build").join(&hash.0);
+    std::fs::create_dir_all(&legacy).unwrap();
+    std::fs::write(legacy.join("marker.txt"), "legacy").unwrap();
+    crate::platform::make_immutable(&legacy).unwrap();
+
+    temp_env::with_vars(
+      [
+        ("SYSLUA_STORE", Some(store.to_str().unwrap())),
+        ("SYSLUA_PARENT_STORE", None::<&str>),
+        ("SYSLUA_ROOT", None::<&str>),
+      ],
+      || {
+        // Looking a build up never moves it
+        assert!(!build_exists_in_store(&hash, &store));
+        assert_eq!(build_dir_path(&hash), builds_dir(&store).join(&hash.0));
+        assert!(!builds_dir(&store).join(&hash.0).exists());
+        assert_eq!(legacy_builds(&store).unwrap(), vec![legacy.clone()]);
+
+        assert!(migrate_legacy_builds(&store, "../escape").is_err());
+        assert_eq!(migrate_legacy_builds(&store, "riscv64-plan9").unwrap(), 1);
+        let path = build_dir_path_for(&hash, "riscv64-plan9");
+        assert_eq!(path, platform_build_dir(&store, "riscv64-plan9").join(&hash.0));
+        assert_eq!(std::fs::read_to_string(path.join("marker.txt")).unwrap(), "legacy");
+
+        // The old path still resolves, but is no longer a build of its own
+        assert!(legacy.symlink_metadata().unwrap().file_type().is_symlink());
+        assert!(legacy.join("marker.txt").exists());
+        assert!(legacy_builds(&store).unwrap().is_empty());
+        assert!(!build_exists_in_store(&hash, &store));
+      },
+    );
+    crate::platform::remove_store_path(&platform_build_dir(&store, "riscv64-plan9").join(&hash.0)).unwrap();
+  }
+
+  #[test]
+  #[serial]
+  fn builds_of_other_platforms_are_not_used() {
+    let temp = tempfile::tempdir().unwrap();
+    let store = temp.path().join("store");
+
+    let hash = ObjectHash("abc123def45678901234".to_string());
+    let other = platform_build_dir(&store, "riscv64-plan9").join(&hash.0);
+    std::fs::create_dir_all(&other).unwrap();
+
+    temp_env::with_vars(
+      [
+        ("SYSLUA_STORE", Some(store.to_str().unwrap())),
+        ("SYSLUA_PARENT_STORE", None::<&str>),
+        ("SYSLUA_ROOT", None::<&str>),
+      ],
+      || {
+        assert_eq!(build_dir_path(&hash), builds_dir(&store).join(&hash.0));
+        assert!(!build_exists_in_store(&hash, &store));
+        assert_eq!(
+          build_dir_path_for(&hash, "riscv64-plan9"),
+          other,
+          "an explicit triple finds its builds"
+        );
+        assert!(legacy_builds(&store).unwrap().is_empty());
+      },
+    );
+  }
 }
@@ -9,7 +9,11 @@
 //! Builds are identified by content-addressed hashes ([`BuildHash`]) computed from
 //! their [`BuildDef`]. This enables deduplication and caching.
 
-use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+use std::{
+  cell::RefCell,
+  collections::{BTreeMap, BTreeSet},
+  rc::Rc,
+};
 
 use mlua::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -17,8 +21,14 @@ use serde_json::Value as JsonValue;
 
 use crate::{
   action::{Action, ActionCtx, actions::exec::ExecOpts},
-  manifest::Manifest,
-  util::hash::{Hashable, ObjectHash},
+  lua::diagnostics::{SpecCaller, SpecKind},
+  manifest::{Manifest, PlaceholderMode, memoized_hash, spec_placeholder_mode, spec_platforms},
+  outputs::{lua::outputs_to_lua_table, schema::OutputSchema},
+  placeholder::{self, Placeholder, Segment},
+  util::{
+    hash::{HashError, HashSpec, Hashable, ObjectHash, hash_bytes},
+    metadata::Metadata,
+  },
 };
 
 /// Lua-side specification for build inputs.
@@ -63,6 +73,23 @@ pub struct BuildSpec {
   /// If true, allows replacing an existing build with the same ID.
   /// Defaults to false, which means duplicate IDs will error.
   pub replace: bool,
+  /// Optional resource hints for the executor.
+  pub resources: Option<BuildResources>,
+  /// Optional description, license and homepage.
+  pub metadata: Option<Metadata>,
+  /// Optional validation run against the outputs after `create`.
+  pub check: Option<LuaFunction>,
+  /// Optional declaration of the outputs `create` returns. Not part of the hash.
+  pub outputs: Option<OutputSchema>,
+  /// How placeholders are checked, overriding `settings.placeholders`. Not
+  /// part of the hash.
+  pub placeholders: Option<PlaceholderMode>,
+  /// Environment variables the build reads at execution, whose values are
+  /// captured into the hash at evaluation.
+  pub impure_env: Vec<String>,
+  /// Platform triples the build is limited to; empty for every platform. Not
+  /// part of the hash.
+  pub platforms: Vec<String>,
 }
 
 impl FromLua for BuildSpec {
@@ -84,16 +111,151 @@ impl FromLua for BuildSpec {
       .get("create")
       .map_err(|_| LuaError::external("build spec requires 'create' function"))?;
     let replace: bool = table.get("replace").unwrap_or(false);
+    let resources: Option<BuildResources> = table.get("resources")?;
+    let metadata: Option<Metadata> = table.get("metadata")?;
+    let check: Option<LuaFunction> = table.get("check")?;
+    let outputs: Option<OutputSchema> = table.get("outputs")?;
+    let placeholders = spec_placeholder_mode(&table, "build")?;
+    let platforms = spec_platforms(&table, "build")?;
+    let impure_env = match table.get::<LuaValue>("impure_env")? {
+      LuaValue::Nil => Vec::new(),
+      LuaValue::Table(t) => t
+        .sequence_values::<String>()
+        .collect::<LuaResult<Vec<_>>>()
+        .map_err(|_| LuaError::external("build `impure_env` must be a list of variable names"))?,
+      other => {
+        return Err(LuaError::external(format!(
+          "build `impure_env` must be a list of variable names, got {}",
+          other.type_name()
+        )));
+      }
+    };
+    if let Some(name) = impure_env.iter().find(|name| name.is_empty() || name.contains('=')) {
+      return Err(LuaError::external(format!(
+        "build `impure_env` has an invalid variable name '{}'",
+        name
+      )));
+    }
 
     Ok(BuildSpec {
       id,
       inputs,
       create,
       replace,
+      resources,
+      metadata,
+      check,
+      outputs,
+      placeholders,
+      impure_env,
+      platforms,
     })
   }
 }
 
+/// Resource hints for a build.
+///
+/// ```lua
+/// sys.build({
+///   id = "llvm",
+///   resources = { cpus = 4, memory = "8G" },
+///   create = function(inputs, ctx) ... end,
+/// })
+/// ```
+///
+/// `cpus` weights the executor's parallelism semaphore so a heavy build occupies
+/// several slots. On Linux, both limits are also enforced with a cgroup when
+/// syslua has permission to create one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildResources {
+  /// Number of CPUs the build may use.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub cpus: Option<u32>,
+  /// Memory limit in bytes.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub memory: Option<u64>,
+}
+
+impl BuildResources {
+  /// Number of parallelism slots this build occupies, clamped to `1..=max`.
+  pub fn weight(&self, max: usize) -> u32 {
+    let max = u32::try_from(max.max(1)).unwrap_or(u32::MAX);
+    self.cpus.unwrap_or(1).clamp(1, max)
+  }
+}
+
+impl FromLua for BuildResources {
+  fn from_lua(value: LuaValue, _lua: &Lua) -> LuaResult<Self> {
+    let table = match value {
+      LuaValue::Table(t) => t,
+      _ => {
+        return Err(LuaError::FromLuaConversionError {
+          from: value.type_name(),
+          to: "BuildResources".to_string(),
+          message: Some("expected table".to_string()),
+        });
+      }
+    };
+
+    let cpus = match table.get::<LuaValue>("cpus")? {
+      LuaValue::Nil => None,
+      LuaValue::Integer(n) if n > 0 => Some(n),
+      // Saturates, so huge numbers are reported as out of range below
+      LuaValue::Number(n) if n >= 1.0 && n.fract() == 0.0 => Some(n as i64),
+      other => {
+        return Err(LuaError::external(format!(
+          "resources.cpus must be a positive integer, got {}",
+          other.type_name()
+        )));
+      }
+    };
+    let cpus = cpus
+      .map(|n| {
+        u32::try_from(n)
+          .map_err(|_| LuaError::external(format!("resources.cpus must be at most {}, got {}", u32::MAX, n)))
+      })
+      .transpose()?;
+
+    let memory = match table.get::<LuaValue>("memory")? {
+      LuaValue::Nil => None,
+      LuaValue::Integer(n) if n > 0 => Some(n.unsigned_abs()),
+      LuaValue::String(s) => Some(parse_memory_size(&s.to_str()?).map_err(LuaError::external)?),
+      other => {
+        return Err(LuaError::external(format!(
+          "resources.memory must be a byte count or a size string like \"2G\", got {}",
+          other.type_name()
+        )));
+      }
+    };
+
+    Ok(BuildResources { cpus, memory })
+  }
+}
+
+/// Parse a memory size like `"512M"`, `"2G"` or `"1GiB"` into bytes.
+///
+/// Suffixes are binary (`K` = 1024). A bare number is a byte count.
+pub fn parse_memory_size(input: &str) -> Result<u64, String> {
+  let trimmed = input.trim();
+  let upper = trimmed.to_ascii_uppercase();
+  let unit = upper.trim_end_matches("IB").trim_end_matches('B');
+  let (digits, multiplier) = match unit.chars().last() {
+    Some('K') => (&unit[..unit.len() - 1], 1u64 << 10),
+    Some('M') => (&unit[..unit.len() - 1], 1 << 20),
+    Some('G') => (&unit[..unit.len() - 1], 1 << 30),
+    Some('T') => (&unit[..unit.len() - 1], 1 << 40),
+    _ => (unit, 1),
+  };
+
+  digits
+    .trim()
+    .parse::<u64>()
+    .ok()
+    .filter(|n| *n > 0)
+    .and_then(|n| n.checked_mul(multiplier))
+    .ok_or_else(|| format!("invalid memory size '{}'", input))
+}
+
 /// A resolved, serializable input value.
 ///
 /// This is the manifest-side representation of inputs. All values are fully
@@ -153,12 +315,13 @@ impl BuildInputs {
   pub fn from_spec(
     manifest: &Rc<RefCell<Manifest>>,
     spec: BuildInputsSpec,
+    caller: SpecCaller,
     lua_value_to_def: impl Fn(LuaValue, &Manifest) -> LuaResult<BuildInputs>,
   ) -> LuaResult<Option<Self>> {
     match spec {
       BuildInputsSpec::Value(v) => Ok(Some(lua_value_to_def(v, &manifest.borrow())?)),
       BuildInputsSpec::Function(f) => {
-        let result = f.call::<LuaValue>(())?;
+        let result = caller.call::<LuaValue>("inputs", &f, ())?;
         if result.is_nil() {
           Ok(None)
         } else {
@@ -199,11 +362,85 @@ pub struct BuildDef {
   pub outputs: Option<BTreeMap<String, JsonValue>>,
   /// The sequence of actions to execute during `create`.
   pub create_actions: Vec<Action>,
+  /// Resource hints (CPU/memory) used by the executor. Not part of the hash,
+  /// so tuning them keeps the store path.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub resources: Option<BuildResources>,
+  /// Description, license and homepage. Part of the hash, so editing it
+  /// rebuilds.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub metadata: Option<Metadata>,
+  /// Actions recorded by `check`, run after `create` in the build directory.
+  ///
+  /// They continue the action numbering of `create_actions`, so check actions
+  /// can reference create outputs. A failing check fails the build.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub check_actions: Option<Vec<Action>>,
+  /// Output hash of a directory imported with `sys store add`, for builds
+  /// declared with `sys.prebuilt`. Such builds have no actions: they are
+  /// never realized, only found in the store.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub prebuilt: Option<String>,
+  /// Digests of the environment variables declared with `impure_env`, taken
+  /// at evaluation (`None` for unset ones). Part of the hash, so the build
+  /// rebuilds when one of them changes; the values aren't recorded.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub impure_env: Option<BTreeMap<String, Option<String>>>,
 }
 
-impl Hashable for BuildDef {}
+impl Hashable for BuildDef {
+  fn hash_input(&self) -> Result<String, HashError> {
+    #[derive(Serialize)]
+    struct BuildDefHashable<'a> {
+      id: &'a Option<String>,
+      inputs: &'a Option<BuildInputs>,
+      outputs: &'a Option<BTreeMap<String, JsonValue>>,
+      create_actions: &'a Vec<Action>,
+      #[serde(skip_serializing_if = "Option::is_none")]
+      metadata: &'a Option<Metadata>,
+      #[serde(skip_serializing_if = "Option::is_none")]
+      check_actions: &'a Option<Vec<Action>>,
+      #[serde(skip_serializing_if = "Option::is_none")]
+      prebuilt: &'a Option<String>,
+      #[serde(skip_serializing_if = "Option::is_none")]
+      impure_env: &'a Option<BTreeMap<String, Option<String>>>,
+    }
+
+    let hashable = BuildDefHashable {
+      id: &self.id,
+      inputs: &self.inputs,
+      outputs: &self.outputs,
+      create_actions: &self.create_actions,
+      metadata: &self.metadata,
+      check_actions: &self.check_actions,
+      prebuilt: &self.prebuilt,
+      impure_env: &self.impure_env,
+    };
+
+    serde_json::to_string(&hashable)
+  }
+}
 
 impl BuildDef {
+  /// The definition of a prebuilt build: an imported directory with output
+  /// hash `output_hash`, whose `out` output is its store path.
+  pub fn prebuilt(id: &str, output_hash: &str) -> Self {
+    BuildDef {
+      id: Some(id.to_string()),
+      inputs: None,
+      outputs: Some(BTreeMap::from([(
+        "out".to_string(),
+        JsonValue::String("$${{out}}".to_string()),
+      )])),
+      create_actions: Vec::new(),
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: Some(output_hash.to_string()),
+      impure_env: None,
+    }
+  }
+
   pub fn from_spec(
     lua: &Lua,
     manifest: &Rc<RefCell<Manifest>>,
@@ -212,8 +449,9 @@ impl BuildDef {
     inputs_def_to_lua: impl Fn(&Lua, &BuildInputs, &Manifest) -> LuaResult<LuaValue>,
     parse_outputs: impl Fn(LuaTable) -> LuaResult<BTreeMap<String, JsonValue>>,
   ) -> LuaResult<Self> {
+    let caller = SpecCaller::new(SpecKind::Build, spec.id.as_deref());
     let inputs = match spec.inputs {
-      Some(input_spec) => BuildInputs::from_spec(manifest, input_spec, &lua_value_to_def)?,
+      Some(input_spec) => BuildInputs::from_spec(manifest, input_spec, caller, &lua_value_to_def)?,
       None => None,
     };
 
@@ -225,7 +463,7 @@ impl BuildDef {
       None => LuaValue::Table(lua.create_table()?),
     };
 
-    let result: LuaValue = spec.create.call((inputs_arg, &ctx_userdata))?;
+    let result: LuaValue = caller.call("create", &spec.create, (inputs_arg, &ctx_userdata))?;
 
     let outputs: BTreeMap<String, JsonValue> = match result {
       LuaValue::Table(t) => {
@@ -245,14 +483,81 @@ impl BuildDef {
       }
     };
 
+    // Call: check(outputs, ctx) -> ignored. It records into the same context,
+    // so its placeholders don't collide with those of create.
+    let create_count = ctx_userdata.borrow::<BuildCtx>()?.action_count();
+    if let Some(check_fn) = spec.check {
+      let outputs_arg = outputs_to_lua_table(lua, &outputs)?;
+      let _: LuaValue = caller.call("check", &check_fn, (outputs_arg, &ctx_userdata))?;
+    }
+
     let ctx: BuildCtx = ctx_userdata.take()?;
+    let mut create_actions = ctx.into_actions();
+    let check_actions = create_actions.split_off(create_count);
 
     Ok(BuildDef {
       id: spec.id,
       inputs,
-      create_actions: ctx.into_actions(),
+      create_actions,
       outputs: Some(outputs),
+      resources: spec.resources,
+      metadata: spec.metadata.filter(|m| !m.is_empty()),
+      check_actions: (!check_actions.is_empty()).then_some(check_actions),
+      prebuilt: None,
+      impure_env: capture_impure_env(&spec.impure_env),
+    })
+  }
+
+  /// Environment variables the build's actions and outputs reference with
+  /// `$${{env:<name>}}` without declaring them in `impure_env`.
+  ///
+  /// Their values aren't part of the hash, so a change doesn't rebuild.
+  pub fn undeclared_env(&self) -> BTreeSet<String> {
+    let actions = self.create_actions.iter().chain(self.check_actions.iter().flatten());
+    let mut values: Vec<JsonValue> = actions
+      .map(|action| serde_json::to_value(action).unwrap_or(JsonValue::Null))
+      .collect();
+    values.extend(self.outputs.iter().flat_map(|outputs| outputs.values().cloned()));
+
+    let mut names = BTreeSet::new();
+    for value in &values {
+      collect_env_references(value, &mut names);
+    }
+    if let Some(declared) = &self.impure_env {
+      names.retain(|name| !declared.contains_key(name));
+    }
+    names
+  }
+}
+
+/// Digest the current values of the `impure_env` variables; `None` when
+/// none are declared.
+pub fn capture_impure_env(names: &[String]) -> Option<BTreeMap<String, Option<String>>> {
+  if names.is_empty() {
+    return None;
+  }
+  let captured = names
+    .iter()
+    .map(|name| {
+      let digest = std::env::var_os(name).map(|value| hash_bytes(value.as_encoded_bytes()).0);
+      (name.clone(), digest)
     })
+    .collect();
+  Some(captured)
+}
+
+fn collect_env_references(value: &JsonValue, names: &mut BTreeSet<String>) {
+  match value {
+    JsonValue::String(s) => {
+      for segment in placeholder::parse(s).unwrap_or_default() {
+        if let Segment::Placeholder(Placeholder::Env(name)) = segment {
+          names.insert(name);
+        }
+      }
+    }
+    JsonValue::Array(values) => values.iter().for_each(|v| collect_env_references(v, names)),
+    JsonValue::Object(map) => map.values().for_each(|v| collect_env_references(v, names)),
+    _ => {}
   }
 }
 
@@ -321,9 +626,10 @@ pub struct BuildRef {
 impl BuildRef {
   /// Create a BuildRef from a BuildDef.
   ///
-  /// Computes the content-addressed hash from the definition.
-  pub fn from_def(def: &BuildDef) -> Result<Self, LuaError> {
-    let hash = match def.compute_hash() {
+  /// Computes the content-addressed hash from the definition with `spec`,
+  /// reusing the hash of an identical definition declared earlier in `lua`.
+  pub fn from_def(lua: &Lua, def: &BuildDef, spec: &HashSpec) -> Result<Self, LuaError> {
+    let hash = match memoized_hash(lua, def, spec) {
       Ok(it) => it,
       Err(err) => return Err(LuaError::external(format!("failed to compute build hash: {}", err))),
     };
@@ -382,8 +688,14 @@ mod tests {
         create_actions: vec![Action::FetchUrl {
           url: "https://example.com/rg.tar.gz".to_string(),
           sha256: "abc123".to_string(),
+          unpack: false,
         }],
         outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       }
     }
 
@@ -424,6 +736,10 @@ mod tests {
         args: None,
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       }));
 
       assert_ne!(def1.compute_hash().unwrap(), def2.compute_hash().unwrap());
@@ -442,15 +758,28 @@ mod tests {
             args: None,
             env: None,
             cwd: None,
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
           Action::Exec(ExecOpts {
             bin: "step2".to_string(),
             args: None,
             env: None,
             cwd: None,
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
         ],
         outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       };
 
       let def2 = BuildDef {
@@ -462,15 +791,28 @@ mod tests {
             args: None,
             env: None,
             cwd: None,
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
           Action::Exec(ExecOpts {
             bin: "step1".to_string(),
             args: None,
             env: None,
             cwd: None,
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
         ],
         outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       };
 
       assert_ne!(def1.compute_hash().unwrap(), def2.compute_hash().unwrap());
@@ -488,18 +830,28 @@ mod tests {
           Action::FetchUrl {
             url: "https://example.com/src.tar.gz".to_string(),
             sha256: "abc123".to_string(),
+            unpack: false,
           },
           Action::Exec(ExecOpts {
             bin: "make".to_string(),
             args: Some(vec!["install".to_string()]),
             env: Some(env),
             cwd: Some("/build".to_string()),
+            creates: None,
+            unless: None,
+            shell: None,
+            run_as: None,
           }),
         ],
         outputs: Some(BTreeMap::from([(
           "out".to_string(),
           JsonValue::String("$${{action:1}}".to_string()),
         )])),
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       };
 
       let json = serde_json::to_string(&def).unwrap();
@@ -508,4 +860,102 @@ mod tests {
       assert_eq!(def, deserialized);
     }
   }
+
+  mod resources {
+    use super::*;
+
+    #[test]
+    fn parse_memory_size_accepts_binary_suffixes() {
+      assert_eq!(parse_memory_size("1024"), Ok(1024));
+      assert_eq!(parse_memory_size("512K"), Ok(512 * 1024));
+      assert_eq!(parse_memory_size("2G"), Ok(2 << 30));
+      assert_eq!(parse_memory_size("1GiB"), Ok(1 << 30));
+      assert_eq!(parse_memory_size("256mb"), Ok(256 << 20));
+      assert!(parse_memory_size("lots").is_err());
+      assert!(parse_memory_size("0G").is_err());
+    }
+
+    #[test]
+    fn weight_is_clamped_to_parallelism() {
+      assert_eq!(BuildResources::default().weight(8), 1);
+      assert_eq!(
+        BuildResources {
+          cpus: Some(4),
+          memory: None
+        }
+        .weight(8),
+        4
+      );
+      assert_eq!(
+        BuildResources {
+          cpus: Some(16),
+          memory: None
+        }
+        .weight(8),
+        8
+      );
+    }
+
+    #[test]
+    fn from_lua_parses_table() {
+      let lua = Lua::new();
+      let value: LuaValue = lua.load(r#"{ cpus = 2, memory = "2G" }"#).eval().unwrap();
+      let resources = BuildResources::from_lua(value, &lua).unwrap();
+      assert_eq!(
+        resources,
+        BuildResources {
+          cpus: Some(2),
+          memory: Some(2 << 30),
+        }
+      );
+
+      let value: LuaValue = lua.load(r#"{ cpus = "two" }"#).eval().unwrap();
+      assert!(BuildResources::from_lua(value, &lua).is_err());
+
+      let value: LuaValue = lua.load("{ cpus = 2^40 }").eval().unwrap();
+      let err = BuildResources::from_lua(value, &lua).unwrap_err().to_string();
+      assert!(err.contains("at most"), "{}", err);
+    }
+
+    #[test]
+    fn hash_unchanged_without_resources() {
+      let def = BuildDef {
+        id: Some("test".to_string()),
+        inputs: None,
+        outputs: None,
+        create_actions: vec![],
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
+      };
+      let json = serde_json::to_string(&def).unwrap();
+      assert!(!json.contains("resources"));
+    }
+
+    #[test]
+    fn resources_are_not_part_of_the_hash() {
+      let def = BuildDef {
+        id: Some("test".to_string()),
+        inputs: None,
+        outputs: None,
+        create_actions: vec![],
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
+      };
+      let limited = BuildDef {
+        resources: Some(BuildResources {
+          cpus: Some(4),
+          memory: Some(1 << 30),
+        }),
+        ..def.clone()
+      };
+      assert_eq!(def.compute_hash().unwrap(), limited.compute_hash().unwrap());
+      assert_eq!(def.hash_input().unwrap(), serde_json::to_string(&def).unwrap());
+    }
+  }
 }
@@ -0,0 +1,130 @@
+//! Build users of untrusted builds.
+//!
+//! The system daemon runs the builds of untrusted users as the members of a
+//! build group (`syslua-build1`, `syslua-build2`, ... in `syslua-build`), the
+//! way Nix uses its `nixbld` users. Each running build holds a user of its
+//! own, so a build can't reach into the output or processes of another; when
+//! the build ends, every process of its user is killed before the user is
+//! handed to the next build.
+
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::platform::run_as::{RunAsError, group_members};
+
+/// Group whose members run untrusted builds when none is configured.
+pub const DEFAULT_BUILD_GROUP: &str = "syslua-build";
+
+/// The free build users of a group, shared by every build of the daemon.
+#[derive(Debug, Clone)]
+pub struct BuildUsers {
+  group: String,
+  count: usize,
+  free: Arc<Mutex<Vec<String>>>,
+  available: Arc<Semaphore>,
+}
+
+impl BuildUsers {
+  /// The members of `group`.
+  pub fn from_group(group: &str) -> Result<Self, RunAsError> {
+    let members = group_members(group)?;
+    if members.is_empty() {
+      return Err(RunAsError::NoBuildUsers(group.to_string()));
+    }
+    Ok(Self::new(group, members))
+  }
+
+  fn new(group: &str, mut users: Vec<String>) -> Self {
+    users.sort();
+    users.dedup();
+    // Handed out from the end, so the first users are used first
+    users.reverse();
+    Self {
+      group: group.to_string(),
+      count: users.len(),
+      available: Arc::new(Semaphore::new(users.len())),
+      free: Arc::new(Mutex::new(users)),
+    }
+  }
+
+  /// The group the users are members of.
+  pub fn group(&self) -> &str {
+    &self.group
+  }
+
+  /// How many builds can run at once.
+  pub fn count(&self) -> usize {
+    self.count
+  }
+
+  /// Take a free user, waiting for a build to finish when all are taken.
+  pub async fn acquire(&self) -> Result<BuildUserLease, RunAsError> {
+    let no_free_user = || RunAsError::NoFreeBuildUser(self.group.clone());
+    let permit = self
+      .available
+      .clone()
+      .acquire_owned()
+      .await
+      .map_err(|_| no_free_user())?;
+    let user = self
+      .free
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .pop()
+      .ok_or_else(no_free_user)?;
+    Ok(BuildUserLease {
+      user,
+      free: self.free.clone(),
+      _permit: permit,
+    })
+  }
+}
+
+/// A build user held by one build; returned to the pool when dropped.
+#[derive(Debug)]
+pub struct BuildUserLease {
+  user: String,
+  free: Arc<Mutex<Vec<String>>>,
+  _permit: OwnedSemaphorePermit,
+}
+
+impl BuildUserLease {
+  pub fn user(&self) -> &str {
+    &self.user
+  }
+}
+
+impl Drop for BuildUserLease {
+  fn drop(&mut self) {
+    // Back before the permit, so the next holder finds it
+    self
+      .free
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .push(std::mem::take(&mut self.user));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn each_build_holds_a_user_of_its_own() {
+    let users = BuildUsers::new("builders", vec!["b2".to_string(), "b1".to_string()]);
+    let first = users.acquire().await.unwrap();
+    let second = users.acquire().await.unwrap();
+    assert_eq!((first.user(), second.user()), ("b1", "b2"));
+
+    // A third build waits for a user to be released
+    let waiting = tokio::spawn({
+      let users = users.clone();
+      async move { users.acquire().await.unwrap().user().to_string() }
+    });
+    tokio::task::yield_now().await;
+    assert!(!waiting.is_finished());
+    drop(first);
+    assert_eq!(waiting.await.unwrap(), "b1");
+  }
+}
@@ -0,0 +1,206 @@
+//! Values for dynamic shell completion.
+//!
+//! The scripts printed by `sys completions` call back into `sys` for values
+//! only known at runtime: snapshot IDs, bind ids, tags and groups from the
+//! current snapshot, and the inputs locked for a config. Lookups never fail; a missing
+//! or unreadable store or lock file just yields no candidates.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::inputs::lock::{LOCK_FILENAME, LockFile};
+use crate::snapshot::SnapshotStore;
+use crate::update::find_config_path;
+
+/// A completion value with an optional description.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+  pub value: String,
+  pub help: Option<String>,
+}
+
+impl Candidate {
+  fn new(value: impl Into<String>, help: Option<String>) -> Self {
+    Self {
+      value: value.into(),
+      help,
+    }
+  }
+}
+
+/// IDs of the snapshots in the default store, newest first.
+pub fn snapshot_ids() -> Vec<Candidate> {
+  snapshot_ids_in(&SnapshotStore::default_store())
+}
+
+/// Ids and tags of the binds in the current snapshot of the default store.
+pub fn bind_selectors() -> Vec<Candidate> {
+  bind_selectors_in(&SnapshotStore::default_store())
+}
+
+/// Groups of the binds in the current snapshot, with their bind counts.
+pub fn bind_groups() -> Vec<Candidate> {
+  bind_groups_in(&SnapshotStore::default_store())
+}
+
+/// Names of the inputs locked for a config, resolved like `sys update` does.
+pub fn input_names(config: Option<&str>) -> Vec<Candidate> {
+  let Ok(config_path) = find_config_path(config) else {
+    return Vec::new();
+  };
+  let config_dir = config_path.parent().unwrap_or(Path::new("."));
+  input_names_in(&config_dir.join(LOCK_FILENAME))
+}
+
+fn snapshot_ids_in(store: &SnapshotStore) -> Vec<Candidate> {
+  let Ok(snapshots) = store.list() else {
+    return Vec::new();
+  };
+  let current = store.current_id().ok().flatten();
+
+  snapshots
+    .into_iter()
+    .rev()
+    .map(|meta| {
+      let mut labels = meta.tags;
+      if current.as_ref() == Some(&meta.id) {
+        labels.insert(0, "current".to_string());
+      }
+      let help = (!labels.is_empty()).then(|| labels.join(", "));
+      Candidate::new(meta.id, help)
+    })
+    .collect()
+}
+
+fn bind_selectors_in(store: &SnapshotStore) -> Vec<Candidate> {
+  let Ok(Some(snapshot)) = store.load_current() else {
+    return Vec::new();
+  };
+
+  let mut ids = BTreeMap::new();
+  let mut tags = BTreeMap::new();
+  for bind in snapshot.manifest.bindings.values() {
+    if let Some(id) = &bind.id {
+      ids.insert(id.clone(), "bind");
+    }
+    for tag in &bind.tags {
+      tags.insert(tag.clone(), "tag");
+    }
+  }
+
+  // An id shadows a tag of the same name, as in bind selection
+  tags.retain(|tag, _| !ids.contains_key(tag));
+  ids
+    .into_iter()
+    .chain(tags)
+    .map(|(value, kind)| Candidate::new(value, Some(kind.to_string())))
+    .collect()
+}
+
+fn bind_groups_in(store: &SnapshotStore) -> Vec<Candidate> {
+  let Ok(Some(snapshot)) = store.load_current() else {
+    return Vec::new();
+  };
+
+  let mut groups: BTreeMap<String, usize> = BTreeMap::new();
+  for group in snapshot.manifest.bindings.values().filter_map(|b| b.group.clone()) {
+    *groups.entry(group).or_default() += 1;
+  }
+  groups
+    .into_iter()
+    .map(|(group, count)| Candidate::new(group, Some(format!("{} bind(s)", count))))
+    .collect()
+}
+
+fn input_names_in(lock_path: &Path) -> Vec<Candidate> {
+  let Ok(Some(lock)) = LockFile::load(lock_path) else {
+    return Vec::new();
+  };
+  lock
+    .inputs()
+    .into_iter()
+    .map(|(name, input)| Candidate::new(name, Some(input.url)))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::bind::BindDef;
+  use crate::manifest::Manifest;
+  use crate::snapshot::Snapshot;
+  use crate::util::hash::ObjectHash;
+  use tempfile::TempDir;
+
+  fn bind(id: Option<&str>, tags: &[&str]) -> BindDef {
+    BindDef {
+      id: id.map(str::to_string),
+      inputs: None,
+      outputs: None,
+      create_actions: vec![],
+      update_actions: None,
+      destroy_actions: vec![],
+      check_actions: None,
+      check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: tags.iter().map(|t| t.to_string()).collect(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
+    }
+  }
+
+  #[test]
+  fn snapshot_ids_newest_first_with_labels() {
+    let temp = TempDir::new().unwrap();
+    let store = SnapshotStore::new(temp.path().to_path_buf());
+    let mut older = Snapshot::new("100".to_string(), None, Manifest::default());
+    older.created_at = 100;
+    let mut newer = Snapshot::new("200".to_string(), None, Manifest::default());
+    newer.created_at = 200;
+    store.save_snapshot(&older).unwrap();
+    store.save_and_set_current(&newer).unwrap();
+    store.set_snapshot_tags("100", vec!["stable".to_string()]).unwrap();
+
+    let candidates = snapshot_ids_in(&store);
+    assert_eq!(
+      candidates,
+      [
+        Candidate::new("200", Some("current".to_string())),
+        Candidate::new("100", Some("stable".to_string())),
+      ]
+    );
+  }
+
+  #[test]
+  fn bind_selectors_list_ids_then_tags() {
+    let temp = TempDir::new().unwrap();
+    let store = SnapshotStore::new(temp.path().to_path_buf());
+    let mut manifest = Manifest::default();
+    manifest
+      .bindings
+      .insert(ObjectHash("a".to_string()), bind(Some("nvim"), &["editor"]));
+    manifest
+      .bindings
+      .insert(ObjectHash("b".to_string()), bind(None, &["editor", "nvim"]));
+    store
+      .save_and_set_current(&Snapshot::new("1".to_string(), None, manifest))
+      .unwrap();
+
+    let values: Vec<_> = bind_selectors_in(&store).into_iter().map(|c| c.value).collect();
+    assert_eq!(values, ["nvim", "editor"]);
+  }
+
+  #[test]
+  fn missing_sources_yield_nothing() {
+    let temp = TempDir::new().unwrap();
+    let store = SnapshotStore::new(temp.path().join("snapshots"));
+    assert!(snapshot_ids_in(&store).is_empty());
+    assert!(bind_selectors_in(&store).is_empty());
+    assert!(bind_groups_in(&store).is_empty());
+    assert!(input_names_in(&temp.path().join(LOCK_FILENAME)).is_empty());
+  }
+}
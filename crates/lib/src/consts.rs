@@ -5,4 +5,8 @@ pub const TOP_LEVEL_DOMAIN: &str = "com";
 /// Length of truncated hash prefixes used as manifest keys and in store paths.
 /// 20 hex characters = 80 bits of entropy, providing collision resistance
 /// up to ~48 billion items at 0.1% probability.
+/// This is the default length and also the shortest one `settings.hash` accepts.
 pub const OBJ_HASH_PREFIX_LEN: usize = 20;
+
+/// Longest object hash: a full SHA-512 digest in hex.
+pub const OBJ_HASH_MAX_LEN: usize = 128;
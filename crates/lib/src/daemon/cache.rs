@@ -0,0 +1,297 @@
+//! Evaluation cache for the daemon.
+//!
+//! Evaluated manifests are cached per config file and reused while the
+//! config's fingerprint is unchanged. The fingerprint is a SHA256 over every
+//! file under the config directory (not just `.lua` files, since configs
+//! read templates and other data files too), the host vars file selected for
+//! this evaluation, plus the id of the current snapshot that `sys.current`
+//! exposes, so editing the config, its data or vars, pointing `--vars-file`
+//! elsewhere, running `sys update` or applying a new state invalidates the
+//! entry. Hidden files and directories (`.git`, `.luarc.json`) and syslua's
+//! own directories (store, snapshots, plans, caches), should they live under
+//! the config directory, are left out.
+//!
+//! Evaluations are never cached when they may depend on state outside the
+//! config directory or on non-default options: impure evaluations, input
+//! overrides, a non-default untrusted inputs policy, and configs whose lock
+//! file contains `path:` inputs.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use sha2::{Digest, Sha256};
+use tracing::debug;
+use walkdir::WalkDir;
+
+use crate::api::ApiError;
+use crate::eval::{EvalOptions, evaluate_config, vars_file};
+use crate::inputs::lock::{LOCK_FILENAME, LockFile};
+use crate::lua::sandbox::UntrustedInputs;
+use crate::manifest::Manifest;
+use crate::platform::paths::{cache_dir, data_dir, plans_dir, root_dir, snapshots_dir, store_dir};
+use crate::snapshot::SnapshotStore;
+
+struct CacheEntry {
+  fingerprint: String,
+  manifest: Manifest,
+}
+
+/// Cache of evaluated manifests keyed by config path.
+#[derive(Default)]
+pub struct EvalCache {
+  entries: HashMap<PathBuf, CacheEntry>,
+  hits: u64,
+  misses: u64,
+}
+
+impl EvalCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Evaluate `config`, reusing a cached manifest if the config is unchanged.
+  pub fn evaluate(&mut self, config: &Path, options: &EvalOptions) -> Result<Manifest, ApiError> {
+    if !config.exists() {
+      return Err(ApiError::ConfigNotFound(config.display().to_string()));
+    }
+
+    let config_dir = config.parent().unwrap_or(Path::new("."));
+
+    if options.impure
+      || !options.input_overrides.is_empty()
+      || options.untrusted_inputs != UntrustedInputs::default()
+      || options.platform.is_some()
+      || has_path_inputs(config_dir)
+    {
+      debug!(config = %config.display(), "evaluation not cacheable");
+      self.misses += 1;
+      return Ok(evaluate_config(config, options)?);
+    }
+
+    let fingerprint = fingerprint(config_dir, vars_file(config_dir, options).as_deref());
+    if let Some(entry) = self.entries.get(config)
+      && entry.fingerprint == fingerprint
+    {
+      debug!(config = %config.display(), "using cached evaluation");
+      self.hits += 1;
+      return Ok(entry.manifest.clone());
+    }
+
+    self.misses += 1;
+    let manifest = evaluate_config(config, options)?;
+    self.entries.insert(
+      config.to_path_buf(),
+      CacheEntry {
+        fingerprint,
+        manifest: manifest.clone(),
+      },
+    );
+    Ok(manifest)
+  }
+
+  /// Number of configs with a cached evaluation.
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  /// Returns true if nothing is cached.
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// Evaluations served from the cache.
+  pub fn hits(&self) -> u64 {
+    self.hits
+  }
+
+  /// Evaluations that ran the config.
+  pub fn misses(&self) -> u64 {
+    self.misses
+  }
+}
+
+/// Hash the path, size and modification time of every file under
+/// `config_dir` and of `vars`, which may live elsewhere, and the current
+/// snapshot id.
+/// Hidden files and directories, and syslua's own directories, are skipped.
+fn fingerprint(config_dir: &Path, vars: Option<&Path>) -> String {
+  let mut hasher = Sha256::new();
+
+  if let Some(id) = SnapshotStore::default_store().current_id().ok().flatten() {
+    hasher.update(id.as_bytes());
+  }
+  hasher.update(b"\0");
+
+  if let Some(vars) = vars {
+    hash_file(&mut hasher, vars, vars.metadata().ok());
+  }
+
+  let config_dir = dunce::canonicalize(config_dir).unwrap_or_else(|_| config_dir.to_path_buf());
+  let own_dirs = [
+    root_dir(),
+    store_dir(),
+    snapshots_dir(),
+    plans_dir(),
+    data_dir(),
+    cache_dir(),
+  ]
+  .into_iter()
+  .filter_map(|dir| dunce::canonicalize(dir).ok())
+  .collect::<Vec<_>>();
+  let entries = WalkDir::new(&config_dir)
+    .sort_by_file_name()
+    .into_iter()
+    .filter_entry(|e| {
+      e.depth() == 0
+        || !(e.file_name().to_string_lossy().starts_with('.') || own_dirs.iter().any(|dir| e.path() == dir))
+    })
+    .filter_map(|e| e.ok())
+    .filter(|e| e.file_type().is_file());
+
+  for entry in entries {
+    hash_file(&mut hasher, entry.path(), entry.metadata().ok());
+  }
+
+  hex::encode(hasher.finalize())
+}
+
+/// Feed the path, size and modification time of a file to `hasher`.
+fn hash_file(hasher: &mut Sha256, path: &Path, metadata: Option<std::fs::Metadata>) {
+  hasher.update(path.as_os_str().as_encoded_bytes());
+  hasher.update(b"\0");
+  if let Some(metadata) = metadata {
+    hasher.update(metadata.len().to_le_bytes());
+    let modified = metadata
+      .modified()
+      .ok()
+      .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+      .unwrap_or_default();
+    hasher.update(modified.as_nanos().to_le_bytes());
+  }
+  hasher.update(b"\0");
+}
+
+/// Returns true if the config's lock file pins any `path:` input, or cannot be read.
+fn has_path_inputs(config_dir: &Path) -> bool {
+  match LockFile::load(&config_dir.join(LOCK_FILENAME)) {
+    Ok(Some(lock)) => lock
+      .as_v1()
+      .nodes
+      .values()
+      .any(|node| node.type_.as_deref() == Some("path")),
+    Ok(None) => false,
+    Err(_) => true,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serial_test::serial;
+  use tempfile::TempDir;
+
+  const EMPTY_CONFIG: &str = r#"
+    local M = {}
+    function M.setup() end
+    return M
+  "#;
+
+  #[test]
+  fn fingerprint_tracks_every_visible_file() {
+    let temp = TempDir::new().unwrap();
+    std::fs::write(temp.path().join("init.lua"), "return {}").unwrap();
+    let before = fingerprint(temp.path(), None);
+    assert_eq!(fingerprint(temp.path(), None), before);
+
+    std::fs::write(temp.path().join(".luarc.json"), "{}").unwrap();
+    std::fs::create_dir(temp.path().join(".git")).unwrap();
+    std::fs::write(temp.path().join(".git").join("HEAD"), "ref").unwrap();
+    assert_eq!(fingerprint(temp.path(), None), before);
+
+    std::fs::create_dir(temp.path().join("templates")).unwrap();
+    std::fs::write(temp.path().join("templates").join("motd.txt"), "hello").unwrap();
+    let with_template = fingerprint(temp.path(), None);
+    assert_ne!(with_template, before);
+
+    std::fs::write(temp.path().join("templates").join("motd.txt"), "hello, world").unwrap();
+    assert_ne!(fingerprint(temp.path(), None), with_template);
+  }
+
+  #[test]
+  #[serial]
+  fn fingerprint_skips_syslua_directories_under_the_config() {
+    let temp = TempDir::new().unwrap();
+    std::fs::write(temp.path().join("init.lua"), "return {}").unwrap();
+    let root = temp.path().join("syslua");
+    std::fs::create_dir_all(root.join("plans")).unwrap();
+
+    temp_env::with_var("SYSLUA_ROOT", Some(&root), || {
+      let before = fingerprint(temp.path(), None);
+      std::fs::write(root.join("plans").join("manifest.json"), "{}").unwrap();
+      assert_eq!(fingerprint(temp.path(), None), before);
+    });
+  }
+
+  #[test]
+  fn fingerprint_tracks_selected_vars_file() {
+    let config = TempDir::new().unwrap();
+    let elsewhere = TempDir::new().unwrap();
+    std::fs::write(config.path().join("init.lua"), "return {}").unwrap();
+    let laptop = elsewhere.path().join("laptop.lua");
+    let server = elsewhere.path().join("server.lua");
+    std::fs::write(&laptop, "return {}").unwrap();
+    std::fs::write(&server, "return {}").unwrap();
+
+    let with_laptop = fingerprint(config.path(), Some(&laptop));
+    assert_ne!(with_laptop, fingerprint(config.path(), None));
+    assert_ne!(with_laptop, fingerprint(config.path(), Some(&server)));
+
+    std::fs::write(&laptop, "return { role = 'laptop' }").unwrap();
+    assert_ne!(fingerprint(config.path(), Some(&laptop)), with_laptop);
+  }
+
+  #[test]
+  fn path_inputs_disable_caching() {
+    let temp = TempDir::new().unwrap();
+    assert!(!has_path_inputs(temp.path()));
+
+    let mut lock = LockFile::new();
+    lock.insert(
+      "dotfiles".to_string(),
+      crate::inputs::lock::LockedInput::new("path", "path:~/dotfiles", "local"),
+    );
+    lock.save(&temp.path().join(LOCK_FILENAME)).unwrap();
+    assert!(has_path_inputs(temp.path()));
+  }
+
+  #[test]
+  #[serial]
+  fn repeated_evaluation_hits_cache() {
+    let temp = TempDir::new().unwrap();
+    temp_env::with_vars(
+      [
+        ("SYSLUA_STORE", Some(temp.path().join("store").to_str().unwrap())),
+        ("XDG_DATA_HOME", Some(temp.path().join("data").to_str().unwrap())),
+      ],
+      || {
+        let config = temp.path().join("init.lua");
+        std::fs::write(&config, EMPTY_CONFIG).unwrap();
+
+        let mut cache = EvalCache::new();
+        let options = EvalOptions::default();
+        cache.evaluate(&config, &options).unwrap();
+        cache.evaluate(&config, &options).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        let impure = EvalOptions {
+          impure: true,
+          ..Default::default()
+        };
+        cache.evaluate(&config, &impure).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+        assert_eq!(cache.len(), 1);
+      },
+    );
+  }
+}
@@ -0,0 +1,124 @@
+//! Blocking daemon client.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+#[cfg(windows)]
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use super::{DaemonError, DaemonRequest, DaemonResponse, NO_DAEMON_ENV, environment_id};
+use crate::platform::paths::{daemon_socket_path, store_dir, system_daemon_socket_path};
+
+/// How long to wait for a busy named pipe to accept the connection.
+#[cfg(windows)]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a busy named pipe is tried again.
+#[cfg(windows)]
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Client for a daemon listening on a socket (or named pipe).
+#[derive(Debug, Clone)]
+pub struct DaemonClient {
+  path: PathBuf,
+}
+
+impl DaemonClient {
+  /// Create a client for the daemon at `path`, without connecting.
+  pub fn new(path: impl Into<PathBuf>) -> Self {
+    Self { path: path.into() }
+  }
+
+  /// Find a running daemon that commands should be delegated to.
+  ///
+  /// Returns `None` if delegation is disabled via `SYSLUA_NO_DAEMON`, no
+  /// daemon is listening, or the daemon serves a different store or syslua
+  /// version, or runs in a different working directory or environment than
+  /// this process.
+  pub fn detect() -> Option<Self> {
+    if std::env::var_os(NO_DAEMON_ENV).is_some() {
+      return None;
+    }
+
+    let client = Self::new(daemon_socket_path());
+    match client.request(&DaemonRequest::Status) {
+      Ok(DaemonResponse::Status(status))
+        if status.store == store_dir() && status.version == env!("CARGO_PKG_VERSION") =>
+      {
+        if status.environment != environment_id() {
+          debug!(
+            pid = status.pid,
+            "ignoring daemon running in a different directory or environment"
+          );
+          return None;
+        }
+        debug!(pid = status.pid, "delegating to running daemon");
+        Some(client)
+      }
+      Ok(_) => {
+        debug!("ignoring daemon for a different store or version");
+        None
+      }
+      Err(e) => {
+        debug!(error = %e, "no daemon available");
+        None
+      }
+    }
+  }
+
+  /// Create a client for the system daemon, without connecting.
+  pub fn system() -> Self {
+    Self::new(system_daemon_socket_path())
+  }
+
+  /// The socket (or named pipe) this client connects to.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Send a request and wait for the response.
+  pub fn request(&self, request: &DaemonRequest) -> Result<DaemonResponse, DaemonError> {
+    let mut stream = connect(&self.path)?;
+
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    stream.flush()?;
+
+    let mut response = String::new();
+    if BufReader::new(stream).read_line(&mut response)? == 0 {
+      return Err(DaemonError::Closed);
+    }
+    Ok(serde_json::from_str(&response)?)
+  }
+}
+
+#[cfg(unix)]
+fn connect(path: &Path) -> std::io::Result<std::os::unix::net::UnixStream> {
+  std::os::unix::net::UnixStream::connect(path)
+}
+
+/// Connect to the named pipe at `path`, waiting up to [`CONNECT_TIMEOUT`]
+/// while every instance of it is busy.
+#[cfg(windows)]
+fn connect(path: &Path) -> std::io::Result<std::fs::File> {
+  // ERROR_PIPE_BUSY: the daemon hasn't created the next instance yet
+  const ERROR_PIPE_BUSY: i32 = 231;
+
+  let deadline = Instant::now() + CONNECT_TIMEOUT;
+  loop {
+    match std::fs::OpenOptions::new().read(true).write(true).open(path) {
+      Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+        if Instant::now() >= deadline {
+          return Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("timed out waiting for {}", path.display()),
+          ));
+        }
+        std::thread::sleep(CONNECT_RETRY_INTERVAL);
+      }
+      result => return result,
+    }
+  }
+}
@@ -0,0 +1,206 @@
+//! Long-running daemon for fast repeated operations.
+//!
+//! Evaluating a config (Lua startup, input resolution) dominates the cost of
+//! small applies. `sys daemon start` keeps a process running that serves
+//! plan/apply/status requests over a unix socket (a named pipe on Windows)
+//! and caches evaluated manifests between requests. The CLI detects a running
+//! daemon for the same store and delegates to it, as long as the daemon runs
+//! in the same working directory and environment (see [`environment_id`]);
+//! otherwise the command runs in the CLI, as it would without a daemon.
+//!
+//! # System Daemon
+//!
+//! `sys daemon start --system` runs a root-owned daemon for the system store
+//! that every local user can connect to, like `nix-daemon`. Unprivileged
+//! users evaluate their config themselves and submit the manifest
+//! (`sys apply --system`); the daemon checks it against its
+//! [`DaemonPolicy`] before realizing builds or applying binds as root. The
+//! daemon can be socket-activated by systemd or launched on demand.
+//!
+//! # Protocol
+//!
+//! One request per connection: the client writes a single JSON-encoded
+//! [`DaemonRequest`] followed by a newline, and the daemon answers with a
+//! single JSON-encoded [`DaemonResponse`] line.
+//!
+//! # Modules
+//!
+//! - [`cache`]: Evaluation cache keyed by config file fingerprints
+//! - [`client`]: Blocking client used by the CLI
+//! - [`policy`]: Who may use the system daemon, and how
+//! - [`server`]: The request loop
+
+pub mod cache;
+pub mod client;
+pub mod policy;
+pub mod server;
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::api::{ApiError, ApplyRequest, ApplyResponse, PlanRequest, PlanResponse};
+use crate::execute::DagResult;
+use crate::manifest::ManifestExport;
+
+pub use cache::EvalCache;
+pub use client::DaemonClient;
+pub use policy::{DaemonPolicy, Peer, PolicyError};
+pub use server::{serve, serve_system};
+
+/// Environment variable that disables delegation to a running daemon.
+pub const NO_DAEMON_ENV: &str = "SYSLUA_NO_DAEMON";
+
+/// Variables shells update on their own, left out of [`environment_id`].
+const SHELL_VARS: &[&str] = &["_", "OLDPWD", "PWD", "SHLVL"];
+
+/// Errors from the daemon transport.
+#[derive(Debug, thiserror::Error)]
+pub enum DaemonError {
+  #[error("daemon io error: {0}")]
+  Io(#[from] std::io::Error),
+
+  #[error("malformed daemon message: {0}")]
+  Protocol(#[from] serde_json::Error),
+
+  #[error("a daemon is already listening on {0}")]
+  AlreadyRunning(PathBuf),
+
+  #[error("daemon closed the connection without responding")]
+  Closed,
+}
+
+/// A request sent to the daemon.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum DaemonRequest {
+  /// Report daemon status.
+  Status,
+  /// Plan a config against the current snapshot.
+  Plan(PlanRequest),
+  /// Apply a config.
+  Apply(ApplyRequest),
+  /// Realize (and unless `build_only`, apply) a manifest evaluated by the
+  /// client. Used with the system daemon.
+  Submit(SubmitRequest),
+  /// Stop the daemon after responding.
+  Shutdown,
+}
+
+/// A response from the daemon.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum DaemonResponse {
+  /// Answer to [`DaemonRequest::Status`].
+  Status(DaemonStatus),
+  /// Answer to [`DaemonRequest::Plan`].
+  Plan(Box<PlanResponse>),
+  /// Answer to [`DaemonRequest::Apply`] and [`DaemonRequest::Submit`].
+  Apply(Box<ApplyResponse>),
+  /// Answer to a `build_only` [`DaemonRequest::Submit`].
+  Built(Box<DagResult>),
+  /// The policy of the system daemon refused the request.
+  Denied(String),
+  /// The operation failed.
+  Error(ApiError),
+  /// The request could not be parsed.
+  BadRequest(String),
+  /// The daemon is stopping.
+  ShuttingDown,
+}
+
+/// A manifest submitted to the system daemon.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubmitRequest {
+  /// The manifest, as written by `sys eval`. Its `config` is recorded as the
+  /// applied config.
+  pub export: ManifestExport,
+  /// Only realize the builds; binds are left alone.
+  #[serde(default)]
+  pub build_only: bool,
+  /// Check unchanged binds for drift and repair if needed.
+  #[serde(default)]
+  pub repair: bool,
+}
+
+/// Information about a running daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+  /// Process ID of the daemon.
+  pub pid: u32,
+  /// syslua version the daemon was built from.
+  pub version: String,
+  /// Store the daemon operates on.
+  pub store: PathBuf,
+  /// Whether this is the system daemon serving every local user.
+  #[serde(default)]
+  pub system: bool,
+  /// Seconds since the daemon started.
+  pub uptime_secs: u64,
+  /// Number of requests handled, including this one.
+  pub requests_served: u64,
+  /// Number of configs with a cached evaluation.
+  pub cached_configs: usize,
+  /// Evaluations served from the cache.
+  pub cache_hits: u64,
+  /// Evaluations that ran the config.
+  pub cache_misses: u64,
+  /// [`environment_id`] of the daemon process.
+  #[serde(default)]
+  pub environment: String,
+}
+
+/// Identify the working directory and environment of this process.
+///
+/// Configs see both (`sys.getenv` placeholders, relative paths, impure
+/// evaluation) and builds and binds inherit them, so a command is only
+/// delegated to a daemon whose id matches the CLI's. The id is a hash, so a
+/// status reply doesn't reveal the daemon's environment.
+pub fn environment_id() -> String {
+  let mut hasher = Sha256::new();
+  if let Ok(cwd) = std::env::current_dir() {
+    hasher.update(cwd.as_os_str().as_encoded_bytes());
+  }
+  let vars: BTreeMap<OsString, OsString> = std::env::vars_os()
+    .filter(|(name, _)| !SHELL_VARS.iter().any(|shell| name == shell))
+    .collect();
+  for (name, value) in vars {
+    hasher.update(b"\0");
+    hasher.update(name.as_encoded_bytes());
+    hasher.update(b"=");
+    hasher.update(value.as_encoded_bytes());
+  }
+  hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serial_test::serial;
+
+  #[test]
+  fn request_roundtrips_with_op_tag() {
+    let request = DaemonRequest::Plan(PlanRequest::new("/etc/syslua/init.lua"));
+    let json = serde_json::to_string(&request).unwrap();
+    assert!(json.starts_with(r#"{"op":"plan","config":"/etc/syslua/init.lua""#));
+    assert_eq!(serde_json::from_str::<DaemonRequest>(&json).unwrap(), request);
+
+    let json = serde_json::to_string(&DaemonRequest::Shutdown).unwrap();
+    assert_eq!(json, r#"{"op":"shutdown"}"#);
+  }
+
+  #[test]
+  #[serial]
+  fn environment_id_tracks_variables_but_not_shell_bookkeeping() {
+    let base = environment_id();
+    assert_eq!(environment_id(), base);
+
+    temp_env::with_var("SHLVL", Some("42"), || assert_eq!(environment_id(), base));
+    temp_env::with_var("SYSLUA_TEST_DAEMON_ENV", Some("1"), || {
+      assert_ne!(environment_id(), base)
+    });
+  }
+}
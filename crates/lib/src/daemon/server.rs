@@ -0,0 +1,581 @@
+//! Daemon request loop.
+//!
+//! Requests are handled one at a time: applies take the exclusive store lock
+//! anyway, and serializing keeps the evaluation cache single-threaded. Each
+//! connection's request is read on a task of its own, with a timeout, so a
+//! client that connects and sends nothing holds up no one else.
+//!
+//! Since the system daemon's socket is open to every local user, what
+//! connections can make it hold is bounded: requests are at most
+//! `MAX_REQUEST_BYTES`, and at most `MAX_CONNECTIONS` are read or waiting
+//! for their turn at once. Connections beyond that are closed right away.
+//!
+//! The system daemon ([`serve_system`]) identifies the user of every
+//! connection from its socket credentials and checks each request against
+//! its [`DaemonPolicy`] first.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc::{Sender, channel};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, info, warn};
+
+use super::policy::{Access, DaemonPolicy, Peer};
+use super::{DaemonError, DaemonRequest, DaemonResponse, DaemonStatus, EvalCache, SubmitRequest, environment_id};
+use crate::api::{ApiError, ApplyRequest, ApplyResponse, PlanRequest, PlanResponse};
+use crate::build::users::BuildUsers;
+use crate::execute::{self, ApplyOptions, DagResult, ExecuteConfig};
+use crate::platform::paths::store_dir;
+use crate::store_lock::{LockMode, StoreLock};
+
+/// How long a client may take to send its request once connected.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a client may take to take its response.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest request read; larger ones are answered with a bad request.
+const MAX_REQUEST_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Connections read or queued at once.
+const MAX_CONNECTIONS: usize = 32;
+
+/// A request read from a connection, waiting for its turn.
+struct Incoming {
+  /// The request, or why it couldn't be parsed.
+  request: Result<DaemonRequest, String>,
+  /// The user on the other end, when the transport tells.
+  peer: Option<Peer>,
+  writer: Box<dyn AsyncWrite + Unpin + Send>,
+  /// The connection's slot, freed once it has been answered.
+  _permit: OwnedSemaphorePermit,
+}
+
+/// Admits connections up to [`MAX_CONNECTIONS`] and queues their requests.
+struct Intake {
+  slots: Arc<Semaphore>,
+  queue: Sender<Incoming>,
+}
+
+impl Intake {
+  fn new() -> (Self, tokio::sync::mpsc::Receiver<Incoming>) {
+    let (queue, requests) = channel(MAX_CONNECTIONS);
+    let intake = Self {
+      slots: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
+      queue,
+    };
+    (intake, requests)
+  }
+
+  /// Read the request of `stream` if there is a free slot; close it otherwise.
+  fn admit<S>(&self, stream: S, peer: Option<Peer>)
+  where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+  {
+    match self.slots.clone().try_acquire_owned() {
+      Ok(permit) => read_request(stream, peer, permit, self.queue.clone()),
+      Err(_) => warn!(uid = ?peer.as_ref().map(|p| p.uid), "too many daemon connections, closing one"),
+    }
+  }
+}
+
+/// Read the request of `stream` on a task of its own and queue it.
+///
+/// Clients that don't send a full request within [`READ_TIMEOUT`] are
+/// dropped without a response.
+fn read_request<S>(stream: S, peer: Option<Peer>, permit: OwnedSemaphorePermit, queue: Sender<Incoming>)
+where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  tokio::spawn(async move {
+    let (reader, writer) = tokio::io::split(stream);
+    let mut line = String::new();
+    let read = BufReader::new(reader.take(MAX_REQUEST_BYTES)).read_line(&mut line);
+    let request = match tokio::time::timeout(READ_TIMEOUT, read).await {
+      Ok(Ok(n)) if n as u64 >= MAX_REQUEST_BYTES && !line.ends_with('\n') => {
+        Err(format!("request is larger than {} bytes", MAX_REQUEST_BYTES))
+      }
+      Ok(Ok(_)) => serde_json::from_str::<DaemonRequest>(&line).map_err(|e| e.to_string()),
+      Ok(Err(e)) => {
+        warn!(error = %e, "failed to read daemon request");
+        return;
+      }
+      Err(_) => {
+        warn!(uid = ?peer.as_ref().map(|p| p.uid), "daemon client sent no request in time");
+        return;
+      }
+    };
+    let _ = queue
+      .send(Incoming {
+        request,
+        peer,
+        writer: Box::new(writer),
+        _permit: permit,
+      })
+      .await;
+  });
+}
+
+/// State kept warm between requests.
+struct Daemon {
+  started: Instant,
+  requests_served: u64,
+  cache: EvalCache,
+  /// Access policy; only the system daemon has one.
+  policy: Option<DaemonPolicy>,
+  /// Users the builds of untrusted users run as, from the policy's group.
+  build_users: Option<BuildUsers>,
+}
+
+impl Daemon {
+  fn new(policy: Option<DaemonPolicy>) -> Self {
+    let build_users = policy
+      .as_ref()
+      .and_then(|policy| match BuildUsers::from_group(&policy.build_group) {
+        Ok(users) => Some(users),
+        Err(e) => {
+          warn!(error = %e, "untrusted users can't submit builds");
+          None
+        }
+      });
+    Self {
+      started: Instant::now(),
+      requests_served: 0,
+      cache: EvalCache::new(),
+      policy,
+      build_users,
+    }
+  }
+
+  /// Handle a request read from a connection and write the response.
+  ///
+  /// Returns false once a shutdown has been requested.
+  async fn respond(&mut self, incoming: Incoming) -> bool {
+    let Incoming {
+      request,
+      peer,
+      mut writer,
+      _permit,
+    } = incoming;
+    let response = match request {
+      Ok(request) => self.handle(request, peer.as_ref()).await,
+      Err(e) => DaemonResponse::BadRequest(e),
+    };
+    let keep_running = !matches!(response, DaemonResponse::ShuttingDown);
+
+    let result = async {
+      let mut out = serde_json::to_vec(&response)?;
+      out.push(b'\n');
+      writer.write_all(&out).await?;
+      writer.flush().await?;
+      Ok::<_, DaemonError>(())
+    };
+    match tokio::time::timeout(WRITE_TIMEOUT, result).await {
+      Ok(Ok(())) => {}
+      Ok(Err(e)) => warn!(error = %e, "failed to write daemon response"),
+      Err(_) => warn!("timed out writing daemon response"),
+    }
+
+    keep_running
+  }
+
+  async fn handle(&mut self, request: DaemonRequest, peer: Option<&Peer>) -> DaemonResponse {
+    self.requests_served += 1;
+    debug!(?request, ?peer, "handling daemon request");
+
+    let access = match (&self.policy, peer) {
+      (None, _) => Access::Full,
+      (Some(policy), Some(peer)) => match policy.authorize(peer, &request) {
+        Ok(access) => access,
+        Err(e) => {
+          warn!(uid = peer.uid, error = %e, "refused daemon request");
+          return DaemonResponse::Denied(e.to_string());
+        }
+      },
+      (Some(_), None) => return DaemonResponse::Denied("cannot identify the connecting user".to_string()),
+    };
+
+    match request {
+      DaemonRequest::Status => DaemonResponse::Status(self.status()),
+      DaemonRequest::Plan(request) => match self.plan(&request).await {
+        Ok(response) => DaemonResponse::Plan(Box::new(response)),
+        Err(e) => DaemonResponse::Error(e),
+      },
+      DaemonRequest::Apply(request) => match self.apply(&request).await {
+        Ok(result) => DaemonResponse::Apply(Box::new(result)),
+        Err(e) => DaemonResponse::Error(e),
+      },
+      DaemonRequest::Submit(request) => self.submit(&request, access).await,
+      DaemonRequest::Shutdown => DaemonResponse::ShuttingDown,
+    }
+  }
+
+  fn status(&self) -> DaemonStatus {
+    DaemonStatus {
+      pid: std::process::id(),
+      version: env!("CARGO_PKG_VERSION").to_string(),
+      store: store_dir(),
+      system: self.policy.is_some(),
+      uptime_secs: self.started.elapsed().as_secs(),
+      requests_served: self.requests_served,
+      cached_configs: self.cache.len(),
+      cache_hits: self.cache.hits(),
+      cache_misses: self.cache.misses(),
+      environment: environment_id(),
+    }
+  }
+
+  async fn plan(&mut self, request: &PlanRequest) -> Result<PlanResponse, ApiError> {
+    let manifest = self.cache.evaluate(&request.config, &request.eval_options())?;
+    let report = execute::plan(&request.config, &request.plan_options(Some(manifest))).await?;
+    Ok(report.into())
+  }
+
+  async fn apply(&mut self, request: &ApplyRequest) -> Result<ApplyResponse, ApiError> {
+    let manifest = self.cache.evaluate(&request.config, &request.eval_options())?;
+    let result = execute::apply(&request.config, &request.apply_options(Some(manifest))).await?;
+    Ok(result.into())
+  }
+
+  async fn submit(&mut self, request: &SubmitRequest, access: Access) -> DaemonResponse {
+    if let Err(e) = request.validate() {
+      return DaemonResponse::Denied(e.to_string());
+    }
+    let manifest = request.export.manifest.clone();
+
+    if request.build_only {
+      // Untrusted builds run as build users and may not weaken the store
+      let config = match (access, &self.policy) {
+        (Access::BuildsOnly, Some(policy)) => match &self.build_users {
+          Some(users) => ExecuteConfig {
+            build_users: Some(users.clone()),
+            ..Default::default()
+          },
+          None => {
+            return DaemonResponse::Denied(format!(
+              "the daemon has no build users (members of group '{}') to run untrusted builds as",
+              policy.build_group
+            ));
+          }
+        },
+        _ => ExecuteConfig::default(),
+      };
+      return match build(&manifest, &config).await {
+        Ok(result) => DaemonResponse::Built(Box::new(result)),
+        Err(e) => DaemonResponse::Error(e),
+      };
+    }
+
+    let Some(config) = request.export.config.as_deref().map(PathBuf::from) else {
+      return DaemonResponse::Denied("the submitted manifest does not name its config".to_string());
+    };
+    let options = ApplyOptions {
+      repair: request.repair,
+      manifest: Some(manifest),
+      ..Default::default()
+    };
+    match execute::apply(&config, &options).await {
+      Ok(result) => DaemonResponse::Apply(Box::new(result.into())),
+      Err(e) => DaemonResponse::Error(e.into()),
+    }
+  }
+}
+
+/// Realize the builds of `manifest` under the store lock.
+async fn build(manifest: &crate::manifest::Manifest, config: &ExecuteConfig) -> Result<DagResult, ApiError> {
+  let _lock = StoreLock::acquire(LockMode::Exclusive, "build")?;
+  execute::execute_builds(manifest, config)
+    .await
+    .map_err(|e| ApiError::Execute(e.to_string()))
+}
+
+/// Serve requests on `path` until a shutdown request arrives.
+///
+/// On Unix `path` is a socket file, created with owner-only permissions since
+/// the daemon acts with the privileges of the user running it. A stale socket
+/// left by a crashed daemon is replaced.
+#[cfg(unix)]
+pub async fn serve(path: &Path) -> Result<(), DaemonError> {
+  let listener = bind_socket(path, 0o600)?;
+  run(listener, Daemon::new(None), Some(path)).await
+}
+
+/// Serve the system store to every local user until a trusted user asks the
+/// daemon to stop.
+///
+/// The socket at `path` is open to everyone; `policy` decides what each user
+/// may do. When started by systemd socket activation (`LISTEN_FDS`), the
+/// inherited socket is used instead of `path` and left in place on exit.
+#[cfg(unix)]
+pub async fn serve_system(path: &Path, policy: DaemonPolicy) -> Result<(), DaemonError> {
+  let daemon = Daemon::new(Some(policy));
+  match activated_listener()? {
+    Some(listener) => run(listener, daemon, None).await,
+    None => run(bind_socket(path, 0o666)?, daemon, Some(path)).await,
+  }
+}
+
+/// Listen on the socket `path` with permissions `mode`.
+#[cfg(unix)]
+fn bind_socket(path: &Path, mode: u32) -> Result<tokio::net::UnixListener, DaemonError> {
+  use std::os::unix::fs::PermissionsExt;
+
+  if path.exists() {
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+      return Err(DaemonError::AlreadyRunning(path.to_path_buf()));
+    }
+    std::fs::remove_file(path)?;
+  }
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+
+  let listener = tokio::net::UnixListener::bind(path)?;
+  std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+  info!(path = %path.display(), "daemon listening");
+  Ok(listener)
+}
+
+/// The listening socket passed by systemd socket activation, if any.
+#[cfg(unix)]
+fn activated_listener() -> Result<Option<tokio::net::UnixListener>, DaemonError> {
+  use std::os::fd::FromRawFd;
+
+  /// First descriptor systemd passes (`SD_LISTEN_FDS_START`).
+  const LISTEN_FDS_START: i32 = 3;
+
+  let ours = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) == Some(std::process::id());
+  let count: u32 = std::env::var("LISTEN_FDS")
+    .ok()
+    .and_then(|n| n.parse().ok())
+    .unwrap_or(0);
+  if !ours || count == 0 {
+    return Ok(None);
+  }
+
+  // SAFETY: systemd hands this process ownership of the descriptors from 3 on
+  let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(LISTEN_FDS_START) };
+  listener.set_nonblocking(true)?;
+  info!("daemon listening on the socket passed by systemd");
+  Ok(Some(tokio::net::UnixListener::from_std(listener)?))
+}
+
+/// Accept connections until a shutdown request, then remove the socket at
+/// `path`, if given.
+#[cfg(unix)]
+async fn run(listener: tokio::net::UnixListener, mut daemon: Daemon, path: Option<&Path>) -> Result<(), DaemonError> {
+  use crate::platform::run_as::user_name;
+
+  let (intake, mut requests) = Intake::new();
+  let result = loop {
+    tokio::select! {
+      accepted = listener.accept() => match accepted {
+        Ok((stream, _)) => {
+          let peer = stream.peer_cred().ok().map(|cred| Peer {
+            uid: cred.uid(),
+            name: user_name(cred.uid()),
+          });
+          intake.admit(stream, peer);
+        }
+        Err(e) => break Err(e.into()),
+      },
+      Some(incoming) = requests.recv() => {
+        if !daemon.respond(incoming).await {
+          break Ok(());
+        }
+      }
+    }
+  };
+
+  if let Some(path) = path {
+    let _ = std::fs::remove_file(path);
+  }
+  info!("daemon stopped");
+  result
+}
+
+/// Serve requests on the named pipe `path` until a shutdown request arrives.
+#[cfg(windows)]
+pub async fn serve(path: &Path) -> Result<(), DaemonError> {
+  use tokio::net::windows::named_pipe::ServerOptions;
+
+  let mut server = ServerOptions::new()
+    .first_pipe_instance(true)
+    .create(path)
+    .map_err(|e| match e.kind() {
+      std::io::ErrorKind::PermissionDenied => DaemonError::AlreadyRunning(path.to_path_buf()),
+      _ => e.into(),
+    })?;
+  info!(path = %path.display(), "daemon listening");
+
+  let mut daemon = Daemon::new(None);
+  let (intake, mut requests) = Intake::new();
+  loop {
+    tokio::select! {
+      connected = server.connect() => {
+        connected?;
+        let connection = server;
+        server = ServerOptions::new().create(path)?;
+        intake.admit(connection, None);
+      }
+      Some(incoming) = requests.recv() => {
+        if !daemon.respond(incoming).await {
+          break;
+        }
+      }
+    }
+  }
+
+  info!("daemon stopped");
+  Ok(())
+}
+
+/// The system daemon needs peer credentials, which named pipes don't provide here.
+#[cfg(windows)]
+pub async fn serve_system(_path: &Path, _policy: DaemonPolicy) -> Result<(), DaemonError> {
+  Err(DaemonError::Io(std::io::Error::new(
+    std::io::ErrorKind::Unsupported,
+    "the system daemon is not supported on Windows",
+  )))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use crate::daemon::DaemonClient;
+  use tempfile::TempDir;
+
+  #[test]
+  fn serves_status_until_shutdown() {
+    let temp = TempDir::new().unwrap();
+    let socket = temp.path().join("daemon.sock");
+
+    let server = {
+      let socket = socket.clone();
+      std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(serve(&socket))
+      })
+    };
+    while !socket.exists() {
+      std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    let client = DaemonClient::new(&socket);
+    match client.request(&DaemonRequest::Status).unwrap() {
+      DaemonResponse::Status(status) => assert_eq!(status.requests_served, 1),
+      other => panic!("unexpected response: {:?}", other),
+    }
+    assert!(matches!(
+      client.request(&DaemonRequest::Shutdown).unwrap(),
+      DaemonResponse::ShuttingDown
+    ));
+
+    server.join().unwrap().unwrap();
+    assert!(!socket.exists());
+  }
+
+  #[test]
+  fn idle_clients_hold_up_no_one() {
+    let temp = TempDir::new().unwrap();
+    let socket = temp.path().join("daemon.sock");
+
+    let server = {
+      let socket = socket.clone();
+      std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(serve(&socket))
+      })
+    };
+    while !socket.exists() {
+      std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    // Connected without sending anything
+    let _idle = std::os::unix::net::UnixStream::connect(&socket).unwrap();
+    let client = DaemonClient::new(&socket);
+    assert!(matches!(
+      client.request(&DaemonRequest::Status).unwrap(),
+      DaemonResponse::Status(_)
+    ));
+    client.request(&DaemonRequest::Shutdown).unwrap();
+    server.join().unwrap().unwrap();
+  }
+
+  #[test]
+  fn connections_and_requests_are_bounded() {
+    use std::io::{BufRead, Write};
+
+    let temp = TempDir::new().unwrap();
+    let socket = temp.path().join("daemon.sock");
+
+    let server = {
+      let socket = socket.clone();
+      std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(serve(&socket))
+      })
+    };
+    while !socket.exists() {
+      std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    // An oversized request is refused without being read in full
+    let mut oversized = std::os::unix::net::UnixStream::connect(&socket).unwrap();
+    let chunk = vec![b'x'; 64 * 1024];
+    let mut sent = 0;
+    while sent <= MAX_REQUEST_BYTES && oversized.write_all(&chunk).is_ok() {
+      sent += chunk.len() as u64;
+    }
+    let mut response = String::new();
+    std::io::BufReader::new(&oversized).read_line(&mut response).unwrap();
+    assert!(response.contains("larger than"), "{}", response);
+
+    // Idle connections take every slot; the next one is closed
+    let idle: Vec<_> = (0..MAX_CONNECTIONS)
+      .map(|_| std::os::unix::net::UnixStream::connect(&socket).unwrap())
+      .collect();
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert!(DaemonClient::new(&socket).request(&DaemonRequest::Status).is_err());
+
+    drop(idle);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let client = DaemonClient::new(&socket);
+    client.request(&DaemonRequest::Shutdown).unwrap();
+    server.join().unwrap().unwrap();
+  }
+
+  #[tokio::test]
+  async fn system_daemon_checks_the_policy() {
+    let mut daemon = Daemon::new(Some(DaemonPolicy::default()));
+    let alice = Peer {
+      uid: 1001,
+      name: Some("alice".to_string()),
+    };
+
+    match daemon.handle(DaemonRequest::Status, Some(&alice)).await {
+      DaemonResponse::Status(status) => assert!(status.system),
+      other => panic!("unexpected response: {:?}", other),
+    }
+    match daemon.handle(DaemonRequest::Shutdown, Some(&alice)).await {
+      DaemonResponse::Denied(message) => assert!(message.contains("alice"), "{}", message),
+      other => panic!("unexpected response: {:?}", other),
+    }
+    assert!(matches!(
+      daemon.handle(DaemonRequest::Shutdown, None).await,
+      DaemonResponse::Denied(_)
+    ));
+
+    let root = Peer {
+      uid: 0,
+      name: Some("root".to_string()),
+    };
+    assert!(matches!(
+      daemon.handle(DaemonRequest::Shutdown, Some(&root)).await,
+      DaemonResponse::ShuttingDown
+    ));
+  }
+}
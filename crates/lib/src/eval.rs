@@ -5,18 +5,37 @@
 //! builds and bindings defined in the configuration.
 
 use std::cell::RefCell;
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use mlua::prelude::*;
-use tracing::{debug, info};
-
+use tracing::{debug, info, warn};
+
+use crate::action::{DEFAULT_SHELL_REGISTRY_KEY, RUN_AS_USERS_REGISTRY_KEY};
+use crate::bind::repair::REPAIR_IGNORE_REGISTRY_KEY;
+use crate::bind::{BACKUP_MAX_SIZE_REGISTRY_KEY, parse_backup_max_size};
+use crate::build::overrides::finish_build_overrides;
+use crate::consts::OBJ_HASH_PREFIX_LEN;
+use crate::execute::hooks::{ApplyHooks, HookCommand};
 use crate::init::update_luarc_inputs;
-use crate::inputs::resolve::{ResolveError, resolve_inputs, save_lock_file_if_changed};
+use crate::inputs::fetch::Fetchers;
+use crate::inputs::lock::LockError;
+use crate::inputs::resolve::{ResolveError, resolve_inputs_with, save_lock_file_if_changed};
 use crate::inputs::{InputDecl, InputDecls, InputOverride, ResolvedInput, ResolvedInputs};
-use crate::lua::runtime;
-use crate::manifest::Manifest;
-use crate::platform;
+use crate::lua::entrypoint::parse_fetch_settings;
+use crate::lua::globals::set_sys_platform;
+use crate::lua::sandbox::{self, UntrustedInputs};
+use crate::lua::{api, io, runtime};
+use crate::manifest::{
+  HASH_SPEC_REGISTRY_KEY, Manifest, PLACEHOLDER_MODE_REGISTRY_KEY, PlaceholderMode, finish_strict_placeholders,
+  registry_hash_spec,
+};
+use crate::platform::paths::expand_path;
+use crate::platform::priority::{MAX_NICE, Throttle};
+use crate::platform::{self, Facts, Platform, Shell};
+use crate::snapshot::{Snapshot, SnapshotStore, set_sys_current};
+use crate::util::hash::{HashAlgorithm, HashMemo, HashSpec};
 
 /// Errors that can occur during config evaluation.
 #[derive(Debug, thiserror::Error)]
@@ -28,6 +47,16 @@ pub enum EvalError {
   /// Input resolution error.
   #[error("input resolution error: {0}")]
   InputResolution(#[from] ResolveError),
+
+  /// The answers of eval-time IO helpers could not be recorded.
+  #[error("failed to record eval-time IO answers: {0}")]
+  RecordIo(#[source] LockError),
+
+  /// A config evaluated for another platform was about to be applied.
+  #[error(
+    "config is evaluated for {platform}, but this host is {host}; only `sys eval` and `sys plan` accept another platform"
+  )]
+  ForeignPlatform { platform: Platform, host: String },
 }
 
 /// Options for config evaluation.
@@ -35,18 +64,85 @@ pub enum EvalError {
 pub struct EvalOptions {
   /// Allow impure Lua libs (io, os). Breaks determinism but useful for tests.
   pub impure: bool,
+
+  /// Replacement URLs for root inputs (name -> URL). Overridden inputs are
+  /// resolved without consulting or updating the lock file.
+  pub input_overrides: BTreeMap<String, String>,
+
+  /// How to evaluate inputs that aren't declared `trusted = true`.
+  pub untrusted_inputs: UntrustedInputs,
+
+  /// Host vars file to load instead of `host_vars/<hostname>.lua`.
+  pub vars_file: Option<PathBuf>,
+
+  /// Platform to evaluate for instead of this host's. `sys.platform`,
+  /// `sys.os` and `sys.arch` report it, `sys.facts` are assumed (see
+  /// [`Facts::for_platform`]) and `sys.current` is empty.
+  pub platform: Option<Platform>,
+}
+
+impl EvalOptions {
+  /// Returns true if the config is evaluated for another platform than this host's.
+  pub fn is_foreign(&self) -> bool {
+    self
+      .platform
+      .is_some_and(|platform| Some(platform) != Platform::current())
+  }
+
+  /// Fail if the config is evaluated for another platform than this host's.
+  ///
+  /// Its manifest can be inspected or exported, but not applied here.
+  pub fn ensure_host_platform(&self) -> Result<(), EvalError> {
+    match self.platform {
+      Some(platform) if self.is_foreign() => Err(EvalError::ForeignPlatform {
+        platform,
+        host: platform::platform_triple().unwrap_or_else(|| "unsupported".to_string()),
+      }),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Directory next to the config holding per-host vars files.
+pub const HOST_VARS_DIR: &str = "host_vars";
+
+/// The vars file evaluation loads into `sys.vars`, if any.
+///
+/// An explicit `options.vars_file` wins. Otherwise `host_vars/<hostname>.lua`
+/// next to the config is used, falling back to the short hostname (up to the
+/// first `.`) so `laptop.local` also finds `laptop.lua`.
+pub fn vars_file(config_dir: &Path, options: &EvalOptions) -> Option<PathBuf> {
+  if let Some(path) = &options.vars_file {
+    return Some(path.clone());
+  }
+
+  let hostname = platform::hostname()?;
+  let short = hostname.split('.').next().unwrap_or(&hostname);
+  [hostname.as_str(), short]
+    .into_iter()
+    .map(|name| config_dir.join(HOST_VARS_DIR).join(format!("{name}.lua")))
+    .find(|path| path.is_file())
 }
 
 /// Evaluate a Lua configuration file and return the resulting manifest.
 ///
 /// This function:
 /// 1. Creates a new Lua runtime with the `sys` global
-/// 2. Loads and executes the configuration file
+/// 2. Sets `sys.platform` and `sys.facts` for `options.platform`, if given,
+///    loads the host vars file (see [`vars_file`]) into `sys.vars` and the
+///    current snapshot into `sys.current`, then
+///    loads and executes the configuration file
 /// 3. Resolves all declared inputs (fetching git repos, resolving paths)
 /// 4. Builds package.path from all inputs' `lua/` directories
-/// 5. Calls each input's `setup(inputs)` function in dependency order
-/// 6. Calls the root config's `setup(inputs)` function last
-/// 7. Returns the manifest containing all registered builds and bindings
+/// 5. Calls each input's `setup(inputs)` function in dependency order,
+///    sandboxing untrusted inputs according to `options.untrusted_inputs`
+/// 6. Calls the root config's `setup(inputs)` function last, then fails if a
+///    `sys.override_build` matched no build or a strict placeholder doesn't
+///    resolve against the finished manifest, and stores the calls of
+///    deprecated helpers in the manifest (see [`crate::lua::api`])
+/// 7. Records the answers of the `sys.io` helpers it used in the lock file
+///    (see [`crate::lua::io`])
+/// 8. Returns the manifest containing all registered builds and bindings
 ///
 /// # Arguments
 /// * `path` - Path to the Lua configuration file
@@ -65,72 +161,429 @@ pub struct EvalOptions {
 /// println!("Bindings: {}", manifest.bindings.len());
 /// ```
 pub fn evaluate_config(path: &Path, options: &EvalOptions) -> Result<Manifest, EvalError> {
+  evaluate_config_with_inputs(path, options).map(|(manifest, _)| manifest)
+}
+
+/// Evaluate a Lua configuration file like [`evaluate_config`], also returning
+/// the inputs it resolved (empty when the config declares none).
+///
+/// Used by `sys info --licenses` to report the metadata of both inputs and builds.
+pub fn evaluate_config_with_inputs(
+  path: &Path,
+  options: &EvalOptions,
+) -> Result<(Manifest, ResolvedInputs), EvalError> {
   let manifest = Rc::new(RefCell::new(Manifest::default()));
-  let config_dir = path.parent().unwrap_or(Path::new("."));
 
-  {
+  let (resolved, hooks, throttle) = {
     let lua = runtime::create_runtime(manifest.clone(), options.impure)?;
-    let config = runtime::load_file(&lua, path)?;
-
-    // Config should return a table with { inputs, setup }
-    if let LuaValue::Table(config_table) = config {
-      // Get the setup function
-      let setup: LuaFunction = config_table
-        .get("setup")
-        .map_err(|_| LuaError::external("config must return a table with a 'setup' function"))?;
-
-      // Extract raw inputs table (supports both simple URLs and extended syntax)
-      let input_decls = extract_raw_inputs(&config_table)?;
-
-      // Resolve inputs (fetch git repos, resolve paths) with transitive dependencies
-      let resolved = if input_decls.is_empty() {
-        info!("no inputs to resolve");
-        None
-      } else {
-        info!(
-          count = input_decls.len(),
-          "resolving inputs with transitive dependencies"
-        );
-        let result = resolve_inputs(&input_decls, config_dir, None)?;
-
-        // Save lock file if it changed
-        save_lock_file_if_changed(&result, config_dir)?;
-
-        // Update .luarc.json with resolved input paths for LuaLS
-        let system = platform::is_elevated();
-        let input_paths: Vec<_> = result.inputs.values().map(|i| i.path.as_path()).collect();
-        update_luarc_inputs(config_dir, input_paths, system);
-
-        Some(result.inputs)
-      };
+    let prepared = prepare_config(&lua, path, options, InputResolution::Resolve)?;
+
+    // Call root config's setup(inputs) last
+    prepared.setup.call::<()>(prepared.inputs)?;
+    finish_build_overrides(&lua, &mut manifest.borrow_mut())?;
+    finish_strict_placeholders(&lua, &manifest.borrow())?;
+    manifest.borrow_mut().deprecations = api::take_deprecations(&lua);
+    io::save_answers(&lua, path.parent().unwrap_or(Path::new("."))).map_err(EvalError::RecordIo)?;
+
+    if let Some(memo) = lua.app_data_ref::<HashMemo>() {
+      debug!(
+        computed = memo.computed,
+        reused = memo.reused,
+        elapsed_ms = memo.elapsed.as_millis() as u64,
+        "hashed builds and binds"
+      );
+    }
 
-      // Build and set package.path from all lua/ directories
-      if let Some(ref inputs) = resolved {
-        let package_path = build_package_path(config_dir, inputs);
-        set_package_path(&lua, &package_path)?;
+    (prepared.resolved.unwrap_or_default(), prepared.hooks, prepared.throttle)
+    // lua is dropped here, releasing its references to manifest
+  };
 
-        // Call input setup() functions in dependency order
-        call_input_setups(&lua, inputs)?;
+  // Now we should have the only reference to manifest
+  let mut manifest = Rc::try_unwrap(manifest)
+    .expect("manifest still has references")
+    .into_inner();
+  manifest.hooks = hooks;
+  manifest.throttle = throttle;
+  manifest.inputs = resolved
+    .iter()
+    .map(|(name, input)| (name.clone(), input.rev.clone()))
+    .collect();
+  Ok((manifest, resolved))
+}
+
+/// A config loaded into a runtime, ready for its root `setup` to be called.
+pub(crate) struct PreparedConfig {
+  /// The root config's `setup` function.
+  pub setup: LuaFunction,
+  /// The Lua inputs table passed to `setup`.
+  pub inputs: LuaTable,
+  /// Resolved inputs, if the config declares any.
+  pub resolved: Option<ResolvedInputs>,
+  /// Audit hooks from `settings.hooks`.
+  pub hooks: ApplyHooks,
+  /// Lowered apply priority from `settings.nice` and `settings.background`.
+  pub throttle: Throttle,
+}
+
+/// How [`prepare_config`] obtains the config's inputs.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum InputResolution<'a> {
+  /// Resolve them, saving the lock file and `.luarc.json` when they change.
+  Resolve,
+  /// Resolve them without writing anything next to the config.
+  ReadOnly,
+  /// Reuse the inputs an earlier call resolved for the same config.
+  Reuse(Option<&'a ResolvedInputs>),
+}
+
+/// Load the config at `path` into `lua` and run everything up to its `setup`.
+///
+/// Loads host vars, applies settings, resolves inputs (see
+/// [`InputResolution`]), sets `package.path` and calls each input's
+/// `setup(inputs)` in dependency order.
+pub(crate) fn prepare_config(
+  lua: &Lua,
+  path: &Path,
+  options: &EvalOptions,
+  resolution: InputResolution<'_>,
+) -> Result<PreparedConfig, EvalError> {
+  let config_dir = path.parent().unwrap_or(Path::new("."));
+
+  // The target platform, host vars, sys.current and recorded sys.io answers come first so the config's top level can
+  // already use them. This host's snapshot says nothing about another platform's.
+  if let Some(platform) = options.platform.filter(|_| options.is_foreign()) {
+    let sys: LuaTable = lua.globals().get("sys")?;
+    set_sys_platform(lua, &sys, platform, &Facts::for_platform(platform))?;
+  }
+  io::load_answers(lua, config_dir);
+  if let Some(vars_path) = vars_file(config_dir, options) {
+    load_vars(lua, &vars_path)?;
+  }
+  let current = if options.is_foreign() { None } else { current_snapshot() };
+  set_sys_current(lua, current.as_ref())?;
+
+  let config = runtime::load_file(lua, path)?;
+
+  // Config should return a table with { inputs, setup }
+  let LuaValue::Table(config_table) = config else {
+    return Err(LuaError::external("config must return a table with 'inputs' and 'setup' fields").into());
+  };
+
+  // Get the setup function
+  let setup: LuaFunction = config_table
+    .get("setup")
+    .map_err(|_| LuaError::external("config must return a table with a 'setup' function"))?;
+
+  // Apply config-level settings before any input or setup code runs
+  apply_settings(lua, &config_table)?;
+  let hooks = parse_hook_settings(&config_table)?;
+  let throttle = parse_throttle_settings(&config_table)?;
+
+  // Extract raw inputs table (supports both simple URLs and extended syntax)
+  let input_decls = extract_raw_inputs(&config_table)?;
+  let trusted = extract_trusted_inputs(&config_table)?;
+
+  // Resolve inputs (fetch git repos, resolve paths) with transitive dependencies
+  let resolved = if let InputResolution::Reuse(resolved) = resolution {
+    resolved.cloned()
+  } else if input_decls.is_empty() && options.input_overrides.is_empty() {
+    info!("no inputs to resolve");
+    None
+  } else {
+    info!(
+      count = input_decls.len(),
+      "resolving inputs with transitive dependencies"
+    );
+    let fetchers = Fetchers::with_settings(parse_fetch_settings(&config_table)?);
+    let mut result = resolve_inputs_with(
+      &input_decls,
+      config_dir,
+      None,
+      Some(&options.input_overrides),
+      &fetchers,
+    )?;
+
+    if let InputResolution::Resolve = resolution {
+      // Record the object hash spec alongside the pinned inputs
+      if result.lock_file.set_hash_spec(registry_hash_spec(lua)?) {
+        result.lock_changed = true;
       }
 
-      // Build Lua inputs table for setup()
-      let inputs_table = build_inputs_table(&lua, resolved.as_ref())?;
+      // Save lock file if it changed
+      save_lock_file_if_changed(&result, config_dir)?;
 
-      // Call root config's setup(inputs) last
-      setup.call::<()>(inputs_table)?;
-    } else {
-      return Err(LuaError::external("config must return a table with 'inputs' and 'setup' fields").into());
+      // Update .luarc.json with resolved input paths for LuaLS
+      let system = platform::is_elevated();
+      let input_paths: Vec<_> = result.inputs.values().map(|i| i.path.as_path()).collect();
+      update_luarc_inputs(config_dir, input_paths, system);
     }
 
-    // lua is dropped here, releasing its references to manifest
+    Some(result.inputs)
+  };
+
+  // Build and set package.path from all lua/ directories
+  if let Some(ref inputs) = resolved {
+    let package_path = build_package_path(config_dir, inputs);
+    set_package_path(lua, &package_path)?;
+
+    // Untrusted input code runs in the sandbox
+    let sandbox = match options.untrusted_inputs {
+      UntrustedInputs::Sandbox => {
+        let (roots, mut trusted_roots) = input_roots(inputs, &trusted);
+        if roots.is_empty() {
+          None
+        } else {
+          debug!(count = roots.len(), "sandboxing untrusted inputs");
+          trusted_roots.push(config_dir.to_path_buf());
+          Some(Sandbox {
+            env: sandbox::create_sandbox(lua, &roots, &trusted_roots)?,
+            roots: roots.into_iter().collect(),
+          })
+        }
+      }
+      UntrustedInputs::Allow => None,
+    };
+
+    // Call input setup() functions in dependency order
+    call_input_setups(lua, inputs, sandbox.as_ref())?;
   }
 
-  // Now we should have the only reference to manifest
-  Ok(
-    Rc::try_unwrap(manifest)
-      .expect("manifest still has references")
-      .into_inner(),
-  )
+  // Build Lua inputs table for setup()
+  let inputs = build_inputs_table(lua, resolved.as_ref())?;
+
+  Ok(PreparedConfig {
+    setup,
+    inputs,
+    resolved,
+    hooks,
+    throttle,
+  })
+}
+
+/// Run the vars file at `path` and expose the table it returns as `sys.vars`.
+/// The snapshot currently applied, for `sys.current`.
+///
+/// An unreadable snapshot store is logged and treated as nothing applied, so
+/// it never prevents evaluation.
+fn current_snapshot() -> Option<Snapshot> {
+  SnapshotStore::default_store().load_current().unwrap_or_else(|e| {
+    warn!(error = %e, "cannot read the current snapshot; sys.current is empty");
+    None
+  })
+}
+
+fn load_vars(lua: &Lua, path: &Path) -> LuaResult<()> {
+  debug!(path = %path.display(), "loading host vars");
+  let vars = match runtime::load_file(lua, path)? {
+    LuaValue::Table(vars) => vars,
+    other => {
+      return Err(LuaError::external(format!(
+        "vars file '{}' must return a table, got {}",
+        path.display(),
+        other.type_name()
+      )));
+    }
+  };
+  lua.globals().get::<LuaTable>("sys")?.set("vars", vars)
+}
+
+/// Apply the optional `settings` table from the config.
+///
+/// Supported settings:
+/// - `shell`: default shell for exec actions declared with `shell = true`
+/// - `backup_max_size`: size cap for the files binds back up, unless their `backup` sets `max_size`
+/// - `repair_ignore`: path patterns whose drift `--repair` leaves alone, for every bind
+/// - `hash`: object hash `algorithm` ("sha256" or "sha512") and `length`
+/// - `run_as`: users bind exec actions may run as with `run_as`/`elevate`
+/// - `placeholders`: how placeholders are checked ("strict", "checked" or
+///   "deferred") in specs that don't set `placeholders` themselves
+///
+/// `fetch` (credentials for input fetchers) is read separately by
+/// [`parse_fetch_settings`] when inputs are resolved, `hooks` by
+/// [`parse_hook_settings`], and `nice` and `background` by
+/// [`parse_throttle_settings`].
+fn apply_settings(lua: &Lua, config_table: &LuaTable) -> LuaResult<()> {
+  let settings: Option<LuaTable> = config_table
+    .get("settings")
+    .map_err(|_| LuaError::external("config 'settings' must be a table"))?;
+  let Some(settings) = settings else {
+    return Ok(());
+  };
+
+  if let Some(shell) = settings.get::<Option<String>>("shell")? {
+    shell.parse::<Shell>().map_err(LuaError::external)?;
+    debug!(shell = %shell, "default exec shell set");
+    lua.set_named_registry_value(DEFAULT_SHELL_REGISTRY_KEY, shell)?;
+  }
+
+  let backup_max_size = settings.get::<LuaValue>("backup_max_size")?;
+  if !backup_max_size.is_nil() {
+    let max_size = parse_backup_max_size(backup_max_size)?;
+    debug!(max_size, "default bind backup size cap set");
+    lua.set_named_registry_value(BACKUP_MAX_SIZE_REGISTRY_KEY, max_size)?;
+  }
+
+  let repair_ignore: Vec<String> = match settings.get::<LuaValue>("repair_ignore")? {
+    LuaValue::Nil => Vec::new(),
+    LuaValue::String(s) => vec![s.to_str()?.to_string()],
+    LuaValue::Table(t) => t
+      .sequence_values::<String>()
+      .collect::<LuaResult<_>>()
+      .map_err(|_| LuaError::external("settings.repair_ignore patterns must be strings"))?,
+    other => {
+      return Err(LuaError::external(format!(
+        "settings.repair_ignore must be a pattern or a list of patterns, got {}",
+        other.type_name()
+      )));
+    }
+  };
+  if !repair_ignore.is_empty() {
+    let patterns: Vec<String> = repair_ignore
+      .iter()
+      .map(|pattern| expand_path(pattern).to_string_lossy().into_owned())
+      .collect();
+    debug!(?patterns, "repair ignore patterns set");
+    lua.set_named_registry_value(REPAIR_IGNORE_REGISTRY_KEY, patterns)?;
+  }
+
+  let run_as: Option<Vec<String>> = settings
+    .get("run_as")
+    .map_err(|_| LuaError::external("settings.run_as must be a list of user names"))?;
+  if let Some(users) = run_as {
+    debug!(?users, "exec run_as users allowed");
+    lua.set_named_registry_value(RUN_AS_USERS_REGISTRY_KEY, users)?;
+  }
+
+  if let Some(mode) = settings
+    .get::<Option<String>>("placeholders")
+    .map_err(|_| LuaError::external("settings.placeholders must be a mode string"))?
+  {
+    mode.parse::<PlaceholderMode>().map_err(LuaError::external)?;
+    debug!(mode = %mode, "placeholder mode set");
+    lua.set_named_registry_value(PLACEHOLDER_MODE_REGISTRY_KEY, mode)?;
+  }
+
+  if let Some(hash) = settings
+    .get::<Option<LuaTable>>("hash")
+    .map_err(|_| LuaError::external("settings.hash must be a table"))?
+  {
+    let algorithm = match hash.get::<Option<String>>("algorithm")? {
+      Some(algorithm) => algorithm.parse::<HashAlgorithm>().map_err(LuaError::external)?,
+      None => HashAlgorithm::default(),
+    };
+    let length = hash.get::<Option<usize>>("length")?.unwrap_or(OBJ_HASH_PREFIX_LEN);
+    let spec = HashSpec::new(algorithm, length).map_err(LuaError::external)?;
+    debug!(hash = %spec, "object hash spec set");
+    lua.set_named_registry_value(HASH_SPEC_REGISTRY_KEY, spec.to_string())?;
+  }
+
+  Ok(())
+}
+
+/// Parse the audit hooks of a config table's `settings.hooks`.
+///
+/// Each hook is an executable path, or a table `{ bin, args, on_failure }`.
+fn parse_hook_settings(config_table: &LuaTable) -> LuaResult<ApplyHooks> {
+  let Some(settings) = config_table.get::<Option<LuaTable>>("settings")? else {
+    return Ok(ApplyHooks::default());
+  };
+  let Some(hooks) = settings
+    .get::<Option<LuaTable>>("hooks")
+    .map_err(|_| LuaError::external("settings.hooks must be a table"))?
+  else {
+    return Ok(ApplyHooks::default());
+  };
+
+  for key in hooks.pairs::<String, LuaValue>() {
+    let (key, _) = key?;
+    if !matches!(key.as_str(), "pre_bind" | "post_bind" | "post_apply") {
+      return Err(LuaError::external(format!(
+        "unknown hook '{}' in settings.hooks (expected pre_bind, post_bind or post_apply)",
+        key
+      )));
+    }
+  }
+
+  let hooks = ApplyHooks {
+    pre_bind: parse_hook_command(&hooks, "pre_bind")?,
+    post_bind: parse_hook_command(&hooks, "post_bind")?,
+    post_apply: parse_hook_command(&hooks, "post_apply")?,
+  };
+  if !hooks.is_empty() {
+    debug!(?hooks, "audit hooks set");
+  }
+  Ok(hooks)
+}
+
+/// Parse the lowered apply priority of a config table's `settings.nice`
+/// (0-19) and `settings.background`.
+fn parse_throttle_settings(config_table: &LuaTable) -> LuaResult<Throttle> {
+  let Some(settings) = config_table.get::<Option<LuaTable>>("settings")? else {
+    return Ok(Throttle::NONE);
+  };
+
+  let nice = match settings.get::<LuaValue>("nice")? {
+    LuaValue::Nil => 0,
+    LuaValue::Integer(nice) if (0..=i64::from(MAX_NICE)).contains(&nice) => nice as u8,
+    LuaValue::Integer(nice) => {
+      return Err(LuaError::external(format!(
+        "settings.nice must be from 0 to {}, got {}",
+        MAX_NICE, nice
+      )));
+    }
+    other => {
+      return Err(LuaError::external(format!(
+        "settings.nice must be an integer from 0 to {}, got {}",
+        MAX_NICE,
+        other.type_name()
+      )));
+    }
+  };
+  let background = settings
+    .get::<Option<bool>>("background")
+    .map_err(|_| LuaError::external("settings.background must be a boolean"))?
+    .unwrap_or(false);
+
+  let throttle = Throttle { nice, background };
+  if !throttle.is_none() {
+    debug!(?throttle, "apply throttle set");
+  }
+  Ok(throttle)
+}
+
+fn parse_hook_command(hooks: &LuaTable, name: &str) -> LuaResult<Option<HookCommand>> {
+  let command = match hooks.get::<LuaValue>(name)? {
+    LuaValue::Nil => return Ok(None),
+    LuaValue::String(bin) => HookCommand {
+      bin: bin.to_str()?.to_string(),
+      args: Vec::new(),
+      on_failure: Default::default(),
+    },
+    LuaValue::Table(t) => HookCommand {
+      bin: t
+        .get::<Option<String>>("bin")?
+        .ok_or_else(|| LuaError::external(format!("settings.hooks.{} requires a 'bin'", name)))?,
+      args: t
+        .get::<Option<Vec<String>>>("args")
+        .map_err(|_| LuaError::external(format!("settings.hooks.{}.args must be a list of strings", name)))?
+        .unwrap_or_default(),
+      on_failure: match t.get::<Option<String>>("on_failure")? {
+        Some(on_failure) => on_failure.parse().map_err(LuaError::external)?,
+        None => Default::default(),
+      },
+    },
+    other => {
+      return Err(LuaError::external(format!(
+        "settings.hooks.{} must be a path or a table, got {}",
+        name,
+        other.type_name()
+      )));
+    }
+  };
+  Ok(Some(HookCommand {
+    bin: expand_path(&command.bin).to_string_lossy().into_owned(),
+    ..command
+  }))
 }
 
 /// Build package.path from all lua/ directories.
@@ -145,7 +598,7 @@ pub fn evaluate_config(path: &Path, options: &EvalOptions) -> Result<Manifest, E
 ///
 /// # Returns
 /// A semicolon-separated package.path string
-fn build_package_path(config_dir: &Path, resolved: &ResolvedInputs) -> String {
+pub(crate) fn build_package_path(config_dir: &Path, resolved: &ResolvedInputs) -> String {
   let mut paths = Vec::new();
 
   // 1. Config directory's lua/ (if exists) - highest priority
@@ -182,7 +635,7 @@ fn collect_lua_paths(inputs: &ResolvedInputs, paths: &mut Vec<String>) {
 /// Set package.path in the Lua runtime.
 ///
 /// Prepends the new paths to the existing package.path.
-fn set_package_path(lua: &Lua, new_paths: &str) -> LuaResult<()> {
+pub(crate) fn set_package_path(lua: &Lua, new_paths: &str) -> LuaResult<()> {
   if new_paths.is_empty() {
     return Ok(());
   }
@@ -197,30 +650,89 @@ fn set_package_path(lua: &Lua, new_paths: &str) -> LuaResult<()> {
   Ok(())
 }
 
+/// Sandbox environment and the input roots whose code runs in it.
+struct Sandbox {
+  env: LuaTable,
+  roots: BTreeSet<PathBuf>,
+}
+
+/// Names of root inputs declared with `trusted = true`.
+fn extract_trusted_inputs(config_table: &LuaTable) -> LuaResult<BTreeSet<String>> {
+  let mut trusted = BTreeSet::new();
+
+  if let LuaValue::Table(inputs_table) = config_table.get::<LuaValue>("inputs")? {
+    for pair in inputs_table.pairs::<String, LuaValue>() {
+      let (name, value) = pair?;
+      let LuaValue::Table(table) = value else {
+        continue;
+      };
+      let flag = table
+        .get::<Option<bool>>("trusted")
+        .map_err(|_| LuaError::external(format!("input '{}': trusted must be a boolean", name)))?;
+      if flag == Some(true) {
+        trusted.insert(name);
+      }
+    }
+  }
+
+  Ok(trusted)
+}
+
+/// Directories of resolved inputs that are not trusted, and of those that are.
+///
+/// Transitive inputs inherit the trust of the root input that pulls them in;
+/// an input reachable through both a trusted and an untrusted root is trusted.
+fn input_roots(resolved: &ResolvedInputs, trusted: &BTreeSet<String>) -> (Vec<PathBuf>, Vec<PathBuf>) {
+  fn walk(input: &ResolvedInput, out: &mut BTreeSet<PathBuf>) {
+    out.insert(input.path.clone());
+    for dep in input.inputs.values() {
+      walk(dep, out);
+    }
+  }
+
+  let mut trusted_paths = BTreeSet::new();
+  let mut all_paths = BTreeSet::new();
+  for (name, input) in resolved {
+    if trusted.contains(name) {
+      walk(input, &mut trusted_paths);
+    }
+    walk(input, &mut all_paths);
+  }
+
+  let untrusted = all_paths.difference(&trusted_paths).cloned().collect();
+  (untrusted, trusted_paths.into_iter().collect())
+}
+
 /// Call setup() functions for all inputs in dependency order.
 ///
 /// Walks the input tree depth-first, calling each input's setup() function
 /// after its dependencies have been set up. This ensures that libraries can
 /// rely on their dependencies being initialized before their own setup runs.
-fn call_input_setups(lua: &Lua, resolved: &ResolvedInputs) -> LuaResult<()> {
+fn call_input_setups(lua: &Lua, resolved: &ResolvedInputs, sandbox: Option<&Sandbox>) -> LuaResult<()> {
   for (name, input) in resolved {
-    call_input_setup_recursive(lua, name, input)?;
+    call_input_setup_recursive(lua, name, input, sandbox)?;
   }
   Ok(())
 }
 
 /// Recursively call setup() for an input and its dependencies.
-fn call_input_setup_recursive(lua: &Lua, name: &str, input: &ResolvedInput) -> LuaResult<()> {
+fn call_input_setup_recursive(
+  lua: &Lua,
+  name: &str,
+  input: &ResolvedInput,
+  sandbox: Option<&Sandbox>,
+) -> LuaResult<()> {
   // First, recursively call setup for transitive dependencies
   for (dep_name, dep_input) in &input.inputs {
-    call_input_setup_recursive(lua, dep_name, dep_input)?;
+    call_input_setup_recursive(lua, dep_name, dep_input, sandbox)?;
   }
 
   // Then call this input's setup() if it has one
   let init_path = input.path.join("init.lua");
   if init_path.exists() {
-    // Load the input's init.lua
-    let init_result = runtime::load_file(lua, &init_path)?;
+    // Load the input's init.lua, in the sandbox unless it is trusted
+    let env = sandbox.filter(|s| s.roots.contains(&input.path)).map(|s| &s.env);
+    let init_result = runtime::load_file_in(lua, &init_path, env)?;
 
     if let LuaValue::Table(init_table) = init_result {
       // Check if it has a setup function
@@ -392,6 +904,8 @@ fn build_input_entry(lua: &Lua, input: &ResolvedInput) -> LuaResult<LuaTable> {
 
 #[cfg(test)]
 mod tests {
+  use crate::bind::repair::RepairPolicy;
+  use crate::execute::hooks::HookFailure;
   use crate::util::hash::Hashable;
 
   use super::*;
@@ -582,6 +1096,99 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn test_evaluate_config_with_inputs_collects_metadata() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_dir = temp_dir.path();
+
+    let local_input = config_dir.join("my-input");
+    fs::create_dir(&local_input).unwrap();
+    fs::write(
+      local_input.join("init.lua"),
+      "return { metadata = { license = 'MIT', homepage = 'https://example.com' } }",
+    )
+    .unwrap();
+
+    let config_path = config_dir.join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        return {
+          inputs = {
+            myinput = "path:./my-input",
+          },
+          setup = function(inputs)
+            sys.build({
+              id = "tool",
+              metadata = { description = "A tool", license = "Apache-2.0" },
+              create = function(build_inputs, ctx)
+                return { out = "/store/tool" }
+              end,
+            })
+          end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let (manifest, inputs) = evaluate_config_with_inputs(&config_path, &EvalOptions::default())?;
+
+    let input_metadata = inputs["myinput"].metadata.as_ref().expect("input metadata");
+    assert_eq!(input_metadata.license.as_deref(), Some("MIT"));
+    assert_eq!(input_metadata.homepage.as_deref(), Some("https://example.com"));
+
+    let build = manifest.builds.values().next().unwrap();
+    let build_metadata = build.metadata.as_ref().expect("build metadata");
+    assert_eq!(build_metadata.license.as_deref(), Some("Apache-2.0"));
+    assert_eq!(build_metadata.description.as_deref(), Some("A tool"));
+    Ok(())
+  }
+
+  #[test]
+  fn test_input_override_leaves_lock_file_untouched() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_dir = temp_dir.path();
+
+    for name in ["upstream", "fork"] {
+      let lua_dir = config_dir.join(name).join("lua").join("mylib");
+      fs::create_dir_all(&lua_dir).unwrap();
+      fs::write(config_dir.join(name).join("init.lua"), "return {}").unwrap();
+      fs::write(lua_dir.join("init.lua"), format!("return {{ name = '{}' }}", name)).unwrap();
+    }
+
+    let config_path = config_dir.join("init.lua");
+    let config = |expected: &str| {
+      format!(
+        r#"
+          return {{
+            inputs = {{
+              mylib = "path:./upstream",
+            }},
+            setup = function(inputs)
+              assert(require("mylib").name == "{}")
+            end,
+          }}
+        "#,
+        expected
+      )
+    };
+
+    fs::write(&config_path, config("upstream")).unwrap();
+    evaluate_config(&config_path, &EvalOptions::default())?;
+    let lock_path = config_dir.join("syslua.lock");
+    let lock_before = fs::read_to_string(&lock_path).unwrap();
+
+    fs::write(&config_path, config("fork")).unwrap();
+    let options = EvalOptions {
+      input_overrides: BTreeMap::from([("mylib".to_string(), "path:./fork".to_string())]),
+      ..Default::default()
+    };
+    evaluate_config(&config_path, &options)?;
+
+    assert_eq!(fs::read_to_string(&lock_path).unwrap(), lock_before);
+    Ok(())
+  }
+
   #[test]
   fn test_require_from_input_lua_dir() -> Result<(), EvalError> {
     let temp_dir = TempDir::new().unwrap();
@@ -699,6 +1306,95 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn test_untrusted_input_is_sandboxed() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_dir = temp_dir.path();
+
+    // Both inputs record whether they can see io, from init.lua and a module
+    for name in ["untrusted", "trusted"] {
+      let input_dir = config_dir.join(name);
+      fs::create_dir_all(input_dir.join("lua")).unwrap();
+      fs::write(
+        input_dir.join("init.lua"),
+        format!(
+          "return {{ setup = function() _G.{name}_io = io ~= nil; _G.{name}_dofile = dofile ~= nil end }}",
+          name = name
+        ),
+      )
+      .unwrap();
+      fs::write(
+        input_dir.join("lua").join(format!("{}_mod.lua", name)),
+        "return { has_io = io ~= nil }",
+      )
+      .unwrap();
+    }
+
+    let config_path = config_dir.join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        return {
+          inputs = {
+            untrusted = "path:./untrusted",
+            trusted = { url = "path:./trusted", trusted = true },
+          },
+          setup = function(inputs)
+            assert(untrusted_io == nil and untrusted_dofile == nil, "untrusted input set a global")
+            assert(require("untrusted_mod").has_io == false, "untrusted module saw io")
+            assert(trusted_io == true and trusted_dofile == true, "trusted input lost io")
+            assert(require("trusted_mod").has_io == true, "trusted module lost io")
+            assert(io ~= nil, "root config lost io")
+          end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let options = EvalOptions {
+      impure: true,
+      ..Default::default()
+    };
+    evaluate_config(&config_path, &options)?;
+    Ok(())
+  }
+
+  #[test]
+  fn test_untrusted_inputs_allow_policy() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_dir = temp_dir.path();
+
+    let input_dir = config_dir.join("my-lib");
+    fs::create_dir(&input_dir).unwrap();
+    fs::write(
+      input_dir.join("init.lua"),
+      "return { setup = function() _G.LIB_HAS_IO = io ~= nil end }",
+    )
+    .unwrap();
+
+    let config_path = config_dir.join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        return {
+          inputs = { mylib = "path:./my-lib" },
+          setup = function(inputs)
+            assert(LIB_HAS_IO == true, "allow policy should not sandbox inputs")
+          end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let options = EvalOptions {
+      impure: true,
+      untrusted_inputs: UntrustedInputs::Allow,
+      ..Default::default()
+    };
+    evaluate_config(&config_path, &options)?;
+    Ok(())
+  }
+
   #[test]
   fn test_input_setup_is_called() -> Result<(), EvalError> {
     let temp_dir = TempDir::new().unwrap();
@@ -895,4 +1591,461 @@ mod tests {
     evaluate_config(&config_path, &EvalOptions::default())?;
     Ok(())
   }
+
+  #[test]
+  fn test_settings_shell_sets_exec_default() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        return {
+          inputs = {},
+          settings = { shell = "bash" },
+          setup = function(inputs)
+            sys.build({
+              id = "test",
+              create = function(build_inputs, ctx)
+                ctx:exec({ bin = "echo hi", shell = true })
+                ctx:exec({ bin = "echo hi", shell = "sh" })
+                return { out = ctx.out }
+              end,
+            })
+          end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let manifest = evaluate_config(&config_path, &EvalOptions::default())?;
+    let build = manifest.builds.values().next().unwrap();
+    let shells: Vec<_> = build
+      .create_actions
+      .iter()
+      .map(|action| match action {
+        crate::action::Action::Exec(opts) => opts.shell,
+        _ => None,
+      })
+      .collect();
+    assert_eq!(shells, vec![Some(Shell::Bash), Some(Shell::Sh)]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_settings_rejects_unknown_shell() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        return {
+          inputs = {},
+          settings = { shell = "zsh" },
+          setup = function(inputs) end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    assert!(evaluate_config(&config_path, &EvalOptions::default()).is_err());
+  }
+
+  #[test]
+  fn test_settings_backup_max_size_sets_bind_default() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        return {
+          inputs = {},
+          settings = { backup_max_size = "2K" },
+          setup = function(inputs)
+            sys.bind({
+              id = "default-cap",
+              backup = { "/tmp/syslua-default-cap" },
+              create = function(bind_inputs, ctx) end,
+              destroy = function(outputs, ctx) end,
+            })
+            sys.bind({
+              id = "own-cap",
+              backup = { "/tmp/syslua-own-cap", max_size = 512 },
+              create = function(bind_inputs, ctx) end,
+              destroy = function(outputs, ctx) end,
+            })
+            sys.bind({
+              id = "no-backup",
+              create = function(bind_inputs, ctx) end,
+              destroy = function(outputs, ctx) end,
+            })
+          end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let manifest = evaluate_config(&config_path, &EvalOptions::default())?;
+    let mut caps: Vec<_> = manifest
+      .bindings
+      .values()
+      .map(|bind| (bind.id.clone().unwrap(), bind.backup.as_ref().unwrap().max_size))
+      .collect();
+    caps.sort();
+    assert_eq!(
+      caps,
+      vec![
+        ("default-cap".to_string(), 2048),
+        ("no-backup".to_string(), 2048),
+        ("own-cap".to_string(), 512)
+      ]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn test_settings_strict_placeholders_check_the_finished_manifest() {
+    let config = |mode: &str| {
+      format!(
+        r#"
+          return {{
+            inputs = {{}},
+            settings = {{ placeholders = "strict" }},
+            setup = function(inputs)
+              local target = sys.bind({{
+                id = "target",
+                create = function(bind_inputs, ctx) return {{ path = "/tmp/a" }} end,
+                destroy = function(outputs, ctx) end,
+              }})
+              sys.bind({{
+                id = "user",
+                placeholders = "{mode}",
+                create = function(bind_inputs, ctx)
+                  ctx:exec({{ bin = "cat " .. target.outputs.path }})
+                end,
+                destroy = function(outputs, ctx) end,
+              }})
+              sys.bind({{
+                id = "target",
+                replace = true,
+                create = function(bind_inputs, ctx) return {{ path = "/tmp/b" }} end,
+                destroy = function(outputs, ctx) end,
+              }})
+            end,
+          }}
+        "#
+      )
+    };
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("init.lua");
+
+    fs::write(&config_path, config("strict")).unwrap();
+    let err = evaluate_config(&config_path, &EvalOptions::default()).unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains("invalid placeholder in bind 'user'"), "{}", message);
+    assert!(message.contains("unknown bind"), "{}", message);
+
+    // Checked at the call only, when the old target still existed
+    fs::write(&config_path, config("checked")).unwrap();
+    assert!(evaluate_config(&config_path, &EvalOptions::default()).is_ok());
+
+    fs::write(&config_path, config("deferred")).unwrap();
+    assert!(evaluate_config(&config_path, &EvalOptions::default()).is_ok());
+
+    fs::write(&config_path, config("eager")).unwrap();
+    let err = evaluate_config(&config_path, &EvalOptions::default()).unwrap_err();
+    assert!(err.to_string().contains("unknown placeholder mode 'eager'"), "{}", err);
+  }
+
+  #[test]
+  fn test_settings_repair_ignore_applies_to_every_bind() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        return {
+          inputs = {},
+          settings = { repair_ignore = "/etc/*.conf" },
+          setup = function(inputs)
+            sys.bind({
+              id = "plain",
+              create = function(bind_inputs, ctx) end,
+              destroy = function(outputs, ctx) end,
+            })
+            sys.bind({
+              id = "never",
+              repair = "never",
+              repair_ignore = { "/var/lib/app/**" },
+              create = function(bind_inputs, ctx) end,
+              destroy = function(outputs, ctx) end,
+            })
+          end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let manifest = evaluate_config(&config_path, &EvalOptions::default())?;
+    let repair = |id: &str| {
+      manifest
+        .bindings
+        .values()
+        .find(|bind| bind.id.as_deref() == Some(id))
+        .and_then(|bind| bind.repair.clone())
+        .unwrap()
+    };
+    assert_eq!(repair("plain").policy, RepairPolicy::Always);
+    assert_eq!(repair("plain").ignore, vec!["/etc/*.conf".to_string()]);
+    assert_eq!(repair("never").policy, RepairPolicy::Never);
+    assert_eq!(
+      repair("never").ignore,
+      vec!["/var/lib/app/**".to_string(), "/etc/*.conf".to_string()]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn test_settings_hooks_are_recorded_in_manifest() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        return {
+          inputs = {},
+          settings = {
+            hooks = {
+              pre_bind = { bin = "/usr/local/bin/audit", args = { "pre" }, on_failure = "error" },
+              post_apply = "/usr/local/bin/audit",
+            },
+          },
+          setup = function(inputs) end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let manifest = evaluate_config(&config_path, &EvalOptions::default())?;
+    let pre_bind = manifest.hooks.pre_bind.unwrap();
+    assert_eq!(pre_bind.bin, "/usr/local/bin/audit");
+    assert_eq!(pre_bind.args, vec!["pre".to_string()]);
+    assert_eq!(pre_bind.on_failure, HookFailure::Error);
+    assert_eq!(manifest.hooks.post_bind, None);
+    assert_eq!(manifest.hooks.post_apply.unwrap().on_failure, HookFailure::Warn);
+    Ok(())
+  }
+
+  #[test]
+  fn test_settings_rejects_unknown_hook() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        return {
+          inputs = {},
+          settings = { hooks = { pre_build = "/usr/local/bin/audit" } },
+          setup = function(inputs) end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let err = evaluate_config(&config_path, &EvalOptions::default()).unwrap_err();
+    assert!(err.to_string().contains("unknown hook 'pre_build'"), "{err}");
+  }
+
+  #[test]
+  fn test_settings_throttle_is_recorded_in_manifest() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        return {
+          inputs = {},
+          settings = { nice = 10, background = true },
+          setup = function(inputs) end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let manifest = evaluate_config(&config_path, &EvalOptions::default())?;
+    assert_eq!(
+      manifest.throttle,
+      Throttle {
+        nice: 10,
+        background: true
+      }
+    );
+
+    fs::write(
+      &config_path,
+      r#"return { inputs = {}, settings = { nice = 20 }, setup = function(inputs) end }"#,
+    )
+    .unwrap();
+    let err = evaluate_config(&config_path, &EvalOptions::default()).unwrap_err();
+    assert!(
+      err.to_string().contains("settings.nice must be from 0 to 19, got 20"),
+      "{err}"
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn test_settings_hash_sets_object_hash_spec() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("init.lua");
+    let config = |settings: &str| {
+      format!(
+        r#"
+          return {{
+            inputs = {{}},
+            settings = {{ {} }},
+            setup = function(inputs)
+              sys.build({{ id = "tool", create = function(build_inputs, ctx) return {{ out = ctx.out }} end }})
+            end,
+          }}
+        "#,
+        settings
+      )
+    };
+
+    fs::write(&config_path, config("")).unwrap();
+    let default = evaluate_config(&config_path, &EvalOptions::default())?;
+    assert!(default.hash.is_default());
+
+    fs::write(&config_path, config(r#"hash = { length = 32 }"#)).unwrap();
+    let longer = evaluate_config(&config_path, &EvalOptions::default())?;
+    assert_eq!(longer.hash, HashSpec::new(HashAlgorithm::Sha256, 32).unwrap());
+    let (hash, _) = longer.builds.iter().next().unwrap();
+    assert_eq!(hash.0.len(), 32);
+    assert!(default.find_build(hash).is_some());
+
+    fs::write(&config_path, config(r#"hash = { algorithm = "md5" }"#)).unwrap();
+    assert!(evaluate_config(&config_path, &EvalOptions::default()).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_host_vars_file_is_exposed_as_sys_vars() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_dir = temp_dir.path();
+    let Some(hostname) = platform::hostname() else {
+      return Ok(());
+    };
+
+    fs::create_dir(config_dir.join(HOST_VARS_DIR)).unwrap();
+    fs::write(
+      config_dir.join(HOST_VARS_DIR).join(format!("{hostname}.lua")),
+      r#"return { role = "laptop" }"#,
+    )
+    .unwrap();
+    fs::write(config_dir.join("work.lua"), r#"return { role = "workstation" }"#).unwrap();
+
+    let config_path = config_dir.join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        local role = sys.vars.role
+        return {
+          inputs = {},
+          setup = function(inputs)
+            sys.build({
+              id = role,
+              create = function(build_inputs, ctx)
+                return { out = ctx.out }
+              end,
+            })
+          end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let ids = |options: &EvalOptions| -> Result<Vec<Option<String>>, EvalError> {
+      let manifest = evaluate_config(&config_path, options)?;
+      Ok(manifest.builds.values().map(|b| b.id.clone()).collect())
+    };
+
+    assert_eq!(ids(&EvalOptions::default())?, [Some("laptop".to_string())]);
+
+    let options = EvalOptions {
+      vars_file: Some(config_dir.join("work.lua")),
+      ..Default::default()
+    };
+    assert_eq!(ids(&options)?, [Some("workstation".to_string())]);
+    Ok(())
+  }
+
+  #[test]
+  fn test_vars_file_must_return_table() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("init.lua");
+    fs::write(&config_path, "return { setup = function() end }").unwrap();
+    fs::write(temp_dir.path().join("vars.lua"), "return 42").unwrap();
+
+    let options = EvalOptions {
+      vars_file: Some(temp_dir.path().join("vars.lua")),
+      ..Default::default()
+    };
+    let err = evaluate_config(&config_path, &options).unwrap_err().to_string();
+    assert!(err.contains("must return a table, got integer"), "{err}");
+  }
+
+  #[test]
+  fn test_platform_option_overrides_sys_platform_and_facts() -> Result<(), EvalError> {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("init.lua");
+    fs::write(
+      &config_path,
+      r#"
+        return {
+          setup = function()
+            local id = sys.platform .. "-" .. sys.os .. "-" .. sys.arch .. "-" .. tostring(sys.facts.launchd)
+            sys.build({ id = id, create = function(_, ctx) return { out = ctx.out } end })
+            sys.build({
+              id = "mac-only",
+              platforms = { "aarch64-darwin" },
+              create = function(_, ctx) return { out = ctx.out } end,
+            })
+          end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let options = EvalOptions {
+      platform: Some("aarch64-darwin".parse().unwrap()),
+      ..Default::default()
+    };
+    let manifest = evaluate_config(&config_path, &options)?;
+    let mut ids: Vec<_> = manifest.builds.values().filter_map(|b| b.id.clone()).collect();
+    ids.sort();
+    assert_eq!(ids, ["aarch64-darwin-darwin-aarch64-true", "mac-only"]);
+    assert!(manifest.skipped_builds.is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn test_foreign_platform_cannot_be_applied() {
+    let host = Platform::current().unwrap();
+    let foreign: Platform = if host.os == platform::os::Os::Linux {
+      "aarch64-darwin"
+    } else {
+      "x86_64-linux"
+    }
+    .parse()
+    .unwrap();
+
+    let options = |platform| EvalOptions {
+      platform,
+      ..Default::default()
+    };
+    assert!(options(None).ensure_host_platform().is_ok());
+    assert!(options(Some(host)).ensure_host_platform().is_ok());
+    let err = options(Some(foreign)).ensure_host_platform().unwrap_err();
+    assert!(matches!(err, EvalError::ForeignPlatform { .. }), "{err}");
+  }
 }
@@ -0,0 +1,110 @@
+//! Login and logout activation of login-phase binds.
+//!
+//! A bind declared with `phase = "login"` is only recorded by `sys apply`.
+//! The login hook installed by `sys activate-login --install` runs
+//! `sys activate-login` when the user logs in, which runs the `create`
+//! actions of the login-phase binds of the current snapshot, dependencies
+//! first. `sys activate-login --logout` runs their `destroy` actions in the
+//! reverse order.
+//!
+//! One failing bind doesn't keep the others from running, so a broken user
+//! agent can't block a login.
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use super::apply::{ApplyError, build_restore_resolver_data};
+use super::dag::{DagNode, ExecutionDag};
+use super::resolver::BindCtxResolver;
+use crate::bind::BindPhase;
+use crate::bind::execute::{login_bind, logout_bind};
+use crate::snapshot::SnapshotStore;
+use crate::util::hash::ObjectHash;
+
+/// When activation runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEvent {
+  Login,
+  Logout,
+}
+
+/// The outcome of one login-phase bind.
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivatedBind {
+  pub hash: ObjectHash,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  /// Why the bind's actions failed, if they did.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+/// The outcome of [`activate`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivationResult {
+  pub event: SessionEvent,
+  /// The snapshot whose binds ran; `None` when nothing is applied.
+  pub snapshot_id: Option<String>,
+  /// Login-phase binds in the order they ran.
+  pub binds: Vec<ActivatedBind>,
+}
+
+impl ActivationResult {
+  pub fn is_success(&self) -> bool {
+    self.binds.iter().all(|bind| bind.error.is_none())
+  }
+}
+
+/// Run the login-phase binds of the current snapshot for `event`.
+pub async fn activate(event: SessionEvent) -> Result<ActivationResult, ApplyError> {
+  let Some(snapshot) = SnapshotStore::default_store().load_current()? else {
+    return Ok(ActivationResult {
+      event,
+      snapshot_id: None,
+      binds: vec![],
+    });
+  };
+  let manifest = &snapshot.manifest;
+
+  let dag = ExecutionDag::from_manifest(manifest)?;
+  let mut order: Vec<ObjectHash> = dag
+    .topological_order()
+    .into_iter()
+    .filter_map(|node| match node {
+      DagNode::Bind(hash) if manifest.bindings[&hash].phase == BindPhase::Login => Some(hash),
+      _ => None,
+    })
+    .collect();
+  if event == SessionEvent::Logout {
+    order.reverse();
+  }
+
+  let (completed_builds, completed_binds) = build_restore_resolver_data(manifest)?;
+  let resolver = BindCtxResolver::new(&completed_builds, &completed_binds, manifest, "/tmp".to_string());
+
+  let mut binds = Vec::with_capacity(order.len());
+  for hash in order {
+    let def = &manifest.bindings[&hash];
+    let result = match event {
+      SessionEvent::Login => login_bind(&hash, def, &resolver).await,
+      SessionEvent::Logout => logout_bind(&hash, def, &resolver).await,
+    };
+    let error = result.err().map(|e| {
+      warn!(bind = %hash.0, error = %e, "login-phase bind failed");
+      e.to_string()
+    });
+    binds.push(ActivatedBind {
+      hash,
+      id: def.id.clone(),
+      error,
+    });
+  }
+
+  info!(snapshot_id = %snapshot.id, binds = binds.len(), ?event, "activation complete");
+  Ok(ActivationResult {
+    event,
+    snapshot_id: Some(snapshot.id),
+    binds,
+  })
+}
@@ -3,18 +3,21 @@
 //! This module provides the high-level `apply` function that orchestrates
 //! the full apply flow:
 //!
-//! 1. Load current state
-//! 2. Evaluate config to produce desired manifest
-//! 3. Compute diff between desired and current
+//! 1. Evaluate config to produce desired manifest
+//! 2. Load current state
+//! 3. Compute diff between desired and current (see [`super::plan`])
 //! 4. Destroy removed binds
 //! 5. Update modified binds (same ID, different content)
 //! 6. Realize new builds
 //! 7. Apply new binds
 //! 8. Save new snapshot
 //!
-//! On failure, rolls back any applied binds from this run (except updates).
+//! On failure, rolls back any applied binds from this run. A failed update
+//! re-applies the bind's previous definition. Applies and destroys that aren't
+//! dry runs are recorded in the journal (see [`crate::snapshot::journal_path`]),
+//! whether they succeed or not.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
@@ -24,24 +27,45 @@ use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
 use tracing::{debug, error, info, warn};
 
+use crate::bind::backup::transfer_backups;
 use crate::bind::execute::{apply_bind, check_bind, destroy_bind, update_bind};
 use crate::bind::state::{BindState, BindStateError, load_bind_state, remove_bind_state, save_bind_state};
 use crate::bind::store::bind_dir_path;
+use crate::bind::target::target_dirs;
+use crate::bind::{BindCheckResult, BindDef};
 use crate::build::store::build_dir_path;
 use crate::eval::{EvalError, EvalOptions, evaluate_config};
-use crate::execute::execute_manifest;
+use crate::execute::execute_manifest_with_binds;
+use crate::execute::failures::{FailedBuilds, PreviousFailure, failed_builds_path};
+use crate::execute::history::{ExecutionHistory, history_path};
+use crate::execute::live::{LiveRecorder, live_path};
+use crate::execute::progress::ProgressSender;
+use crate::execute::transcript::{ActionTranscript, ApplyTranscript, TranscriptRecorder, transcripts_dir};
+use crate::gc::roots::TempRoots;
+use crate::lua::sandbox::UntrustedInputs;
 use crate::manifest::Manifest;
+use crate::placeholder;
 use crate::platform::paths::store_dir;
-use crate::snapshot::{Snapshot, SnapshotError, SnapshotStore, StateDiff, compute_diff, generate_snapshot_id};
+use crate::platform::priority;
+use crate::snapshot::{
+  JournalChanges, JournalOperation, JournalRecord, Provenance, Snapshot, SnapshotError, SnapshotStore, StateDiff,
+  generate_snapshot_id, record_in_journal,
+};
 use crate::store_lock::{LockMode, StoreLock, StoreLockError};
-use crate::util::hash::ObjectHash;
-
-use super::dag::{DagNode, ExecutionDag};
+use crate::util::fs::dir_size;
+use crate::util::hash::{HashError, ObjectHash};
+
+use super::dag::{DagNode, ExecutionDag, builds_referenced_by};
+use super::fault::{self, FailPhase};
+use super::hooks::{BindOperation, HookEvent, HookRunner};
+use super::plan::diff_against_current;
+use super::preflight::{PreflightReport, preflight};
 use super::resolver::BindCtxResolver;
+use super::serialize::SerializeGroups;
 use super::types::{BindResult, BuildResult, DagResult, DriftResult, ExecuteConfig, ExecuteError};
 
 /// Type alias for restore resolver data to reduce type complexity.
-type RestoreResolverData = (HashMap<ObjectHash, BuildResult>, HashMap<ObjectHash, BindResult>);
+pub(crate) type RestoreResolverData = (HashMap<ObjectHash, BuildResult>, HashMap<ObjectHash, BindResult>);
 
 /// Result of an apply operation.
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -63,6 +87,10 @@ pub struct ApplyResult {
 
   /// Results of drift checks on unchanged binds.
   pub drift_results: Vec<super::types::DriftResult>,
+
+  /// Bytes added to the store by newly realized builds.
+  #[serde(default)]
+  pub store_bytes_added: u64,
 }
 
 /// Errors that can occur during apply.
@@ -88,10 +116,22 @@ pub enum ApplyError {
   #[error("config file not found: {0}")]
   ConfigNotFound(PathBuf),
 
+  /// Hashing the desired manifest failed.
+  #[error("failed to hash manifest: {0}")]
+  Hash(#[from] HashError),
+
   /// Store lock acquisition failed.
   #[error("failed to acquire store lock: {0}")]
   Lock(#[from] StoreLockError),
 
+  /// The preflight checks found problems; nothing was changed.
+  #[error("preflight checks failed: {0}")]
+  Preflight(PreflightReport),
+
+  /// A build to realize failed recently and hasn't changed; nothing was changed.
+  #[error("{0}")]
+  PreviouslyFailed(PreviousFailure),
+
   /// Destroy phase failed.
   #[error("failed to destroy bind {hash}: {source}")]
   DestroyFailed {
@@ -109,13 +149,117 @@ pub enum ApplyError {
   },
 
   /// Update phase failed.
-  #[error("failed to update bind {old_hash} -> {new_hash}: {source}")]
+  #[error(
+    "failed to update bind {old_hash} -> {new_hash}: {source} ({rollback}){}",
+    rolled_back_summary(.reverted)
+  )]
   UpdateFailed {
     old_hash: ObjectHash,
     new_hash: ObjectHash,
     #[source]
     source: ExecuteError,
+    /// What happened to the bind after the failure.
+    rollback: UpdateRollback,
+    /// The binds updated earlier in the run (by new hash), latest first, and
+    /// what rolling them back did.
+    reverted: Vec<(ObjectHash, UpdateRollback)>,
+  },
+
+  /// The apply failed, and restoring the binds it had destroyed failed too.
+  /// The current snapshot pointer was cleared.
+  #[error("{source}; restoring destroyed binds also failed: {restore}")]
+  RollbackFailed {
+    #[source]
+    source: Box<ApplyError>,
+    restore: Box<ApplyError>,
   },
+
+  /// A `destroy --only` selector matched no bind.
+  #[error("no bind matches '{0}' (expected a bind id, hash or tag)")]
+  NoMatchingBinds(String),
+
+  /// A `--group` filter named a group without binds.
+  #[error("no bind belongs to group '{0}'")]
+  EmptyGroup(String),
+
+  /// A `post_bind` or `post_apply` hook with `on_failure = "error"` failed.
+  /// The apply's changes are kept.
+  #[error("{0}")]
+  Hook(String),
+
+  /// A `destroy --only` hash prefix matched several binds.
+  #[error("'{selector}' matches more than one bind: {}", .candidates.join(", "))]
+  AmbiguousSelector { selector: String, candidates: Vec<String> },
+}
+
+impl ApplyError {
+  /// The phase of the apply this error happened in, as a metrics label:
+  /// `select`, `eval`, `lock`, `preflight`, `destroy`, `update`, `execute`,
+  /// `rollback`, `state` or `hook`.
+  pub fn phase(&self) -> &'static str {
+    match self {
+      ApplyError::NoMatchingBinds(_) | ApplyError::EmptyGroup(_) | ApplyError::AmbiguousSelector { .. } => "select",
+      ApplyError::Eval(_) | ApplyError::ConfigNotFound(_) => "eval",
+      ApplyError::Lock(_) => "lock",
+      ApplyError::Preflight(_) | ApplyError::PreviouslyFailed(_) => "preflight",
+      ApplyError::DestroyFailed { .. } => "destroy",
+      ApplyError::UpdateFailed { .. } => "update",
+      ApplyError::Execute(_) => "execute",
+      ApplyError::RestoreFailed { .. } | ApplyError::RollbackFailed { .. } => "rollback",
+      ApplyError::Snapshot(_) | ApplyError::BindState(_) | ApplyError::Hash(_) => "state",
+      ApplyError::Hook(_) => "hook",
+    }
+  }
+
+  /// This error, or [`ApplyError::RollbackFailed`] if `restore` (bringing
+  /// back the binds destroyed before it) failed.
+  fn with_restore(self, restore: Result<(), ApplyError>) -> Self {
+    match restore {
+      Ok(()) => self,
+      Err(restore) => ApplyError::RollbackFailed {
+        source: Box::new(self),
+        restore: Box::new(restore),
+      },
+    }
+  }
+}
+
+/// Describe the rollback of the updates before a failed one.
+fn rolled_back_summary(reverted: &[(ObjectHash, UpdateRollback)]) -> String {
+  let needs_repair = reverted
+    .iter()
+    .filter(|(_, rollback)| matches!(rollback, UpdateRollback::NeedsRepair(_)))
+    .count();
+  match (reverted.len(), needs_repair) {
+    (0, _) => String::new(),
+    (count, 0) => format!("; {} earlier update(s) rolled back", count),
+    (count, needs_repair) => format!(
+      "; {} earlier update(s) rolled back, {} of them need repair",
+      count, needs_repair
+    ),
+  }
+}
+
+/// Outcome of rolling back a bind whose update failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateRollback {
+  /// The update failed before touching the bind.
+  Unchanged,
+  /// The previous definition was re-applied.
+  Restored,
+  /// The previous definition could not be re-applied. Its state file was kept
+  /// and marked as needing repair.
+  NeedsRepair(String),
+}
+
+impl std::fmt::Display for UpdateRollback {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      UpdateRollback::Unchanged => write!(f, "bind left unchanged"),
+      UpdateRollback::Restored => write!(f, "rolled back to the previous definition"),
+      UpdateRollback::NeedsRepair(reason) => write!(f, "rollback failed, bind needs repair: {}", reason),
+    }
+  }
 }
 
 /// Error during the destroy phase, tracking partial progress for rollback.
@@ -146,6 +290,19 @@ pub struct ApplyOptions {
 
   /// Allow impure Lua libs (io, os). Breaks determinism.
   pub impure: bool,
+
+  /// Replacement URLs for root inputs (name -> URL), recorded in the snapshot.
+  pub input_overrides: BTreeMap<String, String>,
+
+  /// How to evaluate inputs that aren't declared `trusted = true`.
+  pub untrusted_inputs: UntrustedInputs,
+
+  /// Host vars file to load instead of `host_vars/<hostname>.lua`.
+  pub vars_file: Option<PathBuf>,
+
+  /// Pre-evaluated manifest for the config. When set, the config is not
+  /// evaluated again (used by the daemon's evaluation cache).
+  pub manifest: Option<Manifest>,
 }
 
 /// Options for the destroy operation.
@@ -156,6 +313,14 @@ pub struct DestroyOptions {
 
   /// Dry run mode - show what would be destroyed without making changes.
   pub dry_run: bool,
+
+  /// Only destroy binds matching these selectors (id, hash prefix, or tag),
+  /// plus the binds that depend on them. Empty destroys every bind.
+  pub only: Vec<String>,
+
+  /// Only destroy binds of these groups, plus the binds that depend on them.
+  /// Combines with `only`.
+  pub groups: Vec<String>,
 }
 
 /// Result of a destroy operation.
@@ -166,6 +331,42 @@ pub struct DestroyResult {
 
   /// Number of builds now orphaned (left for future GC).
   pub builds_orphaned: usize,
+
+  /// Binds removed by a partial destroy (id, or hash when unnamed), dependents first.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub destroyed: Vec<String>,
+
+  /// Snapshot holding the remaining binds after a partial destroy.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub snapshot: Option<String>,
+}
+
+/// Options for the repair operation.
+#[derive(Debug, Clone, Default)]
+pub struct RepairOptions {
+  /// Execution configuration (parallelism, etc.)
+  pub execute: ExecuteConfig,
+
+  /// Binds to check and repair (id, hash prefix, or tag). Their dependents
+  /// are left alone.
+  pub only: Vec<String>,
+
+  /// Only check the selected binds for drift, without repairing them.
+  pub dry_run: bool,
+}
+
+/// Result of a repair operation.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct RepairResult {
+  /// Drift check results of the selected binds.
+  pub drift_results: Vec<DriftResult>,
+
+  /// Selected binds without a drift check to run (id, or hash when unnamed).
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub unchecked: Vec<String>,
+
+  /// Number of drifted binds that were re-applied.
+  pub binds_repaired: usize,
 }
 
 /// Apply a configuration file.
@@ -200,19 +401,24 @@ pub async fn apply(config_path: &Path, options: &ApplyOptions) -> Result<ApplyRe
   // Acquire exclusive lock on the store
   let _lock = StoreLock::acquire(LockMode::Exclusive, "apply")?;
 
-  // 1. Load current state
   let snapshot_store = SnapshotStore::default_store();
-  let current_snapshot = snapshot_store.load_current()?;
-  let current_manifest = current_snapshot.as_ref().map(|s| &s.manifest);
 
   // Capture previous snapshot ID for potential rollback
   let previous_snapshot_id = snapshot_store.current_id()?;
 
-  debug!(has_current = current_snapshot.is_some(), "loaded current state");
-
+  // 1. Evaluate config
   debug!("evaluating config");
-  let eval_options = EvalOptions { impure: options.impure };
-  let desired_manifest = evaluate_config(config_path, &eval_options)?;
+  let eval_options = EvalOptions {
+    impure: options.impure,
+    input_overrides: options.input_overrides.clone(),
+    untrusted_inputs: options.untrusted_inputs,
+    vars_file: options.vars_file.clone(),
+    platform: None,
+  };
+  let desired_manifest = match &options.manifest {
+    Some(manifest) => manifest.clone(),
+    None => evaluate_config(config_path, &eval_options)?,
+  };
 
   debug!(
     builds = desired_manifest.builds.len(),
@@ -220,40 +426,101 @@ pub async fn apply(config_path: &Path, options: &ApplyOptions) -> Result<ApplyRe
     "config evaluated"
   );
 
-  // 3. Compute diff
-  let store_path = store_dir();
-  let diff = compute_diff(&desired_manifest, current_manifest, &store_path);
+  // Audit hooks come from the config being applied
+  let hooks = HookRunner::new(desired_manifest.hooks.clone());
+  // Keep what this apply realizes from a concurrent GC until its snapshot is saved
+  let roots = if options.dry_run {
+    TempRoots::default()
+  } else {
+    TempRoots::create().map_err(ExecuteError::from)?
+  };
+  // Throttled by the command line or the config, whichever asks for more
+  let throttle = options.execute.throttle.max(desired_manifest.throttle);
+  let _throttle = priority::throttle_commands(throttle);
+  if !throttle.is_none() {
+    debug!(
+      nice = throttle.nice(),
+      background = throttle.background,
+      "throttling apply"
+    );
+  }
+  let transcript = TranscriptRecorder::new();
+  // Lets `sys status --follow` watch this apply from another process
+  let (live, live_recorder) = if options.dry_run {
+    (ProgressSender::default(), None)
+  } else {
+    let (live, recorder) = LiveRecorder::start(&live_path(), Some(config_path));
+    (live, Some(recorder))
+  };
+  let execute = ExecuteConfig {
+    parallelism: throttle.parallelism(options.execute.parallelism),
+    throttle,
+    hooks: hooks.clone(),
+    roots,
+    transcript: transcript.clone(),
+    progress: options.execute.progress.clone().join(live),
+    ..options.execute.clone()
+  };
+  let result = apply_manifest(
+    config_path,
+    options,
+    &execute,
+    desired_manifest,
+    &snapshot_store,
+    previous_snapshot_id,
+  )
+  .await;
 
-  debug!(
-    builds_to_realize = diff.builds_to_realize.len(),
-    builds_cached = diff.builds_cached.len(),
-    binds_to_apply = diff.binds_to_apply.len(),
-    binds_to_update = diff.binds_to_update.len(),
-    binds_to_destroy = diff.binds_to_destroy.len(),
-    binds_unchanged = diff.binds_unchanged.len(),
-    "diff computed"
-  );
+  drop(execute);
+  if let Some(recorder) = live_recorder {
+    recorder.finish(result.as_ref().err().map(|e| e.to_string())).await;
+  }
+  if !options.dry_run {
+    hooks.post_apply(&post_apply_event(&result)).await;
+    record_in_journal(apply_journal_record(config_path, &result));
+    save_transcript(&result, transcript.take());
+  }
+  let result = result?;
+  check_hook_errors(&hooks)?;
+  Ok(result)
+}
+
+/// Steps 2-9 of [`apply`], for the evaluated `desired_manifest`.
+async fn apply_manifest(
+  config_path: &Path,
+  options: &ApplyOptions,
+  execute: &ExecuteConfig,
+  desired_manifest: Manifest,
+  snapshot_store: &SnapshotStore,
+  previous_snapshot_id: Option<String>,
+) -> Result<ApplyResult, ApplyError> {
+  // 2 & 3. Load current state and compute diff
+  let (current_snapshot, diff) = diff_against_current(&desired_manifest, snapshot_store)?;
+  let current_manifest = current_snapshot.as_ref().map(|s| &s.manifest);
 
   // Early exit if no changes
   if diff.is_empty() {
     info!("no changes to apply");
 
     // Check unchanged binds for drift even when no other changes
-    let drift_results = check_unchanged_binds(&diff.binds_unchanged, &desired_manifest, &options.execute).await?;
+    let drift_results = check_unchanged_binds(&diff.binds_unchanged, &desired_manifest, execute).await?;
 
     // Repair drifted binds if requested
     let binds_repaired = if options.repair {
-      repair_drifted_binds(&drift_results, &desired_manifest, &options.execute).await?
+      repair_drifted_binds(&drift_results, &desired_manifest, execute).await?
     } else {
       0
     };
 
     // Still create a snapshot to record the state
+    let provenance = Provenance::collect(config_path, Some(&desired_manifest));
     let snapshot = Snapshot::new(
       generate_snapshot_id(),
       Some(config_path.to_path_buf()),
       desired_manifest,
-    );
+    )
+    .with_input_overrides(options.input_overrides.clone())
+    .with_provenance(provenance);
 
     // Save snapshot and set as current
     snapshot_store.save_and_set_current(&snapshot)?;
@@ -269,6 +536,7 @@ pub async fn apply(config_path: &Path, options: &ApplyOptions) -> Result<ApplyRe
       binds_destroyed: 0,
       binds_updated: 0,
       drift_results,
+      store_bytes_added: 0,
     });
   }
 
@@ -276,52 +544,116 @@ pub async fn apply(config_path: &Path, options: &ApplyOptions) -> Result<ApplyRe
   if options.dry_run {
     info!("dry run - not applying changes");
     return Ok(ApplyResult {
-      snapshot: Snapshot::new("dry-run".to_string(), Some(config_path.to_path_buf()), desired_manifest),
+      snapshot: Snapshot::new("dry-run".to_string(), Some(config_path.to_path_buf()), desired_manifest)
+        .with_input_overrides(options.input_overrides.clone()),
       diff,
       execution: DagResult::default(),
       binds_destroyed: 0,
       binds_updated: 0,
       drift_results: vec![],
+      store_bytes_added: 0,
     });
   }
 
+  // Filter to only include builds that need realization and binds that need applying
+  let execution_manifest = build_execution_manifest(&desired_manifest, &diff);
+
+  // Past runs give the expected build sizes and durations
+  let history_path = history_path();
+  let history = ExecutionHistory::load(&history_path).unwrap_or_else(|e| {
+    warn!(error = %e, "ignoring unreadable execution history");
+    ExecutionHistory::default()
+  });
+
+  // Fail before touching anything if the apply can't run to completion
+  if !execute.skip_preflight {
+    let sizes = history.expected_sizes(&execution_manifest);
+    let report = preflight(&desired_manifest, &execution_manifest, &diff, &sizes);
+    if !report.is_ok() {
+      return Err(ApplyError::Preflight(report));
+    }
+    debug!(
+      store_bytes = report.store_bytes,
+      download_bytes = report.download_bytes,
+      unestimated_builds = report.unestimated_builds,
+      "preflight checks passed"
+    );
+  }
+
+  // Builds that failed recently fail the apply again without running, unless retried
+  let failed_builds_path = failed_builds_path();
+  let mut failed_builds = FailedBuilds::load(&failed_builds_path).unwrap_or_else(|e| {
+    warn!(error = %e, "ignoring unreadable failed builds");
+    FailedBuilds::default()
+  });
+  if !execute.retry_failed
+    && let Some(previous) = failed_builds
+      .recent(execution_manifest.builds.keys(), execute.failure_ttl())
+      .into_iter()
+      .next()
+  {
+    return Err(ApplyError::PreviouslyFailed(previous));
+  }
+
   // 4. Destroy removed binds (state file cleanup is deferred until success)
-  let destroyed_hashes = match destroy_removed_binds(&diff.binds_to_destroy, current_manifest, &options.execute).await {
+  let destroyed_hashes = match destroy_removed_binds(&diff.binds_to_destroy, current_manifest, execute).await {
     Ok(hashes) => hashes,
     Err(destroy_err) => {
       // Partial destroy failure - restore what we destroyed
-      if !destroy_err.destroyed.is_empty()
-        && let Some(ref current_snapshot) = current_snapshot
-      {
-        let _ = restore_destroyed_binds(&destroy_err.destroyed, &current_snapshot.manifest, &options.execute).await;
-      }
-      return Err(ApplyError::DestroyFailed {
-        hash: destroy_err.failed_hash,
-        source: destroy_err.source,
-      });
+      let restore = restore_or_clear(&destroy_err.destroyed, current_manifest, snapshot_store, execute).await;
+      return Err(
+        ApplyError::DestroyFailed {
+          hash: destroy_err.failed_hash,
+          source: destroy_err.source,
+        }
+        .with_restore(restore),
+      );
     }
   };
 
-  // 5. Update modified binds (no rollback on failure - just fail with error)
-  let updated_hashes = update_modified_binds(
-    &diff.binds_to_update,
-    current_manifest,
-    &desired_manifest,
-    &options.execute,
-  )
-  .await?;
-
-  // 6 & 7. Build execution manifest and execute (realize builds, apply new binds)
-  // Filter to only include builds that need realization and binds that need applying
-  let execution_manifest = build_execution_manifest(&desired_manifest, &diff);
+  // 5. Update modified binds (a failed update is rolled back to its old definition)
+  let updated_hashes =
+    match update_modified_binds(&diff.binds_to_update, current_manifest, &desired_manifest, execute).await {
+      Ok(hashes) => hashes,
+      Err(update_err) => {
+        // Also bring back the binds destroyed in step 4
+        let restore = restore_or_clear(&destroyed_hashes, current_manifest, snapshot_store, execute).await;
+        return Err(update_err.with_restore(restore));
+      }
+    };
 
+  // 6 & 7. Execute the execution manifest (realize builds, apply new binds)
   debug!(
     builds = execution_manifest.builds.len(),
     binds = execution_manifest.bindings.len(),
     "executing manifest"
   );
 
-  let dag_result = execute_manifest(&execution_manifest, &options.execute).await?;
+  // Past durations let the longest chains of work start first
+  let mut execute_config = execute.clone();
+  execute_config
+    .expected_durations
+    .extend(history.expected_durations(&execution_manifest));
+
+  // New binds may refer to outputs of unchanged and updated binds, which
+  // aren't part of the execution manifest
+  let existing_binds = load_bind_results(
+    diff
+      .binds_unchanged
+      .iter()
+      .chain(diff.binds_to_update.iter().map(|(_, new)| new)),
+  )?;
+  let dag_result = execute_manifest_with_binds(&execution_manifest, &execute_config, &existing_binds).await?;
+
+  if let Err(e) = ExecutionHistory::update(&history_path, |history| {
+    history.record(&execution_manifest, &dag_result)
+  }) {
+    warn!(error = %e, "failed to save execution history");
+  }
+  failed_builds.record(&execution_manifest, &dag_result, execute.failure_ttl());
+  if let Err(e) = failed_builds.save(&failed_builds_path) {
+    warn!(error = %e, "failed to save failed builds");
+  }
 
   // Check for failures
   if !dag_result.is_success() {
@@ -336,38 +668,29 @@ pub async fn apply(config_path: &Path, options: &ApplyOptions) -> Result<ApplyRe
     }
 
     // Execution failed - restore destroyed binds
-    if !destroyed_hashes.is_empty()
-      && let Some(ref current_snapshot) = current_snapshot
+    let restore = restore_or_clear(&destroyed_hashes, current_manifest, snapshot_store, execute).await;
+    if restore.is_ok()
+      && !destroyed_hashes.is_empty()
+      && let Some(ref prev_id) = previous_snapshot_id
     {
-      match restore_destroyed_binds(&destroyed_hashes, &current_snapshot.manifest, &options.execute).await {
-        Ok(_) => {
-          // Restore succeeded - point snapshot back to previous
-          if let Some(ref prev_id) = previous_snapshot_id {
-            let _ = snapshot_store.set_current(prev_id);
-            info!(snapshot_id = %prev_id, "restored previous snapshot");
-          }
-        }
-        Err(restore_err) => {
-          // Restore failed - clear snapshot for self-healing
-          error!(
-            error = %restore_err,
-            "failed to restore destroyed binds, clearing snapshot pointer"
-          );
-          let _ = snapshot_store.clear_current();
-        }
-      }
+      // Restore succeeded - point snapshot back to previous
+      let _ = snapshot_store.set_current(prev_id);
+      info!(snapshot_id = %prev_id, "restored previous snapshot");
     }
 
     // Return the execution error
-    return Err(ApplyError::Execute(ExecuteError::CmdFailed {
-      cmd: "apply".to_string(),
-      code: Some(1),
-    }));
+    return Err(
+      ApplyError::Execute(ExecuteError::CmdFailed {
+        cmd: "apply".to_string(),
+        code: Some(1),
+      })
+      .with_restore(restore),
+    );
   }
 
   // Save bind state for newly applied binds
   for (hash, result) in &dag_result.applied {
-    let bind_state = BindState::new(result.outputs.clone());
+    let bind_state = BindState::from_result(result);
     save_bind_state(hash, &bind_state)?;
     debug!(bind = %hash.0, "saved bind state");
   }
@@ -376,25 +699,36 @@ pub async fn apply(config_path: &Path, options: &ApplyOptions) -> Result<ApplyRe
   cleanup_destroyed_bind_states(&destroyed_hashes)?;
 
   // 7. Check unchanged binds for drift
-  let drift_results = check_unchanged_binds(&diff.binds_unchanged, &desired_manifest, &options.execute).await?;
+  let drift_results = check_unchanged_binds(&diff.binds_unchanged, &desired_manifest, execute).await?;
 
   // 8. Repair drifted binds if requested
   let binds_repaired = if options.repair {
-    repair_drifted_binds(&drift_results, &desired_manifest, &options.execute).await?
+    repair_drifted_binds(&drift_results, &desired_manifest, execute).await?
   } else {
     0
   };
 
   // 9. Create and save snapshot
+  let provenance = Provenance::collect(config_path, Some(&desired_manifest));
   let snapshot = Snapshot::new(
     generate_snapshot_id(),
     Some(config_path.to_path_buf()),
     desired_manifest,
-  );
+  )
+  .with_input_overrides(options.input_overrides.clone())
+  .with_provenance(provenance);
 
   snapshot_store.save_and_set_current(&snapshot)?;
   debug!(snapshot_id = %snapshot.id, binds_repaired = binds_repaired, "snapshot saved");
 
+  // Builds found in the store took no new space
+  let store_bytes_added = dag_result
+    .realized
+    .values()
+    .filter(|build| !build.cached)
+    .map(|build| dir_size(&build.store_path))
+    .sum();
+
   Ok(ApplyResult {
     snapshot,
     diff,
@@ -402,14 +736,90 @@ pub async fn apply(config_path: &Path, options: &ApplyOptions) -> Result<ApplyRe
     binds_destroyed: destroyed_hashes.len(),
     binds_updated: updated_hashes.len(),
     drift_results,
+    store_bytes_added,
   })
 }
 
+/// The `post_apply` hook event for the outcome of an apply.
+fn post_apply_event(result: &Result<ApplyResult, ApplyError>) -> HookEvent {
+  match result {
+    Ok(result) => HookEvent::PostApply {
+      success: true,
+      error: None,
+      snapshot: Some(result.snapshot.id.clone()),
+      builds_realized: result.execution.realized.len(),
+      binds_applied: result.execution.applied.len(),
+      binds_updated: result.binds_updated,
+      binds_destroyed: result.binds_destroyed,
+    },
+    Err(e) => HookEvent::PostApply {
+      success: false,
+      error: Some(e.to_string()),
+      snapshot: None,
+      builds_realized: 0,
+      binds_applied: 0,
+      binds_updated: 0,
+      binds_destroyed: 0,
+    },
+  }
+}
+
+/// The journal entry for the outcome of an apply.
+fn apply_journal_record(config_path: &Path, result: &Result<ApplyResult, ApplyError>) -> JournalRecord {
+  match result {
+    Ok(result) => JournalRecord {
+      operation: JournalOperation::Apply,
+      snapshot: Some(result.snapshot.id.clone()),
+      provenance: result.snapshot.provenance.clone(),
+      error: None,
+      changes: JournalChanges {
+        builds_realized: result.execution.realized.len(),
+        binds_applied: result.execution.applied.len(),
+        binds_updated: result.binds_updated,
+        binds_destroyed: result.binds_destroyed,
+      },
+    },
+    Err(e) => JournalRecord {
+      operation: JournalOperation::Apply,
+      snapshot: None,
+      provenance: Some(Provenance::collect(config_path, None)),
+      error: Some(e.to_string()),
+      changes: JournalChanges::default(),
+    },
+  }
+}
+
+/// Save the commands an apply ran, if it ran any.
+fn save_transcript(result: &Result<ApplyResult, ApplyError>, actions: Vec<ActionTranscript>) {
+  if actions.is_empty() {
+    return;
+  }
+  let transcript = match result {
+    Ok(result) => ApplyTranscript::new(Some(result.snapshot.id.clone()), None, actions),
+    Err(e) => ApplyTranscript::new(None, Some(e.to_string()), actions),
+  };
+  if let Err(e) = transcript.save(&transcripts_dir()) {
+    warn!(error = %e, "failed to save apply transcript");
+  }
+}
+
+/// Fail with the failures of `post_*` hooks whose `on_failure` is `"error"`.
+fn check_hook_errors(hooks: &HookRunner) -> Result<(), ApplyError> {
+  let errors = hooks.errors();
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(ApplyError::Hook(errors.join("; ")))
+  }
+}
+
 /// Check unchanged binds for drift.
 ///
 /// For each bind that has a `check` callback, executes the check actions
 /// and records the drift status. This allows detecting when system state
-/// has diverged from what the bind originally created.
+/// has diverged from what the bind originally created. Files whose mode,
+/// owner or group the bind's file actions set are compared with its state
+/// first, whether or not it has a callback.
 ///
 /// # Arguments
 ///
@@ -423,7 +833,7 @@ pub async fn apply(config_path: &Path, options: &ApplyOptions) -> Result<ApplyRe
 pub async fn check_unchanged_binds(
   hashes: &[ObjectHash],
   manifest: &Manifest,
-  _config: &ExecuteConfig,
+  config: &ExecuteConfig,
 ) -> Result<Vec<DriftResult>, ApplyError> {
   if hashes.is_empty() {
     return Ok(vec![]);
@@ -441,29 +851,83 @@ pub async fn check_unchanged_binds(
       continue;
     };
 
-    if bind_def.check_actions.is_none() {
+    let Some(bind_state) = load_bind_state(hash)? else {
+      if bind_def.check_actions.is_some() {
+        warn!(hash = %hash.0, "bind state not found for check");
+      }
+      continue;
+    };
+
+    let repair = bind_def.repair.clone().unwrap_or_default();
+
+    // A failed update that couldn't be rolled back leaves the bind in an unknown state
+    if bind_state.needs_repair {
+      drift_results.push(DriftResult {
+        hash: hash.clone(),
+        id: bind_def.id.clone(),
+        result: BindCheckResult {
+          drifted: true,
+          message: Some("a failed update left this bind needing repair".to_string()),
+        },
+        repair_skipped: repair.skip_reason(bind_def, &bind_state.outputs, true),
+      });
       continue;
     }
 
-    let Some(bind_state) = load_bind_state(hash)? else {
-      warn!(hash = %hash.0, "bind state not found for check");
+    // Repairing re-runs the file actions, which set the recorded attributes again
+    if let Some(message) = bind_state.file_drift() {
+      drift_results.push(DriftResult {
+        hash: hash.clone(),
+        id: bind_def.id.clone(),
+        result: BindCheckResult {
+          drifted: true,
+          message: Some(message),
+        },
+        repair_skipped: repair.skip_reason(bind_def, &bind_state.outputs, false),
+      });
       continue;
-    };
+    }
+
+    if bind_def.check_actions.is_none() {
+      // The managed files were all the bind had to check
+      if !bind_state.files.is_empty() {
+        drift_results.push(DriftResult {
+          hash: hash.clone(),
+          id: bind_def.id.clone(),
+          result: BindCheckResult {
+            drifted: false,
+            message: None,
+          },
+          repair_skipped: None,
+        });
+      }
+      continue;
+    }
 
     let bind_result = BindResult {
       outputs: bind_state.outputs.clone(),
       action_results: vec![],
     };
 
-    let resolver = BindCtxResolver::new(&empty_builds, &empty_binds, manifest, String::new());
+    let resolver = BindCtxResolver::new(&empty_builds, &empty_binds, manifest, String::new())
+      .with_action_limits(config.action_limits.clone())
+      .with_transcript(config.transcript.clone());
 
     match check_bind(hash, bind_def, &bind_result, &resolver).await {
       Ok(Some(result)) => {
         debug!(hash = %hash.0, drifted = result.drifted, "drift check complete");
+        let repair_skipped = result
+          .drifted
+          .then(|| repair.skip_reason(bind_def, &bind_state.outputs, false))
+          .flatten();
+        if let Some(reason) = &repair_skipped {
+          debug!(hash = %hash.0, reason = %reason, "drift will not be repaired");
+        }
         drift_results.push(DriftResult {
           hash: hash.clone(),
           id: bind_def.id.clone(),
           result,
+          repair_skipped,
         });
       }
       Ok(None) => {}
@@ -487,7 +951,7 @@ async fn repair_drifted_binds(
 ) -> Result<usize, ApplyError> {
   let drifted: Vec<_> = drift_results
     .iter()
-    .filter(|r| r.result.drifted)
+    .filter(|r| r.needs_repair())
     .map(|r| r.hash.clone())
     .collect();
 
@@ -498,6 +962,7 @@ async fn repair_drifted_binds(
   debug!(count = drifted.len(), "repairing drifted binds");
 
   let semaphore = Arc::new(Semaphore::new(config.parallelism));
+  let groups = SerializeGroups::new();
   let mut join_set: JoinSet<Result<(ObjectHash, BindResult), ApplyError>> = JoinSet::new();
 
   for hash in drifted {
@@ -507,22 +972,37 @@ async fn repair_drifted_binds(
     };
 
     let semaphore = semaphore.clone();
+    let groups = groups.clone();
     let manifest = manifest.clone();
     let hash = hash.clone();
+    let hooks = config.hooks.clone();
+    let fail_at = config.fail_at.clone();
+    let action_limits = config.action_limits.clone();
+    let transcript = config.transcript.clone();
 
     join_set.spawn(async move {
-      let _permit = semaphore.acquire().await.unwrap();
-
       let empty_builds: HashMap<ObjectHash, BuildResult> = HashMap::new();
       let empty_binds: HashMap<ObjectHash, BindResult> = HashMap::new();
 
-      let resolver = BindCtxResolver::new(&empty_builds, &empty_binds, &manifest, String::new());
+      let resolver = BindCtxResolver::new(&empty_builds, &empty_binds, &manifest, String::new())
+        .with_action_limits(action_limits)
+        .with_transcript(transcript);
+
+      let _group = groups.lock(bind_def.serialize.as_deref()).await;
+      let _dirs = groups
+        .lock_dirs(&target_dirs(&bind_def, BindOperation::Create, &resolver))
+        .await;
+      let _permit = semaphore.acquire().await.unwrap();
 
-      let result = apply_bind(&hash, &bind_def, &resolver)
+      let result = hooks
+        .around_bind(BindOperation::Repair, &hash, &bind_def, async {
+          fault::check(fail_at.as_ref(), FailPhase::Bind, &hash, bind_def.id.as_deref())?;
+          apply_bind(&hash, &bind_def, &resolver).await
+        })
         .await
         .map_err(ApplyError::Execute)?;
 
-      let bind_state = BindState::new(result.outputs.clone());
+      let bind_state = BindState::from_result(&result);
       save_bind_state(&hash, &bind_state).map_err(ApplyError::BindState)?;
 
       debug!(hash = %hash.0, "bind repaired");
@@ -554,6 +1034,89 @@ async fn repair_drifted_binds(
   Ok(repaired)
 }
 
+/// Check selected binds of the current snapshot for drift and repair them.
+///
+/// This is the main entry point for `sys repair`. It runs the drift checks of
+/// the binds matching `options.only` only, and re-applies the drifted ones like
+/// `sys apply --repair` does for every bind, honoring their `repair` policies.
+/// The current snapshot stays current; repairs are recorded in the journal.
+pub async fn repair(options: &RepairOptions) -> Result<RepairResult, ApplyError> {
+  info!(dry_run = options.dry_run, only = ?options.only, "starting repair");
+
+  let _lock = StoreLock::acquire(LockMode::Exclusive, "repair")?;
+
+  let snapshot_store = SnapshotStore::default_store();
+  let current_snapshot = snapshot_store.load_current()?;
+  let manifest = current_snapshot
+    .as_ref()
+    .map(|s| s.manifest.clone())
+    .unwrap_or_default();
+
+  let result = repair_selected(&manifest, options).await;
+  if !options.dry_run
+    && let Some(snapshot) = &current_snapshot
+  {
+    record_in_journal(repair_journal_record(snapshot.provenance.clone(), &result));
+  }
+  result
+}
+
+/// Check and repair the binds of `manifest` selected by `options.only`.
+async fn repair_selected(manifest: &Manifest, options: &RepairOptions) -> Result<RepairResult, ApplyError> {
+  let selected = match_binds(manifest, &options.only, &[])?;
+  let mut hashes: Vec<ObjectHash> = selected.into_iter().collect();
+  hashes.sort();
+
+  // Hooks come from the config that applied the snapshot
+  let hooks = HookRunner::new(manifest.hooks.clone());
+  let execute = ExecuteConfig {
+    hooks: hooks.clone(),
+    ..options.execute.clone()
+  };
+
+  let drift_results = check_unchanged_binds(&hashes, manifest, &execute).await?;
+  let unchecked = hashes
+    .iter()
+    .filter(|hash| !drift_results.iter().any(|r| &r.hash == *hash))
+    .map(|hash| match manifest.bindings.get(hash).and_then(|b| b.id.as_ref()) {
+      Some(id) => id.clone(),
+      None => hash.0.clone(),
+    })
+    .collect();
+
+  let binds_repaired = if options.dry_run {
+    0
+  } else {
+    repair_drifted_binds(&drift_results, manifest, &execute).await?
+  };
+  info!(binds_repaired, "repair complete");
+  check_hook_errors(&hooks)?;
+
+  Ok(RepairResult {
+    drift_results,
+    unchecked,
+    binds_repaired,
+  })
+}
+
+/// The journal entry for the outcome of a repair.
+fn repair_journal_record(provenance: Option<Provenance>, result: &Result<RepairResult, ApplyError>) -> JournalRecord {
+  let (error, binds_repaired) = match result {
+    Ok(result) => (None, result.binds_repaired),
+    Err(e) => (Some(e.to_string()), 0),
+  };
+  JournalRecord {
+    operation: JournalOperation::Repair,
+    snapshot: None,
+    provenance,
+    error,
+    changes: JournalChanges {
+      binds_repaired,
+      ..Default::default()
+    },
+  }
+}
+
 /// Destroy all binds from the current snapshot.
 ///
 /// This is the main entry point for `sys destroy`. It:
@@ -563,6 +1126,9 @@ async fn repair_drifted_binds(
 /// 4. Cleans up bind state files
 /// 5. Clears the current snapshot pointer
 ///
+/// With `options.only` or `options.groups` set, only the selected binds and
+/// their dependents are destroyed and a new snapshot of the remaining state becomes current.
+///
 /// # Arguments
 ///
 /// * `options` - Destroy options
@@ -581,6 +1147,41 @@ pub async fn destroy(options: &DestroyOptions) -> Result<DestroyResult, ApplyErr
   debug!(snapshot_store_path = ?snapshot_store.base_path(), "using snapshot store");
   let current_snapshot = snapshot_store.load_current()?;
 
+  // A destroy with nothing applied changes nothing worth recording
+  let provenance = current_snapshot.as_ref().map(|s| s.provenance.clone());
+  let result = destroy_current(options, &snapshot_store, current_snapshot).await;
+  if !options.dry_run
+    && let Some(provenance) = provenance
+  {
+    record_in_journal(destroy_journal_record(provenance, &result));
+  }
+  result
+}
+
+/// The journal entry for the outcome of a destroy.
+fn destroy_journal_record(provenance: Option<Provenance>, result: &Result<DestroyResult, ApplyError>) -> JournalRecord {
+  let (snapshot, error, binds_destroyed) = match result {
+    Ok(result) => (result.snapshot.clone(), None, result.binds_destroyed),
+    Err(e) => (None, Some(e.to_string()), 0),
+  };
+  JournalRecord {
+    operation: JournalOperation::Destroy,
+    snapshot,
+    provenance,
+    error,
+    changes: JournalChanges {
+      binds_destroyed,
+      ..Default::default()
+    },
+  }
+}
+
+/// Steps 2-5 of [`destroy`], for the `current_snapshot` loaded in step 1.
+async fn destroy_current(
+  options: &DestroyOptions,
+  snapshot_store: &SnapshotStore,
+  current_snapshot: Option<Snapshot>,
+) -> Result<DestroyResult, ApplyError> {
   // 2. Early exit if no current snapshot (idempotent)
   let snapshot = match current_snapshot {
     Some(s) => s,
@@ -589,10 +1190,25 @@ pub async fn destroy(options: &DestroyOptions) -> Result<DestroyResult, ApplyErr
       return Ok(DestroyResult {
         binds_destroyed: 0,
         builds_orphaned: 0,
+        destroyed: Vec::new(),
+        snapshot: None,
       });
     }
   };
 
+  // Hooks come from the config that applied the snapshot
+  let hooks = HookRunner::new(snapshot.manifest.hooks.clone());
+  let execute = ExecuteConfig {
+    hooks: hooks.clone(),
+    ..options.execute.clone()
+  };
+
+  if !options.only.is_empty() || !options.groups.is_empty() {
+    let result = destroy_selected(&snapshot, snapshot_store, options, &execute).await?;
+    check_hook_errors(&hooks)?;
+    return Ok(result);
+  }
+
   let manifest = &snapshot.manifest;
   let bind_count = manifest.bindings.len();
   let build_count = manifest.builds.len();
@@ -611,6 +1227,8 @@ pub async fn destroy(options: &DestroyOptions) -> Result<DestroyResult, ApplyErr
     return Ok(DestroyResult {
       binds_destroyed: 0,
       builds_orphaned: build_count,
+      destroyed: Vec::new(),
+      snapshot: None,
     });
   }
 
@@ -620,23 +1238,98 @@ pub async fn destroy(options: &DestroyOptions) -> Result<DestroyResult, ApplyErr
     return Ok(DestroyResult {
       binds_destroyed: bind_count,
       builds_orphaned: build_count,
+      destroyed: Vec::new(),
+      snapshot: None,
     });
   }
 
   // 3. Get all bind hashes from the manifest
   let bind_hashes: Vec<ObjectHash> = manifest.bindings.keys().cloned().collect();
 
-  // 4. Destroy all binds
-  // We use destroy_removed_binds which handles:
-  // - Loading bind state for each bind
-  // - Creating the resolver for destroy actions
-  // - Executing destroy_actions with proper error handling
-  // - Returning which binds were destroyed
-  let destroyed_hashes = match destroy_removed_binds(&bind_hashes, Some(manifest), &options.execute).await {
+  // 4. Destroy all binds and clean up their state files
+  let destroyed_hashes = destroy_binds(&bind_hashes, manifest, &execute).await?;
+
+  // 5. Clear the current snapshot pointer
+  snapshot_store.clear_current()?;
+  info!(binds_destroyed = destroyed_hashes.len(), "destroy complete");
+  check_hook_errors(&hooks)?;
+
+  Ok(DestroyResult {
+    binds_destroyed: destroyed_hashes.len(),
+    builds_orphaned: build_count,
+    destroyed: Vec::new(),
+    snapshot: None,
+  })
+}
+
+/// Destroy the binds selected by `options.only` and `options.groups`, and their dependents.
+///
+/// The rest of the snapshot is preserved: a new snapshot without the destroyed
+/// binds (and the builds only they referenced) becomes current, so the previous
+/// state stays available to `sys snapshot rollback`.
+async fn destroy_selected(
+  snapshot: &Snapshot,
+  snapshot_store: &SnapshotStore,
+  options: &DestroyOptions,
+  execute: &ExecuteConfig,
+) -> Result<DestroyResult, ApplyError> {
+  let manifest = &snapshot.manifest;
+  let selected = select_binds(manifest, &options.only, &options.groups)?;
+  let remaining = remaining_manifest(manifest, &selected)?;
+  let builds_orphaned = manifest.builds.len() - remaining.builds.len();
+  let destroyed = selected
+    .iter()
+    .map(|hash| match manifest.bindings.get(hash).and_then(|b| b.id.as_ref()) {
+      Some(id) => id.clone(),
+      None => hash.0.clone(),
+    })
+    .collect();
+
+  info!(selected = selected.len(), "destroying selected binds");
+
+  if options.dry_run {
+    info!("dry run - not destroying");
+    return Ok(DestroyResult {
+      binds_destroyed: selected.len(),
+      builds_orphaned,
+      destroyed,
+      snapshot: None,
+    });
+  }
+
+  let destroyed_hashes = destroy_binds(&selected, manifest, execute).await?;
+
+  let new_snapshot = Snapshot::new(generate_snapshot_id(), snapshot.config_path.clone(), remaining)
+    .with_input_overrides(snapshot.input_overrides.clone());
+  snapshot_store.save_and_set_current(&new_snapshot)?;
+  info!(
+    binds_destroyed = destroyed_hashes.len(),
+    snapshot_id = %new_snapshot.id,
+    "partial destroy complete"
+  );
+
+  Ok(DestroyResult {
+    binds_destroyed: destroyed_hashes.len(),
+    builds_orphaned,
+    destroyed,
+    snapshot: Some(new_snapshot.id),
+  })
+}
+
+/// Destroy the given binds in order and remove their state files.
+///
+/// On failure the state of binds destroyed so far is still cleaned up; nothing
+/// is restored (unlike apply), so the user can retry.
+async fn destroy_binds(
+  hashes: &[ObjectHash],
+  manifest: &Manifest,
+  config: &ExecuteConfig,
+) -> Result<Vec<ObjectHash>, ApplyError> {
+  // destroy_removed_binds handles loading bind state, building the resolver
+  // and executing destroy_actions
+  let destroyed_hashes = match destroy_removed_binds(hashes, Some(manifest), config).await {
     Ok(hashes) => hashes,
     Err(destroy_err) => {
-      // Partial failure - some binds destroyed, one failed
-      // We don't restore here (unlike apply) - user can retry destroy
       error!(
         failed_hash = %destroy_err.failed_hash.0,
         destroyed_count = destroy_err.destroyed.len(),
@@ -656,36 +1349,348 @@ pub async fn destroy(options: &DestroyOptions) -> Result<DestroyResult, ApplyErr
     }
   };
 
-  // 5. Clean up bind state files
   cleanup_destroyed_bind_states(&destroyed_hashes)?;
+  Ok(destroyed_hashes)
+}
 
-  // 6. Clear the current snapshot pointer
-  snapshot_store.clear_current()?;
-  info!(binds_destroyed = destroyed_hashes.len(), "destroy complete");
+/// Resolve destroy selectors to bind hashes, in destroy order.
+///
+/// Binds matching the selectors or groups (see [`match_binds`]) and the binds
+/// that depend on them are selected, dependents ordered before their
+/// dependencies.
+fn select_binds(manifest: &Manifest, selectors: &[String], groups: &[String]) -> Result<Vec<ObjectHash>, ApplyError> {
+  let mut selected = match_binds(manifest, selectors, groups)?;
 
-  Ok(DestroyResult {
-    binds_destroyed: destroyed_hashes.len(),
-    builds_orphaned: build_count,
-  })
+  // Pull in every bind that (transitively) depends on a selected one
+  let dag = ExecutionDag::from_manifest(manifest)?;
+  loop {
+    let dependents: Vec<ObjectHash> = manifest
+      .bindings
+      .keys()
+      .filter(|hash| !selected.contains(*hash))
+      .filter(|hash| {
+        dag
+          .bind_bind_dependencies(hash)
+          .iter()
+          .any(|dep| selected.contains(dep))
+      })
+      .cloned()
+      .collect();
+
+    if dependents.is_empty() {
+      break;
+    }
+    selected.extend(dependents);
+  }
+
+  // Reverse execution order: dependents are destroyed first
+  let mut ordered = Vec::with_capacity(selected.len());
+  for wave in dag.execution_waves()?.iter().rev() {
+    let mut binds: Vec<&ObjectHash> = wave
+      .iter()
+      .filter_map(|node| match node {
+        DagNode::Bind(hash) if selected.contains(hash) => Some(hash),
+        _ => None,
+      })
+      .collect();
+    binds.sort();
+    ordered.extend(binds.into_iter().cloned());
+  }
+
+  Ok(ordered)
+}
+
+/// The binds matching bind selectors or groups.
+///
+/// A selector matches binds by exact id or tag, otherwise by hash prefix (which
+/// must be unambiguous); a group matches the binds declaring it.
+fn match_binds(
+  manifest: &Manifest,
+  selectors: &[String],
+  groups: &[String],
+) -> Result<HashSet<ObjectHash>, ApplyError> {
+  let mut selected: HashSet<ObjectHash> = HashSet::new();
+
+  for group in groups {
+    let in_group: Vec<&ObjectHash> = manifest
+      .bindings
+      .iter()
+      .filter(|(_, bind)| bind.group.as_ref() == Some(group))
+      .map(|(hash, _)| hash)
+      .collect();
+    if in_group.is_empty() {
+      return Err(ApplyError::EmptyGroup(group.clone()));
+    }
+    selected.extend(in_group.into_iter().cloned());
+  }
+
+  for selector in selectors {
+    let by_label: Vec<&ObjectHash> = manifest
+      .bindings
+      .iter()
+      .filter(|(_, bind)| bind.id.as_ref() == Some(selector) || bind.tags.contains(selector))
+      .map(|(hash, _)| hash)
+      .collect();
+
+    if !by_label.is_empty() {
+      selected.extend(by_label.into_iter().cloned());
+      continue;
+    }
+
+    let by_hash: Vec<&ObjectHash> = manifest
+      .bindings
+      .keys()
+      .filter(|hash| hash.0.starts_with(selector.as_str()))
+      .collect();
+
+    match by_hash.as_slice() {
+      [] => return Err(ApplyError::NoMatchingBinds(selector.clone())),
+      [hash] => {
+        selected.insert((*hash).clone());
+      }
+      _ => {
+        return Err(ApplyError::AmbiguousSelector {
+          selector: selector.clone(),
+          candidates: by_hash.iter().map(|h| h.0.clone()).collect(),
+        });
+      }
+    }
+  }
+
+  Ok(selected)
+}
+
+/// The manifest left after destroying `destroyed`.
+///
+/// Builds referenced only by destroyed binds are dropped; builds still used by
+/// a remaining bind, or not referenced by any bind, are kept.
+fn remaining_manifest(manifest: &Manifest, destroyed: &[ObjectHash]) -> Result<Manifest, ApplyError> {
+  let dag = ExecutionDag::from_manifest(manifest)?;
+  let (removed, kept): (Vec<&ObjectHash>, Vec<&ObjectHash>) =
+    manifest.bindings.keys().partition(|hash| destroyed.contains(hash));
+
+  let kept_builds = referenced_builds(&dag, kept);
+  let orphaned: HashSet<ObjectHash> = referenced_builds(&dag, removed)
+    .difference(&kept_builds)
+    .cloned()
+    .collect();
+
+  let mut remaining = manifest.clone();
+  remaining.bindings.retain(|hash, _| !destroyed.contains(hash));
+  remaining.builds.retain(|hash, _| !orphaned.contains(hash));
+  Ok(remaining)
+}
+
+/// Builds reachable from the given binds through bind and build dependencies.
+fn referenced_builds(dag: &ExecutionDag, binds: Vec<&ObjectHash>) -> HashSet<ObjectHash> {
+  build_closure(
+    dag,
+    binds.into_iter().flat_map(|hash| dag.bind_build_dependencies(hash)),
+  )
+}
+
+/// The given builds plus every build they (transitively) depend on.
+fn build_closure(dag: &ExecutionDag, roots: impl IntoIterator<Item = ObjectHash>) -> HashSet<ObjectHash> {
+  let mut pending: Vec<ObjectHash> = roots.into_iter().collect();
+  let mut seen = HashSet::new();
+  while let Some(hash) = pending.pop() {
+    if seen.insert(hash.clone()) {
+      pending.extend(dag.build_dependencies(&hash));
+    }
+  }
+  seen
+}
+
+/// Bind changes of `diff` outside `groups`, to pass to [`deselect_changes`].
+///
+/// A change is inside a group when its new definition belongs to it, or for a
+/// destroy its current one. Fails if a group has no binds in either manifest.
+pub fn changes_outside_groups(
+  desired: &Manifest,
+  current: Option<&Manifest>,
+  diff: &StateDiff,
+  groups: &[String],
+) -> Result<HashSet<ObjectHash>, ApplyError> {
+  let manifests = || std::iter::once(desired).chain(current);
+  for group in groups {
+    let has_binds = manifests().any(|m| m.bindings.values().any(|b| b.group.as_ref() == Some(group)));
+    if !has_binds {
+      return Err(ApplyError::EmptyGroup(group.clone()));
+    }
+  }
+
+  let in_groups = |manifest: Option<&Manifest>, hash: &ObjectHash| {
+    manifest
+      .and_then(|m| m.bindings.get(hash))
+      .and_then(|b| b.group.as_ref())
+      .is_some_and(|group| groups.contains(group))
+  };
+  let changed = diff
+    .binds_to_apply
+    .iter()
+    .map(|hash| (hash, Some(desired)))
+    .chain(diff.binds_to_update.iter().map(|(_, new)| (new, Some(desired))))
+    .chain(diff.binds_to_destroy.iter().map(|hash| (hash, current)));
+
+  Ok(
+    changed
+      .filter(|(hash, manifest)| !in_groups(*manifest, hash))
+      .map(|(hash, _)| hash.clone())
+      .collect(),
+  )
+}
+
+/// Revert deselected bind changes in the desired manifest.
+///
+/// `deselected` holds bind hashes from `diff`: a deselected create is dropped,
+/// while a deselected update or destroy keeps the current definition. Changes
+/// that can't be applied without a deselected one are deselected too: binds
+/// depending on a dropped bind, binds a kept definition depends on, and the
+/// two halves of a replacement (same id, no update actions). Builds only the
+/// dropped binds needed are removed; builds the kept definitions need are
+/// carried over from `current`.
+///
+/// Applying the returned manifest leaves the deselected changes pending for
+/// the next apply.
+pub fn deselect_changes(
+  desired: &Manifest,
+  current: Option<&Manifest>,
+  diff: &StateDiff,
+  deselected: &HashSet<ObjectHash>,
+) -> Result<Manifest, ApplyError> {
+  let empty = Manifest::default();
+  let current = current.unwrap_or(&empty);
+  let desired_dag = ExecutionDag::from_manifest(desired)?;
+  let current_dag = ExecutionDag::from_manifest(current)?;
+
+  // Every change as (old hash, new hash)
+  let changes: Vec<(Option<&ObjectHash>, Option<&ObjectHash>)> = diff
+    .binds_to_apply
+    .iter()
+    .map(|hash| (None, Some(hash)))
+    .chain(diff.binds_to_update.iter().map(|(old, new)| (Some(old), Some(new))))
+    .chain(diff.binds_to_destroy.iter().map(|hash| (Some(hash), None)))
+    .collect();
+  let mut skipped: Vec<bool> = changes
+    .iter()
+    .map(|(old, new)| old.iter().chain(new.iter()).any(|hash| deselected.contains(*hash)))
+    .collect();
+
+  // Creates and destroys that replace the same bind id
+  let id_of = |manifest: &Manifest, hash: &ObjectHash| manifest.bindings.get(hash).and_then(|b| b.id.clone());
+  let replacement_id: Vec<Option<String>> = changes
+    .iter()
+    .map(|(old, new)| match (old, new) {
+      (None, Some(new)) => id_of(desired, new),
+      (Some(old), None) => id_of(current, old),
+      _ => None,
+    })
+    .collect();
+
+  loop {
+    let mut present: HashSet<&ObjectHash> = desired.bindings.keys().collect();
+    let mut kept_deps = HashSet::new();
+    for ((old, new), _) in changes.iter().zip(&skipped).filter(|(_, skip)| **skip) {
+      if let Some(new) = new {
+        present.remove(new);
+      }
+      if let Some(old) = old {
+        present.insert(*old);
+        kept_deps.extend(current_dag.bind_bind_dependencies(old));
+      }
+    }
+    let skipped_ids: HashSet<&String> = replacement_id
+      .iter()
+      .zip(&skipped)
+      .filter_map(|(id, skip)| id.as_ref().filter(|_| *skip))
+      .collect();
+
+    let mut changed = false;
+    for (i, (old, new)) in changes.iter().enumerate() {
+      if skipped[i] {
+        continue;
+      }
+      let missing_dep = new.is_some_and(|hash| {
+        desired_dag
+          .bind_bind_dependencies(hash)
+          .iter()
+          .any(|dep| !present.contains(dep))
+      });
+      let needed = old.is_some_and(|hash| kept_deps.contains(hash));
+      let replaced = replacement_id[i].as_ref().is_some_and(|id| skipped_ids.contains(id));
+      if missing_dep || needed || replaced {
+        skipped[i] = true;
+        changed = true;
+      }
+    }
+    if !changed {
+      break;
+    }
+  }
+
+  let mut manifest = desired.clone();
+  let mut kept_old = Vec::new();
+  for ((old, new), _) in changes.iter().zip(&skipped).filter(|(_, skip)| **skip) {
+    if let Some(new) = new {
+      manifest.bindings.remove(*new);
+    }
+    if let Some(old) = old
+      && let Some(bind) = current.bindings.get(*old)
+    {
+      manifest.bindings.insert((*old).clone(), bind.clone());
+      kept_old.push(*old);
+    }
+  }
+
+  // Builds still needed: those of remaining desired binds, standalone builds,
+  // and those of the kept definitions
+  let all_bound = referenced_builds(&desired_dag, desired.bindings.keys().collect());
+  let standalone = desired.builds.keys().filter(|hash| !all_bound.contains(*hash)).cloned();
+  let mut needed = build_closure(&desired_dag, standalone);
+  needed.extend(referenced_builds(
+    &desired_dag,
+    manifest
+      .bindings
+      .keys()
+      .filter(|hash| desired.bindings.contains_key(*hash))
+      .collect(),
+  ));
+  let kept_builds = referenced_builds(&current_dag, kept_old);
+  manifest
+    .builds
+    .retain(|hash, _| needed.contains(hash) || kept_builds.contains(hash));
+  for hash in kept_builds {
+    if let Some(build) = current.builds.get(&hash) {
+      manifest.builds.entry(hash).or_insert_with(|| build.clone());
+    }
+  }
+
+  Ok(manifest)
 }
 
 /// Build an execution manifest containing only items that need work.
 ///
 /// Filters the desired manifest to include:
-/// - Only builds that need to be realized (not cached)
+/// - Only builds that need to be realized, or are cached, and are needed (see
+///   [`needed_builds`])
 /// - Only binds that need to be applied (new in desired)
 fn build_execution_manifest(desired: &Manifest, diff: &StateDiff) -> Manifest {
   let mut manifest = Manifest::default();
+  let needed = needed_builds(
+    desired,
+    diff
+      .binds_to_apply
+      .iter()
+      .chain(diff.binds_to_update.iter().map(|(_, new)| new)),
+  );
 
-  // Include builds that need realization
-  for hash in &diff.builds_to_realize {
-    if let Some(build_def) = desired.builds.get(hash) {
-      manifest.builds.insert(hash.clone(), build_def.clone());
+  // Include builds that need realization, and cached builds (needed for bind
+  // placeholder resolution)
+  for hash in diff.builds_to_realize.iter().chain(&diff.builds_cached) {
+    if !needed.contains(hash) {
+      debug!(build = %hash.0, "not realizing build no selected bind needs");
+      continue;
     }
-  }
-
-  // Also include cached builds (needed for bind placeholder resolution)
-  for hash in &diff.builds_cached {
     if let Some(build_def) = desired.builds.get(hash) {
       manifest.builds.insert(hash.clone(), build_def.clone());
     }
@@ -701,11 +1706,59 @@ fn build_execution_manifest(desired: &Manifest, diff: &StateDiff) -> Manifest {
   manifest
 }
 
+/// Builds an apply running `binds` has to realize.
+///
+/// These are the builds the binds refer to and the standalone builds no bind
+/// refers to, with every build they need in turn. Builds only needed by binds
+/// the apply leaves alone (unchanged, filtered out by groups, or skipped for
+/// their `requires`) stay unrealized.
+fn needed_builds<'a>(desired: &Manifest, binds: impl IntoIterator<Item = &'a ObjectHash>) -> HashSet<ObjectHash> {
+  let bound: HashSet<ObjectHash> = desired
+    .bindings
+    .values()
+    .flat_map(builds_referenced_by)
+    .chain(
+      desired
+        .skipped
+        .iter()
+        .flat_map(|skipped| skipped.builds.iter().cloned()),
+    )
+    .collect();
+
+  let mut pending: Vec<ObjectHash> = desired
+    .builds
+    .keys()
+    .filter(|hash| !bound.contains(*hash))
+    .cloned()
+    .collect();
+  pending.extend(
+    binds
+      .into_iter()
+      .filter_map(|hash| desired.bindings.get(hash))
+      .flat_map(builds_referenced_by),
+  );
+
+  let mut needed = HashSet::new();
+  while let Some(hash) = pending.pop() {
+    if let Some(def) = desired.builds.get(&hash)
+      && needed.insert(hash)
+    {
+      pending.extend(builds_referenced_by(def));
+    }
+  }
+  needed
+}
+
 /// Destroy removed binds.
 ///
 /// Executes destroy_actions for binds that are in the current state
 /// but not in the desired state.
 ///
+/// Binds are destroyed in the reverse of the current manifest's execution
+/// waves, so dependents go before the binds they depend on. Binds within a
+/// wave are independent and destroyed in parallel. When a destroy fails, the
+/// rest of its wave still finishes and no later wave is started.
+///
 /// # Returns
 ///
 /// List of bind hashes that were successfully destroyed.
@@ -713,7 +1766,7 @@ fn build_execution_manifest(desired: &Manifest, diff: &StateDiff) -> Manifest {
 async fn destroy_removed_binds(
   hashes: &[ObjectHash],
   current_manifest: Option<&Manifest>,
-  _config: &ExecuteConfig,
+  config: &ExecuteConfig,
 ) -> Result<Vec<ObjectHash>, DestroyPhaseError> {
   if hashes.is_empty() {
     return Ok(Vec::new());
@@ -722,83 +1775,157 @@ async fn destroy_removed_binds(
   debug!(count = hashes.len(), "destroying removed binds");
   debug!(bind_hashes = ?hashes.iter().map(|h| &h.0).collect::<Vec<_>>(), "binds to destroy");
 
-  let mut destroyed = Vec::new();
-
-  // Create an empty resolver for destroy operations
-  // (destroy actions typically only need outputs from the bind itself)
-  let empty_builds: HashMap<ObjectHash, BuildResult> = HashMap::new();
-  let empty_binds: HashMap<ObjectHash, BindResult> = HashMap::new();
-  let empty_manifest = Manifest::default();
-  let resolver = BindCtxResolver::new(&empty_builds, &empty_binds, &empty_manifest, "/tmp".to_string());
-
   // Log the bind state directory for debugging
   let bind_store_path = store_dir().join("bind");
   debug!(bind_store_path = ?bind_store_path, "checking bind state directory");
 
+  let mut to_destroy: HashSet<&ObjectHash> = HashSet::new();
   for hash in hashes {
-    // Log the expected bind state path
-    let bind_state_path = bind_dir_path(hash);
-    debug!(bind = %hash.0, bind_state_path = ?bind_state_path, "looking for bind state");
-
-    // Load bind state (outputs from when it was applied)
-    let bind_state = match load_bind_state(hash) {
-      Ok(Some(state)) => {
-        debug!(bind = %hash.0, outputs = ?state.outputs, "loaded bind state");
-        state
-      }
-      Ok(None) => {
-        warn!(bind = %hash.0, bind_state_path = ?bind_state_path, "no bind state found, skipping destroy");
-        continue;
-      }
-      Err(e) => {
-        error!(bind = %hash.0, error = %e, "failed to load bind state");
-        return Err(DestroyPhaseError {
-          destroyed,
-          failed_hash: hash.clone(),
-          source: ExecuteError::CmdFailed {
-            cmd: format!("load bind state for {}", hash.0),
-            code: None,
-          },
-        });
-      }
-    };
+    if current_manifest.is_some_and(|m| m.bindings.contains_key(hash)) {
+      to_destroy.insert(hash);
+    } else {
+      warn!(bind = %hash.0, "bind definition not found in current manifest, skipping");
+    }
+  }
+  let Some(manifest) = current_manifest.filter(|_| !to_destroy.is_empty()) else {
+    return Ok(Vec::new());
+  };
 
-    // Get bind definition from current manifest
-    let bind_def = match current_manifest.and_then(|m| m.bindings.get(hash)) {
-      Some(def) => {
-        debug!(
-          bind = %hash.0,
-          destroy_actions_count = def.destroy_actions.len(),
-          "found bind definition"
-        );
-        def
-      }
-      None => {
-        warn!(bind = %hash.0, "bind definition not found in current manifest, skipping");
-        continue;
-      }
-    };
+  // Build DAG from the current manifest to get the dependency ordering
+  let waves = ExecutionDag::from_manifest(manifest)
+    .and_then(|dag| dag.execution_waves())
+    .map_err(|e| DestroyPhaseError {
+      destroyed: Vec::new(),
+      failed_hash: hashes[0].clone(),
+      source: e,
+    })?;
 
-    // Create a bind result from the saved state
-    let bind_result = BindResult {
-      outputs: bind_state.outputs.clone(),
-      action_results: vec![],
-    };
+  let semaphore = Arc::new(Semaphore::new(config.parallelism));
+  let groups = SerializeGroups::new();
+  let mut destroyed = Vec::new();
+  let no_builds: HashMap<ObjectHash, BuildResult> = HashMap::new();
+  let no_binds: HashMap<ObjectHash, BindResult> = HashMap::new();
+  let target_resolver = BindCtxResolver::new(&no_builds, &no_binds, manifest, String::new());
+
+  for (wave_idx, wave) in waves.iter().rev().enumerate() {
+    let binds_to_destroy: Vec<_> = wave
+      .iter()
+      .filter_map(|node| match node {
+        DagNode::Bind(hash) if to_destroy.contains(hash) => Some((hash.clone(), manifest.bindings[hash].clone())),
+        _ => None,
+      })
+      .collect();
+
+    if binds_to_destroy.is_empty() {
+      continue;
+    }
+
+    debug!(wave = wave_idx, count = binds_to_destroy.len(), "destroying wave");
+
+    let mut join_set: JoinSet<Result<Option<ObjectHash>, (ObjectHash, ExecuteError)>> = JoinSet::new();
+
+    for (hash, bind_def) in binds_to_destroy {
+      let semaphore = semaphore.clone();
+      let groups = groups.clone();
+      let hooks = config.hooks.clone();
+      let action_limits = config.action_limits.clone();
+      let transcript = config.transcript.clone();
+      let dirs = target_dirs(&bind_def, BindOperation::Create, &target_resolver);
+
+      join_set.spawn(async move {
+        let _group = groups.lock(bind_def.serialize.as_deref()).await;
+        let _dirs = groups.lock_dirs(&dirs).await;
+        let _permit = semaphore.acquire().await.unwrap();
+
+        // Log the expected bind state path
+        let bind_state_path = bind_dir_path(&hash);
+        debug!(bind = %hash.0, bind_state_path = ?bind_state_path, "looking for bind state");
+
+        // Load bind state (outputs from when it was applied)
+        let bind_state = match load_bind_state(&hash) {
+          Ok(Some(state)) => {
+            debug!(bind = %hash.0, outputs = ?state.outputs, "loaded bind state");
+            state
+          }
+          Ok(None) => {
+            warn!(bind = %hash.0, bind_state_path = ?bind_state_path, "no bind state found, skipping destroy");
+            return Ok(None);
+          }
+          Err(e) => {
+            error!(bind = %hash.0, error = %e, "failed to load bind state");
+            let source = ExecuteError::CmdFailed {
+              cmd: format!("load bind state for {}", hash.0),
+              code: None,
+            };
+            return Err((hash, source));
+          }
+        };
+
+        // Create an empty resolver for destroy operations
+        // (destroy actions typically only need outputs from the bind itself)
+        let empty_builds: HashMap<ObjectHash, BuildResult> = HashMap::new();
+        let empty_binds: HashMap<ObjectHash, BindResult> = HashMap::new();
+        let empty_manifest = Manifest::default();
+        let resolver = BindCtxResolver::new(&empty_builds, &empty_binds, &empty_manifest, "/tmp".to_string())
+          .with_action_limits(action_limits)
+          .with_transcript(transcript);
+
+        // Create a bind result from the saved state
+        let bind_result = BindResult {
+          outputs: bind_state.outputs,
+          action_results: vec![],
+        };
+
+        // Execute destroy
+        debug!(bind = %hash.0, destroy_actions = bind_def.destroy_actions.len(), "destroying bind");
+        let destroyed = hooks
+          .around_bind(BindOperation::Destroy, &hash, &bind_def, async {
+            destroy_bind(&hash, &bind_def, &bind_result, &resolver).await
+          })
+          .await;
+        match destroyed {
+          Ok(()) => {
+            debug!(bind = %hash.0, "bind destroyed successfully");
+            Ok(Some(hash))
+          }
+          Err(e) => {
+            error!(bind = %hash.0, error = %e, "failed to destroy bind");
+            Err((hash, e))
+          }
+        }
+      });
+    }
+
+    // Let the whole wave finish, so every bind it destroyed is tracked
+    // (state file cleanup is deferred)
+    let mut failure = None;
+    while let Some(join_result) = join_set.join_next().await {
+      match join_result {
+        Ok(Ok(Some(hash))) => destroyed.push(hash),
+        Ok(Ok(None)) => {}
+        Ok(Err(err)) => {
+          failure.get_or_insert(err);
+        }
+        Err(e) => {
+          error!(error = %e, "destroy task panicked");
+          failure.get_or_insert((
+            ObjectHash("unknown".to_string()),
+            ExecuteError::CmdFailed {
+              cmd: format!("destroy task: {}", e),
+              code: None,
+            },
+          ));
+        }
+      }
+    }
 
-    // Execute destroy
-    debug!(bind = %hash.0, destroy_actions = bind_def.destroy_actions.len(), "destroying bind");
-    if let Err(e) = destroy_bind(hash, bind_def, &bind_result, &resolver).await {
-      error!(bind = %hash.0, error = %e, "failed to destroy bind");
+    if let Some((failed_hash, source)) = failure {
       return Err(DestroyPhaseError {
         destroyed,
-        failed_hash: hash.clone(),
-        source: e,
+        failed_hash,
+        source,
       });
     }
-
-    // Track successful destruction (state file cleanup is deferred)
-    destroyed.push(hash.clone());
-    debug!(bind = %hash.0, "bind destroyed successfully");
   }
 
   debug!(count = destroyed.len(), "destroy phase complete");
@@ -823,12 +1950,14 @@ fn cleanup_destroyed_bind_states(destroyed_hashes: &[ObjectHash]) -> Result<(),
 /// 2. Get new bind definition from desired manifest
 /// 3. Call update_bind()
 /// 4. On success: save new bind state, remove old state if hash changed
-/// 5. On failure: log error and return error (no rollback)
+/// 5. On failure: re-apply the old definition (see [`rollback_update`]), roll
+///    back the updates that succeeded earlier in this run, latest first, and
+///    return an error recording the outcomes
 ///
 /// # Arguments
 ///
 /// * `updates` - List of (old_hash, new_hash) pairs to update
-/// * `current` - Current manifest (to get old bind definitions if needed)
+/// * `current` - Current manifest (old bind definitions, for rollback)
 /// * `desired` - Desired manifest (to get new bind definitions)
 /// * `config` - Execution configuration
 ///
@@ -837,104 +1966,262 @@ fn cleanup_destroyed_bind_states(destroyed_hashes: &[ObjectHash]) -> Result<(),
 /// List of new hashes that were successfully updated.
 async fn update_modified_binds(
   updates: &[(ObjectHash, ObjectHash)],
-  _current: Option<&Manifest>,
+  current: Option<&Manifest>,
   desired: &Manifest,
-  _config: &ExecuteConfig,
+  config: &ExecuteConfig,
 ) -> Result<Vec<ObjectHash>, ApplyError> {
   if updates.is_empty() {
     return Ok(Vec::new());
   }
 
-  debug!(count = updates.len(), "updating modified binds");
+  debug!(count = updates.len(), "updating modified binds");
+
+  // Build resolver data for placeholder resolution during update
+  // We need access to builds and existing binds for placeholder resolution
+  let resolver_data = build_restore_resolver_data(desired)?;
+
+  // Updated binds with the state they had before, for rolling them back
+  let mut updated: Vec<(&ObjectHash, &ObjectHash, BindState)> = Vec::new();
+  for (old_hash, new_hash) in updates {
+    match update_modified_bind(old_hash, new_hash, current, desired, config, &resolver_data).await {
+      Ok(old_state) => {
+        updated.push((old_hash, new_hash, old_state));
+        debug!(old_hash = %old_hash.0, new_hash = %new_hash.0, "bind updated");
+      }
+      Err(mut e) => {
+        let rolled_back = roll_back_updates(&updated, current, desired).await;
+        if let ApplyError::UpdateFailed { reverted, .. } = &mut e {
+          *reverted = rolled_back;
+        }
+        return Err(e);
+      }
+    }
+  }
+
+  debug!(count = updated.len(), "update phase complete");
+  Ok(updated.into_iter().map(|(_, new_hash, _)| new_hash.clone()).collect())
+}
+
+/// Update one bind for [`update_modified_binds`], returning the state it had
+/// before.
+///
+/// A failed update is rolled back; the returned error records how.
+async fn update_modified_bind(
+  old_hash: &ObjectHash,
+  new_hash: &ObjectHash,
+  current: Option<&Manifest>,
+  desired: &Manifest,
+  config: &ExecuteConfig,
+  (completed_builds, completed_binds): &RestoreResolverData,
+) -> Result<BindState, ApplyError> {
+  let failed = |source: ExecuteError, rollback: UpdateRollback| ApplyError::UpdateFailed {
+    old_hash: old_hash.clone(),
+    new_hash: new_hash.clone(),
+    source,
+    rollback,
+    reverted: Vec::new(),
+  };
+  let not_started = |cmd: String| failed(ExecuteError::CmdFailed { cmd, code: None }, UpdateRollback::Unchanged);
+
+  // Load old bind state (outputs from when it was originally applied)
+  let old_bind_state = match load_bind_state(old_hash) {
+    Ok(Some(state)) => state,
+    Ok(None) => {
+      error!(old_hash = %old_hash.0, "no bind state found for update, cannot proceed");
+      return Err(not_started(format!("load bind state for {}", old_hash.0)));
+    }
+    Err(e) => {
+      error!(old_hash = %old_hash.0, error = %e, "failed to load bind state for update");
+      return Err(not_started(format!("load bind state for {}", old_hash.0)));
+    }
+  };
+
+  // Get new bind definition from desired manifest
+  let Some(new_bind_def) = desired.bindings.get(new_hash) else {
+    error!(new_hash = %new_hash.0, "bind definition not found in desired manifest");
+    return Err(not_started(format!("find bind definition for {}", new_hash.0)));
+  };
 
-  let mut updated = Vec::new();
+  // Create resolver for update
+  let resolver = BindCtxResolver::new(completed_builds, completed_binds, desired, "/tmp".to_string())
+    .with_action_limits(config.action_limits.clone())
+    .with_transcript(config.transcript.clone());
 
-  // Build resolver data for placeholder resolution during update
-  // We need access to builds and existing binds for placeholder resolution
-  let (completed_builds, completed_binds) = build_restore_resolver_data(desired)?;
+  // Create old bind result from saved state
+  let old_bind_result = BindResult {
+    outputs: old_bind_state.outputs.clone(),
+    action_results: vec![],
+  };
 
-  for (old_hash, new_hash) in updates {
-    // Load old bind state (outputs from when it was originally applied)
-    let old_bind_state = match load_bind_state(old_hash) {
-      Ok(Some(state)) => state,
-      Ok(None) => {
-        error!(old_hash = %old_hash.0, "no bind state found for update, cannot proceed");
-        return Err(ApplyError::UpdateFailed {
-          old_hash: old_hash.clone(),
-          new_hash: new_hash.clone(),
-          source: ExecuteError::CmdFailed {
-            cmd: format!("load bind state for {}", old_hash.0),
-            code: None,
-          },
-        });
-      }
-      Err(e) => {
-        error!(old_hash = %old_hash.0, error = %e, "failed to load bind state for update");
-        return Err(ApplyError::UpdateFailed {
-          old_hash: old_hash.clone(),
-          new_hash: new_hash.clone(),
-          source: ExecuteError::CmdFailed {
-            cmd: format!("load bind state for {}", old_hash.0),
-            code: None,
-          },
-        });
-      }
-    };
+  // Execute update
+  debug!(old_hash = %old_hash.0, new_hash = %new_hash.0, "updating bind");
+  let outcome = config
+    .hooks
+    .around_bind(BindOperation::Update, new_hash, new_bind_def, async {
+      fault::check(
+        config.fail_at.as_ref(),
+        FailPhase::Update,
+        new_hash,
+        new_bind_def.id.as_deref(),
+      )?;
+      let result = update_bind(old_hash, new_hash, new_bind_def, &old_bind_result, &resolver).await?;
+      finish_update(old_hash, new_hash, &result)
+    })
+    .await;
+
+  match outcome {
+    Ok(()) => Ok(old_bind_state),
+    // A failing pre_bind hook stops the update before it touches anything
+    Err(e @ ExecuteError::Hook { .. }) => Err(failed(e, UpdateRollback::Unchanged)),
+    Err(e) => {
+      error!(old_hash = %old_hash.0, new_hash = %new_hash.0, error = %e, "failed to update bind");
+      // The failed update got no outputs of its own; the recorded ones are
+      // the closest, with those of the new definition known up front
+      let mut previous = old_bind_state.outputs.clone();
+      previous.extend(literal_outputs(new_bind_def));
+      let rollback = rollback_update(old_hash, new_hash, previous, current, &old_bind_state).await;
+      Err(failed(e, rollback))
+    }
+  }
+}
 
-    // Get new bind definition from desired manifest
-    let new_bind_def = match desired.bindings.get(new_hash) {
-      Some(def) => def,
-      None => {
-        error!(new_hash = %new_hash.0, "bind definition not found in desired manifest");
-        return Err(ApplyError::UpdateFailed {
-          old_hash: old_hash.clone(),
-          new_hash: new_hash.clone(),
-          source: ExecuteError::CmdFailed {
-            cmd: format!("find bind definition for {}", new_hash.0),
-            code: None,
-          },
-        });
-      }
+/// Roll back binds updated earlier in a run whose later update failed,
+/// latest first, returning the outcome for each new hash.
+async fn roll_back_updates(
+  updated: &[(&ObjectHash, &ObjectHash, BindState)],
+  current: Option<&Manifest>,
+  desired: &Manifest,
+) -> Vec<(ObjectHash, UpdateRollback)> {
+  let mut rolled_back = Vec::new();
+  for (old_hash, new_hash, old_state) in updated.iter().rev() {
+    // The update recorded the outputs the old definition now moves away from
+    let previous = match load_bind_state(new_hash) {
+      Ok(Some(state)) => state.outputs,
+      _ => desired.bindings.get(*new_hash).map(literal_outputs).unwrap_or_default(),
     };
+    if let Err(e) = transfer_backups(new_hash, old_hash) {
+      warn!(new_hash = %new_hash.0, error = %e, "failed to move backups back to rolled back bind");
+    }
+    let rollback = rollback_update(old_hash, new_hash, previous, current, old_state).await;
+    rolled_back.push(((*new_hash).clone(), rollback));
+  }
+  rolled_back
+}
 
-    // Create resolver for update
-    let resolver = BindCtxResolver::new(&completed_builds, &completed_binds, desired, "/tmp".to_string());
+/// Record a successful update: save the new bind state and, if the hash
+/// changed, move the old bind's backups over and drop its state.
+fn finish_update(old_hash: &ObjectHash, new_hash: &ObjectHash, result: &BindResult) -> Result<(), ExecuteError> {
+  let state_error = |e: BindStateError| ExecuteError::Io { message: e.to_string() };
 
-    // Create old bind result from saved state
-    let old_bind_result = BindResult {
-      outputs: old_bind_state.outputs.clone(),
-      action_results: vec![],
-    };
+  save_bind_state(new_hash, &BindState::from_result(result)).map_err(state_error)?;
 
-    // Execute update
-    debug!(old_hash = %old_hash.0, new_hash = %new_hash.0, "updating bind");
-    let update_result = match update_bind(old_hash, new_hash, new_bind_def, &old_bind_result, &resolver).await {
-      Ok(result) => result,
-      Err(e) => {
-        error!(old_hash = %old_hash.0, new_hash = %new_hash.0, error = %e, "failed to update bind");
-        return Err(ApplyError::UpdateFailed {
-          old_hash: old_hash.clone(),
-          new_hash: new_hash.clone(),
-          source: e,
-        });
-      }
-    };
+  if old_hash != new_hash {
+    transfer_backups(old_hash, new_hash)?;
+    remove_bind_state(old_hash).map_err(state_error)?;
+  }
+  Ok(())
+}
 
-    // Save new bind state
-    let new_bind_state = BindState::new(update_result.outputs.clone());
-    save_bind_state(new_hash, &new_bind_state)?;
+/// Re-apply the previous definition of a bind whose update failed or is
+/// rolled back.
+///
+/// Uses the old definition's update actions when it has them (they converge
+/// to the old state), otherwise its create actions. The update actions see
+/// `previous` as `$${{prev:...}}`: the outputs of the state the update was
+/// moving to. On success the old bind state is rewritten and any state saved
+/// for `new_hash` removed. If the old definition is unknown or fails too,
+/// `old_state` is written back with `needs_repair` set so drift checks report
+/// the bind.
+async fn rollback_update(
+  old_hash: &ObjectHash,
+  new_hash: &ObjectHash,
+  previous: HashMap<String, JsonValue>,
+  current: Option<&Manifest>,
+  old_state: &BindState,
+) -> UpdateRollback {
+  let restored = match current.and_then(|m| m.bindings.get(old_hash).map(|def| (m, def))) {
+    Some((manifest, old_def)) => reapply_bind(old_hash, new_hash, old_def, previous, manifest).await,
+    None => Err("previous bind definition not found".to_string()),
+  };
 
-    // Remove old bind state if hash changed
-    if old_hash != new_hash {
-      remove_bind_state(old_hash)?;
+  let result = match restored {
+    Ok(outputs) => {
+      if old_hash != new_hash
+        && let Err(e) = remove_bind_state(new_hash)
+      {
+        warn!(new_hash = %new_hash.0, error = %e, "failed to remove state of rolled back bind");
+      }
+      // The old definition's file actions set the files recorded before
+      let state = BindState {
+        files: old_state.files.clone(),
+        ..BindState::new(outputs)
+      };
+      save_bind_state(old_hash, &state)
+        .map(|_| UpdateRollback::Restored)
+        .map_err(|e| e.to_string())
     }
+    Err(reason) => Err(reason),
+  };
 
-    updated.push(new_hash.clone());
-    debug!(old_hash = %old_hash.0, new_hash = %new_hash.0, "bind updated");
+  match result {
+    Ok(rollback) => {
+      info!(old_hash = %old_hash.0, "rolled back failed update");
+      rollback
+    }
+    Err(reason) => {
+      error!(old_hash = %old_hash.0, error = %reason, "failed to roll back update, marking bind for repair");
+      let state = BindState {
+        needs_repair: true,
+        ..old_state.clone()
+      };
+      if let Err(e) = save_bind_state(old_hash, &state) {
+        error!(old_hash = %old_hash.0, error = %e, "failed to restore bind state");
+      }
+      UpdateRollback::NeedsRepair(reason)
+    }
   }
+}
 
-  debug!(count = updated.len(), "update phase complete");
-  Ok(updated)
+/// Run the old definition's update (or create) actions for [`rollback_update`].
+async fn reapply_bind(
+  old_hash: &ObjectHash,
+  new_hash: &ObjectHash,
+  old_def: &BindDef,
+  previous: HashMap<String, JsonValue>,
+  manifest: &Manifest,
+) -> Result<HashMap<String, JsonValue>, String> {
+  let (completed_builds, completed_binds) = build_restore_resolver_data(manifest).map_err(|e| e.to_string())?;
+  let resolver = BindCtxResolver::new(&completed_builds, &completed_binds, manifest, "/tmp".to_string());
+
+  let result = if old_def.update_actions.is_some() {
+    let previous = BindResult {
+      outputs: previous,
+      action_results: vec![],
+    };
+    update_bind(new_hash, old_hash, old_def, &previous, &resolver).await
+  } else {
+    apply_bind(old_hash, old_def, &resolver).await
+  };
+
+  result.map(|r| r.outputs).map_err(|e| e.to_string())
+}
+
+/// The outputs of `def` known without running it: those without placeholders.
+fn literal_outputs(def: &BindDef) -> HashMap<String, JsonValue> {
+  def
+    .outputs
+    .iter()
+    .flatten()
+    .filter(|(_, value)| match value {
+      JsonValue::String(s) => placeholder::parse(s).is_ok_and(|segments| {
+        segments
+          .iter()
+          .all(|segment| matches!(segment, placeholder::Segment::Literal(_)))
+      }),
+      _ => true,
+    })
+    .map(|(name, value)| (name.clone(), value.clone()))
+    .collect()
 }
 
 /// Build resolver data for restore operations.
@@ -942,9 +2229,8 @@ async fn update_modified_binds(
 /// Loads bind state for all binds in the manifest (destroyed + unchanged)
 /// and computes build store paths from the manifest. This allows placeholder
 /// resolution during restore (e.g., `${{build:hash:out}}`, `${{bind:hash:output}}`).
-fn build_restore_resolver_data(manifest: &Manifest) -> Result<RestoreResolverData, ApplyError> {
+pub(crate) fn build_restore_resolver_data(manifest: &Manifest) -> Result<RestoreResolverData, ApplyError> {
   let mut builds = HashMap::new();
-  let mut binds = HashMap::new();
 
   // Compute BuildResult for each build (just need store_path and outputs)
   for (hash, build_def) in &manifest.builds {
@@ -976,12 +2262,24 @@ fn build_restore_resolver_data(manifest: &Manifest) -> Result<RestoreResolverDat
         store_path,
         outputs,
         action_results: vec![],
+        cached: false,
       },
     );
   }
 
-  // Load BindState for each bind and convert to BindResult
-  for hash in manifest.bindings.keys() {
+  let binds = load_bind_results(manifest.bindings.keys())?;
+
+  Ok((builds, binds))
+}
+
+/// Load the recorded results of applied binds from their bind state.
+///
+/// Binds without state (never applied) are left out.
+fn load_bind_results<'a>(
+  hashes: impl IntoIterator<Item = &'a ObjectHash>,
+) -> Result<HashMap<ObjectHash, BindResult>, ApplyError> {
+  let mut binds = HashMap::new();
+  for hash in hashes {
     if let Some(state) = load_bind_state(hash)? {
       binds.insert(
         hash.clone(),
@@ -992,8 +2290,29 @@ fn build_restore_resolver_data(manifest: &Manifest) -> Result<RestoreResolverDat
       );
     }
   }
+  Ok(binds)
+}
 
-  Ok((builds, binds))
+/// Restore the binds an apply destroyed before failing, for the error paths
+/// of [`apply_manifest`].
+///
+/// If that fails, the current snapshot pointer is cleared, since the system
+/// no longer matches it; the next apply starts from scratch.
+async fn restore_or_clear(
+  destroyed_hashes: &[ObjectHash],
+  current: Option<&Manifest>,
+  snapshot_store: &SnapshotStore,
+  config: &ExecuteConfig,
+) -> Result<(), ApplyError> {
+  let Some(manifest) = current else {
+    return Ok(());
+  };
+  restore_destroyed_binds(destroyed_hashes, manifest, config)
+    .await
+    .inspect_err(|e| {
+      error!(error = %e, "failed to restore destroyed binds, clearing snapshot pointer");
+      let _ = snapshot_store.clear_current();
+    })
 }
 
 /// Restore previously destroyed binds using DAG ordering from the manifest.
@@ -1032,6 +2351,7 @@ async fn restore_destroyed_binds(
 
   // Create semaphore for parallelism control
   let semaphore = Arc::new(Semaphore::new(config.parallelism));
+  let groups = SerializeGroups::new();
 
   for (wave_idx, wave) in waves.iter().enumerate() {
     // Filter wave to only include destroyed binds
@@ -1060,13 +2380,22 @@ async fn restore_destroyed_binds(
       let completed_builds = completed_builds.clone();
       let completed_binds = completed_binds.clone();
       let semaphore = semaphore.clone();
+      let groups = groups.clone();
       let manifest = manifest.clone();
+      let action_limits = config.action_limits.clone();
+      let transcript = config.transcript.clone();
 
       join_set.spawn(async move {
+        let resolver = BindCtxResolver::new(&completed_builds, &completed_binds, &manifest, "/tmp".to_string())
+          .with_action_limits(action_limits)
+          .with_transcript(transcript);
+
+        let _group = groups.lock(bind_def.serialize.as_deref()).await;
+        let _dirs = groups
+          .lock_dirs(&target_dirs(&bind_def, BindOperation::Create, &resolver))
+          .await;
         let _permit = semaphore.acquire().await.unwrap();
 
-        let resolver = BindCtxResolver::new(&completed_builds, &completed_binds, &manifest, "/tmp".to_string());
-
         let result = apply_bind(&hash, &bind_def, &resolver)
           .await
           .map_err(|e| ApplyError::RestoreFailed {
@@ -1075,7 +2404,7 @@ async fn restore_destroyed_binds(
           })?;
 
         // Save bind state
-        let bind_state = BindState::new(result.outputs.clone());
+        let bind_state = BindState::from_result(&result);
         save_bind_state(&hash, &bind_state).map_err(|e| ApplyError::RestoreFailed {
           hash: hash.clone(),
           source: Box::new(e),
@@ -1119,10 +2448,17 @@ mod tests {
 
   fn test_options() -> ApplyOptions {
     ApplyOptions {
-      execute: ExecuteConfig { parallelism: 1 },
+      execute: ExecuteConfig {
+        parallelism: 1,
+        ..Default::default()
+      },
       dry_run: false,
       repair: false,
       impure: false,
+      input_overrides: BTreeMap::new(),
+      untrusted_inputs: UntrustedInputs::default(),
+      vars_file: None,
+      manifest: None,
     }
   }
 
@@ -1156,6 +2492,11 @@ mod tests {
         inputs: None,
         create_actions: vec![],
         outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       },
     );
     desired.builds.insert(
@@ -1165,6 +2506,11 @@ mod tests {
         inputs: None,
         create_actions: vec![],
         outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       },
     );
 
@@ -1180,6 +2526,14 @@ mod tests {
         destroy_actions: vec![],
         check_actions: None,
         check_outputs: None,
+        backup: None,
+        fallback_path: None,
+        tags: Vec::new(),
+        group: None,
+        repair: None,
+        serialize: None,
+        update_strategy: Default::default(),
+        phase: Default::default(),
       },
     );
     desired.bindings.insert(
@@ -1193,6 +2547,14 @@ mod tests {
         destroy_actions: vec![],
         check_actions: None,
         check_outputs: None,
+        backup: None,
+        fallback_path: None,
+        tags: Vec::new(),
+        group: None,
+        repair: None,
+        serialize: None,
+        update_strategy: Default::default(),
+        phase: Default::default(),
       },
     );
 
@@ -1223,6 +2585,88 @@ mod tests {
     );
   }
 
+  #[test]
+  fn build_execution_manifest_skips_builds_no_selected_bind_needs() {
+    use crate::bind::{BindDef, BindInputsDef};
+    use crate::build::{BuildDef, BuildInputs};
+    use crate::manifest::SkippedBind;
+
+    let build = |inputs: Option<BuildInputs>| BuildDef {
+      id: None,
+      inputs,
+      create_actions: vec![],
+      outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
+    };
+    let bind = |inputs: Option<BindInputsDef>, outputs: Option<BTreeMap<String, JsonValue>>| BindDef {
+      id: None,
+      inputs,
+      outputs,
+      create_actions: vec![],
+      update_actions: None,
+      destroy_actions: vec![],
+      check_actions: None,
+      check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
+    };
+    let hash = |name: &str| ObjectHash(name.to_string());
+
+    let mut desired = Manifest::default();
+    desired.builds.insert(hash("lib"), build(None));
+    desired
+      .builds
+      .insert(hash("tool"), build(Some(BuildInputs::Build(hash("lib")))));
+    desired.builds.insert(hash("old"), build(None));
+    desired.builds.insert(hash("conditional"), build(None));
+    desired.builds.insert(hash("standalone"), build(None));
+    // The new bind only uses tool through a placeholder, as a create function
+    // capturing the build would
+    desired.bindings.insert(
+      hash("new_bind"),
+      bind(
+        None,
+        Some(BTreeMap::from([(
+          "link".to_string(),
+          JsonValue::String("$${{build:tool:out}}/bin/tool".to_string()),
+        )])),
+      ),
+    );
+    desired.bindings.insert(
+      hash("unchanged_bind"),
+      bind(Some(BindInputsDef::Build(hash("old"))), None),
+    );
+    desired.skipped.push(SkippedBind {
+      id: Some("service".to_string()),
+      reason: "requires systemd".to_string(),
+      builds: vec![hash("conditional")],
+    });
+
+    let diff = StateDiff {
+      builds_to_realize: vec![hash("tool"), hash("old"), hash("conditional"), hash("standalone")],
+      builds_cached: vec![hash("lib")],
+      binds_to_apply: vec![hash("new_bind")],
+      binds_to_destroy: vec![],
+      binds_unchanged: vec![hash("unchanged_bind")],
+      binds_to_update: vec![],
+      builds_orphaned: vec![],
+    };
+
+    let exec_manifest = build_execution_manifest(&desired, &diff);
+    let builds: HashSet<&str> = exec_manifest.builds.keys().map(|h| h.0.as_str()).collect();
+    assert_eq!(builds, HashSet::from(["lib", "tool", "standalone"]));
+  }
+
   #[tokio::test]
   async fn apply_config_not_found() {
     let result = apply(Path::new("/nonexistent/config.lua"), &test_options()).await;
@@ -1322,6 +2766,11 @@ mod tests {
           inputs: None,
           create_actions: vec![],
           outputs: None,
+          resources: None,
+          metadata: None,
+          check_actions: None,
+          prebuilt: None,
+          impure_env: None,
         },
       );
 
@@ -1370,6 +2819,14 @@ mod tests {
           destroy_actions: vec![],
           check_actions: None,
           check_outputs: None,
+          backup: None,
+          fallback_path: None,
+          tags: Vec::new(),
+          group: None,
+          repair: None,
+          serialize: None,
+          update_strategy: Default::default(),
+          phase: Default::default(),
         },
       );
 
@@ -1406,6 +2863,14 @@ mod tests {
           destroy_actions: vec![],
           check_actions: None,
           check_outputs: None,
+          backup: None,
+          fallback_path: None,
+          tags: Vec::new(),
+          group: None,
+          repair: None,
+          serialize: None,
+          update_strategy: Default::default(),
+          phase: Default::default(),
         },
       );
 
@@ -1451,6 +2916,14 @@ mod tests {
           destroy_actions: vec![],
           check_actions: None,
           check_outputs: None,
+          backup: None,
+          fallback_path: None,
+          tags: Vec::new(),
+          group: None,
+          repair: None,
+          serialize: None,
+          update_strategy: Default::default(),
+          phase: Default::default(),
         },
       );
 
@@ -1495,6 +2968,87 @@ mod tests {
     });
   }
 
+  #[test]
+  #[serial]
+  fn destroy_removed_binds_destroys_dependents_first() {
+    use crate::action::Action;
+    use crate::action::actions::exec::ExecOpts;
+    use crate::bind::{BindDef, BindInputsDef};
+    use crate::util::testutil::shell_cmd;
+
+    with_temp_env(|temp_dir| {
+      let log = temp_dir.path().join("destroy.log");
+      let make_bind = |id: &str, inputs: Option<BindInputsDef>| {
+        let (cmd, args) = shell_cmd(&format!("echo {}>> \"{}\"", id, log.display()));
+        BindDef {
+          id: Some(id.to_string()),
+          inputs,
+          outputs: None,
+          create_actions: vec![],
+          update_actions: None,
+          destroy_actions: vec![Action::Exec(ExecOpts::new(cmd).with_args(args))],
+          check_actions: None,
+          check_outputs: None,
+          backup: None,
+          fallback_path: None,
+          tags: Vec::new(),
+          group: None,
+          repair: None,
+          serialize: None,
+          update_strategy: Default::default(),
+          phase: Default::default(),
+        }
+      };
+
+      // base <- middle <- top, plus an unrelated bind
+      let base = make_bind("base", None);
+      let base_hash = base.compute_hash().unwrap();
+      let middle = make_bind("middle", Some(BindInputsDef::Bind(base_hash.clone())));
+      let middle_hash = middle.compute_hash().unwrap();
+      let top = make_bind("top", Some(BindInputsDef::Bind(middle_hash.clone())));
+      let top_hash = top.compute_hash().unwrap();
+      let other = make_bind("other", None);
+      let other_hash = other.compute_hash().unwrap();
+
+      let mut manifest = Manifest::default();
+      for (hash, bind) in [
+        (&base_hash, base),
+        (&middle_hash, middle),
+        (&top_hash, top),
+        (&other_hash, other),
+      ] {
+        save_bind_state(hash, &BindState::new(HashMap::new())).unwrap();
+        manifest.bindings.insert(hash.clone(), bind);
+      }
+
+      let config = ExecuteConfig {
+        parallelism: 4,
+        ..Default::default()
+      };
+      let hashes = [
+        base_hash.clone(),
+        middle_hash.clone(),
+        top_hash.clone(),
+        other_hash.clone(),
+      ];
+      let rt = tokio::runtime::Runtime::new().unwrap();
+      let destroyed = rt
+        .block_on(destroy_removed_binds(&hashes, Some(&manifest), &config))
+        .unwrap();
+      assert_eq!(destroyed.len(), 4);
+
+      let order: Vec<String> = std::fs::read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(|line| line.trim().to_string())
+        .collect();
+      let position = |id: &str| order.iter().position(|line| line == id).unwrap();
+      assert!(position("top") < position("middle"));
+      assert!(position("middle") < position("base"));
+      assert!(order.contains(&"other".to_string()));
+    });
+  }
+
   #[test]
   #[serial]
   fn restore_destroyed_binds_handles_empty_list() {
@@ -1546,6 +3100,14 @@ mod tests {
           destroy_actions: vec![],
           check_actions: None,
           check_outputs: None,
+          backup: None,
+          fallback_path: None,
+          tags: Vec::new(),
+          group: None,
+          repair: None,
+          serialize: None,
+          update_strategy: Default::default(),
+          phase: Default::default(),
         },
       );
 
@@ -1590,7 +3152,188 @@ mod tests {
       ));
 
       // Should fail because new bind definition doesn't exist
-      assert!(matches!(result, Err(ApplyError::UpdateFailed { .. })));
+      assert!(matches!(
+        result,
+        Err(ApplyError::UpdateFailed {
+          rollback: UpdateRollback::Unchanged,
+          ..
+        })
+      ));
+    });
+  }
+
+  /// Bind whose create and update actions run `script` with `sh -c`.
+  #[cfg(unix)]
+  fn shell_bind(id: &str, script: &str) -> crate::bind::BindDef {
+    use crate::action::Action;
+    use crate::action::actions::exec::ExecOpts;
+
+    let exec = Action::Exec(ExecOpts {
+      bin: "/bin/sh".to_string(),
+      args: Some(vec!["-c".to_string(), script.to_string()]),
+      env: None,
+      cwd: None,
+      creates: None,
+      unless: None,
+      shell: None,
+      run_as: None,
+    });
+    crate::bind::BindDef {
+      id: Some(id.to_string()),
+      inputs: None,
+      outputs: None,
+      create_actions: vec![exec.clone()],
+      update_actions: Some(vec![exec]),
+      destroy_actions: vec![],
+      check_actions: None,
+      check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
+    }
+  }
+
+  #[test]
+  #[serial]
+  #[cfg(unix)]
+  fn update_modified_binds_rolls_back_failed_update() {
+    with_temp_env(|temp_dir| {
+      let marker = temp_dir.path().join("marker");
+      let old_hash = ObjectHash("old_bind".to_string());
+      let new_hash = ObjectHash("new_bind".to_string());
+
+      let mut current = Manifest::default();
+      current.bindings.insert(
+        old_hash.clone(),
+        shell_bind("test-bind", &format!("echo old > {}", marker.display())),
+      );
+      let mut desired = Manifest::default();
+      desired.bindings.insert(
+        new_hash.clone(),
+        shell_bind("test-bind", &format!("echo new > {} && false", marker.display())),
+      );
+      save_bind_state(&old_hash, &BindState::empty()).unwrap();
+
+      let rt = tokio::runtime::Runtime::new().unwrap();
+      let result = rt.block_on(update_modified_binds(
+        &[(old_hash.clone(), new_hash.clone())],
+        Some(&current),
+        &desired,
+        &ExecuteConfig::default(),
+      ));
+
+      assert!(matches!(
+        result,
+        Err(ApplyError::UpdateFailed {
+          rollback: UpdateRollback::Restored,
+          ..
+        })
+      ));
+      assert_eq!(std::fs::read_to_string(&marker).unwrap().trim(), "old");
+      assert!(!load_bind_state(&old_hash).unwrap().unwrap().needs_repair);
+      assert!(load_bind_state(&new_hash).unwrap().is_none());
+    });
+  }
+
+  #[test]
+  #[serial]
+  #[cfg(unix)]
+  fn failed_update_rolls_back_earlier_updates() {
+    with_temp_env(|temp_dir| {
+      let first_marker = temp_dir.path().join("first");
+      let second_marker = temp_dir.path().join("second");
+      let hash = |name: &str| ObjectHash(name.to_string());
+
+      let mut current = Manifest::default();
+      current.bindings.insert(
+        hash("old_first"),
+        shell_bind("first", &format!("echo old > {}", first_marker.display())),
+      );
+      current.bindings.insert(
+        hash("old_second"),
+        shell_bind("second", &format!("echo old > {}", second_marker.display())),
+      );
+      let mut desired = Manifest::default();
+      desired.bindings.insert(
+        hash("new_first"),
+        shell_bind("first", &format!("echo new > {}", first_marker.display())),
+      );
+      desired
+        .bindings
+        .insert(hash("new_second"), shell_bind("second", "false"));
+      save_bind_state(&hash("old_first"), &BindState::empty()).unwrap();
+      save_bind_state(&hash("old_second"), &BindState::empty()).unwrap();
+
+      let rt = tokio::runtime::Runtime::new().unwrap();
+      let result = rt.block_on(update_modified_binds(
+        &[
+          (hash("old_first"), hash("new_first")),
+          (hash("old_second"), hash("new_second")),
+        ],
+        Some(&current),
+        &desired,
+        &ExecuteConfig::default(),
+      ));
+
+      let reverted = match result {
+        Err(ApplyError::UpdateFailed { reverted, .. }) => reverted,
+        other => panic!("expected a failed update, got {:?}", other),
+      };
+      assert_eq!(reverted, vec![(hash("new_first"), UpdateRollback::Restored)]);
+      assert_eq!(std::fs::read_to_string(&first_marker).unwrap().trim(), "old");
+      assert!(load_bind_state(&hash("old_first")).unwrap().is_some());
+      assert!(load_bind_state(&hash("new_first")).unwrap().is_none());
+    });
+  }
+
+  #[test]
+  #[serial]
+  #[cfg(unix)]
+  fn failed_rollback_marks_bind_for_repair() {
+    with_temp_env(|_temp_dir| {
+      let old_hash = ObjectHash("old_bind".to_string());
+      let new_hash = ObjectHash("new_bind".to_string());
+
+      // Old definition is unknown, so the rollback cannot re-apply it
+      let mut desired = Manifest::default();
+      desired
+        .bindings
+        .insert(new_hash.clone(), shell_bind("test-bind", "false"));
+      save_bind_state(&old_hash, &BindState::empty()).unwrap();
+
+      let config = ExecuteConfig::default();
+      let rt = tokio::runtime::Runtime::new().unwrap();
+      let result = rt.block_on(update_modified_binds(
+        &[(old_hash.clone(), new_hash.clone())],
+        None,
+        &desired,
+        &config,
+      ));
+
+      assert!(matches!(
+        result,
+        Err(ApplyError::UpdateFailed {
+          rollback: UpdateRollback::NeedsRepair(_),
+          ..
+        })
+      ));
+      assert!(load_bind_state(&old_hash).unwrap().unwrap().needs_repair);
+
+      // Drift checks report the bind even without a check callback
+      let mut current = Manifest::default();
+      current
+        .bindings
+        .insert(old_hash.clone(), shell_bind("test-bind", "true"));
+      let drift = rt
+        .block_on(check_unchanged_binds(&[old_hash.clone()], &current, &config))
+        .unwrap();
+      assert_eq!(drift.len(), 1);
+      assert!(drift[0].result.drifted);
     });
   }
 
@@ -1604,9 +3347,234 @@ mod tests {
       binds_destroyed: 3,
       binds_updated: 5,
       drift_results: vec![],
+      store_bytes_added: 0,
     };
 
     assert_eq!(result.binds_destroyed, 3);
     assert_eq!(result.binds_updated, 5);
   }
+
+  /// Manifest with a build, a bind using it, a bind depending on that bind,
+  /// and an unrelated tagged bind.
+  fn partial_destroy_manifest() -> Manifest {
+    use crate::bind::{BindDef, BindInputsDef};
+    use crate::build::BuildDef;
+
+    let bind = |id: &str, tags: &[&str], inputs: Option<BindInputsDef>| BindDef {
+      id: Some(id.to_string()),
+      inputs,
+      outputs: None,
+      create_actions: vec![],
+      update_actions: None,
+      destroy_actions: vec![],
+      check_actions: None,
+      check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: tags.iter().map(|t| t.to_string()).collect(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
+    };
+
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(
+      ObjectHash("aaaa1111".to_string()),
+      BuildDef {
+        id: None,
+        inputs: None,
+        create_actions: vec![],
+        outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
+      },
+    );
+    manifest.bindings.insert(
+      ObjectHash("bbbb1111".to_string()),
+      bind(
+        "tool",
+        &[],
+        Some(BindInputsDef::Build(ObjectHash("aaaa1111".to_string()))),
+      ),
+    );
+    manifest.bindings.insert(
+      ObjectHash("bbbb2222".to_string()),
+      bind(
+        "tool-config",
+        &[],
+        Some(BindInputsDef::Bind(ObjectHash("bbbb1111".to_string()))),
+      ),
+    );
+    manifest
+      .bindings
+      .insert(ObjectHash("cccc1111".to_string()), bind("shell", &["shell"], None));
+    manifest
+  }
+
+  #[test]
+  fn select_binds_includes_dependents_first() {
+    let manifest = partial_destroy_manifest();
+
+    let selected = select_binds(&manifest, &["tool".to_string()], &[]).unwrap();
+
+    assert_eq!(
+      selected,
+      vec![ObjectHash("bbbb2222".to_string()), ObjectHash("bbbb1111".to_string())]
+    );
+  }
+
+  #[test]
+  fn select_binds_matches_tags_and_hash_prefixes() {
+    let manifest = partial_destroy_manifest();
+
+    let by_tag = select_binds(&manifest, &["shell".to_string()], &[]).unwrap();
+    assert_eq!(by_tag, vec![ObjectHash("cccc1111".to_string())]);
+
+    let by_hash = select_binds(&manifest, &["bbbb2".to_string()], &[]).unwrap();
+    assert_eq!(by_hash, vec![ObjectHash("bbbb2222".to_string())]);
+  }
+
+  #[test]
+  fn select_binds_rejects_unknown_and_ambiguous_selectors() {
+    let manifest = partial_destroy_manifest();
+
+    let err = select_binds(&manifest, &["missing".to_string()], &[]).unwrap_err();
+    assert!(matches!(err, ApplyError::NoMatchingBinds(s) if s == "missing"));
+
+    let err = select_binds(&manifest, &["bbbb".to_string()], &[]).unwrap_err();
+    assert!(matches!(err, ApplyError::AmbiguousSelector { candidates, .. } if candidates.len() == 2));
+  }
+
+  #[test]
+  fn select_binds_matches_groups() {
+    let mut manifest = partial_destroy_manifest();
+    for bind in manifest.bindings.values_mut() {
+      if bind.id.as_deref() == Some("tool") {
+        bind.group = Some("dev".to_string());
+      }
+    }
+
+    let selected = select_binds(&manifest, &[], &["dev".to_string()]).unwrap();
+    assert_eq!(
+      selected,
+      vec![ObjectHash("bbbb2222".to_string()), ObjectHash("bbbb1111".to_string())]
+    );
+
+    let err = select_binds(&manifest, &[], &["media".to_string()]).unwrap_err();
+    assert!(matches!(err, ApplyError::EmptyGroup(g) if g == "media"));
+  }
+
+  #[test]
+  fn match_binds_leaves_out_dependents() {
+    let manifest = partial_destroy_manifest();
+
+    let matched = match_binds(&manifest, &["tool".to_string()], &[]).unwrap();
+    assert_eq!(matched, HashSet::from([ObjectHash("bbbb1111".to_string())]));
+  }
+
+  #[test]
+  fn remaining_manifest_drops_builds_only_used_by_destroyed_binds() {
+    let manifest = partial_destroy_manifest();
+
+    let kept = remaining_manifest(&manifest, &[ObjectHash("cccc1111".to_string())]).unwrap();
+    assert_eq!(kept.bindings.len(), 2);
+    assert_eq!(kept.builds.len(), 1);
+
+    let selected = select_binds(&manifest, &["tool".to_string()], &[]).unwrap();
+    let remaining = remaining_manifest(&manifest, &selected).unwrap();
+    assert_eq!(
+      remaining.bindings.keys().collect::<Vec<_>>(),
+      vec![&ObjectHash("cccc1111".to_string())]
+    );
+    assert!(remaining.builds.is_empty());
+  }
+
+  fn hashes(names: &[&str]) -> HashSet<ObjectHash> {
+    names.iter().map(|n| ObjectHash(n.to_string())).collect()
+  }
+
+  #[test]
+  fn deselect_create_drops_dependents_and_their_builds() {
+    let desired = partial_destroy_manifest();
+    let mut current = desired.clone();
+    current.bindings.retain(|hash, _| hash.0 == "cccc1111");
+    current.builds.clear();
+    let diff = StateDiff {
+      binds_to_apply: vec![ObjectHash("bbbb1111".to_string()), ObjectHash("bbbb2222".to_string())],
+      binds_unchanged: vec![ObjectHash("cccc1111".to_string())],
+      ..Default::default()
+    };
+
+    let selected = deselect_changes(&desired, Some(&current), &diff, &hashes(&["bbbb1111"])).unwrap();
+    assert_eq!(
+      selected.bindings.keys().collect::<Vec<_>>(),
+      vec![&ObjectHash("cccc1111".to_string())]
+    );
+    assert!(selected.builds.is_empty());
+
+    let selected = deselect_changes(&desired, Some(&current), &diff, &hashes(&["bbbb2222"])).unwrap();
+    assert_eq!(selected.bindings.len(), 2);
+    assert_eq!(selected.builds.len(), 1);
+  }
+
+  #[test]
+  fn deselect_destroy_keeps_current_definition_and_dependencies() {
+    let current = partial_destroy_manifest();
+    let desired = Manifest::default();
+    let diff = StateDiff {
+      binds_to_destroy: vec![
+        ObjectHash("bbbb2222".to_string()),
+        ObjectHash("bbbb1111".to_string()),
+        ObjectHash("cccc1111".to_string()),
+      ],
+      ..Default::default()
+    };
+
+    // Keeping tool-config also keeps the tool bind it depends on
+    let selected = deselect_changes(&desired, Some(&current), &diff, &hashes(&["bbbb2222"])).unwrap();
+    assert_eq!(selected.bindings.len(), 2);
+    assert!(!selected.bindings.contains_key(&ObjectHash("cccc1111".to_string())));
+    assert!(selected.builds.contains_key(&ObjectHash("aaaa1111".to_string())));
+  }
+
+  #[test]
+  fn deselect_replacement_keeps_both_halves_together() {
+    let current = partial_destroy_manifest();
+    let mut desired = current.clone();
+    let shell = desired.bindings.remove(&ObjectHash("cccc1111".to_string())).unwrap();
+    desired.bindings.insert(ObjectHash("cccc2222".to_string()), shell);
+    let diff = StateDiff {
+      binds_to_apply: vec![ObjectHash("cccc2222".to_string())],
+      binds_to_destroy: vec![ObjectHash("cccc1111".to_string())],
+      ..Default::default()
+    };
+
+    let selected = deselect_changes(&desired, Some(&current), &diff, &hashes(&["cccc1111"])).unwrap();
+    assert_eq!(selected.bindings, current.bindings);
+  }
+
+  #[test]
+  fn changes_outside_groups_keeps_group_changes() {
+    let current = partial_destroy_manifest();
+    let mut desired = current.clone();
+    let mut shell = desired.bindings.remove(&ObjectHash("cccc1111".to_string())).unwrap();
+    shell.group = Some("shell".to_string());
+    desired.bindings.insert(ObjectHash("cccc2222".to_string()), shell);
+    let diff = StateDiff {
+      binds_to_apply: vec![ObjectHash("cccc2222".to_string()), ObjectHash("dddd1111".to_string())],
+      binds_to_destroy: vec![ObjectHash("cccc1111".to_string())],
+      ..Default::default()
+    };
+
+    let outside = changes_outside_groups(&desired, Some(&current), &diff, &["shell".to_string()]).unwrap();
+    assert_eq!(outside, hashes(&["dddd1111", "cccc1111"]));
+
+    let err = changes_outside_groups(&desired, Some(&current), &diff, &["media".to_string()]).unwrap_err();
+    assert!(matches!(err, ApplyError::EmptyGroup(_)));
+  }
 }
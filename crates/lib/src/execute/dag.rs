@@ -2,12 +2,28 @@
 //!
 //! This module provides a directed acyclic graph (DAG) for managing build and bind
 //! dependencies and computing parallel execution waves.
+//!
+//! The DAG is also exposed through [`crate::api`] for tooling that asks
+//! questions like "what depends on bind X": [`ExecutionDag::dependents`],
+//! [`ExecutionDag::dependencies`], [`ExecutionDag::topological_order`] and
+//! [`ExecutionDag::subgraph`] work on [`DagNode`]s alone, and the DAG
+//! serializes as its nodes and edges:
+//!
+//! ```json
+//! {
+//!   "nodes": [{ "kind": "build", "hash": "ab12..." }, { "kind": "bind", "hash": "cd34..." }],
+//!   "edges": [{ "from": { "kind": "build", "hash": "ab12..." }, "to": { "kind": "bind", "hash": "cd34..." } }]
+//! }
+//! ```
 
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use petgraph::Direction;
 use petgraph::algo::toposort;
 use petgraph::graph::{DiGraph, NodeIndex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 use tracing::trace;
 
 use crate::bind::{BindDef, BindInputsDef};
@@ -23,7 +39,8 @@ use super::types::ExecuteError;
 /// Represents either a build or bind that needs to be executed.
 /// Used for unified wave computation where builds and binds are
 /// interleaved based on their dependencies.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "hash", rename_all = "snake_case")]
 pub enum DagNode {
   /// A build to be realized.
   Build(ObjectHash),
@@ -37,6 +54,8 @@ pub enum DagNode {
 /// - Topological ordering of builds and binds
 /// - Parallel execution waves (groups of independent nodes)
 /// - Dependency queries for both builds and binds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "DagDocument", try_from = "DagDocument")]
 pub struct ExecutionDag {
   /// The underlying graph.
   graph: DiGraph<DagNode, ()>,
@@ -128,6 +147,56 @@ impl ExecutionDag {
     Ok(dag)
   }
 
+  /// Build a DAG from its nodes and `(dependency, dependent)` edges.
+  ///
+  /// # Errors
+  ///
+  /// Returns `InvalidManifest` if an edge names a node that isn't listed, and
+  /// `CycleDetected` if the edges form a cycle.
+  pub fn from_edges(
+    nodes: impl IntoIterator<Item = DagNode>,
+    edges: impl IntoIterator<Item = (DagNode, DagNode)>,
+  ) -> Result<Self, ExecuteError> {
+    let mut graph = DiGraph::new();
+    let mut build_nodes = HashMap::new();
+    let mut bind_nodes = HashMap::new();
+
+    for node in nodes {
+      let (index, hash) = match &node {
+        DagNode::Build(hash) => (&mut build_nodes, hash.clone()),
+        DagNode::Bind(hash) => (&mut bind_nodes, hash.clone()),
+      };
+      if let Entry::Vacant(entry) = index.entry(hash) {
+        entry.insert(graph.add_node(node));
+      }
+    }
+
+    let mut dag = Self {
+      graph,
+      build_nodes,
+      bind_nodes,
+    };
+    for (from, to) in edges {
+      let (Some(from_idx), Some(to_idx)) = (dag.index_of(&from), dag.index_of(&to)) else {
+        return Err(ExecuteError::InvalidManifest(format!(
+          "DAG edge {:?} -> {:?} names a node that isn't in the DAG",
+          from, to
+        )));
+      };
+      dag.graph.update_edge(from_idx, to_idx, ());
+    }
+
+    dag.verify_acyclic()?;
+    Ok(dag)
+  }
+
+  fn index_of(&self, node: &DagNode) -> Option<NodeIndex> {
+    match node {
+      DagNode::Build(hash) => self.build_nodes.get(hash).copied(),
+      DagNode::Bind(hash) => self.bind_nodes.get(hash).copied(),
+    }
+  }
+
   /// Verify that the graph is acyclic.
   fn verify_acyclic(&self) -> Result<(), ExecuteError> {
     toposort(&self.graph, None).map_err(|_| ExecuteError::CycleDetected)?;
@@ -409,6 +478,147 @@ impl ExecutionDag {
 
     Ok(waves)
   }
+
+  /// Length of the longest chain of work starting at each node.
+  ///
+  /// `estimate` gives the expected cost of a single node; a node's length is
+  /// its own cost plus the longest length among its dependents. Starting the
+  /// nodes with the longest chain first shortens the whole execution.
+  pub fn critical_path_lengths(
+    &self,
+    estimate: impl Fn(&DagNode) -> u64,
+  ) -> Result<HashMap<DagNode, u64>, ExecuteError> {
+    let order = toposort(&self.graph, None).map_err(|_| ExecuteError::CycleDetected)?;
+    let mut lengths: HashMap<NodeIndex, u64> = HashMap::new();
+
+    // Dependents come after their dependencies, so walk backwards
+    for &idx in order.iter().rev() {
+      let downstream = self
+        .graph
+        .neighbors_directed(idx, Direction::Outgoing)
+        .filter_map(|dependent| lengths.get(&dependent).copied())
+        .max()
+        .unwrap_or(0);
+      lengths.insert(idx, estimate(&self.graph[idx]).saturating_add(downstream));
+    }
+
+    Ok(
+      lengths
+        .into_iter()
+        .map(|(idx, length)| (self.graph[idx].clone(), length))
+        .collect(),
+    )
+  }
+
+  /// Whether `node` is part of the DAG.
+  pub fn contains(&self, node: &DagNode) -> bool {
+    self.index_of(node).is_some()
+  }
+
+  /// All nodes, in the order they were added.
+  pub fn nodes(&self) -> impl Iterator<Item = &DagNode> {
+    self.graph.node_weights()
+  }
+
+  /// All `(dependency, dependent)` edges, sorted.
+  pub fn edges(&self) -> Vec<(DagNode, DagNode)> {
+    let edges: BTreeSet<(DagNode, DagNode)> = self
+      .graph
+      .edge_indices()
+      .filter_map(|edge| self.graph.edge_endpoints(edge))
+      .map(|(from, to)| (self.graph[from].clone(), self.graph[to].clone()))
+      .collect();
+    edges.into_iter().collect()
+  }
+
+  /// The nodes `node` directly depends on, sorted; empty for unknown nodes.
+  pub fn dependencies(&self, node: &DagNode) -> Vec<DagNode> {
+    self.neighbors(node, Direction::Incoming)
+  }
+
+  /// The nodes that directly depend on `node`, sorted; empty for unknown nodes.
+  pub fn dependents(&self, node: &DagNode) -> Vec<DagNode> {
+    self.neighbors(node, Direction::Outgoing)
+  }
+
+  fn neighbors(&self, node: &DagNode, direction: Direction) -> Vec<DagNode> {
+    let Some(idx) = self.index_of(node) else {
+      return Vec::new();
+    };
+    let neighbors: BTreeSet<DagNode> = self
+      .graph
+      .neighbors_directed(idx, direction)
+      .map(|neighbor| self.graph[neighbor].clone())
+      .collect();
+    neighbors.into_iter().collect()
+  }
+
+  /// Every node, dependencies before their dependents.
+  pub fn topological_order(&self) -> Vec<DagNode> {
+    toposort(&self.graph, None)
+      .expect("the DAG was checked for cycles when built")
+      .into_iter()
+      .map(|idx| self.graph[idx].clone())
+      .collect()
+  }
+
+  /// The part of the DAG needed for `roots`: the roots themselves and
+  /// everything they transitively depend on. Unknown roots are ignored.
+  pub fn subgraph(&self, roots: impl IntoIterator<Item = DagNode>) -> ExecutionDag {
+    let mut keep: HashSet<NodeIndex> = HashSet::new();
+    let mut stack: Vec<NodeIndex> = roots.into_iter().filter_map(|root| self.index_of(&root)).collect();
+    while let Some(idx) = stack.pop() {
+      if keep.insert(idx) {
+        stack.extend(self.graph.neighbors_directed(idx, Direction::Incoming));
+      }
+    }
+
+    let nodes = self
+      .graph
+      .node_indices()
+      .filter(|idx| keep.contains(idx))
+      .map(|idx| self.graph[idx].clone());
+    let edges = self.edges().into_iter().filter(|(from, to)| {
+      [from, to]
+        .iter()
+        .all(|node| self.index_of(node).is_some_and(|idx| keep.contains(&idx)))
+    });
+    Self::from_edges(nodes, edges).expect("a subgraph of an acyclic DAG is acyclic")
+  }
+}
+
+/// Serialized form of an [`ExecutionDag`].
+#[derive(Serialize, Deserialize)]
+struct DagDocument {
+  nodes: Vec<DagNode>,
+  edges: Vec<DagEdge>,
+}
+
+/// A dependency edge: `to` depends on `from`.
+#[derive(Serialize, Deserialize)]
+struct DagEdge {
+  from: DagNode,
+  to: DagNode,
+}
+
+impl From<ExecutionDag> for DagDocument {
+  fn from(dag: ExecutionDag) -> Self {
+    Self {
+      nodes: dag.nodes().cloned().collect(),
+      edges: dag.edges().into_iter().map(|(from, to)| DagEdge { from, to }).collect(),
+    }
+  }
+}
+
+impl TryFrom<DagDocument> for ExecutionDag {
+  type Error = ExecuteError;
+
+  fn try_from(document: DagDocument) -> Result<Self, Self::Error> {
+    ExecutionDag::from_edges(
+      document.nodes,
+      document.edges.into_iter().map(|edge| (edge.from, edge.to)),
+    )
+  }
 }
 
 /// Extract build dependencies from BuildInputs.
@@ -466,7 +676,11 @@ fn extract_placeholder_deps_for_build(s: &str, deps: &mut Vec<ObjectHash>) -> Re
             "build input contains bind placeholder '${{{{bind:{hash}:...}}}}' - builds cannot depend on binds"
           )));
         }
-        Placeholder::Action(_) | Placeholder::Out | Placeholder::Env(_) => {}
+        Placeholder::Action(_)
+        | Placeholder::ActionOutput { .. }
+        | Placeholder::Out
+        | Placeholder::Env(_)
+        | Placeholder::Prev(_) => {}
       }
     }
   }
@@ -518,9 +732,49 @@ fn extract_placeholder_deps_for_bind(s: &str, deps: &mut Vec<DagNode>) {
         Placeholder::Bind { hash, .. } => {
           deps.push(DagNode::Bind(ObjectHash(hash)));
         }
-        Placeholder::Action(_) | Placeholder::Out | Placeholder::Env(_) => {}
+        Placeholder::Action(_)
+        | Placeholder::ActionOutput { .. }
+        | Placeholder::Out
+        | Placeholder::Env(_)
+        | Placeholder::Prev(_) => {}
+      }
+    }
+  }
+}
+
+/// Builds a definition refers to anywhere in it: as a `Build` input or in a
+/// `$${{build:...}}` placeholder of its inputs, actions or outputs.
+///
+/// Unlike the edges of [`ExecutionDag`], which only follow inputs, this also
+/// finds builds a `create` function captured and used directly.
+pub fn builds_referenced_by(def: &impl Serialize) -> BTreeSet<ObjectHash> {
+  let mut builds = BTreeSet::new();
+  if let Ok(value) = serde_json::to_value(def) {
+    collect_referenced_builds(&value, &mut builds);
+  }
+  builds
+}
+
+fn collect_referenced_builds(value: &JsonValue, builds: &mut BTreeSet<ObjectHash>) {
+  match value {
+    JsonValue::String(s) => {
+      for segment in placeholder::parse(s).unwrap_or_default() {
+        if let Segment::Placeholder(Placeholder::Build { hash, .. }) = segment {
+          builds.insert(ObjectHash(hash));
+        }
       }
     }
+    JsonValue::Object(map) => {
+      // A `Build` input serializes as `{ "Build": "<hash>" }`
+      if map.len() == 1
+        && let Some(JsonValue::String(hash)) = map.get("Build")
+      {
+        builds.insert(ObjectHash(hash.clone()));
+      }
+      map.values().for_each(|v| collect_referenced_builds(v, builds));
+    }
+    JsonValue::Array(values) => values.iter().for_each(|v| collect_referenced_builds(v, builds)),
+    JsonValue::Null | JsonValue::Bool(_) | JsonValue::Number(_) => {}
   }
 }
 
@@ -544,8 +798,17 @@ mod tests {
         args: Some(vec![id.to_string()]),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     }
   }
 
@@ -559,11 +822,23 @@ mod tests {
         args: Some(vec!["test".to_string()]),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: None,
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     }
   }
 
@@ -1190,4 +1465,116 @@ mod tests {
     let bind_deps = dag.bind_bind_dependencies(&bind_hash_c);
     assert_eq!(bind_deps, vec![bind_hash_b]);
   }
+
+  #[test]
+  fn critical_path_lengths_include_dependents() {
+    // A -> B, and an independent C
+    let build_a = make_build("a", None);
+    let hash_a = build_a.compute_hash().unwrap();
+    let build_b = make_build("b", Some(BuildInputs::Build(hash_a.clone())));
+    let hash_b = build_b.compute_hash().unwrap();
+    let build_c = make_build("c", None);
+    let hash_c = build_c.compute_hash().unwrap();
+
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(hash_a.clone(), build_a);
+    manifest.builds.insert(hash_b.clone(), build_b);
+    manifest.builds.insert(hash_c.clone(), build_c);
+
+    let dag = ExecutionDag::from_manifest(&manifest).unwrap();
+    let estimates = HashMap::from([(hash_a.clone(), 10), (hash_b.clone(), 100), (hash_c.clone(), 50)]);
+    let lengths = dag
+      .critical_path_lengths(|node| match node {
+        DagNode::Build(hash) | DagNode::Bind(hash) => estimates[hash],
+      })
+      .unwrap();
+
+    // A is short but B waits on it, so A's chain is the longest
+    assert_eq!(lengths[&DagNode::Build(hash_a)], 110);
+    assert_eq!(lengths[&DagNode::Build(hash_b)], 100);
+    assert_eq!(lengths[&DagNode::Build(hash_c)], 50);
+  }
+
+  /// Build A, build B on A, bind X on A, bind Y on X.
+  fn query_manifest() -> (Manifest, [DagNode; 4]) {
+    let build_a = make_build("a", None);
+    let hash_a = build_a.compute_hash().unwrap();
+    let build_b = make_build("b", Some(BuildInputs::Build(hash_a.clone())));
+    let hash_b = build_b.compute_hash().unwrap();
+    let bind_x = make_bind(Some(BindInputsDef::Build(hash_a.clone())));
+    let hash_x = bind_x.compute_hash().unwrap();
+    let bind_y = make_bind(Some(BindInputsDef::Bind(hash_x.clone())));
+    let hash_y = bind_y.compute_hash().unwrap();
+
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(hash_a.clone(), build_a);
+    manifest.builds.insert(hash_b.clone(), build_b);
+    manifest.bindings.insert(hash_x.clone(), bind_x);
+    manifest.bindings.insert(hash_y.clone(), bind_y);
+    (
+      manifest,
+      [
+        DagNode::Build(hash_a),
+        DagNode::Build(hash_b),
+        DagNode::Bind(hash_x),
+        DagNode::Bind(hash_y),
+      ],
+    )
+  }
+
+  #[test]
+  fn dependents_and_dependencies_of_a_node() {
+    let (manifest, [a, b, x, y]) = query_manifest();
+    let dag = ExecutionDag::from_manifest(&manifest).unwrap();
+
+    let mut dependents_of_a = vec![b.clone(), x.clone()];
+    dependents_of_a.sort();
+    assert_eq!(dag.dependents(&a), dependents_of_a);
+    assert_eq!(dag.dependencies(&y), vec![x.clone()]);
+    assert!(dag.dependents(&y).is_empty());
+    assert!(
+      dag
+        .dependencies(&DagNode::Bind(ObjectHash("missing".to_string())))
+        .is_empty()
+    );
+
+    let order = dag.topological_order();
+    assert_eq!(order.len(), 4);
+    let position = |node: &DagNode| order.iter().position(|n| n == node).unwrap();
+    assert!(position(&a) < position(&b));
+    assert!(position(&a) < position(&x));
+    assert!(position(&x) < position(&y));
+  }
+
+  #[test]
+  fn subgraph_keeps_roots_and_their_dependencies() {
+    let (manifest, [a, b, x, y]) = query_manifest();
+    let dag = ExecutionDag::from_manifest(&manifest).unwrap();
+
+    let sub = dag.subgraph([y.clone()]);
+    assert!(sub.contains(&y) && sub.contains(&x) && sub.contains(&a));
+    assert!(!sub.contains(&b));
+    assert_eq!(sub.edges(), vec![(a.clone(), x.clone()), (x, y)]);
+    assert_eq!(sub.build_count(), 1);
+  }
+
+  #[test]
+  fn dag_round_trips_through_serde() {
+    let (manifest, [a, _, x, _]) = query_manifest();
+    let dag = ExecutionDag::from_manifest(&manifest).unwrap();
+
+    let json = serde_json::to_value(&dag).unwrap();
+    assert_eq!(json["nodes"].as_array().unwrap().len(), 4);
+    assert_eq!(json["nodes"][0]["kind"], "build");
+    let restored: ExecutionDag = serde_json::from_value(json).unwrap();
+    assert_eq!(restored.edges(), dag.edges());
+    assert_eq!(restored.dependents(&a), dag.dependents(&a));
+
+    // Edges must name listed nodes and must not form a cycle
+    assert!(ExecutionDag::from_edges([a.clone()], [(a.clone(), x.clone())]).is_err());
+    assert!(matches!(
+      ExecutionDag::from_edges([a.clone(), x.clone()], [(a.clone(), x.clone()), (x, a)]),
+      Err(ExecuteError::CycleDetected)
+    ));
+  }
 }
@@ -0,0 +1,395 @@
+//! Negative cache of failed builds.
+//!
+//! A build that fails during an apply is recorded by hash in
+//! `<store>/failed-builds.json`, with its error and when it failed. Until the
+//! record is older than [`ExecuteConfig::failure_ttl_secs`], applies needing
+//! the same build fail before changing anything instead of running it again,
+//! and `sys plan` lists it as previously failed. Changing the build's
+//! definition changes its hash, so an edited build always runs;
+//! `sys apply --retry-failed` runs an unchanged one.
+//!
+//! Realizing a build removes its record. Failures injected with
+//! `--fail-at` aren't recorded.
+//!
+//! # Example File
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "builds": {
+//!     "abc123def45678901234": {
+//!       "id": "ripgrep",
+//!       "error": "command failed with exit code Some(2): make",
+//!       "failed_at_ms": 1767225600000
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! [`ExecuteConfig::failure_ttl_secs`]: crate::execute::ExecuteConfig::failure_ttl_secs
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::execute::types::{DagResult, ExecuteError, now_ms};
+use crate::manifest::Manifest;
+use crate::platform::paths::store_dir;
+use crate::util::hash::ObjectHash;
+
+/// Name of the failed builds file in the store.
+pub const FAILED_BUILDS_FILENAME: &str = "failed-builds.json";
+
+/// Current failed builds file format version.
+const FAILED_BUILDS_VERSION: u32 = 1;
+
+/// How long a failed build is skipped by default, in seconds.
+pub const DEFAULT_FAILURE_TTL_SECS: u64 = 3600;
+
+#[derive(Debug, Error)]
+pub enum FailedBuildsError {
+  #[error("failed to read failed builds: {0}")]
+  Read(#[source] io::Error),
+
+  #[error("failed to write failed builds: {0}")]
+  Write(#[source] io::Error),
+
+  #[error("failed to parse failed builds: {0}")]
+  Parse(#[source] serde_json::Error),
+
+  #[error("failed to serialize failed builds: {0}")]
+  Serialize(#[source] serde_json::Error),
+
+  #[error("unsupported failed builds version {0}")]
+  UnsupportedVersion(u32),
+}
+
+/// The last failure of a build.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildFailure {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  /// The error the build failed with.
+  pub error: String,
+  /// Milliseconds since the Unix epoch when the build failed.
+  pub failed_at_ms: u64,
+}
+
+impl BuildFailure {
+  /// Time since the build failed, as of `now_ms`.
+  pub fn age(&self, now_ms: u64) -> Duration {
+    Duration::from_millis(now_ms.saturating_sub(self.failed_at_ms))
+  }
+}
+
+/// A build of a plan that failed recently, and is skipped until it expires.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreviousFailure {
+  pub hash: ObjectHash,
+  #[serde(flatten)]
+  pub failure: BuildFailure,
+  /// Seconds since the build failed.
+  pub age_secs: u64,
+  /// Seconds until the build runs again without `--retry-failed`.
+  pub expires_in_secs: u64,
+}
+
+impl PreviousFailure {
+  /// The build's id, or its hash when it has none.
+  pub fn name(&self) -> &str {
+    self.failure.id.as_deref().unwrap_or(&self.hash.0)
+  }
+}
+
+impl std::fmt::Display for PreviousFailure {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "build '{}' failed {} ago and hasn't changed since: {}; it runs again in {}, or now with --retry-failed",
+      self.name(),
+      short_duration(self.age_secs),
+      self.failure.error,
+      short_duration(self.expires_in_secs)
+    )
+  }
+}
+
+/// `secs` in the largest units that fit, e.g. `45s`, `12m` or `2h 5m`.
+fn short_duration(secs: u64) -> String {
+  match secs {
+    0..60 => format!("{}s", secs),
+    60..3600 => format!("{}m", secs / 60),
+    _ if secs % 3600 < 60 => format!("{}h", secs / 3600),
+    _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+  }
+}
+
+/// Recent failures of builds, keyed by build hash.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailedBuilds {
+  version: u32,
+  #[serde(default)]
+  builds: BTreeMap<ObjectHash, BuildFailure>,
+}
+
+impl Default for FailedBuilds {
+  fn default() -> Self {
+    Self {
+      version: FAILED_BUILDS_VERSION,
+      builds: BTreeMap::new(),
+    }
+  }
+}
+
+/// Path of the failed builds file in the current store.
+pub fn failed_builds_path() -> PathBuf {
+  store_dir().join(FAILED_BUILDS_FILENAME)
+}
+
+impl FailedBuilds {
+  /// Load the failed builds at `path`, or none if there is no file.
+  pub fn load(path: &Path) -> Result<Self, FailedBuildsError> {
+    let content = match fs::read_to_string(path) {
+      Ok(content) => content,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+      Err(e) => return Err(FailedBuildsError::Read(e)),
+    };
+
+    let failed: Self = serde_json::from_str(&content).map_err(FailedBuildsError::Parse)?;
+    if failed.version != FAILED_BUILDS_VERSION {
+      return Err(FailedBuildsError::UnsupportedVersion(failed.version));
+    }
+    Ok(failed)
+  }
+
+  /// Write the failed builds to `path` atomically.
+  pub fn save(&self, path: &Path) -> Result<(), FailedBuildsError> {
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(FailedBuildsError::Write)?;
+    }
+
+    let content = serde_json::to_string_pretty(self).map_err(FailedBuildsError::Serialize)?;
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, content).map_err(FailedBuildsError::Write)?;
+    fs::rename(&temp_path, path).map_err(FailedBuildsError::Write)?;
+    Ok(())
+  }
+
+  /// Record the build that failed in `result` and forget the realized ones.
+  ///
+  /// Failures older than `ttl` are dropped.
+  pub fn record(&mut self, manifest: &Manifest, result: &DagResult, ttl: Duration) {
+    for hash in result.realized.keys() {
+      self.builds.remove(hash);
+    }
+    if let Some((hash, error)) = &result.build_failed
+      && !matches!(error, ExecuteError::Injected { .. })
+    {
+      let failed_at_ms = result
+        .build_timings
+        .get(hash)
+        .map(|timing| timing.finished_at_ms)
+        .unwrap_or_else(now_ms);
+      self.builds.insert(
+        hash.clone(),
+        BuildFailure {
+          id: manifest.builds.get(hash).and_then(|b| b.id.clone()),
+          error: error.to_string(),
+          failed_at_ms,
+        },
+      );
+    }
+
+    let now = now_ms();
+    self.builds.retain(|_, failure| failure.age(now) < ttl);
+  }
+
+  /// The builds of `hashes` that failed less than `ttl` ago, in hash order.
+  pub fn recent<'a>(&self, hashes: impl IntoIterator<Item = &'a ObjectHash>, ttl: Duration) -> Vec<PreviousFailure> {
+    let now = now_ms();
+    let mut recent: Vec<PreviousFailure> = hashes
+      .into_iter()
+      .filter_map(|hash| {
+        let failure = self.builds.get(hash)?;
+        let age = failure.age(now);
+        let expires_in = ttl.checked_sub(age).filter(|left| !left.is_zero())?;
+        Some(PreviousFailure {
+          hash: hash.clone(),
+          failure: failure.clone(),
+          age_secs: age.as_secs(),
+          expires_in_secs: expires_in.as_secs().max(1),
+        })
+      })
+      .collect();
+    recent.sort_by(|a, b| a.hash.cmp(&b.hash));
+    recent.dedup_by(|a, b| a.hash == b.hash);
+    recent
+  }
+}
+
+/// The builds of `hashes` that failed within `ttl`, from the current store.
+///
+/// An unreadable file counts as no failures.
+pub fn recent_failures<'a>(hashes: impl IntoIterator<Item = &'a ObjectHash>, ttl: Duration) -> Vec<PreviousFailure> {
+  match FailedBuilds::load(&failed_builds_path()) {
+    Ok(failed) => failed.recent(hashes, ttl),
+    Err(e) => {
+      tracing::warn!(error = %e, "ignoring unreadable failed builds");
+      Vec::new()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+
+  use crate::build::BuildDef;
+  use crate::execute::types::{BuildResult, NodeTiming};
+  use crate::execute::{FailPhase, FailedDependency};
+  use tempfile::TempDir;
+
+  const HOUR: Duration = Duration::from_secs(3600);
+
+  fn manifest(hash: &ObjectHash) -> Manifest {
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(
+      hash.clone(),
+      BuildDef {
+        id: Some("tool".to_string()),
+        inputs: None,
+        create_actions: vec![],
+        outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
+      },
+    );
+    manifest
+  }
+
+  fn failed(hash: &ObjectHash, error: ExecuteError, finished_at_ms: u64) -> DagResult {
+    let mut result = DagResult::default();
+    result.build_failed = Some((hash.clone(), error));
+    result.build_timings.insert(
+      hash.clone(),
+      NodeTiming {
+        started_at_ms: finished_at_ms,
+        finished_at_ms,
+      },
+    );
+    result
+  }
+
+  #[test]
+  fn failures_are_skipped_until_they_expire_or_the_build_succeeds() {
+    let hash = ObjectHash("abc123def45678901234".to_string());
+    let manifest = manifest(&hash);
+    let mut failed_builds = FailedBuilds::default();
+
+    let error = ExecuteError::CmdFailed {
+      cmd: "make".to_string(),
+      code: Some(2),
+    };
+    failed_builds.record(&manifest, &failed(&hash, error, now_ms() - 60_000), HOUR);
+    let recent = failed_builds.recent([&hash, &hash], HOUR);
+    assert_eq!(recent.len(), 1);
+    assert_eq!(recent[0].failure.id.as_deref(), Some("tool"));
+    assert!(recent[0].failure.error.contains("make"));
+    assert!((3530..=3540).contains(&recent[0].expires_in_secs));
+    assert!(
+      recent[0]
+        .to_string()
+        .starts_with("build 'tool' failed 1m ago and hasn't changed since: command failed"),
+      "{}",
+      recent[0]
+    );
+
+    // A shorter TTL has already expired, and other builds never failed
+    assert!(failed_builds.recent([&hash], Duration::from_secs(30)).is_empty());
+    assert!(
+      failed_builds
+        .recent([&ObjectHash("other".to_string())], HOUR)
+        .is_empty()
+    );
+
+    let mut realized = DagResult::default();
+    realized.realized.insert(
+      hash.clone(),
+      BuildResult {
+        store_path: PathBuf::from("/store"),
+        outputs: HashMap::new(),
+        action_results: vec![],
+        cached: false,
+      },
+    );
+    failed_builds.record(&manifest, &realized, HOUR);
+    assert!(failed_builds.recent([&hash], HOUR).is_empty());
+  }
+
+  #[test]
+  fn injected_and_expired_failures_are_not_kept() {
+    let hash = ObjectHash("h".to_string());
+    let manifest = manifest(&hash);
+    let mut failed_builds = FailedBuilds::default();
+
+    let injected = ExecuteError::Injected {
+      phase: FailPhase::Build,
+      target: "tool".to_string(),
+    };
+    failed_builds.record(&manifest, &failed(&hash, injected, now_ms()), HOUR);
+    assert_eq!(failed_builds, FailedBuilds::default());
+
+    let old = now_ms() - 2 * HOUR.as_millis() as u64;
+    failed_builds.record(&manifest, &failed(&hash, ExecuteError::CycleDetected, old), HOUR);
+    assert_eq!(failed_builds, FailedBuilds::default());
+
+    // Skipped dependents didn't run and aren't recorded either
+    let mut skipped = failed(&hash, ExecuteError::CycleDetected, now_ms());
+    let dependent = ObjectHash("dependent".to_string());
+    skipped
+      .build_skipped
+      .insert(dependent.clone(), FailedDependency::Build(hash.clone()));
+    failed_builds.record(&manifest, &skipped, HOUR);
+    assert_eq!(failed_builds.recent([&hash, &dependent], HOUR).len(), 1);
+  }
+
+  #[test]
+  fn short_durations() {
+    assert_eq!(short_duration(45), "45s");
+    assert_eq!(short_duration(12 * 60 + 5), "12m");
+    assert_eq!(short_duration(2 * 3600 + 30), "2h");
+    assert_eq!(short_duration(2 * 3600 + 5 * 60), "2h 5m");
+  }
+
+  #[test]
+  fn save_and_load_roundtrip() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join(FAILED_BUILDS_FILENAME);
+    assert_eq!(FailedBuilds::load(&path).unwrap(), FailedBuilds::default());
+
+    let hash = ObjectHash("h".to_string());
+    let mut failed_builds = FailedBuilds::default();
+    failed_builds.record(
+      &manifest(&hash),
+      &failed(&hash, ExecuteError::CycleDetected, now_ms()),
+      HOUR,
+    );
+    failed_builds.save(&path).unwrap();
+    assert_eq!(FailedBuilds::load(&path).unwrap(), failed_builds);
+
+    std::fs::write(&path, r#"{"version": 99}"#).unwrap();
+    assert!(matches!(
+      FailedBuilds::load(&path),
+      Err(FailedBuildsError::UnsupportedVersion(99))
+    ));
+  }
+}
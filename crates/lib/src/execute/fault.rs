@@ -0,0 +1,167 @@
+//! Deterministic failure injection, for testing rollback paths.
+//!
+//! `sys apply --fail-at <id|hash> [--fail-phase build|bind|update]` (or
+//! `SYSLUA_FAIL_AT` and `SYSLUA_FAIL_PHASE`) makes the executor fail the
+//! matching node right before it would run, as if its first action had failed.
+//! The rest of the apply reacts as it would to a real failure: dependents are
+//! skipped, and the binds applied so far are rolled back. Nothing of the failed
+//! node itself runs, so its rollback has nothing to undo.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::hash::ObjectHash;
+
+use super::types::ExecuteError;
+
+/// Environment variable naming the node to fail, like `--fail-at`.
+pub const FAIL_AT_ENV: &str = "SYSLUA_FAIL_AT";
+
+/// Environment variable naming the phase to fail in, like `--fail-phase`.
+pub const FAIL_PHASE_ENV: &str = "SYSLUA_FAIL_PHASE";
+
+/// When a node is failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailPhase {
+  /// Realizing a build.
+  Build,
+  /// Creating a bind, including re-creating it for `--repair`.
+  Bind,
+  /// Updating a bind in place.
+  Update,
+}
+
+impl fmt::Display for FailPhase {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      FailPhase::Build => "build",
+      FailPhase::Bind => "bind",
+      FailPhase::Update => "update",
+    })
+  }
+}
+
+impl FromStr for FailPhase {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "build" => Ok(Self::Build),
+      "bind" => Ok(Self::Bind),
+      "update" => Ok(Self::Update),
+      _ => Err(format!("unknown fail phase '{}' (expected build, bind or update)", s)),
+    }
+  }
+}
+
+/// The node an apply deliberately fails.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailPoint {
+  /// Id of the node, or a prefix of its hash.
+  pub target: String,
+  /// Only fail the node in this phase; in any phase when unset.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub phase: Option<FailPhase>,
+}
+
+impl FailPoint {
+  /// The fail point set by `SYSLUA_FAIL_AT` and `SYSLUA_FAIL_PHASE`, if any.
+  pub fn from_env() -> Result<Option<Self>, String> {
+    let Some(target) = std::env::var(FAIL_AT_ENV).ok().filter(|target| !target.is_empty()) else {
+      return Ok(None);
+    };
+    let phase = match std::env::var(FAIL_PHASE_ENV) {
+      Ok(phase) if !phase.is_empty() => Some(phase.parse()?),
+      _ => None,
+    };
+    Ok(Some(Self { target, phase }))
+  }
+
+  /// Whether the node `hash` (with `id`) is failed in `phase`.
+  pub fn matches(&self, phase: FailPhase, hash: &ObjectHash, id: Option<&str>) -> bool {
+    if self.phase.is_some_and(|wanted| wanted != phase) || self.target.is_empty() {
+      return false;
+    }
+    id == Some(self.target.as_str()) || hash.0.starts_with(&self.target)
+  }
+}
+
+/// Fail with [`ExecuteError::Injected`] if `fail_at` targets this node in
+/// `phase`.
+pub fn check(
+  fail_at: Option<&FailPoint>,
+  phase: FailPhase,
+  hash: &ObjectHash,
+  id: Option<&str>,
+) -> Result<(), ExecuteError> {
+  match fail_at {
+    Some(point) if point.matches(phase, hash, id) => Err(ExecuteError::Injected {
+      phase,
+      target: id.unwrap_or(&hash.0).to_string(),
+    }),
+    _ => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serial_test::serial;
+
+  fn hash() -> ObjectHash {
+    ObjectHash("abc123def4567890".to_string())
+  }
+
+  #[test]
+  fn matches_id_or_hash_prefix_in_phase() {
+    let by_id = FailPoint {
+      target: "nginx".to_string(),
+      phase: Some(FailPhase::Bind),
+    };
+    assert!(by_id.matches(FailPhase::Bind, &hash(), Some("nginx")));
+    assert!(!by_id.matches(FailPhase::Update, &hash(), Some("nginx")));
+    assert!(!by_id.matches(FailPhase::Bind, &hash(), Some("nginx-conf")));
+
+    let by_hash = FailPoint {
+      target: "abc123".to_string(),
+      phase: None,
+    };
+    assert!(by_hash.matches(FailPhase::Build, &hash(), None));
+    assert!(by_hash.matches(FailPhase::Update, &hash(), Some("other")));
+    assert!(!by_hash.matches(FailPhase::Build, &ObjectHash("def".to_string()), None));
+  }
+
+  #[test]
+  fn check_names_the_failed_node() {
+    let point = FailPoint {
+      target: "abc".to_string(),
+      phase: None,
+    };
+    let err = check(Some(&point), FailPhase::Build, &hash(), Some("app")).unwrap_err();
+    assert_eq!(err.to_string(), "injected build failure at 'app'");
+    assert!(check(None, FailPhase::Build, &hash(), Some("app")).is_ok());
+  }
+
+  #[test]
+  #[serial]
+  fn reads_fail_point_from_env() {
+    temp_env::with_vars([(FAIL_AT_ENV, Some("app")), (FAIL_PHASE_ENV, Some("update"))], || {
+      assert_eq!(
+        FailPoint::from_env().unwrap(),
+        Some(FailPoint {
+          target: "app".to_string(),
+          phase: Some(FailPhase::Update),
+        })
+      );
+    });
+    temp_env::with_vars([(FAIL_AT_ENV, Some("app")), (FAIL_PHASE_ENV, Some("destroy"))], || {
+      assert!(FailPoint::from_env().is_err());
+    });
+    temp_env::with_vars([(FAIL_AT_ENV, None::<&str>), (FAIL_PHASE_ENV, Some("bind"))], || {
+      assert_eq!(FailPoint::from_env().unwrap(), None);
+    });
+  }
+}
@@ -0,0 +1,614 @@
+//! Persistent execution history of builds and binds.
+//!
+//! Every build and bind that runs during an apply records its duration and
+//! outcome in `<store>/history.json`, and realized builds their size. The
+//! history drives `sys stats` (slowest builds, flakiest binds), gives the
+//! scheduler expected durations so the longest chains of work start first,
+//! and gives the apply preflight checks expected build sizes.
+//!
+//! Nodes are keyed by their `id` when they have one, so a build keeps its
+//! history when its definition (and hash) changes; nodes without an `id` are
+//! keyed by hash.
+//!
+//! Applies add their runs with [`ExecutionHistory::update`], which holds a
+//! lock on `history.json.lock` while it reads and rewrites the file, so
+//! concurrent applies don't drop each other's runs.
+//!
+//! # Example History File
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "nodes": {
+//!     "build:ripgrep": {
+//!       "kind": "build",
+//!       "id": "ripgrep",
+//!       "hash": "abc123def45678901234",
+//!       "runs": [{ "finished_at_ms": 1767225600000, "duration_ms": 41250, "success": true }]
+//!     }
+//!   }
+//! }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::execute::types::{DagResult, NodeTiming};
+use crate::manifest::Manifest;
+use crate::platform::paths::store_dir;
+use crate::util::fs::dir_size;
+use crate::util::hash::ObjectHash;
+
+/// Name of the history file in the store.
+pub const HISTORY_FILENAME: &str = "history.json";
+
+/// Current history file format version.
+const HISTORY_VERSION: u32 = 1;
+
+/// Runs kept per node; older runs are dropped.
+pub const MAX_RUNS: usize = 20;
+
+/// Nodes kept in the history; the ones that ran least recently are dropped.
+pub const MAX_NODES: usize = 2000;
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+  #[error("failed to read execution history: {0}")]
+  Read(#[source] io::Error),
+
+  #[error("failed to write execution history: {0}")]
+  Write(#[source] io::Error),
+
+  #[error("failed to lock execution history: {0}")]
+  Lock(#[source] io::Error),
+
+  #[error("failed to parse execution history: {0}")]
+  Parse(#[source] serde_json::Error),
+
+  #[error("failed to serialize execution history: {0}")]
+  Serialize(#[source] serde_json::Error),
+
+  #[error("unsupported execution history version {0}")]
+  UnsupportedVersion(u32),
+}
+
+/// Whether a history entry is a build or a bind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+  Build,
+  Bind,
+}
+
+impl NodeKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::Build => "build",
+      Self::Bind => "bind",
+    }
+  }
+}
+
+/// A single execution of a build or bind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeRun {
+  /// Milliseconds since the Unix epoch when execution finished.
+  pub finished_at_ms: u64,
+  /// Time spent executing, in milliseconds.
+  pub duration_ms: u64,
+  /// Whether the build was realized or the bind applied.
+  pub success: bool,
+  /// Size of the realized build in the store, in bytes.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub size_bytes: Option<u64>,
+  /// Part of [`size_bytes`](Self::size_bytes) downloaded by `fetch_url`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub download_bytes: Option<u64>,
+}
+
+/// Expected disk usage of realizing a build.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BuildSize {
+  /// Bytes the build takes in the store.
+  pub store_bytes: u64,
+  /// Bytes of it downloaded by `fetch_url`, which the download cache keeps a copy of.
+  pub download_bytes: u64,
+}
+
+/// Recorded runs of one build or bind, oldest first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeHistory {
+  pub kind: NodeKind,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  /// Hash of the definition that ran most recently.
+  pub hash: String,
+  pub runs: Vec<NodeRun>,
+}
+
+impl NodeHistory {
+  /// Mean duration of the successful runs, in milliseconds.
+  pub fn mean_duration_ms(&self) -> Option<u64> {
+    let durations: Vec<u64> = self.runs.iter().filter(|r| r.success).map(|r| r.duration_ms).collect();
+    if durations.is_empty() {
+      return None;
+    }
+    Some(durations.iter().sum::<u64>() / durations.len() as u64)
+  }
+
+  /// Number of failed runs.
+  pub fn failures(&self) -> usize {
+    self.runs.iter().filter(|r| !r.success).count()
+  }
+
+  /// Fraction of consecutive runs whose outcome flipped, from 0.0 to 1.0.
+  ///
+  /// A node that always fails is broken, not flaky, and scores 0.0.
+  pub fn flakiness(&self) -> f64 {
+    if self.runs.len() < 2 {
+      return 0.0;
+    }
+    let flips = self.runs.windows(2).filter(|w| w[0].success != w[1].success).count();
+    flips as f64 / (self.runs.len() - 1) as f64
+  }
+
+  /// Size of the most recent successful run that recorded one.
+  pub fn last_size(&self) -> Option<BuildSize> {
+    self.runs.iter().rev().filter(|r| r.success).find_map(|r| {
+      Some(BuildSize {
+        store_bytes: r.size_bytes?,
+        download_bytes: r.download_bytes.unwrap_or(0),
+      })
+    })
+  }
+
+  fn last_finished_at_ms(&self) -> u64 {
+    self.runs.last().map(|r| r.finished_at_ms).unwrap_or(0)
+  }
+}
+
+/// Summary of one node's history, as reported by `sys stats`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeStats {
+  pub kind: NodeKind,
+  pub id: Option<String>,
+  pub hash: String,
+  pub runs: usize,
+  pub failures: usize,
+  pub mean_duration_ms: Option<u64>,
+  pub max_duration_ms: u64,
+  pub flakiness: f64,
+}
+
+/// Execution history of every build and bind that ran.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionHistory {
+  version: u32,
+  #[serde(default)]
+  nodes: BTreeMap<String, NodeHistory>,
+}
+
+impl Default for ExecutionHistory {
+  fn default() -> Self {
+    Self {
+      version: HISTORY_VERSION,
+      nodes: BTreeMap::new(),
+    }
+  }
+}
+
+/// Path of the history file in the current store.
+pub fn history_path() -> PathBuf {
+  store_dir().join(HISTORY_FILENAME)
+}
+
+fn node_key(kind: NodeKind, id: Option<&str>, hash: &ObjectHash) -> String {
+  format!("{}:{}", kind.as_str(), id.unwrap_or(&hash.0))
+}
+
+impl ExecutionHistory {
+  /// Load the history at `path`, or an empty history if there is none.
+  pub fn load(path: &Path) -> Result<Self, HistoryError> {
+    let content = match fs::read_to_string(path) {
+      Ok(content) => content,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+      Err(e) => return Err(HistoryError::Read(e)),
+    };
+
+    let history: Self = serde_json::from_str(&content).map_err(HistoryError::Parse)?;
+    if history.version != HISTORY_VERSION {
+      return Err(HistoryError::UnsupportedVersion(history.version));
+    }
+    Ok(history)
+  }
+
+  /// Write the history to `path` atomically.
+  pub fn save(&self, path: &Path) -> Result<(), HistoryError> {
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(HistoryError::Write)?;
+    }
+
+    let content = serde_json::to_string_pretty(self).map_err(HistoryError::Serialize)?;
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, content).map_err(HistoryError::Write)?;
+    fs::rename(&temp_path, path).map_err(HistoryError::Write)?;
+    Ok(())
+  }
+
+  /// Apply `f` to the history at `path` and write it back, holding an
+  /// exclusive lock so concurrent updates aren't lost.
+  ///
+  /// An unreadable history is replaced.
+  pub fn update(path: &Path, f: impl FnOnce(&mut Self)) -> Result<(), HistoryError> {
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(HistoryError::Lock)?;
+    }
+    let lock = File::create(path.with_extension("json.lock")).map_err(HistoryError::Lock)?;
+    lock.lock().map_err(HistoryError::Lock)?;
+
+    let mut history = Self::load(path).unwrap_or_default();
+    f(&mut history);
+    history.save(path)
+  }
+
+  /// Recorded history of every node, keyed by `<kind>:<id or hash>`.
+  pub fn nodes(&self) -> &BTreeMap<String, NodeHistory> {
+    &self.nodes
+  }
+
+  /// Record the builds and binds that ran in `result`.
+  ///
+  /// Only nodes with a timing ran; skipped and cached nodes are not recorded.
+  /// Realized builds record the size of their store path.
+  pub fn record(&mut self, manifest: &Manifest, result: &DagResult) {
+    for (hash, timing) in &result.build_timings {
+      let id = manifest.builds.get(hash).and_then(|b| b.id.as_deref());
+      let size = result.realized.get(hash).map(|build| BuildSize {
+        store_bytes: dir_size(&build.store_path),
+        download_bytes: dir_size(&build.store_path.join("downloads")),
+      });
+      self.push(NodeKind::Build, id, hash, timing, size.is_some(), size);
+    }
+    for (hash, timing) in &result.bind_timings {
+      let id = manifest.bindings.get(hash).and_then(|b| b.id.as_deref());
+      self.push(
+        NodeKind::Bind,
+        id,
+        hash,
+        timing,
+        result.applied.contains_key(hash),
+        None,
+      );
+    }
+    self.prune();
+  }
+
+  fn push(
+    &mut self,
+    kind: NodeKind,
+    id: Option<&str>,
+    hash: &ObjectHash,
+    timing: &NodeTiming,
+    success: bool,
+    size: Option<BuildSize>,
+  ) {
+    let entry = self
+      .nodes
+      .entry(node_key(kind, id, hash))
+      .or_insert_with(|| NodeHistory {
+        kind,
+        id: id.map(str::to_string),
+        hash: hash.0.clone(),
+        runs: Vec::new(),
+      });
+
+    entry.hash = hash.0.clone();
+    entry.runs.push(NodeRun {
+      finished_at_ms: timing.finished_at_ms,
+      duration_ms: timing.duration().as_millis() as u64,
+      success,
+      size_bytes: size.map(|size| size.store_bytes),
+      download_bytes: size.map(|size| size.download_bytes),
+    });
+    if entry.runs.len() > MAX_RUNS {
+      entry.runs.drain(..entry.runs.len() - MAX_RUNS);
+    }
+  }
+
+  /// Drop the nodes that ran least recently beyond [`MAX_NODES`].
+  fn prune(&mut self) {
+    if self.nodes.len() <= MAX_NODES {
+      return;
+    }
+    let mut by_age: Vec<(u64, String)> = self
+      .nodes
+      .iter()
+      .map(|(key, node)| (node.last_finished_at_ms(), key.clone()))
+      .collect();
+    by_age.sort();
+    for (_, key) in by_age.into_iter().take(self.nodes.len() - MAX_NODES) {
+      self.nodes.remove(&key);
+    }
+  }
+
+  /// Expected duration of each build and bind in `manifest` that has run
+  /// successfully before, for [`ExecuteConfig::expected_durations`](crate::execute::ExecuteConfig).
+  pub fn expected_durations(&self, manifest: &Manifest) -> BTreeMap<ObjectHash, u64> {
+    let builds = manifest
+      .builds
+      .iter()
+      .map(|(hash, def)| (NodeKind::Build, def.id.as_deref(), hash));
+    let binds = manifest
+      .bindings
+      .iter()
+      .map(|(hash, def)| (NodeKind::Bind, def.id.as_deref(), hash));
+
+    builds
+      .chain(binds)
+      .filter_map(|(kind, id, hash)| {
+        let mean = self.nodes.get(&node_key(kind, id, hash))?.mean_duration_ms()?;
+        Some((hash.clone(), mean))
+      })
+      .collect()
+  }
+
+  /// Expected size of each build in `manifest` that was realized before.
+  pub fn expected_sizes(&self, manifest: &Manifest) -> HashMap<ObjectHash, BuildSize> {
+    manifest
+      .builds
+      .iter()
+      .filter_map(|(hash, def)| {
+        let size = self
+          .nodes
+          .get(&node_key(NodeKind::Build, def.id.as_deref(), hash))?
+          .last_size()?;
+        Some((hash.clone(), size))
+      })
+      .collect()
+  }
+
+  /// Summaries of every recorded node, in key order.
+  pub fn stats(&self) -> Vec<NodeStats> {
+    self
+      .nodes
+      .values()
+      .map(|node| NodeStats {
+        kind: node.kind,
+        id: node.id.clone(),
+        hash: node.hash.clone(),
+        runs: node.runs.len(),
+        failures: node.failures(),
+        mean_duration_ms: node.mean_duration_ms(),
+        max_duration_ms: node.runs.iter().map(|r| r.duration_ms).max().unwrap_or(0),
+        flakiness: node.flakiness(),
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::bind::BindDef;
+  use crate::build::BuildDef;
+  use crate::execute::types::{BindResult, BuildResult, ExecuteError};
+  use tempfile::TempDir;
+
+  fn timing(finished_at_ms: u64, duration_ms: u64) -> NodeTiming {
+    NodeTiming {
+      started_at_ms: finished_at_ms - duration_ms,
+      finished_at_ms,
+    }
+  }
+
+  fn build_def(id: Option<&str>) -> BuildDef {
+    BuildDef {
+      id: id.map(str::to_string),
+      inputs: None,
+      create_actions: vec![],
+      outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
+    }
+  }
+
+  fn bind_def(id: &str) -> BindDef {
+    BindDef {
+      id: Some(id.to_string()),
+      inputs: None,
+      outputs: None,
+      create_actions: vec![],
+      update_actions: None,
+      destroy_actions: vec![],
+      check_actions: None,
+      check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
+    }
+  }
+
+  #[test]
+  fn records_builds_by_id_across_hash_changes() {
+    let mut history = ExecutionHistory::default();
+
+    for (hash, ms) in [("hash_v1", 100), ("hash_v2", 300)] {
+      let hash = ObjectHash(hash.to_string());
+      let mut manifest = Manifest::default();
+      manifest.builds.insert(hash.clone(), build_def(Some("tool")));
+
+      let mut result = DagResult::default();
+      result.build_timings.insert(hash.clone(), timing(10_000, ms));
+      result.realized.insert(
+        hash,
+        BuildResult {
+          store_path: PathBuf::from("/store"),
+          outputs: HashMap::new(),
+          action_results: vec![],
+          cached: false,
+        },
+      );
+      history.record(&manifest, &result);
+    }
+
+    let node = &history.nodes()["build:tool"];
+    assert_eq!(node.hash, "hash_v2");
+    assert_eq!(node.runs.len(), 2);
+    assert_eq!(node.mean_duration_ms(), Some(200));
+
+    // A new hash of the same build gets the estimate too
+    let mut manifest = Manifest::default();
+    let next = ObjectHash("hash_v3".to_string());
+    manifest.builds.insert(next.clone(), build_def(Some("tool")));
+    manifest.builds.insert(ObjectHash("other".to_string()), build_def(None));
+    assert_eq!(history.expected_durations(&manifest), BTreeMap::from([(next, 200)]));
+  }
+
+  #[test]
+  fn records_the_size_of_realized_builds() {
+    let temp = TempDir::new().unwrap();
+    let store_path = temp.path().join("obj");
+    std::fs::create_dir_all(store_path.join("downloads")).unwrap();
+    std::fs::write(store_path.join("bin"), [0u8; 300]).unwrap();
+    std::fs::write(store_path.join("downloads").join("src.tar"), [0u8; 200]).unwrap();
+
+    let hash = ObjectHash("hash_v1".to_string());
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(hash.clone(), build_def(Some("tool")));
+    let mut result = DagResult::default();
+    result.build_timings.insert(hash.clone(), timing(10_000, 100));
+    result.realized.insert(
+      hash.clone(),
+      BuildResult {
+        store_path,
+        outputs: HashMap::new(),
+        action_results: vec![],
+        cached: false,
+      },
+    );
+    let mut history = ExecutionHistory::default();
+    history.record(&manifest, &result);
+
+    // A new hash of the same build gets the estimate
+    let next = ObjectHash("hash_v2".to_string());
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(next.clone(), build_def(Some("tool")));
+    manifest.builds.insert(ObjectHash("other".to_string()), build_def(None));
+    let expected = BuildSize {
+      store_bytes: 500,
+      download_bytes: 200,
+    };
+    assert_eq!(history.expected_sizes(&manifest), HashMap::from([(next, expected)]));
+  }
+
+  #[test]
+  fn failed_binds_count_towards_flakiness() {
+    let hash = ObjectHash("bind_hash".to_string());
+    let mut manifest = Manifest::default();
+    manifest.bindings.insert(hash.clone(), bind_def("service"));
+
+    let mut history = ExecutionHistory::default();
+    for (i, success) in [true, false, true, true].into_iter().enumerate() {
+      let mut result = DagResult::default();
+      result
+        .bind_timings
+        .insert(hash.clone(), timing(1_000 * (i as u64 + 1), 50));
+      if success {
+        result.applied.insert(
+          hash.clone(),
+          BindResult {
+            outputs: HashMap::new(),
+            action_results: vec![],
+          },
+        );
+      } else {
+        result.bind_failed = Some((hash.clone(), ExecuteError::CycleDetected));
+      }
+      history.record(&manifest, &result);
+    }
+
+    let stats = history.stats();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].kind, NodeKind::Bind);
+    assert_eq!(stats[0].runs, 4);
+    assert_eq!(stats[0].failures, 1);
+    assert!((stats[0].flakiness - 2.0 / 3.0).abs() < f64::EPSILON);
+  }
+
+  #[test]
+  fn keeps_only_recent_runs() {
+    let hash = ObjectHash("h".to_string());
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(hash.clone(), build_def(None));
+
+    let mut history = ExecutionHistory::default();
+    for i in 0..(MAX_RUNS as u64 + 5) {
+      let mut result = DagResult::default();
+      result.build_timings.insert(hash.clone(), timing(1_000 + i, 1));
+      history.record(&manifest, &result);
+    }
+
+    let runs = &history.nodes()["build:h"].runs;
+    assert_eq!(runs.len(), MAX_RUNS);
+    assert_eq!(runs[0].finished_at_ms, 1_005);
+  }
+
+  #[test]
+  fn save_and_load_roundtrip() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join(HISTORY_FILENAME);
+    assert_eq!(ExecutionHistory::load(&path).unwrap(), ExecutionHistory::default());
+
+    let hash = ObjectHash("h".to_string());
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(hash.clone(), build_def(None));
+    let mut result = DagResult::default();
+    result.build_timings.insert(hash, timing(2_000, 20));
+
+    let mut history = ExecutionHistory::default();
+    history.record(&manifest, &result);
+    history.save(&path).unwrap();
+    assert_eq!(ExecutionHistory::load(&path).unwrap(), history);
+
+    std::fs::write(&path, r#"{"version": 99}"#).unwrap();
+    assert!(matches!(
+      ExecutionHistory::load(&path),
+      Err(HistoryError::UnsupportedVersion(99))
+    ));
+  }
+
+  #[test]
+  fn concurrent_updates_keep_every_run() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join(HISTORY_FILENAME);
+
+    std::thread::scope(|scope| {
+      for i in 0..8u64 {
+        let path = &path;
+        scope.spawn(move || {
+          let hash = ObjectHash(format!("h{}", i));
+          let mut manifest = Manifest::default();
+          manifest.builds.insert(hash.clone(), build_def(None));
+          let mut result = DagResult::default();
+          result.build_timings.insert(hash, timing(1_000 + i, 1));
+          ExecutionHistory::update(path, |history| history.record(&manifest, &result)).unwrap();
+        });
+      }
+    });
+
+    assert_eq!(ExecutionHistory::load(&path).unwrap().nodes().len(), 8);
+  }
+}
@@ -0,0 +1,387 @@
+//! Audit hooks run around bind execution.
+//!
+//! A config's `settings.hooks` names commands the apply orchestrator runs at
+//! fixed points, for audit trails:
+//!
+//! ```lua
+//! settings = {
+//!   hooks = {
+//!     pre_bind = { bin = "/usr/local/bin/audit", args = { "pre" }, on_failure = "error" },
+//!     post_bind = "/usr/local/bin/audit",
+//!     post_apply = "/usr/local/bin/audit",
+//!   },
+//! }
+//! ```
+//!
+//! - `pre_bind` runs before a bind is created, updated, destroyed or repaired
+//! - `post_bind` runs after it, with its outcome
+//! - `post_apply` runs once at the end of `sys apply`, whether it succeeded or not
+//!
+//! Each command gets a JSON [`HookEvent`] on stdin and `SYSLUA_HOOK` set to the
+//! hook name. A failing hook (non-zero exit, or a command that can't be run) is
+//! logged as a warning unless its `on_failure` is `"error"`: a failing `pre_bind`
+//! then fails the bind before it runs, and a failing `post_bind` or `post_apply`
+//! fails the command once the apply has finished, keeping its changes.
+
+use std::future::Future;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+use crate::bind::BindDef;
+use crate::util::hash::ObjectHash;
+
+use super::types::ExecuteError;
+
+/// The commands of a config's `settings.hooks`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApplyHooks {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub pre_bind: Option<HookCommand>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub post_bind: Option<HookCommand>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub post_apply: Option<HookCommand>,
+}
+
+impl ApplyHooks {
+  pub fn is_empty(&self) -> bool {
+    self == &Self::default()
+  }
+}
+
+/// A hook command and what a failure of it means.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HookCommand {
+  /// Executable to run (not a shell command line).
+  pub bin: String,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub args: Vec<String>,
+  #[serde(default)]
+  pub on_failure: HookFailure,
+}
+
+/// What a failing hook does to the apply.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFailure {
+  /// Log a warning and carry on.
+  #[default]
+  Warn,
+  /// Fail the bind (`pre_bind`) or the command (`post_bind`, `post_apply`).
+  Error,
+}
+
+impl std::str::FromStr for HookFailure {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "warn" => Ok(Self::Warn),
+      "error" => Ok(Self::Error),
+      other => Err(format!("unknown hook on_failure '{}' (expected warn or error)", other)),
+    }
+  }
+}
+
+/// What a bind hook is run around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BindOperation {
+  Create,
+  Update,
+  Destroy,
+  Repair,
+}
+
+/// The JSON document a hook command receives on stdin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "hook", rename_all = "snake_case")]
+pub enum HookEvent {
+  PreBind {
+    operation: BindOperation,
+    hash: ObjectHash,
+    id: Option<String>,
+  },
+  PostBind {
+    operation: BindOperation,
+    hash: ObjectHash,
+    id: Option<String>,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    duration_ms: u64,
+  },
+  PostApply {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshot: Option<String>,
+    builds_realized: usize,
+    binds_applied: usize,
+    binds_updated: usize,
+    binds_destroyed: usize,
+  },
+}
+
+impl HookEvent {
+  fn name(&self) -> &'static str {
+    match self {
+      HookEvent::PreBind { .. } => "pre_bind",
+      HookEvent::PostBind { .. } => "post_bind",
+      HookEvent::PostApply { .. } => "post_apply",
+    }
+  }
+}
+
+/// Runs the hooks of one apply or destroy, remembering the failures of
+/// `post_*` hooks whose `on_failure` is `"error"`.
+#[derive(Debug, Clone, Default)]
+pub struct HookRunner {
+  hooks: ApplyHooks,
+  errors: Arc<Mutex<Vec<String>>>,
+}
+
+impl HookRunner {
+  pub fn new(hooks: ApplyHooks) -> Self {
+    Self {
+      hooks,
+      errors: Arc::default(),
+    }
+  }
+
+  /// Run `operation` on a bind between its `pre_bind` and `post_bind` hooks.
+  pub async fn around_bind<T, F>(
+    &self,
+    operation: BindOperation,
+    hash: &ObjectHash,
+    bind_def: &BindDef,
+    run: F,
+  ) -> Result<T, ExecuteError>
+  where
+    F: Future<Output = Result<T, ExecuteError>>,
+  {
+    if let Some(command) = &self.hooks.pre_bind {
+      let event = HookEvent::PreBind {
+        operation,
+        hash: hash.clone(),
+        id: bind_def.id.clone(),
+      };
+      if let Err(message) = run_hook(command, &event).await
+        && command.on_failure == HookFailure::Error
+      {
+        return Err(ExecuteError::Hook { message });
+      }
+    }
+
+    let started = Instant::now();
+    let result = run.await;
+
+    if let Some(command) = &self.hooks.post_bind {
+      let event = HookEvent::PostBind {
+        operation,
+        hash: hash.clone(),
+        id: bind_def.id.clone(),
+        success: result.is_ok(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+        duration_ms: started.elapsed().as_millis() as u64,
+      };
+      self.run_post(command, &event).await;
+    }
+
+    result
+  }
+
+  /// Run the `post_apply` hook.
+  pub async fn post_apply(&self, event: &HookEvent) {
+    if let Some(command) = &self.hooks.post_apply {
+      self.run_post(command, event).await;
+    }
+  }
+
+  /// Failures of `post_*` hooks that should fail the command.
+  pub fn errors(&self) -> Vec<String> {
+    self.errors.lock().unwrap().clone()
+  }
+
+  async fn run_post(&self, command: &HookCommand, event: &HookEvent) {
+    if let Err(message) = run_hook(command, event).await
+      && command.on_failure == HookFailure::Error
+    {
+      self.errors.lock().unwrap().push(message);
+    }
+  }
+}
+
+/// Run a hook command with `event` on stdin. Failures are logged as warnings
+/// and returned as messages.
+async fn run_hook(command: &HookCommand, event: &HookEvent) -> Result<(), String> {
+  let name = event.name();
+  debug!(hook = name, bin = %command.bin, "running hook");
+
+  let result = async {
+    // Events are plain data; serializing them can't fail
+    let input = serde_json::to_vec(event).unwrap_or_default();
+    let mut child = Command::new(&command.bin)
+      .args(&command.args)
+      .env("SYSLUA_HOOK", name)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::null())
+      .stderr(Stdio::piped())
+      .spawn()
+      .map_err(|e| format!("failed to run {}: {}", command.bin, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+      // A hook that doesn't read its input may close stdin early
+      let _ = stdin.write_all(&input).await;
+    }
+
+    let output = child
+      .wait_with_output()
+      .await
+      .map_err(|e| format!("failed to wait for {}: {}", command.bin, e))?;
+    if output.status.success() {
+      return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut message = format!("{} exited with {}", command.bin, output.status);
+    if !stderr.trim().is_empty() {
+      message.push_str(&format!(": {}", stderr.trim()));
+    }
+    Err(message)
+  }
+  .await;
+
+  result.map_err(|message| {
+    warn!(hook = name, error = %message, "hook failed");
+    format!("{} hook failed: {}", name, message)
+  })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  fn sh(script: &str, on_failure: HookFailure) -> HookCommand {
+    HookCommand {
+      bin: "sh".to_string(),
+      args: vec!["-c".to_string(), script.to_string()],
+      on_failure,
+    }
+  }
+
+  fn bind() -> BindDef {
+    BindDef {
+      id: Some("nvim".to_string()),
+      inputs: None,
+      outputs: None,
+      create_actions: vec![],
+      update_actions: None,
+      destroy_actions: vec![],
+      check_actions: None,
+      check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: vec![],
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
+    }
+  }
+
+  #[tokio::test]
+  async fn hooks_receive_events_on_stdin() {
+    let temp = TempDir::new().unwrap();
+    let log = temp.path().join("audit.log");
+    let script = format!("echo \"$SYSLUA_HOOK $(cat)\" >> {}", log.display());
+    let runner = HookRunner::new(ApplyHooks {
+      pre_bind: Some(sh(&script, HookFailure::Warn)),
+      post_bind: Some(sh(&script, HookFailure::Warn)),
+      post_apply: None,
+    });
+
+    let hash = ObjectHash("abc".to_string());
+    let result = runner
+      .around_bind(BindOperation::Create, &hash, &bind(), async { Ok(()) })
+      .await;
+    assert!(result.is_ok());
+
+    let lines: Vec<String> = std::fs::read_to_string(&log)
+      .unwrap()
+      .lines()
+      .map(String::from)
+      .collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("pre_bind {\"hook\":\"pre_bind\",\"operation\":\"create\""));
+    assert!(lines[1].starts_with("post_bind "));
+    assert!(lines[1].contains("\"success\":true"));
+    assert!(lines[1].contains("\"id\":\"nvim\""));
+  }
+
+  #[tokio::test]
+  async fn failing_pre_bind_error_skips_the_bind() {
+    let runner = HookRunner::new(ApplyHooks {
+      pre_bind: Some(sh("echo denied >&2; exit 3", HookFailure::Error)),
+      ..Default::default()
+    });
+
+    let ran = Arc::new(Mutex::new(false));
+    let ran_in_bind = ran.clone();
+    let result = runner
+      .around_bind(
+        BindOperation::Destroy,
+        &ObjectHash("abc".to_string()),
+        &bind(),
+        async move {
+          *ran_in_bind.lock().unwrap() = true;
+          Ok(())
+        },
+      )
+      .await;
+
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("pre_bind hook failed"), "{err}");
+    assert!(err.contains("denied"), "{err}");
+    assert!(!*ran.lock().unwrap());
+  }
+
+  #[tokio::test]
+  async fn failing_post_hooks_are_collected_or_warned() {
+    let runner = HookRunner::new(ApplyHooks {
+      post_bind: Some(sh("exit 1", HookFailure::Warn)),
+      post_apply: Some(sh("exit 1", HookFailure::Error)),
+      ..Default::default()
+    });
+
+    let result = runner
+      .around_bind(BindOperation::Update, &ObjectHash("abc".to_string()), &bind(), async {
+        Ok(())
+      })
+      .await;
+    assert!(result.is_ok());
+    assert!(runner.errors().is_empty());
+
+    runner
+      .post_apply(&HookEvent::PostApply {
+        success: true,
+        error: None,
+        snapshot: Some("1".to_string()),
+        builds_realized: 0,
+        binds_applied: 0,
+        binds_updated: 0,
+        binds_destroyed: 0,
+      })
+      .await;
+    assert_eq!(runner.errors().len(), 1);
+  }
+}
@@ -0,0 +1,274 @@
+//! Concurrency limits per action type.
+//!
+//! `parallelism` bounds how many builds and binds run at once, but some
+//! actions shouldn't run that often in parallel even when their nodes do:
+//! downloads compete for bandwidth, and package managers hold a global lock
+//! (a second `apt-get` or `brew` fails or waits on it). [`ActionLimits`] caps
+//! them separately, with one semaphore per action type shared by every node
+//! of an apply. A permit is held while the action runs, not for the whole
+//! build or bind.
+//!
+//! | Type    | Actions                                                | Default |
+//! | ------- | ------------------------------------------------------ | ------- |
+//! | `fetch` | `fetch_url`                                            | 4       |
+//! | `pkg`   | `exec` whose command line runs a known package manager | 1       |
+
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::action::Action;
+
+/// Concurrent `fetch_url` downloads by default.
+pub const DEFAULT_MAX_FETCH: usize = 4;
+
+/// Concurrent package manager invocations by default.
+pub const DEFAULT_MAX_PKG: usize = 1;
+
+/// Binaries treated as package managers for the `pkg` limit.
+const PACKAGE_MANAGERS: &[&str] = &[
+  "apk",
+  "apt",
+  "apt-get",
+  "aptitude",
+  "brew",
+  "choco",
+  "dnf",
+  "dpkg",
+  "emerge",
+  "flatpak",
+  "mas",
+  "microdnf",
+  "nix-env",
+  "pacman",
+  "pkgin",
+  "port",
+  "rpm",
+  "scoop",
+  "snap",
+  "winget",
+  "xbps-install",
+  "yum",
+  "zypper",
+];
+
+/// Action types with a concurrency limit of their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionKind {
+  /// `fetch_url` downloads.
+  Fetch,
+  /// Package manager invocations.
+  Pkg,
+}
+
+impl ActionKind {
+  /// The limited type of `action`, if any.
+  ///
+  /// An `exec` is a package manager invocation when its binary, or any word
+  /// of its arguments (such as a `sh -c` script), names a package manager.
+  pub fn of(action: &Action) -> Option<Self> {
+    match action {
+      Action::FetchUrl { .. } => Some(Self::Fetch),
+      Action::Exec(opts) => {
+        let args = opts.args.iter().flatten();
+        std::iter::once(&opts.bin)
+          .chain(args)
+          .flat_map(|arg| arg.split(|c: char| c.is_whitespace() || matches!(c, ';' | '&' | '|' | '(' | ')')))
+          .any(is_package_manager)
+          .then_some(Self::Pkg)
+      }
+      _ => None,
+    }
+  }
+}
+
+fn is_package_manager(word: &str) -> bool {
+  let word = word.trim_matches(|c| c == '"' || c == '\'');
+  Path::new(word)
+    .file_stem()
+    .and_then(|name| name.to_str())
+    .is_some_and(|name| PACKAGE_MANAGERS.contains(&name))
+}
+
+/// A limit on concurrently running actions of one type; 0 means unlimited.
+///
+/// Clones share the permits. Serialized as the limit.
+#[derive(Debug, Clone, Default)]
+pub struct ActionLimit(Option<Arc<Permits>>);
+
+#[derive(Debug)]
+struct Permits {
+  max: usize,
+  semaphore: Semaphore,
+}
+
+impl ActionLimit {
+  /// No limit.
+  pub const NONE: ActionLimit = ActionLimit(None);
+
+  /// Allow `max` actions at once; 0 means unlimited.
+  pub fn new(max: usize) -> Self {
+    Self((max > 0).then(|| {
+      Arc::new(Permits {
+        max,
+        semaphore: Semaphore::new(max),
+      })
+    }))
+  }
+
+  /// The limit, 0 if unlimited.
+  pub fn max(&self) -> usize {
+    self.0.as_ref().map_or(0, |permits| permits.max)
+  }
+
+  /// Wait for a slot, held until the permit is dropped.
+  pub async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+    let permits = self.0.as_ref()?;
+    Some(
+      permits
+        .semaphore
+        .acquire()
+        .await
+        .expect("action limit semaphore is never closed"),
+    )
+  }
+}
+
+impl Serialize for ActionLimit {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    self.max().serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for ActionLimit {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    usize::deserialize(deserializer).map(ActionLimit::new)
+  }
+}
+
+/// The concurrency limits of an apply, per action type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ActionLimits {
+  /// Concurrent `fetch_url` downloads.
+  pub max_fetch: ActionLimit,
+  /// Concurrent package manager invocations.
+  pub max_pkg: ActionLimit,
+}
+
+impl Default for ActionLimits {
+  fn default() -> Self {
+    Self::new(None, None)
+  }
+}
+
+impl ActionLimits {
+  /// Limits with the given maximums; `None` keeps the default and 0 means
+  /// unlimited.
+  pub fn new(max_fetch: Option<usize>, max_pkg: Option<usize>) -> Self {
+    Self {
+      max_fetch: ActionLimit::new(max_fetch.unwrap_or(DEFAULT_MAX_FETCH)),
+      max_pkg: ActionLimit::new(max_pkg.unwrap_or(DEFAULT_MAX_PKG)),
+    }
+  }
+
+  /// No limits.
+  pub fn unlimited() -> Self {
+    Self {
+      max_fetch: ActionLimit::NONE,
+      max_pkg: ActionLimit::NONE,
+    }
+  }
+
+  /// Wait until `action` may run. The returned permit, if any, must be held
+  /// while it runs.
+  pub async fn acquire(&self, action: &Action) -> Option<SemaphorePermit<'_>> {
+    match ActionKind::of(action)? {
+      ActionKind::Fetch => self.max_fetch.acquire().await,
+      ActionKind::Pkg => self.max_pkg.acquire().await,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::time::Duration;
+
+  use super::*;
+  use crate::action::actions::exec::ExecOpts;
+
+  fn exec(bin: &str, args: &[&str]) -> Action {
+    Action::Exec(ExecOpts::new(bin).with_args(args.iter().map(|arg| arg.to_string()).collect()))
+  }
+
+  #[test]
+  fn classifies_downloads_and_package_managers() {
+    let fetch = Action::FetchUrl {
+      url: "https://example.com/a.tar.gz".to_string(),
+      sha256: "abc".to_string(),
+      unpack: false,
+    };
+    assert_eq!(ActionKind::of(&fetch), Some(ActionKind::Fetch));
+    assert_eq!(
+      ActionKind::of(&exec("/usr/bin/apt-get", &["install", "-y", "jq"])),
+      Some(ActionKind::Pkg)
+    );
+    assert_eq!(
+      ActionKind::of(&exec("/bin/sh", &["-c", "set -e; brew install jq"])),
+      Some(ActionKind::Pkg)
+    );
+    // sys.pkgset passes the manager's binary to its script
+    assert_eq!(
+      ActionKind::of(&exec(
+        "/bin/sh",
+        &["-c", "\"$bin\" install $add", "sh", "/opt/homebrew/bin/brew"]
+      )),
+      Some(ActionKind::Pkg)
+    );
+    assert_eq!(ActionKind::of(&exec("/bin/sh", &["-c", "make install"])), None);
+  }
+
+  #[test]
+  fn zero_means_unlimited() {
+    assert_eq!(ActionLimit::new(0).max(), 0);
+    assert!(ActionLimit::new(0).0.is_none());
+    let limits = ActionLimits::new(Some(0), None);
+    assert_eq!(limits.max_fetch.max(), 0);
+    assert_eq!(limits.max_pkg.max(), DEFAULT_MAX_PKG);
+  }
+
+  #[test]
+  fn serializes_the_maximums() {
+    let json = serde_json::to_string(&ActionLimits::new(Some(2), Some(0))).unwrap();
+    assert_eq!(json, r#"{"max_fetch":2,"max_pkg":0}"#);
+    let limits: ActionLimits = serde_json::from_str(r#"{"max_pkg":3}"#).unwrap();
+    assert_eq!(limits.max_fetch.max(), DEFAULT_MAX_FETCH);
+    assert_eq!(limits.max_pkg.max(), 3);
+  }
+
+  #[tokio::test]
+  async fn clones_share_the_permits() {
+    let limits = ActionLimits::new(None, Some(1));
+    let action = exec("brew", &["install", "jq"]);
+    let running = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for _ in 0..3 {
+      let (limits, action, running, most) = (limits.clone(), action.clone(), running.clone(), most.clone());
+      tasks.spawn(async move {
+        let _permit = limits.acquire(&action).await;
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        most.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        running.fetch_sub(1, Ordering::SeqCst);
+      });
+    }
+    while tasks.join_next().await.is_some() {}
+
+    assert_eq!(most.load(Ordering::SeqCst), 1);
+  }
+}
@@ -0,0 +1,408 @@
+//! Live state of a running apply, for `sys status --follow`.
+//!
+//! While an apply runs, a [`LiveRecorder`] appends its [progress
+//! events](super::progress) to `<store>/live.jsonl`, one JSON record per
+//! line, between a [`LiveRecord::Started`] and a [`LiveRecord::Finished`]
+//! record. Each apply replaces the file of the previous one. Another process
+//! reads the records as they are appended with a [`LiveTail`], and folds them
+//! into a [`LiveState`]: the current wave and the builds and binds in flight.
+//!
+//! ```json
+//! {"record":"started","pid":4242,"config":"/home/me/.config/syslua/init.lua","started_at":1767225600}
+//! {"record":"progress","event":{"type":"wave_started","wave":0,"nodes":3}}
+//! {"record":"finished","error":null,"finished_at":1767225612}
+//! ```
+//!
+//! An apply that was killed leaves the file without a `finished` record;
+//! [`LiveState::is_running`] then finds its process gone.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use super::progress::{NodeKind, NodeOutcome, ProgressEvent, ProgressSender};
+use crate::platform::paths::store_dir;
+use crate::platform::process_exists;
+use crate::util::hash::ObjectHash;
+
+/// Name of the live file in the store.
+pub const LIVE_FILENAME: &str = "live.jsonl";
+
+/// Path of the live file in the current store.
+pub fn live_path() -> PathBuf {
+  store_dir().join(LIVE_FILENAME)
+}
+
+/// A line of the live file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum LiveRecord {
+  /// Process `pid` started applying `config`.
+  Started {
+    pid: u32,
+    config: Option<PathBuf>,
+    started_at: u64,
+  },
+  /// A progress event of the apply.
+  Progress { event: ProgressEvent },
+  /// The apply ended, failing with `error` if it did.
+  Finished { error: Option<String>, finished_at: u64 },
+}
+
+/// A message to the live file writer.
+#[derive(Debug)]
+pub(super) enum LiveMessage {
+  /// A progress event of the apply.
+  Event(ProgressEvent),
+  /// The apply ended, failing with this error if it did; the last message.
+  Finish(Option<String>),
+}
+
+/// Writes the progress of an apply to the live file.
+pub struct LiveRecorder {
+  writer: Option<(UnboundedSender<LiveMessage>, JoinHandle<()>)>,
+}
+
+impl LiveRecorder {
+  /// Start recording the apply of `config` to `path`, replacing the previous
+  /// apply's records; the returned sender joins the apply's progress.
+  ///
+  /// The records are written on a blocking task of the current runtime. An
+  /// apply runs the same without a live file, so failing to create it is
+  /// only logged.
+  pub fn start(path: &Path, config: Option<&Path>) -> (ProgressSender, Self) {
+    let started = LiveRecord::Started {
+      pid: std::process::id(),
+      config: config.map(|config| std::path::absolute(config).unwrap_or_else(|_| config.to_path_buf())),
+      started_at: unix_now(),
+    };
+    let file = File::create(path).and_then(|mut file| write_record(&mut file, &started).map(|()| file));
+    let mut file = match file {
+      Ok(file) => file,
+      Err(e) => {
+        warn!(error = %e, path = %path.display(), "failed to create live file");
+        return (ProgressSender::default(), Self { writer: None });
+      }
+    };
+
+    let (tx, mut messages) = unbounded_channel();
+    let writer = tokio::task::spawn_blocking(move || {
+      while let Some(message) = messages.blocking_recv() {
+        let (record, last) = match message {
+          LiveMessage::Event(event) => (LiveRecord::Progress { event }, false),
+          LiveMessage::Finish(error) => (
+            LiveRecord::Finished {
+              error,
+              finished_at: unix_now(),
+            },
+            true,
+          ),
+        };
+        if let Err(e) = write_record(&mut file, &record) {
+          warn!(error = %e, "failed to write live record");
+        }
+        if last {
+          break;
+        }
+      }
+    });
+    (
+      ProgressSender::live(tx.clone()),
+      Self {
+        writer: Some((tx, writer)),
+      },
+    )
+  }
+
+  /// Record the end of the apply, with the error it failed with, once the
+  /// events sent before are written.
+  ///
+  /// Senders still held elsewhere, such as by aborted tasks, don't hold this
+  /// up; what they send afterwards is dropped.
+  pub async fn finish(self, error: Option<String>) {
+    let Some((tx, writer)) = self.writer else {
+      return;
+    };
+    let _ = tx.send(LiveMessage::Finish(error));
+    if let Err(e) = writer.await {
+      warn!(error = %e, "live file writer failed");
+    }
+  }
+}
+
+/// Append `record` as one line, in a single write so readers never see part
+/// of it.
+fn write_record(file: &mut File, record: &LiveRecord) -> io::Result<()> {
+  let mut line = serde_json::to_string(record).map_err(io::Error::other)?;
+  line.push('\n');
+  file.write_all(line.as_bytes())
+}
+
+fn unix_now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0)
+}
+
+/// Reads the records of the live file as they are appended.
+#[derive(Debug)]
+pub struct LiveTail {
+  path: PathBuf,
+  /// The `started` line of the apply being read.
+  first_line: Option<String>,
+  /// Bytes read so far.
+  offset: u64,
+}
+
+impl LiveTail {
+  /// Read the live file at `path` from its beginning.
+  pub fn new(path: PathBuf) -> Self {
+    Self {
+      path,
+      first_line: None,
+      offset: 0,
+    }
+  }
+
+  /// The records appended since the last call.
+  ///
+  /// Only the bytes appended since are read. When another apply replaced
+  /// the file, its records are read from the beginning, starting with
+  /// [`LiveRecord::Started`]. Lines that don't parse, such as those of a
+  /// newer syslua, are skipped.
+  pub fn read(&mut self) -> io::Result<Vec<LiveRecord>> {
+    let mut file = match File::open(&self.path) {
+      Ok(file) => file,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+
+    // The first line tells which apply the file is of
+    let mut first_line = String::new();
+    BufReader::new(&mut file).read_line(&mut first_line)?;
+    let Some(first_line) = first_line.strip_suffix('\n') else {
+      return Ok(Vec::new());
+    };
+    if self.first_line.as_deref() != Some(first_line) || len < self.offset {
+      self.first_line = Some(first_line.to_string());
+      self.offset = 0;
+    }
+
+    let mut appended = Vec::new();
+    file.seek(SeekFrom::Start(self.offset))?;
+    file.read_to_end(&mut appended)?;
+
+    // Only complete lines; the last one may still be written
+    let end = appended.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let records = String::from_utf8_lossy(&appended[..end])
+      .lines()
+      .filter_map(|line| serde_json::from_str(line).ok())
+      .collect();
+    self.offset += end as u64;
+    Ok(records)
+  }
+}
+
+/// A build or bind that is running.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LiveNode {
+  pub kind: NodeKind,
+  pub id: Option<String>,
+}
+
+/// An apply as seen through its live records.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LiveState {
+  /// Process running the apply.
+  pub pid: Option<u32>,
+  /// Config being applied.
+  pub config: Option<PathBuf>,
+  /// When the apply started, in seconds since the Unix epoch.
+  pub started_at: Option<u64>,
+  /// Waves, builds and binds to execute, once execution started.
+  pub waves: usize,
+  pub builds: usize,
+  pub binds: usize,
+  /// Running wave, 0-based.
+  pub wave: Option<usize>,
+  /// Builds and binds that finished, failed or were skipped.
+  pub done: usize,
+  pub failed: usize,
+  pub skipped: usize,
+  /// Builds and binds running now.
+  pub in_flight: BTreeMap<ObjectHash, LiveNode>,
+  /// Binds being rolled back after a failure.
+  pub rolling_back: Option<usize>,
+  /// When the apply finished, in seconds since the Unix epoch.
+  pub finished_at: Option<u64>,
+  /// Error the apply failed with.
+  pub error: Option<String>,
+}
+
+impl LiveState {
+  /// The state of the apply recorded in the live file at `path`.
+  pub fn load(path: &Path) -> io::Result<Self> {
+    let mut state = Self::default();
+    for record in LiveTail::new(path.to_path_buf()).read()? {
+      state.observe(&record);
+    }
+    Ok(state)
+  }
+
+  /// Fold `record` into the state; a `started` record begins a new apply.
+  pub fn observe(&mut self, record: &LiveRecord) {
+    match record {
+      LiveRecord::Started {
+        pid,
+        config,
+        started_at,
+      } => {
+        *self = Self {
+          pid: Some(*pid),
+          config: config.clone(),
+          started_at: Some(*started_at),
+          ..Self::default()
+        };
+      }
+      LiveRecord::Progress { event } => self.observe_event(event),
+      LiveRecord::Finished { error, finished_at } => {
+        self.finished_at = Some(*finished_at);
+        self.error = error.clone();
+        self.in_flight.clear();
+      }
+    }
+  }
+
+  fn observe_event(&mut self, event: &ProgressEvent) {
+    match event {
+      ProgressEvent::Started { waves, builds, binds } => {
+        self.waves = *waves;
+        self.builds = *builds;
+        self.binds = *binds;
+      }
+      ProgressEvent::WaveStarted { wave, .. } => self.wave = Some(*wave),
+      ProgressEvent::NodeStarted { kind, hash, id } => {
+        self.in_flight.insert(
+          hash.clone(),
+          LiveNode {
+            kind: *kind,
+            id: id.clone(),
+          },
+        );
+      }
+      ProgressEvent::NodeFinished { hash, outcome, .. } => {
+        self.in_flight.remove(hash);
+        self.done += 1;
+        match outcome {
+          NodeOutcome::Succeeded => {}
+          NodeOutcome::Failed => self.failed += 1,
+          NodeOutcome::Skipped => self.skipped += 1,
+        }
+      }
+      ProgressEvent::RollingBack { binds } => self.rolling_back = Some(*binds),
+      ProgressEvent::Cached { .. } | ProgressEvent::Download { .. } => {}
+    }
+  }
+
+  /// Whether an apply started, hasn't finished and its process still runs.
+  pub fn is_running(&self) -> bool {
+    self.finished_at.is_none() && self.pid.is_some_and(process_exists)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+
+  fn hash(s: &str) -> ObjectHash {
+    ObjectHash(s.to_string())
+  }
+
+  #[tokio::test]
+  async fn tail_follows_the_records_of_an_apply() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let path = temp.path().join(LIVE_FILENAME);
+    let mut tail = LiveTail::new(path.clone());
+    assert!(tail.read().unwrap().is_empty());
+
+    let (sender, recorder) = LiveRecorder::start(&path, Some(Path::new("/etc/syslua/init.lua")));
+    sender.send(ProgressEvent::Started {
+      waves: 2,
+      builds: 1,
+      binds: 1,
+    });
+    sender.send(ProgressEvent::WaveStarted { wave: 0, nodes: 1 });
+    sender.send(ProgressEvent::NodeStarted {
+      kind: NodeKind::Build,
+      hash: hash("rg"),
+      id: Some("ripgrep".to_string()),
+    });
+    // Let the recorder write what was sent so far
+    let mut state = LiveState::default();
+    for _ in 0..100 {
+      for record in tail.read().unwrap() {
+        state.observe(&record);
+      }
+      if !state.in_flight.is_empty() {
+        break;
+      }
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(state.pid, Some(std::process::id()));
+    assert_eq!(state.config, Some(PathBuf::from("/etc/syslua/init.lua")));
+    assert_eq!(state.wave, Some(0));
+    assert_eq!(state.in_flight[&hash("rg")].id.as_deref(), Some("ripgrep"));
+    assert!(state.is_running());
+
+    sender.send(ProgressEvent::NodeFinished {
+      kind: NodeKind::Build,
+      hash: hash("rg"),
+      outcome: NodeOutcome::Failed,
+      duration: Duration::from_secs(3),
+    });
+    // A sender still held elsewhere doesn't hold up the end of the records
+    let lingering = sender.clone();
+    drop(sender);
+    recorder.finish(Some("build failed".to_string())).await;
+    lingering.send(ProgressEvent::RollingBack { binds: 1 });
+    for record in tail.read().unwrap() {
+      state.observe(&record);
+    }
+    assert!(state.in_flight.is_empty());
+    assert_eq!(state.rolling_back, None);
+    assert_eq!((state.done, state.failed), (1, 1));
+    assert_eq!(state.error.as_deref(), Some("build failed"));
+    assert!(!state.is_running());
+    assert_eq!(LiveState::load(&path).unwrap(), state);
+
+    // The next apply replaces the file, and is read from its start
+    let (sender, recorder) = LiveRecorder::start(&path, None);
+    drop(sender);
+    recorder.finish(None).await;
+    let records = tail.read().unwrap();
+    assert!(matches!(records[0], LiveRecord::Started { config: None, .. }));
+    assert!(matches!(records[1], LiveRecord::Finished { error: None, .. }));
+  }
+
+  #[test]
+  fn records_serialize_one_per_line() {
+    let record = LiveRecord::Progress {
+      event: ProgressEvent::WaveStarted { wave: 1, nodes: 3 },
+    };
+    assert_eq!(
+      serde_json::to_string(&record).unwrap(),
+      r#"{"record":"progress","event":{"type":"wave_started","wave":1,"nodes":3}}"#
+    );
+  }
+}
@@ -0,0 +1,559 @@
+//! Prometheus metrics of an apply.
+//!
+//! A [`MetricsRecorder`] consumes the [progress events](super::progress) of an
+//! apply and counts what happened: builds realized or taken from the store,
+//! binds applied, nodes failed or skipped, their durations and the bytes
+//! downloaded. Once the apply returns, the caller adds its duration and the
+//! phase it failed in, and publishes the result to a [`MetricsSink`]:
+//!
+//! - a textfile in the Prometheus text format, for node_exporter's textfile
+//!   collector, replaced atomically so a scrape never sees half of it
+//! - a Pushgateway, under job `syslua` and the machine's hostname as
+//!   `instance`, replacing the metrics of the previous push
+//!
+//! The metrics describe the last apply, as usual for batch jobs: counters
+//! count within that apply, and `syslua_apply_last_run_timestamp_seconds`
+//! tells when it finished.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+use super::progress::{NodeKind, NodeOutcome, ProgressEvent, ProgressSender, progress_channel};
+use crate::util::hash::ObjectHash;
+
+/// Upper bounds of the build and bind duration buckets, in seconds.
+const NODE_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 5.0, 15.0, 60.0, 300.0, 900.0];
+
+/// Upper bounds of the apply duration buckets, in seconds.
+const APPLY_BUCKETS: &[f64] = &[1.0, 5.0, 15.0, 60.0, 300.0, 900.0, 1800.0, 3600.0];
+
+/// Pushgateway job the metrics are pushed under.
+const PUSH_JOB: &str = "syslua";
+
+/// Where to publish the metrics of an apply; nowhere by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsSink {
+  /// File to write the metrics to, e.g. in node_exporter's textfile directory.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub textfile: Option<PathBuf>,
+  /// Base URL of a Pushgateway to push the metrics to.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub push_url: Option<String>,
+}
+
+impl MetricsSink {
+  /// Whether the metrics go nowhere.
+  pub fn is_empty(&self) -> bool {
+    self.textfile.is_none() && self.push_url.is_none()
+  }
+}
+
+/// Errors publishing metrics.
+#[derive(Debug, Error)]
+pub enum MetricsError {
+  #[error("failed to write metrics to {path}: {source}")]
+  Write {
+    path: PathBuf,
+    #[source]
+    source: std::io::Error,
+  },
+
+  #[error("failed to push metrics to {url}: {message}")]
+  Push { url: String, message: String },
+}
+
+/// Histogram of durations with fixed buckets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+  bounds: &'static [f64],
+  /// Observations per bucket, not cumulative; the last one is `+Inf`.
+  counts: Vec<u64>,
+  sum: f64,
+}
+
+impl Histogram {
+  fn new(bounds: &'static [f64]) -> Self {
+    Self {
+      bounds,
+      counts: vec![0; bounds.len() + 1],
+      sum: 0.0,
+    }
+  }
+
+  fn observe(&mut self, duration: Duration) {
+    let secs = duration.as_secs_f64();
+    let bucket = self
+      .bounds
+      .iter()
+      .position(|bound| secs <= *bound)
+      .unwrap_or(self.bounds.len());
+    self.counts[bucket] += 1;
+    self.sum += secs;
+  }
+
+  /// Number of observations.
+  pub fn count(&self) -> u64 {
+    self.counts.iter().sum()
+  }
+
+  /// Sum of the observed durations, in seconds.
+  pub fn sum(&self) -> f64 {
+    self.sum
+  }
+
+  fn render(&self, out: &mut String, name: &str, labels: &str) {
+    let sep = if labels.is_empty() { "" } else { "," };
+    let mut cumulative = 0;
+    for (bound, count) in self.bounds.iter().zip(&self.counts) {
+      cumulative += count;
+      let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"{bound}\"}} {cumulative}");
+    }
+    let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}", self.count());
+    let labels = if labels.is_empty() {
+      String::new()
+    } else {
+      format!("{{{labels}}}")
+    };
+    let _ = writeln!(out, "{name}_sum{labels} {}", self.sum);
+    let _ = writeln!(out, "{name}_count{labels} {}", self.count());
+  }
+}
+
+/// Builds and binds that ended one way, by kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeCounts {
+  pub builds: u64,
+  pub binds: u64,
+}
+
+impl NodeCounts {
+  fn add(&mut self, kind: NodeKind) {
+    match kind {
+      NodeKind::Build => self.builds += 1,
+      NodeKind::Bind => self.binds += 1,
+    }
+  }
+}
+
+/// What one apply did, as counted from its progress events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplyMetrics {
+  /// Builds that ran their actions.
+  pub builds_realized: u64,
+  /// Builds already in the store.
+  pub cache_hits: u64,
+  /// Binds created or repaired.
+  pub binds_applied: u64,
+  /// Nodes that failed.
+  pub failed: NodeCounts,
+  /// Nodes not run because a dependency failed.
+  pub skipped: NodeCounts,
+  /// Times a failure rolled back the applied binds.
+  pub rollbacks: u64,
+  /// Bytes received by `fetch_url` downloads.
+  pub downloaded_bytes: u64,
+  /// Durations of realized builds.
+  pub build_seconds: Histogram,
+  /// Durations of applied binds.
+  pub bind_seconds: Histogram,
+  /// Duration of the whole apply, once finished.
+  pub duration: Option<Duration>,
+  /// Phase the apply failed in (see [`ApplyError::phase`](super::ApplyError::phase)),
+  /// or `None` if it succeeded.
+  pub failed_phase: Option<&'static str>,
+  /// When the apply finished, in seconds since the Unix epoch.
+  pub finished_at: u64,
+  cached: HashSet<ObjectHash>,
+  downloads: HashMap<(ObjectHash, String), u64>,
+}
+
+impl Default for ApplyMetrics {
+  fn default() -> Self {
+    Self {
+      builds_realized: 0,
+      cache_hits: 0,
+      binds_applied: 0,
+      failed: NodeCounts::default(),
+      skipped: NodeCounts::default(),
+      rollbacks: 0,
+      downloaded_bytes: 0,
+      build_seconds: Histogram::new(NODE_BUCKETS),
+      bind_seconds: Histogram::new(NODE_BUCKETS),
+      duration: None,
+      failed_phase: None,
+      finished_at: 0,
+      cached: HashSet::new(),
+      downloads: HashMap::new(),
+    }
+  }
+}
+
+impl ApplyMetrics {
+  /// Count a progress event.
+  pub fn observe(&mut self, event: &ProgressEvent) {
+    match event {
+      ProgressEvent::Cached { hash } => {
+        self.cached.insert(hash.clone());
+      }
+      ProgressEvent::NodeFinished {
+        kind,
+        hash,
+        outcome,
+        duration,
+      } => match (outcome, kind) {
+        (NodeOutcome::Succeeded, NodeKind::Build) if self.cached.contains(hash) => self.cache_hits += 1,
+        (NodeOutcome::Succeeded, NodeKind::Build) => {
+          self.builds_realized += 1;
+          self.build_seconds.observe(*duration);
+        }
+        (NodeOutcome::Succeeded, NodeKind::Bind) => {
+          self.binds_applied += 1;
+          self.bind_seconds.observe(*duration);
+        }
+        (NodeOutcome::Failed, kind) => self.failed.add(*kind),
+        (NodeOutcome::Skipped, kind) => self.skipped.add(*kind),
+      },
+      ProgressEvent::Download {
+        hash, url, received, ..
+      } => {
+        // Downloads report their running total; keep the latest per download
+        let total = self.downloads.entry((hash.clone(), url.clone())).or_default();
+        *total = (*total).max(*received);
+        self.downloaded_bytes = self.downloads.values().sum();
+      }
+      ProgressEvent::RollingBack { .. } => self.rollbacks += 1,
+      ProgressEvent::Started { .. } | ProgressEvent::WaveStarted { .. } | ProgressEvent::NodeStarted { .. } => {}
+    }
+  }
+
+  /// Record the end of the apply: how long it took and the phase it failed
+  /// in, if it did.
+  pub fn finish(&mut self, duration: Duration, failed_phase: Option<&'static str>) {
+    self.duration = Some(duration);
+    self.failed_phase = failed_phase;
+    self.finished_at = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or(0);
+  }
+
+  /// The metrics in the Prometheus text exposition format.
+  pub fn render(&self) -> String {
+    let mut out = String::new();
+    let header = |out: &mut String, name: &str, kind: &str, help: &str| {
+      let _ = writeln!(out, "# HELP {name} {help}");
+      let _ = writeln!(out, "# TYPE {name} {kind}");
+    };
+
+    header(
+      &mut out,
+      "syslua_apply_success",
+      "gauge",
+      "Whether the last apply succeeded (1) or failed (0).",
+    );
+    let _ = writeln!(out, "syslua_apply_success {}", u8::from(self.failed_phase.is_none()));
+
+    header(
+      &mut out,
+      "syslua_apply_last_run_timestamp_seconds",
+      "gauge",
+      "When the last apply finished, in seconds since the Unix epoch.",
+    );
+    let _ = writeln!(out, "syslua_apply_last_run_timestamp_seconds {}", self.finished_at);
+
+    header(
+      &mut out,
+      "syslua_apply_duration_seconds",
+      "histogram",
+      "Duration of the last apply.",
+    );
+    let mut apply_seconds = Histogram::new(APPLY_BUCKETS);
+    if let Some(duration) = self.duration {
+      apply_seconds.observe(duration);
+    }
+    apply_seconds.render(&mut out, "syslua_apply_duration_seconds", "");
+
+    header(
+      &mut out,
+      "syslua_apply_failures_total",
+      "counter",
+      "Failures of the last apply, by the phase it failed in.",
+    );
+    if let Some(phase) = self.failed_phase {
+      let _ = writeln!(out, "syslua_apply_failures_total{{phase=\"{phase}\"}} 1");
+    }
+
+    let counters = [
+      (
+        "syslua_builds_realized_total",
+        "Builds the last apply realized.",
+        self.builds_realized,
+      ),
+      (
+        "syslua_build_cache_hits_total",
+        "Builds the last apply found in the store.",
+        self.cache_hits,
+      ),
+      (
+        "syslua_binds_applied_total",
+        "Binds the last apply created or repaired.",
+        self.binds_applied,
+      ),
+      (
+        "syslua_rollbacks_total",
+        "Rollbacks of applied binds in the last apply.",
+        self.rollbacks,
+      ),
+      (
+        "syslua_downloaded_bytes_total",
+        "Bytes downloaded by fetch_url in the last apply.",
+        self.downloaded_bytes,
+      ),
+    ];
+    for (name, help, value) in counters {
+      header(&mut out, name, "counter", help);
+      let _ = writeln!(out, "{name} {value}");
+    }
+
+    let by_kind = [
+      (
+        "syslua_nodes_failed_total",
+        "Builds and binds that failed in the last apply.",
+        self.failed,
+      ),
+      (
+        "syslua_nodes_skipped_total",
+        "Builds and binds the last apply skipped because a dependency failed.",
+        self.skipped,
+      ),
+    ];
+    for (name, help, counts) in by_kind {
+      header(&mut out, name, "counter", help);
+      let _ = writeln!(out, "{name}{{kind=\"build\"}} {}", counts.builds);
+      let _ = writeln!(out, "{name}{{kind=\"bind\"}} {}", counts.binds);
+    }
+
+    header(
+      &mut out,
+      "syslua_node_duration_seconds",
+      "histogram",
+      "Durations of the builds realized and binds applied by the last apply.",
+    );
+    self
+      .build_seconds
+      .render(&mut out, "syslua_node_duration_seconds", "kind=\"build\"");
+    self
+      .bind_seconds
+      .render(&mut out, "syslua_node_duration_seconds", "kind=\"bind\"");
+
+    out
+  }
+
+  /// Write the metrics to the textfile and push them to the gateway of `sink`.
+  ///
+  /// Both are attempted; the first error is returned. Must not be called
+  /// from async code, as pushing runs its own runtime.
+  pub fn publish(&self, sink: &MetricsSink) -> Result<(), MetricsError> {
+    let body = self.render();
+    let written = match &sink.textfile {
+      Some(path) => write_textfile(path, &body),
+      None => Ok(()),
+    };
+    let pushed = match &sink.push_url {
+      Some(url) => push(url, body),
+      None => Ok(()),
+    };
+    written.and(pushed)
+  }
+}
+
+/// Collects the metrics of an apply from its progress events.
+pub struct MetricsRecorder {
+  thread: JoinHandle<ApplyMetrics>,
+}
+
+impl MetricsRecorder {
+  /// Start recording; the returned sender goes in the apply's `ExecuteConfig`.
+  pub fn start() -> (ProgressSender, Self) {
+    let (sender, mut events) = progress_channel();
+    let thread = std::thread::spawn(move || {
+      let mut metrics = ApplyMetrics::default();
+      while let Some(event) = events.blocking_recv() {
+        metrics.observe(&event);
+      }
+      metrics
+    });
+    (sender, Self { thread })
+  }
+
+  /// The metrics counted so far.
+  ///
+  /// Every clone of the sender must be dropped first, or this never returns.
+  pub fn finish(self) -> ApplyMetrics {
+    self.thread.join().unwrap_or_default()
+  }
+}
+
+/// Replace `path` with `body` through a temporary file, as the textfile
+/// collector requires.
+fn write_textfile(path: &Path, body: &str) -> Result<(), MetricsError> {
+  let write = || -> std::io::Result<()> {
+    let dir = path
+      .parent()
+      .filter(|dir| !dir.as_os_str().is_empty())
+      .unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let temp = tempfile::NamedTempFile::new_in(dir)?;
+    fs::write(temp.path(), body)?;
+    temp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+  };
+  write().map_err(|source| MetricsError::Write {
+    path: path.to_path_buf(),
+    source,
+  })?;
+  debug!(path = %path.display(), "wrote apply metrics");
+  Ok(())
+}
+
+/// URL of the metrics group of this machine on the gateway at `base`.
+fn push_url(base: &str) -> String {
+  let base = base.trim_end_matches('/');
+  if base.contains("/metrics/job/") {
+    return base.to_string();
+  }
+  let instance = crate::platform::hostname().unwrap_or_else(|| "unknown".to_string());
+  format!("{base}/metrics/job/{PUSH_JOB}/instance/{instance}")
+}
+
+/// PUT `body` to the gateway, replacing the metrics of the previous push.
+fn push(base: &str, body: String) -> Result<(), MetricsError> {
+  let url = push_url(base);
+  let failed = |message: String| MetricsError::Push {
+    url: url.clone(),
+    message,
+  };
+  let rt = tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+    .map_err(|e| failed(e.to_string()))?;
+  let status = rt
+    .block_on(async {
+      reqwest::Client::new()
+        .put(&url)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(body)
+        .send()
+        .await
+        .map(|response| response.status())
+    })
+    .map_err(|e| failed(e.to_string()))?;
+  if !status.is_success() {
+    return Err(failed(format!("HTTP {}", status)));
+  }
+  debug!(url = %url, "pushed apply metrics");
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn hash(s: &str) -> ObjectHash {
+    ObjectHash(s.to_string())
+  }
+
+  fn finished(kind: NodeKind, h: &str, outcome: NodeOutcome, secs: u64) -> ProgressEvent {
+    ProgressEvent::NodeFinished {
+      kind,
+      hash: hash(h),
+      outcome,
+      duration: Duration::from_secs(secs),
+    }
+  }
+
+  #[test]
+  fn counts_events_of_an_apply() {
+    let (sender, recorder) = MetricsRecorder::start();
+    sender.send(ProgressEvent::Cached { hash: hash("cached") });
+    sender.send(finished(NodeKind::Build, "cached", NodeOutcome::Succeeded, 0));
+    sender.send(finished(NodeKind::Build, "new", NodeOutcome::Succeeded, 3));
+    for received in [100, 400, 1000] {
+      sender.send(ProgressEvent::Download {
+        hash: hash("new"),
+        url: "https://example.com/a.tar.gz".to_string(),
+        received,
+        total: Some(1000),
+      });
+    }
+    sender.send(finished(NodeKind::Bind, "ok", NodeOutcome::Succeeded, 1));
+    sender.send(finished(NodeKind::Bind, "bad", NodeOutcome::Failed, 1));
+    sender.send(finished(NodeKind::Bind, "after", NodeOutcome::Skipped, 0));
+    sender.send(ProgressEvent::RollingBack { binds: 1 });
+    drop(sender);
+
+    let metrics = recorder.finish();
+    assert_eq!(metrics.builds_realized, 1);
+    assert_eq!(metrics.cache_hits, 1);
+    assert_eq!(metrics.binds_applied, 1);
+    assert_eq!(metrics.failed, NodeCounts { builds: 0, binds: 1 });
+    assert_eq!(metrics.skipped, NodeCounts { builds: 0, binds: 1 });
+    assert_eq!(metrics.rollbacks, 1);
+    assert_eq!(metrics.downloaded_bytes, 1000);
+    assert_eq!(metrics.build_seconds.count(), 1);
+    assert_eq!(metrics.build_seconds.sum(), 3.0);
+  }
+
+  #[test]
+  fn renders_the_text_format() {
+    let mut metrics = ApplyMetrics::default();
+    metrics.observe(&finished(NodeKind::Build, "a", NodeOutcome::Succeeded, 2));
+    metrics.finish(Duration::from_secs(20), Some("execute"));
+
+    let text = metrics.render();
+    assert!(text.contains("# TYPE syslua_builds_realized_total counter\nsyslua_builds_realized_total 1\n"));
+    assert!(text.contains("syslua_apply_success 0\n"));
+    assert!(text.contains("syslua_apply_failures_total{phase=\"execute\"} 1\n"));
+    assert!(text.contains("syslua_apply_duration_seconds_bucket{le=\"15\"} 0\n"));
+    assert!(text.contains("syslua_apply_duration_seconds_bucket{le=\"60\"} 1\n"));
+    assert!(text.contains("syslua_apply_duration_seconds_sum 20\n"));
+    assert!(text.contains("syslua_node_duration_seconds_bucket{kind=\"build\",le=\"5\"} 1\n"));
+    assert!(text.contains("syslua_node_duration_seconds_count{kind=\"bind\"} 0\n"));
+    assert!(text.ends_with('\n'));
+  }
+
+  #[test]
+  fn publishes_to_a_textfile_atomically() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("collector").join("syslua.prom");
+    let mut metrics = ApplyMetrics::default();
+    metrics.finish(Duration::from_secs(1), None);
+
+    let sink = MetricsSink {
+      textfile: Some(path.clone()),
+      push_url: None,
+    };
+    metrics.publish(&sink).unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(text.contains("syslua_apply_success 1\n"));
+    assert!(!text.contains("syslua_apply_failures_total{"));
+    assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+  }
+
+  #[test]
+  fn push_url_names_the_job_and_instance() {
+    assert!(push_url("http://gw:9091/").starts_with("http://gw:9091/metrics/job/syslua/instance/"));
+    assert_eq!(
+      push_url("http://gw:9091/metrics/job/fleet/instance/web1"),
+      "http://gw:9091/metrics/job/fleet/instance/web1"
+    );
+  }
+}
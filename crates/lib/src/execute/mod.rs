@@ -2,16 +2,40 @@
 //!
 //! This module provides the main entry points for executing builds and binds from a manifest.
 //! It handles:
+//! - Plans: the diff an apply would make, without applying it
 //! - DAG-based dependency ordering
-//! - Parallel execution of independent nodes
+//! - Parallel execution of independent nodes, one at a time within a bind `serialize` group
+//! - Concurrency limits per action type (downloads, package managers)
 //! - Failure propagation and skip tracking
 //! - Atomic rollback of binds on failure
-
+//! - Audit hooks run around each bind and at the end of an apply
+//! - Execution history, used to start the longest chains of work first
+//! - Transcripts of the commands each apply ran, for debugging failures
+//! - Prometheus metrics of an apply, counted from its progress events
+//! - Live state of a running apply, for `sys status --follow` from another terminal
+//! - Preflight checks of disk space and writable directories before an apply
+//! - Login and logout activation of login-phase binds
+
+pub mod activate;
 pub mod apply;
 pub mod dag;
+pub mod failures;
+pub mod fault;
+pub mod history;
+pub mod hooks;
+pub mod limits;
+pub mod live;
+pub mod metrics;
+pub mod plan;
+pub mod preflight;
+pub mod progress;
 pub mod resolver;
+pub mod serialize;
+pub mod touches;
+pub mod transcript;
 pub mod types;
 
+use std::cmp::Reverse;
 use std::collections::{HashMap, HashSet};
 
 use tokio::sync::Semaphore;
@@ -19,24 +43,45 @@ use tracing::{debug, error, info, warn};
 
 use crate::{
   bind::execute::{apply_bind, destroy_bind},
+  bind::target::target_dirs,
   manifest::Manifest,
   util::hash::ObjectHash,
 };
 
 use dag::DagNode;
+use hooks::BindOperation;
+use progress::{NodeKind, NodeOutcome, ProgressEvent};
 use resolver::BindCtxResolver;
+use serialize::SerializeGroups;
 
+pub use activate::{ActivationResult, SessionEvent, activate};
 pub use apply::{
-  ApplyError, ApplyOptions, ApplyResult, DestroyOptions, DestroyResult, apply, check_unchanged_binds, destroy,
+  ApplyError, ApplyOptions, ApplyResult, DestroyOptions, DestroyResult, RepairOptions, RepairResult, apply,
+  changes_outside_groups, check_unchanged_binds, deselect_changes, destroy, repair,
 };
 pub use dag::ExecutionDag;
-pub use types::{BindResult, BuildResult, DagResult, ExecuteConfig, ExecuteError, FailedDependency};
+pub use failures::{FailedBuilds, PreviousFailure};
+pub use fault::{FailPhase, FailPoint};
+pub use hooks::{ApplyHooks, HookRunner};
+pub use limits::ActionLimits;
+pub use live::{LiveRecord, LiveRecorder, LiveState, LiveTail};
+pub use metrics::{ApplyMetrics, MetricsError, MetricsRecorder, MetricsSink};
+pub use plan::{PlanOptions, PlanReport, diff_against_current, plan};
+pub use preflight::{PreflightProblem, PreflightReport};
+pub use touches::{BindTouches, PathTouch, TouchAction};
+pub use types::{BindResult, BuildResult, DagResult, ExecuteConfig, ExecuteError, FailedDependency, NodeTiming};
+
+/// Outcome of a single build task: hash, result and timing.
+type BuildOutcome = (ObjectHash, Result<BuildResult, ExecuteError>, NodeTiming);
+
+/// Outcome of a single bind task: hash, result and timing.
+type BindOutcome = (ObjectHash, Result<BindResult, ExecuteError>, NodeTiming);
 
 /// Type alias for build task JoinSet to reduce complexity.
-type BuildJoinSet = tokio::task::JoinSet<Result<(ObjectHash, Result<BuildResult, ExecuteError>), ExecuteError>>;
+type BuildJoinSet = tokio::task::JoinSet<Result<BuildOutcome, ExecuteError>>;
 
 /// Type alias for bind task JoinSet to reduce complexity.
-type BindJoinSet = tokio::task::JoinSet<Result<(ObjectHash, Result<BindResult, ExecuteError>), ExecuteError>>;
+type BindJoinSet = tokio::task::JoinSet<Result<BindOutcome, ExecuteError>>;
 
 /// Execute all builds in a manifest.
 ///
@@ -60,8 +105,12 @@ pub async fn execute_builds(manifest: &Manifest, config: &ExecuteConfig) -> Resu
   // Build the execution DAG
   let dag = ExecutionDag::from_manifest(manifest)?;
 
-  // Get execution waves
-  let waves = dag.build_waves()?;
+  // Get execution waves, longest chains of work first
+  let mut waves = dag.build_waves()?;
+  let lengths = critical_path_lengths(&dag, config)?;
+  for wave in &mut waves {
+    wave.sort_by_cached_key(|hash| Reverse(lengths.get(&DagNode::Build(hash.clone())).copied()));
+  }
 
   debug!(wave_count = waves.len(), "computed execution waves");
 
@@ -108,7 +157,8 @@ pub async fn execute_builds(manifest: &Manifest, config: &ExecuteConfig) -> Resu
       let wave_results = execute_wave(&ready_builds, manifest, config, &result.realized, semaphore.clone()).await;
 
       // Process results
-      for (hash, build_result) in wave_results {
+      for (hash, build_result, timing) in wave_results {
+        result.build_timings.insert(hash.clone(), timing);
         match build_result {
           Ok(br) => {
             debug!(build = %hash.0, "build succeeded");
@@ -148,6 +198,9 @@ pub async fn execute_builds(manifest: &Manifest, config: &ExecuteConfig) -> Resu
 /// * `manifest` - The manifest containing builds and binds to execute
 /// * `config` - Execution configuration
 ///
+/// Binds can only refer to outputs of binds in the same manifest; see
+/// [`execute_manifest_with_binds`] to also resolve already applied ones.
+///
 /// # Returns
 ///
 /// A `DagResult` containing realized builds, applied binds, failures, and skipped nodes.
@@ -160,6 +213,20 @@ pub async fn execute_builds(manifest: &Manifest, config: &ExecuteConfig) -> Resu
 /// - The failed node is recorded in `build_failed` or `bind_failed`
 /// - Dependent nodes are recorded in `build_skipped` or `bind_skipped`
 pub async fn execute_manifest(manifest: &Manifest, config: &ExecuteConfig) -> Result<DagResult, ExecuteError> {
+  execute_manifest_with_binds(manifest, config, &HashMap::new()).await
+}
+
+/// Execute a manifest whose binds may refer to binds applied earlier.
+///
+/// Like [`execute_manifest`], but `existing_binds` holds the results of binds
+/// outside the manifest, such as the unchanged binds of an apply, so binds in
+/// the manifest can resolve their outputs. They are never re-applied or
+/// rolled back.
+pub async fn execute_manifest_with_binds(
+  manifest: &Manifest,
+  config: &ExecuteConfig,
+  existing_binds: &HashMap<ObjectHash, BindResult>,
+) -> Result<DagResult, ExecuteError> {
   info!(
     build_count = manifest.builds.len(),
     bind_count = manifest.bindings.len(),
@@ -169,10 +236,19 @@ pub async fn execute_manifest(manifest: &Manifest, config: &ExecuteConfig) -> Re
   // Build the execution DAG
   let dag = ExecutionDag::from_manifest(manifest)?;
 
-  // Get unified execution waves
-  let waves = dag.execution_waves()?;
+  // Get unified execution waves, longest chains of work first
+  let mut waves = dag.execution_waves()?;
+  let lengths = critical_path_lengths(&dag, config)?;
+  for wave in &mut waves {
+    wave.sort_by_key(|node| Reverse(lengths.get(node).copied()));
+  }
 
   debug!(wave_count = waves.len(), "computed execution waves");
+  config.progress.send(ProgressEvent::Started {
+    waves: waves.len(),
+    builds: manifest.builds.len(),
+    binds: manifest.bindings.len(),
+  });
 
   // Track results
   let mut result = DagResult::default();
@@ -181,12 +257,20 @@ pub async fn execute_manifest(manifest: &Manifest, config: &ExecuteConfig) -> Re
   // Track applied binds in order for rollback
   let mut applied_binds_order: Vec<ObjectHash> = Vec::new();
 
+  // Outputs binds and builds can refer to: existing binds and those applied so far
+  let mut known_binds = existing_binds.clone();
+
   // Create semaphore for parallelism control
   let semaphore = std::sync::Arc::new(Semaphore::new(config.parallelism));
+  let groups = SerializeGroups::new();
 
   // Execute waves in order
   'waves: for (wave_idx, wave) in waves.iter().enumerate() {
     debug!(wave = wave_idx, nodes = wave.len(), "executing wave");
+    config.progress.send(ProgressEvent::WaveStarted {
+      wave: wave_idx,
+      nodes: wave.len(),
+    });
 
     // Separate builds and binds in this wave
     let mut ready_builds = Vec::new();
@@ -218,6 +302,7 @@ pub async fn execute_manifest(manifest: &Manifest, config: &ExecuteConfig) -> Re
         failed_dep = %failed_dep,
         "skipping build due to failed dependency"
       );
+      send_finished(config, NodeKind::Build, &hash, NodeOutcome::Skipped, None);
       failed_nodes.insert(DagNode::Build(hash.clone()));
       result.build_skipped.insert(hash, failed_dep);
     }
@@ -228,6 +313,7 @@ pub async fn execute_manifest(manifest: &Manifest, config: &ExecuteConfig) -> Re
         failed_dep = %failed_dep,
         "skipping bind due to failed dependency"
       );
+      send_finished(config, NodeKind::Bind, &hash, NodeOutcome::Skipped, None);
       failed_nodes.insert(DagNode::Bind(hash.clone()));
       result.bind_skipped.insert(hash, failed_dep);
     }
@@ -239,20 +325,23 @@ pub async fn execute_manifest(manifest: &Manifest, config: &ExecuteConfig) -> Re
         manifest,
         config,
         &result.realized,
-        &result.applied,
+        &known_binds,
         semaphore.clone(),
       )
       .await;
 
       // Process build results
-      for (hash, build_result) in build_results {
+      for (hash, build_result, timing) in build_results {
+        result.build_timings.insert(hash.clone(), timing);
         match build_result {
           Ok(br) => {
             debug!(build = %hash.0, "build succeeded");
+            send_finished(config, NodeKind::Build, &hash, NodeOutcome::Succeeded, Some(&timing));
             result.realized.insert(hash, br);
           }
           Err(e) => {
             error!(build = %hash.0, error = %e, "build failed");
+            send_finished(config, NodeKind::Build, &hash, NodeOutcome::Failed, Some(&timing));
             failed_nodes.insert(DagNode::Build(hash.clone()));
             result.build_failed = Some((hash, e));
 
@@ -271,21 +360,26 @@ pub async fn execute_manifest(manifest: &Manifest, config: &ExecuteConfig) -> Re
         manifest,
         config,
         &result.realized,
-        &result.applied,
+        &known_binds,
         semaphore.clone(),
+        &groups,
       )
       .await;
 
       // Process bind results
-      for (hash, bind_result) in bind_results {
+      for (hash, bind_result, timing) in bind_results {
+        result.bind_timings.insert(hash.clone(), timing);
         match bind_result {
           Ok(br) => {
             debug!(bind = %hash.0, "bind succeeded");
+            send_finished(config, NodeKind::Bind, &hash, NodeOutcome::Succeeded, Some(&timing));
             applied_binds_order.push(hash.clone());
+            known_binds.insert(hash.clone(), br.clone());
             result.applied.insert(hash, br);
           }
           Err(e) => {
             error!(bind = %hash.0, error = %e, "bind failed");
+            send_finished(config, NodeKind::Bind, &hash, NodeOutcome::Failed, Some(&timing));
             failed_nodes.insert(DagNode::Bind(hash.clone()));
             result.bind_failed = Some((hash, e));
 
@@ -311,6 +405,38 @@ pub async fn execute_manifest(manifest: &Manifest, config: &ExecuteConfig) -> Re
   Ok(result)
 }
 
+/// Expected length of the chain of work starting at each node.
+///
+/// Waves are sorted by it so the nodes holding up the most work are spawned
+/// first and take the parallelism permits first. Empty without estimates,
+/// which keeps the wave order unchanged.
+fn critical_path_lengths(dag: &ExecutionDag, config: &ExecuteConfig) -> Result<HashMap<DagNode, u64>, ExecuteError> {
+  if config.expected_durations.is_empty() {
+    return Ok(HashMap::new());
+  }
+
+  dag.critical_path_lengths(|node| {
+    let (DagNode::Build(hash) | DagNode::Bind(hash)) = node;
+    config.expected_durations.get(hash).copied().unwrap_or(0)
+  })
+}
+
+/// Report that a node finished; skipped nodes have no timing.
+fn send_finished(
+  config: &ExecuteConfig,
+  kind: NodeKind,
+  hash: &ObjectHash,
+  outcome: NodeOutcome,
+  timing: Option<&NodeTiming>,
+) {
+  config.progress.send(ProgressEvent::NodeFinished {
+    kind,
+    hash: hash.clone(),
+    outcome,
+    duration: timing.map(NodeTiming::duration).unwrap_or_default(),
+  });
+}
+
 /// Find a failed dependency for a node.
 fn find_failed_dependency(
   node: &DagNode,
@@ -352,7 +478,7 @@ async fn execute_build_wave(
   completed_builds: &HashMap<ObjectHash, BuildResult>,
   completed_binds: &HashMap<ObjectHash, BindResult>,
   semaphore: std::sync::Arc<Semaphore>,
-) -> Vec<(ObjectHash, Result<BuildResult, ExecuteError>)> {
+) -> Vec<BuildOutcome> {
   use tokio::task::JoinSet;
 
   let mut join_set = JoinSet::new();
@@ -366,25 +492,41 @@ async fn execute_build_wave(
     let semaphore = semaphore.clone();
 
     join_set.spawn(async move {
-      let _permit = semaphore.acquire().await.unwrap();
-
       let build_def = manifest
         .builds
         .get(&hash)
         .ok_or_else(|| ExecuteError::BuildNotFound(hash.clone()))?;
 
+      // Builds declaring CPUs take that many permits from the shared pool
+      let weight = build_def.resources.unwrap_or_default().weight(config.parallelism);
+      let _permit = semaphore.acquire_many(weight).await.unwrap();
+      config.progress.send(ProgressEvent::NodeStarted {
+        kind: NodeKind::Build,
+        hash: hash.clone(),
+        id: build_def.id.clone(),
+      });
+
       // Build execution (builds can only reference other builds, not binds)
-      let result = crate::build::execute::realize_build_with_resolver(
-        &hash,
-        build_def,
-        &completed_builds,
-        &completed_binds,
-        &manifest,
-        &config,
-      )
+      let (result, timing) = NodeTiming::measure(async {
+        fault::check(
+          config.fail_at.as_ref(),
+          FailPhase::Build,
+          &hash,
+          build_def.id.as_deref(),
+        )?;
+        crate::build::execute::realize_build_with_resolver(
+          &hash,
+          build_def,
+          &completed_builds,
+          &completed_binds,
+          &manifest,
+          &config,
+        )
+        .await
+      })
       .await;
 
-      Ok::<_, ExecuteError>((hash, result))
+      Ok::<_, ExecuteError>((hash, result, timing))
     });
   }
 
@@ -399,7 +541,8 @@ async fn execute_bind_wave(
   completed_builds: &HashMap<ObjectHash, BuildResult>,
   completed_binds: &HashMap<ObjectHash, BindResult>,
   semaphore: std::sync::Arc<Semaphore>,
-) -> Vec<(ObjectHash, Result<BindResult, ExecuteError>)> {
+  groups: &SerializeGroups,
+) -> Vec<BindOutcome> {
   use tokio::task::JoinSet;
 
   let mut join_set = JoinSet::new();
@@ -407,14 +550,13 @@ async fn execute_bind_wave(
   for hash in binds {
     let hash = hash.clone();
     let manifest = manifest.clone();
-    let _config = config.clone();
+    let config = config.clone();
     let completed_builds = completed_builds.clone();
     let completed_binds = completed_binds.clone();
     let semaphore = semaphore.clone();
+    let groups = groups.clone();
 
     join_set.spawn(async move {
-      let _permit = semaphore.acquire().await.unwrap();
-
       let bind_def = manifest
         .bindings
         .get(&hash)
@@ -426,11 +568,31 @@ async fn execute_bind_wave(
         &completed_binds,
         &manifest,
         "/tmp".to_string(), // Temporary; apply_bind creates its own working dir
-      );
-
-      let result = apply_bind(&hash, bind_def, &resolver).await;
-
-      Ok::<_, ExecuteError>((hash, result))
+      )
+      .with_action_limits(config.action_limits.clone())
+      .with_transcript(config.transcript.clone());
+
+      // Wait for the bind's serialization group and target directories before taking a slot
+      let _group = groups.lock(bind_def.serialize.as_deref()).await;
+      let _dirs = groups
+        .lock_dirs(&target_dirs(bind_def, BindOperation::Create, &resolver))
+        .await;
+      let _permit = semaphore.acquire().await.unwrap();
+      config.progress.send(ProgressEvent::NodeStarted {
+        kind: NodeKind::Bind,
+        hash: hash.clone(),
+        id: bind_def.id.clone(),
+      });
+
+      let (result, timing) =
+        NodeTiming::measure(config.hooks.around_bind(BindOperation::Create, &hash, bind_def, async {
+          fault::check(config.fail_at.as_ref(), FailPhase::Bind, &hash, bind_def.id.as_deref())?;
+          config.roots.add(&hash)?;
+          apply_bind(&hash, bind_def, &resolver).await
+        }))
+        .await;
+
+      Ok::<_, ExecuteError>((hash, result, timing))
     });
   }
 
@@ -438,13 +600,13 @@ async fn execute_bind_wave(
 }
 
 /// Collect results from a JoinSet of build tasks.
-async fn collect_join_results(mut join_set: BuildJoinSet) -> Vec<(ObjectHash, Result<BuildResult, ExecuteError>)> {
+async fn collect_join_results(mut join_set: BuildJoinSet) -> Vec<BuildOutcome> {
   let mut results = Vec::new();
 
   while let Some(join_result) = join_set.join_next().await {
     match join_result {
-      Ok(Ok((hash, build_result))) => {
-        results.push((hash, build_result));
+      Ok(Ok(outcome)) => {
+        results.push(outcome);
       }
       Ok(Err(e)) => {
         error!(error = %e, "unexpected error in build task");
@@ -459,13 +621,13 @@ async fn collect_join_results(mut join_set: BuildJoinSet) -> Vec<(ObjectHash, Re
 }
 
 /// Collect results from a JoinSet of bind tasks.
-async fn collect_bind_join_results(mut join_set: BindJoinSet) -> Vec<(ObjectHash, Result<BindResult, ExecuteError>)> {
+async fn collect_bind_join_results(mut join_set: BindJoinSet) -> Vec<BindOutcome> {
   let mut results = Vec::new();
 
   while let Some(join_result) = join_set.join_next().await {
     match join_result {
-      Ok(Ok((hash, bind_result))) => {
-        results.push((hash, bind_result));
+      Ok(Ok(outcome)) => {
+        results.push(outcome);
       }
       Ok(Err(e)) => {
         error!(error = %e, "unexpected error in bind task");
@@ -487,19 +649,24 @@ async fn rollback_binds(
   applied_order: &[ObjectHash],
   applied_results: &HashMap<ObjectHash, BindResult>,
   manifest: &Manifest,
-  _config: &ExecuteConfig,
+  config: &ExecuteConfig,
 ) {
   if applied_order.is_empty() {
     return;
   }
 
   info!(count = applied_order.len(), "rolling back applied binds");
+  config.progress.send(ProgressEvent::RollingBack {
+    binds: applied_order.len(),
+  });
 
   // Create an empty resolver for destroy operations
   // (destroy actions typically don't need to reference other completed nodes)
   let empty_builds = HashMap::new();
   let empty_binds = HashMap::new();
-  let resolver = BindCtxResolver::new(&empty_builds, &empty_binds, manifest, "/tmp".to_string());
+  let resolver = BindCtxResolver::new(&empty_builds, &empty_binds, manifest, "/tmp".to_string())
+    .with_action_limits(config.action_limits.clone())
+    .with_transcript(config.transcript.clone());
 
   // Rollback in reverse order
   for hash in applied_order.iter().rev() {
@@ -524,7 +691,7 @@ async fn execute_wave(
   config: &ExecuteConfig,
   completed: &HashMap<ObjectHash, BuildResult>,
   semaphore: std::sync::Arc<Semaphore>,
-) -> Vec<(ObjectHash, Result<BuildResult, ExecuteError>)> {
+) -> Vec<BuildOutcome> {
   use tokio::task::JoinSet;
 
   let mut join_set = JoinSet::new();
@@ -537,17 +704,27 @@ async fn execute_wave(
     let semaphore = semaphore.clone();
 
     join_set.spawn(async move {
-      // Acquire semaphore permit inside the task
-      let _permit = semaphore.acquire().await.unwrap();
-
       let build_def = manifest
         .builds
         .get(&hash)
         .ok_or_else(|| ExecuteError::BuildNotFound(hash.clone()))?;
 
-      let result = crate::build::execute::realize_build(&hash, build_def, &completed, &manifest, &config).await;
+      // Acquire semaphore permits inside the task, weighted by declared CPUs
+      let weight = build_def.resources.unwrap_or_default().weight(config.parallelism);
+      let _permit = semaphore.acquire_many(weight).await.unwrap();
+
+      let (result, timing) = NodeTiming::measure(async {
+        fault::check(
+          config.fail_at.as_ref(),
+          FailPhase::Build,
+          &hash,
+          build_def.id.as_deref(),
+        )?;
+        crate::build::execute::realize_build(&hash, build_def, &completed, &manifest, &config).await
+      })
+      .await;
 
-      Ok::<_, ExecuteError>((hash, result))
+      Ok::<_, ExecuteError>((hash, result, timing))
     });
   }
 
@@ -555,8 +732,8 @@ async fn execute_wave(
 
   while let Some(join_result) = join_set.join_next().await {
     match join_result {
-      Ok(Ok((hash, build_result))) => {
-        results.push((hash, build_result));
+      Ok(Ok(outcome)) => {
+        results.push(outcome);
       }
       Ok(Err(e)) => {
         // This shouldn't happen as we handle errors in the task
@@ -616,13 +793,25 @@ mod tests {
         args: Some(args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     }
   }
 
   fn test_config() -> ExecuteConfig {
-    ExecuteConfig { parallelism: 4 }
+    ExecuteConfig {
+      parallelism: 4,
+      ..Default::default()
+    }
   }
 
   /// Helper to set up a temp store and run a test.
@@ -747,6 +936,33 @@ mod tests {
     });
   }
 
+  #[test]
+  fn execute_builds_starts_longest_expected_first() {
+    with_temp_store(|| async {
+      let build_a = make_build("a", None);
+      let hash_a = build_a.compute_hash().unwrap();
+
+      let build_b = make_build("b", None);
+      let hash_b = build_b.compute_hash().unwrap();
+
+      let mut manifest = Manifest::default();
+      manifest.builds.insert(hash_a.clone(), build_a);
+      manifest.builds.insert(hash_b.clone(), build_b);
+
+      for (slow, fast) in [(&hash_a, &hash_b), (&hash_b, &hash_a)] {
+        let config = ExecuteConfig {
+          parallelism: 1,
+          expected_durations: BTreeMap::from([(slow.clone(), 60_000), (fast.clone(), 10)]),
+          ..Default::default()
+        };
+        let result = execute_builds(&manifest, &config).await.unwrap();
+
+        assert!(result.is_success());
+        assert!(result.build_timings[slow].finished_at_ms <= result.build_timings[fast].started_at_ms);
+      }
+    });
+  }
+
   #[test]
   fn execute_dependent_builds() {
     with_temp_store(|| async {
@@ -784,8 +1000,17 @@ mod tests {
           args: Some(args),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       };
       let hash = build.compute_hash().unwrap();
 
@@ -815,8 +1040,17 @@ mod tests {
           args: Some(args),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       };
       let hash_a = build_a.compute_hash().unwrap();
 
@@ -841,6 +1075,42 @@ mod tests {
     });
   }
 
+  #[test]
+  fn execute_injected_failure_at_hash_prefix() {
+    with_temp_store(|| async {
+      let build_a = make_build("a", None);
+      let hash_a = build_a.compute_hash().unwrap();
+      let build_b = make_build("b", Some(BuildInputs::Build(hash_a.clone())));
+      let hash_b = build_b.compute_hash().unwrap();
+
+      let mut manifest = Manifest::default();
+      manifest.builds.insert(hash_a.clone(), build_a);
+      manifest.builds.insert(hash_b.clone(), build_b);
+
+      let config = ExecuteConfig {
+        fail_at: Some(FailPoint {
+          target: hash_a.0[..8].to_string(),
+          phase: Some(FailPhase::Build),
+        }),
+        ..test_config()
+      };
+      let result = execute_builds(&manifest, &config).await.unwrap();
+
+      let (failed_hash, error) = result.build_failed.as_ref().unwrap();
+      assert_eq!(failed_hash, &hash_a);
+      assert!(matches!(
+        error,
+        ExecuteError::Injected {
+          phase: FailPhase::Build,
+          ..
+        }
+      ));
+      assert_eq!(result.build_skipped[&hash_b], FailedDependency::Build(hash_a));
+
+      assert!(result.realized.is_empty());
+    });
+  }
+
   #[test]
   fn execute_diamond_dependency() {
     with_temp_store(|| async {
@@ -895,11 +1165,23 @@ mod tests {
         args: Some(args),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       update_actions: None,
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     }
   }
 
@@ -971,12 +1253,21 @@ mod tests {
           args: Some(echo_args),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         outputs: Some(
           [("bin".to_string(), JsonValue::String("$${{out}}/bin".to_string()))]
             .into_iter()
             .collect(),
         ),
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       };
       let build_hash = build.compute_hash().unwrap();
 
@@ -992,11 +1283,23 @@ mod tests {
           args: Some(bind_args),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         update_actions: None,
         destroy_actions: vec![],
         check_actions: None,
         check_outputs: None,
+        backup: None,
+        fallback_path: None,
+        tags: Vec::new(),
+        group: None,
+        repair: None,
+        serialize: None,
+        update_strategy: Default::default(),
+        phase: Default::default(),
       };
       let bind_hash = bind.compute_hash().unwrap();
 
@@ -1011,6 +1314,11 @@ mod tests {
       assert_eq!(result.realized.len(), 1);
       assert_eq!(result.applied.len(), 1);
 
+      // Every executed node records its timing
+      let build_timing = result.build_timings[&build_hash];
+      let bind_timing = result.bind_timings[&bind_hash];
+      assert!(bind_timing.started_at_ms >= build_timing.finished_at_ms);
+
       // Verify the build output was resolved
       let build_result = &result.realized[&build_hash];
       assert!(build_result.outputs.contains_key("bin"));
@@ -1019,6 +1327,42 @@ mod tests {
     });
   }
 
+  #[test]
+  fn manifest_bind_resolves_existing_bind_outputs() {
+    // Bind B refers to an output of bind A, which was applied earlier and is
+    // not part of the manifest
+    with_temp_store(|| async {
+      let bind_a = make_bind("bind1", "echo step_a", None);
+      let hash_a = bind_a.compute_hash().unwrap();
+      let existing = HashMap::from([(
+        hash_a.clone(),
+        BindResult {
+          outputs: HashMap::from([("path".to_string(), JsonValue::String("/etc/app.conf".to_string()))]),
+          action_results: vec![],
+        },
+      )]);
+
+      let mut bind_b = make_bind("bind2", "echo step_b", Some(BindInputsDef::Bind(hash_a.clone())));
+      bind_b.outputs = Some(BTreeMap::from([(
+        "conf".to_string(),
+        JsonValue::String(format!("$${{{{bind:{}:path}}}}", hash_a.0)),
+      )]));
+      let hash_b = bind_b.compute_hash().unwrap();
+
+      let mut manifest = Manifest::default();
+      manifest.bindings.insert(hash_b.clone(), bind_b);
+
+      let config = test_config();
+      let result = execute_manifest_with_binds(&manifest, &config, &existing)
+        .await
+        .unwrap();
+
+      assert!(result.is_success());
+      assert_eq!(result.applied.len(), 1);
+      assert_eq!(result.applied[&hash_b].outputs["conf"], "/etc/app.conf");
+    });
+  }
+
   #[test]
   fn manifest_bind_failure_rollback() {
     // Bind A succeeds, Bind B fails -> Bind A should be rolled back (destroyed)
@@ -1049,6 +1393,10 @@ mod tests {
           args: Some(touch_args),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         update_actions: None,
         destroy_actions: vec![Action::Exec(ExecOpts {
@@ -1056,9 +1404,21 @@ mod tests {
           args: Some(rm_args),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         check_actions: None,
         check_outputs: None,
+        backup: None,
+        fallback_path: None,
+        tags: Vec::new(),
+        group: None,
+        repair: None,
+        serialize: None,
+        update_strategy: Default::default(),
+        phase: Default::default(),
       };
       let hash_a = bind_a.compute_hash().unwrap();
 
@@ -1073,11 +1433,23 @@ mod tests {
           args: Some(exit_args),
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         update_actions: None,
         destroy_actions: vec![],
         check_actions: None,
         check_outputs: None,
+        backup: None,
+        fallback_path: None,
+        tags: Vec::new(),
+        group: None,
+        repair: None,
+        serialize: None,
+        update_strategy: Default::default(),
+        phase: Default::default(),
       };
       let hash_b = bind_b.compute_hash().unwrap();
 
@@ -1131,8 +1503,17 @@ mod tests {
           args: None,
           env: None,
           cwd: None,
+          creates: None,
+          unless: None,
+          shell: None,
+          run_as: None,
         })],
         outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       };
       let build_hash = build.compute_hash().unwrap();
 
@@ -1237,4 +1618,94 @@ mod tests {
       assert_eq!(result.applied.len(), 2);
     });
   }
+
+  #[test]
+  #[cfg(unix)]
+  fn manifest_serialize_group_runs_binds_one_at_a_time() {
+    // Independent binds of one group must not overlap: each holds a lock
+    // directory while it runs, and mkdir fails if the other already holds it
+    with_temp_store(|| async {
+      let temp_dir = TempDir::new().unwrap();
+      let lock = temp_dir.path().join("lock");
+      let script = format!("mkdir {0} && sleep 0.2 && rmdir {0}", lock.display());
+
+      let mut manifest = Manifest::default();
+      for id in ["pkg-a", "pkg-b", "pkg-c"] {
+        let mut bind = make_bind(id, &script, None);
+        bind.serialize = Some("pkg".to_string());
+        manifest.bindings.insert(bind.compute_hash().unwrap(), bind);
+      }
+
+      let config = test_config();
+      let result = execute_manifest(&manifest, &config).await.unwrap();
+
+      assert!(result.is_success());
+      assert_eq!(result.applied.len(), 3);
+    });
+  }
+
+  #[test]
+  fn manifest_reports_progress() {
+    with_temp_store(|| async {
+      let build = make_build("app", None);
+      let build_hash = build.compute_hash().unwrap();
+
+      let bind = make_bind("bind1", "echo linking", Some(BindInputsDef::Build(build_hash.clone())));
+      let bind_hash = bind.compute_hash().unwrap();
+
+      let mut manifest = Manifest::default();
+      manifest.builds.insert(build_hash.clone(), build);
+      manifest.bindings.insert(bind_hash.clone(), bind);
+
+      let (progress, mut events) = progress::progress_channel();
+      let config = ExecuteConfig {
+        progress,
+        ..test_config()
+      };
+      let result = execute_manifest(&manifest, &config).await.unwrap();
+      assert!(result.is_success());
+      drop(config);
+
+      let mut received = Vec::new();
+      while let Some(event) = events.recv().await {
+        received.push(event);
+      }
+
+      assert_eq!(
+        received[0],
+        ProgressEvent::Started {
+          waves: 2,
+          builds: 1,
+          binds: 1
+        }
+      );
+      assert_eq!(received[1], ProgressEvent::WaveStarted { wave: 0, nodes: 1 });
+      assert!(matches!(
+        &received[2],
+        ProgressEvent::NodeStarted { kind: NodeKind::Build, hash, .. } if *hash == build_hash
+      ));
+      assert!(matches!(
+        &received[3],
+        ProgressEvent::NodeFinished {
+          kind: NodeKind::Build,
+          outcome: NodeOutcome::Succeeded,
+          ..
+        }
+      ));
+      assert_eq!(received[4], ProgressEvent::WaveStarted { wave: 1, nodes: 1 });
+      assert!(matches!(
+        &received[5],
+        ProgressEvent::NodeStarted { kind: NodeKind::Bind, id: Some(id), .. } if id == "bind1"
+      ));
+      assert!(matches!(
+        &received[6],
+        ProgressEvent::NodeFinished {
+          kind: NodeKind::Bind,
+          outcome: NodeOutcome::Succeeded,
+          ..
+        }
+      ));
+      assert_eq!(received.len(), 7);
+    });
+  }
 }
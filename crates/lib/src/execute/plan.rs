@@ -0,0 +1,219 @@
+//! Plan computation: what an apply of a config would change, without applying it.
+//!
+//! `sys plan`, [`apply`](super::apply::apply), the daemon and the
+//! [`api`](crate::api) facade all compute their diff here:
+//!
+//! 1. Evaluate the config (unless a manifest is given)
+//! 2. Load the current snapshot
+//! 3. Diff the desired manifest against it, probing the store for cached builds
+//! 4. Optionally check the binds left unchanged for drift
+//! 5. List the host paths the binds to create or update will touch
+//! 6. List the builds to realize that failed recently (see [`super::failures`])
+//!
+//! A config evaluated for another platform (`PlanOptions::platform`) is
+//! diffed against an empty state instead: this host's snapshot, drift and
+//! failed builds say nothing about the machine it is for.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::eval::{EvalOptions, evaluate_config};
+use crate::lua::sandbox::UntrustedInputs;
+use crate::manifest::Manifest;
+use crate::platform::Platform;
+use crate::platform::paths::store_dir;
+use crate::snapshot::{GroupChanges, Snapshot, SnapshotError, SnapshotStore, StateDiff, compute_diff};
+use crate::util::hash::Hashable;
+
+use super::apply::{ApplyError, check_unchanged_binds};
+use super::failures::{PreviousFailure, recent_failures};
+use super::touches::{BindTouches, plan_touches};
+use super::types::{DriftResult, ExecuteConfig};
+
+/// Options for the plan operation.
+#[derive(Debug, Clone, Default)]
+pub struct PlanOptions {
+  /// Execution configuration used by drift checks, and whose failure TTL
+  /// decides which failed builds are reported.
+  pub execute: ExecuteConfig,
+
+  /// Run drift checks on binds that would be left unchanged.
+  pub check_drift: bool,
+
+  /// Allow impure Lua libs (io, os). Breaks determinism.
+  pub impure: bool,
+
+  /// Replacement URLs for root inputs (name -> URL).
+  pub input_overrides: BTreeMap<String, String>,
+
+  /// How to evaluate inputs that aren't declared `trusted = true`.
+  pub untrusted_inputs: UntrustedInputs,
+
+  /// Host vars file to load instead of `host_vars/<hostname>.lua`.
+  pub vars_file: Option<PathBuf>,
+
+  /// Platform to evaluate for instead of this host's.
+  pub platform: Option<Platform>,
+
+  /// Pre-evaluated manifest for the config. When set, the config is not
+  /// evaluated again (used by the daemon's evaluation cache).
+  pub manifest: Option<Manifest>,
+}
+
+/// Changes an apply of the planned config would make.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanReport {
+  /// Hash identifying the desired manifest.
+  pub manifest_hash: String,
+
+  /// The desired manifest.
+  pub manifest: Manifest,
+
+  /// Difference between the current snapshot and the desired manifest.
+  pub diff: StateDiff,
+
+  /// Drift check results for unchanged binds (empty unless requested).
+  pub drift_results: Vec<DriftResult>,
+
+  /// Bind changes per bind `group`.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub groups: BTreeMap<String, GroupChanges>,
+
+  /// Host paths the binds to create or update will touch.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub touches: Vec<BindTouches>,
+
+  /// Builds to realize that failed recently; an apply fails on them without
+  /// running them unless it retries failed builds.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub previously_failed: Vec<PreviousFailure>,
+}
+
+/// Compute what applying a config would change.
+pub async fn plan(config_path: &Path, options: &PlanOptions) -> Result<PlanReport, ApplyError> {
+  if options.manifest.is_none() && !config_path.exists() {
+    return Err(ApplyError::ConfigNotFound(config_path.to_path_buf()));
+  }
+
+  let eval_options = EvalOptions {
+    impure: options.impure,
+    input_overrides: options.input_overrides.clone(),
+    untrusted_inputs: options.untrusted_inputs,
+    vars_file: options.vars_file.clone(),
+    platform: options.platform,
+  };
+  let manifest = match &options.manifest {
+    Some(manifest) => manifest.clone(),
+    None => evaluate_config(config_path, &eval_options)?,
+  };
+
+  let foreign = eval_options.is_foreign();
+  let (current, diff) = if foreign {
+    (None, compute_diff(&manifest, None, &store_dir()))
+  } else {
+    diff_against_current(&manifest, &SnapshotStore::default_store())?
+  };
+  let groups = diff.group_changes(&manifest, current.as_ref().map(|s| &s.manifest));
+
+  let drift_results = if options.check_drift && !foreign {
+    check_unchanged_binds(&diff.binds_unchanged, &manifest, &options.execute).await?
+  } else {
+    Vec::new()
+  };
+
+  let touches = plan_touches(&manifest, &diff);
+  let previously_failed = if foreign {
+    Vec::new()
+  } else {
+    recent_failures(&diff.builds_to_realize, options.execute.failure_ttl())
+  };
+
+  Ok(PlanReport {
+    manifest_hash: manifest.compute_hash()?.0,
+    manifest,
+    diff,
+    drift_results,
+    groups,
+    touches,
+    previously_failed,
+  })
+}
+
+/// Load the current snapshot and diff `desired` against it.
+///
+/// Returns the snapshot too, for callers that go on to change the current state.
+pub fn diff_against_current(
+  desired: &Manifest,
+  snapshot_store: &SnapshotStore,
+) -> Result<(Option<Snapshot>, StateDiff), SnapshotError> {
+  let current = snapshot_store.load_current()?;
+  debug!(has_current = current.is_some(), "loaded current state");
+
+  let diff = compute_diff(desired, current.as_ref().map(|s| &s.manifest), &store_dir());
+  debug!(
+    builds_to_realize = diff.builds_to_realize.len(),
+    builds_cached = diff.builds_cached.len(),
+    binds_to_apply = diff.binds_to_apply.len(),
+    binds_to_update = diff.binds_to_update.len(),
+    binds_to_destroy = diff.binds_to_destroy.len(),
+    binds_unchanged = diff.binds_unchanged.len(),
+    "diff computed"
+  );
+
+  Ok((current, diff))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serial_test::serial;
+  use tempfile::TempDir;
+
+  #[test]
+  #[serial]
+  fn plan_reports_new_binds() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = temp_dir.path().join("init.lua");
+    std::fs::write(
+      &config,
+      r#"
+        return {
+          setup = function()
+            sys.bind({
+              id = "greeting",
+              create = function(_, ctx) ctx:exec({ bin = "true" }) end,
+              destroy = function(_, ctx) ctx:exec({ bin = "true" }) end,
+            })
+          end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    temp_env::with_vars(
+      [
+        ("SYSLUA_STORE", Some(temp_dir.path().join("store").to_str().unwrap())),
+        ("XDG_DATA_HOME", Some(temp_dir.path().join("data").to_str().unwrap())),
+      ],
+      || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let report = rt.block_on(plan(&config, &PlanOptions::default())).unwrap();
+
+        assert_eq!(report.diff.binds_to_apply.len(), 1);
+        assert_eq!(report.manifest.bindings.len(), 1);
+        assert_eq!(report.manifest_hash, report.manifest.compute_hash().unwrap().0);
+        assert!(report.drift_results.is_empty());
+      },
+    );
+  }
+
+  #[test]
+  fn plan_missing_config_is_not_found() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let result = rt.block_on(plan(Path::new("/nonexistent/init.lua"), &PlanOptions::default()));
+    assert!(matches!(result, Err(ApplyError::ConfigNotFound(_))));
+  }
+}
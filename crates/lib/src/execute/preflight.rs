@@ -0,0 +1,334 @@
+//! Checks run before an apply changes anything.
+//!
+//! An apply that runs out of disk space or hits an unwritable directory
+//! halfway has to roll back what it already did. Before any bind is destroyed
+//! or created, [`preflight`] checks that:
+//!
+//! - the store has room for the builds to realize, assuming each is as large
+//!   as the last time it was realized ([`ExecutionHistory`](super::history::ExecutionHistory));
+//!   builds that never ran can't be estimated and are only counted,
+//! - the download cache has room for the `fetch_url` downloads that aren't
+//!   cached yet (both are checked together when they share a filesystem),
+//! - the directories of the host paths that binds will touch
+//!   ([`plan_touches`]) are writable. Paths only known during the apply are
+//!   not checked.
+//!
+//! Every problem is collected in one [`PreflightReport`], so they can all be
+//! fixed before the next attempt. `sys apply --skip-preflight` turns the
+//! checks off.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::action::Action;
+use crate::action::actions::download_cache::{cache_key, downloads_dir};
+use crate::manifest::Manifest;
+use crate::platform::disk::{available_space, is_writable, same_filesystem};
+use crate::platform::paths::{home_dir, store_dir};
+use crate::snapshot::StateDiff;
+use crate::util::hash::ObjectHash;
+
+use super::history::BuildSize;
+use super::touches::plan_touches;
+
+/// Outcome of the preflight checks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreflightReport {
+  /// Estimated bytes the builds to realize add to the store.
+  pub store_bytes: u64,
+  /// Estimated bytes of downloads that aren't in the download cache yet.
+  pub download_bytes: u64,
+  /// Builds to realize without a size estimate.
+  pub unestimated_builds: usize,
+  /// Everything that would make the apply fail; empty if it can go ahead.
+  pub problems: Vec<PreflightProblem>,
+}
+
+/// A reason the apply would fail partway.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PreflightProblem {
+  /// The filesystem holding `path` is too full.
+  DiskSpace {
+    path: PathBuf,
+    required_bytes: u64,
+    available_bytes: u64,
+  },
+  /// Binds would write into `dir`, which isn't writable.
+  NotWritable { dir: PathBuf, binds: Vec<String> },
+}
+
+impl PreflightReport {
+  pub fn is_ok(&self) -> bool {
+    self.problems.is_empty()
+  }
+}
+
+impl fmt::Display for PreflightProblem {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PreflightProblem::DiskSpace {
+        path,
+        required_bytes,
+        available_bytes,
+      } => write!(
+        f,
+        "not enough space for {}: about {} needed, {} available",
+        path.display(),
+        format_mib(*required_bytes),
+        format_mib(*available_bytes)
+      ),
+      PreflightProblem::NotWritable { dir, binds } => write!(
+        f,
+        "{} is not writable (needed by {})",
+        dir.display(),
+        binds
+          .iter()
+          .map(|bind| format!("'{}'", bind))
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+    }
+  }
+}
+
+impl fmt::Display for PreflightReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} problem(s)", self.problems.len())?;
+    for problem in &self.problems {
+      write!(f, "\n  - {}", problem)?;
+    }
+    Ok(())
+  }
+}
+
+fn format_mib(bytes: u64) -> String {
+  format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Check that the apply of `diff` can run to completion.
+///
+/// `execution` holds the builds the apply will realize, `desired` the full
+/// evaluated manifest and `sizes` the expected size of builds realized before.
+pub fn preflight(
+  desired: &Manifest,
+  execution: &Manifest,
+  diff: &StateDiff,
+  sizes: &HashMap<ObjectHash, BuildSize>,
+) -> PreflightReport {
+  let mut report = PreflightReport::default();
+
+  for (hash, def) in &execution.builds {
+    let Some(size) = sizes.get(hash) else {
+      report.unestimated_builds += 1;
+      continue;
+    };
+    report.store_bytes += size.store_bytes;
+    let uncached_download = def.create_actions.iter().any(|action| match action {
+      Action::FetchUrl { url, sha256, .. } => !downloads_dir().join(cache_key(url, sha256)).exists(),
+      _ => false,
+    });
+    if uncached_download {
+      report.download_bytes += size.download_bytes;
+    }
+  }
+
+  check_space(&store_dir(), &downloads_dir(), &mut report);
+  report.problems.extend(unwritable_dirs(desired, diff));
+  report
+}
+
+/// Compare the estimates with the free space of the store and download cache.
+fn check_space(store: &Path, downloads: &Path, report: &mut PreflightReport) {
+  let needs: Vec<(&Path, u64)> = if same_filesystem(store, downloads) {
+    vec![(store, report.store_bytes + report.download_bytes)]
+  } else {
+    vec![(store, report.store_bytes), (downloads, report.download_bytes)]
+  };
+
+  for (path, required_bytes) in needs {
+    if required_bytes == 0 {
+      continue;
+    }
+    // A filesystem that can't be queried is left for the apply to find out about
+    if let Ok(available_bytes) = available_space(path)
+      && available_bytes < required_bytes
+    {
+      report.problems.push(PreflightProblem::DiskSpace {
+        path: path.to_path_buf(),
+        required_bytes,
+        available_bytes,
+      });
+    }
+  }
+}
+
+/// Parent directories of touched host paths that aren't writable, with the
+/// binds touching them.
+fn unwritable_dirs(desired: &Manifest, diff: &StateDiff) -> Vec<PreflightProblem> {
+  let mut dirs: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+  for touches in plan_touches(desired, diff) {
+    let bind = touches.id.clone().unwrap_or_else(|| touches.hash.0.clone());
+    for touch in touches.paths.iter().filter(|touch| !touch.dynamic) {
+      let path = match touch.path.strip_prefix("~/") {
+        Some(rest) => home_dir().join(rest),
+        None => PathBuf::from(&touch.path),
+      };
+      let Some(dir) = path
+        .parent()
+        .filter(|dir| path.is_absolute() && !dir.as_os_str().is_empty())
+      else {
+        continue;
+      };
+      let binds = dirs.entry(dir.to_path_buf()).or_default();
+      if !binds.contains(&bind) {
+        binds.push(bind.clone());
+      }
+    }
+  }
+
+  dirs
+    .into_iter()
+    .filter(|(dir, _)| !is_writable(dir))
+    .map(|(dir, binds)| PreflightProblem::NotWritable { dir, binds })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::action::actions::config_section::{ConfigFormat, ConfigSectionOpts, SectionState};
+  use crate::bind::BindDef;
+  use crate::build::BuildDef;
+  use serial_test::serial;
+
+  fn build_def(url: &str) -> BuildDef {
+    BuildDef {
+      id: None,
+      inputs: None,
+      create_actions: vec![Action::FetchUrl {
+        url: url.to_string(),
+        sha256: "abc".to_string(),
+        unpack: false,
+      }],
+      outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
+    }
+  }
+
+  fn bind_def(id: &str, path: &Path) -> BindDef {
+    BindDef {
+      id: Some(id.to_string()),
+      inputs: None,
+      outputs: None,
+      create_actions: vec![Action::ConfigSection(ConfigSectionOpts {
+        format: ConfigFormat::Git,
+        path: path.to_string_lossy().to_string(),
+        section: "app".to_string(),
+        entries: BTreeMap::new(),
+        state: SectionState::default(),
+      })],
+      update_actions: None,
+      destroy_actions: vec![],
+      check_actions: None,
+      check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
+    }
+  }
+
+  #[test]
+  #[serial]
+  fn estimates_builds_with_a_recorded_size() {
+    let temp = tempfile::tempdir().unwrap();
+    temp_env::with_vars(
+      [
+        ("SYSLUA_STORE", Some(temp.path().join("store"))),
+        ("XDG_CACHE_HOME", Some(temp.path().join("cache"))),
+      ],
+      || {
+        let known = ObjectHash("known".to_string());
+        let unknown = ObjectHash("unknown".to_string());
+        let mut execution = Manifest::default();
+        execution
+          .builds
+          .insert(known.clone(), build_def("https://example.com/a.tar"));
+        execution.builds.insert(unknown, build_def("https://example.com/b.tar"));
+        let sizes = HashMap::from([(
+          known,
+          BuildSize {
+            store_bytes: 1000,
+            download_bytes: 400,
+          },
+        )]);
+
+        let report = preflight(&Manifest::default(), &execution, &StateDiff::default(), &sizes);
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.store_bytes, 1000);
+        assert_eq!(report.download_bytes, 400);
+        assert_eq!(report.unestimated_builds, 1);
+
+        // More than any disk holds
+        let huge = HashMap::from([(
+          ObjectHash("known".to_string()),
+          BuildSize {
+            store_bytes: u64::MAX / 2,
+            download_bytes: 0,
+          },
+        )]);
+        let report = preflight(&Manifest::default(), &execution, &StateDiff::default(), &huge);
+        assert!(matches!(report.problems[..], [PreflightProblem::DiskSpace { .. }]));
+      },
+    );
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn reports_unwritable_bind_directories() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Root can write anywhere but read-only mounts
+    if rustix::process::geteuid().is_root() {
+      return;
+    }
+    let temp = tempfile::tempdir().unwrap();
+    let locked = temp.path().join("locked");
+    std::fs::create_dir(&locked).unwrap();
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+    let mut desired = Manifest::default();
+    let mut diff = StateDiff::default();
+    for (id, path) in [
+      ("a", locked.join("a.conf")),
+      ("b", locked.join("b.conf")),
+      ("c", temp.path().join("c.conf")),
+    ] {
+      let hash = ObjectHash(id.to_string());
+      desired.bindings.insert(hash.clone(), bind_def(id, &path));
+      diff.binds_to_apply.push(hash);
+    }
+
+    let problems = unwritable_dirs(&desired, &diff);
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(
+      problems,
+      vec![PreflightProblem::NotWritable {
+        dir: locked,
+        binds: vec!["a".to_string(), "b".to_string()],
+      }]
+    );
+  }
+}
@@ -0,0 +1,152 @@
+//! Progress events emitted while a manifest executes.
+//!
+//! Frontends pass a [`ProgressSender`] in [`ExecuteConfig`](super::ExecuteConfig)
+//! and render the events from the matching receiver, e.g. as progress bars.
+//! Senders [joined](ProgressSender::join) into one deliver every event to
+//! each receiver, so a display and [metrics](super::metrics) can both follow
+//! an apply. Without a receiver, sending is a no-op.
+//!
+//! Events serialize to JSON, so other processes can follow an apply through
+//! its [live file](super::live).
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+
+use super::live::LiveMessage;
+use crate::util::hash::ObjectHash;
+
+/// Whether an event concerns a build or a bind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+  Build,
+  Bind,
+}
+
+/// How a build or bind ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeOutcome {
+  Succeeded,
+  Failed,
+  /// Not run because a dependency failed.
+  Skipped,
+}
+
+/// A step of manifest execution.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProgressEvent {
+  /// Execution started with this many waves, builds and binds.
+  Started { waves: usize, builds: usize, binds: usize },
+  /// Wave `wave` (0-based) started with this many builds and binds.
+  WaveStarted { wave: usize, nodes: usize },
+  /// A build or bind got its parallelism slot and started running.
+  NodeStarted {
+    kind: NodeKind,
+    hash: ObjectHash,
+    id: Option<String>,
+  },
+  /// A build or bind finished, failed or was skipped.
+  NodeFinished {
+    kind: NodeKind,
+    hash: ObjectHash,
+    outcome: NodeOutcome,
+    duration: Duration,
+  },
+  /// A build was found in the store and won't be realized again.
+  ///
+  /// Sent before the build's [`NodeFinished`](Self::NodeFinished).
+  Cached { hash: ObjectHash },
+  /// A build's `fetch_url` received `received` of `total` bytes (if known).
+  ///
+  /// Sent a few times per second while downloading, and once at the end.
+  Download {
+    hash: ObjectHash,
+    url: String,
+    received: u64,
+    total: Option<u64>,
+  },
+  /// A failure triggered the rollback of this many applied binds.
+  RollingBack { binds: usize },
+}
+
+/// Sending half of progress channels; the default sends nowhere.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressSender(Vec<Sink>);
+
+/// A receiver of a [`ProgressSender`].
+#[derive(Debug, Clone)]
+enum Sink {
+  Events(UnboundedSender<ProgressEvent>),
+  /// The writer of the live file, which also takes its finish message.
+  Live(UnboundedSender<LiveMessage>),
+}
+
+impl ProgressSender {
+  /// Send `event` to every receiver, ignoring those that went away.
+  pub fn send(&self, event: ProgressEvent) {
+    for sink in &self.0 {
+      match sink {
+        Sink::Events(tx) => {
+          let _ = tx.send(event.clone());
+        }
+        Sink::Live(tx) => {
+          let _ = tx.send(LiveMessage::Event(event.clone()));
+        }
+      }
+    }
+  }
+
+  /// A sender delivering every event to the receivers of both senders.
+  pub fn join(mut self, other: ProgressSender) -> Self {
+    self.0.extend(other.0);
+    self
+  }
+
+  /// A sender delivering events to the live file writer at `tx`.
+  pub(super) fn live(tx: UnboundedSender<LiveMessage>) -> Self {
+    Self(vec![Sink::Live(tx)])
+  }
+}
+
+/// Create a progress channel.
+pub fn progress_channel() -> (ProgressSender, UnboundedReceiver<ProgressEvent>) {
+  let (tx, rx) = unbounded_channel();
+  (ProgressSender(vec![Sink::Events(tx)]), rx)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn default_sender_discards_events() {
+    ProgressSender::default().send(ProgressEvent::RollingBack { binds: 1 });
+  }
+
+  #[test]
+  fn channel_delivers_events_in_order() {
+    let (tx, mut rx) = progress_channel();
+    tx.send(ProgressEvent::WaveStarted { wave: 0, nodes: 2 });
+    tx.send(ProgressEvent::RollingBack { binds: 1 });
+    drop(tx);
+
+    assert_eq!(rx.try_recv().unwrap(), ProgressEvent::WaveStarted { wave: 0, nodes: 2 });
+    assert_eq!(rx.try_recv().unwrap(), ProgressEvent::RollingBack { binds: 1 });
+    assert!(rx.try_recv().is_err());
+  }
+
+  #[test]
+  fn joined_senders_deliver_to_every_receiver() {
+    let (display, mut first) = progress_channel();
+    let (metrics, mut second) = progress_channel();
+    let tx = ProgressSender::default().join(display).join(metrics);
+    tx.send(ProgressEvent::RollingBack { binds: 2 });
+
+    assert_eq!(first.try_recv().unwrap(), ProgressEvent::RollingBack { binds: 2 });
+    assert_eq!(second.try_recv().unwrap(), ProgressEvent::RollingBack { binds: 2 });
+  }
+}
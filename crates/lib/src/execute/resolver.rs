@@ -4,7 +4,7 @@
 //! - `BuildCtxResolver` for build execution (builds can only reference other builds)
 //! - `BindCtxResolver` for bind execution (binds can reference builds and other binds)
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde_json::Value as JsonValue;
 
@@ -13,12 +13,15 @@ use crate::manifest::Manifest;
 use crate::placeholder::{PlaceholderError, Resolver};
 use crate::util::hash::ObjectHash;
 
+use super::limits::ActionLimits;
+use super::transcript::TranscriptRecorder;
 use super::types::{BindResult, BuildResult};
 
 /// Resolver for placeholders during build execution.
 ///
 /// Builds can only reference other builds, not binds. This resolver supports:
 /// - `$${{action:N}}` - stdout of action at index N
+/// - `$${{action:N:NAME}}` - named sub-output of action N
 /// - `$${{build:HASH:OUTPUT}}` - output from a completed build
 /// - `$${{out}}` - the current build's output directory
 /// - `$${{env:NAME}}` - environment variable
@@ -27,6 +30,7 @@ use super::types::{BindResult, BuildResult};
 /// depend on binds.
 pub struct BuildCtxResolver<'a> {
   action_results: Vec<String>,
+  action_outputs: Vec<BTreeMap<String, String>>,
   completed_builds: &'a HashMap<ObjectHash, BuildResult>,
   manifest: &'a Manifest,
   out_dir: String,
@@ -36,6 +40,7 @@ impl<'a> BuildCtxResolver<'a> {
   pub fn new(completed_builds: &'a HashMap<ObjectHash, BuildResult>, manifest: &'a Manifest, out_dir: String) -> Self {
     Self {
       action_results: Vec::new(),
+      action_outputs: Vec::new(),
       completed_builds,
       manifest,
       out_dir,
@@ -43,7 +48,13 @@ impl<'a> BuildCtxResolver<'a> {
   }
 
   pub fn push_action_result(&mut self, result: String) {
+    self.push_action(result, BTreeMap::new());
+  }
+
+  /// Record an action's output along with its named sub-outputs.
+  pub fn push_action(&mut self, result: String, outputs: BTreeMap<String, String>) {
     self.action_results.push(result);
+    self.action_outputs.push(outputs);
   }
 
   pub fn action_count(&self) -> usize {
@@ -60,6 +71,10 @@ impl Resolver for BuildCtxResolver<'_> {
       .ok_or(PlaceholderError::UnresolvedAction(index))
   }
 
+  fn resolve_action_output(&self, index: usize, output: &str) -> Result<&str, PlaceholderError> {
+    resolve_named_output(&self.action_outputs, index, output)
+  }
+
   fn resolve_build(&self, hash: &str, output: &str) -> Result<&str, PlaceholderError> {
     resolve_build_output(hash, output, self.completed_builds, self.manifest)
   }
@@ -81,23 +96,48 @@ impl Resolver for BuildCtxResolver<'_> {
   }
 }
 
+/// Look up the named sub-output `output` of the action at `index`.
+fn resolve_named_output<'a>(
+  action_outputs: &'a [BTreeMap<String, String>],
+  index: usize,
+  output: &str,
+) -> Result<&'a str, PlaceholderError> {
+  action_outputs
+    .get(index)
+    .and_then(|outputs| outputs.get(output))
+    .map(|s| s.as_str())
+    .ok_or_else(|| PlaceholderError::UnresolvedActionOutput {
+      index,
+      output: output.to_string(),
+    })
+}
+
 /// Resolver for placeholders during bind execution.
 ///
 /// Binds can reference both builds and other binds. This resolver supports:
 /// - `$${{action:N}}` - stdout of action at index N
+/// - `$${{action:N:NAME}}` - named sub-output of action N
 /// - `$${{build:HASH:OUTPUT}}` - output from a completed build
 /// - `$${{bind:HASH:OUTPUT}}` - output from a completed bind
 /// - `$${{out}}` - the current bind's output directory
 /// - `$${{env:NAME}}` - environment variable
+/// - `$${{prev:OUTPUT}}` - previously recorded output, once set with `with_previous()`
+///
+/// Its actions are limited per type once set with `with_action_limits()`, and
+/// their commands recorded once set with `with_transcript()`.
 ///
 /// Use `with_out_dir()` to create child resolvers for bind actions that need
 /// a different output directory (e.g., a temporary working directory).
 pub struct BindCtxResolver<'a> {
   action_results: Vec<String>,
+  action_outputs: Vec<BTreeMap<String, String>>,
   completed_builds: &'a HashMap<ObjectHash, BuildResult>,
   completed_binds: &'a HashMap<ObjectHash, BindResult>,
   manifest: &'a Manifest,
   out_dir: String,
+  previous: Option<&'a HashMap<String, JsonValue>>,
+  action_limits: ActionLimits,
+  transcript: TranscriptRecorder,
 }
 
 impl<'a> BindCtxResolver<'a> {
@@ -109,15 +149,56 @@ impl<'a> BindCtxResolver<'a> {
   ) -> Self {
     Self {
       action_results: Vec::new(),
+      action_outputs: Vec::new(),
       completed_builds,
       completed_binds,
       manifest,
       out_dir,
+      previous: None,
+      action_limits: ActionLimits::unlimited(),
+      transcript: TranscriptRecorder::default(),
     }
   }
 
+  /// Resolve `$${{prev:OUTPUT}}` from `outputs`, those recorded by the
+  /// previous apply of the bind being updated.
+  pub fn with_previous(mut self, outputs: &'a HashMap<String, JsonValue>) -> Self {
+    self.previous = Some(outputs);
+    self
+  }
+
+  /// Limit the bind's actions with `limits`, shared with the rest of the
+  /// apply. Unlimited otherwise.
+  pub fn with_action_limits(mut self, limits: ActionLimits) -> Self {
+    self.action_limits = limits;
+    self
+  }
+
+  /// The concurrency limits of the bind's actions.
+  pub fn action_limits(&self) -> &ActionLimits {
+    &self.action_limits
+  }
+
+  /// Record the commands of the bind's actions with `recorder`. Not recorded
+  /// otherwise.
+  pub fn with_transcript(mut self, recorder: TranscriptRecorder) -> Self {
+    self.transcript = recorder;
+    self
+  }
+
+  /// Where the commands of the bind's actions are recorded.
+  pub fn transcript(&self) -> &TranscriptRecorder {
+    &self.transcript
+  }
+
   pub fn push_action_result(&mut self, result: String) {
+    self.push_action(result, BTreeMap::new());
+  }
+
+  /// Record an action's output along with its named sub-outputs.
+  pub fn push_action(&mut self, result: String, outputs: BTreeMap<String, String>) {
     self.action_results.push(result);
+    self.action_outputs.push(outputs);
   }
 
   #[allow(dead_code)]
@@ -133,10 +214,14 @@ impl<'a> BindCtxResolver<'a> {
   pub fn with_out_dir(&self, out_dir: String) -> BindCtxResolver<'a> {
     BindCtxResolver {
       action_results: Vec::new(),
+      action_outputs: Vec::new(),
       completed_builds: self.completed_builds,
       completed_binds: self.completed_binds,
       manifest: self.manifest,
       out_dir,
+      previous: self.previous,
+      action_limits: self.action_limits.clone(),
+      transcript: self.transcript.clone(),
     }
   }
 }
@@ -150,13 +235,17 @@ impl Resolver for BindCtxResolver<'_> {
       .ok_or(PlaceholderError::UnresolvedAction(index))
   }
 
+  fn resolve_action_output(&self, index: usize, output: &str) -> Result<&str, PlaceholderError> {
+    resolve_named_output(&self.action_outputs, index, output)
+  }
+
   fn resolve_build(&self, hash: &str, output: &str) -> Result<&str, PlaceholderError> {
     resolve_build_output(hash, output, self.completed_builds, self.manifest)
   }
 
   fn resolve_bind(&self, hash: &str, output: &str) -> Result<&str, PlaceholderError> {
     // Find a completed bind with matching hash prefix
-    let matching_hash = self.completed_binds.keys().find(|h| h.0.starts_with(hash)).cloned();
+    let matching_hash = self.completed_binds.keys().find(|h| h.matches(hash)).cloned();
 
     if let Some(full_hash) = matching_hash
       && let Some(result) = self.completed_binds.get(&full_hash)
@@ -187,6 +276,13 @@ impl Resolver for BindCtxResolver<'_> {
   fn resolve_env(&self, name: &str) -> Result<String, PlaceholderError> {
     resolve_env_var(name)
   }
+
+  fn resolve_prev(&self, output: &str) -> Result<&str, PlaceholderError> {
+    match self.previous.and_then(|outputs| outputs.get(output)) {
+      Some(JsonValue::String(value)) => Ok(value.as_str()),
+      _ => Err(PlaceholderError::UnresolvedPrev(output.to_string())),
+    }
+  }
 }
 
 /// Shared logic for resolving environment variables.
@@ -202,7 +298,7 @@ fn resolve_build_output<'a>(
   manifest: &Manifest,
 ) -> Result<&'a str, PlaceholderError> {
   // First, try to find a completed build with matching hash prefix
-  let matching_hash = completed_builds.keys().find(|h| h.0.starts_with(hash)).cloned();
+  let matching_hash = completed_builds.keys().find(|h| h.matches(hash)).cloned();
 
   if let Some(full_hash) = matching_hash
     && let Some(result) = completed_builds.get(&full_hash)
@@ -234,7 +330,7 @@ fn resolve_build_output<'a>(
   // If not in completed builds, check if it's in the manifest
   // and compute its store path (for "out" output)
   if output == "out" {
-    let full_hash = manifest.builds.keys().find(|h| h.0.starts_with(hash)).cloned();
+    let full_hash = manifest.builds.keys().find(|h| h.matches(hash)).cloned();
 
     if let Some(full_hash) = full_hash {
       let store_path = build_dir_path(&full_hash);
@@ -278,6 +374,31 @@ mod tests {
     assert_eq!(resolver.resolve_action(1).unwrap(), "/build/output");
   }
 
+  #[test]
+  fn build_ctx_resolve_named_action_output() {
+    let completed = HashMap::new();
+    let manifest = empty_manifest();
+    let mut resolver = BuildCtxResolver::new(&completed, &manifest, "/out".to_string());
+
+    resolver.push_action(
+      "/out/src/pkg".to_string(),
+      BTreeMap::from([
+        ("archive".to_string(), "/out/downloads/pkg.tar.gz".to_string()),
+        ("src".to_string(), "/out/src/pkg".to_string()),
+      ]),
+    );
+    resolver.push_action_result("done".to_string());
+
+    assert_eq!(
+      resolver.resolve_action_output(0, "archive").unwrap(),
+      "/out/downloads/pkg.tar.gz"
+    );
+    assert!(matches!(
+      resolver.resolve_action_output(1, "src"),
+      Err(PlaceholderError::UnresolvedActionOutput { index: 1, .. })
+    ));
+  }
+
   #[test]
   fn build_ctx_resolve_action_out_of_bounds() {
     let completed = HashMap::new();
@@ -307,6 +428,7 @@ mod tests {
       store_path: PathBuf::from("/store/obj/test"),
       outputs,
       action_results: vec![],
+      cached: false,
     };
 
     let mut completed = HashMap::new();
@@ -375,6 +497,7 @@ mod tests {
       store_path: PathBuf::from("/store/obj/app"),
       outputs: build_outputs,
       action_results: vec![],
+      cached: false,
     };
 
     let mut completed_builds = HashMap::new();
@@ -532,4 +655,26 @@ mod tests {
     child.push_action_result("child_action".to_string());
     assert_eq!(child.action_count(), 1);
   }
+
+  #[test]
+  fn bind_ctx_resolve_prev() {
+    let completed_builds = HashMap::new();
+    let completed_binds = HashMap::new();
+    let manifest = empty_manifest();
+    let previous = HashMap::from([
+      ("packages".to_string(), JsonValue::String("jq ripgrep".to_string())),
+      ("count".to_string(), JsonValue::from(2)),
+    ]);
+
+    let resolver = BindCtxResolver::new(&completed_builds, &completed_binds, &manifest, "/out".to_string());
+    assert!(matches!(
+      resolver.resolve_prev("packages"),
+      Err(PlaceholderError::UnresolvedPrev(_))
+    ));
+
+    let child = resolver.with_previous(&previous).with_out_dir("/child/out".to_string());
+    assert_eq!(child.resolve_prev("packages").unwrap(), "jq ripgrep");
+    assert!(child.resolve_prev("count").is_err());
+    assert!(child.resolve_prev("missing").is_err());
+  }
 }
@@ -0,0 +1,154 @@
+//! Mutual exclusion for binds in the same serialization group, or writing
+//! into the same directory.
+//!
+//! A bind declaring `serialize = "<group>"` never runs at the same time as
+//! another bind of that group, e.g. two binds driving the same package
+//! manager. Binds without a group, and binds of different groups, still run
+//! in parallel as the DAG allows.
+//!
+//! Binds whose targets share a parent directory never run at the same time
+//! either, so one can't remove a directory another is creating a file in.
+//!
+//! A bind takes its group, then its directories in sorted order, then a
+//! parallelism permit. Since every bind takes its locks in that order, two
+//! binds can't each hold a lock the other waits for.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// Per-group and per-directory locks shared by the tasks of one execution.
+#[derive(Debug, Clone, Default)]
+pub struct SerializeGroups {
+  locks: Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>,
+  dirs: Arc<Mutex<HashMap<PathBuf, Arc<AsyncMutex<()>>>>>,
+}
+
+impl SerializeGroups {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Wait until no other bind of `group` runs, and hold the group until the
+  /// guard is dropped. Returns `None` immediately for binds without a group.
+  ///
+  /// Take the group before a parallelism permit, so a bind waiting for its
+  /// group doesn't hold a slot other binds could use.
+  pub async fn lock(&self, group: Option<&str>) -> Option<OwnedMutexGuard<()>> {
+    let group = group?;
+    let lock = self
+      .locks
+      .lock()
+      .expect("serialize group table poisoned")
+      .entry(group.to_string())
+      .or_default()
+      .clone();
+    Some(lock.lock_owned().await)
+  }
+
+  /// Wait until no other bind writes into any of `dirs`, and hold them until
+  /// the guards are dropped. `dirs` are expected to be normalized, as
+  /// `bind::target::target_dirs` returns them.
+  ///
+  /// Take the directories after the group and before a parallelism permit.
+  pub async fn lock_dirs(&self, dirs: &[PathBuf]) -> Vec<OwnedMutexGuard<()>> {
+    let mut guards = Vec::with_capacity(dirs.len());
+    // Sorted, so binds sharing several directories take them in the same order
+    for dir in dirs.iter().collect::<BTreeSet<_>>() {
+      let lock = self
+        .dirs
+        .lock()
+        .expect("serialize directory table poisoned")
+        .entry(dir.clone())
+        .or_default()
+        .clone();
+      guards.push(lock.lock_owned().await);
+    }
+    guards
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  use super::*;
+
+  #[tokio::test]
+  async fn same_group_never_overlaps() {
+    let groups = SerializeGroups::new();
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for _ in 0..4 {
+      let groups = groups.clone();
+      let running = running.clone();
+      let max_running = max_running.clone();
+      tasks.spawn(async move {
+        let _guard = groups.lock(Some("pkg")).await;
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(now, Ordering::SeqCst);
+        for _ in 0..10 {
+          tokio::task::yield_now().await;
+        }
+        running.fetch_sub(1, Ordering::SeqCst);
+      });
+    }
+    while tasks.join_next().await.is_some() {}
+
+    assert_eq!(max_running.load(Ordering::SeqCst), 1);
+  }
+
+  #[tokio::test]
+  async fn different_groups_and_ungrouped_do_not_block() {
+    let groups = SerializeGroups::new();
+    let apt = groups.lock(Some("apt")).await;
+    assert!(apt.is_some());
+
+    assert!(groups.lock(None).await.is_none());
+    assert!(groups.lock(Some("brew")).await.is_some());
+
+    let apt_lock = groups.locks.lock().unwrap()["apt"].clone();
+    assert!(apt_lock.try_lock().is_err());
+    drop(apt);
+    assert!(apt_lock.try_lock().is_ok());
+  }
+
+  #[tokio::test]
+  async fn shared_directories_never_overlap_in_any_order() {
+    let groups = SerializeGroups::new();
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for i in 0..4 {
+      let groups = groups.clone();
+      let running = running.clone();
+      let max_running = max_running.clone();
+      // Both directories, listed in opposite orders
+      let mut dirs = vec![PathBuf::from("/home/u/.config"), PathBuf::from("/home/u/.local/bin")];
+      if i % 2 == 1 {
+        dirs.reverse();
+      }
+      tasks.spawn(async move {
+        let _guards = groups.lock_dirs(&dirs).await;
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(now, Ordering::SeqCst);
+        for _ in 0..10 {
+          tokio::task::yield_now().await;
+        }
+        running.fetch_sub(1, Ordering::SeqCst);
+      });
+    }
+    while tasks.join_next().await.is_some() {}
+    assert_eq!(max_running.load(Ordering::SeqCst), 1);
+
+    // Other directories stay free
+    let _config = groups.lock_dirs(&[PathBuf::from("/home/u/.config")]).await;
+    assert_eq!(groups.lock_dirs(&[PathBuf::from("/etc")]).await.len(), 1);
+    assert!(groups.lock_dirs(&[]).await.is_empty());
+  }
+}
@@ -0,0 +1,626 @@
+//! Host paths a plan's binds will touch.
+//!
+//! For every bind an apply would create or update, `sys plan` lists the paths
+//! it manages on the host, as far as the manifest tells:
+//!
+//! - symlinks its commands create (`ln -s`, `New-Item -ItemType SymbolicLink`, `mklink`)
+//! - files its `write_file` and `copy` actions write, and symlinks its
+//!   `symlink` actions create
+//! - config files its `config_section` actions edit
+//! - its `backup` paths
+//! - absolute paths among its outputs, outside the store
+//!
+//! Placeholders are resolved where the manifest alone determines their value,
+//! so a build output becomes its store path. Values only known once actions
+//! have run (`$${{action:N}}`, a bind's `$${{out}}`) are rendered as `<action:N>` or
+//! `<out>`, and paths containing them or shell variables are marked dynamic.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::action::Action;
+use crate::action::actions::exec::ExecOpts;
+use crate::action::actions::file::{FileSource, FileState};
+use crate::action::actions::font::FontState;
+use crate::bind::BindDef;
+use crate::build::store::build_dir_path;
+use crate::manifest::Manifest;
+use crate::placeholder::{Placeholder, Segment, parse};
+use crate::platform::Shell;
+use crate::platform::font::fonts_dir;
+use crate::platform::paths::store_dir;
+use crate::snapshot::StateDiff;
+use crate::util::hash::ObjectHash;
+
+use super::hooks::BindOperation;
+
+/// Build outputs referencing other builds are followed this deep.
+const MAX_DEPTH: usize = 16;
+
+/// The paths one bind of a plan will touch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BindTouches {
+  pub hash: ObjectHash,
+  pub id: Option<String>,
+  /// `create` or `update`.
+  pub operation: BindOperation,
+  pub paths: Vec<PathTouch>,
+}
+
+/// A host path a bind will touch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathTouch {
+  /// The path, with placeholders resolved or rendered as `<...>`.
+  pub path: String,
+  pub action: TouchAction,
+  /// The path depends on values only known during the apply.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub dynamic: bool,
+}
+
+/// What a bind does to a path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TouchAction {
+  /// Creates a symlink to `target`.
+  Symlink { target: String },
+  /// Writes a file, or copies a file or directory, optionally setting its
+  /// mode and owner (`attrs`).
+  File {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    attrs: String,
+  },
+  /// Writes a section of a git or ssh config file.
+  ConfigSection { section: String },
+  /// Writes a marker-delimited block of a file.
+  FileBlock { marker: String },
+  /// Modifies a pre-existing path, backed up first.
+  Backup,
+  /// Exposes the path as output `name`.
+  Output { name: String },
+}
+
+/// The paths touched by the binds `diff` creates or updates.
+///
+/// Binds without any known path are left out.
+pub fn plan_touches(manifest: &Manifest, diff: &StateDiff) -> Vec<BindTouches> {
+  let to_apply = diff.binds_to_apply.iter().map(|hash| (hash, BindOperation::Create));
+  let to_update = diff
+    .binds_to_update
+    .iter()
+    .map(|(_, hash)| (hash, BindOperation::Update));
+
+  to_apply
+    .chain(to_update)
+    .filter_map(|(hash, operation)| {
+      let def = manifest.bindings.get(hash)?;
+      let paths = bind_touches(def, operation, manifest);
+      (!paths.is_empty()).then(|| BindTouches {
+        hash: hash.clone(),
+        id: def.id.clone(),
+        operation,
+        paths,
+      })
+    })
+    .collect()
+}
+
+/// The paths `def` touches when run for `operation`.
+fn bind_touches(def: &BindDef, operation: BindOperation, manifest: &Manifest) -> Vec<PathTouch> {
+  let renderer = Renderer { manifest };
+  let actions = match (operation, &def.update_actions) {
+    (BindOperation::Update, Some(actions)) => actions,
+    _ => &def.create_actions,
+  };
+
+  let mut touches = Vec::new();
+  for action in actions {
+    match action {
+      Action::Exec(opts) => {
+        for (link, target) in symlinks(&opts.bin, opts.args.as_ref(), opts.shell) {
+          let (path, link_dynamic) = renderer.render(&link);
+          let (target, target_dynamic) = renderer.render(&target);
+          touches.push(PathTouch {
+            path,
+            action: TouchAction::Symlink { target },
+            dynamic: link_dynamic || target_dynamic,
+          });
+        }
+      }
+      Action::File(opts) if opts.state == FileState::Present => {
+        let (path, path_dynamic) = renderer.render(&opts.path);
+        let (action, dynamic) = match &opts.source {
+          FileSource::Symlink(target) => {
+            let (target, target_dynamic) = renderer.render(target);
+            (TouchAction::Symlink { target }, path_dynamic || target_dynamic)
+          }
+          FileSource::Content(_) | FileSource::Copy(_) => (
+            TouchAction::File {
+              attrs: opts.attrs.to_string(),
+            },
+            path_dynamic,
+          ),
+        };
+        touches.push(PathTouch { path, action, dynamic });
+      }
+      Action::ConfigSection(opts) => {
+        let (path, dynamic) = renderer.render(&opts.path);
+        touches.push(PathTouch {
+          path,
+          action: TouchAction::ConfigSection {
+            section: opts.section.clone(),
+          },
+          dynamic,
+        });
+      }
+      Action::FileBlock(opts) => {
+        let (path, dynamic) = renderer.render(&opts.path);
+        touches.push(PathTouch {
+          path,
+          action: TouchAction::FileBlock {
+            marker: opts.marker.clone(),
+          },
+          dynamic,
+        });
+      }
+      Action::FetchUrl { .. } | Action::Firewall(_) | Action::File(_) | Action::Font(_) => {}
+    }
+  }
+
+  for path in def.backup.iter().flat_map(|backup| &backup.paths) {
+    if !touches.iter().any(|touch| &touch.path == path) {
+      touches.push(PathTouch {
+        path: path.clone(),
+        action: TouchAction::Backup,
+        dynamic: false,
+      });
+    }
+  }
+
+  let store = store_dir();
+  for (name, value) in def.outputs.iter().flatten() {
+    let JsonValue::String(value) = value else {
+      continue;
+    };
+    let (path, dynamic) = renderer.render(value);
+    if is_host_path(&path, dynamic)
+      && !Path::new(&path).starts_with(&store)
+      && !touches.iter().any(|touch| touch.path == path)
+    {
+      touches.push(PathTouch {
+        path,
+        action: TouchAction::Output { name: name.clone() },
+        dynamic,
+      });
+    }
+  }
+
+  touches
+}
+
+/// The host paths `def` writes when run for `operation`, as written in its
+/// actions (placeholders unresolved): the symlinks it creates, the paths its
+/// file actions write, the files its `config_section` and `file_block`
+/// actions edit, the fonts directories its `font` actions install into, and
+/// its `backup` paths.
+/// Commands run as another user (`run_as`) write with that user's rights,
+/// so their symlinks are left out.
+pub(crate) fn bind_targets(def: &BindDef, operation: BindOperation) -> Vec<String> {
+  let actions = match (operation, &def.update_actions) {
+    (BindOperation::Update, Some(actions)) => actions,
+    _ => &def.create_actions,
+  };
+
+  let mut targets = Vec::new();
+  for action in actions {
+    match action {
+      Action::Exec(opts) if opts.run_as.is_some() => {}
+      Action::Exec(opts) => targets.extend(
+        symlinks(&opts.bin, opts.args.as_ref(), opts.shell)
+          .into_iter()
+          .map(|(link, _)| link),
+      ),
+      Action::ConfigSection(opts) => targets.push(opts.path.clone()),
+      Action::File(opts) => targets.push(opts.path.clone()),
+      Action::FileBlock(opts) => targets.push(opts.path.clone()),
+      Action::Font(opts) if opts.state == FontState::Present => {
+        targets.push(fonts_dir(opts.scope).to_string_lossy().to_string())
+      }
+      Action::FetchUrl { .. } | Action::Firewall(_) | Action::Font(_) => {}
+    }
+  }
+  targets.extend(def.backup.iter().flat_map(|backup| backup.paths.iter().cloned()));
+
+  let mut seen = std::collections::HashSet::new();
+  targets.retain(|target| seen.insert(target.clone()));
+  targets
+}
+
+/// The host paths whose existing files `def`'s `create` actions replace, as
+/// written in its actions: the symlinks its commands create and the paths its
+/// file actions write or remove, followed by its `backup` paths. Files that
+/// are only edited (`config_section`, `file_block`) are left out, as are
+/// symlinks commands create as another user (`run_as`).
+pub(crate) fn replaced_targets(def: &BindDef) -> Vec<String> {
+  let mut targets = Vec::new();
+  for action in &def.create_actions {
+    match action {
+      Action::Exec(opts) if opts.run_as.is_none() => targets.extend(
+        symlinks(&opts.bin, opts.args.as_ref(), opts.shell)
+          .into_iter()
+          .map(|(link, _)| link),
+      ),
+      Action::File(opts) if opts.state != FileState::Check => targets.push(opts.path.clone()),
+      _ => {}
+    }
+  }
+  targets.extend(def.backup.iter().flat_map(|backup| backup.paths.iter().cloned()));
+
+  let mut seen = std::collections::HashSet::new();
+  targets.retain(|target| seen.insert(target.clone()));
+  targets
+}
+
+/// Whether a rendered output value names a path on the host.
+fn is_host_path(path: &str, dynamic: bool) -> bool {
+  if Path::new(path).is_absolute() || path.starts_with("~/") {
+    return true;
+  }
+  // `$HOME/.config/app`, `<env:APPDATA>\app`
+  dynamic && (path.starts_with('$') || path.starts_with("<env:")) && path.contains(['/', '\\'])
+}
+
+/// Resolves placeholders from the manifest alone.
+struct Renderer<'a> {
+  manifest: &'a Manifest,
+}
+
+impl Renderer<'_> {
+  /// Render `value` as seen from a bind, and whether it is dynamic.
+  fn render(&self, value: &str) -> (String, bool) {
+    let (rendered, dynamic) = self.render_in(value, None, 0);
+    let shell_variable = rendered.contains('$');
+    (rendered, dynamic || shell_variable)
+  }
+
+  /// Render `value` as seen from the build `build` (`None` for a bind).
+  fn render_in(&self, value: &str, build: Option<&ObjectHash>, depth: usize) -> (String, bool) {
+    let Ok(segments) = parse(value) else {
+      return (value.to_string(), false);
+    };
+
+    let mut rendered = String::new();
+    let mut dynamic = false;
+    for segment in segments {
+      match segment {
+        Segment::Literal(text) => rendered.push_str(&text),
+        Segment::Placeholder(placeholder) => {
+          let resolved = match &placeholder {
+            Placeholder::Out => build.map(|hash| (build_dir_path(hash).to_string_lossy().to_string(), false)),
+            Placeholder::Build { hash, output } => self.output(hash, output, true, depth),
+            Placeholder::Bind { hash, output } => self.output(hash, output, false, depth),
+            Placeholder::Env(name) => std::env::var(name).ok().map(|value| (value, false)),
+            Placeholder::Action(_) | Placeholder::ActionOutput { .. } | Placeholder::Prev(_) => None,
+          };
+          match resolved {
+            Some((value, value_dynamic)) => {
+              rendered.push_str(&value);
+              dynamic |= value_dynamic;
+            }
+            None => {
+              rendered.push_str(&format!("<{}>", describe(&placeholder)));
+              dynamic = true;
+            }
+          }
+        }
+      }
+    }
+    (rendered, dynamic)
+  }
+
+  /// Render output `output` of a build or bind.
+  fn output(&self, hash: &str, output: &str, build: bool, depth: usize) -> Option<(String, bool)> {
+    if depth >= MAX_DEPTH {
+      return None;
+    }
+    let hash = ObjectHash(hash.to_string());
+    let outputs = if build {
+      self.manifest.builds.get(&hash)?.outputs.as_ref()
+    } else {
+      self.manifest.bindings.get(&hash)?.outputs.as_ref()
+    };
+    let JsonValue::String(value) = outputs?.get(output)? else {
+      return None;
+    };
+    Some(self.render_in(value, build.then_some(&hash), depth + 1))
+  }
+}
+
+/// A placeholder as shown in place of its unknown value.
+fn describe(placeholder: &Placeholder) -> String {
+  match placeholder {
+    Placeholder::Action(index) => format!("action:{}", index),
+    Placeholder::ActionOutput { index, output } => format!("action:{}:{}", index, output),
+    Placeholder::Build { hash, output } => format!("build:{}:{}", hash, output),
+    Placeholder::Bind { hash, output } => format!("bind:{}:{}", hash, output),
+    Placeholder::Out => "out".to_string(),
+    Placeholder::Env(name) => format!("env:{}", name),
+    Placeholder::Prev(output) => format!("prev:{}", output),
+  }
+}
+
+/// `(link, target)` pairs of the symlinks a command creates, as written.
+fn symlinks(bin: &str, args: Option<&Vec<String>>, shell: Option<Shell>) -> Vec<(String, String)> {
+  let (bin, args) = ExecOpts::invocation(bin, args, shell);
+  let args = args.unwrap_or_default();
+  let program = program_name(&bin);
+
+  // Flag introducing the script, and whether the shell escapes with backslashes
+  let script_flag = match program.as_str() {
+    "sh" | "bash" | "zsh" | "dash" => Some(("-c", true)),
+    "powershell" | "pwsh" => Some(("-command", false)),
+    "cmd" => Some(("/c", false)),
+    _ => None,
+  };
+  let commands = match script_flag {
+    Some((flag, escapes)) => match args.iter().position(|arg| arg.eq_ignore_ascii_case(flag)) {
+      Some(index) => args
+        .get(index + 1)
+        .map(|script| split_commands(script, escapes))
+        .unwrap_or_default(),
+      None => Vec::new(),
+    },
+    None => vec![std::iter::once(bin.clone()).chain(args).collect()],
+  };
+
+  commands.iter().filter_map(|words| symlink_of(words)).collect()
+}
+
+/// The lowercase file name of a program, without `.exe`.
+fn program_name(bin: &str) -> String {
+  let name = bin.rsplit(['/', '\\']).next().unwrap_or(bin).to_lowercase();
+  name.strip_suffix(".exe").map(str::to_string).unwrap_or(name)
+}
+
+/// The `(link, target)` a single command creates, if it creates a symlink.
+fn symlink_of(words: &[String]) -> Option<(String, String)> {
+  let (program, args) = words.split_first()?;
+  match program_name(program).as_str() {
+    "ln" => {
+      let symbolic = args
+        .iter()
+        .any(|arg| arg == "--symbolic" || (arg.starts_with('-') && !arg.starts_with("--") && arg.contains('s')));
+      let operands: Vec<&String> = args.iter().filter(|arg| !arg.starts_with('-')).collect();
+      match operands[..] {
+        [.., target, link] if symbolic => Some((link.clone(), target.clone())),
+        _ => None,
+      }
+    }
+    "new-item" => {
+      let option = |name: &str| {
+        args
+          .iter()
+          .position(|arg| arg.eq_ignore_ascii_case(name))
+          .and_then(|index| args.get(index + 1))
+      };
+      let item_type = option("-ItemType")?;
+      if !item_type.eq_ignore_ascii_case("SymbolicLink") && !item_type.eq_ignore_ascii_case("Junction") {
+        return None;
+      }
+      let target = option("-Target").or_else(|| option("-Value"))?;
+      Some((option("-Path")?.clone(), target.clone()))
+    }
+    "mklink" => {
+      if args.iter().any(|arg| arg.eq_ignore_ascii_case("/H")) {
+        return None;
+      }
+      let operands: Vec<&String> = args.iter().filter(|arg| !arg.starts_with('/')).collect();
+      match operands[..] {
+        [link, target] => Some((link.clone(), target.clone())),
+        _ => None,
+      }
+    }
+    _ => None,
+  }
+}
+
+/// Split a script into the words of its simple commands.
+///
+/// Handles quotes and, when `escapes` is set, backslash escapes; `;`, `&`,
+/// `|` and newlines end a command.
+fn split_commands(script: &str, escapes: bool) -> Vec<Vec<String>> {
+  let mut commands = Vec::new();
+  let mut words = Vec::new();
+  let mut word = String::new();
+  let mut in_word = false;
+  let mut chars = script.chars();
+
+  while let Some(c) = chars.next() {
+    match c {
+      '\'' | '"' => {
+        in_word = true;
+        for quoted in chars.by_ref() {
+          if quoted == c {
+            break;
+          }
+          word.push(quoted);
+        }
+      }
+      '\\' if escapes => {
+        in_word = true;
+        if let Some(escaped) = chars.next() {
+          word.push(escaped);
+        }
+      }
+      ';' | '&' | '|' | '\n' | ' ' | '\t' | '\r' => {
+        if in_word {
+          words.push(std::mem::take(&mut word));
+          in_word = false;
+        }
+        if !matches!(c, ' ' | '\t' | '\r') && !words.is_empty() {
+          commands.push(std::mem::take(&mut words));
+        }
+      }
+      _ => {
+        in_word = true;
+        word.push(c);
+      }
+    }
+  }
+  if in_word {
+    words.push(word);
+  }
+  if !words.is_empty() {
+    commands.push(words);
+  }
+  commands
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::action::actions::config_section::{ConfigFormat, ConfigSectionOpts, SectionState};
+  use crate::bind::BindBackupDef;
+  use crate::build::BuildDef;
+  use std::collections::BTreeMap;
+
+  fn bind(create_actions: Vec<Action>, outputs: &[(&str, &str)]) -> BindDef {
+    BindDef {
+      id: Some("nvim".to_string()),
+      inputs: None,
+      outputs: (!outputs.is_empty()).then(|| {
+        outputs
+          .iter()
+          .map(|(k, v)| (k.to_string(), JsonValue::String(v.to_string())))
+          .collect()
+      }),
+      create_actions,
+      update_actions: None,
+      destroy_actions: vec![],
+      check_actions: None,
+      check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: vec![],
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
+    }
+  }
+
+  fn sh(script: &str) -> Action {
+    Action::Exec(ExecOpts::new("/bin/sh").with_args(vec!["-c".to_string(), script.to_string()]))
+  }
+
+  fn manifest_with_build(hash: &str) -> Manifest {
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(
+      ObjectHash(hash.to_string()),
+      BuildDef {
+        outputs: Some(BTreeMap::from([(
+          "out".to_string(),
+          JsonValue::String("$${{out}}/nvim".to_string()),
+        )])),
+        ..BuildDef::prebuilt("nvim-config", "abc")
+      },
+    );
+    manifest
+  }
+
+  #[test]
+  fn split_commands_handles_quotes_and_separators() {
+    assert_eq!(
+      split_commands(r#"mkdir -p "/a b" && ln -sf '/x' /y; echo done"#, true),
+      vec![
+        vec!["mkdir", "-p", "/a b"],
+        vec!["ln", "-sf", "/x", "/y"],
+        vec!["echo", "done"],
+      ]
+    );
+  }
+
+  #[test]
+  fn symlinks_of_ln_new_item_and_mklink() {
+    let words = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+    assert_eq!(
+      symlink_of(&words("ln -sfn /store/x /home/u/.config/nvim")),
+      Some(("/home/u/.config/nvim".to_string(), "/store/x".to_string()))
+    );
+    assert_eq!(symlink_of(&words("ln /a /b")), None);
+    assert_eq!(
+      symlink_of(&words(
+        "New-Item -ItemType SymbolicLink -Path C:\\link -Target C:\\store"
+      )),
+      Some(("C:\\link".to_string(), "C:\\store".to_string()))
+    );
+    assert_eq!(
+      symlink_of(&words("mklink /D C:\\link C:\\store")),
+      Some(("C:\\link".to_string(), "C:\\store".to_string()))
+    );
+    assert_eq!(symlink_of(&words("mklink /H C:\\link C:\\store")), None);
+  }
+
+  #[test]
+  fn build_outputs_resolve_to_store_paths() {
+    let manifest = manifest_with_build("abcdef0123456789abcd");
+    let def = bind(
+      vec![sh(
+        r#"ln -s "$${{build:abcdef0123456789abcd:out}}" "/home/u/.config/nvim""#,
+      )],
+      &[("link", "/home/u/.config/nvim")],
+    );
+
+    let touches = bind_touches(&def, BindOperation::Create, &manifest);
+    let store_path = build_dir_path(&ObjectHash("abcdef0123456789abcd".to_string()));
+    assert_eq!(
+      touches,
+      vec![PathTouch {
+        path: "/home/u/.config/nvim".to_string(),
+        action: TouchAction::Symlink {
+          target: format!("{}/nvim", store_path.display()),
+        },
+        dynamic: false,
+      }]
+    );
+  }
+
+  #[test]
+  fn dynamic_values_are_marked() {
+    let mut def = bind(
+      vec![
+        sh(r#"ln -s "$${{action:0}}" "$HOME/.local/bin/tool""#),
+        Action::ConfigSection(ConfigSectionOpts {
+          format: ConfigFormat::Git,
+          path: "/home/u/.gitconfig".to_string(),
+          section: "user".to_string(),
+          entries: BTreeMap::new(),
+          state: SectionState::default(),
+        }),
+      ],
+      &[("out", "$${{out}}"), ("log", "$${{action:1}}/log.txt")],
+    );
+    def.backup = Some(BindBackupDef {
+      paths: vec!["/home/u/.gitconfig".to_string(), "/etc/hosts".to_string()],
+      max_size: 1024,
+    });
+
+    let touches = bind_touches(&def, BindOperation::Create, &Manifest::default());
+    assert_eq!(touches.len(), 3, "{touches:?}");
+    assert_eq!(touches[0].path, "$HOME/.local/bin/tool");
+    assert_eq!(
+      touches[0].action,
+      TouchAction::Symlink {
+        target: "<action:0>".to_string()
+      }
+    );
+    assert!(touches[0].dynamic);
+    assert!(matches!(touches[1].action, TouchAction::ConfigSection { .. }));
+    assert!(!touches[1].dynamic);
+    assert_eq!(touches[2].path, "/etc/hosts");
+    assert_eq!(touches[2].action, TouchAction::Backup);
+  }
+}
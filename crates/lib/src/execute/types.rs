@@ -3,15 +3,31 @@
 //! This module defines the error types, result types, and configuration
 //! for executing builds and binds from a manifest.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use serde_json::Value as JsonValue;
 use thiserror::Error;
 
+use crate::action::actions::file::ManagedFile;
+use crate::action::actions::rate_limit::RateLimit;
+use crate::bind::backup::BackupError;
+use crate::bind::target::TargetProblem;
+use crate::build::users::BuildUsers;
+use crate::gc::roots::TempRoots;
 use crate::placeholder::PlaceholderError;
+use crate::platform::priority::Throttle;
+use crate::platform::run_as::RunAs;
 use crate::util::hash::{DirHashError, ObjectHash};
 
+use super::failures::DEFAULT_FAILURE_TTL_SECS;
+use super::fault::{FailPhase, FailPoint};
+use super::hooks::HookRunner;
+use super::limits::ActionLimits;
+use super::progress::ProgressSender;
+use super::transcript::TranscriptRecorder;
+
 /// Identifies what caused a build or bind to be skipped.
 ///
 /// When a node in the execution DAG fails, all dependent nodes are skipped.
@@ -53,10 +69,45 @@ pub enum ExecuteError {
     actual: String,
   },
 
+  /// A fetched archive could not be unpacked.
+  #[error("failed to unpack {path}: {message}")]
+  Unpack { path: String, message: String },
+
   /// Command execution failed.
   #[error("command failed with exit code {code:?}: {cmd}")]
   CmdFailed { cmd: String, code: Option<i32> },
 
+  /// An exec action could not be made to run as its `run_as` user.
+  #[error("cannot run command as '{user}': {message}")]
+  RunAs { user: String, message: String },
+
+  /// A file, symlink or copy could not be put in place or removed.
+  #[error("file action on {path} failed: {message}")]
+  File { path: String, message: String },
+
+  /// A file block could not be written or removed.
+  #[error("file block in {path} failed: {message}")]
+  FileBlock { path: String, message: String },
+
+  /// A firewall rule could not be added, deleted or checked.
+  #[error("firewall rule failed: {message}")]
+  Firewall { message: String },
+
+  /// Fonts could not be installed, removed or checked.
+  #[error("font installation failed: {message}")]
+  Font { message: String },
+
+  /// A build command tried to reach the network while builds were isolated.
+  #[error(
+    "command tried to reach {} with network isolation enabled (download with fetch_url instead): {cmd}",
+    .targets.join(", ")
+  )]
+  NetworkDenied { cmd: String, targets: Vec<String> },
+
+  /// A build's `check` actions failed.
+  #[error("build check failed: {message}")]
+  CheckFailed { message: String },
+
   /// Command produced output on stderr.
   #[error("command error: {message}")]
   CmdError { message: String },
@@ -104,6 +155,27 @@ pub enum ExecuteError {
   /// Failed to parse build marker JSON.
   #[error("failed to parse build marker: {message}")]
   ParseMarker { message: String },
+
+  /// A `sys.prebuilt` build was not imported into the store.
+  #[error("prebuilt build '{id}' is not in the store (import it with `sys store add <dir> --id {id}`)")]
+  PrebuiltMissing { id: String },
+
+  /// Failed to back up or restore a file replaced by a bind.
+  #[error("bind backup failed: {message}")]
+  Backup { message: String },
+
+  /// A bind would write a path it can't write, and has no `fallback_path`
+  /// for it.
+  #[error("bind target {path} is not writable: {problem}; {}", .problem.remediation())]
+  BindTargetNotWritable { path: String, problem: TargetProblem },
+
+  /// An audit hook with `on_failure = "error"` failed.
+  #[error("{message}")]
+  Hook { message: String },
+
+  /// The node was failed on purpose by `--fail-at`.
+  #[error("injected {phase} failure at '{target}'")]
+  Injected { phase: FailPhase, target: String },
 }
 
 /// Result of executing a single action.
@@ -111,6 +183,19 @@ pub enum ExecuteError {
 pub struct ActionResult {
   /// The output of the action (file path for FetchUrl, stdout for Cmd).
   pub output: String,
+  /// True if the action was skipped by a `creates`/`unless` guard.
+  #[serde(default)]
+  pub skipped: bool,
+  /// The user an exec action with `run_as` ran as, and how.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub run_as: Option<RunAs>,
+  /// Named sub-outputs, referenced as `$${{action:N:<name>}}` (`archive` and
+  /// `src` for an unpacking FetchUrl).
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub outputs: BTreeMap<String, String>,
+  /// The mode, owner and group a file action set, kept in the bind's state.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub file: Option<ManagedFile>,
 }
 
 /// Result of realizing a single build.
@@ -122,10 +207,15 @@ pub struct BuildResult {
   /// Resolved outputs from the build (output name -> resolved value).
   /// These are the values from BuildDef.outputs with placeholders resolved.
   /// String values have their placeholders substituted; other JSON types are passed through.
+  #[serde(serialize_with = "crate::util::ordered::sorted")]
   pub outputs: HashMap<String, JsonValue>,
 
   /// Results of individual actions (for debugging/logging).
   pub action_results: Vec<ActionResult>,
+
+  /// The build was already in the store, so nothing ran.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub cached: bool,
 }
 
 /// Result of applying a single bind.
@@ -134,6 +224,7 @@ pub struct BindResult {
   /// Resolved outputs from the bind (output name -> resolved value).
   /// These are the values from BindDef.outputs with placeholders resolved.
   /// String values have their placeholders substituted; other JSON types are passed through.
+  #[serde(serialize_with = "crate::util::ordered::sorted")]
   pub outputs: HashMap<String, JsonValue>,
 
   /// Results of individual actions (for debugging/logging).
@@ -141,7 +232,7 @@ pub struct BindResult {
 }
 
 /// Result of checking a bind for drift.
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct DriftResult {
   /// The bind's hash.
   pub hash: ObjectHash,
@@ -149,6 +240,51 @@ pub struct DriftResult {
   pub id: Option<String>,
   /// The check result.
   pub result: crate::bind::BindCheckResult,
+  /// Why `--repair` leaves this drift alone, per the bind's `repair` policy
+  /// and ignore rules. `None` when the bind is repaired (or hasn't drifted).
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub repair_skipped: Option<String>,
+}
+
+impl DriftResult {
+  /// Whether `--repair` re-creates this bind.
+  pub fn needs_repair(&self) -> bool {
+    self.result.drifted && self.repair_skipped.is_none()
+  }
+}
+
+/// Wall-clock start and end of a single build or bind execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NodeTiming {
+  /// Milliseconds since the Unix epoch when execution started.
+  pub started_at_ms: u64,
+  /// Milliseconds since the Unix epoch when execution finished.
+  pub finished_at_ms: u64,
+}
+
+impl NodeTiming {
+  /// Await `future`, recording when it started and finished.
+  pub async fn measure<F: std::future::Future>(future: F) -> (F::Output, Self) {
+    let started_at_ms = now_ms();
+    let output = future.await;
+    let timing = Self {
+      started_at_ms,
+      finished_at_ms: now_ms(),
+    };
+    (output, timing)
+  }
+
+  /// Time spent executing the node.
+  pub fn duration(&self) -> Duration {
+    Duration::from_millis(self.finished_at_ms.saturating_sub(self.started_at_ms))
+  }
+}
+
+pub(crate) fn now_ms() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis() as u64)
+    .unwrap_or(0)
 }
 
 /// Result of executing the entire DAG.
@@ -156,6 +292,7 @@ pub struct DriftResult {
 pub struct DagResult {
   // === Builds ===
   /// Successfully realized builds.
+  #[serde(serialize_with = "crate::util::ordered::sorted")]
   pub realized: HashMap<ObjectHash, BuildResult>,
 
   /// Build that failed during execution (at most one, stops execution).
@@ -163,10 +300,12 @@ pub struct DagResult {
 
   /// Builds that were skipped because a dependency failed.
   /// Maps skipped build hash -> the failed dependency.
+  #[serde(serialize_with = "crate::util::ordered::sorted")]
   pub build_skipped: HashMap<ObjectHash, FailedDependency>,
 
   // === Binds ===
   /// Successfully applied binds.
+  #[serde(serialize_with = "crate::util::ordered::sorted")]
   pub applied: HashMap<ObjectHash, BindResult>,
 
   /// Bind that failed during execution (at most one, triggers rollback).
@@ -174,7 +313,17 @@ pub struct DagResult {
 
   /// Binds that were skipped because a dependency failed.
   /// Maps skipped bind hash -> the failed dependency.
+  #[serde(serialize_with = "crate::util::ordered::sorted")]
   pub bind_skipped: HashMap<ObjectHash, FailedDependency>,
+
+  // === Timing ===
+  /// Execution timing of every build that ran (realized or failed).
+  #[serde(default, serialize_with = "crate::util::ordered::sorted")]
+  pub build_timings: HashMap<ObjectHash, NodeTiming>,
+
+  /// Execution timing of every bind that ran (applied or failed).
+  #[serde(default, serialize_with = "crate::util::ordered::sorted")]
+  pub bind_timings: HashMap<ObjectHash, NodeTiming>,
 }
 
 impl DagResult {
@@ -207,16 +356,126 @@ impl DagResult {
 pub struct ExecuteConfig {
   /// Maximum number of builds to execute in parallel.
   pub parallelism: usize,
+
+  /// Expected duration in milliseconds of builds and binds, from past runs.
+  ///
+  /// Nodes at the head of the longest expected chain of work are started
+  /// first within each wave. Nodes without an estimate count as instant.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub expected_durations: BTreeMap<ObjectHash, u64>,
+
+  /// Refuse network access to build commands.
+  ///
+  /// Proxy variables of build `exec` commands point at a local proxy that
+  /// fails every request, so only `fetch_url` can download.
+  #[serde(default)]
+  pub isolate_network: bool,
+
+  /// Don't run the `check` actions of builds.
+  #[serde(default)]
+  pub skip_checks: bool,
+
+  /// Don't check disk space and writable directories before applying.
+  #[serde(default)]
+  pub skip_preflight: bool,
+
+  /// Leave realized build outputs writable instead of making them read-only,
+  /// for filesystems that don't handle it.
+  #[serde(default)]
+  pub writable_store: bool,
+
+  /// Run the commands of each build as a user of its own from this pool.
+  ///
+  /// The system daemon sets it for builds submitted by untrusted users: the
+  /// output directory belongs to the user while its commands run, and is
+  /// given back without setuid or setgid bits before anything runs as this
+  /// process again. The user's processes are killed when the build ends.
+  #[serde(skip)]
+  pub build_users: Option<BuildUsers>,
+
+  /// Lowered priority of the commands builds and binds spawn.
+  ///
+  /// Background mode also caps `parallelism` when applying.
+  #[serde(default, skip_serializing_if = "Throttle::is_none")]
+  pub throttle: Throttle,
+
+  /// Combined rate limit of `fetch_url` downloads, shared by all builds.
+  #[serde(default, skip_serializing_if = "RateLimit::is_none")]
+  pub limit_rate: RateLimit,
+
+  /// How many downloads and package manager invocations run at once, across
+  /// all builds and binds.
+  #[serde(flatten)]
+  pub action_limits: ActionLimits,
+
+  /// A node to fail on purpose before it runs, for testing rollback.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub fail_at: Option<FailPoint>,
+
+  /// Run builds that failed within [`failure_ttl_secs`](Self::failure_ttl_secs)
+  /// again instead of failing the apply before it changes anything.
+  #[serde(default)]
+  pub retry_failed: bool,
+
+  /// How long a failed build is remembered and skipped, in seconds (0 = never).
+  ///
+  /// See [`failures`](crate::execute::failures).
+  #[serde(default = "default_failure_ttl_secs")]
+  pub failure_ttl_secs: u64,
+
+  /// Where to report execution progress; nowhere by default.
+  #[serde(skip)]
+  pub progress: ProgressSender,
+
+  /// Where to record the commands `exec` actions run; nowhere by default.
+  #[serde(skip)]
+  pub transcript: TranscriptRecorder,
+
+  /// Audit hooks run around bind execution, from the manifest's `hooks`.
+  #[serde(skip)]
+  pub hooks: HookRunner,
+
+  /// Where builds and binds being executed are registered so a concurrent GC
+  /// keeps them; nowhere by default.
+  #[serde(skip)]
+  pub roots: TempRoots,
 }
 
 impl Default for ExecuteConfig {
   fn default() -> Self {
     Self {
       parallelism: num_cpus(),
+      expected_durations: BTreeMap::new(),
+      isolate_network: false,
+      skip_checks: false,
+      skip_preflight: false,
+      writable_store: false,
+      build_users: None,
+      throttle: Throttle::NONE,
+      limit_rate: RateLimit::NONE,
+      action_limits: ActionLimits::default(),
+      fail_at: None,
+      retry_failed: false,
+      failure_ttl_secs: DEFAULT_FAILURE_TTL_SECS,
+      progress: ProgressSender::default(),
+      transcript: TranscriptRecorder::default(),
+      hooks: HookRunner::default(),
+      roots: TempRoots::default(),
     }
   }
 }
 
+impl ExecuteConfig {
+  /// How long a failed build is skipped.
+  pub fn failure_ttl(&self) -> Duration {
+    Duration::from_secs(self.failure_ttl_secs)
+  }
+}
+
+fn default_failure_ttl_secs() -> u64 {
+  DEFAULT_FAILURE_TTL_SECS
+}
+
 /// Get the number of CPUs for default parallelism.
 fn num_cpus() -> usize {
   std::thread::available_parallelism().map(|p| p.get()).unwrap_or(4)
@@ -230,6 +489,14 @@ impl From<std::io::Error> for ExecuteError {
   }
 }
 
+impl From<BackupError> for ExecuteError {
+  fn from(err: BackupError) -> Self {
+    ExecuteError::Backup {
+      message: err.to_string(),
+    }
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -243,6 +510,7 @@ mod tests {
         store_path: PathBuf::from("/store/obj/test"),
         outputs: HashMap::new(),
         action_results: vec![],
+        cached: false,
       },
     );
     assert!(result.is_success());
@@ -351,4 +619,26 @@ mod tests {
     let config = ExecuteConfig::default();
     assert!(config.parallelism >= 1);
   }
+
+  #[tokio::test]
+  async fn node_timing_measures_future() {
+    let (value, timing) = NodeTiming::measure(async {
+      tokio::time::sleep(Duration::from_millis(20)).await;
+      42
+    })
+    .await;
+
+    assert_eq!(value, 42);
+    assert!(timing.finished_at_ms >= timing.started_at_ms);
+    assert!(timing.duration() >= Duration::from_millis(15));
+  }
+
+  #[test]
+  fn dag_result_without_timings_deserializes() {
+    let json =
+      r#"{"realized":{},"build_failed":null,"build_skipped":{},"applied":{},"bind_failed":null,"bind_skipped":{}}"#;
+    let result: DagResult = serde_json::from_str(json).unwrap();
+    assert!(result.build_timings.is_empty());
+    assert!(result.bind_timings.is_empty());
+  }
 }
@@ -1,14 +1,22 @@
+pub mod roots;
+
 use std::collections::HashSet;
 use std::path::PathBuf;
 use std::{fs, io};
 
 use thiserror::Error;
 use tracing::{debug, info, warn};
-use walkdir::WalkDir;
 
+use crate::action::actions::download_cache::{MAX_UNUSED_AGE, downloads_dir, sweep_stale};
 use crate::build::execute::BUILD_COMPLETE_MARKER;
-use crate::platform::paths::{cache_dir, store_dir};
+use crate::build::refs::reference_closure;
+use crate::build::store::builds_dir;
+use crate::platform::immutable::remove_store_path;
+use crate::platform::paths::{cache_dir, store_dir, store_root};
 use crate::snapshot::SnapshotStore;
+use crate::util::fs::dir_size;
+
+use roots::live_temp_roots;
 
 #[derive(Debug, Error)]
 pub enum GcError {
@@ -33,15 +41,17 @@ pub struct GcStats {
   pub inputs_scanned: usize,
   pub inputs_deleted: usize,
   pub inputs_bytes_freed: u64,
+  pub downloads_deleted: usize,
+  pub downloads_bytes_freed: u64,
 }
 
 impl GcStats {
   pub fn total_deleted(&self) -> usize {
-    self.builds_deleted + self.inputs_deleted
+    self.builds_deleted + self.inputs_deleted + self.downloads_deleted
   }
 
   pub fn total_bytes_freed(&self) -> u64 {
-    self.builds_bytes_freed + self.inputs_bytes_freed
+    self.builds_bytes_freed + self.inputs_bytes_freed + self.downloads_bytes_freed
   }
 }
 
@@ -51,8 +61,11 @@ pub struct GcResult {
   pub deleted_paths: Vec<PathBuf>,
 }
 
-fn collect_live_hashes(snapshot_store: &SnapshotStore) -> Result<HashSet<String>, GcError> {
-  let mut live = HashSet::new();
+fn collect_live_hashes(
+  snapshot_store: &SnapshotStore,
+  in_progress: &HashSet<String>,
+) -> Result<HashSet<String>, GcError> {
+  let mut live = in_progress.clone();
 
   let snapshots = snapshot_store
     .list()
@@ -61,12 +74,12 @@ fn collect_live_hashes(snapshot_store: &SnapshotStore) -> Result<HashSet<String>
   for meta in snapshots {
     match snapshot_store.load_snapshot(&meta.id) {
       Ok(snapshot) => {
-        for hash in snapshot.manifest.builds.keys() {
-          live.insert(hash.0.clone());
-        }
-
-        for hash in snapshot.manifest.bindings.keys() {
+        // Shorter forms keep directories created before the hash length was
+        // raised, which longer hashes of the same definition reuse
+        let hashes = snapshot.manifest.builds.keys().chain(snapshot.manifest.bindings.keys());
+        for hash in hashes {
           live.insert(hash.0.clone());
+          live.extend(hash.shorter_forms().map(|short| short.0));
         }
       }
       Err(e) => {
@@ -75,18 +88,16 @@ fn collect_live_hashes(snapshot_store: &SnapshotStore) -> Result<HashSet<String>
     }
   }
 
-  debug!(count = live.len(), "collected live hashes from snapshots");
-  Ok(live)
-}
+  // Keep builds whose store paths are embedded in live builds' outputs
+  let declared = live.len();
+  let live = reference_closure(&builds_dir(&store_dir()), live);
 
-fn dir_size(path: &std::path::Path) -> u64 {
-  WalkDir::new(path)
-    .into_iter()
-    .filter_map(|e| e.ok())
-    .filter(|e| e.file_type().is_file())
-    .filter_map(|e| e.metadata().ok())
-    .map(|m| m.len())
-    .sum()
+  debug!(
+    count = live.len(),
+    referenced = live.len() - declared,
+    "collected live hashes from snapshots"
+  );
+  Ok(live)
 }
 
 fn is_complete_build(path: &std::path::Path) -> bool {
@@ -94,25 +105,57 @@ fn is_complete_build(path: &std::path::Path) -> bool {
 }
 
 pub fn collect_garbage(dry_run: bool) -> Result<GcResult, GcError> {
+  // Only the current platform's builds are collected; builds of a store
+  // from before the partitioning wait for `sys store migrate`
+  let store = store_dir();
+
   let snapshot_store = SnapshotStore::default_store();
-  let live_hashes = collect_live_hashes(&snapshot_store)?;
+  // Builds and binds of applies still running aren't in a snapshot yet
+  let in_progress = live_temp_roots();
+  let live_hashes = collect_live_hashes(&snapshot_store, &in_progress)?;
 
   let mut stats = GcStats::default();
   let mut deleted_paths = Vec::new();
 
-  let build_dir = store_dir().join("build");
+  let build_dir = builds_dir(&store);
   if build_dir.exists() {
-    sweep_builds(&build_dir, &live_hashes, dry_run, &mut stats, &mut deleted_paths)?;
+    sweep_builds(
+      &build_dir,
+      &live_hashes,
+      &in_progress,
+      dry_run,
+      &mut stats,
+      &mut deleted_paths,
+    )?;
   }
+  if !dry_run {
+    remove_dangling_links(&store.join("build"));
+  }
+
+  // The inputs and downloads caches are shared with the user or system store,
+  // whose snapshots a selected store root (e.g. a project's .syslua) can't see
+  let shared_caches = store_root().is_none();
 
   let inputs_cache = cache_dir().join("inputs").join("store");
-  if inputs_cache.exists() {
+  if shared_caches && inputs_cache.exists() {
     sweep_inputs_cache(&inputs_cache, &live_hashes, dry_run, &mut stats, &mut deleted_paths)?;
   }
 
+  // Downloads aren't tied to snapshots; drop the ones that haven't been used lately
+  let downloads = downloads_dir();
+  if shared_caches && downloads.exists() {
+    for (path, size) in sweep_stale(&downloads, MAX_UNUSED_AGE, dry_run)? {
+      debug!(path = %path.display(), "removing stale download");
+      stats.downloads_deleted += 1;
+      stats.downloads_bytes_freed += size;
+      deleted_paths.push(path);
+    }
+  }
+
   info!(
     builds_deleted = stats.builds_deleted,
     inputs_deleted = stats.inputs_deleted,
+    downloads_deleted = stats.downloads_deleted,
     bytes_freed = stats.total_bytes_freed(),
     dry_run,
     "garbage collection complete"
@@ -124,6 +167,7 @@ pub fn collect_garbage(dry_run: bool) -> Result<GcResult, GcError> {
 fn sweep_builds(
   build_dir: &std::path::Path,
   live_hashes: &HashSet<String>,
+  in_progress: &HashSet<String>,
   dry_run: bool,
   stats: &mut GcStats,
   deleted_paths: &mut Vec<PathBuf>,
@@ -143,6 +187,11 @@ fn sweep_builds(
       None => continue,
     };
 
+    // A build an apply is realizing has no completion marker yet
+    if in_progress.contains(&dir_name) {
+      continue;
+    }
+
     let is_live = live_hashes.contains(&dir_name);
     let is_complete = is_complete_build(&path);
 
@@ -163,7 +212,8 @@ fn sweep_builds(
       stats.builds_bytes_freed += size;
       deleted_paths.push(path);
     } else {
-      match fs::remove_dir_all(&path) {
+      // Realized builds are read-only
+      match remove_store_path(&path) {
         Ok(()) => {
           stats.builds_deleted += 1;
           stats.builds_bytes_freed += size;
@@ -179,6 +229,22 @@ fn sweep_builds(
   Ok(())
 }
 
+/// Remove the links migrations left at the legacy paths of deleted builds.
+fn remove_dangling_links(legacy_dir: &std::path::Path) {
+  let Ok(entries) = fs::read_dir(legacy_dir) else {
+    return;
+  };
+  for path in entries.flatten().map(|entry| entry.path()) {
+    let is_link = path.symlink_metadata().is_ok_and(|meta| meta.file_type().is_symlink());
+    if is_link && !path.exists() {
+      // Windows junctions are removed like directories
+      if let Err(e) = fs::remove_file(&path).or_else(|_| fs::remove_dir(&path)) {
+        warn!(path = %path.display(), error = %e, "failed to remove dangling build link");
+      }
+    }
+  }
+}
+
 fn sweep_inputs_cache(
   cache_dir: &std::path::Path,
   live_hashes: &HashSet<String>,
@@ -242,6 +308,7 @@ fn extract_hash_from_cache_name(name: &str) -> String {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::build::store::{migrate_legacy_builds, store_triple};
 
   #[test]
   fn test_extract_hash_from_cache_name() {
@@ -262,9 +329,124 @@ mod tests {
       inputs_scanned: 5,
       inputs_deleted: 2,
       inputs_bytes_freed: 500,
+      downloads_deleted: 1,
+      downloads_bytes_freed: 250,
     };
 
-    assert_eq!(stats.total_deleted(), 5);
-    assert_eq!(stats.total_bytes_freed(), 1500);
+    assert_eq!(stats.total_deleted(), 6);
+    assert_eq!(stats.total_bytes_freed(), 1750);
+  }
+
+  #[test]
+  #[serial_test::serial]
+  fn gc_keeps_builds_of_running_applies() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let root = temp.path().join("root");
+    let cache = temp.path().join("cache");
+
+    temp_env::with_vars(
+      [
+        ("SYSLUA_ROOT", Some(root.to_str().unwrap())),
+        ("SYSLUA_STORE", None),
+        ("SYSLUA_SNAPSHOTS", None),
+        ("XDG_CACHE_HOME", Some(cache.to_str().unwrap())),
+        ("LOCALAPPDATA", Some(cache.to_str().unwrap())),
+      ],
+      || {
+        let build_dir = builds_dir(&store_dir());
+        let realizing = build_dir.join("abcdef0123456789abcd");
+        let abandoned = build_dir.join("0123456789abcdef0123");
+        fs::create_dir_all(&realizing).unwrap();
+        fs::create_dir_all(&abandoned).unwrap();
+
+        let roots = roots::TempRoots::create().unwrap();
+        roots
+          .add(&crate::util::hash::ObjectHash("abcdef0123456789abcd".to_string()))
+          .unwrap();
+
+        let result = collect_garbage(false).unwrap();
+        assert_eq!(result.deleted_paths, vec![abandoned.clone()]);
+        assert!(realizing.exists());
+
+        // Once the apply is done, its roots no longer protect anything
+        drop(roots);
+        collect_garbage(false).unwrap();
+        assert!(!realizing.exists());
+      },
+    );
+  }
+
+  #[test]
+  #[serial_test::serial]
+  fn gc_deletes_read_only_builds() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let root = temp.path().join("root");
+    let cache = temp.path().join("cache");
+
+    temp_env::with_vars(
+      [
+        ("SYSLUA_ROOT", Some(root.to_str().unwrap())),
+        ("SYSLUA_STORE", None),
+        ("SYSLUA_SNAPSHOTS", None),
+        ("XDG_CACHE_HOME", Some(cache.to_str().unwrap())),
+        ("LOCALAPPDATA", Some(cache.to_str().unwrap())),
+      ],
+      || {
+        let build = builds_dir(&store_dir()).join("abcdef0123456789abcd");
+        fs::create_dir_all(build.join("bin")).unwrap();
+        fs::write(build.join("bin").join("tool"), "#!/bin/sh\n").unwrap();
+        fs::write(
+          build.join(BUILD_COMPLETE_MARKER),
+          r#"{"version":1,"status":"complete"}"#,
+        )
+        .unwrap();
+        crate::platform::make_immutable(&build).unwrap();
+
+        let result = collect_garbage(false).unwrap();
+        assert_eq!(result.deleted_paths, vec![build.clone()]);
+        assert!(!build.exists());
+      },
+    );
+  }
+
+  #[test]
+  #[serial_test::serial]
+  fn gc_leaves_legacy_builds_and_removes_links_to_migrated_ones() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let root = temp.path().join("root");
+    let cache = temp.path().join("cache");
+
+    temp_env::with_vars(
+      [
+        ("SYSLUA_ROOT", Some(root.to_str().unwrap())),
+        ("SYSLUA_STORE", None),
+        ("SYSLUA_SNAPSHOTS", None),
+        ("XDG_CACHE_HOME", Some(cache.to_str().unwrap())),
+        ("LOCALAPPDATA", Some(cache.to_str().unwrap())),
+      ],
+      || {
+        let legacy = store_dir().join("build").join("abcdef0123456789abcd");
+        fs::create_dir_all(&legacy).unwrap();
+        let other_platform =
+          crate::build::store::platform_build_dir(&store_dir(), "riscv64-plan9").join("0123456789abcdef0123");
+        fs::create_dir_all(&other_platform).unwrap();
+
+        // Legacy builds belong to an unknown platform until migrated
+        let result = collect_garbage(false).unwrap();
+        assert!(result.deleted_paths.is_empty());
+        assert!(legacy.exists());
+
+        migrate_legacy_builds(&store_dir(), &store_triple()).unwrap();
+        let migrated = builds_dir(&store_dir()).join("abcdef0123456789abcd");
+        let result = collect_garbage(false).unwrap();
+        assert_eq!(result.deleted_paths, vec![migrated.clone()]);
+        assert!(!migrated.exists());
+        assert!(
+          legacy.symlink_metadata().is_err(),
+          "the link to the deleted build is removed"
+        );
+        assert!(other_platform.exists());
+      },
+    );
   }
 }
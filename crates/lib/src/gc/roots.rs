@@ -0,0 +1,222 @@
+//! Temporary GC roots of in-progress applies.
+//!
+//! A build an apply has just realized isn't referenced by any snapshot until
+//! the apply saves its own, so a concurrent `sys gc` would see it as garbage.
+//! While executing, an apply records the hash of every build and bind it
+//! realizes in a roots file of its own under `store/roots/`:
+//!
+//! ```text
+//! store/roots/
+//! ├── <pid>-<nanos>.lock    # Held locked by the owning process while it runs
+//! └── <pid>-<nanos>.roots   # One build or bind hash per line
+//! ```
+//!
+//! GC keeps every hash listed in a roots file whose lock is held. The files
+//! are removed when the apply finishes; files left by a process that died are
+//! recognized by their unlocked lock file and removed by the next GC.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::{debug, warn};
+
+use crate::platform::paths::store_dir;
+use crate::store_lock::{LockMode, try_lock};
+use crate::util::hash::ObjectHash;
+
+const ROOTS_EXTENSION: &str = "roots";
+const LOCK_EXTENSION: &str = "lock";
+
+/// Directory holding the roots files of running processes.
+pub fn roots_dir() -> PathBuf {
+  store_dir().join("roots")
+}
+
+/// The temporary GC roots of this process.
+///
+/// The default registers nothing, for executions that aren't part of an apply.
+#[derive(Debug, Clone, Default)]
+pub struct TempRoots {
+  file: Option<Arc<RootsFile>>,
+}
+
+#[derive(Debug)]
+struct RootsFile {
+  roots_path: PathBuf,
+  lock_path: PathBuf,
+  /// Kept open to hold the lock for the lifetime of the roots.
+  _lock: File,
+  roots: Mutex<(File, HashSet<String>)>,
+}
+
+impl TempRoots {
+  /// Create a roots file for this process in the store.
+  pub fn create() -> io::Result<Self> {
+    let dir = roots_dir();
+    fs::create_dir_all(&dir)?;
+
+    let nanos = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_nanos();
+    let name = format!("{}-{}", std::process::id(), nanos);
+    let lock_path = dir.join(&name).with_extension(LOCK_EXTENSION);
+    let roots_path = dir.join(&name).with_extension(ROOTS_EXTENSION);
+
+    // Lock before the roots file exists, so GC never sees it unlocked
+    let lock = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(&lock_path)?;
+    try_lock(&lock, LockMode::Exclusive)?;
+    let roots = OpenOptions::new().append(true).create(true).open(&roots_path)?;
+
+    debug!(path = %roots_path.display(), "created temporary gc roots");
+    Ok(Self {
+      file: Some(Arc::new(RootsFile {
+        roots_path,
+        lock_path,
+        _lock: lock,
+        roots: Mutex::new((roots, HashSet::new())),
+      })),
+    })
+  }
+
+  /// Protect the build or bind `hash` from GC until the roots are dropped.
+  pub fn add(&self, hash: &ObjectHash) -> io::Result<()> {
+    let Some(file) = &self.file else {
+      return Ok(());
+    };
+
+    let mut roots = file.roots.lock().unwrap();
+    let (roots_file, added) = &mut *roots;
+    if added.insert(hash.0.clone()) {
+      roots_file.write_all(format!("{}\n", hash.0).as_bytes())?;
+      roots_file.flush()?;
+    }
+    Ok(())
+  }
+
+  /// Path of the roots file, if there is one.
+  pub fn path(&self) -> Option<&Path> {
+    self.file.as_ref().map(|file| file.roots_path.as_path())
+  }
+}
+
+impl Drop for RootsFile {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.roots_path);
+    let _ = fs::remove_file(&self.lock_path);
+  }
+}
+
+/// Hashes listed in the roots files of running processes.
+///
+/// Roots files of processes that died are removed.
+pub fn live_temp_roots() -> HashSet<String> {
+  let mut live = HashSet::new();
+
+  let Ok(entries) = fs::read_dir(roots_dir()) else {
+    return live;
+  };
+
+  for entry in entries.flatten() {
+    let roots_path = entry.path();
+    if roots_path.extension().and_then(|e| e.to_str()) != Some(ROOTS_EXTENSION) {
+      continue;
+    }
+    let lock_path = roots_path.with_extension(LOCK_EXTENSION);
+
+    if is_stale(&lock_path) {
+      debug!(path = %roots_path.display(), "removing gc roots of a process that exited");
+      let _ = fs::remove_file(&roots_path);
+      let _ = fs::remove_file(&lock_path);
+      continue;
+    }
+
+    match fs::read_to_string(&roots_path) {
+      Ok(content) => {
+        for hash in content.lines().map(str::trim).filter(|line| !line.is_empty()) {
+          let hash = ObjectHash(hash.to_string());
+          live.extend(hash.shorter_forms().map(|short| short.0));
+          live.insert(hash.0);
+        }
+      }
+      Err(e) => {
+        warn!(path = %roots_path.display(), error = %e, "failed to read gc roots");
+      }
+    }
+  }
+
+  live
+}
+
+/// Whether the process owning `lock_path` is gone, i.e. the lock can be taken.
+fn is_stale(lock_path: &Path) -> bool {
+  match OpenOptions::new().read(true).write(true).open(lock_path) {
+    Ok(lock) => try_lock(&lock, LockMode::Exclusive).is_ok(),
+    Err(e) => e.kind() == io::ErrorKind::NotFound,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serial_test::serial;
+  use tempfile::TempDir;
+
+  fn with_temp_store<F: FnOnce()>(f: F) {
+    let temp = TempDir::new().unwrap();
+    temp_env::with_var("SYSLUA_STORE", Some(temp.path().to_str().unwrap()), f);
+  }
+
+  #[test]
+  #[serial]
+  fn registered_hashes_are_live_until_dropped() {
+    with_temp_store(|| {
+      let roots = TempRoots::create().unwrap();
+      let clone = roots.clone();
+      roots.add(&ObjectHash("abcdef0123456789abcd".to_string())).unwrap();
+      clone.add(&ObjectHash("abcdef0123456789abcd".to_string())).unwrap();
+
+      let path = roots.path().unwrap().to_path_buf();
+      assert_eq!(fs::read_to_string(&path).unwrap(), "abcdef0123456789abcd\n");
+      assert!(live_temp_roots().contains("abcdef0123456789abcd"));
+
+      drop(roots);
+      assert!(path.exists());
+      drop(clone);
+      assert!(!path.exists());
+      assert!(live_temp_roots().is_empty());
+    });
+  }
+
+  #[test]
+  #[serial]
+  fn roots_of_exited_processes_are_removed() {
+    with_temp_store(|| {
+      let dir = roots_dir();
+      fs::create_dir_all(&dir).unwrap();
+      let roots_path = dir.join("1-1.roots");
+      fs::write(&roots_path, "abcdef0123456789abcd\n").unwrap();
+      fs::write(dir.join("1-1.lock"), "").unwrap();
+
+      assert!(live_temp_roots().is_empty());
+      assert!(!roots_path.exists());
+      assert!(!dir.join("1-1.lock").exists());
+    });
+  }
+
+  #[test]
+  fn default_roots_register_nothing() {
+    let roots = TempRoots::default();
+    roots.add(&ObjectHash("abc".to_string())).unwrap();
+    assert!(roots.path().is_none());
+  }
+}
@@ -14,6 +14,7 @@ use std::path::{Path, PathBuf};
 use thiserror::Error;
 use tracing::warn;
 
+use crate::build::store::builds_dir;
 use crate::platform::paths::{cache_dir, data_dir, root_dir};
 
 pub use templates::{GLOBALS_D_LUA, INIT_LUA_TEMPLATE, LUARC_JSON_TEMPLATE};
@@ -102,8 +103,8 @@ pub fn init(options: &InitOptions) -> Result<InitResult, InitError> {
   let snapshots_dir = base_dir.join("snapshots");
 
   // Create store structure
-  fs::create_dir_all(store_dir.join("build")).map_err(|e| InitError::CreateDir {
-    path: store_dir.join("build"),
+  fs::create_dir_all(builds_dir(&store_dir)).map_err(|e| InitError::CreateDir {
+    path: builds_dir(&store_dir),
     source: e,
   })?;
   fs::create_dir_all(store_dir.join("bind")).map_err(|e| InitError::CreateDir {
@@ -441,4 +442,10 @@ mod tests {
       },
     );
   }
+
+  #[test]
+  fn template_declares_the_current_api_version() {
+    let declaration = format!("sys.api({})", crate::lua::api::API_VERSION);
+    assert!(INIT_LUA_TEMPLATE.contains(&declaration), "{}", declaration);
+  }
 }
@@ -0,0 +1,303 @@
+//! Archive fetcher for `archive:` inputs.
+//!
+//! Downloads a tarball over HTTP(S) and unpacks it:
+//!
+//! ```text
+//! archive:https://example.com/pkg-1.0.tar.gz
+//! archive:https://example.com/pkg-1.0.tar.gz#<sha256>
+//! ```
+//!
+//! The revision is the SHA-256 of the downloaded archive, so a locked input
+//! fails to resolve if the server starts returning different content.
+//! Credentials from [`FetchAuth`] are attached to HTTPS requests only.
+//! Downloads are bounded by [`FetchTimeouts`] and stop when fetches are
+//! [cancelled](super::fetch::cancel_fetches).
+//!
+//! # Cache Structure
+//!
+//! Archives are unpacked to `~/.cache/syslua/inputs/archive/{name}-{sha256}/`.
+//! A single top-level directory in the archive (as in most release tarballs)
+//! is stripped. A pinned revision that is already cached is not downloaded again.
+//!
+//! Unpacking uses the system `tar` ([`extract_command`]), which handles
+//! `.tar`, `.tar.gz`, `.tar.xz` and `.tar.bz2` (and `.zip` where `tar` is
+//! bsdtar, as on Windows and macOS).
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+use tracing::{debug, info};
+
+use super::fetch::{Credentials, FetchAuth, FetchError, FetchTimeouts, Fetcher, fetches_cancelled};
+use crate::platform::archive::extract_command;
+
+/// Fetcher for `archive:` inputs.
+pub struct ArchiveFetcher {
+  auth: FetchAuth,
+  timeouts: FetchTimeouts,
+}
+
+impl ArchiveFetcher {
+  /// Create an archive fetcher using `auth` for HTTPS downloads.
+  pub fn new(auth: FetchAuth) -> Self {
+    Self {
+      auth,
+      timeouts: FetchTimeouts::default(),
+    }
+  }
+
+  /// Bound each download by `timeouts`.
+  pub fn with_timeouts(mut self, timeouts: FetchTimeouts) -> Self {
+    self.timeouts = timeouts;
+    self
+  }
+
+  /// Download `url`, attaching credentials for HTTPS hosts.
+  fn download(&self, url: &str) -> Result<Vec<u8>, FetchError> {
+    let download_error = |message: String| FetchError::Download {
+      url: url.to_string(),
+      message,
+    };
+
+    let parsed = reqwest::Url::parse(url).map_err(|e| download_error(e.to_string()))?;
+    let credentials = match (parsed.scheme(), parsed.host_str()) {
+      ("https", Some(host)) => self.auth.credentials_for(host)?,
+      ("http", _) => None,
+      (scheme, _) => return Err(download_error(format!("unsupported URL scheme '{}'", scheme))),
+    };
+
+    // Inputs resolve synchronously, sometimes from inside an async caller, so
+    // the download gets its own thread and runtime.
+    std::thread::scope(|scope| {
+      scope
+        .spawn(|| {
+          let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| download_error(e.to_string()))?;
+          rt.block_on(async {
+            tokio::select! {
+              result = get(parsed, credentials, &self.timeouts) => result.map_err(|e| {
+                if e.is_timeout() {
+                  FetchError::TimedOut {
+                    url: url.to_string(),
+                    timeout: self.timeouts.total,
+                  }
+                } else {
+                  download_error(e.to_string())
+                }
+              }),
+              _ = cancelled() => Err(FetchError::Cancelled),
+            }
+          })
+        })
+        .join()
+        .unwrap_or_else(|_| Err(download_error("download thread panicked".to_string())))
+    })
+  }
+}
+
+impl Fetcher for ArchiveFetcher {
+  fn scheme(&self) -> &str {
+    "archive"
+  }
+
+  fn fetch(&self, name: &str, url: &str, rev: Option<&str>, cache_dir: &Path) -> Result<(PathBuf, String), FetchError> {
+    let archives_dir = cache_dir.join("archive");
+
+    if let Some(rev) = rev {
+      let cached = archives_dir.join(format!("{}-{}", name, rev));
+      if cached.is_dir() {
+        debug!(name, rev, path = %cached.display(), "using cached archive");
+        return Ok((cached, rev.to_string()));
+      }
+    }
+
+    fs::create_dir_all(&archives_dir).map_err(|e| FetchError::CreateCacheDir(archives_dir.clone(), e))?;
+
+    info!(name, url, "downloading archive");
+    let bytes = self.download(url)?;
+    let actual = hex::encode(Sha256::digest(&bytes));
+
+    if let Some(expected) = rev
+      && expected != actual
+    {
+      return Err(FetchError::HashMismatch {
+        url: url.to_string(),
+        expected: expected.to_string(),
+        actual,
+      });
+    }
+
+    let dest = archives_dir.join(format!("{}-{}", name, actual));
+    if !dest.is_dir() {
+      unpack(&bytes, &archives_dir, &dest)?;
+    }
+
+    debug!(name, rev = %actual, path = %dest.display(), "unpacked archive");
+    Ok((dest, actual))
+  }
+}
+
+async fn get(
+  url: reqwest::Url,
+  credentials: Option<Credentials>,
+  timeouts: &FetchTimeouts,
+) -> Result<Vec<u8>, reqwest::Error> {
+  let client = reqwest::Client::builder()
+    .connect_timeout(timeouts.connect)
+    .timeout(timeouts.total)
+    .build()?;
+  let request = client.get(url);
+  let request = match credentials {
+    Some(Credentials::Bearer(token)) => request.bearer_auth(token),
+    Some(Credentials::Basic { login, password }) => request.basic_auth(login, Some(password)),
+    None => request,
+  };
+
+  let response = request.send().await?.error_for_status()?;
+  Ok(response.bytes().await?.to_vec())
+}
+
+/// Resolves once fetches are cancelled.
+async fn cancelled() {
+  while !fetches_cancelled() {
+    tokio::time::sleep(Duration::from_millis(50)).await;
+  }
+}
+
+/// Unpack `bytes` into `dest`, staging inside `archives_dir` so `dest` only
+/// appears once extraction succeeded.
+fn unpack(bytes: &[u8], archives_dir: &Path, dest: &Path) -> Result<(), FetchError> {
+  let io_error = |path: &Path| {
+    let path = path.to_path_buf();
+    move |source| FetchError::Io { path, source }
+  };
+
+  let staging = tempfile::Builder::new()
+    .prefix(".unpack-")
+    .tempdir_in(archives_dir)
+    .map_err(io_error(archives_dir))?;
+  let archive = staging.path().join("archive");
+  let out = staging.path().join("out");
+  fs::write(&archive, bytes).map_err(io_error(&archive))?;
+  fs::create_dir(&out).map_err(io_error(&out))?;
+
+  let output = extract_command(&archive, &out)
+    .output()
+    .map_err(|e| FetchError::Extract {
+      path: archive.clone(),
+      message: e.to_string(),
+    })?;
+  if !output.status.success() {
+    return Err(FetchError::Extract {
+      path: archive,
+      message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    });
+  }
+
+  // Strip a single top-level directory
+  let entries = fs::read_dir(&out)
+    .and_then(|entries| entries.map(|e| e.map(|e| e.path())).collect::<Result<Vec<_>, _>>())
+    .map_err(io_error(&out))?;
+  let root = match entries.as_slice() {
+    [only] if only.is_dir() => only.clone(),
+    _ => out,
+  };
+
+  fs::rename(&root, dest).map_err(io_error(dest))?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::{Read, Write};
+  use std::net::TcpListener;
+  use std::process::Command;
+  use tempfile::TempDir;
+
+  /// Serve `body` for a single HTTP request and return the URL.
+  fn serve_once(body: Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+      if let Ok((mut stream, _)) = listener.accept() {
+        let mut buf = [0u8; 4096];
+        let _ = stream.read(&mut buf);
+        let header = format!(
+          "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+          body.len()
+        );
+        let _ = stream.write_all(header.as_bytes());
+        let _ = stream.write_all(&body);
+      }
+    });
+    format!("http://{}/pkg-1.0.tar.gz", addr)
+  }
+
+  /// Build a gzipped tarball containing `pkg-1.0/init.lua`.
+  fn make_tarball(dir: &Path) -> Vec<u8> {
+    let src = dir.join("pkg-1.0");
+    fs::create_dir_all(&src).unwrap();
+    fs::write(src.join("init.lua"), "return {}").unwrap();
+    let tarball = dir.join("pkg.tar.gz");
+    let status = Command::new("tar")
+      .arg("-czf")
+      .arg(&tarball)
+      .arg("-C")
+      .arg(dir)
+      .arg("pkg-1.0")
+      .status()
+      .expect("tar failed");
+    assert!(status.success());
+    fs::read(tarball).unwrap()
+  }
+
+  #[test]
+  fn fetch_unpacks_and_strips_top_level_dir() {
+    let temp = TempDir::new().unwrap();
+    let bytes = make_tarball(temp.path());
+    let expected_rev = hex::encode(Sha256::digest(&bytes));
+    let url = serve_once(bytes);
+
+    let fetcher = ArchiveFetcher::new(FetchAuth::default());
+    let (path, rev) = fetcher.fetch("pkg", &url, None, &temp.path().join("cache")).unwrap();
+
+    assert_eq!(rev, expected_rev);
+    assert!(path.join("init.lua").exists());
+  }
+
+  #[test]
+  fn fetch_uses_cache_for_pinned_rev() {
+    let temp = TempDir::new().unwrap();
+    let cache = temp.path().join("cache");
+    let bytes = make_tarball(temp.path());
+    let url = serve_once(bytes);
+
+    let fetcher = ArchiveFetcher::new(FetchAuth::default());
+    let (path, rev) = fetcher.fetch("pkg", &url, None, &cache).unwrap();
+
+    // The server only answers once, so this must come from the cache
+    let (cached, cached_rev) = fetcher.fetch("pkg", &url, Some(&rev), &cache).unwrap();
+    assert_eq!(cached, path);
+    assert_eq!(cached_rev, rev);
+  }
+
+  #[test]
+  fn fetch_rejects_hash_mismatch() {
+    let temp = TempDir::new().unwrap();
+    let url = serve_once(make_tarball(temp.path()));
+
+    let fetcher = ArchiveFetcher::new(FetchAuth::default());
+    let result = fetcher.fetch("pkg", &url, Some("0000"), &temp.path().join("cache"));
+
+    assert!(
+      matches!(result, Err(FetchError::HashMismatch { .. })),
+      "expected HashMismatch, got {:?}",
+      result
+    );
+  }
+}
@@ -0,0 +1,378 @@
+//! Lock file auditing and pruning.
+//!
+//! A lock file accumulates entries over time: inputs removed from the config
+//! or no longer declared by the inputs depending on them keep their pins, and
+//! the same source can end up locked under several labels. [`audit_lock`]
+//! compares a lock file against the inputs a resolution actually used and
+//! reports:
+//!
+//! - **stale** entries for inputs nothing declares anymore (root or transitive)
+//! - **orphaned** nodes no entry refers to
+//! - **duplicate** nodes pinning the same source as another node
+//! - **dangling** entries referring to a node the lock file doesn't contain
+//! - **mismatched** transitive entries pinning another revision than the
+//!   depending input's own `syslua.lock`
+//!
+//! [`prune_lock`] removes everything but mismatches, none of which changes
+//! the revision any used input resolves to. A mismatch is only reported:
+//! fixing it changes a pin, which `sys update <input>` does.
+//!
+//! `sys update --prune` prunes after updating, and `sys lock audit` reports
+//! without changing anything.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use super::ResolvedInputs;
+use super::lock::{LOCK_FILENAME, LockFile, load_input_lock};
+
+/// What is wrong with a lock entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LockIssueKind {
+  /// No input with this path is declared anymore.
+  Stale,
+  /// No entry refers to this node.
+  Orphaned,
+  /// The node pins the same source as node `of`.
+  Duplicate { of: String },
+  /// The entry refers to node `label`, which doesn't exist.
+  Dangling { label: String },
+  /// The depending input's own lock file pins `expected` instead of `locked`.
+  Mismatched {
+    lock_file: PathBuf,
+    locked: String,
+    expected: String,
+  },
+}
+
+/// A problem found in a lock file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LockIssue {
+  /// Input path (e.g. `pkgs/utils`) of an entry, or label of a node.
+  pub key: String,
+  #[serde(flatten)]
+  pub kind: LockIssueKind,
+}
+
+impl LockIssue {
+  /// Whether [`prune_lock`] removes this issue.
+  pub fn is_prunable(&self) -> bool {
+    !matches!(self.kind, LockIssueKind::Mismatched { .. })
+  }
+
+  /// Short description of the issue.
+  pub fn describe(&self) -> String {
+    match &self.kind {
+      LockIssueKind::Stale => "no longer declared by the config or its inputs".to_string(),
+      LockIssueKind::Orphaned => "unused lock node".to_string(),
+      LockIssueKind::Duplicate { of } => format!("same source as {}", of),
+      LockIssueKind::Dangling { label } => format!("refers to missing lock node {}", label),
+      LockIssueKind::Mismatched {
+        lock_file,
+        locked,
+        expected,
+      } => format!(
+        "locked at {} but {} pins {}",
+        short_rev(locked),
+        lock_file.display(),
+        short_rev(expected)
+      ),
+    }
+  }
+}
+
+fn short_rev(rev: &str) -> &str {
+  &rev[..rev.len().min(8)]
+}
+
+/// Issues found by [`audit_lock`], entries before nodes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LockAudit {
+  pub issues: Vec<LockIssue>,
+}
+
+impl LockAudit {
+  /// Whether the lock file has no issues.
+  pub fn is_clean(&self) -> bool {
+    self.issues.is_empty()
+  }
+
+  /// Number of issues [`prune_lock`] removes.
+  pub fn prunable(&self) -> usize {
+    self.issues.iter().filter(|issue| issue.is_prunable()).count()
+  }
+}
+
+/// Audit a lock file against the inputs a resolution used.
+///
+/// `resolved` must come from a complete resolution of the config the lock
+/// file belongs to; an input missing from it is reported as stale.
+pub fn audit_lock(lock: &LockFile, resolved: &ResolvedInputs) -> LockAudit {
+  let v1 = lock.as_v1();
+  let mut issues = Vec::new();
+
+  let mut used = BTreeSet::new();
+  collect_used_paths("", resolved, &mut used);
+
+  // Entries: every input path is an entry of the root node
+  let entries = v1.root_node().map(|root| root.inputs.clone()).unwrap_or_default();
+  for (key, label) in &entries {
+    if !used.contains(key) {
+      issues.push(LockIssue {
+        key: key.clone(),
+        kind: LockIssueKind::Stale,
+      });
+    } else if !v1.nodes.contains_key(label) {
+      issues.push(LockIssue {
+        key: key.clone(),
+        kind: LockIssueKind::Dangling { label: label.clone() },
+      });
+    }
+  }
+  collect_mismatches("", resolved, lock, &mut issues);
+
+  // Nodes
+  let reachable = v1.collect_reachable_nodes();
+  let mut sources: BTreeMap<(&Option<String>, &Option<String>, &Option<String>, &Option<String>), &str> =
+    BTreeMap::new();
+  for (label, node) in &v1.nodes {
+    if node.is_root() {
+      continue;
+    }
+    if !reachable.contains(label) {
+      issues.push(LockIssue {
+        key: label.clone(),
+        kind: LockIssueKind::Orphaned,
+      });
+      continue;
+    }
+    let source = (&node.type_, &node.url, &node.rev, &node.tag);
+    match sources.get(&source) {
+      Some(of) if node.inputs.is_empty() && v1.nodes[*of].inputs.is_empty() => issues.push(LockIssue {
+        key: label.clone(),
+        kind: LockIssueKind::Duplicate { of: of.to_string() },
+      }),
+      Some(_) => {}
+      None => {
+        sources.insert(source, label.as_str());
+      }
+    }
+  }
+
+  LockAudit { issues }
+}
+
+/// Collect the lock keys (`name`, `parent/name`, ...) of resolved inputs.
+fn collect_used_paths(parent: &str, inputs: &ResolvedInputs, used: &mut BTreeSet<String>) {
+  for (name, resolved) in inputs {
+    let path = lock_key(parent, name);
+    collect_used_paths(&path, &resolved.inputs, used);
+    used.insert(path);
+  }
+}
+
+/// Compare transitive entries with the lock files of the inputs declaring them.
+fn collect_mismatches(parent: &str, inputs: &ResolvedInputs, lock: &LockFile, issues: &mut Vec<LockIssue>) {
+  for (name, resolved) in inputs {
+    let path = lock_key(parent, name);
+    if let Some(input_lock) = load_input_lock(&resolved.path) {
+      for dep in resolved.inputs.keys() {
+        let key = lock_key(&path, dep);
+        if let (Some(locked), Some(expected)) = (lock.get(&key), input_lock.get(dep))
+          && locked.rev != expected.rev
+        {
+          issues.push(LockIssue {
+            key,
+            kind: LockIssueKind::Mismatched {
+              lock_file: resolved.path.join(LOCK_FILENAME),
+              locked: locked.rev,
+              expected: expected.rev,
+            },
+          });
+        }
+      }
+    }
+    collect_mismatches(&path, &resolved.inputs, lock, issues);
+  }
+}
+
+fn lock_key(parent: &str, name: &str) -> String {
+  if parent.is_empty() {
+    name.to_string()
+  } else {
+    format!("{}/{}", parent, name)
+  }
+}
+
+/// Remove the prunable issues of `audit` from the lock file.
+///
+/// Entries of duplicate nodes are pointed at the node they duplicate. Returns
+/// the number of entries and nodes removed, including nodes only the removed
+/// entries referred to.
+pub fn prune_lock(lock: &mut LockFile, audit: &LockAudit) -> usize {
+  let v1 = lock.as_v1_mut();
+  let mut removed = 0;
+
+  for issue in &audit.issues {
+    match &issue.kind {
+      LockIssueKind::Stale | LockIssueKind::Dangling { .. } => {
+        if let Some(root) = v1.root_node_mut()
+          && root.inputs.remove(&issue.key).is_some()
+        {
+          removed += 1;
+        }
+      }
+      LockIssueKind::Duplicate { of } => {
+        for node in v1.nodes.values_mut() {
+          for label in node.inputs.values_mut() {
+            if *label == issue.key {
+              *label = of.clone();
+            }
+          }
+        }
+        if v1.nodes.remove(&issue.key).is_some() {
+          removed += 1;
+        }
+      }
+      LockIssueKind::Orphaned => {
+        if v1.nodes.remove(&issue.key).is_some() {
+          removed += 1;
+        }
+      }
+      LockIssueKind::Mismatched { .. } => {}
+    }
+  }
+
+  // Nodes only stale entries referred to
+  removed + v1.remove_orphaned_nodes()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::inputs::ResolvedInput;
+  use crate::inputs::lock::LockedInput;
+  use tempfile::TempDir;
+
+  fn resolved(path: PathBuf, deps: &[(&str, ResolvedInput)]) -> ResolvedInput {
+    let mut input = ResolvedInput::new(path, "abc".to_string());
+    for (name, dep) in deps {
+      input.inputs.insert(name.to_string(), dep.clone());
+    }
+    input
+  }
+
+  fn lock_with(entries: &[(&str, &str, &str)]) -> LockFile {
+    let mut lock = LockFile::new();
+    for (key, url, rev) in entries {
+      lock.insert(key.to_string(), LockedInput::new("git", url, rev));
+    }
+    lock
+  }
+
+  #[test]
+  fn reports_and_prunes_stale_entries() {
+    let temp = TempDir::new().unwrap();
+    let mut lock = lock_with(&[
+      ("pkgs", "git:https://example.com/pkgs", "abc"),
+      ("pkgs/utils", "git:https://example.com/utils", "def"),
+      ("old", "git:https://example.com/old", "123"),
+      ("old/utils", "git:https://example.com/utils", "456"),
+    ]);
+    let mut inputs = ResolvedInputs::new();
+    inputs.insert(
+      "pkgs".to_string(),
+      resolved(
+        temp.path().to_path_buf(),
+        &[("utils", resolved(temp.path().to_path_buf(), &[]))],
+      ),
+    );
+
+    let audit = audit_lock(&lock, &inputs);
+    let stale: Vec<_> = audit
+      .issues
+      .iter()
+      .filter(|issue| issue.kind == LockIssueKind::Stale)
+      .map(|issue| issue.key.as_str())
+      .collect();
+    assert_eq!(stale, ["old", "old/utils"]);
+
+    // Removing the entries also removes their nodes
+    assert_eq!(prune_lock(&mut lock, &audit), 4);
+    assert_eq!(lock.input_names(), ["pkgs", "pkgs/utils"]);
+    assert_eq!(lock.as_v1().nodes.len(), 3);
+    assert!(audit_lock(&lock, &inputs).is_clean());
+  }
+
+  #[test]
+  fn merges_duplicate_nodes_and_removes_orphans() {
+    let temp = TempDir::new().unwrap();
+    let mut lock = lock_with(&[
+      ("a", "git:https://example.com/a", "abc"),
+      ("a/utils", "git:https://example.com/utils", "def"),
+      ("b", "git:https://example.com/b", "abc"),
+      ("b/utils", "git:https://example.com/utils", "def"),
+    ]);
+    lock.as_v1_mut().insert_node(
+      "leftover".to_string(),
+      crate::inputs::LockNode::input("git", "u", "r", None, BTreeMap::new()),
+    );
+    let dir = || temp.path().to_path_buf();
+    let mut inputs = ResolvedInputs::new();
+    inputs.insert("a".to_string(), resolved(dir(), &[("utils", resolved(dir(), &[]))]));
+    inputs.insert("b".to_string(), resolved(dir(), &[("utils", resolved(dir(), &[]))]));
+
+    let audit = audit_lock(&lock, &inputs);
+    let a_utils = lock.as_v1().get_root_input_label("a/utils").unwrap().to_string();
+    let b_utils = lock.as_v1().get_root_input_label("b/utils").unwrap().to_string();
+    assert!(audit.issues.contains(&LockIssue {
+      key: b_utils,
+      kind: LockIssueKind::Duplicate { of: a_utils.clone() },
+    }));
+    assert!(audit.issues.contains(&LockIssue {
+      key: "leftover".to_string(),
+      kind: LockIssueKind::Orphaned,
+    }));
+
+    prune_lock(&mut lock, &audit);
+    assert_eq!(lock.as_v1().get_root_input_label("b/utils"), Some(a_utils.as_str()));
+    assert_eq!(lock.get("b/utils").unwrap().rev, "def");
+    assert!(audit_lock(&lock, &inputs).is_clean());
+  }
+
+  #[test]
+  fn reports_entries_disagreeing_with_input_lock_files() {
+    let temp = TempDir::new().unwrap();
+    lock_with(&[("utils", "git:https://example.com/utils", "def")])
+      .save(&temp.path().join(LOCK_FILENAME))
+      .unwrap();
+    let mut lock = lock_with(&[
+      ("pkgs", "git:https://example.com/pkgs", "abc"),
+      ("pkgs/utils", "git:https://example.com/utils", "999"),
+    ]);
+    let mut inputs = ResolvedInputs::new();
+    inputs.insert(
+      "pkgs".to_string(),
+      resolved(
+        temp.path().to_path_buf(),
+        &[("utils", resolved(temp.path().join("utils"), &[]))],
+      ),
+    );
+
+    let audit = audit_lock(&lock, &inputs);
+    assert_eq!(audit.issues.len(), 1);
+    assert_eq!(audit.issues[0].key, "pkgs/utils");
+    assert!(matches!(
+      &audit.issues[0].kind,
+      LockIssueKind::Mismatched { locked, expected, .. } if locked == "999" && expected == "def"
+    ));
+
+    // Mismatches change a pin, so pruning leaves them alone
+    assert_eq!(audit.prunable(), 0);
+    assert_eq!(prune_lock(&mut lock, &audit), 0);
+    assert_eq!(lock.get("pkgs/utils").unwrap().rev, "999");
+  }
+}
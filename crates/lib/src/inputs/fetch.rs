@@ -1,23 +1,47 @@
-//! Git fetch and path resolution for inputs.
+//! Fetch backends and path resolution for inputs.
 //!
 //! This module handles:
+//! - The [`Fetcher`] trait and the [`Fetchers`] registry mapping URL schemes to backends
 //! - Cloning/fetching git repositories to the cache directory
 //! - Checking out specific revisions
+//! - Listing tags, to resolve `#semver:<range>` refs, through the cached
+//!   GitHub API lookups of [`RefsCache`] where possible
 //! - Resolving path inputs with tilde expansion
+//! - Network timeouts and cancellation of running fetches
+//!
+//! Built-in fetchers are `git:` ([`GitFetcher`]) and `archive:`
+//! ([`ArchiveFetcher`](super::archive::ArchiveFetcher)). `path:` inputs are
+//! resolved in place and never fetched.
 //!
 //! # Cache Structure
 //!
 //! Git inputs are cached at `~/.cache/syslua/inputs/{name}/` with their `.git`
 //! directories intact to enable incremental fetches.
+//!
+//! # Timeouts and Cancellation
+//!
+//! Each fetch is bounded by [`FetchTimeouts`] (`settings.fetch.connect_timeout`
+//! and `settings.fetch.timeout`), so a hung server fails its input instead of
+//! blocking resolution. [`cancel_fetches`] interrupts running fetches and fails
+//! later ones with [`FetchError::Cancelled`]; the CLI calls it on Ctrl-C via
+//! [`install_interrupt_handler`].
 
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
 use gix::remote::Direction;
 use thiserror::Error;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
-use crate::platform::paths::home_dir;
+use super::archive::ArchiveFetcher;
+use super::refs::{RefsCache, RefsSettings};
+use super::source::{InputSource, ParseError, parse, parse_fetcher};
+use crate::platform::paths::{cache_dir, home_dir};
 
 /// Errors that can occur during fetch operations.
 #[derive(Debug, Error)]
@@ -89,9 +113,508 @@ pub enum FetchError {
     #[source]
     source: Box<dyn std::error::Error + Send + Sync>,
   },
+
+  /// No fetcher is registered for the scheme.
+  #[error("no fetcher registered for '{0}:' inputs")]
+  NoFetcher(String),
+
+  /// The fetcher's inputs have no tags to match a semver range against.
+  #[error("'{0}:' inputs have no tags to match a semver range")]
+  TagsUnsupported(String),
+
+  /// Failed to list the tags of a repository.
+  #[error("failed to list tags of '{url}': {message}")]
+  ListTags { url: String, message: String },
+
+  /// Failed to look up the refs of a remote repository.
+  #[error("failed to look up refs of '{url}': {message}")]
+  RemoteRefs { url: String, message: String },
+
+  /// No tag is a version within the semver range.
+  #[error("no tag of '{url}' matches semver range '{range}'")]
+  NoMatchingTag { url: String, range: String },
+
+  /// Failed to download a URL.
+  #[error("failed to download '{url}': {message}")]
+  Download { url: String, message: String },
+
+  /// Downloaded content does not match the pinned hash.
+  #[error("hash mismatch for '{url}': expected {expected}, got {actual}")]
+  HashMismatch {
+    url: String,
+    expected: String,
+    actual: String,
+  },
+
+  /// Failed to unpack an archive.
+  #[error("failed to extract archive '{path}': {message}")]
+  Extract { path: PathBuf, message: String },
+
+  /// Failed to read a configured netrc file.
+  #[error("failed to read netrc file '{path}': {source}")]
+  ReadNetrc {
+    path: PathBuf,
+    #[source]
+    source: std::io::Error,
+  },
+
+  /// Filesystem error while populating the cache.
+  #[error("I/O error at '{path}': {source}")]
+  Io {
+    path: PathBuf,
+    #[source]
+    source: std::io::Error,
+  },
+
+  /// The fetch ran longer than `settings.fetch.timeout`.
+  #[error("fetching '{url}' timed out after {}s (see settings.fetch.timeout)", .timeout.as_secs())]
+  TimedOut { url: String, timeout: Duration },
+
+  /// The fetch was cancelled (Ctrl-C).
+  #[error("fetch cancelled")]
+  Cancelled,
+}
+
+/// A backend that fetches inputs for one URL scheme.
+///
+/// Implementations are registered in [`Fetchers`]; an input URL
+/// `<scheme>:<url>[#rev]` is handed to the fetcher whose [`scheme`](Self::scheme)
+/// matches. The scheme is recorded as the lock entry's `type`.
+pub trait Fetcher: Send + Sync {
+  /// The URL prefix (without `:`) this fetcher handles, e.g. `"git"`.
+  fn scheme(&self) -> &str;
+
+  /// Fetch `url` into `cache_dir`.
+  ///
+  /// `rev` is the revision from the URL or lock file, or `None` to fetch the
+  /// latest. Returns the local path of the fetched input and the revision to
+  /// pin in the lock file.
+  fn fetch(&self, name: &str, url: &str, rev: Option<&str>, cache_dir: &Path) -> Result<(PathBuf, String), FetchError>;
+
+  /// List the tags of `url`, fetching it into `cache_dir` if needed.
+  ///
+  /// Used to resolve `#semver:<range>` revisions. Fetchers without tags
+  /// keep the default, which fails with [`FetchError::TagsUnsupported`].
+  fn tags(&self, name: &str, url: &str, cache_dir: &Path) -> Result<Vec<String>, FetchError> {
+    let _ = (name, url, cache_dir);
+    Err(FetchError::TagsUnsupported(self.scheme().to_string()))
+  }
+}
+
+/// Registry of fetchers by scheme.
+///
+/// [`Fetchers::default`] holds the built-in `git` and `archive` fetchers
+/// without credentials. Registering a fetcher for an existing scheme replaces it.
+#[derive(Clone)]
+pub struct Fetchers {
+  fetchers: BTreeMap<String, Arc<dyn Fetcher>>,
+}
+
+impl Default for Fetchers {
+  fn default() -> Self {
+    Self::with_settings(FetchSettings::default())
+  }
+}
+
+impl Fetchers {
+  /// An empty registry (only `path:` inputs resolve).
+  pub fn empty() -> Self {
+    Self {
+      fetchers: BTreeMap::new(),
+    }
+  }
+
+  /// The built-in fetchers, with `auth` used for archive downloads.
+  pub fn with_auth(auth: FetchAuth) -> Self {
+    Self::with_settings(FetchSettings {
+      auth,
+      ..Default::default()
+    })
+  }
+
+  /// The built-in fetchers, configured from `settings.fetch`.
+  ///
+  /// The git fetcher looks up refs through a [`RefsCache`] in
+  /// `~/.cache/syslua/refs/`.
+  pub fn with_settings(settings: FetchSettings) -> Self {
+    let refs = RefsCache::new(
+      cache_dir().join("refs"),
+      settings.refs,
+      settings.auth.clone(),
+      settings.timeouts,
+    );
+    let mut fetchers = Self::empty();
+    fetchers.register(GitFetcher::new(settings.timeouts).with_refs(refs));
+    fetchers.register(ArchiveFetcher::new(settings.auth).with_timeouts(settings.timeouts));
+    fetchers
+  }
+
+  /// Register a fetcher under its scheme.
+  pub fn register(&mut self, fetcher: impl Fetcher + 'static) {
+    self.fetchers.insert(fetcher.scheme().to_string(), Arc::new(fetcher));
+  }
+
+  /// Look up the fetcher for a scheme.
+  pub fn get(&self, scheme: &str) -> Option<&dyn Fetcher> {
+    self.fetchers.get(scheme).map(|f| f.as_ref())
+  }
+
+  /// Registered schemes, sorted.
+  pub fn schemes(&self) -> impl Iterator<Item = &str> {
+    self.fetchers.keys().map(String::as_str)
+  }
+
+  /// Parse an input URL, accepting the schemes of registered fetchers.
+  ///
+  /// `git:` and `path:` URLs parse as [`InputSource::Git`] and
+  /// [`InputSource::Path`]; other registered schemes as [`InputSource::Fetcher`].
+  pub fn parse(&self, url: &str) -> Result<InputSource, ParseError> {
+    match url.split_once(':') {
+      Some((scheme, _)) if scheme != "git" && scheme != "path" && self.fetchers.contains_key(scheme) => {
+        parse_fetcher(url, scheme)
+      }
+      _ => parse(url),
+    }
+  }
+}
+
+/// A config's `settings.fetch`.
+#[derive(Clone, Default)]
+pub struct FetchSettings {
+  pub auth: FetchAuth,
+  pub timeouts: FetchTimeouts,
+  pub refs: RefsSettings,
+}
+
+/// Network timeouts for input fetches, from the config's `settings.fetch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchTimeouts {
+  /// Time allowed to connect to a server (`settings.fetch.connect_timeout`).
+  pub connect: Duration,
+  /// Time allowed for fetching one input, transfer included (`settings.fetch.timeout`).
+  pub total: Duration,
+}
+
+impl Default for FetchTimeouts {
+  fn default() -> Self {
+    Self {
+      connect: Duration::from_secs(30),
+      total: Duration::from_secs(600),
+    }
+  }
+}
+
+/// Number of input resolutions running, during which Ctrl-C cancels fetches.
+static RESOLVING: AtomicUsize = AtomicUsize::new(0);
+
+/// Cancel running and future fetches.
+///
+/// Running git fetches are interrupted; fetches started afterwards fail with
+/// [`FetchError::Cancelled`]. This can't be undone within the process.
+pub fn cancel_fetches() {
+  gix::interrupt::IS_INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Whether [`cancel_fetches`] has been called.
+pub fn fetches_cancelled() -> bool {
+  gix::interrupt::IS_INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Marks an input resolution as running until dropped.
+///
+/// While one is alive, the handler of [`install_interrupt_handler`] turns
+/// Ctrl-C into [`cancel_fetches`].
+pub(crate) struct ResolvingGuard(());
+
+impl ResolvingGuard {
+  pub(crate) fn enter() -> Self {
+    RESOLVING.fetch_add(1, Ordering::SeqCst);
+    Self(())
+  }
+}
+
+impl Drop for ResolvingGuard {
+  fn drop(&mut self) {
+    RESOLVING.fetch_sub(1, Ordering::SeqCst);
+  }
 }
 
-/// Fetch a git input to the cache directory.
+/// Handle Ctrl-C by cancelling input fetches while inputs are resolving.
+///
+/// Resolution then fails with the inputs it resolved so far. Outside of input
+/// resolution, or on a second Ctrl-C, the process exits with status 130 as it
+/// would without a handler.
+pub fn install_interrupt_handler() -> std::io::Result<()> {
+  let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+  std::thread::Builder::new()
+    .name("syslua-interrupt".to_string())
+    .spawn(move || {
+      rt.block_on(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+          if RESOLVING.load(Ordering::SeqCst) == 0 || fetches_cancelled() {
+            std::process::exit(130);
+          }
+          warn!("cancelling input fetches (press Ctrl-C again to exit)");
+          cancel_fetches();
+        }
+      })
+    })?;
+  Ok(())
+}
+
+/// Run a fetch with an interrupt flag that is raised when fetches are
+/// cancelled or the fetch runs longer than `timeout`.
+fn with_deadline<T>(
+  url: &str,
+  timeout: Duration,
+  fetch: impl FnOnce(&AtomicBool) -> Result<T, FetchError>,
+) -> Result<T, FetchError> {
+  if fetches_cancelled() {
+    return Err(FetchError::Cancelled);
+  }
+
+  let interrupt = AtomicBool::new(false);
+  let timed_out = AtomicBool::new(false);
+  let (done, finished) = mpsc::channel::<()>();
+  let result = std::thread::scope(|scope| {
+    let (interrupt, timed_out) = (&interrupt, &timed_out);
+    scope.spawn(move || {
+      let started = Instant::now();
+      // Returns as soon as the fetch finishes and drops the sender
+      while let Err(mpsc::RecvTimeoutError::Timeout) = finished.recv_timeout(Duration::from_millis(50)) {
+        if started.elapsed() >= timeout {
+          timed_out.store(true, Ordering::SeqCst);
+        }
+        if fetches_cancelled() || timed_out.load(Ordering::SeqCst) {
+          interrupt.store(true, Ordering::SeqCst);
+          break;
+        }
+      }
+    });
+    let result = fetch(interrupt);
+    drop(done);
+    result
+  });
+
+  match result {
+    Err(_) if timed_out.load(Ordering::SeqCst) => Err(FetchError::TimedOut {
+      url: url.to_string(),
+      timeout,
+    }),
+    Err(_) if fetches_cancelled() => Err(FetchError::Cancelled),
+    result => result,
+  }
+}
+
+/// gix configuration applying `timeouts` to HTTP transfers.
+///
+/// A transfer slower than 1 byte/s for the connect timeout counts as stalled.
+fn timeout_config(timeouts: &FetchTimeouts) -> Vec<String> {
+  vec![
+    format!("gitoxide.http.connectTimeout={}", timeouts.connect.as_millis()),
+    "http.lowSpeedLimit=1".to_string(),
+    format!("http.lowSpeedTime={}", timeouts.connect.as_secs().max(1)),
+  ]
+}
+
+/// Credentials for authenticated fetches, from the config's `settings.fetch`.
+///
+/// Bearer tokens take precedence over netrc entries. Credentials are only sent
+/// over HTTPS.
+#[derive(Clone, Default)]
+pub struct FetchAuth {
+  /// Bearer tokens by host (`settings.fetch.tokens`).
+  pub tokens: BTreeMap<String, String>,
+  /// netrc file with `machine`/`login`/`password` entries (`settings.fetch.netrc`).
+  /// When unset, `$NETRC` or `~/.netrc` is used if it exists.
+  pub netrc: Option<PathBuf>,
+}
+
+/// Credentials to attach to a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credentials {
+  /// `Authorization: Bearer <token>`.
+  Bearer(String),
+  /// HTTP basic auth.
+  Basic { login: String, password: String },
+}
+
+impl FetchAuth {
+  /// Credentials for `host`, if any are configured.
+  pub fn credentials_for(&self, host: &str) -> Result<Option<Credentials>, FetchError> {
+    if let Some(token) = self.tokens.get(host) {
+      return Ok(Some(Credentials::Bearer(token.clone())));
+    }
+
+    let (path, explicit) = match &self.netrc {
+      Some(path) => (path.clone(), true),
+      None => match std::env::var_os("NETRC") {
+        Some(path) => (PathBuf::from(path), false),
+        None => (home_dir().join(".netrc"), false),
+      },
+    };
+
+    let contents = match fs::read_to_string(&path) {
+      Ok(contents) => contents,
+      Err(e) if !explicit && e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+      Err(e) => return Err(FetchError::ReadNetrc { path, source: e }),
+    };
+
+    Ok(netrc_lookup(&contents, host).map(|(login, password)| Credentials::Basic { login, password }))
+  }
+}
+
+/// Find the login and password for `host` in netrc `contents`.
+///
+/// Falls back to the `default` entry. `macdef` bodies are skipped.
+fn netrc_lookup(contents: &str, host: &str) -> Option<(String, String)> {
+  let mut found: Option<(String, String)> = None;
+  let mut default: Option<(String, String)> = None;
+  let mut current: Option<(bool, String, String)> = None;
+
+  let mut finish = |entry: Option<(bool, String, String)>| {
+    if let Some((is_default, login, password)) = entry {
+      if is_default {
+        default.get_or_insert((login, password));
+      } else {
+        found.get_or_insert((login, password));
+      }
+    }
+  };
+
+  let mut lines = contents.lines();
+  while let Some(line) = lines.next() {
+    let mut tokens = line.split_whitespace();
+    while let Some(token) = tokens.next() {
+      match token {
+        "machine" => {
+          let machine = tokens.next().unwrap_or_default();
+          finish(current.take());
+          if machine == host {
+            current = Some((false, String::new(), String::new()));
+          }
+        }
+        "default" => {
+          finish(current.take());
+          current = Some((true, String::new(), String::new()));
+        }
+        "login" | "password" | "account" => {
+          let value = tokens.next().unwrap_or_default().to_string();
+          if let Some((_, login, password)) = current.as_mut() {
+            match token {
+              "login" => *login = value,
+              "password" => *password = value,
+              _ => {}
+            }
+          }
+        }
+        "macdef" => {
+          // Macro body runs until the next blank line
+          for line in lines.by_ref() {
+            if line.trim().is_empty() {
+              break;
+            }
+          }
+          break;
+        }
+        _ => {}
+      }
+    }
+  }
+  finish(current.take());
+
+  found.or(default)
+}
+
+/// Fetcher for `git:` inputs.
+#[derive(Default)]
+pub struct GitFetcher {
+  timeouts: FetchTimeouts,
+  refs: Option<RefsCache>,
+}
+
+impl GitFetcher {
+  /// Create a git fetcher bounding each fetch by `timeouts`.
+  pub fn new(timeouts: FetchTimeouts) -> Self {
+    Self { timeouts, refs: None }
+  }
+
+  /// Look up the tags and heads of repositories [`RefsCache`] supports
+  /// through `refs` instead of fetching them.
+  pub fn with_refs(mut self, refs: RefsCache) -> Self {
+    self.refs = Some(refs);
+    self
+  }
+
+  /// The head of an unpinned input, if [`RefsCache`] knows it and the cached
+  /// repository already has it, so nothing needs to be fetched.
+  fn cached_head(&self, name: &str, url: &str, cache_dir: &Path) -> Option<(PathBuf, String)> {
+    let refs = self.refs.as_ref().filter(|_| RefsCache::supports(url))?;
+    let repo_path = cache_dir.join(name);
+    if !repo_path.join(".git").exists() {
+      return None;
+    }
+    let head = refs
+      .head(url)
+      .inspect_err(|e| debug!(name, error = %e, "falling back to fetching the head"))
+      .ok()?;
+    let repo = gix::open(&repo_path).ok()?;
+    let id = gix::ObjectId::from_hex(head.as_bytes()).ok()?;
+    repo.has_object(id).then_some((repo_path, head))
+  }
+}
+
+impl Fetcher for GitFetcher {
+  fn scheme(&self) -> &str {
+    "git"
+  }
+
+  fn fetch(&self, name: &str, url: &str, rev: Option<&str>, cache_dir: &Path) -> Result<(PathBuf, String), FetchError> {
+    if rev.is_none()
+      && let Some((repo_path, head)) = self.cached_head(name, url, cache_dir)
+    {
+      debug!(name, rev = %head, "head is already fetched");
+      return Ok((repo_path, head));
+    }
+
+    let (repo_path, repo) = open_or_clone(name, url, cache_dir, &self.timeouts)?;
+
+    // Resolve the target revision to a commit hash
+    let commit_hash = resolve_revision(&repo, rev)?;
+
+    debug!(name, rev = %commit_hash, "resolved revision");
+    Ok((repo_path, commit_hash))
+  }
+
+  fn tags(&self, name: &str, url: &str, cache_dir: &Path) -> Result<Vec<String>, FetchError> {
+    if let Some(refs) = self.refs.as_ref().filter(|_| RefsCache::supports(url)) {
+      match refs.tags(url) {
+        Ok(tags) => return Ok(tags),
+        Err(e) => debug!(name, error = %e, "falling back to listing tags with git"),
+      }
+    }
+
+    let (_, repo) = open_or_clone(name, url, cache_dir, &self.timeouts)?;
+
+    let list_failed = |message: String| FetchError::ListTags {
+      url: url.to_string(),
+      message,
+    };
+    let references = repo.references().map_err(|e| list_failed(e.to_string()))?;
+    let mut tags: Vec<String> = references
+      .tags()
+      .map_err(|e| list_failed(e.to_string()))?
+      .filter_map(Result::ok)
+      .map(|reference| reference.name().shorten().to_string())
+      .collect();
+    tags.sort();
+
+    debug!(name, count = tags.len(), "listed tags");
+    Ok(tags)
+  }
+}
+
+/// Fetch a git input to the cache directory, with the default [`FetchTimeouts`].
 ///
 /// If the cache exists, fetches updates and checks out the target revision.
 /// If the cache doesn't exist, clones and checks out.
@@ -110,6 +633,24 @@ pub enum FetchError {
 /// - `path` is the full path to the checked-out repository
 /// - `rev` is the actual commit hash that was checked out
 pub fn fetch_git(name: &str, url: &str, rev: Option<&str>, cache_dir: &Path) -> Result<(PathBuf, String), FetchError> {
+  GitFetcher::default().fetch(name, url, rev, cache_dir)
+}
+
+/// List the tag names of a git input (e.g., `v1.2.0`), sorted.
+///
+/// Clones or fetches the repository into the cache like [`fetch_git`], so the
+/// tags reachable from the remote's branches are known locally.
+pub fn list_git_tags(name: &str, url: &str, cache_dir: &Path) -> Result<Vec<String>, FetchError> {
+  GitFetcher::default().tags(name, url, cache_dir)
+}
+
+/// Open the cached repository of an input and fetch updates, or clone it.
+fn open_or_clone(
+  name: &str,
+  url: &str,
+  cache_dir: &Path,
+  timeouts: &FetchTimeouts,
+) -> Result<(PathBuf, gix::Repository), FetchError> {
   let repo_path = cache_dir.join(name);
 
   // Ensure cache directory exists
@@ -120,53 +661,68 @@ pub fn fetch_git(name: &str, url: &str, rev: Option<&str>, cache_dir: &Path) ->
   let repo = if repo_path.join(".git").exists() {
     // Repository exists, open and fetch
     debug!(name, path = %repo_path.display(), "opening existing repository");
-    let repo = gix::open(&repo_path).map_err(|e| FetchError::Open {
+    let options = gix::open::Options::default().config_overrides(timeout_config(timeouts));
+    let repo = gix::open_opts(&repo_path, options).map_err(|e| FetchError::Open {
       path: repo_path.clone(),
       source: Box::new(e),
     })?;
 
     // Fetch updates from origin
-    fetch_updates(&repo, url)?;
+    with_deadline(url, timeouts.total, |interrupt| fetch_updates(&repo, url, interrupt))?;
     repo
   } else {
     // Clone the repository
     info!(name, url, path = %repo_path.display(), "cloning repository");
-    clone_repo(url, &repo_path)?
+    let cloned = with_deadline(url, timeouts.total, |interrupt| {
+      clone_repo(url, &repo_path, timeouts, interrupt)
+    });
+    match cloned {
+      Ok(repo) => repo,
+      Err(e) => {
+        // A partial clone would be opened as the cache next time
+        let _ = fs::remove_dir_all(&repo_path);
+        return Err(e);
+      }
+    }
   };
 
-  // Resolve the target revision to a commit hash
-  let commit_hash = resolve_revision(&repo, rev)?;
-
-  debug!(name, rev = %commit_hash, "resolved revision");
-  Ok((repo_path, commit_hash))
+  Ok((repo_path, repo))
 }
 
 /// Clone a git repository to the specified path.
-fn clone_repo(url: &str, dest: &Path) -> Result<gix::Repository, FetchError> {
-  let mut prepared = gix::prepare_clone(url, dest).map_err(|e| FetchError::Clone {
-    url: url.to_string(),
-    source: Box::new(e),
-  })?;
+fn clone_repo(
+  url: &str,
+  dest: &Path,
+  timeouts: &FetchTimeouts,
+  interrupt: &AtomicBool,
+) -> Result<gix::Repository, FetchError> {
+  let mut prepared = gix::prepare_clone(url, dest)
+    .map_err(|e| FetchError::Clone {
+      url: url.to_string(),
+      source: Box::new(e),
+    })?
+    .with_in_memory_config_overrides(timeout_config(timeouts));
 
   let (mut checkout, _outcome) = prepared
-    .fetch_then_checkout(gix::progress::Discard, &gix::interrupt::IS_INTERRUPTED)
+    .fetch_then_checkout(gix::progress::Discard, interrupt)
     .map_err(|e| FetchError::Clone {
       url: url.to_string(),
       source: Box::new(e),
     })?;
 
-  let (repo, _outcome) = checkout
-    .main_worktree(gix::progress::Discard, &gix::interrupt::IS_INTERRUPTED)
-    .map_err(|e| FetchError::Checkout {
-      rev: "HEAD".to_string(),
-      source: Box::new(e),
-    })?;
+  let (repo, _outcome) =
+    checkout
+      .main_worktree(gix::progress::Discard, interrupt)
+      .map_err(|e| FetchError::Checkout {
+        rev: "HEAD".to_string(),
+        source: Box::new(e),
+      })?;
 
   Ok(repo)
 }
 
 /// Fetch updates from the remote.
-fn fetch_updates(repo: &gix::Repository, url: &str) -> Result<(), FetchError> {
+fn fetch_updates(repo: &gix::Repository, url: &str, interrupt: &AtomicBool) -> Result<(), FetchError> {
   debug!(url, "fetching updates");
 
   let remote = repo
@@ -188,7 +744,7 @@ fn fetch_updates(repo: &gix::Repository, url: &str) -> Result<(), FetchError> {
       url: url.to_string(),
       source: Box::new(e),
     })?
-    .receive(gix::progress::Discard, &gix::interrupt::IS_INTERRUPTED)
+    .receive(gix::progress::Discard, interrupt)
     .map_err(|e| FetchError::Fetch {
       url: url.to_string(),
       source: Box::new(e),
@@ -346,6 +902,83 @@ mod tests {
     }
   }
 
+  mod fetch_auth_tests {
+    use super::*;
+
+    #[test]
+    fn netrc_matches_machine_before_default() {
+      let contents = "default login anon password guest\n\
+                      machine example.com\n  login alice\n  password s3cret\n";
+
+      assert_eq!(
+        netrc_lookup(contents, "example.com"),
+        Some(("alice".to_string(), "s3cret".to_string()))
+      );
+      assert_eq!(
+        netrc_lookup(contents, "other.com"),
+        Some(("anon".to_string(), "guest".to_string()))
+      );
+    }
+
+    #[test]
+    fn netrc_skips_macdef_bodies() {
+      let contents = "macdef init\nmachine evil.com login x password y\n\n\
+                      machine example.com login alice password s3cret\n";
+
+      assert_eq!(netrc_lookup(contents, "evil.com"), None);
+      assert!(netrc_lookup(contents, "example.com").is_some());
+    }
+
+    #[test]
+    fn token_takes_precedence_over_netrc() {
+      let temp = TempDir::new().unwrap();
+      let netrc = temp.path().join("netrc");
+      fs::write(&netrc, "machine example.com login alice password s3cret\n").unwrap();
+
+      let auth = FetchAuth {
+        tokens: BTreeMap::from([("example.com".to_string(), "tok".to_string())]),
+        netrc: Some(netrc),
+      };
+
+      assert!(matches!(
+        auth.credentials_for("example.com").unwrap(),
+        Some(Credentials::Bearer(token)) if token == "tok"
+      ));
+      assert!(auth.credentials_for("other.com").unwrap().is_none());
+    }
+  }
+
+  mod deadline_tests {
+    use super::*;
+
+    #[test]
+    fn stalled_fetch_times_out() {
+      let result: Result<(), FetchError> = with_deadline(
+        "https://example.com/repo.git",
+        Duration::from_millis(100),
+        |interrupt| {
+          // Stands in for a transfer that only notices the interrupt flag
+          while !interrupt.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(10));
+          }
+          Err(FetchError::NoRemote)
+        },
+      );
+      assert!(
+        matches!(result, Err(FetchError::TimedOut { ref url, .. }) if url == "https://example.com/repo.git"),
+        "{result:?}"
+      );
+    }
+
+    #[test]
+    fn finished_fetch_keeps_its_result() {
+      let started = Instant::now();
+      let result = with_deadline("u", Duration::from_secs(60), |_| Ok(42));
+      assert_eq!(result.unwrap(), 42);
+      assert!(started.elapsed() < Duration::from_secs(5));
+    }
+  }
+
   mod git_fetch_tests {
     use super::*;
     use std::process::Command;
@@ -3,6 +3,8 @@
 //! This module handles:
 //! - Building a dependency graph from input declarations
 //! - Resolving `follows` declarations (with chain support)
+//! - Applying overrides keyed by a path (`["utils/helpers"]` or
+//!   `["utils.helpers"]`) to transitive dependencies at any depth
 //! - Topological sorting for resolution order
 //! - Cycle detection and handling
 //!
@@ -83,6 +85,19 @@ impl GraphNode {
   }
 }
 
+/// Marker key the resolver puts in the `inputs` of a dependency that follows
+/// another input. It isn't an override of its own.
+pub const FOLLOWS_MARKER: &str = "__follows__";
+
+/// An override together with the input that declared it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeclaredOverride {
+  /// Full path of the input whose `inputs` table declares the override.
+  pub declared_by: String,
+  /// The override itself.
+  pub override_: InputOverride,
+}
+
 /// The dependency graph structure.
 #[derive(Debug, Default)]
 pub struct DependencyGraph {
@@ -117,6 +132,33 @@ pub enum GraphError {
   /// Invalid follows path format.
   #[error("invalid follows path '{path}': {reason}")]
   InvalidFollowsPath { path: String, reason: String },
+
+  /// Overrides for the same input path that can't both apply.
+  #[error("conflicting overrides for '{path}': {reason}")]
+  ConflictingOverrides { path: String, reason: String },
+}
+
+/// Normalize an override key or follows target to a `/`-separated path.
+///
+/// Both `/` and `.` separate segments, so `"pkgs.utils"` and `"pkgs/utils"`
+/// name the same input.
+pub fn normalize_input_path(path: &str) -> Result<String, GraphError> {
+  if path.is_empty() {
+    return Err(GraphError::InvalidFollowsPath {
+      path: path.to_string(),
+      reason: "path cannot be empty".to_string(),
+    });
+  }
+
+  let segments: Vec<&str> = path.split(['/', '.']).collect();
+  if segments.iter().any(|segment| segment.is_empty()) {
+    return Err(GraphError::InvalidFollowsPath {
+      path: path.to_string(),
+      reason: "path segments cannot be empty".to_string(),
+    });
+  }
+
+  Ok(segments.join("/"))
 }
 
 impl DependencyGraph {
@@ -191,65 +233,143 @@ impl DependencyGraph {
       .unwrap_or_default()
   }
 
-  /// Resolve all follows declarations in the graph.
+  /// Collect the overrides declared by every node, keyed by the full path of
+  /// the input they override.
   ///
-  /// This processes all nodes with follows overrides and resolves them
-  /// to their final targets, handling chains up to [`MAX_FOLLOWS_DEPTH`].
-  pub fn resolve_follows(&mut self) -> Result<(), GraphError> {
-    // Collect all follows declarations that need resolution
-    let mut follows_to_resolve: Vec<(String, String)> = Vec::new();
-
-    for node in self.nodes.values() {
-      if let Some(overrides) = node.decl.overrides() {
-        for (dep_name, override_) in overrides {
-          if let InputOverride::Follows(target) = override_ {
-            let source_path = if node.full_path.is_empty() {
-              dep_name.clone()
-            } else {
-              format!("{}/{}", node.full_path, dep_name)
-            };
-            follows_to_resolve.push((source_path, target.clone()));
+  /// A key like `"utils/helpers"` in the `inputs` of `pkgs` overrides
+  /// `pkgs/utils/helpers`. When several inputs override the same path, the
+  /// outermost one wins, so the config always has the last word over the
+  /// inputs it pulls in.
+  ///
+  /// # Errors
+  ///
+  /// [`GraphError::ConflictingOverrides`] if one `inputs` table overrides a
+  /// path twice in different ways (e.g. as `"utils.helpers"` and
+  /// `"utils/helpers"`), or overrides the inputs of a dependency it also
+  /// makes follow another input, which replaces that dependency with all of
+  /// its inputs.
+  pub fn declared_overrides(&self) -> Result<BTreeMap<String, DeclaredOverride>, GraphError> {
+    let mut nodes: Vec<&GraphNode> = self.nodes.values().collect();
+    nodes.sort_by_key(|node| node.full_path.matches('/').count());
+
+    let mut declared: BTreeMap<String, DeclaredOverride> = BTreeMap::new();
+    for node in nodes {
+      let Some(overrides) = node.decl.overrides() else {
+        continue;
+      };
+
+      let mut own: BTreeMap<String, (&str, &InputOverride)> = BTreeMap::new();
+      for (key, override_) in overrides {
+        if key == FOLLOWS_MARKER {
+          continue;
+        }
+        let source_path = format!("{}/{}", node.full_path, normalize_input_path(key)?);
+        if let Some((other_key, other)) = own.insert(source_path.clone(), (key, override_))
+          && other != override_
+        {
+          return Err(GraphError::ConflictingOverrides {
+            path: source_path,
+            reason: format!("'{}' declares it as both '{}' and '{}'", node.full_path, other_key, key),
+          });
+        }
+      }
+
+      for (source_path, (_, override_)) in own {
+        match declared.get(&source_path) {
+          Some(outer) => trace!(
+            path = %source_path,
+            declared_by = %outer.declared_by,
+            ignored = %node.full_path,
+            "outer override takes precedence"
+          ),
+          None => {
+            declared.insert(
+              source_path,
+              DeclaredOverride {
+                declared_by: node.full_path.clone(),
+                override_: override_.clone(),
+              },
+            );
           }
         }
       }
     }
 
+    // A dependency that follows another input brings that input's own
+    // dependencies, so the same declarer can't also override them
+    for (followed, outer) in declared.iter().filter(|(_, d)| d.override_.is_follows()) {
+      let prefix = format!("{}/", followed);
+      if let Some((path, _)) = declared
+        .range(prefix.clone()..)
+        .take_while(|(path, _)| path.starts_with(&prefix))
+        .find(|(_, inner)| inner.declared_by == outer.declared_by)
+      {
+        return Err(GraphError::ConflictingOverrides {
+          path: path.clone(),
+          reason: format!(
+            "'{}' follows '{}', so its inputs can't be overridden from '{}'",
+            followed,
+            outer.override_.follows_path().unwrap_or_default(),
+            outer.declared_by
+          ),
+        });
+      }
+    }
+
+    Ok(declared)
+  }
+
+  /// Resolve all follows declarations in the graph.
+  ///
+  /// This processes all nodes with follows overrides and resolves them
+  /// to their final targets, handling chains up to [`MAX_FOLLOWS_DEPTH`].
+  pub fn resolve_follows(&mut self) -> Result<(), GraphError> {
+    let declared = self.declared_overrides()?;
+
     // Resolve each follows declaration
-    for (source_path, target) in follows_to_resolve {
-      let resolved = self.resolve_follows_chain(&source_path, &target)?;
+    for (source_path, declared_override) in &declared {
+      let InputOverride::Follows(target) = &declared_override.override_ else {
+        continue;
+      };
+      let resolved = self.resolve_follows_chain(source_path, target, &declared)?;
       debug!(source = %source_path, target = %resolved, "resolved follows");
-      self.follows_resolved.insert(source_path, resolved);
+      self.follows_resolved.insert(source_path.clone(), resolved);
     }
 
     Ok(())
   }
 
   /// Resolve a follows chain to its final target.
-  fn resolve_follows_chain(&self, source: &str, initial_target: &str) -> Result<String, GraphError> {
+  fn resolve_follows_chain(
+    &self,
+    source: &str,
+    initial_target: &str,
+    declared: &BTreeMap<String, DeclaredOverride>,
+  ) -> Result<String, GraphError> {
     let mut visited = HashSet::new();
     let mut chain = vec![source.to_string()];
     let mut current_target = initial_target.to_string();
 
     for depth in 0..MAX_FOLLOWS_DEPTH {
+      // Normalize the target path
+      let normalized = normalize_input_path(&current_target)?;
+
       // Check for cycles
-      if visited.contains(&current_target) {
-        chain.push(current_target.clone());
+      if visited.contains(&normalized) {
+        chain.push(normalized);
         return Err(GraphError::CircularFollows {
           chain: chain.join(" -> "),
         });
       }
 
-      visited.insert(current_target.clone());
-      chain.push(current_target.clone());
-
-      // Normalize the target path
-      let normalized = self.normalize_follows_path(&current_target)?;
+      visited.insert(normalized.clone());
+      chain.push(normalized.clone());
 
       // Check if the target itself has a follows
-      if let Some(next_target) = self.get_follows_target(&normalized) {
+      if let Some(next_target) = self.get_follows_target(&normalized, declared) {
         trace!(
           depth,
-          current = %current_target,
+          current = %normalized,
           next = %next_target,
           "following chain"
         );
@@ -266,52 +386,17 @@ impl DependencyGraph {
     })
   }
 
-  /// Normalize a follows path to a full node path.
-  ///
-  /// Handles:
-  /// - Direct input names: "utils" -> "utils"
-  /// - Nested paths: "pkgs/utils" -> "pkgs/utils"
-  fn normalize_follows_path(&self, path: &str) -> Result<String, GraphError> {
-    // Path is already in the correct format
-    // Just validate it's not empty
-    if path.is_empty() {
-      return Err(GraphError::InvalidFollowsPath {
-        path: path.to_string(),
-        reason: "path cannot be empty".to_string(),
-      });
-    }
-
-    Ok(path.to_string())
-  }
-
   /// Get the follows target for a path, if it has one.
-  fn get_follows_target(&self, path: &str) -> Option<String> {
+  fn get_follows_target(&self, path: &str, declared: &BTreeMap<String, DeclaredOverride>) -> Option<String> {
     // First check if this path has already been resolved
     if let Some(resolved) = self.follows_resolved.get(path) {
       return Some(resolved.clone());
     }
 
-    // Check if this is a pending follows (from node overrides)
-    let parts: Vec<&str> = path.rsplitn(2, '/').collect();
-    if parts.len() == 2 {
-      let (dep_name, parent_path) = (parts[0], parts[1]);
-      if let Some(node) = self.nodes.get(parent_path)
-        && let Some(overrides) = node.decl.overrides()
-        && let Some(InputOverride::Follows(target)) = overrides.get(dep_name)
-      {
-        return Some(target.clone());
-      }
-    } else if let Some(node) = self.nodes.get(path) {
-      // Root-level input - check if it's a follows-only declaration
-      if let InputDecl::Extended { url: None, inputs } = &node.decl
-        && inputs.is_empty()
-      {
-        // This shouldn't happen, but handle gracefully
-        return None;
-      }
-    }
-
-    None
+    declared
+      .get(path)
+      .and_then(|d| d.override_.follows_path())
+      .map(str::to_string)
   }
 
   /// Perform a topological sort of the graph.
@@ -734,6 +819,89 @@ mod tests {
       assert!(matches!(result.unwrap_err(), GraphError::CircularFollows { .. }));
     }
 
+    fn pkgs_with_overrides(overrides: &[(&str, &str)]) -> DependencyGraph {
+      let mut decls = InputDecls::new();
+      decls.insert(
+        "pkgs".to_string(),
+        InputDecl::Extended {
+          url: Some("git:https://example.com/pkgs".to_string()),
+          inputs: overrides
+            .iter()
+            .map(|(key, target)| (key.to_string(), InputOverride::Follows(target.to_string())))
+            .collect(),
+        },
+      );
+      build_initial_graph(&decls)
+    }
+
+    #[test]
+    fn path_keys_follow_deep_dependencies() {
+      let mut graph = pkgs_with_overrides(&[("utils/helpers", "my_helpers"), ("utils.fmt.core", "my_fmt")]);
+      graph.resolve_follows().unwrap();
+
+      assert_eq!(graph.follows_resolved.get("pkgs/utils/helpers").unwrap(), "my_helpers");
+      assert_eq!(graph.follows_resolved.get("pkgs/utils/fmt/core").unwrap(), "my_fmt");
+    }
+
+    #[test]
+    fn outer_override_wins_over_nested_one() {
+      let mut graph = pkgs_with_overrides(&[("utils/helpers", "my_helpers")]);
+      let mut nested = BTreeMap::new();
+      nested.insert(
+        "helpers".to_string(),
+        InputOverride::Follows("pkgs_helpers".to_string()),
+      );
+      graph.add_transitive(
+        "utils",
+        InputDecl::Extended {
+          url: Some("git:https://example.com/utils".to_string()),
+          inputs: nested,
+        },
+        "pkgs",
+      );
+
+      let declared = graph.declared_overrides().unwrap();
+      assert_eq!(declared["pkgs/utils/helpers"].declared_by, "pkgs");
+
+      graph.resolve_follows().unwrap();
+      assert_eq!(graph.follows_resolved.get("pkgs/utils/helpers").unwrap(), "my_helpers");
+    }
+
+    #[test]
+    fn spellings_of_one_path_must_agree() {
+      let graph = pkgs_with_overrides(&[("utils.helpers", "a"), ("utils/helpers", "a")]);
+      assert_eq!(graph.declared_overrides().unwrap().len(), 1);
+
+      let graph = pkgs_with_overrides(&[("utils.helpers", "a"), ("utils/helpers", "b")]);
+      let err = graph.declared_overrides().unwrap_err();
+      assert!(
+        matches!(&err, GraphError::ConflictingOverrides { path, .. } if path == "pkgs/utils/helpers"),
+        "{err}"
+      );
+    }
+
+    #[test]
+    fn inputs_of_a_followed_dependency_cannot_be_overridden() {
+      let graph = pkgs_with_overrides(&[("utils", "my_utils"), ("utils/helpers", "my_helpers")]);
+      let err = graph.declared_overrides().unwrap_err();
+      assert!(
+        matches!(&err, GraphError::ConflictingOverrides { path, .. } if path == "pkgs/utils/helpers"),
+        "{err}"
+      );
+    }
+
+    #[test]
+    fn normalizes_dotted_paths() {
+      assert_eq!(normalize_input_path("pkgs.utils").unwrap(), "pkgs/utils");
+      assert_eq!(
+        normalize_input_path("pkgs/utils.helpers").unwrap(),
+        "pkgs/utils/helpers"
+      );
+      assert!(normalize_input_path("").is_err());
+      assert!(normalize_input_path("pkgs//utils").is_err());
+      assert!(normalize_input_path("pkgs.").is_err());
+    }
+
     // Note: FollowsTargetNotFound is validated during full transitive resolution,
     // not during graph.resolve_follows(). The graph allows unresolved targets
     // because they may be discovered during transitive dependency resolution.
@@ -35,6 +35,18 @@
 //!   }
 //! }
 //! ```
+//!
+//! Inputs declared with a `#semver:<range>` ref also record the tag that
+//! matched, e.g. `"tag": "v1.4.2"`, next to its `rev`.
+//!
+//! A config that sets `settings.hash` also records the object hash algorithm
+//! and length, e.g. `"hash": { "algorithm": "sha512", "length": 40 }`. The
+//! field is omitted for the default (SHA-256, 20 characters).
+//!
+//! Answers of the eval-time IO helpers (`sys.io`, see [`crate::lua::io`]) the
+//! last evaluation used are recorded under `eval`, keyed by helper and
+//! argument, e.g. `"eval": { "prefetch:https://example.com/a.tar.gz": "9f86..." }`,
+//! so evaluating again gives the same answers without the network.
 
 use std::collections::BTreeMap;
 use std::fs;
@@ -46,6 +58,7 @@ use thiserror::Error;
 
 use super::store::InputStore;
 use super::types::LockNode;
+use crate::util::hash::HashSpec;
 
 /// Current lock file format version.
 pub const LOCK_VERSION: u32 = 1;
@@ -60,7 +73,7 @@ pub const ROOT_NODE_LABEL: &str = "root";
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LockedInput {
-  /// Input type: "git" or "path".
+  /// Input type: "git", "path", or a registered fetcher scheme.
   #[serde(rename = "type")]
   pub type_: String,
 
@@ -70,6 +83,10 @@ pub struct LockedInput {
   /// Pinned revision (git commit hash or "local" for path inputs).
   pub rev: String,
 
+  /// Tag chosen for a `#semver:<range>` URL.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tag: Option<String>,
+
   /// Unix timestamp of when this input was last modified/fetched.
   #[serde(skip_serializing_if = "Option::is_none")]
   pub last_modified: Option<u64>,
@@ -82,6 +99,7 @@ impl LockedInput {
       type_: type_.to_string(),
       url: url.to_string(),
       rev: rev.to_string(),
+      tag: None,
       last_modified: None,
     }
   }
@@ -91,6 +109,12 @@ impl LockedInput {
     self.last_modified = Some(timestamp);
     self
   }
+
+  /// Set the tag matched by a semver range.
+  pub fn with_tag(mut self, tag: Option<String>) -> Self {
+    self.tag = tag;
+    self
+  }
 }
 
 // =============================================================================
@@ -106,6 +130,12 @@ pub struct LockFileV1 {
   pub root: String,
   /// All nodes in the dependency graph, keyed by label.
   pub nodes: BTreeMap<String, LockNode>,
+  /// Object hash algorithm and length of the config.
+  #[serde(default, skip_serializing_if = "HashSpec::is_default")]
+  pub hash: HashSpec,
+  /// Answers of eval-time IO helpers, keyed by helper and argument.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub eval: BTreeMap<String, serde_json::Value>,
 }
 
 impl Default for LockFileV1 {
@@ -123,6 +153,8 @@ impl LockFileV1 {
       version: LOCK_VERSION,
       root: ROOT_NODE_LABEL.to_string(),
       nodes,
+      hash: HashSpec::default(),
+      eval: BTreeMap::new(),
     }
   }
 
@@ -154,7 +186,7 @@ impl LockFileV1 {
   /// * `name` - The input name (as declared in config)
   /// * `url` - The input URL
   /// * `rev` - The resolved revision
-  /// * `type_` - The input type ("git" or "path")
+  /// * `type_` - The input type ("git", "path", or a fetcher scheme)
   /// * `last_modified` - Optional last modified timestamp
   pub fn add_root_input(&mut self, name: &str, url: &str, rev: &str, type_: &str, last_modified: Option<u64>) {
     let label = InputStore::compute_store_label(name, url, rev);
@@ -179,7 +211,7 @@ impl LockFileV1 {
   /// * `dep_name` - The dependency name (as declared in parent's inputs)
   /// * `url` - The dependency URL
   /// * `rev` - The resolved revision
-  /// * `type_` - The input type ("git" or "path")
+  /// * `type_` - The input type ("git", "path", or a fetcher scheme)
   /// * `last_modified` - Optional last modified timestamp
   pub fn add_transitive_input(
     &mut self,
@@ -373,6 +405,7 @@ impl LockFile {
           type_: node.type_.clone().unwrap_or_default(),
           url: node.url.clone().unwrap_or_default(),
           rev: node.rev.clone().unwrap_or_default(),
+          tag: node.tag.clone(),
           last_modified: node.last_modified,
         })
       }
@@ -386,6 +419,11 @@ impl LockFile {
     self
       .inner
       .add_root_input(&name, &input.url, &input.rev, &input.type_, input.last_modified);
+    if let Some(label) = self.inner.get_root_input_label(&name).map(str::to_string)
+      && let Some(node) = self.inner.nodes.get_mut(&label)
+    {
+      node.tag = input.tag;
+    }
   }
 
   /// Get all input names (for backwards compatibility).
@@ -410,6 +448,31 @@ impl LockFile {
   pub fn remove(&mut self, name: &str) -> bool {
     self.inner.remove_root_input(name)
   }
+
+  /// Object hash algorithm and length recorded for the config.
+  pub fn hash_spec(&self) -> HashSpec {
+    self.inner.hash
+  }
+
+  /// Record the config's object hash spec. Returns whether it changed.
+  pub fn set_hash_spec(&mut self, spec: HashSpec) -> bool {
+    let changed = self.inner.hash != spec;
+    self.inner.hash = spec;
+    changed
+  }
+
+  /// Answers of eval-time IO helpers recorded by the last evaluation.
+  pub fn eval_cache(&self) -> &BTreeMap<String, serde_json::Value> {
+    &self.inner.eval
+  }
+
+  /// Record the answers of eval-time IO helpers an evaluation used,
+  /// replacing the previous ones. Returns whether they changed.
+  pub fn set_eval_cache(&mut self, answers: BTreeMap<String, serde_json::Value>) -> bool {
+    let changed = self.inner.eval != answers;
+    self.inner.eval = answers;
+    changed
+  }
 }
 
 /// Load a lock file from an input's directory.
@@ -747,6 +810,26 @@ mod tests {
       assert_eq!(orig_pkgs.last_modified, load_pkgs.last_modified);
     }
 
+    #[test]
+    fn semver_tag_roundtrip() {
+      let temp_dir = TempDir::new().unwrap();
+      let lock_path = temp_dir.path().join(LOCK_FILENAME);
+
+      let mut original = LockFile::new();
+      original.insert(
+        "utils".to_string(),
+        LockedInput::new("git", "git:https://github.com/org/utils.git#semver:^1.2", "a1b2c3d4")
+          .with_tag(Some("v1.4.2".to_string())),
+      );
+      original.save(&lock_path).unwrap();
+
+      let content = fs::read_to_string(&lock_path).unwrap();
+      assert!(content.contains(r#""tag": "v1.4.2""#));
+
+      let loaded = LockFile::load(&lock_path).unwrap().unwrap();
+      assert_eq!(loaded.get("utils").unwrap().tag.as_deref(), Some("v1.4.2"));
+    }
+
     #[test]
     fn load_nonexistent_returns_none() {
       let temp_dir = TempDir::new().unwrap();
@@ -1,21 +1,30 @@
 //! Input resolution and management.
 //!
-//! This module handles resolving external inputs (git repositories and local paths)
-//! that are declared in the config's `M.inputs` table.
+//! This module handles resolving external inputs (git repositories, archives,
+//! local paths, and schemes from registered fetchers) that are declared in the
+//! config's `M.inputs` table.
 //!
 //! # Modules
 //!
 //! - [`source`] - URL parsing for input sources
 //! - [`lock`] - Lock file management for reproducible builds
-//! - [`fetch`] - Git fetch and path resolution operations
+//! - [`audit`] - Stale, duplicate and mismatched lock entry detection and pruning
+//! - [`fetch`] - The `Fetcher` trait and registry, git fetch and path resolution
+//! - [`archive`] - Authenticated HTTP(S) archive fetcher
+//! - [`refs`] - Cached, rate limit aware GitHub API lookups of tags and heads
+//! - [`registry`] - JSON indexes of community inputs (`sys search`, `sys add-input`)
 //! - [`resolve`] - High-level resolution orchestration
 //! - [`types`] - Core input types (declarations, overrides, resolved inputs)
 //! - [`graph`] - Dependency graph building and traversal
 //! - [`store`] - Content-addressed input store with dependency linking
 
+pub mod archive;
+pub mod audit;
 pub mod fetch;
 pub mod graph;
 pub mod lock;
+pub mod refs;
+pub mod registry;
 pub mod resolve;
 pub mod source;
 pub mod store;
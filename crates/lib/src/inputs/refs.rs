@@ -0,0 +1,542 @@
+//! Cached lookups of the refs of remote repositories.
+//!
+//! Resolving a `#semver:<range>` input needs the tags of its repository, and
+//! `sys update` needs the head of every unpinned input. For repositories
+//! hosted on GitHub (`https://github.com/<owner>/<repo>`), [`RefsCache`] asks
+//! the GitHub API instead of fetching the repository, and keeps the answers
+//! under `~/.cache/syslua/refs/`:
+//!
+//! - An answer younger than `settings.fetch.refs_ttl` (default 5 minutes) is
+//!   used without asking again.
+//! - An older one is revalidated with its `ETag`. GitHub doesn't count a
+//!   `304 Not Modified` against the rate limit.
+//! - When GitHub reports the rate limit as exhausted, no further requests are
+//!   made until it resets, and stale answers are used where there are any.
+//! - `sys update --refresh` ignores cached answers.
+//!
+//! Lookups that fail are left to the caller, which falls back to git.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use super::fetch::{Credentials, FetchAuth, FetchError, FetchTimeouts};
+
+/// GitHub REST API endpoint.
+const GITHUB_API: &str = "https://api.github.com";
+
+/// Items per page of GitHub list endpoints (their maximum).
+const PER_PAGE: usize = 100;
+
+/// Pages of tags read at most, bounding lookups of huge repositories.
+const MAX_PAGES: usize = 50;
+
+/// How remote refs lookups use the cache, from `settings.fetch` and `--refresh`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefsSettings {
+  /// How long an answer is used without revalidating it (`settings.fetch.refs_ttl`).
+  pub ttl: Duration,
+  /// Ignore cached answers (`sys update --refresh`).
+  pub refresh: bool,
+}
+
+impl Default for RefsSettings {
+  fn default() -> Self {
+    Self {
+      ttl: Duration::from_secs(300),
+      refresh: false,
+    }
+  }
+}
+
+/// One page of a cached answer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Page {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  etag: Option<String>,
+  items: Vec<String>,
+}
+
+/// A cached answer, stored as JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+  /// The API URL of the first page, to tell entries apart when debugging.
+  url: String,
+  /// Unix time the answer was last fetched or revalidated.
+  fetched_at: u64,
+  pages: Vec<Page>,
+}
+
+impl Entry {
+  fn items(&self) -> Vec<String> {
+    self.pages.iter().flat_map(|page| page.items.iter().cloned()).collect()
+  }
+}
+
+/// The response to a (conditional) API request.
+enum Response {
+  NotModified,
+  Ok { etag: Option<String>, body: String },
+}
+
+/// Why an API request failed.
+enum RequestError {
+  /// The rate limit is exhausted until the given Unix time.
+  RateLimited {
+    reset: u64,
+  },
+  Other(String),
+}
+
+/// Cached GitHub API lookups of tags and heads.
+///
+/// Clones share the rate limit state, so one exhausted limit stops all
+/// lookups of a resolution.
+#[derive(Clone)]
+pub struct RefsCache {
+  dir: PathBuf,
+  settings: RefsSettings,
+  auth: FetchAuth,
+  timeouts: FetchTimeouts,
+  /// Unix time until which the API must not be asked (0 when not rate limited).
+  limited_until: Arc<AtomicU64>,
+}
+
+impl RefsCache {
+  /// A cache storing its answers in `dir`.
+  pub fn new(dir: impl Into<PathBuf>, settings: RefsSettings, auth: FetchAuth, timeouts: FetchTimeouts) -> Self {
+    Self {
+      dir: dir.into(),
+      settings,
+      auth,
+      timeouts,
+      limited_until: Arc::new(AtomicU64::new(0)),
+    }
+  }
+
+  /// Whether lookups of `url` are answered by this cache.
+  pub fn supports(url: &str) -> bool {
+    github_repo(url).is_some()
+  }
+
+  /// The tag names of the repository at `url`, sorted.
+  pub fn tags(&self, url: &str) -> Result<Vec<String>, FetchError> {
+    let (owner, repo) = github_repo(url).ok_or_else(|| unsupported(url))?;
+    let first = format!("{}/repos/{}/{}/tags?per_page={}", GITHUB_API, owner, repo, PER_PAGE);
+    let mut tags = self.lookup(url, &first, true, |body| {
+      let tags: Vec<NamedRef> = serde_json::from_str(body).map_err(|e| e.to_string())?;
+      Ok(tags.into_iter().map(|tag| tag.name).collect())
+    })?;
+    tags.sort();
+    debug!(url, count = tags.len(), "looked up tags");
+    Ok(tags)
+  }
+
+  /// The commit hash the default branch of the repository at `url` points to.
+  pub fn head(&self, url: &str) -> Result<String, FetchError> {
+    let (owner, repo) = github_repo(url).ok_or_else(|| unsupported(url))?;
+    let api_url = format!("{}/repos/{}/{}/commits/HEAD", GITHUB_API, owner, repo);
+    let items = self.lookup(url, &api_url, false, |body| Ok(vec![body.trim().to_string()]))?;
+    let head = items.into_iter().next().unwrap_or_default();
+    if head.len() != 40 || !head.chars().all(|c| c.is_ascii_hexdigit()) {
+      return Err(FetchError::RemoteRefs {
+        url: url.to_string(),
+        message: format!("unexpected commit hash '{}'", head),
+      });
+    }
+    debug!(url, head, "looked up head");
+    Ok(head)
+  }
+
+  /// Answer a lookup from the cache, revalidating it when it is too old.
+  ///
+  /// `paginated` lookups follow pages until one isn't full; `parse` turns a
+  /// page's body into its items.
+  fn lookup(
+    &self,
+    url: &str,
+    api_url: &str,
+    paginated: bool,
+    parse: impl Fn(&str) -> Result<Vec<String>, String>,
+  ) -> Result<Vec<String>, FetchError> {
+    let path = self.entry_path(api_url);
+    let cached = if self.settings.refresh { None } else { read_entry(&path) };
+    let now = unix_now();
+
+    if let Some(entry) = &cached
+      && now.saturating_sub(entry.fetched_at) < self.settings.ttl.as_secs()
+    {
+      debug!(url = api_url, "using cached refs");
+      return Ok(entry.items());
+    }
+
+    let refs_error = |message: String| FetchError::RemoteRefs {
+      url: url.to_string(),
+      message,
+    };
+
+    let limited_until = self.limited_until.load(Ordering::SeqCst);
+    let fetched = if limited_until > now {
+      Err(RequestError::RateLimited { reset: limited_until })
+    } else {
+      self.fetch_pages(api_url, paginated, cached.as_ref(), &parse)
+    };
+
+    match fetched {
+      Ok(pages) => {
+        let entry = Entry {
+          url: api_url.to_string(),
+          fetched_at: now,
+          pages,
+        };
+        if let Err(e) = write_entry(&path, &entry) {
+          debug!(path = %path.display(), error = %e, "failed to cache refs");
+        }
+        Ok(entry.items())
+      }
+      Err(RequestError::RateLimited { reset }) => {
+        self.limited_until.fetch_max(reset, Ordering::SeqCst);
+        let wait = reset.saturating_sub(now);
+        match cached {
+          Some(entry) => {
+            warn!(
+              url,
+              wait_secs = wait,
+              "GitHub API rate limit exhausted, using cached refs"
+            );
+            Ok(entry.items())
+          }
+          None => Err(refs_error(format!(
+            "GitHub API rate limit exhausted for {}s (set a token for api.github.com in settings.fetch.tokens)",
+            wait
+          ))),
+        }
+      }
+      Err(RequestError::Other(message)) => match cached {
+        Some(entry) => {
+          warn!(url, error = %message, "failed to revalidate cached refs, using them");
+          Ok(entry.items())
+        }
+        None => Err(refs_error(message)),
+      },
+    }
+  }
+
+  /// Fetch the pages of an answer, sending the cached `ETag` of each page.
+  fn fetch_pages(
+    &self,
+    api_url: &str,
+    paginated: bool,
+    cached: Option<&Entry>,
+    parse: &impl Fn(&str) -> Result<Vec<String>, String>,
+  ) -> Result<Vec<Page>, RequestError> {
+    let credentials = self
+      .auth
+      .credentials_for("api.github.com")
+      .and_then(|found| match found {
+        Some(credentials) => Ok(Some(credentials)),
+        None => self.auth.credentials_for("github.com"),
+      })
+      .map_err(|e| RequestError::Other(e.to_string()))?;
+
+    let mut pages = Vec::new();
+    for number in 0..MAX_PAGES {
+      let page_url = if number == 0 {
+        api_url.to_string()
+      } else {
+        format!("{}&page={}", api_url, number + 1)
+      };
+      let cached_page = cached.and_then(|entry| entry.pages.get(number));
+      let etag = cached_page.and_then(|page| page.etag.as_deref());
+
+      let page = match self.request(&page_url, etag, paginated, credentials.clone())? {
+        Response::NotModified => cached_page
+          .cloned()
+          .ok_or_else(|| RequestError::Other("304 Not Modified without a cached page".to_string()))?,
+        Response::Ok { etag, body } => Page {
+          etag,
+          items: parse(&body).map_err(RequestError::Other)?,
+        },
+      };
+      let full = page.items.len() >= PER_PAGE;
+      pages.push(page);
+      if !paginated || !full {
+        break;
+      }
+    }
+    Ok(pages)
+  }
+
+  /// Send one API request on its own thread and runtime, as inputs resolve
+  /// synchronously.
+  fn request(
+    &self,
+    url: &str,
+    etag: Option<&str>,
+    json: bool,
+    credentials: Option<Credentials>,
+  ) -> Result<Response, RequestError> {
+    std::thread::scope(|scope| {
+      scope
+        .spawn(|| {
+          let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| RequestError::Other(e.to_string()))?;
+          rt.block_on(get(url, etag, json, credentials, &self.timeouts))
+        })
+        .join()
+        .unwrap_or_else(|_| Err(RequestError::Other("request thread panicked".to_string())))
+    })
+  }
+
+  /// Cache file of the answer starting at `api_url`.
+  fn entry_path(&self, api_url: &str) -> PathBuf {
+    let digest = hex::encode(Sha256::digest(api_url.as_bytes()));
+    self.dir.join(format!("{}.json", &digest[..32]))
+  }
+}
+
+/// The `name` of a tag in a GitHub API listing.
+#[derive(Deserialize)]
+struct NamedRef {
+  name: String,
+}
+
+async fn get(
+  url: &str,
+  etag: Option<&str>,
+  json: bool,
+  credentials: Option<Credentials>,
+  timeouts: &FetchTimeouts,
+) -> Result<Response, RequestError> {
+  let other = |e: reqwest::Error| RequestError::Other(e.to_string());
+  let client = reqwest::Client::builder()
+    .connect_timeout(timeouts.connect)
+    .timeout(timeouts.total)
+    .user_agent(concat!("syslua/", env!("CARGO_PKG_VERSION")))
+    .build()
+    .map_err(other)?;
+
+  let accept = if json {
+    "application/vnd.github+json"
+  } else {
+    "application/vnd.github.sha"
+  };
+  let mut request = client
+    .get(url)
+    .header("Accept", accept)
+    .header("X-GitHub-Api-Version", "2022-11-28");
+  if let Some(etag) = etag {
+    request = request.header("If-None-Match", etag);
+  }
+  request = match credentials {
+    Some(Credentials::Bearer(token)) => request.bearer_auth(token),
+    Some(Credentials::Basic { login, password }) => request.basic_auth(login, Some(password)),
+    None => request,
+  };
+
+  let response = request.send().await.map_err(other)?;
+  let status = response.status();
+  let header = |name: &str| {
+    response
+      .headers()
+      .get(name)
+      .and_then(|value| value.to_str().ok())
+      .map(str::to_string)
+  };
+
+  if status == reqwest::StatusCode::NOT_MODIFIED {
+    return Ok(Response::NotModified);
+  }
+  if let Some(reset) = rate_limit_reset(
+    status.as_u16(),
+    header("x-ratelimit-remaining").as_deref(),
+    header("x-ratelimit-reset").as_deref(),
+    header("retry-after").as_deref(),
+    unix_now(),
+  ) {
+    return Err(RequestError::RateLimited { reset });
+  }
+
+  let etag = header("etag");
+  let response = response.error_for_status().map_err(other)?;
+  let body = response.text().await.map_err(other)?;
+  Ok(Response::Ok { etag, body })
+}
+
+/// The Unix time a rate limited response allows requests again, or `None` if
+/// the response isn't rate limited.
+fn rate_limit_reset(
+  status: u16,
+  remaining: Option<&str>,
+  reset: Option<&str>,
+  retry_after: Option<&str>,
+  now: u64,
+) -> Option<u64> {
+  if status != 403 && status != 429 {
+    return None;
+  }
+  if let Some(seconds) = retry_after.and_then(|value| value.trim().parse::<u64>().ok()) {
+    return Some(now + seconds);
+  }
+  if remaining.map(str::trim) == Some("0") {
+    // Without a reset time, back off for a minute
+    return Some(reset.and_then(|value| value.trim().parse().ok()).unwrap_or(now + 60));
+  }
+  // 429 without details still asks to slow down
+  (status == 429).then_some(now + 60)
+}
+
+/// The owner and repository name of a GitHub HTTPS URL.
+fn github_repo(url: &str) -> Option<(String, String)> {
+  let rest = url
+    .strip_prefix("https://github.com/")
+    .or_else(|| url.strip_prefix("https://www.github.com/"))?;
+  let mut parts = rest.trim_end_matches('/').split('/');
+  let owner = parts.next().filter(|owner| !owner.is_empty())?;
+  let repo = parts.next()?;
+  let repo = repo.strip_suffix(".git").unwrap_or(repo);
+  if repo.is_empty() || parts.next().is_some() {
+    return None;
+  }
+  Some((owner.to_string(), repo.to_string()))
+}
+
+fn unsupported(url: &str) -> FetchError {
+  FetchError::RemoteRefs {
+    url: url.to_string(),
+    message: "not a GitHub repository".to_string(),
+  }
+}
+
+fn read_entry(path: &Path) -> Option<Entry> {
+  let content = fs::read_to_string(path).ok()?;
+  serde_json::from_str(&content).ok()
+}
+
+/// Write an entry through a temporary file, so concurrent readers never see
+/// half of it.
+fn write_entry(path: &Path, entry: &Entry) -> std::io::Result<()> {
+  let dir = path.parent().unwrap_or(Path::new("."));
+  fs::create_dir_all(dir)?;
+  let content = serde_json::to_vec_pretty(entry).map_err(std::io::Error::other)?;
+  let temp = tempfile::NamedTempFile::new_in(dir)?;
+  fs::write(temp.path(), content)?;
+  temp.persist(path).map_err(|e| e.error)?;
+  Ok(())
+}
+
+fn unix_now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  fn cache(dir: &Path, settings: RefsSettings) -> RefsCache {
+    RefsCache::new(dir, settings, FetchAuth::default(), FetchTimeouts::default())
+  }
+
+  #[test]
+  fn parses_github_urls() {
+    let expected = Some(("org".to_string(), "repo".to_string()));
+    assert_eq!(github_repo("https://github.com/org/repo.git"), expected);
+    assert_eq!(github_repo("https://github.com/org/repo"), expected);
+    assert_eq!(github_repo("https://github.com/org/repo/"), expected);
+    assert_eq!(github_repo("https://gitlab.com/org/repo.git"), None);
+    assert_eq!(github_repo("https://github.com/org"), None);
+    assert_eq!(github_repo("https://github.com/org/repo/tree/main"), None);
+    assert_eq!(github_repo("git@github.com:org/repo.git"), None);
+  }
+
+  #[test]
+  fn fresh_entries_are_used_without_requests() {
+    let temp = TempDir::new().unwrap();
+    let cache = cache(temp.path(), RefsSettings::default());
+    let api_url = format!("{}/repos/org/repo/tags?per_page={}", GITHUB_API, PER_PAGE);
+    let entry = Entry {
+      url: api_url.clone(),
+      fetched_at: unix_now(),
+      pages: vec![Page {
+        etag: Some("\"abc\"".to_string()),
+        items: vec!["v2.0.0".to_string(), "v1.0.0".to_string()],
+      }],
+    };
+    write_entry(&cache.entry_path(&api_url), &entry).unwrap();
+
+    assert_eq!(
+      cache.tags("https://github.com/org/repo.git").unwrap(),
+      ["v1.0.0", "v2.0.0"]
+    );
+  }
+
+  #[test]
+  fn stale_entries_are_used_while_rate_limited() {
+    let temp = TempDir::new().unwrap();
+    let cache = cache(temp.path(), RefsSettings::default());
+    let api_url = format!("{}/repos/org/repo/commits/HEAD", GITHUB_API);
+    let head = "a".repeat(40);
+    let entry = Entry {
+      url: api_url.clone(),
+      fetched_at: 0,
+      pages: vec![Page {
+        etag: None,
+        items: vec![head.clone()],
+      }],
+    };
+    write_entry(&cache.entry_path(&api_url), &entry).unwrap();
+    cache.limited_until.store(unix_now() + 3600, Ordering::SeqCst);
+
+    assert_eq!(cache.head("https://github.com/org/repo").unwrap(), head);
+
+    // Nothing to fall back on without a cached answer
+    let err = cache.tags("https://github.com/org/repo").unwrap_err();
+    assert!(err.to_string().contains("rate limit exhausted"), "{}", err);
+  }
+
+  #[test]
+  fn refresh_ignores_cached_entries() {
+    let temp = TempDir::new().unwrap();
+    let settings = RefsSettings {
+      refresh: true,
+      ..Default::default()
+    };
+    let cache = cache(temp.path(), settings);
+    let api_url = format!("{}/repos/org/repo/commits/HEAD", GITHUB_API);
+    let entry = Entry {
+      url: api_url.clone(),
+      fetched_at: unix_now(),
+      pages: vec![Page {
+        etag: None,
+        items: vec!["a".repeat(40)],
+      }],
+    };
+    write_entry(&cache.entry_path(&api_url), &entry).unwrap();
+    // Rate limited, so the request that would replace the entry fails
+    cache.limited_until.store(unix_now() + 3600, Ordering::SeqCst);
+
+    assert!(cache.head("https://github.com/org/repo").is_err());
+  }
+
+  #[test]
+  fn detects_rate_limited_responses() {
+    assert_eq!(rate_limit_reset(403, Some("0"), Some("1700"), None, 1000), Some(1700));
+    assert_eq!(rate_limit_reset(429, None, None, Some("30"), 1000), Some(1030));
+    assert_eq!(rate_limit_reset(429, None, None, None, 1000), Some(1060));
+    // A 403 with requests left is a permission error, not a rate limit
+    assert_eq!(rate_limit_reset(403, Some("42"), Some("1700"), None, 1000), None);
+    assert_eq!(rate_limit_reset(200, Some("0"), Some("1700"), None, 1000), None);
+  }
+}
@@ -0,0 +1,297 @@
+//! Registries of community inputs.
+//!
+//! A registry is a JSON index listing inputs others can declare, published
+//! over HTTP (or kept as a local file) and configured in `settings.registries`:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "inputs": [
+//!     {
+//!       "name": "dotfiles-kit",
+//!       "description": "Modules for shells, editors and git",
+//!       "url": "git:https://github.com/example/dotfiles-kit.git",
+//!       "tags": ["shell", "git"]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `sys search <term>` matches entries of every configured registry by name,
+//! description and tags; `sys add-input <name>` declares the entry named
+//! `name` in the config (see [`crate::update::add_input`]). Registries are
+//! consulted in the order they are configured, so the first one listing a
+//! name wins.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+/// Current registry index format version.
+pub const REGISTRY_INDEX_VERSION: u32 = 1;
+
+/// Errors fetching or reading a registry index.
+#[derive(Debug, Error)]
+pub enum RegistryError {
+  #[error("failed to fetch registry {url}: {message}")]
+  Fetch { url: String, message: String },
+
+  #[error("failed to read registry {path}: {source}")]
+  Read {
+    path: PathBuf,
+    #[source]
+    source: std::io::Error,
+  },
+
+  #[error("failed to parse registry {url}: {source}")]
+  Parse {
+    url: String,
+    #[source]
+    source: serde_json::Error,
+  },
+
+  #[error("registry {url} has index format version {version}; this sys supports up to {REGISTRY_INDEX_VERSION}")]
+  UnsupportedVersion { url: String, version: u32 },
+}
+
+/// The index of a registry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryIndex {
+  /// Index format version.
+  pub version: u32,
+  /// Inputs listed by the registry.
+  #[serde(default)]
+  pub inputs: Vec<RegistryEntry>,
+}
+
+/// An input listed by a registry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryEntry {
+  /// Name the input is declared under by default.
+  pub name: String,
+  /// Input URL, as written in a config's `inputs` table.
+  pub url: String,
+  #[serde(default)]
+  pub description: String,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub tags: Vec<String>,
+}
+
+impl RegistryEntry {
+  /// How well this entry matches `term` (lowercase), if at all. Higher is better.
+  fn score(&self, term: &str) -> Option<u8> {
+    let name = self.name.to_lowercase();
+    if name == term {
+      Some(3)
+    } else if name.contains(term) {
+      Some(2)
+    } else if self.tags.iter().any(|tag| tag.to_lowercase() == term) {
+      Some(1)
+    } else if self.description.to_lowercase().contains(term) {
+      Some(0)
+    } else {
+      None
+    }
+  }
+}
+
+/// A registry entry found by [`search`] or [`find`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegistryMatch {
+  /// URL of the registry listing the entry.
+  pub registry: String,
+  #[serde(flatten)]
+  pub entry: RegistryEntry,
+}
+
+impl RegistryIndex {
+  /// Parse the index fetched from `url`, rejecting formats newer than this
+  /// binary understands.
+  pub fn parse(url: &str, content: &str) -> Result<Self, RegistryError> {
+    let index: Self = serde_json::from_str(content).map_err(|source| RegistryError::Parse {
+      url: url.to_string(),
+      source,
+    })?;
+    if index.version > REGISTRY_INDEX_VERSION {
+      return Err(RegistryError::UnsupportedVersion {
+        url: url.to_string(),
+        version: index.version,
+      });
+    }
+    Ok(index)
+  }
+}
+
+/// Fetch the index of the registry at `url`: an `http(s)://` URL, or a
+/// `file://` URL or path of a local index.
+pub async fn fetch_registry(url: &str) -> Result<RegistryIndex, RegistryError> {
+  debug!(url = %url, "fetching registry index");
+  if !url.starts_with("http://") && !url.starts_with("https://") {
+    let path = PathBuf::from(url.strip_prefix("file://").unwrap_or(url));
+    let content = tokio::fs::read_to_string(&path)
+      .await
+      .map_err(|source| RegistryError::Read { path, source })?;
+    return RegistryIndex::parse(url, &content);
+  }
+
+  let fetch_failed = |message: String| RegistryError::Fetch {
+    url: url.to_string(),
+    message,
+  };
+  let response = reqwest::Client::new()
+    .get(url)
+    .send()
+    .await
+    .map_err(|e| fetch_failed(e.to_string()))?;
+  if !response.status().is_success() {
+    return Err(fetch_failed(format!("HTTP {}", response.status())));
+  }
+  let body = response.text().await.map_err(|e| fetch_failed(e.to_string()))?;
+  RegistryIndex::parse(url, &body)
+}
+
+/// Fetch the indexes of `urls` concurrently, in the same order.
+///
+/// A fetch whose task fails (panics) is reported as a fetch error of its URL.
+pub async fn fetch_registries(urls: &[String]) -> Vec<(String, Result<RegistryIndex, RegistryError>)> {
+  let tasks: Vec<_> = urls
+    .iter()
+    .map(|url| {
+      let url = url.clone();
+      tokio::spawn(async move { fetch_registry(&url).await })
+    })
+    .collect();
+  let mut results = Vec::with_capacity(urls.len());
+  for (url, task) in urls.iter().zip(tasks) {
+    let result = task.await.unwrap_or_else(|e| {
+      Err(RegistryError::Fetch {
+        url: url.clone(),
+        message: format!("fetch task failed: {}", e),
+      })
+    });
+    results.push((url.clone(), result));
+  }
+  results
+}
+
+/// Entries of `indexes` (registry URL and index) matching `term`
+/// case-insensitively: exact names first, then names containing it, tags
+/// and descriptions. An empty term lists everything.
+pub fn search(indexes: &[(String, RegistryIndex)], term: &str) -> Vec<RegistryMatch> {
+  let term = term.trim().to_lowercase();
+  let mut matches: Vec<(u8, RegistryMatch)> = indexes
+    .iter()
+    .flat_map(|(registry, index)| {
+      index.inputs.iter().filter_map(|entry| {
+        let score = if term.is_empty() { Some(0) } else { entry.score(&term) }?;
+        Some((
+          score,
+          RegistryMatch {
+            registry: registry.clone(),
+            entry: entry.clone(),
+          },
+        ))
+      })
+    })
+    .collect();
+  // Stable, so an input listed by several registries keeps their order
+  matches.sort_by(|(a, a_match), (b, b_match)| b.cmp(a).then_with(|| a_match.entry.name.cmp(&b_match.entry.name)));
+  matches.into_iter().map(|(_, m)| m).collect()
+}
+
+/// The entry named exactly `name` in the first of `indexes` listing it.
+pub fn find(indexes: &[(String, RegistryIndex)], name: &str) -> Option<RegistryMatch> {
+  indexes.iter().find_map(|(registry, index)| {
+    index
+      .inputs
+      .iter()
+      .find(|entry| entry.name == name)
+      .map(|entry| RegistryMatch {
+        registry: registry.clone(),
+        entry: entry.clone(),
+      })
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entry(name: &str, description: &str, tags: &[&str]) -> RegistryEntry {
+    RegistryEntry {
+      name: name.to_string(),
+      url: format!("git:https://github.com/example/{}.git", name),
+      description: description.to_string(),
+      tags: tags.iter().map(|tag| tag.to_string()).collect(),
+    }
+  }
+
+  fn indexes() -> Vec<(String, RegistryIndex)> {
+    vec![
+      (
+        "https://one.example.com/index.json".to_string(),
+        RegistryIndex {
+          version: 1,
+          inputs: vec![
+            entry("neovim-kit", "Neovim with plugins", &["editor"]),
+            entry("shell-utils", "Prompt and aliases for zsh", &["shell"]),
+          ],
+        },
+      ),
+      (
+        "https://two.example.com/index.json".to_string(),
+        RegistryIndex {
+          version: 1,
+          inputs: vec![entry("zsh", "The Z shell", &[]), entry("neovim-kit", "A fork", &[])],
+        },
+      ),
+    ]
+  }
+
+  #[test]
+  fn search_ranks_names_before_tags_and_descriptions() {
+    let names: Vec<_> = search(&indexes(), "ZSH").into_iter().map(|m| m.entry.name).collect();
+    assert_eq!(names, ["zsh", "shell-utils"]);
+
+    let names: Vec<_> = search(&indexes(), "shell").into_iter().map(|m| m.entry.name).collect();
+    assert_eq!(names, ["shell-utils", "zsh"]);
+
+    assert_eq!(search(&indexes(), "").len(), 4);
+    assert!(search(&indexes(), "emacs").is_empty());
+  }
+
+  #[test]
+  fn find_prefers_the_first_registry() {
+    let found = find(&indexes(), "neovim-kit").unwrap();
+    assert_eq!(found.registry, "https://one.example.com/index.json");
+    assert_eq!(found.entry.description, "Neovim with plugins");
+    assert!(find(&indexes(), "neovim").is_none());
+  }
+
+  #[test]
+  fn parse_rejects_newer_formats() {
+    let index = RegistryIndex::parse("r", r#"{"version": 1, "inputs": [{"name": "a", "url": "path:./a"}]}"#).unwrap();
+    assert_eq!(index.inputs[0].description, "");
+    assert!(matches!(
+      RegistryIndex::parse("r", r#"{"version": 2, "inputs": []}"#),
+      Err(RegistryError::UnsupportedVersion { version: 2, .. })
+    ));
+  }
+
+  #[tokio::test]
+  async fn fetches_local_indexes() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let path = temp.path().join("index.json");
+    std::fs::write(&path, r#"{"version": 1, "inputs": [{"name": "a", "url": "path:./a"}]}"#).unwrap();
+    let urls = vec![
+      format!("file://{}", path.display()),
+      temp.path().join("missing.json").display().to_string(),
+    ];
+
+    let results = fetch_registries(&urls).await;
+    assert_eq!(results[0].1.as_ref().unwrap().inputs.len(), 1);
+    assert!(matches!(results[1].1, Err(RegistryError::Read { .. })));
+  }
+}
@@ -3,13 +3,16 @@
 //! This module coordinates the full input resolution flow:
 //! 1. Parse input URLs from the raw `M.inputs` table
 //! 2. Check lock file for pinned revisions
-//! 3. Fetch/resolve each input (git clone/fetch or path resolution)
+//! 3. Fetch/resolve each input (via the registered [`Fetcher`](super::fetch::Fetcher)
+//!    for its scheme, or path resolution)
 //! 4. Update lock file with new entries
 //!
 //! # Resolution Algorithm
 //!
 //! For each input in the config:
 //! - If config specifies a rev (`#v1.0.0`): use that rev, verify lock matches if present
+//! - If config specifies a semver range (`#semver:^1.2`): use the locked tag's
+//!   revision, or pick the highest matching tag when not locked or updating
 //! - If locked and URL matches: use locked revision
 //! - If locked but URL differs: error (requires `sys update`)
 //! - If not locked: fetch latest and add to lock file
@@ -19,7 +22,8 @@
 //! When an input has its own dependencies (declared in its init.lua), we:
 //! 1. Fetch the input first
 //! 2. Parse its init.lua to extract declared inputs
-//! 3. Apply any `follows` overrides from the parent config
+//! 3. Apply any overrides declared for its full path by the inputs above it
+//!    (see [`DependencyGraph::declared_overrides`])
 //! 4. Recursively resolve transitive dependencies
 
 use std::cell::RefCell;
@@ -31,10 +35,10 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use tracing::{debug, info, trace, warn};
 
-use super::fetch::{FetchError, fetch_git, resolve_path};
-use super::graph::{DependencyGraph, GraphError, build_initial_graph};
+use super::fetch::{FetchError, Fetchers, ResolvingGuard, resolve_path};
+use super::graph::{DependencyGraph, FOLLOWS_MARKER, GraphError, build_initial_graph};
 use super::lock::{LOCK_FILENAME, LockFile, LockedInput, load_input_lock};
-use super::source::{InputSource, ParseError, parse, source_type};
+use super::source::{InputSource, ParseError, semver_range, source_type};
 use super::store::{InputStore, StoreError};
 use super::types::{
   InputDecl, InputDecls, InputOverride, LuaNamespace, ResolvedInput as TypesResolvedInput,
@@ -43,6 +47,7 @@ use super::types::{
 use crate::lua::runtime;
 use crate::manifest::Manifest;
 use crate::platform::paths::cache_dir;
+use crate::util::metadata::Metadata;
 
 /// Result of transitive input resolution.
 #[derive(Debug)]
@@ -60,6 +65,32 @@ pub struct ResolutionResult {
   pub namespaces: Vec<LuaNamespace>,
 }
 
+/// What resolved before some inputs failed to fetch.
+///
+/// Resolution carries on past an input that fails to fetch (e.g. one whose
+/// server timed out), so callers can still report the inputs that resolved.
+#[derive(Debug)]
+pub struct PartialResolution {
+  /// Root inputs that resolved, with those of their transitive deps that resolved.
+  pub inputs: TypesResolvedInputs,
+  /// Lock file with the entries of the inputs that resolved.
+  pub lock_file: LockFile,
+  /// Whether the entries of the inputs that resolved changed the lock file.
+  pub lock_changed: bool,
+  /// Inputs that failed, by full path (e.g. `pkgs/utils`).
+  pub failed: BTreeMap<String, ResolveError>,
+}
+
+impl std::fmt::Display for PartialResolution {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} input(s) failed", self.failed.len())?;
+    for (path, error) in &self.failed {
+      write!(f, "\n  {}: {}", path, error)?;
+    }
+    Ok(())
+  }
+}
+
 /// Details of a namespace conflict between two inputs.
 #[derive(Debug)]
 pub struct NamespaceConflictError {
@@ -140,6 +171,20 @@ pub enum ResolveError {
   /// Cyclic dependency detected.
   #[error("cyclic dependency detected: {cycle_path}")]
   CyclicDependency { cycle_path: String },
+
+  /// An override was given for an input the config does not declare.
+  #[error("cannot override input '{name}': not declared in config")]
+  UnknownOverride { name: String },
+
+  /// The lock file pins an input with a fetcher this build does not have.
+  #[error(
+    "input '{name}' is locked with unsupported fetcher type '{type_}'; upgrade syslua or run 'sys update {name}'"
+  )]
+  UnsupportedLockType { name: String, type_: String },
+
+  /// Some inputs failed to fetch; the others resolved.
+  #[error("{0}")]
+  Incomplete(Box<PartialResolution>),
 }
 
 /// Resolve inputs with full transitive dependency support.
@@ -154,16 +199,36 @@ pub enum ResolveError {
 /// * `input_decls` - Input declarations from the config (supports extended syntax)
 /// * `config_dir` - Directory containing the config file
 /// * `force_update` - Optional set of input names to force update
+/// * `overrides` - Optional map of root input names to replacement URLs. Overridden
+///   inputs (and their transitive dependencies) bypass the lock file entirely: they
+///   are neither checked against nor recorded in it.
 ///
 /// # Returns
 ///
 /// A [`ResolutionResult`] with fully resolved inputs including transitive deps.
+/// When inputs fail to fetch, the others are still resolved and returned in
+/// [`ResolveError::Incomplete`].
 pub fn resolve_inputs(
   input_decls: &InputDecls,
   config_dir: &Path,
   force_update: Option<&HashSet<String>>,
+  overrides: Option<&BTreeMap<String, String>>,
+) -> Result<ResolutionResult, ResolveError> {
+  resolve_inputs_with(input_decls, config_dir, force_update, overrides, &Fetchers::default())
+}
+
+/// [`resolve_inputs`] with an explicit fetcher registry (e.g. one carrying
+/// credentials from `settings.fetch`, or extra schemes).
+pub fn resolve_inputs_with(
+  input_decls: &InputDecls,
+  config_dir: &Path,
+  force_update: Option<&HashSet<String>>,
+  overrides: Option<&BTreeMap<String, String>>,
+  fetchers: &Fetchers,
 ) -> Result<ResolutionResult, ResolveError> {
+  let input_decls = &apply_url_overrides(input_decls, overrides)?;
   let lock_path = config_dir.join(LOCK_FILENAME);
+  let _resolving = ResolvingGuard::enter();
 
   // Load existing lock file (or create new)
   let mut lock_file = LockFile::load(&lock_path)
@@ -183,12 +248,18 @@ pub fn resolve_inputs(
   // Track resolved inputs: full_path -> (path, rev, url)
   let mut resolved_cache: BTreeMap<String, (PathBuf, String, String)> = BTreeMap::new();
 
+  // Track metadata declared in each input's init.lua: full_path -> metadata
+  let mut metadata_cache: BTreeMap<String, Metadata> = BTreeMap::new();
+
   // Track which inputs we've processed for transitive deps
   let mut processed_for_deps: HashSet<String> = HashSet::new();
 
   // Track URLs we've seen to avoid infinite loops with circular deps
   let mut seen_urls: HashSet<String> = HashSet::new();
 
+  // Inputs that failed to fetch: full_path -> error
+  let mut failed: BTreeMap<String, ResolveError> = BTreeMap::new();
+
   info!(
     count = input_decls.len(),
     "resolving inputs with transitive dependencies"
@@ -244,34 +315,53 @@ pub fn resolve_inputs(
           lock_file: &mut lock_file,
           lock_changed: &mut lock_changed,
           force_update,
+          overrides,
           inputs_cache_dir: &inputs_cache_dir,
+          fetchers,
         };
 
-        let (path, rev) = resolve_single_input(name, &url, &full_path, &base_dir, &mut ctx)?;
-
-        resolved_cache.insert(full_path.clone(), (path, rev, url.clone()));
+        match resolve_single_input(name, &url, &full_path, &base_dir, &mut ctx) {
+          Ok((path, rev)) => {
+            resolved_cache.insert(full_path.clone(), (path, rev, url.clone()));
+          }
+          // Keep resolving the other inputs, so the caller can report them
+          Err(e @ ResolveError::Fetch { .. }) => {
+            warn!(input = %full_path, error = %e, "input failed to resolve");
+            failed.insert(full_path.clone(), e);
+            processed_for_deps.insert(full_path);
+            continue;
+          }
+          Err(e) => return Err(e),
+        }
       }
 
-      // Extract transitive dependencies from this input's init.lua
+      // Extract metadata and transitive dependencies from this input's init.lua
       if let Some((path, _, _)) = resolved_cache.get(&full_path) {
         let init_path = path.join("init.lua");
-        if init_path.exists()
-          && !processed_for_deps.contains(&full_path)
-          && let Ok(transitive_decls) = extract_input_decls_from_file(&init_path)
-          && !transitive_decls.is_empty()
-        {
+        let init_info = if init_path.exists() && !processed_for_deps.contains(&full_path) {
+          extract_input_info_from_file(&init_path).ok()
+        } else {
+          None
+        };
+        let transitive_decls = match init_info {
+          Some((decls, metadata)) => {
+            if let Some(metadata) = metadata {
+              metadata_cache.insert(full_path.clone(), metadata);
+            }
+            decls
+          }
+          None => BTreeMap::new(),
+        };
+
+        if !transitive_decls.is_empty() {
           trace!(
             input = %full_path,
             count = transitive_decls.len(),
             "found transitive dependencies"
           );
 
-          // Get overrides from parent's declaration
-          let parent_overrides = graph
-            .get(&full_path)
-            .and_then(|n| n.decl.overrides())
-            .cloned()
-            .unwrap_or_default();
+          // Overrides declared by this input or any input above it, by full path
+          let overrides = graph.declared_overrides()?;
 
           // Load the input's own lock file (if it has one)
           // This is used to pin transitive dependencies to specific revisions
@@ -288,8 +378,8 @@ pub fn resolve_inputs(
             // 3. Input's `init.lua` declaration (floating) - if no lock exists
 
             // Apply override if present (follows takes highest precedence)
-            if let Some(override_) = parent_overrides.get(&dep_name) {
-              dep_decl = apply_override(dep_decl, override_.clone());
+            if let Some(declared) = overrides.get(&format!("{}/{}", full_path, dep_name)) {
+              dep_decl = apply_override(dep_decl, declared.override_.clone());
             } else if let Some(ref lock) = input_lock {
               // No override - check input's lock file for a pinned revision
               dep_decl = apply_input_lock_to_decl(dep_decl, &dep_name, lock);
@@ -334,15 +424,25 @@ pub fn resolve_inputs(
   for name in input_decls.keys() {
     if let Some((path, rev, _)) = resolved_cache.get(name) {
       // Build transitive inputs for this root input
-      let transitive = build_transitive_inputs(&graph, &resolved_cache, name);
+      let transitive = build_transitive_inputs(&graph, &resolved_cache, &metadata_cache, name);
 
       final_resolved.insert(
         name.clone(),
-        TypesResolvedInput::with_inputs(path.clone(), rev.clone(), transitive),
+        TypesResolvedInput::with_inputs(path.clone(), rev.clone(), transitive)
+          .with_metadata(metadata_cache.get(name).cloned()),
       );
     }
   }
 
+  if !failed.is_empty() {
+    return Err(ResolveError::Incomplete(Box::new(PartialResolution {
+      inputs: final_resolved,
+      lock_file,
+      lock_changed,
+      failed,
+    })));
+  }
+
   // Clean up stale lock entries
   let _all_resolved_names: HashSet<&String> = resolved_cache.keys().collect();
   let locked_names = lock_file.input_names();
@@ -367,6 +467,30 @@ pub fn resolve_inputs(
   })
 }
 
+/// Replace the URLs of root input declarations with command-line overrides.
+///
+/// Extended declarations keep their `inputs` overrides so `follows` still applies.
+fn apply_url_overrides(
+  input_decls: &InputDecls,
+  overrides: Option<&BTreeMap<String, String>>,
+) -> Result<InputDecls, ResolveError> {
+  let mut decls = input_decls.clone();
+  for (name, url) in overrides.into_iter().flatten() {
+    let Some(decl) = decls.get_mut(name) else {
+      return Err(ResolveError::UnknownOverride { name: name.clone() });
+    };
+    info!(name = %name, url = %url, "overriding input URL");
+    *decl = match decl {
+      InputDecl::Extended { inputs, .. } => InputDecl::Extended {
+        url: Some(url.clone()),
+        inputs: std::mem::take(inputs),
+      },
+      InputDecl::Url(_) => InputDecl::Url(url.clone()),
+    };
+  }
+  Ok(decls)
+}
+
 /// Get the effective URL for a node, considering follows overrides.
 fn get_effective_url(graph: &DependencyGraph, path: &str, node: &super::graph::GraphNode) -> Option<String> {
   // Check if this path has a follows override
@@ -392,7 +516,7 @@ fn apply_override(decl: InputDecl, override_: InputOverride) -> InputDecl {
         url: decl.url().map(|s| s.to_string()),
         inputs: {
           let mut m = BTreeMap::new();
-          m.insert("__follows__".to_string(), InputOverride::Follows(target));
+          m.insert(FOLLOWS_MARKER.to_string(), InputOverride::Follows(target));
           m
         },
       }
@@ -461,16 +585,17 @@ fn apply_input_lock_to_decl(decl: InputDecl, dep_name: &str, lock: &LockFile) ->
 
 /// Inject a revision into a URL, replacing any existing revision.
 ///
-/// For git URLs, this appends `#<rev>` or replaces an existing `#<ref>`.
+/// For git and fetcher URLs, this appends `#<rev>` or replaces an existing `#<ref>`.
 /// For path URLs, this is a no-op (path inputs don't have revisions).
 fn inject_revision_into_url(url: &str, rev: &str) -> String {
-  if let Some(base) = url.strip_prefix("git:") {
-    // Strip any existing revision
-    let base_without_rev = base.split('#').next().unwrap_or(base);
-    format!("git:{}#{}", base_without_rev, rev)
-  } else {
-    // Path or other URL type - don't modify
-    url.to_string()
+  match url.split_once(':') {
+    Some((scheme, base)) if scheme != "path" => {
+      // Strip any existing revision
+      let base_without_rev = base.split('#').next().unwrap_or(base);
+      format!("{}:{}#{}", scheme, base_without_rev, rev)
+    }
+    // Path or unparseable URL - don't modify
+    _ => url.to_string(),
   }
 }
 
@@ -485,11 +610,15 @@ struct ResolveContext<'a> {
   lock_changed: &'a mut bool,
   /// Optional set of inputs to force update.
   force_update: Option<&'a HashSet<String>>,
-  /// Cache directory for git inputs.
+  /// Optional root input URL overrides that bypass the lock file.
+  overrides: Option<&'a BTreeMap<String, String>>,
+  /// Cache directory for fetched inputs.
   inputs_cache_dir: &'a Path,
+  /// Fetchers by URL scheme.
+  fetchers: &'a Fetchers,
 }
 
-/// Resolve a single input (git or path).
+/// Resolve a single input (fetched or path).
 ///
 /// # Arguments
 ///
@@ -507,7 +636,7 @@ fn resolve_single_input(
 ) -> Result<(PathBuf, String), ResolveError> {
   debug!(name, url, path = full_path, "resolving input");
 
-  let source = parse(url).map_err(|e| ResolveError::Parse {
+  let source = ctx.fetchers.parse(url).map_err(|e| ResolveError::Parse {
     name: name.to_string(),
     source: e,
   })?;
@@ -522,6 +651,14 @@ fn resolve_single_input(
     .map(|set| set.is_empty() || set.contains(name) || set.contains(full_path))
     .unwrap_or(false);
 
+  // Overridden inputs and their transitive deps never read or write the lock file
+  let is_overridden = ctx.overrides.is_some_and(|overrides| {
+    overrides
+      .keys()
+      .any(|root| full_path == root.as_str() || full_path.starts_with(&format!("{}/", root)))
+  });
+  let locked_entry = if is_overridden { None } else { locked_entry };
+
   // Verify URL hasn't changed (if locked and not force-updating)
   if !should_force
     && let Some(ref locked) = locked_entry
@@ -534,30 +671,82 @@ fn resolve_single_input(
     });
   }
 
+  // A lock entry written by a newer syslua may name a fetcher this build lacks
+  if !should_force
+    && let Some(ref locked) = locked_entry
+    && locked.type_ != "path"
+    && ctx.fetchers.get(&locked.type_).is_none()
+  {
+    return Err(ResolveError::UnsupportedLockType {
+      name: name.to_string(),
+      type_: locked.type_.clone(),
+    });
+  }
+
+  let type_ = source_type(&source).to_string();
   let (path, rev) = match source {
     InputSource::Git {
-      url: git_url,
+      url: fetch_url,
       rev: config_rev,
+    }
+    | InputSource::Fetcher {
+      url: fetch_url,
+      rev: config_rev,
+      ..
     } => {
-      let target_rev = if should_force {
-        config_rev.as_deref()
-      } else {
-        config_rev.as_deref().or(locked_entry.as_ref().map(|e| e.rev.as_str()))
+      let fetcher = ctx.fetchers.get(&type_).ok_or_else(|| ResolveError::Fetch {
+        name: name.to_string(),
+        source: FetchError::NoFetcher(type_.clone()),
+      })?;
+
+      // A semver range pins the tag it resolved to, like an unpinned input pins its rev
+      let range = config_rev.as_deref().and_then(semver_range);
+      let locked_rev = locked_entry.as_ref().map(|e| e.rev.as_str());
+      let mut tag = None;
+      let target_rev = match &range {
+        Some(_) if !should_force && locked_rev.is_some() => {
+          tag = locked_entry.as_ref().and_then(|e| e.tag.clone());
+          locked_rev
+        }
+        Some(range) => {
+          let tags = fetcher
+            .tags(name, &fetch_url, ctx.inputs_cache_dir)
+            .map_err(|e| ResolveError::Fetch {
+              name: name.to_string(),
+              source: e,
+            })?;
+          let (matched, version) =
+            range
+              .select(tags.iter().map(String::as_str))
+              .ok_or_else(|| ResolveError::Fetch {
+                name: name.to_string(),
+                source: FetchError::NoMatchingTag {
+                  url: fetch_url.clone(),
+                  range: config_rev.clone().unwrap_or_default(),
+                },
+              })?;
+          debug!(name, tag = matched, %version, "selected tag in semver range");
+          tag = Some(matched.to_string());
+          tag.as_deref()
+        }
+        None if should_force => config_rev.as_deref(),
+        None => config_rev.as_deref().or(locked_rev),
       };
 
-      let (path, actual_rev) =
-        fetch_git(name, &git_url, target_rev, ctx.inputs_cache_dir).map_err(|e| ResolveError::Fetch {
+      let (path, actual_rev) = fetcher
+        .fetch(name, &fetch_url, target_rev, ctx.inputs_cache_dir)
+        .map_err(|e| ResolveError::Fetch {
           name: name.to_string(),
           source: e,
         })?;
 
       let should_update_lock = match &locked_entry {
-        None => true,
-        Some(locked) => should_force || (config_rev.is_some() && locked.rev != actual_rev),
+        None => !is_overridden,
+        Some(locked) => should_force || (config_rev.is_some() && range.is_none() && locked.rev != actual_rev),
       };
 
       if should_update_lock {
-        info!(name, rev = %actual_rev, path = %full_path, "locking input");
+        info!(name, rev = %actual_rev, tag = tag.as_deref(), path = %full_path, "locking input");
         let timestamp = SystemTime::now()
           .duration_since(UNIX_EPOCH)
           .map(|d| d.as_secs())
@@ -565,15 +754,9 @@ fn resolve_single_input(
 
         ctx.lock_file.insert(
           lock_key,
-          LockedInput::new(
-            source_type(&InputSource::Git {
-              url: git_url,
-              rev: config_rev,
-            }),
-            url,
-            &actual_rev,
-          )
-          .with_last_modified(timestamp),
+          LockedInput::new(&type_, url, &actual_rev)
+            .with_tag(tag)
+            .with_last_modified(timestamp),
         );
         *ctx.lock_changed = true;
       }
@@ -586,9 +769,13 @@ fn resolve_single_input(
         source: e,
       })?;
 
-      let rev = "local".to_string();
+      // A vendored input keeps the rev it was locked at before `sys vendor`
+      let rev = match &locked_entry {
+        Some(locked) if locked.type_ == "path" && locked.url == url => locked.rev.clone(),
+        _ => "local".to_string(),
+      };
 
-      if locked_entry.is_none() {
+      if locked_entry.is_none() && !is_overridden {
         info!(name, path = %resolved_path.display(), "locking new path input");
         ctx.lock_file.insert(lock_key, LockedInput::new("path", url, &rev));
         *ctx.lock_changed = true;
@@ -601,8 +788,8 @@ fn resolve_single_input(
   Ok((path, rev))
 }
 
-/// Extract input declarations from an input's init.lua file.
-fn extract_input_decls_from_file(init_path: &Path) -> Result<InputDecls, ResolveError> {
+/// Extract input declarations and metadata from an input's init.lua file.
+fn extract_input_info_from_file(init_path: &Path) -> Result<(InputDecls, Option<Metadata>), ResolveError> {
   let manifest = Rc::new(RefCell::new(Manifest::default()));
   let lua = runtime::create_runtime(manifest, false).map_err(|e| ResolveError::ExtractInputs {
     name: init_path.display().to_string(),
@@ -616,7 +803,16 @@ fn extract_input_decls_from_file(init_path: &Path) -> Result<InputDecls, Resolve
 
   let table = match result {
     mlua::Value::Table(t) => t,
-    _ => return Ok(BTreeMap::new()), // Not a table, no inputs
+    _ => return Ok((BTreeMap::new(), None)), // Not a table, no inputs
+  };
+
+  // Metadata is informational, so a malformed table must not hide the input's dependencies
+  let metadata = match table.get::<Option<Metadata>>("metadata") {
+    Ok(metadata) => metadata.filter(|m| !m.is_empty()),
+    Err(e) => {
+      warn!(path = %init_path.display(), error = %e, "ignoring invalid input metadata");
+      None
+    }
   };
 
   let inputs_value: mlua::Value = table.get("inputs").map_err(|e| ResolveError::ExtractInputs {
@@ -626,7 +822,7 @@ fn extract_input_decls_from_file(init_path: &Path) -> Result<InputDecls, Resolve
 
   let inputs_table = match inputs_value {
     mlua::Value::Table(t) => t,
-    mlua::Value::Nil => return Ok(BTreeMap::new()),
+    mlua::Value::Nil => return Ok((BTreeMap::new(), metadata)),
     _ => {
       return Err(ResolveError::ExtractInputs {
         name: init_path.display().to_string(),
@@ -651,7 +847,7 @@ fn extract_input_decls_from_file(init_path: &Path) -> Result<InputDecls, Resolve
     decls.insert(name, decl);
   }
 
-  Ok(decls)
+  Ok((decls, metadata))
 }
 
 /// Parse a single input declaration from a Lua value.
@@ -713,6 +909,7 @@ fn parse_lua_override(name: &str, value: mlua::Value) -> Result<InputOverride, S
 fn build_transitive_inputs(
   graph: &DependencyGraph,
   resolved_cache: &BTreeMap<String, (PathBuf, String, String)>,
+  metadata_cache: &BTreeMap<String, Metadata>,
   root_path: &str,
 ) -> TypesResolvedInputs {
   let mut transitive = BTreeMap::new();
@@ -728,11 +925,12 @@ fn build_transitive_inputs(
 
       if let Some((path, rev, _)) = resolved_cache.get(effective_path) {
         // Recursively get this dep's transitive deps
-        let nested = build_transitive_inputs(graph, resolved_cache, dep_path);
+        let nested = build_transitive_inputs(graph, resolved_cache, metadata_cache, dep_path);
 
         transitive.insert(
           node.name.clone(),
-          TypesResolvedInput::with_inputs(path.clone(), rev.clone(), nested),
+          TypesResolvedInput::with_inputs(path.clone(), rev.clone(), nested)
+            .with_metadata(metadata_cache.get(effective_path).cloned()),
         );
       }
     }
@@ -934,7 +1132,7 @@ return {{
       let mut decls = InputDecls::new();
       decls.insert("lib_b".to_string(), InputDecl::Url(path_to_lua_url(&lib_b)));
 
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // lib_b should be resolved
       assert!(result.inputs.contains_key("lib_b"));
@@ -944,6 +1142,225 @@ return {{
       assert!(lib_b_resolved.inputs.contains_key("lib_a"));
     }
 
+    #[test]
+    fn url_override_bypasses_lock_file() {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+
+      let upstream = config_dir.join("upstream");
+      create_input_with_deps(&upstream, &[]);
+      let fork = config_dir.join("fork");
+      create_input_with_deps(&fork, &[]);
+
+      let mut decls = InputDecls::new();
+      decls.insert("lib".to_string(), InputDecl::Url(path_to_lua_url(&upstream)));
+
+      // Lock the upstream URL
+      let locked = resolve_inputs(&decls, config_dir, None, None).unwrap();
+      save_lock_file_if_changed(&locked, config_dir).unwrap();
+
+      // Override to the fork without changing the declaration
+      let mut overrides = BTreeMap::new();
+      overrides.insert("lib".to_string(), path_to_lua_url(&fork));
+      let result = resolve_inputs(&decls, config_dir, None, Some(&overrides)).unwrap();
+
+      assert_eq!(
+        result.inputs.get("lib").unwrap().path,
+        dunce::canonicalize(&fork).unwrap()
+      );
+      assert!(!result.lock_changed);
+      assert_eq!(result.lock_file.get("lib").unwrap().url, path_to_lua_url(&upstream));
+    }
+
+    /// Fetcher for `stub:` URLs that serves a fixed directory.
+    struct StubFetcher(PathBuf);
+
+    impl crate::inputs::fetch::Fetcher for StubFetcher {
+      fn scheme(&self) -> &str {
+        "stub"
+      }
+
+      fn fetch(
+        &self,
+        _name: &str,
+        _url: &str,
+        rev: Option<&str>,
+        _cache_dir: &Path,
+      ) -> Result<(PathBuf, String), FetchError> {
+        Ok((self.0.clone(), rev.unwrap_or("stub-rev").to_string()))
+      }
+    }
+
+    #[test]
+    fn registered_fetcher_resolves_and_locks_its_type() {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+      let served = config_dir.join("served");
+      create_input_with_deps(&served, &[]);
+
+      let mut fetchers = Fetchers::default();
+      fetchers.register(StubFetcher(served.clone()));
+
+      let mut decls = InputDecls::new();
+      decls.insert("lib".to_string(), InputDecl::Url("stub:example/lib".to_string()));
+
+      let result = resolve_inputs_with(&decls, config_dir, None, None, &fetchers).unwrap();
+      assert_eq!(result.inputs.get("lib").unwrap().path, served);
+      let locked = result.lock_file.get("lib").unwrap();
+      assert_eq!(locked.type_, "stub");
+      assert_eq!(locked.rev, "stub-rev");
+
+      // Without the fetcher, the locked entry is reported instead of a parse error
+      save_lock_file_if_changed(&result, config_dir).unwrap();
+      let err = resolve_inputs(&decls, config_dir, None, None).unwrap_err();
+      assert!(
+        matches!(err, ResolveError::UnsupportedLockType { ref type_, .. } if type_ == "stub"),
+        "unexpected error: {}",
+        err
+      );
+    }
+
+    /// Fetcher for `tagged:` URLs with a mutable list of tags; tag `t` resolves to `commit-t`.
+    struct TaggedFetcher(PathBuf, std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl crate::inputs::fetch::Fetcher for TaggedFetcher {
+      fn scheme(&self) -> &str {
+        "tagged"
+      }
+
+      fn fetch(
+        &self,
+        _name: &str,
+        _url: &str,
+        rev: Option<&str>,
+        _cache_dir: &Path,
+      ) -> Result<(PathBuf, String), FetchError> {
+        // Locked revisions come back as they are, like a commit hash does
+        let rev = rev.unwrap_or("head");
+        let commit = if rev.starts_with("commit-") {
+          rev.to_string()
+        } else {
+          format!("commit-{}", rev)
+        };
+        Ok((self.0.clone(), commit))
+      }
+
+      fn tags(&self, _name: &str, _url: &str, _cache_dir: &Path) -> Result<Vec<String>, FetchError> {
+        Ok(self.1.lock().unwrap().clone())
+      }
+    }
+
+    #[test]
+    fn semver_range_locks_highest_matching_tag_until_update() {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+      let served = config_dir.join("served");
+      create_input_with_deps(&served, &[]);
+
+      let tags = std::sync::Arc::new(std::sync::Mutex::new(vec![
+        "v1.1.0".to_string(),
+        "v1.2.0".to_string(),
+        "v2.0.0".to_string(),
+      ]));
+      let mut fetchers = Fetchers::default();
+      fetchers.register(TaggedFetcher(served, tags.clone()));
+
+      let mut decls = InputDecls::new();
+      decls.insert(
+        "lib".to_string(),
+        InputDecl::Url("tagged:example/lib#semver:^1.1".to_string()),
+      );
+
+      let result = resolve_inputs_with(&decls, config_dir, None, None, &fetchers).unwrap();
+      let locked = result.lock_file.get("lib").unwrap();
+      assert_eq!(locked.tag.as_deref(), Some("v1.2.0"));
+      assert_eq!(locked.rev, "commit-v1.2.0");
+      save_lock_file_if_changed(&result, config_dir).unwrap();
+
+      // A newer tag in range is ignored while locked...
+      tags.lock().unwrap().push("v1.3.0".to_string());
+      let result = resolve_inputs_with(&decls, config_dir, None, None, &fetchers).unwrap();
+      assert!(!result.lock_changed);
+      assert_eq!(result.inputs.get("lib").unwrap().rev, "commit-v1.2.0");
+
+      // ...and picked up by an update, which stays below the next major
+      let all = HashSet::new();
+      let result = resolve_inputs_with(&decls, config_dir, Some(&all), None, &fetchers).unwrap();
+      let locked = result.lock_file.get("lib").unwrap();
+      assert_eq!(locked.tag.as_deref(), Some("v1.3.0"));
+      assert_eq!(locked.rev, "commit-v1.3.0");
+    }
+
+    #[test]
+    fn semver_range_without_matching_tag_fails() {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+      let served = config_dir.join("served");
+      create_input_with_deps(&served, &[]);
+
+      let tags = std::sync::Arc::new(std::sync::Mutex::new(vec!["v1.0.0".to_string()]));
+      let mut fetchers = Fetchers::default();
+      fetchers.register(TaggedFetcher(served, tags));
+
+      let mut decls = InputDecls::new();
+      decls.insert(
+        "lib".to_string(),
+        InputDecl::Url("tagged:example/lib#semver:^2".to_string()),
+      );
+
+      let err = resolve_inputs_with(&decls, config_dir, None, None, &fetchers).unwrap_err();
+      let ResolveError::Incomplete(partial) = err else {
+        panic!("unexpected error: {}", err);
+      };
+      assert!(
+        matches!(
+          partial.failed.get("lib"),
+          Some(ResolveError::Fetch {
+            source: FetchError::NoMatchingTag { .. },
+            ..
+          })
+        ),
+        "unexpected error: {}",
+        partial
+      );
+    }
+
+    #[test]
+    fn failed_fetch_still_resolves_other_inputs() {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+      let good = config_dir.join("good");
+      create_input_with_deps(&good, &[]);
+
+      let mut decls = InputDecls::new();
+      decls.insert("good".to_string(), InputDecl::Url(path_to_lua_url(&good)));
+      decls.insert(
+        "gone".to_string(),
+        InputDecl::Url(path_to_lua_url(&config_dir.join("gone"))),
+      );
+
+      let err = resolve_inputs(&decls, config_dir, None, None).unwrap_err();
+      let ResolveError::Incomplete(partial) = err else {
+        panic!("unexpected error: {}", err);
+      };
+      assert!(partial.inputs.contains_key("good"));
+      assert!(!partial.inputs.contains_key("gone"));
+      assert_eq!(partial.failed.keys().collect::<Vec<_>>(), vec!["gone"]);
+      assert!(partial.lock_file.get("good").is_some());
+    }
+
+    #[test]
+    fn url_override_for_undeclared_input_fails() {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+
+      let mut overrides = BTreeMap::new();
+      overrides.insert("missing".to_string(), "path:./missing".to_string());
+      let result = resolve_inputs(&InputDecls::new(), config_dir, None, Some(&overrides));
+
+      assert!(matches!(result, Err(ResolveError::UnknownOverride { ref name }) if name == "missing"));
+    }
+
     #[test]
     fn diamond_dependency_deduplication() {
       let temp = TempDir::new().unwrap();
@@ -996,7 +1413,7 @@ return {{
       decls.insert("lib_a".to_string(), InputDecl::Url(path_to_lua_url(&lib_a)));
       decls.insert("lib_b".to_string(), InputDecl::Url(path_to_lua_url(&lib_b)));
 
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // Both A and B should be resolved
       assert!(result.inputs.contains_key("lib_a"));
@@ -1029,7 +1446,7 @@ return {{
       let mut decls = InputDecls::new();
       decls.insert("lib_a".to_string(), InputDecl::Url(path_to_lua_url(&lib_a)));
 
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // lib_a should be resolved with no transitive deps
       assert!(result.inputs.contains_key("lib_a"));
@@ -1106,7 +1523,7 @@ return {{
       // Also declare my_utils pointing to v2
       decls.insert("my_utils".to_string(), InputDecl::Url(path_to_lua_url(&utils_v2)));
 
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // lib should be resolved
       assert!(result.inputs.contains_key("lib"));
@@ -1124,6 +1541,75 @@ return {{
       );
     }
 
+    #[test]
+    fn follows_path_redirects_deep_dependency() {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+
+      let leaf = r#"
+return {
+  inputs = {},
+  setup = function() end,
+}
+"#;
+      let helpers_v1 = config_dir.join("helpers_v1");
+      let helpers_v2 = config_dir.join("helpers_v2");
+      for dir in [&helpers_v1, &helpers_v2] {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("init.lua"), leaf).unwrap();
+      }
+
+      // lib -> utils -> helpers v1
+      let utils = config_dir.join("utils");
+      let lib = config_dir.join("lib");
+      for (dir, dep, url) in [
+        (&utils, "helpers", path_to_lua_url(&helpers_v1)),
+        (&lib, "utils", path_to_lua_url(&utils)),
+      ] {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+          dir.join("init.lua"),
+          format!(
+            r#"
+return {{
+  inputs = {{
+    {} = "{}",
+  }},
+  setup = function() end,
+}}
+"#,
+            dep, url
+          ),
+        )
+        .unwrap();
+      }
+
+      // Redirect lib/utils/helpers to my_helpers (v2) from the config
+      let mut decls = InputDecls::new();
+      let mut overrides = std::collections::BTreeMap::new();
+      overrides.insert(
+        "utils/helpers".to_string(),
+        InputOverride::Follows("my_helpers".to_string()),
+      );
+      decls.insert(
+        "lib".to_string(),
+        InputDecl::Extended {
+          url: Some(path_to_lua_url(&lib)),
+          inputs: overrides,
+        },
+      );
+      decls.insert("my_helpers".to_string(), InputDecl::Url(path_to_lua_url(&helpers_v2)));
+
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
+
+      let helpers = &result.inputs["lib"].inputs["utils"].inputs["helpers"];
+      assert_eq!(
+        helpers.path,
+        dunce::canonicalize(&helpers_v2).unwrap(),
+        "deep follows override should redirect to my_helpers (v2)"
+      );
+    }
+
     #[test]
     fn circular_dependency_is_handled() {
       let temp = TempDir::new().unwrap();
@@ -1173,7 +1659,7 @@ return {{
       decls.insert("lib_a".to_string(), InputDecl::Url(path_to_lua_url(&lib_a)));
 
       // Circular deps should be handled gracefully - resolution should succeed
-      let result = resolve_inputs(&decls, config_dir, None);
+      let result = resolve_inputs(&decls, config_dir, None, None);
 
       // Resolution should succeed (circular deps are supported for runtime)
       assert!(result.is_ok(), "circular deps should be handled: {:?}", result);
@@ -1263,7 +1749,7 @@ return {{
       let mut decls = InputDecls::new();
       decls.insert("lib_a".to_string(), InputDecl::Url(path_to_lua_url(&lib_a)));
 
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // Verify the full chain is resolved
       assert!(result.inputs.contains_key("lib_a"));
@@ -1319,7 +1805,7 @@ return {
       let mut decls = InputDecls::new();
       decls.insert("lib".to_string(), InputDecl::Url(path_to_lua_url(&lib)));
 
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // The namespace should be discovered
       assert_eq!(result.namespaces.len(), 1);
@@ -1339,7 +1825,7 @@ return {
 
       // No inputs
       let decls = InputDecls::new();
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // The config's namespace should be discovered
       assert_eq!(result.namespaces.len(), 1);
@@ -1403,7 +1889,7 @@ return {{
       decls.insert("lib_b".to_string(), InputDecl::Url(path_to_lua_url(&lib_b)));
 
       // Should succeed - same utils version from both paths
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // Should have: lib_a, lib_b, utils (deduplicated)
       let namespace_names: Vec<_> = result.namespaces.iter().map(|ns| ns.name.as_str()).collect();
@@ -1431,7 +1917,7 @@ return {{
       decls.insert("lib_b".to_string(), InputDecl::Url(path_to_lua_url(&lib_b)));
 
       // Should fail with namespace conflict
-      let result = resolve_inputs(&decls, config_dir, None);
+      let result = resolve_inputs(&decls, config_dir, None, None);
       assert!(result.is_err());
 
       let err = result.unwrap_err();
@@ -1461,7 +1947,7 @@ return {{
       decls.insert("lib".to_string(), InputDecl::Url(path_to_lua_url(&lib)));
 
       // Should fail - config's my_lib conflicts with input's my_lib
-      let result = resolve_inputs(&decls, config_dir, None);
+      let result = resolve_inputs(&decls, config_dir, None, None);
       assert!(result.is_err());
 
       let err = result.unwrap_err();
@@ -1500,7 +1986,7 @@ return {
       let mut decls = InputDecls::new();
       decls.insert("lib".to_string(), InputDecl::Url(path_to_lua_url(&lib)));
 
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // Both namespaces should be discovered
       let namespace_names: Vec<_> = result.namespaces.iter().map(|ns| ns.name.as_str()).collect();
@@ -1528,6 +2014,13 @@ return {
       assert_eq!(result, "git:https://github.com/org/repo.git#abc123");
     }
 
+    #[test]
+    fn inject_revision_into_url_fetcher() {
+      let url = "archive:https://example.com/pkg.tar.gz#old";
+      let result = inject_revision_into_url(url, "abc123");
+      assert_eq!(result, "archive:https://example.com/pkg.tar.gz#abc123");
+    }
+
     #[test]
     fn inject_revision_into_url_path() {
       // Path URLs should not be modified
@@ -1678,7 +2171,7 @@ return {{
       let mut decls = InputDecls::new();
       decls.insert("lib".to_string(), InputDecl::Url(path_to_lua_url(&lib)));
 
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // lib should be resolved
       assert!(result.inputs.contains_key("lib"));
@@ -1785,7 +2278,7 @@ return {{
       // Declare my_utils pointing to v3
       decls.insert("my_utils".to_string(), InputDecl::Url(path_to_lua_url(&utils_v3)));
 
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // lib should be resolved
       assert!(result.inputs.contains_key("lib"));
@@ -1840,7 +2333,7 @@ return {{
       let mut decls = InputDecls::new();
       decls.insert("lib".to_string(), InputDecl::Url(path_to_lua_url(&lib)));
 
-      let result = resolve_inputs(&decls, config_dir, None).unwrap();
+      let result = resolve_inputs(&decls, config_dir, None, None).unwrap();
 
       // lib should be resolved
       assert!(result.inputs.contains_key("lib"));
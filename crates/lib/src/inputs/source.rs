@@ -6,15 +6,23 @@
 //!
 //! - `git:https://github.com/org/repo.git` - Git over HTTPS (HEAD)
 //! - `git:https://github.com/org/repo.git#v1.0.0` - Git with specific ref (tag/branch/commit)
+//! - `git:https://github.com/org/repo.git#semver:^1.2` - Git, highest tag matching a semver range
 //! - `git:git@github.com:org/repo.git` - Git over SSH
 //! - `git:git@github.com:org/repo.git#main` - Git over SSH with specific ref
 //! - `path:~/code/foo` - Absolute path with tilde expansion
 //! - `path:./relative` - Relative path (resolved against config dir)
+//! - `<scheme>:<url>[#rev]` - Handled by a registered [`Fetcher`](super::fetch::Fetcher),
+//!   e.g. `archive:https://example.com/foo.tar.gz`
 
 use std::path::PathBuf;
 
 use thiserror::Error;
 
+use crate::util::semver::{SemverError, VersionReq};
+
+/// Ref prefix selecting the highest tag in a semver range, e.g. `#semver:^1.2`.
+pub const SEMVER_REF_PREFIX: &str = "semver:";
+
 /// A parsed input source.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InputSource {
@@ -22,7 +30,7 @@ pub enum InputSource {
   Git {
     /// The git URL (without the `git:` prefix and `#ref` suffix).
     url: String,
-    /// Optional ref to checkout (branch, tag, or commit hash).
+    /// Optional ref to checkout (branch, tag, commit hash, or `semver:<range>`).
     /// If None, uses HEAD (default branch).
     rev: Option<String>,
   },
@@ -31,13 +39,22 @@ pub enum InputSource {
     /// The path string (may contain `~` or be relative).
     path: PathBuf,
   },
+  /// A source handled by a registered [`Fetcher`](super::fetch::Fetcher).
+  Fetcher {
+    /// The fetcher's scheme (the prefix before the first `:`).
+    scheme: String,
+    /// The URL (without the scheme prefix and `#rev` suffix).
+    url: String,
+    /// Optional revision to pin (meaning depends on the fetcher).
+    rev: Option<String>,
+  },
 }
 
 /// Errors that can occur when parsing an input URL.
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
 pub enum ParseError {
   /// The URL scheme (prefix before `:`) is not recognized.
-  #[error("unknown input scheme '{0}': expected 'git:' or 'path:'")]
+  #[error("unknown input scheme '{0}': expected 'git:', 'path:' or a registered fetcher scheme such as 'archive:'")]
   UnknownScheme(String),
 
   /// The URL is missing content after the scheme prefix.
@@ -51,6 +68,18 @@ pub enum ParseError {
   /// The ref after `#` is empty.
   #[error("empty ref after '#' in git URL")]
   EmptyGitRef,
+
+  /// The URL is missing after a fetcher scheme prefix.
+  #[error("missing URL after '{0}:' prefix")]
+  MissingUrl(String),
+
+  /// The revision after `#` is empty in a fetcher URL.
+  #[error("empty revision after '#' in '{0}:' URL")]
+  EmptyRev(String),
+
+  /// The range after `#semver:` is not a valid version requirement.
+  #[error("invalid semver range in git URL: {0}")]
+  InvalidSemverRange(#[source] SemverError),
 }
 
 /// Parse an input URL string into an [`InputSource`].
@@ -63,6 +92,7 @@ pub enum ParseError {
 /// | Git HTTPS + ref | `git:https://github.com/org/repo.git#v1.0.0` | HTTPS with specific ref |
 /// | Git SSH | `git:git@github.com:org/repo.git` | SSH, uses HEAD |
 /// | Git SSH + ref | `git:git@github.com:org/repo.git#main` | SSH with specific ref |
+/// | Git semver | `git:https://github.com/org/repo.git#semver:^1.2` | Highest tag in range |
 /// | Path absolute | `path:~/code/foo` | Tilde-expanded path |
 /// | Path relative | `path:./relative` | Relative to config directory |
 ///
@@ -70,6 +100,7 @@ pub enum ParseError {
 /// - A branch name: `#main`, `#develop`
 /// - A tag: `#v1.0.0`, `#release-2024`
 /// - A commit hash: `#abc123def` (full or abbreviated)
+/// - A semver range: `#semver:^1.2`, `#semver:>=1.0, <2.0` (see [`semver_range`])
 ///
 /// # Errors
 ///
@@ -109,6 +140,9 @@ pub fn parse(url: &str) -> Result<InputSource, ParseError> {
       if ref_part.is_empty() {
         return Err(ParseError::EmptyGitRef);
       }
+      if let Some(range) = ref_part.strip_prefix(SEMVER_REF_PREFIX) {
+        VersionReq::parse(range).map_err(ParseError::InvalidSemverRange)?;
+      }
 
       (url_part.to_string(), Some(ref_part.to_string()))
     } else {
@@ -130,13 +164,54 @@ pub fn parse(url: &str) -> Result<InputSource, ParseError> {
   }
 }
 
+/// Parse a URL for a fetcher registered under `scheme`.
+///
+/// Accepts `<scheme>:<url>` with an optional `#rev` suffix. Built-in `git:`
+/// and `path:` URLs should go through [`parse`].
+pub fn parse_fetcher(url: &str, scheme: &str) -> Result<InputSource, ParseError> {
+  let rest = url
+    .strip_prefix(scheme)
+    .and_then(|rest| rest.strip_prefix(':'))
+    .ok_or_else(|| ParseError::UnknownScheme(url.split(':').next().unwrap_or(url).to_string()))?;
+
+  let (fetch_url, rev) = match rest.rfind('#') {
+    Some(hash_pos) => (&rest[..hash_pos], Some(&rest[hash_pos + 1..])),
+    None => (rest, None),
+  };
+
+  if fetch_url.is_empty() {
+    return Err(ParseError::MissingUrl(scheme.to_string()));
+  }
+  if rev.is_some_and(str::is_empty) {
+    return Err(ParseError::EmptyRev(scheme.to_string()));
+  }
+
+  Ok(InputSource::Fetcher {
+    scheme: scheme.to_string(),
+    url: fetch_url.to_string(),
+    rev: rev.map(str::to_string),
+  })
+}
+
+/// The semver range of a git ref, if it is `semver:<range>`.
+///
+/// Such inputs check out the highest tag matching the range (e.g. `v1.4.2`
+/// for `^1.2`) and lock that tag and its commit; `sys update` moves to the
+/// highest match at that time.
+pub fn semver_range(rev: &str) -> Option<VersionReq> {
+  rev
+    .strip_prefix(SEMVER_REF_PREFIX)
+    .and_then(|range| VersionReq::parse(range).ok())
+}
+
 /// Returns the scheme/type identifier for an [`InputSource`].
 ///
-/// Used for lock file serialization.
-pub fn source_type(source: &InputSource) -> &'static str {
+/// Used for lock file serialization: `git`, `path`, or the fetcher's scheme.
+pub fn source_type(source: &InputSource) -> &str {
   match source {
     InputSource::Git { .. } => "git",
     InputSource::Path { .. } => "path",
+    InputSource::Fetcher { scheme, .. } => scheme,
   }
 }
 
@@ -243,6 +318,26 @@ mod tests {
       assert_eq!(result, Err(ParseError::EmptyGitRef));
     }
 
+    #[test]
+    fn https_url_with_semver_range() {
+      let result = parse("git:https://github.com/org/repo.git#semver:^1.2").unwrap();
+      assert_eq!(
+        result,
+        InputSource::Git {
+          url: "https://github.com/org/repo.git".to_string(),
+          rev: Some("semver:^1.2".to_string()),
+        }
+      );
+      assert!(semver_range("semver:^1.2").is_some());
+      assert!(semver_range("v1.2.0").is_none());
+    }
+
+    #[test]
+    fn invalid_semver_range() {
+      let result = parse("git:https://github.com/org/repo.git#semver:^one");
+      assert!(matches!(result, Err(ParseError::InvalidSemverRange(_))));
+    }
+
     #[test]
     fn only_hash_no_url() {
       let result = parse("git:#v1.0.0");
@@ -315,6 +410,44 @@ mod tests {
     }
   }
 
+  mod parse_fetcher_fn {
+    use super::*;
+
+    #[test]
+    fn url_with_rev() {
+      let result = parse_fetcher("archive:https://example.com/a.tar.gz#abc123", "archive").unwrap();
+      assert_eq!(
+        result,
+        InputSource::Fetcher {
+          scheme: "archive".to_string(),
+          url: "https://example.com/a.tar.gz".to_string(),
+          rev: Some("abc123".to_string()),
+        }
+      );
+      assert_eq!(source_type(&result), "archive");
+    }
+
+    #[test]
+    fn missing_url_and_empty_rev() {
+      assert_eq!(
+        parse_fetcher("archive:", "archive"),
+        Err(ParseError::MissingUrl("archive".to_string()))
+      );
+      assert_eq!(
+        parse_fetcher("archive:https://example.com/a.tar.gz#", "archive"),
+        Err(ParseError::EmptyRev("archive".to_string()))
+      );
+    }
+
+    #[test]
+    fn other_scheme_is_rejected() {
+      assert_eq!(
+        parse_fetcher("hg:https://example.com/repo", "archive"),
+        Err(ParseError::UnknownScheme("hg".to_string()))
+      );
+    }
+  }
+
   mod source_type_fn {
     use super::*;
 
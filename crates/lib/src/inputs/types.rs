@@ -10,6 +10,8 @@ use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use crate::util::metadata::Metadata;
+
 /// Maximum depth for follows chain resolution.
 /// Prevents infinite loops in malformed configurations.
 pub const MAX_FOLLOWS_DEPTH: usize = 10;
@@ -45,7 +47,8 @@ pub enum InputDecl {
   Extended {
     /// The URL of the input. Can be `None` if this is a pure follows override.
     url: Option<String>,
-    /// Overrides for transitive dependencies.
+    /// Overrides for transitive dependencies. Keys are dependency names, or
+    /// paths (`"utils/helpers"` or `"utils.helpers"`) to deeper dependencies.
     inputs: BTreeMap<String, InputOverride>,
   },
 }
@@ -137,6 +140,9 @@ pub struct ResolvedInput {
   /// to its resolved state. This allows isolated dependency resolution where
   /// different inputs can use different versions of the same dependency.
   pub inputs: ResolvedInputs,
+
+  /// The `metadata` table returned by the input's init.lua, if any.
+  pub metadata: Option<Metadata>,
 }
 
 impl ResolvedInput {
@@ -146,12 +152,24 @@ impl ResolvedInput {
       path,
       rev,
       inputs: BTreeMap::new(),
+      metadata: None,
     }
   }
 
   /// Create a new resolved input with transitive dependencies.
   pub fn with_inputs(path: PathBuf, rev: String, inputs: ResolvedInputs) -> Self {
-    Self { path, rev, inputs }
+    Self {
+      path,
+      rev,
+      inputs,
+      metadata: None,
+    }
+  }
+
+  /// Attach the metadata declared in the input's init.lua.
+  pub fn with_metadata(mut self, metadata: Option<Metadata>) -> Self {
+    self.metadata = metadata;
+    self
   }
 }
 
@@ -202,12 +220,15 @@ impl LuaNamespace {
     }
   }
 
-  /// Check if two namespaces are from the same source (same URL + same rev).
+  /// Check if two namespaces are from the same source (same URL + same rev,
+  /// or path inputs of the same directory).
   ///
   /// This is used for diamond dependency deduplication: if two inputs provide
   /// the same namespace from the same source at the same revision, it's not a conflict.
+  /// Vendored inputs name one directory with paths relative to different parents.
   pub fn same_source(&self, other: &LuaNamespace) -> bool {
-    self.url == other.url && self.rev == other.rev
+    let both_paths = self.url.starts_with("path:") && other.url.starts_with("path:");
+    (self.url == other.url && self.rev == other.rev) || (both_paths && self.path == other.path)
   }
 }
 
@@ -215,7 +236,7 @@ impl LuaNamespace {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LockNode {
-  /// Input type: "git" or "path".
+  /// Input type: "git", "path", or a registered fetcher scheme.
   #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
   pub type_: Option<String>,
 
@@ -227,6 +248,10 @@ pub struct LockNode {
   #[serde(skip_serializing_if = "Option::is_none")]
   pub rev: Option<String>,
 
+  /// Tag chosen for a `#semver:<range>` URL (e.g., "v1.4.2").
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub tag: Option<String>,
+
   /// Unix timestamp of when this input was last modified/fetched.
   #[serde(skip_serializing_if = "Option::is_none")]
   pub last_modified: Option<u64>,
@@ -243,6 +268,7 @@ impl LockNode {
       type_: None,
       url: None,
       rev: None,
+      tag: None,
       last_modified: None,
       inputs,
     }
@@ -260,6 +286,7 @@ impl LockNode {
       type_: Some(type_.to_string()),
       url: Some(url.to_string()),
       rev: Some(rev.to_string()),
+      tag: None,
       last_modified,
       inputs,
     }
@@ -487,5 +514,26 @@ mod tests {
 
       assert!(!ns1.same_source(&ns2));
     }
+
+    #[test]
+    fn same_source_with_path_urls_to_one_directory() {
+      let ns1 = LuaNamespace::new(
+        "utils",
+        "pkgs/utils",
+        "path:../utils-abc123",
+        "abc123",
+        PathBuf::from("/config/vendor/utils-abc123/lua/utils"),
+      );
+
+      let ns2 = LuaNamespace::new(
+        "utils",
+        "utils",
+        "path:./vendor/utils-abc123",
+        "abc123",
+        PathBuf::from("/config/vendor/utils-abc123/lua/utils"),
+      );
+
+      assert!(ns1.same_source(&ns2));
+    }
   }
 }
@@ -5,11 +5,18 @@
 //! - `Bind`: describes what to do with bind outputs
 //! - `Manifest`: the complete set of derivations and activations
 //! - `Snapshot`: rollback journal for restoring previous system state
+//!
+//! Third-party tools should drive syslua through the [`api`] module, the only
+//! part of this crate with a stability guarantee.
 
 pub mod action;
+pub mod agent;
+pub mod api;
 pub mod bind;
 pub mod build;
+pub mod completion;
 pub mod consts;
+pub mod daemon;
 pub mod eval;
 pub mod execute;
 pub mod gc;
@@ -20,7 +27,12 @@ pub mod manifest;
 pub mod outputs;
 pub mod placeholder;
 pub mod platform;
+pub mod self_update;
 pub mod snapshot;
+pub mod store_backup;
+pub mod store_inspect;
 pub mod store_lock;
+pub mod store_repair;
+pub mod testing;
 pub mod update;
 pub mod util;
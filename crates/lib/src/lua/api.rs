@@ -0,0 +1,270 @@
+//! Versions of the Lua API.
+//!
+//! A config declares the API version it was written against at its top:
+//!
+//! ```lua
+//! sys.api(2)
+//!
+//! return {
+//!   inputs = { ... },
+//!   setup = function(inputs) ... end,
+//! }
+//! ```
+//!
+//! The runtime then provides the globals of that version. A config that
+//! declares none gets [`DEFAULT_API_VERSION`], so configs written before
+//! versions existed keep working. A version newer than [`API_VERSION`] fails
+//! evaluation with a message to upgrade syslua.
+//!
+//! | Version | Changes                                                      |
+//! | ------- | ------------------------------------------------------------ |
+//! | 1       | `derive{}` and `activate{}` work, with a deprecation warning |
+//! | 2       | `derive{}` and `activate{}` are removed                      |
+//!
+//! Calling a [deprecated helper](DEPRECATED_HELPERS) that the declared
+//! version still has logs one warning per call site and records a
+//! [`Deprecation`], which evaluation stores in the manifest for the CLI to
+//! report. Calling one the version removed fails, naming its replacement.
+//!
+//! Only the first declaration counts, so the config's decides for the inputs
+//! it loads; an input may declare the version it needs too, which only checks
+//! that this syslua supports it.
+
+use serde::{Deserialize, Serialize};
+
+use mlua::prelude::*;
+
+use super::runtime::caller_location;
+
+/// Newest Lua API version this syslua supports.
+pub const API_VERSION: u32 = 2;
+
+/// API version of configs that don't declare one.
+pub const DEFAULT_API_VERSION: u32 = 1;
+
+/// A global kept for older configs, and the version removing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeprecatedHelper {
+  /// Name of the global, e.g. `derive`.
+  pub name: &'static str,
+  /// What to call instead, e.g. `sys.build`.
+  pub replacement: &'static str,
+  /// First API version without the helper.
+  pub removed_in: u32,
+}
+
+/// Deprecated helpers, registered by [`super::legacy`].
+pub const DEPRECATED_HELPERS: &[DeprecatedHelper] = &[
+  DeprecatedHelper {
+    name: "derive",
+    replacement: "sys.build",
+    removed_in: 2,
+  },
+  DeprecatedHelper {
+    name: "activate",
+    replacement: "sys.bind",
+    removed_in: 2,
+  },
+];
+
+/// A call of a deprecated helper during evaluation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deprecation {
+  /// The helper called, e.g. `derive`.
+  pub helper: String,
+  /// What to call instead.
+  pub replacement: String,
+  /// First API version without the helper.
+  pub removed_in: u32,
+  /// Where it was called (`file:line`).
+  pub location: String,
+}
+
+/// The API version of a runtime and the deprecations seen, kept as app data.
+#[derive(Debug)]
+struct ApiState {
+  version: u32,
+  declared: bool,
+  deprecations: Vec<Deprecation>,
+}
+
+/// Register `sys.api` on the sys table, with the runtime at [`DEFAULT_API_VERSION`].
+///
+/// `sys.api(n)` declares the version and returns it; `sys.api()` returns
+/// the version in effect.
+pub fn register_sys_api(lua: &Lua, sys_table: &LuaTable) -> LuaResult<()> {
+  lua.set_app_data(ApiState {
+    version: DEFAULT_API_VERSION,
+    declared: false,
+    deprecations: Vec::new(),
+  });
+
+  let api_fn = lua.create_function(|lua, version: Option<LuaValue>| match version {
+    None => Ok(api_version(lua)),
+    Some(value) => {
+      let version = match value {
+        LuaValue::Integer(n) => u32::try_from(n).ok(),
+        LuaValue::Number(n) if n.fract() == 0.0 && n >= 0.0 => Some(n as u32),
+        _ => None,
+      }
+      .filter(|n| *n >= 1)
+      .ok_or_else(|| LuaError::external("sys.api: version must be a positive integer"))?;
+      declare(lua, version)
+    }
+  })?;
+  sys_table.set("api", api_fn)?;
+  Ok(())
+}
+
+/// The API version in effect.
+pub fn api_version(lua: &Lua) -> u32 {
+  lua
+    .app_data_ref::<ApiState>()
+    .map(|state| state.version)
+    .unwrap_or(DEFAULT_API_VERSION)
+}
+
+fn declare(lua: &Lua, version: u32) -> LuaResult<u32> {
+  if version > API_VERSION {
+    return Err(LuaError::external(format!(
+      "this config requires Lua API version {}, but syslua {} supports up to version {}; \
+       upgrade syslua (e.g. with `sys self-update`)",
+      version,
+      env!("CARGO_PKG_VERSION"),
+      API_VERSION
+    )));
+  }
+
+  {
+    let mut state = lua
+      .app_data_mut::<ApiState>()
+      .ok_or_else(|| LuaError::external("sys.api: runtime has no API state"))?;
+    if state.declared {
+      return Ok(state.version);
+    }
+    state.declared = true;
+    state.version = version;
+  }
+
+  for helper in DEPRECATED_HELPERS.iter().filter(|helper| helper.removed_in <= version) {
+    let removed = lua.create_function(move |_, _: LuaMultiValue| -> LuaResult<()> {
+      Err(LuaError::external(format!(
+        "{}{{}} was removed in Lua API version {}, use {}{{}} instead (`sys migrate-config` rewrites the config)",
+        helper.name, helper.removed_in, helper.replacement
+      )))
+    })?;
+    lua.globals().set(helper.name, removed)?;
+  }
+  tracing::debug!(version, "declared Lua API version");
+  Ok(version)
+}
+
+/// Warn about a call of the deprecated helper `name`, once per call site,
+/// and record it.
+pub fn warn_deprecated(lua: &Lua, name: &str) -> LuaResult<()> {
+  let helper = DEPRECATED_HELPERS
+    .iter()
+    .find(|helper| helper.name == name)
+    .ok_or_else(|| LuaError::external(format!("'{}' is not a deprecated helper", name)))?;
+  let location = caller_location(lua).unwrap_or_else(|| "<unknown>".to_string());
+
+  let mut state = lua
+    .app_data_mut::<ApiState>()
+    .ok_or_else(|| LuaError::external("runtime has no API state"))?;
+  if state
+    .deprecations
+    .iter()
+    .any(|seen| seen.helper == helper.name && seen.location == location)
+  {
+    return Ok(());
+  }
+  tracing::warn!(
+    helper = helper.name,
+    replacement = helper.replacement,
+    removed_in = helper.removed_in,
+    %location,
+    "{}{{}} is deprecated and removed in Lua API version {}, use {}{{}} instead (`sys migrate-config` rewrites the config)",
+    helper.name,
+    helper.removed_in,
+    helper.replacement
+  );
+  state.deprecations.push(Deprecation {
+    helper: helper.name.to_string(),
+    replacement: helper.replacement.to_string(),
+    removed_in: helper.removed_in,
+    location,
+  });
+  Ok(())
+}
+
+/// The deprecated helper calls recorded so far, in the order they were made.
+pub fn take_deprecations(lua: &Lua) -> Vec<Deprecation> {
+  lua
+    .app_data_mut::<ApiState>()
+    .map(|mut state| std::mem::take(&mut state.deprecations))
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  use super::*;
+  use crate::manifest::Manifest;
+
+  fn runtime() -> LuaResult<Lua> {
+    crate::lua::runtime::create_runtime(Rc::new(RefCell::new(Manifest::default())), false)
+  }
+
+  const DERIVE: &str = r#"
+    derive({ name = "x", config = function(opts, ctx) return { out = ctx.out } end })
+  "#;
+
+  #[test]
+  fn undeclared_configs_keep_deprecated_helpers_and_record_their_calls() -> LuaResult<()> {
+    let lua = runtime()?;
+    assert_eq!(lua.load("return sys.api()").eval::<u32>()?, DEFAULT_API_VERSION);
+
+    lua.load(DERIVE).set_name("@init.lua").exec()?;
+    lua.load(DERIVE).set_name("@init.lua").exec()?;
+    let deprecations = take_deprecations(&lua);
+    assert_eq!(deprecations.len(), 1);
+    assert_eq!(deprecations[0].helper, "derive");
+    assert_eq!(deprecations[0].replacement, "sys.build");
+    assert_eq!(deprecations[0].location, "init.lua:2");
+    Ok(())
+  }
+
+  #[test]
+  fn declaring_a_version_removes_the_helpers_it_dropped() -> LuaResult<()> {
+    let lua = runtime()?;
+    assert_eq!(lua.load("return sys.api(2)").eval::<u32>()?, 2);
+
+    let err = lua.load(DERIVE).exec().unwrap_err();
+    assert!(
+      err.to_string().contains("derive{} was removed in Lua API version 2"),
+      "{}",
+      err
+    );
+    assert!(err.to_string().contains("use sys.build{}"), "{}", err);
+
+    // Later declarations only check support
+    assert_eq!(lua.load("return sys.api(1)").eval::<u32>()?, 2);
+    assert!(take_deprecations(&lua).is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn newer_or_invalid_versions_fail() -> LuaResult<()> {
+    let lua = runtime()?;
+    let err = lua.load(format!("sys.api({})", API_VERSION + 1)).exec().unwrap_err();
+    assert!(err.to_string().contains("upgrade syslua"), "{}", err);
+
+    for invalid in ["0", "1.5", "'2'"] {
+      assert!(lua.load(format!("sys.api({})", invalid)).exec().is_err(), "{}", invalid);
+    }
+    assert_eq!(api_version(&lua), DEFAULT_API_VERSION);
+    Ok(())
+  }
+}
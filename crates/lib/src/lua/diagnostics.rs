@@ -0,0 +1,367 @@
+//! Diagnostics for errors raised while evaluating or applying a config.
+//!
+//! Build and bind specs run their `inputs`, `create`, `update`, `destroy` and
+//! `check` functions during evaluation. [`SpecCaller::call`] invokes them and
+//! tags any error with the spec it came from: its kind, id, callback and the
+//! file and line the function was defined at. Without it, an error thrown in a
+//! `create` function surfaces as a bare mlua error naming neither.
+//!
+//! [`Diagnostic::from_error`] turns any error chain into the report shown by
+//! the CLI, both as text and as JSON: whether it happened while evaluating the
+//! config or while applying it, the innermost message, the spec, and the Lua
+//! stack traceback split off from the message.
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::api::ApiError;
+use crate::eval::EvalError;
+use crate::execute::ExecuteError;
+
+/// The phase an error happened in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorPhase {
+  /// Evaluating the config: running Lua code, resolving inputs.
+  Eval,
+  /// Applying a manifest: realizing builds and running bind actions.
+  Apply,
+}
+
+impl fmt::Display for ErrorPhase {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      ErrorPhase::Eval => "evaluation",
+      ErrorPhase::Apply => "apply",
+    })
+  }
+}
+
+/// Whether a spec is a build or a bind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpecKind {
+  Build,
+  Bind,
+}
+
+impl fmt::Display for SpecKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      SpecKind::Build => "build",
+      SpecKind::Bind => "bind",
+    })
+  }
+}
+
+/// The spec function an error was raised in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpecSite {
+  pub kind: SpecKind,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  /// `inputs`, `create`, `update`, `destroy` or `check`.
+  pub callback: String,
+  /// File the function was defined in.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub source: Option<String>,
+  /// Line the function was defined at.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub line: Option<usize>,
+}
+
+impl SpecSite {
+  fn of(kind: SpecKind, id: Option<&str>, callback: &'static str, func: &LuaFunction) -> Self {
+    let info = func.info();
+    // File chunks are named `@<path>`; short_src truncates long paths
+    let source = match info.source.as_deref().and_then(|chunk| chunk.strip_prefix('@')) {
+      Some(path) => Some(path.to_string()),
+      None => info.short_src,
+    };
+    SpecSite {
+      kind,
+      id: id.map(str::to_string),
+      callback: callback.to_string(),
+      source,
+      line: info.line_defined,
+    }
+  }
+}
+
+impl fmt::Display for SpecSite {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.id {
+      Some(id) => write!(f, "{} '{}' {}", self.kind, id, self.callback)?,
+      None => write!(f, "{} {}", self.kind, self.callback)?,
+    }
+    match (&self.source, self.line) {
+      (Some(source), Some(line)) => write!(f, " (defined at {}:{})", source, line),
+      (Some(source), None) => write!(f, " (defined in {})", source),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// An error raised by a spec function, tagged with the function.
+#[derive(Debug)]
+pub struct SpecError {
+  pub site: SpecSite,
+  pub error: LuaError,
+}
+
+impl fmt::Display for SpecError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "in {}: {}", self.site, self.error)
+  }
+}
+
+impl StdError for SpecError {
+  fn source(&self) -> Option<&(dyn StdError + 'static)> {
+    Some(&self.error)
+  }
+}
+
+/// Calls the functions of one build or bind spec.
+#[derive(Debug, Clone, Copy)]
+pub struct SpecCaller<'a> {
+  pub kind: SpecKind,
+  pub id: Option<&'a str>,
+}
+
+impl<'a> SpecCaller<'a> {
+  pub fn new(kind: SpecKind, id: Option<&'a str>) -> Self {
+    Self { kind, id }
+  }
+
+  /// Call the spec's `callback` function `func`, tagging an error it raises
+  /// with a [`SpecError`].
+  pub fn call<R: FromLuaMulti>(
+    &self,
+    callback: &'static str,
+    func: &LuaFunction,
+    args: impl IntoLuaMulti,
+  ) -> LuaResult<R> {
+    func.call(args).map_err(|error| {
+      LuaError::external(SpecError {
+        site: SpecSite::of(self.kind, self.id, callback, func),
+        error,
+      })
+    })
+  }
+}
+
+/// A report of an error for the user.
+///
+/// Serializable so the daemon can send it along with an [`ApiError`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+  /// The phase the error happened in, if it was evaluating or applying.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub phase: Option<ErrorPhase>,
+  /// The innermost error message, without a traceback.
+  pub message: String,
+  /// The spec function that raised the error, if any.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub spec: Option<SpecSite>,
+  /// The Lua stack traceback of the innermost error that has one.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub traceback: Option<String>,
+  /// The messages of the error chain, outermost first.
+  pub chain: Vec<String>,
+}
+
+/// Marks where Lua appends a traceback to an error message.
+const TRACEBACK_MARKER: &str = "stack traceback:";
+
+impl Diagnostic {
+  /// Diagnose the error `err` and the errors that caused it.
+  pub fn from_error(err: &(dyn StdError + 'static)) -> Self {
+    let mut diagnostic = Diagnostic {
+      phase: None,
+      message: err.to_string(),
+      spec: None,
+      traceback: None,
+      chain: Vec::new(),
+    };
+
+    let mut current = Some(err);
+    while let Some(err) = current {
+      if let Some(ApiError::Lua(diagnosed)) = err.downcast_ref::<ApiError>() {
+        // Diagnosed where it happened, possibly in the daemon
+        let mut chain = std::mem::take(&mut diagnostic.chain);
+        chain.extend(diagnosed.chain.iter().cloned());
+        return Diagnostic {
+          chain,
+          ..diagnosed.clone()
+        };
+      }
+      diagnostic.chain.push(err.to_string());
+      if let Some(lua) = err.downcast_ref::<LuaError>() {
+        diagnostic.phase = Some(ErrorPhase::Eval);
+        diagnostic.read_lua(lua);
+        break;
+      }
+      match err.downcast_ref::<ApiError>() {
+        Some(ApiError::Eval(_)) => diagnostic.phase = Some(ErrorPhase::Eval),
+        Some(ApiError::Execute(_)) => diagnostic.phase = Some(ErrorPhase::Apply),
+        _ if err.is::<EvalError>() => diagnostic.phase = Some(ErrorPhase::Eval),
+        _ if err.is::<ExecuteError>() && diagnostic.phase.is_none() => diagnostic.phase = Some(ErrorPhase::Apply),
+        _ => {}
+      }
+      diagnostic.message = err.to_string();
+      current = err.source();
+    }
+    diagnostic
+  }
+
+  /// Collect the spec, message and traceback of a Lua error.
+  fn read_lua(&mut self, err: &LuaError) {
+    match err {
+      LuaError::CallbackError { traceback, cause } => {
+        self.set_traceback(traceback);
+        self.read_lua(cause);
+      }
+      LuaError::WithContext { cause, .. } => self.read_lua(cause),
+      LuaError::ExternalError(external) => {
+        if let Some(spec) = external.downcast_ref::<SpecError>() {
+          self.spec = Some(spec.site.clone());
+          self.read_lua(&spec.error);
+        } else if let Some(lua) = external.downcast_ref::<LuaError>() {
+          self.read_lua(lua);
+        } else {
+          self.message = external.to_string();
+        }
+      }
+      LuaError::RuntimeError(message) => match message.split_once(TRACEBACK_MARKER) {
+        Some((message, traceback)) => {
+          self.message = message.trim_end().to_string();
+          self.set_traceback(&format!("{}{}", TRACEBACK_MARKER, traceback));
+        }
+        None => self.message = message.clone(),
+      },
+      other => self.message = other.to_string(),
+    }
+  }
+
+  fn set_traceback(&mut self, traceback: &str) {
+    let traceback = traceback.trim();
+    if !traceback.is_empty() {
+      self.traceback = Some(traceback.to_string());
+    }
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.phase {
+      Some(phase) => write!(f, "{} error: {}", phase, self.message)?,
+      None => write!(f, "{}", self.message)?,
+    }
+    if let Some(spec) = &self.spec {
+      write!(f, "\n  in {}", spec)?;
+    }
+    if let Some(traceback) = &self.traceback {
+      for line in traceback.lines() {
+        write!(f, "\n  {}", line.trim_end())?;
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn caller_error(lua: &Lua, code: &str) -> LuaError {
+    let func: LuaFunction = lua.load(code).set_name("@/cfg/init.lua").eval().unwrap();
+    SpecCaller::new(SpecKind::Bind, Some("nginx"))
+      .call::<()>("create", &func, ())
+      .unwrap_err()
+  }
+
+  #[test]
+  fn spec_errors_name_the_spec_and_split_the_traceback() {
+    let lua = Lua::new();
+    let err = caller_error(&lua, "return function()\n  error('boom')\nend");
+
+    let diagnostic = Diagnostic::from_error(&err);
+    assert_eq!(diagnostic.phase, Some(ErrorPhase::Eval));
+    assert_eq!(diagnostic.message, "/cfg/init.lua:2: boom");
+    assert_eq!(
+      diagnostic.spec,
+      Some(SpecSite {
+        kind: SpecKind::Bind,
+        id: Some("nginx".to_string()),
+        callback: "create".to_string(),
+        source: Some("/cfg/init.lua".to_string()),
+        line: Some(1),
+      })
+    );
+    assert!(diagnostic.traceback.unwrap().starts_with(TRACEBACK_MARKER));
+  }
+
+  #[test]
+  fn rust_errors_inside_spec_functions_keep_the_callback_traceback() {
+    let lua = Lua::new();
+    let fail = lua
+      .create_function(|_, ()| Err::<(), _>(LuaError::external("bad argument")))
+      .unwrap();
+    lua.globals().set("fail", fail).unwrap();
+    let err = caller_error(&lua, "return function()\n  fail()\nend");
+
+    let diagnostic = Diagnostic::from_error(&err);
+    assert_eq!(diagnostic.message, "bad argument");
+    assert_eq!(diagnostic.spec.unwrap().callback, "create");
+    assert!(diagnostic.traceback.is_some());
+  }
+
+  #[test]
+  fn display_lists_phase_spec_and_traceback() {
+    let diagnostic = Diagnostic {
+      phase: Some(ErrorPhase::Eval),
+      message: "init.lua:2: boom".to_string(),
+      spec: Some(SpecSite {
+        kind: SpecKind::Build,
+        id: None,
+        callback: "check".to_string(),
+        source: Some("init.lua".to_string()),
+        line: Some(1),
+      }),
+      traceback: Some("stack traceback:\n\t[C]: in ?".to_string()),
+      chain: Vec::new(),
+    };
+    assert_eq!(
+      diagnostic.to_string(),
+      "evaluation error: init.lua:2: boom\n  in build check (defined at init.lua:1)\n  stack traceback:\n  \t[C]: in ?"
+    );
+  }
+
+  #[test]
+  fn execute_errors_are_apply_errors() {
+    let err = ExecuteError::CmdFailed {
+      cmd: "/bin/false".to_string(),
+      code: Some(1),
+    };
+    let diagnostic = Diagnostic::from_error(&err);
+    assert_eq!(diagnostic.phase, Some(ErrorPhase::Apply));
+    assert_eq!(diagnostic.message, "command failed with exit code Some(1): /bin/false");
+    assert_eq!(diagnostic.spec, None);
+  }
+
+  #[test]
+  fn api_errors_carry_the_diagnostic_across_the_daemon() {
+    let lua = Lua::new();
+    let err = caller_error(&lua, "return function()\n  error('boom')\nend");
+    let api: ApiError = EvalError::Lua(err).into();
+    let api: ApiError = serde_json::from_str(&serde_json::to_string(&api).unwrap()).unwrap();
+
+    let diagnostic = Diagnostic::from_error(&api);
+    assert_eq!(diagnostic.phase, Some(ErrorPhase::Eval));
+    assert_eq!(diagnostic.message, "/cfg/init.lua:2: boom");
+    assert_eq!(diagnostic.spec.unwrap().id.as_deref(), Some("nginx"));
+  }
+}
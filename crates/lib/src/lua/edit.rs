@@ -0,0 +1,447 @@
+//! Editing a config's entrypoint in place.
+//!
+//! `sys add-input` declares inputs with [`add_input`], which inserts the
+//! declaration into the `inputs` table of the table the entrypoint returns:
+//!
+//! ```lua
+//! return {
+//!   inputs = {
+//!     pkgs = "git:https://github.com/org/pkgs.git",
+//!     dotfiles_kit = "git:https://github.com/example/dotfiles-kit.git", -- added
+//!   },
+//!   setup = function(inputs) ... end,
+//! }
+//! ```
+//!
+//! The new field is indented like its neighbours (or one level deeper than
+//! `inputs`) and everything else, comments and formatting included, is kept.
+//! An `inputs` table is added when the config has none. Configs that don't
+//! end with `return { ... }`, such as `return M`, or whose `inputs` isn't a
+//! table constructor, are left alone.
+//!
+//! `sys vendor` rewrites declarations with [`replace_input`], and `sys
+//! unvendor` puts the original text back with [`restore_input`]. Both only
+//! find inputs declared as `name = ...`.
+
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use super::syntax::{Edit, Field, Lexer, append_field, apply_edits, is_ident_start, line_indent};
+use crate::inputs::{InputDecl, InputOverride};
+
+/// Lua keywords, which can't be used as bare field names.
+const KEYWORDS: &[&str] = &[
+  "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in", "local", "nil", "not",
+  "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+/// Why a config couldn't be edited.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EditError {
+  #[error("the config doesn't end with `return {{ ... }}`; declare the input by hand")]
+  NoConfigTable,
+
+  #[error("the config's `inputs` isn't a table constructor; declare the input by hand")]
+  InputsNotATable,
+
+  #[error("input '{0}' is already declared")]
+  AlreadyDeclared(String),
+
+  #[error("input '{0}' isn't declared as `{0} = ...` in the config's `inputs` table; edit it by hand")]
+  NotDeclared(String),
+}
+
+/// Declare the input `name` with `url` in the entrypoint `source`.
+pub fn add_input(source: &str, name: &str, url: &str) -> Result<String, EditError> {
+  let lexer = Lexer::new(source);
+  let (open, close) = config_table(&lexer).ok_or(EditError::NoConfigTable)?;
+  let fields = lexer.fields(open, close);
+  let unit = indent_unit(source, open, fields.first().map(|field| field.key_start));
+  let declaration = format!("{} = {}", field_key(name), lua_string(url));
+
+  let Some(inputs) = fields.iter().find(|field| field.key == "inputs") else {
+    let indent = line_indent(source, open);
+    let table = format!("inputs = {{\n{indent}{unit}{unit}{declaration},\n{indent}{unit}}}");
+    let edit = match fields.first() {
+      // First in the config, like `sys init` writes it
+      Some(first) if source[open..close].contains('\n') => Edit {
+        start: first.key_start,
+        end: first.key_start,
+        text: format!("{},\n{}", table, line_indent(source, first.key_start)),
+      },
+      Some(first) => Edit {
+        start: first.key_start,
+        end: first.key_start,
+        text: format!("inputs = {{ {} }}, ", declaration),
+      },
+      None => into_empty_table(source, open, close, indent, &format!("{unit}{table},")),
+    };
+    return Ok(apply_edits(source, vec![edit]));
+  };
+
+  let (inputs_open, inputs_close) = inputs_table(&lexer, inputs)?;
+  let declared = lexer.fields(inputs_open, inputs_close);
+  if declared.iter().any(|field| field.key == name) {
+    return Err(EditError::AlreadyDeclared(name.to_string()));
+  }
+  let edit = match declared.last() {
+    Some(last) => append_field(source, inputs_open, inputs_close, last, &declaration),
+    None => {
+      let indent = line_indent(source, inputs.key_start);
+      into_empty_table(
+        source,
+        inputs_open,
+        inputs_close,
+        indent,
+        &format!("{unit}{declaration},"),
+      )
+    }
+  };
+  Ok(apply_edits(source, vec![edit]))
+}
+
+/// The text declaring input `name` in the entrypoint `source`, such as
+/// `"git:https://github.com/org/pkgs.git"` or `{ url = ..., inputs = ... }`.
+pub fn input_declaration<'a>(source: &'a str, name: &str) -> Result<&'a str, EditError> {
+  let ((start, end), _, _) = declared_input(source, name)?;
+  Ok(&source[start..end])
+}
+
+/// Replace the declaration of input `name` in the entrypoint `source` with
+/// `decl`, written as a string when it is only a URL and as a table
+/// otherwise.
+pub fn replace_input(source: &str, name: &str, decl: &InputDecl) -> Result<String, EditError> {
+  let ((start, end), indent, unit) = declared_input(source, name)?;
+  let edit = Edit {
+    start,
+    end,
+    text: declaration(decl, indent.as_deref(), &unit),
+  };
+  Ok(apply_edits(source, vec![edit]))
+}
+
+/// Replace the declaration of input `name` in the entrypoint `source` with
+/// `text` as it is, such as one returned by [`input_declaration`].
+pub fn restore_input(source: &str, name: &str, text: &str) -> Result<String, EditError> {
+  let ((start, end), _, _) = declared_input(source, name)?;
+  let edit = Edit {
+    start,
+    end,
+    text: text.to_string(),
+  };
+  Ok(apply_edits(source, vec![edit]))
+}
+
+/// Where the value declaring input `name` starts and ends, the indentation
+/// of its line (unless the `inputs` table is on one line) and one level of
+/// indentation.
+fn declared_input(source: &str, name: &str) -> Result<((usize, usize), Option<String>, String), EditError> {
+  let lexer = Lexer::new(source);
+  let (open, close) = config_table(&lexer).ok_or(EditError::NoConfigTable)?;
+  let fields = lexer.fields(open, close);
+  let inputs = fields
+    .iter()
+    .find(|field| field.key == "inputs")
+    .ok_or_else(|| EditError::NotDeclared(name.to_string()))?;
+  let (inputs_open, inputs_close) = inputs_table(&lexer, inputs)?;
+
+  let declared = lexer.fields(inputs_open, inputs_close);
+  let unit = indent_unit(source, inputs_open, declared.first().map(|field| field.key_start));
+  let field = declared
+    .iter()
+    .find(|field| field.key == name)
+    .ok_or_else(|| EditError::NotDeclared(name.to_string()))?;
+  let indent = source[inputs_open..inputs_close]
+    .contains('\n')
+    .then(|| line_indent(source, field.key_start).to_string());
+  Ok(((field.value_start, field.value_end), indent, unit))
+}
+
+/// The braces of the `inputs` table constructor.
+fn inputs_table(lexer: &Lexer, inputs: &Field) -> Result<(usize, usize), EditError> {
+  let open = inputs.value_start;
+  let close = match lexer.src.get(open) {
+    Some(&b'{') => lexer.matching(open),
+    _ => None,
+  }
+  .filter(|close| close + 1 == inputs.value_end)
+  .ok_or(EditError::InputsNotATable)?;
+  Ok((open, close))
+}
+
+/// `decl` as Lua source. Tables span several lines, indented one `unit`
+/// deeper than `indent`, unless `indent` is `None`.
+fn declaration(decl: &InputDecl, indent: Option<&str>, unit: &str) -> String {
+  let (url, overrides) = match decl {
+    InputDecl::Url(url) => return lua_string(url),
+    InputDecl::Extended { url, inputs } => (url, inputs),
+  };
+  let mut fields = Vec::new();
+  if let Some(url) = url {
+    fields.push(format!("url = {}", lua_string(url)));
+  }
+  if !overrides.is_empty() {
+    fields.push(format!(
+      "inputs = {}",
+      table(
+        &override_fields(overrides),
+        indent.map(|indent| format!("{indent}{unit}")).as_deref(),
+        unit
+      )
+    ));
+  }
+  table(&fields, indent, unit)
+}
+
+fn override_fields(overrides: &BTreeMap<String, InputOverride>) -> Vec<String> {
+  overrides
+    .iter()
+    .map(|(key, input)| {
+      let value = match input {
+        InputOverride::Url(url) => format!("{{ url = {} }}", lua_string(url)),
+        InputOverride::Follows(target) => format!("{{ follows = {} }}", lua_string(target)),
+      };
+      format!("{} = {}", field_key(key), value)
+    })
+    .collect()
+}
+
+/// A table constructor of `fields`, on one line when `indent` is `None`.
+fn table(fields: &[String], indent: Option<&str>, unit: &str) -> String {
+  match indent {
+    _ if fields.is_empty() => "{}".to_string(),
+    None => format!("{{ {} }}", fields.join(", ")),
+    Some(indent) => {
+      let mut text = "{\n".to_string();
+      for field in fields {
+        text.push_str(&format!("{indent}{unit}{field},\n"));
+      }
+      text.push_str(indent);
+      text.push('}');
+      text
+    }
+  }
+}
+
+/// The braces of the table constructor the entrypoint ends by returning.
+fn config_table(lexer: &Lexer) -> Option<(usize, usize)> {
+  let src = lexer.src;
+  let mut found = None;
+  let mut pos = 0;
+  while pos < src.len() {
+    if let Some(end) = lexer.trivia_end(pos).or_else(|| lexer.string_end(pos)) {
+      pos = end;
+      continue;
+    }
+    if !is_ident_start(src[pos]) {
+      pos += 1;
+      continue;
+    }
+    let end = lexer.ident_end(pos);
+    if &src[pos..end] == b"return" {
+      let open = lexer.skip_trivia(end);
+      if src.get(open) == Some(&b'{')
+        && let Some(close) = lexer.matching(open)
+      {
+        found = Some((open, close));
+        pos = close + 1;
+        continue;
+      }
+    }
+    pos = end;
+  }
+  // Only when nothing but comments follows the table
+  found.filter(|(_, close)| lexer.skip_trivia(close + 1) == src.len())
+}
+
+/// One level of indentation: how much deeper the config's fields are
+/// indented than its `return`, or two spaces.
+fn indent_unit(source: &str, open: usize, first_field: Option<usize>) -> String {
+  let outer = line_indent(source, open);
+  first_field
+    .filter(|field| source[open..*field].contains('\n'))
+    .and_then(|field| line_indent(source, field).strip_prefix(outer))
+    .filter(|unit| !unit.is_empty())
+    .unwrap_or("  ")
+    .to_string()
+}
+
+/// Fill the table between `open` and `close`, which has no fields, with
+/// `line` (already indented one level deeper than `indent`). Comments in it
+/// are kept.
+fn into_empty_table(source: &str, open: usize, close: usize, indent: &str, line: &str) -> Edit {
+  let start = open + 1 + source[open + 1..close].trim_end().len();
+  Edit {
+    start,
+    end: close,
+    text: format!("\n{indent}{line}\n{indent}"),
+  }
+}
+
+/// `name` as a table key: bare when it is an identifier, else bracketed.
+fn field_key(name: &str) -> String {
+  let identifier = name.bytes().next().is_some_and(is_ident_start)
+    && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+    && !KEYWORDS.contains(&name);
+  if identifier {
+    name.to_string()
+  } else {
+    format!("[{}]", lua_string(name))
+  }
+}
+
+/// `value` as a double-quoted Lua string literal.
+fn lua_string(value: &str) -> String {
+  let mut literal = String::with_capacity(value.len() + 2);
+  literal.push('"');
+  for c in value.chars() {
+    match c {
+      '"' => literal.push_str("\\\""),
+      '\\' => literal.push_str("\\\\"),
+      '\n' => literal.push_str("\\n"),
+      '\r' => literal.push_str("\\r"),
+      c => literal.push(c),
+    }
+  }
+  literal.push('"');
+  literal
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const URL: &str = "git:https://github.com/example/kit.git";
+
+  #[test]
+  fn appends_to_the_inputs_table() {
+    let source = r#"-- my config
+return {
+    inputs = {
+        -- packages
+        pkgs = "git:https://github.com/org/pkgs.git", -- pinned by the lock file
+    },
+    setup = function(inputs)
+        return { inputs = {} }
+    end,
+}
+"#;
+    let expected = r#"-- my config
+return {
+    inputs = {
+        -- packages
+        pkgs = "git:https://github.com/org/pkgs.git", -- pinned by the lock file
+        kit = "git:https://github.com/example/kit.git",
+    },
+    setup = function(inputs)
+        return { inputs = {} }
+    end,
+}
+"#;
+    assert_eq!(add_input(source, "kit", URL).unwrap(), expected);
+  }
+
+  #[test]
+  fn fills_an_empty_inputs_table() {
+    let source = "return {\n  inputs = {},\n  setup = function() end,\n}\n";
+    assert_eq!(
+      add_input(source, "kit", URL).unwrap(),
+      "return {\n  inputs = {\n    kit = \"git:https://github.com/example/kit.git\",\n  },\n  setup = function() end,\n}\n"
+    );
+
+    let source = "return { inputs = { a = \"path:./a\" }, setup = function() end }";
+    assert_eq!(
+      add_input(source, "my-kit", URL).unwrap(),
+      "return { inputs = { a = \"path:./a\", [\"my-kit\"] = \"git:https://github.com/example/kit.git\" }, setup = function() end }"
+    );
+  }
+
+  #[test]
+  fn adds_an_inputs_table() {
+    let source = "local M = {}\n\nreturn {\n\tsetup = function() end,\n}\n";
+    assert_eq!(
+      add_input(source, "kit", URL).unwrap(),
+      "local M = {}\n\nreturn {\n\tinputs = {\n\t\tkit = \"git:https://github.com/example/kit.git\",\n\t},\n\tsetup = function() end,\n}\n"
+    );
+
+    assert_eq!(
+      add_input("return {}", "kit", URL).unwrap(),
+      "return {\n  inputs = {\n    kit = \"git:https://github.com/example/kit.git\",\n  },\n}"
+    );
+  }
+
+  #[test]
+  fn leaves_configs_it_cannot_edit_alone() {
+    let source = "local M = { inputs = {} }\nlocal function f() return {} end\nreturn M\n";
+    assert_eq!(add_input(source, "kit", URL), Err(EditError::NoConfigTable));
+
+    let source = "local inputs = {}\nreturn { inputs = inputs }\n";
+    assert_eq!(add_input(source, "kit", URL), Err(EditError::InputsNotATable));
+
+    let source = "return { inputs = { kit = \"path:./kit\" } }\n";
+    assert_eq!(
+      add_input(source, "kit", URL),
+      Err(EditError::AlreadyDeclared("kit".to_string()))
+    );
+    assert_eq!(field_key("end"), "[\"end\"]");
+  }
+
+  #[test]
+  fn replaces_and_restores_declarations() {
+    let source = r#"return {
+  inputs = {
+    -- packages
+    pkgs = { url = "git:https://github.com/org/pkgs.git" }, -- pinned
+    kit = "path:./kit",
+  },
+}
+"#;
+    let decl = InputDecl::Extended {
+      url: Some("path:./vendor/pkgs-1a2b3c4d".to_string()),
+      inputs: BTreeMap::from([
+        (
+          "utils".to_string(),
+          InputOverride::Url("path:../utils-5e6f7a8b".to_string()),
+        ),
+        ("lib/core".to_string(), InputOverride::Follows("kit".to_string())),
+      ]),
+    };
+    let vendored = replace_input(source, "pkgs", &decl).unwrap();
+    assert_eq!(
+      vendored,
+      r#"return {
+  inputs = {
+    -- packages
+    pkgs = {
+      url = "path:./vendor/pkgs-1a2b3c4d",
+      inputs = {
+        ["lib/core"] = { follows = "kit" },
+        utils = { url = "path:../utils-5e6f7a8b" },
+      },
+    }, -- pinned
+    kit = "path:./kit",
+  },
+}
+"#
+    );
+
+    let original = input_declaration(source, "pkgs").unwrap();
+    assert_eq!(original, r#"{ url = "git:https://github.com/org/pkgs.git" }"#);
+    assert_eq!(restore_input(&vendored, "pkgs", original).unwrap(), source);
+
+    let source = "return { inputs = { pkgs = \"git:https://github.com/org/pkgs.git\" } }";
+    let decl = InputDecl::Extended {
+      url: Some("path:./vendor/pkgs".to_string()),
+      inputs: BTreeMap::from([("utils".to_string(), InputOverride::Url("path:../utils".to_string()))]),
+    };
+    assert_eq!(
+      replace_input(source, "pkgs", &decl).unwrap(),
+      "return { inputs = { pkgs = { url = \"path:./vendor/pkgs\", inputs = { utils = { url = \"path:../utils\" } } } } }"
+    );
+    assert_eq!(
+      replace_input(source, "kit", &InputDecl::Url("path:./kit".to_string())),
+      Err(EditError::NotDeclared("kit".to_string()))
+    );
+  }
+}
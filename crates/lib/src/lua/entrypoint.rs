@@ -1,13 +1,19 @@
 use std::cell::RefCell;
 use std::collections::BTreeMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::Duration;
 
 use mlua::prelude::*;
 
+use crate::inputs::fetch::{FetchAuth, FetchSettings, FetchTimeouts};
+use crate::inputs::refs::RefsSettings;
 use crate::inputs::{InputDecl, InputDecls, InputOverride};
 use crate::lua::runtime;
 use crate::manifest::Manifest;
+use crate::placeholder::{self, Placeholder, Segment};
+use crate::platform::paths::expand_path;
+use crate::self_update::SelfUpdateSettings;
 
 /// Extract raw input declarations from an entrypoint file.
 ///
@@ -50,6 +56,333 @@ pub fn extract_input_decls(entrypoint_path: &str) -> LuaResult<InputDecls> {
   }
 }
 
+/// Extract fetch credentials and timeouts from an entrypoint's `settings.fetch` table.
+///
+/// See [`parse_fetch_settings`].
+pub fn extract_fetch_settings(entrypoint_path: &str) -> LuaResult<FetchSettings> {
+  let manifest = Rc::new(RefCell::new(Manifest::default()));
+  let lua = runtime::create_runtime(manifest, false)?;
+
+  let result = runtime::load_file(&lua, Path::new(entrypoint_path))?;
+  let result_table = result
+    .as_table()
+    .ok_or_else(|| LuaError::external("entrypoint must return a table"))?;
+
+  parse_fetch_settings(result_table)
+}
+
+/// Parse fetch credentials and timeouts from a config table's `settings.fetch`.
+///
+/// Timeouts are in seconds, as is `refs_ttl`, how long looked up tags and
+/// heads are cached (0 revalidates them every time).
+///
+/// Settings are read before anything runs, so `sys.getenv` placeholders in
+/// `tokens` and `netrc` are resolved here from the environment; an unset
+/// variable or any other placeholder is an error rather than being sent as a
+/// token.
+///
+/// ```lua
+/// return {
+///   settings = {
+///     fetch = {
+///       netrc = "~/.config/syslua/netrc",
+///       tokens = { ["git.example.com"] = sys.getenv("EXAMPLE_TOKEN") }, -- read from $EXAMPLE_TOKEN
+///       connect_timeout = 10,
+///       timeout = 300,
+///       refs_ttl = 600,
+///     },
+///   },
+///   ...
+/// }
+/// ```
+pub fn parse_fetch_settings(config_table: &LuaTable) -> LuaResult<FetchSettings> {
+  let Some(settings) = config_table.get::<Option<LuaTable>>("settings")? else {
+    return Ok(FetchSettings::default());
+  };
+  let fetch: Option<LuaTable> = settings
+    .get("fetch")
+    .map_err(|_| LuaError::external("settings.fetch must be a table"))?;
+  let Some(fetch) = fetch else {
+    return Ok(FetchSettings::default());
+  };
+
+  let netrc: Option<String> = fetch
+    .get("netrc")
+    .map_err(|_| LuaError::external("settings.fetch.netrc must be a path string"))?;
+  let netrc = netrc
+    .map(|path| resolve_env_placeholders(&path, "settings.fetch.netrc"))
+    .transpose()?;
+  let tokens: Option<BTreeMap<String, String>> = fetch
+    .get("tokens")
+    .map_err(|_| LuaError::external("settings.fetch.tokens must map host names to token strings"))?;
+  let tokens = tokens
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(host, token)| {
+      let token = resolve_env_placeholders(&token, &format!("settings.fetch.tokens[\"{}\"]", host))?;
+      Ok((host, token))
+    })
+    .collect::<LuaResult<BTreeMap<_, _>>>()?;
+
+  let defaults = FetchTimeouts::default();
+  let timeouts = FetchTimeouts {
+    connect: parse_timeout(&fetch, "connect_timeout")?.unwrap_or(defaults.connect),
+    total: parse_timeout(&fetch, "timeout")?.unwrap_or(defaults.total),
+  };
+
+  let invalid_ttl = || LuaError::external("settings.fetch.refs_ttl must be a number of seconds (0 or more)");
+  let refs_ttl: Option<f64> = fetch.get("refs_ttl").map_err(|_| invalid_ttl())?;
+  let refs = match refs_ttl {
+    None => RefsSettings::default(),
+    Some(seconds) if seconds.is_finite() && seconds >= 0.0 => RefsSettings {
+      ttl: Duration::from_secs_f64(seconds),
+      ..Default::default()
+    },
+    Some(_) => return Err(invalid_ttl()),
+  };
+
+  Ok(FetchSettings {
+    auth: FetchAuth {
+      tokens,
+      netrc: netrc.map(|path| expand_path(&path)),
+    },
+    timeouts,
+    refs,
+  })
+}
+
+/// Replace the `$${{env:NAME}}` placeholders `sys.getenv` returns in the
+/// setting `key` with the environment's values.
+fn resolve_env_placeholders(value: &str, key: &str) -> LuaResult<String> {
+  let segments = placeholder::parse(value).map_err(|e| LuaError::external(format!("{}: {}", key, e)))?;
+  let mut resolved = String::new();
+  for segment in segments {
+    match segment {
+      Segment::Literal(text) => resolved.push_str(&text),
+      Segment::Placeholder(Placeholder::Env(name)) => match std::env::var(&name) {
+        Ok(env_value) => resolved.push_str(&env_value),
+        Err(_) => {
+          return Err(LuaError::external(format!(
+            "{}: environment variable {} is not set",
+            key, name
+          )));
+        }
+      },
+      Segment::Placeholder(_) => {
+        return Err(LuaError::external(format!(
+          "{} can only use sys.getenv values, not build or bind outputs",
+          key
+        )));
+      }
+    }
+  }
+  Ok(resolved)
+}
+
+/// Parse a positive number of seconds from `settings.fetch.<key>`.
+fn parse_timeout(fetch: &LuaTable, key: &str) -> LuaResult<Option<Duration>> {
+  let invalid = || LuaError::external(format!("settings.fetch.{} must be a positive number of seconds", key));
+  let seconds: Option<f64> = fetch.get(key).map_err(|_| invalid())?;
+  match seconds {
+    None => Ok(None),
+    Some(seconds) if seconds.is_finite() && seconds > 0.0 => Ok(Some(Duration::from_secs_f64(seconds))),
+    Some(_) => Err(invalid()),
+  }
+}
+
+/// Extract self-update settings from an entrypoint's `settings.self_update` table.
+///
+/// See [`parse_self_update_settings`].
+pub fn extract_self_update_settings(entrypoint_path: &str) -> LuaResult<SelfUpdateSettings> {
+  let manifest = Rc::new(RefCell::new(Manifest::default()));
+  let lua = runtime::create_runtime(manifest, false)?;
+
+  let result = runtime::load_file(&lua, Path::new(entrypoint_path))?;
+  let result_table = result
+    .as_table()
+    .ok_or_else(|| LuaError::external("entrypoint must return a table"))?;
+
+  parse_self_update_settings(result_table)
+}
+
+/// Parse the release channel, endpoint and key from a config table's `settings.self_update`.
+///
+/// ```lua
+/// return {
+///   settings = {
+///     self_update = {
+///       channel = "nightly",
+///       endpoint = "https://mirror.example.com/syslua",
+///       public_key = "3b6a27bc...",
+///     },
+///   },
+///   ...
+/// }
+/// ```
+pub fn parse_self_update_settings(config_table: &LuaTable) -> LuaResult<SelfUpdateSettings> {
+  let Some(settings) = config_table.get::<Option<LuaTable>>("settings")? else {
+    return Ok(SelfUpdateSettings::default());
+  };
+  let self_update: Option<LuaTable> = settings
+    .get("self_update")
+    .map_err(|_| LuaError::external("settings.self_update must be a table"))?;
+  let Some(self_update) = self_update else {
+    return Ok(SelfUpdateSettings::default());
+  };
+
+  let channel: Option<String> = self_update
+    .get("channel")
+    .map_err(|_| LuaError::external("settings.self_update.channel must be a string"))?;
+  let endpoint: Option<String> = self_update
+    .get("endpoint")
+    .map_err(|_| LuaError::external("settings.self_update.endpoint must be a URL string"))?;
+  let public_key: Option<String> = self_update
+    .get("public_key")
+    .map_err(|_| LuaError::external("settings.self_update.public_key must be a hex string"))?;
+
+  Ok(SelfUpdateSettings {
+    channel: channel.map(|c| c.parse()).transpose().map_err(LuaError::external)?,
+    endpoint,
+    public_key,
+  })
+}
+
+/// Extract the store root from an entrypoint's `settings.store`.
+///
+/// See [`parse_store_setting`].
+pub fn extract_store_setting(entrypoint_path: &str) -> LuaResult<Option<PathBuf>> {
+  let manifest = Rc::new(RefCell::new(Manifest::default()));
+  let lua = runtime::create_runtime(manifest, false)?;
+
+  let path = Path::new(entrypoint_path);
+  let result = runtime::load_file(&lua, path)?;
+  let result_table = result
+    .as_table()
+    .ok_or_else(|| LuaError::external("entrypoint must return a table"))?;
+
+  parse_store_setting(result_table, path.parent().unwrap_or(Path::new(".")))
+}
+
+/// Parse the store root from a config table's `settings.store`, relative to `config_dir`.
+///
+/// ```lua
+/// return {
+///   settings = { store = ".syslua" },
+///   ...
+/// }
+/// ```
+pub fn parse_store_setting(config_table: &LuaTable, config_dir: &Path) -> LuaResult<Option<PathBuf>> {
+  let Some(settings) = config_table.get::<Option<LuaTable>>("settings")? else {
+    return Ok(None);
+  };
+  let store: Option<String> = settings
+    .get("store")
+    .map_err(|_| LuaError::external("settings.store must be a directory path string"))?;
+
+  Ok(store.map(|store| config_dir.join(expand_path(&store))))
+}
+
+/// How `settings.pager` asks for long output to be paged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PagerSetting {
+  /// `pager = false`: never page.
+  Disabled,
+  /// `pager = true`: page with the default pager.
+  Enabled,
+  /// `pager = "less -S"`: page with this command.
+  Command(String),
+}
+
+/// Extract the pager of `sys plan` from an entrypoint's `settings.pager`.
+///
+/// See [`parse_pager_setting`].
+pub fn extract_pager_setting(entrypoint_path: &str) -> LuaResult<Option<PagerSetting>> {
+  let manifest = Rc::new(RefCell::new(Manifest::default()));
+  let lua = runtime::create_runtime(manifest, false)?;
+
+  let result = runtime::load_file(&lua, Path::new(entrypoint_path))?;
+  let result_table = result
+    .as_table()
+    .ok_or_else(|| LuaError::external("entrypoint must return a table"))?;
+
+  parse_pager_setting(result_table)
+}
+
+/// Parse a config table's `settings.pager`, a boolean or a pager command.
+///
+/// ```lua
+/// return {
+///   settings = { pager = "less -S" },
+///   ...
+/// }
+/// ```
+pub fn parse_pager_setting(config_table: &LuaTable) -> LuaResult<Option<PagerSetting>> {
+  let Some(settings) = config_table.get::<Option<LuaTable>>("settings")? else {
+    return Ok(None);
+  };
+  match settings.get::<LuaValue>("pager")? {
+    LuaValue::Nil => Ok(None),
+    LuaValue::Boolean(false) => Ok(Some(PagerSetting::Disabled)),
+    LuaValue::Boolean(true) => Ok(Some(PagerSetting::Enabled)),
+    LuaValue::String(command) => Ok(Some(PagerSetting::Command(command.to_str()?.to_string()))),
+    _ => Err(LuaError::external(
+      "settings.pager must be a boolean or a command string",
+    )),
+  }
+}
+
+/// Extract the input registries of `sys search` and `sys add-input` from an
+/// entrypoint's `settings.registries`.
+///
+/// See [`parse_registries_setting`].
+pub fn extract_registries_setting(entrypoint_path: &str) -> LuaResult<Vec<String>> {
+  let manifest = Rc::new(RefCell::new(Manifest::default()));
+  let lua = runtime::create_runtime(manifest, false)?;
+
+  let path = Path::new(entrypoint_path);
+  let result = runtime::load_file(&lua, path)?;
+  let result_table = result
+    .as_table()
+    .ok_or_else(|| LuaError::external("entrypoint must return a table"))?;
+
+  parse_registries_setting(result_table, path.parent().unwrap_or(Path::new(".")))
+}
+
+/// Parse a config table's `settings.registries`, a list of registry index
+/// URLs. Entries without a scheme are paths of local indexes, relative to
+/// `config_dir`.
+///
+/// ```lua
+/// return {
+///   settings = {
+///     registries = { "https://registry.example.com/index.json", "./registry.json" },
+///   },
+///   ...
+/// }
+/// ```
+pub fn parse_registries_setting(config_table: &LuaTable, config_dir: &Path) -> LuaResult<Vec<String>> {
+  let Some(settings) = config_table.get::<Option<LuaTable>>("settings")? else {
+    return Ok(Vec::new());
+  };
+  let registries: Option<Vec<String>> = settings
+    .get("registries")
+    .map_err(|_| LuaError::external("settings.registries must be a list of URL strings"))?;
+
+  Ok(
+    registries
+      .unwrap_or_default()
+      .into_iter()
+      .map(|registry| {
+        if registry.contains("://") {
+          registry
+        } else {
+          config_dir.join(expand_path(&registry)).to_string_lossy().into_owned()
+        }
+      })
+      .collect(),
+  )
+}
+
 /// Parse an inputs table into InputDecls.
 fn parse_input_decls(inputs_table: &LuaTable) -> LuaResult<InputDecls> {
   let mut decls = BTreeMap::new();
@@ -308,4 +641,214 @@ mod tests {
 
     Ok(())
   }
+
+  #[test]
+  fn test_extract_fetch_settings() -> LuaResult<()> {
+    let temp_dir = TempDir::new().unwrap();
+    let entrypoint_path = temp_dir.path().join("init.lua");
+
+    fs::write(
+      &entrypoint_path,
+      r#"
+        return {
+          settings = {
+            fetch = {
+              netrc = "/etc/syslua/netrc",
+              tokens = { ["git.example.com"] = "secret" },
+              timeout = 90,
+              refs_ttl = 0,
+            },
+          },
+          setup = function() end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let settings = extract_fetch_settings(entrypoint_path.to_str().unwrap())?;
+    let auth = &settings.auth;
+    assert_eq!(auth.tokens.get("git.example.com").map(String::as_str), Some("secret"));
+    assert_eq!(auth.netrc, Some(std::path::PathBuf::from("/etc/syslua/netrc")));
+    assert_eq!(settings.timeouts.total, Duration::from_secs(90));
+    assert_eq!(settings.timeouts.connect, FetchTimeouts::default().connect);
+    assert_eq!(settings.refs.ttl, Duration::ZERO);
+    assert!(!settings.refs.refresh);
+
+    Ok(())
+  }
+
+  #[test]
+  #[serial_test::serial]
+  fn test_fetch_tokens_resolve_getenv() {
+    let temp_dir = TempDir::new().unwrap();
+    let entrypoint_path = temp_dir.path().join("init.lua");
+    let write = |token: &str| {
+      fs::write(
+        &entrypoint_path,
+        format!(
+          "return {{ settings = {{ fetch = {{ tokens = {{ ['git.example.com'] = {} }} }} }}, setup = function() end }}",
+          token
+        ),
+      )
+      .unwrap();
+    };
+
+    temp_env::with_vars(
+      [("SYSLUA_TEST_TOKEN", Some("secret")), ("SYSLUA_TEST_UNSET_TOKEN", None)],
+      || {
+        write("sys.getenv('SYSLUA_TEST_TOKEN')");
+        let settings = extract_fetch_settings(entrypoint_path.to_str().unwrap()).unwrap();
+        assert_eq!(
+          settings.auth.tokens.get("git.example.com").map(String::as_str),
+          Some("secret")
+        );
+
+        write("sys.getenv('SYSLUA_TEST_UNSET_TOKEN')");
+        let err = extract_fetch_settings(entrypoint_path.to_str().unwrap())
+          .err()
+          .unwrap()
+          .to_string();
+        assert!(err.contains("SYSLUA_TEST_UNSET_TOKEN is not set"), "{err}");
+
+        write("'$${{out}}'");
+        assert!(extract_fetch_settings(entrypoint_path.to_str().unwrap()).is_err());
+      },
+    );
+  }
+
+  #[test]
+  fn test_fetch_timeouts_must_be_positive() {
+    let temp_dir = TempDir::new().unwrap();
+    let entrypoint_path = temp_dir.path().join("init.lua");
+    fs::write(
+      &entrypoint_path,
+      "return { settings = { fetch = { connect_timeout = 0 } }, setup = function() end }",
+    )
+    .unwrap();
+
+    let err = extract_fetch_settings(entrypoint_path.to_str().unwrap())
+      .err()
+      .unwrap()
+      .to_string();
+    assert!(err.contains("settings.fetch.connect_timeout"), "{err}");
+  }
+
+  #[test]
+  fn test_extract_self_update_settings() -> LuaResult<()> {
+    let temp_dir = TempDir::new().unwrap();
+    let entrypoint_path = temp_dir.path().join("init.lua");
+
+    fs::write(
+      &entrypoint_path,
+      r#"
+        return {
+          settings = {
+            self_update = { channel = "nightly", endpoint = "https://mirror.example.com/syslua" },
+          },
+          setup = function() end,
+        }
+      "#,
+    )
+    .unwrap();
+
+    let settings = extract_self_update_settings(entrypoint_path.to_str().unwrap())?;
+    assert_eq!(settings.channel, Some(crate::self_update::Channel::Nightly));
+    assert_eq!(settings.index_url(), "https://mirror.example.com/syslua/nightly.json");
+
+    fs::write(
+      &entrypoint_path,
+      r#"return { settings = { self_update = { channel = "beta" } }, setup = function() end }"#,
+    )
+    .unwrap();
+    assert!(extract_self_update_settings(entrypoint_path.to_str().unwrap()).is_err());
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_extract_store_setting() -> LuaResult<()> {
+    let temp_dir = TempDir::new().unwrap();
+    let entrypoint_path = temp_dir.path().join("init.lua");
+
+    fs::write(
+      &entrypoint_path,
+      r#"return { settings = { store = ".syslua" }, setup = function() end }"#,
+    )
+    .unwrap();
+    let store = extract_store_setting(entrypoint_path.to_str().unwrap())?;
+    assert_eq!(store, Some(temp_dir.path().join(".syslua")));
+
+    fs::write(
+      &entrypoint_path,
+      r#"return { settings = { store = "/srv/syslua" }, setup = function() end }"#,
+    )
+    .unwrap();
+    let store = extract_store_setting(entrypoint_path.to_str().unwrap())?;
+    assert_eq!(store, Some(std::path::PathBuf::from("/srv/syslua")));
+
+    fs::write(&entrypoint_path, r#"return { setup = function() end }"#).unwrap();
+    assert_eq!(extract_store_setting(entrypoint_path.to_str().unwrap())?, None);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_extract_pager_setting() -> LuaResult<()> {
+    let temp_dir = TempDir::new().unwrap();
+    let entrypoint_path = temp_dir.path().join("init.lua");
+    let extract = |settings: &str| {
+      fs::write(
+        &entrypoint_path,
+        format!("return {{ settings = {{ {settings} }}, setup = function() end }}"),
+      )
+      .unwrap();
+      extract_pager_setting(entrypoint_path.to_str().unwrap())
+    };
+
+    assert_eq!(extract("")?, None);
+    assert_eq!(extract("pager = false")?, Some(PagerSetting::Disabled));
+    assert_eq!(extract("pager = true")?, Some(PagerSetting::Enabled));
+    assert_eq!(
+      extract(r#"pager = "less -S""#)?,
+      Some(PagerSetting::Command("less -S".to_string()))
+    );
+    assert!(extract("pager = 1").is_err());
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_extract_registries_setting() -> LuaResult<()> {
+    let temp_dir = TempDir::new().unwrap();
+    let entrypoint_path = temp_dir.path().join("init.lua");
+
+    fs::write(
+      &entrypoint_path,
+      r#"return {
+        settings = { registries = { "https://registry.example.com/index.json", "registry.json" } },
+        setup = function() end,
+      }"#,
+    )
+    .unwrap();
+    let registries = extract_registries_setting(entrypoint_path.to_str().unwrap())?;
+    assert_eq!(
+      registries,
+      [
+        "https://registry.example.com/index.json".to_string(),
+        temp_dir.path().join("registry.json").to_string_lossy().into_owned(),
+      ]
+    );
+
+    fs::write(&entrypoint_path, r#"return { setup = function() end }"#).unwrap();
+    assert!(extract_registries_setting(entrypoint_path.to_str().unwrap())?.is_empty());
+
+    fs::write(
+      &entrypoint_path,
+      r#"return { settings = { registries = "https://registry.example.com/index.json" }, setup = function() end }"#,
+    )
+    .unwrap();
+    assert!(extract_registries_setting(entrypoint_path.to_str().unwrap()).is_err());
+
+    Ok(())
+  }
 }
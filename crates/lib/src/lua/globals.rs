@@ -4,7 +4,12 @@
 //! - `sys.platform` - Platform triple (e.g., "aarch64-darwin")
 //! - `sys.os` - Operating system name (e.g., "darwin", "linux", "windows")
 //! - `sys.arch` - CPU architecture (e.g., "x86_64", "aarch64")
+//! - `sys.facts` - Host environment facts (WSL, containers, virtualization, init system)
+//! - `sys.vars` - Per-host variables from `host_vars/<hostname>.lua` (empty until loaded)
+//! - `sys.current` - Ids of the builds and binds currently applied (empty until loaded)
 //! - `sys.path` - Path manipulation utilities
+//! - `sys.template` - Templates rendered at eval time
+//! - `sys.api()` - Declare the Lua API version the config was written against
 //! - `sys.build{}` - Define a build
 //! - `sys.bind{}` - Define a bind
 //! - `sys.register_build_ctx_method()` - Register a custom BuildCtx method
@@ -19,10 +24,16 @@ use super::helpers;
 use crate::action::{
   BIND_CTX_METHODS_REGISTRY_KEY, BUILD_CTX_METHODS_REGISTRY_KEY, BUILTIN_BIND_CTX_METHODS, BUILTIN_BUILD_CTX_METHODS,
 };
+use crate::bind::file_block::register_sys_file_block;
+use crate::bind::firewall::register_sys_firewall;
+use crate::bind::font::register_sys_font;
 use crate::bind::lua::register_sys_bind;
-use crate::build::lua::register_sys_build;
+use crate::bind::pkgset::register_sys_pkgset;
+use crate::build::lua::{register_sys_build, register_sys_prebuilt, register_sys_src};
+use crate::build::overrides::register_sys_override_build;
 use crate::manifest::Manifest;
-use crate::platform::{self, Platform};
+use crate::platform::{self, Facts, Platform};
+use crate::snapshot::create_current_table;
 
 /// Register the `sys` global table in the Lua runtime.
 ///
@@ -33,16 +44,26 @@ pub fn register_globals(lua: &Lua, manifest: Rc<RefCell<Manifest>>) -> LuaResult
 
   // Platform information
   let platform = Platform::current().ok_or_else(|| LuaError::external("unsupported platform"))?;
+  set_sys_platform(lua, &sys, platform, &Facts::current())?;
 
-  sys.set("platform", platform.triple())?;
-  sys.set("os", platform.os.as_str())?;
-  sys.set("arch", platform.arch.as_str())?;
+  // API version declaration, deciding which deprecated globals exist
+  super::api::register_sys_api(lua, &sys)?;
   sys.set("is_elevated", platform::is_elevated())?;
 
+  // Filled from the host vars file when a config is evaluated
+  sys.set("vars", lua.create_table()?)?;
+  sys.set("current", create_current_table(lua, None)?)?;
+
   // Path utilities
   let path = helpers::path::create_path_helpers(lua)?;
   sys.set("path", path)?;
 
+  // Loops and conditionals for generating config files
+  sys.set("template", super::template::create_template_helpers(lua)?)?;
+
+  // Eval-time IO helpers, answered once and recorded in the lock file
+  sys.set("io", super::io::create_io_helpers(lua)?)?;
+
   // Environment variable placeholder (resolves at execution time)
   let getenv = lua.create_function(|_, name: String| Ok(format!("$${{{{env:{}}}}}", name)))?;
   sys.set("getenv", getenv)?;
@@ -83,11 +104,19 @@ pub fn register_globals(lua: &Lua, manifest: Rc<RefCell<Manifest>>) -> LuaResult
   })?;
   sys.set("mktime", mktime)?;
 
-  // Register sys.build{}
+  // Register sys.build{}, sys.prebuilt{} and sys.src{}, and sys.override_build() for patching builds
   register_sys_build(lua, &sys, manifest.clone())?;
+  register_sys_prebuilt(lua, &sys, manifest.clone())?;
+  register_sys_src(lua, &sys, manifest.clone())?;
+  register_sys_override_build(lua, &sys, manifest.clone())?;
 
-  // Register sys.bind{}
+  // Register sys.bind{}, and the helpers declaring binds through it (sys.pkgset{}, sys.firewall.rule{},
+  // sys.file_block{} and sys.font{})
   register_sys_bind(lua, &sys, manifest)?;
+  register_sys_pkgset(lua, &sys)?;
+  register_sys_firewall(lua, &sys)?;
+  register_sys_file_block(lua, &sys)?;
+  register_sys_font(lua, &sys)?;
 
   // Initialize the build and bind ctx method registries (empty tables)
   lua.set_named_registry_value(BUILD_CTX_METHODS_REGISTRY_KEY, lua.create_table()?)?;
@@ -145,6 +174,29 @@ pub fn register_globals(lua: &Lua, manifest: Rc<RefCell<Manifest>>) -> LuaResult
   Ok(())
 }
 
+/// Set `sys.platform`, `sys.os`, `sys.arch` and `sys.facts` of the `sys` table.
+///
+/// Evaluating for another platform (`EvalOptions::platform`) calls this again
+/// with [`Facts::for_platform`] in place of the detected facts.
+pub fn set_sys_platform(lua: &Lua, sys: &LuaTable, platform: Platform, facts: &Facts) -> LuaResult<()> {
+  sys.set("platform", platform.triple())?;
+  sys.set("os", platform.os.as_str())?;
+  sys.set("arch", platform.arch.as_str())?;
+  sys.set("facts", create_facts_table(lua, facts)?)?;
+  Ok(())
+}
+
+/// Build the `sys.facts` table: `virtualization` plus one boolean per
+/// [`Facts::FEATURES`] entry.
+pub fn create_facts_table(lua: &Lua, facts: &Facts) -> LuaResult<LuaTable> {
+  let table = lua.create_table()?;
+  table.set("virtualization", facts.virtualization.map(|v| v.as_str()))?;
+  for name in Facts::FEATURES {
+    table.set(*name, facts.feature(name))?;
+  }
+  Ok(table)
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -169,6 +221,26 @@ mod tests {
       assert!(sys.contains_key("path")?);
       assert!(sys.contains_key("build")?);
       assert!(sys.contains_key("bind")?);
+      assert!(sys.contains_key("vars")?);
+      assert!(sys.contains_key("facts")?);
+      assert!(sys.contains_key("current")?);
+      Ok(())
+    }
+
+    #[test]
+    fn facts_table_lists_features() -> LuaResult<()> {
+      let lua = create_test_lua()?;
+      let facts = Facts {
+        virtualization: Some(platform::Virtualization::Docker),
+        systemd: false,
+        launchd: false,
+      };
+      let table = create_facts_table(&lua, &facts)?;
+
+      assert_eq!(table.get::<String>("virtualization")?, "docker");
+      assert!(table.get::<bool>("container")?);
+      assert!(!table.get::<bool>("vm")?);
+      assert!(!table.get::<bool>("systemd")?);
       Ok(())
     }
 
@@ -386,6 +458,41 @@ mod tests {
       assert!(err.contains("failed to canonicalize path"));
       Ok(())
     }
+
+    #[test]
+    fn parent_matches_dirname() -> LuaResult<()> {
+      let lua = create_test_lua()?;
+      let result: String = lua.load(r#"return sys.path.parent("/foo/bar/baz.txt")"#).eval()?;
+      assert_eq!(result, "/foo/bar");
+      Ok(())
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn expand_substitutes_env_vars() -> LuaResult<()> {
+      let lua = create_test_lua()?;
+      temp_env::with_var("SYSLUA_EXPAND_TEST", Some("expanded"), || {
+        let result: String = lua.load(r#"return sys.path.expand("$SYSLUA_EXPAND_TEST/x")"#).eval()?;
+        assert_eq!(result, "expanded/x");
+        Ok(())
+      })
+    }
+
+    #[test]
+    fn exists_checks_filesystem() -> LuaResult<()> {
+      let lua = create_test_lua()?;
+      let temp_dir = std::env::temp_dir();
+      let code = format!(
+        r#"return sys.path.exists("{}")"#,
+        temp_dir.to_string_lossy().replace('\\', "\\\\")
+      );
+      assert!(lua.load(&code).eval::<bool>()?);
+      let missing: bool = lua
+        .load(r#"return sys.path.exists("/this/path/definitely/does/not/exist/12345")"#)
+        .eval()?;
+      assert!(!missing);
+      Ok(())
+    }
   }
 
   mod getenv {
@@ -0,0 +1,50 @@
+use mlua::Lua;
+use mlua::prelude::*;
+
+use crate::platform::paths::expand_path;
+
+/// Create the `sys.fs` table with read-only filesystem helpers.
+///
+/// Only registered in impure mode, where it stays available to sandboxed
+/// inputs that can't use `io`.
+pub fn create_fs_helpers(lua: &Lua) -> LuaResult<LuaTable> {
+  let fs = lua.create_table()?;
+
+  // sys.fs.read(path) - Read a file as a string, or nil and an error message
+  fs.set(
+    "read",
+    lua.create_function(|lua, path: String| match std::fs::read(expand_path(&path)) {
+      Ok(bytes) => Ok((LuaValue::String(lua.create_string(bytes)?), LuaValue::Nil)),
+      Err(e) => Ok((LuaValue::Nil, LuaValue::String(lua.create_string(e.to_string())?))),
+    })?,
+  )?;
+
+  // sys.fs.exists(path) - Check if a path exists
+  fs.set(
+    "exists",
+    lua.create_function(|_, path: String| Ok(expand_path(&path).exists()))?,
+  )?;
+
+  // sys.fs.is_dir(path) - Check if a path is a directory
+  fs.set(
+    "is_dir",
+    lua.create_function(|_, path: String| Ok(expand_path(&path).is_dir()))?,
+  )?;
+
+  // sys.fs.list(dir) - Sorted entry names of a directory
+  fs.set(
+    "list",
+    lua.create_function(|_, path: String| {
+      let entries = std::fs::read_dir(expand_path(&path))
+        .map_err(|e| LuaError::external(format!("cannot list '{}': {}", path, e)))?;
+      let mut names: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+      names.sort();
+      Ok(names)
+    })?,
+  )?;
+
+  Ok(fs)
+}
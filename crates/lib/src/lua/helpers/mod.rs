@@ -2,4 +2,5 @@
 //!
 //! These modules provide utility functions accessible from Lua via `require()`.
 
+pub mod fs;
 pub mod path;
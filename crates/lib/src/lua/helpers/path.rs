@@ -1,6 +1,8 @@
 use mlua::Lua;
 use mlua::prelude::*;
 
+use crate::platform::paths::expand_path;
+
 /// Create the `sys.path` table with path manipulation utilities.
 pub fn create_path_helpers(lua: &Lua) -> LuaResult<LuaTable> {
   let path = lua.create_table()?;
@@ -33,6 +35,9 @@ pub fn create_path_helpers(lua: &Lua) -> LuaResult<LuaTable> {
     })?,
   )?;
 
+  // sys.path.parent(path) - Alias of dirname
+  path.set("parent", path.get::<LuaFunction>("dirname")?)?;
+
   // sys.path.basename(path) - Get file name
   path.set(
     "basename",
@@ -178,6 +183,18 @@ pub fn create_path_helpers(lua: &Lua) -> LuaResult<LuaTable> {
     })?,
   )?;
 
+  // sys.path.expand(path) - Expand leading ~ and environment variables
+  path.set(
+    "expand",
+    lua.create_function(|_, path_str: String| Ok(expand_path(&path_str).to_string_lossy().into_owned()))?,
+  )?;
+
+  // sys.path.exists(path) - Check if path exists at evaluation time
+  path.set(
+    "exists",
+    lua.create_function(|_, path_str: String| Ok(std::path::Path::new(&path_str).exists()))?,
+  )?;
+
   // sys.path.canonicalize(path) - Get canonical filesystem path
   // Resolves symlinks and Windows 8.3 short names.
   // Throws error if path doesn't exist.
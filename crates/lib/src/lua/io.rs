@@ -0,0 +1,447 @@
+//! Eval-time IO helpers (`sys.io`).
+//!
+//! Evaluation runs on one single-threaded Lua VM, so a config that needs IO
+//! while it is evaluated (the hash of a download, the entries of a registry)
+//! would stall it one request at a time. `sys.io` exposes a whitelist of such
+//! helpers, [`IoRequest`], that run on a tokio runtime instead:
+//!
+//! ```lua
+//! local hash = sys.io.prefetch("https://example.com/tool.tar.gz")
+//!
+//! -- Run several lookups at once: each function is a coroutine that yields
+//! -- to the bridge whenever it calls a helper
+//! local results = sys.io.all({
+//!   function() return sys.io.prefetch(url_a) end,
+//!   function() return sys.io.registry(registry_url) end,
+//! })
+//! ```
+//!
+//! Called directly, a helper blocks until its answer arrives. Called from a
+//! task of `sys.io.all`, it yields its request; the bridge collects the
+//! requests of all tasks, runs them concurrently and resumes each task with
+//! its answer, until every task returned.
+//!
+//! Answers are recorded in the lock file's `eval` section (see
+//! [`crate::inputs::lock`]) and reused by later evaluations, so evaluating
+//! the same config gives the same answers without touching the network.
+//! `sys update` without `--input` drops them to look everything up again.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use mlua::prelude::*;
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use crate::inputs::lock::{LOCK_FILENAME, LockFile};
+use crate::inputs::registry::fetch_registry;
+use crate::outputs::lua::json_to_lua_value;
+
+/// Names of the helpers of `sys.io`, besides `all`.
+pub const IO_HELPERS: &[&str] = &["prefetch", "registry"];
+
+/// A request of a whitelisted eval-time IO helper.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IoRequest {
+  /// SHA256 (lowercase hex) of the file at a URL, as `fetch_url` expects it.
+  Prefetch { url: String },
+  /// Entries of the registry index at a URL.
+  Registry { url: String },
+}
+
+impl IoRequest {
+  /// Parse the request of helper `kind` with argument `arg`.
+  pub fn parse(kind: &str, arg: String) -> LuaResult<Self> {
+    match kind {
+      "prefetch" => Ok(Self::Prefetch { url: arg }),
+      "registry" => Ok(Self::Registry { url: arg }),
+      other => Err(LuaError::external(format!("sys.io has no helper '{}'", other))),
+    }
+  }
+
+  /// Key of the request's answer in the lock file.
+  pub fn key(&self) -> String {
+    match self {
+      Self::Prefetch { url } => format!("prefetch:{}", url),
+      Self::Registry { url } => format!("registry:{}", url),
+    }
+  }
+
+  /// Run the request.
+  async fn run(self) -> Result<JsonValue, String> {
+    match self {
+      Self::Prefetch { url } => prefetch(&url).await.map(JsonValue::String),
+      Self::Registry { url } => {
+        let index = fetch_registry(&url).await.map_err(|e| e.to_string())?;
+        serde_json::to_value(index.inputs).map_err(|e| e.to_string())
+      }
+    }
+  }
+}
+
+/// Hash the file at `url`: an `http(s)://` URL, or a `file://` URL or path.
+async fn prefetch(url: &str) -> Result<String, String> {
+  let failed = |message: String| format!("cannot prefetch {}: {}", url, message);
+  let mut hasher = Sha256::new();
+
+  if !url.starts_with("http://") && !url.starts_with("https://") {
+    let path = url.strip_prefix("file://").unwrap_or(url);
+    let mut file = tokio::fs::File::open(path).await.map_err(|e| failed(e.to_string()))?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+      let n = file.read(&mut buf).await.map_err(|e| failed(e.to_string()))?;
+      if n == 0 {
+        break;
+      }
+      hasher.update(&buf[..n]);
+    }
+    return Ok(hex::encode(hasher.finalize()));
+  }
+
+  let mut response = reqwest::Client::new()
+    .get(url)
+    .send()
+    .await
+    .map_err(|e| failed(e.to_string()))?;
+  if !response.status().is_success() {
+    return Err(failed(format!("HTTP {}", response.status())));
+  }
+  while let Some(chunk) = response.chunk().await.map_err(|e| failed(e.to_string()))? {
+    hasher.update(&chunk);
+  }
+  Ok(hex::encode(hasher.finalize()))
+}
+
+/// Answers of eval-time IO helpers, kept in the Lua VM's app data.
+#[derive(Debug, Default)]
+pub struct EvalIo {
+  /// Answers recorded by the last evaluation.
+  locked: BTreeMap<String, JsonValue>,
+  /// Answers this evaluation used, recorded or fetched.
+  used: BTreeMap<String, JsonValue>,
+}
+
+impl EvalIo {
+  /// Answer `requests`, running the ones without a recorded answer
+  /// concurrently. Answers come back in the order of `requests`.
+  pub fn answer(&mut self, requests: Vec<IoRequest>) -> Vec<Result<JsonValue, String>> {
+    let mut answers: Vec<Option<Result<JsonValue, String>>> = vec![None; requests.len()];
+    let mut missing = Vec::new();
+    for (i, request) in requests.into_iter().enumerate() {
+      let key = request.key();
+      match self.used.get(&key).or_else(|| self.locked.get(&key)) {
+        Some(answer) => {
+          debug!(key = %key, "using recorded eval-time IO answer");
+          self.used.insert(key, answer.clone());
+          answers[i] = Some(Ok(answer.clone()));
+        }
+        None => missing.push((i, request)),
+      }
+    }
+
+    if !missing.is_empty() {
+      info!(count = missing.len(), "running eval-time IO");
+      for (i, request, result) in run_all(missing) {
+        if let Ok(answer) = &result {
+          self.used.insert(request.key(), answer.clone());
+        }
+        answers[i] = Some(result);
+      }
+    }
+    answers
+      .into_iter()
+      .map(|answer| answer.unwrap_or_else(|| Err("eval-time IO request was not answered".to_string())))
+      .collect()
+  }
+}
+
+/// Run `requests` concurrently on a runtime of their own.
+///
+/// Evaluation is synchronous, sometimes from inside an async caller, so the
+/// runtime gets its own thread (like input downloads do). If the runtime
+/// can't start or a task panics, every request fails with that error, which
+/// the helpers raise in Lua.
+fn run_all(requests: Vec<(usize, IoRequest)>) -> Vec<(usize, IoRequest, Result<JsonValue, String>)> {
+  let fallback = requests.clone();
+  let joined = std::thread::scope(|scope| {
+    scope
+      .spawn(|| {
+        let rt = tokio::runtime::Builder::new_current_thread()
+          .enable_all()
+          .build()
+          .map_err(|e| e.to_string())?;
+        rt.block_on(async {
+          let mut tasks = JoinSet::new();
+          for (i, request) in requests {
+            tasks.spawn(async move { (i, request.clone(), request.run().await) });
+          }
+          let mut done = Vec::new();
+          while let Some(joined) = tasks.join_next().await {
+            done.push(joined.map_err(|e| format!("eval-time IO task failed: {}", e))?);
+          }
+          Ok::<_, String>(done)
+        })
+      })
+      .join()
+      .unwrap_or_else(|_| Err("eval-time IO thread panicked".to_string()))
+  });
+  joined.unwrap_or_else(|message| {
+    fallback
+      .into_iter()
+      .map(|(i, request)| (i, request, Err(message.clone())))
+      .collect()
+  })
+}
+
+/// Load the answers recorded in the lock file next to a config into `lua`.
+pub fn load_answers(lua: &Lua, config_dir: &Path) {
+  let locked = match LockFile::load(&config_dir.join(LOCK_FILENAME)) {
+    Ok(lock) => lock.map(|lock| lock.eval_cache().clone()).unwrap_or_default(),
+    Err(e) => {
+      warn!(error = %e, "cannot read recorded eval-time IO answers");
+      BTreeMap::new()
+    }
+  };
+  lua.set_app_data(EvalIo {
+    locked,
+    used: BTreeMap::new(),
+  });
+}
+
+/// Record the answers the evaluation in `lua` used in the lock file next to
+/// its config, if they changed.
+pub fn save_answers(lua: &Lua, config_dir: &Path) -> Result<(), crate::inputs::lock::LockError> {
+  let Some(io) = lua.app_data_ref::<EvalIo>() else {
+    return Ok(());
+  };
+  if io.used == io.locked {
+    return Ok(());
+  }
+  let lock_path = config_dir.join(LOCK_FILENAME);
+  let mut lock = LockFile::load(&lock_path)?.unwrap_or_default();
+  if lock.set_eval_cache(io.used.clone()) {
+    info!(path = %lock_path.display(), answers = io.used.len(), "recording eval-time IO answers");
+    lock.save(&lock_path)?;
+  }
+  Ok(())
+}
+
+/// Lua glue of one helper: yields the request to `sys.io.all` when called
+/// from one of its tasks, asks the bridge directly otherwise.
+const HELPER_LUA: &str = r#"
+local kind, request, tasks = ...
+return function(arg)
+  if type(arg) ~= "string" then
+    error("sys.io." .. kind .. ": expected a string, got " .. type(arg), 2)
+  end
+  if tasks[coroutine.running()] then
+    local ok, value = coroutine.yield(tasks, kind, arg)
+    if not ok then
+      error(value, 2)
+    end
+    return value
+  end
+  return request(kind, arg)
+end
+"#;
+
+/// Create the `sys.io` table.
+pub fn create_io_helpers(lua: &Lua) -> LuaResult<LuaTable> {
+  let io = lua.create_table()?;
+
+  // Coroutines run by sys.io.all, weakly keyed so finished ones are collected
+  let tasks = lua.create_table()?;
+  let weak_keys = lua.create_table()?;
+  weak_keys.set("__mode", "k")?;
+  tasks.set_metatable(Some(weak_keys))?;
+
+  let request = lua.create_function(|lua, (kind, arg): (String, String)| {
+    let request = IoRequest::parse(&kind, arg)?;
+    let answer = with_eval_io(lua, |io| io.answer(vec![request]))?
+      .pop()
+      .ok_or_else(|| LuaError::external("sys.io: request was not answered"))?;
+    json_to_lua_value(lua, &answer.map_err(LuaError::external)?)
+  })?;
+  for kind in IO_HELPERS {
+    let helper: LuaFunction = lua
+      .load(HELPER_LUA)
+      .set_name("=sys.io")
+      .call((*kind, request.clone(), tasks.clone()))?;
+    io.set(*kind, helper)?;
+  }
+
+  // sys.io.all(tasks) - Run functions as concurrent tasks, returning their results in order
+  let all = lua.create_function(move |lua, fns: Vec<LuaFunction>| run_tasks(lua, &tasks, fns))?;
+  io.set("all", all)?;
+
+  Ok(io)
+}
+
+/// Run `fns` as coroutines, answering the requests they yield together.
+fn run_tasks(lua: &Lua, tasks: &LuaTable, fns: Vec<LuaFunction>) -> LuaResult<LuaTable> {
+  let mut threads = Vec::with_capacity(fns.len());
+  for f in fns {
+    let thread = lua.create_thread(f)?;
+    tasks.raw_set(thread.clone(), true)?;
+    threads.push(thread);
+  }
+
+  let results = lua.create_table()?;
+  let mut resume: Vec<Option<LuaMultiValue>> = threads.iter().map(|_| Some(LuaMultiValue::new())).collect();
+  loop {
+    let mut pending = Vec::new();
+    for (i, thread) in threads.iter().enumerate() {
+      let Some(args) = resume[i].take() else {
+        continue;
+      };
+      let values: LuaMultiValue = thread.resume(args)?;
+      if thread.status() != LuaThreadStatus::Resumable {
+        results.raw_set(i + 1, values.into_iter().next().unwrap_or(LuaNil))?;
+        continue;
+      }
+      let mut values = values.into_iter();
+      match (values.next(), values.next(), values.next()) {
+        (Some(LuaValue::Table(marker)), Some(LuaValue::String(kind)), Some(LuaValue::String(arg)))
+          if marker.to_pointer() == tasks.to_pointer() =>
+        {
+          pending.push((i, IoRequest::parse(&kind.to_str()?, arg.to_str()?.to_string())?));
+        }
+        _ => {
+          return Err(LuaError::external(format!(
+            "sys.io.all: task {} yielded without calling a sys.io helper",
+            i + 1
+          )));
+        }
+      }
+    }
+    if pending.is_empty() {
+      return Ok(results);
+    }
+
+    let (indexes, requests): (Vec<usize>, Vec<IoRequest>) = pending.into_iter().unzip();
+    let answers = with_eval_io(lua, |io| io.answer(requests))?;
+    for (i, answer) in indexes.into_iter().zip(answers) {
+      let args = match answer {
+        Ok(value) => (true, json_to_lua_value(lua, &value)?).into_lua_multi(lua)?,
+        Err(message) => (false, message).into_lua_multi(lua)?,
+      };
+      resume[i] = Some(args);
+    }
+  }
+}
+
+/// Run `f` on the VM's [`EvalIo`], creating an empty one if evaluation
+/// didn't load any answers.
+fn with_eval_io<T>(lua: &Lua, f: impl FnOnce(&mut EvalIo) -> T) -> LuaResult<T> {
+  if lua.app_data_ref::<EvalIo>().is_none() {
+    lua.set_app_data(EvalIo::default());
+  }
+  let mut io = lua
+    .app_data_mut::<EvalIo>()
+    .ok_or_else(|| LuaError::external("eval-time IO state is unavailable"))?;
+  Ok(f(&mut io))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+  use tempfile::TempDir;
+
+  use crate::manifest::Manifest;
+
+  fn lua_with_globals() -> LuaResult<Lua> {
+    let lua = crate::lua::runtime::create_lua(false)?;
+    crate::lua::globals::register_globals(&lua, Rc::new(RefCell::new(Manifest::default())))?;
+    Ok(lua)
+  }
+
+  #[test]
+  fn prefetch_hashes_local_files() -> LuaResult<()> {
+    let temp = TempDir::new().unwrap();
+    let file = temp.path().join("a.txt");
+    std::fs::write(&file, "hello").unwrap();
+
+    let lua = lua_with_globals()?;
+    let hash: String = lua
+      .load(format!("return sys.io.prefetch({:?})", file.to_str().unwrap()))
+      .eval()?;
+    assert_eq!(hash, "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+
+    let err = lua
+      .load(r#"return sys.io.prefetch("/nonexistent/file")"#)
+      .eval::<String>();
+    assert!(err.unwrap_err().to_string().contains("cannot prefetch"));
+    Ok(())
+  }
+
+  #[test]
+  fn all_runs_tasks_and_returns_results_in_order() -> LuaResult<()> {
+    let temp = TempDir::new().unwrap();
+    let a = temp.path().join("a.txt");
+    let b = temp.path().join("b.txt");
+    std::fs::write(&a, "hello").unwrap();
+    std::fs::write(&b, "world").unwrap();
+
+    let lua = lua_with_globals()?;
+    lua.globals().set("a", a.to_str().unwrap())?;
+    lua.globals().set("b", b.to_str().unwrap())?;
+    let results: LuaTable = lua
+      .load(
+        r#"
+        return sys.io.all({
+          function() return sys.io.prefetch(a) end,
+          function() return sys.io.prefetch(a) .. sys.io.prefetch(b) end,
+          function() return "no io" end,
+        })
+        "#,
+      )
+      .eval()?;
+    let first: String = results.get(1)?;
+    let second: String = results.get(2)?;
+    assert_eq!(second.len(), 128);
+    assert!(second.starts_with(&first));
+    assert_eq!(results.get::<String>(3)?, "no io");
+
+    let err = lua
+      .load(r#"return sys.io.all({ function() return sys.io.prefetch("/nonexistent/file") end })"#)
+      .eval::<LuaTable>()
+      .unwrap_err();
+    assert!(err.to_string().contains("cannot prefetch"), "{}", err);
+
+    let err = lua
+      .load(r#"return sys.io.all({ function() coroutine.yield(1) end })"#)
+      .eval::<LuaTable>()
+      .unwrap_err();
+    assert!(err.to_string().contains("yielded without calling"), "{}", err);
+    Ok(())
+  }
+
+  #[test]
+  fn recorded_answers_are_reused_and_saved() -> LuaResult<()> {
+    let temp = TempDir::new().unwrap();
+    let file = temp.path().join("a.txt");
+    std::fs::write(&file, "hello").unwrap();
+    let script = format!("return sys.io.prefetch({:?})", file.to_str().unwrap());
+
+    let lua = lua_with_globals()?;
+    load_answers(&lua, temp.path());
+    let first: String = lua.load(script.as_str()).eval()?;
+    save_answers(&lua, temp.path()).unwrap();
+
+    let lock = LockFile::load(&temp.path().join(LOCK_FILENAME)).unwrap().unwrap();
+    let key = format!("prefetch:{}", file.to_str().unwrap());
+    assert_eq!(lock.eval_cache().get(&key), Some(&JsonValue::String(first.clone())));
+
+    // The recorded answer wins over the changed file
+    std::fs::write(&file, "changed").unwrap();
+    let lua = lua_with_globals()?;
+    load_answers(&lua, temp.path());
+    assert_eq!(lua.load(script.as_str()).eval::<String>()?, first);
+    Ok(())
+  }
+}
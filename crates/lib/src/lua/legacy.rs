@@ -0,0 +1,187 @@
+//! Compatibility shim for configs written against the old `derive{}` and
+//! `activate{}` globals.
+//!
+//! Both globals translate their spec and call `sys.build` or `sys.bind`, so
+//! the result is an ordinary build or bind. Each call site logs one
+//! deprecation warning pointing at `sys migrate-config`, which rewrites the
+//! calls in place (see [`super::migrate`]). Configs declaring Lua API version
+//! 2 or later don't have them (see [`super::api`]).
+//!
+//! | Legacy field          | Becomes                                          |
+//! | --------------------- | ------------------------------------------------ |
+//! | `name`                | `id`                                             |
+//! | `version`             | appended to the id: `<name>-<version>`           |
+//! | `opts`                | `inputs`                                         |
+//! | `config`              | `create`                                         |
+//! | `destroy` (activate)  | `destroy`, doing nothing when missing            |
+//!
+//! Other fields are passed through unchanged, so a legacy spec can already
+//! use `sys.build`/`sys.bind` fields such as `check` or `replace`.
+
+use mlua::prelude::*;
+
+use super::api::warn_deprecated;
+
+/// Renamed fields shared by both legacy globals.
+const RENAMED: &[(&str, &str)] = &[("name", "id"), ("opts", "inputs"), ("config", "create")];
+
+/// Register the legacy `derive` and `activate` globals. Requires the `sys`
+/// table to be registered.
+pub fn register_legacy_globals(lua: &Lua) -> LuaResult<()> {
+  let sys: LuaTable = lua.globals().get("sys")?;
+
+  let build_fn: LuaFunction = sys.get("build")?;
+  let derive_fn = lua.create_function(move |lua, spec: LuaTable| {
+    warn_deprecated(lua, "derive")?;
+    build_fn.call::<LuaValue>(translate(lua, "derive", &spec)?)
+  })?;
+  lua.globals().set("derive", derive_fn)?;
+
+  let bind_fn: LuaFunction = sys.get("bind")?;
+  let activate_fn = lua.create_function(move |lua, spec: LuaTable| {
+    warn_deprecated(lua, "activate")?;
+    let translated = translate(lua, "activate", &spec)?;
+    if translated.get::<LuaValue>("destroy")?.is_nil() {
+      translated.set("destroy", lua.create_function(|_, _: LuaMultiValue| Ok(()))?)?;
+    }
+    bind_fn.call::<LuaValue>(translated)
+  })?;
+  lua.globals().set("activate", activate_fn)?;
+
+  Ok(())
+}
+
+/// Copy a legacy spec with its fields renamed.
+fn translate(lua: &Lua, function: &str, spec: &LuaTable) -> LuaResult<LuaTable> {
+  let translated = lua.create_table()?;
+  for pair in spec.pairs::<LuaValue, LuaValue>() {
+    let (key, value) = pair?;
+    translated.set(key, value)?;
+  }
+
+  for (old, new) in RENAMED {
+    let value: LuaValue = translated.get(*old)?;
+    if value.is_nil() {
+      continue;
+    }
+    if !translated.get::<LuaValue>(*new)?.is_nil() {
+      return Err(LuaError::external(format!(
+        "{}{{}} spec sets both `{}` and `{}`; `{}` is the legacy name of `{}`",
+        function, old, new, old, new
+      )));
+    }
+    translated.set(*new, value)?;
+    translated.set(*old, LuaNil)?;
+  }
+
+  if let Some(version) = translated.get::<Option<String>>("version")? {
+    let id: String = translated
+      .get::<Option<String>>("id")?
+      .ok_or_else(|| LuaError::external(format!("{}{{}} spec with a `version` requires a `name`", function)))?;
+    translated.set("id", format!("{}-{}", id, version))?;
+    translated.set("version", LuaNil)?;
+  }
+
+  Ok(translated)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  use super::*;
+  use crate::manifest::Manifest;
+
+  fn setup() -> LuaResult<(Lua, Rc<RefCell<Manifest>>)> {
+    let manifest = Rc::new(RefCell::new(Manifest::default()));
+    let lua = crate::lua::runtime::create_runtime(manifest.clone(), false)?;
+    Ok((lua, manifest))
+  }
+
+  #[test]
+  fn derive_declares_the_same_build_as_sys_build() -> LuaResult<()> {
+    let (lua, _) = setup()?;
+    let legacy: String = lua
+      .load(
+        r#"
+        return derive({
+          name = "hello",
+          version = "1.0",
+          opts = { greeting = "hi" },
+          config = function(opts, ctx)
+            ctx:exec({ bin = "echo " .. opts.greeting })
+            return { out = ctx.out }
+          end,
+        }).hash
+      "#,
+      )
+      .eval()?;
+
+    let (lua, manifest) = setup()?;
+    let current: String = lua
+      .load(
+        r#"
+        return sys.build({
+          id = "hello-1.0",
+          inputs = { greeting = "hi" },
+          create = function(inputs, ctx)
+            ctx:exec({ bin = "echo " .. inputs.greeting })
+            return { out = ctx.out }
+          end,
+        }).hash
+      "#,
+      )
+      .eval()?;
+
+    assert_eq!(legacy, current);
+    let manifest = manifest.borrow();
+    let build = manifest.builds.values().next().unwrap();
+    assert_eq!(build.id.as_deref(), Some("hello-1.0"));
+    Ok(())
+  }
+
+  #[test]
+  fn activate_declares_a_bind_with_a_default_destroy() -> LuaResult<()> {
+    let (lua, manifest) = setup()?;
+    lua
+      .load(
+        r#"
+        activate({
+          name = "greeting",
+          config = function(opts, ctx)
+            ctx:exec({ bin = "echo hi" })
+          end,
+        })
+      "#,
+      )
+      .exec()?;
+
+    let manifest = manifest.borrow();
+    let bind = manifest.bindings.values().next().unwrap();
+    assert_eq!(bind.id.as_deref(), Some("greeting"));
+    assert_eq!(bind.create_actions.len(), 1);
+    assert!(bind.destroy_actions.is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn conflicting_fields_fail() -> LuaResult<()> {
+    let (lua, _) = setup()?;
+    let err = lua
+      .load(
+        r#"
+        derive({
+          name = "x",
+          opts = {},
+          inputs = {},
+          config = function(opts, ctx) return { out = ctx.out } end,
+        })
+      "#,
+      )
+      .exec()
+      .unwrap_err();
+    assert!(err.to_string().contains("sets both `opts` and `inputs`"), "{}", err);
+    Ok(())
+  }
+}
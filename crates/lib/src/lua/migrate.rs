@@ -0,0 +1,365 @@
+//! Rewriting legacy `derive{}`/`activate{}` calls into `sys.build{}`/`sys.bind{}`.
+//!
+//! `sys migrate-config` runs [`migrate_config`] over the Lua files of a config
+//! directory. Each call whose spec is a table constructor is rewritten the
+//! way the [compatibility shim](super::legacy) translates it at runtime:
+//!
+//! ```lua
+//! derive { name = "rg", version = "14.1", opts = { ... }, config = function(opts, ctx) ... end }
+//! -- becomes
+//! sys.build { id = "rg-14.1", inputs = { ... }, create = function(opts, ctx) ... end }
+//! ```
+//!
+//! `activate` calls without a `destroy` get an empty one, as the shim gives
+//! them. Calls that can't be rewritten faithfully are left alone and
+//! reported: specs built elsewhere (`derive(spec)`), a `version` or `name`
+//! that isn't a plain string literal, and specs that set both a legacy field
+//! and its new name. Everything outside the rewritten calls, including
+//! comments and formatting, is kept.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use super::syntax::{Call, Edit, Field, Lexer, append_field, apply_edits, is_ident_start};
+
+/// Legacy globals and the functions that replace them.
+const LEGACY_FUNCTIONS: &[(&str, &str)] = &[("derive", "sys.build"), ("activate", "sys.bind")];
+
+/// Legacy field names and their replacements.
+const RENAMED: &[(&str, &str)] = &[("name", "id"), ("opts", "inputs"), ("config", "create")];
+
+/// A legacy call that was left alone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkippedCall {
+  /// 1-based line of the call.
+  pub line: usize,
+  pub function: String,
+  pub reason: String,
+}
+
+/// The result of migrating one Lua source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigratedSource {
+  pub source: String,
+  /// 1-based lines of the rewritten calls.
+  pub migrated: Vec<usize>,
+  pub skipped: Vec<SkippedCall>,
+}
+
+/// The result of migrating one file of a config.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigratedFile {
+  pub path: PathBuf,
+  pub migrated: Vec<usize>,
+  pub skipped: Vec<SkippedCall>,
+}
+
+/// Rewrite the legacy calls in the `.lua` files under `root` (or in `root`
+/// itself when it's a file). Hidden directories are skipped. With `dry_run`
+/// nothing is written.
+///
+/// Only files with legacy calls are returned.
+pub fn migrate_config(root: &Path, dry_run: bool) -> io::Result<Vec<MigratedFile>> {
+  let mut files = Vec::new();
+  let entries = WalkDir::new(root)
+    .sort_by_file_name()
+    .into_iter()
+    .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'));
+  for entry in entries {
+    let entry = entry.map_err(io::Error::other)?;
+    if !entry.file_type().is_file() || entry.path().extension().is_none_or(|ext| ext != "lua") {
+      continue;
+    }
+
+    let source = std::fs::read_to_string(entry.path())?;
+    let result = migrate_source(&source);
+    if result.migrated.is_empty() && result.skipped.is_empty() {
+      continue;
+    }
+    if !dry_run && !result.migrated.is_empty() {
+      std::fs::write(entry.path(), &result.source)?;
+    }
+    files.push(MigratedFile {
+      path: entry.into_path(),
+      migrated: result.migrated,
+      skipped: result.skipped,
+    });
+  }
+  Ok(files)
+}
+
+/// Rewrite the legacy calls in one Lua source.
+pub fn migrate_source(source: &str) -> MigratedSource {
+  let lexer = Lexer::new(source);
+  let mut edits: Vec<Edit> = Vec::new();
+  let mut migrated = Vec::new();
+  let mut skipped = Vec::new();
+
+  // Whether the previous token makes a name something other than a global
+  // being read: a field (`M.derive`), a method or a declaration
+  let mut declared = false;
+  let mut pos = 0;
+  while pos < lexer.src.len() {
+    if let Some(end) = lexer.trivia_end(pos) {
+      pos = end;
+      continue;
+    }
+    if let Some(end) = lexer.string_end(pos) {
+      declared = false;
+      pos = end;
+      continue;
+    }
+    let byte = lexer.src[pos];
+    if !is_ident_start(byte) {
+      // `..` concatenates, a single `.` or `:` indexes
+      let dots = lexer.src[pos..].iter().take_while(|b| **b == b'.').count();
+      declared = (byte == b'.' && dots == 1) || byte == b':';
+      pos += dots.max(1);
+      continue;
+    }
+
+    let end = lexer.ident_end(pos);
+    let ident = &source[pos..end];
+    let skip = declared;
+    declared = matches!(ident, "function" | "local");
+    let Some((_, replacement)) = LEGACY_FUNCTIONS.iter().find(|(name, _)| *name == ident) else {
+      pos = end;
+      continue;
+    };
+    if skip {
+      pos = end;
+      continue;
+    }
+
+    let line = source[..pos].matches('\n').count() + 1;
+    match lexer.call_table(end) {
+      Call::NotACall => {}
+      Call::Other => skipped.push(SkippedCall {
+        line,
+        function: ident.to_string(),
+        reason: "the spec isn't a table constructor".to_string(),
+      }),
+      Call::Table { open, close } => match rewrite_spec(&lexer, source, ident, open, close) {
+        Ok(mut spec_edits) => {
+          edits.push(Edit {
+            start: pos,
+            end,
+            text: replacement.to_string(),
+          });
+          edits.append(&mut spec_edits);
+          migrated.push(line);
+        }
+        Err(reason) => skipped.push(SkippedCall {
+          line,
+          function: ident.to_string(),
+          reason,
+        }),
+      },
+    }
+    pos = end;
+  }
+
+  // Edits of nested calls never overlap
+  MigratedSource {
+    source: apply_edits(source, edits),
+    migrated,
+    skipped,
+  }
+}
+
+/// The edits that turn the legacy spec between `open` and `close` (the
+/// positions of its braces) into a current one.
+fn rewrite_spec(lexer: &Lexer, source: &str, function: &str, open: usize, close: usize) -> Result<Vec<Edit>, String> {
+  let fields = lexer.fields(open, close);
+  let field = |key: &str| fields.iter().find(|field| field.key == key);
+  let mut edits = Vec::new();
+
+  for (old, new) in RENAMED {
+    let Some(legacy) = field(*old) else {
+      continue;
+    };
+    if field(*new).is_some() {
+      return Err(format!("the spec sets both `{}` and `{}`", old, new));
+    }
+    edits.push(Edit {
+      start: legacy.key_start,
+      end: legacy.key_start + old.len(),
+      text: new.to_string(),
+    });
+  }
+
+  if let Some(version) = field("version") {
+    let name = field("name").ok_or("the spec has a `version` but no `name`")?;
+    let (Some((quote, name_text)), Some((_, version_text))) = (
+      plain_string(&source[name.value_start..name.value_end]),
+      plain_string(&source[version.value_start..version.value_end]),
+    ) else {
+      return Err("`name` and `version` must be plain string literals to be combined into the id".to_string());
+    };
+    edits.push(Edit {
+      start: name.value_start,
+      end: name.value_end,
+      text: format!("{quote}{name_text}-{version_text}{quote}"),
+    });
+    let (start, end) = removal_range(source, version.key_start, version.end);
+    edits.push(Edit {
+      start,
+      end,
+      text: String::new(),
+    });
+  }
+
+  if function == "activate" && field("destroy").is_none() {
+    // After the last field that stays, so it can't touch a removed `version`
+    let last = fields.iter().rev().find(|field| field.key != "version");
+    edits.push(destroy_insertion(source, open, close, last));
+  }
+
+  Ok(edits)
+}
+
+/// The contents of a string literal without escapes or long brackets, and
+/// its quote character.
+fn plain_string(value: &str) -> Option<(char, &str)> {
+  let value = value.trim();
+  let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+  let inner = value.strip_prefix(quote)?.strip_suffix(quote)?;
+  (!inner.contains(quote) && !inner.contains('\\') && !inner.contains('\n')).then_some((quote, inner))
+}
+
+/// The range to delete for a field: its whole line when nothing else is on
+/// it, else the field and the spaces after it.
+fn removal_range(source: &str, start: usize, end: usize) -> (usize, usize) {
+  let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+  let line_end = source[end..].find('\n').map_or(source.len(), |i| end + i + 1);
+  let before = &source[line_start..start];
+  let after = &source[end..line_end];
+  if before.trim().is_empty() && after.trim().is_empty() {
+    return (line_start, line_end);
+  }
+  let trailing = source[end..].len() - source[end..].trim_start_matches([' ', '\t']).len();
+  (start, end + trailing)
+}
+
+/// An empty `destroy` added after the last field of the spec.
+fn destroy_insertion(source: &str, open: usize, close: usize, last: Option<&Field>) -> Edit {
+  const DESTROY: &str = "destroy = function(outputs, ctx) end";
+  let Some(last) = last else {
+    let space = if source[..close].ends_with(char::is_whitespace) {
+      ""
+    } else {
+      " "
+    };
+    return Edit {
+      start: close,
+      end: close,
+      text: format!("{}{} ", space, DESTROY),
+    };
+  };
+  append_field(source, open, close, last, DESTROY)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rewrites_derive_and_activate() {
+    let source = r#"local rg = derive {
+  name = "rg",
+  version = "14.1",
+  opts = { url = "https://example.com/rg.tar.gz" },
+  config = function(opts, ctx)
+    return { out = ctx.out }
+  end,
+}
+
+activate({
+  opts = { rg = rg },
+  config = function(opts, ctx)
+    ctx:exec({ bin = "ln -s " .. opts.rg.outputs.out })
+  end
+})
+"#;
+    let result = migrate_source(source);
+    assert_eq!(result.migrated, vec![1, 10]);
+    assert!(result.skipped.is_empty());
+    assert_eq!(
+      result.source,
+      r#"local rg = sys.build {
+  id = "rg-14.1",
+  inputs = { url = "https://example.com/rg.tar.gz" },
+  create = function(opts, ctx)
+    return { out = ctx.out }
+  end,
+}
+
+sys.bind({
+  inputs = { rg = rg },
+  create = function(opts, ctx)
+    ctx:exec({ bin = "ln -s " .. opts.rg.outputs.out })
+  end,
+  destroy = function(outputs, ctx) end,
+})
+"#
+    );
+  }
+
+  #[test]
+  fn leaves_other_code_alone() {
+    let source = r#"-- derive { name = "commented" }
+local s = "derive { name = 'in a string' }"
+local function derive(spec) return spec end
+local x = M.activate { opts = {} }
+"#;
+    let result = migrate_source(source);
+    assert_eq!(result.source, source);
+    assert!(result.migrated.is_empty());
+    assert!(result.skipped.is_empty());
+  }
+
+  #[test]
+  fn reports_calls_it_cannot_rewrite() {
+    let source = r#"derive(spec)
+derive { name = name, version = "1", config = f }
+activate { opts = {}, inputs = {}, config = f, destroy = g }
+"#;
+    let result = migrate_source(source);
+    assert_eq!(result.source, source);
+    let lines: Vec<_> = result.skipped.iter().map(|call| call.line).collect();
+    assert_eq!(lines, vec![1, 2, 3]);
+    assert!(result.skipped[2].reason.contains("both `opts` and `inputs`"));
+  }
+
+  #[test]
+  fn single_line_specs_stay_on_one_line() {
+    let result = migrate_source("activate { name = 'x', config = f }\n");
+    assert_eq!(
+      result.source,
+      "sys.bind { id = 'x', create = f, destroy = function(outputs, ctx) end }\n"
+    );
+  }
+
+  #[test]
+  fn migrate_config_writes_only_changed_files() {
+    let dir = tempfile::TempDir::new().unwrap();
+    std::fs::write(dir.path().join("init.lua"), "derive { name = 'a', config = f }\n").unwrap();
+    std::fs::write(dir.path().join("other.lua"), "return {}\n").unwrap();
+
+    let files = migrate_config(dir.path(), true).unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(
+      std::fs::read_to_string(dir.path().join("init.lua")).unwrap(),
+      "derive { name = 'a', config = f }\n"
+    );
+
+    migrate_config(dir.path(), false).unwrap();
+    assert_eq!(
+      std::fs::read_to_string(dir.path().join("init.lua")).unwrap(),
+      "sys.build { id = 'a', create = f }\n"
+    );
+  }
+}
@@ -6,12 +6,29 @@
 //!
 //! # Submodules
 //!
+//! - [`api`] - Lua API versions (`sys.api`) and deprecated helpers
+//! - [`diagnostics`] - Error reports naming the spec function that failed
+//! - [`edit`] - Formatting-preserving edits of the entrypoint (`sys add-input`)
 //! - [`entrypoint`] - Configuration file loading and evaluation
 //! - [`globals`] - Global Lua functions (`build()`, `bind()`, `input()`, etc.)
 //! - [`helpers`] - Lua helper modules exposed to user scripts
+//! - [`io`] - Eval-time IO helpers (`sys.io`) run concurrently on a tokio runtime
+//! - [`legacy`] - The deprecated `derive{}`/`activate{}` globals
+//! - [`migrate`] - Rewriting legacy calls into `sys.build{}`/`sys.bind{}`
 //! - [`runtime`] - Low-level Lua VM management
+//! - [`sandbox`] - Restricted environment for untrusted input code
+//! - [`template`] - Templates with loops and conditionals (`sys.template`)
 
+pub mod api;
+pub mod diagnostics;
+pub mod edit;
 pub mod entrypoint;
 pub mod globals;
 pub mod helpers;
+pub mod io;
+pub mod legacy;
+pub mod migrate;
 pub mod runtime;
+pub mod sandbox;
+mod syntax;
+pub mod template;
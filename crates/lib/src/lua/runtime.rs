@@ -1,11 +1,11 @@
 use std::cell::RefCell;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use mlua::StdLib;
 use mlua::prelude::*;
 
-use crate::lua::globals;
+use crate::lua::{globals, helpers, legacy};
 use crate::manifest::Manifest;
 
 fn stdlib_for_mode(impure: bool) -> StdLib {
@@ -17,6 +17,36 @@ pub fn create_lua(impure: bool) -> LuaResult<Lua> {
   Lua::new_with(stdlib_for_mode(impure), LuaOptions::default())
 }
 
+/// `file:line` of the Lua code calling the current Rust function, if known.
+pub fn caller_location(lua: &Lua) -> Option<String> {
+  lua
+    .inspect_stack(1, |debug| {
+      let source = debug.source();
+      // File chunks are named `@<path>`; short_src truncates long paths
+      let file = match source.source.as_deref().and_then(|chunk| chunk.strip_prefix('@')) {
+        Some(path) => path.to_string(),
+        None => source.short_src.as_deref()?.to_string(),
+      };
+      Some(match debug.current_line() {
+        Some(line) => format!("{file}:{line}"),
+        None => file,
+      })
+    })
+    .flatten()
+}
+
+/// Directory of the Lua file calling the current Rust function, if it was
+/// loaded from a file.
+pub fn caller_dir(lua: &Lua) -> Option<PathBuf> {
+  lua
+    .inspect_stack(1, |debug| {
+      let source = debug.source();
+      let file = source.source.as_deref()?.strip_prefix('@')?;
+      Path::new(file).parent().map(Path::to_path_buf)
+    })
+    .flatten()
+}
+
 pub fn create_runtime(manifest: Rc<RefCell<Manifest>>, impure: bool) -> LuaResult<Lua> {
   let lua = create_lua(impure)?;
   let package_path = lua.globals().get::<LuaTable>("package")?.get::<String>("path")?;
@@ -28,6 +58,13 @@ pub fn create_runtime(manifest: Rc<RefCell<Manifest>>, impure: bool) -> LuaResul
 
   // Register global tables (sys.platform, sys.os, sys.arch, sys.build, etc.)
   globals::register_globals(&lua, manifest)?;
+  legacy::register_legacy_globals(&lua)?;
+
+  // Read-only filesystem helpers, which sandboxed inputs can use instead of io
+  if impure {
+    let sys = lua.globals().get::<LuaTable>("sys")?;
+    sys.set("fs", helpers::fs::create_fs_helpers(&lua)?)?;
+  }
 
   Ok(lua)
 }
@@ -36,6 +73,11 @@ pub fn create_runtime(manifest: Rc<RefCell<Manifest>>, impure: bool) -> LuaResul
 /// Sets the `sys.dir` global to the directory of the loaded file.
 /// Returns the result of the file execution.
 pub fn load_file(lua: &Lua, path: &Path) -> LuaResult<LuaValue> {
+  load_file_in(lua, path, None)
+}
+
+/// Like [`load_file`], but runs the chunk with `env` as its globals when given.
+pub fn load_file_in(lua: &Lua, path: &Path, env: Option<&LuaTable>) -> LuaResult<LuaValue> {
   let canonical_path = dunce::canonicalize(path)
     .map_err(|e| LuaError::external(format!("cannot canonicalize '{}': {}", path.display(), e)))?;
   let content = std::fs::read_to_string(&canonical_path)
@@ -53,9 +95,10 @@ pub fn load_file(lua: &Lua, path: &Path) -> LuaResult<LuaValue> {
       .to_string(),
   )?;
 
-  let result = lua
-    .load(&content)
-    .set_name(format!("@{}", canonical_path.display()))
-    .eval::<LuaValue>()?;
+  let mut chunk = lua.load(&content).set_name(format!("@{}", canonical_path.display()));
+  if let Some(env) = env {
+    chunk = chunk.set_environment(env.clone());
+  }
+  let result = chunk.eval::<LuaValue>()?;
   Ok(result)
 }
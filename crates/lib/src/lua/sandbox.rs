@@ -0,0 +1,395 @@
+//! Sandboxed evaluation of untrusted input code.
+//!
+//! Third-party inputs run inside the same Lua VM as the root config. Unless an
+//! input is declared with `trusted = true` (or the policy is
+//! [`UntrustedInputs::Allow`]), its `init.lua` and every module loaded from its
+//! directory run against a restricted global environment:
+//!
+//! - `io`, `debug`, `package`, `dofile` and `loadfile` are hidden
+//! - `os` only keeps `clock`, `date`, `difftime` and `time`
+//! - `load` only accepts text chunks and defaults to the sandbox environment
+//! - `require` only loads the `string`, `table`, `math`, `utf8` and
+//!   `coroutine` libraries and Lua modules found under the config or an input;
+//!   the other modules already in `package.loaded` (`_G`, `io`, `os`, ...),
+//!   native (C) modules and modules from anywhere else on `package.path` (the
+//!   cwd's `lua/`, system Lua paths) are refused, since they would run with
+//!   the real globals
+//!
+//! Everything else, including `sys` (with its recorded `sys.io` lookups, and
+//! its read-only `sys.fs` helpers when available), is read through from the
+//! real globals. Tables read that way, the standard libraries and the modules
+//! of the config and trusted inputs are read-only proxies, so sandboxed code
+//! can't change `sys.build`, `string.format` or anything else trusted code
+//! uses. For the same reason `getmetatable` only returns the metatables of
+//! tables (not the metatable shared by all strings), and `setmetatable` and
+//! `rawset` refuse the proxies. Globals that sandboxed code assigns stay in
+//! the sandbox. Functions defined in the sandbox keep its environment wherever
+//! they are called from.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Policy for evaluating inputs that are not declared `trusted`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UntrustedInputs {
+  /// Run untrusted input code in the sandbox.
+  #[default]
+  Sandbox,
+  /// Run all input code with the same globals as the root config.
+  Allow,
+}
+
+impl UntrustedInputs {
+  /// Returns the lowercase string identifier ("sandbox" or "allow").
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::Sandbox => "sandbox",
+      Self::Allow => "allow",
+    }
+  }
+}
+
+impl fmt::Display for UntrustedInputs {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl FromStr for UntrustedInputs {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "sandbox" => Ok(Self::Sandbox),
+      "allow" => Ok(Self::Allow),
+      _ => Err(format!(
+        "unknown untrusted inputs policy '{}' (expected sandbox or allow)",
+        s
+      )),
+    }
+  }
+}
+
+/// Builds the sandbox environment and a `package.searchers` entry for modules
+/// under untrusted roots. Receives the normalized untrusted and trusted roots;
+/// returns the env and the searcher.
+const SANDBOX_LUA: &str = r#"
+local roots, trusted_roots = ...
+local G = _G
+local real_require, real_load, real_loadfile = G.require, G.load, G.loadfile
+local package = G.package
+
+local real_getmetatable, real_setmetatable, real_rawset = G.getmetatable, G.setmetatable, G.rawset
+
+local hidden = {
+  io = true, os = true, debug = true, package = true,
+  dofile = true, loadfile = true, load = true, require = true, _G = true,
+  getmetatable = true, setmetatable = true, rawset = true,
+}
+-- Every module loaded so far is a library of this VM rather than a Lua file
+-- module; only the harmless ones are served
+local safe_modules = { string = true, table = true, math = true, utf8 = true, coroutine = true }
+local hidden_modules = { _G = true, io = true, os = true, debug = true, package = true }
+for name in pairs(package.loaded) do
+  if not safe_modules[name] then hidden_modules[name] = true end
+end
+
+-- Read-only views of the tables shared with trusted code, one per table so
+-- that they compare equal
+local proxies = real_setmetatable({}, { __mode = "k" })
+local is_proxy = real_setmetatable({}, { __mode = "k" })
+
+local function shared(value)
+  if type(value) ~= "table" then return value end
+  local proxy = proxies[value]
+  if proxy then return proxy end
+  proxy = real_setmetatable({}, {
+    __index = function(_, key) return shared(value[key]) end,
+    __newindex = function(_, key)
+      error("cannot assign '" .. tostring(key) .. "': tables shared with the config are read-only to untrusted inputs", 2)
+    end,
+    __pairs = function()
+      return function(_, key)
+        local k, v = next(value, key)
+        return k, shared(v)
+      end, nil, nil
+    end,
+    __len = function() return #value end,
+    __metatable = false,
+  })
+  proxies[value] = proxy
+  is_proxy[proxy] = true
+  return proxy
+end
+
+local env = {}
+real_setmetatable(env, {
+  __index = function(_, key)
+    if hidden[key] then return nil end
+    return shared(G[key])
+  end,
+  __newindex = function(t, key, value)
+    rawset(t, key, value)
+  end,
+  __metatable = false,
+})
+
+if G.os then
+  rawset(env, "os", {
+    clock = G.os.clock,
+    date = G.os.date,
+    difftime = G.os.difftime,
+    time = G.os.time,
+  })
+end
+
+local function under(list, path)
+  path = path:gsub("\\", "/")
+  for _, root in ipairs(list) do
+    if path:sub(1, #root) == root then return true end
+  end
+  return false
+end
+
+rawset(env, "getmetatable", function(value)
+  if type(value) ~= "table" then return nil end
+  return real_getmetatable(value)
+end)
+
+rawset(env, "setmetatable", function(t, mt)
+  if is_proxy[t] then error("cannot change the metatable of a table shared with the config", 2) end
+  return real_setmetatable(t, mt)
+end)
+
+rawset(env, "rawset", function(t, key, value)
+  if is_proxy[t] then error("cannot assign '" .. tostring(key) .. "': tables shared with the config are read-only to untrusted inputs", 2) end
+  return real_rawset(t, key, value)
+end)
+
+rawset(env, "load", function(chunk, name, _, chunk_env)
+  if chunk_env == nil then chunk_env = env end
+  return real_load(chunk, name, "t", chunk_env)
+end)
+
+rawset(env, "require", function(name)
+  if type(name) ~= "string" or hidden_modules[name] then
+    error("module '" .. tostring(name) .. "' is not available to untrusted inputs", 2)
+  end
+  if safe_modules[name] then return shared(package.loaded[name]) end
+  local path = package.searchpath(name, package.path)
+  if not path then
+    error("module '" .. tostring(name) .. "' not found (native modules are not available to untrusted inputs)", 2)
+  end
+  if under(roots, path) then return real_require(name) end
+  if under(trusted_roots, path) then return shared(real_require(name)) end
+  -- Modules from anywhere else would run with the real globals
+  error("module '" .. tostring(name) .. "' is outside the config and its inputs and is not available to untrusted inputs", 2)
+end)
+
+rawset(env, "_G", env)
+
+local function untrusted(path)
+  return under(roots, path)
+end
+
+local function searcher(name)
+  local path = package.searchpath(name, package.path)
+  if not path or not untrusted(path) then return nil end
+  local loader, err = real_loadfile(path, "t", env)
+  if not loader then error(err, 0) end
+  return loader, path
+end
+
+return env, searcher
+"#;
+
+/// Create the sandbox environment for code under `untrusted_roots`.
+///
+/// Also installs a module searcher so that `require` loads modules found under
+/// those roots into the sandbox, whoever requires them. Sandboxed code may
+/// also require the modules under `trusted_roots` (the config and trusted
+/// inputs), but no other module on `package.path`. Returns the sandbox
+/// environment, to be used when loading untrusted `init.lua` files.
+pub fn create_sandbox(lua: &Lua, untrusted_roots: &[PathBuf], trusted_roots: &[PathBuf]) -> LuaResult<LuaTable> {
+  let normalize = |roots: &[PathBuf]| {
+    roots
+      .iter()
+      .map(|root| {
+        let root = root.to_string_lossy().replace("\\", "/");
+        if root.ends_with('/') {
+          root
+        } else {
+          format!("{}/", root)
+        }
+      })
+      .collect::<Vec<_>>()
+  };
+
+  let (env, searcher): (LuaTable, LuaFunction) = lua
+    .load(SANDBOX_LUA)
+    .set_name("=sandbox")
+    .call((normalize(untrusted_roots), normalize(trusted_roots)))?;
+
+  // Ahead of the default Lua file searcher, so untrusted modules never load
+  // with the real globals
+  let searchers: LuaTable = lua.globals().get::<LuaTable>("package")?.get("searchers")?;
+  let insert: LuaFunction = lua.globals().get::<LuaTable>("table")?.get("insert")?;
+  insert.call::<()>((searchers, 2, searcher))?;
+
+  Ok(env)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::lua::runtime;
+  use crate::manifest::Manifest;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+  use tempfile::TempDir;
+
+  fn impure_runtime() -> Lua {
+    runtime::create_runtime(Rc::new(RefCell::new(Manifest::default())), true).unwrap()
+  }
+
+  #[test]
+  fn policy_round_trips_through_strings() {
+    assert_eq!("sandbox".parse::<UntrustedInputs>(), Ok(UntrustedInputs::Sandbox));
+    assert_eq!("allow".parse::<UntrustedInputs>(), Ok(UntrustedInputs::Allow));
+    assert!("trust".parse::<UntrustedInputs>().is_err());
+    assert_eq!(UntrustedInputs::default().to_string(), "sandbox");
+  }
+
+  #[test]
+  fn sandbox_hides_io_and_process_access() {
+    let lua = impure_runtime();
+    let env = create_sandbox(&lua, &[], &[]).unwrap();
+    let eval = |code: &str| lua.load(code).set_environment(env.clone()).eval::<LuaValue>();
+
+    assert!(eval("return io").unwrap().is_nil());
+    assert!(eval("return dofile").unwrap().is_nil());
+    assert!(eval("return os.execute").unwrap().is_nil());
+    assert!(eval("return os.time() ~= nil").unwrap().as_boolean().unwrap());
+    assert!(eval("return _G.io").unwrap().is_nil());
+    assert!(eval("return require('io')").is_err());
+    assert!(eval("return require('_G')").is_err());
+    assert!(eval("return require('string').format('%d', 1)").is_ok());
+    assert!(eval("return load('return io')()").unwrap().is_nil());
+    assert!(eval("return sys.fs.exists('/')").unwrap().as_boolean().unwrap());
+
+    // Globals assigned in the sandbox stay there
+    eval("local_global = true; io = false; sys = nil").unwrap();
+    assert!(eval("return local_global").unwrap().as_boolean().unwrap());
+    assert!(lua.globals().get::<LuaValue>("local_global").unwrap().is_nil());
+    assert!(lua.globals().get::<LuaValue>("io").unwrap().is_table());
+    assert!(lua.globals().get::<LuaValue>("sys").unwrap().is_table());
+  }
+
+  #[test]
+  fn sandbox_cannot_change_tables_shared_with_trusted_code() {
+    let lua = impure_runtime();
+    let env = create_sandbox(&lua, &[], &[]).unwrap();
+    let eval = |code: &str| lua.load(code).set_environment(env.clone()).eval::<LuaValue>();
+
+    for code in [
+      "sys.build = nil",
+      "sys.fs.exists = nil",
+      "string.format = nil",
+      "require('table').insert = nil",
+      "rawset(sys, 'build', nil)",
+      "setmetatable(sys, {})",
+      "getmetatable('').__index.format = nil",
+    ] {
+      assert!(eval(code).is_err(), "sandbox ran {}", code);
+    }
+    assert!(eval("return getmetatable(sys)").unwrap().as_boolean() == Some(false));
+
+    // Reading still works, and so do metatables of the sandbox's own tables
+    assert!(
+      eval("return sys.build == sys.build and #table.pack(1, 2) == 2")
+        .unwrap()
+        .as_boolean()
+        .unwrap()
+    );
+    assert!(
+      eval("local n = 0 for _ in pairs(string) do n = n + 1 end return n > 0")
+        .unwrap()
+        .as_boolean()
+        .unwrap()
+    );
+    assert!(
+      eval("return setmetatable({}, { __index = { x = 1 } }).x == 1")
+        .unwrap()
+        .as_boolean()
+        .unwrap()
+    );
+
+    // Trusted code still sees the real tables
+    assert!(
+      lua
+        .load(
+          "return type(sys.build) == 'function' and type(sys.fs.exists) == 'function' and string.format('%d', 1) == '1'"
+        )
+        .eval::<bool>()
+        .unwrap()
+    );
+  }
+
+  #[test]
+  fn modules_under_untrusted_roots_load_sandboxed() {
+    let temp = TempDir::new().unwrap();
+    let untrusted = temp.path().join("untrusted");
+    let trusted = temp.path().join("trusted");
+    for (dir, name) in [(&untrusted, "evil"), (&trusted, "good")] {
+      std::fs::create_dir_all(dir.join("lua")).unwrap();
+      std::fs::write(dir.join("lua").join(format!("{}.lua", name)), "return io ~= nil").unwrap();
+    }
+
+    let lua = impure_runtime();
+    create_sandbox(&lua, std::slice::from_ref(&untrusted), std::slice::from_ref(&trusted)).unwrap();
+    let package: LuaTable = lua.globals().get("package").unwrap();
+    let path: String = package.get("path").unwrap();
+    let lua_dir = |dir: &PathBuf| dir.join("lua").to_string_lossy().replace("\\", "/");
+    package
+      .set(
+        "path",
+        format!("{}/?.lua;{}/?.lua;{}", lua_dir(&untrusted), lua_dir(&trusted), path),
+      )
+      .unwrap();
+
+    assert!(!lua.load("return require('evil')").eval::<bool>().unwrap());
+    assert!(lua.load("return require('good')").eval::<bool>().unwrap());
+  }
+
+  #[test]
+  fn modules_outside_the_config_and_inputs_are_refused() {
+    let temp = TempDir::new().unwrap();
+    let untrusted = temp.path().join("untrusted");
+    // Stands in for a system Lua path such as /usr/share/lua/5.4
+    let system = temp.path().join("share").join("lua");
+    std::fs::create_dir_all(&untrusted).unwrap();
+    std::fs::create_dir_all(system.join("pl")).unwrap();
+    std::fs::write(system.join("pl").join("file.lua"), "return { io = io }").unwrap();
+
+    let lua = impure_runtime();
+    let env = create_sandbox(&lua, std::slice::from_ref(&untrusted), &[]).unwrap();
+    let package: LuaTable = lua.globals().get("package").unwrap();
+    let path: String = package.get("path").unwrap();
+    let system_dir = system.to_string_lossy().replace("\\", "/");
+    package.set("path", format!("{}/?.lua;{}", system_dir, path)).unwrap();
+
+    let err = lua
+      .load("return require('pl.file')")
+      .set_environment(env)
+      .eval::<LuaValue>()
+      .unwrap_err();
+    assert!(err.to_string().contains("outside the config"), "{}", err);
+    // Trusted code still can
+    assert!(lua.load("return require('pl.file').io ~= nil").eval::<bool>().unwrap());
+  }
+}
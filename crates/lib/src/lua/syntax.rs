@@ -0,0 +1,266 @@
+//! Just enough Lua syntax to edit sources without disturbing them.
+//!
+//! [`Lexer`] finds calls, table constructors and their fields while skipping
+//! strings and comments; [`Edit`]s then replace byte ranges of the source, so
+//! everything they don't touch, comments and formatting included, is kept.
+//! Used by [`migrate`](super::migrate) and [`edit`](super::edit).
+
+/// Replace `start..end` with `text`.
+pub(crate) struct Edit {
+  pub(crate) start: usize,
+  pub(crate) end: usize,
+  pub(crate) text: String,
+}
+
+/// Apply non-overlapping `edits` to `source`.
+pub(crate) fn apply_edits(source: &str, mut edits: Vec<Edit>) -> String {
+  // Back to front, so earlier positions stay valid
+  edits.sort_by_key(|edit| std::cmp::Reverse(edit.start));
+  let mut result = source.to_string();
+  for edit in edits {
+    result.replace_range(edit.start..edit.end, &edit.text);
+  }
+  result
+}
+
+/// Add `field` (`key = value`, without a separator) after `last`, the last
+/// field of the table between `open` and `close`. Single-line tables stay on
+/// one line.
+pub(crate) fn append_field(source: &str, open: usize, close: usize, last: &Field, field: &str) -> Edit {
+  let separator = if last.end > last.value_end { "" } else { "," };
+
+  if !source[open..close].contains('\n') {
+    return Edit {
+      start: last.end,
+      end: last.end,
+      text: format!("{} {}", separator, field),
+    };
+  }
+
+  // Multi-line tables get their own line, indented like the last field and
+  // after a comment ending the last field's line
+  let indent = line_indent(source, last.key_start);
+  let rest = source[last.end..close].split('\n').next().unwrap_or_default();
+  let at = if rest.len() < close - last.end && (rest.trim().is_empty() || rest.trim_start().starts_with("--")) {
+    last.end + rest.trim_end().len()
+  } else {
+    last.end
+  };
+  if at == last.end {
+    return Edit {
+      start: at,
+      end: at,
+      text: format!("{}\n{}{},", separator, indent, field),
+    };
+  }
+  // The separator goes right after the value, before the comment
+  Edit {
+    start: last.value_end,
+    end: at,
+    text: format!("{}{}\n{}{},", separator, &source[last.value_end..at], indent, field),
+  }
+}
+
+/// The indentation of the line `pos` is on.
+pub(crate) fn line_indent(source: &str, pos: usize) -> &str {
+  let line_start = source[..pos].rfind('\n').map_or(0, |i| i + 1);
+  let line = &source[line_start..];
+  &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// A top-level `key = value` field of a table constructor.
+pub(crate) struct Field<'a> {
+  pub(crate) key: &'a str,
+  pub(crate) key_start: usize,
+  pub(crate) value_start: usize,
+  pub(crate) value_end: usize,
+  /// End of the field including its `,`/`;` separator, if any.
+  pub(crate) end: usize,
+}
+
+/// What follows a global name.
+pub(crate) enum Call {
+  /// Not called, e.g. `local d = derive`.
+  NotACall,
+  /// Called with something other than a table constructor.
+  Other,
+  /// Called with the table constructor whose braces are at `open` and `close`.
+  Table { open: usize, close: usize },
+}
+
+/// Just enough of a Lua lexer to find calls and the fields of their specs.
+pub(crate) struct Lexer<'a> {
+  pub(crate) src: &'a [u8],
+}
+
+impl<'a> Lexer<'a> {
+  pub(crate) fn new(source: &'a str) -> Self {
+    Self { src: source.as_bytes() }
+  }
+
+  /// End of the whitespace or comment at `pos`, if there is one.
+  pub(crate) fn trivia_end(&self, pos: usize) -> Option<usize> {
+    let src = self.src;
+    if src[pos].is_ascii_whitespace() {
+      return Some(pos + 1);
+    }
+    if !src[pos..].starts_with(b"--") {
+      return None;
+    }
+    if let Some(level) = self.long_bracket(pos + 2) {
+      return Some(self.long_bracket_end(pos + 2, level));
+    }
+    Some(
+      src[pos..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .map_or(src.len(), |i| pos + i),
+    )
+  }
+
+  /// End of the string literal at `pos`, if there is one.
+  pub(crate) fn string_end(&self, pos: usize) -> Option<usize> {
+    let src = self.src;
+    match src[pos] {
+      quote @ (b'"' | b'\'') => {
+        let mut i = pos + 1;
+        while i < src.len() && src[i] != quote && src[i] != b'\n' {
+          i += if src[i] == b'\\' { 2 } else { 1 };
+        }
+        Some((i + 1).min(src.len()))
+      }
+      b'[' => self.long_bracket(pos).map(|level| self.long_bracket_end(pos, level)),
+      _ => None,
+    }
+  }
+
+  /// Level of the long bracket (`[[`, `[==[`) opening at `pos`, if any.
+  fn long_bracket(&self, pos: usize) -> Option<usize> {
+    let rest = self.src.get(pos..)?;
+    if rest.first() != Some(&b'[') {
+      return None;
+    }
+    let level = rest[1..].iter().take_while(|b| **b == b'=').count();
+    (rest.get(level + 1) == Some(&b'[')).then_some(level)
+  }
+
+  fn long_bracket_end(&self, pos: usize, level: usize) -> usize {
+    let close = format!("]{}]", "=".repeat(level));
+    let body = pos + level + 2;
+    self.src[body..]
+      .windows(close.len())
+      .position(|window| window == close.as_bytes())
+      .map_or(self.src.len(), |i| body + i + close.len())
+  }
+
+  pub(crate) fn ident_end(&self, pos: usize) -> usize {
+    pos
+      + self.src[pos..]
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+        .count()
+  }
+
+  /// Skip whitespace and comments from `pos`.
+  pub(crate) fn skip_trivia(&self, mut pos: usize) -> usize {
+    while pos < self.src.len()
+      && let Some(end) = self.trivia_end(pos)
+    {
+      pos = end;
+    }
+    pos
+  }
+
+  /// Position of the bracket closing the one at `open`.
+  pub(crate) fn matching(&self, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut pos = open;
+    while pos < self.src.len() {
+      if let Some(end) = self.trivia_end(pos).or_else(|| self.string_end(pos)) {
+        pos = end;
+        continue;
+      }
+      match self.src[pos] {
+        b'{' | b'(' | b'[' => depth += 1,
+        b'}' | b')' | b']' => {
+          depth -= 1;
+          if depth == 0 {
+            return Some(pos);
+          }
+        }
+        _ => {}
+      }
+      pos += 1;
+    }
+    None
+  }
+
+  /// What the global name ending at `pos` is followed by.
+  pub(crate) fn call_table(&self, pos: usize) -> Call {
+    let pos = self.skip_trivia(pos);
+    match self.src.get(pos) {
+      Some(b'{') => match self.matching(pos) {
+        Some(close) => Call::Table { open: pos, close },
+        None => Call::Other,
+      },
+      Some(b'(') => {
+        let open = self.skip_trivia(pos + 1);
+        if self.src.get(open) != Some(&b'{') {
+          return Call::Other;
+        }
+        match self.matching(open) {
+          Some(close) if self.src.get(self.skip_trivia(close + 1)) == Some(&b')') => Call::Table { open, close },
+          _ => Call::Other,
+        }
+      }
+      Some(b'"' | b'\'') => Call::Other,
+      _ => Call::NotACall,
+    }
+  }
+
+  /// The `key = value` fields directly inside the table between `open` and `close`.
+  pub(crate) fn fields(&self, open: usize, close: usize) -> Vec<Field<'_>> {
+    let mut fields = Vec::new();
+    let mut pos = self.skip_trivia(open + 1);
+    while pos < close {
+      // Scan to the next top-level separator, remembering where the last
+      // token before it (not a comment) ends
+      let start = pos;
+      let mut scan = pos;
+      let mut value_end = pos;
+      while scan < close && !matches!(self.src[scan], b',' | b';') {
+        if let Some(end) = self.trivia_end(scan) {
+          scan = end;
+          continue;
+        }
+        scan = match self.string_end(scan) {
+          Some(end) => end,
+          None if matches!(self.src[scan], b'{' | b'(' | b'[') => self.matching(scan).map_or(close, |end| end + 1),
+          None => scan + 1,
+        };
+        value_end = scan;
+      }
+      let end = if scan < close { scan + 1 } else { value_end };
+
+      if is_ident_start(self.src[start]) {
+        let key_end = self.ident_end(start);
+        let equals = self.skip_trivia(key_end);
+        if self.src.get(equals) == Some(&b'=') && self.src.get(equals + 1) != Some(&b'=') {
+          fields.push(Field {
+            key: std::str::from_utf8(&self.src[start..key_end]).unwrap_or_default(),
+            key_start: start,
+            value_start: self.skip_trivia(equals + 1),
+            value_end,
+            end,
+          });
+        }
+      }
+      pos = self.skip_trivia(if scan < close { scan + 1 } else { close });
+    }
+    fields
+  }
+}
+
+pub(crate) fn is_ident_start(byte: u8) -> bool {
+  byte.is_ascii_alphabetic() || byte == b'_'
+}
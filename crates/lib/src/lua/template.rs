@@ -0,0 +1,1069 @@
+//! Templates rendered at eval time (`sys.template.render`).
+//!
+//! Plain placeholders can't generate a config from a list, so configs render
+//! text such as nginx or ssh configs from a template and a table of variables:
+//!
+//! ```lua
+//! local conf = sys.template.render([[
+//! {% for server in servers %}
+//! server {
+//!   listen {{ server.port | default(80) }};
+//!   server_name {{ server.names | join(" ") }};
+//! {% if server.tls %}
+//!   ssl_certificate {{ server.tls.cert }};
+//! {% endif %}
+//! }
+//! {% endfor %}
+//! ]], { servers = servers })
+//! ```
+//!
+//! | Syntax                                      | Meaning                            |
+//! | ------------------------------------------- | ---------------------------------- |
+//! | `{{ expr }}`                                | Insert a string, number or boolean |
+//! | `{% if expr %}` / `elif` / `else` / `endif` | Conditionals                       |
+//! | `{% for x in expr %}` / `endfor`            | Loop over a list, or a map's keys  |
+//! | `{% for k, v in expr %}`                    | Loop over indices/keys and values  |
+//! | `{# ... #}`                                 | Comment                            |
+//!
+//! Expressions are variable paths (`server.names`, `ports[1]`), string, number
+//! and boolean literals, `==`, `!=`, `and`, `or`, `not`, and the filters
+//! `join(sep)`, `default(value)`, `length`, `upper`, `lower` and `trim`.
+//! Inside a loop, `loop.index`, `loop.first`, `loop.last` and `loop.length`
+//! describe the iteration. `nil`, `false`, `""` and empty tables are false.
+//!
+//! A `{% %}` or `{# #}` tag alone on its line removes the whole line, so
+//! templates can be indented without blank lines in the output. `$${{...}}`
+//! placeholders are left as they are, to be resolved when the action runs.
+//!
+//! The output only depends on the template and the variables: maps are
+//! visited in key order, and inserting an undefined variable or a table is an
+//! error rather than an empty string, so the result is safe to hash.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use mlua::prelude::*;
+use thiserror::Error;
+
+/// How deep nested tables of variables may be.
+const MAX_DEPTH: usize = 64;
+
+/// Errors parsing or rendering a template.
+#[derive(Debug, Error)]
+pub enum TemplateError {
+  #[error("line {line}: {message}")]
+  Syntax { line: usize, message: String },
+
+  #[error("line {line}: {message}")]
+  Render { line: usize, message: String },
+
+  #[error("vars: {0}")]
+  Vars(String),
+}
+
+/// A template variable, converted from Lua.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+  Nil,
+  Bool(bool),
+  Int(i64),
+  Float(f64),
+  Str(String),
+  List(Vec<Value>),
+  Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+  /// Convert a Lua value. Sequences become lists and other tables maps with
+  /// string keys; functions and userdata are rejected.
+  pub fn from_lua(value: &LuaValue) -> Result<Self, TemplateError> {
+    Self::from_lua_at(value, 0)
+  }
+
+  fn from_lua_at(value: &LuaValue, depth: usize) -> Result<Self, TemplateError> {
+    Ok(match value {
+      LuaValue::Nil => Value::Nil,
+      LuaValue::Boolean(b) => Value::Bool(*b),
+      LuaValue::Integer(n) => Value::Int(*n),
+      LuaValue::Number(n) => Value::Float(*n),
+      LuaValue::String(s) => Value::Str(s.to_string_lossy()),
+      LuaValue::Table(table) => {
+        if depth >= MAX_DEPTH {
+          return Err(TemplateError::Vars(format!(
+            "tables are nested more than {} levels deep (is one its own descendant?)",
+            MAX_DEPTH
+          )));
+        }
+        let mut entries = Vec::new();
+        for pair in table.pairs::<LuaValue, LuaValue>() {
+          let (key, value) = pair.map_err(|e| TemplateError::Vars(e.to_string()))?;
+          entries.push((key, Self::from_lua_at(&value, depth + 1)?));
+        }
+        let len = table.raw_len();
+        let is_list = entries.len() == len
+          && entries
+            .iter()
+            .all(|(key, _)| matches!(key, LuaValue::Integer(i) if *i >= 1 && (*i as usize) <= len));
+        if is_list {
+          let mut items = vec![Value::Nil; len];
+          for (key, value) in entries {
+            if let LuaValue::Integer(i) = key {
+              items[i as usize - 1] = value;
+            }
+          }
+          Value::List(items)
+        } else {
+          let mut map = BTreeMap::new();
+          for (key, value) in entries {
+            let key = match key {
+              LuaValue::String(s) => s.to_string_lossy(),
+              LuaValue::Integer(i) => i.to_string(),
+              LuaValue::Number(n) => Value::Float(n).to_string(),
+              LuaValue::Boolean(b) => b.to_string(),
+              other => {
+                return Err(TemplateError::Vars(format!("a table has a {} key", other.type_name())));
+              }
+            };
+            map.insert(key, value);
+          }
+          Value::Map(map)
+        }
+      }
+      other => {
+        return Err(TemplateError::Vars(format!(
+          "a {} can't be used in a template",
+          other.type_name()
+        )));
+      }
+    })
+  }
+
+  fn type_name(&self) -> &'static str {
+    match self {
+      Value::Nil => "nil",
+      Value::Bool(_) => "boolean",
+      Value::Int(_) | Value::Float(_) => "number",
+      Value::Str(_) => "string",
+      Value::List(_) => "list",
+      Value::Map(_) => "map",
+    }
+  }
+
+  fn is_truthy(&self) -> bool {
+    match self {
+      Value::Nil | Value::Bool(false) => false,
+      Value::Str(s) => !s.is_empty(),
+      Value::List(items) => !items.is_empty(),
+      Value::Map(map) => !map.is_empty(),
+      _ => true,
+    }
+  }
+
+  fn is_scalar(&self) -> bool {
+    matches!(self, Value::Bool(_) | Value::Int(_) | Value::Float(_) | Value::Str(_))
+  }
+
+  fn equals(&self, other: &Value) -> bool {
+    match (self, other) {
+      (Value::Int(a), Value::Float(b)) | (Value::Float(b), Value::Int(a)) => *a as f64 == *b,
+      _ => self == other,
+    }
+  }
+}
+
+/// Scalars as Lua's `tostring` shows them.
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Value::Nil => write!(f, "nil"),
+      Value::Bool(b) => write!(f, "{}", b),
+      Value::Int(n) => write!(f, "{}", n),
+      Value::Float(n) if n.is_finite() && n.fract() == 0.0 => write!(f, "{:.1}", n),
+      Value::Float(n) => write!(f, "{}", n),
+      Value::Str(s) => write!(f, "{}", s),
+      Value::List(_) | Value::Map(_) => write!(f, "<{}>", self.type_name()),
+    }
+  }
+}
+
+/// Render the template `src` with the variables `vars`.
+pub fn render(src: &str, vars: &BTreeMap<String, Value>) -> Result<String, TemplateError> {
+  let tokens = tokenize(src)?;
+  let mut pos = 0;
+  let (nodes, _) = parse_nodes(&tokens, &mut pos, &[])?;
+
+  let mut scope = Scope {
+    vars,
+    locals: Vec::new(),
+  };
+  let mut out = String::with_capacity(src.len());
+  render_nodes(&nodes, &mut scope, &mut out)?;
+  Ok(out)
+}
+
+/// Create the `sys.template` table.
+pub fn create_template_helpers(lua: &Lua) -> LuaResult<LuaTable> {
+  let template = lua.create_table()?;
+  template.set(
+    "render",
+    lua.create_function(|_, (src, vars): (String, Option<LuaTable>)| {
+      let vars = match vars {
+        Some(table) => match Value::from_lua(&LuaValue::Table(table)) {
+          Ok(Value::Map(map)) => map,
+          Ok(Value::List(items)) if items.is_empty() => BTreeMap::new(),
+          Ok(_) => {
+            return Err(LuaError::external(
+              "sys.template.render: vars must be a table with string keys",
+            ));
+          }
+          Err(e) => return Err(LuaError::external(format!("sys.template.render: {}", e))),
+        },
+        None => BTreeMap::new(),
+      };
+      render(&src, &vars).map_err(|e| LuaError::external(format!("sys.template.render: {}", e)))
+    })?,
+  )?;
+  Ok(template)
+}
+
+fn syntax(line: usize, message: impl Into<String>) -> TemplateError {
+  TemplateError::Syntax {
+    line,
+    message: message.into(),
+  }
+}
+
+fn render_error(line: usize, message: impl Into<String>) -> TemplateError {
+  TemplateError::Render {
+    line,
+    message: message.into(),
+  }
+}
+
+// ---------------------------------------------------------------------------
+// Tokenizing
+// ---------------------------------------------------------------------------
+
+#[derive(Debug)]
+enum Token {
+  Text(String),
+  Output { src: String, line: usize },
+  Tag { src: String, line: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TagKind {
+  Output,
+  Block,
+  Comment,
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, TemplateError> {
+  let mut tokens = Vec::new();
+  let mut text = String::new();
+  // Where the text since the last tag starts
+  let mut text_start = 0;
+  let mut pos = 0;
+
+  while let Some(offset) = src[pos..].find('{') {
+    let start = pos + offset;
+    let kind = match src[start..].get(..2) {
+      Some("{{") => TagKind::Output,
+      Some("{%") => TagKind::Block,
+      Some("{#") => TagKind::Comment,
+      _ => {
+        text.push_str(&src[pos..=start]);
+        pos = start + 1;
+        continue;
+      }
+    };
+
+    // Placeholders like $${{build:HASH:out}} are resolved when actions run
+    if kind == TagKind::Output && src[..start].ends_with("$$") {
+      let end = src[start..].find("}}").map_or(src.len(), |i| start + i + 2);
+      text.push_str(&src[pos..end]);
+      pos = end;
+      continue;
+    }
+
+    let line = src[..start].matches('\n').count() + 1;
+    let close = match kind {
+      TagKind::Output => "}}",
+      TagKind::Block => "%}",
+      TagKind::Comment => "#}",
+    };
+    let inner_end = src[start + 2..]
+      .find(close)
+      .map(|i| start + 2 + i)
+      .ok_or_else(|| syntax(line, format!("'{}' is never closed", &src[start..start + 2])))?;
+    let inner = src[start + 2..inner_end].trim().to_string();
+    let mut end = inner_end + 2;
+    text.push_str(&src[pos..start]);
+
+    if kind != TagKind::Output {
+      // A tag alone on its line takes the whole line with it
+      let line_start = src[..start].rfind('\n').map_or(0, |i| i + 1);
+      let indent = &src[line_start..start];
+      let after = &src[end..];
+      let rest_of_line = after.find('\n').map_or(after.len(), |i| i + 1);
+      if line_start >= text_start && indent.trim().is_empty() && after[..rest_of_line].trim().is_empty() {
+        text.truncate(text.len() - indent.len());
+        end += rest_of_line;
+      }
+    }
+
+    match kind {
+      TagKind::Output | TagKind::Block => {
+        if !text.is_empty() {
+          tokens.push(Token::Text(std::mem::take(&mut text)));
+        }
+        if inner.is_empty() {
+          return Err(syntax(line, "empty tag"));
+        }
+        tokens.push(match kind {
+          TagKind::Output => Token::Output { src: inner, line },
+          _ => Token::Tag { src: inner, line },
+        });
+      }
+      TagKind::Comment => {}
+    }
+    pos = end;
+    text_start = end;
+  }
+
+  text.push_str(&src[pos..]);
+  if !text.is_empty() {
+    tokens.push(Token::Text(text));
+  }
+  Ok(tokens)
+}
+
+// ---------------------------------------------------------------------------
+// Parsing
+// ---------------------------------------------------------------------------
+
+#[derive(Debug)]
+enum Node {
+  Text(String),
+  Output {
+    expr: Expr,
+    src: String,
+    line: usize,
+  },
+  If {
+    branches: Vec<(Expr, Vec<Node>)>,
+    otherwise: Vec<Node>,
+    line: usize,
+  },
+  For {
+    key: Option<String>,
+    value: String,
+    iter: Expr,
+    src: String,
+    body: Vec<Node>,
+    line: usize,
+  },
+}
+
+/// A block tag ending the nodes parsed so far.
+struct EndTag {
+  keyword: String,
+  rest: String,
+  line: usize,
+}
+
+/// Parse nodes up to one of the tags `ends`, returned along with them; `None`
+/// at the end of the template.
+fn parse_nodes(tokens: &[Token], pos: &mut usize, ends: &[&str]) -> Result<(Vec<Node>, Option<EndTag>), TemplateError> {
+  let mut nodes = Vec::new();
+  while let Some(token) = tokens.get(*pos) {
+    *pos += 1;
+    match token {
+      Token::Text(text) => nodes.push(Node::Text(text.clone())),
+      Token::Output { src, line } => nodes.push(Node::Output {
+        expr: parse_expr(src, *line)?,
+        src: src.clone(),
+        line: *line,
+      }),
+      Token::Tag { src, line } => {
+        let (keyword, rest) = src.split_once(char::is_whitespace).unwrap_or((src, ""));
+        let rest = rest.trim();
+        if ends.contains(&keyword) {
+          return Ok((
+            nodes,
+            Some(EndTag {
+              keyword: keyword.to_string(),
+              rest: rest.to_string(),
+              line: *line,
+            }),
+          ));
+        }
+        match keyword {
+          "if" => nodes.push(parse_if(tokens, pos, rest, *line)?),
+          "for" => nodes.push(parse_for(tokens, pos, rest, *line)?),
+          "elif" | "else" | "endif" | "endfor" => {
+            return Err(syntax(*line, format!("unexpected '{}'", keyword)));
+          }
+          _ => return Err(syntax(*line, format!("unknown tag '{}'", keyword))),
+        }
+      }
+    }
+  }
+  Ok((nodes, None))
+}
+
+fn parse_if(tokens: &[Token], pos: &mut usize, cond: &str, line: usize) -> Result<Node, TemplateError> {
+  let mut branches = Vec::new();
+  let mut cond = parse_expr(cond, line)?;
+  loop {
+    let (body, end) = parse_nodes(tokens, pos, &["elif", "else", "endif"])?;
+    branches.push((cond, body));
+    let end = end.ok_or_else(|| syntax(line, "'if' is missing its 'endif'"))?;
+    match end.keyword.as_str() {
+      "elif" => cond = parse_expr(&end.rest, end.line)?,
+      "else" => {
+        let (otherwise, end) = parse_nodes(tokens, pos, &["endif"])?;
+        if end.is_none() {
+          return Err(syntax(line, "'if' is missing its 'endif'"));
+        }
+        return Ok(Node::If {
+          branches,
+          otherwise,
+          line,
+        });
+      }
+      _ => {
+        return Ok(Node::If {
+          branches,
+          otherwise: Vec::new(),
+          line,
+        });
+      }
+    }
+  }
+}
+
+fn parse_for(tokens: &[Token], pos: &mut usize, header: &str, line: usize) -> Result<Node, TemplateError> {
+  let invalid = || syntax(line, "expected 'for NAME in EXPR' or 'for KEY, VALUE in EXPR'");
+  let (names, iter_src) = header.split_once(" in ").ok_or_else(invalid)?;
+  let names: Vec<&str> = names.split(',').map(str::trim).collect();
+  if names.len() > 2 || !names.iter().all(|name| is_ident(name)) {
+    return Err(invalid());
+  }
+  let iter_src = iter_src.trim();
+  let iter = parse_expr(iter_src, line)?;
+
+  let (body, end) = parse_nodes(tokens, pos, &["endfor"])?;
+  if end.is_none() {
+    return Err(syntax(line, "'for' is missing its 'endfor'"));
+  }
+  let (key, value) = match names.as_slice() {
+    [value] => (None, value.to_string()),
+    [key, value] => (Some(key.to_string()), value.to_string()),
+    _ => return Err(invalid()),
+  };
+  Ok(Node::For {
+    key,
+    value,
+    iter,
+    src: iter_src.to_string(),
+    body,
+    line,
+  })
+}
+
+fn is_ident(s: &str) -> bool {
+  let mut chars = s.chars();
+  chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    && !matches!(s, "and" | "or" | "not" | "in" | "true" | "false" | "nil")
+}
+
+#[derive(Debug)]
+enum Segment {
+  Key(String),
+  Index(i64),
+}
+
+#[derive(Debug)]
+enum Filter {
+  Join(Option<Box<Expr>>),
+  Default(Box<Expr>),
+  Length,
+  Upper,
+  Lower,
+  Trim,
+}
+
+#[derive(Debug)]
+enum Expr {
+  Literal(Value),
+  Path(String, Vec<Segment>),
+  Not(Box<Expr>),
+  And(Box<Expr>, Box<Expr>),
+  Or(Box<Expr>, Box<Expr>),
+  Eq(Box<Expr>, Box<Expr>),
+  Ne(Box<Expr>, Box<Expr>),
+  Filter(Box<Expr>, Filter),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ExprToken {
+  Ident(String),
+  Str(String),
+  Int(i64),
+  Float(f64),
+  Punct(&'static str),
+}
+
+fn lex_expr(src: &str, line: usize) -> Result<Vec<ExprToken>, TemplateError> {
+  let mut tokens = Vec::new();
+  let chars: Vec<char> = src.chars().collect();
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    if c.is_whitespace() {
+      i += 1;
+    } else if c.is_ascii_alphabetic() || c == '_' {
+      let start = i;
+      while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+        i += 1;
+      }
+      tokens.push(ExprToken::Ident(chars[start..i].iter().collect()));
+    } else if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())) {
+      let start = i;
+      i += 1;
+      while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+        i += 1;
+      }
+      let number: String = chars[start..i].iter().collect();
+      tokens.push(if number.contains('.') {
+        ExprToken::Float(
+          number
+            .parse()
+            .map_err(|_| syntax(line, format!("invalid number '{}'", number)))?,
+        )
+      } else {
+        ExprToken::Int(
+          number
+            .parse()
+            .map_err(|_| syntax(line, format!("invalid number '{}'", number)))?,
+        )
+      });
+    } else if c == '"' || c == '\'' {
+      let mut s = String::new();
+      i += 1;
+      loop {
+        match chars.get(i) {
+          None => return Err(syntax(line, "unterminated string")),
+          Some(&q) if q == c => break,
+          Some('\\') => {
+            s.push(match chars.get(i + 1) {
+              Some('n') => '\n',
+              Some('t') => '\t',
+              Some(&other) => other,
+              None => return Err(syntax(line, "unterminated string")),
+            });
+            i += 2;
+          }
+          Some(&other) => {
+            s.push(other);
+            i += 1;
+          }
+        }
+      }
+      i += 1;
+      tokens.push(ExprToken::Str(s));
+    } else {
+      let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+      let punct = match (two.as_str(), c) {
+        ("==", _) => "==",
+        ("!=", _) | ("~=", _) => "!=",
+        (_, '.') => ".",
+        (_, '[') => "[",
+        (_, ']') => "]",
+        (_, '(') => "(",
+        (_, ')') => ")",
+        (_, '|') => "|",
+        (_, ',') => ",",
+        _ => return Err(syntax(line, format!("unexpected '{}' in expression", c))),
+      };
+      i += if punct.len() == 2 { 2 } else { 1 };
+      tokens.push(ExprToken::Punct(punct));
+    }
+  }
+  Ok(tokens)
+}
+
+fn parse_expr(src: &str, line: usize) -> Result<Expr, TemplateError> {
+  let mut parser = ExprParser {
+    tokens: lex_expr(src, line)?,
+    pos: 0,
+    line,
+  };
+  let expr = parser.or()?;
+  match parser.peek() {
+    None => Ok(expr),
+    Some(token) => Err(syntax(line, format!("unexpected {} in '{}'", describe(token), src))),
+  }
+}
+
+fn describe(token: &ExprToken) -> String {
+  match token {
+    ExprToken::Ident(s) => format!("'{}'", s),
+    ExprToken::Str(s) => format!("\"{}\"", s),
+    ExprToken::Int(n) => format!("'{}'", n),
+    ExprToken::Float(n) => format!("'{}'", n),
+    ExprToken::Punct(p) => format!("'{}'", p),
+  }
+}
+
+struct ExprParser {
+  tokens: Vec<ExprToken>,
+  pos: usize,
+  line: usize,
+}
+
+impl ExprParser {
+  fn peek(&self) -> Option<&ExprToken> {
+    self.tokens.get(self.pos)
+  }
+
+  fn advance(&mut self) -> Option<ExprToken> {
+    let token = self.tokens.get(self.pos).cloned();
+    self.pos += 1;
+    token
+  }
+
+  fn eat_keyword(&mut self, keyword: &str) -> bool {
+    if matches!(self.peek(), Some(ExprToken::Ident(s)) if s == keyword) {
+      self.pos += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn eat_punct(&mut self, punct: &str) -> bool {
+    if matches!(self.peek(), Some(ExprToken::Punct(p)) if *p == punct) {
+      self.pos += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn expect_punct(&mut self, punct: &str) -> Result<(), TemplateError> {
+    if self.eat_punct(punct) {
+      Ok(())
+    } else {
+      Err(self.unexpected(&format!("'{}'", punct)))
+    }
+  }
+
+  fn unexpected(&self, expected: &str) -> TemplateError {
+    match self.peek() {
+      Some(token) => syntax(self.line, format!("expected {}, found {}", expected, describe(token))),
+      None => syntax(
+        self.line,
+        format!("expected {}, found the end of the expression", expected),
+      ),
+    }
+  }
+
+  fn or(&mut self) -> Result<Expr, TemplateError> {
+    let mut expr = self.and()?;
+    while self.eat_keyword("or") {
+      expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+    }
+    Ok(expr)
+  }
+
+  fn and(&mut self) -> Result<Expr, TemplateError> {
+    let mut expr = self.not()?;
+    while self.eat_keyword("and") {
+      expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+    }
+    Ok(expr)
+  }
+
+  fn not(&mut self) -> Result<Expr, TemplateError> {
+    if self.eat_keyword("not") {
+      return Ok(Expr::Not(Box::new(self.not()?)));
+    }
+    self.comparison()
+  }
+
+  fn comparison(&mut self) -> Result<Expr, TemplateError> {
+    let left = self.filtered()?;
+    if self.eat_punct("==") {
+      Ok(Expr::Eq(Box::new(left), Box::new(self.filtered()?)))
+    } else if self.eat_punct("!=") {
+      Ok(Expr::Ne(Box::new(left), Box::new(self.filtered()?)))
+    } else {
+      Ok(left)
+    }
+  }
+
+  fn filtered(&mut self) -> Result<Expr, TemplateError> {
+    let mut expr = self.primary()?;
+    while self.eat_punct("|") {
+      let name = match self.advance() {
+        Some(ExprToken::Ident(name)) => name,
+        _ => return Err(syntax(self.line, "expected a filter name after '|'")),
+      };
+      let arg = if self.eat_punct("(") {
+        let arg = self.or()?;
+        self.expect_punct(")")?;
+        Some(Box::new(arg))
+      } else {
+        None
+      };
+      let filter = match (name.as_str(), arg) {
+        ("join", arg) => Filter::Join(arg),
+        ("default", Some(arg)) => Filter::Default(arg),
+        ("length", None) => Filter::Length,
+        ("upper", None) => Filter::Upper,
+        ("lower", None) => Filter::Lower,
+        ("trim", None) => Filter::Trim,
+        ("default", None) => return Err(syntax(self.line, "filter 'default' takes a value")),
+        ("length" | "upper" | "lower" | "trim", Some(_)) => {
+          return Err(syntax(self.line, format!("filter '{}' takes no argument", name)));
+        }
+        _ => return Err(syntax(self.line, format!("unknown filter '{}'", name))),
+      };
+      expr = Expr::Filter(Box::new(expr), filter);
+    }
+    Ok(expr)
+  }
+
+  fn primary(&mut self) -> Result<Expr, TemplateError> {
+    match self.advance() {
+      Some(ExprToken::Str(s)) => Ok(Expr::Literal(Value::Str(s))),
+      Some(ExprToken::Int(n)) => Ok(Expr::Literal(Value::Int(n))),
+      Some(ExprToken::Float(n)) => Ok(Expr::Literal(Value::Float(n))),
+      Some(ExprToken::Punct("(")) => {
+        let expr = self.or()?;
+        self.expect_punct(")")?;
+        Ok(expr)
+      }
+      Some(ExprToken::Ident(name)) => match name.as_str() {
+        "true" => Ok(Expr::Literal(Value::Bool(true))),
+        "false" => Ok(Expr::Literal(Value::Bool(false))),
+        "nil" => Ok(Expr::Literal(Value::Nil)),
+        "and" | "or" | "not" | "in" => Err(syntax(self.line, format!("unexpected '{}'", name))),
+        _ => {
+          let mut segments = Vec::new();
+          loop {
+            if self.eat_punct(".") {
+              match self.advance() {
+                Some(ExprToken::Ident(key)) => segments.push(Segment::Key(key)),
+                _ => return Err(syntax(self.line, "expected a name after '.'")),
+              }
+            } else if self.eat_punct("[") {
+              match self.advance() {
+                Some(ExprToken::Int(i)) => segments.push(Segment::Index(i)),
+                Some(ExprToken::Str(key)) => segments.push(Segment::Key(key)),
+                _ => return Err(syntax(self.line, "expected an integer or string in '[]'")),
+              }
+              self.expect_punct("]")?;
+            } else {
+              break;
+            }
+          }
+          Ok(Expr::Path(name, segments))
+        }
+      },
+      _ => {
+        self.pos = self.pos.saturating_sub(1);
+        Err(self.unexpected("a value"))
+      }
+    }
+  }
+}
+
+// ---------------------------------------------------------------------------
+// Rendering
+// ---------------------------------------------------------------------------
+
+struct Scope<'a> {
+  vars: &'a BTreeMap<String, Value>,
+  /// Loop variables, innermost last.
+  locals: Vec<(String, Value)>,
+}
+
+impl Scope<'_> {
+  fn lookup(&self, name: &str) -> Value {
+    self
+      .locals
+      .iter()
+      .rev()
+      .find(|(local, _)| local == name)
+      .map(|(_, value)| value.clone())
+      .or_else(|| self.vars.get(name).cloned())
+      .unwrap_or(Value::Nil)
+  }
+}
+
+fn render_nodes(nodes: &[Node], scope: &mut Scope, out: &mut String) -> Result<(), TemplateError> {
+  for node in nodes {
+    match node {
+      Node::Text(text) => out.push_str(text),
+      Node::Output { expr, src, line } => match eval(expr, scope, *line)? {
+        Value::Nil => return Err(render_error(*line, format!("'{}' is undefined", src))),
+        value if value.is_scalar() => out.push_str(&value.to_string()),
+        value => {
+          return Err(render_error(
+            *line,
+            format!("'{}' is a {}; loop over it or join it", src, value.type_name()),
+          ));
+        }
+      },
+      Node::If {
+        branches,
+        otherwise,
+        line,
+      } => {
+        let mut chosen = otherwise;
+        for (cond, body) in branches {
+          if eval(cond, scope, *line)?.is_truthy() {
+            chosen = body;
+            break;
+          }
+        }
+        render_nodes(chosen, scope, out)?;
+      }
+      Node::For {
+        key,
+        value,
+        iter,
+        src,
+        body,
+        line,
+      } => {
+        let items: Vec<(Value, Value)> = match eval(iter, scope, *line)? {
+          Value::List(items) => items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| (Value::Int(i as i64 + 1), item))
+            .collect(),
+          Value::Map(map) => map.into_iter().map(|(k, v)| (Value::Str(k), v)).collect(),
+          Value::Nil => return Err(render_error(*line, format!("'{}' is undefined", src))),
+          other => {
+            return Err(render_error(
+              *line,
+              format!("can't loop over '{}', a {}", src, other.type_name()),
+            ));
+          }
+        };
+
+        let length = items.len();
+        for (i, (item_key, item_value)) in items.into_iter().enumerate() {
+          let is_map = matches!(item_key, Value::Str(_));
+          let locals = scope.locals.len();
+          let loop_info = BTreeMap::from([
+            ("index".to_string(), Value::Int(i as i64 + 1)),
+            ("first".to_string(), Value::Bool(i == 0)),
+            ("last".to_string(), Value::Bool(i + 1 == length)),
+            ("length".to_string(), Value::Int(length as i64)),
+          ]);
+          scope.locals.push(("loop".to_string(), Value::Map(loop_info)));
+          match key {
+            Some(key) => {
+              scope.locals.push((key.clone(), item_key));
+              scope.locals.push((value.clone(), item_value));
+            }
+            // A map's keys, like Lua's `for k in pairs(t)`
+            None if is_map => scope.locals.push((value.clone(), item_key)),
+            None => scope.locals.push((value.clone(), item_value)),
+          }
+          let result = render_nodes(body, scope, out);
+          scope.locals.truncate(locals);
+          result?;
+        }
+      }
+    }
+  }
+  Ok(())
+}
+
+fn eval(expr: &Expr, scope: &Scope, line: usize) -> Result<Value, TemplateError> {
+  Ok(match expr {
+    Expr::Literal(value) => value.clone(),
+    Expr::Path(name, segments) => {
+      let mut value = scope.lookup(name);
+      for segment in segments {
+        value = match (value, segment) {
+          (Value::Map(mut map), Segment::Key(key)) => map.remove(key).unwrap_or(Value::Nil),
+          (Value::Map(mut map), Segment::Index(i)) => map.remove(&i.to_string()).unwrap_or(Value::Nil),
+          (Value::List(items), Segment::Index(i)) if *i >= 1 => {
+            items.into_iter().nth(*i as usize - 1).unwrap_or(Value::Nil)
+          }
+          _ => Value::Nil,
+        };
+      }
+      value
+    }
+    Expr::Not(inner) => Value::Bool(!eval(inner, scope, line)?.is_truthy()),
+    Expr::And(a, b) => Value::Bool(eval(a, scope, line)?.is_truthy() && eval(b, scope, line)?.is_truthy()),
+    Expr::Or(a, b) => Value::Bool(eval(a, scope, line)?.is_truthy() || eval(b, scope, line)?.is_truthy()),
+    Expr::Eq(a, b) => Value::Bool(eval(a, scope, line)?.equals(&eval(b, scope, line)?)),
+    Expr::Ne(a, b) => Value::Bool(!eval(a, scope, line)?.equals(&eval(b, scope, line)?)),
+    Expr::Filter(inner, filter) => apply_filter(eval(inner, scope, line)?, filter, scope, line)?,
+  })
+}
+
+fn apply_filter(value: Value, filter: &Filter, scope: &Scope, line: usize) -> Result<Value, TemplateError> {
+  let invalid =
+    |name: &str, value: &Value| render_error(line, format!("can't apply '{}' to a {}", name, value.type_name()));
+  Ok(match filter {
+    Filter::Default(fallback) => match value {
+      Value::Nil => eval(fallback, scope, line)?,
+      value => value,
+    },
+    Filter::Join(sep) => {
+      let sep = match sep {
+        Some(sep) => eval(sep, scope, line)?.to_string(),
+        None => String::new(),
+      };
+      match value {
+        Value::List(items) => {
+          if let Some(item) = items.iter().find(|item| !item.is_scalar()) {
+            return Err(invalid("join", item));
+          }
+          Value::Str(items.iter().map(Value::to_string).collect::<Vec<_>>().join(&sep))
+        }
+        value => return Err(invalid("join", &value)),
+      }
+    }
+    Filter::Length => match value {
+      Value::Str(s) => Value::Int(s.chars().count() as i64),
+      Value::List(items) => Value::Int(items.len() as i64),
+      Value::Map(map) => Value::Int(map.len() as i64),
+      value => return Err(invalid("length", &value)),
+    },
+    Filter::Upper | Filter::Lower | Filter::Trim => {
+      let name = match filter {
+        Filter::Upper => "upper",
+        Filter::Lower => "lower",
+        _ => "trim",
+      };
+      if !value.is_scalar() {
+        return Err(invalid(name, &value));
+      }
+      let s = value.to_string();
+      Value::Str(match filter {
+        Filter::Upper => s.to_uppercase(),
+        Filter::Lower => s.to_lowercase(),
+        _ => s.trim().to_string(),
+      })
+    }
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn render_lua(src: &str, vars: &str) -> LuaResult<String> {
+    let lua = Lua::new();
+    let sys = lua.create_table()?;
+    sys.set("template", create_template_helpers(&lua)?)?;
+    lua.globals().set("sys", sys)?;
+    lua.globals().set("src", src)?;
+    lua
+      .load(format!("return sys.template.render(src, {})", vars))
+      .eval::<String>()
+  }
+
+  #[test]
+  fn renders_loops_and_conditionals_without_tag_lines() -> LuaResult<()> {
+    let src = "\
+{% for server in servers %}
+server {
+  listen {{ server.port | default(80) }};
+  server_name {{ server.names | join(\" \") }};
+  {% if server.tls %}
+  ssl_certificate {{ server.tls.cert }};
+  {% endif %}
+}
+{% endfor %}
+";
+    let out = render_lua(
+      src,
+      r#"{ servers = {
+        { names = { "example.com", "www.example.com" }, tls = { cert = "/etc/ssl/a.pem" }, port = 443 },
+        { names = { "b.test" } },
+      } }"#,
+    )?;
+    assert_eq!(
+      out,
+      "\
+server {
+  listen 443;
+  server_name example.com www.example.com;
+  ssl_certificate /etc/ssl/a.pem;
+}
+server {
+  listen 80;
+  server_name b.test;
+}
+"
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn maps_are_visited_in_key_order() -> LuaResult<()> {
+    let src = "{% for host, ip in hosts %}{{ ip }} {{ host }}{% if not loop.last %}, {% endif %}{% endfor %}";
+    let vars = r#"{ hosts = { zeta = "10.0.0.3", alpha = "10.0.0.1", mid = "10.0.0.2" } }"#;
+    let first = render_lua(src, vars)?;
+    assert_eq!(first, "10.0.0.1 alpha, 10.0.0.2 mid, 10.0.0.3 zeta");
+    for _ in 0..5 {
+      assert_eq!(render_lua(src, vars)?, first);
+    }
+
+    let keys = render_lua("{% for k in t %}{{ k }};{% endfor %}", "{ t = { b = 1, a = 2 } }")?;
+    assert_eq!(keys, "a;b;");
+    Ok(())
+  }
+
+  #[test]
+  fn expressions_compare_and_filter() -> LuaResult<()> {
+    let src = "{% if mode == 'strict' and not debug %}{{ name | upper }}{% elif mode != nil %}{{ mode }}{% else %}none{% endif %}";
+    assert_eq!(render_lua(src, "{ mode = 'strict', name = 'ssh' }")?, "SSH");
+    assert_eq!(render_lua(src, "{ mode = 'loose' }")?, "loose");
+    assert_eq!(render_lua(src, "{}")?, "none");
+
+    assert_eq!(
+      render_lua("{{ xs | length }} {{ xs[2] }} {{ 1.0 }}", "{ xs = { 5, 6 } }")?,
+      "2 6 1.0"
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn placeholders_and_comments_pass_through() -> LuaResult<()> {
+    let out = render_lua("{# note #}path=$${{build:abc:out}}/{{ name }}", "{ name = 'bin' }")?;
+    assert_eq!(out, "path=$${{build:abc:out}}/bin");
+    Ok(())
+  }
+
+  #[test]
+  fn undefined_values_and_bad_syntax_fail_with_a_line() {
+    let err = render_lua("ok\n{{ missing }}", "{}").unwrap_err().to_string();
+    assert!(err.contains("line 2: 'missing' is undefined"), "{}", err);
+
+    let err = render_lua("{{ xs }}", "{ xs = { 1 } }").unwrap_err().to_string();
+    assert!(err.contains("is a list"), "{}", err);
+
+    let err = render_lua("{% for x in xs %}", "{ xs = {} }").unwrap_err().to_string();
+    assert!(err.contains("missing its 'endfor'"), "{}", err);
+
+    let err = render_lua("{{ x | shout }}", "{ x = 1 }").unwrap_err().to_string();
+    assert!(err.contains("unknown filter 'shout'"), "{}", err);
+
+    let err = render_lua("{{ f }}", "{ f = print }").unwrap_err().to_string();
+    assert!(err.contains("function can't be used"), "{}", err);
+  }
+}
@@ -0,0 +1,268 @@
+//! Manifest export for evaluating once and applying on many machines.
+//!
+//! A manifest export wraps an evaluated [`Manifest`] in a versioned JSON
+//! document that records the platform it was evaluated for. Applying an
+//! export skips Lua evaluation entirely, so it is refused on a different
+//! platform: the manifest's builds and binds were resolved for `sys.os`,
+//! `sys.arch` and `sys.facts` of the evaluating machine. Facts are not
+//! compared, so evaluate on a machine like the ones the export is applied on.
+//! `sys eval --platform` exports a config for another platform, with its
+//! facts assumed (see `Facts::for_platform`).
+//!
+//! # Document Layout
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "syslua_version": "0.1.0",
+//!   "platform": "x86_64-linux",
+//!   "config": "/home/user/.config/syslua/init.lua",
+//!   "exported_at": 1733667300,
+//!   "manifest": { "builds": { ... }, "bindings": { ... } }
+//! }
+//! ```
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::platform::Platform;
+use crate::util::hash::{HashError, ObjectHash, hash_all};
+
+use super::types::Manifest;
+
+/// Current manifest export format version.
+pub const MANIFEST_EXPORT_VERSION: u32 = 1;
+
+/// An evaluated manifest, ready to be applied without its config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestExport {
+  /// Export format version.
+  pub version: u32,
+
+  /// Version of syslua that evaluated the config.
+  pub syslua_version: String,
+
+  /// Platform triple the config was evaluated for (e.g., "x86_64-linux").
+  pub platform: String,
+
+  /// Path of the evaluated config, for reference only.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub config: Option<String>,
+
+  /// Unix timestamp when the export was created.
+  pub exported_at: u64,
+
+  /// The evaluated manifest.
+  pub manifest: Manifest,
+}
+
+impl ManifestExport {
+  /// Wrap a manifest evaluated on this machine from `config_path`.
+  pub fn new(manifest: Manifest, config_path: Option<&Path>) -> Result<Self, ManifestExportError> {
+    let platform = Platform::current().ok_or(ManifestExportError::UnknownPlatform)?;
+    Ok(Self::for_platform(manifest, config_path, platform))
+  }
+
+  /// Wrap a manifest evaluated for `platform` (see `EvalOptions::platform`),
+  /// which only machines of that platform accept.
+  pub fn for_platform(manifest: Manifest, config_path: Option<&Path>, platform: Platform) -> Self {
+    Self {
+      version: MANIFEST_EXPORT_VERSION,
+      syslua_version: env!("CARGO_PKG_VERSION").to_string(),
+      platform: platform.triple(),
+      config: config_path.map(|p| p.display().to_string()),
+      exported_at: std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0),
+      manifest,
+    }
+  }
+
+  /// Serialize the export as pretty-printed JSON.
+  pub fn to_json(&self) -> Result<String, ManifestExportError> {
+    serde_json::to_string_pretty(self).map_err(ManifestExportError::Serialize)
+  }
+
+  /// Parse an export and check that it can be applied on this machine.
+  ///
+  /// See [`validate`](Self::validate) for the checks.
+  pub fn parse(content: &str) -> Result<Self, ManifestExportError> {
+    let export: Self = serde_json::from_str(content).map_err(ManifestExportError::Parse)?;
+    export.validate(&Platform::current().ok_or(ManifestExportError::UnknownPlatform)?)?;
+    Ok(export)
+  }
+
+  /// Read and parse an export file.
+  pub fn load(path: &Path) -> Result<Self, ManifestExportError> {
+    let content = std::fs::read_to_string(path).map_err(ManifestExportError::Read)?;
+    Self::parse(&content)
+  }
+
+  /// Check that this export can be applied on `platform`.
+  ///
+  /// The format version must be supported, the platform must match the one
+  /// the config was evaluated for, and every build and bind must still hash
+  /// to its key (so a hand-edited or truncated document is rejected).
+  pub fn validate(&self, platform: &Platform) -> Result<(), ManifestExportError> {
+    if self.version > MANIFEST_EXPORT_VERSION {
+      return Err(ManifestExportError::UnsupportedVersion(self.version));
+    }
+
+    if self.platform != platform.triple() {
+      return Err(ManifestExportError::PlatformMismatch {
+        expected: self.platform.clone(),
+        actual: platform.triple(),
+      });
+    }
+
+    let spec = &self.manifest.hash;
+    let builds: Vec<_> = self.manifest.builds.values().collect();
+    for (key, computed) in self.manifest.builds.keys().zip(hash_all(&builds, spec)?) {
+      check_key(key, &computed)?;
+    }
+    let binds: Vec<_> = self.manifest.bindings.values().collect();
+    for (key, computed) in self.manifest.bindings.keys().zip(hash_all(&binds, spec)?) {
+      check_key(key, &computed)?;
+    }
+
+    Ok(())
+  }
+}
+
+fn check_key(key: &ObjectHash, computed: &ObjectHash) -> Result<(), ManifestExportError> {
+  if key != computed {
+    return Err(ManifestExportError::HashMismatch {
+      key: key.clone(),
+      computed: computed.clone(),
+    });
+  }
+  Ok(())
+}
+
+/// Errors that can occur when exporting or importing a manifest.
+#[derive(Debug, Error)]
+pub enum ManifestExportError {
+  /// The current OS or architecture is not supported.
+  #[error("unsupported platform: cannot determine the platform triple")]
+  UnknownPlatform,
+
+  /// The document was written by a newer syslua.
+  #[error("unsupported manifest export version {0} (expected at most {MANIFEST_EXPORT_VERSION})")]
+  UnsupportedVersion(u32),
+
+  /// The manifest was evaluated for another platform.
+  #[error("manifest was evaluated for {expected}, but this machine is {actual}")]
+  PlatformMismatch { expected: String, actual: String },
+
+  /// A build or bind does not match its key.
+  #[error("manifest entry {} does not match its content (hashes to {})", key.0, computed.0)]
+  HashMismatch { key: ObjectHash, computed: ObjectHash },
+
+  /// Failed to hash a build or bind.
+  #[error("failed to hash manifest entry: {0}")]
+  Hash(#[from] HashError),
+
+  /// Failed to read the document.
+  #[error("failed to read manifest export: {0}")]
+  Read(#[source] std::io::Error),
+
+  /// Failed to parse JSON.
+  #[error("failed to parse manifest export: {0}")]
+  Parse(#[source] serde_json::Error),
+
+  /// Failed to serialize JSON.
+  #[error("failed to serialize manifest export: {0}")]
+  Serialize(#[source] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::bind::BindDef;
+  use crate::platform::arch::Arch;
+  use crate::platform::os::Os;
+  use crate::util::hash::Hashable;
+
+  fn test_manifest() -> Manifest {
+    let bind = BindDef {
+      id: Some("greeting".to_string()),
+      inputs: None,
+      outputs: None,
+      create_actions: vec![],
+      update_actions: None,
+      destroy_actions: vec![],
+      check_actions: None,
+      check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
+    };
+    let mut manifest = Manifest::default();
+    manifest.bindings.insert(bind.compute_hash().unwrap(), bind);
+    manifest
+  }
+
+  fn test_export(platform: &Platform) -> ManifestExport {
+    ManifestExport {
+      version: MANIFEST_EXPORT_VERSION,
+      syslua_version: "0.1.0".to_string(),
+      platform: platform.triple(),
+      config: None,
+      exported_at: 1733667300,
+      manifest: test_manifest(),
+    }
+  }
+
+  #[test]
+  fn export_round_trips_through_json() {
+    let platform = Platform::new(Arch::X86_64, Os::Linux);
+    let export = test_export(&platform);
+
+    let parsed: ManifestExport = serde_json::from_str(&export.to_json().unwrap()).unwrap();
+    assert_eq!(parsed, export);
+    assert!(parsed.validate(&platform).is_ok());
+  }
+
+  #[test]
+  fn validate_rejects_other_platforms_and_versions() {
+    let linux = Platform::new(Arch::X86_64, Os::Linux);
+    let darwin = Platform::new(Arch::Aarch64, Os::MacOs);
+    let export = test_export(&linux);
+
+    assert!(matches!(
+      export.validate(&darwin),
+      Err(ManifestExportError::PlatformMismatch { .. })
+    ));
+
+    let newer = ManifestExport {
+      version: MANIFEST_EXPORT_VERSION + 1,
+      ..export
+    };
+    assert!(matches!(
+      newer.validate(&linux),
+      Err(ManifestExportError::UnsupportedVersion(_))
+    ));
+  }
+
+  #[test]
+  fn validate_rejects_edited_entries() {
+    let platform = Platform::new(Arch::X86_64, Os::Linux);
+    let mut export = test_export(&platform);
+    for bind in export.manifest.bindings.values_mut() {
+      bind.id = Some("edited".to_string());
+    }
+
+    assert!(matches!(
+      export.validate(&platform),
+      Err(ManifestExportError::HashMismatch { .. })
+    ));
+  }
+}
@@ -2,7 +2,17 @@
 //!
 //! Manifests are the evaluated result of Lua configuration, containing all
 //! defined builds, binds, and their dependencies ready for execution.
+//!
+//! # Modules
+//!
+//! - [`types`]: Core types (`Manifest`, `SkippedBind`, `SkippedBuild`, `OverriddenBuild`)
+//! - [`export`]: Versioned manifest documents for applying without evaluation
+//! - [`validate`]: Placeholder checks for builds and binds entering the manifest
 
+mod export;
 mod types;
+mod validate;
 
+pub use export::*;
 pub use types::*;
+pub use validate::*;
@@ -9,6 +9,10 @@
 //! The manifest contains:
 //! - `builds`: Content-addressed map of [`BuildDef`]s, keyed by [`BuildHash`]
 //! - `bindings`: Content-addressed map of [`BindDef`]s, keyed by [`BindHash`]
+//! - `skipped`: Binds left out because their `requires` or `platforms` weren't met on this host
+//! - `skipped_builds`: Builds left out because their `platforms` don't include this host
+//! - `overridden`: Builds changed by `sys.override_build`, and by which overrides
+//! - `deprecations`: Calls of deprecated Lua helpers made while evaluating
 //!
 //! # Content Addressing
 //!
@@ -24,11 +28,20 @@
 
 use std::collections::BTreeMap;
 
+use mlua::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::bind::BindDef;
 use crate::build::BuildDef;
-use crate::util::hash::{Hashable, ObjectHash};
+use crate::consts::OBJ_HASH_PREFIX_LEN;
+use crate::execute::ApplyHooks;
+use crate::lua::api::Deprecation;
+use crate::platform::Platform;
+use crate::platform::priority::Throttle;
+use crate::util::hash::{HashError, HashMemo, HashSpec, Hashable, ObjectHash};
+
+/// Lua registry key holding the config's `settings.hash` (as `<algorithm>:<length>`).
+pub const HASH_SPEC_REGISTRY_KEY: &str = "__syslua_hash_spec";
 
 /// The complete desired state manifest.
 ///
@@ -66,6 +79,197 @@ pub struct Manifest {
   pub builds: BTreeMap<ObjectHash, BuildDef>,
   /// All bindings in the manifest, keyed by their content hash.
   pub bindings: BTreeMap<ObjectHash, BindDef>,
+  /// Binds skipped during evaluation because a requirement was missing or
+  /// they are for other platforms.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub skipped: Vec<SkippedBind>,
+  /// Builds skipped during evaluation because they are for other platforms.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub skipped_builds: Vec<SkippedBuild>,
+  /// Builds changed by `sys.override_build` during evaluation.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub overridden: Vec<OverriddenBuild>,
+  /// Calls of deprecated Lua helpers made while evaluating, one per call site.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub deprecations: Vec<Deprecation>,
+  /// Algorithm and length of the hashes used as keys. Omitted for the default
+  /// (SHA-256, 20 characters), which older manifests use.
+  #[serde(default, skip_serializing_if = "HashSpec::is_default")]
+  pub hash: HashSpec,
+  /// Audit hooks from the config's `settings.hooks`, run by apply and destroy.
+  #[serde(default, skip_serializing_if = "ApplyHooks::is_empty")]
+  pub hooks: ApplyHooks,
+  /// Lowered priority of applies, from the config's `settings.nice` and
+  /// `settings.background`.
+  #[serde(default, skip_serializing_if = "Throttle::is_none")]
+  pub throttle: Throttle,
+  /// Revision of each root input the evaluation resolved (name -> rev),
+  /// overridden ones included.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub inputs: BTreeMap<String, String>,
+}
+
+/// A bind left out of the manifest because of its `requires` or `platforms`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedBind {
+  /// The bind's id, if it has one.
+  pub id: Option<String>,
+  /// Why it was skipped (e.g. "requires systemd", "platform (only aarch64-darwin)").
+  pub reason: String,
+  /// Builds its inputs refer to. An apply leaves them unrealized unless
+  /// another bind needs them.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub builds: Vec<ObjectHash>,
+}
+
+/// A build left out of the manifest because of its `platforms`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedBuild {
+  /// The build's id, if it has one.
+  pub id: Option<String>,
+  /// Why it was skipped (e.g. "platform (only aarch64-darwin)").
+  pub reason: String,
+}
+
+/// Read the `platforms` of a build or bind spec: the platform triples it is
+/// limited to, or none for every platform.
+pub fn spec_platforms(table: &LuaTable, kind: &str) -> LuaResult<Vec<String>> {
+  let platforms: Vec<String> = match table.get::<LuaValue>("platforms")? {
+    LuaValue::Nil => Vec::new(),
+    LuaValue::Table(t) => t
+      .sequence_values::<String>()
+      .collect::<LuaResult<_>>()
+      .map_err(|_| LuaError::external(format!("{} `platforms` must be a list of platform triples", kind)))?,
+    other => {
+      return Err(LuaError::external(format!(
+        "{} `platforms` must be a list of platform triples, got {}",
+        kind,
+        other.type_name()
+      )));
+    }
+  };
+  for platform in &platforms {
+    platform
+      .parse::<Platform>()
+      .map_err(|e| LuaError::external(format!("{} `platforms`: {}", kind, e)))?;
+  }
+  Ok(platforms)
+}
+
+/// Reason to skip a spec limited to `platforms` on this host (`sys.platform`),
+/// if it isn't one of them.
+pub fn unsupported_platform(lua: &Lua, platforms: &[String]) -> LuaResult<Option<String>> {
+  if platforms.is_empty() {
+    return Ok(None);
+  }
+
+  let sys: LuaTable = lua.globals().get("sys")?;
+  let current: String = sys.get("platform")?;
+  if platforms.contains(&current) {
+    return Ok(None);
+  }
+  Ok(Some(format!("platform (only {})", platforms.join(", "))))
+}
+
+/// A build changed by `sys.override_build`, for tracing where it came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverriddenBuild {
+  /// The build's id.
+  pub id: String,
+  /// The hash of the build as overridden.
+  pub hash: ObjectHash,
+  /// Where the overrides that ran were registered (`file:line`), in the order they ran.
+  pub overrides: Vec<String>,
 }
 
 impl Hashable for Manifest {}
+
+impl Manifest {
+  /// Look up a build by hash, accepting a longer or shorter form of its key.
+  pub fn find_build(&self, hash: &ObjectHash) -> Option<(&ObjectHash, &BuildDef)> {
+    find_by_hash(&self.builds, hash)
+  }
+
+  /// Look up a bind by hash, accepting a longer or shorter form of its key.
+  pub fn find_binding(&self, hash: &ObjectHash) -> Option<(&ObjectHash, &BindDef)> {
+    find_by_hash(&self.bindings, hash)
+  }
+
+  /// Hashes of the binds of each bind `group`; binds without a group are left out.
+  pub fn bind_groups(&self) -> BTreeMap<&str, Vec<&ObjectHash>> {
+    let mut groups: BTreeMap<&str, Vec<&ObjectHash>> = BTreeMap::new();
+    for (hash, bind) in &self.bindings {
+      if let Some(group) = &bind.group {
+        groups.entry(group.as_str()).or_default().push(hash);
+      }
+    }
+    groups
+  }
+}
+
+/// Exact lookup first, then keys the hash is a longer form of, then keys that
+/// are a longer form of the hash.
+fn find_by_hash<'a, V>(map: &'a BTreeMap<ObjectHash, V>, hash: &ObjectHash) -> Option<(&'a ObjectHash, &'a V)> {
+  map
+    .get_key_value(hash)
+    .or_else(|| hash.shorter_forms().find_map(|short| map.get_key_value(&short)))
+    .or_else(|| {
+      map
+        .range(hash.clone()..)
+        .next()
+        .filter(|(key, _)| hash.0.len() >= OBJ_HASH_PREFIX_LEN && key.0.starts_with(hash.0.as_str()))
+    })
+}
+
+/// The object hash algorithm and length set by the config's `settings.hash`.
+pub fn registry_hash_spec(lua: &Lua) -> LuaResult<HashSpec> {
+  match lua.named_registry_value::<Option<String>>(HASH_SPEC_REGISTRY_KEY)? {
+    Some(spec) => spec.parse().map_err(LuaError::external),
+    None => Ok(HashSpec::default()),
+  }
+}
+
+/// Hash `def` with `spec`, reusing the hash of an identical definition
+/// declared earlier in this runtime (see [`HashMemo`]).
+pub fn memoized_hash(lua: &Lua, def: &impl Hashable, spec: &HashSpec) -> Result<ObjectHash, HashError> {
+  if lua.app_data_ref::<HashMemo>().is_none() {
+    lua.set_app_data(HashMemo::default());
+  }
+  let mut memo = lua.app_data_mut::<HashMemo>().expect("hash memo was just set");
+  memo.hash(def, spec)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn find_build_accepts_mixed_length_hashes() {
+    let build = BuildDef {
+      id: Some("rg".to_string()),
+      inputs: None,
+      create_actions: vec![],
+      outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
+    };
+    let short = ObjectHash("0123456789abcdef0123".to_string());
+    let long = ObjectHash("0123456789abcdef0123456789ab".to_string());
+
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(short.clone(), build.clone());
+    assert_eq!(manifest.find_build(&long).map(|(key, _)| key), Some(&short));
+
+    let mut manifest = Manifest::default();
+    manifest.builds.insert(long.clone(), build);
+    assert_eq!(manifest.find_build(&short).map(|(key, _)| key), Some(&long));
+    assert!(
+      manifest
+        .find_build(&ObjectHash("fedcba9876543210fedc".to_string()))
+        .is_none()
+    );
+  }
+}
@@ -0,0 +1,517 @@
+//! Placeholder checks for definitions entering the manifest.
+//!
+//! `sys.build` and `sys.bind` run these before adding a definition, so a
+//! placeholder naming a missing build, bind, output or action fails evaluation
+//! instead of the apply that would have resolved it.
+//!
+//! Action references are checked where their numbering is known: build
+//! `create` and `check` actions, build and bind outputs, bind `create` actions
+//! and bind check outputs. Bind `update`, `destroy` and `check` actions also
+//! receive create's outputs, so their action indices aren't checked. Where
+//! indices are checked, so are the names of `$${{action:N:<name>}}`.
+//!
+//! How thoroughly a definition is checked is its [`PlaceholderMode`], set for
+//! the whole config with `settings.placeholders` or per spec with
+//! `placeholders = "..."`:
+//!
+//! - `checked` (the default) runs the checks above at the call.
+//! - `strict` also requires `$${{env:<name>}}` variables to be set during
+//!   evaluation, and checks the definition again once evaluation finished,
+//!   so a reference to a build or bind that a later `replace = true`
+//!   dropped fails before anything runs.
+//! - `deferred` skips the checks, for definitions that intentionally refer
+//!   to values only known when their actions run. Mistakes then fail the
+//!   action that resolves them.
+
+use mlua::prelude::*;
+use serde_json::Value as JsonValue;
+use thiserror::Error;
+
+use crate::action::Action;
+use crate::bind::BindDef;
+use crate::build::BuildDef;
+use crate::manifest::Manifest;
+use crate::placeholder::{self, PlaceholderError, Scope};
+use crate::util::hash::ObjectHash;
+
+/// A placeholder that can't be resolved, and where in the definition it is.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{location}: {source}")]
+pub struct InvalidPlaceholder {
+  /// The part of the definition holding the placeholder, e.g. `create action 2`.
+  pub location: String,
+  pub source: PlaceholderError,
+}
+
+impl InvalidPlaceholder {
+  /// Describe the error for a `kind` (`build` or `bind`) definition, naming its
+  /// id and the Lua code that defined it when known.
+  pub fn describe(&self, kind: &str, id: Option<&str>, caller: Option<&str>) -> String {
+    let mut message = format!("invalid placeholder in {kind}");
+    if let Some(id) = id {
+      message.push_str(&format!(" '{id}'"));
+    }
+    if let Some(caller) = caller {
+      message.push_str(&format!(" at {caller}"));
+    }
+    format!("{message}: {self}")
+  }
+}
+
+/// Lua registry key of the config-wide mode set by `settings.placeholders`.
+pub const PLACEHOLDER_MODE_REGISTRY_KEY: &str = "__syslua_placeholder_mode";
+
+/// Lua registry key of the strict definitions to check again after evaluation.
+const STRICT_PLACEHOLDERS_REGISTRY_KEY: &str = "__syslua_strict_placeholders";
+
+/// How a definition's placeholders are checked during evaluation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlaceholderMode {
+  /// Checked at the call, and again against the finished manifest, with
+  /// `env:` variables required to be set.
+  Strict,
+  /// Checked against the manifest at the call.
+  #[default]
+  Checked,
+  /// Not checked; unresolvable placeholders fail when their action runs.
+  Deferred,
+}
+
+impl std::str::FromStr for PlaceholderMode {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "strict" => Ok(Self::Strict),
+      "checked" => Ok(Self::Checked),
+      "deferred" => Ok(Self::Deferred),
+      other => Err(format!(
+        "unknown placeholder mode '{}' (expected strict, checked or deferred)",
+        other
+      )),
+    }
+  }
+}
+
+impl PlaceholderMode {
+  /// The mode of a spec: its own `placeholders`, else `settings.placeholders`.
+  pub fn for_spec(lua: &Lua, spec: Option<PlaceholderMode>) -> LuaResult<Self> {
+    if let Some(mode) = spec {
+      return Ok(mode);
+    }
+    match lua.named_registry_value::<Option<String>>(PLACEHOLDER_MODE_REGISTRY_KEY)? {
+      Some(mode) => mode.parse().map_err(LuaError::external),
+      None => Ok(Self::default()),
+    }
+  }
+}
+
+/// Read the `placeholders` mode of a `kind` (`build` or `bind`) spec table.
+pub fn spec_placeholder_mode(table: &LuaTable, kind: &str) -> LuaResult<Option<PlaceholderMode>> {
+  table
+    .get::<Option<String>>("placeholders")
+    .map_err(|_| LuaError::external(format!("{} `placeholders` must be a mode string", kind)))?
+    .map(|mode| mode.parse().map_err(LuaError::external))
+    .transpose()
+}
+
+/// What placeholders of one part of a definition may refer to.
+struct ManifestScope<'a> {
+  manifest: &'a Manifest,
+  actions: Option<usize>,
+  /// Named outputs of the actions in the numbering `actions` counts.
+  named: &'a [&'static [&'static str]],
+  binds: bool,
+  env: bool,
+}
+
+impl Scope for ManifestScope<'_> {
+  fn action_count(&self) -> Option<usize> {
+    self.actions
+  }
+
+  fn action_outputs(&self, index: usize) -> Option<Vec<String>> {
+    self.actions?;
+    let named = self.named.get(index)?;
+    Some(named.iter().map(|name| name.to_string()).collect())
+  }
+
+  fn build_outputs(&self, hash: &str) -> Option<Vec<String>> {
+    let def = self.manifest.builds.get(&ObjectHash(hash.to_string()))?;
+    Some(def.outputs.iter().flat_map(|outputs| outputs.keys().cloned()).collect())
+  }
+
+  fn bind_outputs(&self, hash: &str) -> Option<Vec<String>> {
+    let def = self.manifest.bindings.get(&ObjectHash(hash.to_string()))?;
+    Some(def.outputs.iter().flat_map(|outputs| outputs.keys().cloned()).collect())
+  }
+
+  fn allows_binds(&self) -> bool {
+    self.binds
+  }
+
+  fn checks_env(&self) -> bool {
+    self.env
+  }
+}
+
+impl ManifestScope<'_> {
+  fn with_actions(&self, actions: Option<usize>) -> Self {
+    ManifestScope {
+      manifest: self.manifest,
+      actions,
+      named: self.named,
+      binds: self.binds,
+      env: self.env,
+    }
+  }
+
+  /// Validate every string in `value`.
+  fn check(&self, value: &JsonValue, location: impl Fn() -> String) -> Result<(), InvalidPlaceholder> {
+    match value {
+      JsonValue::String(s) => placeholder::validate(s, self).map_err(|source| InvalidPlaceholder {
+        location: location(),
+        source,
+      }),
+      JsonValue::Array(values) => values.iter().try_for_each(|v| self.check(v, &location)),
+      JsonValue::Object(map) => map.values().try_for_each(|v| self.check(v, &location)),
+      JsonValue::Null | JsonValue::Bool(_) | JsonValue::Number(_) => Ok(()),
+    }
+  }
+
+  /// Validate a phase's actions. With `first_index`, each action may only
+  /// reference the actions recorded before it.
+  fn check_actions(
+    &self,
+    actions: &[Action],
+    phase: &str,
+    first_index: Option<usize>,
+  ) -> Result<(), InvalidPlaceholder> {
+    for (i, action) in actions.iter().enumerate() {
+      let scope = self.with_actions(first_index.map(|first| first + i));
+      // Actions are plain data; serializing them can't fail
+      let value = serde_json::to_value(action).unwrap_or(JsonValue::Null);
+      scope.check(&value, || format!("{phase} action {i}"))?;
+    }
+    Ok(())
+  }
+}
+
+/// Check the placeholders of a build against the builds already in `manifest`.
+pub fn validate_build(manifest: &Manifest, def: &BuildDef) -> Result<(), InvalidPlaceholder> {
+  check_build(manifest, def, false)
+}
+
+/// Check the placeholders of a build in `mode`.
+pub fn validate_build_in(manifest: &Manifest, def: &BuildDef, mode: PlaceholderMode) -> Result<(), InvalidPlaceholder> {
+  match mode {
+    PlaceholderMode::Strict => check_build(manifest, def, true),
+    PlaceholderMode::Checked => check_build(manifest, def, false),
+    PlaceholderMode::Deferred => Ok(()),
+  }
+}
+
+fn check_build(manifest: &Manifest, def: &BuildDef, env: bool) -> Result<(), InvalidPlaceholder> {
+  // Check actions continue the numbering of create actions
+  let named: Vec<_> = def
+    .create_actions
+    .iter()
+    .chain(def.check_actions.iter().flatten())
+    .map(Action::named_outputs)
+    .collect();
+  let scope = ManifestScope {
+    manifest,
+    actions: None,
+    named: &named,
+    binds: false,
+    env,
+  };
+  let create_count = def.create_actions.len();
+
+  scope.check_actions(&def.create_actions, "create", Some(0))?;
+  if let Some(check_actions) = &def.check_actions {
+    scope.check_actions(check_actions, "check", Some(create_count))?;
+  }
+
+  let outputs_scope = scope.with_actions(Some(create_count));
+  for (name, value) in def.outputs.iter().flatten() {
+    outputs_scope.check(value, || format!("output '{name}'"))?;
+  }
+
+  Ok(())
+}
+
+/// Check the placeholders of a bind against the builds and binds already in `manifest`.
+pub fn validate_bind(manifest: &Manifest, def: &BindDef) -> Result<(), InvalidPlaceholder> {
+  check_bind(manifest, def, false)
+}
+
+/// Check the placeholders of a bind in `mode`.
+pub fn validate_bind_in(manifest: &Manifest, def: &BindDef, mode: PlaceholderMode) -> Result<(), InvalidPlaceholder> {
+  match mode {
+    PlaceholderMode::Strict => check_bind(manifest, def, true),
+    PlaceholderMode::Checked => check_bind(manifest, def, false),
+    PlaceholderMode::Deferred => Ok(()),
+  }
+}
+
+fn check_bind(manifest: &Manifest, def: &BindDef, env: bool) -> Result<(), InvalidPlaceholder> {
+  let named: Vec<_> = def.create_actions.iter().map(Action::named_outputs).collect();
+  let scope = ManifestScope {
+    manifest,
+    actions: None,
+    named: &named,
+    binds: true,
+    env,
+  };
+
+  scope.check_actions(&def.create_actions, "create", Some(0))?;
+  if let Some(update_actions) = &def.update_actions {
+    scope.check_actions(update_actions, "update", None)?;
+  }
+  scope.check_actions(&def.destroy_actions, "destroy", None)?;
+  if let Some(check_actions) = &def.check_actions {
+    scope.check_actions(check_actions, "check", None)?;
+  }
+
+  let outputs_scope = scope.with_actions(Some(def.create_actions.len()));
+  for (name, value) in def.outputs.iter().flatten() {
+    outputs_scope.check(value, || format!("output '{name}'"))?;
+  }
+
+  if let Some(check_outputs) = &def.check_outputs {
+    let check_actions = def.check_actions.as_deref().unwrap_or_default();
+    let check_named: Vec<_> = check_actions.iter().map(Action::named_outputs).collect();
+    let check_scope = ManifestScope {
+      named: &check_named,
+      ..scope.with_actions(Some(check_actions.len()))
+    };
+    let patterns = std::iter::once(("drifted", &check_outputs.drifted))
+      .chain(check_outputs.message.as_ref().map(|message| ("message", message)));
+    for (name, pattern) in patterns {
+      placeholder::validate(pattern, &check_scope).map_err(|source| InvalidPlaceholder {
+        location: format!("check result '{name}'"),
+        source,
+      })?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Remember a strict build or bind, added to the manifest at `hash`, so
+/// [`finish_strict_placeholders`] checks it again.
+pub fn record_strict_placeholders(
+  lua: &Lua,
+  kind: &'static str,
+  hash: &ObjectHash,
+  caller: Option<&str>,
+) -> LuaResult<()> {
+  let entries = match lua.named_registry_value::<Option<LuaTable>>(STRICT_PLACEHOLDERS_REGISTRY_KEY)? {
+    Some(entries) => entries,
+    None => {
+      let entries = lua.create_table()?;
+      lua.set_named_registry_value(STRICT_PLACEHOLDERS_REGISTRY_KEY, &entries)?;
+      entries
+    }
+  };
+  let entry = lua.create_table()?;
+  entry.set("kind", kind)?;
+  entry.set("hash", hash.0.as_str())?;
+  entry.set("caller", caller)?;
+  entries.push(entry)
+}
+
+/// Check the strict builds and binds again once evaluation finished.
+///
+/// Definitions a later one with the same id replaced are skipped. Fails for
+/// the first placeholder the finished manifest can't resolve.
+pub fn finish_strict_placeholders(lua: &Lua, manifest: &Manifest) -> LuaResult<()> {
+  let Some(entries) = lua.named_registry_value::<Option<LuaTable>>(STRICT_PLACEHOLDERS_REGISTRY_KEY)? else {
+    return Ok(());
+  };
+  for entry in entries.sequence_values::<LuaTable>() {
+    let entry = entry?;
+    let kind: String = entry.get("kind")?;
+    let hash = ObjectHash(entry.get("hash")?);
+    let caller: Option<String> = entry.get("caller")?;
+    let (id, result) = if kind == "build" {
+      let Some(def) = manifest.builds.get(&hash) else {
+        continue;
+      };
+      (def.id.as_deref(), check_build(manifest, def, true))
+    } else {
+      let Some(def) = manifest.bindings.get(&hash) else {
+        continue;
+      };
+      (def.id.as_deref(), check_bind(manifest, def, true))
+    };
+    result.map_err(|e| LuaError::external(e.describe(&kind, id, caller.as_deref())))?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use super::*;
+  use crate::action::actions::exec::ExecOpts;
+
+  fn exec(bin: &str) -> Action {
+    Action::Exec(ExecOpts::new(bin))
+  }
+
+  fn build(outputs: &[(&str, &str)], create_actions: Vec<Action>) -> BuildDef {
+    BuildDef {
+      id: None,
+      inputs: None,
+      outputs: Some(
+        outputs
+          .iter()
+          .map(|(k, v)| (k.to_string(), JsonValue::String(v.to_string())))
+          .collect::<BTreeMap<_, _>>(),
+      ),
+      create_actions,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
+    }
+  }
+
+  fn manifest_with_build(hash: &str) -> Manifest {
+    let mut manifest = Manifest::default();
+    manifest
+      .builds
+      .insert(ObjectHash(hash.to_string()), build(&[("out", "$${{out}}")], vec![]));
+    manifest
+  }
+
+  #[test]
+  fn valid_build_passes() {
+    let manifest = manifest_with_build("rg123");
+    let def = build(
+      &[("out", "$${{action:1}}")],
+      vec![exec("$${{build:rg123:out}}/bin/rg"), exec("echo $${{action:0}}")],
+    );
+    assert_eq!(validate_build(&manifest, &def), Ok(()));
+  }
+
+  #[test]
+  fn build_errors_name_the_location() {
+    let manifest = manifest_with_build("rg123");
+
+    let def = build(&[("out", "$${{out}}")], vec![exec("$${{build:rg123:bin}}")]);
+    let err = validate_build(&manifest, &def).unwrap_err();
+    assert_eq!(err.location, "create action 0");
+    assert!(matches!(err.source, PlaceholderError::UnknownBuildOutput { .. }));
+
+    let def = build(&[("out", "$${{out}}")], vec![exec("echo $${{action:0}}")]);
+    let err = validate_build(&manifest, &def).unwrap_err();
+    assert_eq!(
+      err.source,
+      PlaceholderError::ActionOutOfRange { index: 0, available: 0 }
+    );
+
+    let def = build(&[("bin", "$${{action:3}}")], vec![exec("true")]);
+    let err = validate_build(&manifest, &def).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "output 'bin': action 3 does not exist (only 1 actions recorded before it)"
+    );
+
+    let def = build(&[("out", "$${{bind:link456:path}}")], vec![]);
+    let err = validate_build(&manifest, &def).unwrap_err();
+    assert_eq!(err.source, PlaceholderError::BindInBuild("link456".to_string()));
+  }
+
+  #[test]
+  fn describe_names_definition_and_caller() {
+    let err = InvalidPlaceholder {
+      location: "create action 1".to_string(),
+      source: PlaceholderError::UnknownBuild("abc".to_string()),
+    };
+    assert_eq!(
+      err.describe("bind", Some("nvim-config"), Some("init.lua:12")),
+      "invalid placeholder in bind 'nvim-config' at init.lua:12: create action 1: unknown build: abc"
+    );
+    assert_eq!(
+      err.describe("build", None, None),
+      "invalid placeholder in build: create action 1: unknown build: abc"
+    );
+  }
+
+  #[test]
+  fn build_check_actions_continue_create_numbering() {
+    let manifest = Manifest::default();
+    let mut def = build(&[("out", "$${{out}}")], vec![exec("make")]);
+    def.check_actions = Some(vec![exec("test -x $${{action:0}}"), exec("echo $${{action:1}}")]);
+    assert_eq!(validate_build(&manifest, &def), Ok(()));
+
+    def.check_actions = Some(vec![exec("echo $${{action:1}}")]);
+    let err = validate_build(&manifest, &def).unwrap_err();
+    assert_eq!(err.location, "check action 0");
+  }
+
+  #[test]
+  fn action_output_names_are_checked() {
+    let manifest = Manifest::default();
+    let fetch = Action::FetchUrl {
+      url: "https://example.com/src.tar.gz".to_string(),
+      sha256: "abc".to_string(),
+      unpack: true,
+    };
+    let def = build(
+      &[("out", "$${{action:0:src}}")],
+      vec![fetch.clone(), exec("tar tf $${{action:0:archive}}")],
+    );
+    assert_eq!(validate_build(&manifest, &def), Ok(()));
+
+    let def = build(&[("out", "$${{out}}")], vec![fetch, exec("ls $${{action:0:source}}")]);
+    let err = validate_build(&manifest, &def).unwrap_err();
+    assert_eq!(err.location, "create action 1");
+    assert_eq!(
+      err.source.to_string(),
+      "action 0 has no output 'source' (outputs: archive, src)"
+    );
+
+    let def = build(&[("out", "$${{action:0:src}}")], vec![exec("make")]);
+    let err = validate_build(&manifest, &def).unwrap_err();
+    assert_eq!(err.source.to_string(), "action 0 has no output 'src' (outputs: none)");
+  }
+
+  #[test]
+  fn strict_mode_requires_env_variables() {
+    let manifest = Manifest::default();
+    let def = build(
+      &[("out", "$${{out}}")],
+      vec![exec("$${{env:SYSLUA_SURELY_UNSET_VARIABLE}}/bin/tool")],
+    );
+    assert_eq!(validate_build_in(&manifest, &def, PlaceholderMode::Checked), Ok(()));
+    let err = validate_build_in(&manifest, &def, PlaceholderMode::Strict).unwrap_err();
+    assert_eq!(
+      err.source,
+      PlaceholderError::UnresolvedEnv("SYSLUA_SURELY_UNSET_VARIABLE".to_string())
+    );
+
+    let def = build(&[("out", "$${{env:PATH}}")], vec![]);
+    assert_eq!(validate_build_in(&manifest, &def, PlaceholderMode::Strict), Ok(()));
+  }
+
+  #[test]
+  fn deferred_mode_skips_checks() {
+    let manifest = Manifest::default();
+    let def = build(&[("out", "$${{build:missing:out}}")], vec![exec("echo $${{action:4}}")]);
+    assert!(validate_build_in(&manifest, &def, PlaceholderMode::Checked).is_err());
+    assert_eq!(validate_build_in(&manifest, &def, PlaceholderMode::Deferred), Ok(()));
+  }
+
+  #[test]
+  fn placeholder_modes_parse() {
+    assert_eq!("strict".parse(), Ok(PlaceholderMode::Strict));
+    assert_eq!("deferred".parse(), Ok(PlaceholderMode::Deferred));
+    assert!("lazy".parse::<PlaceholderMode>().is_err());
+  }
+}
@@ -65,7 +65,7 @@ fn lua_value_to_json(value: LuaValue) -> LuaResult<JsonValue> {
 }
 
 /// Convert a JSON value to a Lua value.
-fn json_to_lua_value(lua: &Lua, value: &JsonValue) -> LuaResult<LuaValue> {
+pub(crate) fn json_to_lua_value(lua: &Lua, value: &JsonValue) -> LuaResult<LuaValue> {
   match value {
     JsonValue::Null => Ok(LuaValue::Nil),
     JsonValue::Bool(b) => Ok(LuaValue::Boolean(*b)),
@@ -1,5 +1,7 @@
 //! Build and bind output path resolution.
 //!
-//! Provides utilities for resolving output paths from build/bind hashes.
+//! Provides utilities for resolving output paths from build/bind hashes, and
+//! for checking outputs against the types a spec declares.
 
 pub mod lua;
+pub mod schema;
@@ -0,0 +1,273 @@
+//! Declared output types of builds and binds.
+//!
+//! A spec may declare the outputs its `create` returns:
+//!
+//! ```lua
+//! sys.build({
+//!   id = "ripgrep",
+//!   outputs = { out = "dir", bin = "file", version = "string?" },
+//!   create = function(inputs, ctx) ... end,
+//! })
+//! ```
+//!
+//! `sys.build` and `sys.bind` check the returned outputs against the
+//! declaration, so a misspelled or missing output fails evaluation with the
+//! spec's id instead of a placeholder failing deep in execution. A trailing `?`
+//! makes an output optional. The declaration is not part of the hash.
+//!
+//! Outputs are mostly placeholders at evaluation time, so `dir`, `file` and
+//! `path` only check for a non-empty string; they document what the value
+//! resolves to.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use mlua::prelude::*;
+use serde_json::Value as JsonValue;
+use thiserror::Error;
+
+/// The kind of value a declared output holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputType {
+  Dir,
+  File,
+  Path,
+  String,
+  Number,
+  Boolean,
+  Table,
+}
+
+impl OutputType {
+  const NAMES: &[&str] = &["dir", "file", "path", "string", "number", "boolean", "table"];
+
+  fn accepts(self, value: &JsonValue) -> bool {
+    match self {
+      OutputType::Dir | OutputType::File | OutputType::Path => value.as_str().is_some_and(|s| !s.is_empty()),
+      OutputType::String => value.is_string(),
+      OutputType::Number => value.is_number(),
+      OutputType::Boolean => value.is_boolean(),
+      OutputType::Table => value.is_array() || value.is_object(),
+    }
+  }
+}
+
+impl FromStr for OutputType {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "dir" => Ok(Self::Dir),
+      "file" => Ok(Self::File),
+      "path" => Ok(Self::Path),
+      "string" => Ok(Self::String),
+      "number" => Ok(Self::Number),
+      "boolean" => Ok(Self::Boolean),
+      "table" => Ok(Self::Table),
+      other => Err(format!(
+        "unknown output type '{}' (expected one of: {}, optionally followed by '?')",
+        other,
+        Self::NAMES.join(", ")
+      )),
+    }
+  }
+}
+
+impl fmt::Display for OutputType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      OutputType::Dir => "dir",
+      OutputType::File => "file",
+      OutputType::Path => "path",
+      OutputType::String => "string",
+      OutputType::Number => "number",
+      OutputType::Boolean => "boolean",
+      OutputType::Table => "table",
+    };
+    f.write_str(name)
+  }
+}
+
+/// A declared output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputDecl {
+  pub type_: OutputType,
+  pub optional: bool,
+}
+
+/// The `outputs` declaration of a build or bind spec.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputSchema(pub BTreeMap<String, OutputDecl>);
+
+impl FromLua for OutputSchema {
+  fn from_lua(value: LuaValue, _lua: &Lua) -> LuaResult<Self> {
+    let LuaValue::Table(table) = value else {
+      return Err(LuaError::external(format!(
+        "`outputs` must map output names to types, got {}",
+        value.type_name()
+      )));
+    };
+
+    let mut outputs = BTreeMap::new();
+    for pair in table.pairs::<String, String>() {
+      let (name, type_) = pair.map_err(|_| LuaError::external("`outputs` must map output names to type strings"))?;
+      let (type_, optional) = match type_.strip_suffix('?') {
+        Some(type_) => (type_, true),
+        None => (type_.as_str(), false),
+      };
+      let type_ = type_
+        .parse::<OutputType>()
+        .map_err(|e| LuaError::external(format!("output '{}': {}", name, e)))?;
+      outputs.insert(name, OutputDecl { type_, optional });
+    }
+    Ok(OutputSchema(outputs))
+  }
+}
+
+/// How returned outputs differ from their declaration.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum OutputMismatch {
+  #[error("create returned undeclared output '{name}' (declared: {declared})")]
+  Undeclared { name: String, declared: String },
+
+  #[error("create did not return declared output '{name}'")]
+  Missing { name: String },
+
+  #[error("output '{name}' must be a {expected}, got {actual}")]
+  WrongType {
+    name: String,
+    expected: OutputType,
+    actual: String,
+  },
+}
+
+impl OutputMismatch {
+  /// Describe the error for a `kind` (`build` or `bind`) definition, naming its
+  /// id and the Lua code that defined it when known.
+  pub fn describe(&self, kind: &str, id: Option<&str>, caller: Option<&str>) -> String {
+    let mut message = format!("outputs of {kind}");
+    if let Some(id) = id {
+      message.push_str(&format!(" '{id}'"));
+    }
+    if let Some(caller) = caller {
+      message.push_str(&format!(" at {caller}"));
+    }
+    format!("{message} don't match their declaration: {self}")
+  }
+}
+
+impl OutputSchema {
+  /// Check `outputs` (`None` when create returned none) against the declaration.
+  pub fn validate(&self, outputs: Option<&BTreeMap<String, JsonValue>>) -> Result<(), OutputMismatch> {
+    let empty = BTreeMap::new();
+    let outputs = outputs.unwrap_or(&empty);
+
+    for (name, value) in outputs {
+      let Some(decl) = self.0.get(name) else {
+        return Err(OutputMismatch::Undeclared {
+          name: name.clone(),
+          declared: self.0.keys().cloned().collect::<Vec<_>>().join(", "),
+        });
+      };
+      if !decl.type_.accepts(value) {
+        return Err(OutputMismatch::WrongType {
+          name: name.clone(),
+          expected: decl.type_,
+          actual: json_type_name(value).to_string(),
+        });
+      }
+    }
+
+    match self
+      .0
+      .iter()
+      .find(|(name, decl)| !decl.optional && !outputs.contains_key(*name))
+    {
+      Some((name, _)) => Err(OutputMismatch::Missing { name: name.clone() }),
+      None => Ok(()),
+    }
+  }
+}
+
+/// The Lua name of a JSON value's type.
+fn json_type_name(value: &JsonValue) -> &'static str {
+  match value {
+    JsonValue::Null => "nil",
+    JsonValue::Bool(_) => "boolean",
+    JsonValue::Number(_) => "number",
+    JsonValue::String(s) if s.is_empty() => "empty string",
+    JsonValue::String(_) => "string",
+    JsonValue::Array(_) | JsonValue::Object(_) => "table",
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn schema(lua: &Lua, source: &str) -> LuaResult<OutputSchema> {
+    lua.load(source).eval::<OutputSchema>()
+  }
+
+  fn outputs(pairs: &[(&str, JsonValue)]) -> BTreeMap<String, JsonValue> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+  }
+
+  #[test]
+  fn parses_types_and_optional_marker() -> LuaResult<()> {
+    let lua = Lua::new();
+    let parsed = schema(&lua, r#"return { out = "dir", version = "string?" }"#)?;
+    assert_eq!(
+      parsed.0.get("version"),
+      Some(&OutputDecl {
+        type_: OutputType::String,
+        optional: true
+      })
+    );
+    assert!(!parsed.0["out"].optional);
+
+    let err = schema(&lua, r#"return { out = "directory" }"#).unwrap_err().to_string();
+    assert!(err.contains("output 'out': unknown output type 'directory'"), "{err}");
+    Ok(())
+  }
+
+  #[test]
+  fn validate_reports_undeclared_missing_and_mistyped_outputs() -> LuaResult<()> {
+    let lua = Lua::new();
+    let schema = schema(&lua, r#"return { out = "dir", bin = "file", version = "string?" }"#)?;
+    let out = JsonValue::String("$${{out}}".to_string());
+
+    assert_eq!(
+      schema.validate(Some(&outputs(&[("out", out.clone()), ("bin", out.clone())]))),
+      Ok(())
+    );
+    assert_eq!(
+      schema.validate(Some(&outputs(&[("out", out.clone()), ("bni", out.clone())]))),
+      Err(OutputMismatch::Undeclared {
+        name: "bni".to_string(),
+        declared: "bin, out, version".to_string(),
+      })
+    );
+    assert_eq!(
+      schema.validate(Some(&outputs(&[("out", out.clone())]))),
+      Err(OutputMismatch::Missing {
+        name: "bin".to_string()
+      })
+    );
+    assert_eq!(
+      schema.validate(Some(&outputs(&[
+        ("out", out.clone()),
+        ("bin", out),
+        ("version", JsonValue::from(14))
+      ]))),
+      Err(OutputMismatch::WrongType {
+        name: "version".to_string(),
+        expected: OutputType::String,
+        actual: "number".to_string(),
+      })
+    );
+    assert!(schema.validate(None).is_err());
+    Ok(())
+  }
+}
@@ -9,10 +9,14 @@
 //! Uses `$${{}}` delimiters (double-dollar, double-brace) for shell safety:
 //!
 //! - `$${{action:N}}` - stdout of action at index N within the same spec
+//! - `$${{action:N:<name>}}` - named sub-output of action N (`archive` and
+//!   `src` of a `fetch_url` with `unpack = true`)
 //! - `$${{build:<hash>:<output>}}` - output from a realized build
 //! - `$${{bind:<hash>:<output>}}` - output from an applied bind
 //! - `$${{out}}` - the current build/bind's output directory
 //! - `$${{env:<name>}}` - environment variable resolved at execution time
+//! - `$${{prev:<output>}}` - output of the bind recorded by the previous apply,
+//!   in its update actions
 //!
 //! # Shell Variables
 //!
@@ -24,6 +28,15 @@
 //! Use `$$$` before `{{` to produce a literal `$${{` sequence. This is only
 //! needed in the rare case where you want literal `$${{` in output.
 //!
+//! # Validation
+//!
+//! [`validate`] checks a string's placeholders against a [`Scope`] without
+//! resolving them: unknown builds and binds, unknown output names and action
+//! indices past the recorded actions. Manifest evaluation runs it for every
+//! build and bind, so mistakes surface at the `sys.build`/`sys.bind` call
+//! instead of halfway through an apply. How thoroughly is up to the spec's
+//! [`PlaceholderMode`](crate::manifest::PlaceholderMode).
+//!
 //! # Example
 //!
 //! ```
@@ -44,6 +57,9 @@ pub enum Placeholder {
   /// `$${{action:N}}` - stdout of action at index N
   Action(usize),
 
+  /// `$${{action:N:<output>}}` - named sub-output of action N
+  ActionOutput { index: usize, output: String },
+
   /// `$${{build:<hash>:<output>}}` - output from realized build
   Build { hash: String, output: String },
 
@@ -55,6 +71,10 @@ pub enum Placeholder {
 
   /// `$${{env:<name>}}` - environment variable resolved at execution time
   Env(String),
+
+  /// `$${{prev:<output>}}` - output recorded by the previous apply of the
+  /// bind being updated
+  Prev(String),
 }
 
 /// A segment of parsed text.
@@ -85,6 +105,9 @@ pub enum PlaceholderError {
   #[error("unresolved action: index {0}")]
   UnresolvedAction(usize),
 
+  #[error("unresolved action output: index {index} output '{output}'")]
+  UnresolvedActionOutput { index: usize, output: String },
+
   #[error("unresolved build: {hash} output '{output}'")]
   UnresolvedBuild { hash: String, output: String },
 
@@ -93,6 +116,42 @@ pub enum PlaceholderError {
 
   #[error("unresolved env variable: {0}")]
   UnresolvedEnv(String),
+
+  #[error("unresolved previous output '{0}' (only bind updates have previous outputs)")]
+  UnresolvedPrev(String),
+
+  #[error("action {index} does not exist (only {available} actions recorded before it)")]
+  ActionOutOfRange { index: usize, available: usize },
+
+  #[error("action {index} has no output '{output}' (outputs: {})", if available.is_empty() { "none".to_string() } else { available.join(", ") })]
+  UnknownActionOutput {
+    index: usize,
+    output: String,
+    available: Vec<String>,
+  },
+
+  #[error("unknown build: {0}")]
+  UnknownBuild(String),
+
+  #[error("build {hash} has no output '{output}' (outputs: {})", available.join(", "))]
+  UnknownBuildOutput {
+    hash: String,
+    output: String,
+    available: Vec<String>,
+  },
+
+  #[error("unknown bind: {0}")]
+  UnknownBind(String),
+
+  #[error("bind {hash} has no output '{output}' (outputs: {})", available.join(", "))]
+  UnknownBindOutput {
+    hash: String,
+    output: String,
+    available: Vec<String>,
+  },
+
+  #[error("builds cannot reference binds: {0}")]
+  BindInBuild(String),
 }
 
 /// Trait for resolving placeholder values during execution.
@@ -100,6 +159,15 @@ pub trait Resolver {
   /// Resolve an action output by index.
   fn resolve_action(&self, index: usize) -> Result<&str, PlaceholderError>;
 
+  /// Resolve a named sub-output of the action at `index`. Only actions
+  /// with named outputs have any.
+  fn resolve_action_output(&self, index: usize, output: &str) -> Result<&str, PlaceholderError> {
+    Err(PlaceholderError::UnresolvedActionOutput {
+      index,
+      output: output.to_string(),
+    })
+  }
+
   /// Resolve a build output by hash and output name.
   fn resolve_build(&self, hash: &str, output: &str) -> Result<&str, PlaceholderError>;
 
@@ -111,6 +179,39 @@ pub trait Resolver {
 
   /// Resolve an environment variable by name.
   fn resolve_env(&self, name: &str) -> Result<String, PlaceholderError>;
+
+  /// Resolve an output recorded by the previous apply of the bind being
+  /// updated. Nothing else has previous outputs.
+  fn resolve_prev(&self, output: &str) -> Result<&str, PlaceholderError> {
+    Err(PlaceholderError::UnresolvedPrev(output.to_string()))
+  }
+}
+
+/// What placeholders may refer to, for [`validate`].
+pub trait Scope {
+  /// Number of actions whose output may be referenced, or `None` if action
+  /// references can't be checked.
+  fn action_count(&self) -> Option<usize>;
+
+  /// Named sub-outputs of the action at `index`, or `None` if they can't be
+  /// checked.
+  fn action_outputs(&self, _index: usize) -> Option<Vec<String>> {
+    None
+  }
+
+  /// Output names of the build with this hash, or `None` if there is none.
+  fn build_outputs(&self, hash: &str) -> Option<Vec<String>>;
+
+  /// Output names of the bind with this hash, or `None` if there is none.
+  fn bind_outputs(&self, hash: &str) -> Option<Vec<String>>;
+
+  /// Whether bind placeholders are allowed (builds cannot depend on binds).
+  fn allows_binds(&self) -> bool;
+
+  /// Whether `$${{env:<name>}}` variables must already be set.
+  fn checks_env(&self) -> bool {
+    false
+  }
 }
 
 /// Parse a string containing placeholders into segments.
@@ -120,10 +221,12 @@ pub trait Resolver {
 /// Uses `$${{}}` delimiters (double-dollar, double-brace) for shell safety:
 ///
 /// - `$${{action:N}}` - reference action stdout at index N
+/// - `$${{action:N:OUTPUT}}` - reference a named sub-output of action N
 /// - `$${{build:HASH:OUTPUT}}` - reference build output
 /// - `$${{bind:HASH:OUTPUT}}` - reference bind output
 /// - `$${{out}}` - reference the current build/bind's output directory
 /// - `$${{env:NAME}}` - reference environment variable at execution time
+/// - `$${{prev:OUTPUT}}` - reference the bind's previously recorded output
 ///
 /// # Escaping
 ///
@@ -230,10 +333,23 @@ fn parse_placeholder_content(content: &str) -> Result<Placeholder, PlaceholderEr
 
   match kind {
     "action" => {
-      let index = rest
+      let (index, output) = match rest.split_once(':') {
+        Some((index, output)) => (index, Some(output)),
+        None => (rest, None),
+      };
+      let index = index
         .parse::<usize>()
-        .map_err(|_| PlaceholderError::InvalidActionIndex(rest.to_string()))?;
-      Ok(Placeholder::Action(index))
+        .map_err(|_| PlaceholderError::InvalidActionIndex(index.to_string()))?;
+      match output {
+        None => Ok(Placeholder::Action(index)),
+        Some("") => Err(PlaceholderError::Malformed(format!(
+          "action placeholder missing output name: '{content}'"
+        ))),
+        Some(output) => Ok(Placeholder::ActionOutput {
+          index,
+          output: output.to_string(),
+        }),
+      }
     }
     "build" => {
       let (hash, output) = rest
@@ -253,7 +369,17 @@ fn parse_placeholder_content(content: &str) -> Result<Placeholder, PlaceholderEr
         output: output.to_string(),
       })
     }
+    "env" if rest.is_empty() => Err(PlaceholderError::Malformed(format!(
+      "env placeholder missing variable name: '{content}'"
+    ))),
     "env" => Ok(Placeholder::Env(rest.to_string())),
+    "prev" if rest.is_empty() => Err(PlaceholderError::Malformed(format!(
+      "prev placeholder missing output name: '{content}'"
+    ))),
+    "prev" => Ok(Placeholder::Prev(rest.to_string())),
+    "out" => Err(PlaceholderError::Malformed(format!(
+      "out placeholder takes no arguments: '{content}'"
+    ))),
     _ => Err(PlaceholderError::UnknownType(kind.to_string())),
   }
 }
@@ -270,6 +396,85 @@ pub fn substitute(input: &str, resolver: &impl Resolver) -> Result<String, Place
   substitute_segments(&segments, resolver)
 }
 
+/// Check that every placeholder in a string refers to something in `scope`.
+///
+/// # Errors
+///
+/// Returns the first parse error or placeholder that `scope` can't satisfy.
+pub fn validate(input: &str, scope: &impl Scope) -> Result<(), PlaceholderError> {
+  for segment in parse(input)? {
+    let Segment::Placeholder(placeholder) = segment else {
+      continue;
+    };
+
+    match placeholder {
+      Placeholder::Action(index) => {
+        if let Some(available) = scope.action_count() {
+          if index >= available {
+            return Err(PlaceholderError::ActionOutOfRange { index, available });
+          }
+        }
+      }
+      Placeholder::ActionOutput { index, output } => {
+        if let Some(available) = scope.action_count()
+          && index >= available
+        {
+          return Err(PlaceholderError::ActionOutOfRange { index, available });
+        }
+        if let Some(available) = scope.action_outputs(index)
+          && !available.contains(&output)
+        {
+          return Err(PlaceholderError::UnknownActionOutput {
+            index,
+            output,
+            available,
+          });
+        }
+      }
+      Placeholder::Build { hash, output } => {
+        let available = scope
+          .build_outputs(&hash)
+          .ok_or_else(|| PlaceholderError::UnknownBuild(hash.clone()))?;
+        if !available.contains(&output) {
+          return Err(PlaceholderError::UnknownBuildOutput {
+            hash,
+            output,
+            available,
+          });
+        }
+      }
+      Placeholder::Bind { hash, output } => {
+        if !scope.allows_binds() {
+          return Err(PlaceholderError::BindInBuild(hash));
+        }
+        let available = scope
+          .bind_outputs(&hash)
+          .ok_or_else(|| PlaceholderError::UnknownBind(hash.clone()))?;
+        if !available.contains(&output) {
+          return Err(PlaceholderError::UnknownBindOutput {
+            hash,
+            output,
+            available,
+          });
+        }
+      }
+      Placeholder::Prev(output) => {
+        if !scope.allows_binds() {
+          return Err(PlaceholderError::UnresolvedPrev(output));
+        }
+      }
+      Placeholder::Env(name) => {
+        if scope.checks_env() && std::env::var_os(&name).is_none() {
+          return Err(PlaceholderError::UnresolvedEnv(name));
+        }
+      }
+      Placeholder::Out => {}
+    }
+  }
+
+  Ok(())
+}
+
 /// Substitute placeholders in pre-parsed segments.
 ///
 /// Use this when you've already parsed the string and want to substitute
@@ -283,10 +488,14 @@ pub fn substitute_segments(segments: &[Segment], resolver: &impl Resolver) -> Re
       Segment::Placeholder(p) => {
         match p {
           Placeholder::Action(index) => result.push_str(resolver.resolve_action(*index)?),
+          Placeholder::ActionOutput { index, output } => {
+            result.push_str(resolver.resolve_action_output(*index, output)?)
+          }
           Placeholder::Build { hash, output } => result.push_str(resolver.resolve_build(hash, output)?),
           Placeholder::Bind { hash, output } => result.push_str(resolver.resolve_bind(hash, output)?),
           Placeholder::Out => result.push_str(resolver.resolve_out()?),
           Placeholder::Env(name) => result.push_str(&resolver.resolve_env(name)?),
+          Placeholder::Prev(output) => result.push_str(resolver.resolve_prev(output)?),
         };
       }
     }
@@ -573,6 +782,36 @@ export PATH=/store/obj/go-1.21.0-go123/bin:/store/obj/rust-1.75.0-rust456/bin:$P
     assert!(matches!(result, Err(PlaceholderError::InvalidActionIndex(ref s)) if s == "foo"));
   }
 
+  #[test]
+  fn parse_action_output() {
+    assert_eq!(
+      parse("$${{action:2:src}}/configure").unwrap(),
+      vec![
+        Segment::Placeholder(Placeholder::ActionOutput {
+          index: 2,
+          output: "src".to_string(),
+        }),
+        Segment::Literal("/configure".to_string()),
+      ]
+    );
+    assert!(matches!(parse("$${{action:2:}}"), Err(PlaceholderError::Malformed(_))));
+    assert!(matches!(
+      parse("$${{action:x:src}}"),
+      Err(PlaceholderError::InvalidActionIndex(ref s)) if s == "x"
+    ));
+  }
+
+  #[test]
+  fn error_unresolved_action_output() {
+    // Resolvers without named outputs reject them
+    let resolver = TestResolver::new().with_action("/tmp/a.tar.gz");
+    let result = substitute("$${{action:0:src}}", &resolver);
+    assert!(matches!(
+      result,
+      Err(PlaceholderError::UnresolvedActionOutput { index: 0, ref output }) if output == "src"
+    ));
+  }
+
   #[test]
   fn error_malformed_missing_colon() {
     let result = parse("$${{action}}");
@@ -785,4 +1024,115 @@ export PATH=/store/obj/go-1.21.0-go123/bin:/store/obj/rust-1.75.0-rust456/bin:$P
     let result = substitute(cmd, &resolver).unwrap();
     assert_eq!(result, "echo $HOME vs /resolved/home");
   }
+
+  // ==========================================================================
+  // $${{prev:OUTPUT}} Placeholder Tests
+  // ==========================================================================
+
+  #[test]
+  fn parse_prev_placeholder() {
+    assert_eq!(
+      parse("$${{prev:packages}}").unwrap(),
+      vec![Segment::Placeholder(Placeholder::Prev("packages".to_string()))]
+    );
+    assert!(matches!(parse("$${{prev:}}"), Err(PlaceholderError::Malformed(_))));
+  }
+
+  #[test]
+  fn prev_is_unresolved_by_default() {
+    let result = substitute("$${{prev:packages}}", &TestResolver::new());
+    assert_eq!(result, Err(PlaceholderError::UnresolvedPrev("packages".to_string())));
+  }
+
+  // ==========================================================================
+  // Validation
+  // ==========================================================================
+
+  struct TestScope {
+    actions: Option<usize>,
+    binds: bool,
+  }
+
+  impl Scope for TestScope {
+    fn action_count(&self) -> Option<usize> {
+      self.actions
+    }
+
+    fn build_outputs(&self, hash: &str) -> Option<Vec<String>> {
+      (hash == "rg123").then(|| vec!["bin".to_string(), "out".to_string()])
+    }
+
+    fn bind_outputs(&self, hash: &str) -> Option<Vec<String>> {
+      (hash == "link456").then(|| vec!["path".to_string()])
+    }
+
+    fn allows_binds(&self) -> bool {
+      self.binds
+    }
+  }
+
+  #[test]
+  fn validate_accepts_known_references() {
+    let scope = TestScope {
+      actions: Some(2),
+      binds: true,
+    };
+    let input = "$${{action:1}} $${{build:rg123:bin}} $${{bind:link456:path}} $${{out}} $${{env:HOME}} $HOME";
+    assert_eq!(validate(input, &scope), Ok(()));
+  }
+
+  #[test]
+  fn validate_rejects_unknown_references() {
+    let scope = TestScope {
+      actions: Some(2),
+      binds: true,
+    };
+
+    assert_eq!(
+      validate("$${{action:2}}", &scope),
+      Err(PlaceholderError::ActionOutOfRange { index: 2, available: 2 })
+    );
+    assert_eq!(
+      validate("$${{build:nope:out}}", &scope),
+      Err(PlaceholderError::UnknownBuild("nope".to_string()))
+    );
+    assert_eq!(
+      validate("$${{build:rg123:lib}}", &scope),
+      Err(PlaceholderError::UnknownBuildOutput {
+        hash: "rg123".to_string(),
+        output: "lib".to_string(),
+        available: vec!["bin".to_string(), "out".to_string()],
+      })
+    );
+    assert_eq!(
+      validate("$${{bind:link456:dest}}", &scope).unwrap_err().to_string(),
+      "bind link456 has no output 'dest' (outputs: path)"
+    );
+    assert!(matches!(
+      validate("$${{out:x}}", &scope),
+      Err(PlaceholderError::Malformed(_))
+    ));
+    assert!(matches!(
+      validate("$${{env:}}", &scope),
+      Err(PlaceholderError::Malformed(_))
+    ));
+  }
+
+  #[test]
+  fn validate_respects_scope_limits() {
+    let scope = TestScope {
+      actions: None,
+      binds: false,
+    };
+
+    assert_eq!(validate("$${{action:9}}", &scope), Ok(()));
+    assert_eq!(
+      validate("$${{bind:link456:path}}", &scope),
+      Err(PlaceholderError::BindInBuild("link456".to_string()))
+    );
+    assert_eq!(
+      validate("$${{prev:packages}}", &scope),
+      Err(PlaceholderError::UnresolvedPrev("packages".to_string()))
+    );
+  }
 }
@@ -0,0 +1,32 @@
+//! Cross-platform archive extraction.
+//!
+//! Archives are extracted with the system `tar`. On Windows that is the
+//! bsdtar shipped in `System32` (Windows 10 and later), which also reads
+//! `.zip`; it is named explicitly because a GNU tar earlier on `PATH` (from
+//! Git for Windows or MSYS) takes `C:\...` for a remote host.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The `tar` executable archives are extracted with.
+#[cfg(windows)]
+pub fn tar_program() -> PathBuf {
+  let system_root = std::env::var_os("SystemRoot").unwrap_or_else(|| "C:\\Windows".into());
+  PathBuf::from(system_root).join("System32").join("tar.exe")
+}
+
+/// The `tar` executable archives are extracted with.
+#[cfg(not(windows))]
+pub fn tar_program() -> PathBuf {
+  PathBuf::from("tar")
+}
+
+/// Command extracting `archive` into the existing directory `dest`.
+///
+/// The caller runs it (blocking, or through `tokio::process::Command::from`)
+/// and reports its stderr on failure.
+pub fn extract_command(archive: &Path, dest: &Path) -> Command {
+  let mut command = Command::new(tar_program());
+  command.arg("-xf").arg(archive).arg("-C").arg(dest);
+  command
+}
@@ -0,0 +1,152 @@
+//! Per-build resource limits via Linux cgroups (v2).
+//!
+//! When a build declares `resources`, its commands run inside a dedicated
+//! cgroup under `/sys/fs/cgroup/syslua` with `cpu.max` and `memory.max` set.
+//! Each realization gets its own cgroup, so concurrent applies building the
+//! same hash don't share one, and commands join it before they exec, so none
+//! of their children escape it.
+//!
+//! ## Platform Behavior
+//!
+//! - **Linux**: Requires the unified (v2) hierarchy and write access to the
+//!   cgroup root, which in practice means running elevated.
+//! - **Other platforms**: Limits are not enforced; only the scheduler weight applies.
+//!
+//! Limits are best-effort: if the cgroup cannot be created the build still runs,
+//! and a warning is logged.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+/// Parent cgroup that holds one child cgroup per running build.
+#[cfg(target_os = "linux")]
+const CGROUP_PARENT: &str = "/sys/fs/cgroup/syslua";
+
+/// `cpu.max` period in microseconds.
+#[cfg(target_os = "linux")]
+const CPU_PERIOD_US: u64 = 100_000;
+
+/// Cgroups created by this process so far, to keep their names unique.
+static CREATED: AtomicU64 = AtomicU64::new(0);
+
+/// A cgroup that build commands are moved into.
+///
+/// The cgroup directory is removed when this value is dropped.
+#[derive(Debug)]
+pub struct Cgroup {
+  path: PathBuf,
+}
+
+impl Cgroup {
+  /// Create a cgroup for `name` limited to `cpus` CPUs and `memory` bytes.
+  ///
+  /// The cgroup is named `<name>-<pid>-<n>`, unique among running processes.
+  /// Returns `None` if neither limit is set, cgroups are unsupported on this
+  /// platform, or the cgroup could not be set up.
+  pub fn create(name: &str, cpus: Option<u32>, memory: Option<u64>) -> Option<Self> {
+    if cpus.is_none() && memory.is_none() {
+      return None;
+    }
+
+    let name = format!(
+      "{}-{}-{}",
+      name,
+      std::process::id(),
+      CREATED.fetch_add(1, Ordering::Relaxed)
+    );
+    match Self::try_create(&name, cpus, memory) {
+      Ok(cgroup) => cgroup,
+      Err(e) => {
+        warn!(%name, error = %e, "failed to set up cgroup, running without resource limits");
+        None
+      }
+    }
+  }
+
+  #[cfg(target_os = "linux")]
+  fn try_create(name: &str, cpus: Option<u32>, memory: Option<u64>) -> std::io::Result<Option<Self>> {
+    use std::fs;
+    use std::path::Path;
+
+    if !Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+      debug!("cgroup v2 hierarchy not mounted, skipping resource limits");
+      return Ok(None);
+    }
+
+    let parent = Path::new(CGROUP_PARENT);
+    fs::create_dir_all(parent)?;
+    fs::write(parent.join("cgroup.subtree_control"), "+cpu +memory")?;
+
+    let path = parent.join(name);
+    fs::create_dir_all(&path)?;
+    let cgroup = Self { path };
+
+    if let Some(cpus) = cpus {
+      let quota = u64::from(cpus) * CPU_PERIOD_US;
+      fs::write(cgroup.path.join("cpu.max"), format!("{} {}", quota, CPU_PERIOD_US))?;
+    }
+    if let Some(memory) = memory {
+      fs::write(cgroup.path.join("memory.max"), memory.to_string())?;
+    }
+
+    debug!(path = ?cgroup.path, ?cpus, ?memory, "created build cgroup");
+    Ok(Some(cgroup))
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  fn try_create(_name: &str, _cpus: Option<u32>, _memory: Option<u64>) -> std::io::Result<Option<Self>> {
+    debug!("cgroups are only supported on Linux, skipping resource limits");
+    Ok(None)
+  }
+
+  /// Make `command` start inside this cgroup.
+  ///
+  /// The child joins it between fork and exec, so every process it starts is
+  /// limited too. Call once the command is otherwise complete: a `sudo`
+  /// wrapper is a new command.
+  #[cfg(target_os = "linux")]
+  pub fn enter_on_spawn(&self, command: &mut Command) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let procs = std::fs::OpenOptions::new()
+      .write(true)
+      .open(self.path.join("cgroup.procs"))?;
+    // SAFETY: the closure only writes to a descriptor opened before the fork
+    unsafe {
+      command.pre_exec(move || {
+        // Writing 0 moves the writing process. Permission was checked when
+        // opening, so a failure here is ignored like any other limit failure.
+        let _ = (&procs).write_all(b"0");
+        Ok(())
+      });
+    }
+    Ok(())
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  pub fn enter_on_spawn(&self, _command: &mut Command) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+impl Drop for Cgroup {
+  fn drop(&mut self) {
+    // Fails if processes are still running in the cgroup; the kernel keeps it until they exit
+    if let Err(e) = std::fs::remove_dir(&self.path) {
+      debug!(path = ?self.path, error = %e, "failed to remove build cgroup");
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn create_without_limits_is_none() {
+    assert!(Cgroup::create("no-limits", None, None).is_none());
+  }
+}
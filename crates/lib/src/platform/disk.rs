@@ -0,0 +1,123 @@
+//! Free space and writability of filesystem locations.
+//!
+//! Used by the apply preflight checks, which ask about paths that may not
+//! exist yet: each function looks at the nearest existing ancestor, where the
+//! path would be created.
+
+use std::io;
+use std::path::Path;
+
+/// `path` itself if it exists, or its nearest existing ancestor.
+pub fn existing_ancestor(path: &Path) -> Option<&Path> {
+  path.ancestors().find(|ancestor| ancestor.exists())
+}
+
+/// Bytes available to this user on the filesystem that holds (or would hold) `path`.
+pub fn available_space(path: &Path) -> io::Result<u64> {
+  let existing = existing_ancestor(path).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+  available_space_at(existing)
+}
+
+#[cfg(unix)]
+fn available_space_at(path: &Path) -> io::Result<u64> {
+  let stat = rustix::fs::statvfs(path).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+  Ok(stat.f_bavail.saturating_mul(stat.f_frsize))
+}
+
+#[cfg(windows)]
+fn available_space_at(path: &Path) -> io::Result<u64> {
+  use std::os::windows::ffi::OsStrExt;
+  use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+  let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+  let mut available = 0u64;
+  // SAFETY: `wide` is NUL-terminated, and the total/free out-pointers may be null.
+  let result = unsafe {
+    GetDiskFreeSpaceExW(
+      wide.as_ptr(),
+      &mut available,
+      std::ptr::null_mut(),
+      std::ptr::null_mut(),
+    )
+  };
+  if result == 0 {
+    Err(io::Error::last_os_error())
+  } else {
+    Ok(available)
+  }
+}
+
+/// Whether this process could create entries in the directory `dir`, or in
+/// its nearest existing ancestor if it doesn't exist yet.
+///
+/// Read-only mounts count as unwritable even for root.
+pub fn is_writable(dir: &Path) -> bool {
+  existing_ancestor(dir).is_some_and(is_writable_at)
+}
+
+#[cfg(unix)]
+fn is_writable_at(path: &Path) -> bool {
+  rustix::fs::access(path, rustix::fs::Access::WRITE_OK).is_ok()
+}
+
+#[cfg(windows)]
+fn is_writable_at(path: &Path) -> bool {
+  std::fs::metadata(path).is_ok_and(|metadata| !metadata.permissions().readonly())
+}
+
+/// Whether `a` and `b` are (or would be created) on the same filesystem.
+pub fn same_filesystem(a: &Path, b: &Path) -> bool {
+  let (Some(a), Some(b)) = (existing_ancestor(a), existing_ancestor(b)) else {
+    return false;
+  };
+  same_filesystem_at(a, b)
+}
+
+#[cfg(unix)]
+fn same_filesystem_at(a: &Path, b: &Path) -> bool {
+  use std::os::unix::fs::MetadataExt;
+
+  match (std::fs::metadata(a), std::fs::metadata(b)) {
+    (Ok(a), Ok(b)) => a.dev() == b.dev(),
+    _ => false,
+  }
+}
+
+#[cfg(windows)]
+fn same_filesystem_at(a: &Path, b: &Path) -> bool {
+  // Volumes are told apart by drive prefix
+  let prefix = |path: &Path| path.components().next().map(|c| c.as_os_str().to_ascii_lowercase());
+  prefix(a) == prefix(b)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn missing_paths_use_their_existing_ancestor() {
+    let temp = tempfile::tempdir().unwrap();
+    let missing = temp.path().join("a").join("b");
+    assert_eq!(existing_ancestor(&missing), Some(temp.path()));
+    assert!(is_writable(&missing));
+    assert!(available_space(&missing).unwrap() > 0);
+    assert!(same_filesystem(&missing, temp.path()));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn read_only_directories_are_not_writable() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Root can write anywhere but read-only mounts
+    if rustix::process::geteuid().is_root() {
+      return;
+    }
+    let temp = tempfile::tempdir().unwrap();
+    let dir = temp.path().join("locked");
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+    assert!(!is_writable(&dir.join("child")));
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+  }
+}
@@ -0,0 +1,557 @@
+//! Host firewall rules: nftables on Linux, pf on macOS, Windows Firewall.
+//!
+//! syslua only ever touches the rules it added, found by their name:
+//!
+//! - nftables: rules in the `inet syslua` table, commented `syslua:<name>`.
+//!   The table and its `input`/`output` chains are created on first use with
+//!   an accept policy, so rules only allow or drop what they match.
+//! - pf: each rule is loaded into its own anchor, `syslua/<name>`. pf only
+//!   evaluates them when the main ruleset references the anchors
+//!   (`anchor "syslua/*"` in `/etc/pf.conf`).
+//! - Windows Firewall: rules named `syslua-<name>`, managed with
+//!   `netsh advfirewall`.
+//!
+//! Adding a rule replaces the rules of the same name, and deleting removes
+//! all of them, so both are idempotent.
+
+use std::process::Stdio;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::debug;
+
+/// nftables table holding syslua's rules.
+const NFT_TABLE: &str = "syslua";
+
+/// Prefix of the pf anchors and the nftables comments and Windows rule names.
+const PREFIX: &str = "syslua";
+
+/// Traffic a rule applies to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+  /// Incoming traffic; `address` is where it comes from.
+  #[default]
+  In,
+  /// Outgoing traffic; `address` is where it goes.
+  Out,
+}
+
+/// What a rule does with the traffic it matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+  #[default]
+  Allow,
+  Deny,
+}
+
+/// Transport protocol a rule matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+  #[default]
+  Tcp,
+  Udp,
+  Any,
+}
+
+/// A named firewall rule.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FirewallRule {
+  /// Identifies the rule on the host: letters, digits, `-` and `_`.
+  pub name: String,
+  #[serde(default)]
+  pub direction: Direction,
+  #[serde(default, rename = "action")]
+  pub verdict: Verdict,
+  #[serde(default)]
+  pub protocol: Protocol,
+  /// Destination port or range (`22`, `8000-8100`); needs `tcp` or `udp`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub port: Option<String>,
+  /// Remote address or CIDR range.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub address: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum FirewallError {
+  #[error("invalid firewall rule '{name}': {reason}")]
+  Invalid { name: String, reason: String },
+
+  #[error("firewall rules are not supported on this platform")]
+  Unsupported,
+
+  #[error("'{command}' failed: {message}")]
+  Command { command: String, message: String },
+}
+
+impl FirewallRule {
+  /// Check the name, port and address, which end up in firewall commands.
+  pub fn validate(&self) -> Result<(), FirewallError> {
+    let invalid = |reason: &str| FirewallError::Invalid {
+      name: self.name.clone(),
+      reason: reason.to_string(),
+    };
+
+    if self.name.is_empty()
+      || !self
+        .name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+      return Err(invalid("names may only contain letters, digits, '-' and '_'"));
+    }
+    if let Some(port) = &self.port {
+      if self.protocol == Protocol::Any {
+        return Err(invalid("a port needs protocol 'tcp' or 'udp'"));
+      }
+      let valid_port = |p: &str| p.parse::<u16>().is_ok_and(|p| p > 0);
+      let valid = match port.split_once('-') {
+        Some((low, high)) => valid_port(low) && valid_port(high) && low.parse::<u16>() <= high.parse::<u16>(),
+        None => valid_port(port),
+      };
+      if !valid {
+        return Err(invalid("port must be a number or a range like 8000-8100"));
+      }
+    }
+    if let Some(address) = &self.address {
+      let (ip, prefix) = address.split_once('/').unwrap_or((address, "0"));
+      let valid = match ip.parse::<std::net::IpAddr>() {
+        Ok(ip) => prefix
+          .parse::<u8>()
+          .is_ok_and(|p| p <= if ip.is_ipv6() { 128 } else { 32 }),
+        Err(_) => false,
+      };
+      if !valid {
+        return Err(invalid("address must be an IP address or CIDR range"));
+      }
+    }
+    Ok(())
+  }
+
+  /// The tag identifying this rule on the host.
+  pub fn tag(&self) -> String {
+    format!("{}:{}", PREFIX, self.name)
+  }
+
+  fn is_ipv6(&self) -> bool {
+    self.address.as_deref().is_some_and(|address| address.contains(':'))
+  }
+}
+
+/// The firewall syslua drives on a platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+  Nftables,
+  Pf,
+  WindowsFirewall,
+}
+
+impl Backend {
+  /// The backend of this host.
+  pub fn current() -> Option<Self> {
+    match std::env::consts::OS {
+      "linux" => Some(Self::Nftables),
+      "macos" => Some(Self::Pf),
+      "windows" => Some(Self::WindowsFirewall),
+      _ => None,
+    }
+  }
+
+  /// Add `rule`, replacing the rules with the same name.
+  pub async fn add(&self, rule: &FirewallRule) -> Result<(), FirewallError> {
+    rule.validate()?;
+    match self {
+      Backend::Nftables => {
+        for command in nft_setup() {
+          run(&command, None).await?;
+        }
+        self.delete(rule).await?;
+        run(&nft_add(rule), None).await?;
+      }
+      Backend::Pf => {
+        // Loading an anchor replaces its rules
+        run(&pf_load(rule), Some(&pf_rule(rule))).await?;
+      }
+      Backend::WindowsFirewall => {
+        self.delete(rule).await?;
+        run(&netsh_add(rule), None).await?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Delete every rule named like `rule`.
+  pub async fn delete(&self, rule: &FirewallRule) -> Result<(), FirewallError> {
+    match self {
+      Backend::Nftables => {
+        for chain in NFT_CHAINS {
+          for handle in nft_handles(rule, chain).await? {
+            run(&nft_delete(chain, &handle), None).await?;
+          }
+        }
+      }
+      Backend::Pf => {
+        run(&pf_flush(rule), None).await?;
+      }
+      Backend::WindowsFirewall => {
+        if self.is_present(rule).await? {
+          run(
+            &netsh(&["delete", "rule", &format!("name={}", windows_name(rule))]),
+            None,
+          )
+          .await?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Whether a rule named like `rule` is on the host.
+  pub async fn is_present(&self, rule: &FirewallRule) -> Result<bool, FirewallError> {
+    match self {
+      Backend::Nftables => {
+        for chain in NFT_CHAINS {
+          if !nft_handles(rule, chain).await?.is_empty() {
+            return Ok(true);
+          }
+        }
+        Ok(false)
+      }
+      Backend::Pf => {
+        let rules = run(&pf_show(rule), None).await?;
+        Ok(!rules.trim().is_empty())
+      }
+      Backend::WindowsFirewall => {
+        let show = netsh(&["show", "rule", &format!("name={}", windows_name(rule))]);
+        // netsh fails when no rule has the name
+        Ok(run(&show, None).await.is_ok())
+      }
+    }
+  }
+}
+
+const NFT_CHAINS: [&str; 2] = ["input", "output"];
+
+fn nft_chain(rule: &FirewallRule) -> &'static str {
+  match rule.direction {
+    Direction::In => "input",
+    Direction::Out => "output",
+  }
+}
+
+fn args(words: &[&str]) -> Vec<String> {
+  words.iter().map(|word| word.to_string()).collect()
+}
+
+/// Commands creating the syslua table and chains if they don't exist.
+fn nft_setup() -> Vec<Vec<String>> {
+  let mut commands = vec![args(&["nft", "add", "table", "inet", NFT_TABLE])];
+  for chain in NFT_CHAINS {
+    commands.push(args(&[
+      "nft", "add", "chain", "inet", NFT_TABLE, chain, "{", "type", "filter", "hook", chain, "priority", "0", ";",
+      "policy", "accept", ";", "}",
+    ]));
+  }
+  commands
+}
+
+fn nft_add(rule: &FirewallRule) -> Vec<String> {
+  let mut command = args(&["nft", "add", "rule", "inet", NFT_TABLE, nft_chain(rule)]);
+  if let Some(address) = &rule.address {
+    let family = if rule.is_ipv6() { "ip6" } else { "ip" };
+    let field = match rule.direction {
+      Direction::In => "saddr",
+      Direction::Out => "daddr",
+    };
+    command.extend(args(&[family, field, address]));
+  }
+  let protocol = match rule.protocol {
+    Protocol::Tcp => Some("tcp"),
+    Protocol::Udp => Some("udp"),
+    Protocol::Any => None,
+  };
+  match (protocol, &rule.port) {
+    (Some(protocol), Some(port)) => command.extend(args(&[protocol, "dport", port])),
+    (Some(protocol), None) => command.extend(args(&["meta", "l4proto", protocol])),
+    (None, _) => {}
+  }
+  command.push(
+    match rule.verdict {
+      Verdict::Allow => "accept",
+      Verdict::Deny => "drop",
+    }
+    .to_string(),
+  );
+  command.extend(["comment".to_string(), format!("\"{}\"", rule.tag())]);
+  command
+}
+
+fn nft_delete(chain: &str, handle: &str) -> Vec<String> {
+  args(&["nft", "delete", "rule", "inet", NFT_TABLE, chain, "handle", handle])
+}
+
+/// Handles of the rules tagged like `rule` in `chain`.
+async fn nft_handles(rule: &FirewallRule, chain: &str) -> Result<Vec<String>, FirewallError> {
+  let list = args(&["nft", "-a", "list", "chain", "inet", NFT_TABLE, chain]);
+  match run(&list, None).await {
+    Ok(listing) => Ok(parse_nft_handles(&listing, &rule.tag())),
+    // The table or chain doesn't exist yet, so neither does the rule
+    Err(FirewallError::Command { message, .. }) if message.contains("No such file or directory") => Ok(Vec::new()),
+    Err(e) => Err(e),
+  }
+}
+
+/// Handles of the rules commented with `tag` in `nft -a list` output.
+fn parse_nft_handles(listing: &str, tag: &str) -> Vec<String> {
+  let comment = format!("comment \"{}\"", tag);
+  listing
+    .lines()
+    .filter(|line| line.contains(&comment))
+    .filter_map(|line| {
+      line
+        .rsplit_once("# handle ")
+        .map(|(_, handle)| handle.trim().to_string())
+    })
+    .collect()
+}
+
+const PFCTL: &str = "/sbin/pfctl";
+
+fn pf_anchor(rule: &FirewallRule) -> String {
+  format!("{}/{}", PREFIX, rule.name)
+}
+
+fn pf_rule(rule: &FirewallRule) -> String {
+  let mut line = vec![
+    match rule.verdict {
+      Verdict::Allow => "pass",
+      Verdict::Deny => "block",
+    }
+    .to_string(),
+  ];
+  line.extend(args(&[
+    match rule.direction {
+      Direction::In => "in",
+      Direction::Out => "out",
+    },
+    "quick",
+  ]));
+  match rule.protocol {
+    Protocol::Tcp => line.extend(args(&["proto", "tcp"])),
+    Protocol::Udp => line.extend(args(&["proto", "udp"])),
+    Protocol::Any => {}
+  }
+  let address = rule.address.clone().unwrap_or_else(|| "any".to_string());
+  let (from, to) = match rule.direction {
+    Direction::In => (address, "any".to_string()),
+    Direction::Out => ("any".to_string(), address),
+  };
+  line.extend(["from".to_string(), from, "to".to_string(), to]);
+  if let Some(port) = &rule.port {
+    line.extend(["port".to_string(), port.replace('-', ":")]);
+  }
+  line.push(format!("label \"{}\"", rule.tag()));
+  format!("{}\n", line.join(" "))
+}
+
+fn pf_load(rule: &FirewallRule) -> Vec<String> {
+  args(&[PFCTL, "-a", &pf_anchor(rule), "-f", "-"])
+}
+
+fn pf_flush(rule: &FirewallRule) -> Vec<String> {
+  args(&[PFCTL, "-a", &pf_anchor(rule), "-F", "rules"])
+}
+
+fn pf_show(rule: &FirewallRule) -> Vec<String> {
+  args(&[PFCTL, "-a", &pf_anchor(rule), "-s", "rules"])
+}
+
+fn windows_name(rule: &FirewallRule) -> String {
+  format!("{}-{}", PREFIX, rule.name)
+}
+
+fn netsh(words: &[&str]) -> Vec<String> {
+  let mut command = args(&["netsh", "advfirewall", "firewall"]);
+  command.extend(args(words));
+  command
+}
+
+fn netsh_add(rule: &FirewallRule) -> Vec<String> {
+  let mut command = netsh(&["add", "rule"]);
+  command.push(format!("name={}", windows_name(rule)));
+  command.push(format!(
+    "dir={}",
+    match rule.direction {
+      Direction::In => "in",
+      Direction::Out => "out",
+    }
+  ));
+  command.push(format!(
+    "action={}",
+    match rule.verdict {
+      Verdict::Allow => "allow",
+      Verdict::Deny => "block",
+    }
+  ));
+  command.push(format!(
+    "protocol={}",
+    match rule.protocol {
+      Protocol::Tcp => "TCP",
+      Protocol::Udp => "UDP",
+      Protocol::Any => "any",
+    }
+  ));
+  if let Some(port) = &rule.port {
+    let field = match rule.direction {
+      Direction::In => "localport",
+      Direction::Out => "remoteport",
+    };
+    command.push(format!("{}={}", field, port));
+  }
+  if let Some(address) = &rule.address {
+    command.push(format!("remoteip={}", address));
+  }
+  command.push(format!("description={}", rule.tag()));
+  command
+}
+
+/// Run `command`, feeding it `stdin`, and return its stdout.
+async fn run(command: &[String], stdin: Option<&str>) -> Result<String, FirewallError> {
+  let (program, arguments) = command.split_first().expect("firewall command is empty");
+  let failed = |message: String| FirewallError::Command {
+    command: command.join(" "),
+    message,
+  };
+  debug!(command = %command.join(" "), "running firewall command");
+
+  let mut child = Command::new(program)
+    .args(arguments)
+    .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|e| failed(e.to_string()))?;
+  if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+    pipe
+      .write_all(input.as_bytes())
+      .await
+      .map_err(|e| failed(e.to_string()))?;
+  }
+  let output = child.wait_with_output().await.map_err(|e| failed(e.to_string()))?;
+  if !output.status.success() {
+    return Err(failed(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ssh_rule() -> FirewallRule {
+    FirewallRule {
+      name: "ssh".to_string(),
+      direction: Direction::In,
+      verdict: Verdict::Allow,
+      protocol: Protocol::Tcp,
+      port: Some("22".to_string()),
+      address: Some("10.0.0.0/8".to_string()),
+    }
+  }
+
+  #[test]
+  fn rules_are_validated() {
+    assert!(ssh_rule().validate().is_ok());
+
+    let invalid = [
+      FirewallRule {
+        name: "ssh; reboot".to_string(),
+        ..ssh_rule()
+      },
+      FirewallRule {
+        protocol: Protocol::Any,
+        ..ssh_rule()
+      },
+      FirewallRule {
+        port: Some("9000-8000".to_string()),
+        ..ssh_rule()
+      },
+      FirewallRule {
+        address: Some("10.0.0.0/8 accept".to_string()),
+        ..ssh_rule()
+      },
+    ];
+    for rule in invalid {
+      assert!(rule.validate().is_err(), "{:?}", rule);
+    }
+  }
+
+  #[test]
+  fn nft_rules_are_tagged_with_a_comment() {
+    assert_eq!(
+      nft_add(&ssh_rule()).join(" "),
+      "nft add rule inet syslua input ip saddr 10.0.0.0/8 tcp dport 22 accept comment \"syslua:ssh\""
+    );
+
+    let rule = FirewallRule {
+      name: "block-dns".to_string(),
+      direction: Direction::Out,
+      verdict: Verdict::Deny,
+      protocol: Protocol::Udp,
+      port: None,
+      address: Some("fd00::/8".to_string()),
+    };
+    assert_eq!(
+      nft_add(&rule).join(" "),
+      "nft add rule inet syslua output ip6 daddr fd00::/8 meta l4proto udp drop comment \"syslua:block-dns\""
+    );
+  }
+
+  #[test]
+  fn nft_handles_are_found_by_tag() {
+    let listing = r#"table inet syslua {
+	chain input { # handle 1
+		type filter hook input priority filter; policy accept;
+		tcp dport 22 accept comment "syslua:ssh" # handle 4
+		tcp dport 80 accept comment "syslua:ssh-web" # handle 5
+		tcp dport 2222 accept comment "syslua:ssh" # handle 7
+	}
+}"#;
+    assert_eq!(parse_nft_handles(listing, "syslua:ssh"), ["4", "7"]);
+    assert!(parse_nft_handles(listing, "syslua:other").is_empty());
+  }
+
+  #[test]
+  fn pf_rules_go_in_their_own_anchor() {
+    assert_eq!(
+      pf_rule(&ssh_rule()),
+      "pass in quick proto tcp from 10.0.0.0/8 to any port 22 label \"syslua:ssh\"\n"
+    );
+    let rule = FirewallRule {
+      direction: Direction::Out,
+      verdict: Verdict::Deny,
+      port: Some("8000-8100".to_string()),
+      address: None,
+      ..ssh_rule()
+    };
+    assert_eq!(
+      pf_rule(&rule),
+      "block out quick proto tcp from any to any port 8000:8100 label \"syslua:ssh\"\n"
+    );
+    assert_eq!(pf_load(&rule)[1..3], ["-a", "syslua/ssh"]);
+  }
+
+  #[test]
+  fn windows_rules_are_named_after_the_rule() {
+    assert_eq!(
+      netsh_add(&ssh_rule()).join(" "),
+      "netsh advfirewall firewall add rule name=syslua-ssh dir=in action=allow protocol=TCP localport=22 \
+       remoteip=10.0.0.0/8 description=syslua:ssh"
+    );
+  }
+}
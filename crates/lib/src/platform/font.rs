@@ -0,0 +1,457 @@
+//! Font installation: where each OS looks for fonts, and refreshing its cache.
+//!
+//! | OS      | User fonts                                  | System fonts             | Cache refresh              |
+//! | ------- | ------------------------------------------- | ------------------------ | -------------------------- |
+//! | Linux   | `$XDG_DATA_HOME/fonts` (`~/.local/share/fonts`) | `/usr/local/share/fonts` | `fc-cache -f <dir>`        |
+//! | macOS   | `~/Library/Fonts`                           | `/Library/Fonts`         | `atsutil databases -remove[User]` |
+//! | Windows | `%LOCALAPPDATA%\Microsoft\Windows\Fonts`     | `%WINDIR%\Fonts`         | registry entry per file    |
+//!
+//! Installed files are named `syslua.<name>.<file name>`, so removing the
+//! fonts of `name` never touches fonts syslua didn't install, and installing
+//! a new version removes the files the old one had and the new one hasn't.
+//! Missing cache tools are skipped; a failed refresh only logs a warning.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::fs;
+use tracing::{debug, warn};
+
+/// Extensions of the font files installed from a directory.
+pub const FONT_EXTENSIONS: &[&str] = &["ttf", "otf", "ttc", "otc"];
+
+/// Prefix of the file names of installed fonts.
+const PREFIX: &str = "syslua";
+
+/// Whether fonts are installed for the current user or for everyone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FontScope {
+  #[default]
+  User,
+  /// Needs elevation.
+  System,
+}
+
+impl FontScope {
+  pub fn is_user(&self) -> bool {
+    *self == Self::User
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      FontScope::User => "user",
+      FontScope::System => "system",
+    }
+  }
+}
+
+#[derive(Debug, Error)]
+pub enum FontError {
+  #[error("invalid font name '{name}': use letters, digits, '-' and '_'")]
+  InvalidName { name: String },
+
+  #[error("no font files ({}) in {}", FONT_EXTENSIONS.join(", "), .path.display())]
+  NoFonts { path: PathBuf },
+
+  #[error("{}: {source}", .path.display())]
+  Io {
+    path: PathBuf,
+    #[source]
+    source: std::io::Error,
+  },
+
+  #[error("'{command}' failed: {message}")]
+  Command { command: String, message: String },
+}
+
+/// Check a font name, which ends up in file names. Names can't contain
+/// `.`, which separates them from the file name.
+pub fn validate_name(name: &str) -> Result<(), FontError> {
+  let valid = !name.is_empty()
+    && name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+  if valid {
+    Ok(())
+  } else {
+    Err(FontError::InvalidName { name: name.to_string() })
+  }
+}
+
+/// The directory the OS loads `scope`'s fonts from.
+#[cfg(target_os = "linux")]
+pub fn fonts_dir(scope: FontScope) -> PathBuf {
+  match scope {
+    FontScope::User => std::env::var("XDG_DATA_HOME")
+      .map(PathBuf::from)
+      .unwrap_or_else(|_| super::paths::home_dir().join(".local").join("share"))
+      .join("fonts"),
+    FontScope::System => PathBuf::from("/usr/local/share/fonts"),
+  }
+}
+
+/// The directory the OS loads `scope`'s fonts from.
+#[cfg(target_os = "macos")]
+pub fn fonts_dir(scope: FontScope) -> PathBuf {
+  match scope {
+    FontScope::User => super::paths::home_dir().join("Library").join("Fonts"),
+    FontScope::System => PathBuf::from("/Library/Fonts"),
+  }
+}
+
+/// The directory the OS loads `scope`'s fonts from.
+#[cfg(windows)]
+pub fn fonts_dir(scope: FontScope) -> PathBuf {
+  match scope {
+    FontScope::User => {
+      let local_appdata = std::env::var("LOCALAPPDATA").expect("LOCALAPPDATA not set");
+      PathBuf::from(local_appdata)
+        .join("Microsoft")
+        .join("Windows")
+        .join("Fonts")
+    }
+    FontScope::System => {
+      let windir = std::env::var("WINDIR").unwrap_or_else(|_| r"C:\Windows".to_string());
+      PathBuf::from(windir).join("Fonts")
+    }
+  }
+}
+
+/// The directory the OS loads `scope`'s fonts from.
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn fonts_dir(scope: FontScope) -> PathBuf {
+  match scope {
+    FontScope::User => super::paths::home_dir().join(".fonts"),
+    FontScope::System => PathBuf::from("/usr/local/share/fonts"),
+  }
+}
+
+/// Whether `path` has a font file extension.
+pub fn is_font_file(path: &Path) -> bool {
+  path
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .is_some_and(|ext| FONT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// The font files of `source`: the file itself, or the font files anywhere
+/// below the directory, sorted.
+pub fn source_fonts(source: &Path) -> Result<Vec<PathBuf>, FontError> {
+  let io_error = |source: std::io::Error, path: &Path| FontError::Io {
+    path: path.to_path_buf(),
+    source,
+  };
+  let metadata = std::fs::metadata(source).map_err(|e| io_error(e, source))?;
+  let mut fonts = if metadata.is_dir() {
+    walkdir::WalkDir::new(source)
+      .follow_links(true)
+      .into_iter()
+      .filter_map(Result::ok)
+      .filter(|entry| entry.file_type().is_file() && is_font_file(entry.path()))
+      .map(|entry| entry.into_path())
+      .collect()
+  } else {
+    vec![source.to_path_buf()]
+  };
+  fonts.sort();
+  if fonts.is_empty() {
+    return Err(FontError::NoFonts {
+      path: source.to_path_buf(),
+    });
+  }
+  Ok(fonts)
+}
+
+/// The file name `font` is installed under for `name`.
+fn installed_name(name: &str, font: &Path) -> String {
+  let file_name = font.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+  format!("{}.{}.{}", PREFIX, name, file_name)
+}
+
+/// The files installed for `name` in `dir`.
+async fn installed_fonts(dir: &Path, name: &str) -> Result<Vec<PathBuf>, FontError> {
+  let prefix = format!("{}.{}.", PREFIX, name);
+  let mut entries = match fs::read_dir(dir).await {
+    Ok(entries) => entries,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(source) => {
+      return Err(FontError::Io {
+        path: dir.to_path_buf(),
+        source,
+      });
+    }
+  };
+  let mut installed = Vec::new();
+  while let Some(entry) = entries.next_entry().await.map_err(|source| FontError::Io {
+    path: dir.to_path_buf(),
+    source,
+  })? {
+    if entry.file_name().to_string_lossy().starts_with(&prefix) {
+      installed.push(entry.path());
+    }
+  }
+  installed.sort();
+  Ok(installed)
+}
+
+/// Install the fonts of `source` as `name` into `dir`, replacing the files
+/// an earlier version installed. Returns whether anything changed.
+pub async fn install_fonts(dir: &Path, name: &str, source: &Path, scope: FontScope) -> Result<bool, FontError> {
+  let io_error = |path: &Path| {
+    let path = path.to_path_buf();
+    move |source| FontError::Io { path, source }
+  };
+  let fonts = source_fonts(source)?;
+  fs::create_dir_all(dir).await.map_err(io_error(dir))?;
+
+  let mut changed = false;
+  let wanted: Vec<PathBuf> = fonts.iter().map(|font| dir.join(installed_name(name, font))).collect();
+  for stale in installed_fonts(dir, name).await? {
+    if !wanted.contains(&stale) {
+      unregister(&stale, scope).await?;
+      fs::remove_file(&stale).await.map_err(io_error(&stale))?;
+      changed = true;
+    }
+  }
+  for (font, target) in fonts.iter().zip(&wanted) {
+    let content = fs::read(font).await.map_err(io_error(font))?;
+    if fs::read(target).await.is_ok_and(|existing| existing == content) {
+      continue;
+    }
+    // Written rather than copied: store files are read-only
+    fs::write(target, content).await.map_err(io_error(target))?;
+    register(target, scope).await?;
+    changed = true;
+  }
+  debug!(name, dir = ?dir, fonts = wanted.len(), changed, "installed fonts");
+  Ok(changed)
+}
+
+/// Remove the fonts installed as `name` from `dir`. Returns whether there
+/// were any.
+pub async fn remove_fonts(dir: &Path, name: &str, scope: FontScope) -> Result<bool, FontError> {
+  let installed = installed_fonts(dir, name).await?;
+  for font in &installed {
+    unregister(font, scope).await?;
+    fs::remove_file(font).await.map_err(|source| FontError::Io {
+      path: font.clone(),
+      source,
+    })?;
+  }
+  Ok(!installed.is_empty())
+}
+
+/// Whether any font of `source` is missing from `dir` or differs from the
+/// installed one.
+pub async fn fonts_missing(dir: &Path, name: &str, source: &Path) -> Result<bool, FontError> {
+  for font in source_fonts(source)? {
+    let installed = fs::read(dir.join(installed_name(name, &font))).await.ok();
+    let expected = fs::read(&font).await.map_err(|source| FontError::Io {
+      path: font.clone(),
+      source,
+    })?;
+    if installed.as_ref() != Some(&expected) {
+      return Ok(true);
+    }
+  }
+  Ok(false)
+}
+
+/// Make the OS pick up changed fonts in `dir`.
+#[cfg(target_os = "linux")]
+pub async fn refresh_cache(dir: &Path, _scope: FontScope) {
+  run_refresh(&["fc-cache".to_string(), "-f".to_string(), dir.display().to_string()]).await;
+}
+
+/// Make the OS pick up changed fonts in `dir`.
+#[cfg(target_os = "macos")]
+pub async fn refresh_cache(_dir: &Path, scope: FontScope) {
+  let database = match scope {
+    FontScope::User => "-removeUser",
+    FontScope::System => "-remove",
+  };
+  run_refresh(&["atsutil".to_string(), "databases".to_string(), database.to_string()]).await;
+}
+
+/// Make the OS pick up changed fonts in `dir`: Windows reads the registry
+/// entries written per file.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub async fn refresh_cache(_dir: &Path, _scope: FontScope) {}
+
+/// Run a cache refresh command, which may not be installed.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+async fn run_refresh(command: &[String]) {
+  match run(command).await {
+    Ok(_) => debug!(command = %command.join(" "), "refreshed font cache"),
+    Err(FontError::Command { message, .. }) if message.contains("No such file") || message.contains("not found") => {
+      debug!(command = %command[0], "font cache tool not installed, skipping refresh")
+    }
+    Err(e) => warn!(error = %e, "failed to refresh the font cache"),
+  }
+}
+
+/// Registry key listing `scope`'s fonts.
+#[cfg(windows)]
+fn registry_key(scope: FontScope) -> &'static str {
+  match scope {
+    FontScope::User => r"HKCU\Software\Microsoft\Windows NT\CurrentVersion\Fonts",
+    FontScope::System => r"HKLM\Software\Microsoft\Windows NT\CurrentVersion\Fonts",
+  }
+}
+
+/// Registry value naming the installed font file `path`.
+#[cfg(windows)]
+fn registry_value(path: &Path) -> String {
+  let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+  let kind = match path.extension().and_then(|ext| ext.to_str()) {
+    Some(ext) if ext.eq_ignore_ascii_case("otf") => "OpenType",
+    _ => "TrueType",
+  };
+  format!("{} ({})", stem, kind)
+}
+
+/// Register the installed font file `path`. System fonts are listed by
+/// file name, user fonts by full path.
+#[cfg(windows)]
+async fn register(path: &Path, scope: FontScope) -> Result<(), FontError> {
+  let data = match scope {
+    FontScope::User => path.display().to_string(),
+    FontScope::System => path
+      .file_name()
+      .map(|n| n.to_string_lossy().into_owned())
+      .unwrap_or_default(),
+  };
+  let value = registry_value(path);
+  let command = [
+    "reg",
+    "add",
+    registry_key(scope),
+    "/v",
+    value.as_str(),
+    "/t",
+    "REG_SZ",
+    "/d",
+    data.as_str(),
+    "/f",
+  ];
+  run(&command.map(String::from)).await.map(|_| ())
+}
+
+#[cfg(windows)]
+async fn unregister(path: &Path, scope: FontScope) -> Result<(), FontError> {
+  let value = registry_value(path);
+  let command = ["reg", "delete", registry_key(scope), "/v", value.as_str(), "/f"];
+  // Already gone is fine
+  if let Err(e) = run(&command.map(String::from)).await {
+    debug!(path = ?path, error = %e, "font was not registered");
+  }
+  Ok(())
+}
+
+#[cfg(not(windows))]
+async fn register(_path: &Path, _scope: FontScope) -> Result<(), FontError> {
+  Ok(())
+}
+
+#[cfg(not(windows))]
+async fn unregister(_path: &Path, _scope: FontScope) -> Result<(), FontError> {
+  Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+async fn run(command: &[String]) -> Result<String, FontError> {
+  let (program, arguments) = command.split_first().expect("font command is empty");
+  let failed = |message: String| FontError::Command {
+    command: command.join(" "),
+    message,
+  };
+  debug!(command = %command.join(" "), "running font command");
+
+  let output = tokio::process::Command::new(program)
+    .args(arguments)
+    .stdin(std::process::Stdio::null())
+    .output()
+    .await
+    .map_err(|e| failed(e.to_string()))?;
+  if !output.status.success() {
+    return Err(failed(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn validates_names() {
+    assert!(validate_name("jetbrains-mono_2").is_ok());
+    for name in ["", "mono.2", "a/b", "a b"] {
+      assert!(validate_name(name).is_err(), "{}", name);
+    }
+  }
+
+  #[test]
+  fn collects_font_files_of_a_directory() {
+    let temp = tempfile::TempDir::new().unwrap();
+    std::fs::create_dir_all(temp.path().join("ttf")).unwrap();
+    for file in ["ttf/Mono-Regular.ttf", "Mono-Bold.OTF", "LICENSE.txt"] {
+      std::fs::write(temp.path().join(file), file).unwrap();
+    }
+
+    let fonts = source_fonts(temp.path()).unwrap();
+    assert_eq!(
+      fonts,
+      [
+        temp.path().join("Mono-Bold.OTF"),
+        temp.path().join("ttf/Mono-Regular.ttf")
+      ]
+    );
+
+    let empty = temp.path().join("empty");
+    std::fs::create_dir(&empty).unwrap();
+    assert!(matches!(source_fonts(&empty), Err(FontError::NoFonts { .. })));
+  }
+
+  #[tokio::test]
+  async fn installs_updates_and_removes_only_its_fonts() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let source = temp.path().join("source");
+    let dir = temp.path().join("fonts");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(source.join("Mono-Regular.ttf"), "v1").unwrap();
+    std::fs::write(source.join("Mono-Bold.ttf"), "v1").unwrap();
+    std::fs::write(dir.join("Other.ttf"), "theirs").unwrap();
+
+    assert!(fonts_missing(&dir, "mono", &source).await.unwrap());
+    assert!(install_fonts(&dir, "mono", &source, FontScope::User).await.unwrap());
+    assert!(!fonts_missing(&dir, "mono", &source).await.unwrap());
+    assert!(!install_fonts(&dir, "mono", &source, FontScope::User).await.unwrap());
+    assert_eq!(
+      std::fs::read_to_string(dir.join("syslua.mono.Mono-Bold.ttf")).unwrap(),
+      "v1"
+    );
+
+    // A new version without the bold font
+    std::fs::remove_file(source.join("Mono-Bold.ttf")).unwrap();
+    std::fs::write(source.join("Mono-Regular.ttf"), "v2").unwrap();
+    assert!(fonts_missing(&dir, "mono", &source).await.unwrap());
+    assert!(install_fonts(&dir, "mono", &source, FontScope::User).await.unwrap());
+    assert!(!dir.join("syslua.mono.Mono-Bold.ttf").exists());
+    assert_eq!(
+      std::fs::read_to_string(dir.join("syslua.mono.Mono-Regular.ttf")).unwrap(),
+      "v2"
+    );
+
+    assert!(remove_fonts(&dir, "mono", FontScope::User).await.unwrap());
+    assert!(!remove_fonts(&dir, "mono", FontScope::User).await.unwrap());
+    let left: Vec<_> = std::fs::read_dir(&dir)
+      .unwrap()
+      .map(|e| e.unwrap().file_name())
+      .collect();
+    assert_eq!(left, ["Other.ttf"]);
+  }
+}
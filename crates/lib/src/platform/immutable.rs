@@ -2,13 +2,22 @@
 //!
 //! After a build completes, its store path is made immutable (write-protected)
 //! to prevent accidental modification. This mirrors Nix's approach of using
-//! basic file permissions rather than ACLs.
+//! basic file permissions rather than ACLs. `sys apply --no-readonly` leaves
+//! build outputs writable, for filesystems that don't handle this.
+//!
+//! Anything deleting a store path goes through [`remove_store_path`], which
+//! restores write permissions (and clears the flags) first.
 //!
 //! ## Platform Behavior
 //!
 //! - **Unix**: Sets permissions to 0444 (files) or 0555 (dirs/executables)
-//! - **macOS**: Additionally clears BSD file flags to allow future GC
+//! - **Linux**: Additionally sets the immutable attribute (`chattr +i`) when
+//!   running as root, on filesystems that support it
+//! - **macOS**: Additionally sets the user immutable flag (`chflags uchg`)
 //! - **Windows**: Sets FILE_ATTRIBUTE_READONLY via `set_readonly(true)`
+//!
+//! Symlinks are left alone: changing their permissions would change their
+//! targets, which may be outside the store.
 
 use std::path::Path;
 
@@ -48,7 +57,8 @@ pub enum ImmutableError {
 /// # Platform Behavior
 ///
 /// - **Unix**: Sets permissions to 0444 (files) or 0555 (dirs/executables)
-/// - **macOS**: Additionally clears BSD file flags
+/// - **Linux**: Additionally sets the immutable attribute, as root
+/// - **macOS**: Additionally sets the user immutable flag
 /// - **Windows**: Sets FILE_ATTRIBUTE_READONLY
 pub fn make_immutable(path: &Path) -> Result<(), ImmutableError> {
   if !path.exists() {
@@ -64,16 +74,18 @@ pub fn make_immutable(path: &Path) -> Result<(), ImmutableError> {
       path: path.display().to_string(),
       source: e,
     })?;
+    if entry.path_is_symlink() {
+      continue;
+    }
 
     if let Err(e) = make_entry_immutable(entry.path()) {
       warn!(path = ?entry.path(), error = %e, "failed to make immutable, continuing");
     }
+    // After the permissions, which the flag would otherwise lock in place
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    set_immutable_flag(entry.path(), true);
   }
 
-  // On macOS, clear BSD flags to allow future GC
-  #[cfg(target_os = "macos")]
-  clear_bsd_flags(path);
-
   Ok(())
 }
 
@@ -85,6 +97,7 @@ pub fn make_immutable(path: &Path) -> Result<(), ImmutableError> {
 /// # Platform Behavior
 ///
 /// - **Unix**: Sets permissions to 0644 (files) or 0755 (dirs/executables)
+/// - **Linux/macOS**: First clears the immutable attribute or flag
 /// - **Windows**: Clears FILE_ATTRIBUTE_READONLY
 pub fn make_mutable(path: &Path) -> Result<(), ImmutableError> {
   if !path.exists() {
@@ -99,7 +112,12 @@ pub fn make_mutable(path: &Path) -> Result<(), ImmutableError> {
       path: path.display().to_string(),
       source: e,
     })?;
+    if entry.path_is_symlink() {
+      continue;
+    }
 
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    set_immutable_flag(entry.path(), false);
     if let Err(e) = make_entry_mutable(entry.path()) {
       warn!(path = ?entry.path(), error = %e, "failed to make mutable, continuing");
     }
@@ -108,6 +126,15 @@ pub fn make_mutable(path: &Path) -> Result<(), ImmutableError> {
   Ok(())
 }
 
+/// Delete a store path, making it mutable first so read-only build outputs
+/// can be removed.
+pub fn remove_store_path(path: &Path) -> std::io::Result<()> {
+  if let Err(e) = make_mutable(path) {
+    warn!(path = ?path, error = %e, "failed to make store path mutable before removal");
+  }
+  std::fs::remove_dir_all(path)
+}
+
 // ============ Unix Implementation ============
 
 #[cfg(unix)]
@@ -166,24 +193,65 @@ fn make_entry_mutable(path: &Path) -> Result<(), ImmutableError> {
   Ok(())
 }
 
-/// Clear BSD file flags on macOS to allow future deletion.
+/// Set or clear the immutable attribute of `path` (`chattr +i`/`-i`).
 ///
-/// This clears flags like UF_IMMUTABLE that could prevent garbage collection.
+/// Only root may change it; filesystems without attributes (tmpfs on older
+/// kernels, FAT, network mounts) refuse it. Both are fine, the permissions
+/// still apply.
+#[cfg(target_os = "linux")]
+fn set_immutable_flag(path: &Path, immutable: bool) {
+  use rustix::fs::{IFlags, Mode, OFlags, ioctl_getflags, ioctl_setflags, open};
+
+  if immutable && !crate::platform::is_elevated() {
+    return;
+  }
+  let Ok(fd) = open(
+    path,
+    OFlags::RDONLY | OFlags::NOFOLLOW | OFlags::NONBLOCK | OFlags::CLOEXEC,
+    Mode::empty(),
+  ) else {
+    return;
+  };
+  let Ok(flags) = ioctl_getflags(&fd) else {
+    return;
+  };
+  let wanted = if immutable {
+    flags | IFlags::IMMUTABLE
+  } else {
+    flags - IFlags::IMMUTABLE
+  };
+  if wanted != flags
+    && let Err(e) = ioctl_setflags(&fd, wanted)
+  {
+    tracing::trace!(path = ?path, error = %e, "failed to change immutable attribute");
+  }
+}
+
+/// Set or clear the user immutable flag of `path` (`chflags uchg`/`nouchg`).
+///
+/// The owner may change it, so GC can clear it again before deleting.
 #[cfg(target_os = "macos")]
-fn clear_bsd_flags(path: &Path) {
+fn set_immutable_flag(path: &Path, immutable: bool) {
   use std::ffi::CString;
+  use std::os::macos::fs::MetadataExt;
   use std::os::unix::ffi::OsStrExt;
 
-  for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-    if let Ok(cpath) = CString::new(entry.path().as_os_str().as_bytes()) {
-      // chflags(path, 0) clears all flags including UF_IMMUTABLE
-      // We use chflags (not lchflags) as libc doesn't export lchflags
-      // This means symlinks themselves won't have flags cleared, but
-      // that's acceptable for our use case
-      // We ignore errors here as this is best-effort
-      unsafe {
-        libc::chflags(cpath.as_ptr(), 0);
-      }
+  let Ok(metadata) = std::fs::symlink_metadata(path) else {
+    return;
+  };
+  let flags = metadata.st_flags();
+  let wanted = if immutable {
+    flags | libc::UF_IMMUTABLE
+  } else {
+    flags & !libc::UF_IMMUTABLE
+  };
+  if wanted == flags {
+    return;
+  }
+  if let Ok(cpath) = CString::new(path.as_os_str().as_bytes()) {
+    // SAFETY: `cpath` is a valid NUL-terminated path
+    if unsafe { libc::chflags(cpath.as_ptr(), wanted as _) } != 0 {
+      tracing::trace!(path = ?path, error = %std::io::Error::last_os_error(), "failed to change immutable flag");
     }
   }
 }
@@ -0,0 +1,270 @@
+//! The hook that runs `sys activate-login` when the user logs in.
+//!
+//! - Linux: a marked block in `~/.profile`, read by login shells, and one in
+//!   `~/.bash_logout` that runs `sys activate-login --logout`.
+//! - macOS: a launchd agent in `~/Library/LaunchAgents` that runs at load,
+//!   i.e. when the user logs in.
+//! - Windows: a script in the Startup folder of the user's start menu.
+//!
+//! Like the agent service, the hook passes the current `SYSLUA_ROOT` on, so
+//! activation finds the snapshot the binds were applied in. Its output goes
+//! to `<root>/activate-login.log`.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::shell::Shell;
+
+/// Label of the launchd agent.
+pub const LAUNCHD_LABEL: &str = "org.syslua.login";
+
+/// Name of the Startup folder script.
+pub const STARTUP_SCRIPT: &str = "syslua-login.cmd";
+
+const BLOCK_BEGIN: &str = "# >>> syslua login hook >>>";
+const BLOCK_END: &str = "# <<< syslua login hook <<<";
+
+/// Files that make up the login hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginHook {
+  /// Files owned by the hook, with their contents.
+  pub files: Vec<(PathBuf, String)>,
+  /// Marked blocks the hook adds to existing files, such as shell profiles.
+  pub blocks: Vec<(PathBuf, String)>,
+}
+
+impl LoginHook {
+  /// The hook for this platform, running `exe` with `root` as `SYSLUA_ROOT`.
+  pub fn for_platform(exe: &Path, root: &Path) -> Self {
+    let home = super::paths::home_dir();
+    if cfg!(target_os = "macos") {
+      launchd(exe, root, &home)
+    } else if cfg!(windows) {
+      let appdata = std::env::var_os("APPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join("AppData").join("Roaming"));
+      startup(exe, root, &appdata)
+    } else {
+      shell(exe, root, &home)
+    }
+  }
+
+  /// Write the hook, replacing an earlier one.
+  pub fn install(&self) -> io::Result<()> {
+    for (path, content) in &self.files {
+      if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+      }
+      std::fs::write(path, content)?;
+    }
+    for (path, block) in &self.blocks {
+      let existing = read_or_empty(path)?;
+      std::fs::write(path, with_block(&existing, Some(block)))?;
+    }
+    Ok(())
+  }
+
+  /// Remove the hook; parts already gone are fine.
+  pub fn uninstall(&self) -> io::Result<()> {
+    for (path, _) in &self.files {
+      match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+      }
+    }
+    for (path, _) in &self.blocks {
+      let existing = read_or_empty(path)?;
+      let updated = with_block(&existing, None);
+      if updated != existing {
+        std::fs::write(path, updated)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Whether any part of the hook is in place.
+  pub fn is_installed(&self) -> bool {
+    self.files.iter().any(|(path, _)| path.exists())
+      || self
+        .blocks
+        .iter()
+        .any(|(path, _)| read_or_empty(path).is_ok_and(|content| content.contains(BLOCK_BEGIN)))
+  }
+}
+
+/// Blocks in the login shell profile and the bash logout script.
+pub fn shell(exe: &Path, root: &Path, home: &Path) -> LoginHook {
+  let command = |extra: &str| {
+    let log = root.join("activate-login.log");
+    format!(
+      "{}\nSYSLUA_ROOT={} {} activate-login{} >>{} 2>&1 || true\n{}\n",
+      BLOCK_BEGIN,
+      Shell::Sh.quote(&root.display().to_string()),
+      Shell::Sh.quote(&exe.display().to_string()),
+      extra,
+      Shell::Sh.quote(&log.display().to_string()),
+      BLOCK_END
+    )
+  };
+  LoginHook {
+    files: Vec::new(),
+    blocks: vec![
+      (home.join(".profile"), command("")),
+      (home.join(".bash_logout"), command(" --logout")),
+    ],
+  }
+}
+
+/// A launchd agent that runs once when the user logs in.
+pub fn launchd(exe: &Path, root: &Path, home: &Path) -> LoginHook {
+  let path = home
+    .join("Library")
+    .join("LaunchAgents")
+    .join(format!("{}.plist", LAUNCHD_LABEL));
+  let log = root.join("activate-login.log");
+  let plist = format!(
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>Label</key>
+  <string>{label}</string>
+  <key>ProgramArguments</key>
+  <array>
+    <string>{exe}</string>
+    <string>activate-login</string>
+  </array>
+  <key>EnvironmentVariables</key>
+  <dict>
+    <key>SYSLUA_ROOT</key>
+    <string>{root}</string>
+  </dict>
+  <key>RunAtLoad</key>
+  <true/>
+  <key>StandardOutPath</key>
+  <string>{log}</string>
+  <key>StandardErrorPath</key>
+  <string>{log}</string>
+</dict>
+</plist>
+"#,
+    label = LAUNCHD_LABEL,
+    exe = xml_escape(&exe.display().to_string()),
+    root = xml_escape(&root.display().to_string()),
+    log = xml_escape(&log.display().to_string()),
+  );
+  LoginHook {
+    files: vec![(path, plist)],
+    blocks: Vec::new(),
+  }
+}
+
+/// A script in the Startup folder under `appdata`.
+pub fn startup(exe: &Path, root: &Path, appdata: &Path) -> LoginHook {
+  let path = appdata
+    .join("Microsoft")
+    .join("Windows")
+    .join("Start Menu")
+    .join("Programs")
+    .join("Startup")
+    .join(STARTUP_SCRIPT);
+  let log = root.join("activate-login.log");
+  let script = format!(
+    "@echo off\r\nset SYSLUA_ROOT={}\r\n{} activate-login >>{} 2>&1\r\n",
+    root.display(),
+    Shell::Cmd.quote(&exe.display().to_string()),
+    Shell::Cmd.quote(&log.display().to_string()),
+  );
+  LoginHook {
+    files: vec![(path, script)],
+    blocks: Vec::new(),
+  }
+}
+
+fn read_or_empty(path: &Path) -> io::Result<String> {
+  match std::fs::read_to_string(path) {
+    Ok(content) => Ok(content),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+    Err(e) => Err(e),
+  }
+}
+
+/// `content` without its hook block, with `block` appended if given.
+fn with_block(content: &str, block: Option<&str>) -> String {
+  let mut kept = String::new();
+  let mut in_block = false;
+  for line in content.split_inclusive('\n') {
+    match line.trim_end() {
+      BLOCK_BEGIN => in_block = true,
+      BLOCK_END if in_block => in_block = false,
+      _ if !in_block => kept.push_str(line),
+      _ => {}
+    }
+  }
+  if let Some(block) = block {
+    if !kept.is_empty() && !kept.ends_with('\n') {
+      kept.push('\n');
+    }
+    kept.push_str(block);
+  }
+  kept
+}
+
+fn xml_escape(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn shell_hook_replaces_its_block() {
+    let hook = shell(
+      Path::new("/usr/bin/sys"),
+      Path::new("/home/me/.local/share/syslua"),
+      Path::new("/home/me"),
+    );
+    let (path, block) = &hook.blocks[0];
+    assert_eq!(path, Path::new("/home/me/.profile"));
+    assert!(block.contains("SYSLUA_ROOT=/home/me/.local/share/syslua /usr/bin/sys activate-login >>"));
+    assert!(hook.blocks[1].1.contains("activate-login --logout"));
+
+    let profile = "export EDITOR=vim\n";
+    let installed = with_block(profile, Some(block));
+    assert_eq!(with_block(&installed, Some(block)), installed);
+    assert_eq!(with_block(&installed, None), profile);
+    assert_eq!(with_block("no newline", None), "no newline");
+  }
+
+  #[test]
+  fn install_and_uninstall_keep_the_rest_of_the_profile() {
+    let home = tempfile::TempDir::new().unwrap();
+    let profile = home.path().join(".profile");
+    std::fs::write(&profile, "export EDITOR=vim\n").unwrap();
+    let hook = shell(Path::new("/usr/bin/sys"), Path::new("/syslua"), home.path());
+
+    assert!(!hook.is_installed());
+    hook.install().unwrap();
+    assert!(hook.is_installed());
+    assert!(
+      std::fs::read_to_string(home.path().join(".bash_logout"))
+        .unwrap()
+        .contains("--logout")
+    );
+
+    hook.uninstall().unwrap();
+    assert!(!hook.is_installed());
+    assert_eq!(std::fs::read_to_string(&profile).unwrap(), "export EDITOR=vim\n");
+  }
+
+  #[test]
+  fn launchd_agent_runs_at_load() {
+    let hook = launchd(Path::new("/opt/a&b/sys"), Path::new("/syslua"), Path::new("/Users/me"));
+    let (path, plist) = &hook.files[0];
+    assert_eq!(path, Path::new("/Users/me/Library/LaunchAgents/org.syslua.login.plist"));
+    assert!(plist.contains("<string>/opt/a&amp;b/sys</string>"));
+    assert!(plist.contains("<key>RunAtLoad</key>\n  <true/>"));
+  }
+}
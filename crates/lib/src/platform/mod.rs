@@ -1,18 +1,36 @@
 //! Cross-platform abstractions.
 //!
-//! Provides platform detection, path conventions, and OS-specific utilities.
+//! Provides platform detection, host facts (WSL, containers, virtualization,
+//! init system), path conventions, and OS-specific utilities.
 
 pub mod arch;
+pub mod archive;
+pub mod cgroup;
+pub mod disk;
+pub mod firewall;
+pub mod font;
 pub mod immutable;
 pub mod link;
+pub mod login_hook;
+pub mod network;
 pub mod os;
 pub mod paths;
+pub mod priority;
+pub mod reflink;
+pub mod run_as;
+pub mod shell;
+pub mod stdio;
+pub mod virt;
 
 use arch::Arch;
 use os::Os;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
+use std::str::FromStr;
 
-pub use immutable::{ImmutableError, make_immutable, make_mutable};
+pub use immutable::{ImmutableError, make_immutable, make_mutable, remove_store_path};
+pub use shell::Shell;
+pub use virt::{Virtualization, has_systemd, is_container, is_wsl};
 
 /// Platform identifier combining architecture and OS (e.g., "aarch64-darwin")
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -49,6 +67,96 @@ impl fmt::Display for Platform {
   }
 }
 
+impl FromStr for Platform {
+  type Err = String;
+
+  /// Parse a platform triple as returned by [`Platform::triple`].
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let unknown = || {
+      format!(
+        "unknown platform '{}' (expected <arch>-<os> like x86_64-linux or aarch64-darwin)",
+        s
+      )
+    };
+    let (arch, os) = s.split_once('-').ok_or_else(unknown)?;
+    let arch = match arch {
+      "x86_64" => Arch::X86_64,
+      "aarch64" => Arch::Aarch64,
+      _ => return Err(unknown()),
+    };
+    let os = match os {
+      "linux" => Os::Linux,
+      "darwin" => Os::MacOs,
+      "windows" => Os::Windows,
+      _ => return Err(unknown()),
+    };
+    Ok(Self { arch, os })
+  }
+}
+
+impl Serialize for Platform {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl<'de> Deserialize<'de> for Platform {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    String::deserialize(deserializer)?
+      .parse()
+      .map_err(serde::de::Error::custom)
+  }
+}
+
+/// Facts about the host environment, exposed to Lua as `sys.facts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Facts {
+  /// WSL, container or hypervisor the process runs in, if any.
+  pub virtualization: Option<Virtualization>,
+  /// systemd is the running init system.
+  pub systemd: bool,
+  /// launchd manages services (macOS).
+  pub launchd: bool,
+}
+
+impl Facts {
+  /// Boolean facts that binds can list in `requires`.
+  pub const FEATURES: &'static [&'static str] = &["systemd", "launchd", "wsl", "container", "vm"];
+
+  /// Detect facts for the current host.
+  pub fn current() -> Self {
+    Self {
+      virtualization: Virtualization::current(),
+      systemd: has_systemd(),
+      launchd: cfg!(target_os = "macos"),
+    }
+  }
+
+  /// Facts assumed when evaluating a config for `platform` on another host,
+  /// which can't be detected: no virtualization, systemd on Linux and
+  /// launchd on macOS.
+  pub fn for_platform(platform: Platform) -> Self {
+    Self {
+      virtualization: None,
+      systemd: platform.os == Os::Linux,
+      launchd: platform.os == Os::MacOs,
+    }
+  }
+
+  /// Look up a boolean fact by name, or `None` if the name is unknown.
+  pub fn feature(&self, name: &str) -> Option<bool> {
+    let virt = self.virtualization;
+    match name {
+      "systemd" => Some(self.systemd),
+      "launchd" => Some(self.launchd),
+      "wsl" => Some(virt == Some(Virtualization::Wsl)),
+      "container" => Some(virt.is_some_and(|v| v.is_container())),
+      "vm" => Some(virt.is_some_and(|v| v.is_vm())),
+      _ => None,
+    }
+  }
+}
+
 /// Returns the platform triple for the current system (e.g., "aarch64-darwin")
 ///
 /// Returns `None` if the current platform is not supported
@@ -56,6 +164,21 @@ pub fn platform_triple() -> Option<String> {
   Platform::current().map(|p| p.triple())
 }
 
+/// Returns the hostname of the current machine.
+///
+/// Returns `None` if the hostname cannot be determined.
+#[cfg(unix)]
+pub fn hostname() -> Option<String> {
+  let uname = rustix::system::uname();
+  let name = uname.nodename().to_string_lossy().into_owned();
+  if name.is_empty() { None } else { Some(name) }
+}
+
+#[cfg(windows)]
+pub fn hostname() -> Option<String> {
+  std::env::var("COMPUTERNAME").ok().filter(|name| !name.is_empty())
+}
+
 /// Check if the current process is running with elevated privileges.
 ///
 /// On Unix systems, this checks if the effective user ID is root (0).
@@ -94,3 +217,35 @@ pub fn is_elevated() -> bool {
     result != 0 && elevation.TokenIsElevated != 0
   }
 }
+
+/// Whether a process with `pid` is running.
+#[cfg(unix)]
+pub fn process_exists(pid: u32) -> bool {
+  use rustix::io::Errno;
+  use rustix::process::{Pid, test_kill_process};
+
+  let Some(pid) = i32::try_from(pid).ok().and_then(Pid::from_raw) else {
+    return false;
+  };
+  // Signalling another user's process is denied, but it exists
+  matches!(test_kill_process(pid), Ok(()) | Err(Errno::PERM))
+}
+
+#[cfg(windows)]
+pub fn process_exists(pid: u32) -> bool {
+  use windows_sys::Win32::{
+    Foundation::{CloseHandle, STILL_ACTIVE},
+    System::Threading::{GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION},
+  };
+
+  unsafe {
+    let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+    if process.is_null() {
+      return false;
+    }
+    let mut code: u32 = 0;
+    let running = GetExitCodeProcess(process, &mut code) != 0 && code == STILL_ACTIVE as u32;
+    CloseHandle(process);
+    running
+  }
+}
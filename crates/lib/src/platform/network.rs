@@ -0,0 +1,177 @@
+//! Network isolation for build commands.
+//!
+//! When builds run with network isolation, every proxy variable of a build
+//! command points at a local proxy that refuses all requests and records what
+//! was asked for. Tools that honor proxy settings (curl, wget, git, pip, npm,
+//! ...) fail fast instead of quietly downloading, and the build fails naming
+//! what it tried to reach. `fetch_url` downloads in-process and is unaffected,
+//! so it stays the only sanctioned way to fetch during a build.
+//!
+//! This guards against accidental downloads; it is not a sandbox. Programs
+//! that open sockets directly bypass the proxy.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Proxy variables pointed at the deny proxy.
+const PROXY_VARS: &[&str] = &[
+  "http_proxy",
+  "HTTP_PROXY",
+  "https_proxy",
+  "HTTPS_PROXY",
+  "ftp_proxy",
+  "FTP_PROXY",
+  "all_proxy",
+  "ALL_PROXY",
+];
+
+/// Upper bound on the request head read from a client.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+const DENIED_BODY: &str = "syslua: network access is disabled for builds; download with fetch_url instead\n";
+
+/// A local HTTP proxy that refuses every request.
+///
+/// The proxy stops when this value is dropped.
+#[derive(Debug)]
+pub struct DenyProxy {
+  addr: SocketAddr,
+  attempts: Arc<Mutex<Vec<String>>>,
+  task: JoinHandle<()>,
+}
+
+impl DenyProxy {
+  /// Start a deny proxy on a free loopback port.
+  pub async fn start() -> std::io::Result<Self> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let addr = listener.local_addr()?;
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+
+    let recorded = attempts.clone();
+    let task = tokio::spawn(async move {
+      loop {
+        match listener.accept().await {
+          Ok((stream, _)) => {
+            let recorded = recorded.clone();
+            tokio::spawn(async move {
+              if let Err(e) = deny(stream, &recorded).await {
+                debug!(error = %e, "deny proxy connection failed");
+              }
+            });
+          }
+          Err(e) => {
+            warn!(error = %e, "deny proxy stopped accepting connections");
+            return;
+          }
+        }
+      }
+    });
+
+    debug!(%addr, "started network deny proxy");
+    Ok(Self { addr, attempts, task })
+  }
+
+  /// The proxy URL, e.g. `http://127.0.0.1:41234`.
+  pub fn url(&self) -> String {
+    format!("http://{}", self.addr)
+  }
+
+  /// Environment variables that route a command's traffic through the proxy.
+  ///
+  /// `no_proxy` is cleared so no host bypasses it, and git may only use local
+  /// (`file`) transports since ssh doesn't honor proxy settings.
+  pub fn env(&self) -> Vec<(&'static str, String)> {
+    let url = self.url();
+    let mut env: Vec<_> = PROXY_VARS.iter().map(|var| (*var, url.clone())).collect();
+    env.push(("no_proxy", String::new()));
+    env.push(("NO_PROXY", String::new()));
+    env.push(("GIT_ALLOW_PROTOCOL", "file".to_string()));
+    env
+  }
+
+  /// Targets requested since the last call, in order.
+  pub fn take_attempts(&self) -> Vec<String> {
+    std::mem::take(&mut *self.attempts.lock().expect("deny proxy attempts poisoned"))
+  }
+}
+
+impl Drop for DenyProxy {
+  fn drop(&mut self) {
+    self.task.abort();
+  }
+}
+
+/// Record the request target of one client and answer `403 Forbidden`.
+async fn deny(mut stream: TcpStream, attempts: &Mutex<Vec<String>>) -> std::io::Result<()> {
+  let mut head = Vec::new();
+  let mut buf = [0u8; 1024];
+  while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST_HEAD {
+    let n = stream.read(&mut buf).await?;
+    if n == 0 {
+      break;
+    }
+    head.extend_from_slice(&buf[..n]);
+  }
+
+  let head = String::from_utf8_lossy(&head);
+  let target = request_target(&head).unwrap_or("<unknown>").to_string();
+  debug!(%target, "denied build network access");
+  attempts.lock().expect("deny proxy attempts poisoned").push(target);
+
+  let response = format!(
+    "HTTP/1.1 403 Forbidden\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+    DENIED_BODY.len(),
+    DENIED_BODY
+  );
+  stream.write_all(response.as_bytes()).await?;
+  stream.shutdown().await
+}
+
+/// The target of an HTTP request line: `host:port` for `CONNECT`, the URL otherwise.
+fn request_target(head: &str) -> Option<&str> {
+  let line = head.lines().next()?;
+  let mut parts = line.split_whitespace();
+  parts.next()?;
+  parts.next()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn request_target_from_request_line() {
+    assert_eq!(
+      request_target("CONNECT example.com:443 HTTP/1.1\r\nHost: example.com\r\n\r\n"),
+      Some("example.com:443")
+    );
+    assert_eq!(
+      request_target("GET http://example.com/a.tar.gz HTTP/1.1\r\n\r\n"),
+      Some("http://example.com/a.tar.gz")
+    );
+    assert_eq!(request_target(""), None);
+  }
+
+  #[tokio::test]
+  async fn proxy_refuses_and_records_requests() {
+    let proxy = DenyProxy::start().await.unwrap();
+
+    let mut stream = TcpStream::connect(proxy.addr).await.unwrap();
+    stream
+      .write_all(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n")
+      .await
+      .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 403"));
+    assert!(response.contains("fetch_url"));
+    assert_eq!(proxy.take_attempts(), vec!["example.com:443".to_string()]);
+    assert!(proxy.take_attempts().is_empty());
+  }
+}
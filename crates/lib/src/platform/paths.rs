@@ -1,10 +1,32 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 use crate::consts::APP_NAME;
 use crate::platform::is_elevated;
 
+/// Root selected with `sys --store` or a config's `settings.store`.
+static STORE_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Keep the store, snapshots, plans and daemon socket of this process under
+/// `root`, e.g. a project's `.syslua/`, instead of the user or system root.
+///
+/// Takes precedence over `SYSLUA_ROOT` and the per-directory variables. The
+/// root can only be selected once; returns `false` if a different one already was.
+pub fn set_store_root(root: &Path) -> bool {
+  let root = std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf());
+  STORE_ROOT.get_or_init(|| root.clone()) == &root
+}
+
+/// The root selected with [`set_store_root`], if any.
+pub fn store_root() -> Option<&'static Path> {
+  STORE_ROOT.get().map(PathBuf::as_path)
+}
+
 #[cfg(windows)]
 pub fn root_dir() -> PathBuf {
+  if let Some(root) = store_root() {
+    return root.to_path_buf();
+  }
   if let Ok(root) = std::env::var("SYSLUA_ROOT") {
     return PathBuf::from(root);
   }
@@ -19,6 +41,9 @@ pub fn root_dir() -> PathBuf {
 
 #[cfg(not(windows))]
 pub fn root_dir() -> PathBuf {
+  if let Some(root) = store_root() {
+    return root.to_path_buf();
+  }
   if let Ok(root) = std::env::var("SYSLUA_ROOT") {
     return PathBuf::from(root);
   }
@@ -106,6 +131,9 @@ pub fn cache_dir() -> PathBuf {
 }
 
 pub fn store_dir() -> PathBuf {
+  if let Some(root) = store_root() {
+    return root.join("store");
+  }
   std::env::var("SYSLUA_STORE")
     .map(PathBuf::from)
     .unwrap_or_else(|_| root_dir().join("store"))
@@ -118,17 +146,144 @@ pub fn parent_store_dir() -> Option<PathBuf> {
 }
 
 pub fn snapshots_dir() -> PathBuf {
+  if let Some(root) = store_root() {
+    return root.join("snapshots");
+  }
   std::env::var("SYSLUA_SNAPSHOTS")
     .map(PathBuf::from)
     .unwrap_or_else(|_| root_dir().join("snapshots"))
 }
 
 pub fn plans_dir() -> PathBuf {
+  if let Some(root) = store_root() {
+    return root.join("plans");
+  }
   std::env::var("SYSLUA_PLANS")
     .map(PathBuf::from)
     .unwrap_or_else(|_| root_dir().join("plans"))
 }
 
+/// Returns the IPC endpoint the daemon listens on.
+///
+/// A unix socket in the root directory, overridable with `SYSLUA_DAEMON_SOCKET`
+/// unless a store root was selected.
+#[cfg(not(windows))]
+pub fn daemon_socket_path() -> PathBuf {
+  if let Some(root) = store_root() {
+    return root.join("daemon.sock");
+  }
+  std::env::var("SYSLUA_DAEMON_SOCKET")
+    .map(PathBuf::from)
+    .unwrap_or_else(|_| root_dir().join("daemon.sock"))
+}
+
+/// Returns the IPC endpoint the daemon listens on.
+///
+/// A named pipe, overridable with `SYSLUA_DAEMON_SOCKET`. A selected store
+/// root gets a pipe of its own, named after the root.
+#[cfg(windows)]
+pub fn daemon_socket_path() -> PathBuf {
+  if let Some(root) = store_root() {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    let mut hasher = DefaultHasher::new();
+    root.hash(&mut hasher);
+    return PathBuf::from(format!(r"\\.\pipe\{}-daemon-{:016x}", APP_NAME, hasher.finish()));
+  }
+  std::env::var("SYSLUA_DAEMON_SOCKET")
+    .map(PathBuf::from)
+    .unwrap_or_else(|_| PathBuf::from(format!(r"\\.\pipe\{}-daemon", APP_NAME)))
+}
+
+/// Returns the IPC endpoint of the system daemon (`sys daemon start --system`).
+///
+/// A world-connectable unix socket next to the system store, overridable with
+/// `SYSLUA_SYSTEM_DAEMON_SOCKET`.
+#[cfg(not(windows))]
+pub fn system_daemon_socket_path() -> PathBuf {
+  std::env::var("SYSLUA_SYSTEM_DAEMON_SOCKET")
+    .map(PathBuf::from)
+    .unwrap_or_else(|_| PathBuf::from("/").join(APP_NAME).join("daemon.sock"))
+}
+
+/// Returns the IPC endpoint of the system daemon (`sys daemon start --system`).
+///
+/// A named pipe, overridable with `SYSLUA_SYSTEM_DAEMON_SOCKET`.
+#[cfg(windows)]
+pub fn system_daemon_socket_path() -> PathBuf {
+  std::env::var("SYSLUA_SYSTEM_DAEMON_SOCKET")
+    .map(PathBuf::from)
+    .unwrap_or_else(|_| PathBuf::from(format!(r"\\.\pipe\{}-system-daemon", APP_NAME)))
+}
+
+/// Expand a leading `~` and environment variable references in a path string.
+///
+/// `~` and `~/...` expand to [`home_dir`]. Variables may be written as `$NAME` or
+/// `${NAME}` on every platform, and additionally as `%NAME%` on Windows. Unset
+/// variables expand to an empty string. Use `$$` (or `%%` on Windows) for a literal.
+/// A `${` or `%` without its closing `}` or `%` is kept as written.
+pub fn expand_path(input: &str) -> PathBuf {
+  let with_home = match input.strip_prefix('~') {
+    Some("") => home_dir().to_string_lossy().into_owned(),
+    Some(rest) if rest.starts_with('/') || rest.starts_with('\\') => {
+      format!("{}{}", home_dir().to_string_lossy(), rest)
+    }
+    _ => input.to_string(),
+  };
+  PathBuf::from(expand_env_vars(&with_home))
+}
+
+fn expand_env_vars(input: &str) -> String {
+  expand_env_vars_with(input, cfg!(windows))
+}
+
+/// [`expand_env_vars`], with `%NAME%` references expanded when `percent_vars`.
+fn expand_env_vars_with(input: &str, percent_vars: bool) -> String {
+  let mut out = String::with_capacity(input.len());
+  let mut chars = input.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    match c {
+      '$' => match chars.peek().copied() {
+        Some('$') => {
+          chars.next();
+          out.push('$');
+        }
+        // Without a closing brace, `${` is kept literally
+        Some('{') if chars.clone().any(|ch| ch == '}') => {
+          chars.next();
+          let name: String = chars.by_ref().take_while(|&ch| ch != '}').collect();
+          out.push_str(&std::env::var(&name).unwrap_or_default());
+        }
+        Some(ch) if ch.is_ascii_alphanumeric() || ch == '_' => {
+          let mut name = String::new();
+          while let Some(&ch) = chars.peek() {
+            if ch.is_ascii_alphanumeric() || ch == '_' {
+              name.push(ch);
+              chars.next();
+            } else {
+              break;
+            }
+          }
+          out.push_str(&std::env::var(&name).unwrap_or_default());
+        }
+        _ => out.push('$'),
+      },
+      // Scans chars rather than byte offsets so non-ASCII names stay aligned
+      '%' if percent_vars && chars.clone().any(|ch| ch == '%') => {
+        let name: String = chars.by_ref().take_while(|&ch| ch != '%').collect();
+        if name.is_empty() {
+          out.push('%');
+        } else {
+          out.push_str(&std::env::var(&name).unwrap_or_default());
+        }
+      }
+      _ => out.push(c),
+    }
+  }
+
+  out
+}
+
 #[cfg(test)]
 #[cfg(not(windows))]
 mod tests {
@@ -183,4 +338,60 @@ mod tests {
       assert_eq!(parent_store_dir(), Some(PathBuf::from("/parent/store")));
     });
   }
+
+  #[test]
+  #[serial]
+  fn expand_path_expands_tilde() {
+    temp_env::with_vars([("HOME", Some("/home/user"))], || {
+      assert_eq!(expand_path("~"), PathBuf::from("/home/user"));
+      assert_eq!(expand_path("~/.config/app"), PathBuf::from("/home/user/.config/app"));
+      assert_eq!(expand_path("/srv/~/x"), PathBuf::from("/srv/~/x"));
+      assert_eq!(expand_path("~other/x"), PathBuf::from("~other/x"));
+    });
+  }
+
+  #[test]
+  #[serial]
+  fn expand_path_expands_env_vars() {
+    temp_env::with_vars(
+      [
+        ("SYSLUA_TEST_DIR", Some("/opt/tools")),
+        ("SYSLUA_TEST_UNSET", None::<&str>),
+      ],
+      || {
+        assert_eq!(expand_path("$SYSLUA_TEST_DIR/bin"), PathBuf::from("/opt/tools/bin"));
+        assert_eq!(expand_path("${SYSLUA_TEST_DIR}x"), PathBuf::from("/opt/toolsx"));
+        assert_eq!(expand_path("/a/$SYSLUA_TEST_UNSET/b"), PathBuf::from("/a//b"));
+        assert_eq!(expand_path("/cost/$$5"), PathBuf::from("/cost/$5"));
+        assert_eq!(
+          expand_path("/a/${SYSLUA_TEST_DIR/b"),
+          PathBuf::from("/a/${SYSLUA_TEST_DIR/b")
+        );
+      },
+    );
+  }
+
+  #[test]
+  #[serial]
+  fn percent_vars_expand_non_ascii_names_and_values() {
+    temp_env::with_vars(
+      [
+        ("SYSLUA_TEST_ÜBER", Some("C:\\Benutzer\\Jürgen")),
+        ("SYSLUA_TEST_DIR", Some("C:\\tools")),
+      ],
+      || {
+        assert_eq!(
+          expand_env_vars_with("%SYSLUA_TEST_ÜBER%\\bin", true),
+          "C:\\Benutzer\\Jürgen\\bin"
+        );
+        assert_eq!(
+          expand_env_vars_with("%SYSLUA_TEST_DIR%\\ñ\\%SYSLUA_TEST_DIR%", true),
+          "C:\\tools\\ñ\\C:\\tools"
+        );
+        assert_eq!(expand_env_vars_with("100%% é", true), "100% é");
+        assert_eq!(expand_env_vars_with("50% é", true), "50% é");
+        assert_eq!(expand_env_vars_with("%SYSLUA_TEST_DIR%", false), "%SYSLUA_TEST_DIR%");
+      },
+    );
+  }
 }
@@ -0,0 +1,244 @@
+//! Lowered scheduling priority for the commands an apply spawns.
+//!
+//! An apply started from a login hook shouldn't saturate the machine. With a
+//! [`Throttle`] in effect, every command spawned by a build or bind action
+//! runs at a lower priority:
+//!
+//! - Unix: the command's niceness is raised by `nice` (19 in background mode)
+//! - Linux, background mode: the command gets the idle I/O scheduling class
+//! - Windows: the command runs with the below-normal priority class, or the
+//!   idle class for background mode and niceness 15 and up
+//!
+//! Background mode also runs one build or bind at a time. The sys process
+//! itself keeps its priority, so a daemon can serve throttled and regular
+//! applies alike.
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+/// Highest niceness on Unix.
+pub const MAX_NICE: u8 = 19;
+
+/// Parallelism of background applies.
+pub const BACKGROUND_PARALLELISM: usize = 1;
+
+/// Niceness from which Windows commands get the idle priority class.
+#[cfg(windows)]
+const IDLE_NICE: u8 = 15;
+
+/// How much to lower the priority of spawned commands.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Throttle {
+  /// Niceness added to spawned commands (0-19); 0 leaves it unchanged.
+  #[serde(default, skip_serializing_if = "is_zero")]
+  pub nice: u8,
+  /// Lowest CPU and I/O priority, one build or bind at a time.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub background: bool,
+}
+
+fn is_zero(nice: &u8) -> bool {
+  *nice == 0
+}
+
+impl Throttle {
+  /// No throttling.
+  pub const NONE: Throttle = Throttle {
+    nice: 0,
+    background: false,
+  };
+
+  /// Whether commands run at their normal priority.
+  pub fn is_none(&self) -> bool {
+    self.nice() == 0 && !self.background
+  }
+
+  /// The stronger of both throttles, e.g. of the command line and the config.
+  pub fn max(self, other: Throttle) -> Throttle {
+    Throttle {
+      nice: self.nice.max(other.nice),
+      background: self.background || other.background,
+    }
+  }
+
+  /// Niceness added to spawned commands.
+  pub fn nice(&self) -> u8 {
+    if self.background {
+      MAX_NICE
+    } else {
+      self.nice.min(MAX_NICE)
+    }
+  }
+
+  /// Cap `parallelism` for background mode.
+  pub fn parallelism(&self, parallelism: usize) -> usize {
+    if self.background {
+      parallelism.min(BACKGROUND_PARALLELISM)
+    } else {
+      parallelism
+    }
+  }
+
+  /// Make `command` run at the lowered priority once spawned.
+  pub(crate) fn apply_to(&self, command: &mut Command) {
+    if self.is_none() {
+      return;
+    }
+
+    #[cfg(unix)]
+    {
+      let nice = i32::from(self.nice());
+      let idle_io = self.background;
+      // SAFETY: the closure only makes async-signal-safe syscalls
+      unsafe {
+        command.pre_exec(move || {
+          // Failing to lower the priority shouldn't fail the command
+          let _ = rustix::process::nice(nice);
+          if idle_io {
+            set_idle_io();
+          }
+          Ok(())
+        });
+      }
+    }
+
+    #[cfg(windows)]
+    {
+      use windows_sys::Win32::System::Threading::{BELOW_NORMAL_PRIORITY_CLASS, IDLE_PRIORITY_CLASS};
+
+      let class = if self.background || self.nice() >= IDLE_NICE {
+        IDLE_PRIORITY_CLASS
+      } else {
+        BELOW_NORMAL_PRIORITY_CLASS
+      };
+      command.creation_flags(class);
+    }
+  }
+}
+
+/// Put the calling process in the idle I/O scheduling class.
+#[cfg(unix)]
+fn set_idle_io() {
+  #[cfg(target_os = "linux")]
+  {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+    // SAFETY: ioprio_set only reads its integer arguments
+    unsafe {
+      libc::syscall(
+        libc::SYS_ioprio_set,
+        IOPRIO_WHO_PROCESS,
+        0,
+        IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+      );
+    }
+  }
+}
+
+static CURRENT: Mutex<Throttle> = Mutex::new(Throttle::NONE);
+
+/// The throttle applied to commands spawned now.
+pub fn current() -> Throttle {
+  *CURRENT.lock().expect("throttle lock poisoned")
+}
+
+/// Throttle the commands spawned until the returned guard is dropped.
+pub fn throttle_commands(throttle: Throttle) -> ThrottleGuard {
+  let previous = std::mem::replace(&mut *CURRENT.lock().expect("throttle lock poisoned"), throttle);
+  ThrottleGuard { previous }
+}
+
+/// Restores the previous throttle when dropped.
+#[derive(Debug)]
+#[must_use = "commands are only throttled while the guard is alive"]
+pub struct ThrottleGuard {
+  previous: Throttle,
+}
+
+impl Drop for ThrottleGuard {
+  fn drop(&mut self) {
+    *CURRENT.lock().expect("throttle lock poisoned") = self.previous;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serial_test::serial;
+
+  #[test]
+  fn background_takes_lowest_priority_and_caps_parallelism() {
+    let background = Throttle {
+      nice: 5,
+      background: true,
+    };
+    assert_eq!(background.nice(), MAX_NICE);
+    assert_eq!(background.parallelism(8), BACKGROUND_PARALLELISM);
+
+    let nice = Throttle {
+      nice: 40,
+      background: false,
+    };
+    assert_eq!(nice.nice(), MAX_NICE);
+    assert_eq!(nice.parallelism(8), 8);
+    assert!(Throttle::NONE.is_none());
+  }
+
+  #[test]
+  fn max_keeps_the_stronger_throttle() {
+    let cli = Throttle {
+      nice: 10,
+      background: false,
+    };
+    let config = Throttle {
+      nice: 5,
+      background: true,
+    };
+    assert_eq!(
+      cli.max(config),
+      Throttle {
+        nice: 10,
+        background: true
+      }
+    );
+    assert_eq!(Throttle::NONE.max(cli), cli);
+  }
+
+  #[test]
+  #[serial]
+  fn guard_restores_previous_throttle() {
+    let throttle = Throttle {
+      nice: 10,
+      background: false,
+    };
+    {
+      let _guard = throttle_commands(throttle);
+      assert_eq!(current(), throttle);
+    }
+    assert_eq!(current(), Throttle::NONE);
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn spawned_commands_run_nicer() {
+    async fn niceness(throttle: Throttle) -> i32 {
+      let mut command = Command::new("sh");
+      command.args(["-c", "nice"]);
+      throttle.apply_to(&mut command);
+      let output = command.output().await.unwrap();
+      String::from_utf8_lossy(&output.stdout).trim().parse().unwrap()
+    }
+
+    let base = niceness(Throttle::NONE).await;
+    let lowered = niceness(Throttle {
+      nice: 5,
+      background: false,
+    })
+    .await;
+    assert_eq!(lowered, (base + 5).min(i32::from(MAX_NICE)));
+  }
+}
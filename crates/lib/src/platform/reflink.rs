@@ -0,0 +1,58 @@
+//! Copy-on-write file clones.
+//!
+//! [`clone_file`] makes `dst` share the data blocks of `src` instead of
+//! copying them, which takes the same short time however large the file is:
+//!
+//! | Platform | Mechanism                                        |
+//! | -------- | ------------------------------------------------ |
+//! | Linux    | `FICLONE` ioctl (Btrfs, XFS, bcachefs, ...)      |
+//! | macOS    | `clonefile` (APFS)                               |
+//! | Windows  | not supported                                    |
+//!
+//! Clones only work within one filesystem that supports them, so callers
+//! fall back to a regular copy when it fails; see
+//! [`crate::util::fs::copy_file`].
+
+use std::io;
+use std::path::Path;
+
+/// Clone the file `src` to `dst`, with the permissions of `src`.
+///
+/// Replaces `dst` on Linux; on macOS `dst` must not exist. Fails when the
+/// filesystem can't clone, e.g. across filesystems or on ext4, leaving `dst`
+/// empty or missing for the caller's fallback copy to fill.
+#[cfg(target_os = "linux")]
+pub fn clone_file(src: &Path, dst: &Path) -> io::Result<()> {
+  let source = std::fs::File::open(src)?;
+  let target = std::fs::File::create(dst)?;
+  rustix::fs::ioctl_ficlone(&target, &source).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+  target.set_permissions(source.metadata()?.permissions())
+}
+
+/// Clone the file `src` to `dst`, with the permissions of `src`.
+///
+/// Replaces `dst` on Linux; on macOS `dst` must not exist. Fails when the
+/// filesystem can't clone, e.g. across volumes or on HFS+, leaving `dst`
+/// missing for the caller's fallback copy to fill.
+#[cfg(target_os = "macos")]
+pub fn clone_file(src: &Path, dst: &Path) -> io::Result<()> {
+  use std::ffi::CString;
+  use std::os::unix::ffi::OsStrExt;
+
+  let c_src = CString::new(src.as_os_str().as_bytes()).map_err(io::Error::other)?;
+  let c_dst = CString::new(dst.as_os_str().as_bytes()).map_err(io::Error::other)?;
+  // SAFETY: both are valid NUL-terminated paths
+  if unsafe { libc::clonefile(c_src.as_ptr(), c_dst.as_ptr(), 0) } != 0 {
+    return Err(io::Error::last_os_error());
+  }
+  Ok(())
+}
+
+/// Clone the file `src` to `dst`; not supported on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn clone_file(_src: &Path, _dst: &Path) -> io::Result<()> {
+  Err(io::Error::new(
+    io::ErrorKind::Unsupported,
+    "copy-on-write clones are not supported on this platform",
+  ))
+}
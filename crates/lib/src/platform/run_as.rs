@@ -0,0 +1,585 @@
+//! Running commands as another user.
+//!
+//! Bind `exec` actions may set `run_as` to a user name (`elevate = true` is
+//! `run_as = "root"`). How the command switches user depends on this process:
+//!
+//! - Running as root ([`RunAsMethod::Setuid`]): the spawned process drops to
+//!   the user's uid, primary gid and supplementary groups before it execs.
+//! - Otherwise ([`RunAsMethod::Sudo`]): the command is wrapped in
+//!   `sudo -n -u <user> -- env -i ...`, which must be allowed without a
+//!   password. The isolated environment is passed through `env` because sudo
+//!   resets it.
+//!
+//! Either way the command gets a temp directory of its own, owned by the user
+//! ([`RunAs::create_tmp_dir`]).
+//!
+//! Windows is not supported. Which users actions may switch to is decided by
+//! the config's `settings.run_as` when it is evaluated.
+//!
+//! The system daemon also runs the builds of untrusted users as build users,
+//! the [members](group_members) of a group: [`chown_tree`] hands the output
+//! directory to one, and [`reclaim_tree`] takes it back before anything runs
+//! as root again. [`kill_user_processes`] ends what a build left running.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::process::Command;
+
+/// How a command was made to run as another user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunAsMethod {
+  /// Spawned by root, switching uid and gid before exec.
+  Setuid,
+  /// Wrapped in non-interactive `sudo`.
+  Sudo,
+}
+
+/// The user an action ran as, recorded in its result for auditing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunAs {
+  pub user: String,
+  pub method: RunAsMethod,
+}
+
+#[derive(Debug, Error)]
+pub enum RunAsError {
+  #[error("unknown user '{0}'")]
+  UnknownUser(String),
+
+  #[error("unknown group '{0}'")]
+  UnknownGroup(String),
+
+  #[error("group '{0}' has no members to run builds as")]
+  NoBuildUsers(String),
+
+  #[error("no build user of group '{0}' is free")]
+  NoFreeBuildUser(String),
+
+  #[error("{0} is not in PATH")]
+  NotInPath(&'static str),
+
+  #[error("running commands as another user is not supported on this platform")]
+  Unsupported,
+
+  #[error("failed to look up user '{user}': {source}")]
+  Lookup {
+    user: String,
+    #[source]
+    source: std::io::Error,
+  },
+
+  #[error("failed to {action} the temp directory of '{user}': {source}")]
+  TmpDir {
+    action: &'static str,
+    user: String,
+    #[source]
+    source: std::io::Error,
+  },
+}
+
+impl RunAs {
+  /// Run as `user` with the method available to this process.
+  pub fn for_user(user: &str) -> Result<Self, RunAsError> {
+    if cfg!(not(any(target_os = "linux", target_os = "macos"))) {
+      return Err(RunAsError::Unsupported);
+    }
+    let method = if super::is_elevated() {
+      RunAsMethod::Setuid
+    } else {
+      RunAsMethod::Sudo
+    };
+    Ok(Self {
+      user: user.to_string(),
+      method,
+    })
+  }
+
+  /// Make `command` run as this user.
+  ///
+  /// Call once the program, arguments, environment and working directory are
+  /// set: a sudo wrapper is a new command built from them.
+  pub fn apply(&self, command: Command) -> Result<Command, RunAsError> {
+    match self.method {
+      RunAsMethod::Setuid => self.setuid(command),
+      RunAsMethod::Sudo => self.sudo(command),
+    }
+  }
+
+  #[cfg(any(target_os = "linux", target_os = "macos"))]
+  fn setuid(&self, mut command: Command) -> Result<Command, RunAsError> {
+    let user = lookup_user(&self.user)?;
+    // SAFETY: the closure only makes async-signal-safe syscalls on data
+    // prepared before the fork
+    unsafe {
+      command.pre_exec(move || {
+        if libc::setgroups(user.groups.len() as _, user.groups.as_ptr()) != 0
+          || libc::setgid(user.gid) != 0
+          || libc::setuid(user.uid) != 0
+        {
+          return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+      });
+    }
+    Ok(command)
+  }
+
+  #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+  fn setuid(&self, _command: Command) -> Result<Command, RunAsError> {
+    Err(RunAsError::Unsupported)
+  }
+
+  fn sudo(&self, command: Command) -> Result<Command, RunAsError> {
+    let inner = command.as_std();
+
+    let mut wrapped = self.sudo_command("env")?;
+    wrapped.arg("-i");
+    for (key, value) in inner.get_envs() {
+      if let Some(value) = value {
+        let mut pair = key.to_os_string();
+        pair.push("=");
+        pair.push(value);
+        wrapped.arg(pair);
+      }
+    }
+    wrapped.arg(inner.get_program()).args(inner.get_args());
+    if let Some(dir) = inner.get_current_dir() {
+      wrapped.current_dir(dir);
+    }
+    Ok(wrapped)
+  }
+
+  /// `sudo -n -u <user> -- <program>`, with `program` looked up in `PATH`.
+  fn sudo_command(&self, program: &'static str) -> Result<Command, RunAsError> {
+    let sudo = find_in_path("sudo").ok_or(RunAsError::NotInPath("sudo"))?;
+    let program = find_in_path(program).ok_or(RunAsError::NotInPath(program))?;
+    let mut command = Command::new(sudo);
+    command.args(["-n", "-u", &self.user, "--"]).arg(program);
+    Ok(command)
+  }
+
+  /// Create a temp directory that only this user can use.
+  ///
+  /// As root it is created in the system temp directory and handed to the
+  /// user; through sudo the user creates it with `mktemp -d`. Remove it with
+  /// [`RunAs::remove_tmp_dir`] once the command has finished.
+  #[cfg(any(target_os = "linux", target_os = "macos"))]
+  pub async fn create_tmp_dir(&self) -> Result<PathBuf, RunAsError> {
+    let tmp_err = |source| RunAsError::TmpDir {
+      action: "create",
+      user: self.user.clone(),
+      source,
+    };
+    match self.method {
+      RunAsMethod::Setuid => {
+        let (uid, gid) = user_ids(&self.user)?;
+        // Created with mode 0700
+        let dir = tempfile::Builder::new()
+          .prefix("syslua-")
+          .tempdir()
+          .map_err(tmp_err)?
+          .keep();
+        if let Err(e) = std::os::unix::fs::chown(&dir, Some(uid), Some(gid)) {
+          let _ = std::fs::remove_dir(&dir);
+          return Err(tmp_err(e));
+        }
+        Ok(dir)
+      }
+      RunAsMethod::Sudo => {
+        let output = self
+          .sudo_command("mktemp")?
+          .arg("-d")
+          .stdin(std::process::Stdio::null())
+          .output()
+          .await
+          .map_err(tmp_err)?;
+        if !output.status.success() {
+          return Err(tmp_err(std::io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+          )));
+        }
+        Ok(PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+      }
+    }
+  }
+
+  #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+  pub async fn create_tmp_dir(&self) -> Result<PathBuf, RunAsError> {
+    Err(RunAsError::Unsupported)
+  }
+
+  /// Remove a temp directory made by [`RunAs::create_tmp_dir`], with whatever
+  /// the command left in it.
+  pub async fn remove_tmp_dir(&self, dir: &Path) -> Result<(), RunAsError> {
+    let tmp_err = |source| RunAsError::TmpDir {
+      action: "remove",
+      user: self.user.clone(),
+      source,
+    };
+    match self.method {
+      RunAsMethod::Setuid => tokio::fs::remove_dir_all(dir).await.map_err(tmp_err),
+      // Only the user can delete what they wrote inside it
+      RunAsMethod::Sudo => {
+        let status = self
+          .sudo_command("rm")?
+          .arg("-rf")
+          .arg(dir)
+          .stdin(std::process::Stdio::null())
+          .status()
+          .await
+          .map_err(tmp_err)?;
+        if !status.success() {
+          return Err(tmp_err(std::io::Error::other(format!("rm exited with {}", status))));
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+/// `name` in one of the directories of this process's `PATH`.
+fn find_in_path(name: &str) -> Option<PathBuf> {
+  let path = std::env::var_os("PATH")?;
+  std::env::split_paths(&path)
+    .map(|dir| dir.join(name))
+    .find(|candidate| candidate.is_file())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+struct User {
+  uid: libc::uid_t,
+  gid: libc::gid_t,
+  groups: Vec<libc::gid_t>,
+}
+
+/// Look up the uid, gid and supplementary groups of `name`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn lookup_user(name: &str) -> Result<User, RunAsError> {
+  use std::ffi::CString;
+
+  let c_name = CString::new(name).map_err(|_| RunAsError::UnknownUser(name.to_string()))?;
+  let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+  let mut buf = vec![0 as libc::c_char; 16 * 1024];
+  let mut result = std::ptr::null_mut();
+  // SAFETY: every pointer is valid for the duration of the call and `buf.len()` is its size
+  let rc = unsafe { libc::getpwnam_r(c_name.as_ptr(), &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+  if rc != 0 {
+    return Err(RunAsError::Lookup {
+      user: name.to_string(),
+      source: std::io::Error::from_raw_os_error(rc),
+    });
+  }
+  if result.is_null() {
+    return Err(RunAsError::UnknownUser(name.to_string()));
+  }
+  let (uid, gid) = (passwd.pw_uid, passwd.pw_gid);
+
+  // getgrouplist fails when the list is too small; grow it until it fits
+  let mut capacity: libc::c_int = 64;
+  let groups = loop {
+    let mut groups: Vec<libc::gid_t> = vec![0; capacity as usize];
+    let mut count = capacity;
+    // SAFETY: `groups` holds `count` entries; macOS takes the same memory as `c_int`s
+    let rc = unsafe { libc::getgrouplist(c_name.as_ptr(), gid as _, groups.as_mut_ptr() as *mut _, &mut count) };
+    if rc >= 0 {
+      groups.truncate(count as usize);
+      break groups;
+    }
+    if capacity >= 65536 {
+      break vec![gid];
+    }
+    capacity = (capacity * 2).max(count);
+  };
+
+  Ok(User { uid, gid, groups })
+}
+
+/// The uid and primary gid of `name`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn user_ids(name: &str) -> Result<(u32, u32), RunAsError> {
+  lookup_user(name).map(|user| (user.uid, user.gid))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn user_ids(_name: &str) -> Result<(u32, u32), RunAsError> {
+  Err(RunAsError::Unsupported)
+}
+
+/// The gid of the group `name`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn group_id(name: &str) -> Result<u32, RunAsError> {
+  lookup_group(name).map(|(gid, _)| gid)
+}
+
+/// The users listed as members of the group `name`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn group_members(name: &str) -> Result<Vec<String>, RunAsError> {
+  lookup_group(name).map(|(_, members)| members)
+}
+
+/// Look up the gid and listed members of the group `name`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn lookup_group(name: &str) -> Result<(u32, Vec<String>), RunAsError> {
+  use std::ffi::{CStr, CString};
+
+  let c_name = CString::new(name).map_err(|_| RunAsError::UnknownGroup(name.to_string()))?;
+  let mut group: libc::group = unsafe { std::mem::zeroed() };
+  let mut buf = vec![0 as libc::c_char; 64 * 1024];
+  let mut result = std::ptr::null_mut();
+  // SAFETY: every pointer is valid for the duration of the call and `buf.len()` is its size
+  let rc = unsafe { libc::getgrnam_r(c_name.as_ptr(), &mut group, buf.as_mut_ptr(), buf.len(), &mut result) };
+  if rc != 0 {
+    return Err(RunAsError::Lookup {
+      user: name.to_string(),
+      source: std::io::Error::from_raw_os_error(rc),
+    });
+  }
+  if result.is_null() {
+    return Err(RunAsError::UnknownGroup(name.to_string()));
+  }
+
+  let mut members = Vec::new();
+  let mut member = group.gr_mem;
+  // SAFETY: on success gr_mem is a NULL-terminated array of NUL-terminated
+  // strings inside `buf`
+  unsafe {
+    while !member.is_null() && !(*member).is_null() {
+      members.push(CStr::from_ptr(*member).to_string_lossy().into_owned());
+      member = member.add(1);
+    }
+  }
+  Ok((group.gr_gid, members))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn group_members(_name: &str) -> Result<Vec<String>, RunAsError> {
+  Err(RunAsError::Unsupported)
+}
+
+/// Kill every process running as `uid`, such as those a build left behind.
+///
+/// A child switches to `uid`:`gid` and signals all the processes it may,
+/// which are exactly that user's. Only works when running as root.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn kill_user_processes(uid: u32, gid: u32) -> std::io::Result<()> {
+  if uid == 0 {
+    return Err(std::io::Error::new(
+      std::io::ErrorKind::InvalidInput,
+      "refusing to kill the processes of root",
+    ));
+  }
+  // SAFETY: the child only makes async-signal-safe calls before _exit
+  let pid = unsafe { libc::fork() };
+  if pid < 0 {
+    return Err(std::io::Error::last_os_error());
+  }
+  if pid == 0 {
+    unsafe {
+      if libc::setgid(gid) != 0 || libc::setuid(uid) != 0 {
+        libc::_exit(1);
+      }
+      // Repeat until no process is left: one may fork while being signalled
+      while libc::kill(-1, libc::SIGKILL) == 0 {}
+      libc::_exit(0);
+    }
+  }
+
+  let mut status = 0;
+  // SAFETY: `pid` is the child forked above
+  if unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+    return Err(std::io::Error::last_os_error());
+  }
+  // The child may kill itself along with the others
+  if libc::WIFEXITED(status) && libc::WEXITSTATUS(status) != 0 {
+    return Err(std::io::Error::new(
+      std::io::ErrorKind::PermissionDenied,
+      format!("failed to switch to uid {} to kill its processes", uid),
+    ));
+  }
+  Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn kill_user_processes(_uid: u32, _gid: u32) -> std::io::Result<()> {
+  Err(std::io::Error::new(
+    std::io::ErrorKind::Unsupported,
+    "killing the processes of a user is not supported on this platform",
+  ))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn group_id(_name: &str) -> Result<u32, RunAsError> {
+  Err(RunAsError::Unsupported)
+}
+
+/// The name of the user with `uid`, if it has one.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn user_name(uid: u32) -> Option<String> {
+  let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+  let mut buf = vec![0 as libc::c_char; 16 * 1024];
+  let mut result = std::ptr::null_mut();
+  // SAFETY: every pointer is valid for the duration of the call and `buf.len()` is its size
+  let rc = unsafe {
+    libc::getpwuid_r(
+      uid as libc::uid_t,
+      &mut passwd,
+      buf.as_mut_ptr(),
+      buf.len(),
+      &mut result,
+    )
+  };
+  if rc != 0 || result.is_null() {
+    return None;
+  }
+  // SAFETY: on success pw_name points to a NUL-terminated string inside `buf`
+  let name = unsafe { std::ffi::CStr::from_ptr(passwd.pw_name) };
+  Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn user_name(_uid: u32) -> Option<String> {
+  None
+}
+
+/// Give everything under `path` (and `path` itself) to `uid`:`gid`, without
+/// following symlinks.
+///
+/// With `clear_setid`, setuid and setgid bits are removed as well, so files
+/// written by one user can't run with the privileges of another.
+#[cfg(unix)]
+pub fn chown_tree(path: &std::path::Path, uid: u32, gid: u32, clear_setid: bool) -> std::io::Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+
+  for entry in walkdir::WalkDir::new(path).follow_links(false) {
+    let entry = entry.map_err(std::io::Error::other)?;
+    std::os::unix::fs::lchown(entry.path(), Some(uid), Some(gid))?;
+    if clear_setid && !entry.path_is_symlink() {
+      let mode = entry.metadata().map_err(std::io::Error::other)?.permissions().mode();
+      if mode & 0o6000 != 0 {
+        std::fs::set_permissions(entry.path(), std::fs::Permissions::from_mode(mode & !0o6000))?;
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Give the tree at `path` back to the user of this process, clearing
+/// setuid and setgid bits (see [`chown_tree`]).
+#[cfg(unix)]
+pub fn reclaim_tree(path: &std::path::Path) -> std::io::Result<()> {
+  use rustix::process::{getegid, geteuid};
+  chown_tree(path, geteuid().as_raw(), getegid().as_raw(), true)
+}
+
+#[cfg(not(unix))]
+pub fn reclaim_tree(path: &std::path::Path) -> std::io::Result<()> {
+  chown_tree(path, 0, 0, true)
+}
+
+#[cfg(not(unix))]
+pub fn chown_tree(_path: &std::path::Path, _uid: u32, _gid: u32, _clear_setid: bool) -> std::io::Result<()> {
+  Err(std::io::Error::new(
+    std::io::ErrorKind::Unsupported,
+    "changing file owners is not supported on this platform",
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(any(target_os = "linux", target_os = "macos"))]
+  #[test]
+  fn looks_up_users() {
+    let root = lookup_user("root").unwrap();
+    assert_eq!((root.uid, root.gid), (0, 0));
+    assert!(matches!(
+      lookup_user("syslua-no-such-user"),
+      Err(RunAsError::UnknownUser(_))
+    ));
+  }
+
+  #[cfg(any(target_os = "linux", target_os = "macos"))]
+  #[test]
+  fn maps_uids_to_names() {
+    assert_eq!(user_name(0).as_deref(), Some("root"));
+    assert_eq!(user_ids("root").unwrap(), (0, 0));
+    assert!(matches!(
+      group_id("syslua-no-such-group"),
+      Err(RunAsError::UnknownGroup(_))
+    ));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn chown_tree_clears_setid_bits() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let temp = tempfile::TempDir::new().unwrap();
+    let file = temp.path().join("tool");
+    std::fs::write(&file, "#!/bin/sh").unwrap();
+    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o4755)).unwrap();
+    std::os::unix::fs::symlink("/etc/passwd", temp.path().join("link")).unwrap();
+
+    let metadata = std::fs::metadata(temp.path()).unwrap();
+    chown_tree(temp.path(), metadata.uid(), metadata.gid(), true).unwrap();
+    assert_eq!(std::fs::metadata(&file).unwrap().permissions().mode() & 0o7777, 0o755);
+  }
+
+  #[test]
+  fn sudo_wrapper_passes_the_environment_through_env() {
+    if find_in_path("sudo").is_none() {
+      return;
+    }
+    let mut command = Command::new("whoami");
+    command.env_clear().env("PATH", "/path-not-set").current_dir("/");
+    let run_as = RunAs {
+      user: "postgres".to_string(),
+      method: RunAsMethod::Sudo,
+    };
+
+    let wrapped = run_as.apply(command).unwrap();
+    let env = find_in_path("env").unwrap();
+    let args: Vec<_> = wrapped.as_std().get_args().map(|a| a.to_string_lossy()).collect();
+    assert_eq!(
+      args,
+      [
+        "-n",
+        "-u",
+        "postgres",
+        "--",
+        &env.to_string_lossy(),
+        "-i",
+        "PATH=/path-not-set",
+        "whoami"
+      ]
+    );
+    assert_eq!(wrapped.as_std().get_current_dir(), Some(Path::new("/")));
+  }
+
+  #[cfg(any(target_os = "linux", target_os = "macos"))]
+  #[tokio::test]
+  async fn tmp_dir_is_private_to_the_user() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let Ok((uid, gid)) = user_ids("nobody") else {
+      return;
+    };
+    if !crate::platform::is_elevated() {
+      return;
+    }
+    let run_as = RunAs::for_user("nobody").unwrap();
+
+    let dir = run_as.create_tmp_dir().await.unwrap();
+    let metadata = std::fs::metadata(&dir).unwrap();
+    assert_eq!((metadata.uid(), metadata.gid()), (uid, gid));
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+
+    std::fs::write(dir.join("leftover"), "").unwrap();
+    run_as.remove_tmp_dir(&dir).await.unwrap();
+    assert!(!dir.exists());
+  }
+}
@@ -0,0 +1,252 @@
+//! Shells that exec actions run command lines through.
+//!
+//! With `shell` set, an exec action's `bin` and its quoted `args` are joined
+//! into one command line, run with the shell's command flag. Each shell gets
+//! its own quoting, so arguments reach the program literally:
+//!
+//! - `sh`/`bash`: single quotes
+//! - `pwsh`/`powershell`: single quotes, with `'` doubled
+//! - `cmd`: quoted for the program's argument parser, then every character
+//!   `cmd.exe` treats specially escaped with `^` (see [`Shell::quote`])
+//!
+//! `unless` probes without a shell of their own keep running through `/bin/sh`
+//! on Unix and `cmd.exe` on Windows ([`Shell::probe_default`]).
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Shells that exec actions can run their command through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shell {
+  /// POSIX shell (`/bin/sh`)
+  Sh,
+  /// GNU Bash
+  Bash,
+  /// PowerShell 7+ (`pwsh`)
+  Pwsh,
+  /// Windows PowerShell 5.1 (`powershell.exe`)
+  PowerShell,
+  /// Windows command processor (`cmd.exe`)
+  Cmd,
+}
+
+impl Shell {
+  /// Returns the default shell for the current platform
+  ///
+  /// `/bin/sh` on Unix, Windows PowerShell on Windows.
+  pub fn platform_default() -> Self {
+    if cfg!(windows) { Self::PowerShell } else { Self::Sh }
+  }
+
+  /// Returns the shell `unless` probes run through when their action sets none
+  ///
+  /// `/bin/sh` on Unix, `cmd.exe` on Windows.
+  pub fn probe_default() -> Self {
+    if cfg!(windows) { Self::Cmd } else { Self::Sh }
+  }
+
+  /// Returns the lowercase string identifier for this shell
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::Sh => "sh",
+      Self::Bash => "bash",
+      Self::Pwsh => "pwsh",
+      Self::PowerShell => "powershell",
+      Self::Cmd => "cmd",
+    }
+  }
+
+  /// Returns the binary used to launch this shell
+  pub fn bin(&self) -> &'static str {
+    match self {
+      Self::Sh => "/bin/sh",
+      Self::Bash if cfg!(windows) => "bash.exe",
+      Self::Bash => "/bin/bash",
+      Self::Pwsh if cfg!(windows) => "pwsh.exe",
+      Self::Pwsh => "pwsh",
+      Self::PowerShell => "powershell.exe",
+      Self::Cmd => "cmd.exe",
+    }
+  }
+
+  /// Returns the binary and arguments that run `script` in this shell
+  pub fn invocation(&self, script: &str) -> (&'static str, Vec<String>) {
+    let args = match self {
+      Self::Sh | Self::Bash => vec!["-c".to_string(), script.to_string()],
+      Self::Pwsh => vec![
+        "-NoProfile".to_string(),
+        "-NonInteractive".to_string(),
+        "-Command".to_string(),
+        script.to_string(),
+      ],
+      Self::PowerShell => vec![
+        "-NoProfile".to_string(),
+        "-NonInteractive".to_string(),
+        "-ExecutionPolicy".to_string(),
+        "Bypass".to_string(),
+        "-Command".to_string(),
+        script.to_string(),
+      ],
+      // With /S, cmd.exe strips the outer quotes and runs the rest as written
+      Self::Cmd => vec!["/S".to_string(), "/C".to_string(), format!("\"{}\"", script)],
+    };
+    (self.bin(), args)
+  }
+
+  /// Quote a single argument so this shell passes it through literally
+  pub fn quote(&self, arg: &str) -> String {
+    match self {
+      Self::Sh | Self::Bash => {
+        if !arg.is_empty()
+          && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c))
+        {
+          arg.to_string()
+        } else {
+          format!("'{}'", arg.replace('\'', r"'\''"))
+        }
+      }
+      Self::Pwsh | Self::PowerShell => format!("'{}'", arg.replace('\'', "''")),
+      // Quotes are escaped too, so cmd.exe never enters a quoted region where
+      // carets stop working. `%` can't be escaped on the command line (`%%`
+      // stays literal); a caret after it breaks up the variable name instead,
+      // and escapes the next character.
+      Self::Cmd => {
+        let mut escaped = String::new();
+        let mut after_percent = false;
+        for c in program_quote(arg).chars() {
+          if matches!(c, '^' | '&' | '|' | '<' | '>' | '(' | ')' | '"') && !after_percent {
+            escaped.push('^');
+          }
+          escaped.push(c);
+          after_percent = c == '%';
+          if after_percent {
+            escaped.push('^');
+          }
+        }
+        escaped
+      }
+    }
+  }
+
+  /// Build a command line from a command and arguments, quoting each argument
+  pub fn command_line(&self, cmd: &str, args: &[String]) -> String {
+    let mut line = cmd.to_string();
+    for arg in args {
+      line.push(' ');
+      line.push_str(&self.quote(arg));
+    }
+    line
+  }
+}
+
+/// Quote `arg` for the argument parser of Windows programs (the MSVCRT rules):
+/// wrapped in quotes if needed, with quotes and the backslashes before them
+/// escaped.
+fn program_quote(arg: &str) -> String {
+  if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+    return arg.to_string();
+  }
+
+  let mut quoted = String::from('"');
+  let mut backslashes = 0;
+  for c in arg.chars() {
+    if c == '\\' {
+      backslashes += 1;
+      continue;
+    }
+    let escapes = if c == '"' { backslashes * 2 + 1 } else { backslashes };
+    quoted.push_str(&"\\".repeat(escapes));
+    quoted.push(c);
+    backslashes = 0;
+  }
+  quoted.push_str(&"\\".repeat(backslashes * 2));
+  quoted.push('"');
+  quoted
+}
+
+impl fmt::Display for Shell {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl FromStr for Shell {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "sh" => Ok(Self::Sh),
+      "bash" => Ok(Self::Bash),
+      "pwsh" => Ok(Self::Pwsh),
+      "powershell" => Ok(Self::PowerShell),
+      "cmd" => Ok(Self::Cmd),
+      other => Err(format!(
+        "unknown shell '{}', expected one of: sh, bash, pwsh, powershell, cmd",
+        other
+      )),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_roundtrips_as_str() {
+    for shell in [Shell::Sh, Shell::Bash, Shell::Pwsh, Shell::PowerShell, Shell::Cmd] {
+      assert_eq!(shell.as_str().parse::<Shell>().unwrap(), shell);
+    }
+    assert!("zsh".parse::<Shell>().is_err());
+  }
+
+  #[test]
+  fn posix_quote_escapes_single_quotes() {
+    assert_eq!(Shell::Sh.quote("plain"), "plain");
+    assert_eq!(Shell::Sh.quote("has space"), "'has space'");
+    assert_eq!(Shell::Sh.quote("it's"), r"'it'\''s'");
+    assert_eq!(Shell::Sh.quote(""), "''");
+    assert_eq!(Shell::Bash.quote("$HOME"), "'$HOME'");
+  }
+
+  #[test]
+  fn powershell_quote_doubles_single_quotes() {
+    assert_eq!(Shell::Pwsh.quote("it's"), "'it''s'");
+    assert_eq!(Shell::PowerShell.quote("a b"), "'a b'");
+  }
+
+  #[test]
+  fn cmd_quote_escapes_quotes_and_percent() {
+    assert_eq!(Shell::Cmd.quote("plain"), "plain");
+    assert_eq!(Shell::Cmd.quote(""), r#"^"^""#);
+    assert_eq!(Shell::Cmd.quote(r#"say "hi" 100%"#), r#"^"say \^"hi\^" 100%^""#);
+    assert_eq!(Shell::Cmd.quote("%PATH%"), "%^PATH%^");
+    assert_eq!(Shell::Cmd.quote("a&b|c"), "a^&b^|c");
+    assert_eq!(Shell::Cmd.quote(r"C:\dir with space\"), r#"^"C:\dir with space\\^""#);
+  }
+
+  #[test]
+  fn command_line_quotes_args() {
+    let line = Shell::Sh.command_line("make", &["install".to_string(), "DESTDIR=/a b".to_string()]);
+    assert_eq!(line, "make install 'DESTDIR=/a b'");
+  }
+
+  #[test]
+  fn invocation_uses_command_flag() {
+    let (bin, args) = Shell::Sh.invocation("echo hi");
+    assert_eq!(bin, "/bin/sh");
+    assert_eq!(args, vec!["-c".to_string(), "echo hi".to_string()]);
+
+    let (bin, args) = Shell::Cmd.invocation("echo hi");
+    assert_eq!(bin, "cmd.exe");
+    assert_eq!(
+      args,
+      vec!["/S".to_string(), "/C".to_string(), r#""echo hi""#.to_string()]
+    );
+  }
+}
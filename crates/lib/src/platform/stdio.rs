@@ -0,0 +1,98 @@
+//! Redirecting the standard output of this process.
+//!
+//! The CLI pages long output by pointing its stdout at the stdin of a pager
+//! for the rest of the command, so everything printed with `println!` goes
+//! through it. [`redirect_stdout`] swaps the underlying descriptor (or, on
+//! Windows, the standard handle) and the returned guard swaps it back.
+
+use std::io::{self, Write};
+
+/// Restores the previous stdout when dropped.
+#[derive(Debug)]
+#[must_use = "stdout is only redirected while the guard is alive"]
+pub struct StdoutRedirect {
+  #[cfg(unix)]
+  saved: std::os::fd::OwnedFd,
+  #[cfg(windows)]
+  saved: std::os::windows::io::RawHandle,
+}
+
+/// Send everything written to stdout to `to` until the guard is dropped.
+///
+/// On Linux and macOS, writing to stdout once the reader of `to` has exited
+/// ends the process quietly, like other command line tools feeding a pager,
+/// instead of failing every later `println!`.
+#[cfg(unix)]
+pub fn redirect_stdout(to: &impl std::os::fd::AsFd) -> io::Result<StdoutRedirect> {
+  io::stdout().flush()?;
+  let saved = rustix::io::dup(io::stdout())?;
+  rustix::stdio::dup2_stdout(to)?;
+
+  // SAFETY: restoring the default disposition of SIGPIPE installs no handler
+  #[cfg(any(target_os = "linux", target_os = "macos"))]
+  unsafe {
+    libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+  }
+
+  Ok(StdoutRedirect { saved })
+}
+
+/// Send everything written to stdout to `to` until the guard is dropped.
+#[cfg(windows)]
+pub fn redirect_stdout(to: &impl std::os::windows::io::AsRawHandle) -> io::Result<StdoutRedirect> {
+  use windows_sys::Win32::System::Console::{GetStdHandle, STD_OUTPUT_HANDLE, SetStdHandle};
+
+  io::stdout().flush()?;
+  // SAFETY: both calls only read and replace the process's standard handle
+  unsafe {
+    let saved = GetStdHandle(STD_OUTPUT_HANDLE);
+    if SetStdHandle(STD_OUTPUT_HANDLE, to.as_raw_handle()) == 0 {
+      return Err(io::Error::last_os_error());
+    }
+    Ok(StdoutRedirect { saved })
+  }
+}
+
+impl Drop for StdoutRedirect {
+  fn drop(&mut self) {
+    let _ = io::stdout().flush();
+
+    #[cfg(unix)]
+    {
+      let _ = rustix::stdio::dup2_stdout(&self.saved);
+    }
+
+    #[cfg(windows)]
+    {
+      use windows_sys::Win32::System::Console::{STD_OUTPUT_HANDLE, SetStdHandle};
+
+      // SAFETY: puts back the handle saved by `redirect_stdout`
+      unsafe {
+        SetStdHandle(STD_OUTPUT_HANDLE, self.saved);
+      }
+    }
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use serial_test::serial;
+  use std::io::Read;
+
+  #[test]
+  #[serial]
+  fn stdout_goes_to_the_target_until_dropped() {
+    let (mut reader, writer) = std::io::pipe().unwrap();
+    {
+      let _redirect = redirect_stdout(&writer).unwrap();
+      // Not print!, which the test harness captures
+      io::stdout().write_all(b"paged").unwrap();
+    }
+    drop(writer);
+
+    let mut captured = String::new();
+    reader.read_to_string(&mut captured).unwrap();
+    assert_eq!(captured, "paged");
+  }
+}
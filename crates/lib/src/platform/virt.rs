@@ -0,0 +1,256 @@
+//! WSL, container and virtualization detection.
+//!
+//! Detection reads well-known marker files, `/proc` and DMI data, so every
+//! check is best effort: when nothing conclusive is found the host is treated
+//! as bare metal. Only Linux hosts are inspected.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Kind of virtualized environment the process runs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Virtualization {
+  /// Windows Subsystem for Linux.
+  Wsl,
+  Docker,
+  Podman,
+  Lxc,
+  Kubernetes,
+  /// A container of an unrecognized runtime.
+  Container,
+  Kvm,
+  Qemu,
+  Vmware,
+  VirtualBox,
+  HyperV,
+  Xen,
+  Parallels,
+  /// A virtual machine of an unrecognized hypervisor.
+  Vm,
+}
+
+impl Virtualization {
+  /// Detect the current environment at runtime.
+  ///
+  /// Returns `None` on bare metal or when detection is inconclusive.
+  pub fn current() -> Option<Self> {
+    if cfg!(target_os = "linux") {
+      detect(Path::new("/"), &|name| std::env::var(name).ok())
+    } else {
+      None
+    }
+  }
+
+  /// Returns the lowercase string identifier (e.g., "docker", "kvm").
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::Wsl => "wsl",
+      Self::Docker => "docker",
+      Self::Podman => "podman",
+      Self::Lxc => "lxc",
+      Self::Kubernetes => "kubernetes",
+      Self::Container => "container",
+      Self::Kvm => "kvm",
+      Self::Qemu => "qemu",
+      Self::Vmware => "vmware",
+      Self::VirtualBox => "virtualbox",
+      Self::HyperV => "hyperv",
+      Self::Xen => "xen",
+      Self::Parallels => "parallels",
+      Self::Vm => "vm",
+    }
+  }
+
+  /// Whether this is a container (sharing the host kernel).
+  pub fn is_container(&self) -> bool {
+    matches!(
+      self,
+      Self::Docker | Self::Podman | Self::Lxc | Self::Kubernetes | Self::Container
+    )
+  }
+
+  /// Whether this is a full virtual machine under a hypervisor.
+  pub fn is_vm(&self) -> bool {
+    !self.is_container() && *self != Self::Wsl
+  }
+}
+
+impl fmt::Display for Virtualization {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+/// Check if the process runs under Windows Subsystem for Linux.
+pub fn is_wsl() -> bool {
+  Virtualization::current() == Some(Virtualization::Wsl)
+}
+
+/// Check if the process runs inside a container (Docker, Podman, LXC, ...).
+pub fn is_container() -> bool {
+  Virtualization::current().is_some_and(|v| v.is_container())
+}
+
+/// Check if systemd is the running init system.
+///
+/// Uses the same test as `sd_booted()`: `/run/systemd/system` exists.
+pub fn has_systemd() -> bool {
+  cfg!(target_os = "linux") && Path::new("/run/systemd/system").is_dir()
+}
+
+/// Detect the environment using files under `root` and the `env` lookup.
+fn detect(root: &Path, env: &dyn Fn(&str) -> Option<String>) -> Option<Virtualization> {
+  if detect_wsl(root, env) {
+    return Some(Virtualization::Wsl);
+  }
+  detect_container(root, env).or_else(|| detect_vm(root))
+}
+
+fn detect_wsl(root: &Path, env: &dyn Fn(&str) -> Option<String>) -> bool {
+  env("WSL_DISTRO_NAME").is_some()
+    || root.join("proc/sys/fs/binfmt_misc/WSLInterop").exists()
+    || read_lower(root, "proc/sys/kernel/osrelease").is_some_and(|release| release.contains("microsoft"))
+}
+
+fn detect_container(root: &Path, env: &dyn Fn(&str) -> Option<String>) -> Option<Virtualization> {
+  if root.join(".dockerenv").exists() {
+    return Some(Virtualization::Docker);
+  }
+  if root.join("run/.containerenv").exists() {
+    return Some(Virtualization::Podman);
+  }
+
+  // Set by systemd-nspawn, podman, lxc and others
+  if let Some(container) = env("container").filter(|c| !c.is_empty()) {
+    return Some(match container.as_str() {
+      "docker" => Virtualization::Docker,
+      "podman" => Virtualization::Podman,
+      "lxc" | "lxc-libvirt" => Virtualization::Lxc,
+      _ => Virtualization::Container,
+    });
+  }
+  if env("KUBERNETES_SERVICE_HOST").is_some() {
+    return Some(Virtualization::Kubernetes);
+  }
+
+  let cgroup = read_lower(root, "proc/1/cgroup")?;
+  [
+    ("kubepods", Virtualization::Kubernetes),
+    ("libpod", Virtualization::Podman),
+    ("docker", Virtualization::Docker),
+    ("lxc", Virtualization::Lxc),
+  ]
+  .into_iter()
+  .find(|(needle, _)| cgroup.contains(needle))
+  .map(|(_, virt)| virt)
+}
+
+fn detect_vm(root: &Path) -> Option<Virtualization> {
+  let dmi = ["sys_vendor", "product_name", "board_vendor"]
+    .iter()
+    .filter_map(|file| read_lower(root, &format!("sys/class/dmi/id/{}", file)))
+    .collect::<Vec<_>>()
+    .join(" ");
+
+  let by_dmi = [
+    ("kvm", Virtualization::Kvm),
+    ("qemu", Virtualization::Qemu),
+    ("vmware", Virtualization::Vmware),
+    ("virtualbox", Virtualization::VirtualBox),
+    ("innotek", Virtualization::VirtualBox),
+    ("xen", Virtualization::Xen),
+    ("parallels", Virtualization::Parallels),
+  ]
+  .into_iter()
+  .find(|(needle, _)| dmi.contains(needle))
+  .map(|(_, virt)| virt);
+  if by_dmi.is_some() {
+    return by_dmi;
+  }
+  if dmi.contains("microsoft corporation") && dmi.contains("virtual machine") {
+    return Some(Virtualization::HyperV);
+  }
+  if root.join("proc/xen").exists() {
+    return Some(Virtualization::Xen);
+  }
+
+  // The CPU advertises a hypervisor but DMI didn't say which
+  read_lower(root, "proc/cpuinfo")
+    .filter(|cpuinfo| {
+      cpuinfo
+        .lines()
+        .any(|line| line.starts_with("flags") && line.split_whitespace().any(|flag| flag == "hypervisor"))
+    })
+    .map(|_| Virtualization::Vm)
+}
+
+fn read_lower(root: &Path, relative: &str) -> Option<String> {
+  fs::read_to_string(root.join(relative)).ok().map(|s| s.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  fn write(root: &Path, relative: &str, contents: &str) {
+    let path = root.join(relative);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+  }
+
+  fn no_env(_: &str) -> Option<String> {
+    None
+  }
+
+  #[test]
+  fn bare_metal_detects_nothing() {
+    let root = TempDir::new().unwrap();
+    write(root.path(), "proc/cpuinfo", "flags\t\t: fpu vme sse2\n");
+    write(root.path(), "sys/class/dmi/id/sys_vendor", "Framework\n");
+    assert_eq!(detect(root.path(), &no_env), None);
+  }
+
+  #[test]
+  fn detects_wsl_from_kernel_release() {
+    let root = TempDir::new().unwrap();
+    write(
+      root.path(),
+      "proc/sys/kernel/osrelease",
+      "5.15.153.1-microsoft-standard-WSL2\n",
+    );
+    assert_eq!(detect(root.path(), &no_env), Some(Virtualization::Wsl));
+  }
+
+  #[test]
+  fn detects_containers() {
+    let root = TempDir::new().unwrap();
+    write(root.path(), "proc/1/cgroup", "0::/kubepods/besteffort/pod1234\n");
+    assert_eq!(detect(root.path(), &no_env), Some(Virtualization::Kubernetes));
+
+    let env = |name: &str| (name == "container").then(|| "podman".to_string());
+    assert_eq!(detect(root.path(), &env), Some(Virtualization::Podman));
+
+    write(root.path(), ".dockerenv", "");
+    assert_eq!(detect(root.path(), &no_env), Some(Virtualization::Docker));
+    assert!(Virtualization::Docker.is_container());
+  }
+
+  #[test]
+  fn detects_hypervisors() {
+    let root = TempDir::new().unwrap();
+    write(root.path(), "sys/class/dmi/id/sys_vendor", "QEMU\n");
+    assert_eq!(detect(root.path(), &no_env), Some(Virtualization::Qemu));
+
+    let root = TempDir::new().unwrap();
+    write(root.path(), "sys/class/dmi/id/sys_vendor", "Microsoft Corporation\n");
+    write(root.path(), "sys/class/dmi/id/product_name", "Virtual Machine\n");
+    assert_eq!(detect(root.path(), &no_env), Some(Virtualization::HyperV));
+
+    let root = TempDir::new().unwrap();
+    write(root.path(), "proc/cpuinfo", "flags\t\t: fpu hypervisor sse2\n");
+    assert_eq!(detect(root.path(), &no_env), Some(Virtualization::Vm));
+    assert!(Virtualization::Vm.is_vm());
+  }
+}
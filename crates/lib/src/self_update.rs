@@ -0,0 +1,637 @@
+//! Self-update: replace the running `sys` binary with a newer release.
+//!
+//! Releases are published per channel (`stable` or `nightly`) as a JSON index
+//! at `<endpoint>/<channel>.json`:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "channel": "stable",
+//!   "release": "0.8.0",
+//!   "assets": {
+//!     "x86_64-linux": {
+//!       "url": "https://.../sys-v0.8.0-x86_64-linux",
+//!       "sha256": "9f86d08...",
+//!       "signature": "4c1a..."
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! Each asset is the bare `sys` executable for a platform triple. Its SHA256
+//! is always verified, and its hex-encoded Ed25519 `signature` must verify
+//! against the release public key. The signature covers a statement binding
+//! the release version, channel, platform triple and SHA256 together
+//! ([`release_statement`]), so a signed executable can't be served as another
+//! version, channel or platform. The key built in via
+//! `SYSLUA_RELEASE_PUBLIC_KEY` always wins, so a config (which may come from
+//! anywhere `sys` is run) can point `settings.self_update.endpoint` at a
+//! mirror but never swap in its own key; `settings.self_update.public_key`
+//! only applies to binaries built without one. Without any key, updates are
+//! refused unless `sys self-update --insecure` accepts the SHA256 alone.
+//!
+//! A release that is not newer than the running version is refused unless
+//! `--allow-downgrade` is passed, so an endpoint can't replay an older,
+//! correctly signed release.
+//!
+//! The new executable is staged next to the current one and renamed over it,
+//! so an interrupted update never leaves a half-written binary. Windows cannot
+//! replace a running executable, so the current one is first renamed to
+//! `<name>.old` (removed by the next update).
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use ring::signature::{ED25519, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, info};
+
+use crate::action::actions::download_cache::{DownloadOptions, fetch_cached};
+use crate::execute::types::ExecuteError;
+use crate::inputs::fetch::FetchTimeouts;
+use crate::platform::Platform;
+use crate::util::semver::{SemverError, Version};
+
+/// Current release index format version.
+pub const RELEASE_INDEX_VERSION: u32 = 1;
+
+/// Where release indexes are published unless `settings.self_update.endpoint` says otherwise.
+pub const DEFAULT_RELEASE_ENDPOINT: &str = match option_env!("SYSLUA_RELEASE_ENDPOINT") {
+  Some(endpoint) => endpoint,
+  None => "https://github.com/syslua/syslua/releases/download/channels",
+};
+
+/// Release public key (hex-encoded Ed25519) built into this binary, if any.
+pub const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("SYSLUA_RELEASE_PUBLIC_KEY");
+
+/// Version of the running binary.
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Release channel to update from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+  /// Tagged releases.
+  #[default]
+  Stable,
+  /// Builds of the main branch.
+  Nightly,
+}
+
+impl Channel {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Channel::Stable => "stable",
+      Channel::Nightly => "nightly",
+    }
+  }
+}
+
+impl fmt::Display for Channel {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl FromStr for Channel {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "stable" => Ok(Channel::Stable),
+      "nightly" => Ok(Channel::Nightly),
+      other => Err(format!(
+        "unknown release channel '{}' (expected stable or nightly)",
+        other
+      )),
+    }
+  }
+}
+
+/// The `settings.self_update` table of a config.
+///
+/// ```lua
+/// settings = {
+///   self_update = {
+///     channel = "nightly",
+///     endpoint = "https://mirror.example.com/syslua",
+///     public_key = "3b6a27bc...",
+///   },
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfUpdateSettings {
+  /// Channel to update from (default stable).
+  pub channel: Option<Channel>,
+  /// Base URL of the release indexes (default [`DEFAULT_RELEASE_ENDPOINT`]).
+  pub endpoint: Option<String>,
+  /// Hex-encoded Ed25519 release key, for binaries built without
+  /// [`RELEASE_PUBLIC_KEY`].
+  pub public_key: Option<String>,
+}
+
+impl SelfUpdateSettings {
+  pub fn channel(&self) -> Channel {
+    self.channel.unwrap_or_default()
+  }
+
+  pub fn endpoint(&self) -> &str {
+    self.endpoint.as_deref().unwrap_or(DEFAULT_RELEASE_ENDPOINT)
+  }
+
+  /// The key releases must be signed with: the built-in one if any, so a
+  /// config can't replace it.
+  pub fn public_key(&self) -> Option<&str> {
+    select_public_key(RELEASE_PUBLIC_KEY, self.public_key.as_deref())
+  }
+
+  /// URL of the release index for the configured channel.
+  pub fn index_url(&self) -> String {
+    format!("{}/{}.json", self.endpoint().trim_end_matches('/'), self.channel())
+  }
+}
+
+/// The latest release of a channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleaseIndex {
+  /// Index format version.
+  pub version: u32,
+  /// Channel this index describes.
+  pub channel: Channel,
+  /// syslua version of the release.
+  pub release: String,
+  /// Executables by platform triple (e.g., "x86_64-linux").
+  pub assets: BTreeMap<String, ReleaseAsset>,
+}
+
+/// A release executable for one platform.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleaseAsset {
+  /// Download URL of the bare executable.
+  pub url: String,
+  /// SHA256 of the executable (lowercase hex).
+  pub sha256: String,
+  /// Hex-encoded Ed25519 signature over the [`release_statement`] of this asset.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub signature: Option<String>,
+}
+
+/// How to treat a release that can't be fully verified or isn't an upgrade.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpdateOptions {
+  /// Accept a release verified by its SHA256 alone when there is no public key.
+  pub insecure: bool,
+  /// Accept a release that is not newer than the running version.
+  pub allow_downgrade: bool,
+}
+
+/// The built-in key if there is one, else the configured key.
+fn select_public_key<'a>(built_in: Option<&'a str>, configured: Option<&'a str>) -> Option<&'a str> {
+  built_in.or(configured)
+}
+
+/// The statement a release signature covers for the executable of `triple`.
+///
+/// One line per field, so no field can spill into another:
+///
+/// ```text
+/// syslua-release-v1
+/// version 0.8.0
+/// channel stable
+/// target x86_64-linux
+/// sha256 9f86d08...
+/// ```
+pub fn release_statement(release: &str, channel: Channel, triple: &str, sha256: &str) -> String {
+  format!(
+    "syslua-release-v1\nversion {}\nchannel {}\ntarget {}\nsha256 {}\n",
+    release,
+    channel,
+    triple,
+    sha256.to_ascii_lowercase()
+  )
+}
+
+impl ReleaseIndex {
+  /// Parse an index, rejecting formats newer than this binary understands.
+  pub fn parse(content: &str) -> Result<Self, SelfUpdateError> {
+    let index: Self = serde_json::from_str(content).map_err(SelfUpdateError::Parse)?;
+    if index.version > RELEASE_INDEX_VERSION {
+      return Err(SelfUpdateError::UnsupportedVersion(index.version));
+    }
+    Ok(index)
+  }
+
+  /// The executable for `platform`.
+  pub fn asset(&self, platform: &Platform) -> Result<&ReleaseAsset, SelfUpdateError> {
+    let triple = platform.triple();
+    self.assets.get(&triple).ok_or(SelfUpdateError::NoAsset {
+      release: self.release.clone(),
+      platform: triple,
+    })
+  }
+
+  /// Whether this release is newer than `current`.
+  pub fn is_newer_than(&self, current: &str) -> Result<bool, SelfUpdateError> {
+    Ok(Version::parse(&self.release)? > Version::parse(current)?)
+  }
+}
+
+/// Fetch the release index of the configured channel.
+pub async fn fetch_index(settings: &SelfUpdateSettings) -> Result<ReleaseIndex, SelfUpdateError> {
+  let url = settings.index_url();
+  debug!(url = %url, "fetching release index");
+
+  let fetch_failed = |message: String| SelfUpdateError::FetchIndex {
+    url: url.clone(),
+    message,
+  };
+  let timeouts = FetchTimeouts::default();
+  let client = reqwest::Client::builder()
+    .connect_timeout(timeouts.connect)
+    .timeout(timeouts.total)
+    .build()
+    .map_err(|e| fetch_failed(e.to_string()))?;
+  let response = client.get(&url).send().await.map_err(|e| fetch_failed(e.to_string()))?;
+  if !response.status().is_success() {
+    return Err(fetch_failed(format!("HTTP {}", response.status())));
+  }
+  let body = response.text().await.map_err(|e| fetch_failed(e.to_string()))?;
+
+  let index = ReleaseIndex::parse(&body)?;
+  if index.channel != settings.channel() {
+    return Err(SelfUpdateError::ChannelMismatch {
+      expected: settings.channel(),
+      actual: index.channel,
+    });
+  }
+  Ok(index)
+}
+
+/// Download the executable of `index` for `platform` after checking that the
+/// release is an upgrade and that its signature by `public_key` covers it.
+///
+/// The signature is verified before anything is downloaded, and the download
+/// against the signed SHA256. Without a `public_key` the release is refused
+/// unless `options.insecure` accepts the SHA256 alone; a release that is not
+/// newer than [`CURRENT_VERSION`] is refused unless `options.allow_downgrade`.
+/// Returns the path of the verified executable in the download cache.
+pub async fn download_asset(
+  index: &ReleaseIndex,
+  platform: &Platform,
+  public_key: Option<&str>,
+  options: UpdateOptions,
+) -> Result<PathBuf, SelfUpdateError> {
+  if !options.allow_downgrade && !index.is_newer_than(CURRENT_VERSION)? {
+    return Err(SelfUpdateError::NotNewer {
+      release: index.release.clone(),
+      current: CURRENT_VERSION.to_string(),
+    });
+  }
+  let asset = index.asset(platform)?;
+
+  match public_key {
+    Some(public_key) => {
+      verify_release(index, platform, public_key)?;
+      debug!(url = %asset.url, "release signature verified");
+    }
+    None if options.insecure => {
+      debug!("no release public key configured; only the SHA256 of the release is verified")
+    }
+    None => return Err(SelfUpdateError::NoPublicKey),
+  }
+
+  fetch_cached(&asset.url, &asset.sha256, &DownloadOptions::default())
+    .await
+    .map_err(SelfUpdateError::Download)
+}
+
+/// Verify the signature of the executable of `index` for `platform` over its
+/// [`release_statement`].
+pub fn verify_release(index: &ReleaseIndex, platform: &Platform, public_key: &str) -> Result<(), SelfUpdateError> {
+  let asset = index.asset(platform)?;
+  let signature = asset.signature.as_deref().ok_or(SelfUpdateError::MissingSignature)?;
+  let statement = release_statement(&index.release, index.channel, &platform.triple(), &asset.sha256);
+  verify_signature(statement.as_bytes(), signature, public_key)
+}
+
+/// Verify a hex-encoded Ed25519 `signature` over `content`.
+pub fn verify_signature(content: &[u8], signature: &str, public_key: &str) -> Result<(), SelfUpdateError> {
+  let public_key = hex::decode(public_key.trim()).map_err(|_| SelfUpdateError::InvalidPublicKey)?;
+  let signature = hex::decode(signature.trim()).map_err(|_| SelfUpdateError::InvalidSignature)?;
+  UnparsedPublicKey::new(&ED25519, public_key)
+    .verify(content, &signature)
+    .map_err(|_| SelfUpdateError::InvalidSignature)
+}
+
+/// Path the replaced executable is moved to on Windows.
+pub fn replaced_path(target: &Path) -> PathBuf {
+  let mut name = target.file_name().unwrap_or_default().to_os_string();
+  name.push(".old");
+  target.with_file_name(name)
+}
+
+/// Remove the executable left behind by a previous update on Windows.
+pub fn cleanup_replaced(target: &Path) {
+  let old = replaced_path(target);
+  if old.exists()
+    && let Err(e) = std::fs::remove_file(&old)
+  {
+    debug!(path = ?old, error = %e, "failed to remove replaced executable");
+  }
+}
+
+/// Atomically replace the executable at `target` with a copy of `new`.
+///
+/// The copy is staged in `target`'s directory (so the final rename stays on
+/// one filesystem) with `target`'s permissions.
+pub fn replace_executable(new: &Path, target: &Path) -> Result<(), SelfUpdateError> {
+  let replace_failed = |source: io::Error| SelfUpdateError::Replace {
+    path: target.to_path_buf(),
+    source,
+  };
+
+  let mut staged_name = std::ffi::OsString::from(".");
+  staged_name.push(target.file_name().unwrap_or_default());
+  staged_name.push(".new");
+  let staged = target.with_file_name(staged_name);
+
+  let result = (|| {
+    std::fs::copy(new, &staged)?;
+    let permissions = std::fs::metadata(target)?.permissions();
+    std::fs::set_permissions(&staged, permissions)?;
+    std::fs::File::open(&staged)?.sync_all()?;
+    swap_executable(&staged, target)
+  })();
+
+  if let Err(e) = result {
+    let _ = std::fs::remove_file(&staged);
+    return Err(replace_failed(e));
+  }
+
+  info!(path = ?target, "executable replaced");
+  Ok(())
+}
+
+#[cfg(not(windows))]
+fn swap_executable(staged: &Path, target: &Path) -> io::Result<()> {
+  std::fs::rename(staged, target)
+}
+
+/// Windows locks a running executable against writes and deletion but allows
+/// renaming it, so move it aside before renaming the new one into place.
+#[cfg(windows)]
+fn swap_executable(staged: &Path, target: &Path) -> io::Result<()> {
+  let old = replaced_path(target);
+  if old.exists() {
+    std::fs::remove_file(&old)?;
+  }
+  std::fs::rename(target, &old)?;
+  if let Err(e) = std::fs::rename(staged, target) {
+    // Put the current executable back
+    let _ = std::fs::rename(&old, target);
+    return Err(e);
+  }
+  Ok(())
+}
+
+/// Errors that can occur during self-update.
+#[derive(Debug, Error)]
+pub enum SelfUpdateError {
+  /// Failed to fetch the release index.
+  #[error("failed to fetch release index {url}: {message}")]
+  FetchIndex { url: String, message: String },
+
+  /// Failed to parse the release index.
+  #[error("failed to parse release index: {0}")]
+  Parse(#[source] serde_json::Error),
+
+  /// The index was written for a newer syslua.
+  #[error("unsupported release index version {0} (expected at most {RELEASE_INDEX_VERSION})")]
+  UnsupportedVersion(u32),
+
+  /// The endpoint served the index of another channel.
+  #[error("release index is for the {actual} channel, expected {expected}")]
+  ChannelMismatch { expected: Channel, actual: Channel },
+
+  /// The release has no executable for this platform.
+  #[error("release {release} has no executable for {platform}")]
+  NoAsset { release: String, platform: String },
+
+  /// A release or current version is not a semantic version.
+  #[error(transparent)]
+  InvalidVersion(#[from] SemverError),
+
+  /// Failed to download or verify the SHA256 of the executable.
+  #[error("failed to download release: {0}")]
+  Download(#[source] ExecuteError),
+
+  /// No release public key is built in or configured.
+  #[error(
+    "no release public key is built in or configured, so the release can't be verified (pass --insecure to trust its SHA256 alone)"
+  )]
+  NoPublicKey,
+
+  /// The release is not newer than the running version.
+  #[error("release {release} is not newer than the running {current} (pass --allow-downgrade to install it anyway)")]
+  NotNewer { release: String, current: String },
+
+  /// A public key is configured but the release is unsigned.
+  #[error("release is not signed, but a release public key is configured")]
+  MissingSignature,
+
+  /// The signature does not match the release statement and public key.
+  #[error("release signature verification failed")]
+  InvalidSignature,
+
+  /// The configured public key is not hex-encoded.
+  #[error("release public key must be hex-encoded")]
+  InvalidPublicKey,
+
+  /// Failed to replace the executable.
+  #[error("failed to replace {path}: {source}")]
+  Replace {
+    path: PathBuf,
+    #[source]
+    source: io::Error,
+  },
+
+  /// Other I/O error.
+  #[error("I/O error: {0}")]
+  Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+  use ring::rand::SystemRandom;
+  use ring::signature::{Ed25519KeyPair, KeyPair};
+  use tempfile::TempDir;
+
+  use super::*;
+  use crate::platform::arch::Arch;
+  use crate::platform::os::Os;
+
+  const INDEX: &str = r#"{
+    "version": 1,
+    "channel": "nightly",
+    "release": "0.8.0-nightly.20261016",
+    "assets": {
+      "x86_64-linux": { "url": "https://example.com/sys", "sha256": "abc" }
+    }
+  }"#;
+
+  #[test]
+  fn index_selects_asset_for_platform() {
+    let index = ReleaseIndex::parse(INDEX).unwrap();
+    assert_eq!(index.channel, Channel::Nightly);
+
+    let linux = Platform::new(Arch::X86_64, Os::Linux);
+    assert_eq!(index.asset(&linux).unwrap().url, "https://example.com/sys");
+
+    let darwin = Platform::new(Arch::Aarch64, Os::MacOs);
+    assert!(matches!(index.asset(&darwin), Err(SelfUpdateError::NoAsset { .. })));
+  }
+
+  #[test]
+  fn index_url_uses_channel_and_endpoint() {
+    let settings = SelfUpdateSettings {
+      channel: Some(Channel::Nightly),
+      endpoint: Some("https://mirror.example.com/syslua/".to_string()),
+      public_key: None,
+    };
+    assert_eq!(settings.index_url(), "https://mirror.example.com/syslua/nightly.json");
+  }
+
+  fn key_pair() -> (Ed25519KeyPair, String) {
+    let rng = SystemRandom::new();
+    let key = Ed25519KeyPair::from_pkcs8(Ed25519KeyPair::generate_pkcs8(&rng).unwrap().as_ref()).unwrap();
+    let public_key = hex::encode(key.public_key().as_ref());
+    (key, public_key)
+  }
+
+  /// A stable release of `release` for x86_64-linux signed by `key`.
+  fn signed_index(release: &str, key: &Ed25519KeyPair) -> ReleaseIndex {
+    let statement = release_statement(release, Channel::Stable, "x86_64-linux", "abc");
+    ReleaseIndex {
+      version: RELEASE_INDEX_VERSION,
+      channel: Channel::Stable,
+      release: release.to_string(),
+      assets: BTreeMap::from([(
+        "x86_64-linux".to_string(),
+        ReleaseAsset {
+          url: "https://example.com/sys".to_string(),
+          sha256: "abc".to_string(),
+          signature: Some(hex::encode(key.sign(statement.as_bytes()).as_ref())),
+        },
+      )]),
+    }
+  }
+
+  #[test]
+  fn config_key_cannot_override_built_in_key() {
+    let (built_in, built_in_public) = key_pair();
+    let (config, config_public) = key_pair();
+    let linux = Platform::new(Arch::X86_64, Os::Linux);
+
+    // A release signed by the config's key fails against the key that wins
+    let public_key = select_public_key(Some(&built_in_public), Some(&config_public)).unwrap();
+    assert!(matches!(
+      verify_release(&signed_index("9.0.0", &config), &linux, public_key),
+      Err(SelfUpdateError::InvalidSignature)
+    ));
+    assert!(verify_release(&signed_index("9.0.0", &built_in), &linux, public_key).is_ok());
+
+    // Without a built-in key the config's applies
+    assert_eq!(
+      select_public_key(None, Some(&config_public)),
+      Some(config_public.as_str())
+    );
+  }
+
+  #[test]
+  fn signature_binds_version_channel_and_target() {
+    let (key, public_key) = key_pair();
+    let linux = Platform::new(Arch::X86_64, Os::Linux);
+
+    let mut relabeled = signed_index("9.0.0", &key);
+    relabeled.release = "9.0.1".to_string();
+    assert!(verify_release(&relabeled, &linux, &public_key).is_err());
+
+    let mut other_channel = signed_index("9.0.0", &key);
+    other_channel.channel = Channel::Nightly;
+    assert!(verify_release(&other_channel, &linux, &public_key).is_err());
+
+    let mut other_target = signed_index("9.0.0", &key);
+    let asset = other_target.assets.remove("x86_64-linux").unwrap();
+    other_target.assets.insert("aarch64-linux".to_string(), asset);
+    let arm = Platform::new(Arch::Aarch64, Os::Linux);
+    assert!(verify_release(&other_target, &arm, &public_key).is_err());
+  }
+
+  #[tokio::test]
+  async fn unverifiable_and_older_releases_are_refused() {
+    let (key, public_key) = key_pair();
+    let linux = Platform::new(Arch::X86_64, Os::Linux);
+
+    assert!(matches!(
+      download_asset(&signed_index("9.0.0", &key), &linux, None, UpdateOptions::default()).await,
+      Err(SelfUpdateError::NoPublicKey)
+    ));
+    for release in ["0.0.1", CURRENT_VERSION] {
+      assert!(matches!(
+        download_asset(
+          &signed_index(release, &key),
+          &linux,
+          Some(&public_key),
+          UpdateOptions::default()
+        )
+        .await,
+        Err(SelfUpdateError::NotNewer { .. })
+      ));
+    }
+  }
+
+  #[test]
+  fn verify_signature_checks_content_and_key() {
+    let (key, public_key) = key_pair();
+    let signature = hex::encode(key.sign(b"sys binary").as_ref());
+
+    assert!(verify_signature(b"sys binary", &signature, &public_key).is_ok());
+    assert!(matches!(
+      verify_signature(b"tampered", &signature, &public_key),
+      Err(SelfUpdateError::InvalidSignature)
+    ));
+    assert!(matches!(
+      verify_signature(b"sys binary", &signature, "not hex"),
+      Err(SelfUpdateError::InvalidPublicKey)
+    ));
+  }
+
+  #[test]
+  fn replace_executable_swaps_content_and_keeps_permissions() {
+    let temp = TempDir::new().unwrap();
+    let target = temp.path().join("sys");
+    let new = temp.path().join("download");
+    std::fs::write(&target, "old").unwrap();
+    std::fs::write(&new, "new").unwrap();
+
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    replace_executable(&new, &target).unwrap();
+
+    assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+    assert!(!temp.path().join(".sys.new").exists());
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      assert_eq!(std::fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o755);
+    }
+  }
+}
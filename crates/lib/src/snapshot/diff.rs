@@ -4,7 +4,7 @@
 //! current state, determining what builds need to be realized and what
 //! binds need to be applied or destroyed.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 
 use crate::build::store::build_exists_in_store;
@@ -62,6 +62,55 @@ impl StateDiff {
   }
 }
 
+/// Bind changes within one bind `group`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct GroupChanges {
+  pub to_apply: usize,
+  pub to_update: usize,
+  pub to_destroy: usize,
+  pub unchanged: usize,
+}
+
+impl GroupChanges {
+  /// Number of binds the group has after the changes.
+  pub fn total(&self) -> usize {
+    self.to_apply + self.to_update + self.unchanged
+  }
+}
+
+impl StateDiff {
+  /// Count the bind changes per group.
+  ///
+  /// Destroyed binds are counted under their group in `current`; binds
+  /// without a group are left out.
+  pub fn group_changes(&self, desired: &Manifest, current: Option<&Manifest>) -> BTreeMap<String, GroupChanges> {
+    let mut groups: BTreeMap<String, GroupChanges> = BTreeMap::new();
+    let mut count = |manifest: Option<&Manifest>, hash: &ObjectHash, field: fn(&mut GroupChanges) -> &mut usize| {
+      let group = manifest
+        .and_then(|m| m.bindings.get(hash))
+        .and_then(|b| b.group.clone());
+      if let Some(group) = group {
+        *field(groups.entry(group).or_default()) += 1;
+      }
+    };
+
+    for hash in &self.binds_to_apply {
+      count(Some(desired), hash, |g| &mut g.to_apply);
+    }
+    for (_, new_hash) in &self.binds_to_update {
+      count(Some(desired), new_hash, |g| &mut g.to_update);
+    }
+    for hash in &self.binds_to_destroy {
+      count(current, hash, |g| &mut g.to_destroy);
+    }
+    for hash in &self.binds_unchanged {
+      count(Some(desired), hash, |g| &mut g.unchanged);
+    }
+
+    groups
+  }
+}
+
 /// Compute diff between desired manifest and current state.
 ///
 /// # Arguments
@@ -85,7 +134,8 @@ impl StateDiff {
 /// For binds with IDs:
 /// - Same ID + same hash → `binds_unchanged`
 /// - Same ID + different hash + has update_actions → `binds_to_update`
-/// - Same ID + different hash + no update_actions → `binds_to_destroy` + `binds_to_apply`
+/// - Same ID + different hash + no update_actions, or `update_strategy = "recreate"`
+///   → `binds_to_destroy` + `binds_to_apply`
 /// - ID only in desired → `binds_to_apply`
 /// - ID only in current → `binds_to_destroy`
 ///
@@ -93,6 +143,9 @@ impl StateDiff {
 /// - Hash in both → `binds_unchanged`
 /// - Hash only in desired → `binds_to_apply`
 /// - Hash only in current → `binds_to_destroy`
+///
+/// Hashes are compared with [`ObjectHash::matches`], so a bind recorded under
+/// a shorter hash (before `settings.hash.length` was raised) is the same bind.
 pub fn compute_diff(desired: &Manifest, current: Option<&Manifest>, store_path: &Path) -> StateDiff {
   let mut diff = StateDiff::default();
 
@@ -108,7 +161,7 @@ pub fn compute_diff(desired: &Manifest, current: Option<&Manifest>, store_path:
   // Compute orphaned builds (in current but not in desired)
   if let Some(current_manifest) = current {
     for hash in current_manifest.builds.keys() {
-      if !desired.builds.contains_key(hash) {
+      if desired.find_build(hash).is_none() {
         diff.builds_orphaned.push(hash.clone());
       }
     }
@@ -116,8 +169,8 @@ pub fn compute_diff(desired: &Manifest, current: Option<&Manifest>, store_path:
 
   // Compute bind diff
   // First, build ID -> hash maps for binds with IDs
-  let mut desired_by_id: HashMap<&String, &ObjectHash> = HashMap::new();
-  let mut desired_without_id: HashSet<&ObjectHash> = HashSet::new();
+  let mut desired_by_id: BTreeMap<&String, &ObjectHash> = BTreeMap::new();
+  let mut desired_without_id: BTreeSet<&ObjectHash> = BTreeSet::new();
 
   for (hash, bind_def) in &desired.bindings {
     if let Some(ref id) = bind_def.id {
@@ -127,8 +180,8 @@ pub fn compute_diff(desired: &Manifest, current: Option<&Manifest>, store_path:
     }
   }
 
-  let mut current_by_id: HashMap<&String, &ObjectHash> = HashMap::new();
-  let mut current_without_id: HashSet<&ObjectHash> = HashSet::new();
+  let mut current_by_id: BTreeMap<&String, &ObjectHash> = BTreeMap::new();
+  let mut current_without_id: BTreeSet<&ObjectHash> = BTreeSet::new();
 
   if let Some(current_manifest) = current {
     for (hash, bind_def) in &current_manifest.bindings {
@@ -141,8 +194,8 @@ pub fn compute_diff(desired: &Manifest, current: Option<&Manifest>, store_path:
   }
 
   // Track which hashes we've already processed via ID-based logic
-  let mut processed_desired: HashSet<&ObjectHash> = HashSet::new();
-  let mut processed_current: HashSet<&ObjectHash> = HashSet::new();
+  let mut processed_desired: BTreeSet<&ObjectHash> = BTreeSet::new();
+  let mut processed_current: BTreeSet<&ObjectHash> = BTreeSet::new();
 
   // Process binds with IDs
   for (id, desired_hash) in &desired_by_id {
@@ -151,13 +204,13 @@ pub fn compute_diff(desired: &Manifest, current: Option<&Manifest>, store_path:
     if let Some(current_hash) = current_by_id.get(*id) {
       processed_current.insert(*current_hash);
 
-      if desired_hash == current_hash {
+      if desired_hash.matches(&current_hash.0) {
         // Same hash - unchanged
         diff.binds_unchanged.push((*desired_hash).clone());
       } else {
-        // Different hash - check if update is possible
+        // Different hash - check if update is possible and wanted
         let desired_bind = desired.bindings.get(*desired_hash).unwrap();
-        if desired_bind.update_actions.is_some() {
+        if desired_bind.update_actions.is_some() && desired_bind.update_strategy.is_in_place() {
           // Update path
           diff
             .binds_to_update
@@ -183,8 +236,9 @@ pub fn compute_diff(desired: &Manifest, current: Option<&Manifest>, store_path:
   }
 
   // Process binds without IDs (hash-only identity)
+  let has_match = |hashes: &BTreeSet<&ObjectHash>, hash: &ObjectHash| hashes.iter().any(|h| h.matches(&hash.0));
   for hash in &desired_without_id {
-    if current_without_id.contains(hash) {
+    if has_match(&current_without_id, hash) {
       diff.binds_unchanged.push((*hash).clone());
     } else {
       diff.binds_to_apply.push((*hash).clone());
@@ -192,7 +246,7 @@ pub fn compute_diff(desired: &Manifest, current: Option<&Manifest>, store_path:
   }
 
   for hash in &current_without_id {
-    if !desired_without_id.contains(hash) {
+    if !has_match(&desired_without_id, hash) {
       diff.binds_to_destroy.push((*hash).clone());
     }
   }
@@ -203,8 +257,9 @@ pub fn compute_diff(desired: &Manifest, current: Option<&Manifest>, store_path:
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::bind::BindDef;
+  use crate::bind::{BindDef, UpdateStrategy};
   use crate::build::BuildDef;
+  use crate::build::store::builds_dir;
   use tempfile::TempDir;
 
   fn make_build_def(id: &str) -> BuildDef {
@@ -213,6 +268,11 @@ mod tests {
       inputs: None,
       create_actions: vec![],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     }
   }
 
@@ -226,6 +286,14 @@ mod tests {
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     }
   }
 
@@ -243,10 +311,22 @@ mod tests {
         args: Some(vec!["update".to_string()]),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })]),
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     }
   }
 
@@ -260,6 +340,14 @@ mod tests {
       destroy_actions: vec![],
       check_actions: None,
       check_outputs: None,
+      backup: None,
+      fallback_path: None,
+      tags: Vec::new(),
+      group: None,
+      repair: None,
+      serialize: None,
+      update_strategy: Default::default(),
+      phase: Default::default(),
     }
   }
 
@@ -302,7 +390,7 @@ mod tests {
 
     // Create the build directory to simulate cached build
     let build_hash = ObjectHash("abc123def45678901234".to_string());
-    let build_dir = temp_dir.path().join("build").join("abc123def45678901234");
+    let build_dir = builds_dir(temp_dir.path()).join("abc123def45678901234");
     std::fs::create_dir_all(&build_dir).unwrap();
 
     let mut desired = Manifest::default();
@@ -315,13 +403,45 @@ mod tests {
     assert_eq!(diff.builds_to_realize.len(), 0);
   }
 
+  #[test]
+  fn longer_bind_hashes_match_shorter_recorded_ones() {
+    let temp_dir = TempDir::new().unwrap();
+    let short = |n: u8| ObjectHash(format!("{}0123456789abcdef012", n));
+    let long = |n: u8| ObjectHash(format!("{}0123456789abcdef0123456789ab", n));
+
+    let mut current = Manifest::default();
+    current.bindings.insert(short(1), make_bind_def_with_update("with_id"));
+    current.bindings.insert(
+      short(2),
+      BindDef {
+        id: None,
+        ..make_bind_def("hash_only")
+      },
+    );
+
+    let mut desired = Manifest::default();
+    desired.bindings.insert(long(1), make_bind_def_with_update("with_id"));
+    desired.bindings.insert(
+      long(2),
+      BindDef {
+        id: None,
+        ..make_bind_def("hash_only")
+      },
+    );
+
+    let diff = compute_diff(&desired, Some(&current), temp_dir.path());
+
+    assert!(diff.is_empty(), "{diff:?}");
+    assert_eq!(diff.binds_unchanged, vec![long(1), long(2)]);
+  }
+
   #[test]
   fn diff_no_changes() {
     let temp_dir = TempDir::new().unwrap();
 
     // Create cached build
     let build_hash = ObjectHash("abc123def45678901234".to_string());
-    let build_dir = temp_dir.path().join("build").join("abc123def45678901234");
+    let build_dir = builds_dir(temp_dir.path()).join("abc123def45678901234");
     std::fs::create_dir_all(&build_dir).unwrap();
 
     let bind_hash = ObjectHash("bind1".to_string());
@@ -413,7 +533,7 @@ mod tests {
     let temp_dir = TempDir::new().unwrap();
 
     // Create some cached builds
-    std::fs::create_dir_all(temp_dir.path().join("build").join("abc123def45678901234")).unwrap();
+    std::fs::create_dir_all(builds_dir(temp_dir.path()).join("abc123def45678901234")).unwrap();
 
     let mut current = Manifest::default();
     current
@@ -532,6 +652,11 @@ mod tests {
       inputs: None,
       create_actions: vec![],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     };
     let base_v1_hash = base_v1.compute_hash().unwrap();
 
@@ -541,6 +666,11 @@ mod tests {
       inputs: None,
       create_actions: vec![],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     };
     let base_v2_hash = base_v2.compute_hash().unwrap();
 
@@ -556,6 +686,11 @@ mod tests {
       inputs: Some(BuildInputs::Build(base_v1_hash.clone())),
       create_actions: vec![],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     };
     let dep_v1_hash = dependent_on_v1.compute_hash().unwrap();
 
@@ -565,6 +700,11 @@ mod tests {
       inputs: Some(BuildInputs::Build(base_v2_hash.clone())),
       create_actions: vec![],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     };
     let dep_v2_hash = dependent_on_v2.compute_hash().unwrap();
 
@@ -589,6 +729,11 @@ mod tests {
       inputs: None,
       create_actions: vec![],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     };
     let hash_v1 = build_v1.compute_hash().unwrap();
 
@@ -606,6 +751,11 @@ mod tests {
       inputs: None,
       create_actions: vec![],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     };
     let hash_v2 = build_v2.compute_hash().unwrap();
 
@@ -635,8 +785,17 @@ mod tests {
         args: Some(vec!["hello".to_string()]),
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     };
     let hash1 = build_action1.compute_hash().unwrap();
 
@@ -649,8 +808,17 @@ mod tests {
         args: Some(vec!["world".to_string()]), // Different argument
         env: None,
         cwd: None,
+        creates: None,
+        unless: None,
+        shell: None,
+        run_as: None,
       })],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     };
     let hash2 = build_action2.compute_hash().unwrap();
 
@@ -670,6 +838,11 @@ mod tests {
       inputs: Some(BuildInputs::String("foo".to_string())),
       create_actions: vec![],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     };
     let hash1 = build_input1.compute_hash().unwrap();
 
@@ -679,6 +852,11 @@ mod tests {
       inputs: Some(BuildInputs::String("bar".to_string())),
       create_actions: vec![],
       outputs: None,
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
     };
     let hash2 = build_input2.compute_hash().unwrap();
 
@@ -738,6 +916,33 @@ mod tests {
     assert!(diff.binds_to_apply.contains(&ObjectHash("new_hash".to_string())));
   }
 
+  #[test]
+  fn diff_updated_bind_with_recreate_strategy_uses_destroy_create() {
+    // `update_strategy = "recreate"` skips update_actions
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut current = Manifest::default();
+    current
+      .bindings
+      .insert(ObjectHash("old_hash".to_string()), make_bind_def_with_update("my-bind"));
+
+    let mut desired = Manifest::default();
+    desired.bindings.insert(
+      ObjectHash("new_hash".to_string()),
+      BindDef {
+        update_strategy: UpdateStrategy::Recreate,
+        phase: Default::default(),
+        ..make_bind_def_with_update("my-bind")
+      },
+    );
+
+    let diff = compute_diff(&desired, Some(&current), temp_dir.path());
+
+    assert_eq!(diff.binds_to_update.len(), 0);
+    assert_eq!(diff.binds_to_destroy, vec![ObjectHash("old_hash".to_string())]);
+    assert_eq!(diff.binds_to_apply, vec![ObjectHash("new_hash".to_string())]);
+  }
+
   #[test]
   fn diff_bind_without_id_changed_uses_destroy_create() {
     // Binds without ID always use destroy + create, never update
@@ -877,4 +1082,47 @@ mod tests {
     };
     assert!(!diff.is_empty());
   }
+
+  #[test]
+  fn group_changes_counts_per_group() {
+    let grouped = |id: &str, group: &str| BindDef {
+      group: Some(group.to_string()),
+      ..make_bind_def(id)
+    };
+
+    let mut current = Manifest::default();
+    current
+      .bindings
+      .insert(ObjectHash("old".to_string()), grouped("nvim-plugins", "neovim"));
+    current
+      .bindings
+      .insert(ObjectHash("same".to_string()), grouped("nvim-config", "neovim"));
+
+    let mut desired = Manifest::default();
+    desired
+      .bindings
+      .insert(ObjectHash("same".to_string()), grouped("nvim-config", "neovim"));
+    desired
+      .bindings
+      .insert(ObjectHash("new".to_string()), grouped("zshrc", "shell"));
+    desired
+      .bindings
+      .insert(ObjectHash("plain".to_string()), make_bind_def("hosts"));
+
+    let temp_dir = TempDir::new().unwrap();
+    let diff = compute_diff(&desired, Some(&current), temp_dir.path());
+    let groups = diff.group_changes(&desired, Some(&current));
+
+    assert_eq!(groups.len(), 2);
+    assert_eq!(
+      groups["neovim"],
+      GroupChanges {
+        to_destroy: 1,
+        unchanged: 1,
+        ..Default::default()
+      }
+    );
+    assert_eq!(groups["neovim"].total(), 1);
+    assert_eq!(groups["shell"].to_apply, 1);
+  }
 }
@@ -0,0 +1,323 @@
+//! Signed state export for machine inventory.
+//!
+//! A state export bundles the current snapshot, the persisted bind states and
+//! the drift status of every bind into a single JSON document, signed with an
+//! Ed25519 key so a central system can verify where it came from.
+//!
+//! # Document Layout
+//!
+//! ```json
+//! {
+//!   "document": {
+//!     "version": 1,
+//!     "hostname": "workstation",
+//!     "platform": "x86_64-linux",
+//!     "exported_at": 1733667300,
+//!     "snapshot": { "id": "1765208363188", ... },
+//!     "bind_states": { "<hash>": { "outputs": { ... } } },
+//!     "drift": [ { "hash": "<hash>", "id": "nvim", "result": { "drifted": false } } ]
+//!   },
+//!   "signature": {
+//!     "algorithm": "ed25519",
+//!     "public_key": "<hex>",
+//!     "value": "<hex>"
+//!   }
+//! }
+//! ```
+//!
+//! The signature covers the `document` object serialized as compact JSON with
+//! sorted keys, so verification does not depend on the file's formatting.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use ring::rand::SystemRandom;
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use thiserror::Error;
+
+use crate::bind::state::{BindState, BindStateError, load_bind_state};
+use crate::execute::types::DriftResult;
+use crate::platform::{self, Platform};
+use crate::util::hash::ObjectHash;
+
+use super::types::Snapshot;
+
+/// Current state export format version.
+pub const STATE_EXPORT_VERSION: u32 = 1;
+
+/// Signature algorithm identifier recorded in exported documents.
+pub const SIGNATURE_ALGORITHM: &str = "ed25519";
+
+/// Managed state of a single machine at export time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateExport {
+  /// Export format version.
+  pub version: u32,
+
+  /// Hostname of the exporting machine, if known.
+  pub hostname: Option<String>,
+
+  /// Platform triple of the exporting machine (e.g., "x86_64-linux").
+  pub platform: Option<String>,
+
+  /// Unix timestamp when the export was created.
+  pub exported_at: u64,
+
+  /// The current snapshot, if any has been applied.
+  pub snapshot: Option<Snapshot>,
+
+  /// Persisted state of each bind in the current snapshot.
+  pub bind_states: BTreeMap<ObjectHash, BindState>,
+
+  /// Drift check results for binds that define a `check` callback.
+  pub drift: Vec<DriftResult>,
+}
+
+impl StateExport {
+  /// Collect the export document for the given snapshot.
+  ///
+  /// Bind states are loaded from the store for every bind in the snapshot.
+  /// Binds without persisted state are omitted.
+  pub fn collect(snapshot: Option<Snapshot>, drift: Vec<DriftResult>) -> Result<Self, StateExportError> {
+    let mut bind_states = BTreeMap::new();
+    if let Some(ref snapshot) = snapshot {
+      for hash in snapshot.manifest.bindings.keys() {
+        if let Some(state) = load_bind_state(hash)? {
+          bind_states.insert(hash.clone(), state);
+        }
+      }
+    }
+
+    Ok(Self {
+      version: STATE_EXPORT_VERSION,
+      hostname: platform::hostname(),
+      platform: Platform::current().map(|p| p.triple()),
+      exported_at: std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0),
+      snapshot,
+      bind_states,
+      drift,
+    })
+  }
+
+  /// Number of binds reported as drifted.
+  pub fn drifted_count(&self) -> usize {
+    self.drift.iter().filter(|d| d.result.drifted).count()
+  }
+
+  /// Sign this document with the given key pair.
+  pub fn sign(self, key: &Ed25519KeyPair) -> Result<SignedStateExport, StateExportError> {
+    let message = canonical_bytes(&serde_json::to_value(&self).map_err(StateExportError::Serialize)?)?;
+    let signature = key.sign(&message);
+
+    Ok(SignedStateExport {
+      document: self,
+      signature: StateSignature {
+        algorithm: SIGNATURE_ALGORITHM.to_string(),
+        public_key: hex::encode(key.public_key().as_ref()),
+        value: hex::encode(signature.as_ref()),
+      },
+    })
+  }
+}
+
+/// Signature block attached to an exported document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateSignature {
+  /// Signature algorithm (currently always "ed25519").
+  pub algorithm: String,
+
+  /// Hex-encoded public key of the signer.
+  pub public_key: String,
+
+  /// Hex-encoded signature over the canonical document bytes.
+  pub value: String,
+}
+
+/// A state export together with its signature.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedStateExport {
+  /// The exported state.
+  pub document: StateExport,
+
+  /// Signature over `document`.
+  pub signature: StateSignature,
+}
+
+impl SignedStateExport {
+  /// Parse a signed document from JSON, verifying its signature against `public_key`.
+  ///
+  /// `public_key` is the hex-encoded Ed25519 public key the document is expected
+  /// to be signed with. The public key embedded in the document is informational
+  /// and is never trusted on its own.
+  pub fn verify(content: &str, public_key: &str) -> Result<Self, StateExportError> {
+    let raw: JsonValue = serde_json::from_str(content).map_err(StateExportError::Parse)?;
+    let signed: SignedStateExport = serde_json::from_value(raw.clone()).map_err(StateExportError::Parse)?;
+
+    if signed.signature.algorithm != SIGNATURE_ALGORITHM {
+      return Err(StateExportError::UnsupportedAlgorithm(signed.signature.algorithm));
+    }
+
+    // Verify against the document exactly as it appears in the file
+    let document = raw.get("document").ok_or(StateExportError::MissingDocument)?;
+    let message = canonical_bytes(document)?;
+
+    let key_bytes = hex::decode(public_key.trim()).map_err(|_| StateExportError::InvalidKey)?;
+    let signature_bytes = hex::decode(&signed.signature.value).map_err(|_| StateExportError::InvalidSignature)?;
+
+    UnparsedPublicKey::new(&ED25519, key_bytes)
+      .verify(&message, &signature_bytes)
+      .map_err(|_| StateExportError::InvalidSignature)?;
+
+    Ok(signed)
+  }
+
+  /// Parse a signed document from JSON without verifying its signature.
+  pub fn parse_unverified(content: &str) -> Result<Self, StateExportError> {
+    serde_json::from_str(content).map_err(StateExportError::Parse)
+  }
+}
+
+/// Generate a new Ed25519 signing key.
+///
+/// Returns the hex-encoded PKCS#8 private key and the hex-encoded public key.
+pub fn generate_signing_key() -> Result<(String, String), StateExportError> {
+  let rng = SystemRandom::new();
+  let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).map_err(|_| StateExportError::KeyGeneration)?;
+  let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).map_err(|_| StateExportError::InvalidKey)?;
+  Ok((hex::encode(pkcs8.as_ref()), hex::encode(key.public_key().as_ref())))
+}
+
+/// Load a signing key written by [`generate_signing_key`].
+pub fn load_signing_key(path: &Path) -> Result<Ed25519KeyPair, StateExportError> {
+  let content = std::fs::read_to_string(path).map_err(StateExportError::ReadKey)?;
+  let bytes = hex::decode(content.trim()).map_err(|_| StateExportError::InvalidKey)?;
+  Ed25519KeyPair::from_pkcs8(&bytes).map_err(|_| StateExportError::InvalidKey)
+}
+
+/// Serialize a JSON value as compact JSON with sorted object keys.
+fn canonical_bytes(value: &JsonValue) -> Result<Vec<u8>, StateExportError> {
+  // serde_json's Map is a BTreeMap, so keys are emitted in sorted order
+  serde_json::to_vec(value).map_err(StateExportError::Serialize)
+}
+
+/// Errors that can occur when exporting or verifying state.
+#[derive(Debug, Error)]
+pub enum StateExportError {
+  /// Failed to load a bind state.
+  #[error("failed to load bind state: {0}")]
+  BindState(#[from] BindStateError),
+
+  /// Failed to read the signing key.
+  #[error("failed to read signing key: {0}")]
+  ReadKey(#[source] std::io::Error),
+
+  /// The signing or public key is malformed.
+  #[error("invalid key")]
+  InvalidKey,
+
+  /// Key generation failed.
+  #[error("failed to generate signing key")]
+  KeyGeneration,
+
+  /// The signature does not match the document and public key.
+  #[error("signature verification failed")]
+  InvalidSignature,
+
+  /// The signature uses an unsupported algorithm.
+  #[error("unsupported signature algorithm: {0}")]
+  UnsupportedAlgorithm(String),
+
+  /// The document has no `document` field.
+  #[error("missing 'document' field")]
+  MissingDocument,
+
+  /// Failed to parse JSON.
+  #[error("failed to parse: {0}")]
+  Parse(#[source] serde_json::Error),
+
+  /// Failed to serialize JSON.
+  #[error("failed to serialize: {0}")]
+  Serialize(#[source] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::bind::BindCheckResult;
+  use crate::manifest::Manifest;
+
+  fn test_export() -> StateExport {
+    StateExport {
+      version: STATE_EXPORT_VERSION,
+      hostname: Some("test-host".to_string()),
+      platform: Some("x86_64-linux".to_string()),
+      exported_at: 1733667300,
+      snapshot: Some(Snapshot::new("123".to_string(), None, Manifest::default())),
+      bind_states: BTreeMap::new(),
+      drift: vec![DriftResult {
+        hash: ObjectHash("abc".to_string()),
+        id: Some("nvim".to_string()),
+        result: BindCheckResult {
+          drifted: true,
+          message: Some("link missing".to_string()),
+        },
+        repair_skipped: None,
+      }],
+    }
+  }
+
+  fn test_key() -> (Ed25519KeyPair, String) {
+    let (private, public) = generate_signing_key().unwrap();
+    let key = Ed25519KeyPair::from_pkcs8(&hex::decode(private).unwrap()).unwrap();
+    (key, public)
+  }
+
+  #[test]
+  fn signed_export_roundtrip_verifies() {
+    let (key, public) = test_key();
+    let signed = test_export().sign(&key).unwrap();
+    let content = serde_json::to_string_pretty(&signed).unwrap();
+
+    let verified = SignedStateExport::verify(&content, &public).unwrap();
+    assert_eq!(verified, signed);
+    assert_eq!(verified.document.drifted_count(), 1);
+  }
+
+  #[test]
+  fn verify_rejects_tampered_document() {
+    let (key, public) = test_key();
+    let mut signed = test_export().sign(&key).unwrap();
+    signed.document.hostname = Some("other-host".to_string());
+    let content = serde_json::to_string(&signed).unwrap();
+
+    let result = SignedStateExport::verify(&content, &public);
+    assert!(matches!(result, Err(StateExportError::InvalidSignature)));
+  }
+
+  #[test]
+  fn verify_rejects_wrong_public_key() {
+    let (key, _) = test_key();
+    let (_, other_public) = test_key();
+    let signed = test_export().sign(&key).unwrap();
+    let content = serde_json::to_string(&signed).unwrap();
+
+    let result = SignedStateExport::verify(&content, &other_public);
+    assert!(matches!(result, Err(StateExportError::InvalidSignature)));
+  }
+
+  #[test]
+  fn load_signing_key_reads_generated_key() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("export.key");
+    let (private, public) = generate_signing_key().unwrap();
+    std::fs::write(&path, private).unwrap();
+
+    let key = load_signing_key(&path).unwrap();
+    assert_eq!(hex::encode(key.public_key().as_ref()), public);
+  }
+}
@@ -0,0 +1,504 @@
+//! Append-only journal of applies, destroys and repairs.
+//!
+//! Every `sys apply`, `sys destroy` and `sys repair` that changes the system appends one
+//! entry to `<store>/journal.jsonl`: who ran it, when, on which host, the
+//! snapshot it produced, the provenance of the config and the outcome. The
+//! journal is never rewritten, which makes it an audit trail for shared and
+//! system machines; `sys history` shows and verifies it.
+//!
+//! # Tamper Evidence
+//!
+//! Entries are JSON Lines numbered from 1, and each records in `prev` the
+//! SHA-256 of the previous line exactly as written, so editing, removing or
+//! reordering an entry breaks the chain at the next one. When the store holds
+//! a machine key (`<store>/machine.key`, created by `sys state keygen`), each
+//! entry is also signed with it, like a state export: the signature covers the
+//! entry without its `signature` field, as compact JSON with sorted keys.
+//! Once an entry is signed, every later entry must be too.
+//!
+//! ```json
+//! {"seq":2,"recorded_at":1767225600,"operation":"apply","user":"alice","hostname":"build01",
+//!  "snapshot":"1767225600123","success":true,"changes":{"binds_applied":3},
+//!  "prev":"<sha256 of line 1>","signature":{"algorithm":"ed25519","public_key":"<hex>","value":"<hex>"}}
+//! ```
+//!
+//! The chain can't reveal entries cut from the end of the file; compare the
+//! last `seq` with a copy kept elsewhere (for example in a state export
+//! pipeline) to catch that.
+
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::platform::{self, paths::store_dir};
+use crate::util::hash::hash_bytes;
+
+use super::export::{SIGNATURE_ALGORITHM, StateExportError, StateSignature, load_signing_key};
+use super::provenance::Provenance;
+
+/// Name of the journal file in the store.
+pub const JOURNAL_FILENAME: &str = "journal.jsonl";
+
+/// Name of the machine key in the store.
+pub const MACHINE_KEY_FILENAME: &str = "machine.key";
+
+/// Path of the journal in the current store.
+pub fn journal_path() -> PathBuf {
+  store_dir().join(JOURNAL_FILENAME)
+}
+
+/// Path of the machine key in the current store.
+pub fn machine_key_path() -> PathBuf {
+  store_dir().join(MACHINE_KEY_FILENAME)
+}
+
+/// Load the machine key, or `None` if the store has none.
+pub fn load_machine_key() -> Result<Option<Ed25519KeyPair>, StateExportError> {
+  let path = machine_key_path();
+  if !path.exists() {
+    return Ok(None);
+  }
+  load_signing_key(&path).map(Some)
+}
+
+/// Errors that can occur when reading, appending to or verifying the journal.
+#[derive(Debug, Error)]
+pub enum JournalError {
+  #[error("failed to read journal: {0}")]
+  Read(#[source] io::Error),
+
+  #[error("failed to write journal: {0}")]
+  Write(#[source] io::Error),
+
+  #[error("failed to parse journal line {line}: {source}")]
+  Parse {
+    line: usize,
+    #[source]
+    source: serde_json::Error,
+  },
+
+  #[error("failed to serialize journal entry: {0}")]
+  Serialize(#[source] serde_json::Error),
+
+  #[error("invalid key")]
+  InvalidKey,
+
+  /// The journal was modified after the entry was written.
+  #[error("journal line {line} was tampered with: {reason}")]
+  Tampered { line: usize, reason: String },
+}
+
+/// The command an entry records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalOperation {
+  Apply,
+  Destroy,
+  Repair,
+}
+
+impl JournalOperation {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::Apply => "apply",
+      Self::Destroy => "destroy",
+      Self::Repair => "repair",
+    }
+  }
+}
+
+/// What an apply, destroy or repair changed. Zero counts are left out of the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalChanges {
+  #[serde(default, skip_serializing_if = "is_zero")]
+  pub builds_realized: usize,
+  #[serde(default, skip_serializing_if = "is_zero")]
+  pub binds_applied: usize,
+  #[serde(default, skip_serializing_if = "is_zero")]
+  pub binds_updated: usize,
+  #[serde(default, skip_serializing_if = "is_zero")]
+  pub binds_destroyed: usize,
+  #[serde(default, skip_serializing_if = "is_zero")]
+  pub binds_repaired: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+  *n == 0
+}
+
+/// The outcome of an apply, destroy or repair, as the caller knows it.
+///
+/// [`append_journal`] adds who ran it, when, where, and the chain fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalRecord {
+  pub operation: JournalOperation,
+  /// Snapshot that became current, if any.
+  pub snapshot: Option<String>,
+  /// Provenance of the config that was applied.
+  pub provenance: Option<Provenance>,
+  /// The error, if the command failed.
+  pub error: Option<String>,
+  pub changes: JournalChanges,
+}
+
+/// One line of the journal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+  /// Position in the journal, starting at 1.
+  pub seq: u64,
+
+  /// Unix timestamp when the entry was written.
+  pub recorded_at: u64,
+
+  pub operation: JournalOperation,
+
+  /// User that ran the command (`USER`, or `USERNAME` on Windows).
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub user: Option<String>,
+
+  /// User that ran it through sudo (`SUDO_USER`).
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub sudo_user: Option<String>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hostname: Option<String>,
+
+  /// Snapshot that became current, if any.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub snapshot: Option<String>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub provenance: Option<Provenance>,
+
+  pub success: bool,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+
+  #[serde(default)]
+  pub changes: JournalChanges,
+
+  /// SHA-256 of the previous line; absent on the first entry.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub prev: Option<String>,
+
+  /// Signature with the machine key, if the store had one.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub signature: Option<StateSignature>,
+}
+
+/// Result of a successful [`verify_journal`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct JournalReport {
+  /// Number of entries.
+  pub entries: usize,
+  /// Number of signed entries.
+  pub signed: usize,
+  /// Public keys the signed entries were made with.
+  pub public_keys: BTreeSet<String>,
+}
+
+/// Load every entry of the journal at `path`; empty if there is none.
+pub fn load_journal(path: &Path) -> Result<Vec<JournalEntry>, JournalError> {
+  read_lines(path)?
+    .iter()
+    .enumerate()
+    .map(|(i, line)| serde_json::from_str(line).map_err(|source| JournalError::Parse { line: i + 1, source }))
+    .collect()
+}
+
+/// Append `record` to the journal at `path`, signed with `key` if given.
+///
+/// Callers must hold the store lock, so entries are appended one at a time.
+pub fn append_journal(
+  path: &Path,
+  record: JournalRecord,
+  key: Option<&Ed25519KeyPair>,
+) -> Result<JournalEntry, JournalError> {
+  let lines = read_lines(path)?;
+  let last = match lines.last() {
+    Some(line) => Some(
+      serde_json::from_str::<JournalEntry>(line).map_err(|source| JournalError::Parse {
+        line: lines.len(),
+        source,
+      })?,
+    ),
+    None => None,
+  };
+
+  let mut entry = JournalEntry {
+    seq: last.map(|entry| entry.seq + 1).unwrap_or(1),
+    recorded_at: std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or(0),
+    operation: record.operation,
+    user: env_var("USER").or_else(|| env_var("USERNAME")),
+    sudo_user: env_var("SUDO_USER"),
+    hostname: platform::hostname(),
+    snapshot: record.snapshot,
+    provenance: record.provenance,
+    success: record.error.is_none(),
+    error: record.error,
+    changes: record.changes,
+    prev: lines.last().map(|line| hash_bytes(line.as_bytes()).0),
+    signature: None,
+  };
+  if let Some(key) = key {
+    let message = canonical_bytes(&serde_json::to_value(&entry).map_err(JournalError::Serialize)?)?;
+    entry.signature = Some(StateSignature {
+      algorithm: SIGNATURE_ALGORITHM.to_string(),
+      public_key: hex::encode(key.public_key().as_ref()),
+      value: hex::encode(key.sign(&message).as_ref()),
+    });
+  }
+
+  let mut line = serde_json::to_string(&entry).map_err(JournalError::Serialize)?;
+  line.push('\n');
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(JournalError::Write)?;
+  }
+  let mut file = OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(path)
+    .map_err(JournalError::Write)?;
+  file.write_all(line.as_bytes()).map_err(JournalError::Write)?;
+  file.sync_all().map_err(JournalError::Write)?;
+  Ok(entry)
+}
+
+/// Append `record` to the current store's journal, signed with its machine key.
+///
+/// The journal must not fail the command it records, so errors are logged.
+pub fn record_in_journal(record: JournalRecord) {
+  let key = load_machine_key().unwrap_or_else(|e| {
+    warn!(error = %e, "failed to load machine key, writing an unsigned journal entry");
+    None
+  });
+  if let Err(e) = append_journal(&journal_path(), record, key.as_ref()) {
+    warn!(error = %e, "failed to record the command in the journal");
+  }
+}
+
+/// Check the chain and signatures of the journal at `path`.
+///
+/// Without `public_key`, signatures are checked against the key each entry
+/// embeds, which only proves the entries weren't changed by someone without
+/// *some* key; pass the hex-encoded machine public key to require that every
+/// signed entry was made with it.
+pub fn verify_journal(path: &Path, public_key: Option<&str>) -> Result<JournalReport, JournalError> {
+  let expected_key = match public_key {
+    Some(key) => Some(hex::decode(key.trim()).map_err(|_| JournalError::InvalidKey)?),
+    None => None,
+  };
+
+  let lines = read_lines(path)?;
+  let mut report = JournalReport::default();
+  let mut prev: Option<&str> = None;
+  for (i, line) in lines.iter().enumerate() {
+    let number = i + 1;
+    let tampered = |reason: String| JournalError::Tampered { line: number, reason };
+    let raw: JsonValue = serde_json::from_str(line).map_err(|source| JournalError::Parse { line: number, source })?;
+    let entry: JournalEntry =
+      serde_json::from_value(raw.clone()).map_err(|source| JournalError::Parse { line: number, source })?;
+
+    if entry.seq != number as u64 {
+      return Err(tampered(format!(
+        "expected entry {}, found entry {}",
+        number, entry.seq
+      )));
+    }
+    let expected_prev = prev.map(|line| hash_bytes(line.as_bytes()).0);
+    if entry.prev != expected_prev {
+      return Err(tampered(
+        "the previous line doesn't match its recorded hash".to_string(),
+      ));
+    }
+    prev = Some(line);
+
+    let Some(signature) = &entry.signature else {
+      if report.signed > 0 {
+        return Err(tampered("entry is not signed, but earlier entries are".to_string()));
+      }
+      report.entries += 1;
+      continue;
+    };
+    if signature.algorithm != SIGNATURE_ALGORITHM {
+      return Err(tampered(format!(
+        "unsupported signature algorithm: {}",
+        signature.algorithm
+      )));
+    }
+    let embedded_key = hex::decode(&signature.public_key).map_err(|_| tampered("invalid public key".to_string()))?;
+    let key = match &expected_key {
+      Some(expected) if *expected != embedded_key => {
+        return Err(tampered("entry is signed with another key".to_string()));
+      }
+      Some(expected) => expected.clone(),
+      None => embedded_key,
+    };
+
+    // Verify against the entry exactly as it appears in the file
+    let mut unsigned = raw;
+    if let Some(object) = unsigned.as_object_mut() {
+      object.remove("signature");
+    }
+    let message = canonical_bytes(&unsigned)?;
+    let value = hex::decode(&signature.value).map_err(|_| tampered("invalid signature".to_string()))?;
+    UnparsedPublicKey::new(&ED25519, key)
+      .verify(&message, &value)
+      .map_err(|_| tampered("signature verification failed".to_string()))?;
+
+    report.entries += 1;
+    report.signed += 1;
+    report.public_keys.insert(signature.public_key.clone());
+  }
+  Ok(report)
+}
+
+/// Non-empty lines of the journal at `path`, without their line endings.
+fn read_lines(path: &Path) -> Result<Vec<String>, JournalError> {
+  let content = match fs::read_to_string(path) {
+    Ok(content) => content,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(e) => return Err(JournalError::Read(e)),
+  };
+  Ok(
+    content
+      .lines()
+      .filter(|line| !line.trim().is_empty())
+      .map(str::to_string)
+      .collect(),
+  )
+}
+
+/// Serialize a JSON value as compact JSON with sorted object keys, as state
+/// exports are signed.
+fn canonical_bytes(value: &JsonValue) -> Result<Vec<u8>, JournalError> {
+  serde_json::to_vec(value).map_err(JournalError::Serialize)
+}
+
+fn env_var(name: &str) -> Option<String> {
+  std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::snapshot::generate_signing_key;
+  use tempfile::TempDir;
+
+  fn record(snapshot: &str) -> JournalRecord {
+    JournalRecord {
+      operation: JournalOperation::Apply,
+      snapshot: Some(snapshot.to_string()),
+      provenance: None,
+      error: None,
+      changes: JournalChanges {
+        binds_applied: 2,
+        ..Default::default()
+      },
+    }
+  }
+
+  fn test_key() -> (Ed25519KeyPair, String) {
+    let (private, public) = generate_signing_key().unwrap();
+    let key = Ed25519KeyPair::from_pkcs8(&hex::decode(private).unwrap()).unwrap();
+    (key, public)
+  }
+
+  #[test]
+  fn entries_are_chained_and_numbered() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join(JOURNAL_FILENAME);
+
+    let first = append_journal(&path, record("1"), None).unwrap();
+    let second = append_journal(&path, record("2"), None).unwrap();
+    assert_eq!((first.seq, second.seq), (1, 2));
+    assert_eq!(first.prev, None);
+    assert!(second.success);
+
+    let content = fs::read_to_string(&path).unwrap();
+    let first_line = content.lines().next().unwrap();
+    assert_eq!(second.prev, Some(hash_bytes(first_line.as_bytes()).0));
+    assert_eq!(load_journal(&path).unwrap(), vec![first, second]);
+
+    let report = verify_journal(&path, None).unwrap();
+    assert_eq!((report.entries, report.signed), (2, 0));
+  }
+
+  #[test]
+  fn edited_entries_break_the_chain() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join(JOURNAL_FILENAME);
+    for id in ["1", "2", "3"] {
+      append_journal(&path, record(id), None).unwrap();
+    }
+
+    let content = fs::read_to_string(&path).unwrap();
+    fs::write(&path, content.replacen("\"snapshot\":\"2\"", "\"snapshot\":\"9\"", 1)).unwrap();
+    assert!(matches!(
+      verify_journal(&path, None),
+      Err(JournalError::Tampered { line: 3, .. })
+    ));
+
+    // Dropping a line renumbers the rest
+    let lines: Vec<&str> = content.lines().collect();
+    fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+    assert!(matches!(
+      verify_journal(&path, None),
+      Err(JournalError::Tampered { line: 2, .. })
+    ));
+  }
+
+  #[test]
+  fn signed_entries_verify_against_the_machine_key() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join(JOURNAL_FILENAME);
+    let (key, public) = test_key();
+
+    append_journal(&path, record("1"), None).unwrap();
+    let signed = append_journal(&path, record("2"), Some(&key)).unwrap();
+    assert_eq!(signed.signature.as_ref().unwrap().public_key, public);
+
+    let report = verify_journal(&path, Some(&public)).unwrap();
+    assert_eq!((report.entries, report.signed), (2, 1));
+    assert_eq!(report.public_keys, BTreeSet::from([public]));
+
+    let (_, other) = test_key();
+    assert!(matches!(
+      verify_journal(&path, Some(&other)),
+      Err(JournalError::Tampered { line: 2, .. })
+    ));
+
+    // Entries written without the key after signed ones stand out
+    append_journal(&path, record("3"), None).unwrap();
+    assert!(matches!(
+      verify_journal(&path, None),
+      Err(JournalError::Tampered { line: 3, .. })
+    ));
+  }
+
+  #[test]
+  fn resigning_an_edited_entry_needs_the_key() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join(JOURNAL_FILENAME);
+    let (key, public) = test_key();
+    append_journal(&path, record("1"), Some(&key)).unwrap();
+
+    let content = fs::read_to_string(&path).unwrap();
+    fs::write(&path, content.replace("\"success\":true", "\"success\":false")).unwrap();
+    assert!(matches!(
+      verify_journal(&path, Some(&public)),
+      Err(JournalError::Tampered { line: 1, .. })
+    ));
+  }
+}
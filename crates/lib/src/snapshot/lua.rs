@@ -0,0 +1,188 @@
+//! The `sys.current` table: what is currently applied.
+//!
+//! Configs can read the snapshot that was current when evaluation started,
+//! to behave differently on first install or to migrate from a previous
+//! layout:
+//!
+//! ```lua
+//! if sys.current.id == nil then
+//!   -- nothing applied yet
+//! elseif sys.current.binds["old-service"] then
+//!   -- the bind was renamed; clean up after it
+//! end
+//! ```
+//!
+//! The table is read-only and holds plain values, so a build only changes
+//! hash when its spec actually uses them.
+
+use mlua::prelude::*;
+
+use super::Snapshot;
+
+/// Create the `sys.current` table for `snapshot` (`None` when nothing has
+/// been applied).
+///
+/// Fields: `id`, `created_at` and `config_path` of the snapshot (nil without
+/// one), and `builds` and `binds` mapping the ids of its builds and binds to
+/// their hashes. Builds and binds without an id are left out.
+pub fn create_current_table(lua: &Lua, snapshot: Option<&Snapshot>) -> LuaResult<LuaTable> {
+  let current = lua.create_table()?;
+  let builds = lua.create_table()?;
+  let binds = lua.create_table()?;
+
+  if let Some(snapshot) = snapshot {
+    current.set("id", snapshot.id.as_str())?;
+    current.set("created_at", snapshot.created_at)?;
+    current.set(
+      "config_path",
+      snapshot.config_path.as_ref().map(|p| p.to_string_lossy().to_string()),
+    )?;
+    for (hash, def) in &snapshot.manifest.builds {
+      if let Some(id) = &def.id {
+        builds.set(id.as_str(), hash.0.as_str())?;
+      }
+    }
+    for (hash, def) in &snapshot.manifest.bindings {
+      if let Some(id) = &def.id {
+        binds.set(id.as_str(), hash.0.as_str())?;
+      }
+    }
+  }
+
+  current.set("builds", read_only(lua, builds, "sys.current.builds")?)?;
+  current.set("binds", read_only(lua, binds, "sys.current.binds")?)?;
+  read_only(lua, current, "sys.current")
+}
+
+/// Set `sys.current` to the table for `snapshot`.
+pub fn set_sys_current(lua: &Lua, snapshot: Option<&Snapshot>) -> LuaResult<()> {
+  let sys: LuaTable = lua.globals().get("sys")?;
+  sys.set("current", create_current_table(lua, snapshot)?)
+}
+
+/// Wrap `table` in an empty proxy that reads (and iterates) through to it
+/// and refuses assignments.
+fn read_only(lua: &Lua, table: LuaTable, name: &'static str) -> LuaResult<LuaTable> {
+  let next: LuaFunction = lua.globals().get("next")?;
+  let meta = lua.create_table()?;
+  meta.set("__index", table.clone())?;
+  meta.set(
+    "__newindex",
+    lua.create_function(move |_, _: LuaMultiValue| -> LuaResult<()> {
+      Err(LuaError::external(format!("{} is read-only", name)))
+    })?,
+  )?;
+  meta.set(
+    "__pairs",
+    lua.create_function(move |_, _: LuaValue| Ok((next.clone(), table.clone(), LuaNil)))?,
+  )?;
+  meta.set("__metatable", false)?;
+
+  let proxy = lua.create_table()?;
+  proxy.set_metatable(Some(meta))?;
+  Ok(proxy)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  use super::*;
+  use crate::bind::BindDef;
+  use crate::lua::globals::register_globals;
+  use crate::manifest::Manifest;
+  use crate::util::hash::ObjectHash;
+
+  fn setup() -> LuaResult<Lua> {
+    let lua = crate::lua::runtime::create_lua(false)?;
+    register_globals(&lua, Rc::new(RefCell::new(Manifest::default())))?;
+    Ok(lua)
+  }
+
+  fn snapshot() -> Snapshot {
+    let mut manifest = Manifest::default();
+    manifest.bindings.insert(
+      ObjectHash("aaaaaaaaaaaaaaaaaaaa".to_string()),
+      BindDef {
+        id: Some("old-service".to_string()),
+        inputs: None,
+        outputs: None,
+        create_actions: vec![],
+        update_actions: None,
+        destroy_actions: vec![],
+        check_actions: None,
+        check_outputs: None,
+        backup: None,
+        fallback_path: None,
+        tags: Vec::new(),
+        group: None,
+        repair: None,
+        serialize: None,
+        update_strategy: Default::default(),
+        phase: Default::default(),
+      },
+    );
+    Snapshot::new("1700000000000".to_string(), None, manifest)
+  }
+
+  const BUILD: &str = r#"
+    return sys.build({
+      id = "tool",
+      inputs = { first_install = sys.current.id == nil },
+      create = function(inputs, ctx)
+        return { out = ctx.out }
+      end,
+    }).hash
+  "#;
+
+  #[test]
+  fn lists_what_is_applied() -> LuaResult<()> {
+    let lua = setup()?;
+    assert!(
+      lua
+        .load("return sys.current.id == nil and next(sys.current.binds) == nil")
+        .eval::<bool>()?
+    );
+
+    set_sys_current(&lua, Some(&snapshot()))?;
+    assert_eq!(lua.load("return sys.current.id").eval::<String>()?, "1700000000000");
+    assert_eq!(
+      lua
+        .load(r#"return sys.current.binds["old-service"]"#)
+        .eval::<String>()?,
+      "aaaaaaaaaaaaaaaaaaaa"
+    );
+    let ids: Vec<String> = lua
+      .load("local ids = {} for id in pairs(sys.current.binds) do ids[#ids + 1] = id end return ids")
+      .eval()?;
+    assert_eq!(ids, ["old-service"]);
+    Ok(())
+  }
+
+  #[test]
+  fn is_read_only() -> LuaResult<()> {
+    let lua = setup()?;
+    set_sys_current(&lua, Some(&snapshot()))?;
+    let err = lua.load(r#"sys.current.binds["new"] = "x""#).exec().unwrap_err();
+    assert!(err.to_string().contains("sys.current.binds is read-only"), "{}", err);
+    let err = lua.load("sys.current.id = nil").exec().unwrap_err();
+    assert!(err.to_string().contains("sys.current is read-only"), "{}", err);
+    Ok(())
+  }
+
+  #[test]
+  fn changes_build_hashes_only_when_used() -> LuaResult<()> {
+    let unused = r#"return sys.build({ id = "plain", create = function(_, ctx) return { out = ctx.out } end }).hash"#;
+    let (empty_unused, empty_used) = {
+      let lua = setup()?;
+      (lua.load(unused).eval::<String>()?, lua.load(BUILD).eval::<String>()?)
+    };
+
+    let lua = setup()?;
+    set_sys_current(&lua, Some(&snapshot()))?;
+    assert_eq!(lua.load(unused).eval::<String>()?, empty_unused);
+    assert_ne!(lua.load(BUILD).eval::<String>()?, empty_used);
+    Ok(())
+  }
+}
@@ -8,11 +8,23 @@
 //! - [`types`]: Core types (`Snapshot`, `SnapshotIndex`, etc.)
 //! - [`storage`]: Disk persistence (`SnapshotStore`)
 //! - [`diff`]: Diff computation between manifests
+//! - [`export`]: Signed state export for machine inventory
+//! - [`journal`]: Append-only, tamper-evident journal of applies, destroys and repairs
+//! - [`provenance`]: Config revision that produced a snapshot
+//! - [`lua`]: The read-only `sys.current` table
 
 mod diff;
+mod export;
+mod journal;
+mod lua;
+mod provenance;
 mod storage;
 mod types;
 
 pub use diff::*;
+pub use export::*;
+pub use journal::*;
+pub use lua::*;
+pub use provenance::*;
 pub use storage::*;
 pub use types::*;
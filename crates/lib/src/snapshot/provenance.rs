@@ -0,0 +1,104 @@
+//! Provenance of a snapshot.
+//!
+//! Records which configuration revision produced a snapshot so applied state
+//! can be traced back to its source: the config file's content hash, the git
+//! commit of the config directory, the input revisions the evaluation
+//! resolved (overrides included, which the lock file doesn't record), the
+//! syslua version and the hostname. Every field is best effort; anything that cannot
+//! be determined is left empty rather than failing the apply.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::Manifest;
+use crate::platform;
+use crate::util::hash::hash_file;
+
+/// Where a snapshot's configuration came from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+  /// SHA-256 of the config file's contents.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub config_hash: Option<String>,
+
+  /// HEAD commit of the git repository containing the config, if any.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub git_commit: Option<String>,
+
+  /// Resolved revision of each root input (name -> rev).
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub inputs: BTreeMap<String, String>,
+
+  /// Version of syslua that produced the snapshot.
+  pub syslua_version: String,
+
+  /// Hostname of the machine the snapshot was applied on.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hostname: Option<String>,
+}
+
+impl Provenance {
+  /// Collect provenance for the config at `config_path`, evaluated into
+  /// `manifest` unless the evaluation failed.
+  pub fn collect(config_path: &Path, manifest: Option<&Manifest>) -> Self {
+    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+
+    Self {
+      config_hash: hash_file(config_path).ok().map(|hash| hash.0),
+      git_commit: git_commit(config_dir),
+      inputs: manifest.map(|manifest| manifest.inputs.clone()).unwrap_or_default(),
+      syslua_version: env!("CARGO_PKG_VERSION").to_string(),
+      hostname: platform::hostname(),
+    }
+  }
+}
+
+/// HEAD commit of the repository containing `dir`.
+fn git_commit(dir: &Path) -> Option<String> {
+  let repo = gix::discover(dir).ok()?;
+  let head = repo.head_id().ok()?;
+  Some(head.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::inputs::lock::{LOCK_FILENAME, LockFile, LockedInput};
+  use crate::util::hash::hash_bytes;
+  use tempfile::TempDir;
+
+  #[test]
+  fn collects_config_hash_and_resolved_inputs() {
+    let temp = TempDir::new().unwrap();
+    let config = temp.path().join("init.lua");
+    std::fs::write(&config, "return {}").unwrap();
+
+    // The lock file pins another revision, as with `--override-input`
+    let mut lock = LockFile::new();
+    lock.insert(
+      "pkgs".to_string(),
+      LockedInput::new("git", "git:https://example.com/pkgs.git", "abc123"),
+    );
+    lock.save(&temp.path().join(LOCK_FILENAME)).unwrap();
+    let manifest = Manifest {
+      inputs: [("pkgs".to_string(), "def456".to_string())].into_iter().collect(),
+      ..Default::default()
+    };
+
+    let provenance = Provenance::collect(&config, Some(&manifest));
+    assert_eq!(provenance.config_hash, Some(hash_bytes(b"return {}").0));
+    assert_eq!(provenance.inputs.get("pkgs").map(String::as_str), Some("def456"));
+    assert_eq!(provenance.syslua_version, env!("CARGO_PKG_VERSION"));
+  }
+
+  #[test]
+  fn missing_config_leaves_fields_empty() {
+    let temp = TempDir::new().unwrap();
+    let provenance = Provenance::collect(&temp.path().join("missing.lua"), None);
+
+    assert!(provenance.config_hash.is_none());
+    assert!(provenance.inputs.is_empty());
+  }
+}
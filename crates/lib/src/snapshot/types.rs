@@ -3,6 +3,7 @@
 //! Snapshots capture system state as a manifest of builds and binds.
 //! They enable rollback, diff computation, and garbage collection.
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
@@ -10,6 +11,8 @@ use thiserror::Error;
 
 use crate::manifest::Manifest;
 
+use super::provenance::Provenance;
+
 /// Current snapshot index format version.
 pub const SNAPSHOT_INDEX_VERSION: u32 = 1;
 
@@ -30,6 +33,16 @@ pub struct Snapshot {
 
   /// The manifest containing builds and binds.
   pub manifest: Manifest,
+
+  /// Input URL overrides (name -> URL) that were active when this state was
+  /// produced. Non-empty means the snapshot does not match the lock file.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub input_overrides: BTreeMap<String, String>,
+
+  /// Config revision, input revisions and host that produced this snapshot.
+  /// Absent for snapshots recorded before provenance was tracked.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub provenance: Option<Provenance>,
 }
 
 impl Snapshot {
@@ -40,9 +53,28 @@ impl Snapshot {
       created_at: current_timestamp(),
       config_path,
       manifest,
+      input_overrides: BTreeMap::new(),
+      provenance: None,
     }
   }
 
+  /// Record the input overrides used to produce this snapshot.
+  pub fn with_input_overrides(mut self, overrides: BTreeMap<String, String>) -> Self {
+    self.input_overrides = overrides;
+    self
+  }
+
+  /// Record the provenance of this snapshot.
+  pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+    self.provenance = Some(provenance);
+    self
+  }
+
+  /// Whether this snapshot was produced with input overrides.
+  pub fn has_input_overrides(&self) -> bool {
+    !self.input_overrides.is_empty()
+  }
+
   /// Get the number of builds in this snapshot.
   pub fn build_count(&self) -> usize {
     self.manifest.builds.len()
@@ -274,6 +306,11 @@ mod tests {
         inputs: None,
         create_actions: vec![],
         outputs: None,
+        resources: None,
+        metadata: None,
+        check_actions: None,
+        prebuilt: None,
+        impure_env: None,
       },
     );
 
@@ -290,6 +327,21 @@ mod tests {
     assert_eq!(metadata.config_path, Some(PathBuf::from("/path/to/config.lua")));
   }
 
+  #[test]
+  fn snapshot_input_overrides_roundtrip() {
+    let plain = Snapshot::new("plain".to_string(), None, Manifest::default());
+    let json = serde_json::to_string(&plain).unwrap();
+    assert!(!json.contains("input_overrides"));
+    assert!(!serde_json::from_str::<Snapshot>(&json).unwrap().has_input_overrides());
+
+    let overridden = Snapshot::new("overridden".to_string(), None, Manifest::default())
+      .with_input_overrides(BTreeMap::from([("pkgs".to_string(), "path:../pkgs".to_string())]));
+    let json = serde_json::to_string(&overridden).unwrap();
+    let parsed: Snapshot = serde_json::from_str(&json).unwrap();
+    assert!(parsed.has_input_overrides());
+    assert_eq!(parsed, overridden);
+  }
+
   #[test]
   fn snapshot_index_add_maintains_order() {
     let mut index = SnapshotIndex::new();
@@ -0,0 +1,769 @@
+//! Store backups: copy the state of a machine to a backup directory and back.
+//!
+//! `sys store backup <dest>` saves what can't be rebuilt from the config:
+//! snapshots, bind state (including the files binds backed up before
+//! replacing them), the apply journal, execution history and recent build
+//! failures. Build outputs are left out, since applying the config again
+//! realizes them; with `include_builds_below`, builds smaller than the
+//! threshold are saved too, to spare a slow rebuild or a download that may
+//! have disappeared. The machine key stays on the machine.
+//!
+//! A backup directory holds every backup made to it:
+//!
+//! ```text
+//! <dest>/
+//! ├── objects/<ab>/<sha256>   # File contents, each stored once
+//! └── backups/<id>.json       # One listing per backup: paths, modes, hashes
+//! ```
+//!
+//! Backups are incremental: a file whose contents are already among the
+//! objects isn't copied again, so backing up on a schedule (e.g. with
+//! `sys agent install --backup <dest>`) only copies what changed.
+//!
+//! `sys store restore <archive>` writes a backup (the newest one of a backup
+//! directory, or the given `backups/<id>.json`) into the current store,
+//! checking each file against its hash. A store that already has a current
+//! snapshot is only overwritten with `force`.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, info};
+use walkdir::WalkDir;
+
+use crate::build::execute::is_build_complete;
+use crate::build::store::builds_dir;
+use crate::execute::failures::FAILED_BUILDS_FILENAME;
+use crate::execute::history::HISTORY_FILENAME;
+use crate::platform::immutable::{make_immutable, make_mutable};
+use crate::platform::paths::{snapshots_dir, store_dir};
+use crate::snapshot::{JOURNAL_FILENAME, SnapshotStore};
+use crate::util::fs::{copy_file, dir_size};
+use crate::util::hash::{DirHashError, hash_file};
+
+/// Current backup listing format version.
+const BACKUP_VERSION: u32 = 1;
+
+/// Directory of a backup directory holding file contents.
+const OBJECTS_DIR: &str = "objects";
+
+/// Directory of a backup directory holding the listings of its backups.
+const BACKUPS_DIR: &str = "backups";
+
+/// Files at the top of the store that are backed up.
+const STORE_FILES: &[&str] = &[HISTORY_FILENAME, FAILED_BUILDS_FILENAME, JOURNAL_FILENAME];
+
+#[derive(Debug, Error)]
+pub enum StoreBackupError {
+  #[error("failed to access {}: {source}", path.display())]
+  Io {
+    path: PathBuf,
+    #[source]
+    source: io::Error,
+  },
+
+  #[error("invalid backup listing {}: {source}", path.display())]
+  Json {
+    path: PathBuf,
+    #[source]
+    source: serde_json::Error,
+  },
+
+  #[error(transparent)]
+  Hash(#[from] DirHashError),
+
+  #[error("failed to walk {}: {source}", path.display())]
+  Walk {
+    path: PathBuf,
+    #[source]
+    source: walkdir::Error,
+  },
+
+  #[error("unsupported backup version {0} (expected {BACKUP_VERSION})")]
+  UnsupportedVersion(u32),
+
+  #[error("no backups found in {}", .0.display())]
+  NoBackups(PathBuf),
+
+  #[error("backup entry '{0}' is not a relative path")]
+  InvalidPath(String),
+
+  #[error("backup entry '{0}' is under a symlink")]
+  UnderSymlink(String),
+
+  #[error("backup object of '{path}' is corrupt: expected sha256 {expected}, found {actual}")]
+  Corrupt {
+    path: String,
+    expected: String,
+    actual: String,
+  },
+
+  #[error("the store already has a current snapshot ({0}); restore with --force to overwrite it")]
+  NotEmpty(String),
+
+  #[error("failed to read snapshots: {0}")]
+  Snapshots(String),
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> StoreBackupError + '_ {
+  move |source| StoreBackupError::Io {
+    path: path.to_path_buf(),
+    source,
+  }
+}
+
+/// Options for [`backup_store`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BackupOptions {
+  /// Also back up complete builds of the current platform smaller than this
+  /// many bytes.
+  pub include_builds_below: Option<u64>,
+}
+
+/// Options for [`restore_store`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RestoreOptions {
+  /// Restore even though the store has a current snapshot.
+  pub force: bool,
+}
+
+/// Which directory a backed up path is relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupRoot {
+  Store,
+  Snapshots,
+}
+
+/// What a backed up path is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EntryKind {
+  Dir,
+  File {
+    sha256: String,
+    size: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    executable: bool,
+  },
+  Symlink {
+    target: String,
+  },
+}
+
+/// One backed up path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupEntry {
+  pub root: BackupRoot,
+  /// Path relative to the root, with `/` separators.
+  pub path: String,
+  #[serde(flatten)]
+  pub kind: EntryKind,
+}
+
+/// The listing of one backup, `backups/<id>.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupListing {
+  pub version: u32,
+  pub id: String,
+  /// Unix timestamp (seconds) of the backup.
+  pub created_at: u64,
+  /// Hashes of the builds included.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub builds: Vec<String>,
+  pub entries: Vec<BackupEntry>,
+}
+
+/// What [`backup_store`] saved.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupReport {
+  pub id: String,
+  /// The listing written, `<dest>/backups/<id>.json`.
+  pub listing: PathBuf,
+  /// Files in the backup, and their total size.
+  pub files: usize,
+  pub bytes: u64,
+  /// Files whose contents weren't in the backup directory yet, and their size.
+  pub new_files: usize,
+  pub new_bytes: u64,
+  /// Builds included.
+  pub builds: usize,
+}
+
+/// What [`restore_store`] wrote.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreReport {
+  pub id: String,
+  pub files: usize,
+  pub bytes: u64,
+  pub builds: usize,
+  /// The current snapshot after restoring.
+  pub snapshot: Option<String>,
+}
+
+/// Back up the current store's snapshots and bind state to `dest`.
+pub fn backup_store(dest: &Path, options: &BackupOptions) -> Result<BackupReport, StoreBackupError> {
+  backup_dirs(&store_dir(), &snapshots_dir(), dest, options)
+}
+
+/// Restore the backup `archive` (a backup directory or a listing in it) into
+/// the current store.
+pub fn restore_store(archive: &Path, options: &RestoreOptions) -> Result<RestoreReport, StoreBackupError> {
+  let snapshots = snapshots_dir();
+  if !options.force
+    && let Some(current) = SnapshotStore::new(snapshots.clone())
+      .current_id()
+      .map_err(|e| StoreBackupError::Snapshots(e.to_string()))?
+  {
+    return Err(StoreBackupError::NotEmpty(current));
+  }
+
+  let mut report = restore_dirs(archive, &store_dir(), &snapshots)?;
+  report.snapshot = SnapshotStore::new(snapshots)
+    .current_id()
+    .map_err(|e| StoreBackupError::Snapshots(e.to_string()))?;
+  Ok(report)
+}
+
+fn backup_dirs(
+  store: &Path,
+  snapshots: &Path,
+  dest: &Path,
+  options: &BackupOptions,
+) -> Result<BackupReport, StoreBackupError> {
+  let mut entries = Vec::new();
+  collect(snapshots, BackupRoot::Snapshots, snapshots, &mut entries)?;
+
+  collect(store, BackupRoot::Store, &store.join("bind"), &mut entries)?;
+  for name in STORE_FILES {
+    let path = store.join(name);
+    if path.is_file() {
+      collect(store, BackupRoot::Store, &path, &mut entries)?;
+    }
+  }
+
+  let mut builds = Vec::new();
+  if let Some(threshold) = options.include_builds_below {
+    let dir = builds_dir(store);
+    let read = match fs::read_dir(&dir) {
+      Ok(read) => read.collect::<Result<Vec<_>, _>>().map_err(io_error(&dir))?,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+      Err(e) => return Err(io_error(&dir)(e)),
+    };
+    let mut paths: Vec<PathBuf> = read.into_iter().map(|entry| entry.path()).collect();
+    paths.sort();
+    for path in paths {
+      if path.is_dir() && is_build_complete(&path) && dir_size(&path) < threshold {
+        collect(store, BackupRoot::Store, &path, &mut entries)?;
+        builds.push(path.file_name().unwrap_or_default().to_string_lossy().to_string());
+      }
+    }
+  }
+
+  let objects = dest.join(OBJECTS_DIR);
+  let (mut files, mut bytes, mut new_files, mut new_bytes) = (0, 0, 0, 0);
+  for (entry, source) in &mut entries {
+    let Some(source) = source else { continue };
+    let hash = hash_file(source)?.0;
+    let size = fs::metadata(&*source).map_err(io_error(source))?.len();
+    files += 1;
+    bytes += size;
+
+    let object = object_path(&objects, &hash);
+    if !object.exists() {
+      write_object(source, &object)?;
+      new_files += 1;
+      new_bytes += size;
+    }
+    if let EntryKind::File { sha256, size: len, .. } = &mut entry.kind {
+      *sha256 = hash;
+      *len = size;
+    }
+  }
+
+  let created_at = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0);
+  let listings = dest.join(BACKUPS_DIR);
+  fs::create_dir_all(&listings).map_err(io_error(&listings))?;
+  let mut id = created_at.to_string();
+  let mut suffix = 1;
+  while listings.join(format!("{}.json", id)).exists() {
+    suffix += 1;
+    id = format!("{}-{}", created_at, suffix);
+  }
+
+  let listing = BackupListing {
+    version: BACKUP_VERSION,
+    id: id.clone(),
+    created_at,
+    builds: builds.clone(),
+    entries: entries.into_iter().map(|(entry, _)| entry).collect(),
+  };
+  let listing_path = listings.join(format!("{}.json", id));
+  let json = serde_json::to_string_pretty(&listing).map_err(|source| StoreBackupError::Json {
+    path: listing_path.clone(),
+    source,
+  })?;
+  // Written last and atomically, so an interrupted backup leaves no listing
+  let tmp = listing_path.with_extension("json.tmp");
+  fs::write(&tmp, json).map_err(io_error(&tmp))?;
+  fs::rename(&tmp, &listing_path).map_err(io_error(&listing_path))?;
+
+  info!(id = %id, files, new_files, new_bytes, "store backed up");
+  Ok(BackupReport {
+    id,
+    listing: listing_path,
+    files,
+    bytes,
+    new_files,
+    new_bytes,
+    builds: builds.len(),
+  })
+}
+
+/// Add `path` and everything under it, relative to `base`, to `entries`,
+/// along with the source of each file. Missing paths are skipped.
+fn collect(
+  base: &Path,
+  root: BackupRoot,
+  path: &Path,
+  entries: &mut Vec<(BackupEntry, Option<PathBuf>)>,
+) -> Result<(), StoreBackupError> {
+  if fs::symlink_metadata(path).is_err() {
+    return Ok(());
+  }
+  for entry in WalkDir::new(path).sort_by_file_name() {
+    let entry = entry.map_err(|source| StoreBackupError::Walk {
+      path: path.to_path_buf(),
+      source,
+    })?;
+    let Ok(rel) = entry.path().strip_prefix(base) else {
+      continue;
+    };
+    if rel.as_os_str().is_empty() {
+      continue;
+    }
+    let rel_str = rel
+      .components()
+      .map(|c| c.as_os_str().to_string_lossy())
+      .collect::<Vec<_>>()
+      .join("/");
+    // Leftovers of interrupted atomic writes
+    if rel_str.ends_with(".tmp") {
+      continue;
+    }
+
+    let file_type = entry.file_type();
+    let (kind, source) = if file_type.is_symlink() {
+      let target = fs::read_link(entry.path()).map_err(io_error(entry.path()))?;
+      (
+        EntryKind::Symlink {
+          target: target.to_string_lossy().to_string(),
+        },
+        None,
+      )
+    } else if file_type.is_dir() {
+      (EntryKind::Dir, None)
+    } else {
+      let metadata = fs::metadata(entry.path()).map_err(io_error(entry.path()))?;
+      (
+        EntryKind::File {
+          sha256: String::new(),
+          size: 0,
+          executable: is_executable(&metadata),
+        },
+        Some(entry.path().to_path_buf()),
+      )
+    };
+    debug!(path = %rel_str, "backing up");
+    entries.push((
+      BackupEntry {
+        root,
+        path: rel_str,
+        kind,
+      },
+      source,
+    ));
+  }
+  Ok(())
+}
+
+fn object_path(objects: &Path, hash: &str) -> PathBuf {
+  objects.join(&hash[..2.min(hash.len())]).join(hash)
+}
+
+/// Copy `source` to `object` through a temporary file.
+fn write_object(source: &Path, object: &Path) -> Result<(), StoreBackupError> {
+  let dir = object.parent().unwrap_or(object);
+  fs::create_dir_all(dir).map_err(io_error(dir))?;
+  let tmp = object.with_extension("tmp");
+  let _ = fs::remove_file(&tmp);
+  copy_file(source, &tmp).map_err(io_error(source))?;
+  set_writable(&tmp);
+  fs::rename(&tmp, object).map_err(io_error(object))
+}
+
+fn restore_dirs(archive: &Path, store: &Path, snapshots: &Path) -> Result<RestoreReport, StoreBackupError> {
+  let (listing_path, repo) = find_listing(archive)?;
+  let content = fs::read_to_string(&listing_path).map_err(io_error(&listing_path))?;
+  let listing: BackupListing = serde_json::from_str(&content).map_err(|source| StoreBackupError::Json {
+    path: listing_path.clone(),
+    source,
+  })?;
+  if listing.version > BACKUP_VERSION {
+    return Err(StoreBackupError::UnsupportedVersion(listing.version));
+  }
+  let objects = repo.join(OBJECTS_DIR);
+
+  // Builds are read-only in the store; unlock any being replaced
+  let build_paths = listing
+    .builds
+    .iter()
+    .map(|hash| match relative_path(hash)?.components().count() {
+      1 => Ok(builds_dir(store).join(hash)),
+      _ => Err(StoreBackupError::InvalidPath(hash.clone())),
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+  for path in &build_paths {
+    if path.exists() {
+      let _ = make_mutable(path);
+    }
+  }
+
+  let (mut files, mut bytes) = (0, 0);
+  for entry in &listing.entries {
+    let root = match entry.root {
+      BackupRoot::Store => store,
+      BackupRoot::Snapshots => snapshots,
+    };
+    let rel = relative_path(&entry.path)?;
+    // A listing could link a directory elsewhere and write through it
+    check_parents(root, &rel, &entry.path)?;
+    let target = root.join(rel);
+
+    match &entry.kind {
+      EntryKind::Dir => fs::create_dir_all(&target).map_err(io_error(&target))?,
+      EntryKind::File { sha256, executable, .. } => {
+        let object = object_path(&objects, sha256);
+        let actual = hash_file(&object)?.0;
+        if &actual != sha256 {
+          return Err(StoreBackupError::Corrupt {
+            path: entry.path.clone(),
+            expected: sha256.clone(),
+            actual,
+          });
+        }
+        if let Some(dir) = target.parent() {
+          fs::create_dir_all(dir).map_err(io_error(dir))?;
+        }
+        remove_existing(&target)?;
+        bytes += copy_file(&object, &target).map_err(io_error(&target))?;
+        set_mode(&target, *executable);
+        files += 1;
+      }
+      EntryKind::Symlink { target: link } => {
+        if let Some(dir) = target.parent() {
+          fs::create_dir_all(dir).map_err(io_error(dir))?;
+        }
+        remove_existing(&target)?;
+        symlink(Path::new(link), &target).map_err(io_error(&target))?;
+      }
+    }
+  }
+
+  for path in &build_paths {
+    let _ = make_immutable(path);
+  }
+
+  info!(id = %listing.id, files, bytes, "store restored");
+  Ok(RestoreReport {
+    id: listing.id,
+    files,
+    bytes,
+    builds: listing.builds.len(),
+    snapshot: None,
+  })
+}
+
+/// The listing `archive` names, and the backup directory it belongs to: the
+/// newest listing of a backup directory, or a listing file itself.
+fn find_listing(archive: &Path) -> Result<(PathBuf, PathBuf), StoreBackupError> {
+  if archive.is_file() {
+    let repo = archive
+      .parent()
+      .and_then(Path::parent)
+      .ok_or_else(|| StoreBackupError::NoBackups(archive.to_path_buf()))?;
+    return Ok((archive.to_path_buf(), repo.to_path_buf()));
+  }
+
+  let listings = archive.join(BACKUPS_DIR);
+  let read = match fs::read_dir(&listings) {
+    Ok(read) => read,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+      return Err(StoreBackupError::NoBackups(archive.to_path_buf()));
+    }
+    Err(e) => return Err(io_error(&listings)(e)),
+  };
+  let mut newest: Option<((u64, u64), PathBuf)> = None;
+  for entry in read {
+    let path = entry.map_err(io_error(&listings))?.path();
+    if path.extension().is_none_or(|ext| ext != "json") {
+      continue;
+    }
+    let Some(key) = path.file_stem().and_then(|stem| stem.to_str()).and_then(listing_order) else {
+      continue;
+    };
+    if newest.as_ref().is_none_or(|(newest, _)| key > *newest) {
+      newest = Some((key, path));
+    }
+  }
+  newest
+    .map(|(_, path)| (path, archive.to_path_buf()))
+    .ok_or_else(|| StoreBackupError::NoBackups(archive.to_path_buf()))
+}
+
+/// Order of a listing by its id, `<created_at>` or `<created_at>-<n>`.
+fn listing_order(id: &str) -> Option<(u64, u64)> {
+  match id.split_once('-') {
+    Some((secs, n)) => Some((secs.parse().ok()?, n.parse().ok()?)),
+    None => Some((id.parse().ok()?, 0)),
+  }
+}
+
+/// `path` of a listing as a relative path, refusing anything that could
+/// write outside the root.
+fn relative_path(path: &str) -> Result<PathBuf, StoreBackupError> {
+  let rel: PathBuf = path.split('/').collect();
+  if path.is_empty() || !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+    return Err(StoreBackupError::InvalidPath(path.to_string()));
+  }
+  Ok(rel)
+}
+
+/// Refuse `rel` when one of its parent directories under `root` is a symlink.
+fn check_parents(root: &Path, rel: &Path, entry: &str) -> Result<(), StoreBackupError> {
+  let mut dir = root.to_path_buf();
+  for component in rel.parent().into_iter().flat_map(Path::components) {
+    dir.push(component);
+    if fs::symlink_metadata(&dir).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+      return Err(StoreBackupError::UnderSymlink(entry.to_string()));
+    }
+  }
+  Ok(())
+}
+
+fn remove_existing(path: &Path) -> Result<(), StoreBackupError> {
+  match fs::symlink_metadata(path) {
+    Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path).map_err(io_error(path)),
+    Ok(_) => fs::remove_file(path).map_err(io_error(path)),
+    Err(_) => Ok(()),
+  }
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+  use std::os::unix::fs::PermissionsExt;
+  metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+  false
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, executable: bool) {
+  use std::os::unix::fs::PermissionsExt;
+  let mode = if executable { 0o755 } else { 0o644 };
+  let _ = fs::set_permissions(path, fs::Permissions::from_mode(mode));
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &Path, _executable: bool) {
+  set_writable(path);
+}
+
+/// Clear a read-only bit copied from a store file.
+#[allow(clippy::permissions_set_readonly_false)]
+fn set_writable(path: &Path) {
+  if let Ok(metadata) = fs::metadata(path) {
+    let mut permissions = metadata.permissions();
+    if permissions.readonly() {
+      permissions.set_readonly(false);
+      let _ = fs::set_permissions(path, permissions);
+    }
+  }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+  std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+  std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  fn write(path: &Path, content: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+  }
+
+  /// A store and snapshots directory with a snapshot and bind state.
+  fn machine(root: &Path) -> (PathBuf, PathBuf) {
+    let store = root.join("store");
+    let snapshots = root.join("snapshots");
+    write(&snapshots.join("index.json"), r#"{"current":"s1"}"#);
+    write(&snapshots.join("s1.json"), "{}");
+    write(&store.join("bind/abc/state.json"), r#"{"outputs":{}}"#);
+    write(&store.join(JOURNAL_FILENAME), "{}\n");
+    (store, snapshots)
+  }
+
+  #[test]
+  fn backups_are_incremental_and_restore_the_same_files() {
+    let temp = TempDir::new().unwrap();
+    let (store, snapshots) = machine(&temp.path().join("old"));
+    let dest = temp.path().join("backup");
+
+    let first = backup_dirs(&store, &snapshots, &dest, &BackupOptions::default()).unwrap();
+    assert_eq!(first.files, 4);
+    assert_eq!(first.new_files, 4);
+
+    // Only the changed file is copied again
+    write(&snapshots.join("s2.json"), "{\"id\":\"s2\"}");
+    let second = backup_dirs(&store, &snapshots, &dest, &BackupOptions::default()).unwrap();
+    assert_eq!(second.files, 5);
+    assert_eq!(second.new_files, 1);
+    assert_ne!(first.id, second.id);
+
+    let new_root = temp.path().join("new");
+    let (new_store, new_snapshots) = (new_root.join("store"), new_root.join("snapshots"));
+    let restored = restore_dirs(&dest, &new_store, &new_snapshots).unwrap();
+    assert_eq!(restored.id, second.id);
+    assert_eq!(restored.files, 5);
+    assert_eq!(
+      fs::read_to_string(new_store.join("bind/abc/state.json")).unwrap(),
+      r#"{"outputs":{}}"#
+    );
+    assert!(new_snapshots.join("s2.json").is_file());
+
+    // An older listing restores the state it saved
+    let older = restore_dirs(
+      &first.listing,
+      &temp.path().join("older/store"),
+      &temp.path().join("older/snap"),
+    );
+    assert_eq!(older.unwrap().files, 4);
+  }
+
+  #[test]
+  fn small_builds_are_included_on_request() {
+    let temp = TempDir::new().unwrap();
+    let (store, snapshots) = machine(temp.path());
+    let build = builds_dir(&store).join("small");
+    write(&build.join("bin/tool"), "#!/bin/sh\n");
+    write(&build.join(crate::build::execute::BUILD_COMPLETE_MARKER), "{}");
+    let big = builds_dir(&store).join("big");
+    write(&big.join("data"), &"x".repeat(4096));
+    write(&big.join(crate::build::execute::BUILD_COMPLETE_MARKER), "{}");
+    let dest = temp.path().join("backup");
+
+    let without = backup_dirs(&store, &snapshots, &dest, &BackupOptions::default()).unwrap();
+    assert_eq!(without.builds, 0);
+
+    let options = BackupOptions {
+      include_builds_below: Some(1024),
+    };
+    let with = backup_dirs(&store, &snapshots, &dest, &options).unwrap();
+    assert_eq!(with.builds, 1);
+    let listing: BackupListing = serde_json::from_str(&fs::read_to_string(&with.listing).unwrap()).unwrap();
+    assert_eq!(listing.builds, vec!["small".to_string()]);
+  }
+
+  #[test]
+  fn corrupt_objects_and_escaping_paths_fail_restore() {
+    let temp = TempDir::new().unwrap();
+    let (store, snapshots) = machine(temp.path());
+    let dest = temp.path().join("backup");
+    let report = backup_dirs(&store, &snapshots, &dest, &BackupOptions::default()).unwrap();
+
+    let listing: BackupListing = serde_json::from_str(&fs::read_to_string(&report.listing).unwrap()).unwrap();
+    let EntryKind::File { sha256, .. } = &listing.entries.iter().find(|e| e.path == "index.json").unwrap().kind else {
+      panic!("expected a file");
+    };
+    fs::write(object_path(&dest.join(OBJECTS_DIR), sha256), "tampered").unwrap();
+    let err = restore_dirs(&dest, &temp.path().join("a"), &temp.path().join("b")).unwrap_err();
+    assert!(matches!(err, StoreBackupError::Corrupt { ref path, .. } if path == "index.json"));
+
+    assert!(relative_path("../etc/passwd").is_err());
+    assert!(relative_path("/etc/passwd").is_err());
+    assert!(relative_path("bind/abc/state.json").is_ok());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn restore_never_writes_through_symlinks() {
+    let temp = TempDir::new().unwrap();
+    let (store, snapshots) = machine(temp.path());
+    let dest = temp.path().join("backup");
+    let report = backup_dirs(&store, &snapshots, &dest, &BackupOptions::default()).unwrap();
+
+    // A listing linking a store directory elsewhere, then writing into it
+    let outside = temp.path().join("outside");
+    fs::create_dir_all(&outside).unwrap();
+    let mut listing: BackupListing = serde_json::from_str(&fs::read_to_string(&report.listing).unwrap()).unwrap();
+    let file = listing
+      .entries
+      .iter()
+      .find(|e| e.path == "index.json")
+      .unwrap()
+      .kind
+      .clone();
+    listing.entries = vec![
+      BackupEntry {
+        root: BackupRoot::Store,
+        path: "bind/x".to_string(),
+        kind: EntryKind::Symlink {
+          target: outside.to_string_lossy().to_string(),
+        },
+      },
+      BackupEntry {
+        root: BackupRoot::Store,
+        path: "bind/x/passwd".to_string(),
+        kind: file,
+      },
+    ];
+    let evil = dest.join(BACKUPS_DIR).join("evil.json");
+    fs::write(&evil, serde_json::to_string(&listing).unwrap()).unwrap();
+
+    let err = restore_dirs(&evil, &temp.path().join("a"), &temp.path().join("b")).unwrap_err();
+    assert!(matches!(err, StoreBackupError::UnderSymlink(ref path) if path == "bind/x/passwd"));
+    assert!(!outside.join("passwd").exists());
+
+    listing.builds = vec!["../../etc".to_string()];
+    fs::write(&evil, serde_json::to_string(&listing).unwrap()).unwrap();
+    let err = restore_dirs(&evil, &temp.path().join("c"), &temp.path().join("d")).unwrap_err();
+    assert!(matches!(err, StoreBackupError::InvalidPath(_)));
+  }
+
+  #[test]
+  fn missing_backups_are_reported() {
+    let temp = TempDir::new().unwrap();
+    let err = restore_dirs(temp.path(), &temp.path().join("a"), &temp.path().join("b")).unwrap_err();
+    assert!(matches!(err, StoreBackupError::NoBackups(_)));
+    assert_eq!(listing_order("1700000000-2"), Some((1700000000, 2)));
+    assert!(listing_order("notes").is_none());
+  }
+}
@@ -0,0 +1,282 @@
+//! Store inspection: what takes up space in the store and who references it.
+//!
+//! Build directories are named by hash only, so ids and references come from
+//! the manifests of all snapshots. Builds are grouped by their id without a
+//! trailing version (`ripgrep-15.1.0` -> `ripgrep`). Only the current
+//! platform's builds are measured, along with legacy builds not migrated yet.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::{fs, io};
+
+use serde::Serialize;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::build::store::{builds_dir, legacy_builds};
+use crate::platform::paths::store_dir;
+use crate::snapshot::SnapshotStore;
+use crate::util::fs::dir_size;
+
+/// Group of builds not referenced by any snapshot.
+pub const UNREFERENCED_GROUP: &str = "(unreferenced)";
+
+/// Group of referenced builds without an id.
+pub const UNNAMED_GROUP: &str = "(no id)";
+
+#[derive(Debug, Error)]
+pub enum StoreInspectError {
+  #[error("failed to list snapshots: {0}")]
+  ListSnapshots(String),
+
+  #[error("failed to read store directory: {0}")]
+  ReadStore(#[from] io::Error),
+}
+
+/// Disk usage of one build directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BuildUsage {
+  /// Directory name in `<store>/build/<triple>`, the build hash.
+  pub hash: String,
+  /// Build id, from the snapshots referencing the build.
+  pub id: Option<String>,
+  /// Group the build is aggregated into.
+  pub group: String,
+  /// Total size of the build's files.
+  pub bytes: u64,
+  /// IDs of the snapshots referencing the build, oldest first.
+  pub snapshots: Vec<String>,
+}
+
+/// Disk usage of all builds of a group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GroupUsage {
+  pub group: String,
+  pub bytes: u64,
+  pub builds: usize,
+}
+
+/// Disk usage of the store's builds, largest first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoreUsage {
+  /// Size of all builds, including those below the threshold.
+  pub total_bytes: u64,
+  /// Number of builds, including those below the threshold.
+  pub total_builds: usize,
+  /// Groups at or above the threshold.
+  pub groups: Vec<GroupUsage>,
+  /// Builds at or above the threshold.
+  pub builds: Vec<BuildUsage>,
+}
+
+impl StoreUsage {
+  /// Aggregate `builds` into groups, keeping only entries of at least `threshold` bytes.
+  pub fn new(builds: Vec<BuildUsage>, threshold: u64) -> Self {
+    let mut groups: BTreeMap<&str, GroupUsage> = BTreeMap::new();
+    for build in &builds {
+      let group = groups.entry(build.group.as_str()).or_insert_with(|| GroupUsage {
+        group: build.group.clone(),
+        bytes: 0,
+        builds: 0,
+      });
+      group.bytes += build.bytes;
+      group.builds += 1;
+    }
+
+    let mut groups: Vec<_> = groups.into_values().filter(|g| g.bytes >= threshold).collect();
+    groups.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.group.cmp(&b.group)));
+
+    let total_bytes = builds.iter().map(|b| b.bytes).sum();
+    let total_builds = builds.len();
+    let mut builds: Vec<_> = builds.into_iter().filter(|b| b.bytes >= threshold).collect();
+    builds.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.hash.cmp(&b.hash)));
+
+    Self {
+      total_bytes,
+      total_builds,
+      groups,
+      builds,
+    }
+  }
+}
+
+/// Id and referencing snapshots of a build hash.
+#[derive(Debug, Default)]
+struct BuildRefs {
+  id: Option<String>,
+  snapshots: Vec<String>,
+}
+
+/// Measure the builds in the store, keeping entries of at least `threshold` bytes.
+pub fn store_usage(threshold: u64) -> Result<StoreUsage, StoreInspectError> {
+  let refs = collect_references(&SnapshotStore::default_store())?;
+  let store = store_dir();
+  let mut builds = scan_builds(&builds_dir(&store), &refs)?;
+  builds.extend(
+    legacy_builds(&store)?
+      .iter()
+      .filter_map(|path| build_usage(path, &refs)),
+  );
+  Ok(StoreUsage::new(builds, threshold))
+}
+
+/// Map every build hash (and its shorter forms) to its id and snapshots.
+fn collect_references(snapshot_store: &SnapshotStore) -> Result<BTreeMap<String, BuildRefs>, StoreInspectError> {
+  let mut refs: BTreeMap<String, BuildRefs> = BTreeMap::new();
+
+  let snapshots = snapshot_store
+    .list()
+    .map_err(|e| StoreInspectError::ListSnapshots(e.to_string()))?;
+
+  for meta in snapshots {
+    let snapshot = match snapshot_store.load_snapshot(&meta.id) {
+      Ok(snapshot) => snapshot,
+      Err(e) => {
+        warn!(id = %meta.id, error = %e, "skipping snapshot with incompatible format");
+        continue;
+      }
+    };
+
+    for (hash, build) in &snapshot.manifest.builds {
+      // Builds may live under a shorter hash from before the hash length was raised
+      for name in std::iter::once(hash.clone()).chain(hash.shorter_forms()) {
+        let entry = refs.entry(name.0).or_default();
+        if build.id.is_some() {
+          entry.id = build.id.clone();
+        }
+        if !entry.snapshots.contains(&meta.id) {
+          entry.snapshots.push(meta.id.clone());
+        }
+      }
+    }
+  }
+
+  Ok(refs)
+}
+
+/// Measure every build directory under `build_dir`.
+///
+/// Symlinks into a parent store are skipped; their space belongs to that store.
+fn scan_builds(build_dir: &Path, refs: &BTreeMap<String, BuildRefs>) -> Result<Vec<BuildUsage>, StoreInspectError> {
+  if !build_dir.exists() {
+    return Ok(Vec::new());
+  }
+
+  let mut builds = Vec::new();
+  for entry in fs::read_dir(build_dir)?.flatten() {
+    if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+      continue;
+    }
+    builds.extend(build_usage(&entry.path(), refs));
+  }
+
+  Ok(builds)
+}
+
+/// Usage of the build directory at `path`, attributed through `refs`.
+fn build_usage(path: &Path, refs: &BTreeMap<String, BuildRefs>) -> Option<BuildUsage> {
+  let hash = path.file_name()?.to_str()?.to_string();
+  let build_refs = refs.get(&hash);
+  let id = build_refs.and_then(|r| r.id.clone());
+  let group = match (build_refs, &id) {
+    (None, _) => UNREFERENCED_GROUP.to_string(),
+    (Some(_), None) => UNNAMED_GROUP.to_string(),
+    (Some(_), Some(id)) => id_group(id).to_string(),
+  };
+
+  Some(BuildUsage {
+    bytes: dir_size(path),
+    snapshots: build_refs.map(|r| r.snapshots.clone()).unwrap_or_default(),
+    hash,
+    id,
+    group,
+  })
+}
+
+/// The id without trailing version segments: `rust-analyzer-2024.1` -> `rust-analyzer`.
+pub fn id_group(id: &str) -> &str {
+  let mut end = 0;
+  for (i, segment) in id.split('-').enumerate() {
+    if i > 0 && segment.starts_with(|c: char| c.is_ascii_digit()) {
+      break;
+    }
+    end += segment.len() + usize::from(i > 0);
+  }
+  &id[..end]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn build_usage(hash: &str, group: &str, bytes: u64) -> BuildUsage {
+    BuildUsage {
+      hash: hash.to_string(),
+      id: None,
+      group: group.to_string(),
+      bytes,
+      snapshots: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn id_group_strips_versions() {
+    assert_eq!(id_group("ripgrep-15.1.0"), "ripgrep");
+    assert_eq!(id_group("rust-analyzer-2024.1-x86_64"), "rust-analyzer");
+    assert_eq!(id_group("file-hosts"), "file-hosts");
+    assert_eq!(id_group("7zip"), "7zip");
+  }
+
+  #[test]
+  fn usage_groups_sorts_and_filters() {
+    let usage = StoreUsage::new(
+      vec![
+        build_usage("a", "ripgrep", 100),
+        build_usage("b", "ripgrep", 300),
+        build_usage("c", "fd", 350),
+        build_usage("d", UNREFERENCED_GROUP, 5),
+      ],
+      50,
+    );
+
+    assert_eq!(usage.total_bytes, 755);
+    assert_eq!(usage.total_builds, 4);
+    let groups: Vec<_> = usage
+      .groups
+      .iter()
+      .map(|g| (g.group.as_str(), g.bytes, g.builds))
+      .collect();
+    assert_eq!(groups, [("ripgrep", 400, 2), ("fd", 350, 1)]);
+    let hashes: Vec<_> = usage.builds.iter().map(|b| b.hash.as_str()).collect();
+    assert_eq!(hashes, ["c", "b", "a"]);
+  }
+
+  #[test]
+  fn scan_builds_attributes_references() {
+    let temp = tempfile::TempDir::new().unwrap();
+    for (name, size) in [("aaa", 10), ("bbb", 20), ("ccc", 30)] {
+      fs::create_dir(temp.path().join(name)).unwrap();
+      fs::write(temp.path().join(name).join("file"), vec![0u8; size]).unwrap();
+    }
+
+    let mut refs = BTreeMap::new();
+    refs.insert(
+      "aaa".to_string(),
+      BuildRefs {
+        id: Some("ripgrep-15.1.0".to_string()),
+        snapshots: vec!["s1".to_string(), "s2".to_string()],
+      },
+    );
+    refs.insert("bbb".to_string(), BuildRefs::default());
+
+    let mut builds = scan_builds(temp.path(), &refs).unwrap();
+    builds.sort_by(|a, b| a.hash.cmp(&b.hash));
+
+    assert_eq!(builds[0].group, "ripgrep");
+    assert_eq!(builds[0].bytes, 10);
+    assert_eq!(builds[0].snapshots, ["s1", "s2"]);
+    assert_eq!(builds[1].group, UNNAMED_GROUP);
+    assert_eq!(builds[2].group, UNREFERENCED_GROUP);
+    assert!(builds[2].snapshots.is_empty());
+  }
+}
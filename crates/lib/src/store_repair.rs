@@ -0,0 +1,464 @@
+//! Store repair: recover or remove builds an interrupted apply left behind.
+//!
+//! A build is complete once its [completion marker](BUILD_COMPLETE_MARKER) is
+//! written; a crash before that leaves a directory `sys gc` simply deletes.
+//! `sys store repair` looks at each such directory first:
+//!
+//! - A prebuilt build whose files hash to the expected output hash is
+//!   recovered.
+//! - A build that only fetches URLs (without unpacking) is recovered when
+//!   every download is in place with its expected SHA256. With `resume`,
+//!   missing or partial downloads are fetched again and the build is
+//!   completed.
+//! - Everything else can't be verified (its outputs depend on commands that
+//!   didn't finish) and is removed; the next apply rebuilds it.
+//!
+//! Definitions come from the manifests of all snapshots, so builds of an
+//! apply that crashed before saving its snapshot are unknown and removed.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::action::Action;
+use crate::action::actions::download_cache::DownloadOptions;
+use crate::action::actions::download_cache::hash_file;
+use crate::action::actions::fetch_url::{execute_fetch_url, url_to_filename};
+use crate::build::BuildDef;
+use crate::build::execute::{BUILD_COMPLETE_MARKER, BUILD_HASH_EXCLUSIONS, complete_marker, read_build_marker};
+use crate::build::store::builds_dir;
+use crate::gc::roots::live_temp_roots;
+use crate::placeholder::{self, Segment};
+use crate::platform::immutable::{make_immutable, remove_store_path};
+use crate::platform::paths::store_dir;
+use crate::snapshot::SnapshotStore;
+use crate::util::fs::dir_size;
+use crate::util::hash::hash_directory;
+
+#[derive(Debug, Error)]
+pub enum RepairError {
+  #[error("failed to list snapshots: {0}")]
+  ListSnapshots(String),
+
+  #[error("failed to read store directory: {0}")]
+  ReadStore(#[from] io::Error),
+}
+
+/// Options for [`repair_store`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RepairOptions {
+  /// Finish builds whose actions can be resumed instead of removing them.
+  pub resume: bool,
+  /// Report what would happen without changing the store.
+  pub dry_run: bool,
+  /// Leave recovered builds writable instead of making them read-only.
+  pub writable_store: bool,
+}
+
+/// What repair did with an incomplete build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepairOutcome {
+  /// Its outputs were verified as they are and the build was completed.
+  Recovered,
+  /// Its missing outputs were fetched again and the build was completed.
+  Resumed,
+  /// It couldn't be verified and was removed.
+  Removed,
+}
+
+/// One incomplete build and what repair did with it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RepairedBuild {
+  /// Directory name in `<store>/build/<triple>`, the build hash.
+  pub hash: String,
+  /// Build id, from the snapshots declaring the build.
+  pub id: Option<String>,
+  pub path: PathBuf,
+  pub outcome: RepairOutcome,
+  /// Why the build was recovered or removed.
+  pub reason: String,
+  /// Size of the build directory when it was found.
+  pub bytes: u64,
+}
+
+/// Result of [`repair_store`].
+#[derive(Debug, Default, Serialize)]
+pub struct RepairReport {
+  /// Number of build directories looked at.
+  pub scanned: usize,
+  /// The incomplete builds, by hash.
+  pub builds: Vec<RepairedBuild>,
+  pub dry_run: bool,
+}
+
+impl RepairReport {
+  /// Number of builds with `outcome`.
+  pub fn count(&self, outcome: RepairOutcome) -> usize {
+    self.builds.iter().filter(|b| b.outcome == outcome).count()
+  }
+}
+
+/// Repair the incomplete builds in the store.
+///
+/// The caller holds the store lock exclusively, so no apply is writing builds.
+pub async fn repair_store(options: RepairOptions) -> Result<RepairReport, RepairError> {
+  let defs = collect_definitions(&SnapshotStore::default_store())?;
+  let build_dir = builds_dir(&store_dir());
+  let mut report = RepairReport {
+    dry_run: options.dry_run,
+    ..Default::default()
+  };
+  if !build_dir.exists() {
+    return Ok(report);
+  }
+
+  // Builds of applies still running have no marker yet
+  let in_progress = live_temp_roots();
+  let mut entries: Vec<_> = fs::read_dir(&build_dir)?.collect::<Result<_, _>>()?;
+  entries.sort_by_key(|entry| entry.file_name());
+
+  for entry in entries {
+    // Symlinked entries are builds of a parent store
+    if !entry.file_type()?.is_dir() {
+      continue;
+    }
+    let Some(hash) = entry.file_name().to_str().map(String::from) else {
+      continue;
+    };
+    report.scanned += 1;
+
+    let path = entry.path();
+    if in_progress.contains(&hash) || matches!(read_build_marker(&path), Ok(Some(_))) {
+      continue;
+    }
+
+    let def = defs.get(&hash);
+    let bytes = dir_size(&path);
+    let (outcome, reason) = repair_build(&path, def, options).await;
+    info!(hash = %hash, outcome = ?outcome, reason = %reason, "repaired incomplete build");
+    report.builds.push(RepairedBuild {
+      hash,
+      id: def.and_then(|def| def.id.clone()),
+      path,
+      outcome,
+      reason,
+      bytes,
+    });
+  }
+
+  Ok(report)
+}
+
+/// Map every build hash (and its shorter forms) to its definition.
+fn collect_definitions(snapshot_store: &SnapshotStore) -> Result<BTreeMap<String, BuildDef>, RepairError> {
+  let mut defs = BTreeMap::new();
+
+  let snapshots = snapshot_store
+    .list()
+    .map_err(|e| RepairError::ListSnapshots(e.to_string()))?;
+
+  for meta in snapshots {
+    let snapshot = match snapshot_store.load_snapshot(&meta.id) {
+      Ok(snapshot) => snapshot,
+      Err(e) => {
+        warn!(id = %meta.id, error = %e, "skipping snapshot with incompatible format");
+        continue;
+      }
+    };
+
+    for (hash, build) in snapshot.manifest.builds {
+      for name in std::iter::once(hash.clone()).chain(hash.shorter_forms()) {
+        defs.insert(name.0, build.clone());
+      }
+    }
+  }
+
+  Ok(defs)
+}
+
+/// Recover, resume or remove the incomplete build at `path`.
+async fn repair_build(path: &Path, def: Option<&BuildDef>, options: RepairOptions) -> (RepairOutcome, String) {
+  let (outcome, reason) = match def {
+    None => (RepairOutcome::Removed, "not declared by any snapshot".to_string()),
+    Some(def) => verify_build(path, def, options).await,
+  };
+
+  if options.dry_run {
+    return (outcome, reason);
+  }
+
+  if outcome == RepairOutcome::Removed {
+    if let Err(e) = remove_store_path(path) {
+      return (outcome, format!("{} (failed to remove: {})", reason, e));
+    }
+    return (outcome, reason);
+  }
+
+  // Imported prebuilt builds stay writable
+  let protect = !options.writable_store && def.is_some_and(|def| def.prebuilt.is_none());
+  if let Err(e) = complete_build(path, protect) {
+    let _ = remove_store_path(path);
+    return (RepairOutcome::Removed, format!("failed to complete: {}", e));
+  }
+  (outcome, reason)
+}
+
+/// Decide what to do with the build at `path`, resuming it if asked to.
+async fn verify_build(path: &Path, def: &BuildDef, options: RepairOptions) -> (RepairOutcome, String) {
+  if let Some(expected) = &def.prebuilt {
+    return match hash_directory(path, BUILD_HASH_EXCLUSIONS) {
+      Ok(actual) if actual.0 == *expected => (RepairOutcome::Recovered, "files match the prebuilt hash".to_string()),
+      Ok(_) => (
+        RepairOutcome::Removed,
+        "files don't match the prebuilt hash".to_string(),
+      ),
+      Err(e) => (RepairOutcome::Removed, format!("failed to hash files: {}", e)),
+    };
+  }
+
+  let Some(fetches) = fetch_only(def) else {
+    return (
+      RepairOutcome::Removed,
+      "outputs can't be verified; the next apply rebuilds it".to_string(),
+    );
+  };
+
+  let pending = pending_fetches(path, &fetches).await;
+  if pending.is_empty() {
+    return (
+      RepairOutcome::Recovered,
+      format!("all {} download(s) verified", fetches.len()),
+    );
+  }
+  if !options.resume {
+    return (
+      RepairOutcome::Removed,
+      format!(
+        "{} of {} download(s) missing or partial (--resume fetches them)",
+        pending.len(),
+        fetches.len()
+      ),
+    );
+  }
+  if options.dry_run {
+    return (
+      RepairOutcome::Resumed,
+      format!("would fetch {} of {} download(s)", pending.len(), fetches.len()),
+    );
+  }
+
+  for (url, sha256) in &pending {
+    debug!(url = %url, "resuming download");
+    if let Err(e) = execute_fetch_url(url, sha256, path, &DownloadOptions::default()).await {
+      return (RepairOutcome::Removed, format!("failed to resume {}: {}", url, e));
+    }
+  }
+  if let Err(e) = remove_strays(path, &fetches) {
+    return (RepairOutcome::Removed, format!("failed to clean up: {}", e));
+  }
+  (
+    RepairOutcome::Resumed,
+    format!("fetched {} of {} download(s)", pending.len(), fetches.len()),
+  )
+}
+
+/// The URLs and hashes of a build that only fetches literal URLs, or `None`
+/// for any other build.
+fn fetch_only(def: &BuildDef) -> Option<Vec<(String, String)>> {
+  if def.create_actions.is_empty() || def.check_actions.as_ref().is_some_and(|checks| !checks.is_empty()) {
+    return None;
+  }
+  def
+    .create_actions
+    .iter()
+    .map(|action| match action {
+      Action::FetchUrl {
+        url,
+        sha256,
+        unpack: false,
+      } if is_literal(url) && is_literal(sha256) => Some((url.clone(), sha256.clone())),
+      _ => None,
+    })
+    .collect()
+}
+
+fn is_literal(value: &str) -> bool {
+  placeholder::parse(value).is_ok_and(|segments| segments.iter().all(|s| matches!(s, Segment::Literal(_))))
+}
+
+/// The fetches whose download is missing or doesn't match its hash. A build
+/// with files of its own is treated as having all fetches pending, since
+/// only a clean download directory hashes like a fresh build.
+async fn pending_fetches(path: &Path, fetches: &[(String, String)]) -> Vec<(String, String)> {
+  let mut pending = Vec::new();
+  for (url, sha256) in fetches {
+    let file = path.join("downloads").join(url_to_filename(url));
+    match hash_file(&file).await {
+      Ok(actual) if actual == *sha256 => {}
+      _ => pending.push((url.clone(), sha256.clone())),
+    }
+  }
+  if pending.is_empty() && !strays(path, fetches).is_empty() {
+    return fetches.to_vec();
+  }
+  pending
+}
+
+/// Files in the build directory no fetch writes.
+fn strays(path: &Path, fetches: &[(String, String)]) -> Vec<PathBuf> {
+  let expected: Vec<String> = fetches.iter().map(|(url, _)| url_to_filename(url)).collect();
+  let mut strays = Vec::new();
+  for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+    let name = entry.file_name();
+    if name == "downloads" {
+      for download in fs::read_dir(entry.path()).into_iter().flatten().flatten() {
+        if !expected.iter().any(|e| download.file_name() == e.as_str()) {
+          strays.push(download.path());
+        }
+      }
+    } else if !BUILD_HASH_EXCLUSIONS.iter().any(|e| *e == name) {
+      strays.push(entry.path());
+    }
+  }
+  strays
+}
+
+fn remove_strays(path: &Path, fetches: &[(String, String)]) -> io::Result<()> {
+  for stray in strays(path, fetches) {
+    if stray.is_dir() {
+      fs::remove_dir_all(&stray)?;
+    } else {
+      fs::remove_file(&stray)?;
+    }
+  }
+  Ok(())
+}
+
+/// Write the completion marker of a verified build, making it read-only if `protect`.
+fn complete_build(path: &Path, protect: bool) -> Result<(), String> {
+  let marker = complete_marker(path).map_err(|e| e.to_string())?;
+  let content = serde_json::to_string(&marker).expect("failed to serialize marker");
+  fs::write(path.join(BUILD_COMPLETE_MARKER), format!("{}\n", content)).map_err(|e| e.to_string())?;
+  if protect && let Err(e) = make_immutable(path) {
+    warn!(path = ?path, error = %e, "failed to make build output read-only");
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::build::execute::is_build_complete;
+  use sha2::{Digest, Sha256};
+  use tempfile::TempDir;
+
+  fn fetch_build(urls: &[(&str, &[u8])]) -> BuildDef {
+    BuildDef {
+      id: Some("src".to_string()),
+      inputs: None,
+      outputs: None,
+      create_actions: urls
+        .iter()
+        .map(|(url, content)| Action::FetchUrl {
+          url: url.to_string(),
+          sha256: hex::encode(Sha256::digest(content)),
+          unpack: false,
+        })
+        .collect(),
+      resources: None,
+      metadata: None,
+      check_actions: None,
+      prebuilt: None,
+      impure_env: None,
+    }
+  }
+
+  const OPTIONS: RepairOptions = RepairOptions {
+    resume: false,
+    dry_run: false,
+    writable_store: true,
+  };
+
+  #[tokio::test]
+  async fn fetch_build_with_verified_downloads_is_recovered() {
+    let temp = TempDir::new().unwrap();
+    let def = fetch_build(&[("https://example.com/a.tar.gz", b"archive")]);
+    fs::create_dir_all(temp.path().join("downloads")).unwrap();
+    fs::write(temp.path().join("downloads").join("a.tar.gz"), b"archive").unwrap();
+
+    let (outcome, reason) = repair_build(temp.path(), Some(&def), OPTIONS).await;
+    assert_eq!(outcome, RepairOutcome::Recovered, "{}", reason);
+    assert!(is_build_complete(temp.path()));
+  }
+
+  #[tokio::test]
+  async fn partial_download_is_removed_without_resume() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join("build");
+    let def = fetch_build(&[("https://example.com/a.tar.gz", b"archive")]);
+    fs::create_dir_all(path.join("downloads")).unwrap();
+    fs::write(path.join("downloads").join("a.tar.gz"), b"arch").unwrap();
+
+    let dry_run = RepairOptions {
+      dry_run: true,
+      resume: true,
+      ..OPTIONS
+    };
+    let (outcome, _) = repair_build(&path, Some(&def), dry_run).await;
+    assert_eq!(outcome, RepairOutcome::Resumed);
+    assert!(path.exists());
+
+    let (outcome, reason) = repair_build(&path, Some(&def), OPTIONS).await;
+    assert_eq!(outcome, RepairOutcome::Removed);
+    assert!(reason.contains("--resume"), "{}", reason);
+    assert!(!path.exists());
+  }
+
+  #[tokio::test]
+  async fn stray_files_make_downloads_pending() {
+    let temp = TempDir::new().unwrap();
+    let fetches = [("https://example.com/a".to_string(), hex::encode(Sha256::digest(b"a")))];
+    fs::create_dir_all(temp.path().join("downloads")).unwrap();
+    fs::create_dir_all(temp.path().join("tmp")).unwrap();
+    fs::write(temp.path().join("downloads").join("a"), b"a").unwrap();
+    assert!(pending_fetches(temp.path(), &fetches).await.is_empty());
+
+    fs::write(temp.path().join("downloads").join("a.part"), b"").unwrap();
+    assert_eq!(pending_fetches(temp.path(), &fetches).await.len(), 1);
+    remove_strays(temp.path(), &fetches).unwrap();
+    assert!(pending_fetches(temp.path(), &fetches).await.is_empty());
+  }
+
+  #[tokio::test]
+  async fn prebuilt_and_unverifiable_builds() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join("build");
+    fs::create_dir_all(path.join("bin")).unwrap();
+    fs::write(path.join("bin").join("tool"), b"binary").unwrap();
+    let output_hash = hash_directory(&path, BUILD_HASH_EXCLUSIONS).unwrap();
+
+    let prebuilt = BuildDef::prebuilt("tool", &output_hash.0);
+    let (outcome, _) = repair_build(&path, Some(&prebuilt), OPTIONS).await;
+    assert_eq!(outcome, RepairOutcome::Recovered);
+    fs::remove_file(path.join(BUILD_COMPLETE_MARKER)).unwrap();
+
+    // A URL resolved at build time can't be checked
+    let computed = fetch_build(&[("https://example.com/$${{action:0}}", b"")]);
+    let (outcome, _) = repair_build(&path, Some(&computed), OPTIONS).await;
+    assert_eq!(outcome, RepairOutcome::Removed);
+    assert!(!path.exists());
+  }
+
+  #[tokio::test]
+  async fn unknown_builds_are_removed() {
+    let temp = TempDir::new().unwrap();
+    let (outcome, reason) = repair_build(temp.path(), None, OPTIONS).await;
+    assert_eq!(outcome, RepairOutcome::Removed);
+    assert_eq!(reason, "not declared by any snapshot");
+  }
+}
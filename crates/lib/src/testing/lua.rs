@@ -0,0 +1,336 @@
+//! The `sys.testing` table available to spec files.
+//!
+//! - `sys.testing.inputs`: the config's resolved inputs table
+//! - `sys.testing.setup()`: run the config's root `setup(inputs)`
+//! - `sys.testing.builds()` / `sys.testing.binds()`: refs to everything recorded so far
+//! - `sys.testing.expect_build{}` / `sys.testing.expect_bind{}`: assert that a
+//!   matching build/bind was recorded and return its ref
+//!
+//! Expectations match on any combination of:
+//!
+//! | Field          | Matches                                                      |
+//! | -------------- | ------------------------------------------------------------ |
+//! | `id`           | Exact id                                                     |
+//! | `inputs`       | Subset of the resolved inputs (nested tables compare by key) |
+//! | `outputs`      | Output names that must exist                                 |
+//! | `exec`         | Substrings of some `create` exec command line                |
+//! | `fetch`        | Substrings of some fetched URL (builds only)                 |
+//! | `destroy_exec` | Substrings of some `destroy` exec command line (binds only)  |
+//! | `backup`       | Paths listed in the bind's `backup` (binds only)             |
+//! | `count`        | Exact number of matches (default: at least one)              |
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use mlua::prelude::*;
+
+use crate::action::Action;
+use crate::bind::lua::{bind_hash_to_lua, bind_inputs_ref_to_lua};
+use crate::build::lua::{build_hash_to_lua, build_inputs_def_to_lua};
+use crate::manifest::Manifest;
+use crate::platform::paths::expand_path;
+use crate::util::hash::ObjectHash;
+
+/// Register `sys.testing` on the `sys` global.
+pub fn register_testing(
+  lua: &Lua,
+  manifest: Rc<RefCell<Manifest>>,
+  setup: LuaFunction,
+  inputs: LuaTable,
+) -> LuaResult<()> {
+  let testing = lua.create_table()?;
+  testing.set("inputs", inputs.clone())?;
+
+  // sys.testing.setup() - run the config's setup(inputs)
+  testing.set(
+    "setup",
+    lua.create_function(move |_, ()| setup.call::<()>(inputs.clone()))?,
+  )?;
+
+  let m = manifest.clone();
+  testing.set(
+    "builds",
+    lua.create_function(move |lua, ()| {
+      let manifest = m.borrow();
+      let refs = lua.create_table()?;
+      for hash in manifest.builds.keys() {
+        refs.push(build_hash_to_lua(lua, hash, &manifest)?)?;
+      }
+      Ok(refs)
+    })?,
+  )?;
+
+  let m = manifest.clone();
+  testing.set(
+    "binds",
+    lua.create_function(move |lua, ()| {
+      let manifest = m.borrow();
+      let refs = lua.create_table()?;
+      for hash in manifest.bindings.keys() {
+        refs.push(bind_hash_to_lua(lua, hash, &manifest)?)?;
+      }
+      Ok(refs)
+    })?,
+  )?;
+
+  let m = manifest.clone();
+  testing.set(
+    "expect_build",
+    lua.create_function(move |lua, table: LuaTable| {
+      let expected = Expectation::from_table(&table)?;
+      let manifest = m.borrow();
+      let mut matched = Vec::new();
+      for (hash, def) in &manifest.builds {
+        let actual_inputs = match &def.inputs {
+          Some(inputs) => build_inputs_def_to_lua(lua, inputs, &manifest)?,
+          None => LuaValue::Nil,
+        };
+        let matches = expected.id.as_ref().is_none_or(|id| def.id.as_ref() == Some(id))
+          && has_outputs(&expected.outputs, def.outputs.as_ref().map(|o| o.keys()))
+          && all_found(&expected.exec, exec_lines(&def.create_actions))
+          && all_found(&expected.fetch, fetch_urls(&def.create_actions))
+          && expected.inputs_match(&actual_inputs)?;
+        if matches {
+          matched.push(hash);
+        }
+      }
+
+      let recorded = manifest
+        .builds
+        .iter()
+        .map(|(hash, def)| describe(def.id.as_deref(), hash))
+        .collect();
+      expected.check("expect_build", &table, matched.len(), recorded)?;
+      match matched.first() {
+        Some(hash) => build_hash_to_lua(lua, hash, &manifest),
+        None => Ok(LuaValue::Nil),
+      }
+    })?,
+  )?;
+
+  let m = manifest;
+  testing.set(
+    "expect_bind",
+    lua.create_function(move |lua, table: LuaTable| {
+      let expected = Expectation::from_table(&table)?;
+      let manifest = m.borrow();
+      let mut matched = Vec::new();
+      for (hash, def) in &manifest.bindings {
+        let actual_inputs = match &def.inputs {
+          Some(inputs) => bind_inputs_ref_to_lua(lua, inputs, &manifest)?,
+          None => LuaValue::Nil,
+        };
+        let backup_paths = def.backup.iter().flat_map(|b| b.paths.iter().cloned());
+        let matches = expected.id.as_ref().is_none_or(|id| def.id.as_ref() == Some(id))
+          && has_outputs(&expected.outputs, def.outputs.as_ref().map(|o| o.keys()))
+          && all_found(&expected.exec, exec_lines(&def.create_actions))
+          && all_found(&expected.destroy_exec, exec_lines(&def.destroy_actions))
+          && has_backups(&expected.backup, backup_paths)
+          && expected.inputs_match(&actual_inputs)?;
+        if matches {
+          matched.push(hash);
+        }
+      }
+
+      let recorded = manifest
+        .bindings
+        .iter()
+        .map(|(hash, def)| describe(def.id.as_deref(), hash))
+        .collect();
+      expected.check("expect_bind", &table, matched.len(), recorded)?;
+      match matched.first() {
+        Some(hash) => bind_hash_to_lua(lua, hash, &manifest),
+        None => Ok(LuaValue::Nil),
+      }
+    })?,
+  )?;
+
+  let sys: LuaTable = lua.globals().get("sys")?;
+  sys.set("testing", testing)?;
+  Ok(())
+}
+
+/// Criteria parsed from an `expect_build{}`/`expect_bind{}` table.
+struct Expectation {
+  id: Option<String>,
+  inputs: LuaValue,
+  outputs: Vec<String>,
+  exec: Vec<String>,
+  fetch: Vec<String>,
+  destroy_exec: Vec<String>,
+  backup: Vec<String>,
+  count: Option<usize>,
+}
+
+impl Expectation {
+  fn from_table(table: &LuaTable) -> LuaResult<Self> {
+    Ok(Self {
+      id: table.get("id")?,
+      inputs: table.get("inputs")?,
+      outputs: string_list(table, "outputs")?,
+      exec: string_list(table, "exec")?,
+      fetch: string_list(table, "fetch")?,
+      destroy_exec: string_list(table, "destroy_exec")?,
+      backup: string_list(table, "backup")?,
+      count: table.get("count")?,
+    })
+  }
+
+  fn inputs_match(&self, actual: &LuaValue) -> LuaResult<bool> {
+    match &self.inputs {
+      LuaValue::Nil => Ok(true),
+      expected => is_subset(expected, actual),
+    }
+  }
+
+  /// Fail with a readable message unless `matched` satisfies `count`.
+  fn check(&self, name: &str, table: &LuaTable, matched: usize, recorded: Vec<String>) -> LuaResult<()> {
+    let ok = match self.count {
+      Some(count) => matched == count,
+      None => matched > 0,
+    };
+    if ok {
+      return Ok(());
+    }
+
+    let wanted = match self.count {
+      Some(count) => format!("{} match(es)", count),
+      None => "a match".to_string(),
+    };
+    let recorded = if recorded.is_empty() {
+      "nothing".to_string()
+    } else {
+      recorded.join(", ")
+    };
+    Err(LuaError::external(format!(
+      "{}: expected {} for {}, found {} (recorded: {})",
+      name,
+      wanted,
+      format_lua(&LuaValue::Table(table.clone()))?,
+      matched,
+      recorded
+    )))
+  }
+}
+
+/// Read a field that may be a single string or a list of strings.
+fn string_list(table: &LuaTable, key: &str) -> LuaResult<Vec<String>> {
+  match table.get::<LuaValue>(key)? {
+    LuaValue::Nil => Ok(Vec::new()),
+    LuaValue::String(s) => Ok(vec![s.to_str()?.to_string()]),
+    LuaValue::Table(t) => t.sequence_values::<String>().collect(),
+    other => Err(LuaError::external(format!(
+      "'{}' must be a string or a list of strings, got {}",
+      key,
+      other.type_name()
+    ))),
+  }
+}
+
+fn has_outputs<'a>(expected: &[String], actual: Option<impl Iterator<Item = &'a String>>) -> bool {
+  let actual: Vec<_> = actual.map(|keys| keys.collect()).unwrap_or_default();
+  expected.iter().all(|name| actual.contains(&name))
+}
+
+fn has_backups(expected: &[String], actual: impl Iterator<Item = String>) -> bool {
+  let actual: Vec<_> = actual.collect();
+  expected
+    .iter()
+    .all(|path| actual.contains(&expand_path(path).to_string_lossy().into_owned()))
+}
+
+/// Returns true if every pattern is a substring of at least one candidate.
+fn all_found(patterns: &[String], candidates: Vec<String>) -> bool {
+  patterns
+    .iter()
+    .all(|p| candidates.iter().any(|c| c.contains(p.as_str())))
+}
+
+/// Command lines of the exec actions, as `bin arg1 arg2 ...`.
+fn exec_lines(actions: &[Action]) -> Vec<String> {
+  actions
+    .iter()
+    .filter_map(|action| match action {
+      Action::Exec(opts) => {
+        let mut line = opts.bin.clone();
+        for arg in opts.args.iter().flatten() {
+          line.push(' ');
+          line.push_str(arg);
+        }
+        Some(line)
+      }
+      Action::FetchUrl { .. }
+      | Action::ConfigSection(_)
+      | Action::Firewall(_)
+      | Action::File(_)
+      | Action::FileBlock(_)
+      | Action::Font(_) => None,
+    })
+    .collect()
+}
+
+fn fetch_urls(actions: &[Action]) -> Vec<String> {
+  actions
+    .iter()
+    .filter_map(|action| match action {
+      Action::FetchUrl { url, .. } => Some(url.clone()),
+      Action::Exec(_)
+      | Action::ConfigSection(_)
+      | Action::Firewall(_)
+      | Action::File(_)
+      | Action::FileBlock(_)
+      | Action::Font(_) => None,
+    })
+    .collect()
+}
+
+/// Returns true if `expected` is contained in `actual`.
+///
+/// Tables match when every key in `expected` matches the same key in
+/// `actual`; other values compare by value, with integers and floats equal
+/// when numerically equal.
+fn is_subset(expected: &LuaValue, actual: &LuaValue) -> LuaResult<bool> {
+  match (expected, actual) {
+    (LuaValue::Table(expected), LuaValue::Table(actual)) => {
+      for pair in expected.pairs::<LuaValue, LuaValue>() {
+        let (key, value) = pair?;
+        if !is_subset(&value, &actual.raw_get::<LuaValue>(key)?)? {
+          return Ok(false);
+        }
+      }
+      Ok(true)
+    }
+    (LuaValue::Integer(a), LuaValue::Number(b)) | (LuaValue::Number(b), LuaValue::Integer(a)) => Ok(*a as f64 == *b),
+    (LuaValue::String(a), LuaValue::String(b)) => Ok(a.as_bytes() == b.as_bytes()),
+    (a, b) => Ok(a == b),
+  }
+}
+
+/// Render a Lua value for error messages.
+fn format_lua(value: &LuaValue) -> LuaResult<String> {
+  Ok(match value {
+    LuaValue::Table(table) => {
+      let mut fields = Vec::new();
+      for pair in table.pairs::<LuaValue, LuaValue>() {
+        let (key, value) = pair?;
+        let key = match key {
+          LuaValue::String(s) => s.to_str()?.to_string(),
+          other => format!("[{}]", format_lua(&other)?),
+        };
+        fields.push(format!("{} = {}", key, format_lua(&value)?));
+      }
+      fields.sort();
+      format!("{{ {} }}", fields.join(", "))
+    }
+    LuaValue::String(s) => format!("{:?}", s.to_str()?.to_string()),
+    LuaValue::Nil => "nil".to_string(),
+    other => other.to_string()?,
+  })
+}
+
+fn describe(id: Option<&str>, hash: &ObjectHash) -> String {
+  match id {
+    Some(id) => format!("{} ({})", id, hash.0),
+    None => hash.0.clone(),
+  }
+}
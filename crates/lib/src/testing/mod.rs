@@ -0,0 +1,459 @@
+//! Spec runner for `sys test`.
+//!
+//! Module and config authors write `*_spec.lua` files next to their code. Each
+//! spec runs in a fresh syslua runtime with the config's settings and inputs
+//! loaded, so `require` resolves modules exactly as it does during `setup`.
+//! Nothing is executed: `sys.build{}` and `sys.bind{}` only record actions
+//! into the runtime's manifest, and the [`sys.testing`](lua) helpers assert
+//! against what was recorded.
+//!
+//! A spec either makes its assertions at the top level, or returns a table of
+//! named cases:
+//!
+//! ```lua
+//! local ripgrep = require('pkgs.ripgrep')
+//!
+//! return {
+//!   ['builds the requested version'] = function()
+//!     ripgrep.setup({ version = '14.1.0' })
+//!     sys.testing.expect_build({ id = 'ripgrep-14.1.0', fetch = 'ripgrep-14.1.0' })
+//!   end,
+//! }
+//! ```
+//!
+//! Every case starts from the manifest left behind by the spec's top level,
+//! so cases don't see each other's builds and binds.
+//!
+//! # Modules
+//!
+//! - [`lua`]: The `sys.testing` table available to specs
+
+pub mod lua;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+use walkdir::WalkDir;
+
+use crate::eval::{EvalError, EvalOptions, InputResolution, build_package_path, prepare_config, set_package_path};
+use crate::inputs::ResolvedInputs;
+use crate::lua::runtime;
+use crate::manifest::Manifest;
+
+/// File name suffix that marks a Lua file as a spec.
+pub const SPEC_SUFFIX: &str = "_spec.lua";
+
+/// Errors that stop a test run before its specs can report results.
+#[derive(Debug, Error)]
+pub enum TestError {
+  #[error("config not found: {0}")]
+  ConfigNotFound(PathBuf),
+
+  /// The config itself failed to load (settings, inputs, input setups).
+  #[error("failed to load config: {0}")]
+  Config(#[from] EvalError),
+}
+
+impl From<LuaError> for TestError {
+  fn from(err: LuaError) -> Self {
+    TestError::Config(err.into())
+  }
+}
+
+/// Options for [`run_tests`].
+#[derive(Debug, Clone, Default)]
+pub struct TestOptions {
+  /// Only run cases whose full name contains this string.
+  pub filter: Option<String>,
+  /// Replacement URLs for root inputs, as for evaluation.
+  pub input_overrides: BTreeMap<String, String>,
+}
+
+/// Outcome of a single spec case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestCaseResult {
+  /// Spec file, relative to the config directory when possible.
+  pub file: String,
+  /// Case name, or `None` for a spec that asserts at its top level.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  /// Error message if the case failed.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub failure: Option<String>,
+}
+
+impl TestCaseResult {
+  /// `file > name`, used for display and filtering.
+  pub fn full_name(&self) -> String {
+    match &self.name {
+      Some(name) => format!("{} > {}", self.file, name),
+      None => self.file.clone(),
+    }
+  }
+
+  pub fn passed(&self) -> bool {
+    self.failure.is_none()
+  }
+}
+
+/// Results of a test run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TestReport {
+  /// Number of spec files discovered.
+  pub specs: usize,
+  /// Results for every case that ran, in discovery order.
+  pub cases: Vec<TestCaseResult>,
+}
+
+impl TestReport {
+  pub fn passed(&self) -> usize {
+    self.cases.iter().filter(|c| c.passed()).count()
+  }
+
+  pub fn failed(&self) -> usize {
+    self.cases.len() - self.passed()
+  }
+
+  /// Returns true if no case failed.
+  pub fn is_success(&self) -> bool {
+    self.failed() == 0
+  }
+}
+
+/// Discover and run every spec for the config at `config`.
+///
+/// Specs are looked up under the config directory and under each resolved
+/// input. Inputs are resolved once, without writing the lock file or
+/// `.luarc.json`, and reused for every spec. Failing cases are reported in
+/// the [`TestReport`]; an `Err` means the config could not be loaded at all.
+pub fn run_tests(config: &Path, options: &TestOptions) -> Result<TestReport, TestError> {
+  if !config.exists() {
+    return Err(TestError::ConfigNotFound(config.to_path_buf()));
+  }
+  let config_dir = config.parent().unwrap_or(Path::new("."));
+  let eval_options = EvalOptions {
+    impure: false,
+    input_overrides: options.input_overrides.clone(),
+    ..Default::default()
+  };
+
+  // Load the config once to find the inputs that may contain specs
+  let resolved = {
+    let lua = runtime::create_runtime(Rc::new(RefCell::new(Manifest::default())), false)?;
+    prepare_config(&lua, config, &eval_options, InputResolution::ReadOnly)?.resolved
+  };
+  let specs = discover_specs(config_dir, resolved.as_ref());
+  debug!(count = specs.len(), "discovered specs");
+
+  let mut report = TestReport {
+    specs: specs.len(),
+    cases: Vec::new(),
+  };
+  for spec in &specs {
+    run_spec(
+      config,
+      spec,
+      &eval_options,
+      resolved.as_ref(),
+      options.filter.as_deref(),
+      &mut report,
+    )?;
+  }
+  Ok(report)
+}
+
+/// Run one spec file in a fresh runtime, appending its cases to `report`.
+fn run_spec(
+  config: &Path,
+  spec: &Path,
+  eval_options: &EvalOptions,
+  resolved: Option<&ResolvedInputs>,
+  filter: Option<&str>,
+  report: &mut TestReport,
+) -> Result<(), TestError> {
+  let config_dir = config.parent().unwrap_or(Path::new("."));
+  let file = spec
+    .strip_prefix(config_dir)
+    .unwrap_or(spec)
+    .to_string_lossy()
+    .replace('\\', "/");
+  let selected = |case: &TestCaseResult| filter.is_none_or(|f| case.full_name().contains(f));
+
+  let manifest = Rc::new(RefCell::new(Manifest::default()));
+  let lua = runtime::create_runtime(manifest.clone(), false)?;
+  let prepared = prepare_config(&lua, config, eval_options, InputResolution::Reuse(resolved))?;
+  if prepared.resolved.is_none() {
+    // Inputs set package.path; without any, still expose the config's lua/
+    set_package_path(&lua, &build_package_path(config_dir, &ResolvedInputs::new()))?;
+  }
+  lua::register_testing(&lua, manifest.clone(), prepared.setup, prepared.inputs)?;
+
+  debug!(spec = %spec.display(), "running spec");
+  let cases = match runtime::load_file(&lua, spec) {
+    Ok(LuaValue::Table(cases)) => cases,
+    Ok(_) => {
+      let case = TestCaseResult {
+        file,
+        name: None,
+        failure: None,
+      };
+      if selected(&case) {
+        report.cases.push(case);
+      }
+      return Ok(());
+    }
+    Err(e) => {
+      report.cases.push(TestCaseResult {
+        file,
+        name: None,
+        failure: Some(e.to_string()),
+      });
+      return Ok(());
+    }
+  };
+
+  let mut named = Vec::new();
+  for pair in cases.pairs::<String, LuaValue>() {
+    let (name, value) = pair?;
+    match value {
+      LuaValue::Function(f) => named.push((name, f)),
+      other => {
+        return Err(
+          LuaError::external(format!(
+            "{}: case '{}' must be a function, got {}",
+            file,
+            name,
+            other.type_name()
+          ))
+          .into(),
+        );
+      }
+    }
+  }
+  named.sort_by(|a, b| a.0.cmp(&b.0));
+
+  let baseline = manifest.borrow().clone();
+  for (name, f) in named {
+    let mut case = TestCaseResult {
+      file: file.clone(),
+      name: Some(name),
+      failure: None,
+    };
+    if !selected(&case) {
+      continue;
+    }
+
+    *manifest.borrow_mut() = baseline.clone();
+    if let Err(e) = f.call::<()>(()) {
+      case.failure = Some(e.to_string());
+    }
+    report.cases.push(case);
+  }
+  Ok(())
+}
+
+/// Find `*_spec.lua` files under the config directory and every resolved input.
+///
+/// Hidden directories are skipped. Results are sorted per root and
+/// deduplicated, since `path:` inputs may live inside the config directory.
+pub fn discover_specs(config_dir: &Path, resolved: Option<&ResolvedInputs>) -> Vec<PathBuf> {
+  let mut roots = vec![config_dir.to_path_buf()];
+  if let Some(resolved) = resolved {
+    collect_input_roots(resolved, &mut roots);
+  }
+
+  let mut seen = BTreeSet::new();
+  let mut specs = Vec::new();
+  for root in roots {
+    let entries = WalkDir::new(&root)
+      .sort_by_file_name()
+      .into_iter()
+      .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+      .filter_map(|e| e.ok())
+      .filter(|e| e.file_type().is_file() && e.file_name().to_string_lossy().ends_with(SPEC_SUFFIX));
+
+    for entry in entries {
+      let canonical = dunce::canonicalize(entry.path()).unwrap_or_else(|_| entry.path().to_path_buf());
+      if seen.insert(canonical) {
+        specs.push(entry.into_path());
+      }
+    }
+  }
+  specs
+}
+
+fn collect_input_roots(inputs: &ResolvedInputs, roots: &mut Vec<PathBuf>) {
+  for input in inputs.values() {
+    roots.push(input.path.clone());
+    collect_input_roots(&input.inputs, roots);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::inputs::lock::LOCK_FILENAME;
+  use crate::util::testutil::path_to_lua_url;
+  use serial_test::serial;
+  use tempfile::TempDir;
+
+  const CONFIG: &str = r#"
+    return {
+      inputs = {},
+      setup = function()
+        require('tools').setup()
+      end,
+    }
+  "#;
+
+  const TOOLS: &str = r#"
+    local M = {}
+    function M.setup(opts)
+      opts = opts or {}
+      sys.build({
+        id = 'hello',
+        inputs = { greeting = opts.greeting or 'hi' },
+        create = function(inputs, ctx)
+          ctx:exec({ bin = '/bin/echo', args = { inputs.greeting } })
+          return { out = ctx.out }
+        end,
+      })
+    end
+    return M
+  "#;
+
+  fn with_config<F>(specs: &[(&str, &str)], f: F)
+  where
+    F: FnOnce(&Path),
+  {
+    let temp = TempDir::new().unwrap();
+    temp_env::with_vars(
+      [
+        ("SYSLUA_STORE", Some(temp.path().join("store").to_str().unwrap())),
+        ("XDG_DATA_HOME", Some(temp.path().join("data").to_str().unwrap())),
+      ],
+      || {
+        let config_dir = temp.path().join("config");
+        std::fs::create_dir_all(config_dir.join("lua")).unwrap();
+        std::fs::write(config_dir.join("init.lua"), CONFIG).unwrap();
+        std::fs::write(config_dir.join("lua").join("tools.lua"), TOOLS).unwrap();
+        for (name, content) in specs {
+          let path = config_dir.join(name);
+          std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+          std::fs::write(path, content).unwrap();
+        }
+        f(&config_dir.join("init.lua"));
+      },
+    );
+  }
+
+  #[test]
+  fn discovers_specs_and_skips_hidden_dirs() {
+    let temp = TempDir::new().unwrap();
+    std::fs::create_dir_all(temp.path().join("lua").join(".cache")).unwrap();
+    std::fs::write(temp.path().join("init_spec.lua"), "").unwrap();
+    std::fs::write(temp.path().join("lua").join("tools_spec.lua"), "").unwrap();
+    std::fs::write(temp.path().join("lua").join("tools.lua"), "").unwrap();
+    std::fs::write(temp.path().join("lua").join(".cache").join("old_spec.lua"), "").unwrap();
+
+    let specs = discover_specs(temp.path(), None);
+    let names: Vec<_> = specs
+      .iter()
+      .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+      .collect();
+    assert_eq!(names, vec!["init_spec.lua", "tools_spec.lua"]);
+  }
+
+  #[test]
+  #[serial]
+  fn runs_named_cases_in_isolation() {
+    let spec = r#"
+      local tools = require('tools')
+      return {
+        ['a builds hello'] = function()
+          tools.setup({ greeting = 'hey' })
+          sys.testing.expect_build({ id = 'hello', inputs = { greeting = 'hey' }, exec = 'echo' })
+        end,
+        ['b starts empty'] = function()
+          sys.testing.expect_build({ id = 'hello', count = 0 })
+        end,
+        ['c fails'] = function()
+          sys.testing.expect_bind({ id = 'missing' })
+        end,
+      }
+    "#;
+    with_config(&[("tools_spec.lua", spec)], |config| {
+      let report = run_tests(config, &TestOptions::default()).unwrap();
+      assert_eq!(report.specs, 1);
+      assert_eq!(report.passed(), 2);
+      assert_eq!(report.failed(), 1);
+
+      let failure = report.cases[2].failure.as_deref().unwrap();
+      assert!(failure.contains("expect_bind"), "{}", failure);
+    });
+  }
+
+  #[test]
+  #[serial]
+  fn top_level_spec_and_config_setup() {
+    let spec = r#"
+      sys.testing.setup()
+      local build = sys.testing.expect_build({ id = 'hello', inputs = { greeting = 'hi' } })
+      assert(build.outputs.out, 'expected an out placeholder')
+    "#;
+    with_config(&[("init_spec.lua", spec)], |config| {
+      let report = run_tests(config, &TestOptions::default()).unwrap();
+      assert_eq!(report.cases.len(), 1);
+      assert!(report.is_success(), "{:?}", report.cases);
+      assert_eq!(report.cases[0].full_name(), "init_spec.lua");
+    });
+  }
+
+  #[test]
+  #[serial]
+  fn leaves_the_config_directory_untouched() {
+    with_config(&[("init_spec.lua", "sys.testing.setup()")], |config| {
+      let config_dir = config.parent().unwrap();
+      let input_dir = config_dir.parent().unwrap().join("input");
+      std::fs::create_dir_all(&input_dir).unwrap();
+      std::fs::write(input_dir.join("init.lua"), "return { setup = function() end }").unwrap();
+      let source = CONFIG.replace(
+        "inputs = {}",
+        &format!("inputs = {{ extra = '{}' }}", path_to_lua_url(&input_dir)),
+      );
+      std::fs::write(config, source).unwrap();
+      std::fs::write(config_dir.join(".luarc.json"), "{}").unwrap();
+
+      let report = run_tests(config, &TestOptions::default()).unwrap();
+      assert!(report.is_success(), "{:?}", report.cases);
+      assert!(!config_dir.join(LOCK_FILENAME).exists());
+      assert_eq!(std::fs::read_to_string(config_dir.join(".luarc.json")).unwrap(), "{}");
+    });
+  }
+
+  #[test]
+  #[serial]
+  fn filter_selects_cases() {
+    let spec = r#"
+      return {
+        ['first'] = function() end,
+        ['second'] = function() error('boom') end,
+      }
+    "#;
+    with_config(&[("lua/x_spec.lua", spec)], |config| {
+      let options = TestOptions {
+        filter: Some("first".to_string()),
+        ..Default::default()
+      };
+      let report = run_tests(config, &options).unwrap();
+      assert_eq!(report.cases.len(), 1);
+      assert_eq!(report.cases[0].full_name(), "lua/x_spec.lua > first");
+    });
+  }
+}
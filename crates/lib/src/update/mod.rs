@@ -1,19 +1,28 @@
 //! Input update orchestration.
 //!
 //! This module provides the core logic for the `sys update` command, which
-//! re-resolves inputs (fetching latest revisions) and updates the lock file.
+//! re-resolves inputs (fetching latest revisions) and updates the lock file,
+//! and for `sys lock audit`, which checks the lock file for entries nothing
+//! uses anymore. `sys add-input` declares an input found in a registry with
+//! [`add_input`]. `sys vendor` and `sys unvendor` copy inputs into the config
+//! repository and back out; see [`vendor`].
+
+pub mod vendor;
 
 use std::collections::{BTreeMap, HashSet};
 use std::path::{Path, PathBuf};
 
 use thiserror::Error;
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::init::update_luarc_inputs;
 use crate::inputs::ResolvedInputs;
+use crate::inputs::audit::{LockAudit, LockIssue, audit_lock, prune_lock};
+use crate::inputs::fetch::Fetchers;
 use crate::inputs::lock::{LOCK_FILENAME, LockFile};
-use crate::inputs::resolve::{ResolutionResult, ResolveError, resolve_inputs, save_lock_file_if_changed};
-use crate::lua::entrypoint::extract_input_decls;
+use crate::inputs::resolve::{ResolutionResult, ResolveError, resolve_inputs_with, save_lock_file_if_changed};
+use crate::lua::edit::{self, EditError};
+use crate::lua::entrypoint::{extract_fetch_settings, extract_input_decls};
 use crate::platform::paths::config_dir;
 
 /// Options for the update operation.
@@ -25,6 +34,13 @@ pub struct UpdateOptions {
   pub dry_run: bool,
   /// Whether running as elevated (affects .luarc.json paths).
   pub system: bool,
+  /// Replacement URLs for root inputs (name -> URL). Overridden inputs are
+  /// resolved but their lock entries are left as they are.
+  pub input_overrides: BTreeMap<String, String>,
+  /// Look up tags and heads again instead of using cached answers.
+  pub refresh: bool,
+  /// Remove stale, orphaned and duplicate lock entries after updating.
+  pub prune: bool,
 }
 
 /// Result of a successful update operation.
@@ -40,10 +56,18 @@ pub struct UpdateResult {
   pub added: Vec<String>,
   /// New transitive inputs that were added.
   pub transitive_added: Vec<String>,
+  /// Direct inputs resolved from an override instead of the lock file.
+  pub overridden: Vec<String>,
   /// Resolved inputs with their final paths and revisions (including transitive deps).
   pub resolved: ResolvedInputs,
   /// Whether the lock file changed.
   pub lock_changed: bool,
+  /// Inputs that failed to resolve in a dry run: full_path -> error message.
+  /// The other fields describe the inputs that did resolve.
+  pub failed: BTreeMap<String, String>,
+  /// Lock issues found with [`UpdateOptions::prune`]. The prunable ones were
+  /// removed from the lock file (unless a dry run).
+  pub lock_issues: Vec<LockIssue>,
 }
 
 /// Errors that can occur during update.
@@ -68,6 +92,26 @@ pub enum UpdateError {
   /// Specified input not found in config.
   #[error("input '{name}' not found in config")]
   InputNotFound { name: String },
+
+  /// Failed to declare an input in the config.
+  #[error("failed to declare input: {0}")]
+  Edit(#[from] EditError),
+
+  /// Failed to read or write the config.
+  #[error("failed to read or write config: {0}")]
+  Io(#[from] std::io::Error),
+
+  /// The config's inputs are already vendored.
+  #[error("inputs are already vendored into {path}; run `sys unvendor` first")]
+  AlreadyVendored { path: String },
+
+  /// The config's inputs aren't vendored.
+  #[error("no vendored inputs to restore: {path} not found")]
+  NotVendored { path: String },
+
+  /// Failed to read or write the vendor manifest.
+  #[error("invalid vendor manifest: {0}")]
+  VendorManifest(#[from] serde_json::Error),
 }
 
 /// Find the config file path, with fallback resolution.
@@ -112,6 +156,8 @@ pub fn find_config_path(explicit: Option<&str>) -> Result<PathBuf, UpdateError>
 /// This function handles both direct and transitive dependencies:
 /// - Direct inputs are force-updated if named, or all direct inputs if no names given
 /// - Transitive dependencies are re-resolved but reuse lock entries when URLs match
+/// - Updating all inputs also drops the recorded answers of the `sys.io`
+///   helpers (see [`crate::lua::io`])
 ///
 /// # Arguments
 ///
@@ -127,7 +173,8 @@ pub fn find_config_path(explicit: Option<&str>) -> Result<PathBuf, UpdateError>
 /// Returns an error if:
 /// - Config file cannot be parsed
 /// - A specified input doesn't exist in the config
-/// - Input resolution fails
+/// - Input resolution fails (a dry run instead reports the inputs that failed
+///   in [`UpdateResult::failed`])
 pub fn update_inputs(config_path: &Path, options: &UpdateOptions) -> Result<UpdateResult, UpdateError> {
   let config_dir = config_path.parent().unwrap_or(Path::new("."));
   let config_path_str = config_path.to_string_lossy();
@@ -136,6 +183,9 @@ pub fn update_inputs(config_path: &Path, options: &UpdateOptions) -> Result<Upda
 
   // Extract input declarations from config (supports extended syntax)
   let input_decls = extract_input_decls(&config_path_str)?;
+  let mut fetch_settings = extract_fetch_settings(&config_path_str)?;
+  fetch_settings.refs.refresh = options.refresh;
+  let fetchers = Fetchers::with_settings(fetch_settings);
 
   // Validate that requested inputs exist in config
   for input_name in &options.inputs {
@@ -167,7 +217,32 @@ pub fn update_inputs(config_path: &Path, options: &UpdateOptions) -> Result<Upda
   );
 
   // Resolve inputs with force update (transitive resolution)
-  let result: ResolutionResult = resolve_inputs(&input_decls, config_dir, Some(&force_update))?;
+  let mut failed = BTreeMap::new();
+  let mut result: ResolutionResult = match resolve_inputs_with(
+    &input_decls,
+    config_dir,
+    Some(&force_update),
+    Some(&options.input_overrides),
+    &fetchers,
+  ) {
+    Ok(result) => result,
+    // A dry run still reports what the inputs that resolved would change
+    Err(ResolveError::Incomplete(partial)) if options.dry_run => {
+      let partial = *partial;
+      failed = partial
+        .failed
+        .into_iter()
+        .map(|(path, error)| (path, error.to_string()))
+        .collect();
+      ResolutionResult {
+        inputs: partial.inputs,
+        lock_file: partial.lock_file,
+        lock_changed: partial.lock_changed,
+        namespaces: Vec::new(),
+      }
+    }
+    Err(e) => return Err(e.into()),
+  };
 
   // Compute what changed for direct inputs
   let mut updated = BTreeMap::new();
@@ -177,9 +252,16 @@ pub fn update_inputs(config_path: &Path, options: &UpdateOptions) -> Result<Upda
   // Track transitive updates
   let mut transitive_updated = BTreeMap::new();
   let mut transitive_added = Vec::new();
+  let mut overridden = Vec::new();
 
   // Check direct inputs
   for (name, resolved) in &result.inputs {
+    // Overridden inputs are not locked, so there is nothing to compare
+    if options.input_overrides.contains_key(name) {
+      overridden.push(name.clone());
+      continue;
+    }
+
     if let Some(old_entry) = old_lock.get(name) {
       if old_entry.rev != resolved.rev {
         updated.insert(name.clone(), (old_entry.rev.clone(), resolved.rev.clone()));
@@ -200,6 +282,24 @@ pub fn update_inputs(config_path: &Path, options: &UpdateOptions) -> Result<Upda
     );
   }
 
+  // Updating every input also drops the recorded sys.io answers, so the next
+  // evaluation looks them up again
+  if options.inputs.is_empty() && result.lock_file.set_eval_cache(BTreeMap::new()) {
+    result.lock_changed = true;
+  }
+
+  // Prune only after a complete resolution; an input that failed to resolve
+  // would look unused
+  let mut lock_issues = Vec::new();
+  if options.prune && failed.is_empty() {
+    let audit = audit_lock(&result.lock_file, &result.inputs);
+    if prune_lock(&mut result.lock_file, &audit) > 0 {
+      info!(issues = audit.prunable(), "pruned lock file");
+      result.lock_changed = true;
+    }
+    lock_issues = audit.issues;
+  }
+
   // Write lock file and update .luarc.json (unless dry run)
   if !options.dry_run {
     save_lock_file_if_changed(&result, config_dir)?;
@@ -215,11 +315,73 @@ pub fn update_inputs(config_path: &Path, options: &UpdateOptions) -> Result<Upda
     unchanged,
     added,
     transitive_added,
+    overridden,
     resolved: result.inputs,
     lock_changed: result.lock_changed,
+    failed,
+    lock_issues,
   })
 }
 
+/// Declare the input `name` with `url` in a config and resolve it.
+///
+/// The declaration is added to the config's `inputs` table keeping its
+/// formatting (see [`crate::lua::edit`]), then the input is resolved and
+/// locked like `sys update --input <name>` would. If it fails to resolve, the
+/// config is restored.
+///
+/// # Errors
+///
+/// Returns an error if the config already declares `name`, can't be edited,
+/// or the input fails to resolve.
+pub fn add_input(config_path: &Path, name: &str, url: &str, system: bool) -> Result<UpdateResult, UpdateError> {
+  let input_decls = extract_input_decls(&config_path.to_string_lossy())?;
+  if input_decls.contains_key(name) {
+    return Err(EditError::AlreadyDeclared(name.to_string()).into());
+  }
+
+  let source = std::fs::read_to_string(config_path)?;
+  let edited = edit::add_input(&source, name, url)?;
+  std::fs::write(config_path, edited)?;
+  info!(input = %name, url = %url, "declared input");
+
+  let options = UpdateOptions {
+    inputs: vec![name.to_string()],
+    system,
+    ..Default::default()
+  };
+  update_inputs(config_path, &options).inspect_err(|_| {
+    if let Err(e) = std::fs::write(config_path, &source) {
+      warn!(error = %e, path = %config_path.display(), "failed to restore config");
+    }
+  })
+}
+
+/// Audit the lock file of a config without changing it.
+///
+/// Resolves the config's inputs as evaluation would (from the lock file,
+/// fetching only what isn't cached) and compares the lock file on disk with
+/// the inputs that were used. See [`crate::inputs::audit`].
+///
+/// # Errors
+///
+/// Returns an error if the config cannot be parsed, the lock file cannot be
+/// loaded, or an input fails to resolve.
+pub fn audit_lock_file(config_path: &Path) -> Result<LockAudit, UpdateError> {
+  let config_dir = config_path.parent().unwrap_or(Path::new("."));
+  let config_path_str = config_path.to_string_lossy();
+
+  let Some(lock) = LockFile::load(&config_dir.join(LOCK_FILENAME)).map_err(UpdateError::LoadLock)? else {
+    return Ok(LockAudit::default());
+  };
+
+  let input_decls = extract_input_decls(&config_path_str)?;
+  let fetchers = Fetchers::with_settings(extract_fetch_settings(&config_path_str)?);
+  let result = resolve_inputs_with(&input_decls, config_dir, None, None, &fetchers)?;
+
+  Ok(audit_lock(&lock, &result.inputs))
+}
+
 /// Recursively collect transitive input changes.
 fn collect_transitive_changes(
   parent_path: &str,
@@ -464,6 +626,215 @@ mod tests {
       );
     }
 
+    #[test]
+    #[serial]
+    fn dry_run_reports_inputs_that_resolved() {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+      fs::create_dir(config_dir.join("my-input")).unwrap();
+
+      let config_path = config_dir.join("init.lua");
+      fs::write(
+        &config_path,
+        r#"
+          return {
+            inputs = {
+              myinput = "path:./my-input",
+              gone = "path:./gone",
+            },
+            setup = function(inputs) end,
+          }
+        "#,
+      )
+      .unwrap();
+
+      temp_env::with_vars(
+        [
+          ("XDG_DATA_HOME", Some(temp.path().to_str().unwrap())),
+          ("XDG_CACHE_HOME", Some(temp.path().to_str().unwrap())),
+          ("HOME", Some(temp.path().to_str().unwrap())),
+        ],
+        || {
+          let options = UpdateOptions {
+            dry_run: true,
+            ..Default::default()
+          };
+          let result = update_inputs(&config_path, &options).unwrap();
+          assert_eq!(result.added, vec!["myinput".to_string()]);
+          assert_eq!(result.failed.keys().collect::<Vec<_>>(), vec!["gone"]);
+          assert!(!config_dir.join("syslua.lock").exists());
+
+          // Without --dry-run, nothing is written and the update fails
+          let err = update_inputs(&config_path, &UpdateOptions::default()).unwrap_err();
+          assert!(
+            matches!(err, UpdateError::Resolve(ResolveError::Incomplete(_))),
+            "{err}"
+          );
+          assert!(!config_dir.join("syslua.lock").exists());
+        },
+      );
+    }
+
+    #[test]
+    #[serial]
+    fn overridden_input_not_locked() {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+
+      fs::create_dir(config_dir.join("my-input")).unwrap();
+      fs::write(config_dir.join("my-input").join("init.lua"), "return {}").unwrap();
+      fs::create_dir(config_dir.join("my-fork")).unwrap();
+      fs::write(config_dir.join("my-fork").join("init.lua"), "return {}").unwrap();
+
+      let config_path = config_dir.join("init.lua");
+      fs::write(
+        &config_path,
+        r#"
+          return {
+            inputs = {
+              myinput = "path:./my-input",
+            },
+            setup = function(inputs) end,
+          }
+        "#,
+      )
+      .unwrap();
+
+      temp_env::with_vars(
+        [
+          ("XDG_DATA_HOME", Some(temp.path().to_str().unwrap())),
+          ("XDG_CACHE_HOME", Some(temp.path().to_str().unwrap())),
+          ("HOME", Some(temp.path().to_str().unwrap())),
+        ],
+        || {
+          let options = UpdateOptions {
+            input_overrides: BTreeMap::from([("myinput".to_string(), "path:./my-fork".to_string())]),
+            ..Default::default()
+          };
+          let result = update_inputs(&config_path, &options).unwrap();
+
+          assert_eq!(result.overridden, vec!["myinput".to_string()]);
+          assert!(result.added.is_empty());
+          assert!(!result.lock_changed);
+          assert!(!config_dir.join("syslua.lock").exists());
+        },
+      );
+    }
+
+    #[test]
+    #[serial]
+    fn prune_removes_entries_nothing_uses() {
+      use crate::inputs::lock::LockedInput;
+
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+
+      fs::create_dir(config_dir.join("my-input")).unwrap();
+      fs::write(config_dir.join("my-input").join("init.lua"), "return {}").unwrap();
+
+      let config_path = config_dir.join("init.lua");
+      fs::write(
+        &config_path,
+        r#"
+          return {
+            inputs = {
+              myinput = "path:./my-input",
+            },
+            setup = function(inputs) end,
+          }
+        "#,
+      )
+      .unwrap();
+
+      temp_env::with_vars(
+        [
+          ("XDG_DATA_HOME", Some(temp.path().to_str().unwrap())),
+          ("XDG_CACHE_HOME", Some(temp.path().to_str().unwrap())),
+          ("HOME", Some(temp.path().to_str().unwrap())),
+        ],
+        || {
+          update_inputs(&config_path, &UpdateOptions::default()).unwrap();
+
+          // Entries of dependencies the inputs no longer declare
+          let lock_path = config_dir.join(LOCK_FILENAME);
+          let mut lock = LockFile::load(&lock_path).unwrap().unwrap();
+          lock.insert(
+            "myinput/utils".to_string(),
+            LockedInput::new("git", "git:https://example.com/utils", "abc"),
+          );
+          lock.save(&lock_path).unwrap();
+
+          let audit = audit_lock_file(&config_path).unwrap();
+          assert_eq!(audit.issues.len(), 1);
+          assert_eq!(audit.issues[0].key, "myinput/utils");
+
+          let options = UpdateOptions {
+            prune: true,
+            ..Default::default()
+          };
+          let result = update_inputs(&config_path, &options).unwrap();
+          assert_eq!(result.lock_issues, audit.issues);
+
+          let lock = LockFile::load(&lock_path).unwrap().unwrap();
+          assert_eq!(lock.input_names(), ["myinput"]);
+          assert!(audit_lock_file(&config_path).unwrap().is_clean());
+        },
+      );
+    }
+
+    #[test]
+    #[serial]
+    fn full_update_drops_recorded_io_answers() {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+
+      fs::create_dir(config_dir.join("my-input")).unwrap();
+      fs::write(config_dir.join("my-input").join("init.lua"), "return {}").unwrap();
+
+      let config_path = config_dir.join("init.lua");
+      fs::write(
+        &config_path,
+        r#"
+          return {
+            inputs = {
+              myinput = "path:./my-input",
+            },
+            setup = function(inputs) end,
+          }
+        "#,
+      )
+      .unwrap();
+
+      temp_env::with_vars(
+        [
+          ("XDG_DATA_HOME", Some(temp.path().to_str().unwrap())),
+          ("XDG_CACHE_HOME", Some(temp.path().to_str().unwrap())),
+          ("HOME", Some(temp.path().to_str().unwrap())),
+        ],
+        || {
+          update_inputs(&config_path, &UpdateOptions::default()).unwrap();
+
+          let lock_path = config_dir.join(LOCK_FILENAME);
+          let mut lock = LockFile::load(&lock_path).unwrap().unwrap();
+          let answers = BTreeMap::from([("prefetch:https://example.com/a".to_string(), "abc".into())]);
+          lock.set_eval_cache(answers);
+          lock.save(&lock_path).unwrap();
+
+          // Updating one input keeps them
+          let options = UpdateOptions {
+            inputs: vec!["myinput".to_string()],
+            ..Default::default()
+          };
+          update_inputs(&config_path, &options).unwrap();
+          assert_eq!(LockFile::load(&lock_path).unwrap().unwrap().eval_cache().len(), 1);
+
+          let result = update_inputs(&config_path, &UpdateOptions::default()).unwrap();
+          assert!(result.lock_changed);
+          assert!(LockFile::load(&lock_path).unwrap().unwrap().eval_cache().is_empty());
+        },
+      );
+    }
+
     #[test]
     fn input_not_found_error() {
       let temp = TempDir::new().unwrap();
@@ -489,6 +860,46 @@ mod tests {
       assert!(matches!(result.unwrap_err(), UpdateError::InputNotFound { .. }));
     }
 
+    #[test]
+    #[serial]
+    fn add_input_declares_and_locks_input() {
+      let temp = TempDir::new().unwrap();
+      let config_dir = temp.path();
+      fs::create_dir(config_dir.join("my-input")).unwrap();
+
+      let config_path = config_dir.join("init.lua");
+      let config = "-- dotfiles\nreturn {\n  inputs = {},\n  setup = function(inputs) end,\n}\n";
+      fs::write(&config_path, config).unwrap();
+
+      temp_env::with_vars(
+        [
+          ("XDG_DATA_HOME", Some(temp.path().to_str().unwrap())),
+          ("XDG_CACHE_HOME", Some(temp.path().to_str().unwrap())),
+          ("HOME", Some(temp.path().to_str().unwrap())),
+        ],
+        || {
+          let result = add_input(&config_path, "my-input", "path:./my-input", false).unwrap();
+          assert_eq!(result.added, ["my-input".to_string()]);
+          assert_eq!(
+            fs::read_to_string(&config_path).unwrap(),
+            "-- dotfiles\nreturn {\n  inputs = {\n    [\"my-input\"] = \"path:./my-input\",\n  },\n  setup = function(inputs) end,\n}\n"
+          );
+          let lock = LockFile::load(&config_dir.join(LOCK_FILENAME)).unwrap().unwrap();
+          assert!(lock.get("my-input").is_some());
+
+          assert!(matches!(
+            add_input(&config_path, "my-input", "path:./other", false),
+            Err(UpdateError::Edit(EditError::AlreadyDeclared(_)))
+          ));
+
+          // An input that fails to resolve isn't left in the config
+          let before = fs::read_to_string(&config_path).unwrap();
+          assert!(add_input(&config_path, "missing", "path:./missing", false).is_err());
+          assert_eq!(fs::read_to_string(&config_path).unwrap(), before);
+        },
+      );
+    }
+
     #[test]
     #[serial]
     fn updates_input_with_transitive_deps() {
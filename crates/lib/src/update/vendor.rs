@@ -0,0 +1,545 @@
+//! Copying a config's inputs into the config repository.
+//!
+//! `sys vendor` copies every input the config resolves to, transitive ones
+//! included, into `vendor/` next to the entrypoint, and declares them as
+//! `path:` inputs there:
+//!
+//! ```lua
+//! inputs = {
+//!   pkgs = {
+//!     url = "path:./vendor/pkgs-1a2b3c4d",
+//!     inputs = {
+//!       utils = { url = "path:../utils-5e6f7a8b" },
+//!     },
+//!   },
+//! },
+//! ```
+//!
+//! Directories are named like the input store's (`{name}-{hash(url+rev)[:8]}`),
+//! so inputs shared by several others are copied once. Evaluating the config
+//! then fetches nothing. The lock entries of vendored inputs keep the
+//! revisions they were locked at. Inputs already inside the config directory
+//! stay where they are, and inputs that follow others aren't copied.
+//!
+//! `vendor/vendor.json` records the original declarations, the lock file
+//! before vendoring and the directories copied. `sys unvendor` puts the
+//! declarations and the lock file back and removes the copies.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::{UpdateError, collect_all_input_paths};
+use crate::init::update_luarc_inputs;
+use crate::inputs::fetch::Fetchers;
+use crate::inputs::lock::{LOCK_FILENAME, LockFile, LockedInput};
+use crate::inputs::resolve::{ResolveError, resolve_inputs_with, save_lock_file_if_changed};
+use crate::inputs::store::InputStore;
+use crate::inputs::{InputDecl, InputDecls, InputOverride, ResolvedInput, ResolvedInputs};
+use crate::lua::edit;
+use crate::lua::entrypoint::{extract_fetch_settings, extract_input_decls};
+use crate::util::fs::copy_dir;
+
+/// Directory next to the entrypoint that vendored inputs are copied into.
+pub const VENDOR_DIR: &str = "vendor";
+
+/// File in [`VENDOR_DIR`] recording what `sys vendor` changed.
+pub const VENDOR_MANIFEST: &str = "vendor.json";
+
+/// What `sys vendor` changed, so `sys unvendor` can undo it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VendorManifest {
+  /// Original declarations of the rewritten root inputs, as written in the config.
+  pub declarations: BTreeMap<String, String>,
+  /// The lock file before vendoring, if the config had one.
+  pub lock: Option<String>,
+  /// Vendored inputs: full path in the dependency graph -> directory in [`VENDOR_DIR`].
+  pub inputs: BTreeMap<String, String>,
+}
+
+/// Result of vendoring a config's inputs.
+#[derive(Debug)]
+pub struct VendorResult {
+  /// Vendored inputs: full path in the dependency graph -> directory in [`VENDOR_DIR`].
+  pub vendored: BTreeMap<String, String>,
+  /// Inputs already inside the config directory, left in place.
+  pub kept: Vec<String>,
+  /// The inputs as resolved from their vendored copies.
+  pub resolved: ResolvedInputs,
+}
+
+/// Result of undoing [`vendor_inputs`].
+#[derive(Debug)]
+pub struct UnvendorResult {
+  /// Root inputs whose original declaration was put back.
+  pub restored: Vec<String>,
+  /// Root inputs no longer declared as `name = ...`, left as they are.
+  pub skipped: Vec<String>,
+  /// Number of vendored directories removed.
+  pub removed: usize,
+}
+
+/// Where vendoring puts the inputs of a config.
+#[derive(Debug, Default)]
+struct VendorPlan {
+  /// Full path -> directory in [`VENDOR_DIR`].
+  vendored: BTreeMap<String, String>,
+  /// Directory in [`VENDOR_DIR`] -> the resolved input to copy there.
+  copies: BTreeMap<String, PathBuf>,
+  /// Full path -> lock entry naming the vendored copy.
+  lock_entries: BTreeMap<String, LockedInput>,
+  /// Root input -> its new URL, if it is vendored itself.
+  urls: BTreeMap<String, String>,
+  /// Root input -> URL overrides of its vendored transitive inputs.
+  overrides: BTreeMap<String, BTreeMap<String, String>>,
+  kept: Vec<String>,
+}
+
+/// Copy the inputs of a config into its [`VENDOR_DIR`] and declare them from
+/// there.
+///
+/// The inputs are resolved from the lock file first (fetching what isn't
+/// cached), so they are vendored at their locked revisions. If the vendored
+/// config fails to resolve, the config and lock file are restored and the
+/// copies removed.
+///
+/// # Errors
+///
+/// Returns an error if the inputs are already vendored, fail to resolve, or a
+/// rewritten input isn't declared as `name = ...` in the config.
+pub fn vendor_inputs(config_path: &Path, system: bool) -> Result<VendorResult, UpdateError> {
+  let config_dir = config_path.parent().unwrap_or(Path::new("."));
+  let config_path_str = config_path.to_string_lossy();
+  let vendor_dir = config_dir.join(VENDOR_DIR);
+  let manifest_path = vendor_dir.join(VENDOR_MANIFEST);
+  if manifest_path.exists() {
+    return Err(UpdateError::AlreadyVendored {
+      path: vendor_dir.display().to_string(),
+    });
+  }
+
+  let input_decls = extract_input_decls(&config_path_str)?;
+  let fetchers = Fetchers::with_settings(extract_fetch_settings(&config_path_str)?);
+  let result = resolve_inputs_with(&input_decls, config_dir, None, None, &fetchers)?;
+  save_lock_file_if_changed(&result, config_dir)?;
+
+  let root = dunce::canonicalize(config_dir)?;
+  let mut plan = VendorPlan::default();
+  for (name, resolved) in &result.inputs {
+    plan_input(name, resolved, Path::new(""), &root, &result.lock_file, &mut plan);
+  }
+  if plan.vendored.is_empty() {
+    return Ok(VendorResult {
+      vendored: plan.vendored,
+      kept: plan.kept,
+      resolved: result.inputs,
+    });
+  }
+
+  // Edit the config before copying anything, so an input that can't be
+  // rewritten leaves everything alone
+  let source = fs::read_to_string(config_path)?;
+  let mut edited = source.clone();
+  let mut manifest = VendorManifest {
+    inputs: plan.vendored.clone(),
+    ..Default::default()
+  };
+  for name in plan.urls.keys().chain(plan.overrides.keys()) {
+    if manifest.declarations.contains_key(name) {
+      continue;
+    }
+    let decl = vendored_decl(name, &input_decls, &plan);
+    manifest
+      .declarations
+      .insert(name.clone(), edit::input_declaration(&edited, name)?.to_string());
+    edited = edit::replace_input(&edited, name, &decl)?;
+  }
+
+  let lock_path = config_dir.join(LOCK_FILENAME);
+  manifest.lock = read_optional(&lock_path)?;
+  let mut lock_file = result.lock_file;
+  for (full_path, entry) in &plan.lock_entries {
+    lock_file.insert(full_path.clone(), entry.clone());
+  }
+  lock_file.as_v1_mut().remove_orphaned_nodes();
+
+  let resolved = write_vendored(config_path, &plan, &manifest, &edited, &lock_file, &fetchers).inspect_err(|_| {
+    if let Err(e) = fs::write(config_path, &source) {
+      warn!(error = %e, path = %config_path.display(), "failed to restore config");
+    }
+    let restored = match manifest.lock {
+      Some(ref lock) => fs::write(&lock_path, lock),
+      None => fs::remove_file(&lock_path),
+    };
+    if let Err(e) = restored {
+      warn!(error = %e, path = %lock_path.display(), "failed to restore lock file");
+    }
+    remove_vendored(&vendor_dir, &manifest);
+  })?;
+
+  update_luarc_inputs(config_dir, collect_all_input_paths(&resolved), system);
+  Ok(VendorResult {
+    vendored: plan.vendored,
+    kept: plan.kept,
+    resolved,
+  })
+}
+
+/// Copy the inputs `plan` vendors, write the vendored config, lock file and
+/// `manifest`, and resolve the inputs from their copies.
+fn write_vendored(
+  config_path: &Path,
+  plan: &VendorPlan,
+  manifest: &VendorManifest,
+  edited: &str,
+  lock_file: &LockFile,
+  fetchers: &Fetchers,
+) -> Result<ResolvedInputs, UpdateError> {
+  let config_dir = config_path.parent().unwrap_or(Path::new("."));
+  let vendor_dir = config_dir.join(VENDOR_DIR);
+  fs::create_dir_all(&vendor_dir)?;
+  for (dir, src) in &plan.copies {
+    info!(src = %src.display(), dir = %dir, "vendoring input");
+    copy_dir(src, &vendor_dir.join(dir), &[".git"])?;
+  }
+  fs::write(
+    vendor_dir.join(VENDOR_MANIFEST),
+    serde_json::to_string_pretty(manifest)?,
+  )?;
+  fs::write(config_path, edited)?;
+  lock_file
+    .save(&config_dir.join(LOCK_FILENAME))
+    .map_err(ResolveError::SaveLock)?;
+
+  let input_decls = extract_input_decls(&config_path.to_string_lossy())?;
+  let result = resolve_inputs_with(&input_decls, config_dir, None, None, fetchers)?;
+  save_lock_file_if_changed(&result, config_dir)?;
+  Ok(result.inputs)
+}
+
+/// Undo [`vendor_inputs`]: put the original declarations and lock file back
+/// and remove the vendored copies.
+///
+/// Root inputs whose declaration was removed or renamed since are skipped.
+/// Files added to [`VENDOR_DIR`] by hand are kept.
+///
+/// # Errors
+///
+/// Returns an error if the inputs aren't vendored or the vendor manifest
+/// can't be read.
+pub fn unvendor_inputs(config_path: &Path) -> Result<UnvendorResult, UpdateError> {
+  let config_dir = config_path.parent().unwrap_or(Path::new("."));
+  let vendor_dir = config_dir.join(VENDOR_DIR);
+  let manifest_path = vendor_dir.join(VENDOR_MANIFEST);
+  let Some(manifest) = read_optional(&manifest_path)? else {
+    return Err(UpdateError::NotVendored {
+      path: manifest_path.display().to_string(),
+    });
+  };
+  let manifest: VendorManifest = serde_json::from_str(&manifest)?;
+
+  let mut source = fs::read_to_string(config_path)?;
+  let mut restored = Vec::new();
+  let mut skipped = Vec::new();
+  for (name, text) in &manifest.declarations {
+    match edit::restore_input(&source, name, text) {
+      Ok(edited) => {
+        source = edited;
+        restored.push(name.clone());
+      }
+      Err(e) => {
+        warn!(input = %name, error = %e, "not restoring input declaration");
+        skipped.push(name.clone());
+      }
+    }
+  }
+  fs::write(config_path, source)?;
+
+  let lock_path = config_dir.join(LOCK_FILENAME);
+  match manifest.lock {
+    Some(ref lock) => fs::write(&lock_path, lock)?,
+    None if lock_path.exists() => fs::remove_file(&lock_path)?,
+    None => {}
+  }
+
+  let removed = remove_vendored(&vendor_dir, &manifest);
+  Ok(UnvendorResult {
+    restored,
+    skipped,
+    removed,
+  })
+}
+
+/// Decide where the input at `full_path` and its dependencies go. `parent`
+/// is the directory its parent resolves to once vendored, relative to the
+/// config directory `root`.
+fn plan_input(
+  full_path: &str,
+  resolved: &ResolvedInput,
+  parent: &Path,
+  root: &Path,
+  lock_file: &LockFile,
+  plan: &mut VendorPlan,
+) {
+  // Followed and overridden inputs have no lock entry of their own
+  let Some(locked) = lock_file.get(full_path) else {
+    return;
+  };
+
+  let location = match resolved.path.strip_prefix(root) {
+    Ok(local) => {
+      plan.kept.push(full_path.to_string());
+      local.to_path_buf()
+    }
+    Err(_) => {
+      let name = full_path.rsplit('/').next().unwrap_or(full_path);
+      let dir = InputStore::compute_store_label(name, &locked.url, &locked.rev);
+      // A dependency shared by several inputs is copied once
+      let dir = plan
+        .copies
+        .iter()
+        .find(|(_, src)| **src == resolved.path)
+        .map(|(dir, _)| dir.clone())
+        .unwrap_or(dir);
+      plan.copies.insert(dir.clone(), resolved.path.clone());
+
+      let location = Path::new(VENDOR_DIR).join(&dir);
+      let url = relative_url(parent, &location);
+      let mut entry = LockedInput::new("path", &url, &locked.rev).with_tag(locked.tag);
+      entry.last_modified = locked.last_modified;
+      plan.lock_entries.insert(full_path.to_string(), entry);
+      plan.vendored.insert(full_path.to_string(), dir);
+
+      match full_path.split_once('/') {
+        None => {
+          plan.urls.insert(full_path.to_string(), url);
+        }
+        Some((root_input, key)) => {
+          plan
+            .overrides
+            .entry(root_input.to_string())
+            .or_default()
+            .insert(key.to_string(), url);
+        }
+      }
+      location
+    }
+  };
+
+  for (name, dep) in &resolved.inputs {
+    plan_input(
+      &format!("{}/{}", full_path, name),
+      dep,
+      &location,
+      root,
+      lock_file,
+      plan,
+    );
+  }
+}
+
+/// The declaration of root input `name` once vendored: its own URL and
+/// those of its vendored dependencies replaced, its other overrides kept.
+fn vendored_decl(name: &str, input_decls: &InputDecls, plan: &VendorPlan) -> InputDecl {
+  let decl = input_decls.get(name);
+  let url = plan
+    .urls
+    .get(name)
+    .cloned()
+    .or_else(|| decl.and_then(InputDecl::url).map(str::to_string));
+
+  let vendored = plan.overrides.get(name);
+  let mut overrides = decl.and_then(InputDecl::overrides).cloned().unwrap_or_default();
+  overrides.retain(|key, _| !vendored.is_some_and(|urls| urls.contains_key(&key.replace('.', "/"))));
+  for (key, url) in vendored.into_iter().flatten() {
+    overrides.insert(key.clone(), InputOverride::Url(url.clone()));
+  }
+
+  match url {
+    Some(url) if overrides.is_empty() => InputDecl::Url(url),
+    url => InputDecl::Extended { url, inputs: overrides },
+  }
+}
+
+/// The `path:` URL of `to` relative to `from`, both relative to the config
+/// directory.
+fn relative_url(from: &Path, to: &Path) -> String {
+  let from: Vec<Component> = from.components().collect();
+  let to: Vec<Component> = to.components().collect();
+  let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+  let up = from.len() - common;
+  let rest: Vec<String> = to[common..]
+    .iter()
+    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+    .collect();
+  let prefix = if up == 0 { "./".to_string() } else { "../".repeat(up) };
+  format!("path:{}{}", prefix, rest.join("/"))
+}
+
+/// Remove the directories and files `manifest` records from `vendor_dir`,
+/// and `vendor_dir` itself once empty. Returns the number of directories
+/// removed.
+fn remove_vendored(vendor_dir: &Path, manifest: &VendorManifest) -> usize {
+  let mut removed = 0;
+  let dirs: std::collections::BTreeSet<&String> = manifest.inputs.values().collect();
+  for dir in dirs {
+    let path = vendor_dir.join(dir);
+    match fs::remove_dir_all(&path) {
+      Ok(()) => removed += 1,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+      Err(e) => warn!(error = %e, path = %path.display(), "failed to remove vendored input"),
+    }
+  }
+  let _ = fs::remove_file(vendor_dir.join(VENDOR_MANIFEST));
+  // Only removes the directory when nothing else is in it
+  let _ = fs::remove_dir(vendor_dir);
+  removed
+}
+
+/// The contents of `path`, or `None` if it doesn't exist.
+fn read_optional(path: &Path) -> Result<Option<String>, UpdateError> {
+  match fs::read_to_string(path) {
+    Ok(contents) => Ok(Some(contents)),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(e.into()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serial_test::serial;
+  use tempfile::TempDir;
+
+  use super::*;
+  use crate::util::testutil::path_to_lua_url;
+
+  /// An input at `dir` providing the Lua namespace `namespace`, depending on `deps`.
+  fn create_input(dir: &Path, namespace: &str, deps: &[(&str, String)]) {
+    fs::create_dir_all(dir.join("lua").join(namespace)).unwrap();
+    fs::write(dir.join("lua").join(namespace).join("init.lua"), "return {}").unwrap();
+    let inputs: Vec<String> = deps
+      .iter()
+      .map(|(name, url)| format!("{} = \"{}\"", name, url))
+      .collect();
+    fs::write(
+      dir.join("init.lua"),
+      format!(
+        "return {{ inputs = {{ {} }}, setup = function() end }}",
+        inputs.join(", ")
+      ),
+    )
+    .unwrap();
+  }
+
+  fn with_temp_home<R>(home: &Path, f: impl FnOnce() -> R) -> R {
+    temp_env::with_vars(
+      [
+        ("XDG_DATA_HOME", Some(home.to_str().unwrap())),
+        ("XDG_CACHE_HOME", Some(home.to_str().unwrap())),
+        ("HOME", Some(home.to_str().unwrap())),
+      ],
+      f,
+    )
+  }
+
+  #[test]
+  fn relative_urls() {
+    assert_eq!(
+      relative_url(Path::new(""), Path::new("vendor/pkgs-1a2b3c4d")),
+      "path:./vendor/pkgs-1a2b3c4d"
+    );
+    assert_eq!(
+      relative_url(Path::new("vendor/pkgs-1a2b3c4d"), Path::new("vendor/utils-5e6f7a8b")),
+      "path:../utils-5e6f7a8b"
+    );
+    assert_eq!(
+      relative_url(Path::new("modules/kit"), Path::new("vendor/utils-5e6f7a8b")),
+      "path:../../vendor/utils-5e6f7a8b"
+    );
+  }
+
+  #[test]
+  #[serial]
+  fn vendors_and_unvendors_inputs() {
+    let temp = TempDir::new().unwrap();
+    let upstream = TempDir::new().unwrap();
+    let config_dir = temp.path().join("config");
+    fs::create_dir(&config_dir).unwrap();
+
+    // `pkgs` and the config both use `utils`; `kit` lives in the config
+    let utils = upstream.path().join("utils");
+    let pkgs = upstream.path().join("pkgs");
+    create_input(&utils, "utils", &[]);
+    create_input(&pkgs, "pkgs", &[("utils", path_to_lua_url(&utils))]);
+    create_input(&config_dir.join("kit"), "kit", &[]);
+
+    let config_path = config_dir.join("init.lua");
+    let source = format!(
+      r#"return {{
+  inputs = {{
+    pkgs = "{}", -- packages
+    utils = "{}",
+    kit = "path:./kit",
+  }},
+  setup = function(inputs) end,
+}}
+"#,
+      path_to_lua_url(&pkgs),
+      path_to_lua_url(&utils)
+    );
+    fs::write(&config_path, &source).unwrap();
+
+    with_temp_home(temp.path(), || {
+      let result = vendor_inputs(&config_path, false).unwrap();
+      assert_eq!(result.kept, vec!["kit".to_string()]);
+      assert_eq!(result.vendored.len(), 3);
+      // `utils` is copied once for both inputs using it
+      assert_eq!(result.vendored["utils"], result.vendored["pkgs/utils"]);
+      let utils_dir = &result.vendored["utils"];
+      assert!(config_dir.join("vendor").join(utils_dir).join("init.lua").exists());
+
+      let edited = fs::read_to_string(&config_path).unwrap();
+      assert!(edited.contains(&format!("url = \"path:./vendor/{}\"", result.vendored["pkgs"])));
+      assert!(edited.contains(&format!("utils = {{ url = \"path:../{}\" }}", utils_dir)));
+      assert!(edited.contains(&format!("utils = \"path:./vendor/{}\"", utils_dir)));
+      assert!(edited.contains("kit = \"path:./kit\""));
+
+      let lock = LockFile::load(&config_dir.join(LOCK_FILENAME)).unwrap().unwrap();
+      let locked = lock.get("pkgs/utils").unwrap();
+      assert_eq!(locked.type_, "path");
+      assert_eq!(locked.url, format!("path:../{}", utils_dir));
+
+      // The vendored config resolves without the upstream inputs
+      let upstream_path = upstream.path().to_path_buf();
+      fs::remove_dir_all(&upstream_path).unwrap();
+      let decls = extract_input_decls(&config_path.to_string_lossy()).unwrap();
+      let resolved = resolve_inputs_with(&decls, &config_dir, None, None, &Fetchers::default()).unwrap();
+      assert!(
+        resolved.inputs["pkgs"]
+          .path
+          .starts_with(dunce::canonicalize(&config_dir).unwrap())
+      );
+
+      assert!(matches!(
+        vendor_inputs(&config_path, false),
+        Err(UpdateError::AlreadyVendored { .. })
+      ));
+
+      let result = unvendor_inputs(&config_path).unwrap();
+      assert_eq!(result.restored, vec!["pkgs".to_string(), "utils".to_string()]);
+      assert_eq!(result.removed, 2);
+      assert_eq!(fs::read_to_string(&config_path).unwrap(), source);
+      assert!(!config_dir.join("vendor").exists());
+      let lock = LockFile::load(&config_dir.join(LOCK_FILENAME)).unwrap().unwrap();
+      assert_eq!(lock.get("pkgs/utils").unwrap().url, path_to_lua_url(&utils));
+
+      assert!(matches!(
+        unvendor_inputs(&config_path),
+        Err(UpdateError::NotVendored { .. })
+      ));
+    });
+  }
+}
@@ -0,0 +1,151 @@
+//! Filesystem helpers.
+
+use std::io;
+use std::path::Path;
+
+use tracing::trace;
+use walkdir::WalkDir;
+
+use crate::platform::reflink::clone_file;
+
+/// Total size in bytes of all regular files under `path`.
+///
+/// Unreadable entries are ignored. Returns 0 if `path` does not exist.
+pub fn dir_size(path: &Path) -> u64 {
+  WalkDir::new(path)
+    .into_iter()
+    .filter_map(|e| e.ok())
+    .filter(|e| e.file_type().is_file())
+    .filter_map(|e| e.metadata().ok())
+    .map(|m| m.len())
+    .sum()
+}
+
+/// Copy the file `src` to `dst` like [`std::fs::copy`], returning its size.
+///
+/// Where the filesystem supports it, the copy is a copy-on-write clone (see
+/// [`crate::platform::reflink`]), which is instant however large the file
+/// is; elsewhere the bytes are copied.
+pub fn copy_file(src: &Path, dst: &Path) -> io::Result<u64> {
+  match clone_file(src, dst) {
+    Ok(()) => {
+      trace!(src = ?src, dst = ?dst, "cloned file");
+      Ok(std::fs::metadata(dst)?.len())
+    }
+    Err(e) => {
+      trace!(src = ?src, error = %e, "cannot clone file, copying");
+      std::fs::copy(src, dst)
+    }
+  }
+}
+
+/// Copy the directory tree `src` to `dst`, skipping entries named in `exclude`.
+///
+/// Files are copied with [`copy_file`]. Symlinks are copied as symlinks on
+/// Unix and followed elsewhere. Copied directories are made writable by their
+/// owner, so trees from read-only stores (such as `/nix/store`) can later be
+/// removed.
+pub fn copy_dir(src: &Path, dst: &Path, exclude: &[&str]) -> io::Result<()> {
+  let walker = WalkDir::new(src).sort_by_file_name().into_iter().filter_entry(|e| {
+    e.depth() == 0
+      || e
+        .file_name()
+        .to_str()
+        .map(|name| !exclude.contains(&name))
+        .unwrap_or(true)
+  });
+
+  for entry in walker {
+    let entry = entry.map_err(io::Error::other)?;
+    let rel = entry.path().strip_prefix(src).map_err(io::Error::other)?;
+    let target = dst.join(rel);
+    let file_type = entry.file_type();
+
+    if file_type.is_dir() {
+      std::fs::create_dir_all(&target)?;
+      let mut permissions = entry.metadata().map_err(io::Error::other)?.permissions();
+      set_owner_writable(&mut permissions);
+      std::fs::set_permissions(&target, permissions)?;
+    } else if file_type.is_symlink() {
+      #[cfg(unix)]
+      std::os::unix::fs::symlink(std::fs::read_link(entry.path())?, &target)?;
+      #[cfg(not(unix))]
+      copy_file(entry.path(), &target)?;
+    } else if file_type.is_file() {
+      copy_file(entry.path(), &target)?;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(unix)]
+fn set_owner_writable(permissions: &mut std::fs::Permissions) {
+  use std::os::unix::fs::PermissionsExt;
+  permissions.set_mode(permissions.mode() | 0o200);
+}
+
+#[cfg(not(unix))]
+fn set_owner_writable(permissions: &mut std::fs::Permissions) {
+  permissions.set_readonly(false);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn dir_size_sums_nested_files() {
+    let temp = tempfile::TempDir::new().unwrap();
+    std::fs::write(temp.path().join("a"), b"12345").unwrap();
+    std::fs::create_dir(temp.path().join("sub")).unwrap();
+    std::fs::write(temp.path().join("sub").join("b"), b"123").unwrap();
+
+    assert_eq!(dir_size(temp.path()), 8);
+    assert_eq!(dir_size(&temp.path().join("missing")), 0);
+  }
+
+  #[test]
+  fn copy_file_copies_content_and_permissions() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let src = temp.path().join("tool");
+    std::fs::write(&src, b"#!/bin/sh\necho hi\n").unwrap();
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      std::fs::set_permissions(&src, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let dst = temp.path().join("copy");
+    std::fs::write(&dst, b"previous content that is longer").unwrap();
+    assert_eq!(copy_file(&src, &dst).unwrap(), 18);
+    assert_eq!(std::fs::read(&dst).unwrap(), b"#!/bin/sh\necho hi\n");
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      assert_eq!(std::fs::metadata(&dst).unwrap().permissions().mode() & 0o777, 0o755);
+    }
+  }
+
+  #[test]
+  fn copy_dir_copies_tree_without_excluded_entries() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let src = temp.path().join("src");
+    std::fs::create_dir_all(src.join("bin")).unwrap();
+    std::fs::write(src.join("bin").join("tool"), b"#!/bin/sh").unwrap();
+    std::fs::create_dir(src.join("tmp")).unwrap();
+    std::fs::write(src.join("tmp").join("junk"), b"x").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("bin/tool", src.join("tool")).unwrap();
+
+    let dst = temp.path().join("dst");
+    copy_dir(&src, &dst, &["tmp"]).unwrap();
+
+    assert_eq!(std::fs::read(dst.join("bin").join("tool")).unwrap(), b"#!/bin/sh");
+    assert!(!dst.join("tmp").exists());
+    #[cfg(unix)]
+    assert_eq!(
+      std::fs::read_link(dst.join("tool")).unwrap(),
+      std::path::PathBuf::from("bin/tool")
+    );
+  }
+}
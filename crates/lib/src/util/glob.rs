@@ -0,0 +1,58 @@
+//! Glob matching of paths.
+
+/// Match `path` against a glob `pattern`.
+///
+/// `*` and `?` match within a path component, `**` across components. `/`
+/// and `\` are both separators.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+  let pattern: Vec<char> = pattern.chars().collect();
+  let path: Vec<char> = path.chars().collect();
+  glob_match_from(&pattern, &path)
+}
+
+fn glob_match_from(pattern: &[char], path: &[char]) -> bool {
+  match pattern {
+    [] => path.is_empty(),
+    ['*', '*', rest @ ..] => {
+      // `**/` also matches no components at all
+      let rest_after_slash = rest.strip_prefix(&['/']).unwrap_or(rest);
+      glob_match_from(rest_after_slash, path) || (0..=path.len()).any(|i| glob_match_from(rest, &path[i..]))
+    }
+    ['*', rest @ ..] => (0..=path.len())
+      .take_while(|&i| i == 0 || !is_separator(path[i - 1]))
+      .any(|i| glob_match_from(rest, &path[i..])),
+    ['?', rest @ ..] => path
+      .split_first()
+      .is_some_and(|(c, path)| !is_separator(*c) && glob_match_from(rest, path)),
+    [p, rest @ ..] => path
+      .split_first()
+      .is_some_and(|(c, path)| (c == p || (is_separator(*c) && is_separator(*p))) && glob_match_from(rest, path)),
+  }
+}
+
+fn is_separator(c: char) -> bool {
+  c == '/' || c == '\\'
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn glob_match_components() {
+    assert!(glob_match(
+      "/home/u/.config/app/*.json",
+      "/home/u/.config/app/state.json"
+    ));
+    assert!(!glob_match("/home/u/.config/*.json", "/home/u/.config/app/state.json"));
+    assert!(glob_match(
+      "/home/u/.config/**/*.json",
+      "/home/u/.config/app/state.json"
+    ));
+    assert!(glob_match("/home/u/.config/**/*.json", "/home/u/.config/state.json"));
+    assert!(glob_match("/home/u/**", "/home/u/a/b/c"));
+    assert!(glob_match("/tmp/file?.txt", "/tmp/file1.txt"));
+    assert!(!glob_match("/tmp/file?.txt", "/tmp/file/.txt"));
+    assert!(!glob_match("/tmp/a", "/tmp/ab"));
+  }
+}
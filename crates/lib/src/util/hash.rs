@@ -1,29 +1,37 @@
 //! Hashing utilities for content-addressed storage and verification.
 //!
 //! This module provides:
-//! - `ObjectHash`: A truncated 20-character hash for store paths
+//! - `ObjectHash`: A truncated hash for store paths (20 characters by default)
+//! - `HashSpec`: The algorithm and length used for object hashes
+//! - `HashMemo`: Object hashes shared by identical definitions within an evaluation
+//! - `hash_all()`: Object hashing spread over the available cores
 //! - `ContentHash`: A full 64-character hash for content verification
 //! - `hash_directory()`: Deterministic directory hashing
 //! - `hash_file()`: Single file hashing
 //! - `hash_bytes()`: Arbitrary byte hashing
 
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::io::Read;
 use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use tracing::trace;
+use sha2::{Digest, Sha256, Sha512};
+use tracing::{debug, trace};
 use walkdir::WalkDir;
 
-use crate::consts::OBJ_HASH_PREFIX_LEN;
+use crate::consts::{OBJ_HASH_MAX_LEN, OBJ_HASH_PREFIX_LEN};
 
 pub type HashError = serde_json::Error;
 
 /// A content-addressed hash identifying a unique object.
 ///
-/// The hash is a 20-character truncated SHA-256 of the JSON-serialized struct.
-/// This provides sufficient collision resistance while keeping paths readable.
+/// The hash is a truncated digest of the JSON-serialized struct, by default
+/// the first 20 characters of its SHA-256 (see [`HashSpec`]). This provides
+/// sufficient collision resistance while keeping paths readable.
 ///
 /// # Format
 ///
@@ -31,19 +39,244 @@ pub type HashError = serde_json::Error;
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ObjectHash(pub String);
 
+impl ObjectHash {
+  /// Shorter forms of this hash, longest first, down to [`OBJ_HASH_PREFIX_LEN`].
+  pub fn shorter_forms(&self) -> impl Iterator<Item = ObjectHash> + '_ {
+    (OBJ_HASH_PREFIX_LEN..self.0.len())
+      .rev()
+      .filter_map(|len| self.0.get(..len).map(|prefix| ObjectHash(prefix.to_string())))
+  }
+}
+
 impl std::fmt::Display for ObjectHash {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     write!(f, "{}", self.0)
   }
 }
 
+/// Digest algorithm for object hashes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+  #[default]
+  Sha256,
+  Sha512,
+}
+
+impl HashAlgorithm {
+  /// Returns the lowercase string identifier ("sha256" or "sha512").
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::Sha256 => "sha256",
+      Self::Sha512 => "sha512",
+    }
+  }
+
+  /// Length of the full digest in hex characters.
+  pub fn hex_len(&self) -> usize {
+    match self {
+      Self::Sha256 => 64,
+      Self::Sha512 => OBJ_HASH_MAX_LEN,
+    }
+  }
+
+  /// Full lowercase hex digest of `data`.
+  pub fn digest_hex(&self, data: &[u8]) -> String {
+    match self {
+      Self::Sha256 => hex::encode(Sha256::digest(data)),
+      Self::Sha512 => hex::encode(Sha512::digest(data)),
+    }
+  }
+}
+
+impl fmt::Display for HashAlgorithm {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl FromStr for HashAlgorithm {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "sha256" => Ok(Self::Sha256),
+      "sha512" => Ok(Self::Sha512),
+      _ => Err(format!("unknown hash algorithm '{}' (expected sha256 or sha512)", s)),
+    }
+  }
+}
+
+/// Algorithm and length used to compute object hashes.
+///
+/// Recorded in manifests and lock files so the hashes they contain can be
+/// interpreted. Configured with `settings.hash` in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct HashSpec {
+  pub algorithm: HashAlgorithm,
+  /// Number of hex characters kept from the digest.
+  pub length: usize,
+}
+
+impl Default for HashSpec {
+  fn default() -> Self {
+    Self {
+      algorithm: HashAlgorithm::Sha256,
+      length: OBJ_HASH_PREFIX_LEN,
+    }
+  }
+}
+
+impl HashSpec {
+  /// Create a spec, checking that `length` fits the algorithm's digest.
+  pub fn new(algorithm: HashAlgorithm, length: usize) -> Result<Self, String> {
+    if length < OBJ_HASH_PREFIX_LEN || length > algorithm.hex_len() {
+      return Err(format!(
+        "hash length must be between {} and {} for {}, got {}",
+        OBJ_HASH_PREFIX_LEN,
+        algorithm.hex_len(),
+        algorithm,
+        length
+      ));
+    }
+    Ok(Self { algorithm, length })
+  }
+
+  /// Whether this is the default spec (SHA-256, 20 characters).
+  pub fn is_default(&self) -> bool {
+    *self == Self::default()
+  }
+
+  /// Hash `data` with this spec.
+  pub fn hash(&self, data: &[u8]) -> ObjectHash {
+    let mut full = self.algorithm.digest_hex(data);
+    full.truncate(self.length);
+    ObjectHash(full)
+  }
+}
+
+impl fmt::Display for HashSpec {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.algorithm, self.length)
+  }
+}
+
+impl FromStr for HashSpec {
+  type Err = String;
+
+  /// Parse `<algorithm>:<length>`, e.g. `sha256:32`.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (algorithm, length) = s
+      .split_once(':')
+      .ok_or_else(|| format!("expected <algorithm>:<length>, got '{}'", s))?;
+    let length = length
+      .parse::<usize>()
+      .map_err(|_| format!("invalid hash length '{}'", length))?;
+    Self::new(algorithm.parse()?, length)
+  }
+}
+
 pub trait Hashable: Serialize {
+  /// Hash with the default [`HashSpec`].
   fn compute_hash(&self) -> Result<ObjectHash, HashError> {
-    let serialized = serde_json::to_string(self)?;
-    let mut hasher = Sha256::new();
-    hasher.update(serialized.as_bytes());
-    let full = format!("{:x}", hasher.finalize());
-    Ok(ObjectHash(full[..OBJ_HASH_PREFIX_LEN].to_string()))
+    self.compute_hash_with(&HashSpec::default())
+  }
+
+  /// Hash with the given algorithm and length.
+  fn compute_hash_with(&self, spec: &HashSpec) -> Result<ObjectHash, HashError> {
+    Ok(spec.hash(self.hash_input()?.as_bytes()))
+  }
+
+  /// The serialization that is digested, the whole value by default.
+  fn hash_input(&self) -> Result<String, HashError> {
+    serde_json::to_string(self)
+  }
+}
+
+/// Number of objects below which [`hash_all`] doesn't bother with threads.
+const PARALLEL_HASH_MIN: usize = 256;
+
+/// Hash every value in `values` with `spec`, in order.
+///
+/// Large batches (such as every build and bind of a loaded manifest) are
+/// split into one contiguous chunk per available core, so at most
+/// `available_parallelism()` threads are spawned however many values there
+/// are.
+pub fn hash_all<T: Hashable + Sync>(values: &[&T], spec: &HashSpec) -> Result<Vec<ObjectHash>, HashError> {
+  let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+  if values.len() < PARALLEL_HASH_MIN || threads == 1 {
+    return values.iter().map(|value| value.compute_hash_with(spec)).collect();
+  }
+
+  let started = Instant::now();
+  let chunk_size = values.len().div_ceil(threads);
+  let hashes = std::thread::scope(|scope| {
+    let workers: Vec<_> = values
+      .chunks(chunk_size)
+      .map(|chunk| {
+        scope.spawn(move || {
+          chunk
+            .iter()
+            .map(|value| value.compute_hash_with(spec))
+            .collect::<Result<Vec<_>, _>>()
+        })
+      })
+      .collect();
+
+    let mut hashes = Vec::with_capacity(values.len());
+    for worker in workers {
+      // A panic while serializing surfaces here as it would when hashing serially
+      let chunk = worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+      hashes.extend(chunk?);
+    }
+    Ok(hashes)
+  });
+  debug!(
+    count = values.len(),
+    threads,
+    elapsed_ms = started.elapsed().as_millis() as u64,
+    "hashed objects in parallel"
+  );
+  hashes
+}
+
+/// Object hashes computed during one evaluation.
+///
+/// Configs that instantiate the same module many times declare the same
+/// builds and binds over and over; each distinct definition is digested only
+/// once. Lives in the Lua runtime's app data, see
+/// [`memoized_hash`](crate::manifest::memoized_hash).
+#[derive(Debug, Default)]
+pub struct HashMemo {
+  hashes: HashMap<HashSpec, HashMap<String, ObjectHash>>,
+  /// Definitions that were digested.
+  pub computed: usize,
+  /// Definitions whose hash was reused.
+  pub reused: usize,
+  /// Time spent serializing and digesting.
+  pub elapsed: Duration,
+}
+
+impl HashMemo {
+  /// Hash `value` with `spec`, reusing the hash of an identical value.
+  pub fn hash(&mut self, value: &impl Hashable, spec: &HashSpec) -> Result<ObjectHash, HashError> {
+    let started = Instant::now();
+    let input = value.hash_input()?;
+    let hashes = self.hashes.entry(*spec).or_default();
+    let hash = match hashes.get(&input) {
+      Some(hash) => {
+        self.reused += 1;
+        hash.clone()
+      }
+      None => {
+        self.computed += 1;
+        let hash = spec.hash(input.as_bytes());
+        hashes.insert(input, hash.clone());
+        hash
+      }
+    };
+    self.elapsed += started.elapsed();
+    Ok(hash)
   }
 }
 
@@ -323,6 +556,39 @@ mod tests {
     assert_ne!(hash1, hash2);
   }
 
+  #[test]
+  fn hash_spec_lengths_share_a_prefix() {
+    #[derive(Serialize)]
+    struct Object {
+      name: &'static str,
+    }
+    impl Hashable for Object {}
+
+    let object = Object { name: "ripgrep" };
+    let short = object.compute_hash().unwrap();
+    let long = object
+      .compute_hash_with(&HashSpec::new(HashAlgorithm::Sha256, 32).unwrap())
+      .unwrap();
+    assert_eq!(short.0.len(), OBJ_HASH_PREFIX_LEN);
+    assert_eq!(long.0.len(), 32);
+    assert!(long.0.starts_with(&short.0));
+    assert_eq!(long.shorter_forms().last(), Some(short.clone()));
+
+    let sha512 = object
+      .compute_hash_with(&HashSpec::new(HashAlgorithm::Sha512, OBJ_HASH_MAX_LEN).unwrap())
+      .unwrap();
+    assert!(!sha512.0.starts_with(&short.0));
+  }
+
+  #[test]
+  fn hash_spec_parses_and_validates() {
+    assert_eq!("sha512:40".parse::<HashSpec>().unwrap().to_string(), "sha512:40");
+    assert!("sha256:16".parse::<HashSpec>().is_err());
+    assert!("sha256:65".parse::<HashSpec>().is_err());
+    assert!("md5:20".parse::<HashSpec>().is_err());
+    assert!(HashSpec::default().is_default());
+  }
+
   #[test]
   fn hash_file_works() {
     let temp = tempdir().unwrap();
@@ -336,4 +602,38 @@ mod tests {
     let hash2 = hash_file(&file_path).unwrap();
     assert_eq!(hash, hash2);
   }
+
+  #[derive(Serialize)]
+  struct Named(String);
+
+  impl Hashable for Named {}
+
+  #[test]
+  fn memo_reuses_hashes_of_identical_values() {
+    let spec = HashSpec::default();
+    let mut memo = HashMemo::default();
+    let a = memo.hash(&Named("a".to_string()), &spec).unwrap();
+    let again = memo.hash(&Named("a".to_string()), &spec).unwrap();
+    let b = memo.hash(&Named("b".to_string()), &spec).unwrap();
+
+    assert_eq!(a, again);
+    assert_eq!(a, Named("a".to_string()).compute_hash().unwrap());
+    assert_ne!(a, b);
+    assert_eq!((memo.computed, memo.reused), (2, 1));
+
+    // Another spec is another hash
+    let long = HashSpec::new(HashAlgorithm::Sha512, 40).unwrap();
+    assert_eq!(memo.hash(&Named("a".to_string()), &long).unwrap().0.len(), 40);
+    assert_eq!(memo.computed, 3);
+  }
+
+  #[test]
+  fn hash_all_matches_serial_hashing_in_order() {
+    let spec = HashSpec::default();
+    let values: Vec<_> = (0..PARALLEL_HASH_MIN * 3 + 7).map(|i| Named(i.to_string())).collect();
+    let refs: Vec<_> = values.iter().collect();
+    let expected: Vec<_> = values.iter().map(|v| v.compute_hash().unwrap()).collect();
+    assert_eq!(hash_all(&refs, &spec).unwrap(), expected);
+    assert_eq!(hash_all(&refs[..3], &spec).unwrap(), expected[..3]);
+  }
 }
@@ -0,0 +1,101 @@
+//! Descriptive metadata for inputs and builds.
+//!
+//! Inputs declare it next to `inputs` and `setup` in their init.lua, and
+//! builds in their `sys.build{}` spec:
+//!
+//! ```lua
+//! metadata = {
+//!   description = "Ripgrep, a fast line-oriented search tool",
+//!   license = "MIT OR Unlicense",
+//!   homepage = "https://github.com/BurntSushi/ripgrep",
+//! }
+//! ```
+//!
+//! Metadata is informational only. `sys info --licenses` lists it for
+//! compliance reporting.
+
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Description, license and homepage of an input or build.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+  /// Short human-readable description.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+  /// License, preferably as an SPDX expression (e.g., "MIT OR Apache-2.0").
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub license: Option<String>,
+  /// Project homepage URL.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub homepage: Option<String>,
+}
+
+impl Metadata {
+  /// Whether no field is set.
+  pub fn is_empty(&self) -> bool {
+    self.description.is_none() && self.license.is_none() && self.homepage.is_none()
+  }
+}
+
+impl FromLua for Metadata {
+  fn from_lua(value: LuaValue, _lua: &Lua) -> LuaResult<Self> {
+    let table = match value {
+      LuaValue::Table(t) => t,
+      _ => {
+        return Err(LuaError::FromLuaConversionError {
+          from: value.type_name(),
+          to: "Metadata".to_string(),
+          message: Some("expected table".to_string()),
+        });
+      }
+    };
+
+    let field = |name: &str| -> LuaResult<Option<String>> {
+      match table.get::<LuaValue>(name)? {
+        LuaValue::Nil => Ok(None),
+        LuaValue::String(s) => Ok(Some(s.to_str()?.to_string())),
+        other => Err(LuaError::external(format!(
+          "metadata.{} must be a string, got {}",
+          name,
+          other.type_name()
+        ))),
+      }
+    };
+
+    Ok(Metadata {
+      description: field("description")?,
+      license: field("license")?,
+      homepage: field("homepage")?,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_metadata_table() -> LuaResult<()> {
+    let lua = Lua::new();
+    let value: LuaValue = lua
+      .load(r#"{ license = "MIT", homepage = "https://example.com" }"#)
+      .eval()?;
+
+    let metadata = Metadata::from_lua(value, &lua)?;
+    assert_eq!(metadata.license.as_deref(), Some("MIT"));
+    assert_eq!(metadata.homepage.as_deref(), Some("https://example.com"));
+    assert_eq!(metadata.description, None);
+    Ok(())
+  }
+
+  #[test]
+  fn rejects_non_string_fields() -> LuaResult<()> {
+    let lua = Lua::new();
+    let value: LuaValue = lua.load("{ license = 42 }").eval()?;
+
+    let err = Metadata::from_lua(value, &lua).unwrap_err();
+    assert!(err.to_string().contains("metadata.license must be a string"));
+    Ok(())
+  }
+}
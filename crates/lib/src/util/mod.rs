@@ -1,8 +1,15 @@
 //! Shared utilities.
 //!
-//! Common utilities used across the crate including hashing and test helpers.
+//! Common utilities used across the crate including hashing, filesystem
+//! helpers, path globs, input/build metadata, semantic versions and test
+//! helpers.
 
+pub mod fs;
+pub mod glob;
 pub mod hash;
+pub mod metadata;
+pub mod ordered;
+pub mod semver;
 
 #[cfg(test)]
 pub mod testutil;
@@ -0,0 +1,52 @@
+//! Deterministic serialization of hash maps.
+//!
+//! Results keep `HashMap`s for lookups, but their JSON is diffed in CI and
+//! must not change from run to run. Fields use
+//! `#[serde(serialize_with = "crate::util::ordered::sorted")]` to write
+//! their entries in key order.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+
+use serde::{Serialize, Serializer};
+
+/// Serialize `map` with its entries sorted by key.
+pub fn sorted<K, V, H, S>(map: &HashMap<K, V, H>, serializer: S) -> Result<S::Ok, S::Error>
+where
+  K: Ord + Serialize,
+  V: Serialize,
+  H: BuildHasher,
+  S: Serializer,
+{
+  serializer.collect_map(map.iter().collect::<BTreeMap<_, _>>())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::execute::types::{BuildResult, DagResult};
+  use crate::util::hash::ObjectHash;
+
+  #[test]
+  fn result_maps_serialize_in_key_order() {
+    let mut result = DagResult::default();
+    for i in 0..32u8 {
+      let hash = ObjectHash(format!("{:02x}{}", 255 - i, "0".repeat(18)));
+      let outputs = (0..8).map(|o| (format!("out{}", o), serde_json::json!(o))).collect();
+      result.realized.insert(
+        hash,
+        BuildResult {
+          store_path: "/store".into(),
+          outputs,
+          action_results: Vec::new(),
+          cached: false,
+        },
+      );
+    }
+
+    let json = serde_json::to_string(&result).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    // serde_json::Value keeps maps sorted, so re-serializing it sorts them
+    assert_eq!(json, serde_json::to_string(&value).unwrap());
+  }
+}
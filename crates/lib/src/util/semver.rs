@@ -0,0 +1,357 @@
+//! Semantic versions and version requirements.
+//!
+//! Used to compare `sys` releases and to pick the highest git tag matching a
+//! `git:URL#semver:<req>` input. Requirements follow Cargo's syntax:
+//!
+//! - `^1.2.3`, `1.2.3`: compatible updates (`>=1.2.3, <2.0.0`; `^0.2.3` is `>=0.2.3, <0.3.0`)
+//! - `~1.2.3`: patch updates (`>=1.2.3, <1.3.0`)
+//! - `=1.2.3`, `>1.2`, `>=1.2`, `<2`, `<=1.4`: plain comparisons
+//! - `*`: any release
+//!
+//! Several comparators separated by commas must all match. Pre-releases only
+//! match a comparator naming a pre-release of the same `MAJOR.MINOR.PATCH`.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use thiserror::Error;
+
+/// Errors parsing versions and requirements.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SemverError {
+  /// A version string is not `MAJOR.MINOR.PATCH[-PRERELEASE]`.
+  #[error("invalid version: {0}")]
+  InvalidVersion(String),
+
+  /// A requirement string is not a comma-separated list of comparators.
+  #[error("invalid version requirement: {0}")]
+  InvalidReq(String),
+}
+
+/// A semantic version (`MAJOR.MINOR.PATCH[-PRERELEASE]`), ordered by semver
+/// precedence. A leading `v` and build metadata after `+` are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+  pub major: u64,
+  pub minor: u64,
+  pub patch: u64,
+  pub pre: Vec<String>,
+}
+
+impl Version {
+  pub fn parse(input: &str) -> Result<Self, SemverError> {
+    let invalid = || SemverError::InvalidVersion(input.to_string());
+    let version = input.trim().trim_start_matches('v');
+    let version = version.split('+').next().unwrap_or(version);
+    let (core, pre) = match version.split_once('-') {
+      Some((core, pre)) => (core, pre.split('.').map(str::to_string).collect()),
+      None => (version, Vec::new()),
+    };
+
+    let mut parts = core.split('.').map(|p| p.parse::<u64>().map_err(|_| invalid()));
+    let (Some(major), Some(minor), Some(patch), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+      return Err(invalid());
+    };
+
+    Ok(Version {
+      major: major?,
+      minor: minor?,
+      patch: patch?,
+      pre,
+    })
+  }
+
+  fn new(major: u64, minor: u64, patch: u64) -> Self {
+    Version {
+      major,
+      minor,
+      patch,
+      pre: Vec::new(),
+    }
+  }
+}
+
+impl fmt::Display for Version {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+    if !self.pre.is_empty() {
+      write!(f, "-{}", self.pre.join("."))?;
+    }
+    Ok(())
+  }
+}
+
+impl Ord for Version {
+  fn cmp(&self, other: &Self) -> Ordering {
+    (self.major, self.minor, self.patch)
+      .cmp(&(other.major, other.minor, other.patch))
+      .then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+        // A release is newer than any of its pre-releases
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => compare_pre(&self.pre, &other.pre),
+      })
+  }
+}
+
+impl PartialOrd for Version {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+/// Compare pre-release identifiers: numeric ones numerically and below alphanumeric ones.
+fn compare_pre(a: &[String], b: &[String]) -> Ordering {
+  for (x, y) in a.iter().zip(b) {
+    let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+      (Ok(x), Ok(y)) => x.cmp(&y),
+      (Ok(_), Err(_)) => Ordering::Less,
+      (Err(_), Ok(_)) => Ordering::Greater,
+      (Err(_), Err(_)) => x.cmp(y),
+    };
+    if ordering != Ordering::Equal {
+      return ordering;
+    }
+  }
+  a.len().cmp(&b.len())
+}
+
+/// Comparison operator of a [`Comparator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+  Exact,
+  Greater,
+  GreaterEq,
+  Less,
+  LessEq,
+  Tilde,
+  Caret,
+}
+
+/// One comparator of a requirement; `minor` and `patch` may be omitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Comparator {
+  op: Op,
+  major: u64,
+  minor: Option<u64>,
+  patch: Option<u64>,
+  pre: Vec<String>,
+}
+
+impl Comparator {
+  fn parse(input: &str) -> Result<Self, SemverError> {
+    let invalid = || SemverError::InvalidReq(input.to_string());
+    let input = input.trim();
+    let (op, rest) = [
+      (">=", Op::GreaterEq),
+      ("<=", Op::LessEq),
+      (">", Op::Greater),
+      ("<", Op::Less),
+      ("=", Op::Exact),
+      ("~", Op::Tilde),
+      ("^", Op::Caret),
+    ]
+    .into_iter()
+    .find_map(|(prefix, op)| input.strip_prefix(prefix).map(|rest| (op, rest)))
+    .unwrap_or((Op::Caret, input));
+
+    let rest = rest.trim().trim_start_matches('v');
+    let (core, pre) = match rest.split_once('-') {
+      Some((core, pre)) => (core, pre.split('.').map(str::to_string).collect()),
+      None => (rest, Vec::new()),
+    };
+
+    let mut parts = core.split('.');
+    let mut number = || -> Result<Option<u64>, SemverError> {
+      match parts.next() {
+        None | Some("*" | "x" | "X") => Ok(None),
+        Some(part) => part.parse().map(Some).map_err(|_| invalid()),
+      }
+    };
+    let major = number()?.ok_or_else(invalid)?;
+    let minor = number()?;
+    let patch = if minor.is_some() { number()? } else { None };
+    if parts.next().is_some() || (!pre.is_empty() && patch.is_none()) {
+      return Err(invalid());
+    }
+
+    Ok(Comparator {
+      op,
+      major,
+      minor,
+      patch,
+      pre,
+    })
+  }
+
+  /// The version with omitted parts as zero.
+  fn lower(&self) -> Version {
+    Version {
+      pre: self.pre.clone(),
+      ..Version::new(self.major, self.minor.unwrap_or(0), self.patch.unwrap_or(0))
+    }
+  }
+
+  fn matches(&self, version: &Version) -> bool {
+    let major = self.major;
+    match self.op {
+      Op::Exact => match (self.minor, self.patch) {
+        (None, _) => version.major == major,
+        (Some(minor), None) => (version.major, version.minor) == (major, minor),
+        (Some(_), Some(_)) => *version == self.lower(),
+      },
+      Op::Greater => match (self.minor, self.patch) {
+        (None, _) => version.major > major,
+        (Some(minor), None) => (version.major, version.minor) > (major, minor),
+        (Some(_), Some(_)) => *version > self.lower(),
+      },
+      Op::GreaterEq => *version >= self.lower(),
+      Op::Less => *version < self.lower(),
+      Op::LessEq => match (self.minor, self.patch) {
+        (None, _) => version.major <= major,
+        (Some(minor), None) => (version.major, version.minor) <= (major, minor),
+        (Some(_), Some(_)) => *version <= self.lower(),
+      },
+      Op::Tilde => {
+        let upper = match self.minor {
+          Some(minor) => Version::new(major, minor + 1, 0),
+          None => Version::new(major + 1, 0, 0),
+        };
+        *version >= self.lower() && *version < upper
+      }
+      Op::Caret => {
+        let upper = match (major, self.minor, self.patch) {
+          (0, Some(0), Some(patch)) => Version::new(0, 0, patch + 1),
+          (0, Some(minor), _) => Version::new(0, minor + 1, 0),
+          _ => Version::new(major + 1, 0, 0),
+        };
+        *version >= self.lower() && *version < upper
+      }
+    }
+  }
+}
+
+/// A version requirement such as `^1.2` or `>=1.0, <2.0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq {
+  /// Empty for `*`.
+  comparators: Vec<Comparator>,
+}
+
+impl VersionReq {
+  pub fn parse(input: &str) -> Result<Self, SemverError> {
+    if input.trim().is_empty() {
+      return Err(SemverError::InvalidReq(input.to_string()));
+    }
+    if input.trim() == "*" {
+      return Ok(VersionReq {
+        comparators: Vec::new(),
+      });
+    }
+
+    let comparators = input.split(',').map(Comparator::parse).collect::<Result<_, _>>()?;
+    Ok(VersionReq { comparators })
+  }
+
+  /// Whether `version` satisfies every comparator.
+  pub fn matches(&self, version: &Version) -> bool {
+    if !self.comparators.iter().all(|c| c.matches(version)) {
+      return false;
+    }
+
+    // Pre-releases need a comparator opting in to that exact release
+    version.pre.is_empty()
+      || self.comparators.iter().any(|c| {
+        !c.pre.is_empty() && (c.major, c.minor, c.patch) == (version.major, Some(version.minor), Some(version.patch))
+      })
+  }
+
+  /// The highest of `candidates` satisfying the requirement, with its version.
+  ///
+  /// Candidates that are not semantic versions (e.g. a `latest` tag) are ignored.
+  pub fn select<'a>(&self, candidates: impl IntoIterator<Item = &'a str>) -> Option<(&'a str, Version)> {
+    candidates
+      .into_iter()
+      .filter_map(|candidate| Version::parse(candidate).ok().map(|version| (candidate, version)))
+      .filter(|(_, version)| self.matches(version))
+      .max_by(|(a, va), (b, vb)| va.cmp(vb).then_with(|| b.cmp(a)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn v(s: &str) -> Version {
+    Version::parse(s).unwrap()
+  }
+
+  fn req(s: &str) -> VersionReq {
+    VersionReq::parse(s).unwrap()
+  }
+
+  #[test]
+  fn versions_follow_semver_precedence() {
+    assert!(v("0.8.0") > v("0.7.9"));
+    assert!(v("v1.0.0") > v("0.99.0"));
+    assert!(v("0.8.0") > v("0.8.0-nightly.20261016"));
+    assert!(v("0.8.0-nightly.20261016") > v("0.8.0-nightly.20261015"));
+    assert!(v("0.8.0-nightly.2") < v("0.8.0-nightly.10"));
+    assert_eq!(v("0.8.0+build.5"), v("0.8.0"));
+    assert!(Version::parse("0.8").is_err());
+    assert!(Version::parse("latest").is_err());
+  }
+
+  #[test]
+  fn caret_allows_compatible_updates() {
+    assert!(req("^1.2").matches(&v("1.2.0")));
+    assert!(req("^1.2").matches(&v("1.9.3")));
+    assert!(!req("^1.2").matches(&v("2.0.0")));
+    assert!(!req("^1.2").matches(&v("1.1.9")));
+    assert!(req("1.2.3").matches(&v("1.4.0")));
+    assert!(req("^0.2.3").matches(&v("0.2.9")));
+    assert!(!req("^0.2.3").matches(&v("0.3.0")));
+    assert!(req("^0.0.3").matches(&v("0.0.3")));
+    assert!(!req("^0.0.3").matches(&v("0.0.4")));
+  }
+
+  #[test]
+  fn tilde_and_comparisons() {
+    assert!(req("~1.2.3").matches(&v("1.2.7")));
+    assert!(!req("~1.2.3").matches(&v("1.3.0")));
+    assert!(req(">=1.0, <2.0").matches(&v("1.5.0")));
+    assert!(!req(">=1.0, <2.0").matches(&v("2.0.0")));
+    assert!(req(">1.2").matches(&v("1.3.0")));
+    assert!(!req(">1.2").matches(&v("1.2.9")));
+    assert!(req("<=1.4").matches(&v("1.4.9")));
+    assert!(req("=1.2").matches(&v("1.2.5")));
+    assert!(req("1.x").matches(&v("1.7.0")));
+    assert!(req("*").matches(&v("3.0.0")));
+  }
+
+  #[test]
+  fn pre_releases_need_an_explicit_comparator() {
+    assert!(!req("^1.2").matches(&v("1.3.0-rc.1")));
+    assert!(!req("*").matches(&v("1.0.0-beta")));
+    assert!(req(">=1.3.0-rc.1").matches(&v("1.3.0-rc.2")));
+    assert!(!req(">=1.3.0-rc.1").matches(&v("1.4.0-rc.1")));
+  }
+
+  #[test]
+  fn rejects_malformed_requirements() {
+    assert!(VersionReq::parse("").is_err());
+    assert!(VersionReq::parse("^one").is_err());
+    assert!(VersionReq::parse("1.2.3.4").is_err());
+    assert!(VersionReq::parse("1.2-rc.1").is_err());
+  }
+
+  #[test]
+  fn select_picks_highest_matching_tag() {
+    let tags = ["v1.1.0", "v1.2.0", "v1.10.0", "v2.0.0", "v1.11.0-rc.1", "latest"];
+    let (tag, version) = req("^1.2").select(tags).unwrap();
+    assert_eq!(tag, "v1.10.0");
+    assert_eq!(version, v("1.10.0"));
+    assert!(req("^3").select(tags).is_none());
+  }
+}
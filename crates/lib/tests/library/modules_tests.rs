@@ -738,6 +738,75 @@ mod env_module {
     Ok(())
   }
 
+  #[test]
+  #[cfg(not(windows))]
+  fn shell_configs_source_stable_current_link() -> LuaResult<()> {
+    // Bind hashes of the shell config blocks, excluding the link switch
+    fn shell_bind_hashes(editor: &str) -> LuaResult<Vec<String>> {
+      let (lua, manifest) = create_test_runtime()?;
+      lua
+        .load(format!(
+          r#"
+            local syslua = require('syslua')
+            syslua.environment.variables.setup({{ EDITOR = '{editor}' }})
+          "#
+        ))
+        .exec()?;
+
+      let m = manifest.borrow();
+      let current = m
+        .bindings
+        .values()
+        .filter(|b| b.id.as_deref() == Some("__syslua_env_current"))
+        .count();
+      assert_eq!(current, 1, "env should create one current link bind");
+      assert!(
+        m.bindings
+          .values()
+          .any(|b| b.id.as_deref() == Some("__syslua_env_current") && b.update_actions.is_some()),
+        "current link bind should switch links in place on update"
+      );
+
+      Ok(
+        m.bindings
+          .iter()
+          .filter(|(_, b)| b.id.is_none())
+          .map(|(hash, _)| hash.0.clone())
+          .collect(),
+      )
+    }
+
+    let vim = shell_bind_hashes("vim")?;
+    assert!(!vim.is_empty(), "env should create shell config binds");
+    assert_eq!(
+      vim,
+      shell_bind_hashes("nvim")?,
+      "changing variables should not rewrite shell configs"
+    );
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(windows))]
+  fn source_command_points_at_current_link() -> LuaResult<()> {
+    let (lua, _) = create_test_runtime()?;
+
+    let (sh, fish): (String, String) = lua
+      .load(
+        r#"
+            local variables = require('syslua.environment.variables')
+            return variables.source_command('bash'), variables.source_command('fish')
+        "#,
+      )
+      .eval()?;
+
+    assert!(sh.starts_with("[ -f \""), "{sh}");
+    assert!(sh.ends_with("/syslua/env/current.sh\""), "{sh}");
+    assert!(fish.starts_with("test -f \""), "{fish}");
+    assert!(fish.ends_with("/syslua/env/current.fish\""), "{fish}");
+    Ok(())
+  }
+
   #[test]
   fn conflicting_env_vars_without_priority_fails() -> LuaResult<()> {
     let (lua, _) = create_test_runtime()?;
@@ -813,3 +882,91 @@ mod env_module {
     Ok(())
   }
 }
+
+mod config_section_modules {
+  use super::*;
+  use syslua_lib::action::Action;
+  use syslua_lib::action::actions::config_section::{ConfigFormat, SectionState};
+
+  #[test]
+  fn git_config_creates_one_bind_per_section() -> LuaResult<()> {
+    let (lua, manifest) = create_test_runtime()?;
+
+    lua
+      .load(
+        r#"
+            local syslua = require('syslua')
+            syslua.environment.git_config.setup({
+                path = '/tmp/gitconfig',
+                user = { name = 'Jane', email = 'jane@example.com' },
+                ['remote "origin"'] = { fetch = { '+refs/heads/*:refs/remotes/origin/*' } },
+            })
+        "#,
+      )
+      .exec()?;
+
+    let m = manifest.borrow();
+    assert_eq!(m.builds.len(), 0);
+    let mut ids: Vec<_> = m.bindings.values().filter_map(|b| b.id.clone()).collect();
+    ids.sort();
+    assert_eq!(ids, vec!["git-config:remote \"origin\"", "git-config:user"]);
+
+    let user = m
+      .bindings
+      .values()
+      .find(|b| b.id.as_deref() == Some("git-config:user"))
+      .unwrap();
+    match &user.create_actions[..] {
+      [Action::ConfigSection(opts)] => {
+        assert_eq!(opts.format, ConfigFormat::Git);
+        assert_eq!(opts.path, "/tmp/gitconfig");
+        assert_eq!(opts.entries["name"], vec!["Jane"]);
+        assert_eq!(opts.state, SectionState::Present);
+      }
+      other => panic!("expected a config section action, got {:?}", other),
+    }
+    assert!(matches!(
+      &user.destroy_actions[..],
+      [Action::ConfigSection(opts)] if opts.state == SectionState::Absent
+    ));
+    assert!(user.check_actions.is_some(), "git config binds should detect drift");
+    Ok(())
+  }
+
+  #[test]
+  fn ssh_config_setup_calls_accumulate_hosts() -> LuaResult<()> {
+    let (lua, manifest) = create_test_runtime()?;
+
+    lua
+      .load(
+        r#"
+            local syslua = require('syslua')
+            syslua.environment.ssh_config.setup({
+                path = '/tmp/ssh_config',
+                ['github.com'] = { User = 'git' },
+            })
+            syslua.environment.ssh_config.setup({
+                ['*.internal'] = { ProxyJump = 'bastion', Port = 2222 },
+            })
+        "#,
+      )
+      .exec()?;
+
+    let m = manifest.borrow();
+    assert_eq!(m.bindings.len(), 2);
+    let internal = m
+      .bindings
+      .values()
+      .find(|b| b.id.as_deref() == Some("ssh-config:*.internal"))
+      .expect("second setup call should add its host");
+    match &internal.create_actions[..] {
+      [Action::ConfigSection(opts)] => {
+        assert_eq!(opts.format, ConfigFormat::Ssh);
+        assert_eq!(opts.path, "/tmp/ssh_config");
+        assert_eq!(opts.entries["Port"], vec!["2222"]);
+      }
+      other => panic!("expected a config section action, got {:?}", other),
+    }
+    Ok(())
+  }
+}
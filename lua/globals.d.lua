@@ -5,17 +5,108 @@
 ---@field args? string[] Optional: arguments to pass to the binary
 ---@field env? table<string,string> Optional: environment variables
 ---@field cwd? string Optional: working directory
+---@field creates? string Optional: skip the command if this path already exists (relative to `cwd`, or `ctx.out`)
+---@field unless? string Optional: skip the command if this shell probe exits successfully
+---@field shell? boolean | "sh" | "bash" | "pwsh" | "powershell" | "cmd" Optional: run `bin` as a command line through a shell (`true` uses `settings.shell` or the platform default); args are quoted and appended
+---@field run_as? string Optional (binds only): run the command and its `unless` probe as this user, which must be listed in `settings.run_as`
+---@field elevate? boolean Optional (binds only): shorthand for `run_as = "root"`
 
 ---@class BuildCtx
 ---@field out string returns the store path placeholder
 ---@field action_count number returns the number of actions performed so far
----@field fetch_url fun(self: BuildCtx, url: string, sha256: string): string Fetches a URL and returns the store path
+---@field fetch_url (fun(self: BuildCtx, url: string, sha256: string): string) | (fun(self: BuildCtx, opts: FetchUrlOpts): string | FetchedArchive) Fetches a URL and returns the store path; with `unpack = true`, the archive and unpacked directory
 ---@field exec fun(self: BuildCtx, opts: string | ExecOpts, args?: string[]): string Performs a command during application, returns stdout
 
+---@class FetchUrlOpts
+---@field url string
+---@field sha256 string expected SHA-256 of the download (lowercase hex)
+---@field unpack? boolean also unpack the archive (stripping a single top-level directory)
+
+---@class FetchedArchive
+---@field archive string placeholder for the downloaded archive
+---@field src string placeholder for the unpacked directory
+
 ---@class BindCtx
 ---@field out string returns the store path placeholder
 ---@field action_count number returns the number of actions performed so far
 ---@field exec fun(self: BindCtx, opts: string | ExecOpts, args?: string[]): string Performs a command during application, returns stdout
+---@field git_config fun(self: BindCtx, opts: GitConfigOpts): string Writes, removes or checks a syslua-managed git config section; returns the path, or "true"/"false" (drifted) for `state = 'check'`
+---@field ssh_config fun(self: BindCtx, opts: SshConfigOpts): string Writes, removes or checks a syslua-managed ssh `Host` block; returns the path, or "true"/"false" (drifted) for `state = 'check'`
+---@field file_block fun(self: BindCtx, opts: FileBlockOpts): string Writes, removes or checks a marker-delimited block of a file; returns the path, or "true"/"false" (missing or edited) for `state = 'check'`
+---@field firewall_rule fun(self: BindCtx, opts: FirewallRuleOpts): string Adds, deletes or checks a syslua-tagged host firewall rule; returns the rule's tag, or "true"/"false" (missing) for `state = 'check'`
+---@field font fun(self: BindCtx, opts: FontOpts): string Installs, removes or checks the fonts of a file or directory; returns the fonts directory, or "true"/"false" (missing or differing) for `state = 'check'`
+---@field write_file fun(self: BindCtx, opts: WriteFileOpts): string Writes, removes or checks a file with the given content, mode and ownership; returns the path, or "true"/"false" (missing or differing) for `state = 'check'`
+---@field symlink fun(self: BindCtx, opts: SymlinkOpts): string Creates, removes or checks a symlink and its ownership; returns the path, or "true"/"false" for `state = 'check'`
+---@field copy fun(self: BindCtx, opts: CopyOpts): string Copies a file or directory and sets its mode and ownership; returns the path, or "true"/"false" for `state = 'check'`
+
+---@alias ConfigSectionState "present" | "absent" | "check"
+
+---@class GitConfigOpts
+---@field path string Config file (`~` and environment variables are expanded)
+---@field section string Section name, e.g. `user` or `remote "origin"`
+---@field entries? table<string, string|number|boolean|(string|number|boolean)[]> Entries of the section; arrays write the key once per value
+---@field state? ConfigSectionState Default `present`
+
+---@class SshConfigOpts
+---@field path string Config file (`~` and environment variables are expanded)
+---@field host string Host patterns, e.g. `github.com` or `*.internal bastion`
+---@field entries? table<string, string|number|boolean|(string|number|boolean)[]> Options of the block; arrays write the keyword once per value
+---@field state? ConfigSectionState Default `present`
+
+---@class FileAttrOpts
+---@field path string Destination (`~` is expanded)
+---@field owner? string Owner name or numeric uid; drift is reported when it changes
+---@field group? string Group name or numeric gid; drift is reported when it changes
+---@field state? ConfigSectionState Default `present`
+
+---@class WriteFileOpts: FileAttrOpts
+---@field content string Content of the file
+---@field mode? string Octal mode, e.g. `'0640'`; drift is reported when it changes
+
+---@class SymlinkOpts: FileAttrOpts
+---@field target string Path the symlink points to
+
+---@class CopyOpts: FileAttrOpts
+---@field source string File or directory to copy
+---@field mode? string Octal mode, e.g. `'0640'` (of the directory only, for a copied directory)
+
+---@class FileBlockSpec
+---@field path string File (`~` is expanded)
+---@field marker string Name of the block in its markers, unique within the file
+---@field content string|string[] Lines of the block, without the markers
+---@field comment? string Comment prefix of the marker lines (default `#`)
+
+---@class FileBlockOpts: FileBlockSpec
+---@field state? ConfigSectionState Default `present`
+
+---@class SysFileBlockSpec: FileBlockSpec
+---@field id? string Bind id (default: `file-block-<marker>`)
+---@field replace? boolean Replace a different bind with the same id
+---@field tags? string[] Tags for `--tag` filtering
+---@field group? string Bind group
+---@field requires? SysFeature[] Host features the bind needs
+---@field platforms? Platform[] Platforms the bind is limited to; skipped elsewhere
+---@field update_strategy? "in_place"|"recreate" How a changed block is applied (default `in_place`)
+
+---@alias FontScope "user" | "system"
+
+---@class FontOpts
+---@field source string Font file, or directory searched for `.ttf`/`.otf`/`.ttc`/`.otc` files (`~` is expanded)
+---@field name? string Name the fonts are installed under (default: file name of `source` without extension)
+---@field scope? FontScope Install for the current user or everyone (default `user`)
+---@field state? ConfigSectionState Default `present`
+
+---@class SysFontSpec
+---@field source string|BuildRef Font file, directory or build whose `out` holds the fonts
+---@field name? string Name the fonts are installed under; required for a build (default: file name of `source` without extension)
+---@field scope? FontScope Install for the current user or everyone (default `user`)
+---@field id? string Bind id (default: `font-<name>`)
+---@field replace? boolean Replace a different bind with the same id
+---@field tags? string[] Tags for `--tag` filtering
+---@field group? string Bind group
+---@field requires? SysFeature[] Host features the bind needs
+---@field platforms? Platform[] Platforms the bind is limited to; skipped elsewhere
+---@field update_strategy? "in_place"|"recreate" How changed fonts are applied (default `in_place`)
 
 ---@class BuildRef
 ---@field id? string Build id
@@ -23,10 +114,69 @@
 ---@field outputs table All outputs from the build
 ---@field hash string Content-addressed hash
 
+---@alias OutputType "dir" | "file" | "path" | "string" | "number" | "boolean" | "table" | "dir?" | "file?" | "path?" | "string?" | "number?" | "boolean?" | "table?"
+
+---@class SrcSpec
+---@field path string Directory to snapshot, relative to the calling file
+---@field id? string Build id (default: the directory's name)
+---@field include? string|string[] Keep only files matching one of these globs, relative to `path`
+---@field exclude? string|string[] Leave out files and directories matching one of these globs
+---@field gitignore? boolean Honor `.gitignore` files (default: true)
+---@field replace? boolean Replace a different build with the same id
+
+---@class FirewallRuleSpec
+---@field name string Rule name: letters, digits, `-` and `_`
+---@field direction? "in" | "out" Default `in`
+---@field action? "allow" | "deny" Default `allow`
+---@field protocol? "tcp" | "udp" | "any" Default `tcp`
+---@field port? integer|string Destination port or range like `"8000-8100"`; needs `tcp` or `udp`
+---@field address? string Remote IP address or CIDR range (the source of incoming, the destination of outgoing traffic)
+
+---@class FirewallRuleOpts: FirewallRuleSpec
+---@field state? "present" | "absent" | "check" Default `present`
+
+---@class FirewallSpec: FirewallRuleSpec
+---@field id? string Bind id (default: `firewall-<name>`)
+---@field replace? boolean Replace a different bind with the same id
+---@field tags? string[] Tags for `--tag` filtering
+---@field group? string Bind group
+---@field requires? SysFeature[] Host features the bind needs
+---@field platforms? Platform[] Platforms the bind is limited to; skipped elsewhere
+
+---@class SysFirewall
+---@field rule fun(spec: FirewallSpec): BindRef Adds a host firewall rule (nftables, pf or Windows Firewall) as a bind that deletes exactly that rule on destroy and reports it as drifted when it is missing
+
+---@class PkgsetSpec
+---@field manager "brew" Package manager
+---@field packages string[] Packages that should be installed
+---@field id? string Bind id (default: `pkgset-<manager>`)
+---@field bin? string Path of the package manager's executable (default: found in its usual install location)
+---@field replace? boolean Replace a different bind with the same id
+---@field tags? string[] Tags for `--tag` filtering
+---@field group? string Bind group
+---@field requires? SysFeature[] Host features the bind needs
+---@field platforms? Platform[] Platforms the bind is limited to; skipped elsewhere
+
 ---@class BuildSpec
 ---@field id? string Required: build id, must be unique
 ---@field inputs? table|fun(): table Optional: input data
+---@field outputs? table<string, OutputType> Optional: outputs create returns, checked at evaluation (`?` marks optional; not part of the hash)
 ---@field create fun(inputs: table, ctx: BuildCtx): table Required: build logic, returns outputs
+---@field check? fun(outputs: table, ctx: BuildCtx) Optional: validation actions run after create; a failure fails the build
+---@field resources? BuildResources Optional: CPU/memory hints for the executor
+---@field metadata? Metadata Optional: description and license, reported by `sys info --licenses`
+---@field placeholders? "strict"|"checked"|"deferred" Optional: how placeholders are checked at evaluation, overriding `settings.placeholders` (default `checked`; not part of the hash)
+---@field impure_env? string[] Optional: environment variables read through `sys.getenv`, whose values at evaluation are hashed into the build so a change rebuilds
+---@field platforms? Platform[] Optional: platforms the build is limited to; elsewhere it is skipped and `sys.build` returns nil (not part of the hash)
+
+---@class Metadata
+---@field description? string Short description
+---@field license? string License, preferably an SPDX expression (e.g. `MIT OR Apache-2.0`)
+---@field homepage? string Project homepage URL
+
+---@class BuildResources
+---@field cpus? integer Scheduler weight; on Linux also caps CPU time via cgroups
+---@field memory? integer|string Memory limit in bytes or with a K/M/G/T suffix (Linux cgroups only)
 
 ---@class BindRef
 ---@field id? string Binding id
@@ -41,40 +191,147 @@
 ---@class BindSpec
 ---@field id? string Binding id. Required when providing update method
 ---@field inputs? table|fun(): table Optional: input data
+---@field outputs? table<string, OutputType> Optional: outputs create returns, checked at evaluation (`?` marks optional; not part of the hash)
 ---@field create fun(inputs: table, ctx: BindCtx): table | nil Required: binding logic, optionally returns outputs
 ---@field update? fun(outputs: table, inputs: table, ctx: BindCtx): table | nil Optional: update logic, optionally returns outputs
 ---@field destroy fun(outputs: table, ctx: BindCtx): nil Required: cleanup logic, receives outputs from create or update
 ---@field check? fun(outputs: table, inputs: table, ctx: BindCtx): BindCheckResult Optional: drift detection, returns drifted status
+---@field backup? string|BindBackup Optional: extra existing files to back up before create and restore after destroy, and the size cap (files the bind's actions replace are always backed up; not part of the hash)
+---@field fallback_path? string|table<string, string> Optional: path written instead of a target that is read-only, immutable or needs elevation; a table maps targets to fallbacks
+---@field tags? string|string[] Optional: labels for selecting binds, e.g. `sys destroy --only <tag>` (not part of the hash)
+---@field group? string Optional: role the bind belongs to, for `sys status`/`sys plan` summaries and `--group` filters (not part of the hash)
+---@field repair? "always"|"never"|"if-missing" Optional: when `sys apply --repair` re-creates the bind after drift (default `always`; not part of the hash)
+---@field repair_ignore? string|string[] Optional: managed path patterns whose drift `--repair` leaves alone, added to `settings.repair_ignore` (not part of the hash)
+---@field serialize? string Optional: group name; binds of the same group never run concurrently (not part of the hash)
+---@field update_strategy? "in_place"|"recreate" Optional: whether a changed bind runs `update` or is destroyed and re-created (default `in_place`; not part of the hash)
+---@field phase? "apply"|"login" Optional: `login` defers `create` to `sys activate-login` at login and `destroy` to logout (default `apply`; no outputs, `update`, `check`, `backup` or `fallback_path`)
+---@field placeholders? "strict"|"checked"|"deferred" Optional: how placeholders are checked at evaluation, overriding `settings.placeholders` (default `checked`; not part of the hash)
+---@field requires? SysFeature|SysFeature[]|string|string[] Optional: facts the host must have (`"!name"` for must not); otherwise the bind is skipped and `sys.bind` returns nil
+---@field platforms? Platform[] Optional: platforms the bind is limited to; elsewhere it is skipped and `sys.bind` returns nil (not part of the hash)
+
+---@class BindBackup
+---@field [integer] string Paths the bind replaces without syslua seeing it, e.g. from a script (`~` and environment variables are expanded)
+---@field max_size? integer|string Skip files larger than this, in bytes or with a K/M/G/T suffix (default `settings.backup_max_size` or 64M)
 
 ---@class PathHelpers
 ---@field resolve fun(...: string): string Resolves a sequence of path segments into an absolute path
 ---@field join fun(...: string): string Joins multiple path segments into a single path
 ---@field dirname fun(path: string): string Returns the directory name of the given path
+---@field parent fun(path: string): string Alias of dirname
 ---@field basename fun(path: string): string Returns the base name of the given path
 ---@field extname fun(path: string): string Returns the file extension of the given path
 ---@field is_absolute fun(path: string): boolean Checks if the given path is absolute
 ---@field normalize fun(path: string): string Normalizes the given path, resolving '..' and '.' segments
 ---@field relative fun(from: string, to: string): string Returns the relative path from one path to another
 ---@field split fun(path: string): table<string> Splits the path into its components
+---@field expand fun(path: string): string Expands a leading '~' and environment variables ($VAR, ${VAR}, %VAR% on Windows)
+---@field exists fun(path: string): boolean Checks if the path exists at evaluation time
 ---@field canonicalize fun(path: string): string Returns the canonical filesystem path (resolves symlinks, Windows 8.3 names). Throws if path doesn't exist.
 
+---@class FsHelpers
+---@field read fun(path: string): string|nil, string|nil Reads a file, returning its contents or nil and an error message
+---@field exists fun(path: string): boolean Checks if the path exists
+---@field is_dir fun(path: string): boolean Checks if the path is a directory
+---@field list fun(dir: string): string[] Returns the sorted entry names of a directory. Throws if it can't be read.
+
+---@class IoHelpers
+---@field prefetch fun(url: string): string Returns the SHA256 of the file at an http(s) URL or local path; recorded in the lock file
+---@field registry fun(url: string): RegistryEntry[] Returns the entries of the registry index at a URL or local path; recorded in the lock file
+---@field all fun(tasks: (fun(): any)[]): any[] Runs each function as a coroutine whose `sys.io` calls are answered concurrently; returns their results in order
+
+---@class TemplateHelpers
+---@field render fun(src: string, vars?: table): string Renders a template with `{{ expr }}`, `{% if %}`/`{% elif %}`/`{% else %}`/`{% endif %}`, `{% for x in xs %}`/`{% for k, v in t %}`/`{% endfor %}` and `{# comments #}`; filters: join(sep), default(v), length, upper, lower, trim. Maps are visited in key order; an undefined or table value in `{{ }}` is an error
+
+---@class RegistryEntry
+---@field name string
+---@field url string
+---@field description string
+---@field tags? string[]
+
 ---@alias Platform "x86_64-windows" | "aarch64-windows" | "x86_64-linux" | "aarch64-linux" | "i386-linux" | "x86_64-darwin" | "aarch64-darwin"
 ---@alias Os "windows" | "linux" | "darwin"
 ---@alias Arch "x86_64" | "aarch64" | "i386"
 
 ---@class Sys
 ---@field dir string Directory containing the root config file
+---@field api fun(version?: integer): integer Declares the Lua API version the config was written against (call it at the top of the config; configs without it get version 1); returns the version in effect. Fails for versions newer than this syslua supports
 ---@field platform Platform Active platform
 ---@field os Os Operating system name
 ---@field arch Arch System architecture
 ---@field is_elevated boolean Whether the process has elevated privileges
+---@field facts SysFacts Host environment facts
+---@field vars table<string, any> Per-host variables returned by `host_vars/<hostname>.lua` (or `--vars-file`); empty if there is none
+---@field current SysCurrent What the current snapshot applied when evaluation started (read-only); `id` is nil if nothing has been applied
 ---@field path PathHelpers File path utilities
+---@field fs? FsHelpers Read-only filesystem helpers, only available with `--impure`
+---@field template TemplateHelpers Eval-time templates with loops and conditionals, for generating config files
+---@field io IoHelpers Eval-time IO lookups run off the Lua VM, answered once and recorded in the lock file
 ---@field build fun(spec: BuildSpec): BuildRef Creates a build within the store
+---@field override_build fun(id: string, fn: fun(spec: BuildSpec): BuildSpec?) Patches the build declared with `id` (e.g. by an input) before it is hashed; `fn` gets a copy of its spec and returns the spec to use, or nil to keep the changed copy. Must be called before the build is declared
+---@field prebuilt fun(spec: { id: string, hash: string, replace?: boolean }): BuildRef Refers to a directory imported with `sys store add`, by the output hash it printed; `outputs.out` is its store path
+---@field src fun(spec: SrcSpec): BuildRef Snapshots a filtered copy of a local directory into the store (honoring `.gitignore`); `outputs.out` is its store path
 ---@field bind fun(spec: BindSpec): BindRef Creates a binding to the active system
+---@field firewall SysFirewall Host firewall rules managed as binds
+---@field file_block fun(spec: SysFileBlockSpec): BindRef Manages a marker-delimited block of a file as a bind that removes only that block on destroy and reports it as drifted when it is missing or was edited outside syslua
+---@field font fun(spec: SysFontSpec): BindRef Installs fonts where the OS loads them from as a bind that refreshes the font cache and removes only those fonts on destroy
+---@field pkgset fun(spec: PkgsetSpec): BindRef Manages the installed packages of a package manager as one bind that installs and uninstalls only what changed; `outputs.packages` is the set
 ---@field getenv fun(name: string): string Returns a placeholder that resolves to the environment variable at execution time
 ---@field register_build_ctx_method fun(name: string, fn: fun(ctx: BuildCtx, ...: any): any) Registers a custom method on BuildCtx
 ---@field register_bind_ctx_method fun(name: string, fn: fun(ctx: BindCtx, ...: any): any) Registers a custom method on BindCtx
+---@field testing? SysTesting Assertion helpers, only available in `*_spec.lua` files run by `sys test`
+
+---@alias SysFeature "systemd" | "launchd" | "wsl" | "container" | "vm"
+
+---@class SysFacts
+---@field virtualization? string WSL, container runtime or hypervisor ("wsl", "docker", "kvm", ...), nil on bare metal
+---@field wsl boolean Running under Windows Subsystem for Linux
+---@field container boolean Running in a container (Docker, Podman, LXC, Kubernetes)
+---@field vm boolean Running in a virtual machine
+---@field systemd boolean systemd is the init system
+---@field launchd boolean launchd manages services (macOS)
+
+---@class SysCurrent
+---@field id? string Id of the current snapshot, nil if nothing has been applied
+---@field created_at? integer Unix timestamp the snapshot was created at
+---@field config_path? string Config that produced the snapshot
+---@field builds table<string, string> Build id -> hash, for builds with an id
+---@field binds table<string, string> Bind id -> hash, for binds with an id
+
+---@class SysTesting
+---@field inputs table Inputs table passed to the config's setup
+---@field setup fun() Runs the config's setup(inputs)
+---@field builds fun(): BuildRef[] Refs to all recorded builds
+---@field binds fun(): BindRef[] Refs to all recorded binds
+---@field expect_build fun(expected: BuildExpectation): BuildRef|nil Fails unless a matching build was recorded
+---@field expect_bind fun(expected: BindExpectation): BindRef|nil Fails unless a matching bind was recorded
+
+---@class BuildExpectation
+---@field id? string Exact build id
+---@field inputs? table Subset of the build's resolved inputs
+---@field outputs? string|string[] Output names that must exist
+---@field exec? string|string[] Substrings of some create exec command line
+---@field fetch? string|string[] Substrings of some fetched URL
+---@field count? integer Exact number of matching builds (default: at least one)
+
+---@class BindExpectation
+---@field id? string Exact bind id
+---@field inputs? table Subset of the bind's resolved inputs
+---@field outputs? string|string[] Output names that must exist
+---@field exec? string|string[] Substrings of some create exec command line
+---@field destroy_exec? string|string[] Substrings of some destroy exec command line
+---@field backup? string|string[] Paths listed in the bind's backup
+---@field count? integer Exact number of matching binds (default: at least one)
 
 ---@type Sys
 ---@diagnostic disable-next-line: missing-fields
 sys = {}
+
+---@deprecated Use `sys.build` (removed in Lua API version 2); `sys migrate-config` rewrites calls (`name` -> `id`, `opts` -> `inputs`, `config` -> `create`, `version` appended to the id)
+---@param spec table
+---@return BuildRef
+function derive(spec) end
+
+---@deprecated Use `sys.bind` (removed in Lua API version 2); `sys migrate-config` rewrites calls (`name` -> `id`, `opts` -> `inputs`, `config` -> `create`, empty `destroy` when missing)
+---@param spec table
+---@return BindRef|nil
+function activate(spec) end
@@ -0,0 +1,78 @@
+local prio = require('syslua.priority')
+local lib = require('syslua.lib')
+
+---@class syslua.environment.git_config
+local M = {}
+
+---@alias syslua.environment.git_config.Value string|number|boolean|(string|number|boolean)[]
+
+---@alias syslua.environment.git_config.Section table<string, syslua.Option<syslua.environment.git_config.Value>>
+
+---@class syslua.environment.git_config.Options
+---@field path? syslua.Option<string> Config file to manage (default: ~/.gitconfig)
+---@field [string] syslua.environment.git_config.Section Entries of each section, keyed by section name (e.g. `user`, `remote "origin"`)
+
+---@type syslua.environment.git_config.Options
+M.opts = {}
+
+--- Copy a merged value into a plain table, so it can be used as bind input
+---@param value unknown
+---@return unknown
+local function plain(value)
+  if type(value) ~= 'table' then
+    return value
+  end
+  local copy = {}
+  for k, v in pairs(value) do
+    copy[k] = plain(v)
+  end
+  return copy
+end
+
+--- Manage sections of the git config according to the provided options
+--- Only the listed sections are written; the rest of the file is left alone,
+--- and destroying a section removes only what syslua wrote
+---@param provided_opts syslua.environment.git_config.Options
+M.setup = function(provided_opts)
+  local new_opts = prio.merge(M.opts, provided_opts)
+  if not new_opts then
+    error('Failed to merge git config options')
+  end
+
+  M.opts = new_opts
+
+  local path = M.opts.path or (lib.get_home() .. '/.gitconfig')
+
+  for section, entries in pairs(M.opts) do
+    if section ~= 'path' then
+      sys.bind({
+        id = 'git-config:' .. section,
+        replace = true,
+        inputs = {
+          path = path,
+          section = section,
+          entries = plain(entries),
+        },
+        create = function(inputs, ctx)
+          ctx:git_config({ path = inputs.path, section = inputs.section, entries = inputs.entries })
+          return { path = inputs.path, section = inputs.section }
+        end,
+        destroy = function(outputs, ctx)
+          ctx:git_config({ path = outputs.path, section = outputs.section, state = 'absent' })
+        end,
+        check = function(_, inputs, ctx)
+          return {
+            drifted = ctx:git_config({
+              path = inputs.path,
+              section = inputs.section,
+              entries = inputs.entries,
+              state = 'check',
+            }),
+          }
+        end,
+      })
+    end
+  end
+end
+
+return M
@@ -3,6 +3,8 @@
 ---@field variables syslua.environment.variables
 ---@field aliases syslua.environment.aliases
 ---@field packages syslua.environment.packages
+---@field git_config syslua.environment.git_config
+---@field ssh_config syslua.environment.ssh_config
 local M = {}
 
 setmetatable(M, {
@@ -0,0 +1,78 @@
+local prio = require('syslua.priority')
+local lib = require('syslua.lib')
+
+---@class syslua.environment.ssh_config
+local M = {}
+
+---@alias syslua.environment.ssh_config.Value string|number|boolean|(string|number|boolean)[]
+
+---@alias syslua.environment.ssh_config.Host table<string, syslua.Option<syslua.environment.ssh_config.Value>>
+
+---@class syslua.environment.ssh_config.Options
+---@field path? syslua.Option<string> Config file to manage (default: ~/.ssh/config)
+---@field [string] syslua.environment.ssh_config.Host Options of each `Host` block, keyed by host patterns (e.g. `github.com`, `*.internal`)
+
+---@type syslua.environment.ssh_config.Options
+M.opts = {}
+
+--- Copy a merged value into a plain table, so it can be used as bind input
+---@param value unknown
+---@return unknown
+local function plain(value)
+  if type(value) ~= 'table' then
+    return value
+  end
+  local copy = {}
+  for k, v in pairs(value) do
+    copy[k] = plain(v)
+  end
+  return copy
+end
+
+--- Manage Host blocks of the ssh config according to the provided options
+--- Only the listed hosts are written, ahead of existing blocks so their options
+--- take precedence; destroying a host removes only what syslua wrote
+---@param provided_opts syslua.environment.ssh_config.Options
+M.setup = function(provided_opts)
+  local new_opts = prio.merge(M.opts, provided_opts)
+  if not new_opts then
+    error('Failed to merge ssh config options')
+  end
+
+  M.opts = new_opts
+
+  local path = M.opts.path or (lib.get_home() .. '/.ssh/config')
+
+  for host, entries in pairs(M.opts) do
+    if host ~= 'path' then
+      sys.bind({
+        id = 'ssh-config:' .. host,
+        replace = true,
+        inputs = {
+          path = path,
+          host = host,
+          entries = plain(entries),
+        },
+        create = function(inputs, ctx)
+          ctx:ssh_config({ path = inputs.path, host = inputs.host, entries = inputs.entries })
+          return { path = inputs.path, host = inputs.host }
+        end,
+        destroy = function(outputs, ctx)
+          ctx:ssh_config({ path = outputs.path, host = outputs.host, state = 'absent' })
+        end,
+        check = function(_, inputs, ctx)
+          return {
+            drifted = ctx:ssh_config({
+              path = inputs.path,
+              host = inputs.host,
+              entries = inputs.entries,
+              state = 'check',
+            }),
+          }
+        end,
+      })
+    end
+  end
+end
+
+return M
@@ -82,6 +82,23 @@ local function get_shell_configs()
   end
 end
 
+--- Directory holding the stable `current.*` links to the active env scripts
+---@return string
+local function get_current_dir()
+  if sys.is_elevated then
+    return '/etc/syslua/env'
+  end
+  return lib.get_home() .. '/.local/state/syslua/env'
+end
+
+--- Stable path of the active env script for a shell
+---@param shell 'sh' | 'bash' | 'zsh' | 'fish'
+---@return string
+local function current_script(shell)
+  local ext = shell == 'fish' and 'fish' or 'sh'
+  return get_current_dir() .. '/current.' .. ext
+end
+
 --- Extract environment variables from merged opts
 --- Note: opts is a MergedTable whose __pairs resolves all values
 ---@param opts syslua.environment.variables.Options
@@ -210,6 +227,77 @@ local function create_env_build(opts)
   })
 end
 
+--- Shell script atomically pointing the `current.*` links at a generation.
+--- The link being replaced is kept as `current.*.bak`.
+---@param dir string
+---@param sh string
+---@param fish string
+---@return string
+local function switch_current_script(dir, sh, fish)
+  return string.format(
+    [[
+dir="%s"
+mkdir -p "$dir"
+switch() {
+  link="$dir/$1"
+  target="$2"
+  if [ -L "$link" ] && [ "$(readlink "$link")" != "$target" ]; then
+    ln -sf "$(readlink "$link")" "$link.bak.tmp" && mv -f "$link.bak.tmp" "$link.bak"
+  fi
+  ln -sf "$target" "$link.tmp" && mv -f "$link.tmp" "$link"
+}
+switch current.sh "%s"
+switch current.fish "%s"
+]],
+    dir,
+    sh,
+    fish
+  )
+end
+
+--- Create the bind that points the stable `current.*` links at the env build.
+---
+--- Links are replaced with a rename, so a shell starting mid-apply sources
+--- either the previous or the new scripts, never a partial file. Scripts
+--- live in the store and are only linked once their build completed.
+---@param build table Build outputs reference
+local function create_current_bind(build)
+  sys.bind({
+    id = '__syslua_env_current',
+    replace = true,
+    inputs = {
+      build = build,
+      dir = get_current_dir(),
+    },
+    create = function(inputs, ctx)
+      ctx:exec({
+        bin = '/bin/sh',
+        args = { '-c', switch_current_script(inputs.dir, inputs.build.outputs.sh, inputs.build.outputs.fish) },
+      })
+      return { dir = inputs.dir }
+    end,
+    update = function(_, inputs, ctx)
+      ctx:exec({
+        bin = '/bin/sh',
+        args = { '-c', switch_current_script(inputs.dir, inputs.build.outputs.sh, inputs.build.outputs.fish) },
+      })
+      return { dir = inputs.dir }
+    end,
+    destroy = function(outputs, ctx)
+      ctx:exec({
+        bin = '/bin/sh',
+        args = {
+          '-c',
+          string.format(
+            'cd "%s" 2>/dev/null && rm -f current.sh current.fish current.sh.bak current.fish.bak',
+            outputs.dir
+          ),
+        },
+      })
+    end,
+  })
+end
+
 --- Create bind steps to source env files from shell configs
 ---@param build table Build outputs reference
 local function create_env_binds(build)
@@ -283,26 +371,22 @@ if (Test-Path $configPath) {
       end,
     })
   else
-    -- POSIX shells (bash, zsh)
+    create_current_bind(build)
+
+    -- POSIX shells (bash, zsh) source the stable link, so their config
+    -- blocks stay the same when the variables change
     for _, shell in ipairs({ 'zsh', 'bash' }) do
       local config_path = shell_configs[shell]
       if config_path then
         sys.bind({
           inputs = {
-            build = build,
+            source_line = M.source_command(shell),
             config_path = config_path,
-            shell = shell,
             begin_marker = BEGIN_MARKER,
             end_marker = END_MARKER,
           },
           create = function(inputs, ctx)
-            local source_line
-            if inputs.shell == 'bash' then
-              source_line =
-                string.format('[ -f "%s" ] && source "%s"', inputs.build.outputs.sh, inputs.build.outputs.sh)
-            else
-              source_line = string.format('source "%s"', inputs.build.outputs.sh)
-            end
+            local source_line = inputs.source_line
 
             -- Create config dir/file if needed and add source block
             ctx:exec({
@@ -358,13 +442,13 @@ fi
     if shell_configs.fish then
       sys.bind({
         inputs = {
-          build = build,
+          source_line = M.source_command('fish'),
           config_path = shell_configs.fish,
           begin_marker = BEGIN_MARKER,
           end_marker = END_MARKER,
         },
         create = function(inputs, ctx)
-          local source_line = string.format('source "%s"', inputs.build.outputs.fish)
+          local source_line = inputs.source_line
 
           ctx:exec({
             bin = '/bin/sh',
@@ -419,6 +503,23 @@ end
 -- Public API
 -- ============================================================================
 
+--- Command that sources the active env script for a shell.
+---
+--- The path is a stable link switched atomically on every apply, so the
+--- command can be written once into any shell config.
+---@param shell 'sh' | 'bash' | 'zsh' | 'fish'
+---@return string
+M.source_command = function(shell)
+  if sys.os == 'windows' then
+    error('source_command is not available on windows; source the build output ps1 instead')
+  end
+  local script = current_script(shell)
+  if shell == 'fish' then
+    return string.format('test -f "%s"; and source "%s"', script, script)
+  end
+  return string.format('[ -f "%s" ] && . "%s"', script, script)
+end
+
 --- Set up environment variables according to the provided options
 --- Environment variables are specified as top-level keys (e.g., EDITOR = 'vim')
 --- PATH is predefined as mergeable and can be extended with prio.before()/after()
@@ -1,5 +1,9 @@
 --- syslua configuration
 --- See https://syslua.dev/docs for documentation
+
+--- Lua API version this config is written against
+sys.api(2)
+
 local M = {}
 
 --- External inputs